@@ -0,0 +1,56 @@
+use std::fmt;
+
+/// Errors from talking to a kerai server.
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent or its reply not read.
+    Http(reqwest::Error),
+    /// The server answered with a non-2xx status.
+    Status { status: u16, url: String, body: String },
+    /// The reply was not the JSON expected.
+    Decode { url: String, message: String },
+    /// The event stream's WebSocket failed.
+    WebSocket(String),
+    /// The base URL or a request built from it is invalid.
+    Url(String),
+}
+
+impl Error {
+    /// HTTP status the server answered with, if it answered.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::Status { status, .. } => Some(*status),
+            Error::Http(e) => e.status().map(|s| s.as_u16()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "request failed: {e}"),
+            Error::Status { status, url, body } => write!(f, "{url} returned {status}: {body}"),
+            Error::Decode { url, message } => write!(f, "invalid JSON from {url}: {message}"),
+            Error::WebSocket(e) => write!(f, "event stream failed: {e}"),
+            Error::Url(e) => write!(f, "invalid URL: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,125 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{SinkExt, Stream, StreamExt};
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::error::{Error, Result};
+
+type Socket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// A notification from the server's `/api/ws` stream.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A CRDT operation was applied (`kerai_ops`).
+    Op {
+        op_type: String,
+        node_id: Option<String>,
+        lamport_ts: i64,
+        author: String,
+    },
+    /// A change to the subscribed workspace's stack.
+    StackDelta(Value),
+    /// Any other notification, as sent.
+    Other(Value),
+}
+
+impl Event {
+    /// Classify a notification payload.
+    pub fn parse(text: &str) -> Event {
+        let value: Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            Err(_) => return Event::Other(Value::String(text.to_string())),
+        };
+        if value["type"] == "stack_delta" {
+            return Event::StackDelta(value);
+        }
+        match (value["op_type"].as_str(), value["author"].as_str()) {
+            (Some(op_type), Some(author)) => Event::Op {
+                op_type: op_type.to_string(),
+                node_id: value["node_id"].as_str().map(str::to_string),
+                lamport_ts: value["lamport_ts"].as_i64().unwrap_or(0),
+                author: author.to_string(),
+            },
+            _ => Event::Other(value),
+        }
+    }
+}
+
+/// Stream of server events, from `Client::events`. Ends when the server
+/// closes the connection.
+pub struct EventStream {
+    socket: Socket,
+}
+
+impl EventStream {
+    pub(crate) async fn connect(url: &str, session_token: Option<&str>) -> Result<Self> {
+        let (mut socket, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(|e| Error::WebSocket(e.to_string()))?;
+        // Stack deltas are only sent for the session's workspace
+        if let Some(token) = session_token {
+            let subscribe = serde_json::json!({"subscribe": {"session_token": token}});
+            socket
+                .send(Message::Text(subscribe.to_string().into()))
+                .await
+                .map_err(|e| Error::WebSocket(e.to_string()))?;
+        }
+        Ok(EventStream { socket })
+    }
+
+    /// Close the connection.
+    pub async fn close(mut self) -> Result<()> {
+        self.socket
+            .close(None)
+            .await
+            .map_err(|e| Error::WebSocket(e.to_string()))
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Result<Event>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            return match self.socket.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(Message::Text(text)))) => {
+                    Poll::Ready(Some(Ok(Event::parse(text.as_str()))))
+                }
+                Poll::Ready(Some(Ok(Message::Close(_)))) | Poll::Ready(None) => Poll::Ready(None),
+                // Pings are answered by tungstenite; binary frames are not used
+                Poll::Ready(Some(Ok(_))) => continue,
+                Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(Error::WebSocket(e.to_string())))),
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications_are_classified() {
+        let op = Event::parse(
+            r#"{"op_type":"insert_node","node_id":"n1","lamport_ts":4,"author":"fp"}"#,
+        );
+        assert_eq!(
+            op,
+            Event::Op {
+                op_type: "insert_node".into(),
+                node_id: Some("n1".into()),
+                lamport_ts: 4,
+                author: "fp".into(),
+            }
+        );
+        assert!(matches!(
+            Event::parse(r#"{"type":"stack_delta","workspace_id":"w"}"#),
+            Event::StackDelta(_)
+        ));
+        assert!(matches!(Event::parse("not json"), Event::Other(Value::String(_))));
+    }
+}
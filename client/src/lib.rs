@@ -0,0 +1,476 @@
+//! Typed async client for the kerai HTTP API (`kerai serve`).
+//!
+//! ```no_run
+//! # async fn run() -> kerai_client::Result<()> {
+//! let client = kerai_client::Client::builder("http://localhost:3000")
+//!     .session_token("...")
+//!     .build()?;
+//! let hits = client.search(&kerai_client::SearchQuery {
+//!     q: "parser".into(),
+//!     ..Default::default()
+//! }).await?;
+//! # Ok(()) }
+//! ```
+//!
+//! Requests that are safe to repeat are retried with exponential backoff on
+//! connection failures and on 429/502/503/504 replies; operations that
+//! change nodes are only retried when the request never reached the server.
+mod error;
+mod events;
+mod types;
+
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+pub use error::{Error, Result};
+pub use events::{Event, EventStream};
+pub use types::*;
+
+/// Name of the cookie the server keeps its session token in.
+const SESSION_COOKIE: &str = "kerai_session";
+
+/// How often and how patiently failed requests are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each one after.
+    pub base_delay: Duration,
+    /// Longest wait between retries.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `attempt` (0-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+/// Whether a request is safe to send again after it may have reached the
+/// server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Repeat {
+    Safe,
+    OnlyIfUnsent,
+}
+
+/// Whether a failed attempt is worth retrying.
+fn should_retry(repeat: Repeat, outcome: &std::result::Result<StatusCode, &reqwest::Error>) -> bool {
+    match outcome {
+        Err(e) if e.is_connect() => true,
+        Err(e) => repeat == Repeat::Safe && e.is_timeout(),
+        Ok(status) => {
+            repeat == Repeat::Safe
+                && matches!(
+                    *status,
+                    StatusCode::TOO_MANY_REQUESTS
+                        | StatusCode::BAD_GATEWAY
+                        | StatusCode::SERVICE_UNAVAILABLE
+                        | StatusCode::GATEWAY_TIMEOUT
+                )
+        }
+    }
+}
+
+/// Builder for a `Client`.
+pub struct ClientBuilder {
+    base_url: String,
+    session_token: Option<String>,
+    retry: RetryPolicy,
+    timeout: Duration,
+}
+
+impl ClientBuilder {
+    /// Session token (from signing in to the web UI) sent with every request.
+    pub fn session_token(mut self, token: impl Into<String>) -> Self {
+        self.session_token = Some(token.into());
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Per-request timeout (default 30s).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn build(self) -> Result<Client> {
+        let base_url = self.base_url.trim_end_matches('/').to_string();
+        if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+            return Err(Error::Url(format!("{base_url} is not an http(s) URL")));
+        }
+        let http = reqwest::Client::builder().timeout(self.timeout).build()?;
+        Ok(Client {
+            http,
+            base_url,
+            session_token: self.session_token,
+            retry: self.retry,
+        })
+    }
+}
+
+/// Client for one kerai server. Cheap to clone.
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    session_token: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl Client {
+    /// Client with default settings and no session.
+    pub fn new(base_url: &str) -> Result<Client> {
+        Client::builder(base_url).build()
+    }
+
+    pub fn builder(base_url: &str) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.to_string(),
+            session_token: None,
+            retry: RetryPolicy::default(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/api{}", self.base_url, path)
+    }
+
+    /// Send a request, retrying as `repeat` allows, and return the reply
+    /// of the last attempt.
+    async fn send<B: Serialize + ?Sized, Q: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        path: &str,
+        query: Option<&Q>,
+        body: Option<&B>,
+        repeat: Repeat,
+    ) -> Result<reqwest::Response> {
+        let url = self.url(path);
+        let mut attempt = 0;
+        loop {
+            let mut req = self.http.request(method.clone(), &url);
+            if let Some(query) = query {
+                req = req.query(query);
+            }
+            if let Some(body) = body {
+                req = req.json(body);
+            }
+            if let Some(token) = &self.session_token {
+                req = req
+                    .bearer_auth(token)
+                    .header(reqwest::header::COOKIE, format!("{SESSION_COOKIE}={token}"));
+            }
+
+            let result = req.send().await;
+            let outcome = result.as_ref().map(|r| r.status());
+            if attempt < self.retry.max_retries && should_retry(repeat, &outcome) {
+                tokio::time::sleep(self.retry.delay(attempt)).await;
+                attempt += 1;
+                continue;
+            }
+
+            let resp = result?;
+            let status = resp.status();
+            if !status.is_success() {
+                let body = resp.text().await.unwrap_or_default();
+                return Err(Error::Status { status: status.as_u16(), url, body });
+            }
+            return Ok(resp);
+        }
+    }
+
+    async fn json<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T> {
+        let url = resp.url().to_string();
+        let bytes = resp.bytes().await?;
+        serde_json::from_slice(&bytes).map_err(|e| Error::Decode { url, message: e.to_string() })
+    }
+
+    async fn get<T: DeserializeOwned, Q: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        query: Option<&Q>,
+    ) -> Result<T> {
+        let resp = self.send::<(), Q>(Method::GET, path, query, None, Repeat::Safe).await?;
+        Self::json(resp).await
+    }
+
+    async fn call<T: DeserializeOwned, B: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        repeat: Repeat,
+    ) -> Result<T> {
+        let resp = self.send::<B, ()>(method, path, None, body, repeat).await?;
+        Self::json(resp).await
+    }
+
+    /// `GET /api/health`.
+    pub async fn health(&self) -> Result<Value> {
+        self.get::<_, ()>("/health", None).await
+    }
+
+    // --- Nodes ---
+
+    /// Apply a CRDT operation (`insert_node`, `update_content`, ...).
+    pub async fn apply_op(&self, op: &ApplyOp) -> Result<OpResult> {
+        self.call(Method::POST, "/nodes", Some(op), Repeat::OnlyIfUnsent).await
+    }
+
+    /// Insert a node of `kind` under `parent_id` at `position`.
+    pub async fn create_node(
+        &self,
+        kind: &str,
+        content: &str,
+        parent_id: Option<&str>,
+        position: i32,
+    ) -> Result<OpResult> {
+        let mut payload = json!({"kind": kind, "content": content, "position": position});
+        if let Some(parent) = parent_id {
+            payload["parent_id"] = json!(parent);
+        }
+        self.apply_op(&ApplyOp {
+            op_type: "insert_node".into(),
+            node_id: None,
+            payload,
+        })
+        .await
+    }
+
+    pub async fn update_content(&self, node_id: &str, content: &str) -> Result<OpResult> {
+        let path = format!("/nodes/{node_id}/content");
+        self.call(Method::PATCH, &path, Some(&json!({"content": content})), Repeat::OnlyIfUnsent)
+            .await
+    }
+
+    /// Move a node to `position`, under `parent_id` if given (otherwise
+    /// among its current siblings).
+    pub async fn move_node(
+        &self,
+        node_id: &str,
+        parent_id: Option<&str>,
+        position: i32,
+    ) -> Result<OpResult> {
+        let path = format!("/nodes/{node_id}/move");
+        let mut payload = json!({"new_position": position});
+        if let Some(parent) = parent_id {
+            payload["new_parent_id"] = json!(parent);
+        }
+        self.call(Method::POST, &path, Some(&payload), Repeat::OnlyIfUnsent).await
+    }
+
+    pub async fn delete_node(&self, node_id: &str) -> Result<OpResult> {
+        let path = format!("/nodes/{node_id}");
+        self.call::<_, ()>(Method::DELETE, &path, None, Repeat::OnlyIfUnsent).await
+    }
+
+    // --- Documents ---
+
+    /// Parse markdown `source` into a document named `filename`.
+    pub async fn create_document(&self, filename: &str, source: &str) -> Result<ParsedDocument> {
+        let body = json!({"filename": filename, "source": source});
+        self.call(Method::POST, "/documents", Some(&body), Repeat::OnlyIfUnsent).await
+    }
+
+    /// Documents, newest first.
+    pub async fn documents(&self) -> Result<Vec<DocumentSummary>> {
+        self.get::<_, ()>("/documents", None).await
+    }
+
+    pub async fn document_tree(&self, document_id: &str) -> Result<Vec<TreeNode>> {
+        self.get::<_, ()>(&format!("/documents/{document_id}/tree"), None).await
+    }
+
+    /// A document reconstructed as markdown.
+    pub async fn document_markdown(&self, document_id: &str) -> Result<String> {
+        let path = format!("/documents/{document_id}/markdown");
+        let resp = self.send::<(), ()>(Method::GET, &path, None, None, Repeat::Safe).await?;
+        Ok(resp.text().await?)
+    }
+
+    // --- Search ---
+
+    pub async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>> {
+        self.get("/search", Some(query)).await
+    }
+
+    /// Search `text` ranked by the perspectives of `agents` (all when empty).
+    pub async fn suggest(&self, text: &str, agents: &[&str], limit: Option<i32>) -> Result<Vec<Value>> {
+        let mut query = vec![("text", text.to_string())];
+        if !agents.is_empty() {
+            query.push(("agents", agents.join(",")));
+        }
+        if let Some(limit) = limit {
+            query.push(("limit", limit.to_string()));
+        }
+        self.get("/suggest", Some(&query)).await
+    }
+
+    // --- Perspectives ---
+
+    /// `agent`'s perspectives, strongest first.
+    pub async fn perspectives(
+        &self,
+        agent: &str,
+        context_id: Option<&str>,
+        min_weight: Option<f64>,
+    ) -> Result<Vec<Perspective>> {
+        let mut query = vec![("agent", agent.to_string())];
+        if let Some(ctx) = context_id {
+            query.push(("context_id", ctx.to_string()));
+        }
+        if let Some(weight) = min_weight {
+            query.push(("min_weight", weight.to_string()));
+        }
+        self.get("/perspectives", Some(&query)).await
+    }
+
+    /// Nodes several agents agree on.
+    pub async fn consensus(
+        &self,
+        context_id: Option<&str>,
+        min_agents: Option<i32>,
+        min_weight: Option<f64>,
+    ) -> Result<Value> {
+        let mut query = Vec::new();
+        if let Some(ctx) = context_id {
+            query.push(("context_id", ctx.to_string()));
+        }
+        if let Some(agents) = min_agents {
+            query.push(("min_agents", agents.to_string()));
+        }
+        if let Some(weight) = min_weight {
+            query.push(("min_weight", weight.to_string()));
+        }
+        self.get("/consensus", Some(&query)).await
+    }
+
+    // --- Sync ---
+
+    /// Send a signed pull request (from `kerai.sign_sync_message`) and
+    /// return the peer's signed reply, for `kerai.open_sync_message`.
+    pub async fn sync_pull(&self, message: &Value) -> Result<Value> {
+        self.call(Method::POST, "/sync/pull", Some(message), Repeat::Safe).await
+    }
+
+    /// Send a signed push of operations. The peer skips ops it already
+    /// holds, so a push is safe to repeat.
+    pub async fn sync_push(&self, message: &Value) -> Result<PushResult> {
+        self.call(Method::POST, "/sync/push", Some(message), Repeat::Safe).await
+    }
+
+    /// Send a signed request for the peer's economy report and return its
+    /// signed reply, for `kerai.record_economy_summary`.
+    pub async fn economy_summary(&self, message: &Value) -> Result<Value> {
+        self.call(Method::POST, "/sync/economy", Some(message), Repeat::Safe).await
+    }
+
+    // --- Events ---
+
+    /// Open the server's event stream: applied operations, plus stack
+    /// changes in the session's workspace when a session token is set.
+    pub async fn events(&self) -> Result<EventStream> {
+        let ws_url = match self.base_url.strip_prefix("https://") {
+            Some(rest) => format!("wss://{rest}/api/ws"),
+            None => format!("ws://{}/api/ws", self.base_url.trim_start_matches("http://")),
+        };
+        EventStream::connect(&ws_url, self.session_token.as_deref()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delays_double_up_to_the_cap() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        assert_eq!(policy.delay(3), Duration::from_millis(500));
+        assert_eq!(policy.delay(40), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn only_safe_requests_retry_server_errors() {
+        let unavailable = Ok(StatusCode::SERVICE_UNAVAILABLE);
+        assert!(should_retry(Repeat::Safe, &unavailable));
+        assert!(!should_retry(Repeat::OnlyIfUnsent, &unavailable));
+        assert!(!should_retry(Repeat::Safe, &Ok(StatusCode::BAD_REQUEST)));
+    }
+
+    #[tokio::test]
+    async fn safe_requests_retry_with_the_session_token() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        let calls = Arc::new(AtomicU32::new(0));
+        let seen = calls.clone();
+        let app = axum::Router::new().route(
+            "/api/search",
+            axum::routing::get(move |headers: axum::http::HeaderMap| async move {
+                assert_eq!(headers["authorization"], "Bearer tok");
+                if seen.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return Err(axum::http::StatusCode::SERVICE_UNAVAILABLE);
+                }
+                Ok(axum::Json(json!([
+                    {"id": "n1", "kind": "fn", "content": "parse", "path": null, "rank": 0.5}
+                ])))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = Client::builder(&format!("http://{addr}"))
+            .session_token("tok")
+            .retry(RetryPolicy { base_delay: Duration::from_millis(1), ..Default::default() })
+            .build()
+            .unwrap();
+        let hits = client
+            .search(&SearchQuery { q: "parse".into(), ..Default::default() })
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, "n1");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn base_url_must_be_http() {
+        assert!(Client::new("localhost:3000").is_err());
+        let client = Client::new("http://localhost:3000/").unwrap();
+        assert_eq!(client.url("/search"), "http://localhost:3000/api/search");
+    }
+}
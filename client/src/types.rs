@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// An operation to apply, as sent to `POST /api/nodes`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplyOp {
+    pub op_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    pub payload: Value,
+}
+
+/// Result of an applied operation (`kerai.apply_op`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpResult {
+    pub op_type: String,
+    pub node_id: Option<String>,
+    pub lamport_ts: i64,
+    pub author_seq: i64,
+    pub author: String,
+}
+
+/// Result of parsing a markdown document into nodes.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ParsedDocument {
+    pub file: String,
+    pub nodes: i64,
+    pub edges: i64,
+    pub elapsed_ms: Option<i64>,
+}
+
+/// A document node, as listed by `GET /api/documents`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DocumentSummary {
+    pub id: String,
+    pub content: Option<String>,
+    #[serde(default)]
+    pub metadata: Value,
+    pub created_at: Option<String>,
+}
+
+/// One node of a document tree, parents before children.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TreeNode {
+    pub id: String,
+    pub kind: String,
+    pub content: Option<String>,
+    pub parent_id: Option<String>,
+    pub position: i32,
+    #[serde(default)]
+    pub metadata: Value,
+    pub depth: i32,
+}
+
+/// Parameters of a full-text search.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchQuery {
+    pub q: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i32>,
+}
+
+/// A full-text search hit.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchHit {
+    pub id: String,
+    pub kind: String,
+    pub content: Option<String>,
+    pub path: Option<String>,
+    pub rank: f64,
+    #[serde(default)]
+    pub metadata: Value,
+}
+
+/// An agent's weighted view of a node.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Perspective {
+    pub id: String,
+    pub node_id: String,
+    pub weight: f64,
+    pub context_id: Option<String>,
+    pub reasoning: Option<String>,
+    pub node_kind: Option<String>,
+    pub node_content: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+/// Outcome of pushing operations to a peer (`POST /api/sync/push`).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PushResult {
+    #[serde(default)]
+    pub applied: u64,
+    #[serde(default)]
+    pub superseded: u64,
+    #[serde(default)]
+    pub duplicates: u64,
+    #[serde(default)]
+    pub missing: u64,
+}
@@ -0,0 +1,49 @@
+use postgres::Client;
+
+use crate::output::{print_json, print_rows, OutputFormat};
+
+pub fn indexes(client: &mut Client, format: &OutputFormat) -> Result<(), String> {
+    let row = client
+        .query_one("SELECT kerai.advise_indexes()::text", &[])
+        .map_err(|e| format!("advise_indexes failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let advice = value["advice"].as_array().cloned().unwrap_or_default();
+    println!(
+        "Sampled {} statements from pg_stat_statements.",
+        value["sampled"].as_i64().unwrap_or(0)
+    );
+    if advice.is_empty() {
+        println!("No index advice.");
+        return Ok(());
+    }
+
+    let columns = vec![
+        "estimated_benefit_ms".into(),
+        "calls".into(),
+        "reason".into(),
+        "index_sql".into(),
+    ];
+    let rows: Vec<Vec<String>> = advice
+        .iter()
+        .map(|a| {
+            vec![
+                format!("{:.1}", a["estimated_benefit"].as_f64().unwrap_or(0.0)),
+                a["calls"].as_i64().unwrap_or(0).to_string(),
+                a["reason"].as_str().unwrap_or("").to_string(),
+                a["index_sql"].as_str().unwrap_or("").to_string(),
+            ]
+        })
+        .collect();
+
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
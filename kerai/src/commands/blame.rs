@@ -0,0 +1,41 @@
+use postgres::Client;
+
+use crate::commands::diff::resolve_file;
+use crate::output::{print_json, OutputFormat};
+
+pub fn run(client: &mut Client, path: &str, format: &OutputFormat) -> Result<(), String> {
+    let file_id = resolve_file(client, path)?;
+
+    let row = client
+        .query_one("SELECT kerai.blame($1::text::uuid)::text", &[&file_id])
+        .map_err(|e| format!("blame failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ => {
+            let lines = value["lines"].as_array().cloned().unwrap_or_default();
+            let author = |l: &serde_json::Value| l["author"].as_str().unwrap_or("?").to_string();
+            let stamp = |l: &serde_json::Value| {
+                l["lamport_ts"].as_i64().map_or("-".to_string(), |ts| ts.to_string())
+            };
+            let author_width = lines.iter().map(|l| author(l).len()).max().unwrap_or(0);
+            let stamp_width = lines.iter().map(|l| stamp(l).len()).max().unwrap_or(0);
+            let line_width = lines.len().to_string().len();
+
+            for l in &lines {
+                println!(
+                    "{:<author_width$} {:>stamp_width$} {:>line_width$} | {}",
+                    author(l),
+                    stamp(l),
+                    l["line"].as_u64().unwrap_or(0),
+                    l["text"].as_str().unwrap_or(""),
+                );
+            }
+        }
+    }
+    Ok(())
+}
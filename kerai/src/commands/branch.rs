@@ -0,0 +1,100 @@
+use postgres::Client;
+
+use crate::output::{print_json, print_rows, OutputFormat};
+
+fn call(client: &mut Client, function: &str, name: &str) -> Result<serde_json::Value, String> {
+    let row = client
+        .query_one(&format!("SELECT kerai.{function}($1)::text"), &[&name])
+        .map_err(|e| format!("{function} failed: {e}"))?;
+
+    let text: String = row.get(0);
+    serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))
+}
+
+pub fn create(client: &mut Client, name: &str, format: &OutputFormat) -> Result<(), String> {
+    let value = call(client, "create_branch", name)?;
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ => println!(
+            "Created branch '{name}' from '{}' at ts {}",
+            value["parent"].as_str().unwrap_or("?"),
+            value["fork_ts"].as_i64().unwrap_or(0),
+        ),
+    }
+    Ok(())
+}
+
+pub fn list(client: &mut Client, format: &OutputFormat) -> Result<(), String> {
+    let row = client
+        .query_one("SELECT kerai.list_branches()::text", &[])
+        .map_err(|e| format!("list_branches failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    let arr = value.as_array().ok_or("Expected JSON array")?;
+
+    let columns = vec![
+        "current".into(),
+        "name".into(),
+        "parent".into(),
+        "fork_ts".into(),
+        "versions".into(),
+        "merged_into".into(),
+    ];
+
+    let rows: Vec<Vec<String>> = arr
+        .iter()
+        .map(|b| {
+            vec![
+                if b["current"].as_bool().unwrap_or(false) { "*" } else { "" }.to_string(),
+                b["name"].as_str().unwrap_or("").to_string(),
+                b["parent"].as_str().unwrap_or("").to_string(),
+                b["fork_ts"].as_i64().map(|n| n.to_string()).unwrap_or_default(),
+                b["versions"].as_i64().map(|n| n.to_string()).unwrap_or_default(),
+                b["merged_into"].as_str().unwrap_or("").to_string(),
+            ]
+        })
+        .collect();
+
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
+
+pub fn switch(client: &mut Client, name: &str, format: &OutputFormat) -> Result<(), String> {
+    let value = call(client, "switch_branch", name)?;
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ => println!(
+            "Switched to branch '{name}' (undid {}, replayed {})",
+            value["undone"].as_i64().unwrap_or(0),
+            value["replayed"].as_i64().unwrap_or(0),
+        ),
+    }
+    Ok(())
+}
+
+pub fn merge(client: &mut Client, name: &str, format: &OutputFormat) -> Result<(), String> {
+    let value = call(client, "merge_branch", name)?;
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let conflicts = value["conflicts"].as_array().cloned().unwrap_or_default();
+    println!(
+        "Merged '{name}' into '{}': {} change(s) applied, {} conflict(s)",
+        value["into"].as_str().unwrap_or("?"),
+        value["applied"].as_i64().unwrap_or(0),
+        conflicts.len(),
+    );
+    for c in &conflicts {
+        println!(
+            "  {} {}",
+            c["node_id"].as_str().unwrap_or("?"),
+            c["reason"].as_str().unwrap_or(""),
+        );
+    }
+    Ok(())
+}
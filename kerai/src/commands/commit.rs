@@ -24,34 +24,69 @@ pub fn run(client: &mut Client, message: Option<&str>) -> Result<(), String> {
     let mut total_edges = 0u64;
 
     for file_path in &rs_files {
-        let row = client
-            .query_one("SELECT kerai.parse_file($1)::text", &[file_path])
-            .map_err(|e| format!("parse_file failed for {file_path}: {e}"))?;
-
-        let text: String = row.get(0);
-        let value: serde_json::Value =
-            serde_json::from_str(&text).unwrap_or(serde_json::Value::Null);
-
-        let nodes = value["nodes_inserted"].as_u64().unwrap_or(0);
-        let edges = value["edges_inserted"].as_u64().unwrap_or(0);
-        total_nodes += nodes;
-        total_edges += edges;
-
-        // Show relative path for cleaner output
         let rel = file_path
             .strip_prefix(&project_root.to_string_lossy().as_ref())
             .unwrap_or(file_path)
             .trim_start_matches('/');
+        let (nodes, edges) = parse_file(client, file_path, rel)?;
+        total_nodes += nodes;
+        total_edges += edges;
         println!("  {rel}: {nodes} nodes, {edges} edges");
     }
 
+    let calls = resolve_calls(client)?;
     println!(
-        "Committed {} files: {total_nodes} nodes, {total_edges} edges",
+        "Committed {} files: {total_nodes} nodes, {total_edges} edges, {calls} call edges",
         rs_files.len()
     );
     Ok(())
 }
 
+/// Re-link call sites to function definitions across all parsed files,
+/// returning the number of `calls` edges.
+pub(crate) fn resolve_calls(client: &mut Client) -> Result<u64, String> {
+    let row = kerai_cli::txn::serializable(client, "resolve_calls", |tx| {
+        tx.query_one("SELECT kerai.resolve_calls()::text", &[])
+    })
+    .map_err(|e| format!("resolve_calls failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).unwrap_or(serde_json::Value::Null);
+
+    Ok(value["edges"].as_u64().unwrap_or(0))
+}
+
+/// Re-parse one source file into the extension, returning (nodes, edges) inserted.
+/// `rel_path` is recorded on the file node for `kerai pg.export --write`.
+pub(crate) fn parse_file(
+    client: &mut Client,
+    file_path: &str,
+    rel_path: &str,
+) -> Result<(u64, u64), String> {
+    let row = kerai_cli::txn::serializable(client, "parse_file", |tx| {
+        tx.query_one(
+            "SELECT kerai.parse_file($1, false, $2)::text",
+            &[&file_path, &rel_path],
+        )
+    })
+    .map_err(|e| format!("parse_file failed for {file_path}: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).unwrap_or(serde_json::Value::Null);
+
+    Ok((
+        value["nodes"].as_u64().unwrap_or(0),
+        value["edges"].as_u64().unwrap_or(0),
+    ))
+}
+
+/// Build and hidden directories that are never parsed.
+pub(crate) fn is_skipped_dir(name: &str) -> bool {
+    name == "target" || name == "tgt" || name == ".kerai" || name.starts_with('.')
+}
+
 fn walk_rs_files(root: &Path, dir: &Path, out: &mut Vec<String>) -> Result<(), String> {
     let entries =
         std::fs::read_dir(dir).map_err(|e| format!("Cannot read {}: {e}", dir.display()))?;
@@ -64,11 +99,7 @@ fn walk_rs_files(root: &Path, dir: &Path, out: &mut Vec<String>) -> Result<(), S
 
         if path.is_dir() {
             // Skip build/hidden directories
-            if name_str == "target"
-                || name_str == "tgt"
-                || name_str == ".kerai"
-                || name_str.starts_with('.')
-            {
+            if is_skipped_dir(&name_str) {
                 continue;
             }
             walk_rs_files(root, &path, out)?;
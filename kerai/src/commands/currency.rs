@@ -159,3 +159,84 @@ pub fn set_reward(
     print_json(&value, format);
     Ok(())
 }
+
+pub fn reward(
+    client: &mut Client,
+    work_type: &str,
+    reference_id: Option<&str>,
+    reference_type: Option<&str>,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let row = client
+        .query_one(
+            "SELECT kerai.mint_reward($1, NULL, $2::text::uuid, $3)::text",
+            &[&work_type, &reference_id, &reference_type],
+        )
+        .map_err(|e| format!("mint_reward failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    match value["reward"].as_i64() {
+        Some(reward) => println!("Minted {reward} Koi for '{work_type}'"),
+        None => println!("No enabled reward for '{work_type}'"),
+    }
+    print_json(&value, format);
+    Ok(())
+}
+
+pub fn ledger(
+    client: &mut Client,
+    reference_id: &str,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let row = client
+        .query_one(
+            "SELECT kerai.ledger_for($1::text::uuid)::text",
+            &[&reference_id],
+        )
+        .map_err(|e| format!("ledger_for failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    let arr = value["entries"]
+        .as_array()
+        .ok_or("Expected entries array")?;
+
+    if arr.is_empty() {
+        println!("No ledger entries for {reference_id}.");
+        return Ok(());
+    }
+
+    let columns = vec![
+        "reason".into(),
+        "amount".into(),
+        "from".into(),
+        "to".into(),
+        "type".into(),
+        "created".into(),
+    ];
+
+    let rows: Vec<Vec<String>> = arr
+        .iter()
+        .map(|e| {
+            vec![
+                e["reason"].as_str().unwrap_or("").to_string(),
+                e["amount"]
+                    .as_i64()
+                    .map(|n| n.to_string())
+                    .unwrap_or_default(),
+                e["from_wallet"].as_str().unwrap_or("mint").to_string(),
+                e["to_wallet"].as_str().unwrap_or("").to_string(),
+                e["reference_type"].as_str().unwrap_or("").to_string(),
+                e["created_at"].as_str().unwrap_or("").to_string(),
+            ]
+        })
+        .collect();
+
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
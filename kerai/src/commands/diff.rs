@@ -0,0 +1,175 @@
+use postgres::Client;
+
+use crate::output::{print_json, OutputFormat};
+
+/// Resolve a file argument: a node UUID, or the name of a parsed file.
+pub fn resolve_file(client: &mut Client, file: &str) -> Result<String, String> {
+    let rows = client
+        .query(
+            "SELECT id::text FROM kerai.nodes \
+             WHERE kind = 'file' AND deleted_at IS NULL AND (id::text = $1 OR content = $1)",
+            &[&file],
+        )
+        .map_err(|e| format!("File lookup failed: {e}"))?;
+    match rows.len() {
+        0 => Err(format!("No file node matches '{file}'")),
+        1 => Ok(rows[0].get(0)),
+        n => Err(format!("'{file}' matches {n} file nodes; pass a node id instead")),
+    }
+}
+
+pub fn run(
+    client: &mut Client,
+    file_a: &str,
+    file_b: &str,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let a = resolve_file(client, file_a)?;
+    let b = resolve_file(client, file_b)?;
+
+    let row = client
+        .query_one("SELECT kerai.diff_nodes($1::text::uuid, $2::text::uuid)::text", &[&a, &b])
+        .map_err(|e| format!("diff failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let mut value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+    value["impact"] = impact(client, &changed_ids(&value))?;
+
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ => {
+            let name = |key: &str| value[key]["name"].as_str().unwrap_or("?").to_string();
+            println!("--- {}", name("file_a"));
+            println!("+++ {}", name("file_b"));
+            for line in render_lines(&value) {
+                println!("{line}");
+            }
+            println!();
+            println!("{}", summary(&value));
+            let impact = render_impact(&value["impact"]);
+            if !impact.is_empty() {
+                println!();
+                println!("Impact:");
+                for line in impact {
+                    println!("{line}");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ids of every changed node, on either side of the diff.
+fn changed_ids(value: &serde_json::Value) -> Vec<String> {
+    let mut ids = Vec::new();
+    for key in ["inserted", "deleted", "moved", "modified"] {
+        for e in value[key].as_array().into_iter().flatten() {
+            for field in ["id", "a_id", "b_id"] {
+                if let Some(id) = e[field].as_str() {
+                    ids.push(id.to_string());
+                }
+            }
+        }
+    }
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+/// What depends on the changed nodes, from `kerai.impact_all`.
+fn impact(client: &mut Client, ids: &[String]) -> Result<serde_json::Value, String> {
+    let row = client
+        .query_one("SELECT kerai.impact_all($1::text[]::uuid[])::text", &[&ids])
+        .map_err(|e| format!("impact failed: {e}"))?;
+    let text: String = row.get(0);
+    serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))
+}
+
+/// One line per affected file or document, nearest first.
+fn render_impact(impact: &serde_json::Value) -> Vec<String> {
+    impact["files"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|f| {
+            let n = f["nodes"].as_u64().unwrap_or(1);
+            format!(
+                "  {} {} (distance {}, confidence {:.2}, {n} node{})",
+                f["kind"].as_str().unwrap_or("file"),
+                f["file"].as_str().unwrap_or("?"),
+                f["distance"].as_u64().unwrap_or(0),
+                f["confidence"].as_f64().unwrap_or(0.0),
+                if n == 1 { "" } else { "s" },
+            )
+        })
+        .collect()
+}
+
+/// Path below the file node, used to order and indent entries.
+fn relative_path(entry: &serde_json::Value) -> &str {
+    let path = entry["path"].as_str().unwrap_or("");
+    path.split_once('.').map(|(_, rest)| rest).unwrap_or("")
+}
+
+fn label(entry: &serde_json::Value) -> String {
+    let kind = entry["kind"].as_str().unwrap_or("?");
+    match entry["content"].as_str() {
+        Some(content) => format!("{kind} {content}"),
+        None => kind.to_string(),
+    }
+}
+
+/// One line per change, grouped by item path and indented by depth.
+fn render_lines(value: &serde_json::Value) -> Vec<String> {
+    let entries = |key: &str| value[key].as_array().cloned().unwrap_or_default();
+    let mut lines: Vec<(String, String)> = Vec::new();
+
+    for e in entries("inserted") {
+        let n = e["nodes"].as_i64().unwrap_or(1);
+        let size = if n > 1 { format!(" ({n} nodes)") } else { String::new() };
+        lines.push((relative_path(&e).into(), format!("+ {}{size}", label(&e))));
+    }
+    for e in entries("deleted") {
+        let n = e["nodes"].as_i64().unwrap_or(1);
+        let size = if n > 1 { format!(" ({n} nodes)") } else { String::new() };
+        lines.push((relative_path(&e).into(), format!("- {}{size}", label(&e))));
+    }
+    for e in entries("moved") {
+        let how = if e["reparented"].as_bool().unwrap_or(false) { "moved" } else { "reordered" };
+        lines.push((relative_path(&e).into(), format!("> {} ({how})", label(&e))));
+    }
+    for e in entries("modified") {
+        let kind = e["kind"].as_str().unwrap_or("?");
+        let before = e["before"].as_str().unwrap_or("");
+        let after = e["after"].as_str().unwrap_or("");
+        let change = if before == after {
+            "metadata changed".to_string()
+        } else {
+            format!("{before:?} -> {after:?}")
+        };
+        lines.push((relative_path(&e).into(), format!("~ {kind} {change}")));
+    }
+
+    lines.sort_by(|x, y| x.0.cmp(&y.0));
+    lines
+        .into_iter()
+        .map(|(path, text)| {
+            let depth = if path.is_empty() { 0 } else { path.matches('.').count() + 1 };
+            format!("{}{}", "  ".repeat(depth.max(1)), text)
+        })
+        .collect()
+}
+
+fn summary(value: &serde_json::Value) -> String {
+    let count = |key: &str| value[key].as_array().map_or(0, |a| a.len());
+    format!(
+        "{} inserted, {} deleted, {} moved, {} modified, {} unchanged",
+        count("inserted"),
+        count("deleted"),
+        count("moved"),
+        count("modified"),
+        value["unchanged"].as_u64().unwrap_or(0),
+    )
+}
+
@@ -0,0 +1,191 @@
+use postgres::Client;
+use serde_json::Value;
+
+use super::sync::sign_message;
+use crate::db;
+use crate::output::{print_json, print_rows, OutputFormat};
+
+const NKOI_PER_KOI: f64 = 1_000_000_000.0;
+
+fn koi(nkoi: &Value) -> String {
+    format!("{:.2}", nkoi.as_f64().unwrap_or(0.0) / NKOI_PER_KOI)
+}
+
+/// Report ledger activity for `period`. With `federation`, first fetch a
+/// signed summary from every reachable peer, then report all instances.
+pub fn run(
+    client: &mut Client,
+    period: &str,
+    federation: bool,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    if !federation {
+        let report = db::query_json(
+            client,
+            "economy_report",
+            "SELECT kerai.economy_report($1)::text",
+            &[&period],
+        )?;
+        if let OutputFormat::Json = format {
+            print_json(&report, format);
+            return Ok(());
+        }
+        println!(
+            "Minted {} Koi in {} mints; transferred {} Koi in {} transfers",
+            koi(&report["minted"]),
+            report["mints"],
+            koi(&report["transferred"]),
+            report["transfers"]
+        );
+        print_actions(&report["actions"], format);
+        return Ok(());
+    }
+
+    let skipped = gather(client, period, format)?;
+    let mut report = db::query_json(
+        client,
+        "federation_economy_report",
+        "SELECT kerai.federation_economy_report($1)::text",
+        &[&period],
+    )?;
+
+    if let OutputFormat::Json = format {
+        report["unreachable"] = serde_json::json!(skipped);
+        print_json(&report, format);
+        return Ok(());
+    }
+
+    let columns = vec![
+        "instance".into(),
+        "minted".into(),
+        "mints".into(),
+        "transferred".into(),
+        "transfers".into(),
+        "summary".into(),
+    ];
+    let rows: Vec<Vec<String>> = report["instances"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|i| {
+            let summary = if i["self"] == true {
+                "local".to_string()
+            } else if i["stale"] == true {
+                format!("stale ({})", i["generated_at"].as_str().unwrap_or("?"))
+            } else {
+                i["generated_at"].as_str().unwrap_or("").to_string()
+            };
+            vec![
+                i["name"].as_str().unwrap_or("").to_string(),
+                koi(&i["minted"]),
+                i["mints"].to_string(),
+                koi(&i["transferred"]),
+                i["transfers"].to_string(),
+                summary,
+            ]
+        })
+        .collect();
+    print_rows(&columns, &rows, format);
+
+    let totals = &report["totals"];
+    println!(
+        "Federation minted {} Koi in {} mints; transferred {} Koi in {} transfers",
+        koi(&totals["minted"]),
+        totals["mints"],
+        koi(&totals["transferred"]),
+        totals["transfers"]
+    );
+    if let Some(top) = report["top_minter"].as_str() {
+        println!("Top minter: {top}");
+    }
+    print_actions(&report["actions"], format);
+    Ok(())
+}
+
+fn print_actions(actions: &Value, format: &OutputFormat) {
+    let actions = actions.as_array().cloned().unwrap_or_default();
+    if actions.is_empty() {
+        println!("Nothing minted in this period.");
+        return;
+    }
+    let columns = vec!["reason".into(), "count".into(), "koi".into()];
+    let rows: Vec<Vec<String>> = actions
+        .iter()
+        .map(|a| {
+            vec![
+                a["reason"].as_str().unwrap_or("").to_string(),
+                a["count"].to_string(),
+                koi(&a["amount"]),
+            ]
+        })
+        .collect();
+    print_rows(&columns, &rows, format);
+}
+
+/// Fetch and record a signed economy summary from each peer with a
+/// connection string or endpoint. A peer that fails is reported and
+/// skipped; its last recorded summary, if any, still counts. Returns the
+/// names of the peers skipped.
+fn gather(client: &mut Client, period: &str, format: &OutputFormat) -> Result<Vec<String>, String> {
+    let peers = client
+        .query(
+            "SELECT name, connection, endpoint FROM kerai.instances
+             WHERE is_self = false AND (connection IS NOT NULL OR endpoint IS NOT NULL)
+             ORDER BY name",
+            &[],
+        )
+        .map_err(|e| format!("Failed to list peers: {e}"))?;
+
+    let mut skipped = Vec::new();
+    for peer in peers {
+        let name: String = peer.get(0);
+        let connection: Option<String> = peer.get(1);
+        let endpoint: Option<String> = peer.get(2);
+
+        let fetched = match (connection, endpoint) {
+            (Some(conn), _) => fetch_direct(&conn, period),
+            (_, endpoint) => fetch_http(client, &endpoint.unwrap_or_default(), period),
+        };
+        let recorded = fetched.and_then(|message| {
+            let text =
+                serde_json::to_string(&message).map_err(|e| format!("JSON encode failed: {e}"))?;
+            client
+                .query_one(
+                    "SELECT kerai.record_economy_summary($1::text::jsonb)",
+                    &[&text],
+                )
+                .map_err(|e| format!("record_economy_summary failed: {e}"))
+        });
+        if let Err(e) = recorded {
+            if !matches!(format, OutputFormat::Json) {
+                eprintln!("Skipping peer '{name}': {e}");
+            }
+            skipped.push(name);
+        }
+    }
+    Ok(skipped)
+}
+
+/// Ask the peer's database to sign its own report.
+fn fetch_direct(peer_conn: &str, period: &str) -> Result<Value, String> {
+    let mut peer =
+        db::connect_url(peer_conn).map_err(|e| format!("Cannot connect to peer: {e}"))?;
+    db::query_json(
+        &mut peer,
+        "economy_summary",
+        "SELECT kerai.economy_summary($1)::text",
+        &[&period],
+    )
+}
+
+/// Ask the peer's web server (`/api/sync/economy`) with a signed request.
+fn fetch_http(client: &mut Client, endpoint: &str, period: &str) -> Result<Value, String> {
+    let runtime =
+        tokio::runtime::Runtime::new().map_err(|e| format!("Failed to start HTTP runtime: {e}"))?;
+    let peer = kerai_client::Client::new(endpoint).map_err(|e| e.to_string())?;
+    let body = serde_json::json!({ "period": period }).to_string();
+    let request = sign_message(client, &body)?;
+    runtime
+        .block_on(peer.economy_summary(&request))
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,192 @@
+//! `kerai edge`: link and unlink nodes by hand through registered relations.
+
+use postgres::Client;
+use serde_json::Value;
+
+use crate::output::{print_json, print_rows, OutputFormat};
+use crate::progress;
+
+/// What `kerai edge add/remove` acts on: one edge from the arguments, or a
+/// batch from a JSON file of `[{source_id, target_id, relation, metadata?}]`.
+pub enum Edges<'a> {
+    One {
+        source: &'a str,
+        target: &'a str,
+        relation: &'a str,
+        metadata: Option<&'a str>,
+    },
+    File(&'a str),
+}
+
+impl<'a> Edges<'a> {
+    /// From `kerai edge` arguments, which clap only lets through with either
+    /// a file or all of source, target and relation.
+    pub fn from_args(
+        source: &'a Option<String>,
+        target: &'a Option<String>,
+        relation: &'a Option<String>,
+        metadata: Option<&'a str>,
+        file: &'a Option<String>,
+    ) -> Edges<'a> {
+        match file {
+            Some(path) => Edges::File(path),
+            None => Edges::One {
+                source: source.as_deref().unwrap_or_default(),
+                target: target.as_deref().unwrap_or_default(),
+                relation: relation.as_deref().unwrap_or_default(),
+                metadata,
+            },
+        }
+    }
+}
+
+/// Run one write statement through `kerai_cli::txn` and parse its JSON.
+fn write_json(client: &mut Client, what: &str, sql: &str, args: &[String]) -> Result<Value, String> {
+    let row = kerai_cli::txn::serializable(client, what, |tx| {
+        let params: Vec<&(dyn postgres::types::ToSql + Sync)> =
+            args.iter().map(|a| a as &(dyn postgres::types::ToSql + Sync)).collect();
+        tx.query_one(sql, &params)
+    })
+    .map_err(|e| progress::query_error(what, &e))?;
+    let text: String = row.get(0);
+    serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))
+}
+
+fn read_batch(path: &str) -> Result<String, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {path}: {e}"))?;
+    let batch: Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON in {path}: {e}"))?;
+    if !batch.is_array() {
+        return Err(format!("{path} must hold a JSON array of edges"));
+    }
+    Ok(batch.to_string())
+}
+
+pub fn add(client: &mut Client, edges: Edges, format: &OutputFormat) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let value = match edges {
+        Edges::One {
+            source,
+            target,
+            relation,
+            metadata,
+        } => write_json(
+            client,
+            "add_edge",
+            "SELECT kerai.add_edge($1::text::uuid, $2::text::uuid, $3, $4::text::jsonb)::text",
+            &[
+                source.to_string(),
+                target.to_string(),
+                relation.to_string(),
+                metadata.unwrap_or("{}").to_string(),
+            ],
+        )?,
+        Edges::File(path) => write_json(
+            client,
+            "add_edges",
+            "SELECT kerai.add_edges($1::text::jsonb)::text",
+            &[read_batch(path)?],
+        )?,
+    };
+
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ if value.get("added").is_some_and(Value::is_boolean) => {
+            if value["added"] == true {
+                println!("Added {} edge", value["relation"].as_str().unwrap_or(""));
+            } else {
+                println!("Edge already exists");
+            }
+        }
+        _ => println!(
+            "Added {} edges ({} already existed)",
+            value["added"], value["existing"]
+        ),
+    }
+    Ok(())
+}
+
+pub fn remove(client: &mut Client, edges: Edges, format: &OutputFormat) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let value = match edges {
+        Edges::One {
+            source,
+            target,
+            relation,
+            ..
+        } => write_json(
+            client,
+            "remove_edge",
+            "SELECT kerai.remove_edge($1::text::uuid, $2::text::uuid, $3)::text",
+            &[source.to_string(), target.to_string(), relation.to_string()],
+        )?,
+        Edges::File(path) => write_json(
+            client,
+            "remove_edges",
+            "SELECT kerai.remove_edges($1::text::jsonb)::text",
+            &[read_batch(path)?],
+        )?,
+    };
+
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ if value.get("removed").is_some_and(Value::is_boolean) => {
+            if value["removed"] == true {
+                println!("Removed {} edge", value["relation"].as_str().unwrap_or(""));
+            } else {
+                println!("No such edge");
+            }
+        }
+        _ => println!(
+            "Removed {} edges ({} not found)",
+            value["removed"], value["missing"]
+        ),
+    }
+    Ok(())
+}
+
+pub fn relations(client: &mut Client, format: &OutputFormat) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let row = client
+        .query_one("SELECT kerai.list_relations()::text", &[])
+        .map_err(|e| format!("list_relations failed: {e}"))?;
+    let text: String = row.get(0);
+    let value: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let kinds = |v: &Value| match v.as_array() {
+        Some(kinds) => kinds
+            .iter()
+            .filter_map(|k| k.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        None => "any".to_string(),
+    };
+    let columns = vec![
+        "relation".into(),
+        "source_kinds".into(),
+        "target_kinds".into(),
+        "edges".into(),
+        "description".into(),
+    ];
+    let rows: Vec<Vec<String>> = value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|r| {
+            vec![
+                r["relation"].as_str().unwrap_or("").to_string(),
+                kinds(&r["source_kinds"]),
+                kinds(&r["target_kinds"]),
+                r["edges"].to_string(),
+                r["description"].as_str().unwrap_or("").to_string(),
+            ]
+        })
+        .collect();
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
@@ -1,105 +1,567 @@
-use postgres::Client;
+use postgres::{Client, GenericClient};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
 
+use similar::TextDiff;
+
+use super::diff::resolve_file;
 use crate::config;
 
-pub fn run(client: &mut Client, file: Option<&str>) -> Result<(), String> {
-    match file {
-        Some(filename) => checkout_file(client, filename),
-        None => checkout_crate(client),
+/// What to do with reconstructed sources.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Print them to stdout.
+    Print,
+    /// Show a diff of what writing them would change on disk.
+    DryRun,
+    /// Write them to disk.
+    Write,
+}
+
+/// A file node to reconstruct and where it lives relative to the output root.
+struct Target {
+    id: uuid::Uuid,
+    rel_path: String,
+}
+
+pub fn run(
+    client: &mut Client,
+    file: Option<&str>,
+    mode: Mode,
+    out_dir: Option<&str>,
+    snapshot: Option<&str>,
+) -> Result<(), String> {
+    if let Some(name) = snapshot {
+        // The snapshot goes back into kerai.nodes only until the rollback
+        let mut tx = client
+            .transaction()
+            .map_err(|e| format!("Transaction failed: {e}"))?;
+        let targets = snapshot_targets(&mut tx, name)?;
+        let checked_out = check_out(&mut tx, &targets, mode, out_dir);
+        tx.rollback().map_err(|e| format!("Rollback failed: {e}"))?;
+        return checked_out;
     }
+
+    let targets = match file {
+        Some(file) => vec![file_target(client, file)?],
+        None => project_targets(client)?,
+    };
+    check_out(client, &targets, mode, out_dir)
 }
 
-fn checkout_file(client: &mut Client, filename: &str) -> Result<(), String> {
-    // Find the file node by name
-    let row = client
-        .query_opt(
-            "SELECT id FROM kerai.nodes WHERE kind = 'file' AND metadata->>'filename' = $1",
-            &[&filename],
-        )
-        .map_err(|e| format!("Query failed: {e}"))?
-        .ok_or_else(|| format!("File node not found: {filename}"))?;
+/// Reconstruct `targets` and print, diff or write them as `mode` says.
+fn check_out(
+    client: &mut impl GenericClient,
+    targets: &[Target],
+    mode: Mode,
+    out_dir: Option<&str>,
+) -> Result<(), String> {
+    if targets.is_empty() {
+        println!("No parsed files to check out.");
+        return Ok(());
+    }
 
-    let file_id: uuid::Uuid = row.get(0);
+    let root = match out_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => config::find_project_root()
+            .map(Ok)
+            .unwrap_or_else(std::env::current_dir)
+            .map_err(|e| format!("Cannot get cwd: {e}"))?,
+    };
 
-    let row = client
-        .query_one(
-            "SELECT kerai.reconstruct_file($1)",
-            &[&file_id],
-        )
-        .map_err(|e| format!("reconstruct_file failed: {e}"))?;
+    let mut changed = 0usize;
+    let mut created = 0usize;
+    let mut total_bytes = 0usize;
+    for target in targets {
+        let row = client
+            .query_one("SELECT kerai.reconstruct_file($1)", &[&target.id])
+            .map_err(|e| format!("reconstruct_file failed for {}: {e}", target.rel_path))?;
+        let content: String = row.get(0);
 
-    let content: String = row.get(0);
+        if mode == Mode::Print {
+            if targets.len() > 1 {
+                println!("==> {} <==", target.rel_path);
+            }
+            print!("{content}");
+            continue;
+        }
 
-    // Write to the filename in the current directory
-    let out_path = std::path::Path::new(filename);
-    if let Some(parent) = out_path.parent() {
-        if !parent.as_os_str().is_empty() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| format!("Failed to create directories: {e}"))?;
+        let Some(new) = place(&root, &target.rel_path, &content, mode)? else {
+            continue;
+        };
+        changed += 1;
+        if new {
+            created += 1;
+        }
+        if mode == Mode::Write {
+            total_bytes += content.len();
+            println!("  {} ({} bytes)", target.rel_path, content.len());
         }
     }
-    std::fs::write(out_path, &content)
-        .map_err(|e| format!("Failed to write {filename}: {e}"))?;
 
-    println!("Wrote {filename} ({} bytes)", content.len());
+    let unchanged = targets.len() - changed;
+    match mode {
+        Mode::Print => {}
+        Mode::DryRun => println!(
+            "{changed} of {} files would change ({created} new, {unchanged} unchanged) under {}",
+            targets.len(),
+            root.display()
+        ),
+        Mode::Write => println!(
+            "Checked out {changed} files ({total_bytes} bytes, {unchanged} unchanged) to {}",
+            root.display()
+        ),
+    }
     Ok(())
 }
 
-fn checkout_crate(client: &mut Client) -> Result<(), String> {
-    let project_root = config::find_project_root()
-        .ok_or("No .kerai/config.toml found. Run 'kerai pg.import' first.")?;
-
-    let config_path = project_root.join(".kerai").join("config.toml");
-    let content = std::fs::read_to_string(&config_path)
-        .map_err(|e| format!("Failed to read config: {e}"))?;
-    let cfg: config::ConfigFile = toml::from_str(&content)
-        .map_err(|e| format!("Invalid config: {e}"))?;
+/// Diff or write one reconstructed file at `rel_path` under `root`, as
+/// `mode` says. `None` when the file on disk already matches, otherwise
+/// whether it is new.
+fn place(root: &Path, rel_path: &str, content: &str, mode: Mode) -> Result<Option<bool>, String> {
+    let out_path = root.join(safe_relative(rel_path)?);
+    let on_disk = match std::fs::read_to_string(&out_path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to read {}: {e}", out_path.display())),
+    };
+    if on_disk.as_deref() == Some(content) {
+        return Ok(None);
+    }
 
-    let crate_name = cfg
-        .default
-        .as_ref()
-        .and_then(|d| d.crate_name.as_deref())
-        .ok_or("No crate_name in project config")?;
+    if mode == Mode::DryRun {
+        let old = on_disk.as_deref().unwrap_or("");
+        let old_header = if on_disk.is_some() {
+            format!("a/{rel_path}")
+        } else {
+            "/dev/null".to_string()
+        };
+        print!(
+            "{}",
+            TextDiff::from_lines(old, content)
+                .unified_diff()
+                .header(&old_header, &format!("b/{rel_path}"))
+        );
+    } else {
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create dirs for {rel_path}: {e}"))?;
+        }
+        std::fs::write(&out_path, content)
+            .map_err(|e| format!("Failed to write {}: {e}", out_path.display()))?;
+    }
+    Ok(Some(on_disk.is_none()))
+}
 
+/// A single file, by node id or parsed name, at its recorded source path.
+fn file_target(client: &mut Client, file: &str) -> Result<Target, String> {
+    let id = resolve_file(client, file)?;
     let row = client
         .query_one(
-            "SELECT kerai.reconstruct_crate($1)::text",
-            &[&crate_name],
+            "SELECT id, COALESCE(metadata->>'source_path', content) FROM kerai.nodes \
+             WHERE id = $1::text::uuid AND deleted_at IS NULL",
+            &[&id],
         )
-        .map_err(|e| format!("reconstruct_crate failed: {e}"))?;
+        .map_err(|e| format!("File lookup failed: {e}"))?;
+    Ok(Target {
+        id: row.get(0),
+        rel_path: row.get(1),
+    })
+}
 
+/// The files of snapshot `name`, restored into kerai.nodes for the rest of
+/// the transaction.
+fn snapshot_targets(client: &mut impl GenericClient, name: &str) -> Result<Vec<Target>, String> {
+    let row = client
+        .query_one("SELECT kerai.restore_snapshot($1)::text", &[&name])
+        .map_err(|e| format!("restore_snapshot failed: {e}"))?;
     let text: String = row.get(0);
-    let value: serde_json::Value =
-        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
-
-    let files = value["files"]
+    let restored: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+    restored["files"]
         .as_array()
-        .ok_or("Expected 'files' array in response")?;
+        .into_iter()
+        .flatten()
+        .map(|file| {
+            let id = file["id"].as_str().unwrap_or_default();
+            Ok(Target {
+                id: id.parse().map_err(|e| format!("Bad node id {id}: {e}"))?,
+                rel_path: file["path"].as_str().unwrap_or_default().to_string(),
+            })
+        })
+        .collect()
+}
 
-    let mut total_bytes = 0usize;
-    for file_obj in files {
-        let filename = file_obj["filename"]
-            .as_str()
-            .ok_or("Missing filename in response")?;
-        let file_content = file_obj["content"]
-            .as_str()
-            .ok_or("Missing content in response")?;
-
-        let out_path = project_root.join(filename);
-        if let Some(parent) = out_path.parent() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| format!("Failed to create dirs for {filename}: {e}"))?;
-        }
-        std::fs::write(&out_path, file_content)
-            .map_err(|e| format!("Failed to write {filename}: {e}"))?;
+/// Every file of the current project: files of its crate plus files
+/// committed with a source path. Where several nodes claim one path the
+/// newest wins.
+fn project_targets(client: &mut impl GenericClient) -> Result<Vec<Target>, String> {
+    let crate_name = config::find_project_root()
+        .map(|root| root.join(".kerai").join("config.toml"))
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| toml::from_str::<config::ConfigFile>(&text).ok())
+        .and_then(|cfg| cfg.default.and_then(|d| d.crate_name));
+
+    let rows = client
+        .query(
+            "SELECT DISTINCT ON (rel_path) id, rel_path FROM (
+                SELECT f.id, COALESCE(f.metadata->>'source_path', f.content) AS rel_path,
+                       f.created_at
+                FROM kerai.nodes f
+                WHERE f.kind = 'file' AND f.deleted_at IS NULL AND (
+                    f.parent_id IN (
+                        SELECT id FROM kerai.nodes
+                        WHERE kind = 'crate' AND content = $1 AND deleted_at IS NULL
+                    )
+                    OR (f.parent_id IS NULL AND f.metadata ? 'source_path')
+                )
+             ) files
+             ORDER BY rel_path, created_at DESC",
+            &[&crate_name],
+        )
+        .map_err(|e| format!("File lookup failed: {e}"))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| Target {
+            id: row.get(0),
+            rel_path: row.get(1),
+        })
+        .collect())
+}
 
-        total_bytes += file_content.len();
-        println!("  {filename} ({} bytes)", file_content.len());
+/// Reconstruct every project file as `rel_path -> content`.
+fn snapshot(client: &mut impl GenericClient) -> Result<BTreeMap<String, String>, String> {
+    let mut files = BTreeMap::new();
+    for target in project_targets(client)? {
+        let row = client
+            .query_one("SELECT kerai.reconstruct_file($1)", &[&target.id])
+            .map_err(|e| format!("reconstruct_file failed for {}: {e}", target.rel_path))?;
+        files.insert(target.rel_path, row.get(0));
     }
+    Ok(files)
+}
+
+/// Export the project into a git repository at `dir`, creating it if
+/// needed. Without `history` the current files are committed once. With
+/// it, the current branch's versions are replayed as one commit per
+/// Lamport timestamp: each commit holds the files as they stood after that
+/// batch and is authored by the instance that made it (further authors of
+/// the same batch go in `Co-authored-by` trailers). Batches replayed by
+/// `kerai import-git` keep their original author and message.
+///
+/// Parser writes are not versioned, so files parsed later than a batch
+/// still appear in its commit at their rewound state.
+pub fn git(client: &mut Client, dir: &str, history: bool) -> Result<(), String> {
+    let root = PathBuf::from(dir);
+    std::fs::create_dir_all(&root).map_err(|e| format!("Failed to create {dir}: {e}"))?;
+    if !root.join(".git").exists() {
+        run_git(&root, &["init", "-q"], &[])?;
+    }
+
+    let batches = if history {
+        let row = client
+            .query_one("SELECT kerai.version_batches()::text", &[])
+            .map_err(|e| format!("version_batches failed: {e}"))?;
+        let text: String = row.get(0);
+        let value: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+        value.as_array().cloned().unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    let local = client
+        .query_opt(
+            "SELECT name, key_fingerprint FROM kerai.instances WHERE is_self = true",
+            &[],
+        )
+        .map_err(|e| format!("Instance lookup failed: {e}"))?
+        .map(|row| git_identity(Some(row.get(0)), row.get(1)))
+        .unwrap_or_else(|| git_identity(None, "kerai"));
+
+    let mut written = BTreeMap::new();
+    if batches.is_empty() {
+        let files = snapshot(client)?;
+        write_tree(&root, &files, &mut written)?;
+        commit(&root, "Export from kerai", &local, &[], None)?;
+        println!("Exported {} files to {}", files.len(), root.display());
+        return Ok(());
+    }
+
+    // Batches replayed from git by `kerai import-git` keep their message
+    let imported: BTreeMap<i64, String> = client
+        .query("SELECT timestamp, message FROM kerai.git_imports", &[])
+        .map_err(|e| format!("git_imports lookup failed: {e}"))?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+
+    // The state before the first versioned change, from parsing alone
+    let first = batches[0]["timestamp"].as_i64().unwrap_or(0);
+    let files = rewound(client, first - 1)?;
+    if !files.is_empty() {
+        write_tree(&root, &files, &mut written)?;
+        commit(&root, "Initial state from kerai", &local, &[], None)?;
+    }
+
+    for batch in &batches {
+        let ts = batch["timestamp"].as_i64().unwrap_or(0);
+        let files = rewound(client, ts)?;
+        write_tree(&root, &files, &mut written)?;
 
+        let mut authors = batch["authors"].as_array().into_iter().flatten().map(|a| {
+            git_identity(
+                a["name"].as_str(),
+                a["fingerprint"].as_str().unwrap_or("unknown"),
+            )
+        });
+        let author = authors.next().unwrap_or_else(|| local.clone());
+        let co_authors: Vec<(String, String)> = authors.collect();
+        let message = imported.get(&ts).cloned().unwrap_or_else(|| {
+            format!(
+                "kerai: {} ops on {} nodes at timestamp {ts}",
+                batch["ops"], batch["nodes"]
+            )
+        });
+        commit(
+            &root,
+            &message,
+            &author,
+            &co_authors,
+            batch["created_at"].as_str(),
+        )?;
+    }
     println!(
-        "Checked out {} files ({total_bytes} bytes total)",
-        files.len()
+        "Exported {} commits ({} files) to {}",
+        batches.len(),
+        written.len(),
+        root.display()
     );
     Ok(())
 }
+
+/// The project's files as they stood at Lamport time `ts`, read inside a
+/// transaction that is rolled back.
+fn rewound(client: &mut Client, ts: i64) -> Result<BTreeMap<String, String>, String> {
+    let mut tx = client
+        .transaction()
+        .map_err(|e| format!("Transaction failed: {e}"))?;
+    tx.query_one("SELECT kerai.rewind_to($1)", &[&ts])
+        .map_err(|e| format!("rewind_to failed: {e}"))?;
+    let files = snapshot(&mut tx)?;
+    tx.rollback().map_err(|e| format!("Rollback failed: {e}"))?;
+    Ok(files)
+}
+
+/// Bring the working tree in line with `files`, removing files an earlier
+/// state wrote that no longer exist. `written` tracks what is on disk.
+fn write_tree(
+    root: &Path,
+    files: &BTreeMap<String, String>,
+    written: &mut BTreeMap<String, String>,
+) -> Result<(), String> {
+    for rel_path in written.keys().filter(|p| !files.contains_key(*p)) {
+        let path = root.join(safe_relative(rel_path)?);
+        std::fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove {}: {e}", path.display()))?;
+    }
+    for (rel_path, content) in files {
+        if written.get(rel_path) == Some(content) {
+            continue;
+        }
+        let path = root.join(safe_relative(rel_path)?);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create dirs for {rel_path}: {e}"))?;
+        }
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    }
+    *written = files.clone();
+    Ok(())
+}
+
+/// Git `(name, email)` for a version author. Authors imported from git are
+/// already `Name <email>`; instance fingerprints get a `@kerai` address
+/// and the instance name when known.
+fn git_identity(name: Option<&str>, author: &str) -> (String, String) {
+    if let Some((ident_name, rest)) = author.split_once(" <") {
+        if let Some(email) = rest.strip_suffix('>') {
+            return (ident_name.to_string(), email.to_string());
+        }
+    }
+    (
+        name.unwrap_or(author).to_string(),
+        format!("{author}@kerai"),
+    )
+}
+
+/// Stage everything and commit as `author` (`(name, email)`), who is also
+/// the committer so replaying the same history gives the same commits.
+fn commit(
+    root: &Path,
+    message: &str,
+    author: &(String, String),
+    co_authors: &[(String, String)],
+    date: Option<&str>,
+) -> Result<(), String> {
+    let mut message = message.trim_end().to_string();
+    if !co_authors.is_empty() {
+        message.push('\n');
+        for (name, email) in co_authors {
+            message.push_str(&format!("\nCo-authored-by: {name} <{email}>"));
+        }
+    }
+
+    let mut env = vec![
+        ("GIT_AUTHOR_NAME", author.0.as_str()),
+        ("GIT_AUTHOR_EMAIL", author.1.as_str()),
+        ("GIT_COMMITTER_NAME", author.0.as_str()),
+        ("GIT_COMMITTER_EMAIL", author.1.as_str()),
+    ];
+    if let Some(date) = date {
+        env.push(("GIT_AUTHOR_DATE", date));
+        env.push(("GIT_COMMITTER_DATE", date));
+    }
+    run_git(root, &["add", "-A"], &[])?;
+    run_git(
+        root,
+        &["commit", "-q", "--allow-empty", "-m", &message],
+        &env,
+    )
+}
+
+fn run_git(root: &Path, args: &[&str], env: &[(&str, &str)]) -> Result<(), String> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .envs(env.iter().copied())
+        .output()
+        .map_err(|e| format!("git failed: {e}"))?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+/// Reject recorded paths that would land outside the output directory:
+/// absolute ones, `..`, and drive prefixes (`C:`), which are checked on
+/// every platform since paths come from other instances too.
+pub(crate) fn safe_relative(rel_path: &str) -> Result<&Path, String> {
+    let path = Path::new(rel_path);
+    let drive = matches!(rel_path.as_bytes(), [letter, b':', ..] if letter.is_ascii_alphabetic());
+    if !drive
+        && path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        Ok(path)
+    } else {
+        Err(format!("Refusing to write outside the output directory: {rel_path}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty scratch directory for one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kerai-export-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn safe_relative_keeps_paths_inside() {
+        assert_eq!(
+            safe_relative("src/lib.rs").unwrap(),
+            Path::new("src/lib.rs")
+        );
+        assert_eq!(
+            safe_relative("./src/a..b.rs").unwrap(),
+            Path::new("./src/a..b.rs")
+        );
+        for bad in [
+            "../escape.rs",
+            "src/../../escape.rs",
+            "src/..",
+            "/etc/passwd",
+            "C:/Windows/system.ini",
+            "c:escape.rs",
+        ] {
+            assert!(safe_relative(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn safe_relative_rejects_windows_forms() {
+        for bad in [r"..\escape.rs", r"\\server\share\x.rs", r"\escape.rs"] {
+            assert!(safe_relative(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn checkout_round_trip() {
+        let root = scratch("round-trip");
+        let files = [("src/main.rs", "fn main() {}\n"), ("README.md", "# x\n")];
+
+        // A dry run reports the changes and writes nothing
+        for (rel, content) in files {
+            assert_eq!(
+                place(&root, rel, content, Mode::DryRun).unwrap(),
+                Some(true)
+            );
+        }
+        assert!(!root.join("src").exists());
+
+        for (rel, content) in files {
+            assert_eq!(place(&root, rel, content, Mode::Write).unwrap(), Some(true));
+            assert_eq!(std::fs::read_to_string(root.join(rel)).unwrap(), content);
+        }
+        // Checking the same sources out again changes nothing
+        for (rel, content) in files {
+            assert_eq!(place(&root, rel, content, Mode::Write).unwrap(), None);
+        }
+        assert_eq!(
+            place(&root, "README.md", "# y\n", Mode::Write).unwrap(),
+            Some(false)
+        );
+
+        // Nothing is written outside the root
+        let escape = root.join("../kerai-escape.rs");
+        for mode in [Mode::DryRun, Mode::Write] {
+            assert!(place(&root, "../kerai-escape.rs", "x", mode).is_err());
+            assert!(place(&root, "/tmp/kerai-escape.rs", "x", mode).is_err());
+        }
+        assert!(!escape.exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn write_tree_follows_each_state() {
+        let root = scratch("write-tree");
+        let mut written = BTreeMap::new();
+        let first = BTreeMap::from([
+            ("a.rs".to_string(), "1".to_string()),
+            ("dir/b.rs".to_string(), "2".to_string()),
+        ]);
+        write_tree(&root, &first, &mut written).unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("dir/b.rs")).unwrap(), "2");
+
+        let second = BTreeMap::from([("a.rs".to_string(), "3".to_string())]);
+        write_tree(&root, &second, &mut written).unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("a.rs")).unwrap(), "3");
+        assert!(!root.join("dir/b.rs").exists());
+        assert_eq!(written, second);
+
+        let bad = BTreeMap::from([("../kerai-escape.rs".to_string(), "x".to_string())]);
+        assert!(write_tree(&root, &bad, &mut written).is_err());
+        assert!(!root.join("../kerai-escape.rs").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+//! `kerai export-vault`: write markdown documents out as an Obsidian-style
+//! vault.
+//!
+//! The server reconstructs the notes and appends their links and backlinks;
+//! this writes them under `--out`. Attachments are stored as a size and
+//! hash only, so their bytes come from `--assets-from`, the original vault
+//! folder, when the file there still has the same hash.
+
+use std::fs;
+use std::path::Path;
+
+use postgres::Client;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::export::safe_relative;
+use crate::output::{print_json, OutputFormat};
+
+pub fn run(
+    client: &mut Client,
+    path: Option<&str>,
+    out: &str,
+    assets_from: Option<&str>,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let row = client
+        .query_one("SELECT kerai.export_vault($1)::text", &[&path])
+        .map_err(|e| format!("export_vault failed: {e}"))?;
+    let text: String = row.get(0);
+    let vault: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    let notes = vault["notes"].as_array().cloned().unwrap_or_default();
+    let attachments = vault["attachments"].as_array().cloned().unwrap_or_default();
+    if notes.is_empty() && attachments.is_empty() {
+        println!("No documents to export.");
+        return Ok(());
+    }
+
+    let root = Path::new(out);
+    for note in &notes {
+        let rel = note["path"].as_str().unwrap_or("");
+        write_file(root, rel, note["content"].as_str().unwrap_or("").as_bytes())?;
+    }
+    let mut copied = 0usize;
+    let mut missing = Vec::new();
+    for attachment in &attachments {
+        let rel = attachment["path"].as_str().unwrap_or("");
+        match assets_from.and_then(|dir| original_bytes(dir, attachment)) {
+            Some(bytes) => {
+                write_file(root, rel, &bytes)?;
+                copied += 1;
+            }
+            None => missing.push(rel.to_string()),
+        }
+    }
+
+    if let OutputFormat::Json = format {
+        let report = json!({
+            "out": out,
+            "notes": notes.len(),
+            "attachments": copied,
+            "missing": missing,
+            "links": vault["links"],
+            "backlinks": vault["backlinks"],
+        });
+        print_json(&report, format);
+        return Ok(());
+    }
+
+    println!(
+        "Exported {} notes and {copied} attachments to {out}; {} links, {} backlinks",
+        notes.len(),
+        vault["links"],
+        vault["backlinks"]
+    );
+    if !missing.is_empty() {
+        let hint = match assets_from {
+            Some(dir) => format!("not found in {dir} or changed since import"),
+            None => "pass --assets-from with the original vault folder".to_string(),
+        };
+        println!("{} attachments not written ({hint}):", missing.len());
+        for rel in &missing {
+            println!("  {rel}");
+        }
+    }
+    Ok(())
+}
+
+/// Write `bytes` to `rel` under `root`, creating its folders.
+fn write_file(root: &Path, rel: &str, bytes: &[u8]) -> Result<(), String> {
+    let dest = root.join(safe_relative(rel)?);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+    }
+    fs::write(&dest, bytes).map_err(|e| format!("Failed to write {}: {e}", dest.display()))
+}
+
+/// The attachment's file in the original vault, if it still has the hash
+/// recorded at import.
+fn original_bytes(dir: &str, attachment: &Value) -> Option<Vec<u8>> {
+    let vault_path = attachment["vault_path"].as_str()?;
+    let bytes = fs::read(Path::new(dir).join(safe_relative(vault_path).ok()?)).ok()?;
+    let sha256: String = Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    (attachment["sha256"].as_str() == Some(sha256.as_str())).then_some(bytes)
+}
@@ -1,19 +1,22 @@
 use postgres::Client;
 
-use crate::output::{print_rows, OutputFormat};
+use crate::output::{print_json, print_rows, OutputFormat};
 
 pub fn run(
     client: &mut Client,
     pattern: &str,
     kind: Option<&str>,
     limit: Option<i32>,
+    fts: bool,
     format: &OutputFormat,
 ) -> Result<(), String> {
+    let sql = if fts {
+        "SELECT kerai.search($1, $2, $3)::text"
+    } else {
+        "SELECT kerai.find($1, $2, $3)::text"
+    };
     let row = client
-        .query_one(
-            "SELECT kerai.find($1, $2, $3)::text",
-            &[&pattern, &kind, &limit],
-        )
+        .query_one(sql, &[&pattern, &kind, &limit])
         .map_err(|e| format!("find failed: {e}"))?;
 
     let text: String = row.get(0);
@@ -27,19 +30,86 @@ pub fn run(
         return Ok(());
     }
 
-    let columns = vec![
+    let mut columns = vec![
         "kind".into(),
         "content".into(),
         "path".into(),
         "id".into(),
     ];
+    if fts {
+        columns.insert(0, "rank".into());
+    }
+
+    let rows: Vec<Vec<String>> = arr
+        .iter()
+        .map(|n| {
+            let mut row = vec![
+                n["kind"].as_str().unwrap_or("").to_string(),
+                // Ranked results show the matching fragment
+                n["headline"]
+                    .as_str()
+                    .or(n["content"].as_str())
+                    .unwrap_or("")
+                    .to_string(),
+                n["path"].as_str().unwrap_or("").to_string(),
+                n["id"].as_str().unwrap_or("").to_string(),
+            ];
+            if fts {
+                row.insert(0, format!("{:.3}", n["rank"].as_f64().unwrap_or(0.0)));
+            }
+            row
+        })
+        .collect();
+
+    println!("{} match(es)", rows.len());
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
+
+/// Symbols whose names are within `max_distance` edits of `name`.
+pub fn fuzzy(
+    client: &mut Client,
+    name: &str,
+    max_distance: i32,
+    kind: Option<&str>,
+    limit: Option<i32>,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let row = client
+        .query_one(
+            "SELECT kerai.find_fuzzy($1, $2, $3, $4)::text",
+            &[&name, &max_distance, &kind, &limit],
+        )
+        .map_err(|e| format!("find_fuzzy failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    let arr = value.as_array().ok_or("Expected JSON array")?;
+
+    if arr.is_empty() {
+        println!("No symbols within {max_distance} edits of '{name}'.");
+        return Ok(());
+    }
+
+    let columns = vec![
+        "distance".into(),
+        "similarity".into(),
+        "kind".into(),
+        "name".into(),
+        "path".into(),
+        "id".into(),
+    ];
 
     let rows: Vec<Vec<String>> = arr
         .iter()
         .map(|n| {
             vec![
+                n["distance"].as_i64().unwrap_or(0).to_string(),
+                format!("{:.3}", n["similarity"].as_f64().unwrap_or(0.0)),
                 n["kind"].as_str().unwrap_or("").to_string(),
-                n["content"].as_str().unwrap_or("").to_string(),
+                n["name"].as_str().unwrap_or("").to_string(),
                 n["path"].as_str().unwrap_or("").to_string(),
                 n["id"].as_str().unwrap_or("").to_string(),
             ]
@@ -50,3 +120,65 @@ pub fn run(
     print_rows(&columns, &rows, format);
     Ok(())
 }
+
+/// Nodes whose content matches a Postgres regex, with file and line.
+pub fn grep(
+    client: &mut Client,
+    pattern: &str,
+    kind: Option<&str>,
+    path: Option<&str>,
+    limit: Option<i32>,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let row = client
+        .query_one(
+            "SELECT kerai.grep($1, $2, $3, $4)::text",
+            &[&pattern, &kind, &path, &limit],
+        )
+        .map_err(|e| format!("grep failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let arr = value.as_array().ok_or("Expected JSON array")?;
+
+    if arr.is_empty() {
+        println!("No matches found.");
+        return Ok(());
+    }
+
+    let columns = vec![
+        "file".into(),
+        "line".into(),
+        "col".into(),
+        "kind".into(),
+        "text".into(),
+        "path".into(),
+    ];
+
+    let rows: Vec<Vec<String>> = arr
+        .iter()
+        .map(|n| {
+            // Lines are unknown for nodes without location metadata
+            let line = n["line"].as_i64().map(|l| l.to_string()).unwrap_or_default();
+            vec![
+                n["file"].as_str().unwrap_or("").to_string(),
+                line,
+                n["col"].as_i64().unwrap_or(0).to_string(),
+                n["kind"].as_str().unwrap_or("").to_string(),
+                n["text"].as_str().unwrap_or("").trim().to_string(),
+                n["path"].as_str().unwrap_or("").to_string(),
+            ]
+        })
+        .collect();
+
+    println!("{} match(es)", rows.len());
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
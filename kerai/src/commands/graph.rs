@@ -0,0 +1,242 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use postgres::Client;
+use serde_json::Value;
+
+use crate::output::{print_json, print_rows, OutputFormat};
+
+pub fn run(
+    client: &mut Client,
+    root: Option<&str>,
+    cycles_only: bool,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let row = client
+        .query_one("SELECT kerai.find_cycles()::text", &[])
+        .map_err(|e| format!("find_cycles failed: {e}"))?;
+    let text: String = row.get(0);
+    let cycles: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+    let cycles = cycles.as_array().cloned().unwrap_or_default();
+
+    if cycles_only {
+        return print_cycles(&cycles, format);
+    }
+
+    let row = client
+        .query_one(
+            "SELECT kerai.dependency_graph($1::text::ltree)::text",
+            &[&root.unwrap_or("")],
+        )
+        .map_err(|e| format!("dependency_graph failed: {e}"))?;
+    let text: String = row.get(0);
+    let mut graph: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    // Only cycles lying wholly inside the graph are relevant to it
+    let ids: HashSet<&str> = graph["modules"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m["id"].as_str())
+        .collect();
+    let cycles: Vec<Value> = cycles
+        .into_iter()
+        .filter(|c| cycle_ids(c).iter().all(|id| ids.contains(id.as_str())))
+        .collect();
+
+    match format {
+        OutputFormat::Json => {
+            graph["cycles"] = Value::Array(cycles);
+            print_json(&graph, format);
+        }
+        OutputFormat::Dot => print!("{}", to_dot(&graph, &cycles)),
+        _ => {
+            let names = module_names(&graph["modules"]);
+            let name = |id: &Value| {
+                let id = id.as_str().unwrap_or("");
+                names.get(id).cloned().unwrap_or_else(|| id.to_string())
+            };
+            let edges = graph["edges"].as_array().cloned().unwrap_or_default();
+            if edges.is_empty() {
+                println!("No dependencies between modules.");
+                return Ok(());
+            }
+            let columns = vec![
+                "source".into(),
+                "target".into(),
+                "relation".into(),
+                "weight".into(),
+            ];
+            let rows: Vec<Vec<String>> = edges
+                .iter()
+                .map(|e| {
+                    vec![
+                        name(&e["source"]),
+                        name(&e["target"]),
+                        e["relation"].as_str().unwrap_or("").to_string(),
+                        e["weight"].as_i64().unwrap_or(0).to_string(),
+                    ]
+                })
+                .collect();
+            print_rows(&columns, &rows, format);
+            println!(
+                "{} module(s), {} edge(s)",
+                graph["modules"].as_array().map_or(0, |m| m.len()),
+                edges.len()
+            );
+            if !cycles.is_empty() {
+                println!();
+                print_cycles(&cycles, format)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_cycles(cycles: &[Value], format: &OutputFormat) -> Result<(), String> {
+    match format {
+        OutputFormat::Json => print_json(&Value::Array(cycles.to_vec()), format),
+        OutputFormat::Dot => {
+            // Just the modules and edges that take part in a cycle
+            let mut modules = Vec::new();
+            let mut edges = Vec::new();
+            for cycle in cycles {
+                let members = cycle["modules"].as_array().cloned().unwrap_or_default();
+                for (i, m) in members.iter().enumerate() {
+                    modules.push(m.clone());
+                    let next = &members[(i + 1) % members.len()];
+                    edges.push(serde_json::json!({
+                        "source": m["id"],
+                        "target": next["id"],
+                        "relation": "",
+                        "weight": 0,
+                    }));
+                }
+            }
+            let graph = serde_json::json!({ "modules": modules, "edges": edges });
+            print!("{}", to_dot(&graph, cycles));
+        }
+        _ => {
+            if cycles.is_empty() {
+                println!("No circular dependencies.");
+                return Ok(());
+            }
+            println!("Cycles ({}):", cycles.len());
+            for cycle in cycles {
+                let mut names: Vec<&str> = cycle["modules"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|m| m["name"].as_str().unwrap_or("?"))
+                    .collect();
+                if let Some(first) = names.first().copied() {
+                    names.push(first);
+                }
+                let relations: Vec<&str> = cycle["relations"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|r| r.as_str())
+                    .collect();
+                println!("  {}  ({})", names.join(" -> "), relations.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cycle_ids(cycle: &Value) -> Vec<String> {
+    cycle["modules"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m["id"].as_str().map(str::to_string))
+        .collect()
+}
+
+fn module_names(modules: &Value) -> HashMap<String, String> {
+    modules
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|m| {
+            Some((
+                m["id"].as_str()?.to_string(),
+                m["name"].as_str().unwrap_or("").to_string(),
+            ))
+        })
+        .collect()
+}
+
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Render a dependency graph as Graphviz. Parallel relations between the
+/// same pair of modules share one edge; modules outside the requested
+/// subtree are dashed and edges that close a cycle are red.
+fn to_dot(graph: &Value, cycles: &[Value]) -> String {
+    let mut cyclic: HashSet<(String, String)> = HashSet::new();
+    for cycle in cycles {
+        let ids = cycle_ids(cycle);
+        for (i, id) in ids.iter().enumerate() {
+            cyclic.insert((id.clone(), ids[(i + 1) % ids.len()].clone()));
+        }
+    }
+
+    let mut out = String::from("digraph kerai {\n    rankdir=LR;\n    node [shape=box];\n");
+
+    let mut seen = HashSet::new();
+    for m in graph["modules"].as_array().into_iter().flatten() {
+        let id = m["id"].as_str().unwrap_or("");
+        if !seen.insert(id.to_string()) {
+            continue;
+        }
+        let mut attrs = vec![format!(
+            "label={}",
+            dot_quote(m["name"].as_str().unwrap_or(id))
+        )];
+        if m["internal"] == false {
+            attrs.push("style=dashed".into());
+        }
+        out.push_str(&format!("    {} [{}];\n", dot_quote(id), attrs.join(", ")));
+    }
+
+    let mut pairs: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for e in graph["edges"].as_array().into_iter().flatten() {
+        let key = (
+            e["source"].as_str().unwrap_or("").to_string(),
+            e["target"].as_str().unwrap_or("").to_string(),
+        );
+        let relation = e["relation"].as_str().unwrap_or("");
+        let labels = pairs.entry(key).or_default();
+        if relation.is_empty() {
+            continue;
+        }
+        match e["weight"].as_i64().unwrap_or(1) {
+            1 => labels.push(relation.to_string()),
+            n => labels.push(format!("{relation} x{n}")),
+        }
+    }
+    for ((source, target), labels) in &pairs {
+        let mut attrs = Vec::new();
+        if !labels.is_empty() {
+            attrs.push(format!("label={}", dot_quote(&labels.join(", "))));
+        }
+        if cyclic.contains(&(source.clone(), target.clone())) {
+            attrs.push("color=red".into());
+        }
+        let attrs = if attrs.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", attrs.join(", "))
+        };
+        out.push_str(&format!(
+            "    {} -> {}{attrs};\n",
+            dot_quote(source),
+            dot_quote(target)
+        ));
+    }
+
+    out.push_str("}\n");
+    out
+}
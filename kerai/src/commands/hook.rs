@@ -0,0 +1,310 @@
+use std::path::Path;
+use std::process::Command;
+
+use postgres::Transaction;
+use serde_json::{json, Value};
+
+use crate::config;
+use crate::db;
+use crate::output::{print_json, OutputFormat};
+use crate::secrets;
+
+/// Files checked structurally: extension, parse function, root node kind,
+/// and the query reconstructing source from a root id.
+const LANGUAGES: &[(&str, &str, &str, &str)] = &[
+    (
+        "rs",
+        "parse_source",
+        "file",
+        "SELECT kerai.reconstruct_file_with_options($1::text::uuid, \
+         '{\"sort_imports\": false, \"order_derives\": false, \"suggestions\": false}'::jsonb)",
+    ),
+    (
+        "go",
+        "parse_go_source",
+        "file",
+        "SELECT kerai.reconstruct_go_file($1::text::uuid)",
+    ),
+    (
+        "c",
+        "parse_c_source",
+        "file",
+        "SELECT kerai.reconstruct_c_file($1::text::uuid)",
+    ),
+    (
+        "h",
+        "parse_c_source",
+        "file",
+        "SELECT kerai.reconstruct_c_file($1::text::uuid)",
+    ),
+    (
+        "md",
+        "parse_markdown",
+        "document",
+        "SELECT kerai.reconstruct_markdown($1::text::uuid)",
+    ),
+];
+
+/// Lint severities, lowest first.
+const SEVERITIES: &[&str] = &["info", "warning"];
+
+/// Pre-commit hook: check every staged file and exit 1 if any check fails.
+///
+/// Staged content (the index, not the working tree) is parsed inside a
+/// transaction that is rolled back, so the shared graph is never touched.
+/// Per file: the parse must succeed, reconstruct-then-reparse must
+/// reproduce the same source, and no lint suggestion may reach `fail_on`
+/// severity. Every text file is also scanned for credentials. Without a
+/// database the structural checks are skipped with a warning; the secrets
+/// scan still runs.
+pub fn pre_commit(
+    profile_name: &str,
+    db_override: Option<&str>,
+    fail_on: &str,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let staged = git(&[
+        "diff",
+        "--cached",
+        "--name-only",
+        "-z",
+        "--diff-filter=ACMR",
+    ])?;
+    let paths: Vec<&str> = staged.split('\0').filter(|p| !p.is_empty()).collect();
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let threshold = SEVERITIES.iter().position(|s| *s == fail_on);
+
+    let profile = config::load_config(profile_name);
+    let mut client = match db::connect(&profile, db_override) {
+        Ok(client) => Some(client),
+        Err(e) => {
+            eprintln!("kerai: structural checks skipped ({e})");
+            None
+        }
+    };
+    let mut tx = match client.as_mut() {
+        Some(c) => Some(
+            c.transaction()
+                .map_err(|e| format!("Transaction failed: {e}"))?,
+        ),
+        None => None,
+    };
+
+    let mut report = Vec::new();
+    for (n, path) in paths.iter().enumerate() {
+        let Ok(bytes) = git_bytes(&["show", &format!(":{path}")]) else {
+            continue;
+        };
+        let Ok(text) = String::from_utf8(bytes) else {
+            continue; // binary
+        };
+
+        let mut diagnostics = Vec::new();
+        for finding in secrets::scan(&text) {
+            diagnostics.push(json!({
+                "check": "secret",
+                "severity": "error",
+                "line": finding.line,
+                "message": format!("possible {} {}", finding.rule, finding.excerpt),
+            }));
+        }
+
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
+        let language = LANGUAGES.iter().find(|(e, ..)| *e == ext);
+        if let (Some(language), Some(tx)) = (language, tx.as_mut()) {
+            let name = format!("kerai-hook-{}-{n}.{ext}", std::process::id());
+            let mut savepoint = tx
+                .transaction()
+                .map_err(|e| format!("Savepoint failed: {e}"))?;
+            match check_structure(&mut savepoint, language, &name, &text, threshold) {
+                Ok(found) => diagnostics.extend(found),
+                Err(e) => diagnostics.push(json!({
+                    "check": "parse",
+                    "severity": "error",
+                    "line": null,
+                    "message": e,
+                })),
+            }
+            savepoint
+                .rollback()
+                .map_err(|e| format!("Rollback failed: {e}"))?;
+        }
+
+        if !diagnostics.is_empty() {
+            report.push(json!({ "file": path, "diagnostics": diagnostics }));
+        }
+    }
+    if let Some(tx) = tx {
+        tx.rollback().map_err(|e| format!("Rollback failed: {e}"))?;
+    }
+
+    let errors = report
+        .iter()
+        .flat_map(|f| f["diagnostics"].as_array().cloned().unwrap_or_default())
+        .filter(|d| d["severity"] == "error")
+        .count();
+
+    match format {
+        OutputFormat::Json => print_json(&Value::Array(report), format),
+        _ => {
+            for file in &report {
+                eprintln!("{}", file["file"].as_str().unwrap_or(""));
+                for d in file["diagnostics"].as_array().into_iter().flatten() {
+                    let line = d["line"]
+                        .as_u64()
+                        .map(|l| format!(":{l}"))
+                        .unwrap_or_default();
+                    eprintln!(
+                        "  {:<7} {}{line}  {}",
+                        d["severity"].as_str().unwrap_or(""),
+                        d["check"].as_str().unwrap_or(""),
+                        d["message"].as_str().unwrap_or("")
+                    );
+                }
+            }
+            if errors > 0 {
+                eprintln!("kerai: {errors} problem(s) in staged files; commit blocked");
+            }
+        }
+    }
+    if errors > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Parse `text` as `name`, then check round-trip stability and lint
+/// findings. Returns diagnostics; `Err` means the content does not parse.
+fn check_structure(
+    tx: &mut Transaction,
+    (_, parse_fn, root_kind, reconstruct): &(&str, &str, &str, &str),
+    name: &str,
+    text: &str,
+    threshold: Option<usize>,
+) -> Result<Vec<Value>, String> {
+    let parse = |tx: &mut Transaction, source: &str, name: &str| -> Result<String, String> {
+        tx.query_one(
+            &format!("SELECT kerai.{parse_fn}($1, $2)::text"),
+            &[&source, &name],
+        )
+        .map_err(|e| format!("does not parse: {e}"))?;
+        let row = tx
+            .query_opt(
+                "SELECT id::text FROM kerai.nodes WHERE kind = $1 AND content = $2",
+                &[root_kind, &name],
+            )
+            .map_err(|e| format!("does not parse: {e}"))?
+            .ok_or("does not parse")?;
+        Ok(row.get(0))
+    };
+    let rebuild = |tx: &mut Transaction, root: &str| -> Result<String, String> {
+        let row = tx
+            .query_one(*reconstruct, &[&root])
+            .map_err(|e| format!("reconstruct failed: {e}"))?;
+        Ok(row.get(0))
+    };
+
+    let root = parse(tx, text, name)?;
+    let mut diagnostics = Vec::new();
+
+    // Lint findings are suggestion nodes under the root
+    let rows = tx
+        .query(
+            "SELECT content, position, metadata->>'rule', metadata->>'severity'
+             FROM kerai.nodes WHERE parent_id = $1::text::uuid AND kind = 'suggestion'
+             ORDER BY position",
+            &[&root],
+        )
+        .map_err(|e| format!("lint query failed: {e}"))?;
+    for row in rows {
+        let message: Option<String> = row.get(0);
+        let line: i32 = row.get(1);
+        let rule: Option<String> = row.get(2);
+        let severity: Option<String> = row.get(3);
+        let severity = severity.unwrap_or_else(|| "info".into());
+        let blocking = threshold
+            .is_some_and(|t| SEVERITIES.iter().position(|s| *s == severity).unwrap_or(0) >= t);
+        diagnostics.push(json!({
+            "check": format!("lint {}", rule.unwrap_or_default()),
+            "severity": if blocking { "error".to_string() } else { severity },
+            "line": line,
+            "message": message.unwrap_or_default(),
+        }));
+    }
+
+    // Round trip: reconstructing the reparsed reconstruction must be a fixed point
+    let first = rebuild(tx, &root)?;
+    let second_root = parse(tx, &first, &format!("{name}.roundtrip"))
+        .map_err(|e| format!("reconstruction {e}"))?;
+    let second = rebuild(tx, &second_root)?;
+    if let Some(line) = first_difference(&first, &second) {
+        diagnostics.push(json!({
+            "check": "round-trip",
+            "severity": "error",
+            "line": line,
+            "message": "reconstructed source changes when parsed again",
+        }));
+    }
+
+    Ok(diagnostics)
+}
+
+/// 1-based number of the first line where `a` and `b` differ.
+fn first_difference(a: &str, b: &str) -> Option<usize> {
+    if a == b {
+        return None;
+    }
+    let mut lines = a.lines().zip(b.lines());
+    let same = lines.by_ref().take_while(|(x, y)| x == y).count();
+    Some(same + 1)
+}
+
+/// Install `kerai hook pre-commit` as the repository's pre-commit hook.
+/// An existing hook that does not call kerai is left alone.
+pub fn install() -> Result<(), String> {
+    let hooks = git(&["rev-parse", "--git-path", "hooks"])?;
+    let hook = Path::new(hooks.trim()).join("pre-commit");
+    if let Ok(existing) = std::fs::read_to_string(&hook) {
+        if existing.contains("kerai hook pre-commit") {
+            println!("Pre-commit hook already installed at {}", hook.display());
+            return Ok(());
+        }
+        return Err(format!(
+            "{} already exists; add 'kerai hook pre-commit' to it by hand",
+            hook.display()
+        ));
+    }
+
+    std::fs::create_dir_all(hook.parent().unwrap_or(Path::new(".")))
+        .map_err(|e| format!("Cannot create {}: {e}", hooks.trim()))?;
+    std::fs::write(&hook, "#!/bin/sh\nexec kerai hook pre-commit\n")
+        .map_err(|e| format!("Cannot write {}: {e}", hook.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("Cannot make {} executable: {e}", hook.display()))?;
+    }
+    println!("Installed pre-commit hook at {}", hook.display());
+    Ok(())
+}
+
+fn git(args: &[&str]) -> Result<String, String> {
+    git_bytes(args).map(|out| String::from_utf8_lossy(&out).into_owned())
+}
+
+fn git_bytes(args: &[&str]) -> Result<Vec<u8>, String> {
+    let out = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| format!("git failed: {e}"))?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+    }
+    Ok(out.stdout)
+}
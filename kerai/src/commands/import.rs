@@ -1,7 +1,9 @@
 use postgres::Client;
+use serde_json::json;
 use std::path::Path;
 
 use crate::output::{print_json, OutputFormat};
+use crate::progress::{self, Job};
 
 pub fn run(
     client: &mut Client,
@@ -46,17 +48,35 @@ pub fn run(
     // Ensure extension is loaded
     crate::db::ensure_extension(client)?;
 
-    // Parse the crate
-    let row = client
-        .query_one(
-            "SELECT kerai.parse_crate($1)::text",
-            &[&project_str.as_ref()],
-        )
-        .map_err(|e| format!("parse_crate failed: {e}"))?;
+    // Parse every source file under the project; the server reports each
+    // file as it goes
+    let job = Job::start(client, "import", "Parsing", None);
+    progress::cancel_on_interrupt(client);
+    let result = client.query_one(
+        "SELECT kerai.parse_project($1)::text",
+        &[&project_str.as_ref()],
+    );
+    let row = match result {
+        Ok(row) => row,
+        Err(e) => {
+            let error = progress::query_error("parse_project", &e);
+            let partial = json!({ "project": crate_name, "rolled_back": true });
+            let (cancelled, at) = job.fail(client, &error, partial);
+            if !cancelled {
+                return Err(error);
+            }
+            let files = at.total.map(|t| format!(" of {t}")).unwrap_or_default();
+            return Err(format!(
+                "Import cancelled after {}{files} files; nothing was kept",
+                at.done
+            ));
+        }
+    };
 
     let text: String = row.get(0);
     let value: serde_json::Value =
         serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+    job.finish(client, "done", &value);
 
     print_json(&value, format);
     Ok(())
@@ -0,0 +1,193 @@
+//! `kerai import-git`: replay a git repository's history into kerai.versions.
+//!
+//! Commits are sent oldest first, one statement each, so an interrupted
+//! import keeps what it finished and a second run carries on from there:
+//! commits the server has already replayed are skipped.
+
+use std::path::Path;
+use std::process::Command;
+
+use postgres::Client;
+use serde_json::{json, Value};
+
+use crate::output::{print_json, OutputFormat};
+use crate::progress::{self, Job};
+
+#[derive(Default)]
+struct Tally {
+    commits: usize,
+    skipped_commits: usize,
+    parsed: u64,
+    deleted: u64,
+    versions: u64,
+}
+
+impl Tally {
+    fn to_json(&self) -> Value {
+        json!({
+            "commits": self.commits,
+            "already_imported": self.skipped_commits,
+            "files_parsed": self.parsed,
+            "files_deleted": self.deleted,
+            "versions": self.versions,
+        })
+    }
+
+    fn add(&mut self, result: &Value) {
+        if result["status"] == "imported" {
+            self.commits += 1;
+        } else {
+            self.skipped_commits += 1;
+        }
+        self.parsed += result["parsed"].as_u64().unwrap_or(0);
+        self.deleted += result["deleted"].as_u64().unwrap_or(0);
+        self.versions += result["versions"].as_u64().unwrap_or(0);
+    }
+}
+
+pub fn run(
+    client: &mut Client,
+    repo: &str,
+    rev: &str,
+    limit: Option<usize>,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let repo = Path::new(repo);
+    crate::db::ensure_extension(client)?;
+
+    let row = client
+        .query_one("SELECT kerai.git_imported_shas()::text", &[])
+        .map_err(|e| format!("git_imported_shas failed: {e}"))?;
+    let text: String = row.get(0);
+    let imported: Vec<String> =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    let listed = git(repo, &["rev-list", "--reverse", "--topo-order", rev])?;
+    let mut pending: Vec<&str> = listed
+        .lines()
+        .filter(|sha| !imported.iter().any(|i| i == sha))
+        .collect();
+    let skipped = listed.lines().count() - pending.len();
+    if let Some(limit) = limit {
+        pending.truncate(limit);
+    }
+
+    let mut tally = Tally {
+        skipped_commits: skipped,
+        ..Default::default()
+    };
+    let total = pending.len() as i32;
+    let job = Job::start(client, "import-git", "Importing commits", Some(total));
+    progress::cancel_on_interrupt(client);
+    for (done, sha) in pending.iter().enumerate() {
+        let imported = job
+            .step(client, done as i32, &sha[..sha.len().min(12)])
+            .and_then(|()| import_commit(client, repo, sha));
+        match imported {
+            Ok(result) => tally.add(&result),
+            Err(e) => {
+                let (cancelled, _) = job.fail(client, &e, tally.to_json());
+                if !cancelled {
+                    return Err(e);
+                }
+                return Err(format!(
+                    "Import cancelled after {} of {total} commits; run it again to carry on",
+                    tally.commits
+                ));
+            }
+        }
+    }
+    job.finish(client, "done", &tally.to_json());
+
+    match format {
+        OutputFormat::Json => print_json(&tally.to_json(), format),
+        _ => println!(
+            "Imported {} commits ({} already imported): {} files parsed, {} deleted, {} versions",
+            tally.commits, tally.skipped_commits, tally.parsed, tally.deleted, tally.versions
+        ),
+    }
+    Ok(())
+}
+
+/// Send one commit, with the contents of every file it added or changed.
+fn import_commit(client: &mut Client, repo: &Path, sha: &str) -> Result<Value, String> {
+    let meta = git(
+        repo,
+        &["show", "-s", "--format=%an%x00%ae%x00%cI%x00%B", sha],
+    )?;
+    let mut fields = meta.splitn(4, '\0');
+    let mut field = || fields.next().unwrap_or("").to_string();
+    let (author, email, committed_at, message) = (field(), field(), field(), field());
+
+    // First-parent diff, so a merge brings in what it changed on the mainline
+    let changes = git(
+        repo,
+        &[
+            "diff-tree",
+            "--root",
+            "-r",
+            "-z",
+            "--no-commit-id",
+            "--name-status",
+            "-m",
+            "--first-parent",
+            sha,
+        ],
+    )?;
+    let mut files = Vec::new();
+    let mut parts = changes.split('\0').filter(|p| !p.is_empty());
+    while let Some(status) = parts.next() {
+        let Some(path) = parts.next() else { break };
+        match status.chars().next() {
+            Some('D') => files.push(json!({ "path": path, "deleted": true })),
+            Some('A' | 'M' | 'T') => {
+                let blob = git_bytes(repo, &["cat-file", "blob", &format!("{sha}:{path}")])?;
+                // Binary files have no parser anyway, and Postgres text
+                // cannot hold a NUL
+                match String::from_utf8(blob) {
+                    Ok(source) if !source.contains('\0') => {
+                        files.push(json!({ "path": path, "source": source }))
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let commit = json!({
+        "sha": sha,
+        "author": author,
+        "email": email,
+        "committed_at": committed_at.trim(),
+        "message": message.trim_end(),
+        "files": files,
+    })
+    .to_string();
+    let row = kerai_cli::txn::serializable(client, "import_git_commit", |tx| {
+        tx.query_one(
+            "SELECT kerai.import_git_commit($1::text::jsonb)::text",
+            &[&commit],
+        )
+    })
+    .map_err(|e| progress::query_error("import_git_commit", &e))?;
+    let text: String = row.get(0);
+    serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, String> {
+    git_bytes(repo, args).map(|out| String::from_utf8_lossy(&out).into_owned())
+}
+
+fn git_bytes(repo: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .map_err(|e| format!("git failed: {e}"))?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+    }
+    Ok(out.stdout)
+}
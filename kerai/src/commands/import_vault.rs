@@ -0,0 +1,192 @@
+//! `kerai import-vault`: load an Obsidian-style markdown vault.
+//!
+//! Every file is sent in its own statement, notes with their text and
+//! attachments with just their size and SHA-256, then the server resolves
+//! the `[[wikilinks]]` of every note at once. Hidden files and folders,
+//! such as `.obsidian` and `.trash`, are skipped.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use postgres::Client;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::output::{print_json, OutputFormat};
+use crate::progress::{self, Job};
+
+#[derive(Default)]
+struct Tally {
+    notes: usize,
+    assets: usize,
+    folders: u64,
+    nodes: u64,
+}
+
+impl Tally {
+    fn to_json(&self) -> Value {
+        json!({
+            "notes": self.notes,
+            "assets": self.assets,
+            "folders": self.folders,
+            "nodes": self.nodes,
+        })
+    }
+
+    fn add(&mut self, result: &Value) {
+        if result["kind"] == "asset" {
+            self.assets += 1;
+        } else {
+            self.notes += 1;
+        }
+        self.folders += result["folders"].as_u64().unwrap_or(0);
+        self.nodes += result["nodes"].as_u64().unwrap_or(0);
+    }
+}
+
+pub fn run(
+    client: &mut Client,
+    dir: &str,
+    name: Option<&str>,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let root = Path::new(dir);
+    if !root.is_dir() {
+        return Err(format!("{dir} is not a directory"));
+    }
+    let vault = match name {
+        Some(name) => name.to_string(),
+        None => root
+            .canonicalize()
+            .ok()
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+            .ok_or_else(|| format!("Cannot name a vault after {dir}; pass --name"))?,
+    };
+    crate::db::ensure_extension(client)?;
+
+    let mut files = Vec::new();
+    collect_files(root, &mut files)?;
+    files.sort();
+
+    let mut tally = Tally::default();
+    let total = files.len() as i32;
+    let job = Job::start(client, "import-vault", "Importing vault", Some(total));
+    progress::cancel_on_interrupt(client);
+    for (done, file) in files.iter().enumerate() {
+        let rel = relative_path(root, file);
+        let imported = job
+            .step(client, done as i32, &rel)
+            .and_then(|()| import_file(client, &vault, file, &rel));
+        match imported {
+            Ok(result) => tally.add(&result),
+            Err(e) => {
+                let (cancelled, _) = job.fail(client, &e, tally.to_json());
+                if !cancelled {
+                    return Err(e);
+                }
+                return Err(format!(
+                    "Import cancelled after {} of {total} files; run it again to finish",
+                    tally.notes + tally.assets
+                ));
+            }
+        }
+    }
+    job.finish(client, "done", &tally.to_json());
+
+    let row = kerai_cli::txn::serializable(client, "resolve_vault_links", |tx| {
+        tx.query_one("SELECT kerai.resolve_vault_links($1)::text", &[&vault])
+    })
+    .map_err(|e| progress::query_error("resolve_vault_links", &e))?;
+    let text: String = row.get(0);
+    let links: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        let mut report = tally.to_json();
+        report["vault"] = json!(vault);
+        report["links"] = links["links"].clone();
+        report["unresolved"] = links["unresolved"].clone();
+        print_json(&report, format);
+        return Ok(());
+    }
+
+    let unresolved = links["unresolved"].as_array().cloned().unwrap_or_default();
+    println!(
+        "Imported vault {vault}: {} notes, {} attachments, {} new folders; {} links, {} unresolved",
+        tally.notes,
+        tally.assets,
+        tally.folders,
+        links["links"],
+        unresolved.len()
+    );
+    for link in &unresolved {
+        println!(
+            "  {}: [[{}]]",
+            link["path"].as_str().unwrap_or(""),
+            link["target"].as_str().unwrap_or("")
+        );
+    }
+    Ok(())
+}
+
+/// Every file under `dir`, leaving out hidden files and folders.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {e}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {e}", dir.display()))?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// `file` relative to the vault root, `/`-separated.
+fn relative_path(root: &Path, file: &Path) -> String {
+    file.strip_prefix(root)
+        .unwrap_or(file)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Send one file: a note's text, or an attachment's size and hash. A
+/// `.md` file that is not UTF-8 goes in as an attachment.
+fn import_file(client: &mut Client, vault: &str, file: &Path, rel: &str) -> Result<Value, String> {
+    let bytes = fs::read(file).map_err(|e| format!("Failed to read {rel}: {e}"))?;
+    let is_note = rel.to_lowercase().ends_with(".md");
+    let entry = match String::from_utf8(bytes) {
+        // Postgres text cannot hold a NUL
+        Ok(source) if is_note && !source.contains('\0') => {
+            json!({ "path": rel, "source": source })
+        }
+        Ok(source) => attachment(rel, source.as_bytes()),
+        Err(e) => attachment(rel, e.as_bytes()),
+    }
+    .to_string();
+
+    let row = kerai_cli::txn::serializable(client, "import_vault_file", |tx| {
+        tx.query_one(
+            "SELECT kerai.import_vault_file($1, $2::text::jsonb)::text",
+            &[&vault, &entry],
+        )
+    })
+    .map_err(|e| progress::query_error("import_vault_file", &e))?;
+    let text: String = row.get(0);
+    serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))
+}
+
+fn attachment(rel: &str, bytes: &[u8]) -> Value {
+    let sha256: String = Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    json!({ "path": rel, "size": bytes.len(), "sha256": sha256 })
+}
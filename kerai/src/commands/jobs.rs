@@ -0,0 +1,193 @@
+use postgres::Client;
+
+use crate::output::{print_json, print_rows, OutputFormat};
+
+pub fn list(client: &mut Client, limit: i32, format: &OutputFormat) -> Result<(), String> {
+    let row = client
+        .query_one("SELECT kerai.list_jobs($1)::text", &[&limit])
+        .map_err(|e| format!("list_jobs failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let arr = value.as_array().ok_or("Expected JSON array")?;
+    if arr.is_empty() {
+        println!("No jobs found.");
+        return Ok(());
+    }
+
+    let columns = vec![
+        "id".into(),
+        "kind".into(),
+        "status".into(),
+        "progress".into(),
+        "current".into(),
+        "pid".into(),
+        "started_at".into(),
+    ];
+    let rows: Vec<Vec<String>> = arr
+        .iter()
+        .map(|j| {
+            let done = j["done"].as_i64().unwrap_or(0);
+            let progress = match j["total"].as_i64() {
+                Some(total) => format!("{done}/{total}"),
+                None => done.to_string(),
+            };
+            let mut status = j["status"].as_str().unwrap_or("").to_string();
+            if status == "running" && j["cancel_requested"] == true {
+                status.push_str(" (cancelling)");
+            }
+            vec![
+                j["id"]
+                    .as_str()
+                    .unwrap_or("")
+                    .chars()
+                    .take(8)
+                    .collect::<String>(),
+                j["kind"].as_str().unwrap_or("").to_string(),
+                status,
+                progress,
+                j["current"].as_str().unwrap_or("").to_string(),
+                j["pid"].as_i64().map(|p| p.to_string()).unwrap_or_default(),
+                j["started_at"].as_str().unwrap_or("").to_string(),
+            ]
+        })
+        .collect();
+
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
+
+pub fn cancel(client: &mut Client, id: &str, format: &OutputFormat) -> Result<(), String> {
+    let row = client
+        .query_one("SELECT kerai.cancel_job($1)::text", &[&id])
+        .map_err(|e| format!("cancel_job failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ => println!(
+            "Cancelling {} job {} (backend {})",
+            value["kind"].as_str().unwrap_or(""),
+            value["id"].as_str().unwrap_or(""),
+            value["pid"]
+        ),
+    }
+    Ok(())
+}
+
+pub fn summary(client: &mut Client, hours: i32, format: &OutputFormat) -> Result<(), String> {
+    let row = client
+        .query_one("SELECT kerai.jobs_summary($1)::text", &[&hours])
+        .map_err(|e| format!("jobs_summary failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let arr = value.as_array().ok_or("Expected JSON array")?;
+    if arr.is_empty() {
+        println!("No jobs in the last {hours} hours.");
+        return Ok(());
+    }
+
+    let columns = vec![
+        "kind".into(),
+        "runs".into(),
+        "running".into(),
+        "queued".into(),
+        "done".into(),
+        "failed".into(),
+        "retries".into(),
+        "failure_rate".into(),
+        "done_per_hour".into(),
+        "avg_seconds".into(),
+    ];
+    let number = |v: &serde_json::Value| match v {
+        serde_json::Value::Null => String::new(),
+        v => v.to_string(),
+    };
+    let rows: Vec<Vec<String>> = arr
+        .iter()
+        .map(|s| {
+            vec![
+                s["kind"].as_str().unwrap_or("").to_string(),
+                number(&s["runs"]),
+                number(&s["running"]),
+                number(&s["queued"]),
+                number(&s["done"]),
+                number(&s["failed"]),
+                number(&s["retries"]),
+                number(&s["failure_rate"]),
+                number(&s["done_per_hour"]),
+                number(&s["avg_seconds"]),
+            ]
+        })
+        .collect();
+
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
+
+pub fn reap(
+    client: &mut Client,
+    stale_secs: i32,
+    requeue: bool,
+    backoff_secs: i32,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let row = client
+        .query_one(
+            "SELECT kerai.reap_jobs($1, $2, $3)::text",
+            &[&stale_secs, &requeue, &backoff_secs],
+        )
+        .map_err(|e| format!("reap_jobs failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let empty = Vec::new();
+    let failed = value["failed"].as_array().unwrap_or(&empty);
+    let requeued = value["requeued"].as_array().unwrap_or(&empty);
+    if failed.is_empty() && requeued.is_empty() {
+        println!("No dead jobs.");
+        return Ok(());
+    }
+    for job in failed {
+        println!(
+            "Failed {} job {} after {} attempt(s)",
+            job["kind"].as_str().unwrap_or(""),
+            job["id"].as_str().unwrap_or(""),
+            job["attempts"]
+        );
+    }
+    for job in requeued {
+        println!(
+            "Requeued {} job {}, due {}",
+            job["kind"].as_str().unwrap_or(""),
+            job["id"].as_str().unwrap_or(""),
+            job["run_after"].as_str().unwrap_or("")
+        );
+    }
+    Ok(())
+}
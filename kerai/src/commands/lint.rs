@@ -0,0 +1,150 @@
+use postgres::Client;
+
+use crate::db::query_json;
+use crate::output::{print_json, print_rows, OutputFormat};
+
+/// List open suggestions per file, optionally evaluating the custom rules
+/// first.
+pub fn run(
+    client: &mut Client,
+    file: Option<&str>,
+    run_rules: bool,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    if run_rules {
+        let result = query_json(client, "run_rules", "SELECT kerai.run_rules()::text", &[])?;
+        if !matches!(format, OutputFormat::Json) {
+            println!(
+                "Ran {} rules: {} new, {} resolved",
+                result["rules"].as_array().map_or(0, Vec::len),
+                result["created"],
+                result["resolved"]
+            );
+        }
+    }
+
+    let value = query_json(
+        client,
+        "list_suggestions",
+        "SELECT kerai.list_suggestions($1)::text",
+        &[&file],
+    )?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let files = value.as_array().cloned().unwrap_or_default();
+    if files.is_empty() {
+        println!("No open suggestions.");
+        return Ok(());
+    }
+
+    let columns = vec![
+        "file".into(),
+        "line".into(),
+        "severity".into(),
+        "rule".into(),
+        "message".into(),
+    ];
+    let mut rows = Vec::new();
+    for f in &files {
+        for s in f["suggestions"].as_array().into_iter().flatten() {
+            rows.push(vec![
+                f["file"].as_str().unwrap_or("").to_string(),
+                s["line"].to_string(),
+                s["severity"].as_str().unwrap_or("").to_string(),
+                s["rule"].as_str().unwrap_or("").to_string(),
+                s["message"].as_str().unwrap_or("").to_string(),
+            ]);
+        }
+    }
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
+
+pub fn list_rules(client: &mut Client, format: &OutputFormat) -> Result<(), String> {
+    let value = query_json(client, "list_rules", "SELECT kerai.list_rules()::text", &[])?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let rules = value.as_array().cloned().unwrap_or_default();
+    if rules.is_empty() {
+        println!("No rules defined.");
+        return Ok(());
+    }
+
+    let columns = vec![
+        "name".into(),
+        "severity".into(),
+        "category".into(),
+        "pattern".into(),
+        "predicate".into(),
+        "message".into(),
+    ];
+    let rows: Vec<Vec<String>> = rules
+        .iter()
+        .map(|r| {
+            [
+                "name",
+                "severity",
+                "category",
+                "pattern",
+                "predicate",
+                "message",
+            ]
+            .iter()
+            .map(|key| r[*key].as_str().unwrap_or("").to_string())
+            .collect()
+        })
+        .collect();
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
+
+/// Define or redefine a rule; its category stays the default unless set
+/// through `kerai.add_rule` directly.
+pub fn add_rule(
+    client: &mut Client,
+    name: &str,
+    message: &str,
+    predicate: Option<&str>,
+    pattern: Option<&str>,
+    severity: &str,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let value = query_json(
+        client,
+        "add_rule",
+        "SELECT kerai.add_rule($1, $2, $3, $4, $5)::text",
+        &[&name, &message, &predicate, &pattern, &severity],
+    )?;
+
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ => println!("Saved rule '{name}'. Run `kerai lint --run` to apply it."),
+    }
+    Ok(())
+}
+
+pub fn remove_rule(client: &mut Client, name: &str, format: &OutputFormat) -> Result<(), String> {
+    let value = query_json(
+        client,
+        "remove_rule",
+        "SELECT kerai.remove_rule($1)::text",
+        &[&name],
+    )?;
+
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ => println!(
+            "Removed rule '{name}' and {} suggestions",
+            value["suggestions"]
+        ),
+    }
+    Ok(())
+}
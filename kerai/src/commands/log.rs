@@ -9,15 +9,19 @@ pub fn run(
     format: &OutputFormat,
 ) -> Result<(), String> {
     let (sql, rows) = if let Some(author_val) = author {
-        let sql = "SELECT lamport_ts, author_seq, op_type, node_id::text, author, created_at::text \
-                    FROM kerai.operations WHERE author = $1 ORDER BY lamport_ts DESC LIMIT $2";
+        let sql = "SELECT o.lamport_ts, o.author_seq, o.op_type, o.node_id::text, o.author, \
+                    kerai.hlc_wall_time(o.lamport_ts, i.clock_skew_ms)::text, o.created_at::text \
+                    FROM kerai.operations o LEFT JOIN kerai.instances i ON i.id = o.instance_id \
+                    WHERE o.author = $1 ORDER BY o.lamport_ts DESC LIMIT $2";
         let rows = client
             .query(sql, &[&author_val, &limit])
             .map_err(|e| format!("Query failed: {e}"))?;
         (sql, rows)
     } else {
-        let sql = "SELECT lamport_ts, author_seq, op_type, node_id::text, author, created_at::text \
-                    FROM kerai.operations ORDER BY lamport_ts DESC LIMIT $1";
+        let sql = "SELECT o.lamport_ts, o.author_seq, o.op_type, o.node_id::text, o.author, \
+                    kerai.hlc_wall_time(o.lamport_ts, i.clock_skew_ms)::text, o.created_at::text \
+                    FROM kerai.operations o LEFT JOIN kerai.instances i ON i.id = o.instance_id \
+                    ORDER BY o.lamport_ts DESC LIMIT $1";
         let rows = client
             .query(sql, &[&limit])
             .map_err(|e| format!("Query failed: {e}"))?;
@@ -36,6 +40,7 @@ pub fn run(
         "op_type".into(),
         "node_id".into(),
         "author".into(),
+        "wall_time".into(),
         "created_at".into(),
     ];
 
@@ -53,6 +58,7 @@ pub fn run(
                 row.try_get::<_, String>(3).unwrap_or_default(),
                 row.try_get::<_, String>(4).unwrap_or_default(),
                 row.try_get::<_, String>(5).unwrap_or_default(),
+                row.try_get::<_, String>(6).unwrap_or_default(),
             ]
         })
         .collect();
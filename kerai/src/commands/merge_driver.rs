@@ -0,0 +1,166 @@
+use std::path::Path;
+use std::process::Command;
+
+use postgres::Client;
+
+use crate::config;
+use crate::db;
+
+/// Name of the driver in git config and .gitattributes.
+const DRIVER: &str = "kerai";
+
+/// Files the driver handles; the parser is Rust-only.
+const PATTERN: &str = "*.rs";
+
+/// Git merge driver: merge `theirs` into `ours` against `base` and leave
+/// the result in `ours`, as git expects of `%O %A %B`.
+///
+/// Rust files are parsed into throwaway graphs (the transaction is rolled
+/// back), merged node by node with `kerai.merge_nodes`, and reconstructed.
+/// Conflict markers appear only around nodes both sides changed. Other
+/// files, or anything the structural merge cannot handle (no database,
+/// unparseable input), fall back to `git merge-file`.
+///
+/// Exits with status 1 when conflicts remain, so git marks the file
+/// unmerged.
+pub fn run(
+    profile_name: &str,
+    db_override: Option<&str>,
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    path: Option<&str>,
+) -> Result<(), String> {
+    let label = path.unwrap_or(ours);
+    if !label.ends_with(".rs") {
+        return line_merge(base, ours, theirs, label);
+    }
+
+    let read = |file: &str| {
+        std::fs::read_to_string(file).map_err(|e| format!("Cannot read {file}: {e}"))
+    };
+    let texts = [read(base)?, read(ours)?, read(theirs)?];
+
+    let profile = config::load_config(profile_name);
+    let merged = db::connect(&profile, db_override)
+        .and_then(|mut client| structural_merge(&mut client, &texts));
+    match merged {
+        Ok((text, conflicts)) => {
+            std::fs::write(ours, text).map_err(|e| format!("Cannot write {ours}: {e}"))?;
+            if conflicts > 0 {
+                eprintln!("kerai: {conflicts} conflicting node(s) in {label}");
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("kerai: structural merge of {label} unavailable ({e}); merging lines");
+            line_merge(base, ours, theirs, label)
+        }
+    }
+}
+
+/// Parse base, ours and theirs, merge them, and return the reconstructed
+/// source with its conflict count. Nothing is kept in the database.
+fn structural_merge(client: &mut Client, texts: &[String; 3]) -> Result<(String, usize), String> {
+    let mut tx = client
+        .transaction()
+        .map_err(|e| format!("Transaction failed: {e}"))?;
+
+    let mut roots = Vec::new();
+    for (side, text) in ["base", "ours", "theirs"].iter().zip(texts) {
+        let name = format!("kerai-merge-{}-{side}.rs", std::process::id());
+        tx.query_one("SELECT kerai.parse_source($1, $2)::text", &[text, &name])
+            .map_err(|e| format!("parse failed: {e}"))?;
+        let row = tx
+            .query_opt(
+                "SELECT id::text FROM kerai.nodes WHERE kind = 'file' AND content = $1",
+                &[&name],
+            )
+            .map_err(|e| format!("parse failed: {e}"))?
+            .ok_or_else(|| format!("{side} does not parse"))?;
+        roots.push(row.get::<_, String>(0));
+    }
+
+    let row = tx
+        .query_one(
+            "SELECT kerai.merge_nodes($1::text::uuid, $2::text::uuid, $3::text::uuid)::text",
+            &[&roots[0], &roots[1], &roots[2]],
+        )
+        .map_err(|e| format!("merge failed: {e}"))?;
+    let text: String = row.get(0);
+    let merge: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+    let root = merge["root"].as_str().ok_or("merge produced no tree")?;
+    let conflicts = merge["conflicts"]
+        .as_array()
+        .map_or(0, |c| c.iter().filter(|c| !c["node_id"].is_null()).count());
+
+    // Keep the source as written: no import sorting, derive ordering or advice
+    let row = tx
+        .query_one(
+            "SELECT kerai.reconstruct_file_with_options($1::text::uuid, \
+             '{\"sort_imports\": false, \"order_derives\": false, \"suggestions\": false}'::jsonb)",
+            &[&root],
+        )
+        .map_err(|e| format!("reconstruct failed: {e}"))?;
+    let source: String = row.get(0);
+
+    tx.rollback().map_err(|e| format!("Rollback failed: {e}"))?;
+    Ok((source, conflicts))
+}
+
+/// Textual three-way merge with `git merge-file`, writing into `ours`.
+fn line_merge(base: &str, ours: &str, theirs: &str, label: &str) -> Result<(), String> {
+    let status = Command::new("git")
+        .args(["merge-file", "-L", label, "-L", "base", "-L", "theirs", ours, base, theirs])
+        .status()
+        .map_err(|e| format!("git merge-file failed: {e}"))?;
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(n) if n > 0 => std::process::exit(1),
+        _ => Err("git merge-file failed".into()),
+    }
+}
+
+/// Register the driver for the current repository: `merge.kerai.*` in
+/// git config and a `*.rs merge=kerai` line in .gitattributes.
+pub fn install() -> Result<(), String> {
+    let git = |args: &[&str]| -> Result<String, String> {
+        let out = Command::new("git")
+            .args(args)
+            .output()
+            .map_err(|e| format!("git failed: {e}"))?;
+        if !out.status.success() {
+            return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+        }
+        Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+    };
+
+    let top = git(&["rev-parse", "--show-toplevel"])?;
+    git(&["config", &format!("merge.{DRIVER}.name"), "kerai structural merge"])?;
+    git(&[
+        "config",
+        &format!("merge.{DRIVER}.driver"),
+        "kerai merge-driver %O %A %B %P",
+    ])?;
+
+    let attributes = Path::new(&top).join(".gitattributes");
+    let line = format!("{PATTERN} merge={DRIVER}");
+    let existing = std::fs::read_to_string(&attributes).unwrap_or_default();
+    if existing.lines().any(|l| l.trim() == line) {
+        println!("Merge driver configured; {} already routes {PATTERN} to it", attributes.display());
+        return Ok(());
+    }
+    let mut updated = existing;
+    if !updated.is_empty() && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated.push_str(&line);
+    updated.push('\n');
+    std::fs::write(&attributes, updated)
+        .map_err(|e| format!("Cannot write {}: {e}", attributes.display()))?;
+
+    println!("Merge driver configured; added '{line}' to {}", attributes.display());
+    Ok(())
+}
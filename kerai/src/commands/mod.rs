@@ -1,30 +1,52 @@
+pub mod advise;
 pub mod agent;
+pub mod blame;
 pub mod bounty;
+pub mod branch;
 pub mod config_cmd;
 pub mod export;
+pub mod export_vault;
 pub mod commit;
 pub mod init_cmd;
 pub mod stack_cmd;
 pub mod connect;
 pub mod consensus_cmd;
 pub mod currency;
+pub mod diff;
+pub mod economy;
+pub mod edge;
 pub mod find;
+pub mod graph;
+pub mod hook;
 pub mod info;
 pub mod import;
+pub mod import_git;
+pub mod import_vault;
+pub mod jobs;
+pub mod lint;
 pub mod log;
 pub mod market;
+pub mod merge_driver;
 pub mod model;
 pub mod peer;
 pub mod perspective;
 pub mod ping;
 pub mod query;
 pub mod refs;
+pub mod replace;
+pub mod revert;
+pub mod script;
+pub mod seed;
+pub mod stale_docs;
+pub mod subscribe;
 pub mod swarm;
 pub mod sync;
 pub mod task;
 pub mod tree;
 pub mod version;
+pub mod view;
 pub mod wallet;
+pub mod watch;
 
 use crate::config;
 use crate::db;
@@ -34,6 +56,10 @@ pub enum Command {
     Connect {
         connection: String,
     },
+    Run {
+        file: String,
+        env: Vec<String>,
+    },
     Import {
         path: Option<String>,
     },
@@ -45,6 +71,13 @@ pub enum Command {
     },
     Export {
         file: Option<String>,
+        mode: export::Mode,
+        out_dir: Option<String>,
+        snapshot: Option<String>,
+    },
+    ExportGit {
+        dir: String,
+        history: bool,
     },
     Log {
         author: Option<String>,
@@ -53,6 +86,132 @@ pub enum Command {
     Commit {
         message: Option<String>,
     },
+    Watch {
+        path: Option<String>,
+        debounce_ms: u64,
+    },
+    Graph {
+        root: Option<String>,
+        cycles: bool,
+    },
+    MergeDriver {
+        base: String,
+        ours: String,
+        theirs: String,
+        path: Option<String>,
+    },
+    MergeDriverInstall,
+    HookPreCommit {
+        fail_on: String,
+    },
+    HookInstall,
+    JobList {
+        limit: i32,
+    },
+    JobCancel {
+        id: String,
+    },
+    JobSummary {
+        hours: i32,
+    },
+    JobReap {
+        stale_secs: i32,
+        requeue: bool,
+        backoff_secs: i32,
+    },
+    Seed {
+        fixture: Option<String>,
+    },
+    ImportGit {
+        repo: String,
+        rev: String,
+        limit: Option<usize>,
+    },
+    ImportVault {
+        dir: String,
+        name: Option<String>,
+    },
+    ExportVault {
+        path: Option<String>,
+        out: String,
+        assets_from: Option<String>,
+    },
+    Replace {
+        pattern: String,
+        replacement: String,
+        path: String,
+        regex: bool,
+        mode: replace::Mode,
+    },
+    Grep {
+        pattern: String,
+        kind: Option<String>,
+        path: Option<String>,
+        limit: Option<i32>,
+    },
+    Lint {
+        file: Option<String>,
+        run: bool,
+    },
+    EdgeAdd {
+        source: Option<String>,
+        target: Option<String>,
+        relation: Option<String>,
+        metadata: Option<String>,
+        file: Option<String>,
+    },
+    EdgeRemove {
+        source: Option<String>,
+        target: Option<String>,
+        relation: Option<String>,
+        file: Option<String>,
+    },
+    EdgeRelations,
+    RuleList,
+    RuleAdd {
+        name: String,
+        message: String,
+        predicate: Option<String>,
+        pattern: Option<String>,
+        severity: String,
+    },
+    RuleRemove {
+        name: String,
+    },
+    ViewList,
+    ViewCreate {
+        name: String,
+        paths: Vec<String>,
+        kinds: Vec<String>,
+        branch: Option<String>,
+        frontier: Option<String>,
+        description: Option<String>,
+    },
+    ViewDrop {
+        name: String,
+    },
+    Subscribe {
+        user: String,
+        path: String,
+        events: Vec<String>,
+        frequency: String,
+    },
+    Unsubscribe {
+        user: String,
+        path: String,
+    },
+    Subscriptions {
+        user: Option<String>,
+    },
+    Inbox {
+        user: String,
+        limit: i32,
+    },
+    StaleDocs {
+        threshold: Option<i32>,
+        limit: Option<i32>,
+        suggest: bool,
+    },
     PeerAdd {
         name: String,
         public_key: String,
@@ -66,13 +225,39 @@ pub enum Command {
     PeerInfo {
         name: String,
     },
+    PeerPolicy {
+        name: String,
+        windows: Vec<String>,
+        max_ops_per_minute: Option<i32>,
+        max_bytes_per_hour: Option<i64>,
+    },
+    BranchCreate {
+        name: String,
+    },
+    BranchList,
+    BranchSwitch {
+        name: String,
+    },
+    BranchMerge {
+        name: String,
+    },
+    Revert {
+        version_id: Option<String>,
+        last: Option<i32>,
+        author: Option<String>,
+    },
+    AdviseIndexes,
     Sync {
         peer: String,
+        force: bool,
     },
     Find {
         pattern: String,
         kind: Option<String>,
         limit: Option<i32>,
+        fts: bool,
+        /// Fuzzy match within this many edits
+        fuzzy: Option<i32>,
     },
     Refs {
         symbol: String,
@@ -80,6 +265,13 @@ pub enum Command {
     Tree {
         path: Option<String>,
     },
+    Blame {
+        path: String,
+    },
+    Diff {
+        file_a: String,
+        file_b: String,
+    },
     ImportCsv {
         path: String,
         schema: String,
@@ -228,6 +420,10 @@ pub enum Command {
         reason: Option<String>,
     },
     CurrencySupply,
+    Economy {
+        period: String,
+        federation: bool,
+    },
     CurrencyShare {
         wallet_id: String,
     },
@@ -237,6 +433,14 @@ pub enum Command {
         reward: i64,
         enabled: Option<bool>,
     },
+    CurrencyReward {
+        work_type: String,
+        reference_id: Option<String>,
+        reference_type: Option<String>,
+    },
+    CurrencyLedger {
+        reference_id: String,
+    },
     ModelCreate {
         agent: String,
         dim: Option<i32>,
@@ -312,6 +516,7 @@ pub fn run(
     command: Command,
     profile_name: &str,
     db_override: Option<&str>,
+    view_name: Option<&str>,
     format: &OutputFormat,
 ) -> Result<(), String> {
     // Connect doesn't need an existing DB connection — handle it early
@@ -319,6 +524,31 @@ pub fn run(
         return connect::run(&connection, format);
     }
 
+    // Scripts run on a local stack machine — no DB connection
+    if let Command::Run { file, env } = command {
+        return script::run(&file, &env, format);
+    }
+
+    // The merge driver falls back to a line merge when there is no database
+    if let Command::MergeDriver {
+        base,
+        ours,
+        theirs,
+        path,
+    } = command
+    {
+        return merge_driver::run(profile_name, db_override, &base, &ours, &theirs, path.as_deref());
+    }
+    if let Command::MergeDriverInstall = command {
+        return merge_driver::install();
+    }
+    if let Command::HookPreCommit { fail_on } = command {
+        return hook::pre_commit(profile_name, db_override, &fail_on, format);
+    }
+    if let Command::HookInstall = command {
+        return hook::install();
+    }
+
     let profile = config::load_config(profile_name);
 
     // Determine the connection string for import's config file
@@ -328,6 +558,9 @@ pub fn run(
         .to_string();
 
     let mut client = db::connect(&profile, db_override)?;
+    if let Some(name) = view_name {
+        view::apply(&mut client, name)?;
+    }
 
     match command {
         Command::Import { path } => import::run(&mut client, path.as_deref(), &conn_str, format),
@@ -335,9 +568,152 @@ pub fn run(
         Command::Info => info::run(&mut client, format),
         Command::Version => version::run(&mut client, format),
         Command::Query { sql } => query::run(&mut client, &sql, format),
-        Command::Export { file } => export::run(&mut client, file.as_deref()),
+        Command::Export {
+            file,
+            mode,
+            out_dir,
+            snapshot,
+        } => export::run(
+            &mut client,
+            file.as_deref(),
+            mode,
+            out_dir.as_deref(),
+            snapshot.as_deref(),
+        ),
+        Command::ExportGit { dir, history } => export::git(&mut client, &dir, history),
         Command::Log { author, limit } => log::run(&mut client, author.as_deref(), limit, format),
         Command::Commit { message } => commit::run(&mut client, message.as_deref()),
+        Command::StaleDocs {
+            threshold,
+            limit,
+            suggest,
+        } => stale_docs::run(&mut client, threshold, limit, suggest, format),
+        Command::Watch { path, debounce_ms } => {
+            watch::run(&mut client, path.as_deref(), debounce_ms, format)
+        }
+        Command::Graph { root, cycles } => graph::run(&mut client, root.as_deref(), cycles, format),
+        Command::JobList { limit } => jobs::list(&mut client, limit, format),
+        Command::JobCancel { id } => jobs::cancel(&mut client, &id, format),
+        Command::JobSummary { hours } => jobs::summary(&mut client, hours, format),
+        Command::JobReap {
+            stale_secs,
+            requeue,
+            backoff_secs,
+        } => jobs::reap(&mut client, stale_secs, requeue, backoff_secs, format),
+        Command::Seed { fixture } => seed::run(&mut client, fixture.as_deref(), format),
+        Command::ImportGit { repo, rev, limit } => {
+            import_git::run(&mut client, &repo, &rev, limit, format)
+        }
+        Command::ImportVault { dir, name } => {
+            import_vault::run(&mut client, &dir, name.as_deref(), format)
+        }
+        Command::ExportVault {
+            path,
+            out,
+            assets_from,
+        } => export_vault::run(
+            &mut client,
+            path.as_deref(),
+            &out,
+            assets_from.as_deref(),
+            format,
+        ),
+        Command::Replace {
+            pattern,
+            replacement,
+            path,
+            regex,
+            mode,
+        } => replace::run(
+            &mut client,
+            &path,
+            &pattern,
+            &replacement,
+            regex,
+            mode,
+            format,
+        ),
+        Command::Grep {
+            pattern,
+            kind,
+            path,
+            limit,
+        } => find::grep(
+            &mut client,
+            &pattern,
+            kind.as_deref(),
+            path.as_deref(),
+            limit,
+            format,
+        ),
+        Command::Lint { file, run } => lint::run(&mut client, file.as_deref(), run, format),
+        Command::EdgeAdd {
+            source,
+            target,
+            relation,
+            metadata,
+            file,
+        } => {
+            let edges = edge::Edges::from_args(&source, &target, &relation, metadata.as_deref(), &file);
+            edge::add(&mut client, edges, format)
+        }
+        Command::EdgeRemove {
+            source,
+            target,
+            relation,
+            file,
+        } => {
+            let edges = edge::Edges::from_args(&source, &target, &relation, None, &file);
+            edge::remove(&mut client, edges, format)
+        }
+        Command::EdgeRelations => edge::relations(&mut client, format),
+        Command::RuleList => lint::list_rules(&mut client, format),
+        Command::RuleAdd {
+            name,
+            message,
+            predicate,
+            pattern,
+            severity,
+        } => lint::add_rule(
+            &mut client,
+            &name,
+            &message,
+            predicate.as_deref(),
+            pattern.as_deref(),
+            &severity,
+            format,
+        ),
+        Command::RuleRemove { name } => lint::remove_rule(&mut client, &name, format),
+        Command::ViewList => view::list(&mut client, format),
+        Command::ViewCreate {
+            name,
+            paths,
+            kinds,
+            branch,
+            frontier,
+            description,
+        } => view::create(
+            &mut client,
+            &name,
+            view::ViewSpec {
+                paths: &paths,
+                kinds: &kinds,
+                branch: branch.as_deref(),
+                frontier: frontier.as_deref(),
+                description: description.as_deref(),
+            },
+            format,
+        ),
+        Command::ViewDrop { name } => view::drop(&mut client, &name),
+        Command::Subscribe {
+            user,
+            path,
+            events,
+            frequency,
+        } => subscribe::subscribe(&mut client, &user, &path, &events, &frequency, format),
+        Command::Unsubscribe { user, path } => subscribe::unsubscribe(&mut client, &user, &path),
+        Command::Subscriptions { user } => subscribe::list(&mut client, user.as_deref(), format),
+        Command::Inbox { user, limit } => subscribe::inbox(&mut client, &user, limit, format),
         Command::PeerAdd {
             name,
             public_key,
@@ -354,14 +730,52 @@ pub fn run(
         Command::PeerList => peer::list(&mut client, format),
         Command::PeerRemove { name } => peer::remove(&mut client, &name),
         Command::PeerInfo { name } => peer::info(&mut client, &name, format),
-        Command::Sync { peer } => sync::run(&mut client, &peer),
+        Command::PeerPolicy {
+            name,
+            windows,
+            max_ops_per_minute,
+            max_bytes_per_hour,
+        } => peer::policy(
+            &mut client,
+            &name,
+            &windows,
+            max_ops_per_minute,
+            max_bytes_per_hour,
+            format,
+        ),
+        Command::BranchCreate { name } => branch::create(&mut client, &name, format),
+        Command::BranchList => branch::list(&mut client, format),
+        Command::BranchSwitch { name } => branch::switch(&mut client, &name, format),
+        Command::BranchMerge { name } => branch::merge(&mut client, &name, format),
+        Command::Revert {
+            version_id,
+            last,
+            author,
+        } => revert::run(
+            &mut client,
+            version_id.as_deref(),
+            last,
+            author.as_deref(),
+            format,
+        ),
+        Command::AdviseIndexes => advise::indexes(&mut client, format),
+        Command::Sync { peer, force } => sync::run(&mut client, &peer, force),
         Command::Find {
             pattern,
             kind,
             limit,
-        } => find::run(&mut client, &pattern, kind.as_deref(), limit, format),
+            fts,
+            fuzzy,
+        } => match fuzzy {
+            Some(max_distance) => {
+                find::fuzzy(&mut client, &pattern, max_distance, kind.as_deref(), limit, format)
+            }
+            None => find::run(&mut client, &pattern, kind.as_deref(), limit, fts, format),
+        },
         Command::Refs { symbol } => refs::run(&mut client, &symbol, format),
         Command::Tree { path } => tree::run(&mut client, path.as_deref(), format),
+        Command::Blame { path } => blame::run(&mut client, &path, format),
+        Command::Diff { file_a, file_b } => diff::run(&mut client, &file_a, &file_b, format),
         Command::ImportCsv {
             path,
             schema,
@@ -547,6 +961,9 @@ pub fn run(
             reason.as_deref(),
             format,
         ),
+        Command::Economy { period, federation } => {
+            economy::run(&mut client, &period, federation, format)
+        }
         Command::CurrencySupply => currency::supply(&mut client, format),
         Command::CurrencyShare { wallet_id } => {
             currency::share(&mut client, &wallet_id, format)
@@ -557,6 +974,20 @@ pub fn run(
             reward,
             enabled,
         } => currency::set_reward(&mut client, &work_type, reward, enabled, format),
+        Command::CurrencyReward {
+            work_type,
+            reference_id,
+            reference_type,
+        } => currency::reward(
+            &mut client,
+            &work_type,
+            reference_id.as_deref(),
+            reference_type.as_deref(),
+            format,
+        ),
+        Command::CurrencyLedger { reference_id } => {
+            currency::ledger(&mut client, &reference_id, format)
+        }
         Command::ModelCreate {
             agent,
             dim,
@@ -637,5 +1068,12 @@ pub fn run(
         Command::StackDrop => stack_cmd::drop(&mut client, format),
         Command::StackClear => stack_cmd::clear(&mut client, format),
         Command::Connect { .. } => unreachable!("handled before db::connect()"),
+        Command::Run { .. } => unreachable!("handled before db::connect()"),
+        Command::MergeDriver { .. }
+        | Command::MergeDriverInstall
+        | Command::HookPreCommit { .. }
+        | Command::HookInstall => {
+            unreachable!("handled before db::connect()")
+        }
     }
 }
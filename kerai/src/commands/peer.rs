@@ -56,6 +56,7 @@ pub fn list(client: &mut Client, format: &OutputFormat) -> Result<(), String> {
         "endpoint".into(),
         "connection".into(),
         "last_seen".into(),
+        "clock_skew_ms".into(),
     ];
 
     let rows: Vec<Vec<String>> = arr
@@ -67,6 +68,10 @@ pub fn list(client: &mut Client, format: &OutputFormat) -> Result<(), String> {
                 p["endpoint"].as_str().unwrap_or("").to_string(),
                 p["connection"].as_str().unwrap_or("").to_string(),
                 p["last_seen"].as_str().unwrap_or("").to_string(),
+                p["clock_skew_ms"]
+                    .as_i64()
+                    .map(|n| n.to_string())
+                    .unwrap_or_default(),
             ]
         })
         .collect();
@@ -122,3 +127,27 @@ pub fn info(client: &mut Client, name: &str, format: &OutputFormat) -> Result<()
     print_json(&value, format);
     Ok(())
 }
+
+pub fn policy(
+    client: &mut Client,
+    name: &str,
+    windows: &[String],
+    max_ops_per_minute: Option<i32>,
+    max_bytes_per_hour: Option<i64>,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let row = client
+        .query_one(
+            "SELECT kerai.set_sync_policy($1, $2, $3, $4)::text",
+            &[&name, &windows, &max_ops_per_minute, &max_bytes_per_hour],
+        )
+        .map_err(|e| format!("set_sync_policy failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    println!("Updated sync policy for '{name}'");
+    print_json(&value, format);
+    Ok(())
+}
@@ -43,6 +43,32 @@ pub fn run(
                 }
             }
 
+            // Resolved callers
+            if let Some(callers) = value["callers"].as_array() {
+                if !callers.is_empty() {
+                    println!("Callers ({}):", callers.len());
+                    let columns = vec![
+                        "caller".into(),
+                        "sites".into(),
+                        "resolution".into(),
+                        "path".into(),
+                    ];
+                    let rows: Vec<Vec<String>> = callers
+                        .iter()
+                        .map(|c| {
+                            vec![
+                                c["content"].as_str().unwrap_or("").to_string(),
+                                c["sites"].as_i64().unwrap_or(0).to_string(),
+                                c["resolution"].as_str().unwrap_or("").to_string(),
+                                c["path"].as_str().unwrap_or("").to_string(),
+                            ]
+                        })
+                        .collect();
+                    print_rows(&columns, &rows, format);
+                    println!();
+                }
+            }
+
             // Impl blocks
             if let Some(impls) = value["impls"].as_array() {
                 if !impls.is_empty() {
@@ -90,10 +116,38 @@ pub fn run(
                 }
             }
 
+            // Dependent crates
+            if let Some(deps) = value["dependents"].as_array() {
+                if !deps.is_empty() {
+                    println!();
+                    println!("Dependents ({}):", deps.len());
+                    let columns = vec![
+                        "crate".into(),
+                        "version".into(),
+                        "dep_type".into(),
+                        "path".into(),
+                    ];
+                    let rows: Vec<Vec<String>> = deps
+                        .iter()
+                        .map(|d| {
+                            vec![
+                                d["crate"].as_str().unwrap_or("").to_string(),
+                                d["version"].as_str().unwrap_or("").to_string(),
+                                d["dep_type"].as_str().unwrap_or("").to_string(),
+                                d["path"].as_str().unwrap_or("").to_string(),
+                            ]
+                        })
+                        .collect();
+                    print_rows(&columns, &rows, format);
+                }
+            }
+
             // Summary if all empty
             let total = value["definitions"].as_array().map_or(0, |a| a.len())
+                + value["callers"].as_array().map_or(0, |a| a.len())
                 + value["impls"].as_array().map_or(0, |a| a.len())
-                + value["references"].as_array().map_or(0, |a| a.len());
+                + value["references"].as_array().map_or(0, |a| a.len())
+                + value["dependents"].as_array().map_or(0, |a| a.len());
             if total == 0 {
                 println!("No references found for '{symbol}'.");
             }
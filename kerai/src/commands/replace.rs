@@ -0,0 +1,187 @@
+//! `kerai replace`: find and replace across node content, previewed per
+//! file and applied as one changeset.
+
+use std::io::{self, BufRead, Write};
+
+use postgres::Client;
+use serde_json::Value;
+
+use crate::db::query_json;
+use crate::output::{print_json, OutputFormat};
+
+/// Run `kerai.replace`, limited to `files` when given.
+fn call_replace(
+    client: &mut Client,
+    path: &str,
+    pattern: &str,
+    replacement: &str,
+    regex: bool,
+    dry_run: bool,
+    files: Option<&[String]>,
+) -> Result<Value, String> {
+    query_json(
+        client,
+        "replace",
+        "SELECT kerai.replace($1, $2, $3, $4, $5, $6)::text",
+        &[&path, &pattern, &replacement, &regex, &dry_run, &files],
+    )
+}
+
+fn file_label(file: &Value) -> &str {
+    match file.as_str() {
+        Some(name) if !name.is_empty() => name,
+        _ => "(outside any file)",
+    }
+}
+
+/// Print a file's changes: each node's path, then its changed lines.
+fn print_file(file: &Value) {
+    println!(
+        "{}: {} match(es) in {} node(s)",
+        file_label(&file["file"]),
+        file["matches"],
+        file["nodes"]
+    );
+    for change in file["changes"].as_array().into_iter().flatten() {
+        println!("  {}", change["path"].as_str().unwrap_or(""));
+        let before: Vec<&str> = change["before"].as_str().unwrap_or("").lines().collect();
+        let after: Vec<&str> = change["after"].as_str().unwrap_or("").lines().collect();
+        if before.len() == after.len() {
+            for (old, new) in before.iter().zip(&after).filter(|(old, new)| old != new) {
+                println!("    - {old}");
+                println!("    + {new}");
+            }
+        } else {
+            // Lines were joined or split; show the whole content
+            before.iter().for_each(|l| println!("    - {l}"));
+            after.iter().for_each(|l| println!("    + {l}"));
+        }
+    }
+}
+
+fn print_summary(value: &Value) {
+    let files = value["files"].as_array().map_or(0, Vec::len);
+    if value["dry_run"].as_bool().unwrap_or(false) {
+        println!(
+            "Would replace {} match(es) in {} node(s) across {files} file(s)",
+            value["matches"], value["nodes"]
+        );
+    } else {
+        println!(
+            "Replaced {} match(es) in {} node(s) across {files} file(s), changeset {}",
+            value["matches"],
+            value["nodes"],
+            value["changeset"].as_str().unwrap_or("-")
+        );
+    }
+}
+
+/// Ask about one file: `y`, `n`, `a` (this and all the rest) or `q`.
+fn confirm(file: &Value) -> Result<char, String> {
+    let mut line = String::new();
+    loop {
+        print!(
+            "Apply to {}? [y]es/[n]o/[a]ll/[q]uit ",
+            file_label(&file["file"])
+        );
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        line.clear();
+        // EOF counts as quit
+        if io::stdin()
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?
+            == 0
+        {
+            return Ok('q');
+        }
+        if let Some(answer @ ('y' | 'n' | 'a' | 'q')) = line.trim().chars().next() {
+            return Ok(answer);
+        }
+    }
+}
+
+/// How `kerai replace` goes about it.
+pub enum Mode {
+    /// Show what would change, write nothing
+    Preview,
+    /// Apply every change
+    Apply,
+    /// Preview each file and apply the ones confirmed
+    Confirm,
+}
+
+pub fn run(
+    client: &mut Client,
+    path: &str,
+    pattern: &str,
+    replacement: &str,
+    regex: bool,
+    mode: Mode,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+
+    if !matches!(mode, Mode::Confirm) {
+        let dry_run = matches!(mode, Mode::Preview);
+        let value = call_replace(client, path, pattern, replacement, regex, dry_run, None)?;
+        match format {
+            OutputFormat::Json => print_json(&value, format),
+            _ => {
+                if dry_run {
+                    for file in value["files"].as_array().into_iter().flatten() {
+                        print_file(file);
+                    }
+                }
+                print_summary(&value);
+            }
+        }
+        return Ok(());
+    }
+
+    let preview = call_replace(client, path, pattern, replacement, regex, true, None)?;
+    let files = preview["files"].as_array().cloned().unwrap_or_default();
+    if files.is_empty() {
+        println!("No matches found.");
+        return Ok(());
+    }
+
+    let mut accepted: Vec<String> = Vec::new();
+    let mut rest = false;
+    for file in &files {
+        let name = file["file"].as_str().unwrap_or("").to_string();
+        if rest {
+            accepted.push(name);
+            continue;
+        }
+        print_file(file);
+        match confirm(file)? {
+            'y' => accepted.push(name),
+            'a' => {
+                accepted.push(name);
+                rest = true;
+            }
+            'q' => break,
+            _ => {}
+        }
+    }
+
+    if accepted.is_empty() {
+        println!("Nothing applied.");
+        return Ok(());
+    }
+    let value = call_replace(
+        client,
+        path,
+        pattern,
+        replacement,
+        regex,
+        false,
+        Some(&accepted),
+    )?;
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ => print_summary(&value),
+    }
+    Ok(())
+}
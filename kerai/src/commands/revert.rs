@@ -0,0 +1,60 @@
+use postgres::Client;
+
+use crate::output::{print_json, OutputFormat};
+
+pub fn run(
+    client: &mut Client,
+    version_id: Option<&str>,
+    last: Option<i32>,
+    author: Option<&str>,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let row = match version_id {
+        Some(id) => client
+            .query_one("SELECT kerai.revert_version($1::text::uuid)::text", &[&id])
+            .map_err(|e| format!("revert_version failed: {e}"))?,
+        None => client
+            .query_one(
+                "SELECT kerai.undo_last($1, $2)::text",
+                &[&author, &last.unwrap_or(1)],
+            )
+            .map_err(|e| format!("undo_last failed: {e}"))?,
+    };
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let reverts = match version_id {
+        Some(_) => vec![value],
+        None => value["undone"].as_array().cloned().unwrap_or_default(),
+    };
+    if reverts.is_empty() {
+        println!("Nothing to undo.");
+    }
+    for r in &reverts {
+        let conflicts = r["conflicts"].as_array().cloned().unwrap_or_default();
+        println!(
+            "Reverted {}: {} op(s) applied, {} conflict(s)",
+            r["version_id"].as_str().unwrap_or("?"),
+            r["ops"].as_array().map_or(0, |ops| ops.len()),
+            conflicts.len(),
+        );
+        for (old, new) in r["resurrected"].as_object().into_iter().flatten() {
+            println!("  restored {} as {}", old, new.as_str().unwrap_or("?"));
+        }
+        for c in &conflicts {
+            println!(
+                "  {} {}",
+                c["node_id"].as_str().unwrap_or("?"),
+                c["reason"].as_str().unwrap_or(""),
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use crate::lang::handlers;
+use crate::lang::machine::{Machine, Role};
+use crate::lang::template;
+use crate::output::{print_json, OutputFormat};
+
+/// Run a `.kerai` script through a local stack machine and print the final stack.
+///
+/// `${VAR}` references are filled from `--env KEY=VALUE` pairs. Words that
+/// need the server (login, workspace, admin) leave their request markers on
+/// the stack unresolved.
+pub fn run(file: &str, env_pairs: &[String], format: &OutputFormat) -> Result<(), String> {
+    let source =
+        std::fs::read_to_string(file).map_err(|e| format!("Failed to read {file}: {e}"))?;
+    let env = template::parse_env_pairs(env_pairs)?;
+
+    // A local script runs with the operator's own authority; server-side
+    // checks still apply when its markers are resolved.
+    let (handler_map, type_methods, help, roles) = handlers::register_all();
+    let mut machine = Machine::new(
+        uuid::Uuid::nil(),
+        uuid::Uuid::nil(),
+        Role::Admin,
+        handler_map,
+        type_methods,
+        help,
+        roles,
+    );
+    machine.execute_with_env(&source, &env)?;
+
+    match format {
+        OutputFormat::Json => {
+            let value = serde_json::to_value(&machine.stack).map_err(|e| e.to_string())?;
+            print_json(&value, format);
+        }
+        _ => {
+            for ptr in &machine.stack {
+                println!("{ptr}");
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use postgres::Client;
+use serde_json::Value;
+
+use crate::output::{print_json, print_rows, OutputFormat};
+use crate::progress;
+
+pub fn run(
+    client: &mut Client,
+    fixture: Option<&str>,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let Some(fixture) = fixture else {
+        return list(client, format);
+    };
+
+    progress::start(&format!("Seeding {fixture}"));
+    let result = client.query_one("SELECT kerai.seed($1)::text", &[&fixture]);
+    progress::finish();
+    let row = result.map_err(|e| format!("seed failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    for seeded in value["seeded"].as_array().into_iter().flatten() {
+        println!(
+            "Seeded {}: {} files, {} nodes",
+            seeded["name"].as_str().unwrap_or(""),
+            seeded["files"],
+            seeded["nodes"]
+        );
+    }
+    let skipped: Vec<&str> = value["skipped"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|s| s.as_str())
+        .collect();
+    if !skipped.is_empty() {
+        println!("Already seeded: {}", skipped.join(", "));
+    }
+    if let Some(linked) = value["citations"]["linked"].as_u64() {
+        println!("Linked {linked} citations");
+    }
+    Ok(())
+}
+
+fn list(client: &mut Client, format: &OutputFormat) -> Result<(), String> {
+    let row = client
+        .query_one("SELECT kerai.seed_fixtures()::text", &[])
+        .map_err(|e| format!("seed_fixtures failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let columns = vec!["name".into(), "description".into(), "files".into()];
+    let rows: Vec<Vec<String>> = value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|f| {
+            let files: Vec<&str> = f["files"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|p| p.as_str())
+                .collect();
+            vec![
+                f["name"].as_str().unwrap_or("").to_string(),
+                f["description"].as_str().unwrap_or("").to_string(),
+                files.join(", "),
+            ]
+        })
+        .collect();
+
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use postgres::Client;
+
+use crate::output::{print_json, print_rows, OutputFormat};
+
+pub fn run(
+    client: &mut Client,
+    threshold: Option<i32>,
+    limit: Option<i32>,
+    suggest: bool,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let suggested = if suggest {
+        let row = client
+            .query_one("SELECT kerai.suggest_doc_updates($1)::text", &[&threshold])
+            .map_err(|e| format!("suggest_doc_updates failed: {e}"))?;
+        let text: String = row.get(0);
+        Some(
+            serde_json::from_str::<serde_json::Value>(&text)
+                .map_err(|e| format!("Invalid JSON: {e}"))?,
+        )
+    } else {
+        None
+    };
+
+    let row = client
+        .query_one(
+            "SELECT kerai.stale_docs($1, $2)::text",
+            &[&threshold, &limit.unwrap_or(100)],
+        )
+        .map_err(|e| format!("stale_docs failed: {e}"))?;
+
+    let text: String = row.get(0);
+    let value: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let docs = value["docs"].as_array().cloned().unwrap_or_default();
+    if docs.is_empty() {
+        println!("All linked docs are current.");
+    } else {
+        let columns = vec![
+            "score".into(),
+            "gap_days".into(),
+            "file".into(),
+            "code".into(),
+            "doc".into(),
+        ];
+        let rows: Vec<Vec<String>> = docs
+            .iter()
+            .map(|d| {
+                let marker = if d["stale"].as_bool().unwrap_or(false) { "!" } else { "" };
+                vec![
+                    format!("{:.2}{marker}", d["score"].as_f64().unwrap_or(0.0)),
+                    format!("{:.1}", d["gap_days"].as_f64().unwrap_or(0.0)),
+                    d["file"].as_str().unwrap_or("").to_string(),
+                    format!(
+                        "{} {}",
+                        d["code_kind"].as_str().unwrap_or(""),
+                        d["code_name"].as_str().unwrap_or("")
+                    ),
+                    d["doc_excerpt"].as_str().unwrap_or("").trim().to_string(),
+                ]
+            })
+            .collect();
+        print_rows(&columns, &rows, format);
+        println!(
+            "{} of {} docs past the {}-day threshold (marked !)",
+            value["stale"].as_i64().unwrap_or(0),
+            docs.len(),
+            value["threshold_days"].as_f64().unwrap_or(0.0)
+        );
+    }
+
+    if let Some(s) = suggested {
+        println!(
+            "Suggestions: {} created, {} resolved",
+            s["created"].as_i64().unwrap_or(0),
+            s["resolved"].as_i64().unwrap_or(0)
+        );
+    }
+    Ok(())
+}
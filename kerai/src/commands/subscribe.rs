@@ -0,0 +1,140 @@
+//! `kerai subscribe`, `unsubscribe`, `subscriptions` and `inbox`: follow
+//! subtrees and read the digests the digest worker delivers for them.
+
+use postgres::Client;
+use serde_json::Value;
+
+use crate::db::query_json;
+use crate::output::{print_json, print_rows, OutputFormat};
+
+fn events_label(events: &Value) -> String {
+    match events.as_array() {
+        Some(items) => items
+            .iter()
+            .filter_map(|i| i.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        None => "all".to_string(),
+    }
+}
+
+pub fn subscribe(
+    client: &mut Client,
+    user: &str,
+    path: &str,
+    events: &[String],
+    frequency: &str,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let events = (!events.is_empty()).then_some(events);
+    let value = query_json(
+        client,
+        "subscribe",
+        "SELECT kerai.subscribe($1, $2, $3, $4)::text",
+        &[&user, &path, &events, &frequency],
+    )?;
+
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ => println!(
+            "{user} follows {path}: {} events, {frequency} digest",
+            events_label(&value["events"])
+        ),
+    }
+    Ok(())
+}
+
+pub fn unsubscribe(client: &mut Client, user: &str, path: &str) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let row = client
+        .query_one("SELECT kerai.unsubscribe($1, $2)", &[&user, &path])
+        .map_err(|e| format!("unsubscribe failed: {e}"))?;
+    if row.get::<_, bool>(0) {
+        println!("{user} no longer follows {path}");
+        Ok(())
+    } else {
+        Err(format!("{user} does not follow {path}"))
+    }
+}
+
+pub fn list(client: &mut Client, user: Option<&str>, format: &OutputFormat) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let value = query_json(
+        client,
+        "list_subscriptions",
+        "SELECT kerai.list_subscriptions($1)::text",
+        &[&user],
+    )?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let columns = vec![
+        "user".into(),
+        "path".into(),
+        "events".into(),
+        "frequency".into(),
+        "digested_at".into(),
+    ];
+    let rows: Vec<Vec<String>> = value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|s| {
+            vec![
+                s["handle"].as_str().unwrap_or("").to_string(),
+                s["path"].as_str().unwrap_or("").to_string(),
+                events_label(&s["events"]),
+                s["frequency"].as_str().unwrap_or("").to_string(),
+                s["digested_at"].as_str().unwrap_or("").to_string(),
+            ]
+        })
+        .collect();
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
+
+pub fn inbox(
+    client: &mut Client,
+    user: &str,
+    limit: i32,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let value = query_json(
+        client,
+        "inbox",
+        "SELECT kerai.inbox($1, $2)::text",
+        &[&user, &limit],
+    )?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let digests = value.as_array().cloned().unwrap_or_default();
+    if digests.is_empty() {
+        println!("No digests for {user}.");
+        return Ok(());
+    }
+    for digest in &digests {
+        println!(
+            "{}  {}",
+            digest["created_at"].as_str().unwrap_or(""),
+            digest["summary"].as_str().unwrap_or("")
+        );
+        for change in digest["changes"].as_array().into_iter().flatten() {
+            println!(
+                "    {}  {} ({})",
+                change["path"].as_str().unwrap_or(""),
+                events_label(&change["types"]),
+                change["events"]
+            );
+        }
+    }
+    Ok(())
+}
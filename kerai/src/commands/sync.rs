@@ -1,111 +1,488 @@
-use postgres::{Client, NoTls};
+use postgres::Client;
+
+use crate::db;
+use crate::progress::{self, Job};
 
 /// Sync protocol: pull-then-push between local and peer databases.
 ///
-/// 1. Look up peer's connection string from kerai.instances
-/// 2. Connect to peer's Postgres
-/// 3. Get both version vectors
-/// 4. Pull: for each author where peer is ahead, fetch ops and apply locally
-/// 5. Push: for each author where local is ahead, fetch ops and apply on peer
-/// 6. Print summary
-pub fn run(client: &mut Client, peer_name: &str) -> Result<(), String> {
-    // Look up peer's connection string
+/// 1. Look up the peer's connection string and endpoint in kerai.instances,
+///    and refuse when its sync policy holds exchanges now (unless `force`)
+/// 2. Connect to the peer's Postgres, or fall back to its HTTP endpoint when
+///    it has no connection string
+/// 3. Exchange version vectors and Merkle hashes, noting which subtrees
+///    differ, and ask each side for the ops the other is missing; when
+///    neither the vectors nor the trees differ there is nothing to ask for
+/// 4. Pull: apply the peer's delta locally
+/// 5. Push: apply the local delta on the peer, skipping ops the peer's
+///    version filter says it already has
+/// 6. Record how far the peer's clock is off ours and print a summary,
+///    which warns about a peer more than two seconds off
+///
+/// Both directions are cut to what the policy's rate caps have left, as
+/// the sync worker cuts them (`kerai.limit_ops`), and the exchange goes in
+/// kerai.sync_log, so the worker and the CLI draw on the same budgets.
+/// `force` ignores the policy.
+///
+/// The sync runs as a job (see `kerai jobs`). Cancelling it, with Ctrl-C
+/// or `kerai jobs cancel`, keeps a pull that has already been applied.
+pub fn run(client: &mut Client, peer_name: &str, force: bool) -> Result<(), String> {
+    // Look up peer's connection string and endpoint
     let peer_row = client
         .query_opt(
-            "SELECT connection FROM kerai.instances WHERE name = $1 AND is_self = false",
+            "SELECT connection, endpoint, id::text, clock_timestamp()::text
+             FROM kerai.instances WHERE name = $1 AND is_self = false",
             &[&peer_name],
         )
         .map_err(|e| format!("Failed to look up peer: {e}"))?
         .ok_or_else(|| format!("Peer '{peer_name}' not found"))?;
 
     let peer_conn: Option<String> = peer_row.get(0);
-    let peer_conn = peer_conn.ok_or_else(|| {
-        format!("Peer '{peer_name}' has no connection string. Use: kerai peer add {peer_name} --public-key <hex> --connection <pg_url>")
-    })?;
-
-    // Connect to peer
-    let mut peer_client =
-        Client::connect(&peer_conn, NoTls).map_err(|e| format!("Cannot connect to peer: {e}"))?;
-
-    // Get both version vectors
-    let local_vv = get_version_vector(client)?;
-    let peer_vv = get_version_vector(&mut peer_client)?;
-
-    let mut pulled = 0u64;
-    let mut pushed = 0u64;
+    let peer_endpoint: Option<String> = peer_row.get(1);
+    let peer_id: String = peer_row.get(2);
+    let started_at: String = peer_row.get(3);
+    if peer_conn.is_none() && peer_endpoint.is_none() {
+        return Err(format!(
+            "Peer '{peer_name}' has no connection string or endpoint. Use: kerai peer add {peer_name} --public-key <hex> --connection <pg_url> (or --endpoint <url>)"
+        ));
+    }
 
-    // Pull: for each author in peer's VV where peer is ahead
-    for (author, peer_seq) in &peer_vv {
-        let local_seq = local_vv.get(author).copied().unwrap_or(0);
-        if *peer_seq > local_seq {
-            let ops = get_ops_since(&mut peer_client, author, local_seq)?;
-            for op in &ops {
-                apply_remote_op(client, op)?;
-                pulled += 1;
-            }
+    let budget = if force {
+        Budget::default()
+    } else {
+        let state = get_sync_state(client, peer_name)?;
+        if let Some(reason) = state["held"].as_str() {
+            return Err(format!(
+                "Sync with '{peer_name}' held: {reason} (use --force to sync anyway)"
+            ));
         }
-    }
+        Budget {
+            max_ops: state["ops_left"].as_i64(),
+            max_bytes: state["bytes_left"].as_i64(),
+        }
+    };
 
-    // Push: for each author in local VV where local is ahead
-    for (author, local_seq) in &local_vv {
-        let peer_seq = peer_vv.get(author).copied().unwrap_or(0);
-        if *local_seq > peer_seq {
-            let ops = get_ops_since(client, author, peer_seq)?;
-            for op in &ops {
-                apply_remote_op(&mut peer_client, op)?;
-                pushed += 1;
-            }
+    let job = Job::start(client, "sync", &format!("Syncing with '{peer_name}'"), Some(STEPS));
+    progress::cancel_on_interrupt(client);
+    let mut tally = Tally::default();
+    let endpoint = peer_endpoint.clone();
+    let synced = match (peer_conn, peer_endpoint) {
+        (Some(conn), _) => sync_direct(client, &conn, &job, budget, &mut tally),
+        (_, endpoint) => sync_http(
+            client,
+            &endpoint.unwrap_or_default(),
+            &job,
+            budget,
+            &mut tally,
+        ),
+    };
+    let log = SyncLog {
+        peer_id: &peer_id,
+        endpoint: endpoint.as_deref(),
+        started_at: &started_at,
+    };
+    if let Err(e) = synced {
+        // The ops that did move still count against the caps
+        let _ = log.record(client, &tally, Some(&e));
+        let (cancelled, _) = job.fail(client, &e, tally.to_json());
+        if !cancelled {
+            return Err(e);
         }
+        return Err(format!("Sync with '{peer_name}' cancelled: {}", tally.describe()));
     }
+    job.finish(client, "done", &tally.to_json());
+    log.record(client, &tally, None)?;
 
-    // Update last_seen
+    // Update last_seen, and the clock skew when it was measured
     client
         .execute(
-            "UPDATE kerai.instances SET last_seen = now() WHERE name = $1",
-            &[&peer_name],
+            "UPDATE kerai.instances SET last_seen = now(),
+                 clock_skew_ms = COALESCE($2, clock_skew_ms),
+                 clock_checked_at = CASE WHEN $2 IS NULL THEN clock_checked_at ELSE now() END
+             WHERE name = $1",
+            &[&peer_name, &tally.clock_skew_ms],
         )
         .map_err(|e| format!("Failed to update last_seen: {e}"))?;
 
-    println!("Synced with '{peer_name}': pulled {pulled}, pushed {pushed}");
+    println!("Synced with '{peer_name}': {}", tally.describe());
 
     Ok(())
 }
 
-/// Get the version vector from a database as a map of author -> max_seq.
-fn get_version_vector(
+/// Job steps: exchange vectors, pull, push.
+const STEPS: i32 = 3;
+
+/// Subtrees named in the summary; the job result lists them all.
+const SHOWN_SUBTREES: usize = 5;
+
+/// Clock skew, in milliseconds, past which the summary warns about it
+/// (as `kerai.open_sync_message` does).
+const CLOCK_SKEW_WARN_MS: i64 = 2_000;
+
+/// What a sync has done so far; `None` for a direction not yet applied.
+/// `differing` holds the subtrees whose Merkle hashes differed before the
+/// exchange, as `kerai.merkle_diff` reports them, and `clock_skew_ms` how
+/// far the peer's clock is ahead of ours (negative: behind), once measured.
+/// `transferred` and `bytes` count the ops sent either way and their size,
+/// `deferred` those the rate caps left for a later sync.
+#[derive(Default)]
+struct Tally {
+    pulled: Option<u64>,
+    pushed: Option<u64>,
+    differing: Vec<serde_json::Value>,
+    clock_skew_ms: Option<i64>,
+    transferred: i64,
+    bytes: i64,
+    deferred: i64,
+}
+
+/// What the peer's rate caps have left for this sync: ops and bytes across
+/// both directions, `None` for no cap.
+#[derive(Default, Clone, Copy)]
+struct Budget {
+    max_ops: Option<i64>,
+    max_bytes: Option<i64>,
+}
+
+impl Tally {
+    fn describe(&self) -> String {
+        let side = |n: Option<u64>, verb: &str| match n {
+            Some(n) => format!("{verb} {n}"),
+            None => format!("{verb} nothing"),
+        };
+        let mut text = format!("{}, {}", side(self.pulled, "pulled"), side(self.pushed, "pushed"));
+        if !self.differing.is_empty() {
+            let mut keys: Vec<&str> = self
+                .differing
+                .iter()
+                .take(SHOWN_SUBTREES)
+                .filter_map(|d| d["key"].as_str())
+                .collect();
+            if self.differing.len() > SHOWN_SUBTREES {
+                keys.push("...");
+            }
+            text.push_str(&format!(
+                "; {} subtrees differed ({})",
+                self.differing.len(),
+                keys.join(", ")
+            ));
+        }
+        if self.deferred > 0 {
+            text.push_str(&format!("; {} ops deferred by rate caps", self.deferred));
+        }
+        if let Some(skew) = self.clock_skew_ms.filter(|s| s.abs() > CLOCK_SKEW_WARN_MS) {
+            let side = if skew > 0 { "ahead" } else { "behind" };
+            text.push_str(&format!(
+                "; warning: peer clock {:.1}s {side}",
+                skew.abs() as f64 / 1000.0
+            ));
+        }
+        text
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "pulled": self.pulled,
+            "pushed": self.pushed,
+            "differing": self.differing,
+            "clock_skew_ms": self.clock_skew_ms,
+            "transferred": self.transferred,
+            "bytes": self.bytes,
+            "deferred": self.deferred,
+        })
+    }
+}
+
+/// Where a sync's kerai.sync_log row goes.
+struct SyncLog<'a> {
+    peer_id: &'a str,
+    endpoint: Option<&'a str>,
+    started_at: &'a str,
+}
+
+impl SyncLog<'_> {
+    /// Record the sync in kerai.sync_log, as the sync worker records its
+    /// exchanges; a direct sync's endpoint is `direct`.
+    fn record(
+        &self,
+        client: &mut Client,
+        tally: &Tally,
+        error: Option<&str>,
+    ) -> Result<(), String> {
+        let count = |n: Option<u64>| n.unwrap_or(0) as i32;
+        client
+            .execute(
+                "INSERT INTO kerai.sync_log
+                    (peer_id, endpoint, started_at, status, pulled, pushed, clock_skew_ms,
+                     transferred, bytes, deferred, error)
+                 VALUES ($1::text::uuid, COALESCE($2, 'direct'), $3::text::timestamptz,
+                         CASE WHEN $10::text IS NULL THEN 'ok' ELSE 'error' END,
+                         $4, $5, $6, $7, $8, $9, $10)",
+                &[
+                    &self.peer_id,
+                    &self.endpoint,
+                    &self.started_at,
+                    &count(tally.pulled),
+                    &count(tally.pushed),
+                    &tally.clock_skew_ms,
+                    &(tally.transferred as i32),
+                    &tally.bytes,
+                    &(tally.deferred as i32),
+                    &error,
+                ],
+            )
+            .map_err(|e| format!("Failed to record sync: {e}"))?;
+        Ok(())
+    }
+}
+
+/// Sync over a direct Postgres connection to the peer.
+fn sync_direct(
+    client: &mut Client,
+    peer_conn: &str,
+    job: &Job,
+    budget: Budget,
+    tally: &mut Tally,
+) -> Result<(), String> {
+    let mut peer_client =
+        db::connect_url(peer_conn).map_err(|e| format!("Cannot connect to peer: {e}"))?;
+    progress::cancel_on_interrupt(&peer_client);
+    tally.clock_skew_ms = Some(measure_clock_skew(client, &mut peer_client)?);
+
+    // Both deltas come from the vectors as they were before either side changed
+    let local_vv = get_version_vector(client)?;
+    let peer_vv = get_version_vector(&mut peer_client)?;
+    // A peer whose extension predates the Merkle hashes is synced by vectors alone
+    let peer_hashes = get_merkle_hashes(&mut peer_client).ok();
+    if let Some(peer_hashes) = &peer_hashes {
+        tally.differing = get_merkle_diff(client, peer_hashes)?;
+    }
+    if peer_hashes.is_some() && tally.differing.is_empty() && same_vector(&local_vv, &peer_vv) {
+        tally.pulled = Some(0);
+        tally.pushed = Some(0);
+        return Ok(());
+    }
+    let incoming = get_version_delta(&mut peer_client, &local_vv, None)?;
+    let incoming = limit_ops(client, incoming, budget, tally)?;
+
+    // Push from the common frontier, so ops the peer is missing below its
+    // own vector are sent too, and let the peer's filter drop what it holds
+    let frontier = get_version_frontier(client, &local_vv, &peer_vv)?;
+    let filter = get_version_filter(&mut peer_client, &frontier)?;
+    let outgoing = get_version_delta(client, &frontier, Some(&filter))?;
+    let outgoing = limit_ops(client, outgoing, budget, tally)?;
+
+    job.step(client, 1, &format!("pulling {} ops", incoming.len()))?;
+    tally.pulled = Some(apply_operations(client, &incoming)?);
+    job.step(client, 2, &format!("pushing {} ops", outgoing.len()))?;
+    tally.pushed = Some(apply_operations(&mut peer_client, &outgoing)?);
+
+    Ok(())
+}
+
+/// Sync through the peer's web server (`/api/sync/pull`, `/api/sync/push`).
+/// Both directions carry messages signed with the sending instance's key;
+/// each side must have the other registered as a peer.
+fn sync_http(
     client: &mut Client,
-) -> Result<std::collections::HashMap<String, i64>, String> {
+    endpoint: &str,
+    job: &Job,
+    budget: Budget,
+    tally: &mut Tally,
+) -> Result<(), String> {
+    let runtime = tokio::runtime::Runtime::new()
+        .map_err(|e| format!("Failed to start HTTP runtime: {e}"))?;
+    let peer = kerai_client::Client::new(endpoint).map_err(|e| e.to_string())?;
+
+    let local_vv = get_version_vector(client)?;
+    let limit = serde_json::json!({"max_ops": budget.max_ops, "max_bytes": budget.max_bytes});
+    let request = sign_message(
+        client,
+        &format!(r#"{{"vector":{local_vv},"limit":{limit}}}"#),
+    )?;
+    let reply = runtime
+        .block_on(peer.sync_pull(&request))
+        .map_err(|e| e.to_string())?;
+    let opened = open_message(client, &reply)?;
+    tally.clock_skew_ms = opened["clock_skew_ms"].as_i64();
+    let body = &opened["body"];
+
+    let peer_vv = body["vector"].to_string();
+    // Peers whose extension predates the Merkle hashes send none
+    if body["merkle"].is_object() {
+        tally.differing = get_merkle_diff(client, &body["merkle"].to_string())?;
+    }
+    let incoming = body["ops"]
+        .as_array()
+        .cloned()
+        .ok_or("Expected ops in the peer's pull reply")?;
+    // A peer that predates rate caps sends everything, so cut here as well
+    tally.deferred += body["deferred"].as_i64().unwrap_or(0);
+    let incoming = limit_ops(client, incoming, budget, tally)?;
+
+    // Work out the push before applying the pull, as in the direct path
+    let frontier = get_version_frontier(client, &local_vv, &peer_vv)?;
+    let outgoing = get_version_delta(client, &frontier, Some(&body["filter"].to_string()))?;
+    let outgoing = limit_ops(client, outgoing, budget, tally)?;
+
+    job.step(client, 1, &format!("pulling {} ops", incoming.len()))?;
+    tally.pulled = Some(apply_operations(client, &incoming)?);
+    job.step(client, 2, &format!("pushing {} ops", outgoing.len()))?;
+
+    let mut pushed = 0u64;
+    if !outgoing.is_empty() {
+        let ops = serde_json::to_string(&outgoing).map_err(|e| format!("JSON encode failed: {e}"))?;
+        let request = sign_message(client, &format!(r#"{{"ops":{ops}}}"#))?;
+        let result = runtime
+            .block_on(peer.sync_push(&request))
+            .map_err(|e| e.to_string())?;
+        pushed = result.applied;
+    }
+    tally.pushed = Some(pushed);
+
+    Ok(())
+}
+
+/// Wrap a JSON body in a message signed by the local instance.
+pub(super) fn sign_message(client: &mut Client, body: &str) -> Result<serde_json::Value, String> {
+    let row = client
+        .query_one("SELECT kerai.sign_sync_message($1::text::jsonb)::text", &[&body])
+        .map_err(|e| format!("sign_sync_message failed: {e}"))?;
+    let text: String = row.get(0);
+    serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))
+}
+
+/// Verify a message signed by a registered peer and return it opened:
+/// `{from, instance, clock_skew_ms, body}`.
+fn open_message(client: &mut Client, message: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let text = serde_json::to_string(message).map_err(|e| format!("JSON encode failed: {e}"))?;
+    let row = client
+        .query_one("SELECT kerai.open_sync_message($1::text::jsonb)::text", &[&text])
+        .map_err(|e| format!("open_sync_message failed: {e}"))?;
+    let text: String = row.get(0);
+    serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))
+}
+
+/// How far the peer's clock is ahead of ours, in milliseconds: its clock
+/// against the midpoint of our readings either side of it.
+fn measure_clock_skew(client: &mut Client, peer_client: &mut Client) -> Result<i64, String> {
+    const NOW_MS: &str = "SELECT (extract(epoch FROM clock_timestamp()) * 1000)::bigint";
+    let read = |c: &mut Client| -> Result<i64, String> {
+        c.query_one(NOW_MS, &[])
+            .map(|row| row.get(0))
+            .map_err(|e| progress::query_error("clock_timestamp", &e))
+    };
+    let before = read(client)?;
+    let peer = read(peer_client)?;
+    let after = read(client)?;
+    Ok(peer - (before + after) / 2)
+}
+
+/// The peer's sync policy and throttle state (`kerai.peer_sync_state`).
+fn get_sync_state(client: &mut Client, peer_name: &str) -> Result<serde_json::Value, String> {
+    let row = client
+        .query_one("SELECT kerai.peer_sync_state($1)::text", &[&peer_name])
+        .map_err(|e| progress::query_error("peer_sync_state", &e))?;
+    let text: String = row.get(0);
+    serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))
+}
+
+/// Cut a batch of ops to what `budget` has left after the ops already
+/// counted in `tally`, keeping each author's ops a prefix, and count what
+/// goes and what is deferred.
+fn limit_ops(
+    client: &mut Client,
+    ops: Vec<serde_json::Value>,
+    budget: Budget,
+    tally: &mut Tally,
+) -> Result<Vec<serde_json::Value>, String> {
+    if ops.is_empty() {
+        return Ok(ops);
+    }
+    let ops_json = serde_json::to_string(&ops).map_err(|e| format!("JSON encode failed: {e}"))?;
+    let max_ops = budget.max_ops.map(|n| n - tally.transferred);
+    let max_bytes = budget.max_bytes.map(|n| n - tally.bytes);
+    let row = client
+        .query_one(
+            "SELECT kerai.limit_ops($1::text::jsonb, $2, $3)::text",
+            &[&ops_json, &max_ops, &max_bytes],
+        )
+        .map_err(|e| progress::query_error("limit_ops", &e))?;
+    let text: String = row.get(0);
+    let cut: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+
+    let kept = cut["ops"].as_array().cloned().unwrap_or_default();
+    tally.transferred += kept.len() as i64;
+    tally.bytes += cut["bytes"].as_i64().unwrap_or(0);
+    tally.deferred += cut["deferred"].as_i64().unwrap_or(0);
+    Ok(kept)
+}
+
+/// Get the version vector from a database as JSON text ({author: max_seq}).
+fn get_version_vector(client: &mut Client) -> Result<String, String> {
     let row = client
         .query_one("SELECT kerai.version_vector()::text", &[])
-        .map_err(|e| format!("version_vector failed: {e}"))?;
+        .map_err(|e| progress::query_error("version_vector", &e))?;
+    Ok(row.get(0))
+}
+
+/// Whether two version vectors (JSON text) hold the same entries.
+fn same_vector(a: &str, b: &str) -> bool {
+    let parse = |v: &str| serde_json::from_str::<serde_json::Value>(v).ok();
+    parse(a).is_some() && parse(a) == parse(b)
+}
+
+/// Get a database's Merkle root and subtree hashes as JSON text.
+fn get_merkle_hashes(client: &mut Client) -> Result<String, String> {
+    let row = client
+        .query_one("SELECT kerai.merkle_hashes()::text", &[])
+        .map_err(|e| progress::query_error("merkle_hashes", &e))?;
+    Ok(row.get(0))
+}
 
+/// Subtrees where a database's tree departs from a peer's `merkle_hashes`.
+fn get_merkle_diff(client: &mut Client, peer_hashes: &str) -> Result<Vec<serde_json::Value>, String> {
+    let row = client
+        .query_one("SELECT kerai.merkle_diff($1::text::jsonb)::text", &[&peer_hashes])
+        .map_err(|e| progress::query_error("merkle_diff", &e))?;
     let text: String = row.get(0);
     let value: serde_json::Value =
         serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
+    Ok(value.as_array().cloned().unwrap_or_default())
+}
 
-    let mut map = std::collections::HashMap::new();
-    if let Some(obj) = value.as_object() {
-        for (k, v) in obj {
-            if let Some(seq) = v.as_i64() {
-                map.insert(k.clone(), seq);
-            }
-        }
-    }
-    Ok(map)
+/// Common frontier of two version vectors (per-author minimum), as JSON text.
+fn get_version_frontier(client: &mut Client, a: &str, b: &str) -> Result<String, String> {
+    let row = client
+        .query_one(
+            "SELECT kerai.version_frontier($1::text::jsonb, $2::text::jsonb)::text",
+            &[&a, &b],
+        )
+        .map_err(|e| progress::query_error("version_frontier", &e))?;
+    Ok(row.get(0))
+}
+
+/// Get a database's filter of the ops it holds past `frontier`.
+fn get_version_filter(client: &mut Client, frontier: &str) -> Result<String, String> {
+    let row = client
+        .query_one("SELECT kerai.version_filter($1::text::jsonb)::text", &[&frontier])
+        .map_err(|e| progress::query_error("version_filter", &e))?;
+    Ok(row.get(0))
 }
 
-/// Get operations from a database for a given author since a sequence number.
-fn get_ops_since(
+/// Get the operations a database has that a peer with `peer_vv` is missing,
+/// leaving out those in the peer's version filter if one is given.
+fn get_version_delta(
     client: &mut Client,
-    author: &str,
-    since_seq: i64,
+    peer_vv: &str,
+    filter: Option<&str>,
 ) -> Result<Vec<serde_json::Value>, String> {
     let row = client
         .query_one(
-            "SELECT kerai.ops_since($1, $2)::text",
-            &[&author, &since_seq],
+            "SELECT kerai.version_delta($1::text::jsonb, $2::text::jsonb)::text",
+            &[&peer_vv, &filter],
         )
-        .map_err(|e| format!("ops_since failed: {e}"))?;
+        .map_err(|e| progress::query_error("version_delta", &e))?;
 
     let text: String = row.get(0);
     let value: serde_json::Value =
@@ -114,28 +491,29 @@ fn get_ops_since(
     value
         .as_array()
         .cloned()
-        .ok_or_else(|| "Expected JSON array from ops_since".to_string())
+        .ok_or_else(|| "Expected JSON array from version_delta".to_string())
 }
 
-/// Apply a remote operation on a target database.
-fn apply_remote_op(client: &mut Client, op: &serde_json::Value) -> Result<(), String> {
-    let op_json = serde_json::to_string(op).map_err(|e| format!("JSON encode failed: {e}"))?;
+/// Apply a batch of remote operations on a target database. Returns how
+/// many took effect; ops the target already had, or whose fields a later
+/// write already decided, are not counted.
+fn apply_operations(client: &mut Client, ops: &[serde_json::Value]) -> Result<u64, String> {
+    if ops.is_empty() {
+        return Ok(0);
+    }
+    let ops_json = serde_json::to_string(ops).map_err(|e| format!("JSON encode failed: {e}"))?;
 
-    let row = client
-        .query_one(
-            "SELECT kerai.apply_remote_op($1::jsonb)::text",
-            &[&op_json],
+    let row = kerai_cli::txn::serializable(client, "apply_operations", |tx| {
+        tx.query_one(
+            "SELECT kerai.apply_operations($1::text::jsonb)::text",
+            &[&ops_json],
         )
-        .map_err(|e| format!("apply_remote_op failed: {e}"))?;
+    })
+    .map_err(|e| progress::query_error("apply_operations", &e))?;
 
     let text: String = row.get(0);
     let result: serde_json::Value =
         serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
 
-    let status = result["status"].as_str().unwrap_or("unknown");
-    if status == "duplicate" {
-        // Skip silently — idempotent
-    }
-
-    Ok(())
+    Ok(result["applied"].as_u64().unwrap_or(0))
 }
@@ -1,16 +1,47 @@
 use postgres::Client;
 
-use crate::output::{print_json, OutputFormat};
+use crate::output::{print_json, print_rows, OutputFormat};
 
 pub fn run(client: &mut Client, format: &OutputFormat) -> Result<(), String> {
     let row = client
-        .query_one("SELECT kerai.version_vector()::text", &[])
+        .query_one("SELECT kerai.version_vector(true)::text", &[])
         .map_err(|e| format!("Failed to get version vector: {e}"))?;
 
     let text: String = row.get(0);
     let value: serde_json::Value =
         serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))?;
 
-    print_json(&value, format);
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let obj = value.as_object().ok_or("Expected JSON object")?;
+
+    let columns = vec![
+        "author".into(),
+        "instance".into(),
+        "seq".into(),
+        "lamport".into(),
+        "wall_time".into(),
+        "updated_at".into(),
+    ];
+
+    let mut rows: Vec<Vec<String>> = obj
+        .iter()
+        .map(|(author, e)| {
+            vec![
+                author.clone(),
+                e["instance"].as_str().unwrap_or("").to_string(),
+                e["seq"].as_i64().map(|n| n.to_string()).unwrap_or_default(),
+                e["lamport"].as_i64().map(|n| n.to_string()).unwrap_or_default(),
+                e["wall_time"].as_str().unwrap_or("").to_string(),
+                e["updated_at"].as_str().unwrap_or("").to_string(),
+            ]
+        })
+        .collect();
+    rows.sort();
+
+    print_rows(&columns, &rows, format);
     Ok(())
 }
@@ -0,0 +1,128 @@
+//! `kerai view` and `--view`: named slices of the graph for a team.
+
+use postgres::Client;
+use serde_json::{json, Value};
+
+use crate::db::query_json;
+use crate::output::{print_json, print_rows, OutputFormat};
+
+/// Put view `name` in effect for the rest of this connection, so find,
+/// grep, tree and search only see its nodes.
+pub fn apply(client: &mut Client, name: &str) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let view = query_json(
+        client,
+        "apply_view",
+        "SELECT kerai.apply_view($1)::text",
+        &[&name],
+    )?;
+    if view["on_branch"] == false {
+        eprintln!(
+            "Warning: view {name} is meant for branch {}, but {} is checked out",
+            view["branch"].as_str().unwrap_or(""),
+            view["current_branch"].as_str().unwrap_or("no branch"),
+        );
+    }
+    Ok(())
+}
+
+pub fn list(client: &mut Client, format: &OutputFormat) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let value = query_json(client, "list_views", "SELECT kerai.list_views()::text", &[])?;
+
+    if let OutputFormat::Json = format {
+        print_json(&value, format);
+        return Ok(());
+    }
+
+    let listed = |v: &Value| match v.as_array() {
+        Some(items) => items
+            .iter()
+            .filter_map(|i| i.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        None => "any".to_string(),
+    };
+    let columns = vec![
+        "name".into(),
+        "paths".into(),
+        "kinds".into(),
+        "branch".into(),
+        "description".into(),
+    ];
+    let rows: Vec<Vec<String>> = value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|v| {
+            vec![
+                v["name"].as_str().unwrap_or("").to_string(),
+                listed(&v["paths"]),
+                listed(&v["kinds"]),
+                v["branch"].as_str().unwrap_or("").to_string(),
+                v["description"].as_str().unwrap_or("").to_string(),
+            ]
+        })
+        .collect();
+    print_rows(&columns, &rows, format);
+    Ok(())
+}
+
+/// Filters and defaults of `kerai view create`.
+pub struct ViewSpec<'a> {
+    pub paths: &'a [String],
+    pub kinds: &'a [String],
+    pub branch: Option<&'a str>,
+    pub frontier: Option<&'a str>,
+    pub description: Option<&'a str>,
+}
+
+pub fn create(
+    client: &mut Client,
+    name: &str,
+    spec: ViewSpec,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let filter = |items: &[String]| (!items.is_empty()).then(|| json!(items).to_string());
+    let frontier = match spec.frontier {
+        Some(f) => {
+            let parsed: Value =
+                serde_json::from_str(f).map_err(|e| format!("Invalid --frontier JSON: {e}"))?;
+            Some(parsed.to_string())
+        }
+        None => None,
+    };
+    let value = query_json(
+        client,
+        "create_view",
+        "SELECT kerai.create_view($1, $2::text::jsonb, $3::text::jsonb, $4, $5::text::jsonb, $6)::text",
+        &[
+            &name,
+            &filter(spec.paths),
+            &filter(spec.kinds),
+            &spec.branch,
+            &frontier,
+            &spec.description,
+        ],
+    )?;
+
+    match format {
+        OutputFormat::Json => print_json(&value, format),
+        _ => println!("View {name} saved; use it with --view {name}"),
+    }
+    Ok(())
+}
+
+pub fn drop(client: &mut Client, name: &str) -> Result<(), String> {
+    crate::db::ensure_extension(client)?;
+    let row = client
+        .query_one("SELECT kerai.drop_view($1)", &[&name])
+        .map_err(|e| format!("drop_view failed: {e}"))?;
+    if row.get::<_, bool>(0) {
+        println!("Dropped view {name}");
+        Ok(())
+    } else {
+        Err(format!("No view named {name}"))
+    }
+}
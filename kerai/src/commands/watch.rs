@@ -0,0 +1,221 @@
+use postgres::Client;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
+
+use super::commit;
+use crate::config;
+use crate::output::OutputFormat;
+
+/// Watch a project for changes to source files and re-parse each changed
+/// file into the extension once its changes settle for `debounce_ms`.
+///
+/// Runs until interrupted. A file that fails to parse is reported and
+/// watching continues.
+pub fn run(
+    client: &mut Client,
+    path: Option<&str>,
+    debounce_ms: u64,
+    format: &OutputFormat,
+) -> Result<(), String> {
+    let root = match path {
+        Some(p) => std::fs::canonicalize(p).map_err(|e| format!("Invalid path '{p}': {e}"))?,
+        None => config::find_project_root()
+            .map(Ok)
+            .unwrap_or_else(std::env::current_dir)
+            .map_err(|e| format!("Cannot get cwd: {e}"))?,
+    };
+
+    let extensions = parsed_extensions(client)?;
+
+    let (tx, rx) = mpsc::channel::<DebounceEventResult>();
+    let mut debouncer = new_debouncer(Duration::from_millis(debounce_ms), tx)
+        .map_err(|e| format!("Failed to start watcher: {e}"))?;
+    debouncer
+        .watcher()
+        .watch(&root, RecursiveMode::Recursive)
+        .map_err(|e| format!("Cannot watch {}: {e}", root.display()))?;
+
+    println!("Watching {} (Ctrl-C to stop)", root.display());
+
+    for result in rx {
+        let events = match result {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("Watch error: {e}");
+                continue;
+            }
+        };
+
+        let changed = changed_files(&root, &extensions, events.into_iter().map(|e| e.path));
+
+        let mut parsed = false;
+        for file in changed {
+            let rel = file.strip_prefix(&root).unwrap_or(&file).display().to_string();
+            if !file.exists() {
+                report(format, &rel, serde_json::json!({"file": rel, "status": "removed"}));
+                continue;
+            }
+            let file_str = file.to_string_lossy();
+            let entry = match commit::parse_file(client, &file_str, &rel) {
+                Ok((nodes, edges)) => {
+                    parsed = true;
+                    serde_json::json!({
+                        "file": rel, "status": "parsed", "nodes": nodes, "edges": edges,
+                    })
+                }
+                Err(e) => serde_json::json!({"file": rel, "status": "error", "error": e}),
+            };
+            report(format, &rel, entry);
+        }
+
+        // Calls into or out of the changed files may now resolve differently
+        if parsed {
+            if let Err(e) = commit::resolve_calls(client) {
+                eprintln!("  {e}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// File extensions the extension has a parser for, from
+/// `kerai.supported_languages()`, lowercased.
+fn parsed_extensions(client: &mut Client) -> Result<BTreeSet<String>, String> {
+    let rows = client
+        .query(
+            "SELECT DISTINCT lower(ext)
+             FROM jsonb_array_elements(kerai.supported_languages()) lang,
+                  jsonb_array_elements_text(lang->'extensions') ext",
+            &[],
+        )
+        .map_err(|e| format!("supported_languages failed: {e}"))?;
+    Ok(rows.iter().map(|r| r.get(0)).collect())
+}
+
+/// The tracked files among a batch of debounced event paths: one entry per
+/// file however often it changed, in a stable order.
+fn changed_files(
+    root: &Path,
+    extensions: &BTreeSet<String>,
+    paths: impl IntoIterator<Item = PathBuf>,
+) -> BTreeSet<PathBuf> {
+    paths
+        .into_iter()
+        .filter(|p| is_tracked(root, extensions, p))
+        .collect()
+}
+
+/// Whether a changed path is a file some registered parser handles (by
+/// extension, ignoring case), outside build and hidden directories.
+fn is_tracked(root: &Path, extensions: &BTreeSet<String>, path: &Path) -> bool {
+    let parsed = path
+        .extension()
+        .is_some_and(|ext| extensions.contains(&ext.to_string_lossy().to_lowercase()));
+    if !parsed {
+        return false;
+    }
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    let mut dirs = rel.components().rev().skip(1);
+    !dirs.any(|c| commit::is_skipped_dir(&c.as_os_str().to_string_lossy()))
+}
+
+/// Print one file's outcome: a line per file, or compact JSON lines.
+fn report(format: &OutputFormat, rel: &str, entry: serde_json::Value) {
+    if let OutputFormat::Json = format {
+        println!("{entry}");
+        return;
+    }
+    match entry["status"].as_str() {
+        Some("parsed") => println!("  {rel}: {} nodes, {} edges", entry["nodes"], entry["edges"]),
+        Some("removed") => println!("  {rel}: removed, skipped"),
+        _ => eprintln!("  {rel}: {}", entry["error"].as_str().unwrap_or("failed")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extensions() -> BTreeSet<String> {
+        ["rs", "go", "md"].into_iter().map(String::from).collect()
+    }
+
+    #[test]
+    fn tracks_registered_extensions() {
+        let root = Path::new("/p");
+        let ext = extensions();
+        for path in [
+            "/p/src/lib.rs",
+            "/p/cmd/main.go",
+            "/p/README.MD",
+            "/p/docs/a.b.md",
+        ] {
+            assert!(is_tracked(root, &ext, Path::new(path)), "{path}");
+        }
+        for path in [
+            "/p/Makefile",
+            "/p/src/lib.rs.orig",
+            "/p/notes.txt",
+            "/p/.rs",
+        ] {
+            assert!(!is_tracked(root, &ext, Path::new(path)), "{path}");
+        }
+        assert!(!is_tracked(
+            root,
+            &BTreeSet::new(),
+            Path::new("/p/src/lib.rs")
+        ));
+    }
+
+    #[test]
+    fn ignores_build_and_hidden_dirs() {
+        let root = Path::new("/p");
+        let ext = extensions();
+        for path in [
+            "/p/target/debug/build/out.rs",
+            "/p/tgt/x.rs",
+            "/p/.kerai/cache.md",
+            "/p/src/.hidden/x.rs",
+            "/p/.git/HEAD.md",
+        ] {
+            assert!(!is_tracked(root, &ext, Path::new(path)), "{path}");
+        }
+        // Only directories under the root count, not the root's own path
+        let root = Path::new("/home/u/.projects/p");
+        assert!(is_tracked(
+            root,
+            &ext,
+            Path::new("/home/u/.projects/p/src/lib.rs")
+        ));
+        // Nor the file name itself
+        assert!(is_tracked(Path::new("/p"), &ext, Path::new("/p/target.rs")));
+    }
+
+    #[test]
+    fn a_batch_parses_each_file_once() {
+        let root = Path::new("/p");
+        let events = [
+            "/p/b.rs",
+            "/p/a.rs",
+            "/p/b.rs",
+            "/p/target/c.rs",
+            "/p/a.rs",
+            "/p/x.txt",
+        ]
+        .into_iter()
+        .map(PathBuf::from);
+        let changed: Vec<PathBuf> = changed_files(root, &extensions(), events)
+            .into_iter()
+            .collect();
+        assert_eq!(
+            changed,
+            [PathBuf::from("/p/a.rs"), PathBuf::from("/p/b.rs")]
+        );
+    }
+}
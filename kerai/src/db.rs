@@ -1,4 +1,6 @@
+use postgres::types::ToSql;
 use postgres::{Client, NoTls};
+use serde_json::Value;
 
 use crate::config::Profile;
 
@@ -9,12 +11,42 @@ pub fn connect(profile: &Profile, db_override: Option<&str>) -> Result<Client, S
         .or(profile.connection.as_deref())
         .ok_or("No connection string. Use --db or set one in .kerai/config.toml")?;
 
-    Client::connect(conn_str, NoTls).map_err(|e| format!("Connection failed: {e}"))
+    connect_url(conn_str)
 }
 
-/// Ensure ltree and kerai extensions are loaded.
+/// Connect with a connection string, showing the server's progress
+/// notices on the way (see `progress::on_notice`).
+pub fn connect_url(conn_str: &str) -> Result<Client, String> {
+    let mut config: postgres::Config = conn_str
+        .parse()
+        .map_err(|e| format!("Connection failed: {e}"))?;
+    config
+        .notice_callback(|notice| crate::progress::on_notice(notice.message()))
+        .connect(NoTls)
+        .map_err(|e| format!("Connection failed: {e}"))
+}
+
+/// Ensure ltree, pg_trgm and kerai extensions are loaded.
 pub fn ensure_extension(client: &mut Client) -> Result<(), String> {
     client
-        .batch_execute("CREATE EXTENSION IF NOT EXISTS ltree; CREATE EXTENSION IF NOT EXISTS kerai CASCADE;")
+        .batch_execute(
+            "CREATE EXTENSION IF NOT EXISTS ltree; CREATE EXTENSION IF NOT EXISTS pg_trgm; \
+             CREATE EXTENSION IF NOT EXISTS kerai CASCADE;",
+        )
         .map_err(|e| format!("Failed to create extension: {e}"))
 }
+
+/// Run `sql`, which returns one row with JSON as text in its first column,
+/// and parse that JSON. `what` names the call in errors.
+pub fn query_json(
+    client: &mut Client,
+    what: &str,
+    sql: &str,
+    args: &[&(dyn ToSql + Sync)],
+) -> Result<Value, String> {
+    let row = client
+        .query_one(sql, args)
+        .map_err(|e| format!("{what} failed: {e}"))?;
+    let text: String = row.get(0);
+    serde_json::from_str(&text).map_err(|e| format!("Invalid JSON: {e}"))
+}
@@ -1,6 +1,8 @@
 use crate::lang::machine::Machine;
 use crate::lang::ptr::Ptr;
 
+use super::time;
+
 /// Pop two numeric items, apply op, push result.
 fn binary_op(m: &mut Machine, op: &str) -> Result<(), String> {
     if m.depth() < 2 {
@@ -10,6 +12,15 @@ fn binary_op(m: &mut Machine, op: &str) -> Result<(), String> {
     let b = m.pop().unwrap();
     let a = m.pop().unwrap();
 
+    // Timestamp/interval operands: defer to Postgres via a time_request
+    if matches!(op, "+" | "-") && (time::is_temporal(&a) || time::is_temporal(&b)) {
+        let time_op = if op == "+" { "add" } else { "sub" };
+        if let Some(req) = time::binary_request(time_op, &a, &b) {
+            m.push(req);
+            return Ok(());
+        }
+    }
+
     if !a.is_numeric() || !b.is_numeric() {
         // Push both back and error
         m.push(a);
@@ -102,10 +113,11 @@ pub fn modulo(m: &mut Machine) -> Result<(), String> {
 mod tests {
     use super::*;
     use crate::lang::handlers::register_all;
+    use crate::lang::machine::Role;
 
     fn make_machine() -> Machine {
-        let (handlers, type_methods, help) = register_all();
-        Machine::new(uuid::Uuid::nil(), uuid::Uuid::nil(), handlers, type_methods, help)
+        let (handlers, type_methods, help, roles) = register_all();
+        Machine::new(uuid::Uuid::nil(), uuid::Uuid::nil(), Role::User, handlers, type_methods, help, roles)
     }
 
     #[test]
@@ -0,0 +1,220 @@
+use serde_json::{json, Value};
+
+use crate::lang::machine::Machine;
+use crate::lang::ptr::Ptr;
+
+/// Most histogram bins chosen automatically (sqrt rule, capped).
+const MAX_AUTO_BINS: usize = 20;
+
+/// Sparkline glyphs from lowest to highest.
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Chart words turn a list or a loaded query result into a `chart` item.
+/// The spec in meta follows vega-lite naming (`mark`, `data.values`,
+/// `encoding.x/y`) so the web UI can draw it directly; text renderers show
+/// the `y` series as a sparkline.
+fn chart(mark: &str, title: &str, x_type: &str, values: Vec<Value>) -> Ptr {
+    Ptr {
+        kind: "chart".into(),
+        ref_id: title.into(),
+        meta: json!({
+            "mark": mark,
+            "title": title,
+            "data": {"values": values},
+            "encoding": {
+                "x": {"field": "x", "type": x_type},
+                "y": {"field": "y", "type": "quantitative"},
+            },
+        }),
+        id: 0,
+    }
+}
+
+/// Numeric series of a list (numeric items) or result (numeric row content).
+fn series(ptr: &Ptr) -> Option<Vec<f64>> {
+    match ptr.kind.as_str() {
+        "list" => {
+            let items: Vec<Ptr> = serde_json::from_value(ptr.meta.clone()).ok()?;
+            Some(items.iter().filter_map(|p| p.as_float()).collect())
+        }
+        "result" => {
+            let rows = ptr.meta.get("rows")?.as_array()?;
+            Some(
+                rows.iter()
+                    .filter_map(|r| r.get("content")?.as_str()?.trim().parse().ok())
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Row counts per `kind` of a loaded result, in first-seen order.
+fn kind_counts(ptr: &Ptr) -> Vec<(String, i64)> {
+    let mut counts: Vec<(String, i64)> = Vec::new();
+    let rows = ptr.meta.get("rows").and_then(|v| v.as_array());
+    for row in rows.into_iter().flatten() {
+        let kind = row.get("kind").and_then(|v| v.as_str()).unwrap_or("?");
+        match counts.iter_mut().find(|(k, _)| k == kind) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((kind.to_string(), 1)),
+        }
+    }
+    counts
+}
+
+/// Pop the chart source, restoring it (and any popped extras) on error.
+fn pop_source(m: &mut Machine, word: &str) -> Result<Ptr, String> {
+    let source = m.pop().ok_or_else(|| format!("{word}: need a list or result"))?;
+    if source.kind == "list" || source.kind == "result" {
+        Ok(source)
+    } else {
+        m.push(source);
+        Err(format!("{word}: expected a list or result"))
+    }
+}
+
+/// Equal-width bins over `values`; returns `(label, count)` per bin.
+pub fn bin(values: &[f64], bins: usize) -> Vec<(String, i64)> {
+    if values.is_empty() || bins == 0 {
+        return Vec::new();
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let width = if max > min { (max - min) / bins as f64 } else { 1.0 };
+
+    let mut counts = vec![0i64; bins];
+    for &v in values {
+        let idx = (((v - min) / width) as usize).min(bins - 1);
+        counts[idx] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, n)| {
+            let lo = min + width * i as f64;
+            (format!("{}–{}", trim_float(lo), trim_float(lo + width)), n)
+        })
+        .collect()
+}
+
+fn trim_float(f: f64) -> String {
+    let s = format!("{:.2}", f);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Render a series as a one-line sparkline.
+pub fn sparkline(values: &[f64]) -> String {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|&v| {
+            if max > min {
+                let level = ((v - min) / (max - min) * (SPARKS.len() - 1) as f64).round();
+                SPARKS[level as usize]
+            } else {
+                SPARKS[SPARKS.len() / 2]
+            }
+        })
+        .collect()
+}
+
+/// `histogram` — bin a numeric list/result: `list histogram` or `list N histogram`.
+pub fn histogram(m: &mut Machine) -> Result<(), String> {
+    let bins = match m.peek().and_then(|p| p.as_int()) {
+        Some(n) if m.depth() >= 2 => {
+            if n <= 0 {
+                return Err("histogram: bin count must be positive".into());
+            }
+            m.pop();
+            Some(n as usize)
+        }
+        _ => None,
+    };
+    let source = match pop_source(m, "histogram") {
+        Ok(s) => s,
+        Err(e) => {
+            if let Some(n) = bins {
+                m.push(Ptr::int(n as i64));
+            }
+            return Err(e);
+        }
+    };
+
+    let values = series(&source).unwrap_or_default();
+    if values.is_empty() {
+        m.push(source);
+        return Err("histogram: no numeric values".into());
+    }
+    let bins = bins.unwrap_or_else(|| ((values.len() as f64).sqrt().ceil() as usize).clamp(1, MAX_AUTO_BINS));
+
+    let points = bin(&values, bins)
+        .into_iter()
+        .map(|(label, n)| json!({"x": label, "y": n}))
+        .collect();
+    m.push(chart("bar", "histogram", "ordinal", points));
+    Ok(())
+}
+
+/// `bar` — one bar per list item, or per node kind for a result.
+pub fn bar(m: &mut Machine) -> Result<(), String> {
+    let source = pop_source(m, "bar")?;
+    let points: Vec<Value> = if source.kind == "result" {
+        kind_counts(&source)
+            .into_iter()
+            .map(|(kind, n)| json!({"x": kind, "y": n}))
+            .collect()
+    } else {
+        series(&source)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| json!({"x": i, "y": v}))
+            .collect()
+    };
+    if points.is_empty() {
+        m.push(source);
+        return Err("bar: nothing to plot".into());
+    }
+    m.push(chart("bar", "bar", "ordinal", points));
+    Ok(())
+}
+
+/// `timeseries` — line chart of a numeric list/result in order.
+pub fn timeseries(m: &mut Machine) -> Result<(), String> {
+    let source = pop_source(m, "timeseries")?;
+    let values = series(&source).unwrap_or_default();
+    if values.is_empty() {
+        m.push(source);
+        return Err("timeseries: no numeric values".into());
+    }
+    let points = values
+        .into_iter()
+        .enumerate()
+        .map(|(i, v)| json!({"x": i, "y": v}))
+        .collect();
+    m.push(chart("line", "timeseries", "quantitative", points));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparkline_spans_range() {
+        assert_eq!(sparkline(&[0.0, 7.0, 3.5]), "▁█▅");
+        assert_eq!(sparkline(&[2.0, 2.0]), "▅▅");
+    }
+
+    #[test]
+    fn bin_counts_all_values() {
+        let bins = bin(&[1.0, 2.0, 2.5, 4.0], 3);
+        assert_eq!(bins.len(), 3);
+        assert_eq!(bins.iter().map(|(_, n)| n).sum::<i64>(), 4);
+        assert_eq!(bins[0].0, "1–2");
+        // The maximum lands in the last bin
+        assert_eq!(bins[2].1, 1);
+    }
+}
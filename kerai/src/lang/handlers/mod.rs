@@ -1,125 +1,193 @@
-pub mod admin;
-pub mod arithmetic;
-pub mod login;
-pub mod stack_ops;
-pub mod workspace;
-
-use std::collections::HashMap;
-
-use super::machine::Handler;
-
-/// Register all handlers, type methods, and help text.
-/// Returns (global_handlers, type_methods, help).
-pub fn register_all() -> (
-    HashMap<String, Handler>,
-    HashMap<(String, String), Handler>,
-    HashMap<String, String>,
-) {
-    let mut handlers: HashMap<String, Handler> = HashMap::new();
-    let mut type_methods: HashMap<(String, String), Handler> = HashMap::new();
-    let mut help: HashMap<String, String> = HashMap::new();
-
-    // Stack operations (drop, fold, view handled as special words in execute())
-    handlers.insert("dup".into(), stack_ops::dup);
-    handlers.insert("swap".into(), stack_ops::swap);
-    handlers.insert("over".into(), stack_ops::over);
-    handlers.insert("rot".into(), stack_ops::rot);
-    handlers.insert("clear".into(), stack_ops::clear);
-    handlers.insert("depth".into(), stack_ops::depth);
-
-    help.insert("dup".into(), "duplicate top of stack".into());
-    help.insert("drop".into(), "remove items (drop. = all, drop.-N, drop.A-B, drop.ROWID)".into());
-    help.insert("fold".into(), "collapse items to one line (fold. = all, same targeting as drop)".into());
-    help.insert("view".into(), "expand items (view. = all, same targeting as drop)".into());
-    help.insert("swap".into(), "swap top two stack items".into());
-    help.insert("over".into(), "copy second item to top".into());
-    help.insert("rot".into(), "rotate top three items".into());
-    help.insert("clear".into(), "clear the stack".into());
-    help.insert("depth".into(), "push stack depth".into());
-
-    // Arithmetic operators
-    handlers.insert("+".into(), arithmetic::add);
-    handlers.insert("-".into(), arithmetic::sub);
-    handlers.insert("*".into(), arithmetic::mul);
-    handlers.insert("/".into(), arithmetic::div);
-    handlers.insert("%".into(), arithmetic::modulo);
-
-    help.insert("+".into(), "add top two numbers".into());
-    help.insert("-".into(), "subtract top from second".into());
-    help.insert("*".into(), "multiply top two numbers".into());
-    help.insert("/".into(), "divide second by top".into());
-    help.insert("%".into(), "modulo second by top".into());
-
-    // Library pushers
-    handlers.insert("workspace".into(), workspace::workspace_lib);
-    handlers.insert("login".into(), login::login_lib);
-    handlers.insert("admin".into(), admin::admin_lib);
-
-    help.insert("workspace".into(), "workspace management commands".into());
-    help.insert("login".into(), "authentication commands".into());
-    help.insert("admin".into(), "administration commands".into());
-
-    // Workspace library methods
-    type_methods.insert(
-        ("library:workspace".into(), "list".into()),
-        workspace::ws_list,
-    );
-    type_methods.insert(
-        ("library:workspace".into(), "load".into()),
-        workspace::ws_load,
-    );
-    type_methods.insert(
-        ("library:workspace".into(), "new".into()),
-        workspace::ws_new,
-    );
-    type_methods.insert(
-        ("library:workspace".into(), "save".into()),
-        workspace::ws_save,
-    );
-
-    help.insert("library:workspace/list".into(), "list all workspaces".into());
-    help.insert("library:workspace/load".into(), "load workspace by number".into());
-    help.insert("library:workspace/new".into(), "create a new workspace".into());
-    help.insert("library:workspace/save".into(), "save current workspace with a name".into());
-
-    // Login library methods
-    type_methods.insert(
-        ("library:login".into(), "bsky".into()),
-        login::login_bsky,
-    );
-
-    help.insert("library:login/bsky".into(), "authenticate with Bluesky".into());
-
-    // Admin library methods
-    type_methods.insert(
-        ("library:admin".into(), "oauth".into()),
-        admin::admin_oauth,
-    );
-    type_methods.insert(
-        ("library:admin.oauth".into(), "setup".into()),
-        admin::admin_oauth_setup,
-    );
-    type_methods.insert(
-        ("library:admin.oauth.setup".into(), "bsky".into()),
-        admin::admin_oauth_setup_bsky,
-    );
-    type_methods.insert(
-        ("library:admin".into(), "user".into()),
-        admin::admin_user,
-    );
-    type_methods.insert(
-        ("library:admin.user".into(), "allow".into()),
-        admin::admin_user_allow,
-    );
-
-    help.insert("library:admin/oauth".into(), "OAuth configuration".into());
-    help.insert("library:admin/user".into(), "user management".into());
-    help.insert("library:admin.oauth/setup".into(), "OAuth setup commands".into());
-    help.insert("library:admin.oauth.setup/bsky".into(), "generate ES256 keypair for Bluesky OAuth".into());
-    help.insert("library:admin.user/allow".into(), "allowlist a bsky handle for login".into());
-
-    // help command (handled as special word in execute(), not via handler map)
-    help.insert("help".into(), "list all commands".into());
-
-    (handlers, type_methods, help)
-}
+pub mod admin;
+pub mod arithmetic;
+pub mod chart;
+pub mod login;
+pub mod query;
+pub mod stack_ops;
+pub mod time;
+pub mod words;
+pub mod workspace;
+
+use std::collections::HashMap;
+
+use super::machine::{Handler, Role};
+
+/// Handler maps as passed to `Machine::new`:
+/// (global_handlers, type_methods, help, required_roles).
+pub type Registry = (
+    HashMap<String, Handler>,
+    HashMap<(String, String), Handler>,
+    HashMap<String, String>,
+    HashMap<String, Role>,
+);
+
+/// Register the words that run without a database: stack operations,
+/// arithmetic on numbers and charts of lists. Used on its own for
+/// client-side previews (see `preview`).
+pub fn register_pure() -> Registry {
+    let mut handlers: HashMap<String, Handler> = HashMap::new();
+    let mut help: HashMap<String, String> = HashMap::new();
+
+    // Stack operations (drop, fold, view handled as special words in execute())
+    handlers.insert("dup".into(), stack_ops::dup);
+    handlers.insert("swap".into(), stack_ops::swap);
+    handlers.insert("over".into(), stack_ops::over);
+    handlers.insert("rot".into(), stack_ops::rot);
+    handlers.insert("clear".into(), stack_ops::clear);
+    handlers.insert("depth".into(), stack_ops::depth);
+
+    help.insert("dup".into(), "duplicate top of stack".into());
+    help.insert("drop".into(), "remove items (drop. = all, drop.-N, drop.A-B, drop.ROWID)".into());
+    help.insert("fold".into(), "collapse items to one line (fold. = all, same targeting as drop)".into());
+    help.insert("view".into(), "expand items (view. = all, same targeting as drop)".into());
+    help.insert("swap".into(), "swap top two stack items".into());
+    help.insert("over".into(), "copy second item to top".into());
+    help.insert("rot".into(), "rotate top three items".into());
+    help.insert("clear".into(), "clear the stack".into());
+    help.insert("depth".into(), "push stack depth".into());
+
+    // Arithmetic operators
+    handlers.insert("+".into(), arithmetic::add);
+    handlers.insert("-".into(), arithmetic::sub);
+    handlers.insert("*".into(), arithmetic::mul);
+    handlers.insert("/".into(), arithmetic::div);
+    handlers.insert("%".into(), arithmetic::modulo);
+
+    help.insert("+".into(), "add top two numbers".into());
+    help.insert("-".into(), "subtract top from second".into());
+    help.insert("*".into(), "multiply top two numbers".into());
+    help.insert("/".into(), "divide second by top".into());
+    help.insert("%".into(), "modulo second by top".into());
+
+    // Charts (lists or loaded results; sparkline in text, drawn in the web UI)
+    handlers.insert("histogram".into(), chart::histogram);
+    handlers.insert("bar".into(), chart::bar);
+    handlers.insert("timeseries".into(), chart::timeseries);
+
+    help.insert("histogram".into(), "bin numbers into a bar chart (list histogram, or list N histogram)".into());
+    help.insert("bar".into(), "bar chart of list values, or of node kinds in a result".into());
+    help.insert("timeseries".into(), "line chart of numbers in order".into());
+
+    // help command (handled as special word in execute(), not via handler map)
+    help.insert("help".into(), "list all commands".into());
+
+    (handlers, HashMap::new(), help, HashMap::new())
+}
+
+/// Register all handlers, type methods, help text, and required roles.
+pub fn register_all() -> Registry {
+    let (mut handlers, mut type_methods, mut help, mut roles) = register_pure();
+
+    // Date/time (resolved against Postgres by the serve layer)
+    handlers.insert("now".into(), time::now);
+    handlers.insert("+days".into(), time::plus_days);
+    handlers.insert("diff".into(), time::diff);
+    handlers.insert("format".into(), time::format);
+    handlers.insert("timestamp".into(), time::timestamp);
+    handlers.insert("interval".into(), time::interval);
+
+    help.insert("now".into(), "push the current timestamp".into());
+    help.insert("+days".into(), "shift a timestamp by N days (ts N +days)".into());
+    help.insert("diff".into(), "interval between two timestamps (second minus top)".into());
+    help.insert("format".into(), "format a timestamp with a to_char pattern (ts \"YYYY-MM-DD\" format)".into());
+    help.insert("timestamp".into(), "read text as a timestamp (\"2026-01-01\" timestamp)".into());
+    help.insert("interval".into(), "read text as an interval (\"7 days\" interval)".into());
+
+    // Queries (results are paged in by the serve layer on `view`)
+    handlers.insert("find".into(), query::find);
+    handlers.insert("limit".into(), query::limit);
+    handlers.insert("explain".into(), query::explain);
+
+    help.insert("find".into(), "search node content, paged result (\"%pattern%\" find)".into());
+    help.insert("limit".into(), "cap rows fetched per page of a result (result N limit)".into());
+    help.insert("explain".into(), "query plan with row estimates for a result (result explain)".into());
+
+    // User words (stored with the workspace by the serve layer)
+    handlers.insert("define".into(), words::define);
+
+    help.insert("define".into(), "make a user word of a body (\"dup +\" \"double\" define)".into());
+
+    // Library pushers
+    handlers.insert("workspace".into(), workspace::workspace_lib);
+    handlers.insert("login".into(), login::login_lib);
+    handlers.insert("admin".into(), admin::admin_lib);
+
+    help.insert("workspace".into(), "workspace management commands".into());
+    help.insert("login".into(), "authentication commands".into());
+    help.insert("admin".into(), "administration commands".into());
+
+    // Workspace library methods
+    type_methods.insert(
+        ("library:workspace".into(), "list".into()),
+        workspace::ws_list,
+    );
+    type_methods.insert(
+        ("library:workspace".into(), "load".into()),
+        workspace::ws_load,
+    );
+    type_methods.insert(
+        ("library:workspace".into(), "new".into()),
+        workspace::ws_new,
+    );
+    type_methods.insert(
+        ("library:workspace".into(), "save".into()),
+        workspace::ws_save,
+    );
+    type_methods.insert(
+        ("library:workspace".into(), "export".into()),
+        workspace::ws_export,
+    );
+    type_methods.insert(
+        ("library:workspace".into(), "import".into()),
+        workspace::ws_import,
+    );
+
+    help.insert("library:workspace/list".into(), "list all workspaces".into());
+    help.insert("library:workspace/load".into(), "load workspace by number".into());
+    help.insert("library:workspace/new".into(), "create a new workspace".into());
+    help.insert("library:workspace/save".into(), "save current workspace with a name".into());
+    help.insert("library:workspace/export".into(), "export current workspace as a JSON bundle".into());
+    help.insert("library:workspace/import".into(), "create a new workspace from a bundle".into());
+
+    // Login library methods
+    type_methods.insert(
+        ("library:login".into(), "bsky".into()),
+        login::login_bsky,
+    );
+
+    help.insert("library:login/bsky".into(), "authenticate with Bluesky".into());
+
+    // Admin library methods
+    type_methods.insert(
+        ("library:admin".into(), "oauth".into()),
+        admin::admin_oauth,
+    );
+    type_methods.insert(
+        ("library:admin.oauth".into(), "setup".into()),
+        admin::admin_oauth_setup,
+    );
+    type_methods.insert(
+        ("library:admin.oauth.setup".into(), "bsky".into()),
+        admin::admin_oauth_setup_bsky,
+    );
+    type_methods.insert(
+        ("library:admin".into(), "user".into()),
+        admin::admin_user,
+    );
+    type_methods.insert(
+        ("library:admin.user".into(), "allow".into()),
+        admin::admin_user_allow,
+    );
+
+    help.insert("library:admin/oauth".into(), "OAuth configuration".into());
+    help.insert("library:admin/user".into(), "user management".into());
+    help.insert("library:admin.oauth/setup".into(), "OAuth setup commands".into());
+    help.insert("library:admin.oauth.setup/bsky".into(), "generate ES256 keypair for Bluesky OAuth".into());
+    help.insert("library:admin.user/allow".into(), "allowlist a bsky handle for login".into());
+
+    // Admin actions; navigating the admin library stays open
+    roles.insert("library:admin.oauth.setup/bsky".into(), Role::Admin);
+    roles.insert("library:admin.user/allow".into(), Role::Admin);
+
+    (handlers, type_methods, help, roles)
+}
@@ -0,0 +1,116 @@
+use serde_json::{json, Value};
+
+use crate::lang::machine::Machine;
+use crate::lang::ptr::Ptr;
+
+/// Rows fetched per `view` unless capped with `limit`.
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+/// Upper bound for `limit`, so a single page stays small enough to ship.
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Query words never put result rows on the stack directly. They push a
+/// `query_request` describing the query; the serve layer counts matches and
+/// turns it into a `result` item holding that description plus a paging
+/// offset (the cursor). Rows are fetched one page at a time when the item
+/// is viewed, so a 100k-row match costs one small page per `view`.
+fn query_request(label: &str, spec: serde_json::Value) -> Ptr {
+    Ptr {
+        kind: "query_request".into(),
+        ref_id: label.into(),
+        meta: json!({"query": spec, "page_size": DEFAULT_PAGE_SIZE}),
+        id: 0,
+    }
+}
+
+/// `find` — pop a text pattern (ILIKE), push a paged result of matching nodes.
+pub fn find(m: &mut Machine) -> Result<(), String> {
+    let pattern = m.pop().ok_or("find: need a pattern")?;
+    if pattern.kind != "text" {
+        m.push(pattern);
+        return Err("find: expected \"pattern\" find".into());
+    }
+    m.push(query_request(
+        &pattern.ref_id,
+        json!({"op": "find", "pattern": pattern.ref_id}),
+    ));
+    Ok(())
+}
+
+/// `limit` — pop an int N and cap the page size of the query or result below it.
+pub fn limit(m: &mut Machine) -> Result<(), String> {
+    if m.depth() < 2 {
+        return Err("limit: need a query result and a row count".into());
+    }
+    let n = m.pop().unwrap();
+    let size = match n.as_int() {
+        Some(size) if size > 0 => size.min(MAX_PAGE_SIZE),
+        _ => {
+            m.push(n);
+            return Err("limit: expected a positive int".into());
+        }
+    };
+
+    let target = m.stack.last_mut().unwrap();
+    if !matches!(target.kind.as_str(), "query_request" | "result") {
+        m.push(n);
+        return Err("limit: expected <result> N limit".into());
+    }
+    target.meta["page_size"] = json!(size);
+
+    // Trim an already loaded page and pull the cursor back to its new end
+    let mut dropped = 0;
+    if let Some(rows) = target.meta.get_mut("rows").and_then(|v| v.as_array_mut()) {
+        dropped = rows.len().saturating_sub(size as usize) as i64;
+        rows.truncate(size as usize);
+    }
+    if dropped > 0 {
+        let offset = target.meta["offset"].as_i64().unwrap_or(0);
+        target.meta["offset"] = json!((offset - dropped).max(0));
+    }
+    Ok(())
+}
+
+/// `explain` — pop a query or result, push an `explain_request` that the
+/// serve layer answers with a `plan` item for the page query it would run.
+pub fn explain(m: &mut Machine) -> Result<(), String> {
+    let target = m.pop().ok_or("explain: need a query result")?;
+    if !matches!(target.kind.as_str(), "query_request" | "result") {
+        m.push(target);
+        return Err("explain: expected <result> explain".into());
+    }
+    let mut meta = target.meta;
+    if let Some(obj) = meta.as_object_mut() {
+        obj.remove("rows");
+        obj.remove("fetch");
+    }
+    m.push(Ptr {
+        kind: "explain_request".into(),
+        ref_id: target.ref_id,
+        meta,
+        id: 0,
+    });
+    Ok(())
+}
+
+/// Indented text rendering of an annotated plan tree, one line per node.
+pub fn plan_lines(node: &Value, depth: usize, out: &mut Vec<String>) {
+    let mut line = format!("{}{}", "  ".repeat(depth), node["node"].as_str().unwrap_or("?"));
+    if let Some(rel) = node["relation"].as_str() {
+        line.push_str(&format!(" on {rel}"));
+    }
+    if let Some(index) = node["index"].as_str() {
+        line.push_str(&format!(" using {index}"));
+    }
+    line.push_str(&format!(
+        " (rows≈{}, cost {:.2})",
+        node["rows"].as_f64().unwrap_or(0.0),
+        node["cost"].as_f64().unwrap_or(0.0),
+    ));
+    if let Some(detail) = node["detail"].as_str() {
+        line.push_str(&format!(" {detail}"));
+    }
+    out.push(line);
+    for child in node["children"].as_array().into_iter().flatten() {
+        plan_lines(child, depth + 1, out);
+    }
+}
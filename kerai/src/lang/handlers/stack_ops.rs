@@ -72,10 +72,11 @@ pub fn depth(m: &mut Machine) -> Result<(), String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lang::machine::Role;
 
     fn make_stack(items: Vec<Ptr>) -> Machine {
-        let (handlers, type_methods, help) = crate::lang::handlers::register_all();
-        let mut m = Machine::new(uuid::Uuid::nil(), uuid::Uuid::nil(), handlers, type_methods, help);
+        let (handlers, type_methods, help, roles) = crate::lang::handlers::register_all();
+        let mut m = Machine::new(uuid::Uuid::nil(), uuid::Uuid::nil(), Role::User, handlers, type_methods, help, roles);
         m.stack = items;
         m
     }
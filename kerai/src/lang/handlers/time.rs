@@ -0,0 +1,139 @@
+use serde_json::{json, Value};
+
+use crate::lang::machine::Machine;
+use crate::lang::ptr::Ptr;
+
+/// Date/time words build an expression tree in a `time_request` marker.
+/// The serve layer compiles the tree to SQL so that all date math uses
+/// Postgres `timestamptz`/`interval` semantics — the same ones applied to
+/// stored data. Chained words (`now 7 +days`) nest their operand's tree
+/// instead of resolving it, so a whole expression becomes one query.
+fn time_request(label: &str, expr: Value) -> Ptr {
+    Ptr {
+        kind: "time_request".into(),
+        ref_id: label.into(),
+        meta: expr,
+        id: 0,
+    }
+}
+
+/// Expression tree for a stack item usable as a date/time operand.
+///
+/// Text is taken as a timestamp literal; Postgres validates it on resolution.
+pub fn time_expr(ptr: &Ptr) -> Option<Value> {
+    match ptr.kind.as_str() {
+        "timestamp" | "text" => Some(json!({"op": "lit", "type": "timestamp", "value": ptr.ref_id})),
+        "interval" => Some(json!({"op": "lit", "type": "interval", "value": ptr.ref_id})),
+        "time_request" => Some(ptr.meta.clone()),
+        _ => None,
+    }
+}
+
+/// True for items that arithmetic should route to date/time handling.
+pub fn is_temporal(ptr: &Ptr) -> bool {
+    matches!(ptr.kind.as_str(), "timestamp" | "interval" | "time_request")
+}
+
+/// Build an add/sub request from two temporal operands (used by `+`/`-`).
+pub fn binary_request(op: &str, a: &Ptr, b: &Ptr) -> Option<Ptr> {
+    let (ea, eb) = (time_expr(a)?, time_expr(b)?);
+    Some(time_request(op, json!({"op": op, "a": ea, "b": eb})))
+}
+
+/// `now` — push the current transaction timestamp.
+pub fn now(m: &mut Machine) -> Result<(), String> {
+    m.push(time_request("now", json!({"op": "now"})));
+    Ok(())
+}
+
+/// Pop text and push it as a literal of `kind`, made by `make`.
+fn literal(m: &mut Machine, kind: &str, make: fn(&str) -> Ptr) -> Result<(), String> {
+    let top = m.pop().ok_or_else(|| format!("{kind}: need text"))?;
+    if top.kind != "text" {
+        m.push(top);
+        return Err(format!("{kind}: expected \"text\" {kind}"));
+    }
+    m.push(make(&top.ref_id));
+    Ok(())
+}
+
+/// `timestamp` — pop text, push it as a timestamp literal.
+pub fn timestamp(m: &mut Machine) -> Result<(), String> {
+    literal(m, "timestamp", Ptr::timestamp)
+}
+
+/// `interval` — pop text, push it as an interval literal (`"7 days" interval`).
+pub fn interval(m: &mut Machine) -> Result<(), String> {
+    literal(m, "interval", Ptr::interval)
+}
+
+/// `+days` — pop an int N and a timestamp/interval, push it shifted by N days.
+pub fn plus_days(m: &mut Machine) -> Result<(), String> {
+    if m.depth() < 2 {
+        return Err("+days: need a timestamp and a day count".into());
+    }
+    let days = m.pop().unwrap();
+    let base = m.pop().unwrap();
+
+    match (days.as_int(), time_expr(&base)) {
+        (Some(n), Some(expr)) => {
+            m.push(time_request("+days", json!({"op": "add_days", "arg": expr, "days": n})));
+            Ok(())
+        }
+        _ => {
+            m.push(base);
+            m.push(days);
+            Err("+days: expected <timestamp> <int> +days".into())
+        }
+    }
+}
+
+/// `diff` — pop two timestamps, push the interval `second - top`.
+pub fn diff(m: &mut Machine) -> Result<(), String> {
+    if m.depth() < 2 {
+        return Err("diff: need two timestamps".into());
+    }
+    let b = m.pop().unwrap();
+    let a = m.pop().unwrap();
+
+    match binary_request("sub", &a, &b) {
+        Some(req) => {
+            m.push(req);
+            Ok(())
+        }
+        None => {
+            m.push(a);
+            m.push(b);
+            Err("diff: both operands must be timestamps".into())
+        }
+    }
+}
+
+/// `format` — pop a pattern and a timestamp/interval, push `to_char` text.
+pub fn format(m: &mut Machine) -> Result<(), String> {
+    if m.depth() < 2 {
+        return Err("format: need a timestamp and a pattern".into());
+    }
+    let pattern = m.pop().unwrap();
+    let value = m.pop().unwrap();
+
+    let expr = if pattern.kind == "text" && is_temporal(&value) {
+        time_expr(&value)
+    } else {
+        None
+    };
+    match expr {
+        Some(expr) => {
+            m.push(time_request(
+                "format",
+                json!({"op": "format", "arg": expr, "pattern": pattern.ref_id}),
+            ));
+            Ok(())
+        }
+        None => {
+            m.push(value);
+            m.push(pattern);
+            Err("format: expected <timestamp> \"pattern\" format".into())
+        }
+    }
+}
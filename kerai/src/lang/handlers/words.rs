@@ -0,0 +1,28 @@
+use crate::lang::machine::Machine;
+use crate::lang::ptr::Ptr;
+
+/// `define` — pop a name and the body under it, both text, and make the
+/// name a user word that runs the body (`"dup +" "double" define`). The
+/// serve layer stores it with the workspace.
+pub fn define(m: &mut Machine) -> Result<(), String> {
+    let name = m.pop().ok_or("define: need a body and a name")?;
+    let body = match m.pop() {
+        Some(body) => body,
+        None => {
+            m.push(name);
+            return Err("define: need a body and a name".into());
+        }
+    };
+    if name.kind != "text" || body.kind != "text" {
+        m.push(body);
+        m.push(name);
+        return Err("define: expected \"body\" \"name\" define".into());
+    }
+    if let Err(e) = m.define_word(&name.ref_id, &body.ref_id) {
+        m.push(body);
+        m.push(name);
+        return Err(e);
+    }
+    m.push(Ptr::success(&format!("defined '{}'", name.ref_id)));
+    Ok(())
+}
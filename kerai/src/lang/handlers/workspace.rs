@@ -79,3 +79,45 @@ pub fn ws_save(m: &mut Machine) -> Result<(), String> {
     });
     Ok(())
 }
+
+/// `workspace export` — request a JSON bundle of the current workspace.
+/// The serve layer replaces the marker with a `workspace_bundle` item.
+pub fn ws_export(m: &mut Machine) -> Result<(), String> {
+    m.push(Ptr {
+        kind: "workspace_export_request".into(),
+        ref_id: m.workspace_id.to_string(),
+        meta: serde_json::Value::Null,
+        id: 0,
+    });
+    Ok(())
+}
+
+/// `workspace import` — pop a bundle (a `workspace_bundle` item or text holding
+/// bundle JSON) and request that the serve layer recreate it as a new workspace.
+pub fn ws_import(m: &mut Machine) -> Result<(), String> {
+    let source = m.pop().ok_or("workspace import: need a bundle on the stack")?;
+
+    let bundle = match source.kind.as_str() {
+        "workspace_bundle" => source.meta.clone(),
+        "text" => match serde_json::from_str::<serde_json::Value>(&source.ref_id) {
+            Ok(v) => v,
+            Err(e) => {
+                m.push(source);
+                return Err(format!("workspace import: invalid bundle JSON: {e}"));
+            }
+        },
+        other => {
+            let msg = format!("workspace import: expected workspace_bundle or text, got {other}");
+            m.push(source);
+            return Err(msg);
+        }
+    };
+
+    m.push(Ptr {
+        kind: "workspace_import_request".into(),
+        ref_id: String::new(),
+        meta: bundle,
+        id: 0,
+    });
+    Ok(())
+}
@@ -6,6 +6,7 @@ pub mod machine;
 mod parser;
 mod pratt;
 pub mod ptr;
+pub mod template;
 pub mod token;
 
 use std::fs;
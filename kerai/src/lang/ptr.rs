@@ -2,6 +2,9 @@ use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
+use super::handlers::chart::sparkline;
+use super::handlers::query::plan_lines;
+
 /// A typed pointer on the stack. Every stack item is a Ptr.
 ///
 /// `kind` determines how the item is rendered and what methods dispatch on it.
@@ -100,6 +103,16 @@ impl Ptr {
         }
     }
 
+    /// An error for a word the session's role may not run.
+    pub fn permission_denied(word: &str, required: &str) -> Self {
+        Self {
+            kind: "error".into(),
+            ref_id: format!("permission denied: {word} requires {required}"),
+            meta: serde_json::json!({"permission": {"word": word, "required": required}}),
+            id: 0,
+        }
+    }
+
     pub fn help_list(items: Vec<serde_json::Value>) -> Self {
         Self {
             kind: "list.help".into(),
@@ -109,6 +122,24 @@ impl Ptr {
         }
     }
 
+    pub fn timestamp(s: &str) -> Self {
+        Self {
+            kind: "timestamp".into(),
+            ref_id: s.to_string(),
+            meta: serde_json::Value::Null,
+            id: 0,
+        }
+    }
+
+    pub fn interval(s: &str) -> Self {
+        Self {
+            kind: "interval".into(),
+            ref_id: s.to_string(),
+            meta: serde_json::Value::Null,
+            id: 0,
+        }
+    }
+
     pub fn library(name: &str) -> Self {
         Self {
             kind: "library".into(),
@@ -136,6 +167,15 @@ impl Ptr {
         }
     }
 
+    /// The `y` series of a chart spec, in data order.
+    pub fn chart_values(&self) -> Vec<f64> {
+        self.meta
+            .pointer("/data/values")
+            .and_then(|v| v.as_array())
+            .map(|points| points.iter().filter_map(|p| p.get("y")?.as_f64()).collect())
+            .unwrap_or_default()
+    }
+
     /// Check if this Ptr is numeric (int or float).
     pub fn is_numeric(&self) -> bool {
         matches!(self.kind.as_str(), "int" | "float")
@@ -162,6 +202,15 @@ impl fmt::Display for Ptr {
                         .and_then(|v| v.as_array()).map(|a| a.len()).unwrap_or(0);
                     write!(f, "[workspaces: {}]", count)
                 }
+                "result" => {
+                    let total = self.meta.get("total").and_then(|v| v.as_i64()).unwrap_or(0);
+                    write!(f, "[result: {} rows]", total)
+                }
+                "chart" => write!(f, "[chart: {}, {} points]", self.ref_id, self.chart_values().len()),
+                "plan" => {
+                    let cost = self.meta.get("total_cost").and_then(|v| v.as_f64()).unwrap_or(0.0);
+                    write!(f, "[plan: {}, cost {:.2}]", self.ref_id, cost)
+                }
                 "list" => {
                     if let Ok(items) = serde_json::from_value::<Vec<Ptr>>(self.meta.clone()) {
                         write!(f, "[list: {}]", items.len())
@@ -266,6 +315,19 @@ impl fmt::Display for Ptr {
                     write!(f, "commands: (none)")
                 }
             }
+            "workspace_bundle" => {
+                let count = |key: &str| {
+                    self.meta.get(key).and_then(|v| v.as_array()).map(|a| a.len()).unwrap_or(0)
+                };
+                write!(
+                    f,
+                    "workspace bundle: {} ({} items, {} words, {} documents)",
+                    self.ref_id,
+                    count("items"),
+                    count("words"),
+                    count("documents"),
+                )
+            }
             "session" => {
                 let handle = self.meta.get("handle").and_then(|v| v.as_str()).unwrap_or("anonymous");
                 let provider = self.meta.get("provider").and_then(|v| v.as_str()).unwrap_or("?");
@@ -275,6 +337,50 @@ impl fmt::Display for Ptr {
                 let url = self.meta.get("url").and_then(|v| v.as_str()).unwrap_or("?");
                 write!(f, "auth: redirecting to {}", url)
             }
+            "result" => {
+                let total = self.meta.get("total").and_then(|v| v.as_i64()).unwrap_or(0);
+                let rows = self.meta.get("rows").and_then(|v| v.as_array());
+                match rows {
+                    Some(rows) if !rows.is_empty() => {
+                        // `offset` already points past the loaded page
+                        let end = self.meta.get("offset").and_then(|v| v.as_i64()).unwrap_or(0);
+                        let start = end - rows.len() as i64 + 1;
+                        let lines: Vec<String> = rows
+                            .iter()
+                            .map(|r| {
+                                let kind = r.get("kind").and_then(|v| v.as_str()).unwrap_or("?");
+                                let content = r.get("content").and_then(|v| v.as_str()).unwrap_or("");
+                                let path = r.get("path").and_then(|v| v.as_str()).unwrap_or("");
+                                format!("  {} {} {}", kind, content, path).trim_end().to_string()
+                            })
+                            .collect();
+                        write!(f, "result: {}-{} of {} rows\n{}", start, end, total, lines.join("\n"))
+                    }
+                    _ => write!(f, "result: {} rows (view to load)", total),
+                }
+            }
+            "chart" => {
+                let ys = self.chart_values();
+                if ys.is_empty() {
+                    return write!(f, "chart({}): (empty)", self.ref_id);
+                }
+                let min = ys.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = ys.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                write!(
+                    f,
+                    "chart({}): {} {}..{}",
+                    self.ref_id,
+                    sparkline(&ys),
+                    Ptr::float(min),
+                    Ptr::float(max),
+                )
+            }
+            "plan" => {
+                let mut lines = Vec::new();
+                plan_lines(&self.meta["plan"], 1, &mut lines);
+                write!(f, "plan({}):\n{}", self.ref_id, lines.join("\n"))
+            }
+            "timestamp" | "interval" => write!(f, "{}", self.ref_id),
             "error" => write!(f, "error: {}", self.ref_id),
             "library" => write!(f, "[{}]", self.ref_id),
             _ => write!(f, "{}:{}", self.kind, self.ref_id),
@@ -337,6 +443,12 @@ mod tests {
         assert_eq!(Ptr::muted("hint").to_string(), "hint");
     }
 
+    #[test]
+    fn timestamp_display() {
+        assert_eq!(Ptr::timestamp("2026-01-01 00:00:00+00").to_string(), "2026-01-01 00:00:00+00");
+        assert_eq!(Ptr::interval("7 days").to_string(), "7 days");
+    }
+
     #[test]
     fn as_int() {
         assert_eq!(Ptr::int(42).as_int(), Some(42));
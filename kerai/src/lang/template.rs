@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+/// Directive declaring variables a script needs: `kerai.require NAME [NAME ...]`.
+const REQUIRE_DIRECTIVE: &str = "kerai.require";
+
+/// Expand `${VAR}` references in source text from an environment map.
+///
+/// Before any substitution, every `kerai.require` line is checked against
+/// `env`; if any listed variable is absent the whole expansion fails with an
+/// error naming all of them. Require lines are blanked in the output (line
+/// numbers are preserved) since they have no meaning past this point.
+///
+/// `$${` escapes a literal `${`. References to variables that are neither
+/// required nor supplied are left as written.
+pub fn expand(source: &str, env: &HashMap<String, String>) -> Result<String, String> {
+    let missing = missing_required(source, env);
+    if !missing.is_empty() {
+        return Err(format!("missing required variables: {}", missing.join(", ")));
+    }
+
+    let lines: Vec<String> = source
+        .lines()
+        .map(|line| {
+            if is_require_line(line) {
+                String::new()
+            } else {
+                substitute(line, env)
+            }
+        })
+        .collect();
+
+    let mut output = lines.join("\n");
+    if source.ends_with('\n') {
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// Variables named by `kerai.require` lines that `env` does not supply,
+/// in first-mention order without duplicates.
+pub fn missing_required(source: &str, env: &HashMap<String, String>) -> Vec<String> {
+    let mut missing: Vec<String> = Vec::new();
+    for line in source.lines().filter(|l| is_require_line(l)) {
+        for name in line.split_whitespace().skip(1) {
+            if !env.contains_key(name) && !missing.iter().any(|m| m == name) {
+                missing.push(name.to_string());
+            }
+        }
+    }
+    missing
+}
+
+/// Parse `KEY=VALUE` pairs (as given to `--env`) into an environment map.
+pub fn parse_env_pairs(pairs: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut env = HashMap::new();
+    for pair in pairs {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("invalid --env '{pair}': expected KEY=VALUE"))?;
+        if !is_var_name(key) {
+            return Err(format!("invalid variable name '{key}'"));
+        }
+        env.insert(key.to_string(), value.to_string());
+    }
+    Ok(env)
+}
+
+fn is_require_line(line: &str) -> bool {
+    line.split_whitespace().next() == Some(REQUIRE_DIRECTIVE)
+}
+
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Substitute `${VAR}` references in a single line.
+fn substitute(line: &str, env: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(idx) = rest.find('$') {
+        out.push_str(&rest[..idx]);
+        let tail = &rest[idx..];
+
+        if let Some(escaped) = tail.strip_prefix("$${") {
+            out.push_str("${");
+            rest = escaped;
+            continue;
+        }
+
+        if let Some(body) = tail.strip_prefix("${") {
+            if let Some(end) = body.find('}') {
+                let name = &body[..end];
+                if is_var_name(name) {
+                    if let Some(value) = env.get(name) {
+                        out.push_str(value);
+                        rest = &body[end + 1..];
+                        continue;
+                    }
+                }
+            }
+        }
+
+        out.push('$');
+        rest = &tail[1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        let e = env(&[("HOST", "db.local"), ("PORT", "5432")]);
+        let out = expand("postgres.connect ${HOST}:${PORT}\n", &e).unwrap();
+        assert_eq!(out, "postgres.connect db.local:5432\n");
+    }
+
+    #[test]
+    fn unknown_variables_are_left_alone() {
+        let out = expand("echo ${NOPE} $5", &HashMap::new()).unwrap();
+        assert_eq!(out, "echo ${NOPE} $5");
+    }
+
+    #[test]
+    fn double_dollar_escapes() {
+        let e = env(&[("X", "1")]);
+        assert_eq!(expand("$${X} ${X}", &e).unwrap(), "${X} 1");
+    }
+
+    #[test]
+    fn require_lists_all_missing() {
+        let src = "kerai.require A B\nkerai.require C A\n${A}\n";
+        let err = expand(src, &env(&[("B", "2")])).unwrap_err();
+        assert_eq!(err, "missing required variables: A, C");
+    }
+
+    #[test]
+    fn require_lines_are_blanked() {
+        let src = "kerai.require N\n${N} 1 +\n";
+        let out = expand(src, &env(&[("N", "41")])).unwrap();
+        assert_eq!(out, "\n41 1 +\n");
+    }
+
+    #[test]
+    fn parse_env_pairs_splits_on_first_equals() {
+        let e = parse_env_pairs(&["URL=a=b".into(), "EMPTY=".into()]).unwrap();
+        assert_eq!(e["URL"], "a=b");
+        assert_eq!(e["EMPTY"], "");
+        assert!(parse_env_pairs(&["novalue".into()]).is_err());
+        assert!(parse_env_pairs(&["1X=y".into()]).is_err());
+    }
+}
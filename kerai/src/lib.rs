@@ -1,2 +1,8 @@
 pub mod lang;
+pub mod preview;
+#[cfg(feature = "native")]
 pub mod serve;
+#[cfg(feature = "native")]
+pub mod txn;
+#[cfg(feature = "wasm")]
+pub mod wasm;
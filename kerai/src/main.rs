@@ -5,6 +5,8 @@ mod db;
 mod home;
 mod lang;
 mod output;
+mod progress;
+mod secrets;
 
 use std::collections::HashMap;
 use std::env;
@@ -28,6 +30,10 @@ struct Cli {
     #[arg(long, global = true, value_enum, default_value = "table")]
     format: OutputFormat,
 
+    /// Named view (see `kerai view`) limiting find, grep, tree and search
+    #[arg(long, global = true)]
+    view: Option<String>,
+
     #[command(subcommand)]
     command: CliCommand,
 }
@@ -64,6 +70,33 @@ enum CliCommand {
         action: PeerAction,
     },
 
+    /// Branches of node history
+    Branch {
+        #[command(subcommand)]
+        action: BranchAction,
+    },
+
+    /// Revert a version and the rest of its operation, or undo recent operations
+    Revert {
+        /// Version id (kerai postgres blame --format json shows each node's)
+        #[arg(required_unless_present = "last", conflicts_with = "last")]
+        version_id: Option<String>,
+
+        /// Undo this many of the latest operations instead
+        #[arg(long)]
+        last: Option<i32>,
+
+        /// With --last, whose operations: key fingerprint or instance name (default: this instance)
+        #[arg(long, requires = "last")]
+        author: Option<String>,
+    },
+
+    /// Schema advice from observed query patterns
+    Advise {
+        #[command(subcommand)]
+        action: AdviseAction,
+    },
+
     /// Manage AI agents
     Agent {
         #[command(subcommand)]
@@ -106,6 +139,17 @@ enum CliCommand {
         action: CurrencyAction,
     },
 
+    /// Ledger activity per period: what was minted, and for which work
+    Economy {
+        /// day, week, month or year (calendar, UTC), or all
+        #[arg(long, default_value = "month")]
+        period: String,
+
+        /// Fetch signed summaries from every peer and report the federation
+        #[arg(long)]
+        federation: bool,
+    },
+
     /// Manage MicroGPT neural models
     Model {
         #[command(subcommand)]
@@ -136,11 +180,264 @@ enum CliCommand {
         action: StackAction,
     },
 
-    /// Start the web server
+    /// Run a .kerai script through the stack machine
+    Run {
+        /// Path to the script
+        file: String,
+
+        /// Template variable for ${VAR} substitution (repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+    },
+
+    /// Re-parse source files as they change (debounced)
+    Watch {
+        /// Project directory (default: the project containing the cwd)
+        path: Option<String>,
+
+        /// Milliseconds a file must be quiet before it is re-parsed
+        #[arg(long, default_value = "500")]
+        debounce: u64,
+    },
+
+    /// Module dependency graph and circular dependencies (--format dot for Graphviz)
+    Graph {
+        /// ltree path of the subtree to start from (default: everything)
+        root: Option<String>,
+
+        /// List circular dependencies only
+        #[arg(long)]
+        cycles: bool,
+    },
+
+    /// Git merge driver: structural three-way merge of %O %A %B into %A
+    MergeDriver {
+        /// Common ancestor version (%O)
+        #[arg(required_unless_present = "install")]
+        base: Option<String>,
+
+        /// Our version (%A); receives the merge result
+        #[arg(required_unless_present = "install")]
+        ours: Option<String>,
+
+        /// Their version (%B)
+        #[arg(required_unless_present = "install")]
+        theirs: Option<String>,
+
+        /// Path in the repository (%P), used to pick the merge and label conflicts
+        path: Option<String>,
+
+        /// Register the driver in git config and .gitattributes instead
+        #[arg(long, conflicts_with_all = ["base", "ours", "theirs", "path"])]
+        install: bool,
+    },
+
+    /// Git hooks that check staged files before they reach the graph
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
+
+    /// Long-running operations (imports, syncs): progress and cancellation
+    Jobs {
+        #[command(subcommand)]
+        action: JobsAction,
+    },
+
+    /// Seed the database from a bundled fixture (`demo` for all of them)
+    Seed {
+        /// Fixture to seed; omit to list the fixtures
+        fixture: Option<String>,
+    },
+
+    /// Import a git repository's history into kerai.versions, keeping
+    /// each commit's author and date
+    ImportGit {
+        /// Path to the repository
+        repo: String,
+
+        /// Commit whose history to import
+        #[arg(long, default_value = "HEAD")]
+        rev: String,
+
+        /// Import at most this many commits (a later run carries on)
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// Import an Obsidian-style markdown vault, resolving [[wikilinks]]
+    /// between its notes and attachments
+    ImportVault {
+        /// Path to the vault folder
+        dir: String,
+
+        /// Vault name, the root of its ltree paths (default: folder name)
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// Write markdown documents out as an Obsidian-style vault, with
+    /// wikilinks for their links and a backlinks section per note
+    ExportVault {
+        /// Only documents whose path matches this lquery (e.g. 'docs.*')
+        #[arg(long)]
+        path: Option<String>,
+
+        /// Directory to write the vault into
+        #[arg(long)]
+        out: String,
+
+        /// Copy attachments from this folder of the original vault
+        #[arg(long, value_name = "DIR")]
+        assets_from: Option<String>,
+    },
+
+    /// Regex search over node content, narrowed by kind and path
+    Grep {
+        /// Postgres regular expression
+        pattern: String,
+
+        /// Only nodes of this kind (e.g. fn, comment, heading)
+        #[arg(long)]
+        kind: Option<String>,
+
+        /// Only nodes whose path matches this lquery (e.g. '*.parser.*')
+        #[arg(long)]
+        path: Option<String>,
+
+        /// Ignore case
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Maximum results (default 200)
+        #[arg(long)]
+        limit: Option<i32>,
+    },
+
+    /// Find and replace text across node content, previewed per file
+    Replace {
+        /// Text to find (a Postgres regular expression with --regex)
+        pattern: String,
+
+        /// Replacement; with --regex, \1..\9 and \& refer to the match
+        replacement: String,
+
+        /// Only nodes whose path matches this lquery (default: everywhere)
+        #[arg(long, default_value = "*")]
+        path: String,
+
+        /// Treat the pattern as a regular expression
+        #[arg(long)]
+        regex: bool,
+
+        /// Show what would change without writing anything
+        #[arg(long, conflicts_with = "interactive")]
+        dry_run: bool,
+
+        /// Confirm each file before its changes are applied
+        #[arg(short, long)]
+        interactive: bool,
+    },
+
+    /// List open suggestions per file, from built-in and custom rules
+    Lint {
+        /// Only files whose name or path starts with this
+        file: Option<String>,
+
+        /// Evaluate the custom rules first
+        #[arg(long)]
+        run: bool,
+    },
+
+    /// Link nodes by hand through relations registered in kerai.relations
+    Edge {
+        #[command(subcommand)]
+        action: EdgeAction,
+    },
+
+    /// Custom lint rules evaluated by `kerai lint --run`
+    Rules {
+        #[command(subcommand)]
+        action: RulesAction,
+    },
+
+    /// Named views: the paths and node kinds a team works in
+    View {
+        #[command(subcommand)]
+        action: ViewAction,
+    },
+
+    /// Follow a subtree and get digests of its changes in an inbox
+    Subscribe {
+        /// Subtree path, or lquery when it has * | !
+        path: String,
+
+        /// User handle or id
+        #[arg(long)]
+        user: String,
+
+        /// Operation type to include (repeatable; default every type)
+        #[arg(long = "event")]
+        events: Vec<String>,
+
+        /// How often to digest: hourly, daily or weekly
+        #[arg(long, default_value = "daily")]
+        frequency: String,
+    },
+
+    /// Stop following a subtree
+    Unsubscribe {
+        /// Path as given to subscribe
+        path: String,
+
+        /// User handle or id
+        #[arg(long)]
+        user: String,
+    },
+
+    /// List subscriptions
+    Subscriptions {
+        /// Only this user's (handle or id)
+        #[arg(long)]
+        user: Option<String>,
+    },
+
+    /// Read the digests delivered for a user's subscriptions
+    Inbox {
+        /// User handle or id
+        #[arg(long)]
+        user: String,
+
+        /// Most recent digests to show
+        #[arg(long, default_value = "20")]
+        limit: i32,
+    },
+
+    /// Report docs whose linked code changed after them
+    StaleDocs {
+        /// Days code may run ahead of its docs (default: kerai.stale_doc_days)
+        #[arg(long)]
+        threshold: Option<i32>,
+
+        /// Maximum docs listed (default 100)
+        #[arg(long)]
+        limit: Option<i32>,
+
+        /// Also emit suggestion nodes on docs past the threshold
+        #[arg(long)]
+        suggest: bool,
+    },
+
+    /// Start the web server: the API, WebSocket and, with --static-dir, the
+    /// frontend, against the --db / profile / DATABASE_URL database
     Serve {
         /// Listen address (default: 0.0.0.0:62830)
         #[arg(long, default_value = "0.0.0.0:62830")]
         addr: String,
+
+        /// Serve the built frontend from this directory (default: STATIC_DIR)
+        #[arg(long)]
+        static_dir: Option<String>,
     },
 }
 
@@ -152,7 +449,7 @@ enum PostgresAction {
         connection: String,
     },
 
-    /// Import a project: create config and parse crate
+    /// Import a project: create config and parse every source file in it
     Import {
         /// Path to project root (defaults to current directory)
         path: Option<String>,
@@ -173,11 +470,36 @@ enum PostgresAction {
         sql: String,
     },
 
-    /// Export source files reconstructed from AST
+    /// Reconstruct source files from AST, printing them or writing them back
+    #[command(alias = "checkout")]
     Export {
-        /// Export a single file by name
+        /// Export a single file by name or node id
         #[arg(long)]
         file: Option<String>,
+
+        /// Write files to disk at their recorded relative paths
+        #[arg(long)]
+        write: bool,
+
+        /// Show a diff of what --write would change, writing nothing
+        #[arg(long, conflicts_with = "write")]
+        dry_run: bool,
+
+        /// Directory to write into (default: project root)
+        #[arg(long)]
+        out_dir: Option<String>,
+
+        /// Export the project as a git repository into this directory
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["file", "write", "dry_run", "out_dir"])]
+        git: Option<String>,
+
+        /// With --git, replay version history as one commit per Lamport timestamp
+        #[arg(long, requires = "git")]
+        history: bool,
+
+        /// Reconstruct the files as of this snapshot (see kerai.create_snapshot)
+        #[arg(long, conflicts_with_all = ["file", "git"])]
+        snapshot: Option<String>,
     },
 
     /// Show operation history
@@ -200,7 +522,7 @@ enum PostgresAction {
 
     /// Search AST nodes by content pattern
     Find {
-        /// Search pattern (ILIKE syntax, e.g. %hello%)
+        /// Search pattern (ILIKE syntax, e.g. %hello%; terms with --fts, a name with --fuzzy)
         pattern: String,
 
         /// Filter by node kind (e.g. fn, struct, enum)
@@ -210,6 +532,18 @@ enum PostgresAction {
         /// Maximum results (default 50)
         #[arg(long)]
         limit: Option<i32>,
+
+        /// Ranked full-text search ("exact phrase", or, -exclude)
+        #[arg(long)]
+        fts: bool,
+
+        /// Fuzzy symbol-name match that tolerates typos
+        #[arg(long, conflicts_with = "fts")]
+        fuzzy: bool,
+
+        /// Most edits a fuzzy match may be away (default 3)
+        #[arg(long, requires = "fuzzy")]
+        max_distance: Option<i32>,
     },
 
     /// Find definitions, references, and impls for a symbol
@@ -224,6 +558,21 @@ enum PostgresAction {
         path: Option<String>,
     },
 
+    /// Show who last changed each line of a parsed file
+    Blame {
+        /// File node id or file name
+        path: String,
+    },
+
+    /// Structural diff of two parsed files
+    Diff {
+        /// Before: file node id or file name
+        file_a: String,
+
+        /// After: file node id or file name
+        file_b: String,
+    },
+
     /// Import CSV files into typed Postgres tables with kerai nodes
     ImportCsv {
         /// Path to CSV file or directory
@@ -245,6 +594,10 @@ enum SyncAction {
     Run {
         /// Peer name to sync with
         peer: String,
+
+        /// Sync even outside the peer's sync windows and past its rate caps
+        #[arg(long)]
+        force: bool,
     },
 }
 
@@ -312,11 +665,59 @@ enum PeerAction {
         name: String,
     },
 
-    /// Show peer details
+    /// Show peer details, with its sync policy and throttle state
     Info {
         /// Peer name
         name: String,
     },
+
+    /// Set when and how much a peer is synced, replacing its sync policy
+    Policy {
+        /// Peer name
+        name: String,
+
+        /// Local times syncing is allowed, as HH:MM-HH:MM (repeatable; default any time)
+        #[arg(long = "window")]
+        windows: Vec<String>,
+
+        /// Most ops sent either way per minute
+        #[arg(long)]
+        max_ops_per_minute: Option<i32>,
+
+        /// Most bytes of ops sent either way per hour
+        #[arg(long)]
+        max_bytes_per_hour: Option<i64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum BranchAction {
+    /// Create a branch from the current one
+    Create {
+        /// Branch name
+        name: String,
+    },
+
+    /// List branches (current marked with *)
+    List,
+
+    /// Check out a branch into the node graph
+    Switch {
+        /// Branch name
+        name: String,
+    },
+
+    /// Merge a branch into the current one
+    Merge {
+        /// Branch to merge
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum AdviseAction {
+    /// Suggest indexes for kerai tables from pg_stat_statements
+    Indexes,
 }
 
 #[derive(Subcommand)]
@@ -836,6 +1237,27 @@ enum CurrencyAction {
         #[arg(long)]
         enabled: Option<bool>,
     },
+
+    /// Mint the scheduled reward for a piece of work
+    Reward {
+        /// Work type from the reward schedule
+        #[arg(long)]
+        work_type: String,
+
+        /// ID of what the work was done on (node, changeset, job)
+        #[arg(long)]
+        reference_id: Option<String>,
+
+        /// What the reference ID names, e.g. node or changeset
+        #[arg(long)]
+        reference_type: Option<String>,
+    },
+
+    /// Show what the ledger records against a reference ID
+    Ledger {
+        /// Node, changeset, parse run or other ID rewards refer to
+        reference_id: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -925,14 +1347,188 @@ enum StackAction {
     Clear,
 }
 
+#[derive(Subcommand)]
+enum HookAction {
+    /// Check staged files: parse, round-trip, lint severity and secrets
+    PreCommit {
+        /// Lowest lint severity that blocks the commit (info, warning or none)
+        #[arg(long, default_value = "warning", value_parser = ["info", "warning", "none"])]
+        fail_on: String,
+    },
+
+    /// Install `kerai hook pre-commit` as the repository's pre-commit hook
+    Install,
+}
+
+#[derive(Subcommand)]
+enum JobsAction {
+    /// List recent jobs, running ones first
+    List {
+        /// Maximum number of jobs
+        #[arg(long, default_value = "20")]
+        limit: i32,
+    },
+
+    /// Cancel a running or queued job
+    Cancel {
+        /// Job id, or a unique prefix of one
+        id: String,
+    },
+
+    /// Throughput and failure rates per job kind
+    Summary {
+        /// Window to summarise, in hours
+        #[arg(long, default_value = "24")]
+        hours: i32,
+    },
+
+    /// Fail jobs whose worker has died, queueing them again while attempts remain
+    Reap {
+        /// Seconds without a heartbeat before an idle worker counts as dead
+        #[arg(long, default_value = "300")]
+        stale_secs: i32,
+
+        /// Fail dead jobs without queueing them again
+        #[arg(long)]
+        no_requeue: bool,
+
+        /// Delay before the first retry, doubled for each further attempt
+        #[arg(long, default_value = "30")]
+        backoff_secs: i32,
+    },
+}
+
+#[derive(Subcommand)]
+enum EdgeAction {
+    /// Add an edge, or every edge in a JSON file
+    Add {
+        /// Source node ID
+        #[arg(required_unless_present = "file")]
+        source: Option<String>,
+
+        /// Target node ID
+        #[arg(required_unless_present = "file")]
+        target: Option<String>,
+
+        /// Relation (see `kerai edge relations`)
+        #[arg(required_unless_present = "file")]
+        relation: Option<String>,
+
+        /// Edge metadata as a JSON object
+        #[arg(long, conflicts_with = "file")]
+        metadata: Option<String>,
+
+        /// JSON array of {source_id, target_id, relation, metadata?}; all
+        /// are checked before any is added
+        #[arg(long, conflicts_with_all = ["source", "target", "relation"])]
+        file: Option<String>,
+    },
+
+    /// Remove an edge, or every edge in a JSON file
+    Remove {
+        /// Source node ID
+        #[arg(required_unless_present = "file")]
+        source: Option<String>,
+
+        /// Target node ID
+        #[arg(required_unless_present = "file")]
+        target: Option<String>,
+
+        /// Relation
+        #[arg(required_unless_present = "file")]
+        relation: Option<String>,
+
+        /// JSON array of {source_id, target_id, relation}
+        #[arg(long, conflicts_with_all = ["source", "target", "relation"])]
+        file: Option<String>,
+    },
+
+    /// List registered relations and the node kinds each allows
+    Relations,
+}
+
+#[derive(Subcommand)]
+enum RulesAction {
+    /// List the rules
+    List,
+
+    /// Define a rule, or redefine one with the same name
+    Add {
+        /// Rule id shown on its suggestions (letters, digits, '_', '-')
+        name: String,
+
+        /// Suggestion text; {kind}, {name} and {path} name the matched node
+        #[arg(long)]
+        message: String,
+
+        /// SQL condition on the node, aliased `n` (e.g. "n.kind = 'fn'")
+        #[arg(long)]
+        predicate: Option<String>,
+
+        /// lquery pattern the node's path must match (e.g. "*.tests.*")
+        #[arg(long)]
+        pattern: Option<String>,
+
+        /// info or warning
+        #[arg(long, default_value = "warning")]
+        severity: String,
+    },
+
+    /// Delete a rule and its suggestions
+    Remove {
+        /// Rule name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ViewAction {
+    /// List the views
+    List,
+
+    /// Define a view, or redefine one with the same name
+    Create {
+        /// View name, passed to --view
+        name: String,
+
+        /// Subtree path the view covers (repeatable; default every path)
+        #[arg(long = "path")]
+        paths: Vec<String>,
+
+        /// Node kind the view shows (repeatable; default every kind)
+        #[arg(long = "kind")]
+        kinds: Vec<String>,
+
+        /// Branch the view is meant to be read on
+        #[arg(long)]
+        branch: Option<String>,
+
+        /// Version vector the view is pinned to, as JSON
+        #[arg(long)]
+        frontier: Option<String>,
+
+        /// What the view is for
+        #[arg(long)]
+        description: Option<String>,
+    },
+
+    /// Delete a view
+    Drop {
+        /// View name
+        name: String,
+    },
+}
+
 /// Known global flags that take a value argument.
-const FLAGS_WITH_VALUE: &[&str] = &["--db", "--profile", "--format"];
+const FLAGS_WITH_VALUE: &[&str] = &["--db", "--profile", "--format", "--view"];
 
 /// Known subcommand names — if the first positional matches one, skip eval mode.
 const SUBCOMMANDS: &[&str] = &[
-    "postgres", "sync", "perspective", "consensus", "peer",
+    "postgres", "sync", "perspective", "consensus", "peer", "branch", "revert", "advise",
     "agent", "task", "swarm", "market", "wallet", "bounty",
-    "currency", "model", "config", "alias", "init", "stack", "serve",
+    "currency", "economy", "model", "config", "alias", "init", "stack", "run", "serve",
+    "watch", "stale-docs", "merge-driver", "graph", "hook", "jobs", "seed", "import-git", "grep", "lint", "edge", "rules",
+    "view", "import-vault", "export-vault", "subscribe", "unsubscribe", "subscriptions", "inbox", "replace",
 ];
 
 /// Notation switch tokens mapped to notation modes.
@@ -1200,15 +1796,16 @@ fn main() {
     let cli = Cli::parse_from(args);
 
     // Handle serve subcommand separately — it creates its own tokio runtime
-    if let CliCommand::Serve { addr } = &cli.command {
+    if let CliCommand::Serve { addr, static_dir } = &cli.command {
         let profile = config::load_config(&cli.profile);
-        let db_url = cli.db.as_deref()
-            .or(profile.connection.as_deref())
-            .unwrap_or("host=/tmp dbname=kerai")
-            .to_string();
+        let (db_url, source) = kerai_cli::serve::config::discover_database(
+            cli.db.as_deref(),
+            profile.connection.as_deref(),
+        );
+        eprintln!("Using database {db_url} (from {source})");
         tokio::runtime::Runtime::new()
             .expect("Failed to create tokio runtime")
-            .block_on(kerai_cli::serve::run(addr, &db_url));
+            .block_on(kerai_cli::serve::run(addr, &db_url, static_dir.clone()));
         return;
     }
 
@@ -1220,20 +1817,52 @@ fn main() {
             PostgresAction::Info => commands::Command::Info,
             PostgresAction::Version => commands::Command::Version,
             PostgresAction::Query { sql } => commands::Command::Query { sql },
-            PostgresAction::Export { file } => commands::Command::Export { file },
+            PostgresAction::Export {
+                git: Some(dir),
+                history,
+                ..
+            } => commands::Command::ExportGit { dir, history },
+            PostgresAction::Export {
+                file,
+                write,
+                dry_run,
+                out_dir,
+                snapshot,
+                ..
+            } => commands::Command::Export {
+                file,
+                mode: if dry_run {
+                    commands::export::Mode::DryRun
+                } else if write {
+                    commands::export::Mode::Write
+                } else {
+                    commands::export::Mode::Print
+                },
+                out_dir,
+                snapshot,
+            },
             PostgresAction::Log { author, limit } => commands::Command::Log { author, limit },
             PostgresAction::Commit { message } => commands::Command::Commit { message },
             PostgresAction::Find {
                 pattern,
                 kind,
                 limit,
+                fts,
+                fuzzy,
+                max_distance,
             } => commands::Command::Find {
                 pattern,
                 kind,
                 limit,
+                fts,
+                fuzzy: fuzzy.then(|| max_distance.unwrap_or(3)),
             },
             PostgresAction::Refs { symbol } => commands::Command::Refs { symbol },
             PostgresAction::Tree { path } => commands::Command::Tree { path },
+            PostgresAction::Blame { path } => commands::Command::Blame { path },
+            PostgresAction::Diff { file_a, file_b } => {
+                commands::Command::Diff { file_a, file_b }
+            }
             PostgresAction::ImportCsv {
                 path,
                 schema,
@@ -1245,7 +1874,7 @@ fn main() {
             },
         },
         CliCommand::Sync { action } => match action {
-            SyncAction::Run { peer } => commands::Command::Sync { peer },
+            SyncAction::Run { peer, force } => commands::Command::Sync { peer, force },
         },
         CliCommand::Perspective { action } => match action {
             PerspectiveAction::List {
@@ -1284,6 +1913,35 @@ fn main() {
             PeerAction::List => commands::Command::PeerList,
             PeerAction::Remove { name } => commands::Command::PeerRemove { name },
             PeerAction::Info { name } => commands::Command::PeerInfo { name },
+            PeerAction::Policy {
+                name,
+                windows,
+                max_ops_per_minute,
+                max_bytes_per_hour,
+            } => commands::Command::PeerPolicy {
+                name,
+                windows,
+                max_ops_per_minute,
+                max_bytes_per_hour,
+            },
+        },
+        CliCommand::Branch { action } => match action {
+            BranchAction::Create { name } => commands::Command::BranchCreate { name },
+            BranchAction::List => commands::Command::BranchList,
+            BranchAction::Switch { name } => commands::Command::BranchSwitch { name },
+            BranchAction::Merge { name } => commands::Command::BranchMerge { name },
+        },
+        CliCommand::Revert {
+            version_id,
+            last,
+            author,
+        } => commands::Command::Revert {
+            version_id,
+            last,
+            author,
+        },
+        CliCommand::Advise { action } => match action {
+            AdviseAction::Indexes => commands::Command::AdviseIndexes,
         },
         CliCommand::Agent { action } => match action {
             AgentAction::Add { name, kind, model } => commands::Command::AgentAdd {
@@ -1515,6 +2173,9 @@ fn main() {
             StackAction::Drop => commands::Command::StackDrop,
             StackAction::Clear => commands::Command::StackClear,
         },
+        CliCommand::Economy { period, federation } => {
+            commands::Command::Economy { period, federation }
+        }
         CliCommand::Currency { action } => match action {
             CurrencyAction::Register {
                 pubkey,
@@ -1554,11 +2215,206 @@ fn main() {
                 reward,
                 enabled,
             },
+            CurrencyAction::Reward {
+                work_type,
+                reference_id,
+                reference_type,
+            } => commands::Command::CurrencyReward {
+                work_type,
+                reference_id,
+                reference_type,
+            },
+            CurrencyAction::Ledger { reference_id } => {
+                commands::Command::CurrencyLedger { reference_id }
+            }
+        },
+        CliCommand::Run { file, env } => commands::Command::Run { file, env },
+        CliCommand::Watch { path, debounce } => commands::Command::Watch {
+            path,
+            debounce_ms: debounce,
+        },
+        CliCommand::MergeDriver {
+            base,
+            ours,
+            theirs,
+            path,
+            install,
+        } => match (install, base, ours, theirs) {
+            (false, Some(base), Some(ours), Some(theirs)) => commands::Command::MergeDriver {
+                base,
+                ours,
+                theirs,
+                path,
+            },
+            _ => commands::Command::MergeDriverInstall,
+        },
+        CliCommand::Graph { root, cycles } => commands::Command::Graph { root, cycles },
+        CliCommand::Hook { action } => match action {
+            HookAction::PreCommit { fail_on } => commands::Command::HookPreCommit { fail_on },
+            HookAction::Install => commands::Command::HookInstall,
+        },
+        CliCommand::Jobs { action } => match action {
+            JobsAction::List { limit } => commands::Command::JobList { limit },
+            JobsAction::Cancel { id } => commands::Command::JobCancel { id },
+            JobsAction::Summary { hours } => commands::Command::JobSummary { hours },
+            JobsAction::Reap {
+                stale_secs,
+                no_requeue,
+                backoff_secs,
+            } => commands::Command::JobReap {
+                stale_secs,
+                requeue: !no_requeue,
+                backoff_secs,
+            },
+        },
+        CliCommand::Seed { fixture } => commands::Command::Seed { fixture },
+        CliCommand::ImportGit { repo, rev, limit } => {
+            commands::Command::ImportGit { repo, rev, limit }
+        }
+        CliCommand::ImportVault { dir, name } => commands::Command::ImportVault { dir, name },
+        CliCommand::ExportVault {
+            path,
+            out,
+            assets_from,
+        } => commands::Command::ExportVault {
+            path,
+            out,
+            assets_from,
+        },
+        CliCommand::Grep {
+            pattern,
+            kind,
+            path,
+            ignore_case,
+            limit,
+        } => commands::Command::Grep {
+            // Embedded ARE option; only valid at the very start
+            pattern: if ignore_case {
+                format!("(?i){pattern}")
+            } else {
+                pattern
+            },
+            kind,
+            path,
+            limit,
+        },
+        CliCommand::Replace {
+            pattern,
+            replacement,
+            path,
+            regex,
+            dry_run,
+            interactive,
+        } => commands::Command::Replace {
+            pattern,
+            replacement,
+            path,
+            regex,
+            mode: if dry_run {
+                commands::replace::Mode::Preview
+            } else if interactive {
+                commands::replace::Mode::Confirm
+            } else {
+                commands::replace::Mode::Apply
+            },
+        },
+        CliCommand::Lint { file, run } => commands::Command::Lint { file, run },
+        CliCommand::Edge { action } => match action {
+            EdgeAction::Add {
+                source,
+                target,
+                relation,
+                metadata,
+                file,
+            } => commands::Command::EdgeAdd {
+                source,
+                target,
+                relation,
+                metadata,
+                file,
+            },
+            EdgeAction::Remove {
+                source,
+                target,
+                relation,
+                file,
+            } => commands::Command::EdgeRemove {
+                source,
+                target,
+                relation,
+                file,
+            },
+            EdgeAction::Relations => commands::Command::EdgeRelations,
+        },
+        CliCommand::Rules { action } => match action {
+            RulesAction::List => commands::Command::RuleList,
+            RulesAction::Add {
+                name,
+                message,
+                predicate,
+                pattern,
+                severity,
+            } => commands::Command::RuleAdd {
+                name,
+                message,
+                predicate,
+                pattern,
+                severity,
+            },
+            RulesAction::Remove { name } => commands::Command::RuleRemove { name },
+        },
+        CliCommand::View { action } => match action {
+            ViewAction::List => commands::Command::ViewList,
+            ViewAction::Create {
+                name,
+                paths,
+                kinds,
+                branch,
+                frontier,
+                description,
+            } => commands::Command::ViewCreate {
+                name,
+                paths,
+                kinds,
+                branch,
+                frontier,
+                description,
+            },
+            ViewAction::Drop { name } => commands::Command::ViewDrop { name },
+        },
+        CliCommand::Subscribe {
+            path,
+            user,
+            events,
+            frequency,
+        } => commands::Command::Subscribe {
+            user,
+            path,
+            events,
+            frequency,
+        },
+        CliCommand::Unsubscribe { path, user } => commands::Command::Unsubscribe { user, path },
+        CliCommand::Subscriptions { user } => commands::Command::Subscriptions { user },
+        CliCommand::Inbox { user, limit } => commands::Command::Inbox { user, limit },
+        CliCommand::StaleDocs {
+            threshold,
+            limit,
+            suggest,
+        } => commands::Command::StaleDocs {
+            threshold,
+            limit,
+            suggest,
         },
         CliCommand::Serve { .. } => unreachable!("handled above"),
     };
 
-    if let Err(e) = commands::run(command, &cli.profile, cli.db.as_deref(), &cli.format) {
+    if let Err(e) = commands::run(
+        command,
+        &cli.profile,
+        cli.db.as_deref(),
+        cli.view.as_deref(),
+        &cli.format,
+    ) {
         eprintln!("Error: {e}");
         std::process::exit(1);
     }
@@ -1609,6 +2465,42 @@ mod tests {
         assert_eq!(result, vec!["kerai", "--db", "mydb", "--format", "json", "postgres", "ping"]);
     }
 
+    #[test]
+    fn every_subcommand_skips_eval() {
+        use clap::CommandFactory;
+        for sub in Cli::command().get_subcommands() {
+            assert!(
+                SUBCOMMANDS.contains(&sub.get_name()),
+                "'{}' missing from SUBCOMMANDS, so it is evaluated as an expression",
+                sub.get_name()
+            );
+        }
+    }
+
+    #[test]
+    fn merge_driver_args_parse() {
+        let cli = Cli::try_parse_from(args("kerai merge-driver %O %A %B src/lib.rs")).unwrap();
+        assert!(matches!(
+            cli.command,
+            CliCommand::MergeDriver { ref ours, ref path, install: false, .. }
+                if ours.as_deref() == Some("%A") && path.as_deref() == Some("src/lib.rs")
+        ));
+        assert!(Cli::try_parse_from(args("kerai merge-driver --install")).is_ok());
+        assert!(Cli::try_parse_from(args("kerai merge-driver %O")).is_err());
+        assert!(Cli::try_parse_from(args("kerai merge-driver --install %O %A %B")).is_err());
+    }
+
+    #[test]
+    fn hook_pre_commit_args_parse() {
+        let cli = Cli::try_parse_from(args("kerai hook pre-commit")).unwrap();
+        assert!(matches!(
+            cli.command,
+            CliCommand::Hook { action: HookAction::PreCommit { ref fail_on } } if fail_on == "warning"
+        ));
+        assert!(Cli::try_parse_from(args("kerai hook pre-commit --fail-on none")).is_ok());
+        assert!(Cli::try_parse_from(args("kerai hook pre-commit --fail-on fatal")).is_err());
+    }
+
     #[test]
     fn no_dot_no_alias_passthrough() {
         let aliases = HashMap::new();
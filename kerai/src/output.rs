@@ -8,6 +8,8 @@ pub enum OutputFormat {
     Table,
     Json,
     Csv,
+    /// Graphviz; commands without a graph print a table instead
+    Dot,
 }
 
 /// Print a JSON value in the requested format.
@@ -16,7 +18,7 @@ pub fn print_json(value: &serde_json::Value, format: &OutputFormat) {
         OutputFormat::Json => {
             println!("{}", serde_json::to_string_pretty(value).unwrap());
         }
-        OutputFormat::Table | OutputFormat::Csv => {
+        OutputFormat::Table | OutputFormat::Csv | OutputFormat::Dot => {
             // For non-JSON formats, just pretty-print the JSON
             println!("{}", serde_json::to_string_pretty(value).unwrap());
         }
@@ -31,7 +33,7 @@ pub fn print_rows(columns: &[String], rows: &[Vec<String>], format: &OutputForma
     let camel_columns: Vec<String> = columns.iter().map(|c| case::to_camel(c)).collect();
 
     match format {
-        OutputFormat::Table => {
+        OutputFormat::Table | OutputFormat::Dot => {
             let mut table = Table::new();
             table.load_preset(UTF8_FULL_CONDENSED);
             table.set_header(&camel_columns);
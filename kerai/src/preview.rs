@@ -0,0 +1,176 @@
+//! Database-free entry points into `lang` for previewing input before it
+//! reaches the server: tokens, parse trees, bracket checks, and runs of the
+//! pure words (`handlers::register_pure`), all as JSON. The web editor calls
+//! these through the wasm build (`crate::wasm`); words that need the
+//! database come back as `unknown word` errors on the preview stack.
+use serde_json::{json, Value};
+
+use crate::lang::ast::Line;
+use crate::lang::expr::Expr;
+use crate::lang::handlers;
+use crate::lang::machine::{Machine, Role};
+use crate::lang::ptr::Ptr;
+use crate::lang::token::{tokenize, TokenKind};
+
+fn kind_name(kind: TokenKind) -> &'static str {
+    match kind {
+        TokenKind::Word => "word",
+        TokenKind::LParen => "lparen",
+        TokenKind::RParen => "rparen",
+        TokenKind::LBracket => "lbracket",
+        TokenKind::RBracket => "rbracket",
+    }
+}
+
+/// Tokens of each source line: `[[{value, quoted, kind}]]`.
+pub fn tokens(source: &str) -> Value {
+    let lines: Vec<Value> = source
+        .lines()
+        .map(|line| {
+            let toks: Vec<Value> = tokenize(line)
+                .iter()
+                .map(|t| json!({"value": t.value, "quoted": t.quoted, "kind": kind_name(t.kind)}))
+                .collect();
+            Value::Array(toks)
+        })
+        .collect();
+    Value::Array(lines)
+}
+
+/// An expression as JSON: atoms are strings, lists `{"list": [...]}` and
+/// applications `{"apply": function, "args": [...]}`.
+fn expr_json(expr: &Expr) -> Value {
+    match expr {
+        Expr::Atom(s) => json!(s),
+        Expr::List(items) => json!({"list": items.iter().map(expr_json).collect::<Vec<_>>()}),
+        Expr::Apply { function, args } => json!({
+            "apply": function,
+            "args": args.iter().map(expr_json).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+/// Parse tree of a document: `{default_notation, lines: [...]}`, each line
+/// tagged with its `type`.
+pub fn parse(source: &str) -> Value {
+    let doc = crate::lang::parse(source);
+    let lines: Vec<Value> = doc
+        .lines
+        .iter()
+        .map(|line| match line {
+            Line::Empty => json!({"type": "empty"}),
+            Line::Comment { text } => json!({"type": "comment", "text": text}),
+            Line::Definition { name, target, notation } => json!({
+                "type": "definition", "name": name, "target": target,
+                "notation": notation.to_string(),
+            }),
+            Line::Call { function, args, notation } => json!({
+                "type": "call", "function": function,
+                "args": args.iter().map(expr_json).collect::<Vec<_>>(),
+                "notation": notation.to_string(),
+            }),
+            Line::Directive { name, args } => json!({"type": "directive", "name": name, "args": args}),
+        })
+        .collect();
+    json!({"default_notation": doc.default_notation.to_string(), "lines": lines})
+}
+
+/// Unbalanced brackets and parentheses, per line (1-based):
+/// `{ok, errors: [{line, message}]}`.
+pub fn check(source: &str) -> Value {
+    let mut errors = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        let mut open: Vec<TokenKind> = Vec::new();
+        for token in tokenize(line) {
+            match token.kind {
+                TokenKind::LParen | TokenKind::LBracket => open.push(token.kind),
+                TokenKind::RParen | TokenKind::RBracket => {
+                    let expected = if token.kind == TokenKind::RParen {
+                        TokenKind::LParen
+                    } else {
+                        TokenKind::LBracket
+                    };
+                    if open.pop() != Some(expected) {
+                        errors.push(json!({"line": n + 1, "message": format!("unexpected '{}'", token.value)}));
+                        // One error per line; what is still open no longer matters
+                        open.clear();
+                        break;
+                    }
+                }
+                TokenKind::Word => {}
+            }
+        }
+        if let Some(kind) = open.last() {
+            let close = if *kind == TokenKind::LParen { ')' } else { ']' };
+            errors.push(json!({"line": n + 1, "message": format!("missing '{close}'")}));
+        }
+    }
+    json!({"ok": errors.is_empty(), "errors": errors})
+}
+
+/// Run `input` through a machine that knows only the pure words, starting
+/// from `stack` (a previous result's `stack`, or empty).
+///
+/// Returns `{stack: [Ptr], display: [text]}`, `display` holding each item
+/// as the terminal renders it.
+pub fn execute(input: &str, stack: Vec<Ptr>) -> Value {
+    let (handler_map, type_methods, help, roles) = handlers::register_pure();
+    let mut machine = Machine::new(
+        uuid::Uuid::nil(),
+        uuid::Uuid::nil(),
+        Role::Anonymous,
+        handler_map,
+        type_methods,
+        help,
+        roles,
+    );
+    machine.stack = stack;
+    if let Err(e) = machine.execute(input) {
+        machine.push(Ptr::error(&e));
+    }
+    let display: Vec<String> = machine.stack.iter().map(|p| p.to_string()).collect();
+    json!({"stack": machine.stack, "display": display})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pure_words_run_and_db_words_are_unknown() {
+        let result = execute("1 2 + dup *", Vec::new());
+        assert_eq!(result["stack"][0]["kind"], "int");
+        assert_eq!(result["stack"][0]["ref_id"], "9");
+
+        let result = execute("now", Vec::new());
+        assert_eq!(result["stack"][0]["kind"], "error");
+    }
+
+    #[test]
+    fn execution_continues_from_a_previous_stack() {
+        let first = execute("4", Vec::new());
+        let stack: Vec<Ptr> = serde_json::from_value(first["stack"].clone()).unwrap();
+        let second = execute("5 *", stack);
+        assert_eq!(second["stack"][0]["ref_id"], "20");
+    }
+
+    #[test]
+    fn brackets_are_checked_per_line() {
+        assert_eq!(check("[1 2 3] sum\n(a b)")["ok"], true);
+        let result = check("ok\n[1 2 (3]\nx )");
+        assert_eq!(result["errors"][0]["line"], 2);
+        assert_eq!(result["errors"][0]["message"], "unexpected ']'");
+        assert_eq!(result["errors"][1]["line"], 3);
+    }
+
+    #[test]
+    fn parse_tags_each_line() {
+        let tree = parse("# note\nkerai.prefix\nadd (mul 2 3) [4 5]\n");
+        assert_eq!(tree["lines"][0]["type"], "comment");
+        assert_eq!(tree["lines"][1]["type"], "directive");
+        let call = &tree["lines"][2];
+        assert_eq!(call["function"], "add");
+        assert_eq!(call["args"][0]["apply"], "mul");
+        assert_eq!(call["args"][1]["list"], json!(["4", "5"]));
+    }
+}
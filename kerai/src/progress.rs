@@ -0,0 +1,284 @@
+//! Progress and cancellation for long operations.
+//!
+//! A command doing something long starts a [`Job`], which records it in
+//! `kerai.jobs` so `kerai jobs` in another terminal can follow or cancel
+//! it, and draws a progress line on stderr. Steps the command takes itself
+//! go through [`Job::step`]; inside a single long statement the server
+//! sends `kerai:progress` notices, which [`on_notice`] feeds to the same
+//! line. Ctrl-C cancels whatever statement is running on the connections
+//! passed to [`cancel_on_interrupt`]; a second Ctrl-C exits at once.
+
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Once};
+
+use postgres::error::SqlState;
+use postgres::{CancelToken, Client, NoTls};
+use serde_json::{json, Value};
+
+/// Prefix of the notices the server sends while a long statement runs.
+const NOTICE_PREFIX: &str = "kerai:progress ";
+
+/// Width of the bar, in cells.
+const BAR_WIDTH: usize = 24;
+
+/// Longest step description shown after the bar.
+const CURRENT_WIDTH: usize = 40;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Progress {
+    pub label: String,
+    pub done: u64,
+    pub total: Option<u64>,
+    pub current: String,
+}
+
+static PROGRESS: Mutex<Option<Progress>> = Mutex::new(None);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static STATEMENT_CANCELLED: AtomicBool = AtomicBool::new(false);
+static CANCEL_TOKENS: Mutex<Vec<CancelToken>> = Mutex::new(Vec::new());
+static HANDLER: Once = Once::new();
+
+/// One progress line: `label [#####-----] 12/40 current`, or without the
+/// bar when the total is unknown.
+pub fn render(p: &Progress) -> String {
+    let mut line = p.label.clone();
+    match p.total {
+        Some(total) if total > 0 => {
+            let filled = (p.done.min(total) as usize * BAR_WIDTH) / total as usize;
+            line.push_str(&format!(
+                " [{}{}] {}/{}",
+                "#".repeat(filled),
+                "-".repeat(BAR_WIDTH - filled),
+                p.done,
+                total
+            ));
+        }
+        _ => line.push_str(&format!(" {}", p.done)),
+    }
+    if !p.current.is_empty() {
+        let current: String = p.current.chars().take(CURRENT_WIDTH).collect();
+        line.push_str(&format!(" {current}"));
+    }
+    line
+}
+
+fn draw(p: &Progress) {
+    let mut err = std::io::stderr();
+    if err.is_terminal() {
+        let _ = write!(err, "\r\x1b[2K{}", render(p));
+        let _ = err.flush();
+    }
+}
+
+fn clear_line() {
+    let mut err = std::io::stderr();
+    if err.is_terminal() {
+        let _ = write!(err, "\r\x1b[2K");
+        let _ = err.flush();
+    }
+}
+
+/// Start a progress line.
+pub fn start(label: &str) {
+    let p = Progress {
+        label: label.to_string(),
+        ..Default::default()
+    };
+    draw(&p);
+    *PROGRESS.lock().unwrap() = Some(p);
+}
+
+/// Move the current line on to `done` of `total`.
+pub fn set(done: u64, total: Option<u64>, current: &str) {
+    let mut guard = PROGRESS.lock().unwrap();
+    let p = guard.get_or_insert_with(Progress::default);
+    p.done = done;
+    p.total = total.or(p.total);
+    p.current = current.to_string();
+    draw(p);
+}
+
+/// Clear the line, returning where it got to.
+pub fn finish() -> Progress {
+    clear_line();
+    PROGRESS.lock().unwrap().take().unwrap_or_default()
+}
+
+/// Notice callback for connections: shows server-side progress.
+pub fn on_notice(message: &str) {
+    let Some(body) = message.strip_prefix(NOTICE_PREFIX) else {
+        return;
+    };
+    if let Ok(v) = serde_json::from_str::<Value>(body) {
+        set(
+            v["done"].as_u64().unwrap_or(0),
+            v["total"].as_u64(),
+            v["current"].as_str().unwrap_or(""),
+        );
+    }
+}
+
+/// Cancel the statement running on `client` when Ctrl-C is pressed.
+pub fn cancel_on_interrupt(client: &Client) {
+    HANDLER.call_once(|| {
+        std::thread::spawn(|| {
+            let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            else {
+                return;
+            };
+            while runtime.block_on(tokio::signal::ctrl_c()).is_ok() {
+                clear_line();
+                if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                    std::process::exit(130);
+                }
+                eprintln!("Cancelling... (Ctrl-C again to quit now)");
+                for token in CANCEL_TOKENS.lock().unwrap().iter() {
+                    let _ = token.cancel_query(NoTls);
+                }
+            }
+        });
+    });
+    CANCEL_TOKENS.lock().unwrap().push(client.cancel_token());
+}
+
+/// Whether Ctrl-C has been pressed.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Error text for a failed `what` statement. A statement cancelled on
+/// request is remembered, since `kerai jobs cancel` signals the backend
+/// before its flag on the job commits.
+pub fn query_error(what: &str, e: &postgres::Error) -> String {
+    if let Some(db) = e.as_db_error() {
+        if *db.code() == SqlState::QUERY_CANCELED && db.message().contains("user request") {
+            STATEMENT_CANCELLED.store(true, Ordering::SeqCst);
+        }
+    }
+    format!("{what} failed: {e}")
+}
+
+/// A long operation recorded in `kerai.jobs`. Servers without the jobs
+/// table still run the operation, just without a record.
+pub struct Job {
+    id: Option<String>,
+}
+
+impl Job {
+    pub fn start(client: &mut Client, kind: &str, label: &str, total: Option<i32>) -> Job {
+        let id = client
+            .query_one("SELECT kerai.job_start($1, $2)->>'id'", &[&kind, &total])
+            .ok()
+            .map(|row| row.get(0));
+        start(label);
+        if let Some(total) = total {
+            set(0, Some(total as u64), "");
+        }
+        Job { id }
+    }
+
+    /// Record that `done` steps are finished and `current` is under way.
+    /// Fails once the job has been cancelled, here or from elsewhere.
+    pub fn step(&self, client: &mut Client, done: i32, current: &str) -> Result<(), String> {
+        set(done as u64, None, current);
+        if interrupted() {
+            return Err("Cancelled".into());
+        }
+        let Some(id) = &self.id else {
+            return Ok(());
+        };
+        let row = client
+            .query_one(
+                "SELECT (kerai.job_progress($1::text::uuid, $2, $3)->>'cancel_requested')::bool",
+                &[id, &done, &current],
+            )
+            .map_err(|e| format!("job_progress failed: {e}"))?;
+        if row.get::<_, bool>(0) {
+            return Err("Cancelled".into());
+        }
+        Ok(())
+    }
+
+    /// Whether the job was cancelled, by Ctrl-C or through `kerai jobs
+    /// cancel`. Worth asking when a statement fails.
+    pub fn cancelled(&self, client: &mut Client) -> bool {
+        if interrupted() || STATEMENT_CANCELLED.load(Ordering::SeqCst) {
+            return true;
+        }
+        let Some(id) = &self.id else {
+            return false;
+        };
+        client
+            .query_one(
+                "SELECT cancel_requested FROM kerai.jobs WHERE id = $1::text::uuid",
+                &[id],
+            )
+            .map(|row| row.get(0))
+            .unwrap_or(false)
+    }
+
+    /// Clear the progress line and record the outcome: `done`, `cancelled`
+    /// (with whatever partial result there is) or `failed`.
+    pub fn finish(self, client: &mut Client, status: &str, result: &Value) -> Progress {
+        let progress = finish();
+        if let Some(id) = &self.id {
+            let result = result.to_string();
+            let _ = client.execute(
+                "SELECT kerai.job_finish($1::text::uuid, $2, $3::text::jsonb)",
+                &[id, &status, &result],
+            );
+        }
+        progress
+    }
+
+    /// Finish as failed or cancelled after `error`, whichever it was.
+    pub fn fail(self, client: &mut Client, error: &str, partial: Value) -> (bool, Progress) {
+        let cancelled = self.cancelled(client);
+        let (status, result) = if cancelled {
+            ("cancelled", partial)
+        } else {
+            ("failed", json!({ "error": error }))
+        };
+        (cancelled, self.finish(client, status, &result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_bar_when_total_known() {
+        let p = Progress {
+            label: "Parsing".into(),
+            done: 6,
+            total: Some(24),
+            current: "src/lib.rs".into(),
+        };
+        assert_eq!(
+            render(&p),
+            "Parsing [######------------------] 6/24 src/lib.rs"
+        );
+
+        let open = Progress {
+            total: None,
+            current: String::new(),
+            ..p
+        };
+        assert_eq!(render(&open), "Parsing 6");
+    }
+
+    #[test]
+    fn done_past_total_fills_bar() {
+        let p = Progress {
+            label: "x".into(),
+            done: 9,
+            total: Some(3),
+            current: String::new(),
+        };
+        assert!(render(&p).starts_with(&format!("x [{}] 9/3", "#".repeat(BAR_WIDTH))));
+    }
+}
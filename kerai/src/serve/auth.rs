@@ -1,5 +1,5 @@
 use axum::extract::{Query, State};
-use axum::http::{HeaderMap, StatusCode};
+use axum::http::HeaderMap;
 use axum::response::{IntoResponse, Redirect};
 use axum::Json;
 use serde::{Deserialize, Serialize};
@@ -8,8 +8,12 @@ use std::sync::Arc;
 use tracing::{error, info};
 use uuid::Uuid;
 
+use crate::lang::machine::Role;
+
 use super::db::Pool;
+use super::error::ApiError;
 use super::oauth::{self, OAuthConfig};
+use super::validate::ValidJson;
 
 #[derive(Serialize)]
 pub struct SessionInfo {
@@ -27,10 +31,8 @@ pub struct SessionInfo {
 pub async fn get_session(
     State(pool): State<Arc<Pool>>,
     headers: HeaderMap,
-) -> Result<Json<SessionInfo>, (StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<SessionInfo>, ApiError> {
+    let client = pool.get().await?;
 
     // Check for existing session cookie
     if let Some(token) = extract_session_token(&headers) {
@@ -45,8 +47,7 @@ pub async fn get_session(
             "INSERT INTO kerai.users (auth_provider) VALUES ('anonymous') RETURNING id",
             &[],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .await?
         .get(0);
 
     // Generate a short anonymous workspace name from user_id
@@ -58,8 +59,7 @@ pub async fn get_session(
              VALUES ($1, $2, true, true) RETURNING id",
             &[&user_id, &ws_name],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .await?
         .get(0);
 
     // Generate session token
@@ -70,8 +70,7 @@ pub async fn get_session(
             "INSERT INTO kerai.sessions (user_id, workspace_id, token) VALUES ($1, $2, $3)",
             &[&user_id, &workspace_id, &token],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     Ok(Json(SessionInfo {
         user_id: user_id.to_string(),
@@ -94,32 +93,28 @@ pub struct BskyStartRequest {
 pub async fn bsky_start(
     State(pool): State<Arc<Pool>>,
     headers: HeaderMap,
-    Json(req): Json<BskyStartRequest>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    ValidJson(req): ValidJson<BskyStartRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let client = pool.get().await?;
 
     let session_token = extract_session_token(&headers)
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "no session".to_string()))?;
+        .ok_or_else(|| ApiError::unauthorized("no session"))?;
 
     // Load OAuth config
-    let config = load_oauth_config(&client).await.map_err(|e| {
-        (StatusCode::INTERNAL_SERVER_ERROR, e)
-    })?;
+    let config = load_oauth_config(&client).await.map_err(ApiError::internal)?;
 
     // Discover auth server — resolve handle if provided, otherwise go direct to bsky.social
     let (handle, auth_meta) = if let Some(h) = req.handle.filter(|h| !h.is_empty()) {
         let did = oauth::resolve_handle(&h).await.map_err(|e| {
-            (StatusCode::BAD_REQUEST, format!("handle resolution failed: {e}"))
+            ApiError::bad_request(format!("handle resolution failed: {e}"))
         })?;
         let meta = oauth::discover_auth_server(&did).await.map_err(|e| {
-            (StatusCode::BAD_GATEWAY, format!("auth server discovery failed: {e}"))
+            ApiError::upstream(format!("auth server discovery failed: {e}"))
         })?;
         (Some(h), meta)
     } else {
         let meta = oauth::discover_auth_server_from_pds("https://bsky.social").await.map_err(|e| {
-            (StatusCode::BAD_GATEWAY, format!("auth server discovery failed: {e}"))
+            ApiError::upstream(format!("auth server discovery failed: {e}"))
         })?;
         (None, meta)
     };
@@ -132,7 +127,7 @@ pub async fn bsky_start(
     // PAR → authorize URL
     let authorize_url = oauth::pushed_auth_request(&config, &auth_meta, &code_challenge, &state, &dpop_key)
         .await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("PAR failed: {e}")))?;
+        .map_err(|e| ApiError::upstream(format!("PAR failed: {e}")))?;
 
     // Store state
     let handle_ref: Option<&str> = handle.as_deref();
@@ -153,7 +148,7 @@ pub async fn bsky_start(
             ],
         )
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("state storage failed: {e}")))?;
+        .map_err(|e| ApiError::internal(format!("state storage failed: {e}")))?;
 
     Ok(Json(json!({ "url": authorize_url })))
 }
@@ -170,12 +165,12 @@ pub struct CallbackParams {
 pub async fn bsky_callback(
     State(pool): State<Arc<Pool>>,
     Query(params): Query<CallbackParams>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<impl IntoResponse, ApiError> {
     info!("OAuth callback: state={}", &params.state[..8.min(params.state.len())]);
 
     let client = pool.get().await.map_err(|e| {
         error!("callback: pool error: {e}");
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        ApiError::from(e)
     })?;
 
     // Look up oauth_state
@@ -188,11 +183,11 @@ pub async fn bsky_callback(
         .await
         .map_err(|e| {
             error!("callback: state lookup error: {e}");
-            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            ApiError::from(e)
         })?
         .ok_or_else(|| {
             error!("callback: state not found or expired");
-            (StatusCode::BAD_REQUEST, "invalid or expired state".to_string())
+            ApiError::bad_request("invalid or expired state")
         })?;
 
     let code_verifier: String = state_row.get(0);
@@ -209,13 +204,13 @@ pub async fn bsky_callback(
     // Restore ephemeral DPoP key
     let dpop_key = oauth::dpop_key_from_b64(&dpop_key_b64).map_err(|e| {
         error!("callback: dpop key restore failed: {e}");
-        (StatusCode::INTERNAL_SERVER_ERROR, format!("dpop key error: {e}"))
+        ApiError::internal(format!("dpop key error: {e}"))
     })?;
 
     // Load OAuth config
     let config = load_oauth_config(&client).await.map_err(|e| {
         error!("callback: config load failed: {e}");
-        (StatusCode::INTERNAL_SERVER_ERROR, e)
+        ApiError::internal(e)
     })?;
 
     // Exchange code for tokens
@@ -232,7 +227,7 @@ pub async fn bsky_callback(
     .await
     .map_err(|e| {
         error!("callback: token exchange failed: {e}");
-        (StatusCode::BAD_GATEWAY, format!("token exchange failed: {e}"))
+        ApiError::upstream(format!("token exchange failed: {e}"))
     })?;
 
     // Extract DID from token response (sub field) or use stored DID
@@ -242,7 +237,7 @@ pub async fn bsky_callback(
         .or(did.as_deref())
         .ok_or_else(|| {
             error!("callback: no DID in token response");
-            (StatusCode::INTERNAL_SERVER_ERROR, "no DID in token response".to_string())
+            ApiError::internal("no DID in token response")
         })?
         .to_string();
 
@@ -274,11 +269,11 @@ pub async fn bsky_callback(
         .await
         .map_err(|e| {
             error!("callback: session lookup error: {e}");
-            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            ApiError::from(e)
         })?
         .ok_or_else(|| {
             error!("callback: session expired for token_len={}", session_token.len());
-            (StatusCode::BAD_REQUEST, "session expired".to_string())
+            ApiError::bad_request("session expired")
         })?;
 
     let current_user_id: Uuid = session_row.get(0);
@@ -290,8 +285,7 @@ pub async fn bsky_callback(
             "SELECT id, is_allowed FROM kerai.users WHERE did = $1",
             &[&user_did],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     if let Some(existing_row) = existing_user {
         let existing_user_id: Uuid = existing_row.get(0);
@@ -316,8 +310,7 @@ pub async fn bsky_callback(
                      WHERE token = $2",
                     &[&existing_user_id, &session_token],
                 )
-                .await
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                .await?;
 
             // Update handle in case it changed
             client
@@ -325,8 +318,7 @@ pub async fn bsky_callback(
                     "UPDATE kerai.users SET handle = $1, last_login = now() WHERE id = $2",
                     &[&user_handle, &existing_user_id],
                 )
-                .await
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                .await?;
         } else {
             info!("callback: DID already linked to this user, updating handle");
             client
@@ -334,8 +326,7 @@ pub async fn bsky_callback(
                     "UPDATE kerai.users SET handle = $1, last_login = now() WHERE id = $2",
                     &[&user_handle, &current_user_id],
                 )
-                .await
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                .await?;
         }
     } else {
         // New user — check if any admin exists
@@ -344,8 +335,7 @@ pub async fn bsky_callback(
                 "SELECT EXISTS(SELECT 1 FROM kerai.users WHERE is_admin = true)",
                 &[],
             )
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .await?
             .get(0);
 
         if !has_admin {
@@ -358,8 +348,7 @@ pub async fn bsky_callback(
                      WHERE id = $3",
                     &[&user_did, &user_handle, &current_user_id],
                 )
-                .await
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                .await?;
         } else {
             // Admin exists — check if handle was pre-allowlisted (placeholder row)
             let placeholder = client
@@ -367,8 +356,7 @@ pub async fn bsky_callback(
                     "SELECT id FROM kerai.users WHERE handle = $1 AND is_allowed = true AND did IS NULL",
                     &[&user_handle],
                 )
-                .await
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                .await?;
 
             if let Some(placeholder_row) = placeholder {
                 let placeholder_id: Uuid = placeholder_row.get(0);
@@ -376,8 +364,7 @@ pub async fn bsky_callback(
                 // Delete the placeholder, upgrade the current anonymous user
                 client
                     .execute("DELETE FROM kerai.users WHERE id = $1", &[&placeholder_id])
-                    .await
-                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                    .await?;
                 client
                     .execute(
                         "UPDATE kerai.users SET did = $1, handle = $2, auth_provider = 'bsky', \
@@ -385,8 +372,7 @@ pub async fn bsky_callback(
                          WHERE id = $3",
                         &[&user_did, &user_handle, &current_user_id],
                     )
-                    .await
-                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                    .await?;
             } else {
                 info!("callback: user {} not allowlisted, rejecting", user_handle);
                 // Not allowlisted — reject
@@ -415,8 +401,7 @@ pub async fn bsky_callback(
             "DELETE FROM kerai.oauth_state WHERE state = $1",
             &[&params.state],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     info!("callback: success, redirecting to /");
     // Redirect to home
@@ -426,10 +411,8 @@ pub async fn bsky_callback(
 /// GET /.well-known/oauth-client-metadata — AT Protocol client metadata.
 pub async fn client_metadata(
     State(pool): State<Arc<Pool>>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let client = pool.get().await?;
 
     let public_url = get_config_value(&client, "public_url")
         .await
@@ -452,17 +435,15 @@ pub async fn client_metadata(
 /// GET /oauth/jwks.json — ES256 public key in JWKS format.
 pub async fn jwks(
     State(pool): State<Arc<Pool>>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let client = pool.get().await?;
 
     let jwk_str = get_config_value(&client, "oauth.bsky.public_jwk").await.map_err(|e| {
-        (StatusCode::NOT_FOUND, format!("JWKS not configured: {e}"))
+        ApiError::not_found(format!("JWKS not configured: {e}"))
     })?;
 
     let jwk: serde_json::Value = serde_json::from_str(&jwk_str).map_err(|e| {
-        (StatusCode::INTERNAL_SERVER_ERROR, format!("invalid JWK: {e}"))
+        ApiError::internal(format!("invalid JWK: {e}"))
     })?;
 
     Ok(Json(json!({ "keys": [jwk] })))
@@ -472,10 +453,8 @@ pub async fn jwks(
 pub async fn logout(
     State(pool): State<Arc<Pool>>,
     headers: HeaderMap,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let client = pool.get().await?;
 
     if let Some(token) = extract_session_token(&headers) {
         client
@@ -483,15 +462,24 @@ pub async fn logout(
                 "DELETE FROM kerai.sessions WHERE token = $1",
                 &[&token],
             )
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            .await?;
     }
 
     Ok(Json(json!({"status": "logged_out"})))
 }
 
-/// Extract session token from Cookie header.
+/// Extract session token from an `Authorization: Bearer` header (as sent by
+/// API clients) or the session cookie.
 pub(crate) fn extract_session_token(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|t| !t.is_empty());
+    if let Some(token) = bearer {
+        return Some(token.to_string());
+    }
     let cookie_header = headers.get("cookie")?.to_str().ok()?;
     for pair in cookie_header.split(';') {
         let pair = pair.trim();
@@ -510,7 +498,7 @@ async fn lookup_session(
     client: &tokio_postgres::Client,
     token: &str,
     pg_host: &str,
-) -> Result<Option<SessionInfo>, (StatusCode, String)> {
+) -> Result<Option<SessionInfo>, ApiError> {
     let row = client
         .query_opt(
             "SELECT s.user_id, s.workspace_id, w.name, u.handle, u.auth_provider, s.token, u.is_admin \
@@ -520,8 +508,7 @@ async fn lookup_session(
              WHERE s.token = $1 AND s.expires_at > now()",
             &[&token],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     Ok(row.map(|r| SessionInfo {
         user_id: r.get::<_, Uuid>(0).to_string(),
@@ -569,6 +556,30 @@ pub async fn resolve_session(
     Ok((user_id, workspace_id))
 }
 
+/// Role of a user for word permissions in the stack machine.
+///
+/// Until any admin exists every user counts as admin, so the first operator
+/// can run `admin oauth setup bsky` (the same bootstrap rule its resolver uses).
+pub async fn resolve_role(pool: &Pool, user_id: Uuid) -> Result<Role, String> {
+    let client = pool.get().await.map_err(|e| e.to_string())?;
+
+    let row = client
+        .query_opt(
+            "SELECT CASE \
+                 WHEN is_admin OR NOT EXISTS (SELECT 1 FROM kerai.users WHERE is_admin) THEN 'admin' \
+                 WHEN auth_provider = 'anonymous' THEN 'anonymous' \
+                 ELSE 'user' END \
+             FROM kerai.users WHERE id = $1",
+            &[&user_id],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(row
+        .and_then(|r| r.get::<_, &str>(0).parse().ok())
+        .unwrap_or(Role::Anonymous))
+}
+
 /// Load OAuth config from kerai.config table.
 async fn load_oauth_config(
     client: &tokio_postgres::Client,
@@ -0,0 +1,382 @@
+/// Workspace bundles — portable JSON snapshots of a workspace.
+///
+/// A bundle carries everything needed to recreate a workspace on another
+/// serve instance: the stack items (kind/ref_id/meta, in position order),
+/// the user words defined in the workspace, and references to any document
+/// subtrees the stack points at. Documents themselves are not copied — they
+/// live in the shared node graph — so the bundle records enough (id, filename,
+/// path) for the importer to report which references resolve locally.
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio_postgres::Client;
+use uuid::Uuid;
+
+use super::db::Pool;
+
+/// Bundle format identifier, checked on import.
+pub const BUNDLE_FORMAT: &str = "kerai.workspace";
+
+/// Current bundle schema version.
+pub const BUNDLE_VERSION: i64 = 1;
+
+/// Result of importing a bundle.
+pub struct ImportResult {
+    pub workspace_id: Uuid,
+    pub name: String,
+    pub items: usize,
+    pub words: usize,
+    pub documents_resolved: usize,
+    pub documents_missing: Vec<String>,
+}
+
+/// Build a bundle for a workspace.
+pub async fn export_workspace(client: &Client, workspace_id: Uuid) -> Result<Value, String> {
+    let ws = client
+        .query_opt(
+            "SELECT name FROM kerai.workspaces WHERE id = $1",
+            &[&workspace_id],
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("workspace not found")?;
+    let name: String = ws.get(0);
+
+    let item_rows = client
+        .query(
+            "SELECT kind, ref_id, meta FROM kerai.stack_items \
+             WHERE workspace_id = $1 ORDER BY position ASC",
+            &[&workspace_id],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let items: Vec<Value> = item_rows
+        .iter()
+        .map(|r| {
+            json!({
+                "kind": r.get::<_, String>(0),
+                "ref_id": r.get::<_, String>(1),
+                "meta": r.get::<_, Value>(2),
+            })
+        })
+        .collect();
+
+    let word_rows = client
+        .query(
+            "SELECT name, body FROM kerai.workspace_words \
+             WHERE workspace_id = $1 ORDER BY name",
+            &[&workspace_id],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let words: Vec<Value> = word_rows
+        .iter()
+        .map(|r| word_entry(r.get(0), r.get(1)))
+        .collect();
+
+    // Any stack item whose ref_id is the id of a document node is a document reference.
+    let doc_rows = client
+        .query(
+            "SELECT DISTINCT n.id, n.content, n.path::text \
+             FROM kerai.stack_items si \
+             JOIN kerai.nodes n ON n.id::text = si.ref_id \
+             WHERE si.workspace_id = $1 AND n.kind = 'document'",
+            &[&workspace_id],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let documents: Vec<Value> = doc_rows
+        .iter()
+        .map(|r| {
+            json!({
+                "id": r.get::<_, Uuid>(0).to_string(),
+                "filename": r.get::<_, Option<String>>(1),
+                "path": r.get::<_, Option<String>>(2),
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "format": BUNDLE_FORMAT,
+        "version": BUNDLE_VERSION,
+        "name": name,
+        "items": items,
+        "words": words,
+        "documents": documents,
+    }))
+}
+
+/// Recreate a workspace from a bundle under a new id owned by `user_id`.
+///
+/// The bundle's name is reused when free; otherwise a short suffix from the
+/// new id is appended to satisfy the per-user unique name constraint. It is
+/// created in one transaction, so a bad item leaves no workspace behind.
+pub async fn import_workspace(
+    client: &mut Client,
+    user_id: Uuid,
+    bundle: &Value,
+) -> Result<ImportResult, String> {
+    validate(bundle)?;
+
+    let base_name = bundle["name"].as_str().unwrap_or("imported");
+    let items = bundle["items"].as_array().cloned().unwrap_or_default();
+    let words = bundle_words(bundle);
+    let documents = bundle["documents"].as_array().cloned().unwrap_or_default();
+
+    let tx = client.transaction().await.map_err(|e| e.to_string())?;
+    let workspace_id = Uuid::new_v4();
+    let inserted = tx
+        .execute(
+            "INSERT INTO kerai.workspaces (id, user_id, name) VALUES ($1, $2, $3) \
+             ON CONFLICT (user_id, name) DO NOTHING",
+            &[&workspace_id, &user_id, &base_name],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let name = if inserted == 1 {
+        base_name.to_string()
+    } else {
+        let suffixed = format!("{}-{}", base_name, &workspace_id.to_string()[..8]);
+        tx.execute(
+            "INSERT INTO kerai.workspaces (id, user_id, name) VALUES ($1, $2, $3)",
+            &[&workspace_id, &user_id, &suffixed],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        suffixed
+    };
+
+    for (pos, item) in items.iter().enumerate() {
+        let pos_i32 = pos as i32;
+        let kind = item["kind"].as_str().unwrap_or("text");
+        let ref_id = item["ref_id"].as_str().unwrap_or("");
+        let meta = item.get("meta").cloned().unwrap_or(Value::Null);
+        tx.execute(
+            "INSERT INTO kerai.stack_items (workspace_id, position, kind, ref_id, meta) \
+                 VALUES ($1, $2, $3, $4, $5)",
+            &[&workspace_id, &pos_i32, &kind, &ref_id, &meta],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    for (word_name, body) in &words {
+        tx.execute(
+            "INSERT INTO kerai.workspace_words (workspace_id, name, body) VALUES ($1, $2, $3) \
+                 ON CONFLICT (workspace_id, name) DO UPDATE SET body = EXCLUDED.body",
+            &[&workspace_id, &word_name, &body],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    let mut documents_resolved = 0;
+    let mut documents_missing = Vec::new();
+    for doc in &documents {
+        let Some(doc_id) = doc["id"].as_str().and_then(|s| s.parse::<Uuid>().ok()) else {
+            continue;
+        };
+        let exists: bool = tx
+            .query_one(
+                "SELECT EXISTS(SELECT 1 FROM kerai.nodes WHERE id = $1 AND kind = 'document')",
+                &[&doc_id],
+            )
+            .await
+            .map_err(|e| e.to_string())?
+            .get(0);
+        if exists {
+            documents_resolved += 1;
+        } else {
+            documents_missing.push(doc_id.to_string());
+        }
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ImportResult {
+        workspace_id,
+        name,
+        items: items.len(),
+        words: words.len(),
+        documents_resolved,
+        documents_missing,
+    })
+}
+
+/// A user word as a bundle carries it.
+pub fn word_entry(name: &str, body: &str) -> Value {
+    json!({"name": name, "body": body})
+}
+
+/// The user words of a bundle as (name, body); entries missing either are
+/// skipped.
+pub fn bundle_words(bundle: &Value) -> Vec<(&str, &str)> {
+    bundle["words"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|w| Some((w["name"].as_str()?, w["body"].as_str()?)))
+        .collect()
+}
+
+/// The user words of a workspace, by name, for the stack machine.
+pub async fn load_words(
+    pool: &Pool,
+    workspace_id: Uuid,
+) -> Result<HashMap<String, String>, String> {
+    let client = pool.get().await.map_err(|e| e.to_string())?;
+    let rows = client
+        .query(
+            "SELECT name, body FROM kerai.workspace_words WHERE workspace_id = $1",
+            &[&workspace_id],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+/// Store words the stack machine defined, replacing earlier definitions.
+pub async fn save_words(
+    pool: &Pool,
+    workspace_id: Uuid,
+    words: &[(String, String)],
+) -> Result<(), String> {
+    if words.is_empty() {
+        return Ok(());
+    }
+    let client = pool.get().await.map_err(|e| e.to_string())?;
+    for (name, body) in words {
+        client
+            .execute(
+                "INSERT INTO kerai.workspace_words (workspace_id, name, body) VALUES ($1, $2, $3) \
+                 ON CONFLICT (workspace_id, name) DO UPDATE SET body = EXCLUDED.body",
+                &[&workspace_id, name, body],
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Check that a value looks like a workspace bundle we understand.
+pub fn validate(bundle: &Value) -> Result<(), String> {
+    if bundle.get("format").and_then(|v| v.as_str()) != Some(BUNDLE_FORMAT) {
+        return Err(format!("not a workspace bundle (expected format '{}')", BUNDLE_FORMAT));
+    }
+    match bundle.get("version").and_then(|v| v.as_i64()) {
+        Some(v) if v <= BUNDLE_VERSION => {}
+        Some(v) => return Err(format!("unsupported bundle version {v}")),
+        None => return Err("bundle is missing a version".into()),
+    }
+    if !bundle.get("items").is_some_and(|v| v.is_array()) {
+        return Err("bundle is missing an items array".into());
+    }
+    Ok(())
+}
+
+impl ImportResult {
+    /// One-line summary for stack output and logs.
+    pub fn summary(&self) -> String {
+        let mut msg = format!(
+            "workspace '{}' imported ({}): {} items, {} words",
+            self.name,
+            &self.workspace_id.to_string()[..8],
+            self.items,
+            self.words,
+        );
+        if self.documents_resolved > 0 || !self.documents_missing.is_empty() {
+            msg.push_str(&format!(
+                ", {} documents linked, {} missing",
+                self.documents_resolved,
+                self.documents_missing.len()
+            ));
+        }
+        msg
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "workspaceId": self.workspace_id.to_string(),
+            "name": self.name,
+            "items": self.items,
+            "words": self.words,
+            "documentsResolved": self.documents_resolved,
+            "documentsMissing": self.documents_missing,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::handlers;
+    use crate::lang::machine::{Machine, Role};
+    use crate::lang::ptr::Ptr;
+
+    #[test]
+    fn validate_accepts_minimal_bundle() {
+        let b = json!({"format": BUNDLE_FORMAT, "version": 1, "items": []});
+        assert!(validate(&b).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_wrong_format() {
+        let b = json!({"format": "other", "version": 1, "items": []});
+        assert!(validate(&b).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_future_version() {
+        let b = json!({"format": BUNDLE_FORMAT, "version": 99, "items": []});
+        assert!(validate(&b).unwrap_err().contains("unsupported"));
+    }
+
+    #[test]
+    fn validate_requires_items() {
+        let b = json!({"format": BUNDLE_FORMAT, "version": 1});
+        assert!(validate(&b).is_err());
+    }
+
+    fn machine() -> Machine {
+        let (handlers, type_methods, help, roles) = handlers::register_all();
+        Machine::new(Uuid::nil(), Uuid::nil(), Role::User, handlers, type_methods, help, roles)
+    }
+
+    #[test]
+    fn defined_words_round_trip_through_a_bundle() {
+        let mut source = machine();
+        source.execute("\"dup *\" \"square\" define").unwrap();
+        let words: Vec<Value> = source
+            .defined
+            .iter()
+            .map(|(name, body)| word_entry(name, body))
+            .collect();
+        let bundle = json!({
+            "format": BUNDLE_FORMAT,
+            "version": BUNDLE_VERSION,
+            "items": [],
+            "words": words,
+        });
+        let text = serde_json::to_string(&bundle).unwrap();
+        let bundle: Value = serde_json::from_str(&text).unwrap();
+        assert!(validate(&bundle).is_ok());
+
+        let mut target = machine();
+        target.words = bundle_words(&bundle)
+            .into_iter()
+            .map(|(name, body)| (name.to_string(), body.to_string()))
+            .collect();
+        target.execute("7 square").unwrap();
+        assert_eq!(target.stack, vec![Ptr::int(49)]);
+    }
+
+    #[test]
+    fn bundle_words_skips_incomplete_entries() {
+        let b = json!({"words": [{"name": "a", "body": "1"}, {"name": "b"}, {"body": "2"}]});
+        assert_eq!(bundle_words(&b), vec![("a", "1")]);
+        assert!(bundle_words(&json!({})).is_empty());
+    }
+}
@@ -1,8 +1,58 @@
 /// Configuration for the serve subcommand.
+use std::time::Duration;
+
+use super::validate::Limits;
+
+/// Database used when neither `--db`, the profile nor `DATABASE_URL` names
+/// one: the local server's socket in /tmp.
+pub const DEFAULT_DATABASE_URL: &str = "host=/tmp dbname=kerai";
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
     pub listen_addr: String,
     pub static_dir: Option<String>,
+    /// Record websocket sessions (`KERAI_RECORD_SESSIONS=1`).
+    pub record_sessions: bool,
+    /// Request body limits (`KERAI_MAX_*`, see [`Limits::from_env`]).
+    pub limits: Limits,
+    /// How often to reap dead jobs (`KERAI_REAP_INTERVAL` seconds, default
+    /// 60); `None` when set to 0.
+    pub reap_interval: Option<Duration>,
+}
+
+impl Config {
+    /// Config for `database_url` and `listen_addr`, the rest from the
+    /// environment: `STATIC_DIR`, `KERAI_RECORD_SESSIONS`,
+    /// `KERAI_REAP_INTERVAL` and the limits.
+    pub fn from_env(database_url: &str, listen_addr: &str) -> Self {
+        Self {
+            database_url: database_url.to_string(),
+            listen_addr: listen_addr.to_string(),
+            static_dir: std::env::var("STATIC_DIR").ok(),
+            record_sessions: std::env::var("KERAI_RECORD_SESSIONS")
+                .is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")),
+            limits: Limits::from_env(),
+            reap_interval: std::env::var("KERAI_REAP_INTERVAL")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .map_or(Some(60), |secs: u64| (secs > 0).then_some(secs))
+                .map(Duration::from_secs),
+        }
+    }
+}
+
+/// The database to serve and where it came from: `--db`, then the config
+/// profile's connection, then `DATABASE_URL`, then [`DEFAULT_DATABASE_URL`].
+pub fn discover_database(db: Option<&str>, profile: Option<&str>) -> (String, &'static str) {
+    if let Some(db) = db {
+        return (db.to_string(), "--db");
+    }
+    if let Some(connection) = profile {
+        return (connection.to_string(), "config profile");
+    }
+    match std::env::var("DATABASE_URL") {
+        Ok(url) if !url.is_empty() => (url, "DATABASE_URL"),
+        _ => (DEFAULT_DATABASE_URL.to_string(), "default"),
+    }
 }
@@ -1,9 +1,12 @@
 /// Database connection pool using tokio-postgres.
+use axum::http::HeaderMap;
+use std::future::Future;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio_postgres::{Client, NoTls};
 
 use super::config::Config;
+use super::error::ApiError;
 
 /// Simple connection pool wrapper.
 pub struct Pool {
@@ -69,10 +72,80 @@ impl Pool {
             }
         });
 
+        if let Some(token) = principal() {
+            client
+                .execute("SELECT kerai.set_principal($1)", &[&token])
+                .await?;
+        }
         Ok(client)
     }
 }
 
+tokio::task_local! {
+    static PRINCIPAL: String;
+}
+
+/// The token row-level security acts for on connections opened by the
+/// request being served, if it has one.
+pub fn principal() -> Option<String> {
+    PRINCIPAL.try_with(Clone::clone).ok()
+}
+
+/// Serve `request` with every connection it opens acting as the user of
+/// `token` (a session or API token) for row-level security, via
+/// `kerai.set_principal`. Work it spawns, like a WebSocket after the
+/// upgrade, runs outside and acts for no one.
+pub async fn with_principal<F: Future>(token: Option<String>, request: F) -> F::Output {
+    match token {
+        Some(token) => PRINCIPAL.scope(token, request).await,
+        None => request.await,
+    }
+}
+
+/// `token` if `kerai.set_principal` accepts it: a live session or API token
+/// of a user who is allowed in. Anything else, or a database without row-level
+/// security, leaves the request with no principal.
+pub async fn check_principal(pool: &Pool, token: &str) -> Result<Option<String>, ApiError> {
+    let client = pool.get().await?;
+    let accepted = client
+        .execute("SELECT kerai.set_principal($1)", &[&token])
+        .await
+        .is_ok();
+    Ok(accepted.then(|| token.to_string()))
+}
+
+/// Request header naming the view (kerai.views) to read through.
+pub const VIEW_HEADER: &str = "x-kerai-view";
+
+/// Apply the view named in the request's `X-Kerai-View` header, if any, to
+/// `client`. Every request gets its own connection, so the view ends with it.
+pub async fn apply_view(client: &Client, headers: &HeaderMap) -> Result<(), tokio_postgres::Error> {
+    let Some(view) = headers.get(VIEW_HEADER).and_then(|v| v.to_str().ok()) else {
+        return Ok(());
+    };
+    client
+        .execute("SELECT kerai.apply_view($1)", &[&view])
+        .await?;
+    Ok(())
+}
+
+/// Read through changeset `id` (kerai.changesets), if one is given: its
+/// staged ops are applied on `client` in a transaction that is never
+/// committed, so they are rolled back when the request's connection closes.
+pub async fn preview_changeset(
+    client: &Client,
+    id: Option<&str>,
+) -> Result<(), tokio_postgres::Error> {
+    let Some(id) = id else {
+        return Ok(());
+    };
+    client.batch_execute("BEGIN").await?;
+    client
+        .execute("SELECT kerai.preview_changeset($1::text::uuid)", &[&id])
+        .await?;
+    Ok(())
+}
+
 fn parse_host(url: &str) -> String {
     // Key=value format: "host=/tmp dbname=kerai"
     if let Some(pos) = url.find("host=") {
@@ -86,3 +159,21 @@ fn parse_host(url: &str) -> String {
     }
     "localhost".into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn principal_is_scoped_to_the_request() {
+        assert_eq!(principal(), None);
+        let seen = with_principal(Some("kerai_abc".into()), async {
+            tokio::task::yield_now().await;
+            principal()
+        })
+        .await;
+        assert_eq!(seen.as_deref(), Some("kerai_abc"));
+        assert_eq!(principal(), None);
+        assert_eq!(with_principal(None, async { principal() }).await, None);
+    }
+}
@@ -0,0 +1,198 @@
+/// Error responses for the HTTP API, as RFC 7807 problem details.
+///
+/// Every handler fails with an [`ApiError`], which renders as an
+/// `application/problem+json` body:
+///
+/// ```json
+/// {"type": "https://kerai.dev/errors/not_found", "title": "Not Found",
+///  "status": 404, "code": "not_found", "detail": "node not found"}
+/// ```
+///
+/// `code` is the stable, machine-readable part clients branch on; `detail`
+/// is for people and may change. Database errors are classified by their
+/// SQLSTATE in one place ([`classify`]), so an error raised by the kerai
+/// extension reads the same whichever route ran the query.
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
+use tokio_postgres::error::SqlState;
+
+/// Base of the `type` URI; the error code is appended.
+pub const TYPE_BASE: &str = "https://kerai.dev/errors/";
+
+/// Media type of every error body.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// An error returned from a handler, with its HTTP status and kerai code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub detail: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            detail: detail.into(),
+        }
+    }
+
+    /// The request itself is malformed or names something invalid.
+    pub fn bad_request(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", detail)
+    }
+
+    /// No session, or the session has expired.
+    pub fn unauthorized(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "unauthorized", detail)
+    }
+
+    /// Signed in, but not allowed to do this.
+    pub fn forbidden(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "forbidden", detail)
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", detail)
+    }
+
+    /// A service kerai depends on (PDS, auth server, model backend) failed.
+    pub fn upstream(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_GATEWAY, "upstream", detail)
+    }
+
+    pub fn internal(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal", detail)
+    }
+}
+
+/// Status and code for a database error with SQLSTATE `code`; `closed` is
+/// whether the connection was lost, which has no SQLSTATE.
+///
+/// The kerai extension reports bad arguments with `error!`, which Postgres
+/// raises as `XX000`, and its PL/pgSQL functions with `RAISE` (`P0001`);
+/// both come back as `extension`, a client error.
+pub fn classify(code: Option<&SqlState>, closed: bool) -> (StatusCode, &'static str) {
+    let Some(code) = code else {
+        return if closed {
+            (StatusCode::SERVICE_UNAVAILABLE, "database_unavailable")
+        } else {
+            (StatusCode::INTERNAL_SERVER_ERROR, "database")
+        };
+    };
+    let state = code.code();
+    match state {
+        "XX000" | "P0001" => (StatusCode::BAD_REQUEST, "extension"),
+        "P0002" => (StatusCode::NOT_FOUND, "not_found"),
+        "23505" => (StatusCode::CONFLICT, "conflict"),
+        "40001" | "40P01" => (StatusCode::CONFLICT, "serialization_failure"),
+        "42501" => (StatusCode::FORBIDDEN, "forbidden"),
+        "57014" => (StatusCode::GATEWAY_TIMEOUT, "timeout"),
+        _ if state.starts_with("22") => (StatusCode::BAD_REQUEST, "invalid_input"),
+        _ if state.starts_with("23") => (StatusCode::CONFLICT, "constraint_violation"),
+        _ if state.starts_with("42") => (StatusCode::BAD_REQUEST, "invalid_query"),
+        _ if state.starts_with("08") || state.starts_with("57") => {
+            (StatusCode::SERVICE_UNAVAILABLE, "database_unavailable")
+        }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "database"),
+    }
+}
+
+impl From<tokio_postgres::Error> for ApiError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        let (status, code) = classify(e.code(), e.is_closed());
+        // The server's own message, without the "db error: " prefix.
+        let detail = match e.as_db_error() {
+            Some(db) => db.message().to_string(),
+            None => e.to_string(),
+        };
+        Self::new(status, code, detail)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            tracing::error!("{} ({}): {}", self.status, self.code, self.detail);
+        }
+        let body = json!({
+            "type": format!("{TYPE_BASE}{}", self.code),
+            "title": self.status.canonical_reason().unwrap_or("Error"),
+            "status": self.status.as_u16(),
+            "code": self.code,
+            "detail": self.detail,
+        });
+        (
+            self.status,
+            [(header::CONTENT_TYPE, PROBLEM_JSON)],
+            body.to_string(),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(code: &str) -> SqlState {
+        SqlState::from_code(code)
+    }
+
+    #[test]
+    fn extension_errors_are_client_errors() {
+        assert_eq!(
+            classify(Some(&state("XX000")), false),
+            (StatusCode::BAD_REQUEST, "extension")
+        );
+        assert_eq!(
+            classify(Some(&state("P0001")), false),
+            (StatusCode::BAD_REQUEST, "extension")
+        );
+    }
+
+    #[test]
+    fn classifies_by_sqlstate_class() {
+        assert_eq!(classify(Some(&state("22P02")), false).1, "invalid_input");
+        assert_eq!(classify(Some(&state("23505")), false).1, "conflict");
+        assert_eq!(
+            classify(Some(&state("23503")), false).1,
+            "constraint_violation"
+        );
+        assert_eq!(
+            classify(Some(&state("40001")), false).1,
+            "serialization_failure"
+        );
+        assert_eq!(classify(Some(&state("42P01")), false).1, "invalid_query");
+        assert_eq!(
+            classify(Some(&state("42501")), false).0,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(classify(Some(&state("53100")), false).1, "database");
+    }
+
+    #[test]
+    fn errors_without_sqlstate() {
+        assert_eq!(classify(None, true).0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(classify(None, false).0, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn renders_problem_json() {
+        let response = ApiError::not_found("node not found").into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["title"], "Not Found");
+        assert_eq!(body["type"], "https://kerai.dev/errors/not_found");
+        assert_eq!(body["detail"], "node not found");
+    }
+}
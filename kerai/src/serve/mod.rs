@@ -1,27 +1,51 @@
 pub mod auth;
+pub mod bundle;
 pub mod config;
 pub mod db;
+pub mod error;
 pub mod notify;
 pub mod oauth;
+pub mod page;
+pub mod poll;
+pub mod query;
+pub mod reaper;
+pub mod recording;
+pub mod rooms;
 pub mod routes;
+pub mod stack_sync;
+pub mod stream;
+pub mod time;
+pub mod tokens;
+pub mod validate;
 
 use tower_http::cors::CorsLayer;
 use tower_http::services::ServeDir;
 
 use config::Config;
 
-/// Run the web server.
-pub async fn run(addr: &str, db_url: &str) {
+/// Run the web server on `addr` against `db_url`, serving the frontend
+/// from `static_dir` (or `STATIC_DIR`) when there is one. Exits if the
+/// database cannot be reached or does not have the kerai extension.
+pub async fn run(addr: &str, db_url: &str, static_dir: Option<String>) {
     tracing_subscriber::fmt::init();
 
-    let config = Config {
-        database_url: db_url.to_string(),
-        listen_addr: addr.to_string(),
-        static_dir: std::env::var("STATIC_DIR").ok(),
-    };
+    let mut config = Config::from_env(db_url, addr);
+    if static_dir.is_some() {
+        config.static_dir = static_dir;
+    }
 
     tracing::info!("Starting kerai serve on {}", config.listen_addr);
     tracing::info!("Database: {}", config.database_url);
+    match check_database(&config.database_url).await {
+        Ok(version) => tracing::info!("kerai extension {}", version),
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    }
+    if config.record_sessions {
+        tracing::info!("Recording websocket sessions to kerai.session_recordings");
+    }
 
     // Database pool
     let pool = db::Pool::new(config.clone());
@@ -29,8 +53,13 @@ pub async fn run(addr: &str, db_url: &str) {
     // Start LISTEN/NOTIFY background task
     let notify_tx = notify::start_listener(config.database_url.clone());
 
+    // Fail or requeue jobs whose worker has died
+    if let Some(interval) = config.reap_interval {
+        reaper::start(pool.clone(), interval);
+    }
+
     // Build router
-    let mut app = routes::build_router(pool, notify_tx)
+    let mut app = routes::build_router(pool, notify_tx, config.record_sessions, config.limits)
         .layer(CorsLayer::permissive());
 
     // Serve static files if configured
@@ -46,3 +75,22 @@ pub async fn run(addr: &str, db_url: &str) {
     tracing::info!("Listening on {}", addr);
     axum::serve(listener, app).await.expect("Server error");
 }
+
+/// Connect to `database_url` and return the installed kerai extension's
+/// version, or why the server cannot run against it.
+async fn check_database(database_url: &str) -> Result<String, String> {
+    let (client, connection) = tokio_postgres::connect(database_url, tokio_postgres::NoTls)
+        .await
+        .map_err(|e| format!("cannot connect to {database_url}: {e}"))?;
+    tokio::spawn(connection);
+    let row = client
+        .query_opt(
+            "SELECT extversion FROM pg_extension WHERE extname = 'kerai'",
+            &[],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    row.map(|r| r.get(0)).ok_or_else(|| {
+        format!("the kerai extension is not installed in {database_url}; run CREATE EXTENSION kerai CASCADE")
+    })
+}
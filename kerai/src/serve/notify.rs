@@ -1,12 +1,127 @@
-/// Background task: LISTEN kerai_ops → broadcast to WebSocket clients.
+/// Background task: LISTEN kerai_ops, kerai_stack and the change event
+/// channels → broadcast to WebSocket clients.
+///
+/// `kerai_ops` carries each applied CRDT operation and `kerai_stack` each
+/// stack delta. The change event channels carry a JSON event per row change
+/// to nodes, edges and versions (the `trigger_change_events` schema block):
+/// `{table, op, id, path, kind, instance, ...}`, `op` being `insert`,
+/// `update` or `delete`. A node moved elsewhere also has `old_path`; an
+/// edge's path and instance are its source node's, and its kind its
+/// relation; a version's path and kind are its node's.
 use futures::StreamExt;
+use serde_json::{json, Value};
 use tokio::sync::broadcast;
 use tokio_postgres::NoTls;
 
+use super::stack_sync;
+
+/// Channel of applied CRDT operations.
+pub const OPS_CHANNEL: &str = "kerai_ops";
+
+/// Channels of row change events.
+pub const CHANGE_CHANNELS: &[&str] = &["kerai_nodes", "kerai_edges", "kerai_versions"];
+
+/// One notification, with the channel it came on.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub channel: String,
+    pub payload: String,
+}
+
+/// Which change events a WebSocket client wants: those on one of `tables`
+/// whose path is under one of `paths` (ltree prefixes, as `a.b` matches
+/// `a.b` and `a.b.c` but not `a.bc`) and whose kind is one of `kinds`.
+/// An empty list lets everything through.
+#[derive(Clone, Debug, Default)]
+pub struct Subscription {
+    pub tables: Vec<String>,
+    pub paths: Vec<String>,
+    pub kinds: Vec<String>,
+}
+
+impl Subscription {
+    /// The subscription a `subscribe` message asks for: its `tables`,
+    /// `paths` and `kinds`, each a string or an array of them.
+    pub fn from_message(msg: &Value) -> Result<Self, String> {
+        let list = |key: &str| -> Result<Vec<String>, String> {
+            match &msg[key] {
+                Value::Null => Ok(Vec::new()),
+                Value::String(s) => Ok(vec![s.clone()]),
+                Value::Array(items) => items
+                    .iter()
+                    .map(|i| i.as_str().map(String::from))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| format!("{} must be strings", key)),
+                _ => Err(format!("{} must be a string or an array of strings", key)),
+            }
+        };
+        let subscription = Subscription {
+            tables: list("tables")?,
+            paths: list("paths")?,
+            kinds: list("kinds")?,
+        };
+        let known = |t: &String| CHANGE_CHANNELS.contains(&format!("kerai_{}", t).as_str());
+        if let Some(table) = subscription.tables.iter().find(|t| !known(t)) {
+            return Err(format!("unknown table: {}", table));
+        }
+        Ok(subscription)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "tables": self.tables,
+            "paths": self.paths,
+            "kinds": self.kinds,
+        })
+    }
+
+    /// Whether a change event passes the filters. A moved node matches on
+    /// either its old or its new path.
+    pub fn matches(&self, event: &Value) -> bool {
+        let any = |list: &[String], key: &str| {
+            list.is_empty()
+                || event[key]
+                    .as_str()
+                    .is_some_and(|v| list.iter().any(|l| l == v))
+        };
+        let under = |path: &str| {
+            self.paths.iter().any(|prefix| {
+                path == prefix
+                    || path
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with('.'))
+            })
+        };
+        let in_paths = self.paths.is_empty()
+            || ["path", "old_path"]
+                .iter()
+                .any(|key| event[*key].as_str().is_some_and(under));
+        any(&self.tables, "table") && any(&self.kinds, "kind") && in_paths
+    }
+}
+
+/// What to send a WebSocket client for an op or change notification:
+/// `kerai_ops` payloads as they are until it subscribes, then only the
+/// change events its filters pass. Stack deltas are never relayed here.
+pub fn relayed(notification: &Notification, subscription: Option<&Subscription>) -> Option<String> {
+    let Some(subscription) = subscription else {
+        return (notification.channel == OPS_CHANNEL).then(|| notification.payload.clone());
+    };
+    if !CHANGE_CHANNELS.contains(&notification.channel.as_str()) {
+        return None;
+    }
+    let mut event: Value = serde_json::from_str(&notification.payload).ok()?;
+    if !subscription.matches(&event) {
+        return None;
+    }
+    event["type"] = json!("change");
+    Some(event.to_string())
+}
+
 /// Start the LISTEN background task.
 /// Returns a broadcast Sender that WebSocket handlers subscribe to.
-pub fn start_listener(database_url: String) -> broadcast::Sender<String> {
-    let (tx, _) = broadcast::channel::<String>(256);
+pub fn start_listener(database_url: String) -> broadcast::Sender<Notification> {
+    let (tx, _) = broadcast::channel::<Notification>(256);
     let tx_clone = tx.clone();
 
     tokio::spawn(async move {
@@ -28,7 +143,7 @@ pub fn start_listener(database_url: String) -> broadcast::Sender<String> {
 
 async fn listen_loop(
     database_url: &str,
-    tx: &broadcast::Sender<String>,
+    tx: &broadcast::Sender<Notification>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let (client, mut connection) = tokio_postgres::connect(database_url, NoTls).await?;
 
@@ -36,22 +151,74 @@ async fn listen_loop(
     let stream = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
     let mut stream = std::pin::pin!(stream);
 
-    // Start LISTEN on the kerai_ops channel
-    client.execute("LISTEN kerai_ops", &[]).await?;
-    tracing::info!("LISTEN kerai_ops started");
+    // Start LISTEN on the op, stack delta and change event channels
+    let channels = [OPS_CHANNEL, stack_sync::CHANNEL]
+        .into_iter()
+        .chain(CHANGE_CHANNELS.iter().copied());
+    let mut listening = Vec::new();
+    for channel in channels {
+        client.execute(&format!("LISTEN {}", channel), &[]).await?;
+        listening.push(channel);
+    }
+    tracing::info!("LISTEN {} started", listening.join(", "));
 
     // Forward notifications to the broadcast channel
     while let Some(msg) = stream.next().await {
-        match msg? {
-            tokio_postgres::AsyncMessage::Notification(n) => {
-                let payload = n.payload().to_string();
-                tracing::debug!("notification: {}", payload);
-                // Ignore send errors (no active receivers)
-                let _ = tx.send(payload);
-            }
-            _ => {}
+        if let tokio_postgres::AsyncMessage::Notification(n) = msg? {
+            tracing::debug!("notification on {}: {}", n.channel(), n.payload());
+            // Ignore send errors (no active receivers)
+            let _ = tx.send(Notification {
+                channel: n.channel().to_string(),
+                payload: n.payload().to_string(),
+            });
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(channel: &str, payload: Value) -> Notification {
+        Notification {
+            channel: channel.to_string(),
+            payload: payload.to_string(),
+        }
+    }
+
+    #[test]
+    fn subscriptions_filter_change_events() {
+        let msg = json!({"type": "subscribe", "tables": "nodes", "paths": ["docs.a"]});
+        let subscription = Subscription::from_message(&msg).unwrap();
+        let event = |path: &str| json!({"table": "nodes", "op": "update", "path": path});
+        assert!(subscription.matches(&event("docs.a")));
+        assert!(subscription.matches(&event("docs.a.b")));
+        assert!(!subscription.matches(&event("docs.ab")));
+
+        let moved = json!({"table": "nodes", "path": "elsewhere", "old_path": "docs.a.b"});
+        assert!(subscription.matches(&moved));
+
+        let bad = json!({"tables": ["nodes", "users"]});
+        let error = Subscription::from_message(&bad).unwrap_err();
+        assert_eq!(error, "unknown table: users");
+    }
+
+    #[test]
+    fn relays_ops_until_subscribed() {
+        let op = notification(OPS_CHANNEL, json!({"op_type": "insert_node"}));
+        let change = notification("kerai_nodes", json!({"table": "nodes", "path": "a"}));
+        let delta = notification(stack_sync::CHANNEL, json!({"type": "stack_delta"}));
+
+        assert_eq!(relayed(&op, None), Some(op.payload.clone()));
+        assert_eq!(relayed(&change, None), None);
+        assert_eq!(relayed(&delta, None), None);
+
+        let all = Subscription::default();
+        assert_eq!(relayed(&op, Some(&all)), None);
+        let event: Value = serde_json::from_str(&relayed(&change, Some(&all)).unwrap()).unwrap();
+        assert_eq!(event["type"], "change");
+        assert_eq!(relayed(&delta, Some(&all)), None);
+    }
+}
@@ -0,0 +1,288 @@
+/// Keyset pagination for list endpoints.
+///
+/// [`Page`] is extracted from the query string: `limit` (1 to
+/// [`MAX_LIMIT`]), `order` (`desc`, newest first, or `asc`), `cursor`, and
+/// the `kind` and `language` filters. Rows are ordered by `(created_at,
+/// id)`, so a page picks up strictly after the last row of the one before
+/// it however many rows were added in between. A route reads one row more
+/// than the limit ([`Page::order_by`]); [`Page::respond`] drops it and,
+/// when there was one, names where the next page starts in the
+/// `X-Next-Cursor` header.
+///
+/// A cursor is `<created_at in microseconds since the epoch>_<id>`; clients
+/// should pass it back as given rather than build one.
+use axum::extract::{FromRequestParts, Query};
+use axum::http::request::Parts;
+use axum::http::HeaderValue;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use super::error::ApiError;
+
+/// Response header carrying the cursor of the next page.
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+/// Largest page a client may ask for.
+pub const MAX_LIMIT: i64 = 1000;
+
+/// Page size when `limit` is not given.
+pub const DEFAULT_LIMIT: i64 = 100;
+
+/// Where a page starts: just past the row with this `created_at` and id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub micros: i64,
+    pub id: Uuid,
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.micros, self.id)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || ApiError::bad_request(format!("invalid cursor '{s}'"));
+        let (micros, id) = s.split_once('_').ok_or_else(bad)?;
+        Ok(Self {
+            micros: micros.parse().map_err(|_| bad())?,
+            id: id.parse().map_err(|_| bad())?,
+        })
+    }
+}
+
+/// Direction of a listing over `(created_at, id)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+impl Order {
+    fn keyword(self) -> &'static str {
+        match self {
+            Order::Asc => "ASC",
+            Order::Desc => "DESC",
+        }
+    }
+
+    fn past(self) -> &'static str {
+        match self {
+            Order::Asc => ">",
+            Order::Desc => "<",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PageParams {
+    limit: Option<i64>,
+    order: Option<String>,
+    cursor: Option<String>,
+    kind: Option<String>,
+    language: Option<String>,
+}
+
+/// A page of a listing, as asked for in the query string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub limit: i64,
+    pub order: Order,
+    pub cursor: Option<Cursor>,
+    pub kind: Option<String>,
+    pub language: Option<String>,
+}
+
+impl Page {
+    fn from_params(params: PageParams) -> Result<Self, ApiError> {
+        let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(ApiError::bad_request(format!(
+                "limit must be between 1 and {MAX_LIMIT}, not {limit}"
+            )));
+        }
+        let order = match params.order.as_deref().unwrap_or("desc") {
+            "desc" => Order::Desc,
+            "asc" => Order::Asc,
+            other => {
+                return Err(ApiError::bad_request(format!(
+                    "unknown order '{other}' (expected asc or desc)"
+                )))
+            }
+        };
+        Ok(Self {
+            limit,
+            order,
+            cursor: params.cursor.as_deref().map(str::parse).transpose()?,
+            kind: params.kind.filter(|k| !k.is_empty()),
+            language: params.language.filter(|l| !l.is_empty()),
+        })
+    }
+
+    /// `WHERE` condition keeping rows past the cursor, on the row's
+    /// `created_at` and `id` columns, with the cursor bound as `$n` (its
+    /// microseconds) and `$n+1` (its id); true when there is no cursor.
+    pub fn after(&self, created_at: &str, id: &str, n: usize) -> String {
+        format!(
+            "(${n}::bigint IS NULL OR ({created_at}, {id}) {} \
+             ('epoch'::timestamptz + ${n}::bigint * interval '1 microsecond', ${m}::uuid))",
+            self.order.past(),
+            m = n + 1,
+        )
+    }
+
+    /// `ORDER BY ... LIMIT ...` for the page, one row over the limit so
+    /// [`Page::respond`] can tell whether another page follows.
+    pub fn order_by(&self, created_at: &str, id: &str) -> String {
+        let dir = self.order.keyword();
+        format!(
+            "ORDER BY {created_at} {dir}, {id} {dir} LIMIT {}",
+            self.limit + 1
+        )
+    }
+
+    /// The cursor's microseconds and id, to bind for [`Page::after`].
+    pub fn cursor_args(&self) -> (Option<i64>, Option<Uuid>) {
+        match self.cursor {
+            Some(c) => (Some(c.micros), Some(c.id)),
+            None => (None, None),
+        }
+    }
+
+    /// The page as a JSON array, given rows of `(item, cursor)` as read
+    /// with [`Page::order_by`], the cursor from [`cursor_sql`], with
+    /// `X-Next-Cursor` set when there are more.
+    pub fn respond(&self, rows: Vec<(Value, String)>) -> Response {
+        let (items, next) = self.split(rows);
+        let mut response = Json(Value::Array(items)).into_response();
+        if let Some(next) = next.and_then(|c| HeaderValue::from_str(&c).ok()) {
+            response.headers_mut().insert(NEXT_CURSOR_HEADER, next);
+        }
+        response
+    }
+
+    fn split(&self, mut rows: Vec<(Value, String)>) -> (Vec<Value>, Option<String>) {
+        let more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit as usize);
+        let next = more
+            .then(|| rows.last().map(|(_, cursor)| cursor.clone()))
+            .flatten();
+        (rows.into_iter().map(|(item, _)| item).collect(), next)
+    }
+}
+
+/// SQL for a row's cursor, given its `created_at` and `id` columns.
+pub fn cursor_sql(created_at: &str, id: &str) -> String {
+    format!("(extract(epoch FROM {created_at}) * 1000000)::bigint::text || '_' || {id}::text")
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Page {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<PageParams>::try_from_uri(&parts.uri)
+            .map_err(|e| ApiError::bad_request(e.body_text()))?;
+        Self::from_params(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(limit: i64) -> Page {
+        Page {
+            limit,
+            order: Order::Desc,
+            cursor: None,
+            kind: None,
+            language: None,
+        }
+    }
+
+    fn params(query: &str) -> Result<Page, ApiError> {
+        let uri: axum::http::Uri = format!("/x?{query}").parse().unwrap();
+        let Query(params) = Query::<PageParams>::try_from_uri(&uri).unwrap();
+        Page::from_params(params)
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = Cursor {
+            micros: 1_760_000_000_123_456,
+            id: "0192f0c4-1b2a-7c3d-8e4f-5a6b7c8d9e0f".parse().unwrap(),
+        };
+        let text = cursor.to_string();
+        assert_eq!(
+            text,
+            "1760000000123456_0192f0c4-1b2a-7c3d-8e4f-5a6b7c8d9e0f"
+        );
+        assert_eq!(text.parse::<Cursor>().unwrap(), cursor);
+        for bad in [
+            "",
+            "123",
+            "abc_0192f0c4-1b2a-7c3d-8e4f-5a6b7c8d9e0f",
+            "123_nope",
+        ] {
+            let err = bad.parse::<Cursor>().unwrap_err();
+            assert_eq!(err.code, "bad_request", "{bad}");
+        }
+    }
+
+    #[test]
+    fn params_default_and_validate() {
+        let p = params("").unwrap();
+        assert_eq!(p.limit, DEFAULT_LIMIT);
+        assert_eq!(p.order, Order::Desc);
+        assert_eq!(p.cursor, None);
+
+        let p = params("limit=5&order=asc&kind=heading&language=").unwrap();
+        assert_eq!((p.limit, p.order), (5, Order::Asc));
+        assert_eq!(p.kind.as_deref(), Some("heading"));
+        assert_eq!(p.language, None);
+
+        assert!(params("limit=0").is_err());
+        assert!(params("limit=1001").is_err());
+        assert!(params("order=sideways").is_err());
+        assert!(params("cursor=nope").is_err());
+    }
+
+    #[test]
+    fn keyset_sql_follows_order() {
+        let mut p = page(10);
+        assert!(p
+            .after("d.created_at", "d.id", 1)
+            .contains("(d.created_at, d.id) <"));
+        assert_eq!(
+            p.order_by("d.created_at", "d.id"),
+            "ORDER BY d.created_at DESC, d.id DESC LIMIT 11"
+        );
+        p.order = Order::Asc;
+        assert!(p
+            .after("d.created_at", "d.id", 3)
+            .contains("(d.created_at, d.id) > ('epoch'::timestamptz + $3::bigint"));
+        assert!(p.after("d.created_at", "d.id", 3).contains("$4::uuid"));
+    }
+
+    #[test]
+    fn split_sets_the_next_cursor_only_when_there_are_more() {
+        let rows = |n: usize| -> Vec<(Value, String)> {
+            (0..n).map(|i| (Value::from(i), format!("c{i}"))).collect()
+        };
+        let (items, next) = page(2).split(rows(3));
+        assert_eq!(items, vec![Value::from(0), Value::from(1)]);
+        assert_eq!(next.as_deref(), Some("c1"));
+
+        let (items, next) = page(2).split(rows(2));
+        assert_eq!(items.len(), 2);
+        assert_eq!(next, None);
+    }
+}
@@ -0,0 +1,157 @@
+/// Long-polling over the operation stream, for agents that cannot hold a
+/// websocket (`GET /api/notifications/poll`).
+///
+/// Events come from `kerai.poll_changes`, so a poll sees the same
+/// operations the websocket announces, in commit-safe order. Each session
+/// keeps a cursor per region in `kerai.notification_cursors`; a poll
+/// without `cursor` resumes from it, so a batch agent can ask what changed
+/// since its last run without keeping state of its own.
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use tokio_postgres::Client;
+
+use super::notify::{Notification, OPS_CHANNEL};
+
+/// Wait used when a poll does not give one.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest a poll is held open.
+pub const MAX_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Re-check this often while waiting, since the commit horizon can move
+/// without a notification.
+const RECHECK: Duration = Duration::from_secs(2);
+
+/// Parse a timeout like `30s`, `500ms`, `2m` or bare seconds, capped at
+/// [`MAX_TIMEOUT`].
+pub fn parse_timeout(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (digits, unit) = text.split_at(split);
+    let n: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid timeout '{text}'"))?;
+    let duration = match unit {
+        "" | "s" => Duration::from_secs(n),
+        "ms" => Duration::from_millis(n),
+        "m" => Duration::from_secs(n * 60),
+        _ => return Err(format!("invalid timeout unit in '{text}'")),
+    };
+    Ok(duration.min(MAX_TIMEOUT))
+}
+
+/// Session id for a token, if the session is live.
+pub async fn session_id(client: &Client, token: &str) -> Result<uuid::Uuid, String> {
+    client
+        .query_opt(
+            "SELECT id FROM kerai.sessions WHERE token = $1 AND expires_at > now()",
+            &[&token],
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|row| row.get(0))
+        .ok_or_else(|| "invalid or expired session".to_string())
+}
+
+/// The session's stored cursor for a region ('' for everything).
+pub async fn stored_cursor(
+    client: &Client,
+    session_id: uuid::Uuid,
+    region: &str,
+) -> Result<Option<String>, String> {
+    let row = client
+        .query_opt(
+            "SELECT cursor FROM kerai.notification_cursors \
+             WHERE session_id = $1 AND region = $2::text::ltree",
+            &[&session_id, &region],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Record what a poll delivered to the session.
+pub async fn store_cursor(
+    client: &Client,
+    session_id: uuid::Uuid,
+    region: &str,
+    cursor: &str,
+    delivered: i64,
+) -> Result<(), String> {
+    client
+        .execute(
+            "INSERT INTO kerai.notification_cursors (session_id, region, cursor, delivered) \
+             VALUES ($1, $2::text::ltree, $3, $4) \
+             ON CONFLICT (session_id, region) DO UPDATE SET \
+                 cursor = EXCLUDED.cursor, \
+                 delivered = kerai.notification_cursors.delivered + EXCLUDED.delivered, \
+                 polled_at = now()",
+            &[&session_id, &region, &cursor, &delivered],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Poll for operations after `cursor` under `region`, waiting up to
+/// `timeout` for some to arrive. Returns `kerai.poll_changes`' result,
+/// with empty `events` when the wait ran out.
+pub async fn wait_for_changes(
+    client: &Client,
+    notify_rx: &mut broadcast::Receiver<Notification>,
+    cursor: Option<&str>,
+    region: Option<&str>,
+    limit: i32,
+    timeout: Duration,
+) -> Result<Value, String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let row = client
+            .query_one(
+                "SELECT kerai.poll_changes($1, $2, $3)",
+                &[&cursor, &region, &limit],
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        let result: Value = row.get(0);
+
+        let now = Instant::now();
+        if result["events"].as_array().is_some_and(|e| !e.is_empty()) || now >= deadline {
+            return Ok(result);
+        }
+
+        // Sleep until an op is announced, the recheck interval passes, or time is up
+        let wake = deadline.min(now + RECHECK);
+        loop {
+            match tokio::time::timeout_at(wake, notify_rx.recv()).await {
+                // Stack deltas and change events are not operations
+                Ok(Ok(notification)) if notification.channel != OPS_CHANNEL => continue,
+                Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => break,
+                Ok(Err(broadcast::error::RecvError::Closed)) => {
+                    tokio::time::sleep_until(wake).await;
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeouts_parse_with_units_and_cap() {
+        assert_eq!(parse_timeout("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_timeout("45").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_timeout("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_timeout("1m").unwrap(), Duration::from_secs(60));
+        assert_eq!(parse_timeout("1h").unwrap_err(), "invalid timeout unit in '1h'");
+        assert!(parse_timeout("soon").is_err());
+        assert_eq!(parse_timeout("10m").unwrap(), MAX_TIMEOUT);
+    }
+}
@@ -0,0 +1,246 @@
+/// Resolution of `query_request` markers, page fetches and plans for
+/// `result` items.
+///
+/// A result never holds more than one page of rows. Its meta keeps the query
+/// description and an `offset` into the ordered match set; each fetch runs the
+/// query with `LIMIT page_size OFFSET offset` on whatever pooled connection
+/// is at hand, so no server-side cursor has to outlive a request. Every
+/// statement runs under the same time budget.
+use serde_json::{json, Value};
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, Row};
+
+use crate::lang::handlers::query::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+use crate::lang::ptr::Ptr;
+
+/// Compile a query description to a `FROM ... WHERE ...` clause over
+/// `kerai.nodes` plus its text parameters.
+pub fn compile(spec: &Value) -> Result<(String, Vec<String>), String> {
+    match spec["op"].as_str() {
+        Some("find") => {
+            let pattern = spec["pattern"].as_str().ok_or("find: missing pattern")?;
+            Ok((
+                "FROM kerai.nodes WHERE content ILIKE $1 AND deleted_at IS NULL".into(),
+                vec![pattern.to_string()],
+            ))
+        }
+        Some(other) => Err(format!("query: unknown op '{other}'")),
+        None => Err("query: malformed request".into()),
+    }
+}
+
+/// SQL for one page. Ordering includes `id` so pages are stable and disjoint.
+pub fn page_sql(from_where: &str, page_size: i64, offset: i64) -> String {
+    format!(
+        "SELECT id::text, kind, content, path::text {from_where} \
+         ORDER BY kind, content, id LIMIT {page_size} OFFSET {offset}"
+    )
+}
+
+/// Longest any single query for a result (or its plan) may run.
+pub const QUERY_BUDGET_MS: u64 = 5000;
+
+/// Run a statement in a read-only transaction with `statement_timeout` set
+/// to [`QUERY_BUDGET_MS`], so a pathological pattern cannot tie up a pooled
+/// connection.
+pub async fn query_budgeted(
+    client: &Client,
+    sql: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<Vec<Row>, String> {
+    client
+        .batch_execute(&format!(
+            "BEGIN READ ONLY; SET LOCAL statement_timeout = {QUERY_BUDGET_MS}"
+        ))
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = client.query(sql, params).await;
+    let end = if result.is_ok() { "COMMIT" } else { "ROLLBACK" };
+    client.batch_execute(end).await.map_err(|e| e.to_string())?;
+    result.map_err(|e| e.to_string())
+}
+
+fn page_size(meta: &Value) -> i64 {
+    meta["page_size"]
+        .as_i64()
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Count matches for a `query_request` and turn it into an unloaded `result`.
+pub async fn open(client: &Client, request: &Ptr) -> Result<Ptr, String> {
+    let spec = &request.meta["query"];
+    let (from_where, params) = compile(spec)?;
+    let refs: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+
+    let rows = query_budgeted(client, &format!("SELECT count(*) {from_where}"), &refs).await?;
+    let total: i64 = rows.first().map_or(0, |r| r.get(0));
+
+    Ok(Ptr {
+        kind: "result".into(),
+        ref_id: request.ref_id.clone(),
+        meta: json!({
+            "query": spec,
+            "total": total,
+            "offset": 0,
+            "page_size": page_size(&request.meta),
+        }),
+        id: 0,
+    })
+}
+
+/// Load the page at the result's offset and advance the offset past it.
+///
+/// Viewing past the last page starts over from the first.
+pub async fn fetch_page(client: &Client, result: &Ptr) -> Result<Ptr, String> {
+    let mut meta = result.meta.clone();
+    let (from_where, params) = compile(&meta["query"])?;
+    let refs: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+
+    let total = meta["total"].as_i64().unwrap_or(0);
+    let size = page_size(&meta);
+    let mut offset = meta["offset"].as_i64().unwrap_or(0);
+    if offset >= total {
+        offset = 0;
+    }
+
+    let rows = query_budgeted(client, &page_sql(&from_where, size, offset), &refs).await?;
+    let rows: Vec<Value> = rows
+        .iter()
+        .map(|r| {
+            json!({
+                "id": r.get::<_, String>(0),
+                "kind": r.get::<_, String>(1),
+                "content": r.get::<_, Option<String>>(2),
+                "path": r.get::<_, Option<String>>(3),
+            })
+        })
+        .collect();
+
+    meta["offset"] = json!(offset + rows.len() as i64);
+    meta["rows"] = json!(rows);
+    if let Some(obj) = meta.as_object_mut() {
+        obj.remove("fetch");
+    }
+
+    Ok(Ptr {
+        meta,
+        ..result.clone()
+    })
+}
+
+/// Reduce one node of `EXPLAIN (FORMAT JSON)` output to what the plan views
+/// show: node type, relation or index, estimated rows, costs, the first
+/// condition that applies, and annotated children.
+pub fn annotate_plan(plan: &Value) -> Value {
+    let detail = ["Index Cond", "Filter", "Hash Cond", "Join Filter", "Recheck Cond"]
+        .iter()
+        .find_map(|key| plan[*key].as_str())
+        .or_else(|| plan["Sort Key"].as_array().and_then(|keys| keys.first()?.as_str()));
+    let children: Vec<Value> = plan["Plans"]
+        .as_array()
+        .map(|plans| plans.iter().map(annotate_plan).collect())
+        .unwrap_or_default();
+    json!({
+        "node": plan["Node Type"],
+        "relation": plan["Relation Name"],
+        "index": plan["Index Name"],
+        "rows": plan["Plan Rows"],
+        "width": plan["Plan Width"],
+        "startup_cost": plan["Startup Cost"],
+        "cost": plan["Total Cost"],
+        "detail": detail,
+        "children": children,
+    })
+}
+
+/// Plan the page query an `explain_request` (a result's description) would
+/// run next, under the query budget. Returns a `plan` item whose meta holds
+/// the SQL, top-level estimates and the annotated tree.
+pub async fn explain(client: &Client, request: &Ptr) -> Result<Ptr, String> {
+    let meta = &request.meta;
+    let (from_where, params) = compile(&meta["query"])?;
+    let refs: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+    let sql = page_sql(&from_where, page_size(meta), meta["offset"].as_i64().unwrap_or(0));
+
+    let rows = query_budgeted(client, &format!("EXPLAIN (FORMAT JSON) {sql}"), &refs).await?;
+    let raw: Value = rows.first().ok_or("explain: no plan returned")?.get(0);
+    let plan = annotate_plan(&raw[0]["Plan"]);
+
+    Ok(Ptr {
+        kind: "plan".into(),
+        ref_id: request.ref_id.clone(),
+        meta: json!({
+            "sql": sql,
+            "total_cost": plan["cost"],
+            "estimated_rows": plan["rows"],
+            "plan": plan,
+        }),
+        id: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_find_binds_pattern() {
+        let (sql, params) = compile(&json!({"op": "find", "pattern": "%parse%"})).unwrap();
+        assert_eq!(
+            sql,
+            "FROM kerai.nodes WHERE content ILIKE $1 AND deleted_at IS NULL"
+        );
+        assert_eq!(params, vec!["%parse%"]);
+    }
+
+    #[test]
+    fn compile_rejects_unknown_op() {
+        assert!(compile(&json!({"op": "drop_tables"})).is_err());
+    }
+
+    #[test]
+    fn page_sql_orders_for_stable_paging() {
+        let sql = page_sql("FROM kerai.nodes WHERE true", 50, 100);
+        assert!(sql.ends_with("ORDER BY kind, content, id LIMIT 50 OFFSET 100"));
+    }
+
+    #[test]
+    fn page_size_is_clamped() {
+        assert_eq!(page_size(&json!({"page_size": 100_000})), MAX_PAGE_SIZE);
+        assert_eq!(page_size(&json!({})), DEFAULT_PAGE_SIZE);
+    }
+
+    #[test]
+    fn annotate_plan_keeps_estimates_and_children() {
+        let raw = json!({
+            "Node Type": "Limit",
+            "Plan Rows": 50,
+            "Total Cost": 42.5,
+            "Plans": [{
+                "Node Type": "Sort",
+                "Sort Key": ["kind", "content", "id"],
+                "Plan Rows": 120,
+                "Total Cost": 40.0,
+                "Plans": [{
+                    "Node Type": "Seq Scan",
+                    "Relation Name": "nodes",
+                    "Filter": "(content ~~* $1)",
+                    "Plan Rows": 120,
+                    "Total Cost": 30.25,
+                }],
+            }],
+        });
+        let plan = annotate_plan(&raw);
+        assert_eq!(plan["rows"], 50);
+        assert_eq!(plan["children"][0]["detail"], "kind");
+        let scan = &plan["children"][0]["children"][0];
+        assert_eq!(scan["relation"], "nodes");
+        assert_eq!(scan["detail"], "(content ~~* $1)");
+
+        let mut lines = Vec::new();
+        crate::lang::handlers::query::plan_lines(&plan, 0, &mut lines);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], "    Seq Scan on nodes (rows≈120, cost 30.25) (content ~~* $1)");
+    }
+}
@@ -0,0 +1,35 @@
+/// Periodic reaping of dead jobs (`kerai.reap_jobs`), so a worker that
+/// crashed mid-job does not leave it `running` until someone notices.
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::db::Pool;
+
+/// Spawn a task calling `kerai.reap_jobs()` every `interval`, logging
+/// what it failed or queued again. Errors are logged and retried on the
+/// next tick, so a database restart does not stop the reaper.
+pub fn start(pool: Arc<Pool>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            match reap(&pool).await {
+                Ok((0, 0)) => {}
+                Ok((failed, requeued)) => {
+                    tracing::info!("reaped dead jobs: {failed} failed, {requeued} requeued")
+                }
+                Err(e) => tracing::warn!("reap_jobs failed: {e}"),
+            }
+        }
+    });
+}
+
+/// Run one reap; returns how many jobs were failed and requeued.
+async fn reap(pool: &Pool) -> Result<(usize, usize), tokio_postgres::Error> {
+    let client = pool.get().await?;
+    let row = client.query_one("SELECT kerai.reap_jobs()", &[]).await?;
+    let result: serde_json::Value = row.get(0);
+    let count = |key: &str| result[key].as_array().map_or(0, Vec::len);
+    Ok((count("failed"), count("requeued")))
+}
@@ -0,0 +1,354 @@
+/// Opt-in recording of websocket sessions, and their replay.
+///
+/// With `KERAI_RECORD_SESSIONS=1` the serve layer logs every websocket
+/// message of a connection, in both directions, to
+/// `kerai.session_recordings` with its offset from the connection's start.
+/// Content is never stored: every string that is not an id or an operation
+/// keyword is replaced by a short hash, so equal content stays recognisable
+/// while its text is gone.
+///
+/// Replay re-applies a recording's client messages inside a transaction
+/// that is always rolled back, against a scratch workspace, and reports
+/// where the outcome differs from what was recorded.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use super::db::Pool;
+
+/// Keys whose string values are structure, not content, and are kept.
+const KEPT_KEYS: &[&str] = &["op_type", "type", "kind", "language", "relation", "placement"];
+
+/// Keys whose values are credentials and are dropped outright.
+const SECRET_KEYS: &[&str] = &["session_token", "token"];
+
+/// Which way a message went.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// From the client.
+    In,
+    /// To the client.
+    Out,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::In => "in",
+            Direction::Out => "out",
+        }
+    }
+}
+
+struct Entry {
+    seq: i32,
+    offset_ms: i32,
+    direction: Direction,
+    identity: Option<(Uuid, Uuid)>,
+    message: Value,
+    outcome: Option<Value>,
+}
+
+/// Records one websocket connection. Rows are written by a background
+/// task, so recording never holds up the socket.
+pub struct Recorder {
+    pub recording_id: Uuid,
+    started: Instant,
+    seq: AtomicI32,
+    /// (user, workspace) once the client has subscribed.
+    identity: Mutex<Option<(Uuid, Uuid)>>,
+    tx: mpsc::UnboundedSender<Entry>,
+}
+
+impl Recorder {
+    /// Start recording a new connection.
+    pub fn start(pool: Arc<Pool>) -> Arc<Recorder> {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Entry>();
+        let recording_id = Uuid::new_v4();
+
+        tokio::spawn(async move {
+            let client = match pool.get().await {
+                Ok(client) => client,
+                Err(e) => {
+                    tracing::warn!("session recording {recording_id} disabled: {e}");
+                    return;
+                }
+            };
+            while let Some(entry) = rx.recv().await {
+                let (user_id, workspace_id) = entry.identity.unzip();
+                let result = client
+                    .execute(
+                        "INSERT INTO kerai.session_recordings \
+                         (recording_id, seq, offset_ms, direction, user_id, workspace_id, message, outcome) \
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                        &[
+                            &recording_id,
+                            &entry.seq,
+                            &entry.offset_ms,
+                            &entry.direction.as_str(),
+                            &user_id,
+                            &workspace_id,
+                            &entry.message,
+                            &entry.outcome,
+                        ],
+                    )
+                    .await;
+                if let Err(e) = result {
+                    tracing::warn!("session recording {recording_id}: {e}");
+                }
+            }
+        });
+
+        Arc::new(Recorder {
+            recording_id,
+            started: Instant::now(),
+            seq: AtomicI32::new(0),
+            identity: Mutex::new(None),
+            tx,
+        })
+    }
+
+    /// Attribute the rest of the recording to a subscribed session.
+    pub fn identify(&self, user_id: Uuid, workspace_id: Uuid) {
+        *self.identity.lock().unwrap() = Some((user_id, workspace_id));
+    }
+
+    /// Log one message (anonymized), with the server's outcome for client messages.
+    pub fn record(&self, direction: Direction, text: &str, outcome: Option<Value>) {
+        let message = match serde_json::from_str::<Value>(text) {
+            Ok(value) => anonymize(&value),
+            Err(_) => anonymize(&Value::String(text.to_string())),
+        };
+        let _ = self.tx.send(Entry {
+            seq: self.seq.fetch_add(1, Ordering::Relaxed),
+            offset_ms: self.started.elapsed().as_millis().min(i32::MAX as u128) as i32,
+            direction,
+            identity: *self.identity.lock().unwrap(),
+            message,
+            outcome: outcome.map(|o| anonymize(&o)),
+        });
+    }
+}
+
+/// Replace content in a message with hashes, keeping its shape, ids,
+/// numbers and operation keywords; credentials are dropped.
+pub fn anonymize(value: &Value) -> Value {
+    match value {
+        Value::Object(obj) => Value::Object(
+            obj.iter()
+                .map(|(key, v)| {
+                    let v = if SECRET_KEYS.contains(&key.as_str()) {
+                        Value::String("redacted".into())
+                    } else if KEPT_KEYS.contains(&key.as_str()) && v.is_string() {
+                        v.clone()
+                    } else {
+                        anonymize(v)
+                    };
+                    (key.clone(), v)
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(anonymize).collect()),
+        Value::String(s) if Uuid::parse_str(s).is_ok() => value.clone(),
+        Value::String(s) => Value::String(content_hash(s)),
+        _ => value.clone(),
+    }
+}
+
+fn content_hash(s: &str) -> String {
+    let digest = Sha256::digest(s.as_bytes());
+    let hex: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+    format!("sha256:{hex}")
+}
+
+/// Swap recorded node ids for the ones created during replay.
+fn remap_ids(value: &Value, ids: &HashMap<String, String>) -> Value {
+    match value {
+        Value::Object(obj) => Value::Object(
+            obj.iter()
+                .map(|(k, v)| (k.clone(), remap_ids(v, ids)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(|v| remap_ids(v, ids)).collect()),
+        Value::String(s) => ids.get(s).map_or_else(|| value.clone(), |id| Value::String(id.clone())),
+        _ => value.clone(),
+    }
+}
+
+/// Recent recordings, newest first.
+pub async fn list(client: &tokio_postgres::Client, limit: i64) -> Result<Value, String> {
+    let rows = client
+        .query(
+            "SELECT recording_id, min(recorded_at)::text, max(offset_ms), \
+                    count(*) FILTER (WHERE direction = 'in'), \
+                    count(*) FILTER (WHERE direction = 'out'), \
+                    (array_agg(user_id) FILTER (WHERE user_id IS NOT NULL))[1] \
+             FROM kerai.session_recordings \
+             GROUP BY recording_id \
+             ORDER BY min(recorded_at) DESC \
+             LIMIT $1",
+            &[&limit],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(Value::Array(
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "recording_id": r.get::<_, Uuid>(0).to_string(),
+                    "started_at": r.get::<_, String>(1),
+                    "duration_ms": r.get::<_, i32>(2),
+                    "messages_in": r.get::<_, i64>(3),
+                    "messages_out": r.get::<_, i64>(4),
+                    "user_id": r.get::<_, Option<Uuid>>(5).map(|u| u.to_string()),
+                })
+            })
+            .collect(),
+    ))
+}
+
+/// Replay a recording's client messages for `user_id` and report each
+/// outcome next to the recorded one. Nothing is kept: the scratch
+/// workspace and every applied operation are rolled back.
+pub async fn replay(
+    client: &mut tokio_postgres::Client,
+    recording_id: Uuid,
+    user_id: Uuid,
+) -> Result<Value, String> {
+    let mut tx = client.transaction().await.map_err(|e| e.to_string())?;
+
+    let rows = tx
+        .query(
+            "SELECT seq, offset_ms, message, outcome FROM kerai.session_recordings \
+             WHERE recording_id = $1 AND direction = 'in' ORDER BY seq",
+            &[&recording_id],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    if rows.is_empty() {
+        return Err(format!("no recording {recording_id}"));
+    }
+
+    let scratch: Uuid = tx
+        .query_one(
+            "INSERT INTO kerai.workspaces (user_id, name) VALUES ($1, $2) RETURNING id",
+            &[&user_id, &format!("replay-{recording_id}")],
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .get(0);
+
+    let mut ids: HashMap<String, String> = HashMap::new();
+    let mut steps = Vec::with_capacity(rows.len());
+    let mut diverged = 0;
+    for row in &rows {
+        let seq: i32 = row.get(0);
+        let offset_ms: i32 = row.get(1);
+        let message: Value = row.get(2);
+        let recorded: Option<Value> = row.get(3);
+
+        let replayed = if message.get("subscribe").is_some() {
+            json!({"workspace_id": scratch.to_string()})
+        } else {
+            let message = remap_ids(&message, &ids);
+            let op_type = message["op_type"].as_str().unwrap_or_default().to_string();
+            let node_id = message["node_id"].as_str().map(str::to_string);
+            let payload = message.get("payload").cloned().unwrap_or_else(|| json!({}));
+            let sp = tx.savepoint("replay_step").await.map_err(|e| e.to_string())?;
+            let result = sp
+                .query_one(
+                    "SELECT kerai.apply_op($1, $2::text::uuid, $3::jsonb)",
+                    &[&op_type, &node_id, &payload],
+                )
+                .await;
+            match result {
+                Ok(r) => {
+                    sp.commit().await.map_err(|e| e.to_string())?;
+                    let applied: Value = r.get(0);
+                    json!({"node_id": applied["node_id"]})
+                }
+                Err(e) => {
+                    sp.rollback().await.map_err(|e| e.to_string())?;
+                    json!({"error": e.to_string()})
+                }
+            }
+        };
+
+        // Later messages refer to nodes by the ids the original server made
+        if let (Some(old), Some(new)) = (
+            recorded.as_ref().and_then(|o| o["node_id"].as_str()),
+            replayed["node_id"].as_str(),
+        ) {
+            if old != new {
+                ids.insert(old.to_string(), new.to_string());
+            }
+        }
+
+        let recorded_failed = recorded.as_ref().is_some_and(|o| o.get("error").is_some());
+        let differs = recorded_failed != replayed.get("error").is_some();
+        if differs {
+            diverged += 1;
+        }
+        steps.push(json!({
+            "seq": seq,
+            "offset_ms": offset_ms,
+            "op_type": message["op_type"],
+            "recorded": recorded,
+            "replayed": replayed,
+            "diverged": differs,
+        }));
+    }
+
+    tx.rollback().await.map_err(|e| e.to_string())?;
+
+    Ok(json!({
+        "recording_id": recording_id.to_string(),
+        "scratch_workspace": scratch.to_string(),
+        "messages": steps.len(),
+        "diverged": diverged,
+        "steps": steps,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anonymize_hashes_content_and_keeps_structure() {
+        let id = "6f1c2a94-0000-4000-8000-000000000001";
+        let msg = json!({
+            "op_type": "update_content",
+            "node_id": id,
+            "payload": {"content": "secret text", "position": 3, "kind": "paragraph"},
+            "subscribe": {"session_token": "abc123"},
+        });
+        let out = anonymize(&msg);
+        assert_eq!(out["op_type"], "update_content");
+        assert_eq!(out["node_id"], id);
+        assert_eq!(out["payload"]["position"], 3);
+        assert_eq!(out["payload"]["kind"], "paragraph");
+        assert_eq!(out["subscribe"]["session_token"], "redacted");
+        let hashed = out["payload"]["content"].as_str().unwrap();
+        assert!(hashed.starts_with("sha256:") && !hashed.contains("secret"));
+        // Equal content hashes equally
+        assert_eq!(anonymize(&json!("secret text")), out["payload"]["content"]);
+    }
+
+    #[test]
+    fn remap_replaces_known_ids_anywhere() {
+        let ids = HashMap::from([("old".to_string(), "new".to_string())]);
+        let msg = json!({"node_id": "old", "payload": {"parent_id": "old", "other": "keep"}});
+        let out = remap_ids(&msg, &ids);
+        assert_eq!(out["node_id"], "new");
+        assert_eq!(out["payload"]["parent_id"], "new");
+        assert_eq!(out["payload"]["other"], "keep");
+    }
+}
@@ -0,0 +1,567 @@
+/// Document rooms: collaborative editing and presence over a WebSocket.
+///
+/// A client editing a document joins its room:
+///
+/// - `{"type": "join", "document_id"}` answers `{"type": "joined",
+///   document_id, members, present, versions}`, `present` listing who is in
+///   the room and `versions` mapping each node that has been edited to the
+///   Lamport timestamp of its last operation.
+/// - `{"type": "op", ref?, op_type, node_id?, payload, base_ts?}` applies
+///   `insert_node`, `update_content` or `move_node` to a node of that
+///   document. The sender gets `{"type": "ack", ref, op_type, node_id,
+///   lamport_ts}` and the other members `{"type": "op", document_id,
+///   op_type, node_id, payload, lamport_ts, author}`.
+/// - `{"type": "cursor", node_id, mode}` says which node the client is
+///   `viewing` or `editing` (no `node_id`: none).
+/// - `{"type": "leave"}` leaves the room.
+///
+/// Members are told who else is there with `{"type": "presence", event,
+/// document_id, member}`, `event` being `join`, `leave` or `cursor`. A
+/// member is `{connection, user_id, handle, node_id, mode}`, the user
+/// being the one whose session token the connection was opened with (the
+/// `kerai_session` cookie, a bearer token or `?token=`); connections
+/// without one are anonymous.
+///
+/// Edits to an existing node carry `base_ts`, the timestamp of the last
+/// operation on it the client has seen. If the node has changed since,
+/// the edit is refused with `{"type": "rejected", ref, reason: "stale",
+/// node_id, current_ts}` and the client rebases on what it has been sent.
+/// The check and the operation run in one SERIALIZABLE transaction, so of
+/// two edits racing on a node one applies and the other is refused.
+///
+/// The same connection may also `{"type": "subscribe", tables?, paths?,
+/// kinds?}` to change events and `{"type": "unsubscribe"}` again; see
+/// [`notify::relayed`](super::notify::relayed).
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinHandle;
+
+use super::db::Pool;
+use super::notify::Subscription;
+use crate::txn;
+
+/// Cursor modes a member may report.
+const CURSOR_MODES: &[&str] = &["viewing", "editing"];
+
+/// Operations a room member may submit.
+const ROOM_OPS: &[&str] = &["insert_node", "update_content", "move_node"];
+
+/// Room events buffered per member before a slow one starts missing them.
+const ROOM_CAPACITY: usize = 256;
+
+/// An applied edit or presence change, as broadcast to a room.
+#[derive(Clone)]
+struct RoomEvent {
+    /// Connection the event is about, which already knows of it.
+    from: u64,
+    message: String,
+}
+
+/// Who a connection belongs to, from the session it was opened with.
+#[derive(Clone)]
+pub struct Identity {
+    pub user_id: Option<String>,
+    pub handle: String,
+}
+
+/// A room member: who it is and the node it last said it was on.
+#[derive(Clone)]
+struct Member {
+    identity: Identity,
+    node_id: Option<String>,
+    mode: Option<String>,
+}
+
+impl Member {
+    fn to_json(&self, connection: u64) -> Value {
+        json!({
+            "connection": connection,
+            "user_id": self.identity.user_id,
+            "handle": self.identity.handle,
+            "node_id": self.node_id,
+            "mode": self.mode,
+        })
+    }
+}
+
+/// A document being edited: its broadcast channel and who is in it.
+struct Room {
+    tx: broadcast::Sender<RoomEvent>,
+    members: HashMap<u64, Member>,
+}
+
+impl Room {
+    fn present(&self) -> Vec<Value> {
+        let mut present: Vec<Value> = self
+            .members
+            .iter()
+            .map(|(&connection, member)| member.to_json(connection))
+            .collect();
+        present.sort_by_key(|m| m["connection"].as_u64());
+        present
+    }
+
+    /// Tell the room about `connection`; no receivers left is fine, the
+    /// room is closing.
+    fn announce(&self, document_id: &str, connection: u64, event: &str, member: Value) {
+        let message = json!({
+            "type": "presence",
+            "event": event,
+            "document_id": document_id,
+            "member": member,
+        });
+        let _ = self.tx.send(RoomEvent {
+            from: connection,
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Document rooms, by document id.
+#[derive(Default)]
+pub struct Rooms {
+    rooms: Mutex<HashMap<String, Room>>,
+    next_connection: AtomicU64,
+}
+
+impl Rooms {
+    /// A new connection's id, unique among this server's connections.
+    pub fn connection_id(&self) -> u64 {
+        self.next_connection.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Add `connection` to `document_id`'s room, opening it if need be,
+    /// and tell the others. Returns the receiver and who is present, this
+    /// one included.
+    fn join(
+        &self,
+        document_id: &str,
+        connection: u64,
+        identity: Identity,
+    ) -> (broadcast::Receiver<RoomEvent>, Vec<Value>) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms
+            .entry(document_id.to_string())
+            .or_insert_with(|| Room {
+                tx: broadcast::channel(ROOM_CAPACITY).0,
+                members: HashMap::new(),
+            });
+        let rx = room.tx.subscribe();
+        let member = Member {
+            identity,
+            node_id: None,
+            mode: None,
+        };
+        room.announce(document_id, connection, "join", member.to_json(connection));
+        room.members.insert(connection, member);
+        (rx, room.present())
+    }
+
+    /// Take `connection` out of `document_id`'s room, tell the others, and
+    /// close the room if it is empty.
+    fn leave(&self, document_id: &str, connection: u64) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let Some(room) = rooms.get_mut(document_id) else {
+            return;
+        };
+        if let Some(member) = room.members.remove(&connection) {
+            room.announce(document_id, connection, "leave", member.to_json(connection));
+        }
+        if room.members.is_empty() {
+            rooms.remove(document_id);
+        }
+    }
+
+    /// Record the node `connection` is on and tell the others.
+    fn cursor(
+        &self,
+        document_id: &str,
+        connection: u64,
+        node_id: Option<String>,
+        mode: Option<String>,
+    ) -> Option<Value> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(document_id)?;
+        let member = room.members.get_mut(&connection)?;
+        member.node_id = node_id;
+        member.mode = mode;
+        let member = member.to_json(connection);
+        room.announce(document_id, connection, "cursor", member.clone());
+        Some(member)
+    }
+
+    fn publish(&self, document_id: &str, event: RoomEvent) {
+        if let Some(room) = self.rooms.lock().unwrap().get(document_id) {
+            // No receivers left is fine: the room is closing
+            let _ = room.tx.send(event);
+        }
+    }
+
+    /// Who is in `document_id`'s room.
+    pub fn present(&self, document_id: &str) -> Vec<Value> {
+        self.rooms
+            .lock()
+            .unwrap()
+            .get(document_id)
+            .map(Room::present)
+            .unwrap_or_default()
+    }
+}
+
+/// One connection's room membership: the document and the task relaying
+/// the room's events to the client.
+struct Membership {
+    document_id: String,
+    relay: JoinHandle<()>,
+}
+
+/// The user behind a session token; anonymous when there is none or it
+/// is unknown or expired.
+pub async fn identify(pool: &Pool, token: Option<&str>) -> Identity {
+    let anonymous = Identity {
+        user_id: None,
+        handle: "anonymous".to_string(),
+    };
+    let Some(token) = token else {
+        return anonymous;
+    };
+    let Ok(client) = pool.get().await else {
+        return anonymous;
+    };
+    let row = client
+        .query_opt(
+            "SELECT u.id::text, COALESCE(u.handle, 'anon-' || left(u.id::text, 8))
+             FROM kerai.sessions s JOIN kerai.users u ON u.id = s.user_id
+             WHERE s.token = $1 AND s.expires_at > now()",
+            &[&token],
+        )
+        .await;
+    match row {
+        Ok(Some(row)) => Identity {
+            user_id: Some(row.get(0)),
+            handle: row.get(1),
+        },
+        Ok(None) => anonymous,
+        Err(e) => {
+            tracing::warn!("session lookup failed: {}", e);
+            anonymous
+        }
+    }
+}
+
+/// One connection: its id, who opened it, where its replies go, its
+/// change event filters and the room it is in.
+pub struct Session {
+    pub connection: u64,
+    pub identity: Identity,
+    pub outbox: mpsc::UnboundedSender<String>,
+    pub subscription: watch::Sender<Option<Subscription>>,
+    membership: Option<Membership>,
+}
+
+impl Session {
+    pub fn new(
+        connection: u64,
+        identity: Identity,
+        outbox: mpsc::UnboundedSender<String>,
+        subscription: watch::Sender<Option<Subscription>>,
+    ) -> Self {
+        Self {
+            connection,
+            identity,
+            outbox,
+            subscription,
+            membership: None,
+        }
+    }
+
+    /// Leave the room the connection is in, if any.
+    pub fn leave(&mut self, rooms: &Rooms) {
+        if let Some(left) = self.membership.take() {
+            left.relay.abort();
+            rooms.leave(&left.document_id, self.connection);
+        }
+    }
+}
+
+/// Handle one client message that has a `type`, returning the reply to
+/// send it, if any.
+pub async fn handle_message(
+    pool: &Pool,
+    rooms: &Rooms,
+    session: &mut Session,
+    msg: &Value,
+) -> Option<Value> {
+    match msg["type"].as_str() {
+        Some("join") => {
+            let joined = join(pool, rooms, session, msg).await;
+            Some(joined.unwrap_or_else(|e| error_reply(msg, &e)))
+        }
+        Some("leave") => {
+            session.leave(rooms);
+            Some(json!({"type": "left"}))
+        }
+        Some("subscribe") => match Subscription::from_message(msg) {
+            Ok(subscription) => {
+                let mut reply = subscription.to_json();
+                reply["type"] = json!("subscribed");
+                session.subscription.send_replace(Some(subscription));
+                Some(reply)
+            }
+            Err(e) => Some(error_reply(msg, &e)),
+        },
+        Some("unsubscribe") => {
+            session.subscription.send_replace(None);
+            Some(json!({"type": "unsubscribed"}))
+        }
+        Some("cursor") => {
+            let moved = cursor(rooms, session, msg);
+            moved.err().map(|e| error_reply(msg, &e))
+        }
+        Some("op") => {
+            let applied = room_op(pool, rooms, session, msg).await;
+            Some(applied.unwrap_or_else(|e| error_reply(msg, &e)))
+        }
+        Some(other) => {
+            let error = format!("unknown message type: {}", other);
+            Some(error_reply(msg, &error))
+        }
+        None => Some(error_reply(msg, "missing type")),
+    }
+}
+
+/// An error reply to `msg`, echoing its `ref`.
+pub fn error_reply(msg: &Value, error: &str) -> Value {
+    json!({"type": "error", "ref": msg.get("ref"), "error": error})
+}
+
+/// Join the room of `msg.document_id`, leaving any other first.
+async fn join(
+    pool: &Pool,
+    rooms: &Rooms,
+    session: &mut Session,
+    msg: &Value,
+) -> Result<Value, String> {
+    let document_id = msg["document_id"]
+        .as_str()
+        .ok_or_else(|| "missing document_id".to_string())?;
+
+    // Last operation per node, for the client's base timestamps
+    let client = pool.get().await.map_err(|e| e.to_string())?;
+    let row = client
+        .query_one(
+            "WITH RECURSIVE tree AS (
+                SELECT id FROM kerai.nodes WHERE id = $1::text::uuid
+                UNION ALL
+                SELECT n.id FROM kerai.nodes n JOIN tree t ON n.parent_id = t.id
+            )
+            SELECT (SELECT count(*) FROM tree) > 0,
+                   COALESCE((
+                       SELECT jsonb_object_agg(node_id, lamport_ts)
+                       FROM (
+                           SELECT node_id, max(lamport_ts) AS lamport_ts
+                           FROM kerai.operations
+                           WHERE node_id IN (SELECT id FROM tree)
+                           GROUP BY node_id
+                       ) last
+                   ), '{}'::jsonb)",
+            &[&document_id],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    let exists: bool = row.get(0);
+    if !exists {
+        return Err(format!("document not found: {}", document_id));
+    }
+    let versions: Value = row.get(1);
+
+    let connection = session.connection;
+    session.leave(rooms);
+    let identity = session.identity.clone();
+    let (mut room_rx, present) = rooms.join(document_id, connection, identity);
+    let outbox = session.outbox.clone();
+    let relay = tokio::spawn(async move {
+        loop {
+            match room_rx.recv().await {
+                Ok(event) if event.from == connection => {}
+                Ok(event) => {
+                    if outbox.send(event.message).is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    // The client has lost edits and must reload the document
+                    let resync = json!({"type": "resync", "missed": missed});
+                    if outbox.send(resync.to_string()).is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    session.membership = Some(Membership {
+        document_id: document_id.to_string(),
+        relay,
+    });
+
+    Ok(json!({
+        "type": "joined",
+        "document_id": document_id,
+        "members": present.len(),
+        "present": present,
+        "versions": versions,
+    }))
+}
+
+/// Record the node a member is viewing or editing, for the rest of the
+/// room to see.
+fn cursor(rooms: &Rooms, session: &Session, msg: &Value) -> Result<(), String> {
+    let connection = session.connection;
+    let document_id = &session
+        .membership
+        .as_ref()
+        .ok_or_else(|| "join a document before sharing a cursor".to_string())?
+        .document_id;
+    let node_id = msg["node_id"].as_str().map(String::from);
+    let mode = match msg["mode"].as_str() {
+        Some(mode) if CURSOR_MODES.contains(&mode) => Some(mode.to_string()),
+        Some(mode) => return Err(format!("unknown cursor mode: {}", mode)),
+        None if node_id.is_some() => Some("viewing".to_string()),
+        None => None,
+    };
+    rooms
+        .cursor(document_id, connection, node_id, mode)
+        .map(|_| ())
+        .ok_or_else(|| "not in the room".to_string())
+}
+
+/// Apply a room member's operation if it is to a node of the room's
+/// document and, for an existing node, `base_ts` is still current.
+async fn room_op(
+    pool: &Pool,
+    rooms: &Rooms,
+    session: &Session,
+    msg: &Value,
+) -> Result<Value, String> {
+    let connection = session.connection;
+    let document_id = &session
+        .membership
+        .as_ref()
+        .ok_or_else(|| "join a document before editing".to_string())?
+        .document_id;
+    let op_type = msg["op_type"]
+        .as_str()
+        .ok_or_else(|| "missing op_type".to_string())?;
+    if !ROOM_OPS.contains(&op_type) {
+        return Err(format!("{} is not a room operation", op_type));
+    }
+    let payload = msg.get("payload").cloned().unwrap_or_else(|| json!({}));
+    let node_id = msg["node_id"].as_str();
+    let base_ts = msg["base_ts"].as_i64();
+
+    // Nodes that must lie in the document: the edited node, and the parent
+    // an insert or move puts it under
+    let mut anchors: Vec<String> = Vec::new();
+    match node_id {
+        Some(id) => anchors.push(id.to_string()),
+        None if op_type != "insert_node" => return Err("missing node_id".to_string()),
+        None => {}
+    }
+    let parent_key = if op_type == "move_node" {
+        "new_parent_id"
+    } else {
+        "parent_id"
+    };
+    match payload[parent_key].as_str() {
+        Some(parent) => anchors.push(parent.to_string()),
+        None if op_type == "insert_node" => return Err("missing parent_id".to_string()),
+        None => {}
+    }
+    if node_id.is_some() && base_ts.is_none() {
+        return Err("missing base_ts".to_string());
+    }
+
+    let sql = "WITH RECURSIVE up AS (
+            SELECT id AS start, id, parent_id FROM kerai.nodes
+            WHERE id = ANY($2::text[]::uuid[])
+            UNION ALL
+            SELECT up.start, n.id, n.parent_id FROM kerai.nodes n
+            JOIN up ON n.id = up.parent_id
+        ), state AS (
+            SELECT (SELECT count(DISTINCT start) FROM up WHERE id = $1::text::uuid)
+                       = cardinality($2::text[]) AS in_document,
+                   COALESCE((
+                       SELECT max(lamport_ts) FROM kerai.operations
+                       WHERE node_id = $3::text::uuid
+                   ), 0) AS current_ts
+        )
+        SELECT CASE
+            WHEN NOT in_document THEN
+                jsonb_build_object('rejected', 'outside_document')
+            WHEN $3::text IS NOT NULL AND current_ts > $4::bigint THEN
+                jsonb_build_object('rejected', 'stale', 'current_ts', current_ts)
+            ELSE kerai.apply_op($5, $3::text::uuid, $6::jsonb)
+        END
+        FROM state";
+
+    let mut client = pool.get().await.map_err(|e| e.to_string())?;
+    let row = txn::serializable_one(
+        &mut client,
+        "room_op",
+        sql,
+        &[
+            document_id,
+            &anchors,
+            &node_id,
+            &base_ts,
+            &op_type,
+            &payload,
+        ],
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let result: Value = row.get(0);
+
+    match result["rejected"].as_str() {
+        Some("stale") => {
+            return Ok(json!({
+                "type": "rejected",
+                "ref": msg.get("ref"),
+                "reason": "stale",
+                "node_id": node_id,
+                "current_ts": result["current_ts"],
+            }));
+        }
+        Some(_) => return Err("node is not in this document".to_string()),
+        None => {}
+    }
+
+    let event = json!({
+        "type": "op",
+        "document_id": document_id,
+        "op_type": op_type,
+        "node_id": result["node_id"],
+        "payload": payload,
+        "lamport_ts": result["lamport_ts"],
+        "author": result["author"],
+    });
+    rooms.publish(
+        document_id,
+        RoomEvent {
+            from: connection,
+            message: event.to_string(),
+        },
+    );
+
+    Ok(json!({
+        "type": "ack",
+        "ref": msg.get("ref"),
+        "op_type": op_type,
+        "node_id": result["node_id"],
+        "lamport_ts": result["lamport_ts"],
+    }))
+}
@@ -1,5 +1,5 @@
 use axum::extract::State;
-use axum::http::{HeaderMap, StatusCode};
+use axum::http::HeaderMap;
 use axum::Json;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -8,6 +8,8 @@ use uuid::Uuid;
 use crate::lang::ptr::Ptr;
 use crate::serve::auth;
 use crate::serve::db::Pool;
+use crate::serve::error::ApiError;
+use crate::serve::validate::ValidJson;
 
 #[derive(Serialize)]
 pub struct WorkspaceEntry {
@@ -29,23 +31,20 @@ pub struct ConnectionsResponse {
 pub async fn connections(
     State(pool): State<Arc<Pool>>,
     headers: HeaderMap,
-) -> Result<Json<ConnectionsResponse>, (StatusCode, String)> {
+) -> Result<Json<ConnectionsResponse>, ApiError> {
     let token = auth::extract_session_token(&headers)
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "no session".into()))?;
+        .ok_or_else(|| ApiError::unauthorized("no session"))?;
 
     let (user_id, workspace_id) = auth::resolve_session(&pool, &token)
         .await
-        .map_err(|e| (StatusCode::UNAUTHORIZED, e))?;
+        .map_err(ApiError::unauthorized)?;
 
-    let client = pool.get().await.map_err(|e| {
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let client = pool.get().await?;
 
     // Get handle
     let handle: String = client
         .query_one("SELECT COALESCE(handle, 'anonymous') FROM kerai.users WHERE id = $1", &[&user_id])
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .await?
         .get(0);
 
     // Get workspaces with item counts (same query as eval.rs workspace_list_request)
@@ -58,8 +57,7 @@ pub async fn connections(
              ORDER BY w.updated_at DESC",
             &[&user_id],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     let workspaces: Vec<WorkspaceEntry> = rows
         .iter()
@@ -94,19 +92,18 @@ pub struct SwitchResponse {
 /// POST /api/workspace/switch — switches active workspace by UUID.
 pub async fn switch_workspace(
     State(pool): State<Arc<Pool>>,
-    Json(req): Json<SwitchRequest>,
-) -> Result<Json<SwitchResponse>, (StatusCode, String)> {
+    ValidJson(req): ValidJson<SwitchRequest>,
+) -> Result<Json<SwitchResponse>, ApiError> {
     let (user_id, old_workspace_id) = auth::resolve_session(&pool, &req.session_token)
         .await
-        .map_err(|e| (StatusCode::UNAUTHORIZED, e))?;
+        .map_err(ApiError::unauthorized)?;
 
-    let ws_id: Uuid = req.workspace_id.parse().map_err(|_| {
-        (StatusCode::BAD_REQUEST, "invalid workspace_id".into())
-    })?;
+    let ws_id: Uuid = req
+        .workspace_id
+        .parse()
+        .map_err(|_| ApiError::bad_request("invalid workspace_id"))?;
 
-    let client = pool.get().await.map_err(|e| {
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let client = pool.get().await?;
 
     // Verify workspace belongs to user
     let ws_row = client
@@ -114,9 +111,8 @@ pub async fn switch_workspace(
             "SELECT name FROM kerai.workspaces WHERE id = $1 AND user_id = $2",
             &[&ws_id, &user_id],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or_else(|| (StatusCode::NOT_FOUND, "workspace not found".into()))?;
+        .await?
+        .ok_or_else(|| ApiError::not_found("workspace not found"))?;
 
     let workspace_name: String = ws_row.get(0);
 
@@ -126,16 +122,14 @@ pub async fn switch_workspace(
             "UPDATE kerai.workspaces SET is_active = false WHERE user_id = $1 AND is_active = true",
             &[&user_id],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     client
         .execute(
             "UPDATE kerai.workspaces SET is_active = true, updated_at = now() WHERE id = $1",
             &[&ws_id],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     // Update session to point to new workspace
     client
@@ -143,8 +137,7 @@ pub async fn switch_workspace(
             "UPDATE kerai.sessions SET workspace_id = $1 WHERE user_id = $2 AND workspace_id = $3",
             &[&ws_id, &user_id, &old_workspace_id],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     // Load new stack
     let rows = client
@@ -155,8 +148,7 @@ pub async fn switch_workspace(
              ORDER BY position ASC",
             &[&ws_id],
         )
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     let stack: Vec<Ptr> = rows
         .iter()
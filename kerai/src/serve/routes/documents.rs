@@ -1,10 +1,31 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde::Deserialize;
 use serde_json::Value;
 use std::sync::Arc;
 
-use super::super::db::Pool;
+use super::super::db::{self, Pool};
+use super::super::error::ApiError;
+use super::super::page::{self, Page};
+use super::super::stream;
+use super::super::validate::ValidJson;
+use crate::txn;
+
+#[derive(Deserialize)]
+pub struct ReadParams {
+    /// Read as if this staged changeset were applied
+    pub changeset: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TreeParams {
+    /// Stream the nodes as NDJSON, one per line
+    pub stream: Option<bool>,
+    /// Read as if this staged changeset were applied
+    pub changeset: Option<String>,
+}
 
 #[derive(Deserialize)]
 pub struct ParseMarkdownRequest {
@@ -15,11 +36,9 @@ pub struct ParseMarkdownRequest {
 /// POST /api/documents — parse markdown into kerai nodes
 pub async fn create_document(
     State(pool): State<Arc<Pool>>,
-    Json(req): Json<ParseMarkdownRequest>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    ValidJson(req): ValidJson<ParseMarkdownRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
 
     let sql = format!(
         "SELECT kerai.parse_markdown('{}', '{}')",
@@ -27,95 +46,353 @@ pub async fn create_document(
         req.filename.replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = txn::serializable_one(&mut client, "parse_markdown", &sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
 }
 
-/// GET /api/documents — list document nodes
+/// GET /api/documents — list document nodes, newest first, a page at a
+/// time (`limit`, `order`, `cursor`, `language`; see [`Page`]), within the
+/// `X-Kerai-View` view when one is given and as if `changeset` were applied
 pub async fn list_documents(
     State(pool): State<Arc<Pool>>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    headers: HeaderMap,
+    page: Page,
+    Query(params): Query<ReadParams>,
+) -> Result<Response, ApiError> {
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
+    db::apply_view(&client, &headers).await?;
 
-    let sql = "SELECT COALESCE(jsonb_agg(jsonb_build_object(
-        'id', id,
-        'content', content,
-        'metadata', metadata,
-        'created_at', created_at
-    ) ORDER BY created_at DESC), '[]'::jsonb)
-    FROM kerai.nodes WHERE kind = 'document'";
+    // stale_links: doc links under the document past kerai.stale_doc_days
+    let sql = format!(
+        "SELECT jsonb_build_object(
+            'id', d.id,
+            'content', d.content,
+            'metadata', d.metadata,
+            'language', d.language,
+            'created_at', d.created_at,
+            'modified_at', d.modified_at,
+            'render', kerai.render_hints(d.kind, d.language),
+            'stale_links', (
+                SELECT count(*) FROM kerai.doc_staleness s
+                JOIN kerai.nodes n ON n.id = s.doc_id
+                WHERE n.path <@ d.path AND s.over_threshold
+            )
+        ), {}
+        FROM kerai.nodes d
+        WHERE d.kind = 'document' AND d.deleted_at IS NULL AND kerai.in_view(d.path, d.kind)
+          AND ($1::text IS NULL OR d.language = $1) AND {}
+        {}",
+        page::cursor_sql("d.created_at", "d.id"),
+        page.after("d.created_at", "d.id", 2),
+        page.order_by("d.created_at", "d.id"),
+    );
 
-    let row = client.query_one(sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let (micros, id) = page.cursor_args();
+    let rows = client.query(&sql, &[&page.language, &micros, &id]).await?;
 
-    let result: Value = row.get(0);
-    Ok(Json(result))
+    Ok(page.respond(rows.iter().map(|r| (r.get(0), r.get(1))).collect()))
 }
 
-/// GET /api/documents/:id/tree — get recursive document tree
+/// GET /api/documents/:id/tree — get recursive document tree; empty when
+/// the document is outside the `X-Kerai-View` view. With `stream=true` the
+/// nodes are written as NDJSON, one per line, as they are read; with
+/// `changeset` the tree is read as if that changeset were applied.
 pub async fn document_tree(
     State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
     Path(doc_id): Path<String>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    Query(params): Query<TreeParams>,
+) -> Result<Response, ApiError> {
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
+    db::apply_view(&client, &headers).await?;
 
     // Recursive CTE to get the full tree
+    let streamed = params.stream.unwrap_or(false);
     let sql = format!(
         "WITH RECURSIVE tree AS (
-            SELECT id, kind, content, parent_id, position, metadata, 0 AS depth
-            FROM kerai.nodes WHERE id = '{}'::uuid
+            SELECT id, kind, language, content, parent_id, position, metadata, 0 AS depth
+            FROM kerai.nodes WHERE id = '{}'::uuid AND deleted_at IS NULL AND kerai.in_view(path, kind)
             UNION ALL
-            SELECT n.id, n.kind, n.content, n.parent_id, n.position, n.metadata, t.depth + 1
+            SELECT n.id, n.kind, n.language, n.content, n.parent_id, n.position, n.metadata,
+                t.depth + 1
             FROM kerai.nodes n
             JOIN tree t ON n.parent_id = t.id
+            WHERE n.deleted_at IS NULL
+        ), nodes AS (
+            SELECT depth, position, jsonb_build_object(
+                'id', id,
+                'kind', kind,
+                'content', content,
+                'parent_id', parent_id,
+                'position', position,
+                'metadata', metadata,
+                'depth', depth,
+                'render', kerai.render_hints(kind, language),
+                -- Worst lag behind linked code, for doc nodes with such links
+                'staleness', (
+                    SELECT jsonb_build_object(
+                        'score', round(max(s.score)::numeric, 3),
+                        'gap_days', round(max(s.gap_days)::numeric, 1),
+                        'stale', bool_or(s.over_threshold)
+                    )
+                    FROM kerai.doc_staleness s
+                    WHERE s.doc_id = tree.id
+                    HAVING count(*) > 0
+                ),
+                -- Attached summaries and whether the subtree changed since
+                'summaries', (
+                    SELECT jsonb_agg(jsonb_build_object(
+                        'id', s.id,
+                        'method', s.metadata->>'method',
+                        'content', s.content,
+                        'stale', COALESCE((s.metadata->>'stale')::boolean, false),
+                        'summarized_at', s.metadata->>'summarized_at'
+                    ))
+                    FROM kerai.edges e
+                    JOIN kerai.nodes s ON s.id = e.source_id
+                    WHERE e.target_id = tree.id AND e.relation = 'summarizes'
+                )
+            ) AS node
+            FROM tree
         )
-        SELECT COALESCE(jsonb_agg(jsonb_build_object(
-            'id', id,
-            'kind', kind,
-            'content', content,
-            'parent_id', parent_id,
-            'position', position,
-            'metadata', metadata,
-            'depth', depth
-        ) ORDER BY depth, position), '[]'::jsonb)
-        FROM tree",
+        {}",
         doc_id.replace('\'', "''"),
+        if streamed {
+            "SELECT node FROM nodes ORDER BY depth, position"
+        } else {
+            "SELECT COALESCE(jsonb_agg(node ORDER BY depth, position), '[]'::jsonb) FROM nodes"
+        },
     );
+    if streamed {
+        return stream::ndjson(client, &sql, &[]).await;
+    }
+
+    let row = client.query_one(&sql, &[]).await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result).into_response())
+}
+
+/// GET /api/documents/:id/crossrefs — the `\label`/`\ref` graph of a
+/// LaTeX file and the files it includes, for an outline with backlinks.
+///
+/// `labels` lists each `latex_label` with the element it names
+/// (`kind` section, figure, table, equation, theorem, definition or
+/// other; `title` the section title or caption) and `backlinks`, the refs
+/// pointing at it. `refs` lists each `latex_ref` with its enclosing
+/// section and the `label_id` it resolves to: through its `references`
+/// edge, or by key when the label is in another file of the project;
+/// `unresolved` counts the refs that resolve to nothing. Empty when the
+/// file is outside the `X-Kerai-View` view; read as if `changeset` were
+/// applied when one is given.
+pub async fn document_crossrefs(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    Path(doc_id): Path<String>,
+    Query(params): Query<ReadParams>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
+    db::apply_view(&client, &headers).await?;
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let sql = "WITH RECURSIVE files AS (
+        SELECT n.id FROM kerai.nodes n
+        WHERE n.id = $1::text::uuid AND n.deleted_at IS NULL AND kerai.in_view(n.path, n.kind)
+        UNION
+        SELECT e.target_id FROM kerai.edges e
+        JOIN files f ON e.source_id = f.id
+        WHERE e.relation = 'includes'
+    ), tree AS (
+        SELECT n.id, n.kind, n.content, n.parent_id, n.metadata, n.id AS file_id,
+            NULL::uuid AS section_id
+        FROM kerai.nodes n JOIN files f ON f.id = n.id
+        UNION ALL
+        SELECT n.id, n.kind, n.content, n.parent_id, n.metadata, t.file_id,
+            CASE WHEN t.kind IN ('latex_part', 'latex_chapter', 'latex_section',
+                'latex_subsection', 'latex_subsubsection', 'latex_paragraph')
+            THEN t.id ELSE t.section_id END
+        FROM kerai.nodes n JOIN tree t ON n.parent_id = t.id
+        WHERE n.deleted_at IS NULL
+    ), located AS (
+        SELECT tree.*, (SELECT f.content FROM kerai.nodes f WHERE f.id = tree.file_id) AS file,
+            COALESCE((metadata->>'start_line')::int, (metadata->>'line')::int) AS line
+        FROM tree
+    ), labels AS (
+        SELECT l.id, l.content AS key, l.file, l.line, t.id AS target_id, t.kind AS target_kind,
+            k.kind,
+            CASE WHEN k.kind = 'section' THEN t.content
+                ELSE (SELECT c.content FROM tree c
+                      WHERE c.parent_id = t.id AND c.kind = 'latex_caption' LIMIT 1)
+            END AS title
+        FROM located l
+        JOIN tree t ON t.id = l.parent_id
+        CROSS JOIN LATERAL (SELECT CASE
+            WHEN t.kind IN ('latex_part', 'latex_chapter', 'latex_section',
+                'latex_subsection', 'latex_subsubsection', 'latex_paragraph') THEN 'section'
+            WHEN t.kind = 'latex_figure' THEN 'figure'
+            WHEN t.kind = 'latex_table' THEN 'table'
+            WHEN t.kind IN ('latex_math_env', 'latex_display_math') THEN 'equation'
+            WHEN t.kind = 'latex_theorem' THEN 'theorem'
+            WHEN t.kind = 'latex_definition' THEN 'definition'
+            ELSE 'other'
+        END AS kind) k
+        WHERE l.kind = 'latex_label'
+    ), refs AS (
+        SELECT r.id, r.content AS key, r.metadata->>'command' AS command, r.file, r.line,
+            r.section_id,
+            COALESCE(
+                (SELECT lb.id FROM kerai.edges e JOIN labels lb ON lb.id = e.target_id
+                 WHERE e.source_id = r.id AND e.relation = 'references' LIMIT 1),
+                -- A ref to a label in another file of the project
+                (SELECT min(lb.id::text)::uuid FROM labels lb WHERE lb.key = r.content
+                 HAVING count(*) = 1)
+            ) AS label_id
+        FROM located r
+        WHERE r.kind = 'latex_ref'
+    )
+    SELECT jsonb_build_object(
+        'labels', COALESCE((
+            SELECT jsonb_agg(jsonb_build_object(
+                'id', l.id,
+                'key', l.key,
+                'kind', l.kind,
+                'target_id', l.target_id,
+                'target_kind', l.target_kind,
+                'title', l.title,
+                'file', l.file,
+                'line', l.line,
+                'backlinks', (SELECT COALESCE(jsonb_agg(r.id ORDER BY r.file, r.line), '[]'::jsonb)
+                              FROM refs r WHERE r.label_id = l.id)
+            ) ORDER BY l.file, l.line)
+            FROM labels l
+        ), '[]'::jsonb),
+        'refs', COALESCE((
+            SELECT jsonb_agg(jsonb_build_object(
+                'id', r.id,
+                'key', r.key,
+                'command', r.command,
+                'section_id', r.section_id,
+                'file', r.file,
+                'line', r.line,
+                'label_id', r.label_id
+            ) ORDER BY r.file, r.line)
+            FROM refs r
+        ), '[]'::jsonb),
+        'unresolved', (SELECT count(*) FROM refs WHERE label_id IS NULL)
+    )";
+
+    let row = client.query_one(sql, &[&doc_id]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
 }
 
-/// GET /api/documents/:id/markdown — reconstruct markdown from nodes
+/// GET /api/documents/:id/backlinks — the documents that link to a
+/// document or to any node in it, such as a heading.
+///
+/// Each entry is a linking document (`id`, `content`, `path`) with its
+/// `links`: the node each `links_to` edge leaves from and the node it
+/// reaches, with the edge metadata as `link` (`wikilink` or `url`,
+/// `heading` or `fragment`). Links from inside the document itself are
+/// left out. Empty when the document is outside the `X-Kerai-View` view;
+/// read as if `changeset` were applied when one is given.
+pub async fn document_backlinks(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    Path(doc_id): Path<String>,
+    Query(params): Query<ReadParams>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
+    db::apply_view(&client, &headers).await?;
+
+    let sql = "WITH RECURSIVE sub AS (
+        SELECT n.id FROM kerai.nodes n
+        WHERE n.id = $1::text::uuid AND n.deleted_at IS NULL AND kerai.in_view(n.path, n.kind)
+        UNION ALL
+        SELECT n.id FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+        WHERE n.deleted_at IS NULL
+    ), incoming AS (
+        SELECT e.source_id, e.target_id, e.metadata
+        FROM kerai.edges e JOIN sub ON sub.id = e.target_id
+        WHERE e.relation = 'links_to'
+          AND NOT EXISTS (SELECT 1 FROM sub s WHERE s.id = e.source_id)
+    ), up AS (
+        -- Walk up from each linking node to its document
+        SELECT i.source_id AS start, n.id, n.kind, n.parent_id
+        FROM (SELECT DISTINCT source_id FROM incoming) i
+        JOIN kerai.nodes n ON n.id = i.source_id
+        UNION ALL
+        SELECT up.start, n.id, n.kind, n.parent_id
+        FROM up JOIN kerai.nodes n ON n.id = up.parent_id
+        WHERE up.kind <> 'document'
+    ), linking AS (
+        SELECT d.id, d.content, d.path, jsonb_agg(jsonb_build_object(
+            'source_id', i.source_id,
+            'target_id', i.target_id,
+            'target_kind', t.kind,
+            'target_content', t.content,
+            'link', i.metadata
+        ) ORDER BY t.position, i.target_id) AS links
+        FROM incoming i
+        JOIN up ON up.start = i.source_id AND up.kind = 'document'
+        JOIN kerai.nodes d ON d.id = up.id
+        JOIN kerai.nodes t ON t.id = i.target_id
+        WHERE kerai.in_view(d.path, d.kind)
+        GROUP BY d.id, d.content, d.path
+    )
+    SELECT COALESCE(jsonb_agg(jsonb_build_object(
+        'id', id,
+        'content', content,
+        'path', path::text,
+        'links', links
+    ) ORDER BY content, id), '[]'::jsonb)
+    FROM linking";
+
+    let row = client.query_one(sql, &[&doc_id]).await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// GET /api/documents/:id/markdown — reconstruct markdown from nodes, as
+/// if `changeset` were applied when one is given
 pub async fn document_markdown(
     State(pool): State<Arc<Pool>>,
     Path(doc_id): Path<String>,
-) -> Result<String, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    Query(params): Query<ReadParams>,
+) -> Result<String, ApiError> {
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
 
     let sql = format!(
         "SELECT kerai.reconstruct_markdown('{}'::uuid)",
         doc_id.replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
+
+    let result: String = row.get(0);
+    Ok(result)
+}
+
+/// GET /api/documents/:id/latex — reconstruct a LaTeX file from nodes, as
+/// if `changeset` were applied when one is given
+pub async fn document_latex(
+    State(pool): State<Arc<Pool>>,
+    Path(file_id): Path<String>,
+    Query(params): Query<ReadParams>,
+) -> Result<String, ApiError> {
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
+
+    let row = client
+        .query_one("SELECT kerai.reconstruct_latex($1::text::uuid)", &[&file_id])
+        .await?;
 
     let result: String = row.get(0);
     Ok(result)
@@ -0,0 +1,31 @@
+use axum::extract::{Query, State};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::super::db::Pool;
+use super::super::error::ApiError;
+
+#[derive(Deserialize)]
+pub struct EconomyParams {
+    /// day, week, month (default), year or all
+    pub period: Option<String>,
+}
+
+/// GET /api/economy — ledger activity for the period, this instance merged
+/// with the summaries peers last sent (see `kerai economy --federation`)
+pub async fn economy(
+    State(pool): State<Arc<Pool>>,
+    Query(params): Query<EconomyParams>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+
+    let period = params.period.as_deref().unwrap_or("month");
+    let row = client
+        .query_one("SELECT kerai.federation_economy_report($1)", &[&period])
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
@@ -0,0 +1,39 @@
+use axum::extract::State;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use super::super::db::Pool;
+use super::super::error::ApiError;
+use super::super::validate::ValidJson;
+use crate::txn;
+
+#[derive(Deserialize)]
+pub struct EdgeBatchRequest {
+    #[serde(default)]
+    pub add: Vec<Value>,
+    #[serde(default)]
+    pub remove: Vec<Value>,
+}
+
+/// POST /api/edges/batch — add and remove hand-made edges in one
+/// transaction. Each edge is `{source_id, target_id, relation, metadata?}`;
+/// removals go first, and any edge the relation registry rejects fails the
+/// whole batch. Returns `{added, existing, removed, missing}`.
+pub async fn batch(
+    State(pool): State<Arc<Pool>>,
+    ValidJson(req): ValidJson<EdgeBatchRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
+
+    let sql = "SELECT r.result || a.result
+        FROM (SELECT kerai.remove_edges($2::jsonb) AS result) r,
+        LATERAL (SELECT kerai.add_edges($1::jsonb) AS result) a";
+    let (add, remove) = (json!(req.add), json!(req.remove));
+
+    let row = txn::serializable_one(&mut client, "edge_batch", sql, &[&add, &remove]).await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
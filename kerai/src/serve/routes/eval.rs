@@ -3,20 +3,31 @@ use axum::http::StatusCode;
 use axum::response::{Html, IntoResponse};
 use axum::Json;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use crate::lang::handlers;
 use crate::lang::machine::Machine;
 use crate::lang::ptr::Ptr;
 use crate::serve::auth;
+use crate::serve::bundle;
 use crate::serve::db::Pool;
 use crate::serve::oauth::{self, OAuthConfig};
+use crate::serve::query;
+use crate::serve::stack_sync;
+use crate::serve::time;
 
 #[derive(Deserialize)]
 pub struct EvalRequest {
     input: String,
     #[serde(default)]
     session_token: String,
+    /// Values for `${VAR}` references in `input`.
+    #[serde(default)]
+    env: HashMap<String, String>,
+    /// Per-page id, echoed in stack deltas so a terminal skips its own.
+    #[serde(default)]
+    client_id: String,
 }
 
 #[derive(Serialize)]
@@ -44,13 +55,34 @@ pub async fn eval(
         }
     };
 
+    let role = match auth::resolve_role(&pool, user_id).await {
+        Ok(role) => role,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(EvalResponse {
+                    stack: vec![],
+                    error: Some(format!("failed to resolve role: {e}")),
+                }),
+            );
+        }
+    };
+
     // Build machine with handlers
-    let (handler_map, type_methods, help) = handlers::register_all();
-    let mut machine = Machine::new(workspace_id, user_id, handler_map, type_methods, help);
+    let (handler_map, type_methods, help, roles) = handlers::register_all();
+    let mut machine = Machine::new(
+        workspace_id,
+        user_id,
+        role,
+        handler_map,
+        type_methods,
+        help,
+        roles,
+    );
 
     // Load current stack from DB
-    match load_stack(&pool, workspace_id).await {
-        Ok(stack) => machine.stack = stack,
+    let before = match load_stack(&pool, workspace_id).await {
+        Ok(stack) => stack,
         Err(e) => {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -60,14 +92,39 @@ pub async fn eval(
                 }),
             );
         }
+    };
+    machine.stack = before.clone();
+
+    // Load the workspace's user words
+    match bundle::load_words(&pool, workspace_id).await {
+        Ok(words) => machine.words = words,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(EvalResponse {
+                    stack: vec![],
+                    error: Some(format!("failed to load words: {e}")),
+                }),
+            );
+        }
     }
 
     // Execute input
-    let exec_error = match machine.execute(&req.input) {
+    let exec_error = match machine.execute_with_env(&req.input, &req.env) {
         Ok(()) => None,
         Err(e) => Some(e),
     };
 
+    // Audit words refused for lack of role; a logging failure must not block the eval
+    if let Err(e) = record_denials(&pool, &machine).await {
+        tracing::error!("failed to record permission denials: {e}");
+    }
+
+    // Store words defined by this input with the workspace
+    if let Err(e) = bundle::save_words(&pool, machine.workspace_id, &machine.defined).await {
+        machine.push(Ptr::error(&format!("failed to save words: {e}")));
+    }
+
     // Process any request markers left on the stack by handlers
     resolve_requests(&mut machine, &pool).await;
 
@@ -84,7 +141,10 @@ pub async fn eval(
 
     // Reload to get stable rowids
     match load_stack(&pool, machine.workspace_id).await {
-        Ok(stack) => machine.stack = stack,
+        Ok(stack) => {
+            machine.stack = stack;
+            publish_delta(&pool, &machine, &req, &before).await;
+        }
         Err(_) => {} // Best effort
     }
 
@@ -123,7 +183,54 @@ async fn load_stack(pool: &Pool, workspace_id: uuid::Uuid) -> Result<Vec<Ptr>, S
         .collect())
 }
 
+/// Write one audit row per word the machine refused for lack of role.
+async fn record_denials(pool: &Pool, machine: &Machine) -> Result<(), String> {
+    if machine.denied.is_empty() {
+        return Ok(());
+    }
+    let client = pool.get().await.map_err(|e| e.to_string())?;
+    for denial in &machine.denied {
+        let detail = serde_json::json!({
+            "required": denial.required.as_str(),
+            "role": machine.role.as_str(),
+        });
+        client
+            .execute(
+                "INSERT INTO kerai.audit_log (user_id, workspace_id, action, word, detail) \
+                 VALUES ($1, $2, 'permission_denied', $3, $4)",
+                &[&machine.user_id, &machine.workspace_id, &denial.word, &detail],
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Broadcast what this eval changed to other members viewing the workspace.
+async fn publish_delta(pool: &Pool, machine: &Machine, req: &EvalRequest, before: &[Ptr]) {
+    let Some(delta) = stack_sync::delta(before, &machine.stack) else {
+        return;
+    };
+    let payload = stack_sync::payload(
+        machine.workspace_id,
+        machine.user_id,
+        &req.client_id,
+        &req.input,
+        delta,
+    );
+    let result = match pool.get().await {
+        Ok(client) => stack_sync::broadcast(&client, &payload).await,
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = result {
+        tracing::warn!("failed to broadcast stack delta: {e}");
+    }
+}
+
 /// Save stack items to the database (full replacement).
+///
+/// Items keep their ids so members' views can reconcile stack deltas; new
+/// items and copies of an item (e.g. from `dup`) get fresh ids.
 async fn save_stack(pool: &Pool, workspace_id: uuid::Uuid, stack: &[Ptr]) -> Result<(), String> {
     let client = pool.get().await.map_err(|e| e.to_string())?;
 
@@ -137,13 +244,16 @@ async fn save_stack(pool: &Pool, workspace_id: uuid::Uuid, stack: &[Ptr]) -> Res
         .map_err(|e| e.to_string())?;
 
     // Re-insert with correct positions
+    let mut seen = HashSet::new();
     for (pos, ptr) in stack.iter().enumerate() {
         let pos_i32 = pos as i32;
+        let id = (ptr.id > 0 && seen.insert(ptr.id)).then_some(ptr.id);
         client
             .execute(
-                "INSERT INTO kerai.stack_items (workspace_id, position, kind, ref_id, meta) \
-                 VALUES ($1, $2, $3, $4, $5)",
+                "INSERT INTO kerai.stack_items (id, workspace_id, position, kind, ref_id, meta) \
+                 VALUES (COALESCE($1, nextval('kerai.stack_item_id_seq')), $2, $3, $4, $5, $6)",
                 &[
+                    &id,
                     &workspace_id,
                     &pos_i32,
                     &ptr.kind,
@@ -160,7 +270,7 @@ async fn save_stack(pool: &Pool, workspace_id: uuid::Uuid, stack: &[Ptr]) -> Res
 
 /// Resolve request markers left on the stack by handlers.
 async fn resolve_requests(machine: &mut Machine, pool: &Pool) {
-    let client = match pool.get().await {
+    let mut client = match pool.get().await {
         Ok(c) => c,
         Err(_) => return,
     };
@@ -336,6 +446,58 @@ async fn resolve_requests(machine: &mut Machine, pool: &Pool) {
                     }
                 }
             }
+            "workspace_export_request" => {
+                match bundle::export_workspace(&client, machine.workspace_id).await {
+                    Ok(b) => {
+                        let name = b["name"].as_str().unwrap_or_default().to_string();
+                        machine.stack[i] = Ptr {
+                            kind: "workspace_bundle".into(),
+                            ref_id: name,
+                            meta: b,
+                            id: 0,
+                        };
+                    }
+                    Err(e) => {
+                        machine.stack[i] = Ptr::error(&format!("workspace export failed: {e}"));
+                    }
+                }
+            }
+            "workspace_import_request" => {
+                let b = machine.stack[i].meta.clone();
+                match bundle::import_workspace(&mut client, machine.user_id, &b).await {
+                    Ok(result) => {
+                        machine.stack[i] = Ptr::success(&result.summary());
+                    }
+                    Err(e) => {
+                        machine.stack[i] = Ptr::error(&format!("workspace import failed: {e}"));
+                    }
+                }
+            }
+            "time_request" => {
+                let expr = machine.stack[i].meta.clone();
+                machine.stack[i] = match time::resolve(&client, &expr).await {
+                    Ok(ptr) => ptr,
+                    Err(e) => Ptr::error(&format!("{}: {e}", machine.stack[i].ref_id)),
+                };
+            }
+            "query_request" => {
+                machine.stack[i] = match query::open(&client, &machine.stack[i]).await {
+                    Ok(ptr) => ptr,
+                    Err(e) => Ptr::error(&format!("query failed: {e}")),
+                };
+            }
+            "result" if machine.stack[i].meta["fetch"] == true => {
+                machine.stack[i] = match query::fetch_page(&client, &machine.stack[i]).await {
+                    Ok(ptr) => ptr,
+                    Err(e) => Ptr::error(&format!("page fetch failed: {e}")),
+                };
+            }
+            "explain_request" => {
+                machine.stack[i] = match query::explain(&client, &machine.stack[i]).await {
+                    Ok(ptr) => ptr,
+                    Err(e) => Ptr::error(&format!("explain failed: {e}")),
+                };
+            }
             "auth_pending_request" => {
                 // Load OAuth config from DB
                 let config_rows = client
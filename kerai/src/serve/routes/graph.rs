@@ -0,0 +1,46 @@
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::super::db::{self, Pool};
+use super::super::error::ApiError;
+
+#[derive(Deserialize)]
+pub struct GraphParams {
+    /// Subtree to graph; everything when empty
+    pub path: Option<String>,
+    /// Levels below `path` the clusters are at (default 1)
+    pub lod: Option<i32>,
+    /// Largest clusters kept (default 500)
+    pub max_nodes: Option<i32>,
+}
+
+/// GET /api/graph — the nodes under `path` collapsed to clusters `lod`
+/// levels down, with the edges between them aggregated (kerai.graph_lod).
+/// Expand a cluster by asking again with its `key` as `path`. An
+/// `X-Kerai-View` header limits the graph to that view.
+pub async fn graph(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    Query(params): Query<GraphParams>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+    db::apply_view(&client, &headers).await?;
+
+    let row = client
+        .query_one(
+            "SELECT kerai.graph_lod($1, $2, $3)",
+            &[
+                &params.path.unwrap_or_default(),
+                &params.lod.unwrap_or(1),
+                &params.max_nodes.unwrap_or(500),
+            ],
+        )
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
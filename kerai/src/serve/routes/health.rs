@@ -5,5 +5,6 @@ pub async fn health() -> Json<Value> {
     Json(json!({
         "status": "ok",
         "service": "kerai",
+        "transactions": crate::txn::stats(),
     }))
 }
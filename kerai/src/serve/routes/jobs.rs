@@ -0,0 +1,30 @@
+use axum::extract::{Query, State};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::super::db::Pool;
+use super::super::error::ApiError;
+
+#[derive(Deserialize)]
+pub struct SummaryParams {
+    /// Window in hours (default 24)
+    pub hours: Option<i32>,
+}
+
+/// GET /api/jobs/summary — throughput and failure rates per job kind
+pub async fn summary(
+    State(pool): State<Arc<Pool>>,
+    Query(params): Query<SummaryParams>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+
+    let hours = params.hours.unwrap_or(24);
+    let row = client
+        .query_one("SELECT kerai.jobs_summary($1)", &[&hours])
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
@@ -0,0 +1,34 @@
+use axum::extract::State;
+use axum::Json;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::super::db::Pool;
+use super::super::error::ApiError;
+
+/// GET /api/kinds — render hints for every registered kind and every kind
+/// present in nodes; unregistered kinds get the hints kerai.render_hints
+/// guesses for them.
+pub async fn list_kinds(
+    State(pool): State<Arc<Pool>>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+
+    let sql = "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+        'kind', k.kind,
+        'description', r.description,
+        'registered', r.kind IS NOT NULL,
+        'render_hints', kerai.render_hints(k.kind, NULL)
+    ) ORDER BY k.kind), '[]'::jsonb)
+    FROM (
+        SELECT kind FROM kerai.kinds
+        UNION
+        SELECT DISTINCT kind FROM kerai.nodes WHERE deleted_at IS NULL
+    ) k
+    LEFT JOIN kerai.kinds r ON r.kind = k.kind";
+
+    let row = client.query_one(sql, &[]).await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
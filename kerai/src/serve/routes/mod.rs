@@ -1,28 +1,57 @@
 pub mod connections;
 pub mod documents;
+pub mod economy;
+pub mod edges;
 pub mod eval;
+pub mod graph;
 pub mod health;
+pub mod jobs;
+pub mod kinds;
 pub mod models;
 pub mod nodes;
+pub mod notifications;
 pub mod perspectives;
+pub mod query;
+pub mod recordings;
 pub mod search;
 pub mod stack;
+pub mod subscriptions;
+pub mod suggestions;
+pub mod sync;
+pub mod timeline;
+pub mod tokens;
+pub mod workspaces;
 pub mod ws;
 
+use axum::middleware;
 use axum::routing::{delete, get, patch, post, put};
-use axum::Router;
+use axum::{Extension, Router};
 use std::sync::Arc;
 use tokio::sync::broadcast;
 
 use super::auth;
 use super::db::Pool;
+use super::notify::Notification;
+use super::rooms::Rooms;
+use super::validate::Limits;
 use ws::WsState;
 
-/// Build the application router with all API routes.
-pub fn build_router(pool: Arc<Pool>, notify_tx: broadcast::Sender<String>) -> Router {
+/// Build the application router with all API routes. JSON bodies are
+/// checked against `limits`; workspace import and sync push, which carry a
+/// whole batch, get [`Limits::bulk`]. An API token on an `/api` request is
+/// resolved by [`super::tokens::authenticate`]; routes keep their session
+/// checks, so the token only adds an identity.
+pub fn build_router(
+    pool: Arc<Pool>,
+    notify_tx: broadcast::Sender<Notification>,
+    record_sessions: bool,
+    limits: Limits,
+) -> Router {
     let ws_state = Arc::new(WsState {
         pool: pool.clone(),
         notify_tx,
+        record_sessions,
+        rooms: Rooms::default(),
     });
 
     let api = Router::new()
@@ -33,16 +62,49 @@ pub fn build_router(pool: Arc<Pool>, notify_tx: broadcast::Sender<String>) -> Ro
         .route("/nodes/{id}/content", patch(nodes::update_content))
         .route("/nodes/{id}/move", post(nodes::move_node))
         .route("/nodes/{id}", delete(nodes::delete_node))
+        .route("/nodes/{id}/split", post(nodes::split_node))
+        .route("/nodes/{id}/merge-with-next", post(nodes::merge_with_next))
+        .route("/nodes/{id}/promote", post(nodes::promote_heading))
+        .route("/nodes/{id}/demote", post(nodes::demote_heading))
+        .route("/nodes/{id}/impact", get(nodes::node_impact))
+        .route("/nodes/{id}/mentions", get(nodes::node_mentions))
+        .route("/nodes/{id}/source", get(nodes::node_source))
+        // Edges
+        .route("/edges/batch", post(edges::batch))
         // Documents
         .route("/documents", post(documents::create_document))
         .route("/documents", get(documents::list_documents))
         .route("/documents/{id}/tree", get(documents::document_tree))
         .route("/documents/{id}/markdown", get(documents::document_markdown))
+        .route("/documents/{id}/latex", get(documents::document_latex))
+        .route("/documents/{id}/crossrefs", get(documents::document_crossrefs))
+        .route("/documents/{id}/backlinks", get(documents::document_backlinks))
+        // Economy dashboard
+        .route("/economy", get(economy::economy))
+        // Graph
+        .route("/graph", get(graph::graph))
+        // Jobs
+        .route("/jobs/summary", get(jobs::summary))
+        // Activity timeline
+        .route("/timeline", get(timeline::timeline))
+        // Kinds
+        .route("/kinds", get(kinds::list_kinds))
         // Search
         .route("/search", get(search::search))
         .route("/suggest", get(search::suggest))
+        .route("/query/explain", post(query::explain))
+        // Subscriptions and digests
+        .route("/subscriptions", get(subscriptions::list_subscriptions))
+        .route("/subscriptions", post(subscriptions::subscribe))
+        .route("/subscriptions", delete(subscriptions::unsubscribe))
+        .route("/inbox", get(subscriptions::inbox))
+        // Suggestions review
+        .route("/suggestions", get(suggestions::list))
+        .route("/suggestions/{id}/accept", post(suggestions::accept))
+        .route("/suggestions/{id}/dismiss", post(suggestions::dismiss))
         // Perspectives
         .route("/perspectives", get(perspectives::get_perspectives))
+        .route("/perspectives/batch", post(perspectives::batch))
         .route("/consensus", get(perspectives::consensus))
         // Models
         .route("/models", post(models::create_model))
@@ -67,11 +129,36 @@ pub fn build_router(pool: Arc<Pool>, notify_tx: broadcast::Sender<String>) -> Ro
         // Connections
         .route("/connections", get(connections::connections))
         .route("/workspace/switch", post(connections::switch_workspace))
+        // Workspace bundles
+        .route("/workspace/export", get(workspaces::export_workspace))
+        .route(
+            "/workspace/import",
+            post(workspaces::import_workspace).layer(Extension(limits.bulk())),
+        )
+        // Peer sync (signed messages between instances, no session)
+        .route("/sync/pull", post(sync::pull))
+        .route(
+            "/sync/push",
+            post(sync::push).layer(Extension(limits.bulk())),
+        )
+        .route("/sync/economy", post(sync::economy))
+        // Session recordings (admin)
+        .route("/admin/recordings", get(recordings::list))
+        .route("/admin/recordings/{id}/replay", post(recordings::replay))
+        // API tokens (admin)
+        .route("/tokens", post(tokens::create))
+        .layer(Extension(limits))
+        .layer(middleware::from_fn_with_state(
+            pool.clone(),
+            super::tokens::authenticate,
+        ))
         .with_state(pool.clone());
 
     // WebSocket needs its own state
     let ws_router = Router::new()
         .route("/ws", get(ws::ws_handler))
+        .route("/documents/{id}/presence", get(ws::document_presence))
+        .route("/notifications/poll", get(notifications::poll))
         .with_state(ws_state);
 
     // Eval route (stack machine)
@@ -91,6 +178,7 @@ pub fn build_router(pool: Arc<Pool>, notify_tx: broadcast::Sender<String>) -> Ro
         .route("/bsky/start", post(auth::bsky_start))
         .route("/bsky/callback", get(auth::bsky_callback))
         .route("/logout", post(auth::logout))
+        .layer(Extension(limits))
         .with_state(pool);
 
     Router::new()
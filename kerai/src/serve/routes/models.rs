@@ -5,11 +5,13 @@ use serde_json::Value;
 use std::sync::Arc;
 
 use super::super::db::Pool;
+use super::super::error::ApiError;
+use super::super::validate::{self, ValidJson};
 
-type ApiResult = Result<Json<Value>, (axum::http::StatusCode, String)>;
+type ApiResult = Result<Json<Value>, ApiError>;
 
-fn internal_err(e: impl std::fmt::Display) -> (axum::http::StatusCode, String) {
-    (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+fn internal_err(e: impl std::fmt::Display) -> ApiError {
+    ApiError::internal(e.to_string())
 }
 
 #[derive(Deserialize)]
@@ -25,9 +27,12 @@ pub struct CreateModelBody {
 /// POST /api/models — create a new model
 pub async fn create_model(
     State(pool): State<Arc<Pool>>,
-    Json(body): Json<CreateModelBody>,
+    ValidJson(body): ValidJson<CreateModelBody>,
 ) -> ApiResult {
-    let client = pool.get().await.map_err(internal_err)?;
+    if let Some(scope) = &body.scope {
+        validate::ltree(scope)?;
+    }
+    let client = pool.get().await?;
     let sql = format!(
         "SELECT kerai.create_model('{}', {}, {}, {}, {}, {})::text",
         body.agent.replace('\'', "''"),
@@ -37,7 +42,7 @@ pub async fn create_model(
         body.context_len.map(|v| v.to_string()).unwrap_or("NULL".into()),
         body.scope.as_ref().map(|s| format!("'{}'", s.replace('\'', "''"))).unwrap_or("NULL".into()),
     );
-    let row = client.query_one(&sql, &[]).await.map_err(internal_err)?;
+    let row = client.query_one(&sql, &[]).await?;
     let text: String = row.get(0);
     let value: Value = serde_json::from_str(&text).map_err(internal_err)?;
     Ok(Json(value))
@@ -57,9 +62,12 @@ pub struct TrainModelBody {
 /// POST /api/models/train — train a model
 pub async fn train_model(
     State(pool): State<Arc<Pool>>,
-    Json(body): Json<TrainModelBody>,
+    ValidJson(body): ValidJson<TrainModelBody>,
 ) -> ApiResult {
-    let client = pool.get().await.map_err(internal_err)?;
+    if let Some(scope) = &body.scope {
+        validate::ltree(scope)?;
+    }
+    let client = pool.get().await?;
     let sql = format!(
         "SELECT kerai.train_model('{}', {}, {}, {}, {}, {}, {})::text",
         body.agent.replace('\'', "''"),
@@ -70,7 +78,7 @@ pub async fn train_model(
         body.scope.as_ref().map(|s| format!("'{}'", s.replace('\'', "''"))).unwrap_or("NULL".into()),
         body.perspective_agent.as_ref().map(|s| format!("'{}'", s.replace('\'', "''"))).unwrap_or("NULL".into()),
     );
-    let row = client.query_one(&sql, &[]).await.map_err(internal_err)?;
+    let row = client.query_one(&sql, &[]).await?;
     let text: String = row.get(0);
     let value: Value = serde_json::from_str(&text).map_err(internal_err)?;
     Ok(Json(value))
@@ -86,9 +94,9 @@ pub struct PredictBody {
 /// POST /api/models/predict — predict next nodes
 pub async fn predict_next(
     State(pool): State<Arc<Pool>>,
-    Json(body): Json<PredictBody>,
+    ValidJson(body): ValidJson<PredictBody>,
 ) -> ApiResult {
-    let client = pool.get().await.map_err(internal_err)?;
+    let client = pool.get().await?;
     let context_json = serde_json::json!(body.context).to_string();
     let sql = format!(
         "SELECT kerai.predict_next('{}', '{}'::jsonb, {})::text",
@@ -96,7 +104,7 @@ pub async fn predict_next(
         context_json.replace('\'', "''"),
         body.top_k.map(|v| v.to_string()).unwrap_or("NULL".into()),
     );
-    let row = client.query_one(&sql, &[]).await.map_err(internal_err)?;
+    let row = client.query_one(&sql, &[]).await?;
     let text: String = row.get(0);
     let value: Value = serde_json::from_str(&text).map_err(internal_err)?;
     Ok(Json(value))
@@ -114,14 +122,14 @@ pub async fn neural_search(
     State(pool): State<Arc<Pool>>,
     Query(params): Query<NeuralSearchParams>,
 ) -> ApiResult {
-    let client = pool.get().await.map_err(internal_err)?;
+    let client = pool.get().await?;
     let sql = format!(
         "SELECT kerai.neural_search('{}', '{}', NULL, {})::text",
         params.agent.replace('\'', "''"),
         params.q.replace('\'', "''"),
         params.limit.map(|v| v.to_string()).unwrap_or("NULL".into()),
     );
-    let row = client.query_one(&sql, &[]).await.map_err(internal_err)?;
+    let row = client.query_one(&sql, &[]).await?;
     let text: String = row.get(0);
     let value: Value = serde_json::from_str(&text).map_err(internal_err)?;
     Ok(Json(value))
@@ -137,9 +145,9 @@ pub struct EnsembleBody {
 /// POST /api/models/ensemble — ensemble prediction
 pub async fn ensemble_predict(
     State(pool): State<Arc<Pool>>,
-    Json(body): Json<EnsembleBody>,
+    ValidJson(body): ValidJson<EnsembleBody>,
 ) -> ApiResult {
-    let client = pool.get().await.map_err(internal_err)?;
+    let client = pool.get().await?;
     let agents_json = serde_json::json!(body.agents).to_string();
     let context_json = serde_json::json!(body.context).to_string();
     let sql = format!(
@@ -148,7 +156,7 @@ pub async fn ensemble_predict(
         context_json.replace('\'', "''"),
         body.top_k.map(|v| v.to_string()).unwrap_or("NULL".into()),
     );
-    let row = client.query_one(&sql, &[]).await.map_err(internal_err)?;
+    let row = client.query_one(&sql, &[]).await?;
     let text: String = row.get(0);
     let value: Value = serde_json::from_str(&text).map_err(internal_err)?;
     Ok(Json(value))
@@ -159,12 +167,12 @@ pub async fn model_info(
     State(pool): State<Arc<Pool>>,
     Path(agent): Path<String>,
 ) -> ApiResult {
-    let client = pool.get().await.map_err(internal_err)?;
+    let client = pool.get().await?;
     let sql = format!(
         "SELECT kerai.model_info('{}')::text",
         agent.replace('\'', "''"),
     );
-    let row = client.query_one(&sql, &[]).await.map_err(internal_err)?;
+    let row = client.query_one(&sql, &[]).await?;
     let text: String = row.get(0);
     let value: Value = serde_json::from_str(&text).map_err(internal_err)?;
     Ok(Json(value))
@@ -175,12 +183,12 @@ pub async fn delete_model(
     State(pool): State<Arc<Pool>>,
     Path(agent): Path<String>,
 ) -> ApiResult {
-    let client = pool.get().await.map_err(internal_err)?;
+    let client = pool.get().await?;
     let sql = format!(
         "SELECT kerai.delete_model('{}')::text",
         agent.replace('\'', "''"),
     );
-    let row = client.query_one(&sql, &[]).await.map_err(internal_err)?;
+    let row = client.query_one(&sql, &[]).await?;
     let text: String = row.get(0);
     let value: Value = serde_json::from_str(&text).map_err(internal_err)?;
     Ok(Json(value))
@@ -194,14 +202,14 @@ pub struct FeedbackBody {
 /// POST /api/models/feedback — record selection
 pub async fn record_selection(
     State(pool): State<Arc<Pool>>,
-    Json(body): Json<FeedbackBody>,
+    ValidJson(body): ValidJson<FeedbackBody>,
 ) -> ApiResult {
-    let client = pool.get().await.map_err(internal_err)?;
+    let client = pool.get().await?;
     let sql = format!(
         "SELECT kerai.record_selection('{}'::uuid)::text",
         body.inference_id.replace('\'', "''"),
     );
-    let row = client.query_one(&sql, &[]).await.map_err(internal_err)?;
+    let row = client.query_one(&sql, &[]).await?;
     let text: String = row.get(0);
     let value: Value = serde_json::from_str(&text).map_err(internal_err)?;
     Ok(Json(value))
@@ -1,10 +1,15 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
 use axum::Json;
 use serde::Deserialize;
 use serde_json::{json, Value};
 use std::sync::Arc;
 
-use super::super::db::Pool;
+use super::super::db::{self, Pool};
+use super::super::error::ApiError;
+use super::super::validate::ValidJson;
+use super::documents::ReadParams;
+use crate::txn;
 
 #[derive(Deserialize)]
 pub struct ApplyOpRequest {
@@ -18,14 +23,38 @@ pub struct UpdateContentRequest {
     pub content: String,
 }
 
+#[derive(Deserialize)]
+pub struct SplitRequest {
+    /// Character offset in the node's content to split at
+    pub at: i32,
+}
+
+#[derive(Deserialize)]
+pub struct MergeRequest {
+    /// Text put between the two contents
+    #[serde(default)]
+    pub separator: String,
+}
+
+#[derive(Deserialize)]
+pub struct ImpactParams {
+    pub max_depth: Option<i32>,
+    /// Measure impact as if this staged changeset were applied
+    pub changeset: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MentionParams {
+    /// Read as if this staged changeset were applied
+    pub changeset: Option<String>,
+}
+
 /// POST /api/nodes — apply a CRDT operation
 pub async fn create_node(
     State(pool): State<Arc<Pool>>,
-    Json(req): Json<ApplyOpRequest>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    ValidJson(req): ValidJson<ApplyOpRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
 
     let node_id_param = req.node_id
         .map(|id| format!("'{}'::uuid", id.replace('\'', "''")))
@@ -38,9 +67,7 @@ pub async fn create_node(
         req.payload.to_string().replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = txn::serializable_one(&mut client, "apply_op", &sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
@@ -50,11 +77,9 @@ pub async fn create_node(
 pub async fn update_content(
     State(pool): State<Arc<Pool>>,
     Path(node_id): Path<String>,
-    Json(req): Json<UpdateContentRequest>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    ValidJson(req): ValidJson<UpdateContentRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
 
     let payload = json!({"new_content": req.content});
     let sql = format!(
@@ -63,9 +88,7 @@ pub async fn update_content(
         payload.to_string().replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = txn::serializable_one(&mut client, "apply_op", &sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
@@ -75,11 +98,9 @@ pub async fn update_content(
 pub async fn move_node(
     State(pool): State<Arc<Pool>>,
     Path(node_id): Path<String>,
-    Json(payload): Json<Value>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    ValidJson(payload): ValidJson<Value>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
 
     let sql = format!(
         "SELECT kerai.apply_op('move_node', '{}'::uuid, '{}'::jsonb)",
@@ -87,32 +108,166 @@ pub async fn move_node(
         payload.to_string().replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = txn::serializable_one(&mut client, "apply_op", &sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
 }
 
-/// DELETE /api/nodes/:id — delete a node
+/// DELETE /api/nodes/:id — delete a node (a tombstone until kerai.gc purges it)
 pub async fn delete_node(
     State(pool): State<Arc<Pool>>,
     Path(node_id): Path<String>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
 
     let sql = format!(
         "SELECT kerai.apply_op('delete_node', '{}'::uuid, '{{\"cascade\": false}}'::jsonb)",
         node_id.replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = txn::serializable_one(&mut client, "apply_op", &sql, &[]).await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// POST /api/nodes/:id/split — split a node's content in two
+pub async fn split_node(
+    State(pool): State<Arc<Pool>>,
+    Path(node_id): Path<String>,
+    ValidJson(req): ValidJson<SplitRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
+
+    let row = txn::serializable_one(
+        &mut client,
+        "split_node",
+        "SELECT kerai.split_node($1::text::uuid, $2)",
+        &[&node_id, &req.at],
+    )
+    .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// POST /api/nodes/:id/merge-with-next — merge the next sibling into a node
+pub async fn merge_with_next(
+    State(pool): State<Arc<Pool>>,
+    Path(node_id): Path<String>,
+    ValidJson(req): ValidJson<MergeRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
+
+    let row = txn::serializable_one(
+        &mut client,
+        "merge_with_next",
+        "SELECT kerai.merge_with_next($1::text::uuid, $2)",
+        &[&node_id, &req.separator],
+    )
+    .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// POST /api/nodes/:id/promote — raise a heading one level
+pub async fn promote_heading(
+    State(pool): State<Arc<Pool>>,
+    Path(node_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
+
+    let row = txn::serializable_one(
+        &mut client,
+        "promote_heading",
+        "SELECT kerai.promote_heading($1::text::uuid)",
+        &[&node_id],
+    )
+    .await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
 }
+
+/// POST /api/nodes/:id/demote — lower a heading one level
+pub async fn demote_heading(
+    State(pool): State<Arc<Pool>>,
+    Path(node_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
+
+    let row = txn::serializable_one(
+        &mut client,
+        "demote_heading",
+        "SELECT kerai.demote_heading($1::text::uuid)",
+        &[&node_id],
+    )
+    .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// GET /api/nodes/:id/impact — files and documents affected by changing a
+/// node, as if `changeset` were applied when one is given
+pub async fn node_impact(
+    State(pool): State<Arc<Pool>>,
+    Path(node_id): Path<String>,
+    Query(params): Query<ImpactParams>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
+
+    let row = client
+        .query_one(
+            "SELECT kerai.impact($1::text::uuid, $2)",
+            &[&node_id, &params.max_depth.unwrap_or(3)],
+        )
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// GET /api/nodes/:id/mentions — the code a doc node's code spans name and
+/// the doc blocks naming a code node, as `kerai.mentions` gives them, for
+/// moving between docs and code. Limited to the `X-Kerai-View` view when
+/// one is given; read as if `changeset` were applied when one is given.
+pub async fn node_mentions(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    Path(node_id): Path<String>,
+    Query(params): Query<MentionParams>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
+    db::apply_view(&client, &headers).await?;
+
+    let row = client
+        .query_one("SELECT kerai.mentions($1::text::uuid)", &[&node_id])
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// GET /api/nodes/:id/source — just this item (a fn, struct, impl or
+/// method) as source, with its attributes and doc comments, indented as in
+/// its file; read as if `changeset` were applied when one is given
+pub async fn node_source(
+    State(pool): State<Arc<Pool>>,
+    Path(node_id): Path<String>,
+    Query(params): Query<ReadParams>,
+) -> Result<String, ApiError> {
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
+
+    let row = client
+        .query_one("SELECT kerai.reconstruct_node($1::text::uuid)", &[&node_id])
+        .await?;
+
+    let result: String = row.get(0);
+    Ok(result)
+}
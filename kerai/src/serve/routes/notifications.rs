@@ -0,0 +1,78 @@
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use super::ws::WsState;
+use crate::serve::error::ApiError;
+use crate::serve::{auth, poll};
+
+#[derive(Deserialize)]
+pub struct PollParams {
+    cursor: Option<String>,
+    path: Option<String>,
+    timeout: Option<String>,
+    limit: Option<i32>,
+}
+
+/// GET /api/notifications/poll — operations since `cursor` (or the
+/// session's stored cursor for `path`), waiting up to `timeout` for some.
+pub async fn poll(
+    State(state): State<Arc<WsState>>,
+    headers: HeaderMap,
+    Query(params): Query<PollParams>,
+) -> Result<Json<Value>, ApiError> {
+    let timeout = match params.timeout.as_deref() {
+        Some(t) => poll::parse_timeout(t).map_err(ApiError::bad_request)?,
+        None => poll::DEFAULT_TIMEOUT,
+    };
+    let token = auth::extract_session_token(&headers)
+        .ok_or_else(|| ApiError::unauthorized("no session"))?;
+
+    // Subscribe before the first read so nothing lands in between
+    let mut notify_rx = state.notify_tx.subscribe();
+
+    let client = state.pool.get().await?;
+    let session_id = poll::session_id(&client, &token)
+        .await
+        .map_err(ApiError::unauthorized)?;
+
+    let region = params.path.unwrap_or_default();
+    let cursor = match params.cursor {
+        Some(cursor) => Some(cursor),
+        None => poll::stored_cursor(&client, session_id, &region)
+            .await
+            .map_err(ApiError::internal)?,
+    };
+
+    let result = poll::wait_for_changes(
+        &client,
+        &mut notify_rx,
+        cursor.as_deref(),
+        Some(region.as_str()),
+        params.limit.unwrap_or(100),
+        timeout,
+    )
+    .await
+    .map_err(|e| {
+        if e.contains("Invalid cursor") {
+            ApiError::bad_request(e)
+        } else {
+            ApiError::internal(e)
+        }
+    })?;
+
+    let events = result["events"].as_array().map_or(0, |e| e.len());
+    let next = result["cursor"].as_str().unwrap_or_default();
+    poll::store_cursor(&client, session_id, &region, next, events as i64)
+        .await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(json!({
+        "events": result["events"],
+        "cursor": next,
+        "more": result["more"],
+    })))
+}
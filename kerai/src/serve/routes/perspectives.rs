@@ -1,10 +1,16 @@
 use axum::extract::{Query, State};
+use axum::response::Response;
 use axum::Json;
 use serde::Deserialize;
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::sync::Arc;
+use uuid::Uuid;
 
 use super::super::db::Pool;
+use super::super::error::ApiError;
+use super::super::page::{self, Page};
+use super::super::validate::ValidJson;
+use crate::txn;
 
 #[derive(Deserialize)]
 pub struct PerspectiveParams {
@@ -20,33 +26,96 @@ pub struct ConsensusParams {
     pub min_weight: Option<f64>,
 }
 
-/// GET /api/perspectives — get agent perspectives
+/// GET /api/perspectives — an agent's perspectives, newest first, a page
+/// at a time (`limit`, `order`, `cursor`, and `kind` / `language` of the
+/// node; see [`Page`])
 pub async fn get_perspectives(
     State(pool): State<Arc<Pool>>,
+    page: Page,
     Query(params): Query<PerspectiveParams>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
-
-    let ctx_param = params.context_id
-        .map(|c| format!("'{}'::uuid", c.replace('\'', "''")))
-        .unwrap_or_else(|| "NULL".to_string());
-
-    let weight_param = params.min_weight
-        .map(|w| w.to_string())
-        .unwrap_or_else(|| "NULL".to_string());
+) -> Result<Response, ApiError> {
+    let client = pool.get().await?;
+
+    let agent = client
+        .query_opt(
+            "SELECT id FROM kerai.agents WHERE name = $1",
+            &[&params.agent],
+        )
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("Agent not found: {}", params.agent)))?;
+    let agent_id: Uuid = agent.get(0);
 
     let sql = format!(
-        "SELECT kerai.get_perspectives('{}', {}, {})",
-        params.agent.replace('\'', "''"),
-        ctx_param,
-        weight_param,
+        "SELECT jsonb_build_object(
+            'id', p.id,
+            'node_id', p.node_id,
+            'weight', p.weight,
+            'context_id', p.context_id,
+            'reasoning', p.reasoning,
+            'node_kind', n.kind,
+            'node_content', n.content,
+            'created_at', p.created_at,
+            'updated_at', p.updated_at
+        ), {}
+        FROM kerai.perspectives p
+        JOIN kerai.nodes n ON n.id = p.node_id
+        WHERE p.agent_id = $1
+          AND ($2::text IS NULL OR p.context_id = $2::text::uuid)
+          AND ($3::float8 IS NULL OR p.weight >= $3)
+          AND ($4::text IS NULL OR n.kind = $4) AND ($5::text IS NULL OR n.language = $5)
+          AND {}
+        {}",
+        page::cursor_sql("p.created_at", "p.id"),
+        page.after("p.created_at", "p.id", 6),
+        page.order_by("p.created_at", "p.id"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let (micros, id) = page.cursor_args();
+    let rows = client
+        .query(
+            &sql,
+            &[
+                &agent_id,
+                &params.context_id,
+                &params.min_weight,
+                &page.kind,
+                &page.language,
+                &micros,
+                &id,
+            ],
+        )
+        .await?;
+
+    Ok(page.respond(rows.iter().map(|r| (r.get(0), r.get(1))).collect()))
+}
+
+#[derive(Deserialize)]
+pub struct PerspectiveBatchRequest {
+    pub agent: String,
+    pub perspectives: Vec<Value>,
+}
+
+/// POST /api/perspectives/batch — import a batch of perspectives for one
+/// agent, as `kerai.import_perspectives` does. Each perspective is
+/// `{node_id | node_path, context_id | context_path?, weight, reasoning?}`;
+/// bad items are reported per item rather than failing the batch. Returns
+/// `{agent, inserted, updated, unchanged, duplicates, invalid, items}`.
+pub async fn batch(
+    State(pool): State<Arc<Pool>>,
+    ValidJson(req): ValidJson<PerspectiveBatchRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
+
+    let sql = "SELECT kerai.import_perspectives($1, $2::jsonb)";
+    let perspectives = json!(req.perspectives);
+
+    let row = txn::serializable_one(
+        &mut client,
+        "perspective_batch",
+        sql,
+        &[&req.agent, &perspectives],
+    )
+    .await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
@@ -56,10 +125,8 @@ pub async fn get_perspectives(
 pub async fn consensus(
     State(pool): State<Arc<Pool>>,
     Query(params): Query<ConsensusParams>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let ctx_param = params.context_id
         .map(|c| format!("'{}'::uuid", c.replace('\'', "''")))
@@ -80,9 +147,7 @@ pub async fn consensus(
         weight_param,
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
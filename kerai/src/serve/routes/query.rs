@@ -0,0 +1,55 @@
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::lang::ptr::Ptr;
+use crate::serve::auth;
+use crate::serve::db::Pool;
+use crate::serve::error::ApiError;
+use crate::serve::query;
+use crate::serve::validate::ValidJson;
+
+/// A stored query as it sits in a `result` item's meta.
+#[derive(Deserialize)]
+pub struct ExplainRequest {
+    query: Value,
+    page_size: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// POST /api/query/explain — plan a stored query under the query budget.
+///
+/// Returns `{sql, total_cost, estimated_rows, plan}`; `plan` is the
+/// annotated tree (`node`, `relation`, `rows`, `cost`, `detail`, `children`).
+pub async fn explain(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    ValidJson(req): ValidJson<ExplainRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let token = auth::extract_session_token(&headers)
+        .ok_or_else(|| ApiError::unauthorized("no session"))?;
+    auth::resolve_session(&pool, &token)
+        .await
+        .map_err(ApiError::unauthorized)?;
+
+    let client = pool.get().await?;
+
+    let request = Ptr {
+        kind: "explain_request".into(),
+        ref_id: req.query["pattern"].as_str().unwrap_or("query").into(),
+        meta: json!({
+            "query": req.query,
+            "page_size": req.page_size,
+            "offset": req.offset.unwrap_or(0),
+        }),
+        id: 0,
+    };
+    let plan = query::explain(&client, &request)
+        .await
+        .map_err(ApiError::bad_request)?;
+
+    Ok(Json(plan.meta))
+}
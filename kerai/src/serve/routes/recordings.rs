@@ -0,0 +1,76 @@
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::lang::machine::Role;
+use crate::serve::auth;
+use crate::serve::db::Pool;
+use crate::serve::error::ApiError;
+use crate::serve::recording;
+
+/// Resolve the request's session and require an admin; returns the user id.
+async fn require_admin(pool: &Pool, headers: &HeaderMap) -> Result<uuid::Uuid, ApiError> {
+    let token = auth::extract_session_token(headers)
+        .ok_or_else(|| ApiError::unauthorized("no session"))?;
+    let (user_id, _workspace_id) = auth::resolve_session(pool, &token)
+        .await
+        .map_err(ApiError::unauthorized)?;
+    let role = auth::resolve_role(pool, user_id)
+        .await
+        .map_err(ApiError::internal)?;
+    if role != Role::Admin {
+        return Err(ApiError::forbidden("admin only"));
+    }
+    Ok(user_id)
+}
+
+#[derive(Deserialize)]
+pub struct ListParams {
+    limit: Option<i64>,
+}
+
+/// GET /api/admin/recordings — recent session recordings.
+pub async fn list(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    Query(params): Query<ListParams>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&pool, &headers).await?;
+
+    let client = pool.get().await?;
+
+    let result = recording::list(&client, params.limit.unwrap_or(50))
+        .await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(result))
+}
+
+/// POST /api/admin/recordings/{id}/replay — replay a recording against a
+/// scratch workspace (rolled back) and report where it diverges.
+pub async fn replay(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_admin(&pool, &headers).await?;
+    let id = uuid::Uuid::parse_str(&id)
+        .map_err(|e| ApiError::bad_request(format!("invalid recording id: {e}")))?;
+
+    let mut client = pool.get().await?;
+
+    let result = recording::replay(&mut client, id, user_id)
+        .await
+        .map_err(|e| {
+            if e.starts_with("no recording") {
+                ApiError::not_found(e)
+            } else {
+                ApiError::internal(e)
+            }
+        })?;
+
+    Ok(Json(result))
+}
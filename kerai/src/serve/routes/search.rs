@@ -1,16 +1,32 @@
 use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde::Deserialize;
 use serde_json::Value;
 use std::sync::Arc;
+use tokio_postgres::types::ToSql;
 
-use super::super::db::Pool;
+use super::super::db::{self, Pool};
+use super::super::error::ApiError;
+use super::super::page::{self, Page};
+use super::super::stream;
 
 #[derive(Deserialize)]
 pub struct SearchParams {
     pub q: String,
-    pub kind: Option<String>,
     pub limit: Option<i32>,
+    /// `fts` (default) or `semantic`
+    pub mode: Option<String>,
+    /// `rank` (default), best matches first, or `created`, paged by
+    /// creation time like the other listings
+    pub sort: Option<String>,
+    /// Embedding model for semantic mode; defaults to the latest embedded
+    pub model: Option<String>,
+    /// Stream results as NDJSON, one per line
+    pub stream: Option<bool>,
+    /// Search as if this staged changeset were applied
+    pub changeset: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -20,46 +36,155 @@ pub struct ContextSearchParams {
     pub limit: Option<i32>,
 }
 
-/// GET /api/search — full-text search
+/// GET /api/search — ranked full-text search (web search syntax in `q`),
+/// or nearest node embeddings with `mode=semantic`. `kind` and `language`
+/// filter full-text results; with `sort=created` they are paged by
+/// creation time instead of ranked (`limit`, `order`, `cursor`; see
+/// [`Page`]). An `X-Kerai-View` header limits results to that view, and
+/// `changeset` searches as if that changeset were applied. With
+/// `stream=true` full-text results are written as NDJSON, one per line, as
+/// Postgres returns them instead of as one array.
 pub async fn search(
     State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    page: Page,
     Query(params): Query<SearchParams>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Response, ApiError> {
+    let mode = params.mode.as_deref().unwrap_or("fts");
+    if !matches!(mode, "fts" | "semantic") {
+        return Err(ApiError::bad_request(format!(
+            "unknown search mode '{mode}' (expected fts or semantic)"
+        )));
+    }
+    match params.sort.as_deref().unwrap_or("rank") {
+        "rank" if page.cursor.is_some() => {
+            return Err(ApiError::bad_request("cursor needs sort=created"));
+        }
+        "rank" if params.stream.unwrap_or(false) && mode != "fts" => {
+            return Err(ApiError::bad_request(
+                "semantic results are ranked in one pass and cannot stream",
+            ));
+        }
+        "rank" => {}
+        "created" if mode != "fts" => {
+            return Err(ApiError::bad_request("sort=created needs mode=fts"));
+        }
+        "created" if params.stream.unwrap_or(false) => {
+            return Err(ApiError::bad_request(
+                "sort=created pages with X-Next-Cursor and cannot stream",
+            ));
+        }
+        "created" => return search_created(&pool, &headers, &page, &params).await,
+        other => {
+            return Err(ApiError::bad_request(format!(
+                "unknown sort '{other}' (expected rank or created)"
+            )));
+        }
+    }
 
-    let kind_param = params.kind
-        .map(|k| format!("'{}'", k.replace('\'', "''")))
-        .unwrap_or_else(|| "NULL".to_string());
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
+    db::apply_view(&client, &headers).await?;
 
-    let limit_param = params.limit
-        .map(|l| l.to_string())
-        .unwrap_or_else(|| "NULL".to_string());
+    let semantic_limit = params.limit.unwrap_or(10);
+    let (call, args): (&str, Vec<&(dyn ToSql + Sync)>) = if mode == "fts" {
+        (
+            "kerai.search($1, $2, $3, $4)",
+            vec![&params.q, &page.kind, &params.limit, &page.language],
+        )
+    } else {
+        (
+            "kerai.search_semantic($1, $2, $3)",
+            vec![&params.q, &semantic_limit, &params.model],
+        )
+    };
+
+    if params.stream.unwrap_or(false) {
+        return stream::ndjson(client, STREAM_SQL, &args).await;
+    }
+
+    let row = client
+        .query_one(&format!("SELECT {call}"), &args)
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result).into_response())
+}
+
+/// `kerai.search` as rows, for `stream=true`: the same matches, ranks and
+/// headlines, one `jsonb` object per row so they stream as they are read.
+const STREAM_SQL: &str = "
+    SELECT jsonb_build_object(
+        'id', n.id,
+        'kind', n.kind,
+        'content', n.content,
+        'path', n.path::text,
+        'rank', hits.rank,
+        'headline', ts_headline('english', COALESCE(n.content, ''), hits.query,
+                                'StartSel=**, StopSel=**, MaxFragments=1, MaxWords=20, MinWords=5'),
+        'metadata', n.metadata
+    )
+    FROM (
+        SELECT n.id, q.query, ts_rank(n.tsv, q.query, 1) AS rank
+        FROM kerai.nodes n, websearch_to_tsquery('english', $1) q(query)
+        WHERE n.tsv @@ q.query AND n.deleted_at IS NULL AND kerai.in_view(n.path, n.kind)
+          AND ($2::text IS NULL OR n.kind = $2) AND ($4::text IS NULL OR n.language = $4)
+        ORDER BY rank DESC
+        LIMIT LEAST(GREATEST(COALESCE($3::int, 50), 1), 1000)
+    ) hits
+    JOIN kerai.nodes n ON n.id = hits.id
+    ORDER BY hits.rank DESC";
+
+/// Full-text matches a page at a time by `(created_at, id)`, each with its
+/// rank and headline as `kerai.search` gives them.
+async fn search_created(
+    pool: &Pool,
+    headers: &HeaderMap,
+    page: &Page,
+    params: &SearchParams,
+) -> Result<Response, ApiError> {
+    let client = pool.get().await?;
+    db::preview_changeset(&client, params.changeset.as_deref()).await?;
+    db::apply_view(&client, headers).await?;
 
     let sql = format!(
-        "SELECT kerai.search('{}', {}, {})",
-        params.q.replace('\'', "''"),
-        kind_param,
-        limit_param,
+        "SELECT jsonb_build_object(
+            'id', n.id,
+            'kind', n.kind,
+            'content', n.content,
+            'path', n.path::text,
+            'rank', ts_rank(n.tsv, q.query, 1),
+            'headline', ts_headline('english', COALESCE(n.content, ''), q.query,
+                                    'StartSel=**, StopSel=**, MaxFragments=1, MaxWords=20, MinWords=5'),
+            'metadata', n.metadata,
+            'created_at', n.created_at
+        ), {}
+        FROM kerai.nodes n, websearch_to_tsquery('english', $1) q(query)
+        WHERE n.tsv @@ q.query AND n.deleted_at IS NULL AND kerai.in_view(n.path, n.kind)
+          AND ($2::text IS NULL OR n.kind = $2) AND ($3::text IS NULL OR n.language = $3)
+          AND {}
+        {}",
+        page::cursor_sql("n.created_at", "n.id"),
+        page.after("n.created_at", "n.id", 4),
+        page.order_by("n.created_at", "n.id"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let (micros, id) = page.cursor_args();
+    let rows = client
+        .query(&sql, &[&params.q, &page.kind, &page.language, &micros, &id])
+        .await?;
 
-    let result: Value = row.get(0);
-    Ok(Json(result))
+    Ok(page.respond(rows.iter().map(|r| (r.get(0), r.get(1))).collect()))
 }
 
 /// GET /api/suggest — context-aware search for AI suggestions
 pub async fn suggest(
     State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
     Query(params): Query<ContextSearchParams>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+    db::apply_view(&client, &headers).await?;
 
     let agents_param = params.agents
         .map(|a| {
@@ -81,9 +206,7 @@ pub async fn suggest(
         limit_param,
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
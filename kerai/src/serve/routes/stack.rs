@@ -5,6 +5,8 @@ use serde_json::{json, Value};
 use std::sync::Arc;
 
 use super::super::db::Pool;
+use super::super::error::ApiError;
+use super::super::validate::ValidJson;
 
 #[derive(Deserialize)]
 pub struct PushRequest {
@@ -20,15 +22,12 @@ pub struct ReplaceRequest {
 /// GET /api/stack — peek at stack top
 pub async fn stack_peek(
     State(pool): State<Arc<Pool>>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let row = client
         .query_opt("SELECT kerai.stack_peek()", &[])
-        .await
-        .map_err(|e| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     let content = row.and_then(|r| r.get::<_, Option<String>>(0));
     Ok(Json(json!({ "content": content })))
@@ -37,18 +36,15 @@ pub async fn stack_peek(
 /// GET /api/stack/list — list all stack entries
 pub async fn stack_list(
     State(pool): State<Arc<Pool>>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let rows = client
         .query(
             "SELECT position, label, preview, created_at FROM kerai.stack_list()",
             &[],
         )
-        .await
-        .map_err(|e| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     let entries: Vec<Value> = rows
         .iter()
@@ -68,19 +64,16 @@ pub async fn stack_list(
 /// POST /api/stack/push — push content onto stack
 pub async fn stack_push(
     State(pool): State<Arc<Pool>>,
-    Json(req): Json<PushRequest>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    ValidJson(req): ValidJson<PushRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let row = client
         .query_one(
             "SELECT kerai.stack_push($1, $2)",
             &[&req.content, &req.label],
         )
-        .await
-        .map_err(|e| (axum::http::StatusCode::BAD_REQUEST, e.to_string()))?;
+        .await?;
 
     let position: i32 = row.get(0);
     Ok(Json(json!({ "position": position })))
@@ -89,15 +82,12 @@ pub async fn stack_push(
 /// DELETE /api/stack — drop top entry
 pub async fn stack_drop(
     State(pool): State<Arc<Pool>>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let row = client
         .query_one("SELECT kerai.stack_drop()", &[])
-        .await
-        .map_err(|e| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     let status: String = row.get(0);
     Ok(Json(json!({ "status": status })))
@@ -106,15 +96,12 @@ pub async fn stack_drop(
 /// DELETE /api/stack/all — clear entire stack
 pub async fn stack_clear(
     State(pool): State<Arc<Pool>>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let row = client
         .query_one("SELECT kerai.stack_clear()", &[])
-        .await
-        .map_err(|e| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     let cleared: i32 = row.get(0);
     Ok(Json(json!({ "cleared": cleared })))
@@ -123,16 +110,13 @@ pub async fn stack_clear(
 /// PUT /api/stack — replace top entry content
 pub async fn stack_replace(
     State(pool): State<Arc<Pool>>,
-    Json(req): Json<ReplaceRequest>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    ValidJson(req): ValidJson<ReplaceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let row = client
         .query_one("SELECT kerai.stack_replace($1)", &[&req.content])
-        .await
-        .map_err(|e| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     let status: String = row.get(0);
     Ok(Json(json!({ "status": status })))
@@ -141,15 +125,12 @@ pub async fn stack_replace(
 /// POST /api/init/pull — render preferences and push onto stack
 pub async fn init_pull(
     State(pool): State<Arc<Pool>>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let row = client
         .query_one("SELECT kerai.pull_init()", &[])
-        .await
-        .map_err(|e| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     let status: String = row.get(0);
     Ok(Json(json!({ "status": status })))
@@ -158,15 +139,12 @@ pub async fn init_pull(
 /// POST /api/init/push — parse stack top and apply to preferences
 pub async fn init_push(
     State(pool): State<Arc<Pool>>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let row = client
         .query_one("SELECT kerai.push_init()", &[])
-        .await
-        .map_err(|e| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     let result: String = row.get(0);
     // push_init returns JSON, parse it so we return proper JSON not a string
@@ -177,15 +155,12 @@ pub async fn init_push(
 /// GET /api/init/diff — show what push would change
 pub async fn init_diff(
     State(pool): State<Arc<Pool>>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let row = client
         .query_one("SELECT kerai.diff_init()", &[])
-        .await
-        .map_err(|e| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .await?;
 
     let result: String = row.get(0);
     let parsed: Value = serde_json::from_str(&result).unwrap_or(json!([]));
@@ -0,0 +1,119 @@
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::super::db::Pool;
+use super::super::error::ApiError;
+use super::super::validate::{self, ValidJson};
+use crate::serve::auth;
+
+#[derive(Deserialize)]
+pub struct SubscribeRequest {
+    pub path: String,
+    pub events: Option<Vec<String>>,
+    pub frequency: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UnsubscribeParams {
+    pub path: String,
+}
+
+#[derive(Deserialize)]
+pub struct InboxParams {
+    pub limit: Option<i32>,
+}
+
+/// The session's user id, as text for the kerai.* functions.
+async fn session_user(pool: &Pool, headers: &HeaderMap) -> Result<String, ApiError> {
+    let token =
+        auth::extract_session_token(headers).ok_or_else(|| ApiError::unauthorized("no session"))?;
+    let (user_id, _) = auth::resolve_session(pool, &token)
+        .await
+        .map_err(ApiError::unauthorized)?;
+    Ok(user_id.to_string())
+}
+
+/// GET /api/subscriptions — the session user's subscriptions
+pub async fn list_subscriptions(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    let user = session_user(&pool, &headers).await?;
+    let client = pool.get().await?;
+
+    let row = client
+        .query_one("SELECT kerai.list_subscriptions($1)", &[&user])
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// POST /api/subscriptions — follow a path (or lquery), or change the
+/// event types and digest frequency of an existing subscription to it
+pub async fn subscribe(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    ValidJson(req): ValidJson<SubscribeRequest>,
+) -> Result<Json<Value>, ApiError> {
+    validate::path_or_lquery(&req.path)?;
+    let user = session_user(&pool, &headers).await?;
+    let client = pool.get().await?;
+
+    let frequency = req.frequency.unwrap_or_else(|| "daily".into());
+    let row = client
+        .query_one(
+            "SELECT kerai.subscribe($1, $2, $3, $4)",
+            &[&user, &req.path, &req.events, &frequency],
+        )
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// DELETE /api/subscriptions?path= — stop following a path
+pub async fn unsubscribe(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    Query(params): Query<UnsubscribeParams>,
+) -> Result<StatusCode, ApiError> {
+    validate::path_or_lquery(&params.path)?;
+    let user = session_user(&pool, &headers).await?;
+    let client = pool.get().await?;
+
+    let row = client
+        .query_one("SELECT kerai.unsubscribe($1, $2)", &[&user, &params.path])
+        .await?;
+
+    if row.get::<_, bool>(0) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::not_found(format!(
+            "not subscribed to {}",
+            params.path
+        )))
+    }
+}
+
+/// GET /api/inbox — the session user's digests, newest first
+pub async fn inbox(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+    Query(params): Query<InboxParams>,
+) -> Result<Json<Value>, ApiError> {
+    let user = session_user(&pool, &headers).await?;
+    let client = pool.get().await?;
+
+    let limit = params.limit.unwrap_or(50);
+    let row = client
+        .query_one("SELECT kerai.inbox($1, $2)", &[&user, &limit])
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
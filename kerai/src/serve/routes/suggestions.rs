@@ -0,0 +1,77 @@
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::super::db::Pool;
+use super::super::error::ApiError;
+use super::super::validate::ValidJson;
+use crate::txn;
+
+#[derive(Deserialize)]
+pub struct ListParams {
+    /// Only files whose name or source path starts with this
+    pub file: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DismissRequest {
+    /// Why the suggestion does not apply, kept on it
+    pub reason: Option<String>,
+}
+
+/// GET /api/suggestions — open suggestions grouped by file
+/// (kerai.list_suggestions).
+pub async fn list(
+    State(pool): State<Arc<Pool>>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+    let row = client
+        .query_one("SELECT kerai.list_suggestions($1)", &[&params.file])
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// POST /api/suggestions/:id/accept — apply a suggestion's fix, close it
+/// and credit its rule's reward
+pub async fn accept(
+    State(pool): State<Arc<Pool>>,
+    Path(suggestion_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
+
+    let row = txn::serializable_one(
+        &mut client,
+        "accept_suggestion",
+        "SELECT kerai.accept_suggestion($1::text::uuid)",
+        &[&suggestion_id],
+    )
+    .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
+
+/// POST /api/suggestions/:id/dismiss — close a suggestion without an edit
+pub async fn dismiss(
+    State(pool): State<Arc<Pool>>,
+    Path(suggestion_id): Path<String>,
+    ValidJson(req): ValidJson<DismissRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
+
+    let row = txn::serializable_one(
+        &mut client,
+        "dismiss_suggestion",
+        "SELECT kerai.dismiss_suggestion($1::text::uuid, $2)",
+        &[&suggestion_id, &req.reason],
+    )
+    .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
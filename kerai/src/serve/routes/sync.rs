@@ -0,0 +1,139 @@
+use axum::extract::State;
+use axum::Json;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::serve::db::Pool;
+use crate::serve::error::ApiError;
+use crate::serve::validate::ValidJson;
+use crate::txn;
+
+/// Open a signed sync message with `kerai.open_sync_message`, returning its
+/// body. Messages from unregistered peers or with bad signatures are refused.
+async fn open_message(
+    client: &tokio_postgres::Client,
+    message: &Value,
+) -> Result<Value, ApiError> {
+    let row = client
+        .query_one("SELECT kerai.open_sync_message($1::jsonb)", &[message])
+        .await
+        .map_err(|e| ApiError::unauthorized(e.to_string()))?;
+    let opened: Value = row.get(0);
+    Ok(opened["body"].clone())
+}
+
+/// Sign a reply body with this instance's key.
+async fn sign_message(
+    client: &tokio_postgres::Client,
+    body: &Value,
+) -> Result<Value, ApiError> {
+    let row = client
+        .query_one("SELECT kerai.sign_sync_message($1::jsonb)", &[body])
+        .await?;
+    Ok(row.get(0))
+}
+
+/// POST /api/sync/pull — a peer asks for the ops it is missing.
+///
+/// The request is a signed message whose body is `{vector, limit}`, the
+/// peer's version vector and optionally the `{max_ops, max_bytes}` its sync
+/// policy has left for the exchange. The reply is a signed message whose
+/// body is `{vector, ops, filter, merkle, deferred}`: this instance's
+/// vector, the ops past the peer's vector (cut to the limit, per author,
+/// by `kerai.limit_ops`), a version filter of what this instance holds past
+/// the common frontier, for the peer to skip when it pushes, this
+/// instance's `kerai.merkle_hashes`, for the peer to see which subtrees
+/// differ, and how many ops the limit held back.
+pub async fn pull(
+    State(pool): State<Arc<Pool>>,
+    ValidJson(message): ValidJson<Value>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+
+    let body = open_message(&client, &message).await?;
+    let peer_vv = body["vector"].clone();
+    if !peer_vv.is_object() {
+        return Err(ApiError::bad_request("pull body needs a vector"));
+    }
+    let max_ops = body["limit"]["max_ops"].as_i64();
+    let max_bytes = body["limit"]["max_bytes"].as_i64();
+
+    let row = client
+        .query_one(
+            "WITH v AS (SELECT kerai.version_vector() AS own),
+                  cut AS (SELECT kerai.limit_ops(kerai.version_delta($1::jsonb), $2, $3) AS c)
+             SELECT v.own,
+                    cut.c->'ops',
+                    kerai.version_filter(kerai.version_frontier($1::jsonb, v.own)),
+                    kerai.merkle_hashes(),
+                    (cut.c->>'deferred')::bigint
+             FROM v, cut",
+            &[&peer_vv, &max_ops, &max_bytes],
+        )
+        .await?;
+    let vector: Value = row.get(0);
+    let ops: Value = row.get(1);
+    let filter: Value = row.get(2);
+    let merkle: Value = row.get(3);
+    let deferred: i64 = row.get(4);
+
+    let body = json!({
+        "vector": vector,
+        "ops": ops,
+        "filter": filter,
+        "merkle": merkle,
+        "deferred": deferred,
+    });
+    let reply = sign_message(&client, &body).await?;
+    Ok(Json(reply))
+}
+
+/// POST /api/sync/push — a peer sends ops this instance is missing.
+///
+/// The request is a signed message whose body is `{ops}`. The batch goes to
+/// `kerai.apply_operations`, which checks each op's own signature, skips
+/// ones already held and applies the rest last-writer-wins. Returns
+/// `{applied, superseded, duplicates, missing}`.
+pub async fn push(
+    State(pool): State<Arc<Pool>>,
+    ValidJson(message): ValidJson<Value>,
+) -> Result<Json<Value>, ApiError> {
+    let mut client = pool.get().await?;
+
+    let body = open_message(&client, &message).await?;
+    let ops = &body["ops"];
+    if !ops.is_array() {
+        return Err(ApiError::bad_request("push body needs ops"));
+    }
+
+    let sql = "SELECT kerai.apply_operations($1::jsonb)";
+    let row = txn::serializable_one(&mut client, "apply_operations", sql, &[ops]).await?;
+    let result: Value = row.get(0);
+
+    Ok(Json(json!({
+        "applied": result["applied"],
+        "superseded": result["superseded"],
+        "duplicates": result["duplicates"],
+        "missing": result["missing"],
+    })))
+}
+
+/// POST /api/sync/economy — a peer asks for this instance's economy report.
+///
+/// The request is a signed message whose body is `{period}` (default
+/// `month`). The reply is `kerai.economy_summary(period)`: the report,
+/// signed, for the peer to keep with `kerai.record_economy_summary`.
+pub async fn economy(
+    State(pool): State<Arc<Pool>>,
+    ValidJson(message): ValidJson<Value>,
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
+
+    let body = open_message(&client, &message).await?;
+    let period = body["period"].as_str().unwrap_or("month");
+
+    let row = client
+        .query_one("SELECT kerai.economy_summary($1)", &[&period])
+        .await?;
+    Ok(Json(row.get(0)))
+}
@@ -0,0 +1,45 @@
+use axum::extract::{Query, State};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::super::db::Pool;
+use super::super::error::ApiError;
+
+#[derive(Deserialize)]
+pub struct TimelineParams {
+    /// Subtree to count activity under; everything when empty
+    pub path: Option<String>,
+    /// `day` (default) or `week`
+    pub granularity: Option<String>,
+    /// Window in days (default 365)
+    pub days: Option<i32>,
+}
+
+/// GET /api/timeline — versions, perspective changes and parses per day or
+/// week under `path`, with a breakdown per author (kerai.timeline)
+pub async fn timeline(
+    State(pool): State<Arc<Pool>>,
+    Query(params): Query<TimelineParams>,
+) -> Result<Json<Value>, ApiError> {
+    let granularity = params.granularity.unwrap_or_else(|| "day".into());
+    if !matches!(granularity.as_str(), "day" | "week") {
+        return Err(ApiError::bad_request("granularity must be day or week"));
+    }
+    let client = pool.get().await?;
+
+    let row = client
+        .query_one(
+            "SELECT kerai.timeline($1, $2, $3)",
+            &[
+                &params.path.unwrap_or_default(),
+                &granularity,
+                &params.days.unwrap_or(365),
+            ],
+        )
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok(Json(result))
+}
@@ -0,0 +1,88 @@
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::serve::db::Pool;
+use crate::serve::error::ApiError;
+use crate::serve::tokens::{self, ApiIdentity, Scope};
+use crate::serve::validate::ValidJson;
+
+#[derive(Deserialize)]
+pub struct CreateTokenRequest {
+    name: String,
+    #[serde(default)]
+    scopes: Vec<String>,
+    user_id: Option<String>,
+    instance_id: Option<String>,
+    expires_in_days: Option<i32>,
+}
+
+fn parse_id(field: &str, value: Option<&str>) -> Result<Option<Uuid>, ApiError> {
+    value
+        .map(|v| {
+            Uuid::parse_str(v).map_err(|_| ApiError::bad_request(format!("invalid {field} '{v}'")))
+        })
+        .transpose()
+}
+
+/// POST /api/tokens — issue an API token (admin only) with
+/// `kerai.create_api_token`. The token is in the response and nowhere
+/// else; only its hash is stored. It acts for `user_id` and `instance_id`
+/// (this instance unless given), with `scopes` (default read).
+pub async fn create(
+    State(pool): State<Arc<Pool>>,
+    identity: Option<Extension<ApiIdentity>>,
+    headers: HeaderMap,
+    ValidJson(req): ValidJson<CreateTokenRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let created_by = tokens::require_admin(&pool, identity.as_deref(), &headers).await?;
+
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::bad_request("name must not be empty"));
+    }
+    let mut scopes = req
+        .scopes
+        .iter()
+        .map(|s| {
+            Scope::parse(s).ok_or_else(|| {
+                ApiError::bad_request(format!(
+                    "unknown scope '{s}' (expected read, write or admin)"
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if scopes.is_empty() {
+        scopes.push(Scope::Read);
+    }
+    scopes.sort();
+    scopes.dedup();
+    let scopes: Vec<&str> = scopes.into_iter().map(Scope::as_str).collect();
+    if req.expires_in_days.is_some_and(|d| d <= 0) {
+        return Err(ApiError::bad_request("expires_in_days must be positive"));
+    }
+    let user_id = parse_id("user_id", req.user_id.as_deref())?;
+    let instance_id = parse_id("instance_id", req.instance_id.as_deref())?;
+
+    let client = pool.get().await?;
+    let row = client
+        .query_one(
+            "SELECT kerai.create_api_token($1, $2, $3, $4, $5, $6)",
+            &[
+                &name,
+                &scopes,
+                &user_id,
+                &instance_id,
+                &req.expires_in_days,
+                &created_by,
+            ],
+        )
+        .await?;
+
+    let result: Value = row.get(0);
+    Ok((StatusCode::CREATED, Json(result)))
+}
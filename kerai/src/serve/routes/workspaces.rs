@@ -0,0 +1,59 @@
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::serve::auth;
+use crate::serve::bundle;
+use crate::serve::db::Pool;
+use crate::serve::error::ApiError;
+use crate::serve::validate::ValidJson;
+
+/// GET /api/workspace/export — bundle the session's current workspace as JSON.
+pub async fn export_workspace(
+    State(pool): State<Arc<Pool>>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    let token = auth::extract_session_token(&headers)
+        .ok_or_else(|| ApiError::unauthorized("no session"))?;
+
+    let (_user_id, workspace_id) = auth::resolve_session(&pool, &token)
+        .await
+        .map_err(ApiError::unauthorized)?;
+
+    let client = pool.get().await?;
+
+    let result = bundle::export_workspace(&client, workspace_id)
+        .await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(result))
+}
+
+#[derive(Deserialize)]
+pub struct ImportRequest {
+    session_token: String,
+    bundle: Value,
+}
+
+/// POST /api/workspace/import — recreate a bundle as a new workspace for the session's user.
+pub async fn import_workspace(
+    State(pool): State<Arc<Pool>>,
+    ValidJson(req): ValidJson<ImportRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let (user_id, _workspace_id) = auth::resolve_session(&pool, &req.session_token)
+        .await
+        .map_err(ApiError::unauthorized)?;
+
+    bundle::validate(&req.bundle).map_err(ApiError::bad_request)?;
+
+    let mut client = pool.get().await?;
+
+    let result = bundle::import_workspace(&mut client, user_id, &req.bundle)
+        .await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(result.to_json()))
+}
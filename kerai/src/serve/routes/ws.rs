@@ -1,56 +1,169 @@
-use axum::extract::{State, WebSocketUpgrade};
+/// WebSocket channel: the kerai_ops NOTIFY relay, stack deltas, change
+/// event subscriptions and document rooms.
+///
+/// Every client receives each `kerai_ops` notification until it sends
+/// `{"type": "subscribe", ...}` for change events instead (see
+/// notify.rs). `{"subscribe": {"session_token"}}` follows the stack deltas
+/// of the session's workspace. Messages with a `type` are room and
+/// subscription requests (see rooms.rs); messages without one are applied
+/// as bare operations.
 use axum::extract::ws::{Message, WebSocket};
+use axum::extract::{Path, Query, State, WebSocketUpgrade};
+use axum::http::HeaderMap;
 use axum::response::IntoResponse;
+use axum::Json;
 use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc, watch};
 
+use super::super::auth;
 use super::super::db::Pool;
+use super::super::notify::{self, Notification, Subscription};
+use super::super::recording::{Direction, Recorder};
+use super::super::rooms::{self, Identity, Rooms, Session};
+use super::super::stack_sync;
+use crate::txn;
 
 /// Shared state for WebSocket handlers.
 pub struct WsState {
     pub pool: Arc<Pool>,
-    pub notify_tx: broadcast::Sender<String>,
+    pub notify_tx: broadcast::Sender<Notification>,
+    /// Log each connection's messages to kerai.session_recordings.
+    pub record_sessions: bool,
+    pub rooms: Rooms,
 }
 
 /// GET /api/ws — WebSocket upgrade
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<WsState>>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, state))
+    let token = query
+        .get("token")
+        .cloned()
+        .or_else(|| auth::extract_session_token(&headers));
+    let identity = rooms::identify(&state.pool, token.as_deref()).await;
+    ws.on_upgrade(move |socket| handle_socket(socket, state, identity))
 }
 
-async fn handle_socket(socket: WebSocket, state: Arc<WsState>) {
+/// GET /api/documents/{id}/presence — who is in a document's room:
+/// `{document_id, members: [{connection, user_id, handle, node_id, mode}]}`.
+pub async fn document_presence(
+    State(state): State<Arc<WsState>>,
+    Path(document_id): Path<String>,
+) -> Json<Value> {
+    let members = state.rooms.present(&document_id);
+    Json(json!({"document_id": document_id, "members": members}))
+}
+
+async fn handle_socket(socket: WebSocket, state: Arc<WsState>, identity: Identity) {
     let (mut sender, mut receiver) = socket.split();
 
     // Subscribe to NOTIFY broadcast
     let mut notify_rx = state.notify_tx.subscribe();
 
-    // Forward notifications to WebSocket client
+    // Replies and room events for this client
+    let (outbox, mut outbox_rx) = mpsc::unbounded_channel::<String>();
+
+    // Workspace whose stack deltas this client follows (set by `subscribe`)
+    let (workspace_tx, workspace_rx) = watch::channel(None::<uuid::Uuid>);
+
+    // The client's change event filters, once it subscribes
+    let (subscription, subscription_rx) = watch::channel::<Option<Subscription>>(None);
+
+    let recorder = state
+        .record_sessions
+        .then(|| Recorder::start(state.pool.clone()));
+    let send_recorder = recorder.clone();
+
+    // Forward notifications and replies to WebSocket client
     let send_task = tokio::spawn(async move {
-        while let Ok(payload) = notify_rx.recv().await {
+        loop {
+            let payload = tokio::select! {
+                notified = notify_rx.recv() => match notified {
+                    Ok(notification) => {
+                        let relayed = if notification.channel == stack_sync::CHANNEL {
+                            let ws = stack_sync::delta_workspace(&notification.payload);
+                            (ws.is_some() && *workspace_rx.borrow() == ws)
+                                .then_some(notification.payload)
+                        } else {
+                            notify::relayed(&notification, subscription_rx.borrow().as_ref())
+                        };
+                        match relayed {
+                            Some(payload) => payload,
+                            None => continue,
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                reply = outbox_rx.recv() => match reply {
+                    Some(payload) => payload,
+                    None => break,
+                },
+            };
+            if let Some(recorder) = &send_recorder {
+                recorder.record(Direction::Out, &payload, None);
+            }
             if sender.send(Message::Text(payload.into())).await.is_err() {
                 break;
             }
         }
     });
 
-    // Receive messages from WebSocket client (operations)
-    let pool = state.pool.clone();
+    // Receive messages from WebSocket client (requests and operations)
+    let connection = state.rooms.connection_id();
+    let recv_state = state.clone();
     let recv_task = tokio::spawn(async move {
+        let pool = &recv_state.pool;
+        let mut session = Session::new(connection, identity, outbox, subscription);
         while let Some(Ok(msg)) = receiver.next().await {
             match msg {
                 Message::Text(text) => {
-                    // Parse as operation and execute
-                    if let Err(e) = handle_client_op(&pool, &text).await {
-                        tracing::warn!("client op error: {}", e);
+                    // Parse as subscription, room request or operation and execute
+                    if let Some((user_id, ws)) = workspace_subscription(pool, &text).await {
+                        let _ = workspace_tx.send(Some(ws));
+                        if let Some(recorder) = &recorder {
+                            recorder.identify(user_id, ws);
+                            let outcome = json!({"workspace_id": ws.to_string()});
+                            recorder.record(Direction::In, &text, Some(outcome));
+                        }
+                        continue;
+                    }
+                    let typed = serde_json::from_str::<Value>(&text)
+                        .ok()
+                        .filter(|msg| msg["type"].is_string());
+                    if let Some(msg) = typed {
+                        let rooms = &recv_state.rooms;
+                        let reply = rooms::handle_message(pool, rooms, &mut session, &msg).await;
+                        if let Some(recorder) = &recorder {
+                            recorder.record(Direction::In, &text, None);
+                        }
+                        if let Some(reply) = reply {
+                            let _ = session.outbox.send(reply.to_string());
+                        }
+                        continue;
+                    }
+                    let outcome = match handle_client_op(pool, &text).await {
+                        Ok(applied) => json!({"node_id": applied["node_id"]}),
+                        Err(e) => {
+                            tracing::warn!("client op error: {}", e);
+                            json!({"error": e})
+                        }
+                    };
+                    if let Some(recorder) = &recorder {
+                        recorder.record(Direction::In, &text, Some(outcome));
                     }
                 }
                 Message::Close(_) => break,
                 _ => {}
             }
         }
+        session.leave(&recv_state.rooms);
     });
 
     // Wait for either task to finish
@@ -60,7 +173,23 @@ async fn handle_socket(socket: WebSocket, state: Arc<WsState>) {
     }
 }
 
-async fn handle_client_op(pool: &Pool, text: &str) -> Result<(), String> {
+/// `{"subscribe": {"session_token": ...}}` — follow the stack deltas of the
+/// session's workspace. Returns the session's (user, workspace), or `None`
+/// for anything else (or a bad session).
+async fn workspace_subscription(pool: &Pool, text: &str) -> Option<(uuid::Uuid, uuid::Uuid)> {
+    let msg: serde_json::Value = serde_json::from_str(text).ok()?;
+    let token = msg.get("subscribe")?.get("session_token")?.as_str()?;
+    match auth::resolve_session(pool, token).await {
+        Ok(ids) => Some(ids),
+        Err(e) => {
+            tracing::warn!("ws subscribe rejected: {}", e);
+            None
+        }
+    }
+}
+
+/// Apply a client operation, returning `kerai.apply_op`'s result.
+async fn handle_client_op(pool: &Pool, text: &str) -> Result<serde_json::Value, String> {
     let op: serde_json::Value = serde_json::from_str(text)
         .map_err(|e| format!("invalid JSON: {}", e))?;
 
@@ -83,8 +212,10 @@ async fn handle_client_op(pool: &Pool, text: &str) -> Result<(), String> {
         payload.to_string().replace('\'', "''"),
     );
 
-    let client = pool.get().await.map_err(|e| e.to_string())?;
-    client.execute(&sql, &[]).await.map_err(|e| e.to_string())?;
+    let mut client = pool.get().await.map_err(|e| e.to_string())?;
+    let row = txn::serializable_one(&mut client, "apply_op", &sql, &[])
+        .await
+        .map_err(|e| e.to_string())?;
 
-    Ok(())
+    Ok(row.get(0))
 }
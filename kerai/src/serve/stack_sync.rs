@@ -0,0 +1,165 @@
+/// Live stack sharing between members of a workspace.
+///
+/// After each eval the serve layer compares the stack it loaded with the one
+/// it saved and publishes the difference on `kerai_stack`, keyed by workspace.
+/// Stack item ids survive a save (see `save_stack`), so other members'
+/// terminals can apply the delta to their copy by id; anything they cannot
+/// reconcile makes them reload the stack instead.
+use std::collections::{HashMap, HashSet};
+
+use serde_json::{json, Value};
+use tokio_postgres::Client;
+
+use crate::lang::ptr::Ptr;
+
+/// Notification channel for stack deltas (payloads carry the workspace id).
+pub const CHANNEL: &str = "kerai_stack";
+
+/// Postgres caps NOTIFY payloads at 8000 bytes; larger deltas go out as a
+/// bare resync request.
+const MAX_PAYLOAD: usize = 7900;
+
+/// Change between two snapshots of a workspace stack.
+#[derive(Debug, Default, PartialEq)]
+pub struct StackDelta {
+    /// Ids no longer on the stack.
+    pub removed: Vec<i64>,
+    /// New items, and kept items whose kind, value or meta changed.
+    pub upserted: Vec<Ptr>,
+    /// Ids of the resulting stack, bottom to top.
+    pub order: Vec<i64>,
+}
+
+/// Diff two stack snapshots by item id; `None` when nothing changed.
+///
+/// A pure reorder (`swap`, `rot`) is a delta with only a new `order`.
+pub fn delta(before: &[Ptr], after: &[Ptr]) -> Option<StackDelta> {
+    let old: HashMap<i64, &Ptr> = before.iter().map(|p| (p.id, p)).collect();
+    let kept: HashSet<i64> = after.iter().map(|p| p.id).collect();
+
+    let d = StackDelta {
+        removed: before.iter().map(|p| p.id).filter(|id| !kept.contains(id)).collect(),
+        upserted: after
+            .iter()
+            .filter(|p| old.get(&p.id).is_none_or(|o| *o != *p))
+            .cloned()
+            .collect(),
+        order: after.iter().map(|p| p.id).collect(),
+    };
+    let reordered = before.iter().map(|p| p.id).ne(d.order.iter().copied());
+    (reordered || !d.removed.is_empty() || !d.upserted.is_empty()).then_some(d)
+}
+
+/// Flag an item as coming from another member, for the UI to tell apart.
+///
+/// Lists keep their elements in `meta`, so they cannot carry the flag.
+fn mark_remote(ptr: &mut Ptr, user_id: uuid::Uuid) {
+    let flag = json!({"user_id": user_id.to_string()});
+    match &mut ptr.meta {
+        Value::Object(obj) => {
+            obj.insert("remote".into(), flag);
+        }
+        meta @ Value::Null => *meta = json!({"remote": flag}),
+        _ => {}
+    }
+}
+
+/// Notification payload for a delta. Falls back to `resync: true` without
+/// items when the full delta would not fit in a NOTIFY.
+pub fn payload(
+    workspace_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+    client_id: &str,
+    input: &str,
+    mut delta: StackDelta,
+) -> String {
+    for ptr in &mut delta.upserted {
+        mark_remote(ptr, user_id);
+    }
+    let head = json!({
+        "type": "stack_delta",
+        "workspace_id": workspace_id.to_string(),
+        "client_id": client_id,
+        "input": input,
+    });
+
+    let mut full = head.clone();
+    full["removed"] = json!(delta.removed);
+    full["upserted"] = json!(delta.upserted);
+    full["order"] = json!(delta.order);
+    let text = full.to_string();
+    if text.len() <= MAX_PAYLOAD {
+        return text;
+    }
+
+    let mut bare = head;
+    bare["resync"] = json!(true);
+    if bare.to_string().len() > MAX_PAYLOAD {
+        // Only an enormous input can get here; members just need to reload
+        bare["input"] = json!("");
+    }
+    bare.to_string()
+}
+
+/// Publish a payload built by [`payload`].
+pub async fn broadcast(client: &Client, payload: &str) -> Result<(), String> {
+    client
+        .execute("SELECT pg_notify($1, $2)", &[&CHANNEL, &payload])
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Workspace a notification payload belongs to, if it is a stack delta.
+pub fn delta_workspace(payload: &str) -> Option<uuid::Uuid> {
+    let value: Value = serde_json::from_str(payload).ok()?;
+    if value["type"] != "stack_delta" {
+        return None;
+    }
+    value["workspace_id"].as_str()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: i64, n: i64) -> Ptr {
+        Ptr { id, ..Ptr::int(n) }
+    }
+
+    #[test]
+    fn delta_tracks_ids() {
+        let before = vec![item(1, 10), item(2, 20), item(3, 30)];
+        let after = vec![item(1, 10), item(3, 31), item(7, 5)];
+        let d = delta(&before, &after).unwrap();
+        assert_eq!(d.removed, vec![2]);
+        assert_eq!(d.upserted, vec![item(3, 31), item(7, 5)]);
+        assert_eq!(d.order, vec![1, 3, 7]);
+    }
+
+    #[test]
+    fn reorder_is_not_empty() {
+        let before = vec![item(1, 10), item(2, 20)];
+        let after = vec![item(2, 20), item(1, 10)];
+        let d = delta(&before, &after).unwrap();
+        assert!(d.upserted.is_empty());
+        assert_eq!(d.order, vec![2, 1]);
+        assert!(delta(&before, &before).is_none());
+    }
+
+    #[test]
+    fn payload_marks_remote_and_falls_back_to_resync() {
+        let ws = uuid::Uuid::nil();
+        let d = delta(&[], &[item(1, 10), Ptr { id: 2, ..Ptr::list(vec![Ptr::int(1)]) }]).unwrap();
+        let v: Value = serde_json::from_str(&payload(ws, ws, "c1", "10", d)).unwrap();
+        assert_eq!(v["type"], "stack_delta");
+        assert!(v["upserted"][0]["meta"]["remote"].is_object());
+        assert!(v["upserted"][1]["meta"].is_array());
+        assert_eq!(delta_workspace(&v.to_string()), Some(ws));
+
+        let big = Ptr { id: 3, ..Ptr::text(&"x".repeat(MAX_PAYLOAD)) };
+        let v: Value = serde_json::from_str(&payload(ws, ws, "c1", "", delta(&[], &[big]).unwrap())).unwrap();
+        assert_eq!(v["resync"], true);
+        assert!(v.get("upserted").is_none());
+    }
+}
@@ -0,0 +1,101 @@
+/// Streamed NDJSON responses — one JSON value per line, written as rows
+/// arrive from Postgres rather than after the whole result is built.
+///
+/// Endpoints opt in with `stream=true`. Rows are read through a
+/// `RowStream`, which pulls from the connection only as the response body
+/// is polled, so a slow reader holds back the query instead of the server
+/// buffering for it. An error after the first row ends the body early;
+/// readers see a truncated stream rather than a status code.
+use axum::body::Body;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::BoxError;
+use futures::{Stream, TryStreamExt};
+use serde_json::Value;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Client;
+
+use super::error::ApiError;
+
+/// Content type of streamed responses.
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// One NDJSON line.
+fn line(value: &Value) -> Vec<u8> {
+    let mut bytes = serde_json::to_vec(value).unwrap_or_default();
+    bytes.push(b'\n');
+    bytes
+}
+
+/// Run `sql`, whose first column is one JSON value per row, and stream the
+/// rows as NDJSON. `client` is kept until the last row is written.
+pub async fn ndjson(
+    client: Client,
+    sql: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<Response, ApiError> {
+    let rows = client.query_raw(sql, params.iter().copied()).await?;
+
+    Ok(respond(rows.map_ok(move |row| {
+        // The stream owns the connection
+        let _ = &client;
+        row.get::<_, Value>(0)
+    })))
+}
+
+/// NDJSON response writing each value of `values` as it is produced.
+fn respond<S, E>(values: S) -> Response
+where
+    S: Stream<Item = Result<Value, E>> + Send + 'static,
+    E: Into<BoxError>,
+{
+    let lines = values.map_ok(|value| line(&value));
+    ([(header::CONTENT_TYPE, NDJSON)], Body::from_stream(lines)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn lines_end_in_newline() {
+        assert_eq!(line(&serde_json::json!({"a": 1})), b"{\"a\":1}\n");
+        assert_eq!(line(&serde_json::json!("x\ny")), b"\"x\\ny\"\n");
+    }
+
+    #[tokio::test]
+    async fn one_value_per_line() {
+        let values = futures::stream::iter([
+            Ok::<_, std::io::Error>(json!({"id": 1, "content": "a\nb"})),
+            Ok(json!({"id": 2})),
+        ]);
+        let response = respond(values);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], NDJSON);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let lines: Vec<Value> = body
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(|l| serde_json::from_slice(l).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [json!({"id": 1, "content": "a\nb"}), json!({"id": 2})]
+        );
+        assert!(body.ends_with(b"\n"));
+    }
+
+    #[tokio::test]
+    async fn error_ends_the_body() {
+        let values = futures::stream::iter([
+            Ok(json!(1)),
+            Err(std::io::Error::other("connection lost")),
+            Ok(json!(2)),
+        ]);
+        let body = respond(values).into_body();
+        assert!(axum::body::to_bytes(body, usize::MAX).await.is_err());
+    }
+}
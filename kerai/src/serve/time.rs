@@ -0,0 +1,141 @@
+/// Resolution of `time_request` markers — date/time expression trees built by
+/// the `now`/`+days`/`diff`/`format` words (and `+`/`-` on temporal operands).
+///
+/// A tree is compiled into a single SQL expression and evaluated with one
+/// query, so results follow Postgres `timestamptz`/`interval` rules and the
+/// session time zone, matching how stored timestamps compare and render.
+use serde_json::Value;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Client;
+
+use crate::lang::ptr::Ptr;
+
+/// Result type of a compiled expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeType {
+    Timestamp,
+    Interval,
+    Text,
+}
+
+/// Compile an expression tree to SQL, appending literal values to `params`.
+///
+/// Literals are bound as text parameters and cast in SQL; day counts are
+/// integers from the tree and inlined.
+pub fn compile(expr: &Value, params: &mut Vec<String>) -> Result<(String, TimeType), String> {
+    let op = expr["op"].as_str().ok_or("time: malformed expression")?;
+    match op {
+        "now" => Ok(("now()".into(), TimeType::Timestamp)),
+        "lit" => {
+            let value = expr["value"].as_str().ok_or("time: literal without value")?;
+            params.push(value.to_string());
+            let n = params.len();
+            match expr["type"].as_str() {
+                Some("interval") => Ok((format!("${n}::text::interval"), TimeType::Interval)),
+                _ => Ok((format!("${n}::text::timestamptz"), TimeType::Timestamp)),
+            }
+        }
+        "add_days" => {
+            let (arg, ty) = compile(&expr["arg"], params)?;
+            if ty == TimeType::Text {
+                return Err("+days: operand is not a timestamp".into());
+            }
+            let days = expr["days"].as_i64().ok_or("+days: missing day count")?;
+            Ok((format!("({arg} + make_interval(days => {days}))"), ty))
+        }
+        "add" | "sub" => {
+            let (a, ta) = compile(&expr["a"], params)?;
+            let (b, tb) = compile(&expr["b"], params)?;
+            let ty = match (op, ta, tb) {
+                ("add", TimeType::Timestamp, TimeType::Interval)
+                | ("add", TimeType::Interval, TimeType::Timestamp)
+                | ("sub", TimeType::Timestamp, TimeType::Interval) => TimeType::Timestamp,
+                ("add", TimeType::Interval, TimeType::Interval)
+                | ("sub", TimeType::Interval, TimeType::Interval)
+                | ("sub", TimeType::Timestamp, TimeType::Timestamp) => TimeType::Interval,
+                _ => return Err(format!("time: cannot {op} {ta:?} and {tb:?}")),
+            };
+            let sym = if op == "add" { "+" } else { "-" };
+            Ok((format!("({a} {sym} {b})"), ty))
+        }
+        "format" => {
+            let (arg, ty) = compile(&expr["arg"], params)?;
+            if ty == TimeType::Text {
+                return Err("format: operand is not a timestamp".into());
+            }
+            let pattern = expr["pattern"].as_str().ok_or("format: missing pattern")?;
+            params.push(pattern.to_string());
+            Ok((format!("to_char({arg}, ${}::text)", params.len()), TimeType::Text))
+        }
+        other => Err(format!("time: unknown op '{other}'")),
+    }
+}
+
+/// Evaluate an expression tree against Postgres, returning the result Ptr.
+pub async fn resolve(client: &Client, expr: &Value) -> Result<Ptr, String> {
+    let mut params = Vec::new();
+    let (sql, ty) = compile(expr, &mut params)?;
+    let refs: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+
+    let row = client
+        .query_one(&format!("SELECT ({sql})::text"), &refs)
+        .await
+        .map_err(|e| e.to_string())?;
+    let text: String = row.get(0);
+
+    Ok(match ty {
+        TimeType::Timestamp => Ptr::timestamp(&text),
+        TimeType::Interval => Ptr::interval(&text),
+        TimeType::Text => Ptr::text(&text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn compile_now_plus_days() {
+        let expr = json!({"op": "add_days", "arg": {"op": "now"}, "days": 7});
+        let mut params = Vec::new();
+        let (sql, ty) = compile(&expr, &mut params).unwrap();
+        assert_eq!(sql, "(now() + make_interval(days => 7))");
+        assert_eq!(ty, TimeType::Timestamp);
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn compile_diff_of_literals_is_interval() {
+        let expr = json!({
+            "op": "sub",
+            "a": {"op": "lit", "type": "timestamp", "value": "2026-01-08"},
+            "b": {"op": "lit", "type": "timestamp", "value": "2026-01-01"},
+        });
+        let mut params = Vec::new();
+        let (sql, ty) = compile(&expr, &mut params).unwrap();
+        assert_eq!(sql, "($1::text::timestamptz - $2::text::timestamptz)");
+        assert_eq!(ty, TimeType::Interval);
+        assert_eq!(params, vec!["2026-01-08", "2026-01-01"]);
+    }
+
+    #[test]
+    fn compile_format_binds_pattern() {
+        let expr = json!({"op": "format", "arg": {"op": "now"}, "pattern": "YYYY-MM-DD"});
+        let mut params = Vec::new();
+        let (sql, ty) = compile(&expr, &mut params).unwrap();
+        assert_eq!(sql, "to_char(now(), $1::text)");
+        assert_eq!(ty, TimeType::Text);
+        assert_eq!(params, vec!["YYYY-MM-DD"]);
+    }
+
+    #[test]
+    fn compile_rejects_interval_minus_timestamp() {
+        let expr = json!({
+            "op": "sub",
+            "a": {"op": "lit", "type": "interval", "value": "1 day"},
+            "b": {"op": "now"},
+        });
+        assert!(compile(&expr, &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+/// API tokens: bearer credentials for the HTTP API.
+///
+/// A token is [`TOKEN_PREFIX`] followed by 64 hex digits, issued by
+/// `kerai.create_api_token`; only its sha256 is kept, in `kerai.api_tokens`.
+/// Each token acts for a user and/or an instance and carries scopes, where
+/// [`Scope::Admin`] implies [`Scope::Write`], which implies [`Scope::Read`].
+///
+/// [`authenticate`] resolves an `Authorization: Bearer kerai_...` header
+/// and attaches the [`ApiIdentity`] to the request's extensions; other
+/// bearer values (session tokens) are left to the routes that read them.
+/// Either kind of token is also the request's row-level security principal
+/// (see [`db::with_principal`]).
+/// [`require_write`], layered inside it, turns away any request that is not
+/// a GET, HEAD or OPTIONS unless its token has the write scope; routes that
+/// write some other way, like WebSocket messages, check [`require_scope`].
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::auth;
+use super::db::{self, Pool};
+use super::error::ApiError;
+
+/// Every API token starts with this, so it can be told from a session token.
+pub const TOKEN_PREFIX: &str = "kerai_";
+
+/// What a token may do; each scope implies the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "read" => Some(Scope::Read),
+            "write" => Some(Scope::Write),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Who a request's API token acts for, attached by [`authenticate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiIdentity {
+    pub token_id: Uuid,
+    pub name: String,
+    pub user_id: Option<Uuid>,
+    pub instance_id: Option<Uuid>,
+    pub scopes: Vec<Scope>,
+}
+
+impl ApiIdentity {
+    /// Whether any of the token's scopes covers `scope`.
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.iter().any(|s| *s >= scope)
+    }
+}
+
+/// The sha256 of `token` in hex, as stored in `kerai.api_tokens`.
+pub fn hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Whether `token` is an API token rather than a session token.
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// The API token in the `Authorization: Bearer` header, if it is one.
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let token = value.strip_prefix("Bearer ")?.trim();
+    is_api_token(token).then_some(token)
+}
+
+/// The live token `token`, marking it used; `None` when it is unknown,
+/// expired or revoked.
+pub async fn lookup(pool: &Pool, token: &str) -> Result<Option<ApiIdentity>, ApiError> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            "UPDATE kerai.api_tokens SET last_used_at = now() \
+             WHERE token_hash = $1 AND revoked_at IS NULL \
+               AND (expires_at IS NULL OR expires_at > now()) \
+             RETURNING id, name, user_id, instance_id, scopes",
+            &[&hash(token)],
+        )
+        .await?;
+    Ok(row.map(|r| ApiIdentity {
+        token_id: r.get(0),
+        name: r.get(1),
+        user_id: r.get(2),
+        instance_id: r.get(3),
+        scopes: r
+            .get::<_, Vec<String>>(4)
+            .iter()
+            .filter_map(|s| Scope::parse(s))
+            .collect(),
+    }))
+}
+
+/// The live token `token`; an unknown, expired or revoked one is refused.
+pub async fn resolve(pool: &Pool, token: &str) -> Result<ApiIdentity, ApiError> {
+    lookup(pool, token)
+        .await?
+        .ok_or_else(|| ApiError::unauthorized("invalid, expired or revoked API token"))
+}
+
+/// Middleware: attach the [`ApiIdentity`] of the request's API token, if it
+/// carries one; an unknown, expired or revoked token is refused outright.
+/// The request's database connections then act for its API or session
+/// token's user under row-level security.
+pub async fn authenticate(
+    State(pool): State<Arc<Pool>>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if let Some(token) = bearer(req.headers()) {
+        let identity = resolve(&pool, token).await?;
+        req.extensions_mut().insert(identity);
+    }
+    let principal = match auth::extract_session_token(req.headers()) {
+        Some(token) => db::check_principal(&pool, &token).await?,
+        None => None,
+    };
+    Ok(db::with_principal(principal, next.run(req)).await)
+}
+
+/// Middleware: require a write-scoped token for anything but a read.
+pub async fn require_write(req: Request, next: Next) -> Result<Response, ApiError> {
+    check_write(req.method(), req.extensions().get::<ApiIdentity>())?;
+    Ok(next.run(req).await)
+}
+
+/// Require an admin: a token with the admin scope, or an admin session.
+/// Returns the issuing user, if known. Nothing is an admin by default over
+/// HTTP; the first admin token comes from `kerai.create_api_token` in SQL.
+pub async fn require_admin(
+    pool: &Pool,
+    identity: Option<&ApiIdentity>,
+    headers: &HeaderMap,
+) -> Result<Option<Uuid>, ApiError> {
+    if let Some(identity) = identity {
+        require_scope(Some(identity), Scope::Admin)?;
+        return Ok(identity.user_id);
+    }
+    let token = auth::extract_session_token(headers)
+        .ok_or_else(|| ApiError::unauthorized("needs an admin API token or session"))?;
+    let (user_id, _workspace_id) = auth::resolve_session(pool, &token)
+        .await
+        .map_err(ApiError::unauthorized)?;
+    // Not resolve_role: it makes everyone admin until one exists.
+    let client = pool.get().await?;
+    let is_admin = client
+        .query_opt(
+            "SELECT is_admin FROM kerai.users WHERE id = $1",
+            &[&user_id],
+        )
+        .await?
+        .is_some_and(|r| r.get::<_, bool>(0));
+    if !is_admin {
+        return Err(ApiError::forbidden("admin only"));
+    }
+    Ok(Some(user_id))
+}
+
+/// Require a token with `scope`: unauthorized without one, forbidden when
+/// its scopes fall short.
+pub fn require_scope(identity: Option<&ApiIdentity>, scope: Scope) -> Result<(), ApiError> {
+    match identity {
+        None => Err(ApiError::unauthorized(format!(
+            "needs an API token with the {} scope (Authorization: Bearer)",
+            scope.as_str()
+        ))),
+        Some(id) if !id.allows(scope) => Err(ApiError::forbidden(format!(
+            "token '{}' lacks the {} scope",
+            id.name,
+            scope.as_str()
+        ))),
+        Some(_) => Ok(()),
+    }
+}
+
+fn check_write(method: &Method, identity: Option<&ApiIdentity>) -> Result<(), ApiError> {
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(());
+    }
+    require_scope(identity, Scope::Write)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serve::config::Config;
+    use crate::serve::routes::build_router;
+    use crate::serve::validate::Limits;
+    use axum::http::StatusCode;
+
+    fn identity(scopes: &[Scope]) -> ApiIdentity {
+        ApiIdentity {
+            token_id: Uuid::nil(),
+            name: "ci".into(),
+            user_id: None,
+            instance_id: None,
+            scopes: scopes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn scopes_imply_the_ones_before() {
+        for s in ["read", "write", "admin"] {
+            assert_eq!(Scope::parse(s).unwrap().as_str(), s);
+        }
+        assert_eq!(Scope::parse("root"), None);
+        let admin = identity(&[Scope::Admin]);
+        assert!(admin.allows(Scope::Read) && admin.allows(Scope::Write));
+        let read = identity(&[Scope::Read]);
+        assert!(read.allows(Scope::Read) && !read.allows(Scope::Write));
+    }
+
+    #[test]
+    fn tokens_are_stored_hashed() {
+        let token = format!("{TOKEN_PREFIX}{}", "ab".repeat(32));
+        let h = hash(&token);
+        assert_eq!(h.len(), 64);
+        assert_eq!(h, hash(&token));
+        assert_ne!(h, hash(&format!("{TOKEN_PREFIX}{}", "ba".repeat(32))));
+        // sha256("kerai_"), as kerai.create_api_token computes it in SQL
+        assert_eq!(
+            hash(TOKEN_PREFIX),
+            "442440dbcb8420189bb904aec443c85f0ca96218d496f198a625957a2e521086"
+        );
+    }
+
+    #[test]
+    fn bearer_only_takes_api_tokens() {
+        let headers = |value: &str| {
+            let mut h = HeaderMap::new();
+            h.insert(header::AUTHORIZATION, value.parse().unwrap());
+            h
+        };
+        assert_eq!(bearer(&headers("Bearer kerai_abc")), Some("kerai_abc"));
+        assert_eq!(bearer(&headers("Bearer 0123abcd")), None);
+        assert_eq!(bearer(&headers("Basic kerai_abc")), None);
+        assert_eq!(bearer(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn require_scope_tells_missing_from_short() {
+        assert_eq!(
+            require_scope(None, Scope::Write).unwrap_err().code,
+            "unauthorized"
+        );
+        let read = identity(&[Scope::Read]);
+        let err = require_scope(Some(&read), Scope::Write).unwrap_err();
+        assert_eq!(err.code, "forbidden");
+        assert!(err.detail.contains("write"), "{}", err.detail);
+        assert!(require_scope(Some(&identity(&[Scope::Write])), Scope::Write).is_ok());
+        assert!(is_api_token("kerai_00") && !is_api_token("0123abcd"));
+    }
+
+    /// A pool that is never connected: these calls must settle without it.
+    fn offline_pool() -> Arc<Pool> {
+        Pool::new(Config::from_env(
+            "host=/nonexistent dbname=kerai",
+            "127.0.0.1:0",
+        ))
+    }
+
+    #[tokio::test]
+    async fn only_admins_issue_tokens() {
+        let pool = offline_pool();
+        let none = HeaderMap::new();
+        let err = require_admin(&pool, None, &none).await.unwrap_err();
+        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
+        let writer = identity(&[Scope::Write]);
+        let err = require_admin(&pool, Some(&writer), &none)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+        let admin = identity(&[Scope::Admin]);
+        assert_eq!(
+            require_admin(&pool, Some(&admin), &none).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_token_post_is_unauthorized() {
+        let (notify_tx, _) = tokio::sync::broadcast::channel(1);
+        let app = build_router(offline_pool(), notify_tx, false, Limits::default());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let res = reqwest::Client::new()
+            .post(format!("http://{addr}/api/tokens"))
+            .json(&serde_json::json!({"name": "first", "scopes": ["admin"]}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body["code"], "unauthorized");
+    }
+
+    #[test]
+    fn writes_need_the_write_scope() {
+        assert!(check_write(&Method::GET, None).is_ok());
+        assert!(check_write(&Method::OPTIONS, None).is_ok());
+        assert_eq!(
+            check_write(&Method::POST, None).unwrap_err().code,
+            "unauthorized"
+        );
+        let read = identity(&[Scope::Read]);
+        assert_eq!(
+            check_write(&Method::DELETE, Some(&read)).unwrap_err().code,
+            "forbidden"
+        );
+        assert!(check_write(&Method::PATCH, Some(&identity(&[Scope::Write]))).is_ok());
+        assert!(check_write(&Method::POST, Some(&identity(&[Scope::Admin]))).is_ok());
+    }
+}
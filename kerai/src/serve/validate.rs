@@ -0,0 +1,438 @@
+/// Request validation that runs before a handler touches the database.
+///
+/// [`ValidJson`] replaces `axum::Json` for request bodies. It refuses a
+/// body that is not JSON (415) or larger than the route allows (413), and
+/// then checks the parsed value against [`Limits`] before deserializing
+/// it: nesting depth, elements in any one array (the batch size of
+/// `/edges/batch`, `/perspectives/batch`, `/sync/push` and
+/// `/workspace/import`), and bytes in any one string (node content). A body that breaks one gets a 422 naming
+/// the limit and the JSON pointer where it was broken.
+///
+/// Limits come from the environment when `kerai serve` starts
+/// (`KERAI_MAX_BODY_BYTES`, `KERAI_MAX_JSON_DEPTH`, `KERAI_MAX_BATCH`,
+/// `KERAI_MAX_CONTENT_BYTES`) and reach the extractor as a request
+/// extension, so a route can be given its own with
+/// `.layer(Extension(limits))`.
+///
+/// [`ltree`] and [`lquery`] check path syntax the same way, so a bad path
+/// is reported with the offending character rather than as Postgres's
+/// syntax error.
+use axum::extract::{FromRequest, Request};
+use axum::http::{header, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::error::ApiError;
+
+/// Per-request limits on JSON bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_body_bytes: usize,
+    pub max_depth: usize,
+    pub max_batch: usize,
+    pub max_content_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: 8 << 20,
+            max_depth: 64,
+            max_batch: 10_000,
+            max_content_bytes: 1 << 20,
+        }
+    }
+}
+
+impl Limits {
+    /// Defaults overridden by any `KERAI_MAX_*` variable that is set.
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        let d = Self::default();
+        Self {
+            max_body_bytes: var("KERAI_MAX_BODY_BYTES", d.max_body_bytes),
+            max_depth: var("KERAI_MAX_JSON_DEPTH", d.max_depth),
+            max_batch: var("KERAI_MAX_BATCH", d.max_batch),
+            max_content_bytes: var("KERAI_MAX_CONTENT_BYTES", d.max_content_bytes),
+        }
+    }
+
+    /// Limits for routes that take a whole workspace or sync batch at once:
+    /// eight times the body size and batch.
+    pub fn bulk(self) -> Self {
+        Self {
+            max_body_bytes: self.max_body_bytes.saturating_mul(8),
+            max_batch: self.max_batch.saturating_mul(8),
+            ..self
+        }
+    }
+
+    /// Check a parsed body against the depth, batch and content limits.
+    pub fn check(&self, value: &Value) -> Result<(), ApiError> {
+        self.walk(value, 1, &mut String::new())
+    }
+
+    fn walk(&self, value: &Value, depth: usize, pointer: &mut String) -> Result<(), ApiError> {
+        if depth > self.max_depth {
+            return Err(invalid(
+                "too_deep",
+                format!(
+                    "{} is nested deeper than {} levels",
+                    at(pointer),
+                    self.max_depth
+                ),
+            ));
+        }
+        let len = pointer.len();
+        match value {
+            Value::String(s) if s.len() > self.max_content_bytes => Err(invalid(
+                "content_too_large",
+                format!(
+                    "{} is {} bytes, more than the {} allowed",
+                    at(pointer),
+                    s.len(),
+                    self.max_content_bytes
+                ),
+            )),
+            Value::Array(items) => {
+                if items.len() > self.max_batch {
+                    return Err(invalid(
+                        "batch_too_large",
+                        format!(
+                            "{} has {} elements, more than the {} allowed",
+                            at(pointer),
+                            items.len(),
+                            self.max_batch
+                        ),
+                    ));
+                }
+                for (i, item) in items.iter().enumerate() {
+                    pointer.push_str(&format!("/{i}"));
+                    self.walk(item, depth + 1, pointer)?;
+                    pointer.truncate(len);
+                }
+                Ok(())
+            }
+            Value::Object(fields) => {
+                for (key, field) in fields {
+                    pointer.push('/');
+                    pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+                    self.walk(field, depth + 1, pointer)?;
+                    pointer.truncate(len);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How a JSON pointer reads in an error message.
+fn at(pointer: &str) -> String {
+    if pointer.is_empty() {
+        "the body".into()
+    } else {
+        format!("'{pointer}'")
+    }
+}
+
+fn invalid(code: &'static str, detail: impl Into<String>) -> ApiError {
+    ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, code, detail)
+}
+
+/// A JSON request body that passed the route's [`Limits`].
+pub struct ValidJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        let limits = req
+            .extensions()
+            .get::<Limits>()
+            .copied()
+            .unwrap_or_default();
+
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        if !is_json(content_type) {
+            return Err(ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                format!("expected Content-Type: application/json, got '{content_type}'"),
+            ));
+        }
+
+        let bytes = axum::body::to_bytes(req.into_body(), limits.max_body_bytes)
+            .await
+            .map_err(|_| {
+                ApiError::new(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "payload_too_large",
+                    format!("body is larger than {} bytes", limits.max_body_bytes),
+                )
+            })?;
+        let value: Value = serde_json::from_slice(&bytes)
+            .map_err(|e| invalid("invalid_json", format!("body is not valid JSON: {e}")))?;
+        limits.check(&value)?;
+        let parsed = serde_json::from_value(value)
+            .map_err(|e| invalid("invalid_body", format!("body has the wrong shape: {e}")))?;
+        Ok(ValidJson(parsed))
+    }
+}
+
+/// `application/json`, or any `+json` type, with or without parameters.
+fn is_json(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    essence.eq_ignore_ascii_case("application/json")
+        || essence.to_ascii_lowercase().ends_with("+json")
+}
+
+/// Longest label ltree accepts.
+const MAX_LABEL: usize = 1000;
+
+fn is_label_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+fn bad_path(what: &str, path: &str, detail: String) -> ApiError {
+    invalid(
+        "invalid_path",
+        format!("'{path}' is not a valid {what}: {detail}"),
+    )
+}
+
+/// Check that `path` is a valid ltree: dot-separated labels of letters,
+/// digits, `_` and `-`. The empty path (the root) is allowed.
+pub fn ltree(path: &str) -> Result<(), ApiError> {
+    if path.is_empty() {
+        return Ok(());
+    }
+    let mut offset = 0;
+    for label in path.split('.') {
+        check_label(label, offset).map_err(|detail| bad_path("ltree path", path, detail))?;
+        offset += label.len() + 1;
+    }
+    Ok(())
+}
+
+fn check_label(label: &str, offset: usize) -> Result<(), String> {
+    if label.is_empty() {
+        return Err(format!("empty label at position {offset}"));
+    }
+    if label.len() > MAX_LABEL {
+        return Err(format!(
+            "label at position {offset} is longer than {MAX_LABEL}"
+        ));
+    }
+    match label.char_indices().find(|&(_, c)| !is_label_char(c)) {
+        Some((i, c)) => Err(format!("unexpected '{c}' at position {}", offset + i)),
+        None => Ok(()),
+    }
+}
+
+/// Check that `pattern` is a valid lquery: dot-separated levels, each `*`
+/// or `!`-optional alternatives of labels joined by `|`, labels optionally
+/// followed by `@`, `*` or `%`, any level optionally followed by a
+/// `{n}`, `{n,}`, `{,m}` or `{n,m}` quantifier.
+pub fn lquery(pattern: &str) -> Result<(), ApiError> {
+    let mut offset = 0;
+    for level in pattern.split('.') {
+        check_level(level, offset).map_err(|detail| bad_path("lquery", pattern, detail))?;
+        offset += level.len() + 1;
+    }
+    Ok(())
+}
+
+fn check_level(level: &str, offset: usize) -> Result<(), String> {
+    let (body, quantifier) = match level.find('{') {
+        Some(i) => (&level[..i], Some((&level[i..], offset + i))),
+        None => (level, None),
+    };
+    if let Some((q, at)) = quantifier {
+        check_quantifier(q, at)?;
+    }
+    if body == "*" {
+        return Ok(());
+    }
+    let (body, start) = match body.strip_prefix('!') {
+        Some(rest) => (rest, offset + 1),
+        None => (body, offset),
+    };
+    let mut at = start;
+    for alternative in body.split('|') {
+        let label = alternative.trim_end_matches(['@', '*', '%']);
+        check_label(label, at)?;
+        at += alternative.len() + 1;
+    }
+    Ok(())
+}
+
+fn check_quantifier(q: &str, at: usize) -> Result<(), String> {
+    let inner = q
+        .strip_prefix('{')
+        .and_then(|q| q.strip_suffix('}'))
+        .ok_or_else(|| format!("unterminated quantifier at position {at}"))?;
+    let mut bounds = inner.splitn(2, ',');
+    let low = bounds.next().unwrap_or("");
+    let high = bounds.next();
+    let number = |s: &str| s.is_empty() || s.chars().all(|c| c.is_ascii_digit());
+    if !number(low) || !high.is_none_or(number) || (low.is_empty() && high.is_none()) {
+        return Err(format!("malformed quantifier '{q}' at position {at}"));
+    }
+    Ok(())
+}
+
+/// Check `pattern` as an lquery when it uses lquery syntax, otherwise as an
+/// ltree path; the rule kerai.subscribe applies to subscription paths.
+pub fn path_or_lquery(pattern: &str) -> Result<(), ApiError> {
+    if pattern.contains(['*', '|', '!', '{']) {
+        lquery(pattern)
+    } else {
+        ltree(pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn small() -> Limits {
+        Limits {
+            max_body_bytes: 1024,
+            max_depth: 3,
+            max_batch: 2,
+            max_content_bytes: 5,
+        }
+    }
+
+    #[test]
+    fn depth_limit_names_the_pointer() {
+        assert!(small().check(&json!({"a": {"b": 1}})).is_ok());
+        let err = small().check(&json!({"a": {"b": {"c": 1}}})).unwrap_err();
+        assert_eq!(err.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(err.code, "too_deep");
+        assert!(err.detail.contains("'/a/b/c'"), "{}", err.detail);
+    }
+
+    #[test]
+    fn batch_and_content_limits() {
+        let err = small().check(&json!({"ops": [1, 2, 3]})).unwrap_err();
+        assert_eq!(err.code, "batch_too_large");
+        assert!(
+            err.detail.contains("'/ops' has 3 elements"),
+            "{}",
+            err.detail
+        );
+        let err = small()
+            .check(&json!([{"content": "too long"}]))
+            .unwrap_err();
+        assert_eq!(err.code, "content_too_large");
+        assert!(err.detail.contains("'/0/content'"), "{}", err.detail);
+    }
+
+    #[test]
+    fn bulk_raises_body_and_batch_only() {
+        let bulk = small().bulk();
+        assert_eq!(bulk.max_body_bytes, 8192);
+        assert_eq!(bulk.max_batch, 16);
+        assert_eq!(bulk.max_depth, 3);
+        assert_eq!(bulk.max_content_bytes, 5);
+    }
+
+    async fn extract(content_type: &str, body: &str) -> Result<Value, ApiError> {
+        let mut req = Request::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        req.extensions_mut().insert(Limits {
+            max_body_bytes: 32,
+            ..small()
+        });
+        ValidJson::<Value>::from_request(req, &())
+            .await
+            .map(|ValidJson(v)| v)
+    }
+
+    #[tokio::test]
+    async fn extractor_rejects_before_parsing() {
+        assert_eq!(
+            extract("application/json", r#"{"a": 1}"#).await.unwrap(),
+            json!({"a": 1})
+        );
+        let err = extract("text/plain", "{}").await.unwrap_err();
+        assert_eq!(err.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let err = extract("application/json", &format!("[\"{}\"]", "x".repeat(40))).await;
+        assert_eq!(err.unwrap_err().status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            extract("application/json", "{").await.unwrap_err().code,
+            "invalid_json"
+        );
+    }
+
+    #[test]
+    fn json_content_types() {
+        assert!(is_json("application/json"));
+        assert!(is_json("application/json; charset=utf-8"));
+        assert!(is_json("application/merge-patch+json"));
+        assert!(!is_json("text/plain"));
+        assert!(!is_json(""));
+    }
+
+    #[test]
+    fn ltree_paths() {
+        assert!(ltree("").is_ok());
+        assert!(ltree("docs.api_v2.my-notes").is_ok());
+        let err = ltree("docs..api").unwrap_err();
+        assert_eq!(err.code, "invalid_path");
+        assert!(
+            err.detail.contains("empty label at position 5"),
+            "{}",
+            err.detail
+        );
+        let err = ltree("docs.a b").unwrap_err();
+        assert!(
+            err.detail.contains("unexpected ' ' at position 6"),
+            "{}",
+            err.detail
+        );
+        assert!(ltree(&"a".repeat(1001)).is_err());
+    }
+
+    #[test]
+    fn lquery_patterns() {
+        for ok in [
+            "*.parser.*",
+            "docs.*{1,2}",
+            "!tests.*",
+            "a|b%|c@.*",
+            "*{,3}",
+            "top*.x{2}",
+        ] {
+            assert!(lquery(ok).is_ok(), "{ok}");
+        }
+        for bad in ["docs.*{1", "a.{x}", "a||b", "a.", "*{}"] {
+            assert!(lquery(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn subscription_paths_pick_the_syntax() {
+        assert!(path_or_lquery("docs.api").is_ok());
+        assert!(path_or_lquery("*.api").is_ok());
+        assert!(path_or_lquery("docs api").is_err());
+    }
+}
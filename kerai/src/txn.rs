@@ -0,0 +1,160 @@
+//! How writes run: one SERIALIZABLE transaction per operation, retried
+//! when Postgres aborts it to keep concurrent writers apart.
+//!
+//! Parsing a file and applying a sync batch or an operation all delete,
+//! insert and relink many rows from one function call. Under READ
+//! COMMITTED each statement inside it sees a fresh snapshot, so two such
+//! calls racing over the same file could both clear it and both insert,
+//! or trip the unique edge index half way through. SERIALIZABLE turns
+//! every such race into a serialization failure (40001) on one side, and
+//! that side, like the loser of a deadlock (40P01), is simply run again:
+//! an aborted transaction left nothing behind. After [`MAX_ATTEMPTS`] the
+//! error goes back to the caller.
+//!
+//! [`stats`] counts transactions and retries for the whole process;
+//! `kerai serve` reports them at `/api/health` and logs each retry.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use postgres::error::SqlState;
+use postgres::{IsolationLevel, Transaction};
+use rand::Rng;
+use serde_json::{json, Value};
+
+/// Attempts per operation, the first included.
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry; doubled on each one after.
+const BASE_BACKOFF_MS: u64 = 20;
+
+static TRANSACTIONS: AtomicU64 = AtomicU64::new(0);
+static RETRIES: AtomicU64 = AtomicU64::new(0);
+static EXHAUSTED: AtomicU64 = AtomicU64::new(0);
+
+/// Whether `e` aborted the transaction only to keep it apart from a
+/// concurrent one, so running it again can succeed.
+pub fn retryable(e: &postgres::Error) -> bool {
+    matches!(
+        e.code(),
+        Some(&SqlState::T_R_SERIALIZATION_FAILURE) | Some(&SqlState::T_R_DEADLOCK_DETECTED)
+    )
+}
+
+/// Wait before retry number `retry` (from 1): exponential, with jitter so
+/// the writers that collided do not collide again.
+pub fn backoff(retry: u32) -> Duration {
+    let ceiling = BASE_BACKOFF_MS << retry.saturating_sub(1).min(6);
+    Duration::from_millis(rand::thread_rng().gen_range(ceiling / 2..=ceiling))
+}
+
+/// Process-wide counts: `{transactions, retries, exhausted, retryRate}`,
+/// `retryRate` being retries per transaction.
+pub fn stats() -> Value {
+    let transactions = TRANSACTIONS.load(Ordering::Relaxed);
+    let retries = RETRIES.load(Ordering::Relaxed);
+    json!({
+        "transactions": transactions,
+        "retries": retries,
+        "exhausted": EXHAUSTED.load(Ordering::Relaxed),
+        "retryRate": if transactions == 0 { 0.0 } else { retries as f64 / transactions as f64 },
+    })
+}
+
+fn record(outcome: &Result<impl Sized, postgres::Error>, retries: u32) {
+    TRANSACTIONS.fetch_add(1, Ordering::Relaxed);
+    RETRIES.fetch_add(retries as u64, Ordering::Relaxed);
+    if matches!(outcome, Err(e) if retryable(e)) {
+        EXHAUSTED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Run `op` in a SERIALIZABLE transaction and commit it, running it again
+/// from the start when it is aborted by a concurrent writer.
+pub fn serializable<T>(
+    client: &mut postgres::Client,
+    what: &str,
+    mut op: impl FnMut(&mut Transaction<'_>) -> Result<T, postgres::Error>,
+) -> Result<T, postgres::Error> {
+    let mut retries = 0;
+    loop {
+        let outcome = client
+            .build_transaction()
+            .isolation_level(IsolationLevel::Serializable)
+            .start()
+            .and_then(|mut tx| {
+                let value = op(&mut tx)?;
+                tx.commit()?;
+                Ok(value)
+            });
+        match outcome {
+            Err(e) if retryable(&e) && retries + 1 < MAX_ATTEMPTS => {
+                retries += 1;
+                log_retry(what, retries, &e);
+                std::thread::sleep(backoff(retries));
+            }
+            outcome => {
+                record(&outcome, retries);
+                return outcome;
+            }
+        }
+    }
+}
+
+/// Run one statement as [`serializable`] does, on a `tokio_postgres`
+/// connection, returning its only row.
+pub async fn serializable_one(
+    client: &mut tokio_postgres::Client,
+    what: &str,
+    sql: &str,
+    params: &[&(dyn tokio_postgres::types::ToSql + Sync)],
+) -> Result<tokio_postgres::Row, postgres::Error> {
+    let mut retries = 0;
+    loop {
+        let outcome = async {
+            let tx = client
+                .build_transaction()
+                .isolation_level(IsolationLevel::Serializable)
+                .start()
+                .await?;
+            let row = tx.query_one(sql, params).await?;
+            tx.commit().await?;
+            Ok(row)
+        }
+        .await;
+        match outcome {
+            Err(e) if retryable(&e) && retries + 1 < MAX_ATTEMPTS => {
+                retries += 1;
+                log_retry(what, retries, &e);
+                tokio::time::sleep(backoff(retries)).await;
+            }
+            outcome => {
+                record(&outcome, retries);
+                return outcome;
+            }
+        }
+    }
+}
+
+fn log_retry(what: &str, retry: u32, e: &postgres::Error) {
+    tracing::warn!("{} retry {}/{} after: {}", what, retry, MAX_ATTEMPTS - 1, e);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_within_jitter() {
+        for retry in 1..=4 {
+            let ceiling = BASE_BACKOFF_MS << (retry - 1);
+            let wait = backoff(retry).as_millis() as u64;
+            assert!(
+                (ceiling / 2..=ceiling).contains(&wait),
+                "retry {retry}: {wait}ms"
+            );
+        }
+        // Capped, however many retries
+        assert!(backoff(40) <= Duration::from_millis(BASE_BACKOFF_MS << 6));
+    }
+}
@@ -0,0 +1,37 @@
+//! wasm-bindgen exports of `preview` for the web editor, built with
+//! `wasm-pack build kerai --target web --no-default-features --features wasm`.
+//! Everything crosses the boundary as JSON text.
+use wasm_bindgen::prelude::*;
+
+use crate::preview;
+use crate::lang::ptr::Ptr;
+
+/// Tokens of each line of `source`.
+#[wasm_bindgen]
+pub fn tokenize(source: &str) -> String {
+    preview::tokens(source).to_string()
+}
+
+/// Parse tree of `source`.
+#[wasm_bindgen]
+pub fn parse(source: &str) -> String {
+    preview::parse(source).to_string()
+}
+
+/// Bracket errors in `source`: `{ok, errors: [{line, message}]}`.
+#[wasm_bindgen]
+pub fn check(source: &str) -> String {
+    preview::check(source).to_string()
+}
+
+/// Run `input` with the pure words, continuing from `stack_json` (the
+/// `stack` of a previous result, or empty).
+#[wasm_bindgen]
+pub fn execute(input: &str, stack_json: &str) -> Result<String, JsError> {
+    let stack: Vec<Ptr> = if stack_json.trim().is_empty() {
+        Vec::new()
+    } else {
+        serde_json::from_str(stack_json).map_err(|e| JsError::new(&format!("invalid stack: {e}")))?
+    };
+    Ok(preview::execute(input, stack).to_string())
+}
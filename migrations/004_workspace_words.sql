@@ -0,0 +1,10 @@
+-- Migration: Add per-workspace user word definitions (carried in workspace bundles)
+-- Apply with: psql -d kerai -f migrations/004_workspace_words.sql
+
+CREATE TABLE IF NOT EXISTS kerai.workspace_words (
+    workspace_id   UUID NOT NULL REFERENCES kerai.workspaces(id) ON DELETE CASCADE,
+    name           TEXT NOT NULL,
+    body           TEXT NOT NULL,
+    created_at     TIMESTAMPTZ DEFAULT now(),
+    PRIMARY KEY (workspace_id, name)
+);
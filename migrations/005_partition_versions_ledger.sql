@@ -0,0 +1,108 @@
+-- Migration: Range-partition kerai.versions and kerai.ledger by timestamp
+-- Rebuilds each table as a partitioned table when it is still a plain one,
+-- copies the rows into its default partition, then lets
+-- kerai.create_partitions() split them into timestamp ranges. Run it in a
+-- quiet period: both tables are locked while they are copied.
+-- Apply with: psql -d kerai -f migrations/005_partition_versions_ledger.sql
+
+BEGIN;
+
+DO $$
+BEGIN
+    IF (SELECT relkind FROM pg_class WHERE oid = 'kerai.versions'::regclass) = 'r' THEN
+        -- Columns older installs may predate; copied below
+        ALTER TABLE kerai.versions
+            ADD COLUMN IF NOT EXISTS old_snapshot JSONB,
+            ADD COLUMN IF NOT EXISTS new_snapshot JSONB,
+            ADD COLUMN IF NOT EXISTS branch_id UUID,
+            ADD COLUMN IF NOT EXISTS merged_from UUID;
+        ALTER TABLE kerai.versions RENAME TO versions_unpartitioned;
+        ALTER TABLE kerai.versions_unpartitioned RENAME CONSTRAINT versions_pkey TO versions_unpartitioned_pkey;
+        DROP INDEX IF EXISTS kerai.idx_versions_node, kerai.idx_versions_instance,
+            kerai.idx_versions_timestamp, kerai.idx_versions_author,
+            kerai.idx_versions_node_timestamp, kerai.idx_versions_branch_timestamp;
+
+        CREATE TABLE kerai.versions (
+            id          UUID NOT NULL DEFAULT gen_random_uuid(),
+            node_id     UUID NOT NULL,
+            instance_id UUID NOT NULL REFERENCES kerai.instances(id),
+            operation   TEXT NOT NULL,
+            old_parent  UUID,
+            new_parent  UUID,
+            old_position INTEGER,
+            new_position INTEGER,
+            old_content TEXT,
+            new_content TEXT,
+            old_snapshot JSONB,
+            new_snapshot JSONB,
+            branch_id   UUID,           -- kerai.branches FK added by 044_branches.sql
+            merged_from UUID,
+            author      TEXT NOT NULL,
+            timestamp   BIGINT NOT NULL,
+            signature   BYTEA,
+            created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+            PRIMARY KEY (id, timestamp)
+        ) PARTITION BY RANGE (timestamp);
+        CREATE TABLE kerai.versions_default PARTITION OF kerai.versions DEFAULT;
+
+        CREATE INDEX idx_versions_node ON kerai.versions (node_id);
+        CREATE INDEX idx_versions_instance ON kerai.versions (instance_id);
+        CREATE INDEX idx_versions_timestamp ON kerai.versions (timestamp);
+        CREATE INDEX idx_versions_author ON kerai.versions (author);
+        CREATE INDEX idx_versions_node_timestamp ON kerai.versions (node_id, timestamp);
+        CREATE INDEX idx_versions_branch_timestamp ON kerai.versions (branch_id, timestamp);
+
+        INSERT INTO kerai.versions (id, node_id, instance_id, operation, old_parent, new_parent,
+                old_position, new_position, old_content, new_content, old_snapshot, new_snapshot,
+                branch_id, merged_from, author, timestamp, signature, created_at)
+            SELECT id, node_id, instance_id, operation, old_parent, new_parent,
+                old_position, new_position, old_content, new_content, old_snapshot, new_snapshot,
+                branch_id, merged_from, author, timestamp, signature, created_at
+            FROM kerai.versions_unpartitioned;
+        DROP TABLE kerai.versions_unpartitioned;
+    END IF;
+
+    IF (SELECT relkind FROM pg_class WHERE oid = 'kerai.ledger'::regclass) = 'r' THEN
+        ALTER TABLE kerai.ledger RENAME TO ledger_unpartitioned;
+        ALTER TABLE kerai.ledger_unpartitioned RENAME CONSTRAINT ledger_pkey TO ledger_unpartitioned_pkey;
+        DROP INDEX IF EXISTS kerai.idx_ledger_from, kerai.idx_ledger_to, kerai.idx_ledger_reason,
+            kerai.idx_ledger_timestamp, kerai.idx_ledger_reference;
+
+        CREATE TABLE kerai.ledger (
+            id              UUID NOT NULL DEFAULT gen_random_uuid(),
+            from_wallet     UUID REFERENCES kerai.wallets(id),
+            to_wallet       UUID NOT NULL REFERENCES kerai.wallets(id),
+            amount          BIGINT NOT NULL CHECK (amount > 0),
+            reason          TEXT NOT NULL,
+            reference_id    UUID,
+            reference_type  TEXT,
+            signature       BYTEA,
+            timestamp       BIGINT NOT NULL,
+            created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+            PRIMARY KEY (id, timestamp)
+        ) PARTITION BY RANGE (timestamp);
+        CREATE TABLE kerai.ledger_default PARTITION OF kerai.ledger DEFAULT;
+
+        CREATE INDEX idx_ledger_from ON kerai.ledger (from_wallet);
+        CREATE INDEX idx_ledger_to ON kerai.ledger (to_wallet);
+        CREATE INDEX idx_ledger_reason ON kerai.ledger (reason);
+        CREATE INDEX idx_ledger_timestamp ON kerai.ledger (timestamp);
+        CREATE INDEX idx_ledger_reference
+            ON kerai.ledger (reference_type, reference_id)
+            WHERE reference_id IS NOT NULL;
+
+        INSERT INTO kerai.ledger (id, from_wallet, to_wallet, amount, reason, reference_id,
+                reference_type, signature, timestamp, created_at)
+            SELECT id, from_wallet, to_wallet, amount, reason, reference_id,
+                reference_type, signature, timestamp, created_at
+            FROM kerai.ledger_unpartitioned;
+        DROP TABLE kerai.ledger_unpartitioned;
+    END IF;
+END
+$$;
+
+-- Each call creates at most 100 ranges per table; the partition worker
+-- finishes any remainder on its next passes.
+SELECT kerai.create_partitions();
+
+COMMIT;
@@ -0,0 +1,138 @@
+-- Migration: Add the versioned public API views (kerai.v1_nodes, v1_edges,
+-- v1_history, v1_balances), their registry and the kerai_reader role.
+-- Grant read access with: GRANT kerai_reader TO <role>;
+-- Apply with: psql -d kerai -f migrations/006_public_api_views.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.api_views (
+    name          TEXT PRIMARY KEY,
+    version       INTEGER NOT NULL,
+    description   TEXT NOT NULL,
+    introduced_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    deprecated_at TIMESTAMPTZ,
+    replaced_by   TEXT,
+    sunset_at     TIMESTAMPTZ
+);
+
+CREATE OR REPLACE VIEW kerai.v1_nodes AS
+SELECT
+    n.id,
+    n.instance_id,
+    n.kind,
+    n.language,
+    n.content,
+    n.parent_id,
+    n.position,
+    n.path::text AS path,
+    n.metadata,
+    n.created_at
+FROM kerai.nodes n;
+
+COMMENT ON VIEW kerai.v1_nodes IS 'Stable API v1: AST and document nodes';
+COMMENT ON COLUMN kerai.v1_nodes.id IS 'Node UUID';
+COMMENT ON COLUMN kerai.v1_nodes.instance_id IS 'Instance that created the node';
+COMMENT ON COLUMN kerai.v1_nodes.kind IS 'Node kind, e.g. fn, struct, heading, paragraph';
+COMMENT ON COLUMN kerai.v1_nodes.language IS 'Source language, NULL for non-code nodes';
+COMMENT ON COLUMN kerai.v1_nodes.content IS 'Node text (name for code items)';
+COMMENT ON COLUMN kerai.v1_nodes.parent_id IS 'Parent node, NULL for roots';
+COMMENT ON COLUMN kerai.v1_nodes.position IS 'Order among siblings';
+COMMENT ON COLUMN kerai.v1_nodes.path IS 'Dotted ltree path as text';
+COMMENT ON COLUMN kerai.v1_nodes.metadata IS 'Kind-specific attributes (JSON object)';
+COMMENT ON COLUMN kerai.v1_nodes.created_at IS 'Creation time';
+
+CREATE OR REPLACE VIEW kerai.v1_edges AS
+SELECT
+    e.id,
+    e.source_id,
+    e.target_id,
+    e.relation,
+    e.metadata,
+    e.created_at
+FROM kerai.edges e;
+
+COMMENT ON VIEW kerai.v1_edges IS 'Stable API v1: typed relationships between nodes';
+COMMENT ON COLUMN kerai.v1_edges.id IS 'Edge UUID';
+COMMENT ON COLUMN kerai.v1_edges.source_id IS 'Node the edge starts at';
+COMMENT ON COLUMN kerai.v1_edges.target_id IS 'Node the edge points to';
+COMMENT ON COLUMN kerai.v1_edges.relation IS 'Relation name, e.g. calls, imports, depends_on';
+COMMENT ON COLUMN kerai.v1_edges.metadata IS 'Relation-specific attributes (JSON object)';
+COMMENT ON COLUMN kerai.v1_edges.created_at IS 'Creation time';
+
+CREATE OR REPLACE VIEW kerai.v1_history AS
+SELECT
+    v.id,
+    v.node_id,
+    v.operation,
+    v.author,
+    v.timestamp AS lamport_ts,
+    v.branch_id,
+    v.old_parent,
+    v.new_parent,
+    v.old_content,
+    v.new_content,
+    v.created_at
+FROM kerai.versions v;
+
+COMMENT ON VIEW kerai.v1_history IS 'Stable API v1: per-node change history';
+COMMENT ON COLUMN kerai.v1_history.id IS 'Version UUID';
+COMMENT ON COLUMN kerai.v1_history.node_id IS 'Node the change applies to';
+COMMENT ON COLUMN kerai.v1_history.operation IS 'Operation type, e.g. insert_node, update_content';
+COMMENT ON COLUMN kerai.v1_history.author IS 'Key fingerprint of the authoring instance';
+COMMENT ON COLUMN kerai.v1_history.lamport_ts IS 'Lamport timestamp; orders changes across instances';
+COMMENT ON COLUMN kerai.v1_history.branch_id IS 'Branch the change was made on, NULL for the main line';
+COMMENT ON COLUMN kerai.v1_history.old_parent IS 'Parent before the change';
+COMMENT ON COLUMN kerai.v1_history.new_parent IS 'Parent after the change';
+COMMENT ON COLUMN kerai.v1_history.old_content IS 'Content before the change';
+COMMENT ON COLUMN kerai.v1_history.new_content IS 'Content after the change';
+COMMENT ON COLUMN kerai.v1_history.created_at IS 'Wall-clock time the change was recorded';
+
+CREATE OR REPLACE VIEW kerai.v1_balances AS
+SELECT
+    w.id AS wallet_id,
+    w.key_fingerprint,
+    w.wallet_type,
+    w.label,
+    COALESCE(t.received, 0) - COALESCE(t.sent, 0) AS balance_nkoi,
+    t.last_activity_at
+FROM kerai.wallets w
+LEFT JOIN (
+    SELECT wallet_id,
+           SUM(amount) FILTER (WHERE dir = 'in')::bigint AS received,
+           SUM(amount) FILTER (WHERE dir = 'out')::bigint AS sent,
+           max(created_at) AS last_activity_at
+    FROM (
+        SELECT to_wallet AS wallet_id, amount, created_at, 'in' AS dir FROM kerai.ledger
+        UNION ALL
+        SELECT from_wallet, amount, created_at, 'out' FROM kerai.ledger WHERE from_wallet IS NOT NULL
+    ) moves
+    GROUP BY wallet_id
+) t ON t.wallet_id = w.id;
+
+COMMENT ON VIEW kerai.v1_balances IS 'Stable API v1: wallet balances derived from the ledger';
+COMMENT ON COLUMN kerai.v1_balances.wallet_id IS 'Wallet UUID';
+COMMENT ON COLUMN kerai.v1_balances.key_fingerprint IS 'Fingerprint of the wallet key';
+COMMENT ON COLUMN kerai.v1_balances.wallet_type IS 'instance, human, agent, external or system';
+COMMENT ON COLUMN kerai.v1_balances.label IS 'Display label';
+COMMENT ON COLUMN kerai.v1_balances.balance_nkoi IS 'Balance in nKoi (1 Koi = 10^9 nKoi)';
+COMMENT ON COLUMN kerai.v1_balances.last_activity_at IS 'Time of the latest ledger entry, NULL if none';
+
+INSERT INTO kerai.api_views (name, version, description) VALUES
+    ('v1_nodes', 1, 'AST and document nodes'),
+    ('v1_edges', 1, 'Typed relationships between nodes'),
+    ('v1_history', 1, 'Per-node change history'),
+    ('v1_balances', 1, 'Wallet balances derived from the ledger')
+ON CONFLICT (name) DO NOTHING;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'kerai_reader') THEN
+        CREATE ROLE kerai_reader NOLOGIN;
+    END IF;
+END
+$$;
+GRANT USAGE ON SCHEMA kerai TO kerai_reader;
+GRANT SELECT ON kerai.v1_nodes, kerai.v1_edges, kerai.v1_history, kerai.v1_balances,
+    kerai.api_views TO kerai_reader;
+
+COMMIT;
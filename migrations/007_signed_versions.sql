@@ -0,0 +1,7 @@
+-- Migration: Record which instance signed each kerai.versions row
+-- Rows recorded before this migration stay unsigned; kerai.verify_versions()
+-- reports them separately from tampered rows.
+-- Apply with: psql -d kerai -f migrations/007_signed_versions.sql
+
+ALTER TABLE kerai.versions
+    ADD COLUMN IF NOT EXISTS signed_by UUID REFERENCES kerai.instances(id);
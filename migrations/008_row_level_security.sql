@@ -0,0 +1,46 @@
+-- Migration: Path grants and helper functions for optional row-level security
+-- Policies are not created here; run SELECT kerai.enable_rls(); afterwards to
+-- turn row-level security on for nodes, edges and versions.
+-- Apply with: psql -d kerai -f migrations/008_row_level_security.sql
+
+CREATE TABLE IF NOT EXISTS kerai.path_grants (
+    id          BIGSERIAL PRIMARY KEY,
+    path        ltree NOT NULL,
+    user_id     UUID REFERENCES kerai.users(id) ON DELETE CASCADE,  -- NULL: every allowed user
+    can_write   BOOLEAN NOT NULL DEFAULT false,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE NULLS NOT DISTINCT (path, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_path_grants_path ON kerai.path_grants USING gist (path);
+CREATE INDEX IF NOT EXISTS idx_path_grants_user ON kerai.path_grants (user_id);
+
+CREATE OR REPLACE FUNCTION kerai.principal_user() RETURNS uuid
+LANGUAGE sql STABLE SECURITY DEFINER SET search_path = kerai, public, pg_temp AS $$
+    SELECT s.user_id
+    FROM kerai.sessions s
+    JOIN kerai.users u ON u.id = s.user_id
+    WHERE s.token = NULLIF(current_setting('kerai.current_principal', true), '')
+      AND s.expires_at > now()
+      AND (u.is_allowed OR u.is_admin)
+$$;
+
+CREATE OR REPLACE FUNCTION kerai.rls_can_access(target ltree, want_write boolean) RETURNS boolean
+LANGUAGE sql STABLE SECURITY DEFINER SET search_path = kerai, public, pg_temp AS $$
+    WITH p AS (SELECT kerai.principal_user() AS user_id)
+    SELECT EXISTS (
+            SELECT 1 FROM kerai.users u, p WHERE u.id = p.user_id AND u.is_admin
+        )
+        OR (target IS NOT NULL AND EXISTS (
+            SELECT 1 FROM kerai.path_grants g, p
+            WHERE p.user_id IS NOT NULL
+              AND (g.user_id = p.user_id OR g.user_id IS NULL)
+              AND target <@ g.path
+              AND (g.can_write OR NOT want_write)
+        ))
+$$;
+
+CREATE OR REPLACE FUNCTION kerai.rls_node_access(node uuid, want_write boolean) RETURNS boolean
+LANGUAGE sql STABLE SECURITY DEFINER SET search_path = kerai, public, pg_temp AS $$
+    SELECT kerai.rls_can_access((SELECT path FROM kerai.nodes WHERE id = node), want_write)
+$$;
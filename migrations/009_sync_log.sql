@@ -0,0 +1,20 @@
+-- Migration: Log of background sync exchanges with peers
+-- The sync worker runs every kerai.sync_interval seconds (default 300) when
+-- kerai is in shared_preload_libraries; kerai.sync_now() runs a pass by hand.
+-- Apply with: psql -d kerai -f migrations/009_sync_log.sql
+
+CREATE TABLE IF NOT EXISTS kerai.sync_log (
+    id           BIGSERIAL PRIMARY KEY,
+    peer_id      UUID NOT NULL REFERENCES kerai.instances(id) ON DELETE CASCADE,
+    endpoint     TEXT NOT NULL,
+    started_at   TIMESTAMPTZ NOT NULL,
+    finished_at  TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp(),
+    status       TEXT NOT NULL CHECK (status IN ('ok', 'error')),
+    pulled       INTEGER NOT NULL DEFAULT 0,   -- ops applied from the peer
+    superseded   INTEGER NOT NULL DEFAULT 0,
+    duplicates   INTEGER NOT NULL DEFAULT 0,
+    pushed       INTEGER NOT NULL DEFAULT 0,   -- ops the peer applied from us
+    error        TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_log_peer ON kerai.sync_log (peer_id, started_at DESC);
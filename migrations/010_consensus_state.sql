@@ -0,0 +1,110 @@
+-- Migration: Incrementally maintained consensus and perspective stats
+-- Changes to kerai.perspectives are queued by trigger and folded into
+-- kerai.consensus_state / kerai.agent_stats by the consensus worker every
+-- kerai.consensus_interval seconds (default 5); kerai.refresh_consensus()
+-- folds by hand. Existing perspectives are backfilled below.
+-- Apply with: psql -d kerai -f migrations/010_consensus_state.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.perspective_deltas (
+    id          BIGSERIAL PRIMARY KEY,
+    agent_id    UUID NOT NULL,
+    node_id     UUID NOT NULL,
+    context_id  UUID,
+    weight      DOUBLE PRECISION NOT NULL,
+    diff        SMALLINT NOT NULL CHECK (diff IN (-1, 1)),
+    agent_diff  SMALLINT NOT NULL DEFAULT 0,   -- change in distinct agents on (node, context)
+    queued_at   TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp()
+);
+
+CREATE TABLE IF NOT EXISTS kerai.consensus_state (
+    node_id            UUID NOT NULL,
+    context_id         UUID,
+    agent_count        INTEGER NOT NULL DEFAULT 0,
+    perspective_count  INTEGER NOT NULL DEFAULT 0,
+    weight_sum         DOUBLE PRECISION NOT NULL DEFAULT 0,
+    weight_sq_sum      DOUBLE PRECISION NOT NULL DEFAULT 0,
+    min_weight         DOUBLE PRECISION,
+    max_weight         DOUBLE PRECISION,
+    updated_at         TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp(),
+    UNIQUE NULLS NOT DISTINCT (node_id, context_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_consensus_state_agents ON kerai.consensus_state (agent_count);
+
+CREATE TABLE IF NOT EXISTS kerai.agent_stats (
+    agent_id           UUID PRIMARY KEY,
+    perspective_count  INTEGER NOT NULL DEFAULT 0,
+    weight_sum         DOUBLE PRECISION NOT NULL DEFAULT 0,
+    updated_at         TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp()
+);
+
+CREATE OR REPLACE FUNCTION kerai.queue_perspective_delta() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF TG_OP = 'UPDATE'
+       AND NEW.weight = OLD.weight
+       AND NEW.agent_id = OLD.agent_id
+       AND NEW.node_id = OLD.node_id
+       AND NEW.context_id IS NOT DISTINCT FROM OLD.context_id THEN
+        RETURN NULL;
+    END IF;
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        INSERT INTO kerai.perspective_deltas (agent_id, node_id, context_id, weight, diff, agent_diff)
+        VALUES (OLD.agent_id, OLD.node_id, OLD.context_id, OLD.weight, -1,
+            CASE WHEN EXISTS (
+                SELECT 1 FROM kerai.perspectives p
+                WHERE p.agent_id = OLD.agent_id AND p.node_id = OLD.node_id
+                  AND p.context_id IS NOT DISTINCT FROM OLD.context_id AND p.id <> OLD.id
+            ) THEN 0 ELSE -1 END);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        INSERT INTO kerai.perspective_deltas (agent_id, node_id, context_id, weight, diff, agent_diff)
+        VALUES (NEW.agent_id, NEW.node_id, NEW.context_id, NEW.weight, 1,
+            CASE WHEN EXISTS (
+                SELECT 1 FROM kerai.perspectives p
+                WHERE p.agent_id = NEW.agent_id AND p.node_id = NEW.node_id
+                  AND p.context_id IS NOT DISTINCT FROM NEW.context_id AND p.id <> NEW.id
+            ) THEN 0 ELSE 1 END);
+    END IF;
+    RETURN NULL;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION kerai.clear_consensus_state() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    TRUNCATE kerai.perspective_deltas, kerai.consensus_state, kerai.agent_stats;
+    RETURN NULL;
+END;
+$$;
+
+DROP TRIGGER IF EXISTS perspectives_queue_delta ON kerai.perspectives;
+CREATE TRIGGER perspectives_queue_delta
+    AFTER INSERT OR UPDATE OR DELETE ON kerai.perspectives
+    FOR EACH ROW EXECUTE FUNCTION kerai.queue_perspective_delta();
+
+DROP TRIGGER IF EXISTS perspectives_truncate_state ON kerai.perspectives;
+CREATE TRIGGER perspectives_truncate_state
+    AFTER TRUNCATE ON kerai.perspectives
+    FOR EACH STATEMENT EXECUTE FUNCTION kerai.clear_consensus_state();
+
+-- Backfill, with writers held off so no change is counted twice or missed
+LOCK TABLE kerai.perspectives IN SHARE MODE;
+DELETE FROM kerai.perspective_deltas;
+DELETE FROM kerai.consensus_state;
+DELETE FROM kerai.agent_stats;
+
+INSERT INTO kerai.consensus_state
+    (node_id, context_id, agent_count, perspective_count,
+     weight_sum, weight_sq_sum, min_weight, max_weight)
+SELECT node_id, context_id, count(DISTINCT agent_id), count(*),
+       sum(weight), sum(weight * weight), min(weight), max(weight)
+FROM kerai.perspectives GROUP BY node_id, context_id;
+
+INSERT INTO kerai.agent_stats (agent_id, perspective_count, weight_sum)
+SELECT agent_id, count(*), sum(weight)
+FROM kerai.perspectives GROUP BY agent_id;
+
+COMMIT;
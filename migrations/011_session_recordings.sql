@@ -0,0 +1,21 @@
+-- Migration: Opt-in recording of web editor websocket sessions
+-- `kerai serve` records only when started with KERAI_RECORD_SESSIONS=1.
+-- Message content is stored as hashes; replay through
+-- POST /api/admin/recordings/{id}/replay.
+-- Apply with: psql -d kerai -f migrations/011_session_recordings.sql
+
+CREATE TABLE IF NOT EXISTS kerai.session_recordings (
+    id            BIGSERIAL PRIMARY KEY,
+    recording_id  UUID NOT NULL,               -- one per websocket connection
+    seq           INTEGER NOT NULL,
+    offset_ms     INTEGER NOT NULL,            -- since the connection opened
+    direction     TEXT NOT NULL CHECK (direction IN ('in', 'out')),
+    user_id       UUID REFERENCES kerai.users(id) ON DELETE SET NULL,
+    workspace_id  UUID REFERENCES kerai.workspaces(id) ON DELETE SET NULL,
+    message       JSONB NOT NULL,              -- content strings replaced by hashes
+    outcome       JSONB,                       -- server's result for an 'in' message
+    recorded_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (recording_id, seq)
+);
+
+CREATE INDEX IF NOT EXISTS idx_session_recordings_time ON kerai.session_recordings (recorded_at);
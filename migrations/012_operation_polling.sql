@@ -0,0 +1,31 @@
+-- Migration: Cursor-based polling of the operation log by region
+-- Adds the transaction id, in-transaction order and node path of each op
+-- for kerai.poll_changes and GET /api/notifications/poll, plus per-session
+-- cursors. Existing ops get txid 0 and are delivered first.
+-- Apply with: psql -d kerai -f migrations/012_operation_polling.sql
+
+BEGIN;
+
+ALTER TABLE kerai.operations
+    ADD COLUMN IF NOT EXISTS seq BIGINT GENERATED ALWAYS AS IDENTITY,
+    ADD COLUMN IF NOT EXISTS txid xid8 NOT NULL DEFAULT '0',
+    ADD COLUMN IF NOT EXISTS path ltree;
+ALTER TABLE kerai.operations ALTER COLUMN txid SET DEFAULT pg_current_xact_id();
+
+UPDATE kerai.operations o SET path = n.path
+FROM kerai.nodes n
+WHERE n.id = o.node_id AND o.path IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_operations_txid_seq ON kerai.operations (txid, seq);
+CREATE INDEX IF NOT EXISTS idx_operations_path ON kerai.operations USING gist (path);
+
+CREATE TABLE IF NOT EXISTS kerai.notification_cursors (
+    session_id  UUID NOT NULL REFERENCES kerai.sessions(id) ON DELETE CASCADE,
+    region      ltree NOT NULL DEFAULT '',     -- '' watches everything
+    cursor      TEXT NOT NULL DEFAULT '0-0',   -- from kerai.poll_changes; everything before it was delivered
+    delivered   BIGINT NOT NULL DEFAULT 0,     -- events delivered so far
+    polled_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (session_id, region)
+);
+
+COMMIT;
@@ -0,0 +1,54 @@
+-- Migration: Summary nodes for large subtrees
+-- Summaries are 'summary' nodes linked by 'summarizes' edges, made with
+-- kerai.summarize_subtree() or submitted by agents via kerai.submit_summary().
+-- This adds the trigger that flags them stale when their subtree changes.
+-- Apply with: psql -d kerai -f migrations/013_summaries.sql
+
+BEGIN;
+
+CREATE OR REPLACE FUNCTION kerai.mark_summaries_stale() RETURNS trigger
+LANGUAGE plpgsql AS $$
+DECLARE
+    changed UUID[];
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM kerai.edges WHERE relation = 'summarizes') THEN
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP = 'INSERT' THEN
+        IF NEW.kind = 'summary' THEN RETURN NULL; END IF;
+        changed := ARRAY[NEW.id, NEW.parent_id];
+    ELSIF TG_OP = 'DELETE' THEN
+        IF OLD.kind = 'summary' THEN RETURN NULL; END IF;
+        changed := ARRAY[OLD.parent_id];
+    ELSE
+        IF NEW.kind = 'summary'
+           OR (NEW.content IS NOT DISTINCT FROM OLD.content
+               AND NEW.parent_id IS NOT DISTINCT FROM OLD.parent_id
+               AND NEW.position = OLD.position) THEN
+            RETURN NULL;
+        END IF;
+        changed := ARRAY[NEW.id, NEW.parent_id, OLD.parent_id];
+    END IF;
+
+    WITH RECURSIVE up AS (
+        SELECT id, parent_id FROM kerai.nodes WHERE id = ANY(changed)
+        UNION
+        SELECT n.id, n.parent_id FROM kerai.nodes n JOIN up ON n.id = up.parent_id
+    )
+    UPDATE kerai.nodes s
+    SET metadata = s.metadata || jsonb_build_object('stale', true, 'stale_since', now())
+    FROM kerai.edges e
+    WHERE e.relation = 'summarizes'
+      AND e.source_id = s.id
+      AND e.target_id IN (SELECT id FROM up)
+      AND NOT COALESCE((s.metadata->>'stale')::boolean, false);
+    RETURN NULL;
+END $$;
+
+DROP TRIGGER IF EXISTS nodes_mark_summaries_stale ON kerai.nodes;
+CREATE TRIGGER nodes_mark_summaries_stale
+    AFTER INSERT OR UPDATE OR DELETE ON kerai.nodes
+    FOR EACH ROW EXECUTE FUNCTION kerai.mark_summaries_stale();
+
+COMMIT;
@@ -0,0 +1,16 @@
+-- Migration: Stored full-text search vector on nodes
+-- kerai.search(), `kerai find --fts` and /api/search match against the
+-- generated tsv column instead of computing to_tsvector per row.
+-- Adding the column rewrites kerai.nodes; run it in a quiet window.
+-- Apply with: psql -d kerai -f migrations/014_node_tsv.sql
+
+BEGIN;
+
+ALTER TABLE kerai.nodes
+    ADD COLUMN IF NOT EXISTS tsv tsvector
+    GENERATED ALWAYS AS (to_tsvector('english', COALESCE(content, ''))) STORED;
+
+DROP INDEX IF EXISTS kerai.idx_nodes_content_fts;
+CREATE INDEX IF NOT EXISTS idx_nodes_tsv ON kerai.nodes USING gin (tsv);
+
+COMMIT;
@@ -0,0 +1,59 @@
+-- Migration: Documentation staleness tracking
+-- Adds kerai.nodes.modified_at (bumped on content or parent changes) and
+-- the kerai.doc_staleness view comparing doc nodes with the code they are
+-- linked to by 'documents' / 'references' edges. Existing rows start with
+-- modified_at = created_at. Threshold: SET kerai.stale_doc_days (default 30).
+-- Apply with: psql -d kerai -f migrations/015_doc_staleness.sql
+
+BEGIN;
+
+ALTER TABLE kerai.nodes ADD COLUMN IF NOT EXISTS modified_at TIMESTAMPTZ;
+UPDATE kerai.nodes SET modified_at = created_at WHERE modified_at IS NULL;
+ALTER TABLE kerai.nodes
+    ALTER COLUMN modified_at SET DEFAULT now(),
+    ALTER COLUMN modified_at SET NOT NULL;
+
+CREATE OR REPLACE FUNCTION kerai.touch_node_modified() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    NEW.modified_at := now();
+    RETURN NEW;
+END $$;
+
+DROP TRIGGER IF EXISTS nodes_touch_modified ON kerai.nodes;
+CREATE TRIGGER nodes_touch_modified
+    BEFORE UPDATE OF content, parent_id ON kerai.nodes
+    FOR EACH ROW
+    WHEN (OLD.content IS DISTINCT FROM NEW.content OR OLD.parent_id IS DISTINCT FROM NEW.parent_id)
+    EXECUTE FUNCTION kerai.touch_node_modified();
+
+-- score is 1 - 0.5^(gap / kerai.stale_doc_days): 0.5 at the threshold
+CREATE OR REPLACE VIEW kerai.doc_staleness AS
+SELECT l.doc_id, l.code_id, l.relation,
+       d.modified_at AS doc_modified,
+       c.code_modified,
+       g.gap_days,
+       1 - power(0.5, g.gap_days / t.days) AS score,
+       g.gap_days >= t.days AS over_threshold
+FROM (
+    SELECT source_id AS doc_id, target_id AS code_id, relation
+    FROM kerai.edges WHERE relation IN ('documents', 'references')
+) l
+JOIN kerai.nodes d ON d.id = l.doc_id
+CROSS JOIN LATERAL (
+    WITH RECURSIVE sub AS (
+        SELECT id, modified_at FROM kerai.nodes WHERE id = l.code_id
+        UNION ALL
+        SELECT n.id, n.modified_at FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+    )
+    SELECT max(modified_at) AS code_modified FROM sub
+) c
+CROSS JOIN LATERAL (
+    SELECT GREATEST(extract(epoch FROM c.code_modified - d.modified_at) / 86400.0, 0)::float8 AS gap_days
+) g
+CROSS JOIN LATERAL (
+    SELECT COALESCE(NULLIF(current_setting('kerai.stale_doc_days', true), '')::float8, 30) AS days
+) t
+WHERE c.code_modified IS NOT NULL;
+
+COMMIT;
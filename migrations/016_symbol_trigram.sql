@@ -0,0 +1,14 @@
+-- Migration: Trigram index for fuzzy symbol search
+-- kerai.find_fuzzy() and `kerai find --fuzzy` match symbol names with
+-- pg_trgm, which the extension now requires.
+-- Apply with: psql -d kerai -f migrations/016_symbol_trigram.sql
+
+BEGIN;
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS idx_nodes_symbol_trgm ON kerai.nodes USING gin (content gin_trgm_ops)
+    WHERE kind IN ('fn', 'struct', 'enum', 'trait', 'const', 'static',
+                   'type_alias', 'union', 'macro_def', 'variant', 'field');
+
+COMMIT;
@@ -0,0 +1,21 @@
+-- Migration: Node embeddings for semantic search
+-- kerai.embed_nodes(model) stores one vector per node per MicroGPT model;
+-- kerai.search_semantic() and /api/search?mode=semantic rank by them.
+-- Apply with: psql -d kerai -f migrations/017_node_embeddings.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.node_embeddings (
+    model_id      UUID NOT NULL REFERENCES kerai.agents(id),
+    node_id       UUID NOT NULL REFERENCES kerai.nodes(id) ON DELETE CASCADE,
+    embedding     REAL[] NOT NULL,
+    dim           INTEGER NOT NULL,
+    model_version BIGINT NOT NULL,
+    content_hash  TEXT,
+    embedded_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (model_id, node_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_node_embeddings_node ON kerai.node_embeddings (node_id);
+
+COMMIT;
@@ -0,0 +1,34 @@
+-- Migration: Sandboxes for experiments and agent transforms
+-- kerai.create_sandbox(path) copies a subtree under a kerai_sandbox_<id>
+-- path prefix; kerai.promote_sandbox(id) applies its changes back as
+-- signed operations. The sandbox cleaner worker expires old sandboxes.
+-- Apply with: psql -d kerai -f migrations/018_sandboxes.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.sandboxes (
+    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    prefix      TEXT NOT NULL UNIQUE,
+    source_path ltree NOT NULL,
+    status      TEXT NOT NULL DEFAULT 'active'
+                CHECK (status IN ('active', 'promoted', 'discarded', 'expired')),
+    created_by  TEXT NOT NULL DEFAULT current_user,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at  TIMESTAMPTZ NOT NULL,
+    closed_at   TIMESTAMPTZ,
+    result      JSONB
+);
+
+CREATE INDEX IF NOT EXISTS idx_sandboxes_expiry ON kerai.sandboxes (expires_at) WHERE status = 'active';
+
+CREATE TABLE IF NOT EXISTS kerai.sandbox_nodes (
+    sandbox_id  UUID NOT NULL REFERENCES kerai.sandboxes(id) ON DELETE CASCADE,
+    node_id     UUID NOT NULL,
+    origin_id   UUID NOT NULL,
+    base        JSONB NOT NULL,
+    PRIMARY KEY (sandbox_id, node_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_sandbox_nodes_origin ON kerai.sandbox_nodes (origin_id);
+
+COMMIT;
@@ -0,0 +1,27 @@
+-- Migration: Jobs for long-running operations
+-- kerai.job_start / job_progress / job_finish record imports and syncs
+-- so other sessions can follow them; kerai.cancel_job flags a job and
+-- cancels its backend's current statement.
+-- Apply with: psql -d kerai -f migrations/019_jobs.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.jobs (
+    id               UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    kind             TEXT NOT NULL,
+    pid              INTEGER NOT NULL,
+    status           TEXT NOT NULL DEFAULT 'running'
+                     CHECK (status IN ('running', 'done', 'cancelled', 'failed')),
+    total            INTEGER,
+    done             INTEGER NOT NULL DEFAULT 0,
+    current          TEXT,
+    cancel_requested BOOLEAN NOT NULL DEFAULT false,
+    result           JSONB,
+    started_at       TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at       TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at      TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_jobs_running ON kerai.jobs (pid) WHERE status = 'running';
+
+COMMIT;
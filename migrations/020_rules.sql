@@ -0,0 +1,22 @@
+-- Migration: User-defined lint rules
+-- kerai.add_rule stores a rule (SQL predicate and/or ltree pattern over
+-- nodes, plus a message); kerai.run_rules turns matching nodes into
+-- suggestion nodes with suggests edges, like the built-in rules.
+-- Apply with: psql -d kerai -f migrations/020_rules.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.rules (
+    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name        TEXT NOT NULL UNIQUE,
+    message     TEXT NOT NULL,
+    predicate   TEXT,
+    pattern     lquery,
+    severity    TEXT NOT NULL DEFAULT 'warning' CHECK (severity IN ('info', 'warning')),
+    category    TEXT NOT NULL DEFAULT 'custom',
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK (predicate IS NOT NULL OR pattern IS NOT NULL)
+);
+
+COMMIT;
@@ -0,0 +1,99 @@
+-- Migration: Kinds registry with render hints
+-- kerai.kinds holds per-kind hints (icon, collapsible, preview template,
+-- syntax language) served at GET /api/kinds; kerai.render_hints merges
+-- them over name-based guesses so unregistered kinds still render.
+-- Apply with: psql -d kerai -f migrations/021_kinds.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.kinds (
+    kind          TEXT PRIMARY KEY,
+    description   TEXT,
+    render_hints  JSONB NOT NULL DEFAULT '{}'::jsonb,  -- icon, collapsible, preview, syntax
+    updated_at    TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO kerai.kinds (kind, description, render_hints) VALUES
+    ('crate',          'Rust crate',            '{"icon": "package", "preview": "crate {content}"}'),
+    ('module',         'Rust module',           '{"icon": "folder", "preview": "mod {content}"}'),
+    ('file',           'Source file',           '{"icon": "file"}'),
+    ('fn',             'Rust function',         '{"icon": "function", "preview": "fn {content}"}'),
+    ('struct',         'Rust struct',           '{"icon": "struct", "preview": "struct {content}"}'),
+    ('enum',           'Rust enum',             '{"icon": "enum", "preview": "enum {content}"}'),
+    ('trait',          'Rust trait',            '{"icon": "interface", "preview": "trait {content}"}'),
+    ('impl',           'Rust impl block',       '{"icon": "impl", "preview": "impl {content}"}'),
+    ('use',            'Rust use declaration',  '{"icon": "import", "collapsible": false, "preview": "use {content}"}'),
+    ('doc_comment',    'Doc comment',           '{"icon": "comment", "collapsible": false, "syntax": "markdown"}'),
+    ('document',       'Markdown document',     '{"icon": "book"}'),
+    ('heading',        'Markdown heading',      '{"icon": "heading", "preview": "{content}"}'),
+    ('paragraph',      'Markdown paragraph',    '{"icon": "paragraph", "collapsible": false, "syntax": "markdown"}'),
+    ('code_block',     'Fenced code block',     '{"icon": "code", "preview": "```{metadata.language}"}'),
+    ('list',           'Markdown list',         '{"icon": "list"}'),
+    ('list_item',      'Markdown list item',    '{"icon": "bullet", "collapsible": false}'),
+    ('blockquote',     'Markdown blockquote',   '{"icon": "quote"}'),
+    ('table',          'Markdown table',        '{"icon": "table"}'),
+    ('link',           'Markdown link',         '{"icon": "link", "collapsible": false}'),
+    ('image',          'Markdown image',        '{"icon": "image", "collapsible": false}'),
+    ('latex_document', 'LaTeX document',        '{"icon": "book", "syntax": "latex"}'),
+    ('latex_section',  'LaTeX section',         '{"icon": "heading", "preview": "{content}"}'),
+    ('latex_display_math', 'Display math',      '{"icon": "math", "collapsible": false, "syntax": "latex"}'),
+    ('latex_inline_math',  'Inline math',       '{"icon": "math", "collapsible": false, "syntax": "latex"}'),
+    ('bib_entry',      'BibTeX entry',          '{"icon": "citation", "preview": "@{metadata.entry_type} {content}", "syntax": "bibtex"}'),
+    ('suggestion',     'Suggestion',            '{"icon": "lightbulb", "collapsible": false}'),
+    ('csv_table',      'CSV table',             '{"icon": "table"}')
+ON CONFLICT (kind) DO NOTHING;
+
+-- Hints for a node: defaults, then a guess from the kind's name so kinds
+-- from new parsers (go_func, c_string_lit, ...) look like their relatives,
+-- then whatever the registry says about the kind itself.
+CREATE OR REPLACE FUNCTION kerai.render_hints(node_kind text, node_language text) RETURNS jsonb
+LANGUAGE sql STABLE AS $$
+    SELECT jsonb_strip_nulls(jsonb_build_object(
+            'icon', 'node',
+            'collapsible', true,
+            'preview', '{content}',
+            'syntax', node_language
+        ))
+        || CASE
+            WHEN node_kind ~ '(^|_)(comment|comment_block)(_|$)' THEN
+                '{"icon": "comment", "collapsible": false}'
+            WHEN node_kind ~ '(^|_)(lit|true|false|nil|null|iota|scalar)(_|$)' THEN
+                '{"icon": "literal", "collapsible": false}'
+            WHEN node_kind ~ '(^|_)(ident|lifetime|label)(_|$)' THEN
+                '{"icon": "identifier", "collapsible": false}'
+            WHEN node_kind ~ '(^|_)(if|for|while|loop|switch|select|case|match|return|break|continue|goto|defer)(_|$)' THEN
+                '{"icon": "control"}'
+            WHEN node_kind ~ '(^|_)type(_|$)' THEN
+                '{"icon": "type"}'
+            WHEN node_kind ~ '(^|_)(fn|func|function|method|closure)(_|$)' THEN
+                '{"icon": "function"}'
+            WHEN node_kind ~ '(^|_)(struct|class|union|typedef)(_|$)' THEN
+                '{"icon": "struct"}'
+            WHEN node_kind ~ '(^|_)(interface|trait)(_|$)' THEN
+                '{"icon": "interface"}'
+            WHEN node_kind ~ '(^|_)(enum|enumerator|variant)(_|$)' THEN
+                '{"icon": "enum"}'
+            WHEN node_kind ~ '(^|_)(import|include|use|usepackage)(_|$)' THEN
+                '{"icon": "import", "collapsible": false}'
+            WHEN node_kind ~ '(^|_)(package|namespace|module|mod)(_|$)' THEN
+                '{"icon": "folder"}'
+            WHEN node_kind ~ '(^|_)(field|param|key)(_|$)' THEN
+                '{"icon": "field"}'
+            WHEN node_kind ~ '(^|_)(heading|part|chapter|section|subsection|subsubsection)(_|$)' THEN
+                '{"icon": "heading"}'
+            WHEN node_kind ~ '(^|_)(table|dataset)(_|$)' THEN
+                '{"icon": "table"}'
+            WHEN node_kind ~ '(^|_)math(_|$)' THEN
+                '{"icon": "math", "collapsible": false}'
+            WHEN node_kind ~ '(^|_)(expr|call|binary|unary|assignment)(_|$)' THEN
+                '{"icon": "expression"}'
+            WHEN node_kind ~ '(^|_)(stmt|block)(_|$)' THEN
+                '{"icon": "block"}'
+            WHEN node_kind ~ '^pat_' THEN
+                '{"icon": "pattern"}'
+            ELSE '{}'
+        END::jsonb
+        || COALESCE((SELECT k.render_hints FROM kerai.kinds k WHERE k.kind = node_kind), '{}'::jsonb)
+$$;
+
+COMMIT;
@@ -0,0 +1,17 @@
+-- Migration: Federation economy reports
+-- Peers exchange signed kerai.economy_report summaries; each peer's latest
+-- one per period is kept here and merged by kerai.federation_economy_report.
+-- Apply with: psql -d kerai -f migrations/022_economy_summaries.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.economy_summaries (
+    instance_id  UUID NOT NULL REFERENCES kerai.instances(id) ON DELETE CASCADE,
+    period       TEXT NOT NULL,
+    report       JSONB NOT NULL,       -- the peer's economy_report
+    message      JSONB NOT NULL,       -- signed message it arrived in
+    received_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (instance_id, period)
+);
+
+COMMIT;
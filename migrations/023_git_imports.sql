@@ -0,0 +1,21 @@
+-- Migration: Git history import
+-- Commits replayed by kerai.import_git_commit (kerai import-git), so an
+-- interrupted import can resume and each version batch maps back to a sha.
+-- Apply with: psql -d kerai -f migrations/023_git_imports.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.git_imports (
+    sha           TEXT PRIMARY KEY,
+    timestamp     BIGINT NOT NULL,      -- Lamport timestamp of its versions
+    author        TEXT NOT NULL,        -- "Name <email>", as on its versions
+    committed_at  TIMESTAMPTZ NOT NULL,
+    message       TEXT NOT NULL DEFAULT '',
+    files         INTEGER NOT NULL DEFAULT 0,
+    versions      INTEGER NOT NULL DEFAULT 0,
+    imported_at   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_git_imports_timestamp ON kerai.git_imports (timestamp);
+
+COMMIT;
@@ -0,0 +1,29 @@
+-- Migration: Snapshots
+-- Named, frozen copies of the file and document trees with Merkle hashes,
+-- created by kerai.create_snapshot and checked out with
+-- kerai postgres checkout --snapshot <name>.
+-- Apply with: psql -d kerai -f migrations/024_snapshots.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.snapshots (
+    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name        TEXT NOT NULL UNIQUE,
+    branch_id   UUID,                 -- kerai.branches FK added by 044_branches.sql
+    timestamp   BIGINT NOT NULL,      -- Lamport time it was taken at
+    root_hash   TEXT NOT NULL,        -- Merkle root over its files' paths and hashes
+    files       INTEGER NOT NULL DEFAULT 0,
+    nodes       INTEGER NOT NULL DEFAULT 0,
+    instance_id UUID REFERENCES kerai.instances(id),
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS kerai.snapshot_nodes (
+    snapshot_id UUID NOT NULL REFERENCES kerai.snapshots(id) ON DELETE CASCADE,
+    node_id     UUID NOT NULL,        -- no FK: the node may be gone since
+    hash        TEXT,                 -- subtree hash; NULL for crate nodes above files
+    node        JSONB NOT NULL,       -- the kerai.nodes row
+    PRIMARY KEY (snapshot_id, node_id)
+);
+
+COMMIT;
@@ -0,0 +1,34 @@
+-- Migration: Relations registry for hand-made edges
+-- kerai.relations lists the edge relations kerai.add_edge accepts, with the
+-- node kinds allowed at each end; kerai.add_edges / kerai.remove_edges link
+-- and unlink many nodes at once.
+-- Apply with: psql -d kerai -f migrations/025_relations.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.relations (
+    relation      TEXT PRIMARY KEY,
+    description   TEXT,
+    source_kinds  TEXT[],                               -- NULL: any kind
+    target_kinds  TEXT[],
+    updated_at    TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO kerai.relations (relation, description, source_kinds, target_kinds) VALUES
+    ('calls',         'Function calls function',             NULL, NULL),
+    ('uses',          'Item uses another item',              NULL, NULL),
+    ('includes',      'File includes another file',          NULL, NULL),
+    ('depends_on',    'Crate depends on a dependency',       NULL, '{dependency}'),
+    ('documents',     'Documentation describes code',        NULL, NULL),
+    ('references',    'Text mentions code by name',          NULL, NULL),
+    ('links_to',      'Markdown link to another node',       NULL, NULL),
+    ('cites',         'Citation of a bibliography entry',    NULL, NULL),
+    ('summarizes',    'Summary of a node',                   NULL, NULL),
+    ('suggests',      'Suggestion about a node',             '{suggestion}', NULL),
+    ('duplicates',    'Near-duplicate code',                 NULL, NULL),
+    ('shared_column', 'CSV columns of the same name',        '{csv_column}', '{csv_column}'),
+    ('parent_commit', 'Commit follows its parent',           NULL, NULL)
+
+ON CONFLICT (relation) DO NOTHING;
+
+COMMIT;
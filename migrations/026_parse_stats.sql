@@ -0,0 +1,27 @@
+-- Migration: Parse run log and throughput view
+-- kerai.parse_crate, kerai.parse_file and kerai.parse_source log each call
+-- to kerai.parse_runs; kerai.parse_stats reports rows per second.
+-- Apply with: psql -d kerai -f migrations/026_parse_stats.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.parse_runs (
+    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    kind        TEXT NOT NULL,
+    target      TEXT NOT NULL,
+    files       INTEGER NOT NULL,
+    nodes       BIGINT NOT NULL,
+    edges       BIGINT NOT NULL,
+    elapsed_ms  BIGINT NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_parse_runs_created ON kerai.parse_runs (created_at);
+
+CREATE OR REPLACE VIEW kerai.parse_stats AS
+SELECT kind, target, files, nodes, edges, elapsed_ms,
+       round((nodes + edges) * 1000.0 / GREATEST(elapsed_ms, 1), 1) AS rows_per_sec,
+       created_at
+FROM kerai.parse_runs;
+
+COMMIT;
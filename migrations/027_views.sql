@@ -0,0 +1,29 @@
+-- Migration: Named views
+-- kerai.views names the paths and node kinds a team works in;
+-- kerai.apply_view sets kerai.view_paths / kerai.view_kinds, which
+-- kerai.in_view reads so find, grep, tree, search and document listings
+-- only return nodes inside the view.
+-- Apply with: psql -d kerai -f migrations/027_views.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.views (
+    name         TEXT PRIMARY KEY,
+    description  TEXT,
+    paths        ltree[],                               -- NULL: every path
+    kinds        TEXT[],                                -- NULL: every kind
+    branch       TEXT,                                  -- branch the view is read on
+    frontier     JSONB,                                 -- version vector it is pinned to
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE OR REPLACE FUNCTION kerai.in_view(node_path ltree, node_kind text) RETURNS boolean
+LANGUAGE sql STABLE AS $$
+    SELECT (COALESCE(current_setting('kerai.view_paths', true), '') = ''
+            OR COALESCE(node_path <@ string_to_array(current_setting('kerai.view_paths', true), ',')::ltree[], false))
+       AND (COALESCE(current_setting('kerai.view_kinds', true), '') = ''
+            OR node_kind = ANY(string_to_array(current_setting('kerai.view_kinds', true), ',')))
+$$;
+
+COMMIT;
@@ -0,0 +1,30 @@
+-- Migration: Node subscriptions with digests
+-- kerai.subscriptions records which users follow which subtrees (or
+-- lqueries), for which operation types and how often; the digest worker
+-- calls kerai.deliver_digests, which rolls matching operations into
+-- 'digest' nodes that kerai.inbox and GET /api/inbox read.
+-- Apply with: psql -d kerai -f migrations/028_subscriptions.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.subscriptions (
+    id           UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id      UUID NOT NULL REFERENCES kerai.users(id) ON DELETE CASCADE,
+    pattern      TEXT NOT NULL,                 -- ltree subtree root, or lquery with * | !
+    event_types  TEXT[],                        -- op_types digested; NULL: every one
+    frequency    TEXT NOT NULL DEFAULT 'daily'
+                 CHECK (frequency IN ('hourly', 'daily', 'weekly')),
+    cursor       TEXT NOT NULL DEFAULT '0-0',   -- poll cursor; everything before it was digested
+    digested_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (user_id, pattern)
+);
+
+CREATE INDEX IF NOT EXISTS idx_nodes_digest_user ON kerai.nodes ((metadata->>'user_id'), created_at)
+    WHERE kind = 'digest';
+
+INSERT INTO kerai.kinds (kind, description, render_hints) VALUES
+    ('digest', 'Subscription digest', '{"icon": "inbox", "collapsible": false}')
+ON CONFLICT (kind) DO NOTHING;
+
+COMMIT;
@@ -0,0 +1,64 @@
+-- Migration: Hybrid logical clocks and peer clock skew
+-- operations.lamport_ts and versions.timestamp now hold hybrid logical
+-- clock timestamps (physical_ms << 16 | logical) rather than bare Lamport
+-- counters; existing counters are far below any of them and keep their
+-- order. kerai.hlc_wall_time decodes a timestamp, kerai.v2_history shows
+-- each change's estimated wall time, and the skew measured from each
+-- peer's sync messages is kept on kerai.instances and kerai.sync_log.
+-- Apply with: psql -d kerai -f migrations/029_hybrid_logical_clocks.sql
+
+BEGIN;
+
+ALTER TABLE kerai.instances
+    ADD COLUMN IF NOT EXISTS clock_skew_ms BIGINT,
+    ADD COLUMN IF NOT EXISTS clock_checked_at TIMESTAMPTZ;
+
+ALTER TABLE kerai.sync_log ADD COLUMN IF NOT EXISTS clock_skew_ms BIGINT;
+
+CREATE OR REPLACE FUNCTION kerai.hlc_wall_time(ts bigint, skew_ms bigint DEFAULT 0) RETURNS timestamptz
+LANGUAGE sql IMMUTABLE PARALLEL SAFE AS $$
+    SELECT CASE WHEN ts >> 16 >= 1577836800000
+        THEN to_timestamp(((ts >> 16) - COALESCE(skew_ms, 0)) / 1000.0)
+    END
+$$;
+
+CREATE OR REPLACE VIEW kerai.v2_history AS
+SELECT
+    v.id,
+    v.node_id,
+    v.operation,
+    v.author,
+    v.timestamp AS hlc,
+    kerai.hlc_wall_time(v.timestamp, i.clock_skew_ms) AS wall_time,
+    v.branch_id,
+    v.old_parent,
+    v.new_parent,
+    v.old_content,
+    v.new_content,
+    v.created_at
+FROM kerai.versions v
+LEFT JOIN kerai.instances i ON i.id = v.instance_id;
+
+COMMENT ON VIEW kerai.v2_history IS 'Stable API v2: per-node change history with hybrid logical clock times';
+COMMENT ON COLUMN kerai.v2_history.id IS 'Version UUID';
+COMMENT ON COLUMN kerai.v2_history.node_id IS 'Node the change applies to';
+COMMENT ON COLUMN kerai.v2_history.operation IS 'Operation type, e.g. insert_node, update_content';
+COMMENT ON COLUMN kerai.v2_history.author IS 'Key fingerprint of the authoring instance';
+COMMENT ON COLUMN kerai.v2_history.hlc IS 'Hybrid logical clock timestamp (physical_ms << 16 | logical); orders changes across instances';
+COMMENT ON COLUMN kerai.v2_history.wall_time IS 'When the change was made, estimated from hlc and the authoring instance''s clock skew; NULL for changes stamped before hybrid clocks';
+COMMENT ON COLUMN kerai.v2_history.branch_id IS 'Branch the change was made on, NULL for the main line';
+COMMENT ON COLUMN kerai.v2_history.old_parent IS 'Parent before the change';
+COMMENT ON COLUMN kerai.v2_history.new_parent IS 'Parent after the change';
+COMMENT ON COLUMN kerai.v2_history.old_content IS 'Content before the change';
+COMMENT ON COLUMN kerai.v2_history.new_content IS 'Content after the change';
+COMMENT ON COLUMN kerai.v2_history.created_at IS 'Wall-clock time this instance recorded the change';
+
+COMMENT ON COLUMN kerai.v1_history.lamport_ts IS 'Hybrid logical clock timestamp (Lamport counter before hybrid clocks); orders changes across instances';
+
+INSERT INTO kerai.api_views (name, version, description) VALUES
+    ('v2_history', 2, 'Per-node change history with hybrid logical clock times')
+ON CONFLICT (name) DO NOTHING;
+
+GRANT SELECT ON kerai.v2_history TO kerai_reader;
+
+COMMIT;
@@ -0,0 +1,48 @@
+-- Migration: Changesets staged for review
+-- kerai.changesets and kerai.changeset_ops hold node operations staged
+-- with kerai.stage_op before kerai.apply_changeset applies them.
+-- kerai.read_with_changeset runs a read with a changeset's ops applied
+-- in a subtransaction it rolls back, which is how kerai.with_changeset
+-- lets find, tree and reconstruct read as if a changeset were applied.
+-- Apply with: psql -d kerai -f migrations/030_changesets.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.changesets (
+    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    description TEXT,
+    status      TEXT NOT NULL DEFAULT 'open'
+                CHECK (status IN ('open', 'applied', 'discarded')),
+    created_by  TEXT NOT NULL DEFAULT current_user,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    closed_at   TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS kerai.changeset_ops (
+    changeset_id UUID NOT NULL REFERENCES kerai.changesets(id) ON DELETE CASCADE,
+    seq          INTEGER NOT NULL,
+    op_type      TEXT NOT NULL,
+    node_id      UUID NOT NULL,                 -- preassigned for insert_node
+    payload      JSONB NOT NULL,
+    staged_at    TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (changeset_id, seq)
+);
+
+CREATE OR REPLACE FUNCTION kerai.read_with_changeset(changeset uuid, query text, args jsonb DEFAULT '{}')
+RETURNS jsonb
+LANGUAGE plpgsql AS $$
+DECLARE
+    result jsonb;
+BEGIN
+    BEGIN
+        PERFORM set_config('kerai.changeset', '', true);
+        PERFORM kerai.preview_changeset(changeset);
+        EXECUTE query INTO result USING args;
+        RAISE EXCEPTION USING ERRCODE = 'KR001';
+    EXCEPTION WHEN SQLSTATE 'KR001' THEN
+        NULL;
+    END;
+    RETURN result;
+END $$;
+
+COMMIT;
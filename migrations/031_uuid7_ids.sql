@@ -0,0 +1,40 @@
+-- Migration: Time-ordered UUIDv7 ids
+-- kerai.uuid7() makes UUIDv7 ids (Unix milliseconds, sub-millisecond time,
+-- then random bits), and every kerai column defaulting to
+-- gen_random_uuid() defaults to it instead, so new rows append to their
+-- primary key indexes instead of splitting pages all over them. Existing
+-- v4 ids are left as they are. kerai.benchmark_uuid_index() compares the
+-- index each kind of id builds.
+-- Apply with: psql -d kerai -f migrations/031_uuid7_ids.sql
+
+BEGIN;
+
+CREATE OR REPLACE FUNCTION kerai.uuid7() RETURNS uuid
+LANGUAGE sql VOLATILE PARALLEL SAFE AS $$
+    SELECT encode(
+        substring(int8send(floor(t)::bigint) FROM 3)
+        || int2send((28672 + floor((t - floor(t)) * 4096))::int2)  -- 0x7000: version 7
+        || substring(uuid_send(gen_random_uuid()) FROM 9),
+        'hex')::uuid
+    FROM (SELECT extract(epoch FROM clock_timestamp()) * 1000 AS t) now
+$$;
+
+DO $$
+DECLARE
+    col record;
+BEGIN
+    FOR col IN
+        SELECT c.relname, a.attname
+        FROM pg_attrdef d
+        JOIN pg_class c ON c.oid = d.adrelid
+        JOIN pg_namespace n ON n.oid = c.relnamespace
+        JOIN pg_attribute a ON a.attrelid = d.adrelid AND a.attnum = d.adnum
+        WHERE n.nspname = 'kerai' AND pg_get_expr(d.adbin, d.adrelid) = 'gen_random_uuid()'
+    LOOP
+        EXECUTE format('ALTER TABLE kerai.%I ALTER COLUMN %I SET DEFAULT kerai.uuid7()',
+                       col.relname, col.attname);
+    END LOOP;
+END
+$$;
+
+COMMIT;
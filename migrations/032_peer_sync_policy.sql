@@ -0,0 +1,25 @@
+-- Migration: Peer sync windows and rate caps
+-- kerai.instances keeps each peer's sync policy: the local times of day it
+-- may be synced in and caps on ops per minute and bytes per hour, set with
+-- kerai.set_sync_policy. kerai.sync_log counts the ops and bytes each
+-- exchange moved and deferred, and records exchanges the policy held back
+-- with status 'held'.
+-- Apply with: psql -d kerai -f migrations/032_peer_sync_policy.sql
+
+BEGIN;
+
+ALTER TABLE kerai.instances
+    ADD COLUMN IF NOT EXISTS sync_windows TEXT[],
+    ADD COLUMN IF NOT EXISTS sync_max_ops_per_minute INTEGER,
+    ADD COLUMN IF NOT EXISTS sync_max_bytes_per_hour BIGINT;
+
+ALTER TABLE kerai.sync_log
+    ADD COLUMN IF NOT EXISTS transferred INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS bytes BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS deferred INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE kerai.sync_log DROP CONSTRAINT IF EXISTS sync_log_status_check;
+ALTER TABLE kerai.sync_log ADD CONSTRAINT sync_log_status_check
+    CHECK (status IN ('ok', 'error', 'held'));
+
+COMMIT;
@@ -0,0 +1,79 @@
+-- Migration: Change events
+-- Row changes to kerai.nodes, kerai.edges and kerai.versions are sent as
+-- JSON events ({table, op, id, path, kind, instance, ...}) on the
+-- kerai_nodes, kerai_edges and kerai_versions channels, which kerai-web
+-- relays to WebSocket clients subscribed with matching filters.
+-- SET kerai.change_events = off skips them.
+-- Apply with: psql -d kerai -f migrations/033_change_events.sql
+
+BEGIN;
+
+CREATE OR REPLACE FUNCTION kerai.notify_change() RETURNS trigger
+LANGUAGE plpgsql AS $$
+DECLARE
+    event jsonb;
+BEGIN
+    IF NOT COALESCE(NULLIF(current_setting('kerai.change_events', true), '')::boolean, true) THEN
+        RETURN NULL;
+    END IF;
+
+    IF TG_ARGV[0] = 'nodes' THEN
+        IF TG_OP = 'DELETE' THEN
+            event := jsonb_build_object('id', OLD.id, 'path', OLD.path, 'kind', OLD.kind,
+                                        'instance', OLD.instance_id, 'parent_id', OLD.parent_id);
+        ELSE
+            event := jsonb_build_object('id', NEW.id, 'path', NEW.path, 'kind', NEW.kind,
+                                        'instance', NEW.instance_id, 'parent_id', NEW.parent_id);
+            IF TG_OP = 'UPDATE' AND OLD.path IS DISTINCT FROM NEW.path THEN
+                event := event || jsonb_build_object('old_path', OLD.path);
+            END IF;
+        END IF;
+    ELSIF TG_ARGV[0] = 'edges' THEN
+        IF TG_OP = 'DELETE' THEN
+            event := jsonb_build_object('id', OLD.id, 'kind', OLD.relation,
+                                        'source_id', OLD.source_id, 'target_id', OLD.target_id);
+        ELSE
+            event := jsonb_build_object('id', NEW.id, 'kind', NEW.relation,
+                                        'source_id', NEW.source_id, 'target_id', NEW.target_id);
+        END IF;
+        event := event || COALESCE((
+            SELECT jsonb_build_object('path', n.path, 'instance', n.instance_id)
+            FROM kerai.nodes n WHERE n.id = (event->>'source_id')::uuid
+        ), '{}'::jsonb);
+    ELSE
+        IF TG_OP = 'DELETE' THEN
+            event := jsonb_build_object('id', OLD.id, 'node_id', OLD.node_id,
+                                        'instance', OLD.instance_id, 'operation', OLD.operation,
+                                        'author', OLD.author);
+        ELSE
+            event := jsonb_build_object('id', NEW.id, 'node_id', NEW.node_id,
+                                        'instance', NEW.instance_id, 'operation', NEW.operation,
+                                        'author', NEW.author);
+        END IF;
+        event := event || COALESCE((
+            SELECT jsonb_build_object('path', n.path, 'kind', n.kind)
+            FROM kerai.nodes n WHERE n.id = (event->>'node_id')::uuid
+        ), '{}'::jsonb);
+    END IF;
+
+    PERFORM pg_notify('kerai_' || TG_ARGV[0],
+        (jsonb_build_object('table', TG_ARGV[0], 'op', lower(TG_OP)) || event)::text);
+    RETURN NULL;
+END $$;
+
+DROP TRIGGER IF EXISTS nodes_notify_change ON kerai.nodes;
+CREATE TRIGGER nodes_notify_change
+    AFTER INSERT OR UPDATE OR DELETE ON kerai.nodes
+    FOR EACH ROW EXECUTE FUNCTION kerai.notify_change('nodes');
+
+DROP TRIGGER IF EXISTS edges_notify_change ON kerai.edges;
+CREATE TRIGGER edges_notify_change
+    AFTER INSERT OR UPDATE OR DELETE ON kerai.edges
+    FOR EACH ROW EXECUTE FUNCTION kerai.notify_change('edges');
+
+DROP TRIGGER IF EXISTS versions_notify_change ON kerai.versions;
+CREATE TRIGGER versions_notify_change
+    AFTER INSERT OR UPDATE OR DELETE ON kerai.versions
+    FOR EACH ROW EXECUTE FUNCTION kerai.notify_change('versions');
+
+COMMIT;
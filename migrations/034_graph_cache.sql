@@ -0,0 +1,23 @@
+-- Migration: Graph cache
+-- kerai.graph_lod(path, lod, max_nodes) collapses the nodes under a path
+-- into clusters a few levels down with their edges aggregated, for graph
+-- views that cannot draw every node. Results are kept in kerai.graph_cache
+-- and reused while the subtree and edges are unchanged.
+-- Apply with: psql -d kerai -f migrations/034_graph_cache.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.graph_cache (
+    root        TEXT NOT NULL,               -- ltree path graphed, '' for everything
+    lod         INTEGER NOT NULL,
+    max_nodes   INTEGER NOT NULL,
+    filter_key  TEXT NOT NULL,               -- md5 of the role, view and principal read through
+    fingerprint TEXT NOT NULL,               -- row counts and latest timestamps it was computed at
+    graph       JSONB NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (root, lod, max_nodes, filter_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_graph_cache_computed ON kerai.graph_cache (computed_at);
+
+COMMIT;
@@ -0,0 +1,17 @@
+-- Migration: Suggestion fixes
+-- A rule may carry the operation that fixes what it matches and the reward
+-- work type credited when someone accepts it. kerai.accept_suggestion
+-- applies the fix and mints the reward; kerai.dismiss_suggestion closes a
+-- suggestion without one.
+-- Apply with: psql -d kerai -f migrations/035_suggestion_fixes.sql
+
+BEGIN;
+
+ALTER TABLE kerai.rules ADD COLUMN IF NOT EXISTS fix JSONB;
+ALTER TABLE kerai.rules ADD COLUMN IF NOT EXISTS reward_work_type TEXT;
+
+INSERT INTO kerai.reward_schedule (work_type, reward)
+VALUES ('accept_suggestion', 1000000000)  -- 1 Koi
+ON CONFLICT (work_type) DO NOTHING;
+
+COMMIT;
@@ -0,0 +1,14 @@
+-- Migration: Reverts
+-- kerai.revert_version and kerai.undo_last apply inverse operations that
+-- name the version they undo as payload->>'revert_of'; this index finds
+-- them when checking what has been reverted. Deletes now also leave
+-- tombstone versions (no new snapshot, no branch) that reverts resurrect
+-- nodes from; that needs no schema change.
+-- Apply with: psql -d kerai -f migrations/036_reverts.sql
+
+BEGIN;
+
+CREATE INDEX IF NOT EXISTS idx_operations_revert_of ON kerai.operations ((payload->>'revert_of'))
+    WHERE payload ? 'revert_of';
+
+COMMIT;
@@ -0,0 +1,23 @@
+-- Migration: Job heartbeats and reaping
+-- Workers refresh heartbeat_at through kerai.job_progress or
+-- kerai.job_heartbeat. kerai.reap_jobs fails running jobs whose worker has
+-- died and queues them again, with backoff, while attempts remain;
+-- kerai.claim_job hands a queued job to a worker and kerai.jobs_summary
+-- reports throughput and failure rates per kind.
+-- Apply with: psql -d kerai -f migrations/037_job_heartbeats.sql
+
+BEGIN;
+
+ALTER TABLE kerai.jobs ADD COLUMN IF NOT EXISTS args JSONB;
+ALTER TABLE kerai.jobs ADD COLUMN IF NOT EXISTS attempts INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE kerai.jobs ADD COLUMN IF NOT EXISTS max_attempts INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE kerai.jobs ADD COLUMN IF NOT EXISTS run_after TIMESTAMPTZ;
+ALTER TABLE kerai.jobs ADD COLUMN IF NOT EXISTS heartbeat_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+ALTER TABLE kerai.jobs DROP CONSTRAINT IF EXISTS jobs_status_check;
+ALTER TABLE kerai.jobs ADD CONSTRAINT jobs_status_check
+    CHECK (status IN ('queued', 'running', 'done', 'cancelled', 'failed'));
+
+CREATE INDEX IF NOT EXISTS idx_jobs_queued ON kerai.jobs (kind, run_after) WHERE status = 'queued';
+
+COMMIT;
@@ -0,0 +1,59 @@
+-- Migration: Soft delete and tombstone GC
+-- delete_node ops and re-parses now mark nodes with deleted_at instead of
+-- removing the rows, keeping their history. Each peer's last sync message
+-- leaves its version vector in kerai.instances.acked_vector;
+-- kerai.gc(retention) purges tombstones older than the retention window
+-- once every peer has acknowledged the delete. SET kerai.gc_interval and
+-- kerai.gc_retention_days for the background worker.
+-- Apply with: psql -d kerai -f migrations/038_soft_delete.sql
+
+BEGIN;
+
+ALTER TABLE kerai.nodes ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+CREATE INDEX IF NOT EXISTS idx_nodes_deleted ON kerai.nodes (deleted_at) WHERE deleted_at IS NOT NULL;
+
+ALTER TABLE kerai.instances ADD COLUMN IF NOT EXISTS acked_vector JSONB;
+
+-- Tombstoning a node marks the summaries above it stale, as deleting it did
+CREATE OR REPLACE FUNCTION kerai.mark_summaries_stale() RETURNS trigger
+LANGUAGE plpgsql AS $$
+DECLARE
+    changed UUID[];
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM kerai.edges WHERE relation = 'summarizes') THEN
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP = 'INSERT' THEN
+        IF NEW.kind = 'summary' THEN RETURN NULL; END IF;
+        changed := ARRAY[NEW.id, NEW.parent_id];
+    ELSIF TG_OP = 'DELETE' THEN
+        IF OLD.kind = 'summary' THEN RETURN NULL; END IF;
+        changed := ARRAY[OLD.parent_id];
+    ELSE
+        IF NEW.kind = 'summary'
+           OR (NEW.content IS NOT DISTINCT FROM OLD.content
+               AND NEW.parent_id IS NOT DISTINCT FROM OLD.parent_id
+               AND NEW.position = OLD.position
+               AND NEW.deleted_at IS NOT DISTINCT FROM OLD.deleted_at) THEN
+            RETURN NULL;
+        END IF;
+        changed := ARRAY[NEW.id, NEW.parent_id, OLD.parent_id];
+    END IF;
+
+    WITH RECURSIVE up AS (
+        SELECT id, parent_id FROM kerai.nodes WHERE id = ANY(changed)
+        UNION
+        SELECT n.id, n.parent_id FROM kerai.nodes n JOIN up ON n.id = up.parent_id
+    )
+    UPDATE kerai.nodes s
+    SET metadata = s.metadata || jsonb_build_object('stale', true, 'stale_since', now())
+    FROM kerai.edges e
+    WHERE e.relation = 'summarizes'
+      AND e.source_id = s.id
+      AND e.target_id IN (SELECT id FROM up)
+      AND NOT COALESCE((s.metadata->>'stale')::boolean, false);
+    RETURN NULL;
+END $$;
+
+COMMIT;
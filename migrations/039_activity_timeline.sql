@@ -0,0 +1,103 @@
+-- Migration: Incrementally maintained activity timeline
+-- New versions, perspective changes and parse runs are queued by trigger
+-- in kerai.activity_deltas and folded into kerai.activity_daily (counts
+-- per UTC day, source, author and path scope) by the activity worker every
+-- kerai.activity_interval seconds (default 60); kerai.timeline and
+-- GET /api/timeline read it. parse_runs now records the root path each
+-- run parsed. Existing history is backfilled below.
+-- Apply with: psql -d kerai -f migrations/039_activity_timeline.sql
+
+BEGIN;
+
+ALTER TABLE kerai.parse_runs ADD COLUMN IF NOT EXISTS path ltree;
+
+CREATE TABLE IF NOT EXISTS kerai.activity_deltas (
+    id         BIGSERIAL PRIMARY KEY,
+    day        DATE NOT NULL,
+    source     TEXT NOT NULL CHECK (source IN ('version', 'perspective', 'parse')),
+    author     TEXT NOT NULL,
+    scope      ltree NOT NULL,
+    queued_at  TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp()
+);
+
+CREATE TABLE IF NOT EXISTS kerai.activity_daily (
+    day        DATE NOT NULL,
+    source     TEXT NOT NULL,
+    author     TEXT NOT NULL,
+    scope      ltree NOT NULL,
+    events     INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (day, source, author, scope)
+);
+
+CREATE INDEX IF NOT EXISTS idx_activity_daily_scope ON kerai.activity_daily USING gist (scope);
+
+CREATE OR REPLACE FUNCTION kerai.activity_scope(path ltree) RETURNS ltree
+LANGUAGE sql IMMUTABLE AS $$
+    SELECT COALESCE(subpath(path, 0, least(nlevel(path), 3)), ''::ltree)
+$$;
+
+CREATE OR REPLACE FUNCTION kerai.queue_activity() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF TG_TABLE_NAME = 'perspectives' THEN
+        IF TG_OP = 'UPDATE' AND NEW.weight = OLD.weight THEN
+            RETURN NULL;
+        END IF;
+        INSERT INTO kerai.activity_deltas (day, source, author, scope)
+        SELECT (NEW.updated_at AT TIME ZONE 'UTC')::date, 'perspective',
+               COALESCE((SELECT name FROM kerai.agents WHERE id = NEW.agent_id), NEW.agent_id::text),
+               kerai.activity_scope((SELECT path FROM kerai.nodes WHERE id = NEW.node_id));
+    ELSIF TG_TABLE_NAME = 'parse_runs' THEN
+        INSERT INTO kerai.activity_deltas (day, source, author, scope)
+        SELECT (NEW.created_at AT TIME ZONE 'UTC')::date, 'parse',
+               COALESCE((SELECT key_fingerprint FROM kerai.instances WHERE is_self), ''),
+               kerai.activity_scope(NEW.path);
+    ELSE
+        INSERT INTO kerai.activity_deltas (day, source, author, scope)
+        SELECT (NEW.created_at AT TIME ZONE 'UTC')::date, 'version', NEW.author,
+               kerai.activity_scope((SELECT path FROM kerai.nodes WHERE id = NEW.node_id));
+    END IF;
+    RETURN NULL;
+END;
+$$;
+
+DROP TRIGGER IF EXISTS versions_queue_activity ON kerai.versions;
+CREATE TRIGGER versions_queue_activity
+    AFTER INSERT ON kerai.versions
+    FOR EACH ROW EXECUTE FUNCTION kerai.queue_activity();
+
+DROP TRIGGER IF EXISTS perspectives_queue_activity ON kerai.perspectives;
+CREATE TRIGGER perspectives_queue_activity
+    AFTER INSERT OR UPDATE OF weight ON kerai.perspectives
+    FOR EACH ROW EXECUTE FUNCTION kerai.queue_activity();
+
+DROP TRIGGER IF EXISTS parse_runs_queue_activity ON kerai.parse_runs;
+CREATE TRIGGER parse_runs_queue_activity
+    AFTER INSERT ON kerai.parse_runs
+    FOR EACH ROW EXECUTE FUNCTION kerai.queue_activity();
+
+-- Backfill, with writers held off so no change is counted twice or missed
+LOCK TABLE kerai.versions, kerai.perspectives, kerai.parse_runs IN SHARE MODE;
+DELETE FROM kerai.activity_deltas;
+DELETE FROM kerai.activity_daily;
+INSERT INTO kerai.activity_daily (day, source, author, scope, events)
+SELECT day, source, author, scope, count(*)
+FROM (
+    SELECT (v.created_at AT TIME ZONE 'UTC')::date AS day, 'version' AS source, v.author,
+           kerai.activity_scope(n.path) AS scope
+    FROM kerai.versions v LEFT JOIN kerai.nodes n ON n.id = v.node_id
+    UNION ALL
+    SELECT (p.updated_at AT TIME ZONE 'UTC')::date, 'perspective',
+           COALESCE(a.name, p.agent_id::text), kerai.activity_scope(n.path)
+    FROM kerai.perspectives p
+    LEFT JOIN kerai.agents a ON a.id = p.agent_id
+    LEFT JOIN kerai.nodes n ON n.id = p.node_id
+    UNION ALL
+    SELECT (r.created_at AT TIME ZONE 'UTC')::date, 'parse',
+           COALESCE((SELECT key_fingerprint FROM kerai.instances WHERE is_self), ''),
+           kerai.activity_scope(r.path)
+    FROM kerai.parse_runs r
+) h
+GROUP BY day, source, author, scope;
+
+COMMIT;
@@ -0,0 +1,12 @@
+-- Migration: Keyset pagination for list endpoints
+-- GET /api/documents, /api/search?sort=created and /api/perspectives page
+-- by (created_at, id) with a cursor (X-Next-Cursor); these indexes serve
+-- the document and per-agent perspective listings in that order.
+-- Apply with: psql -d kerai -f migrations/040_list_pagination.sql
+
+BEGIN;
+
+CREATE INDEX IF NOT EXISTS idx_nodes_kind_created ON kerai.nodes (kind, created_at, id);
+CREATE INDEX IF NOT EXISTS idx_perspectives_agent_created ON kerai.perspectives (agent_id, created_at, id);
+
+COMMIT;
@@ -0,0 +1,15 @@
+-- Migration: Markdown code spans linked to code
+-- Parsing a markdown document now links its code spans (`parse_file()`,
+-- `src/lib.rs`) to the symbols and files they name with `mentions` edges,
+-- each with a confidence; kerai.impact follows them from code to docs.
+-- Documents parsed before this are linked by running
+-- SELECT kerai.link_code_mentions(); once the extension is updated.
+-- Apply with: psql -d kerai -f migrations/041_code_mentions.sql
+
+BEGIN;
+
+INSERT INTO kerai.relations (relation, description, source_kinds, target_kinds) VALUES
+    ('mentions',      'Markdown code span names code',       NULL, NULL)
+ON CONFLICT (relation) DO NOTHING;
+
+COMMIT;
@@ -0,0 +1,28 @@
+-- Migration: API tokens for the HTTP API
+-- Bearer tokens for scripts, peers and the web frontend. Only the sha256 of
+-- a token is stored; the token itself is shown once, by POST /api/tokens.
+-- Each token acts for a user and/or an instance (the issuing instance unless
+-- another is named) with scopes read, write or admin, each implying the
+-- ones before it. kerai-web requires a write token for every write route.
+-- Apply with: psql -d kerai -f migrations/042_api_tokens.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.api_tokens (
+    id             UUID PRIMARY KEY DEFAULT kerai.uuid7(),
+    name           TEXT NOT NULL,
+    token_hash     TEXT NOT NULL UNIQUE,
+    scopes         TEXT[] NOT NULL DEFAULT '{read}'
+                   CHECK (scopes <@ ARRAY['read', 'write', 'admin']::text[]),
+    user_id        UUID REFERENCES kerai.users(id) ON DELETE CASCADE,
+    instance_id    UUID REFERENCES kerai.instances(id) ON DELETE CASCADE,
+    created_by     UUID REFERENCES kerai.users(id) ON DELETE SET NULL,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at     TIMESTAMPTZ,
+    last_used_at   TIMESTAMPTZ,
+    revoked_at     TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_tokens_user ON kerai.api_tokens (user_id);
+
+COMMIT;
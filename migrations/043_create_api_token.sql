@@ -0,0 +1,44 @@
+-- Migration: kerai.create_api_token
+-- Issues an API token and returns it, with the row, once. POST /api/tokens
+-- goes through it and now always needs an admin token or session, so the
+-- first admin token is made here:
+--   SELECT kerai.create_api_token('admin', '{admin}');
+-- Apply with: psql -d kerai -f migrations/043_create_api_token.sql
+
+BEGIN;
+
+CREATE OR REPLACE FUNCTION kerai.create_api_token(
+    token_name      text,
+    token_scopes    text[] DEFAULT '{read}',
+    for_user        uuid DEFAULT NULL,
+    for_instance    uuid DEFAULT NULL,
+    expires_in_days integer DEFAULT NULL,
+    issued_by       uuid DEFAULT NULL
+) RETURNS jsonb
+LANGUAGE plpgsql AS $$
+DECLARE
+    token  text := 'kerai_' || replace(gen_random_uuid()::text, '-', '')
+                            || replace(gen_random_uuid()::text, '-', '');
+    issued jsonb;
+BEGIN
+    INSERT INTO kerai.api_tokens
+        (name, token_hash, scopes, user_id, instance_id, created_by, expires_at)
+    VALUES (
+        token_name,
+        encode(sha256(convert_to(token, 'UTF8')), 'hex'),
+        token_scopes,
+        for_user,
+        COALESCE(for_instance, (SELECT id FROM kerai.instances WHERE is_self)),
+        issued_by,
+        now() + expires_in_days * interval '1 day'
+    )
+    RETURNING jsonb_build_object(
+        'id', id, 'name', name, 'scopes', scopes, 'user_id', user_id,
+        'instance_id', instance_id, 'created_at', created_at,
+        'expires_at', expires_at)
+    INTO issued;
+    RETURN issued || jsonb_build_object('token', token);
+END;
+$$;
+
+COMMIT;
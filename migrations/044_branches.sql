@@ -0,0 +1,59 @@
+-- Migration: Branches table
+-- Creates kerai.branches, with 'main' checked out, on installs that predate
+-- it, then adds the branch_id foreign keys that 005_partition_versions_ledger
+-- and 024_snapshots leave off because the table may not exist when they run.
+-- Branch ids that name no branch are cleared first.
+-- Apply with: psql -d kerai -f migrations/044_branches.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.branches (
+    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name        TEXT NOT NULL UNIQUE,
+    parent_id   UUID REFERENCES kerai.branches(id),
+    fork_ts     BIGINT NOT NULL DEFAULT 0,
+    is_current  BOOLEAN NOT NULL DEFAULT false,
+    merged_into UUID REFERENCES kerai.branches(id),
+    merged_at   TIMESTAMPTZ,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Exactly one branch is checked out into kerai.nodes
+CREATE UNIQUE INDEX IF NOT EXISTS idx_branches_current
+    ON kerai.branches (is_current) WHERE is_current;
+
+INSERT INTO kerai.branches (name, is_current)
+    SELECT 'main', NOT EXISTS (SELECT 1 FROM kerai.branches WHERE is_current)
+    ON CONFLICT (name) DO NOTHING;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint
+                   WHERE conrelid = 'kerai.versions'::regclass
+                     AND contype = 'f' AND conkey = ARRAY[(
+                         SELECT attnum FROM pg_attribute
+                         WHERE attrelid = 'kerai.versions'::regclass
+                           AND attname = 'branch_id')]) THEN
+        UPDATE kerai.versions SET branch_id = NULL
+            WHERE branch_id IS NOT NULL
+              AND branch_id NOT IN (SELECT id FROM kerai.branches);
+        ALTER TABLE kerai.versions ADD CONSTRAINT versions_branch_id_fkey
+            FOREIGN KEY (branch_id) REFERENCES kerai.branches(id);
+    END IF;
+
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint
+                   WHERE conrelid = to_regclass('kerai.snapshots')
+                     AND contype = 'f' AND conkey = ARRAY[(
+                         SELECT attnum FROM pg_attribute
+                         WHERE attrelid = to_regclass('kerai.snapshots')
+                           AND attname = 'branch_id')])
+       AND to_regclass('kerai.snapshots') IS NOT NULL THEN
+        UPDATE kerai.snapshots SET branch_id = NULL
+            WHERE branch_id IS NOT NULL
+              AND branch_id NOT IN (SELECT id FROM kerai.branches);
+        ALTER TABLE kerai.snapshots ADD CONSTRAINT snapshots_branch_id_fkey
+            FOREIGN KEY (branch_id) REFERENCES kerai.branches(id);
+    END IF;
+END $$;
+
+COMMIT;
@@ -0,0 +1,32 @@
+-- Migration: Per-author instance and Lamport counter in the version vector
+-- kerai.version_vector gains each author's instance, highest Lamport
+-- timestamp and last update, as written by the CRDT clock on every op.
+-- Existing rows are backfilled from kerai.operations: the instance of the
+-- author's latest op, and the highest Lamport timestamp and seq logged.
+-- Apply with: psql -d kerai -f migrations/045_version_vector_columns.sql
+
+BEGIN;
+
+ALTER TABLE kerai.version_vector
+    ADD COLUMN IF NOT EXISTS instance_id UUID REFERENCES kerai.instances(id),
+    ADD COLUMN IF NOT EXISTS max_lamport BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS updated_at  TIMESTAMPTZ NOT NULL DEFAULT now();
+
+WITH seen AS (
+    SELECT author,
+           (array_agg(instance_id ORDER BY lamport_ts DESC))[1] AS instance_id,
+           max(lamport_ts) AS max_lamport,
+           max(author_seq) AS max_seq,
+           max(created_at) AS updated_at
+    FROM kerai.operations
+    GROUP BY author
+)
+INSERT INTO kerai.version_vector (author, instance_id, max_seq, max_lamport, updated_at)
+SELECT author, instance_id, max_seq, max_lamport, updated_at FROM seen
+ON CONFLICT (author) DO UPDATE SET
+    instance_id = COALESCE(kerai.version_vector.instance_id, EXCLUDED.instance_id),
+    max_seq = GREATEST(kerai.version_vector.max_seq, EXCLUDED.max_seq),
+    max_lamport = GREATEST(kerai.version_vector.max_lamport, EXCLUDED.max_lamport),
+    updated_at = GREATEST(kerai.version_vector.updated_at, EXCLUDED.updated_at);
+
+COMMIT;
@@ -0,0 +1,14 @@
+-- Migration: Subtree content hash on nodes
+-- Parsers store each node's subtree hash in kerai.nodes.content_hash and
+-- reuse identical subtrees on insert (kerai.dedup_stats reports how many).
+-- Existing nodes keep a NULL hash until their file is parsed again.
+-- Apply with: psql -d kerai -f migrations/046_node_content_hash.sql
+
+BEGIN;
+
+ALTER TABLE kerai.nodes ADD COLUMN IF NOT EXISTS content_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_nodes_content_hash
+    ON kerai.nodes (content_hash) WHERE content_hash IS NOT NULL;
+
+COMMIT;
@@ -0,0 +1,26 @@
+-- Migration: Audit log and the user columns roles are derived from
+-- The stack machine refuses words above the session's role (anonymous,
+-- user or admin, read from kerai.users.auth_provider and is_admin) and
+-- records each refusal in kerai.audit_log.
+-- Apply with: psql -d kerai -f migrations/047_audit_log.sql
+
+BEGIN;
+
+ALTER TABLE kerai.users
+    ADD COLUMN IF NOT EXISTS auth_provider TEXT NOT NULL DEFAULT 'anonymous',
+    ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN IF NOT EXISTS is_allowed BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE IF NOT EXISTS kerai.audit_log (
+    id             BIGSERIAL PRIMARY KEY,
+    user_id        UUID REFERENCES kerai.users(id) ON DELETE SET NULL,
+    workspace_id   UUID REFERENCES kerai.workspaces(id) ON DELETE SET NULL,
+    action         TEXT NOT NULL,
+    word           TEXT,
+    detail         JSONB NOT NULL DEFAULT '{}',
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_user ON kerai.audit_log (user_id, created_at);
+
+COMMIT;
@@ -0,0 +1,34 @@
+-- Migration: Version history for branches
+-- Each version records the branch it was made on, the node's row before and
+-- after it (so switching branches can rebuild kerai.nodes), and, for merge
+-- versions, the branch merged from. versions.node_id stops being a foreign
+-- key: a branch keeps the history of nodes that only exist while it is
+-- checked out. Run after 044_branches.sql, which creates kerai.branches.
+-- Apply with: psql -d kerai -f migrations/048_branch_history.sql
+
+BEGIN;
+
+ALTER TABLE kerai.versions
+    ADD COLUMN IF NOT EXISTS old_snapshot JSONB,
+    ADD COLUMN IF NOT EXISTS new_snapshot JSONB,
+    ADD COLUMN IF NOT EXISTS branch_id UUID REFERENCES kerai.branches(id),
+    ADD COLUMN IF NOT EXISTS merged_from UUID;
+
+DO $$
+DECLARE
+    fk TEXT;
+BEGIN
+    FOR fk IN
+        SELECT c.conname FROM pg_constraint c
+        JOIN pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = ANY (c.conkey)
+        WHERE c.conrelid = 'kerai.versions'::regclass AND c.contype = 'f'
+          AND a.attname = 'node_id'
+    LOOP
+        EXECUTE format('ALTER TABLE kerai.versions DROP CONSTRAINT %I', fk);
+    END LOOP;
+END $$;
+
+CREATE INDEX IF NOT EXISTS idx_versions_branch_timestamp
+    ON kerai.versions (branch_id, timestamp);
+
+COMMIT;
@@ -0,0 +1,28 @@
+-- Migration: Index advisor
+-- The index advisor worker samples pg_stat_statements for filters and joins
+-- that no index serves and keeps one row per suggested index in
+-- kerai.index_advice, ranked by estimated benefit.
+-- Apply with: psql -d kerai -f migrations/049_index_advice.sql
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS kerai.index_advice (
+    id                BIGSERIAL PRIMARY KEY,
+    table_name        TEXT NOT NULL,
+    pattern           TEXT NOT NULL,
+    index_sql         TEXT NOT NULL UNIQUE,
+    reason            TEXT NOT NULL,
+    queries           INTEGER NOT NULL DEFAULT 0,
+    calls             BIGINT NOT NULL DEFAULT 0,
+    total_ms          DOUBLE PRECISION NOT NULL DEFAULT 0,
+    estimated_benefit DOUBLE PRECISION NOT NULL DEFAULT 0,
+    status            TEXT NOT NULL DEFAULT 'open'
+                      CHECK (status IN ('open', 'applied', 'dismissed')),
+    first_seen        TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_seen         TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_index_advice_status
+    ON kerai.index_advice (status, estimated_benefit DESC);
+
+COMMIT;
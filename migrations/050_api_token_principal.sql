@@ -0,0 +1,29 @@
+-- Migration: API tokens as row-level security principals
+-- kerai serve and kerai-web call kerai.set_principal with the request's
+-- session or API token, so kerai.principal_user() resolves either: an API
+-- token by its sha256, while live, to the user it acts for.
+-- Apply with: psql -d kerai -f migrations/050_api_token_principal.sql
+
+BEGIN;
+
+CREATE OR REPLACE FUNCTION kerai.principal_user() RETURNS uuid
+LANGUAGE sql STABLE SECURITY DEFINER SET search_path = kerai, public, pg_temp AS $$
+    WITH p AS (SELECT NULLIF(current_setting('kerai.current_principal', true), '') AS token)
+    SELECT s.user_id
+    FROM kerai.sessions s
+    JOIN kerai.users u ON u.id = s.user_id, p
+    WHERE s.token = p.token
+      AND s.expires_at > now()
+      AND (u.is_allowed OR u.is_admin)
+    UNION ALL
+    SELECT t.user_id
+    FROM kerai.api_tokens t
+    JOIN kerai.users u ON u.id = t.user_id, p
+    WHERE t.token_hash = encode(sha256(convert_to(p.token, 'UTF8')), 'hex')
+      AND t.revoked_at IS NULL
+      AND (t.expires_at IS NULL OR t.expires_at > now())
+      AND (u.is_allowed OR u.is_admin)
+    LIMIT 1
+$$;
+
+COMMIT;
@@ -0,0 +1,393 @@
+/// Index advisor — missing-index suggestions from observed query patterns.
+///
+/// Samples `pg_stat_statements` for statements touching kerai tables and
+/// looks for filters the schema cannot serve from an index: JSONB metadata
+/// keys (`metadata->>'key' = ...`), metadata containment (`metadata @> ...`),
+/// ltree matches on `path` (`~`, `<@`, `@>`, `?`) and `relation` filters.
+/// Each pattern not already covered by an index becomes a row in
+/// `kerai.index_advice`, with the time spent in the matching statements as
+/// the estimated benefit. The advisor never creates indexes itself.
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use pgrx::prelude::*;
+use regex::Regex;
+use serde_json::json;
+
+use crate::sql::{sql_escape, sql_text};
+
+/// Share of a matching statement's execution time an index is assumed to
+/// save. Deliberately conservative; the figure is for ranking, not a promise.
+const BENEFIT_FACTOR: f64 = 0.5;
+
+/// Statements sampled per run, most expensive first.
+const SAMPLE_LIMIT: i64 = 500;
+
+/// One normalized statement from `pg_stat_statements`.
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub query: String,
+    pub calls: i64,
+    pub total_ms: f64,
+}
+
+/// A suggested index and the statements that would use it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Advice {
+    pub table: String,
+    pub pattern: &'static str,
+    pub index_sql: String,
+    pub reason: String,
+    pub queries: i64,
+    pub calls: i64,
+    pub total_ms: f64,
+    /// Text an existing index definition must contain to cover this.
+    covered_by: String,
+}
+
+impl Advice {
+    pub fn estimated_benefit(&self) -> f64 {
+        self.total_ms * BENEFIT_FACTOR
+    }
+
+    /// Whether an index in `existing` (`(table, indexdef)` as listed by
+    /// `pg_indexes`) already serves this pattern.
+    pub fn is_covered(&self, existing: &[(String, String)]) -> bool {
+        existing.iter().any(|(table, def)| {
+            *table == self.table && def.to_lowercase().replace("::text", "").contains(&self.covered_by)
+        })
+    }
+}
+
+/// A filter found in a statement, before it is matched to a table.
+struct Pattern {
+    qualifier: Option<String>,
+    pattern: &'static str,
+    /// Indexed expression, e.g. `(metadata->>'lang')` or `USING gist (path)`.
+    target: String,
+    covered_by: String,
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).expect("advisor regex"))
+}
+
+/// Filters in one statement that an index could serve.
+fn patterns(query: &str) -> Vec<Pattern> {
+    static META_KEY: OnceLock<Regex> = OnceLock::new();
+    static META_CONTAINS: OnceLock<Regex> = OnceLock::new();
+    static LTREE: OnceLock<Regex> = OnceLock::new();
+    static RELATION: OnceLock<Regex> = OnceLock::new();
+    let qualifier = |c: &regex::Captures| c.get(1).map(|m| m.as_str().to_lowercase());
+
+    let mut found = Vec::new();
+    let meta_key = regex(
+        &META_KEY,
+        r"(?i)(?:\b(\w+)\.)?\bmetadata\s*->>\s*'(\w+)'\s*(?:=|<>|<=|>=|<|>|\bIN\b|\bLIKE\b|\bILIKE\b)",
+    );
+    for c in meta_key.captures_iter(query) {
+        let key = &c[2];
+        found.push(Pattern {
+            qualifier: qualifier(&c),
+            pattern: "metadata_key",
+            target: format!("((metadata->>'{key}'))"),
+            covered_by: format!("metadata ->> '{}'", key.to_lowercase()),
+        });
+    }
+    let meta_contains = regex(&META_CONTAINS, r"(?i)(?:\b(\w+)\.)?\bmetadata\s*@>");
+    for c in meta_contains.captures_iter(query) {
+        found.push(Pattern {
+            qualifier: qualifier(&c),
+            pattern: "metadata_contains",
+            target: "USING gin (metadata jsonb_path_ops)".into(),
+            covered_by: "using gin (metadata".into(),
+        });
+    }
+    let ltree = regex(&LTREE, r"(?i)(?:\b(\w+)\.)?\bpath\s*(?:~|<@|@>|\?)");
+    for c in ltree.captures_iter(query) {
+        found.push(Pattern {
+            qualifier: qualifier(&c),
+            pattern: "ltree_match",
+            target: "USING gist (path)".into(),
+            covered_by: "using gist (path".into(),
+        });
+    }
+    let relation = regex(&RELATION, r"(?i)(?:\b(\w+)\.)?\brelation\s*(?:=|\bIN\b)");
+    for c in relation.captures_iter(query) {
+        found.push(Pattern {
+            qualifier: qualifier(&c),
+            pattern: "relation_filter",
+            target: "(relation)".into(),
+            covered_by: "(relation".into(),
+        });
+    }
+    found
+}
+
+/// kerai tables in a statement, keyed by the name or alias used for them.
+fn tables(query: &str) -> BTreeMap<String, String> {
+    static FROM: OnceLock<Regex> = OnceLock::new();
+    const KEYWORDS: &[&str] = &[
+        "where", "join", "left", "right", "inner", "outer", "cross", "on", "group", "order",
+        "limit", "offset", "set", "values", "returning", "using", "union", "natural", "full",
+    ];
+    let from = regex(&FROM, r"(?i)\bkerai\.(\w+)(\s*\()?(?:\s+(?:AS\s+)?(\w+))?");
+    let mut found = BTreeMap::new();
+    for c in from.captures_iter(query) {
+        // kerai.search(...) and friends are function calls, not tables
+        if c.get(2).is_some() {
+            continue;
+        }
+        let table = c[1].to_lowercase();
+        if let Some(alias) = c.get(3).map(|m| m.as_str().to_lowercase()) {
+            if !KEYWORDS.contains(&alias.as_str()) {
+                found.insert(alias, table.clone());
+            }
+        }
+        found.insert(table.clone(), table);
+    }
+    found
+}
+
+/// Group the patterns in `statements` into index suggestions, sorted by
+/// estimated benefit, largest first.
+pub fn analyze(statements: &[Statement]) -> Vec<Advice> {
+    let mut advice: BTreeMap<String, Advice> = BTreeMap::new();
+    for stmt in statements {
+        let tables = tables(&stmt.query);
+        let distinct: Vec<&String> = {
+            let mut t: Vec<&String> = tables.values().collect();
+            t.sort();
+            t.dedup();
+            t
+        };
+        let mut seen = Vec::new();
+        for p in patterns(&stmt.query) {
+            // Unqualified columns are only attributable with a single table
+            let table = match &p.qualifier {
+                Some(q) => tables.get(q),
+                None if distinct.len() == 1 => Some(distinct[0]),
+                None => None,
+            };
+            let Some(table) = table else {
+                continue;
+            };
+
+            let index_sql = format!("CREATE INDEX ON kerai.{table} {}", p.target);
+            if seen.contains(&index_sql) {
+                continue;
+            }
+            seen.push(index_sql.clone());
+            let entry = advice.entry(index_sql.clone()).or_insert_with(|| Advice {
+                table: table.clone(),
+                pattern: p.pattern,
+                index_sql,
+                reason: match p.pattern {
+                    "metadata_key" => format!("filters on {} without an expression index", p.target),
+                    "metadata_contains" => "JSONB containment on metadata without a GIN index".into(),
+                    "ltree_match" => "ltree match on path without a GiST index".into(),
+                    _ => "filters on relation without an index".into(),
+                },
+                queries: 0,
+                calls: 0,
+                total_ms: 0.0,
+                covered_by: p.covered_by.clone(),
+            });
+            entry.queries += 1;
+            entry.calls += stmt.calls;
+            entry.total_ms += stmt.total_ms;
+        }
+    }
+
+    let mut out: Vec<Advice> = advice.into_values().collect();
+    out.sort_by(|a, b| b.total_ms.total_cmp(&a.total_ms));
+    out
+}
+
+/// Sample `pg_stat_statements`, record suggestions and return how many
+/// statements were sampled. Errors if the extension is not installed.
+pub fn sample() -> i64 {
+    let installed = Spi::get_one::<bool>(
+        "SELECT EXISTS(SELECT 1 FROM pg_extension WHERE extname = 'pg_stat_statements')",
+    )
+    .unwrap()
+    .unwrap_or(false);
+    if !installed {
+        error!("pg_stat_statements is not installed — CREATE EXTENSION pg_stat_statements first");
+    }
+
+    let mut statements = Vec::new();
+    let mut existing = Vec::new();
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                &format!(
+                    "SELECT query, calls, total_exec_time FROM pg_stat_statements
+                     WHERE query ILIKE '%kerai.%' AND query NOT ILIKE '%pg_stat_statements%'
+                     ORDER BY total_exec_time DESC LIMIT {SAMPLE_LIMIT}"
+                ),
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            statements.push(Statement {
+                query: row.get::<String>(1).unwrap().unwrap_or_default(),
+                calls: row.get::<i64>(2).unwrap().unwrap_or(0),
+                total_ms: row.get::<f64>(3).unwrap().unwrap_or(0.0),
+            });
+        }
+
+        let rows = client
+            .select(
+                "SELECT tablename::text, indexdef FROM pg_indexes WHERE schemaname = 'kerai'",
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            existing.push((
+                row.get::<String>(1).unwrap().unwrap_or_default(),
+                row.get::<String>(2).unwrap().unwrap_or_default(),
+            ));
+        }
+    });
+
+    for a in analyze(&statements) {
+        // An index created since the advice was given closes it
+        if a.is_covered(&existing) {
+            Spi::run(&format!(
+                "UPDATE kerai.index_advice SET status = 'applied', last_seen = now()
+                 WHERE index_sql = {} AND status = 'open'",
+                sql_text(&a.index_sql),
+            ))
+            .unwrap();
+            continue;
+        }
+        Spi::run(&format!(
+            "INSERT INTO kerai.index_advice
+                 (table_name, pattern, index_sql, reason, queries, calls, total_ms, estimated_benefit)
+             VALUES ({}, {}, {}, {}, {}, {}, {}, {})
+             ON CONFLICT (index_sql) DO UPDATE SET
+                 reason = EXCLUDED.reason,
+                 queries = EXCLUDED.queries,
+                 calls = EXCLUDED.calls,
+                 total_ms = EXCLUDED.total_ms,
+                 estimated_benefit = EXCLUDED.estimated_benefit,
+                 -- applied advice whose index was dropped again reopens
+                 status = CASE kerai.index_advice.status
+                     WHEN 'applied' THEN 'open' ELSE kerai.index_advice.status END,
+                 last_seen = now()",
+            sql_text(&a.table),
+            sql_text(a.pattern),
+            sql_text(&a.index_sql),
+            sql_text(&a.reason),
+            a.queries,
+            a.calls,
+            a.total_ms,
+            a.estimated_benefit(),
+        ))
+        .unwrap();
+    }
+
+    statements.len() as i64
+}
+
+/// Sample query statistics now and return open advice, best first:
+/// `{sampled, advice: [{table, pattern, index_sql, reason, queries, calls,
+/// total_ms, estimated_benefit, last_seen}]}`.
+#[pg_extern]
+fn advise_indexes() -> pgrx::JsonB {
+    let sampled = sample();
+    let advice = Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'table', table_name,
+            'pattern', pattern,
+            'index_sql', index_sql,
+            'reason', reason,
+            'queries', queries,
+            'calls', calls,
+            'total_ms', total_ms,
+            'estimated_benefit', estimated_benefit,
+            'last_seen', last_seen
+        ) ORDER BY estimated_benefit DESC), '[]'::jsonb)
+        FROM kerai.index_advice WHERE status = 'open'",
+    )
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+    pgrx::JsonB(json!({"sampled": sampled, "advice": advice}))
+}
+
+/// Mark a suggestion dismissed so it stops being listed.
+#[pg_extern]
+fn dismiss_index_advice(index_sql: &str) -> bool {
+    Spi::get_one::<bool>(&format!(
+        "WITH d AS (UPDATE kerai.index_advice SET status = 'dismissed'
+                    WHERE index_sql = '{}' RETURNING 1)
+         SELECT count(*) > 0 FROM d",
+        sql_escape(index_sql),
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stmt(query: &str, calls: i64, total_ms: f64) -> Statement {
+        Statement { query: query.into(), calls, total_ms }
+    }
+
+    #[test]
+    fn finds_metadata_key_filters_by_alias() {
+        assert!(!tables("SELECT kerai.search($1, NULL, NULL)").contains_key("search"));
+        let stmts = [stmt(
+            "SELECT n.id FROM kerai.nodes n JOIN kerai.edges e ON e.source_id = n.id \
+             WHERE n.metadata->>'visibility' = $1",
+            10,
+            400.0,
+        )];
+        let advice = analyze(&stmts);
+        assert_eq!(advice.len(), 1);
+        assert_eq!(advice[0].index_sql, "CREATE INDEX ON kerai.nodes ((metadata->>'visibility'))");
+        assert_eq!(advice[0].estimated_benefit(), 200.0);
+    }
+
+    #[test]
+    fn existing_indexes_suppress_advice() {
+        let stmts = [
+            stmt("SELECT * FROM kerai.nodes WHERE path <@ $1", 5, 50.0),
+            stmt("SELECT * FROM kerai.edges WHERE relation = $1", 5, 50.0),
+        ];
+        let existing = vec![
+            ("nodes".to_string(), "CREATE INDEX idx_nodes_path ON kerai.nodes USING gist (path)".to_string()),
+            ("edges".to_string(), "CREATE INDEX idx_edges_relation ON kerai.edges USING btree (relation)".to_string()),
+        ];
+        let advice = analyze(&stmts);
+        assert_eq!(advice.len(), 2);
+        assert!(advice.iter().all(|a| a.is_covered(&existing)));
+        assert!(!advice[0].is_covered(&[]));
+    }
+
+    #[test]
+    fn unqualified_columns_need_a_single_table() {
+        let stmts = [stmt(
+            "SELECT * FROM kerai.nodes JOIN kerai.perspectives ON true WHERE metadata @> $1",
+            1,
+            1.0,
+        )];
+        assert!(analyze(&stmts).is_empty());
+    }
+
+    #[test]
+    fn statements_are_summed_per_index() {
+        let stmts = [
+            stmt("SELECT * FROM kerai.tasks WHERE metadata->>'lane' = $1", 3, 30.0),
+            stmt("UPDATE kerai.tasks SET status = $1 WHERE metadata->>'lane' IN ($2)", 2, 90.0),
+        ];
+        let advice = analyze(&stmts);
+        assert_eq!(advice.len(), 1);
+        assert_eq!((advice[0].queries, advice[0].calls, advice[0].total_ms), (2, 5, 120.0));
+    }
+}
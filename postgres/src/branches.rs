@@ -0,0 +1,385 @@
+/// Branches — named lines of node history over kerai.versions.
+///
+/// Every versioned op is stamped with the branch checked out at the time.
+/// A branch sees its own versions plus its parent's up to `fork_ts` (and so
+/// on up the chain). Switching undoes the versions only the old branch sees,
+/// newest first, then replays the ones only the new branch sees, restoring
+/// node rows from the version snapshots. Merging copies the source branch's
+/// changes onto the current branch as new versions; a node changed on both
+/// sides since they diverged is a conflict and keeps the current branch's
+/// value.
+///
+/// `rewind_to` and `version_batches` let a client walk the current branch's
+/// history: rewind kerai.nodes to a Lamport time inside a transaction, read
+/// it, then roll back.
+///
+/// Parser writes and deletes are not versioned, so they apply to every branch.
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::crdt::{self, clock};
+use crate::sql::{sql_text, sql_uuid};
+
+/// Id and name of the branch checked out into kerai.nodes.
+fn current_branch() -> (String, String) {
+    let row = Spi::get_two::<String, String>(
+        "SELECT id::text, name FROM kerai.branches WHERE is_current",
+    )
+    .unwrap();
+    match row {
+        (Some(id), Some(name)) => (id, name),
+        _ => error!("No current branch — is the extension schema up to date?"),
+    }
+}
+
+fn branch_id(name: &str) -> String {
+    Spi::get_one::<String>(&format!(
+        "SELECT id::text FROM kerai.branches WHERE name = {}",
+        sql_text(name),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("Branch not found: {}", name))
+}
+
+/// Versions visible on a branch: its own, plus each ancestor's up to the
+/// point the chain forked from it.
+fn visible_sql(branch_id: &str) -> String {
+    format!(
+        "SELECT v.* FROM kerai.versions v JOIN (
+            WITH RECURSIVE chain(id, upto) AS (
+                SELECT id, 9223372036854775807::bigint FROM kerai.branches WHERE id = {}
+                UNION ALL
+                SELECT p.id, LEAST(c.upto, cb.fork_ts)
+                FROM chain c
+                JOIN kerai.branches cb ON cb.id = c.id
+                JOIN kerai.branches p ON p.id = cb.parent_id
+            ) SELECT id, upto FROM chain
+        ) c ON v.branch_id = c.id AND v.timestamp <= c.upto",
+        sql_uuid(branch_id),
+    )
+}
+
+/// Versions `a` sees and `b` does not, as `[{id, node_id, operation,
+/// new_parent, merged_from}]` in timestamp order (`desc` for undoing).
+fn exclusive_versions(a: &str, b: &str, desc: bool) -> Vec<Value> {
+    let order = if desc { "DESC" } else { "ASC" };
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_agg(jsonb_build_object(
+            'id', x.id, 'node_id', x.node_id, 'operation', x.operation,
+            'new_parent', x.new_parent, 'merged_from', x.merged_from
+        ) ORDER BY x.timestamp {order}, x.created_at {order})
+        FROM ({}) x WHERE x.id NOT IN (SELECT id FROM ({}) y)",
+        visible_sql(a),
+        visible_sql(b),
+    ))
+    .unwrap()
+    .and_then(|j| j.0.as_array().cloned())
+    .unwrap_or_default()
+}
+
+/// Put a node back to a version's `old_snapshot` or `new_snapshot`.
+/// A missing snapshot means the node did not exist on that side.
+fn restore(version_id: &str, node_id: &str, column: &str) {
+    let has_snapshot = Spi::get_one::<bool>(&format!(
+        "SELECT {column} IS NOT NULL FROM kerai.versions WHERE id = {}",
+        sql_uuid(version_id),
+    ))
+    .unwrap()
+    .unwrap_or(false);
+
+    if !has_snapshot {
+        let nid = sql_uuid(node_id);
+        for stmt in [
+            format!("DELETE FROM kerai.edges WHERE source_id = {nid} OR target_id = {nid}"),
+            format!("DELETE FROM kerai.associations WHERE source_id = {nid} OR target_id = {nid}"),
+            format!("DELETE FROM kerai.perspectives WHERE node_id = {nid} OR context_id = {nid}"),
+            format!("DELETE FROM kerai.nodes WHERE id = {nid}"),
+        ] {
+            Spi::run(&stmt).expect("Failed to remove branch-only node");
+        }
+        return;
+    }
+
+    Spi::run(&format!(
+        "INSERT INTO kerai.nodes (id, instance_id, kind, language, content, parent_id,
+                                  position, path, metadata, content_hash, created_at)
+         SELECT r.id, r.instance_id, r.kind, r.language, r.content, r.parent_id,
+                r.position, r.path, r.metadata, r.content_hash, r.created_at
+         FROM kerai.versions v, jsonb_populate_record(NULL::kerai.nodes, v.{column}) r
+         WHERE v.id = {}
+         ON CONFLICT (id) DO UPDATE SET
+             kind = EXCLUDED.kind,
+             language = EXCLUDED.language,
+             content = EXCLUDED.content,
+             parent_id = EXCLUDED.parent_id,
+             position = EXCLUDED.position,
+             path = EXCLUDED.path,
+             metadata = EXCLUDED.metadata,
+             content_hash = EXCLUDED.content_hash",
+        sql_uuid(version_id),
+    ))
+    .expect("Failed to restore node snapshot");
+}
+
+/// Create a branch forked from the current one at the present Lamport time.
+/// The current branch stays checked out.
+///
+/// Returns `{id, name, parent, fork_ts}`.
+#[pg_extern]
+fn create_branch(name: &str) -> pgrx::JsonB {
+    if name.trim().is_empty() {
+        error!("Branch name must not be empty");
+    }
+    let (parent_id, parent_name) = current_branch();
+    let fork_ts = clock::current_lamport_ts();
+
+    let id = Spi::get_one::<String>(&format!(
+        "INSERT INTO kerai.branches (name, parent_id, fork_ts)
+         VALUES ({}, {}, {fork_ts})
+         ON CONFLICT (name) DO NOTHING
+         RETURNING id::text",
+        sql_text(name),
+        sql_uuid(&parent_id),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("Branch already exists: {}", name));
+
+    pgrx::JsonB(json!({
+        "id": id,
+        "name": name,
+        "parent": parent_name,
+        "fork_ts": fork_ts,
+    }))
+}
+
+/// Check out a branch: rewind the current branch's own changes and replay
+/// the target's onto kerai.nodes.
+///
+/// Returns `{branch, previous, undone, replayed}`.
+#[pg_extern]
+fn switch_branch(name: &str) -> pgrx::JsonB {
+    let (from_id, from_name) = current_branch();
+    let to_id = branch_id(name);
+    if to_id == from_id {
+        return pgrx::JsonB(json!({
+            "branch": name,
+            "previous": from_name,
+            "undone": 0,
+            "replayed": 0,
+        }));
+    }
+
+    let undo = exclusive_versions(&from_id, &to_id, true);
+    for v in &undo {
+        let (vid, nid) = (v["id"].as_str().unwrap_or(""), v["node_id"].as_str().unwrap_or(""));
+        restore(vid, nid, "old_snapshot");
+    }
+    let redo = exclusive_versions(&to_id, &from_id, false);
+    for v in &redo {
+        let (vid, nid) = (v["id"].as_str().unwrap_or(""), v["node_id"].as_str().unwrap_or(""));
+        restore(vid, nid, "new_snapshot");
+    }
+
+    // Two statements: the partial unique index allows one current branch
+    Spi::run("UPDATE kerai.branches SET is_current = false WHERE is_current").unwrap();
+    Spi::run(&format!(
+        "UPDATE kerai.branches SET is_current = true WHERE id = {}",
+        sql_uuid(&to_id),
+    ))
+    .unwrap();
+
+    pgrx::JsonB(json!({
+        "branch": name,
+        "previous": from_name,
+        "undone": undo.len(),
+        "replayed": redo.len(),
+    }))
+}
+
+/// Merge a branch into the current one.
+///
+/// Changes only the source branch has are applied in timestamp order and
+/// recorded as new versions on the current branch, keeping their original
+/// author. Nodes the current branch also changed since the two diverged,
+/// and nodes whose new parent no longer exists here, are reported as
+/// conflicts and left as they are.
+///
+/// Returns `{branch, into, applied, conflicts: [{node_id, reason}]}`.
+#[pg_extern]
+fn merge_branch(name: &str) -> pgrx::JsonB {
+    let (into_id, into_name) = current_branch();
+    let from_id = branch_id(name);
+    if from_id == into_id {
+        error!("Cannot merge branch '{}' into itself", name);
+    }
+
+    let theirs = exclusive_versions(&from_id, &into_id, false);
+    let ours = exclusive_versions(&into_id, &from_id, false);
+    // Copies from an earlier merge of this branch are neither side's change
+    let merged: Vec<&str> = ours.iter().filter_map(|v| v["merged_from"].as_str()).collect();
+    let changed_here: Vec<&str> = ours
+        .iter()
+        .filter(|v| !v["merged_from"].as_str().is_some_and(|m| theirs.iter().any(|t| t["id"] == m)))
+        .filter_map(|v| v["node_id"].as_str())
+        .collect();
+
+    let mut conflicts: Vec<Value> = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
+    let mut applied = 0;
+    for v in &theirs {
+        let vid = v["id"].as_str().unwrap_or("");
+        if merged.contains(&vid) {
+            continue;
+        }
+        let nid = v["node_id"].as_str().unwrap_or("").to_string();
+        if skipped.contains(&nid) {
+            continue;
+        }
+
+        let reason = if changed_here.contains(&nid.as_str()) {
+            Some("changed on both branches")
+        } else {
+            let parent_missing = match v["new_parent"].as_str() {
+                Some(parent) => !Spi::get_one::<bool>(&format!(
+                    "SELECT EXISTS(SELECT 1 FROM kerai.nodes WHERE id = {})",
+                    sql_uuid(parent),
+                ))
+                .unwrap()
+                .unwrap_or(false),
+                None => false,
+            };
+            parent_missing.then_some("parent missing")
+        };
+        if let Some(reason) = reason {
+            conflicts.push(json!({"node_id": nid, "reason": reason}));
+            skipped.push(nid);
+            continue;
+        }
+
+        // Record first, so the row as it is here becomes the old snapshot
+        let merged_id = Spi::get_one::<String>(&format!(
+            "INSERT INTO kerai.versions (node_id, instance_id, operation, old_parent, new_parent,
+                 old_position, new_position, old_content, new_content, old_snapshot, new_snapshot,
+                 branch_id, merged_from, author, timestamp, signed_by)
+             SELECT v.node_id, v.instance_id, v.operation, (b.row->>'parent_id')::uuid, v.new_parent,
+                 (b.row->>'position')::integer, v.new_position, b.row->>'content', v.new_content,
+                 b.row, v.new_snapshot, {}, v.id, v.author, {},
+                 (SELECT id FROM kerai.instances WHERE is_self)
+             FROM kerai.versions v
+             LEFT JOIN LATERAL (
+                 SELECT to_jsonb(n) - 'tsv' AS row FROM kerai.nodes n WHERE n.id = v.node_id
+             ) b ON true
+             WHERE v.id = {}
+             RETURNING id::text",
+            sql_uuid(&into_id),
+            clock::next_lamport_ts(),
+            sql_uuid(vid),
+        ))
+        .unwrap()
+        .unwrap_or_else(|| error!("Failed to record merged version {}", vid));
+        crdt::sign_version(&merged_id);
+        restore(vid, &nid, "new_snapshot");
+        applied += 1;
+    }
+
+    Spi::run(&format!(
+        "UPDATE kerai.branches SET merged_into = {}, merged_at = now() WHERE id = {}",
+        sql_uuid(&into_id),
+        sql_uuid(&from_id),
+    ))
+    .unwrap();
+
+    pgrx::JsonB(json!({
+        "branch": name,
+        "into": into_name,
+        "applied": applied,
+        "conflicts": conflicts,
+    }))
+}
+
+/// All branches, oldest first: `[{name, current, parent, fork_ts,
+/// versions, merged_into, created_at}]`, where `versions` counts the
+/// branch's own versions.
+#[pg_extern]
+fn list_branches() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'name', b.name,
+            'current', b.is_current,
+            'parent', p.name,
+            'fork_ts', b.fork_ts,
+            'versions', (SELECT count(*) FROM kerai.versions v WHERE v.branch_id = b.id),
+            'merged_into', m.name,
+            'created_at', b.created_at
+        ) ORDER BY b.created_at, b.name), '[]'::jsonb)
+        FROM kerai.branches b
+        LEFT JOIN kerai.branches p ON p.id = b.parent_id
+        LEFT JOIN kerai.branches m ON m.id = b.merged_into",
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+/// Undo the current branch's versions newer than Lamport time `ts`, newest
+/// first, so kerai.nodes shows the branch as it was at `ts`. Only allowed
+/// inside an explicit transaction, which the caller rolls back once done
+/// reading; the versions themselves are left alone.
+///
+/// Returns `{timestamp, undone}`.
+#[pg_extern]
+fn rewind_to(ts: i64) -> pgrx::JsonB {
+    if !unsafe { pg_sys::IsTransactionBlock() } {
+        error!("kerai.rewind_to must run inside BEGIN ... ROLLBACK");
+    }
+    let (branch, _) = current_branch();
+    let undo = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_agg(jsonb_build_object('id', x.id, 'node_id', x.node_id)
+            ORDER BY x.timestamp DESC, x.created_at DESC)
+        FROM ({}) x WHERE x.timestamp > {ts}",
+        visible_sql(&branch),
+    ))
+    .unwrap()
+    .and_then(|j| j.0.as_array().cloned())
+    .unwrap_or_default();
+
+    for v in &undo {
+        let (vid, nid) = (v["id"].as_str().unwrap_or(""), v["node_id"].as_str().unwrap_or(""));
+        restore(vid, nid, "old_snapshot");
+    }
+    pgrx::JsonB(json!({ "timestamp": ts, "undone": undo.len() }))
+}
+
+/// The current branch's history grouped by Lamport timestamp, oldest first:
+/// `[{timestamp, created_at, authors: [{fingerprint, name}], ops, nodes}]`.
+/// Author names come from the registered instances and are null for
+/// unknown keys.
+#[pg_extern]
+fn version_batches() -> pgrx::JsonB {
+    let (branch, _) = current_branch();
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'timestamp', b.timestamp,
+            'created_at', b.created_at,
+            'authors', b.authors,
+            'ops', b.ops,
+            'nodes', b.nodes
+        ) ORDER BY b.timestamp), '[]'::jsonb)
+        FROM (
+            SELECT v.timestamp,
+                   to_char(max(v.created_at) AT TIME ZONE 'UTC',
+                           'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"') AS created_at,
+                   count(*) AS ops,
+                   count(DISTINCT v.node_id) AS nodes,
+                   (SELECT jsonb_agg(jsonb_build_object('fingerprint', a.author, 'name', i.name)
+                        ORDER BY a.author)
+                    FROM (SELECT DISTINCT author FROM ({visible}) w
+                          WHERE w.timestamp = v.timestamp) a
+                    LEFT JOIN kerai.instances i ON i.key_fingerprint = a.author) AS authors
+            FROM ({visible}) v
+            GROUP BY v.timestamp
+        ) b",
+        visible = visible_sql(&branch),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
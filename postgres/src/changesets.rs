@@ -0,0 +1,387 @@
+/// Changesets — node operations staged for review before they are applied.
+///
+/// `stage_op` records an insert, content or metadata update, move or
+/// delete in an open changeset without touching kerai.nodes; inserts get
+/// their node id when staged, so later ops in the changeset can refer to
+/// the new node. `apply_changeset` applies the ops in order as signed
+/// operations carrying the changeset id in their payload, the way
+/// `kerai.replace` marks its edits; `discard_changeset` closes it instead.
+///
+/// `with_changeset` puts a changeset in effect for the session: find,
+/// tree and the reconstruct functions then answer as if its ops were
+/// applied. They rerun themselves through kerai.read_with_changeset
+/// (schema.rs), which applies the ops in a subtransaction and rolls it
+/// back once the result is read, so reviewers see exactly what applying
+/// would produce and nothing is kept. The web API does the same for a
+/// whole request when given a `changeset` parameter.
+use std::ffi::CString;
+
+use pgrx::guc::{GucContext, GucFlags, GucRegistry, GucSetting};
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::sql::{sql_opt_text, sql_text, sql_uuid};
+
+/// Operations a changeset can stage.
+const STAGED_OPS: &[&str] = &[
+    "insert_node",
+    "update_content",
+    "update_metadata",
+    "move_node",
+    "delete_node",
+];
+
+/// Changeset reads go through, empty for none.
+static CHANGESET: GucSetting<Option<CString>> = GucSetting::<Option<CString>>::new(None);
+
+pub fn register_gucs() {
+    GucRegistry::define_string_guc(
+        c"kerai.changeset",
+        c"Changeset find, tree and reconstruct read as if applied",
+        c"Set with kerai.with_changeset(id). Its staged ops are applied for each read and rolled back.",
+        &CHANGESET,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+}
+
+/// The changeset in effect for this session, if any.
+pub(crate) fn active() -> Option<String> {
+    CHANGESET
+        .get()
+        .and_then(|v| v.into_string().ok())
+        .filter(|v| !v.is_empty())
+}
+
+/// Run `query`, which returns one JSONB value and takes the JSON `args`
+/// as `$1`, as if `changeset`'s staged ops were applied.
+pub(crate) fn read_through(changeset: &str, query: &str, args: Value) -> Value {
+    Spi::get_one_with_args::<pgrx::JsonB>(
+        "SELECT kerai.read_with_changeset($1::uuid, $2, $3)",
+        &[changeset.into(), query.into(), pgrx::JsonB(args).into()],
+    )
+    .unwrap_or_else(|e| error!("Failed to read through changeset {}: {}", changeset, e))
+    .map_or(Value::Null, |j| j.0)
+}
+
+/// Whether `op_type` on `node_id` with `payload` can be staged. Inserts
+/// may leave `node_id` out; everything else names its node.
+fn check_stageable(op_type: &str, node_id: Option<&str>, payload: &Value) -> Result<(), String> {
+    if !STAGED_OPS.contains(&op_type) {
+        return Err(format!(
+            "Cannot stage '{}': changesets hold {}",
+            op_type,
+            STAGED_OPS.join(", ")
+        ));
+    }
+    if op_type != "insert_node" && node_id.is_none() {
+        return Err(format!("op_type '{}' requires a node_id", op_type));
+    }
+    if !payload.is_object() {
+        return Err("Staged op payload must be a JSON object".to_string());
+    }
+    Ok(())
+}
+
+/// A staged op's payload as applied: tagged with its changeset.
+fn stamp(payload: &Value, changeset: &str) -> Value {
+    let mut payload = payload.clone();
+    if let Some(obj) = payload.as_object_mut() {
+        obj.insert("changeset".to_string(), json!(changeset));
+    }
+    payload
+}
+
+/// Status of changeset `id`; errors when there is none.
+fn status(id: &str) -> String {
+    Spi::get_one::<String>(&format!(
+        "SELECT status FROM kerai.changesets WHERE id = {}",
+        sql_uuid(id),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("Changeset not found: {}", id))
+}
+
+fn require_open(id: &str) {
+    let status = status(id);
+    if status != "open" {
+        error!("Changeset {} is {}", id, status);
+    }
+}
+
+/// Whether `node_id` exists or is inserted earlier in changeset `id`.
+fn known_node(id: &str, node_id: &str) -> bool {
+    Spi::get_one::<bool>(&format!(
+        "SELECT EXISTS(SELECT 1 FROM kerai.nodes WHERE id = {nid})
+             OR EXISTS(SELECT 1 FROM kerai.changeset_ops
+                       WHERE changeset_id = {cid} AND node_id = {nid} AND op_type = 'insert_node')",
+        cid = sql_uuid(id),
+        nid = sql_uuid(node_id),
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Staged ops of changeset `id` in order, as `[{seq, op_type, node_id,
+/// payload, staged_at}]`.
+fn staged_ops(id: &str) -> Vec<Value> {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'seq', seq,
+            'op_type', op_type,
+            'node_id', node_id,
+            'payload', payload,
+            'staged_at', staged_at
+        ) ORDER BY seq), '[]'::jsonb)
+        FROM kerai.changeset_ops WHERE changeset_id = {}",
+        sql_uuid(id),
+    ))
+    .unwrap()
+    .and_then(|j| j.0.as_array().cloned())
+    .unwrap_or_default()
+}
+
+/// Apply changeset `id`'s staged ops through `kerai.apply_op`, returning
+/// what each application reports.
+fn apply_staged(id: &str) -> Vec<Value> {
+    staged_ops(id)
+        .iter()
+        .map(|op| {
+            Spi::get_one_with_args::<pgrx::JsonB>(
+                "SELECT kerai.apply_op($1, $2::uuid, $3)",
+                &[
+                    op["op_type"].as_str().unwrap_or_default().into(),
+                    op["node_id"].as_str().unwrap_or_default().into(),
+                    pgrx::JsonB(stamp(&op["payload"], id)).into(),
+                ],
+            )
+            .unwrap_or_else(|e| error!("Changeset {} op {} failed: {}", id, op["seq"], e))
+            .map_or(Value::Null, |j| j.0)
+        })
+        .collect()
+}
+
+/// Changeset `id` as `{id, description, status, created_by, created_at,
+/// closed_at, ops}`, `ops` being the number staged.
+fn changeset_json(id: &str) -> Value {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_build_object(
+            'id', c.id,
+            'description', c.description,
+            'status', c.status,
+            'created_by', c.created_by,
+            'created_at', c.created_at,
+            'closed_at', c.closed_at,
+            'ops', (SELECT count(*) FROM kerai.changeset_ops o WHERE o.changeset_id = c.id)
+        ) FROM kerai.changesets c WHERE c.id = {}",
+        sql_uuid(id),
+    ))
+    .unwrap()
+    .map_or(Value::Null, |j| j.0)
+}
+
+/// Open a changeset to stage operations in.
+///
+/// Returns it as `get_changeset` does, without `staged`.
+#[pg_extern]
+fn create_changeset(description: default!(Option<&str>, "NULL")) -> pgrx::JsonB {
+    let id = Spi::get_one::<String>(&format!(
+        "INSERT INTO kerai.changesets (description) VALUES ({}) RETURNING id::text",
+        sql_opt_text(&description.map(String::from)),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("Failed to create changeset"));
+    pgrx::JsonB(changeset_json(&id))
+}
+
+/// Stage `op_type` in open changeset `changeset_id`, with the node id and
+/// payload `kerai.apply_op` would take. `insert_node` may leave `node_id`
+/// null to have one assigned; other ops must name a node that exists or
+/// that the changeset inserts earlier.
+///
+/// Returns `{changeset, seq, op_type, node_id}`.
+#[pg_extern]
+fn stage_op(
+    changeset_id: pgrx::Uuid,
+    op_type: &str,
+    node_id: Option<pgrx::Uuid>,
+    payload: pgrx::JsonB,
+) -> pgrx::JsonB {
+    let id = changeset_id.to_string();
+    require_open(&id);
+    let node_id = node_id.map(|u| u.to_string());
+    check_stageable(op_type, node_id.as_deref(), &payload.0).unwrap_or_else(|e| error!("{}", e));
+    let node_id = match node_id {
+        Some(n) if op_type != "insert_node" && !known_node(&id, &n) => {
+            error!("Node not found: {}", n)
+        }
+        Some(n) => n,
+        None => uuid::Uuid::now_v7().to_string(),
+    };
+
+    let seq = Spi::get_one_with_args::<i32>(
+        "INSERT INTO kerai.changeset_ops (changeset_id, seq, op_type, node_id, payload)
+         SELECT $1::uuid, COALESCE(max(seq), 0) + 1, $2, $3::uuid, $4
+         FROM kerai.changeset_ops WHERE changeset_id = $1::uuid
+         RETURNING seq",
+        &[
+            id.as_str().into(),
+            op_type.into(),
+            node_id.as_str().into(),
+            payload.into(),
+        ],
+    )
+    .unwrap_or_else(|e| error!("Failed to stage {}: {}", op_type, e))
+    .unwrap_or_default();
+
+    pgrx::JsonB(json!({
+        "changeset": id,
+        "seq": seq,
+        "op_type": op_type,
+        "node_id": node_id,
+    }))
+}
+
+/// Changeset `changeset_id` with its staged ops: `{id, description,
+/// status, created_by, created_at, closed_at, ops, staged: [{seq,
+/// op_type, node_id, payload, staged_at}]}`.
+#[pg_extern]
+fn get_changeset(changeset_id: pgrx::Uuid) -> pgrx::JsonB {
+    let id = changeset_id.to_string();
+    let mut changeset = changeset_json(&id);
+    if changeset.is_null() {
+        error!("Changeset not found: {}", id);
+    }
+    changeset["staged"] = json!(staged_ops(&id));
+    pgrx::JsonB(changeset)
+}
+
+/// Changesets, newest first, optionally only those with `status` (open,
+/// applied or discarded). Each is as `create_changeset` returns it plus
+/// `active`: whether reads in this session go through it.
+#[pg_extern]
+fn list_changesets(status: default!(Option<&str>, "NULL")) -> pgrx::JsonB {
+    let filter = status.map_or(String::new(), |s| {
+        format!("WHERE c.status = {}", sql_text(s))
+    });
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', c.id,
+            'description', c.description,
+            'status', c.status,
+            'created_by', c.created_by,
+            'created_at', c.created_at,
+            'closed_at', c.closed_at,
+            'ops', (SELECT count(*) FROM kerai.changeset_ops o WHERE o.changeset_id = c.id),
+            'active', c.id::text = current_setting('kerai.changeset', true)
+        ) ORDER BY c.created_at DESC), '[]'::jsonb)
+        FROM kerai.changesets c {}",
+        filter,
+    ))
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+/// Apply open changeset `changeset_id`'s staged ops in the current
+/// transaction, for a caller that rolls them back after reading: this is
+/// how kerai.read_with_changeset and the web API's `changeset` parameter
+/// preview a changeset. A closed changeset has nothing to preview.
+///
+/// Returns the number of ops applied.
+#[pg_extern]
+fn preview_changeset(changeset_id: pgrx::Uuid) -> i64 {
+    let id = changeset_id.to_string();
+    if status(&id) != "open" {
+        return 0;
+    }
+    apply_staged(&id).len() as i64
+}
+
+/// Apply open changeset `changeset_id`: each staged op in order through
+/// `kerai.apply_op`, with the changeset id in its payload. The changeset
+/// is then closed as applied.
+///
+/// Returns `{changeset, applied: [what apply_op returned for each op]}`.
+#[pg_extern]
+fn apply_changeset(changeset_id: pgrx::Uuid) -> pgrx::JsonB {
+    let id = changeset_id.to_string();
+    require_open(&id);
+    let applied = apply_staged(&id);
+    Spi::run(&format!(
+        "UPDATE kerai.changesets SET status = 'applied', closed_at = now() WHERE id = {}",
+        sql_uuid(&id),
+    ))
+    .unwrap();
+    pgrx::JsonB(json!({
+        "changeset": id,
+        "applied": applied,
+    }))
+}
+
+/// Close open changeset `changeset_id` without applying it; its staged
+/// ops are kept for the record. Returns whether it was open.
+#[pg_extern]
+fn discard_changeset(changeset_id: pgrx::Uuid) -> bool {
+    Spi::get_one::<bool>(&format!(
+        "WITH d AS (
+            UPDATE kerai.changesets SET status = 'discarded', closed_at = now()
+            WHERE id = {} AND status = 'open' RETURNING 1
+        ) SELECT EXISTS(SELECT 1 FROM d)",
+        sql_uuid(&changeset_id.to_string()),
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Read find, tree and reconstruct as if open changeset `changeset_id`
+/// were applied, for the rest of the session or, with `local`, the current
+/// transaction. Null goes back to reading the graph as it is.
+///
+/// Returns the changeset as `create_changeset` does, null when cleared.
+#[pg_extern]
+fn with_changeset(
+    changeset_id: default!(Option<pgrx::Uuid>, "NULL"),
+    local: default!(bool, false),
+) -> Option<pgrx::JsonB> {
+    let id = changeset_id.map(|u| u.to_string());
+    if let Some(id) = &id {
+        require_open(id);
+    }
+    Spi::run(&format!(
+        "SELECT set_config('kerai.changeset', {}, {})",
+        sql_text(id.as_deref().unwrap_or_default()),
+        local,
+    ))
+    .unwrap();
+    id.map(|id| pgrx::JsonB(changeset_json(&id)))
+}
+
+/// The changeset reads go through, null when none is in effect.
+#[pg_extern]
+fn current_changeset() -> Option<String> {
+    active()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_node_ops_are_stageable() {
+        assert!(check_stageable("insert_node", None, &json!({"kind": "paragraph"})).is_ok());
+        assert!(check_stageable("update_content", Some("n"), &json!({})).is_ok());
+        assert!(check_stageable("update_content", None, &json!({})).is_err());
+        assert!(check_stageable("transfer_koi", None, &json!({})).is_err());
+        assert!(check_stageable("delete_node", Some("n"), &json!(true)).is_err());
+    }
+
+    #[test]
+    fn stamp_tags_the_payload() {
+        let payload = json!({"new_content": "x", "changeset": "old"});
+        assert_eq!(
+            stamp(&payload, "c1"),
+            json!({"new_content": "x", "changeset": "c1"})
+        );
+        assert_eq!(payload["changeset"], "old");
+    }
+}
@@ -13,8 +13,165 @@ fn resolve_agent(name: &str) -> String {
     .unwrap_or_else(|| error!("Agent not found: {}", name))
 }
 
+/// Deltas folded per statement by `fold_deltas`.
+const FOLD_BATCH: i64 = 10_000;
+
+/// Fold queued perspective deltas into kerai.consensus_state and
+/// kerai.agent_stats, a batch at a time until the queue is empty.
+/// Returns the number of deltas folded.
+///
+/// Counts and sums fold directly; min/max fold for added weights, and are
+/// re-read from kerai.perspectives for keys that lost a weight. Deltas
+/// locked by a concurrent fold are left to it.
+pub(crate) fn fold_deltas() -> i64 {
+    let mut total = 0;
+    loop {
+        let batch = Spi::get_one::<pgrx::JsonB>(&format!(
+            "WITH batch AS (
+                DELETE FROM kerai.perspective_deltas
+                WHERE id IN (
+                    SELECT id FROM kerai.perspective_deltas
+                    ORDER BY id LIMIT {FOLD_BATCH}
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING *
+            ), by_key AS (
+                SELECT node_id, context_id,
+                    sum(agent_diff)::int AS agents,
+                    sum(diff)::int AS n,
+                    sum(diff * weight) AS s,
+                    sum(diff * weight * weight) AS sq,
+                    min(weight) FILTER (WHERE diff > 0) AS lo,
+                    max(weight) FILTER (WHERE diff > 0) AS hi,
+                    bool_or(diff < 0) AS retracted
+                FROM batch GROUP BY node_id, context_id
+            ), folded AS (
+                INSERT INTO kerai.consensus_state AS c
+                    (node_id, context_id, agent_count, perspective_count,
+                     weight_sum, weight_sq_sum, min_weight, max_weight)
+                SELECT node_id, context_id, agents, n, s, sq, lo, hi FROM by_key
+                ON CONFLICT (node_id, context_id)
+                DO UPDATE SET
+                    agent_count = c.agent_count + EXCLUDED.agent_count,
+                    perspective_count = c.perspective_count + EXCLUDED.perspective_count,
+                    weight_sum = c.weight_sum + EXCLUDED.weight_sum,
+                    weight_sq_sum = c.weight_sq_sum + EXCLUDED.weight_sq_sum,
+                    min_weight = LEAST(c.min_weight, EXCLUDED.min_weight),
+                    max_weight = GREATEST(c.max_weight, EXCLUDED.max_weight),
+                    updated_at = clock_timestamp()
+            ), agents AS (
+                INSERT INTO kerai.agent_stats AS a (agent_id, perspective_count, weight_sum)
+                SELECT agent_id, sum(diff)::int, sum(diff * weight)
+                FROM batch GROUP BY agent_id
+                ON CONFLICT (agent_id) DO UPDATE SET
+                    perspective_count = a.perspective_count + EXCLUDED.perspective_count,
+                    weight_sum = a.weight_sum + EXCLUDED.weight_sum,
+                    updated_at = clock_timestamp()
+            )
+            SELECT jsonb_build_object(
+                'folded', (SELECT count(*) FROM batch),
+                'retracted', COALESCE((
+                    SELECT jsonb_agg(jsonb_build_object('node_id', node_id, 'context_id', context_id))
+                    FROM by_key WHERE retracted
+                ), '[]'::jsonb)
+            )",
+        ))
+        .unwrap()
+        .unwrap();
+
+        let folded = batch.0["folded"].as_i64().unwrap_or(0);
+        if folded == 0 {
+            break;
+        }
+        total += folded;
+
+        // The fold above can only widen min/max; re-read them where a
+        // weight was removed, and drop keys no perspective holds any more
+        if batch.0["retracted"]
+            .as_array()
+            .is_some_and(|r| !r.is_empty())
+        {
+            Spi::run(&format!(
+                "WITH keys AS (
+                    SELECT * FROM jsonb_to_recordset('{}'::jsonb) AS k(node_id uuid, context_id uuid)
+                ), removed AS (
+                    DELETE FROM kerai.consensus_state c USING keys k
+                    WHERE c.node_id = k.node_id
+                      AND c.context_id IS NOT DISTINCT FROM k.context_id
+                      AND c.perspective_count = 0
+                )
+                UPDATE kerai.consensus_state c
+                SET min_weight = e.lo, max_weight = e.hi
+                FROM keys k, LATERAL (
+                    SELECT min(p.weight) AS lo, max(p.weight) AS hi
+                    FROM kerai.perspectives p
+                    WHERE p.node_id = k.node_id AND p.context_id IS NOT DISTINCT FROM k.context_id
+                ) e
+                WHERE c.node_id = k.node_id
+                  AND c.context_id IS NOT DISTINCT FROM k.context_id
+                  AND c.perspective_count > 0",
+                sql_escape(&batch.0["retracted"].to_string()),
+            ))
+            .unwrap();
+        }
+    }
+    total
+}
+
+/// Fold pending deltas unless the transaction is read-only, in which case
+/// reads see the state as of the last fold.
+fn fold_if_writable() {
+    let read_only = Spi::get_one::<bool>("SELECT current_setting('transaction_read_only')::bool")
+        .unwrap_or(Some(true))
+        .unwrap_or(true);
+    if !read_only {
+        fold_deltas();
+    }
+}
+
+/// Time up to which the materialized consensus reflects every change:
+/// the oldest still-queued delta, or now when the queue is drained.
+fn as_of_sql() -> &'static str {
+    "COALESCE((SELECT min(queued_at) FROM kerai.perspective_deltas), clock_timestamp())"
+}
+
+/// Fold queued perspective changes into the materialized consensus now,
+/// or rebuild it from kerai.perspectives when `rebuild` is true.
+#[pg_extern]
+fn refresh_consensus(rebuild: default!(bool, false)) -> pgrx::JsonB {
+    if rebuild {
+        for stmt in [
+            "LOCK TABLE kerai.perspectives IN SHARE MODE",
+            "DELETE FROM kerai.perspective_deltas",
+            "DELETE FROM kerai.consensus_state",
+            "DELETE FROM kerai.agent_stats",
+            "INSERT INTO kerai.consensus_state
+                 (node_id, context_id, agent_count, perspective_count,
+                  weight_sum, weight_sq_sum, min_weight, max_weight)
+             SELECT node_id, context_id, count(DISTINCT agent_id), count(*),
+                    sum(weight), sum(weight * weight), min(weight), max(weight)
+             FROM kerai.perspectives GROUP BY node_id, context_id",
+            "INSERT INTO kerai.agent_stats (agent_id, perspective_count, weight_sum)
+             SELECT agent_id, count(*), sum(weight)
+             FROM kerai.perspectives GROUP BY agent_id",
+        ] {
+            Spi::run(stmt).unwrap();
+        }
+        let keys = Spi::get_one::<i64>("SELECT count(*)::bigint FROM kerai.consensus_state")
+            .unwrap()
+            .unwrap_or(0);
+        return pgrx::JsonB(serde_json::json!({"rebuilt": true, "keys": keys}));
+    }
+
+    let folded = fold_deltas();
+    pgrx::JsonB(serde_json::json!({"rebuilt": false, "folded": folded}))
+}
+
 /// Multi-agent consensus on nodes. Returns aggregated weight stats
 /// for nodes rated by multiple agents, optionally filtered.
+///
+/// Reads the materialized kerai.consensus_state; each entry carries
+/// `as_of`, the time up to which it reflects all perspective changes.
 #[pg_extern]
 fn consensus(
     context_id: Option<pgrx::Uuid>,
@@ -24,9 +181,11 @@ fn consensus(
     let min_a = min_agents.unwrap_or(2);
     let min_w = min_weight.unwrap_or(-1.0);
 
+    fold_if_writable();
+
     let mut conditions = vec![
         format!("c.agent_count >= {}", min_a),
-        format!("c.avg_weight >= {}", min_w),
+        format!("c.weight_sum / NULLIF(c.perspective_count, 0) >= {}", min_w),
     ];
 
     if let Some(ctx) = context_id {
@@ -36,22 +195,28 @@ fn consensus(
     let where_clause = conditions.join(" AND ");
 
     let json = Spi::get_one::<pgrx::JsonB>(&format!(
-        "SELECT COALESCE(
+        "WITH fresh AS (SELECT {} AS as_of)
+        SELECT COALESCE(
             jsonb_agg(jsonb_build_object(
                 'node_id', c.node_id,
                 'context_id', c.context_id,
                 'agent_count', c.agent_count,
-                'avg_weight', c.avg_weight,
+                'avg_weight', c.weight_sum / NULLIF(c.perspective_count, 0),
                 'min_weight', c.min_weight,
                 'max_weight', c.max_weight,
-                'stddev_weight', c.stddev_weight,
+                'stddev_weight', CASE WHEN c.perspective_count > 1 THEN sqrt(GREATEST(
+                    (c.weight_sq_sum - c.weight_sum * c.weight_sum / NULLIF(c.perspective_count, 0))
+                        / (c.perspective_count - 1), 0)) END,
                 'node_kind', n.kind,
-                'node_content', n.content
-            ) ORDER BY c.avg_weight DESC),
+                'node_content', n.content,
+                'as_of', f.as_of
+            ) ORDER BY c.weight_sum / NULLIF(c.perspective_count, 0) DESC),
             '[]'::jsonb
-        ) FROM kerai.consensus_perspectives c
+        ) FROM kerai.consensus_state c
         JOIN kerai.nodes n ON n.id = c.node_id
-        WHERE {}",
+        CROSS JOIN fresh f
+        WHERE c.perspective_count > 0 AND {}",
+        as_of_sql(),
         where_clause,
     ))
     .unwrap()
@@ -59,6 +224,39 @@ fn consensus(
     json
 }
 
+/// Perspective totals and per-agent stats from the materialized state,
+/// with `as_of` and the number of changes still queued.
+#[pg_extern]
+fn perspective_stats() -> pgrx::JsonB {
+    fold_if_writable();
+
+    let json = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_build_object(
+            'perspectives', COALESCE((SELECT sum(perspective_count) FROM kerai.agent_stats), 0),
+            'rated_nodes', (SELECT count(DISTINCT node_id) FROM kerai.consensus_state
+                            WHERE perspective_count > 0),
+            'contested_nodes', (SELECT count(*) FROM kerai.consensus_state WHERE agent_count >= 2),
+            'agents', COALESCE((
+                SELECT jsonb_agg(jsonb_build_object(
+                    'agent', a.name,
+                    'perspective_count', s.perspective_count,
+                    'avg_weight', s.weight_sum / NULLIF(s.perspective_count, 0),
+                    'updated_at', s.updated_at
+                ) ORDER BY s.perspective_count DESC, a.name)
+                FROM kerai.agent_stats s
+                JOIN kerai.agents a ON a.id = s.agent_id
+                WHERE s.perspective_count > 0
+            ), '[]'::jsonb),
+            'pending', (SELECT count(*) FROM kerai.perspective_deltas),
+            'as_of', {}
+        )",
+        as_of_sql(),
+    ))
+    .unwrap()
+    .unwrap();
+    json
+}
+
 /// Compare two agents' perspectives. Returns nodes only in agent1,
 /// only in agent2, and disagreements (same node, different weights).
 #[pg_extern]
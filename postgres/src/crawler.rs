@@ -104,7 +104,7 @@ fn extract_references(document_filter: default!(&str, "'__all__'")) -> pgrx::Jso
             continue;
         }
 
-        let ref_id = uuid::Uuid::new_v4().to_string();
+        let ref_id = uuid::Uuid::now_v7().to_string();
 
         let meta = json!({
             "ref_type": ext_ref.ref_type,
@@ -573,28 +573,19 @@ fn insert_citation_edges(ref_id: &str, para_ids: &[String]) -> u64 {
     let mut count = 0u64;
 
     for para_id in para_ids {
-        // Check if edge already exists
-        let exists_sql = format!(
-            "SELECT 1 FROM kerai.edges \
-             WHERE source_id = {} AND target_id = {} AND relation = 'cites'",
+        // The unique edge index, not a prior lookup, decides what is new:
+        // a concurrent crawl may add the same edge in between
+        let inserted = Spi::get_one::<i32>(&format!(
+            "INSERT INTO kerai.edges (id, source_id, target_id, relation, metadata) \
+             VALUES ({}, {}, {}, 'cites', '{{}}'::jsonb) \
+             ON CONFLICT (source_id, target_id, relation) DO NOTHING \
+             RETURNING 1",
+            sql_uuid(&uuid::Uuid::now_v7().to_string()),
             sql_uuid(para_id),
-            sql_uuid(ref_id)
-        );
-
-        let exists = Spi::get_one::<i32>(&exists_sql)
-            .unwrap_or(None)
-            .is_some();
-
-        if !exists {
-            let edge_id = uuid::Uuid::new_v4().to_string();
-            let insert_sql = format!(
-                "INSERT INTO kerai.edges (id, source_id, target_id, relation, metadata) \
-                 VALUES ({}, {}, {}, 'cites', '{{}}'::jsonb)",
-                sql_uuid(&edge_id),
-                sql_uuid(para_id),
-                sql_uuid(ref_id),
-            );
-            Spi::run(&insert_sql).ok();
+            sql_uuid(ref_id),
+        ))
+        .unwrap_or(None);
+        if inserted.is_some() {
             count += 1;
         }
     }
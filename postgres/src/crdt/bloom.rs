@@ -0,0 +1,129 @@
+/// Bloom filter over operation ids (`author:author_seq`), exchanged during
+/// sync so a peer can skip sending ops the other side almost certainly has.
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+/// False-positive rate filters are sized for. A false positive means one op
+/// is not pushed this round; the next sync with a fresh filter catches it.
+pub const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Largest filter built, in bits (1 MiB serialized).
+const MAX_BITS: u64 = 8 * 1024 * 1024;
+
+/// Id of an operation as hashed into a filter.
+pub fn op_id(author: &str, author_seq: i64) -> String {
+    format!("{author}:{author_seq}")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    m: u64,
+    k: u32,
+    count: u64,
+}
+
+impl BloomFilter {
+    /// Empty filter sized for `n` items at false-positive rate `p`.
+    pub fn with_capacity(n: usize, p: f64) -> Self {
+        let n = n.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let m = ((-n * p.ln()) / (ln2 * ln2)).ceil().clamp(64.0, MAX_BITS as f64) as u64;
+        let k = ((m as f64 / n) * ln2).round().clamp(1.0, 16.0) as u32;
+        BloomFilter {
+            bits: vec![0; m.div_ceil(8) as usize],
+            m,
+            k,
+            count: 0,
+        }
+    }
+
+    /// Bit positions for an item, by double hashing two halves of its SHA-256.
+    fn positions(&self, item: &str) -> impl Iterator<Item = u64> {
+        let digest = Sha256::digest(item.as_bytes());
+        let h1 = u64::from_le_bytes(digest[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap()) | 1;
+        let m = self.m;
+        (0..self.k as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % m)
+    }
+
+    pub fn insert(&mut self, item: &str) {
+        let positions: Vec<u64> = self.positions(item).collect();
+        for pos in positions {
+            self.bits[(pos / 8) as usize] |= 1 << (pos % 8);
+        }
+        self.count += 1;
+    }
+
+    /// Whether `item` may be in the set. Never false for an inserted item.
+    pub fn contains(&self, item: &str) -> bool {
+        self.positions(item)
+            .all(|pos| self.bits[(pos / 8) as usize] & (1 << (pos % 8)) != 0)
+    }
+
+    /// Serialized form: `{m, k, count, bits}` with `bits` base64-encoded.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "m": self.m,
+            "k": self.k,
+            "count": self.count,
+            "bits": STANDARD.encode(&self.bits),
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let m = value["m"].as_u64().ok_or("filter is missing m")?;
+        let k = value["k"].as_u64().ok_or("filter is missing k")? as u32;
+        let bits = STANDARD
+            .decode(value["bits"].as_str().ok_or("filter is missing bits")?)
+            .map_err(|e| format!("filter bits are not base64: {e}"))?;
+        if m == 0 || k == 0 || bits.len() as u64 != m.div_ceil(8) {
+            return Err(format!("filter of {} bytes does not hold m = {m} bits", bits.len()));
+        }
+        Ok(BloomFilter {
+            bits,
+            m,
+            k,
+            count: value["count"].as_u64().unwrap_or(0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserted_ids_are_always_found() {
+        let mut filter = BloomFilter::with_capacity(500, FALSE_POSITIVE_RATE);
+        for seq in 1..=500 {
+            filter.insert(&op_id("alice", seq));
+        }
+        assert!((1..=500).all(|seq| filter.contains(&op_id("alice", seq))));
+    }
+
+    #[test]
+    fn false_positives_stay_near_the_target_rate() {
+        let mut filter = BloomFilter::with_capacity(1000, FALSE_POSITIVE_RATE);
+        for seq in 1..=1000 {
+            filter.insert(&op_id("alice", seq));
+        }
+        let hits = (1..=10_000).filter(|seq| filter.contains(&op_id("bob", *seq))).count();
+        assert!(hits < 300, "{hits} false positives in 10000");
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut filter = BloomFilter::with_capacity(10, FALSE_POSITIVE_RATE);
+        filter.insert(&op_id("alice", 3));
+        let back = BloomFilter::from_json(&filter.to_json()).unwrap();
+        assert_eq!(back, filter);
+        assert!(back.contains(&op_id("alice", 3)));
+
+        let mut bad = filter.to_json();
+        bad["m"] = json!(10_000);
+        assert!(BloomFilter::from_json(&bad).is_err());
+    }
+}
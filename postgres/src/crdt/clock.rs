@@ -1,16 +1,27 @@
-/// Lamport clock and version vector management for CRDT operations.
+/// Hybrid logical clock and version vector management for CRDT operations.
 use pgrx::prelude::*;
 
-/// Get the current maximum Lamport timestamp from the operations table.
+use super::hlc;
+use crate::sql::{sql_text, sql_uuid};
+
+/// Get the newest timestamp (hybrid or, before hybrid clocks, Lamport)
+/// from the operations table.
+/// Branch merges stamp versions without an operation, so those count too.
 pub fn current_lamport_ts() -> i64 {
-    Spi::get_one::<i64>("SELECT COALESCE(MAX(lamport_ts), 0)::bigint FROM kerai.operations")
-        .unwrap()
-        .unwrap_or(0)
+    Spi::get_one::<i64>(
+        "SELECT GREATEST(
+            (SELECT COALESCE(MAX(lamport_ts), 0) FROM kerai.operations),
+            (SELECT COALESCE(MAX(timestamp), 0) FROM kerai.versions)
+        )::bigint",
+    )
+    .unwrap()
+    .unwrap_or(0)
 }
 
-/// Get the next Lamport timestamp (current + 1).
+/// Get the next timestamp: this instance's wall clock, or the newest
+/// timestamp plus one when that is ahead of it (see `hlc::next`).
 pub fn next_lamport_ts() -> i64 {
-    current_lamport_ts() + 1
+    hlc::next(current_lamport_ts(), hlc::now_ms())
 }
 
 /// Increment and return the next author sequence number.
@@ -27,14 +38,20 @@ pub fn next_author_seq(author: &str) -> i64 {
     .unwrap()
 }
 
-/// Advance the version_vector entry for a remote author to at least `seq`.
-/// Uses GREATEST semantics — never goes backwards.
-pub fn advance_author_seq(author: &str, seq: i64) {
-    let escaped = author.replace('\'', "''");
+/// Record an operation from `author` (local or remote) in its version_vector
+/// entry: the author's instance, and its sequence number and Lamport
+/// timestamp. Uses GREATEST semantics — never goes backwards.
+pub fn observe(author: &str, instance_id: &str, seq: i64, lamport_ts: i64) {
     Spi::run(&format!(
-        "INSERT INTO kerai.version_vector (author, max_seq) VALUES ('{}', {})
-         ON CONFLICT (author) DO UPDATE SET max_seq = GREATEST(kerai.version_vector.max_seq, {})",
-        escaped, seq, seq
+        "INSERT INTO kerai.version_vector (author, instance_id, max_seq, max_lamport)
+         VALUES ({}, {}, {seq}, {lamport_ts})
+         ON CONFLICT (author) DO UPDATE SET
+             instance_id = EXCLUDED.instance_id,
+             max_seq = GREATEST(kerai.version_vector.max_seq, EXCLUDED.max_seq),
+             max_lamport = GREATEST(kerai.version_vector.max_lamport, EXCLUDED.max_lamport),
+             updated_at = now()",
+        sql_text(author),
+        sql_uuid(instance_id),
     ))
     .unwrap();
 }
@@ -51,3 +68,26 @@ pub fn get_version_vector() -> pgrx::JsonB {
     .unwrap_or_else(|| pgrx::JsonB(serde_json::json!({})));
     json
 }
+
+/// Version vector with each author's instance and newest timestamp:
+/// `{"author": {instance, instance_id, seq, lamport, wall_time,
+/// updated_at}, ...}`, `wall_time` being the timestamp's estimated wall
+/// clock time (null for a Lamport counter).
+pub fn get_version_vector_detail() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(
+            jsonb_object_agg(v.author, jsonb_build_object(
+                'instance', i.name,
+                'instance_id', v.instance_id,
+                'seq', v.max_seq,
+                'lamport', v.max_lamport,
+                'wall_time', kerai.hlc_wall_time(v.max_lamport, i.clock_skew_ms),
+                'updated_at', v.updated_at
+            )),
+            '{}'::jsonb
+        ) FROM kerai.version_vector v
+        LEFT JOIN kerai.instances i ON i.id = v.instance_id",
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(serde_json::json!({})))
+}
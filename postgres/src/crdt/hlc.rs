@@ -0,0 +1,109 @@
+/// Hybrid logical clock timestamps.
+///
+/// An HLC packs wall-clock milliseconds and a logical counter into the
+/// BIGINT columns that used to hold plain Lamport counters
+/// (operations.lamport_ts, versions.timestamp): `physical_ms << 16 |
+/// logical`. A new stamp is the wall clock when it is ahead of every stamp
+/// seen, and the newest stamp plus one otherwise, so stamps still order
+/// causally like Lamport timestamps while staying close to real time.
+///
+/// Counters written before the switch are far below any packed wall
+/// clock, so they still sort first and are told apart by `is_hlc`.
+/// `kerai.hlc_wall_time` (schema.rs) decodes stamps in SQL and must agree
+/// with `LOGICAL_BITS` and `EPOCH_MS` here.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bits of the logical counter below the physical milliseconds.
+pub const LOGICAL_BITS: u32 = 16;
+
+/// Stamps whose physical part is before this (2020-01-01) are Lamport
+/// counters from before hybrid clocks.
+pub const EPOCH_MS: i64 = 1_577_836_800_000;
+
+/// Milliseconds in a day, for partition widths.
+pub const DAY_MS: i64 = 86_400_000;
+
+/// Milliseconds since the Unix epoch.
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+pub fn pack(physical_ms: i64, logical: i64) -> i64 {
+    (physical_ms << LOGICAL_BITS) | (logical & ((1 << LOGICAL_BITS) - 1))
+}
+
+pub fn physical_ms(ts: i64) -> i64 {
+    ts >> LOGICAL_BITS
+}
+
+pub fn logical(ts: i64) -> i64 {
+    ts & ((1 << LOGICAL_BITS) - 1)
+}
+
+/// Whether `ts` is a hybrid clock stamp rather than a Lamport counter.
+pub fn is_hlc(ts: i64) -> bool {
+    physical_ms(ts) >= EPOCH_MS
+}
+
+/// The stamp after `current`, the newest one seen, at wall time `now_ms`.
+/// A logical counter that overflows carries into the physical part, which
+/// is then briefly ahead of the wall clock.
+pub fn next(current: i64, now_ms: i64) -> i64 {
+    pack(now_ms, 0).max(current + 1)
+}
+
+/// Width in stamps of `days` of wall-clock time.
+pub fn span_days(days: i64) -> i64 {
+    pack(days * DAY_MS, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_790_000_000_000;
+
+    #[test]
+    fn packs_and_unpacks() {
+        let ts = pack(NOW, 7);
+        assert_eq!(physical_ms(ts), NOW);
+        assert_eq!(logical(ts), 7);
+        assert!(is_hlc(ts));
+        assert!(!is_hlc(123_456));
+    }
+
+    #[test]
+    fn follows_the_wall_clock_when_it_is_ahead() {
+        assert_eq!(next(0, NOW), pack(NOW, 0));
+        // Legacy Lamport counters are all behind any wall clock
+        assert_eq!(next(5_000_000, NOW), pack(NOW, 0));
+        assert_eq!(next(pack(NOW - 10, 3), NOW), pack(NOW, 0));
+    }
+
+    #[test]
+    fn counts_up_when_the_clock_lags() {
+        // Two stamps in the same millisecond
+        assert_eq!(next(pack(NOW, 0), NOW), pack(NOW, 1));
+        // A peer stamped ahead of our clock: stay after it
+        let ahead = pack(NOW + 5_000, 2);
+        assert_eq!(next(ahead, NOW), pack(NOW + 5_000, 3));
+        assert!(next(ahead, NOW) > ahead);
+    }
+
+    #[test]
+    fn logical_overflow_carries() {
+        let full = pack(NOW, (1 << LOGICAL_BITS) - 1);
+        let after = next(full, NOW);
+        assert_eq!(physical_ms(after), NOW + 1);
+        assert_eq!(logical(after), 0);
+    }
+
+    #[test]
+    fn day_spans() {
+        assert_eq!(physical_ms(span_days(1)), DAY_MS);
+        assert_eq!(physical_ms(pack(NOW, 0) + span_days(2)), NOW + 2 * DAY_MS);
+    }
+}
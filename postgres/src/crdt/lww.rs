@@ -0,0 +1,111 @@
+/// Last-writer-wins rules for applying remote operations out of order.
+///
+/// Every write carries a stamp, its Lamport timestamp and author
+/// fingerprint. For each node field the write with the greatest stamp wins,
+/// so replicas that see the same ops in different orders end up with the
+/// same values. Equal timestamps are broken by fingerprint, which also
+/// decides sibling order when two nodes claim the same position.
+use serde_json::Value;
+
+/// When a write happened: Lamport timestamp, then author fingerprint as a
+/// tie-breaker (compared bytewise, as with `COLLATE "C"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Stamp<'a> {
+    pub lamport_ts: i64,
+    pub author: &'a str,
+}
+
+/// Whether an incoming write replaces the field's current one.
+pub fn wins(incoming: Stamp, current: Option<Stamp>) -> bool {
+    current.is_none_or(|c| incoming > c)
+}
+
+/// Op types whose writes count for a node field: `content`, `metadata`
+/// (tracked per key) or `position` (parent and position together).
+pub fn writers(field: &str) -> &'static [&'static str] {
+    match field {
+        "content" => &["insert_node", "update_content"],
+        "metadata" => &["insert_node", "update_metadata"],
+        "position" => &["insert_node", "move_node"],
+        _ => &[],
+    }
+}
+
+/// The node field an op writes, if it is a field-level node write.
+pub fn field_of(op_type: &str) -> Option<&'static str> {
+    match op_type {
+        "update_content" => Some("content"),
+        "update_metadata" => Some("metadata"),
+        "move_node" => Some("position"),
+        _ => None,
+    }
+}
+
+/// Where a node placed at `position` goes relative to a sibling already
+/// there: the lower fingerprint keeps the position and the other follows.
+pub fn goes_first(author: &str, sibling_author: &str) -> bool {
+    author.as_bytes() < sibling_author.as_bytes()
+}
+
+/// Sort a batch into causal order: Lamport timestamp, then author and
+/// author sequence, so inserts come before the ops that touch their nodes.
+pub fn sort_batch(ops: &mut [Value]) {
+    let key = |op: &Value| {
+        (
+            op["lamport_ts"].as_i64().unwrap_or(0),
+            op["author"].as_str().unwrap_or("").to_string(),
+            op["author_seq"].as_i64().unwrap_or(0),
+        )
+    };
+    ops.sort_by_key(key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn stamp(lamport_ts: i64, author: &str) -> Stamp<'_> {
+        Stamp { lamport_ts, author }
+    }
+
+    #[test]
+    fn later_timestamps_win() {
+        assert!(wins(stamp(5, "a"), None));
+        assert!(wins(stamp(5, "a"), Some(stamp(4, "z"))));
+        assert!(!wins(stamp(4, "z"), Some(stamp(5, "a"))));
+    }
+
+    #[test]
+    fn ties_go_to_the_greater_fingerprint() {
+        assert!(wins(stamp(5, "b"), Some(stamp(5, "a"))));
+        assert!(!wins(stamp(5, "a"), Some(stamp(5, "b"))));
+        // The same write never beats itself, so re-applying is a no-op
+        assert!(!wins(stamp(5, "a"), Some(stamp(5, "a"))));
+    }
+
+    #[test]
+    fn the_same_pair_agrees_from_either_side() {
+        assert!(goes_first("Abc", "abc"));
+        assert!(!goes_first("abc", "Abc"));
+    }
+
+    #[test]
+    fn batches_sort_causally() {
+        let mut ops = vec![
+            json!({"lamport_ts": 7, "author": "b", "author_seq": 2}),
+            json!({"lamport_ts": 3, "author": "b", "author_seq": 1}),
+            json!({"lamport_ts": 7, "author": "a", "author_seq": 9}),
+        ];
+        sort_batch(&mut ops);
+        let order: Vec<i64> = ops.iter().map(|o| o["author_seq"].as_i64().unwrap()).collect();
+        assert_eq!(order, vec![1, 9, 2]);
+    }
+
+    #[test]
+    fn only_field_writes_have_a_field() {
+        assert_eq!(field_of("move_node"), Some("position"));
+        assert_eq!(field_of("delete_node"), None);
+        assert!(writers("content").contains(&"update_content"));
+    }
+}
@@ -1,12 +1,20 @@
-/// CRDT operation layer — signed operation log with Lamport clock and version vector.
-mod clock;
+/// CRDT operation layer — signed operation log with hybrid logical clock and version vector.
+mod bloom;
+pub(crate) mod clock;
+pub(crate) mod hlc;
+mod lww;
 mod operations;
 mod signer;
+pub(crate) mod sync;
+pub(crate) mod throttle;
+
+pub(crate) use operations::VALID_OP_TYPES;
 
 use pgrx::prelude::*;
 use serde_json::Value;
 
 use crate::identity;
+use crate::merkle;
 use crate::sql::sql_escape;
 
 /// Format bytes as PostgreSQL hex bytea literal: \xABCD...
@@ -90,21 +98,210 @@ fn insert_operation(
     let payload_str = sql_escape(&payload.to_string());
     let sig_hex = bytes_to_pg_hex(signature);
 
+    // The node's path, for region polling; a deleted node keeps the path
+    // its earlier ops were logged under
     Spi::run(&format!(
-        "INSERT INTO kerai.operations (instance_id, op_type, node_id, author, lamport_ts, author_seq, payload, signature)
-         VALUES ('{}'::uuid, '{}', {}, '{}', {}, {}, '{}'::jsonb, '{}'::bytea)",
+        "INSERT INTO kerai.operations (instance_id, op_type, node_id, author, lamport_ts, author_seq, payload, signature, path)
+         VALUES ('{}'::uuid, '{}', {node}, '{}', {}, {}, '{}'::jsonb, '{}'::bytea,
+             COALESCE(
+                 (SELECT path FROM kerai.nodes WHERE id = {node}),
+                 (SELECT path FROM kerai.operations WHERE node_id = {node} AND path IS NOT NULL
+                  ORDER BY seq DESC LIMIT 1)))",
         sql_escape(instance_id),
         sql_escape(op_type),
-        node_sql,
         sql_escape(author),
         lamport_ts,
         author_seq,
         payload_str,
         sig_hex,
+        node = node_sql,
+    ))
+    .unwrap();
+}
+
+/// Node ops that leave a row in kerai.versions (what `kerai.blame` walks).
+/// A delete drops the node's earlier history and leaves a tombstone for
+/// each node it removes (its last row as old snapshot, no new one), plus a
+/// move for each child it hands to the node's parent, so `revert_version`
+/// can bring them back. Deletes apply to every branch, so these versions
+/// belong to none.
+const VERSIONED_OPS: &[&str] = &[
+    "insert_node",
+    "update_content",
+    "update_metadata",
+    "move_node",
+    "delete_node",
+];
+
+/// The node row before an op touches it (`null` for inserts and
+/// unversioned ops), kept for the `old_*` columns of its version; for a
+/// delete, the rows it will change (see [`deletion_state`]).
+fn node_state(op_type: &str, node_id: Option<&str>, payload: &Value) -> Value {
+    let Some(nid) = node_id.filter(|_| VERSIONED_OPS.contains(&op_type)) else {
+        return Value::Null;
+    };
+    if op_type == "delete_node" {
+        return deletion_state(nid, payload);
+    }
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT to_jsonb(n) - 'tsv' FROM kerai.nodes n WHERE id = '{}'::uuid",
+        sql_escape(nid),
+    ))
+    .unwrap()
+    .map_or(Value::Null, |j| j.0)
+}
+
+/// Rows a delete will change, as `{removed, children}`: the node and, with
+/// `cascade`, its descendants, parents first; without it, the children it
+/// hands to the node's parent.
+fn deletion_state(node_id: &str, payload: &Value) -> Value {
+    let cascade = payload["cascade"].as_bool().unwrap_or(false);
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "WITH RECURSIVE doomed AS (
+            SELECT id, 0 AS depth FROM kerai.nodes WHERE id = '{id}'::uuid AND deleted_at IS NULL
+            UNION ALL
+            SELECT n.id, d.depth + 1 FROM kerai.nodes n JOIN doomed d ON n.parent_id = d.id
+            WHERE {cascade} AND n.deleted_at IS NULL
+        )
+        SELECT jsonb_build_object(
+            'removed', COALESCE((
+                SELECT jsonb_agg(to_jsonb(n) - 'tsv' ORDER BY d.depth, n.id)
+                FROM doomed d JOIN kerai.nodes n ON n.id = d.id
+            ), '[]'::jsonb),
+            'children', COALESCE((
+                SELECT jsonb_agg(to_jsonb(n) - 'tsv' ORDER BY n.position, n.id)
+                FROM kerai.nodes n
+                WHERE n.parent_id = '{id}'::uuid AND n.deleted_at IS NULL AND NOT {cascade}
+            ), '[]'::jsonb))",
+        id = sql_escape(node_id),
+    ))
+    .unwrap()
+    .map_or(Value::Null, |j| j.0)
+}
+
+/// Columns of a kerai.versions row (alias `v`) covered by its signature,
+/// as a jsonb object.
+pub(crate) const VERSION_SIGNED_ROW: &str = "jsonb_build_object(
+    'id', v.id, 'node_id', v.node_id, 'instance_id', v.instance_id,
+    'operation', v.operation, 'old_parent', v.old_parent, 'new_parent', v.new_parent,
+    'old_position', v.old_position, 'new_position', v.new_position,
+    'old_content', v.old_content, 'new_content', v.new_content,
+    'old_snapshot', v.old_snapshot, 'new_snapshot', v.new_snapshot,
+    'branch_id', v.branch_id, 'merged_from', v.merged_from,
+    'author', v.author, 'timestamp', v.timestamp, 'signed_by', v.signed_by)";
+
+/// Sign a just-recorded kerai.versions row with this instance's key. The
+/// row must already carry `signed_by` = this instance.
+pub(crate) fn sign_version(version_id: &str) {
+    let signing_key = identity::load_signing_key()
+        .unwrap_or_else(|| error!("No signing key found — identity not initialized"));
+    let row = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT {} FROM kerai.versions v WHERE v.id = '{}'::uuid",
+        VERSION_SIGNED_ROW,
+        sql_escape(version_id),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("Version {} not found", version_id));
+    let signature = identity::sign_data(&signing_key, &identity::version_signable(&row.0));
+    Spi::run(&format!(
+        "UPDATE kerai.versions SET signature = '{}'::bytea WHERE id = '{}'::uuid",
+        bytes_to_pg_hex(&signature),
+        sql_escape(version_id),
     ))
     .unwrap();
 }
 
+/// Record a node op in kerai.versions on the current branch: old values
+/// from the row taken before applying, new values read back from the node.
+/// Both full rows are kept as snapshots for switching branches. The row is
+/// signed by this instance, whoever authored the op.
+fn record_version(
+    instance_id: &str,
+    op_type: &str,
+    node_id: &str,
+    author: &str,
+    lamport_ts: i64,
+    before: Value,
+) {
+    if !VERSIONED_OPS.contains(&op_type) {
+        return;
+    }
+    if op_type == "delete_node" {
+        record_deletion(instance_id, author, lamport_ts, &before);
+        return;
+    }
+    let version_id = Spi::get_one_with_args::<String>(
+        "INSERT INTO kerai.versions (node_id, instance_id, operation, old_parent, new_parent,
+             old_position, new_position, old_content, new_content, old_snapshot, new_snapshot,
+             branch_id, author, timestamp, signed_by)
+         SELECT n.id, $1::uuid, $2, (b->>'parent_id')::uuid, n.parent_id,
+             (b->>'position')::integer, n.position, b->>'content', n.content,
+             NULLIF(b, 'null'::jsonb), to_jsonb(n) - 'tsv',
+             (SELECT id FROM kerai.branches WHERE is_current), $3, $4,
+             (SELECT id FROM kerai.instances WHERE is_self)
+         FROM kerai.nodes n, (SELECT $5::jsonb AS b) before
+         WHERE n.id = $6::uuid
+         RETURNING id::text",
+        &[
+            instance_id.into(),
+            op_type.into(),
+            author.into(),
+            lamport_ts.into(),
+            pgrx::JsonB(before).into(),
+            node_id.into(),
+        ],
+    )
+    .unwrap_or(None);
+    if let Some(version_id) = version_id {
+        sign_version(&version_id);
+    }
+}
+
+/// Record a delete from the rows [`deletion_state`] took before it: a
+/// tombstone per removed node, and a move per child handed up, its new
+/// parent and position read back. None of them is on a branch.
+fn record_deletion(instance_id: &str, author: &str, lamport_ts: i64, before: &Value) {
+    if !before["removed"].is_array() {
+        return;
+    }
+    let ids = Spi::get_one_with_args::<pgrx::JsonB>(
+        "WITH removed AS (
+            INSERT INTO kerai.versions (node_id, instance_id, operation, old_parent,
+                 old_position, old_content, old_snapshot, author, timestamp, signed_by)
+            SELECT (r->>'id')::uuid, $1::uuid, 'delete_node', (r->>'parent_id')::uuid,
+                 (r->>'position')::integer, r->>'content', r, $2, $3,
+                 (SELECT id FROM kerai.instances WHERE is_self)
+            FROM jsonb_array_elements($4::jsonb) r
+            RETURNING id
+        ), moved AS (
+            INSERT INTO kerai.versions (node_id, instance_id, operation, old_parent, new_parent,
+                 old_position, new_position, old_content, new_content, old_snapshot, new_snapshot,
+                 author, timestamp, signed_by)
+            SELECT n.id, $1::uuid, 'move_node', (c->>'parent_id')::uuid, n.parent_id,
+                 (c->>'position')::integer, n.position, c->>'content', n.content,
+                 c, to_jsonb(n) - 'tsv', $2, $3,
+                 (SELECT id FROM kerai.instances WHERE is_self)
+            FROM jsonb_array_elements($5::jsonb) c
+            JOIN kerai.nodes n ON n.id = (c->>'id')::uuid
+            RETURNING id
+        )
+        SELECT jsonb_agg(id::text) FROM (SELECT id FROM removed UNION ALL SELECT id FROM moved) v",
+        &[
+            instance_id.into(),
+            author.into(),
+            lamport_ts.into(),
+            pgrx::JsonB(before["removed"].clone()).into(),
+            pgrx::JsonB(before["children"].clone()).into(),
+        ],
+    )
+    .unwrap()
+    .and_then(|j| j.0.as_array().cloned())
+    .unwrap_or_default();
+    for id in ids.iter().filter_map(Value::as_str) {
+        sign_version(id);
+    }
+}
+
 /// Apply a local CRDT operation. Validates, applies to materialized state,
 /// signs with the local Ed25519 key, and records in the operation log.
 ///
@@ -119,7 +316,12 @@ fn apply_op(op_type: &str, node_id: Option<pgrx::Uuid>, payload: pgrx::JsonB) ->
     operations::validate_op(op_type, nid_ref, &payload.0);
 
     // Apply to materialized state
+    let before = node_state(op_type, nid_ref, &payload.0);
+    let old_parent = nid_ref
+        .filter(|_| matches!(op_type, "move_node" | "delete_node"))
+        .and_then(merkle::parent_of);
     let affected_id = operations::apply(op_type, nid_ref, &payload.0, &instance_id);
+    merkle::refresh_after_op(op_type, &affected_id, old_parent.as_deref());
 
     // Clock
     let lamport_ts = clock::next_lamport_ts();
@@ -142,6 +344,8 @@ fn apply_op(op_type: &str, node_id: Option<pgrx::Uuid>, payload: pgrx::JsonB) ->
         &payload.0,
         &signature,
     );
+    clock::observe(&fingerprint, &instance_id, author_seq, lamport_ts);
+    record_version(&instance_id, op_type, &affected_id, &fingerprint, lamport_ts, before);
 
     // Notify connected listeners
     let notify_payload = serde_json::json!({
@@ -165,14 +369,108 @@ fn apply_op(op_type: &str, node_id: Option<pgrx::Uuid>, payload: pgrx::JsonB) ->
     }))
 }
 
-/// Apply a remote CRDT operation received from a peer.
-/// Verifies the signature, checks causality, applies to materialized state.
+/// Whether a live (not tombstoned) node row exists.
+fn node_exists(node_id: &str) -> bool {
+    Spi::get_one::<bool>(&format!(
+        "SELECT EXISTS(SELECT 1 FROM kerai.nodes WHERE id = '{}'::uuid AND deleted_at IS NULL)",
+        sql_escape(node_id),
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Whether a delete of the node has been recorded, or it is a tombstone.
+/// Deletes win over concurrent writes: ops arriving for a deleted node are
+/// superseded.
+fn node_deleted(node_id: &str) -> bool {
+    Spi::get_one::<bool>(&format!(
+        "SELECT EXISTS(SELECT 1 FROM kerai.operations
+                       WHERE node_id = '{0}'::uuid AND op_type = 'delete_node')
+             OR EXISTS(SELECT 1 FROM kerai.nodes
+                       WHERE id = '{0}'::uuid AND deleted_at IS NOT NULL)",
+        sql_escape(node_id),
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Stamp of the latest recorded write to a node field, from the operation
+/// log. `key` narrows `metadata` to writes of one key.
+fn field_stamp(node_id: &str, field: &str, key: Option<&str>) -> Option<(i64, String)> {
+    let writers: Vec<String> = lww::writers(field).iter().map(|w| format!("'{}'", w)).collect();
+    let key_clause = key
+        .map(|k| {
+            let k = sql_escape(k);
+            format!(
+                "AND (payload->'merge' ? '{k}' OR payload->'metadata' ? '{k}')"
+            )
+        })
+        .unwrap_or_default();
+    let (ts, author) = Spi::get_two::<i64, String>(&format!(
+        "SELECT lamport_ts, author FROM kerai.operations
+         WHERE node_id = '{}'::uuid AND op_type IN ({}) {}
+         ORDER BY lamport_ts DESC, author COLLATE \"C\" DESC
+         LIMIT 1",
+        sql_escape(node_id),
+        writers.join(", "),
+        key_clause,
+    ))
+    .unwrap_or((None, None));
+    Some((ts?, author?))
+}
+
+/// Resolve a position collision after `node_id` was placed: if a sibling
+/// already holds its position, the lower position-writer fingerprint keeps
+/// it and the other node moves one later, with the siblings after it.
+fn break_position_tie(node_id: &str, author: &str) {
+    let nid = sql_escape(node_id);
+    let sibling = Spi::get_two::<String, i32>(&format!(
+        "SELECT s.id::text, s.position FROM kerai.nodes n
+         JOIN kerai.nodes s ON s.parent_id IS NOT DISTINCT FROM n.parent_id
+             AND s.position = n.position AND s.id <> n.id
+         WHERE n.id = '{nid}'::uuid
+         LIMIT 1"
+    ))
+    .unwrap_or((None, None));
+    let (Some(sibling_id), Some(position)) = sibling else {
+        return;
+    };
+    let sibling_author = field_stamp(&sibling_id, "position", None)
+        .map(|(_, a)| a)
+        .or_else(|| {
+            Spi::get_one::<String>(&format!(
+                "SELECT i.key_fingerprint FROM kerai.nodes n
+                 JOIN kerai.instances i ON i.id = n.instance_id
+                 WHERE n.id = '{}'::uuid",
+                sql_escape(&sibling_id),
+            ))
+            .unwrap_or(None)
+        })
+        .unwrap_or_default();
+
+    let target = if lww::goes_first(author, &sibling_author) { position } else { position + 1 };
+    for stmt in [
+        format!(
+            "UPDATE kerai.nodes s SET position = s.position + 1
+             FROM kerai.nodes n
+             WHERE n.id = '{nid}'::uuid AND s.parent_id IS NOT DISTINCT FROM n.parent_id
+               AND s.id <> n.id AND s.position >= {target}"
+        ),
+        format!("UPDATE kerai.nodes SET position = {target} WHERE id = '{nid}'::uuid"),
+    ] {
+        Spi::run(&stmt).unwrap();
+    }
+}
+
+/// Apply one signed remote op with last-writer-wins per node field.
 ///
-/// Input JSON: {op_type, node_id?, author, author_seq, lamport_ts, payload, signature (hex), public_key (hex)}
-/// Returns JSON: {status: "applied"|"duplicate", ...}
-#[pg_extern]
-fn apply_remote_op(op_json: pgrx::JsonB) -> pgrx::JsonB {
-    let obj = op_json.0.as_object()
+/// Returns `{status, op_type, node_id, author, author_seq, lamport_ts}`
+/// where `status` is `applied`, `duplicate` (already in the log),
+/// `superseded` (logged, but a later write or a delete already decided
+/// the field) or `missing` (its node is not here yet; not logged, so a
+/// later sync delivers it again).
+fn apply_remote(op: &Value) -> Value {
+    let obj = op.as_object()
         .unwrap_or_else(|| error!("apply_remote_op expects a JSON object"));
 
     let op_type = obj["op_type"].as_str()
@@ -185,19 +483,37 @@ fn apply_remote_op(op_json: pgrx::JsonB) -> pgrx::JsonB {
         .unwrap_or_else(|| error!("Missing 'lamport_ts'"));
     let payload = obj.get("payload")
         .unwrap_or_else(|| error!("Missing 'payload'"));
-    let sig_hex = obj["signature"].as_str()
-        .unwrap_or_else(|| error!("Missing 'signature'"));
+    let sig_hex = obj.get("signature").and_then(|v| v.as_str()).unwrap_or("");
     let pk_hex = obj["public_key"].as_str()
         .unwrap_or_else(|| error!("Missing 'public_key'"));
 
     let node_id = obj.get("node_id").and_then(|v| v.as_str());
 
     // Decode hex signature and public key
+    if sig_hex.is_empty() {
+        error!("Rejected unsigned op from '{}' (seq {})", author, author_seq);
+    }
     let signature = hex::decode(sig_hex)
         .unwrap_or_else(|_| error!("Invalid hex signature"));
     let public_key = hex::decode(pk_hex)
         .unwrap_or_else(|_| error!("Invalid hex public_key"));
 
+    // The key must be the author's: its fingerprint is the author id, and a
+    // registered author keeps the key it was registered with
+    let verifying_key = identity::verifying_key_from_bytes(&public_key)
+        .unwrap_or_else(|| error!("Invalid Ed25519 public_key"));
+    if identity::fingerprint(&verifying_key) != author {
+        error!("Rejected op from '{}': public key does not match the author", author);
+    }
+    let registered_key = Spi::get_one::<String>(&format!(
+        "SELECT encode(public_key, 'hex') FROM kerai.instances WHERE key_fingerprint = '{}'",
+        sql_escape(author),
+    ))
+    .unwrap_or(None);
+    if registered_key.is_some_and(|k| k != hex::encode(&public_key)) {
+        error!("Rejected op from '{}': public key differs from the registered one", author);
+    }
+
     // Verify signature
     if !signer::verify_op_signature(
         &public_key,
@@ -207,9 +523,20 @@ fn apply_remote_op(op_json: pgrx::JsonB) -> pgrx::JsonB {
         &payload.to_string(),
         &signature,
     ) {
-        error!("Signature verification failed for remote op");
+        error!("Rejected op from '{}' (seq {}): invalid signature", author, author_seq);
     }
 
+    let outcome = |status: &str| {
+        serde_json::json!({
+            "status": status,
+            "op_type": op_type,
+            "node_id": node_id,
+            "author": author,
+            "author_seq": author_seq,
+            "lamport_ts": lamport_ts,
+        })
+    };
+
     // Check for duplicate (idempotency)
     let exists = Spi::get_one::<bool>(&format!(
         "SELECT EXISTS(SELECT 1 FROM kerai.operations WHERE author = '{}' AND author_seq = {})",
@@ -218,30 +545,80 @@ fn apply_remote_op(op_json: pgrx::JsonB) -> pgrx::JsonB {
     ))
     .unwrap()
     .unwrap_or(false);
-
     if exists {
-        return pgrx::JsonB(serde_json::json!({
-            "status": "duplicate",
-            "author": author,
-            "author_seq": author_seq,
-        }));
+        return outcome("duplicate");
     }
 
+    operations::validate_op(op_type, node_id, payload);
+
+    // Decide whether the op still changes anything here
+    let stamp = lww::Stamp { lamport_ts, author };
+    let mut effective = payload.clone();
+    let status = match (op_type, node_id) {
+        ("insert_node", Some(nid)) if node_exists(nid) || node_deleted(nid) => "superseded",
+        ("delete_node", Some(nid)) if !node_exists(nid) => "superseded",
+        (_, Some(nid)) if lww::field_of(op_type).is_some() && !node_exists(nid) => {
+            if node_deleted(nid) {
+                "superseded"
+            } else {
+                return outcome("missing");
+            }
+        }
+        ("update_metadata", Some(nid)) => {
+            // Per key: drop keys a later write already set
+            let merge: serde_json::Map<String, Value> = payload["merge"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter(|(key, _)| {
+                    let current = field_stamp(nid, "metadata", Some(key));
+                    lww::wins(stamp, current.as_ref().map(|(ts, a)| lww::Stamp { lamport_ts: *ts, author: a }))
+                })
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            if merge.is_empty() {
+                "superseded"
+            } else {
+                effective["merge"] = Value::Object(merge);
+                "applied"
+            }
+        }
+        (_, Some(nid)) if lww::field_of(op_type).is_some() => {
+            let field = lww::field_of(op_type).unwrap();
+            let current = field_stamp(nid, field, None);
+            if lww::wins(stamp, current.as_ref().map(|(ts, a)| lww::Stamp { lamport_ts: *ts, author: a })) {
+                "applied"
+            } else {
+                "superseded"
+            }
+        }
+        _ => "applied",
+    };
+
     // Resolve instance_id for the remote author (auto-registers unknown peers)
     let instance_id = resolve_author_instance(author, pk_hex);
 
-    // Validate and apply
-    operations::validate_op(op_type, node_id, payload);
-    let affected_id = operations::apply(op_type, node_id, payload, &instance_id);
-
-    // Advance clocks
-    clock::advance_author_seq(author, author_seq);
+    let mut affected_id = node_id.map(str::to_string);
+    let before = node_state(op_type, node_id, payload);
+    if status == "applied" {
+        let old_parent = node_id
+            .filter(|_| matches!(op_type, "move_node" | "delete_node"))
+            .and_then(merkle::parent_of);
+        let id = operations::apply(op_type, node_id, &effective, &instance_id);
+        if matches!(op_type, "insert_node" | "move_node") {
+            break_position_tie(&id, author);
+        }
+        merkle::refresh_after_op(op_type, &id, old_parent.as_deref());
+        affected_id = Some(id);
+    }
+    let affected_id = affected_id.unwrap_or_default();
 
-    // Record operation
+    // Advance clocks and log the op, applied or not, so it is not re-sent
+    clock::observe(author, &instance_id, author_seq, lamport_ts);
     insert_operation(
         &instance_id,
         op_type,
-        Some(&affected_id),
+        node_id.or(Some(affected_id.as_str())).filter(|id| !id.is_empty()),
         author,
         lamport_ts,
         author_seq,
@@ -249,41 +626,474 @@ fn apply_remote_op(op_json: pgrx::JsonB) -> pgrx::JsonB {
         &signature,
     );
 
-    // Notify connected listeners
-    let notify_payload = serde_json::json!({
-        "op_type": op_type,
-        "node_id": affected_id,
-        "lamport_ts": lamport_ts,
-        "author": author,
+    if status == "applied" {
+        record_version(&instance_id, op_type, &affected_id, author, lamport_ts, before);
+
+        // Notify connected listeners
+        let notify_payload = serde_json::json!({
+            "op_type": op_type,
+            "node_id": affected_id,
+            "lamport_ts": lamport_ts,
+            "author": author,
+        });
+        Spi::run(&format!(
+            "NOTIFY kerai_ops, '{}'",
+            sql_escape(&notify_payload.to_string()),
+        ))
+        .ok();
+    }
+
+    let mut result = outcome(status);
+    result["node_id"] = Value::from(affected_id);
+    result
+}
+
+/// Apply a remote CRDT operation received from a peer.
+/// Verifies the signature and the author's key, then applies it with
+/// last-writer-wins per node field (see `apply_operations`).
+///
+/// Input JSON: {op_type, node_id?, author, author_seq, lamport_ts, payload, signature (hex), public_key (hex)}
+/// Returns JSON: {status: "applied"|"duplicate"|"superseded"|"missing", ...}
+#[pg_extern]
+fn apply_remote_op(op_json: pgrx::JsonB) -> pgrx::JsonB {
+    pgrx::JsonB(apply_remote(&op_json.0))
+}
+
+/// Apply a batch of remote operations, as returned by a peer's
+/// `version_delta`, in causal (Lamport) order.
+///
+/// Each op is verified and applied idempotently: ops already in the log are
+/// skipped, and for each node field (content, each metadata key, parent and
+/// position) only a write with a later Lamport timestamp than the one that
+/// set it takes effect, ties going to the greater author fingerprint. A
+/// delete wins over concurrent writes to its node. Two nodes placed at the
+/// same sibling position are ordered by fingerprint. Applied node ops are
+/// recorded in kerai.versions.
+///
+/// Returns `{applied, superseded, duplicates, missing, results: [...]}`,
+/// one `apply_remote_op` result per op.
+#[pg_extern]
+pub(crate) fn apply_operations(ops: pgrx::JsonB) -> pgrx::JsonB {
+    let mut ops = match ops.0 {
+        Value::Array(ops) => ops,
+        _ => error!("apply_operations expects a JSON array of operations"),
+    };
+    lww::sort_batch(&mut ops);
+
+    let results: Vec<Value> = ops
+        .iter()
+        .enumerate()
+        .map(|(i, op)| {
+            let result = apply_remote(op);
+            crate::jobs::report(i + 1, ops.len(), op["op_type"].as_str().unwrap_or(""));
+            result
+        })
+        .collect();
+    let count = |status: &str| results.iter().filter(|r| r["status"] == status).count();
+
+    pgrx::JsonB(serde_json::json!({
+        "applied": count("applied"),
+        "superseded": count("superseded"),
+        "duplicates": count("duplicate"),
+        "missing": count("missing"),
+        "results": results,
+    }))
+}
+
+/// Get the current version vector as JSON: {"author_fingerprint": max_seq, ...}
+///
+/// With `detailed`, each entry is `{instance, instance_id, seq, lamport,
+/// wall_time, updated_at}` instead, `lamport` being the newest timestamp
+/// seen from that author and `wall_time` its estimated wall clock time.
+#[pg_extern]
+pub(crate) fn version_vector(detailed: default!(bool, false)) -> pgrx::JsonB {
+    if detailed {
+        clock::get_version_vector_detail()
+    } else {
+        clock::get_version_vector()
+    }
+}
+
+/// Per-author sequence numbers from a version vector or frontier whose
+/// entries are plain sequence numbers or detailed `{seq, ...}` objects.
+fn frontier_seqs(frontier: &Value, func: &str) -> Value {
+    let entries = frontier
+        .as_object()
+        .unwrap_or_else(|| error!("{} expects a JSON object of author -> seq", func));
+    let mut seqs = serde_json::Map::new();
+    for (author, entry) in entries {
+        let seq = entry
+            .as_i64()
+            .or_else(|| entry.get("seq").and_then(|s| s.as_i64()))
+            .unwrap_or_else(|| error!("Invalid version vector entry for author '{}'", author));
+        seqs.insert(author.clone(), seq.into());
+    }
+    Value::Object(seqs)
+}
+
+/// Operations a peer with version vector `peer_vector` is missing: every
+/// operation whose `author_seq` is past the peer's entry for its author
+/// (authors the peer has never seen count from 0).
+///
+/// Entries may be plain sequence numbers or detailed `{seq, ...}` objects.
+/// With `filter` (a peer's `version_filter` output), ops the filter says
+/// the peer already has are left out. Returns the same op objects as
+/// `ops_since`, in Lamport order so that applying them in turn respects
+/// causality.
+#[pg_extern]
+pub(crate) fn version_delta(
+    peer_vector: pgrx::JsonB,
+    filter: default!(Option<pgrx::JsonB>, "NULL"),
+) -> pgrx::JsonB {
+    let seqs = frontier_seqs(&peer_vector.0, "version_delta");
+    let filter = filter.map(|f| {
+        bloom::BloomFilter::from_json(&f.0)
+            .unwrap_or_else(|e| error!("Invalid version filter: {}", e))
+    });
+
+    let ops = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(
+            jsonb_agg(jsonb_build_object(
+                'op_type', o.op_type,
+                'node_id', o.node_id,
+                'author', o.author,
+                'author_seq', o.author_seq,
+                'lamport_ts', o.lamport_ts,
+                'payload', o.payload,
+                'signature', encode(o.signature, 'hex'),
+                'public_key', encode(i.public_key, 'hex')
+            ) ORDER BY o.lamport_ts, o.author, o.author_seq),
+            '[]'::jsonb
+        ) FROM kerai.operations o
+        JOIN kerai.instances i ON i.key_fingerprint = o.author
+        WHERE o.author_seq > COALESCE(('{}'::jsonb ->> o.author)::bigint, 0)",
+        sql_escape(&seqs.to_string()),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(serde_json::json!([])));
+
+    match (filter, ops.0) {
+        (Some(filter), Value::Array(ops)) => pgrx::JsonB(Value::Array(
+            ops.into_iter()
+                .filter(|op| {
+                    let author = op["author"].as_str().unwrap_or_default();
+                    let seq = op["author_seq"].as_i64().unwrap_or_default();
+                    !filter.contains(&bloom::op_id(author, seq))
+                })
+                .collect(),
+        )),
+        (_, ops) => pgrx::JsonB(ops),
+    }
+}
+
+/// Compact filter of the operations held here past `since_frontier`, for a
+/// peer to test its outgoing ops against before sending them.
+///
+/// `since_frontier` is a version vector (plain or detailed); ops with
+/// `author_seq` past its entry for their author are added as
+/// `author:author_seq` ids. Returns `{m, k, count, bits}`, a bloom filter
+/// with base64 `bits` sized for a 1% false-positive rate, which
+/// `version_delta` accepts as its `filter`.
+#[pg_extern]
+fn version_filter(since_frontier: pgrx::JsonB) -> pgrx::JsonB {
+    let seqs = frontier_seqs(&since_frontier.0, "version_filter");
+    let mut ids = Vec::new();
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                &format!(
+                    "SELECT o.author, o.author_seq FROM kerai.operations o
+                     WHERE o.author_seq > COALESCE(('{}'::jsonb ->> o.author)::bigint, 0)",
+                    sql_escape(&seqs.to_string()),
+                ),
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            let author = row.get_by_name::<String, _>("author").unwrap().unwrap_or_default();
+            let seq = row.get_by_name::<i64, _>("author_seq").unwrap().unwrap_or_default();
+            ids.push(bloom::op_id(&author, seq));
+        }
     });
+
+    let mut filter = bloom::BloomFilter::with_capacity(ids.len(), bloom::FALSE_POSITIVE_RATE);
+    for id in &ids {
+        filter.insert(id);
+    }
+    pgrx::JsonB(filter.to_json())
+}
+
+/// Per-author minimum of two version vectors (plain or detailed), authors
+/// missing from either counting as 0: the point both peers have reached,
+/// from which a push starts.
+#[pg_extern]
+pub(crate) fn version_frontier(a: pgrx::JsonB, b: pgrx::JsonB) -> pgrx::JsonB {
+    let a = frontier_seqs(&a.0, "version_frontier");
+    let b = frontier_seqs(&b.0, "version_frontier");
+    let frontier: serde_json::Map<String, Value> = a
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(author, seq)| {
+            let other = b[author].as_i64().unwrap_or(0);
+            (author.clone(), seq.as_i64().unwrap_or(0).min(other).into())
+        })
+        .collect();
+    pgrx::JsonB(Value::Object(frontier))
+}
+
+/// Sync messages older or newer than this many seconds are refused.
+const SYNC_MESSAGE_MAX_SKEW: i64 = 300;
+
+/// A peer whose clock is further off than this many milliseconds is
+/// warned about when its sync messages are opened.
+pub(crate) const CLOCK_SKEW_WARN_MS: i64 = 2_000;
+
+/// Canonical bytes a sync message signature covers:
+/// `"sync|from|sent_at|body_json"`.
+fn sync_signable(from: &str, sent_at: i64, body: &Value) -> Vec<u8> {
+    format!("sync|{}|{}|{}", from, sent_at, body).into_bytes()
+}
+
+/// Wrap `body` in a message signed by this instance, for sending to a peer
+/// over HTTP: `{from, public_key, sent_at, sent_at_ms, body, signature}`.
+///
+/// `sent_at_ms` refines the signed `sent_at` (seconds) for measuring clock
+/// skew; it is only believed when it falls within that second.
+#[pg_extern]
+pub(crate) fn sign_sync_message(body: pgrx::JsonB) -> pgrx::JsonB {
+    let (_instance_id, fingerprint) = get_self_identity();
+    let signing_key = identity::load_signing_key()
+        .unwrap_or_else(|| error!("Signing key not found — run kerai.bootstrap_instance() first"));
+    let public_key_hex = hex::encode(signing_key.verifying_key().as_bytes());
+    let sent_at_ms = hlc::now_ms();
+    let sent_at = sent_at_ms.div_euclid(1000);
+    let signature = identity::sign_data(&signing_key, &sync_signable(&fingerprint, sent_at, &body.0));
+    pgrx::JsonB(serde_json::json!({
+        "from": fingerprint,
+        "public_key": public_key_hex,
+        "sent_at": sent_at,
+        "sent_at_ms": sent_at_ms,
+        "body": body.0,
+        "signature": hex::encode(signature),
+    }))
+}
+
+/// Check a message from `sign_sync_message` and return `{from, instance,
+/// clock_skew_ms, body}`. The sender must be a registered peer whose public
+/// key matches, the signature must hold, and `sent_at` must be within five
+/// minutes of now; otherwise this raises an error.
+///
+/// `clock_skew_ms` is how far the sender's clock is ahead of this one
+/// (negative: behind), less the time the message took to arrive. It is
+/// recorded on the peer's kerai.instances row, and a skew beyond two
+/// seconds raises a warning: the peer's timestamps will run ahead of
+/// (or behind) their real times. A body carrying the peer's version vector
+/// (a pull request or reply) is recorded as its `acked_vector`.
+#[pg_extern]
+pub(crate) fn open_sync_message(message: pgrx::JsonB) -> pgrx::JsonB {
+    let msg = &message.0;
+    let from = msg["from"]
+        .as_str()
+        .unwrap_or_else(|| error!("Sync message is missing 'from'"));
+    let sent_at = msg["sent_at"]
+        .as_i64()
+        .unwrap_or_else(|| error!("Sync message is missing 'sent_at'"));
+    let signature = hex::decode(msg["signature"].as_str().unwrap_or_default())
+        .unwrap_or_else(|_| error!("Sync message signature is not hex"));
+
+    let sent_at_ms = msg["sent_at_ms"]
+        .as_i64()
+        .filter(|ms| ms.div_euclid(1000) == sent_at)
+        .unwrap_or(sent_at * 1000);
+    let skew_ms = sent_at_ms - hlc::now_ms();
+    if skew_ms.abs() > SYNC_MESSAGE_MAX_SKEW * 1000 {
+        error!("Sync message from '{}' is stale or from the future", from);
+    }
+
+    let (name, public_key_hex) = Spi::get_two::<String, String>(&format!(
+        "SELECT name, encode(public_key, 'hex') FROM kerai.instances
+         WHERE key_fingerprint = '{}' AND is_self = false",
+        sql_escape(from),
+    ))
+    .unwrap_or((None, None));
+    let (Some(name), Some(public_key_hex)) = (name, public_key_hex) else {
+        error!("Sync message from unregistered instance '{}'", from);
+    };
+    if msg["public_key"].as_str().is_some_and(|pk| pk != public_key_hex) {
+        error!("Sync message public key does not match peer '{}'", name);
+    }
+
+    let pk_bytes: [u8; 32] = hex::decode(&public_key_hex)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .unwrap_or_else(|| error!("Peer '{}' has an invalid public key", name));
+    let verifying_key = ed25519_dalek::VerifyingKey::from_bytes(&pk_bytes)
+        .unwrap_or_else(|_| error!("Peer '{}' has an invalid public key", name));
+    let signable = sync_signable(from, sent_at, &msg["body"]);
+    if !identity::verify_signature(&verifying_key, &signable, &signature) {
+        error!("Invalid signature on sync message from '{}'", name);
+    }
+
     Spi::run(&format!(
-        "NOTIFY kerai_ops, '{}'",
-        sql_escape(&notify_payload.to_string()),
+        "UPDATE kerai.instances SET last_seen = now(), clock_skew_ms = {skew_ms}, clock_checked_at = now()
+         WHERE key_fingerprint = '{}'",
+        sql_escape(from),
     ))
-    .ok();
+    .unwrap();
+    // A vector in the body is what the peer holds: kerai.gc purges a
+    // tombstone only once every peer has acknowledged its delete
+    if let Some(entries) = msg["body"]["vector"].as_object() {
+        let acked: serde_json::Map<String, Value> = entries
+            .iter()
+            .filter_map(|(author, entry)| {
+                let seq = entry.as_i64().or_else(|| entry.get("seq")?.as_i64())?;
+                Some((author.clone(), seq.into()))
+            })
+            .collect();
+        Spi::run(&format!(
+            "UPDATE kerai.instances SET acked_vector = '{}'::jsonb WHERE key_fingerprint = '{}'",
+            sql_escape(&Value::Object(acked).to_string()),
+            sql_escape(from),
+        ))
+        .unwrap();
+    }
+    if skew_ms.abs() > CLOCK_SKEW_WARN_MS {
+        warning!(
+            "Clock of peer '{}' is {} ms {} this instance's",
+            name,
+            skew_ms.abs(),
+            if skew_ms > 0 { "ahead of" } else { "behind" },
+        );
+    }
 
     pgrx::JsonB(serde_json::json!({
-        "status": "applied",
-        "op_type": op_type,
-        "node_id": affected_id,
-        "lamport_ts": lamport_ts,
-        "author_seq": author_seq,
-        "author": author,
+        "from": from,
+        "instance": name,
+        "clock_skew_ms": skew_ms,
+        "body": msg["body"],
     }))
 }
 
-/// Get the current version vector as JSON: {"author_fingerprint": max_seq, ...}
+/// Check the signatures on kerai.versions rows recorded by an instance
+/// (this one by default) against its registered public key.
+///
+/// Covers rows signed by the instance, plus unsigned rows attributed to it
+/// from before rows were signed. Returns `{instance_id, instance, checked,
+/// valid, unsigned, tampered: [{id, node_id, operation, author, timestamp}]}`;
+/// a tampered row has a signature that no longer matches its contents.
 #[pg_extern]
-fn version_vector() -> pgrx::JsonB {
-    clock::get_version_vector()
+fn verify_versions(instance_id: default!(Option<pgrx::Uuid>, "NULL")) -> pgrx::JsonB {
+    let instance_id = match instance_id {
+        Some(id) => id.to_string(),
+        None => get_self_identity().0,
+    };
+    let (name, public_key_hex) = Spi::get_two::<String, String>(&format!(
+        "SELECT name, encode(public_key, 'hex') FROM kerai.instances WHERE id = '{}'::uuid",
+        sql_escape(&instance_id),
+    ))
+    .unwrap_or((None, None));
+    let (Some(name), Some(public_key_hex)) = (name, public_key_hex) else {
+        error!("Instance not found: {}", instance_id);
+    };
+    let verifying_key = hex::decode(&public_key_hex)
+        .ok()
+        .and_then(|b| identity::verifying_key_from_bytes(&b))
+        .unwrap_or_else(|| error!("Instance '{}' has an invalid public key", name));
+
+    let mut checked = 0u64;
+    let mut valid = 0u64;
+    let mut unsigned = 0u64;
+    let mut tampered = Vec::new();
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                &format!(
+                    "SELECT {} AS row, encode(v.signature, 'hex') AS signature
+                     FROM kerai.versions v
+                     WHERE v.signed_by = '{id}'::uuid
+                        OR (v.signed_by IS NULL AND v.instance_id = '{id}'::uuid)
+                     ORDER BY v.timestamp",
+                    VERSION_SIGNED_ROW,
+                    id = sql_escape(&instance_id),
+                ),
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            checked += 1;
+            let data = row.get_by_name::<pgrx::JsonB, _>("row").unwrap().unwrap().0;
+            let Some(sig_hex) = row.get_by_name::<String, _>("signature").unwrap() else {
+                unsigned += 1;
+                continue;
+            };
+            let signature = hex::decode(sig_hex).unwrap_or_default();
+            if identity::verify_signature(&verifying_key, &identity::version_signable(&data), &signature) {
+                valid += 1;
+            } else {
+                tampered.push(serde_json::json!({
+                    "id": data["id"],
+                    "node_id": data["node_id"],
+                    "operation": data["operation"],
+                    "author": data["author"],
+                    "timestamp": data["timestamp"],
+                }));
+            }
+        }
+    });
+
+    pgrx::JsonB(serde_json::json!({
+        "instance_id": instance_id,
+        "instance": name,
+        "checked": checked,
+        "valid": valid,
+        "unsigned": unsigned,
+        "tampered": tampered,
+    }))
 }
 
-/// Get the current Lamport clock value.
+/// Get the current clock value: the newest timestamp stamped or seen, a
+/// hybrid logical clock (`physical_ms << 16 | logical`) unless only
+/// Lamport counters from before hybrid clocks have been seen.
 #[pg_extern]
 fn lamport_clock() -> i64 {
     clock::current_lamport_ts()
 }
 
+/// How far each peer's clock is off this instance's, as last measured when
+/// one of its sync messages was opened.
+///
+/// Returns `{now, clock, clock_wall_time, peers: [{instance, fingerprint,
+/// clock_skew_ms, checked_at, skewed}]}`: `clock` is `lamport_clock()`,
+/// `clock_wall_time` its estimated wall time, and `skewed` whether the
+/// peer is more than two seconds off. Peers never measured have a null
+/// `clock_skew_ms`.
+#[pg_extern]
+fn clock_skew() -> pgrx::JsonB {
+    let clock = clock::current_lamport_ts();
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_build_object(
+            'now', now(),
+            'clock', {clock}::bigint,
+            'clock_wall_time', kerai.hlc_wall_time({clock}),
+            'peers', COALESCE((
+                SELECT jsonb_agg(jsonb_build_object(
+                    'instance', name,
+                    'fingerprint', key_fingerprint,
+                    'clock_skew_ms', clock_skew_ms,
+                    'checked_at', clock_checked_at,
+                    'skewed', abs(clock_skew_ms) > {CLOCK_SKEW_WARN_MS}
+                ) ORDER BY name)
+                FROM kerai.instances WHERE NOT is_self
+            ), '[]'::jsonb)
+        )"
+    ))
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(serde_json::json!({})))
+}
+
 /// Get operations for a given author since a sequence number (exclusive).
 /// Returns a JSON array of operation objects, including the author's public_key.
 #[pg_extern]
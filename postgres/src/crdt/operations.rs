@@ -7,7 +7,7 @@ use serde_json::Value;
 use crate::sql::sql_escape;
 
 /// Valid operation types.
-const VALID_OP_TYPES: &[&str] = &[
+pub(crate) const VALID_OP_TYPES: &[&str] = &[
     "insert_node",
     "update_content",
     "update_metadata",
@@ -75,7 +75,7 @@ pub fn apply(
     instance_id: &str,
 ) -> String {
     match op_type {
-        "insert_node" => apply_insert_node(payload, instance_id),
+        "insert_node" => apply_insert_node(payload, instance_id, node_id),
         "update_content" => {
             let nid = node_id.unwrap();
             apply_update_content(nid, payload);
@@ -127,8 +127,9 @@ pub fn apply(
     }
 }
 
-/// INSERT a new node. Returns the generated UUID.
-fn apply_insert_node(payload: &Value, instance_id: &str) -> String {
+/// INSERT a new node. Returns its UUID: `node_id` when given (a replicated
+/// insert keeps the id its author created), otherwise a generated one.
+fn apply_insert_node(payload: &Value, instance_id: &str, node_id: Option<&str>) -> String {
     let kind = payload["kind"]
         .as_str()
         .unwrap_or_else(|| error!("insert_node requires 'kind' in payload"));
@@ -158,11 +159,16 @@ fn apply_insert_node(payload: &Value, instance_id: &str) -> String {
         None => "NULL".to_string(),
     };
     let meta_str = sql_escape(&metadata.to_string());
+    let id_sql = match node_id {
+        Some(id) => format!("'{}'::uuid", sql_escape(id)),
+        None => "kerai.uuid7()".to_string(),
+    };
 
     let new_id = Spi::get_one::<String>(&format!(
-        "INSERT INTO kerai.nodes (instance_id, kind, language, content, parent_id, position, path, metadata)
-         VALUES ('{}'::uuid, '{}', {}, {}, {}, {}, {}, '{}'::jsonb)
+        "INSERT INTO kerai.nodes (id, instance_id, kind, language, content, parent_id, position, path, metadata)
+         VALUES ({}, '{}'::uuid, '{}', {}, {}, {}, {}, {}, '{}'::jsonb)
          RETURNING id::text",
+        id_sql,
         sql_escape(instance_id),
         sql_escape(kind),
         lang_sql,
@@ -185,7 +191,7 @@ fn apply_update_content(node_id: &str, payload: &Value) {
         .unwrap_or_else(|| error!("update_content requires 'new_content' in payload"));
 
     Spi::run(&format!(
-        "UPDATE kerai.nodes SET content = '{}' WHERE id = '{}'::uuid",
+        "UPDATE kerai.nodes SET content = '{}' WHERE id = '{}'::uuid AND deleted_at IS NULL",
         sql_escape(new_content),
         sql_escape(node_id),
     ))
@@ -200,7 +206,8 @@ fn apply_update_metadata(node_id: &str, payload: &Value) {
 
     let merge_str = sql_escape(&merge.to_string());
     Spi::run(&format!(
-        "UPDATE kerai.nodes SET metadata = metadata || '{}'::jsonb WHERE id = '{}'::uuid",
+        "UPDATE kerai.nodes SET metadata = metadata || '{}'::jsonb
+         WHERE id = '{}'::uuid AND deleted_at IS NULL",
         merge_str,
         sql_escape(node_id),
     ))
@@ -220,66 +227,52 @@ fn apply_move_node(node_id: &str, payload: &Value) {
         return;
     }
     Spi::run(&format!(
-        "UPDATE kerai.nodes SET {} WHERE id = '{}'::uuid",
+        "UPDATE kerai.nodes SET {} WHERE id = '{}'::uuid AND deleted_at IS NULL",
         sets.join(", "),
         sql_escape(node_id),
     ))
     .unwrap();
 }
 
-/// DELETE a node. If cascade=true, recursively delete children. Otherwise reparent children.
+/// Tombstone a node: set `deleted_at`, keeping the row and its history
+/// until `kerai.gc` purges it. If cascade=true, the live descendants go
+/// with it. Otherwise its children are reparented to its parent. Edges
+/// touching what was deleted are removed.
 fn apply_delete_node(node_id: &str, payload: &Value) {
     let cascade = payload.get("cascade").and_then(|v| v.as_bool()).unwrap_or(false);
     let escaped_id = sql_escape(node_id);
 
-    if cascade {
-        // Delete edges referencing any descendant, then delete descendants
-        Spi::run(&format!(
-            "WITH RECURSIVE descendants AS (
-                SELECT id FROM kerai.nodes WHERE id = '{0}'::uuid
-                UNION ALL
-                SELECT n.id FROM kerai.nodes n JOIN descendants d ON n.parent_id = d.id
-            )
-            DELETE FROM kerai.edges WHERE source_id IN (SELECT id FROM descendants)
-                OR target_id IN (SELECT id FROM descendants)",
-            escaped_id,
-        ))
-        .unwrap();
-
-        Spi::run(&format!(
-            "WITH RECURSIVE descendants AS (
-                SELECT id FROM kerai.nodes WHERE id = '{0}'::uuid
-                UNION ALL
-                SELECT n.id FROM kerai.nodes n JOIN descendants d ON n.parent_id = d.id
-            )
-            DELETE FROM kerai.nodes WHERE id IN (SELECT id FROM descendants)",
-            escaped_id,
-        ))
-        .unwrap();
-    } else {
+    if !cascade {
         // Reparent children to the deleted node's parent
         Spi::run(&format!(
             "UPDATE kerai.nodes SET parent_id = (
                 SELECT parent_id FROM kerai.nodes WHERE id = '{0}'::uuid
-            ) WHERE parent_id = '{0}'::uuid",
-            escaped_id,
-        ))
-        .unwrap();
-
-        // Delete edges referencing this node
-        Spi::run(&format!(
-            "DELETE FROM kerai.edges WHERE source_id = '{0}'::uuid OR target_id = '{0}'::uuid",
-            escaped_id,
-        ))
-        .unwrap();
-
-        // Delete the node itself
-        Spi::run(&format!(
-            "DELETE FROM kerai.nodes WHERE id = '{}'::uuid",
+            ) WHERE parent_id = '{0}'::uuid AND deleted_at IS NULL",
             escaped_id,
         ))
         .unwrap();
     }
+
+    let doomed = format!(
+        "WITH RECURSIVE doomed AS (
+            SELECT id FROM kerai.nodes WHERE id = '{0}'::uuid AND deleted_at IS NULL
+            UNION ALL
+            SELECT n.id FROM kerai.nodes n JOIN doomed d ON n.parent_id = d.id
+            WHERE {1} AND n.deleted_at IS NULL
+        )",
+        escaped_id, cascade,
+    );
+    Spi::run(&format!(
+        "{doomed}
+        DELETE FROM kerai.edges WHERE source_id IN (SELECT id FROM doomed)
+            OR target_id IN (SELECT id FROM doomed)"
+    ))
+    .unwrap();
+    Spi::run(&format!(
+        "{doomed}
+        UPDATE kerai.nodes SET deleted_at = now() WHERE id IN (SELECT id FROM doomed)"
+    ))
+    .unwrap();
 }
 
 /// INSERT an edge. ON CONFLICT DO NOTHING for idempotency.
@@ -759,7 +752,8 @@ fn apply_signed_transfer(payload: &Value) -> String {
     ledger_id
 }
 
-/// Mint reward via CRDT replication. Inserts ledger mint + reward_log entry.
+/// Mint reward via CRDT replication. Inserts ledger mint + reward_log entry;
+/// an optional `reference_id`/`reference_type` names the work rewarded.
 fn apply_mint_reward(payload: &Value) -> String {
     let to_wallet = payload["to_wallet"]
         .as_str()
@@ -775,15 +769,25 @@ fn apply_mint_reward(payload: &Value) -> String {
         .unwrap_or_else(|| error!("mint_reward requires 'timestamp' in payload"));
 
     let reason = format!("reward:{}", work_type);
+    let ref_id_sql = match payload.get("reference_id").and_then(|v| v.as_str()) {
+        Some(r) => format!("'{}'::uuid", sql_escape(r)),
+        None => "NULL".to_string(),
+    };
+    let ref_type_sql = match payload.get("reference_type").and_then(|v| v.as_str()) {
+        Some(r) => format!("'{}'", sql_escape(r)),
+        None => "NULL".to_string(),
+    };
 
     // Insert ledger entry (mint: from_wallet = NULL)
     let ledger_id = Spi::get_one::<String>(&format!(
-        "INSERT INTO kerai.ledger (from_wallet, to_wallet, amount, reason, timestamp)
-         VALUES (NULL, '{}'::uuid, {}, '{}', {})
+        "INSERT INTO kerai.ledger (from_wallet, to_wallet, amount, reason, reference_id, reference_type, timestamp)
+         VALUES (NULL, '{}'::uuid, {}, '{}', {}, {}, {})
          RETURNING id::text",
         sql_escape(to_wallet),
         amount,
         sql_escape(&reason),
+        ref_id_sql,
+        ref_type_sql,
         timestamp,
     ))
     .unwrap()
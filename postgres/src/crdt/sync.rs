@@ -0,0 +1,213 @@
+/// Sync with peers over HTTP, run periodically by the sync background worker
+/// (see workers.rs) or on demand with `kerai.sync_now()`.
+///
+/// One exchange mirrors `kerai sync` against a peer's endpoint: send our
+/// version vector to its `/api/sync/pull`, apply the ops it returns, then
+/// send the ops it is missing (less those its version filter says it holds)
+/// to `/api/sync/push`. Both directions use signed sync messages. Every
+/// exchange, successful or not, is recorded in kerai.sync_log, along with
+/// the peer's clock skew as measured from its signed reply.
+///
+/// The peer's sync policy (see throttle.rs) holds exchanges outside its
+/// windows or past its caps, and cuts the ops moved to what is left of its
+/// budgets; `force` ignores the policy.
+use std::time::Duration;
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::sql::{sql_escape, sql_opt_text};
+
+/// How long one HTTP request to a peer may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Op counts from one exchange with a peer, and how far its clock is
+/// ahead of ours (milliseconds; negative: behind).
+#[derive(Debug, Default)]
+struct Exchange {
+    pulled: i64,
+    superseded: i64,
+    duplicates: i64,
+    pushed: i64,
+    clock_skew_ms: Option<i64>,
+    transferred: i64,
+    bytes: i64,
+    deferred: i64,
+}
+
+/// Registered peers with an HTTP endpoint (just `name`, if given):
+/// `[{id, name, fingerprint, endpoint}]`.
+pub fn peers(name: Option<&str>) -> Vec<Value> {
+    let name_sql = name
+        .map(|n| format!("AND name = '{}'", sql_escape(n)))
+        .unwrap_or_default();
+    let peers = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', id, 'name', name, 'fingerprint', key_fingerprint, 'endpoint', endpoint
+         ) ORDER BY name), '[]'::jsonb)
+         FROM kerai.instances
+         WHERE NOT is_self AND COALESCE(endpoint, '') <> '' {}",
+        name_sql,
+    ))
+    .unwrap()
+    .map(|p| p.0);
+    match peers {
+        Some(Value::Array(peers)) => peers,
+        _ => Vec::new(),
+    }
+}
+
+/// POST a signed message and return the reply, treating non-2xx as an error.
+fn post(http: &reqwest::blocking::Client, url: &str, message: &Value) -> Result<Value, String> {
+    let resp = http
+        .post(url)
+        .json(message)
+        .send()
+        .map_err(|e| format!("Request to {url} failed: {e}"))?;
+    let status = resp.status();
+    if !status.is_success() {
+        let text = resp.text().unwrap_or_default();
+        return Err(format!("{url} returned {status}: {text}"));
+    }
+    resp.json().map_err(|e| format!("Invalid JSON from {url}: {e}"))
+}
+
+/// Pull from and push to one peer, moving at most `max_ops` ops and
+/// `max_bytes` bytes between the two directions.
+fn exchange(
+    peer: &Value,
+    max_ops: Option<i64>,
+    max_bytes: Option<i64>,
+) -> Result<Exchange, String> {
+    let base = peer["endpoint"].as_str().unwrap_or_default().trim_end_matches('/');
+    let http = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {e}"))?;
+
+    let local_vv = super::version_vector(false).0;
+    let limit = json!({"max_ops": max_ops, "max_bytes": max_bytes});
+    let request =
+        super::sign_sync_message(pgrx::JsonB(json!({"vector": local_vv, "limit": limit}))).0;
+    let reply = post(&http, &format!("{base}/api/sync/pull"), &request)?;
+    let opened = super::open_sync_message(pgrx::JsonB(reply)).0;
+    // Any registered peer could sign a reply; only this one's is wanted
+    if opened["from"] != peer["fingerprint"] {
+        return Err(format!("{base} answered as {}, not {}", opened["from"], peer["fingerprint"]));
+    }
+    let body = &opened["body"];
+    if !body["ops"].is_array() || !body["vector"].is_object() {
+        return Err(format!("{base} sent a pull reply without vector and ops"));
+    }
+
+    // Work out the push before applying the pull, as the CLI does
+    let frontier = super::version_frontier(pgrx::JsonB(local_vv), pgrx::JsonB(body["vector"].clone()));
+    let filter = body["filter"].is_object().then(|| pgrx::JsonB(body["filter"].clone()));
+    let outgoing = super::version_delta(frontier, filter).0;
+
+    // The peer cuts to the limit too, but need not be running a kerai that does
+    let incoming = body["ops"].as_array().cloned().unwrap_or_default();
+    let (incoming, held_back, in_bytes) = super::throttle::limit(incoming, max_ops, max_bytes);
+    let transferred = incoming.len() as i64;
+    let pulled = super::apply_operations(pgrx::JsonB(Value::Array(incoming))).0;
+    let mut result = Exchange {
+        pulled: pulled["applied"].as_i64().unwrap_or(0),
+        superseded: pulled["superseded"].as_i64().unwrap_or(0),
+        duplicates: pulled["duplicates"].as_i64().unwrap_or(0),
+        pushed: 0,
+        clock_skew_ms: opened["clock_skew_ms"].as_i64(),
+        transferred,
+        bytes: in_bytes,
+        deferred: held_back as i64 + body["deferred"].as_i64().unwrap_or(0),
+    };
+
+    let outgoing = outgoing.as_array().cloned().unwrap_or_default();
+    let (outgoing, held_back, out_bytes) = super::throttle::limit(
+        outgoing,
+        max_ops.map(|n| n - result.transferred),
+        max_bytes.map(|n| n - result.bytes),
+    );
+    result.deferred += held_back as i64;
+    if !outgoing.is_empty() {
+        result.transferred += outgoing.len() as i64;
+        result.bytes += out_bytes;
+        let request = super::sign_sync_message(pgrx::JsonB(json!({"ops": outgoing}))).0;
+        let pushed = post(&http, &format!("{base}/api/sync/push"), &request)?;
+        result.pushed = pushed["applied"].as_i64().unwrap_or(0);
+    }
+    Ok(result)
+}
+
+/// Sync with one peer (an entry from `peers`) as its sync policy allows,
+/// or regardless with `force`, and record the outcome in kerai.sync_log:
+/// `held`, with the reason as its error, when the policy allows nothing
+/// now. Returns the log row as JSON.
+pub fn sync_peer(peer: &Value, force: bool) -> Value {
+    let started_at = Spi::get_one::<String>("SELECT clock_timestamp()::text")
+        .unwrap()
+        .unwrap_or_default();
+    let policy = super::throttle::state(peer["id"].as_str().unwrap_or_default());
+    let (max_ops, max_bytes) = if force {
+        (None, None)
+    } else {
+        (policy["ops_left"].as_i64(), policy["bytes_left"].as_i64())
+    };
+    let outcome = match policy["held"].as_str() {
+        Some(reason) if !force => Err(("held", reason.to_string())),
+        _ => exchange(peer, max_ops, max_bytes).map_err(|e| ("error", e)),
+    };
+    let (status, counts, error) = match outcome {
+        Ok(counts) => ("ok", counts, None),
+        Err((status, e)) => (status, Exchange::default(), Some(e)),
+    };
+    let skew = counts.clock_skew_ms.map(|ms| ms.to_string());
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "INSERT INTO kerai.sync_log
+            (peer_id, endpoint, started_at, status, pulled, superseded, duplicates, pushed,
+             clock_skew_ms, transferred, bytes, deferred, error)
+         VALUES ('{}'::uuid, '{}', '{}'::timestamptz, '{}', {}, {}, {}, {}, {}, {}, {}, {}, {})
+         RETURNING to_jsonb(sync_log.*) || jsonb_build_object('peer', '{}')",
+        sql_escape(peer["id"].as_str().unwrap_or_default()),
+        sql_escape(peer["endpoint"].as_str().unwrap_or_default()),
+        sql_escape(&started_at),
+        status,
+        counts.pulled,
+        counts.superseded,
+        counts.duplicates,
+        counts.pushed,
+        sql_opt_text(&skew),
+        counts.transferred,
+        counts.bytes,
+        counts.deferred,
+        sql_opt_text(&error),
+        sql_escape(peer["name"].as_str().unwrap_or_default()),
+    ))
+    .unwrap()
+    .map(|row| row.0)
+    .unwrap_or(Value::Null)
+}
+
+/// Sync now with every peer that has an HTTP endpoint, or just `peer` (by
+/// name), instead of waiting for the sync worker. Errors talking to a peer
+/// are recorded rather than raised. Peers' sync policies apply unless
+/// `force` is set.
+///
+/// Returns `{peers, ok, held, failed, results: [sync_log rows]}`.
+#[pg_extern]
+fn sync_now(peer: default!(Option<&str>, "NULL"), force: default!(bool, false)) -> pgrx::JsonB {
+    let peers = peers(peer);
+    if peers.is_empty() {
+        if let Some(name) = peer {
+            error!("No peer named '{}' with an endpoint", name);
+        }
+    }
+    let results: Vec<Value> = peers.iter().map(|p| sync_peer(p, force)).collect();
+    let count = |status: &str| results.iter().filter(|r| r["status"] == status).count();
+    pgrx::JsonB(json!({
+        "peers": results.len(),
+        "ok": count("ok"),
+        "held": count("held"),
+        "failed": count("error"),
+        "results": results,
+    }))
+}
@@ -0,0 +1,274 @@
+/// Peer sync scheduling: when a peer may be synced and how much may move.
+///
+/// A peer's policy lives on its kerai.instances row: `sync_windows`, the
+/// times of day (database local time) syncing is allowed, as `'22:00-06:00'`
+/// (a window ending before it starts runs past midnight; none means any
+/// time), `sync_max_ops_per_minute` and `sync_max_bytes_per_hour`. Usage is
+/// what kerai.sync_log records for the peer over the last minute and hour,
+/// so the sync worker, `kerai.sync_now` and `kerai sync` draw on the same
+/// budgets.
+///
+/// An exchange outside the windows, or with a budget already spent, is
+/// held. Otherwise ops past what is left of a budget are deferred to a
+/// later exchange. They are cut per author: the ops of an author that do go
+/// are a prefix of that author's ops, so the version vector the receiver
+/// ends up with still covers everything below it.
+use std::collections::HashSet;
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// Minutes since midnight for `HH:MM`.
+fn minute_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// A window `HH:MM-HH:MM` as its start and end minute.
+fn parse_window(window: &str) -> Option<(u32, u32)> {
+    let (start, end) = window.split_once('-')?;
+    Some((minute_of_day(start)?, minute_of_day(end)?))
+}
+
+/// Whether `minute` falls in `window`; a window starting and ending at the
+/// same minute is the whole day.
+fn in_window((start, end): (u32, u32), minute: u32) -> bool {
+    match start.cmp(&end) {
+        std::cmp::Ordering::Less => start <= minute && minute < end,
+        std::cmp::Ordering::Greater => minute >= start || minute < end,
+        std::cmp::Ordering::Equal => true,
+    }
+}
+
+/// Minutes from `minute` until one of `windows` is open: 0 when one is
+/// open now or there are none.
+fn minutes_until_open(windows: &[(u32, u32)], minute: u32) -> u32 {
+    windows
+        .iter()
+        .map(|&window| {
+            if in_window(window, minute) {
+                0
+            } else {
+                (window.0 + MINUTES_PER_DAY - minute) % MINUTES_PER_DAY
+            }
+        })
+        .min()
+        .unwrap_or(0)
+}
+
+/// The ops of `ops` that fit in `max_ops` ops and `max_bytes` bytes of
+/// JSON, in order and cut per author. Returns them with the number
+/// deferred and the bytes kept.
+pub(crate) fn limit(
+    ops: Vec<Value>,
+    max_ops: Option<i64>,
+    max_bytes: Option<i64>,
+) -> (Vec<Value>, usize, i64) {
+    let mut kept = Vec::new();
+    let mut bytes = 0;
+    let mut deferred = 0;
+    let mut cut: HashSet<String> = HashSet::new();
+    for op in ops {
+        let author = op["author"].as_str().unwrap_or_default().to_string();
+        let size = op.to_string().len() as i64;
+        let fits = max_ops.is_none_or(|max| (kept.len() as i64) < max)
+            && max_bytes.is_none_or(|max| bytes + size <= max);
+        if cut.contains(&author) || !fits {
+            cut.insert(author);
+            deferred += 1;
+            continue;
+        }
+        bytes += size;
+        kept.push(op);
+    }
+    (kept, deferred, bytes)
+}
+
+/// Policy, usage and what is left of the budgets for the peer with id
+/// `peer_id`: `{windows, max_ops_per_minute, max_bytes_per_hour, in_window,
+/// opens_in_minutes, ops_last_minute, bytes_last_hour, ops_left,
+/// bytes_left, held}`, `held` being why an exchange may not run now (null
+/// when it may) and a `_left` null when there is no cap.
+pub(crate) fn state(peer_id: &str) -> Value {
+    let row = Spi::get_one_with_args::<pgrx::JsonB>(
+        "SELECT jsonb_build_object(
+            'windows', COALESCE(to_jsonb(i.sync_windows), '[]'::jsonb),
+            'max_ops_per_minute', i.sync_max_ops_per_minute,
+            'max_bytes_per_hour', i.sync_max_bytes_per_hour,
+            'minute', (extract(hour FROM localtime) * 60 + extract(minute FROM localtime))::int,
+            'ops_last_minute', (
+                SELECT COALESCE(sum(transferred), 0) FROM kerai.sync_log
+                WHERE peer_id = i.id AND finished_at > now() - interval '1 minute'),
+            'bytes_last_hour', (
+                SELECT COALESCE(sum(bytes), 0) FROM kerai.sync_log
+                WHERE peer_id = i.id AND finished_at > now() - interval '1 hour'))
+         FROM kerai.instances i WHERE i.id = $1::uuid",
+        &[peer_id.into()],
+    )
+    .unwrap()
+    .map(|j| j.0)
+    .unwrap_or_else(|| error!("Peer not found: {}", peer_id));
+
+    let windows: Vec<(u32, u32)> = row["windows"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|w| parse_window(w.as_str()?))
+        .collect();
+    let minute = row["minute"].as_u64().unwrap_or(0) as u32;
+    let opens_in = minutes_until_open(&windows, minute);
+
+    let left = |cap: &str, used: &str| {
+        row[cap]
+            .as_i64()
+            .map(|cap| (cap - row[used].as_i64().unwrap_or(0)).max(0))
+    };
+    let ops_left = left("max_ops_per_minute", "ops_last_minute");
+    let bytes_left = left("max_bytes_per_hour", "bytes_last_hour");
+    let held = if opens_in > 0 {
+        Some(format!(
+            "outside sync windows, next opens in {opens_in} min"
+        ))
+    } else if ops_left == Some(0) {
+        Some("op rate cap reached for this minute".to_string())
+    } else if bytes_left == Some(0) {
+        Some("byte cap reached for this hour".to_string())
+    } else {
+        None
+    };
+
+    json!({
+        "windows": row["windows"],
+        "max_ops_per_minute": row["max_ops_per_minute"],
+        "max_bytes_per_hour": row["max_bytes_per_hour"],
+        "in_window": opens_in == 0,
+        "opens_in_minutes": opens_in,
+        "ops_last_minute": row["ops_last_minute"],
+        "bytes_last_hour": row["bytes_last_hour"],
+        "ops_left": ops_left,
+        "bytes_left": bytes_left,
+        "held": held,
+    })
+}
+
+/// Id of the non-self peer named `peer`.
+fn peer_id(peer: &str) -> String {
+    Spi::get_one_with_args::<String>(
+        "SELECT id::text FROM kerai.instances WHERE name = $1 AND NOT is_self",
+        &[peer.into()],
+    )
+    .unwrap()
+    .unwrap_or_else(|| error!("Peer '{}' not found", peer))
+}
+
+/// Set a peer's sync policy, replacing the one it had: the windows it may
+/// be synced in (`'HH:MM-HH:MM'`, local time) and its op and byte caps.
+/// A NULL leaves that part unrestricted.
+///
+/// Returns the peer's `peer_sync_state`.
+#[pg_extern]
+fn set_sync_policy(
+    peer: &str,
+    windows: default!(Option<Vec<String>>, "NULL"),
+    max_ops_per_minute: default!(Option<i32>, "NULL"),
+    max_bytes_per_hour: default!(Option<i64>, "NULL"),
+) -> pgrx::JsonB {
+    let id = peer_id(peer);
+    for window in windows.iter().flatten() {
+        if parse_window(window).is_none() {
+            error!("Invalid sync window '{}', expected HH:MM-HH:MM", window);
+        }
+    }
+    if max_ops_per_minute.is_some_and(|n| n <= 0) || max_bytes_per_hour.is_some_and(|n| n <= 0) {
+        error!("Sync caps must be positive");
+    }
+    let windows = windows.filter(|w| !w.is_empty());
+    Spi::run_with_args(
+        "UPDATE kerai.instances
+         SET sync_windows = $2, sync_max_ops_per_minute = $3, sync_max_bytes_per_hour = $4
+         WHERE id = $1::uuid",
+        &[
+            id.as_str().into(),
+            windows.into(),
+            max_ops_per_minute.into(),
+            max_bytes_per_hour.into(),
+        ],
+    )
+    .unwrap();
+    pgrx::JsonB(state(&id))
+}
+
+/// A peer's sync policy, recent usage and whether an exchange may run now;
+/// see `state`.
+#[pg_extern]
+fn peer_sync_state(peer: &str) -> pgrx::JsonB {
+    pgrx::JsonB(state(&peer_id(peer)))
+}
+
+/// Cut a batch of ops (as from `version_delta`) to `max_ops` ops and
+/// `max_bytes` bytes, keeping each author's ops a prefix.
+///
+/// Returns `{ops, deferred, bytes}`.
+#[pg_extern]
+fn limit_ops(
+    ops: pgrx::JsonB,
+    max_ops: default!(Option<i64>, "NULL"),
+    max_bytes: default!(Option<i64>, "NULL"),
+) -> pgrx::JsonB {
+    let ops = match ops.0 {
+        Value::Array(ops) => ops,
+        _ => error!("limit_ops expects a JSON array of ops"),
+    };
+    let (kept, deferred, bytes) = limit(ops, max_ops, max_bytes);
+    pgrx::JsonB(json!({"ops": kept, "deferred": deferred, "bytes": bytes}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_wrap_past_midnight() {
+        let night = parse_window("22:00-06:00").unwrap();
+        assert_eq!(night, (1320, 360));
+        assert!(in_window(night, 23 * 60));
+        assert!(in_window(night, 5 * 60 + 59));
+        assert!(!in_window(night, 6 * 60));
+        assert!(!in_window(night, 12 * 60));
+        assert!(in_window(parse_window("00:00-00:00").unwrap(), 720));
+
+        assert_eq!(minutes_until_open(&[night], 12 * 60), 600);
+        assert_eq!(minutes_until_open(&[night], 23 * 60), 0);
+        assert_eq!(minutes_until_open(&[], 12 * 60), 0);
+
+        for bad in ["22:00", "24:00-01:00", "10:60-11:00", "a-b"] {
+            assert_eq!(parse_window(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn limit_keeps_a_prefix_of_each_author() {
+        let op = |author: &str, seq: i64| json!({"author": author, "author_seq": seq});
+        let ops = vec![op("a", 1), op("b", 1), op("a", 2), op("b", 2), op("a", 3)];
+
+        let (kept, deferred, _) = limit(ops.clone(), Some(2), None);
+        assert_eq!(kept, vec![op("a", 1), op("b", 1)]);
+        assert_eq!(deferred, 3);
+
+        // b's first op is too big, so none of b's go, while a's still fit
+        let mut big = op("b", 1);
+        big["payload"] = json!("x".repeat(200));
+        let ops = vec![op("a", 1), big, op("a", 2), op("b", 2)];
+        let size = op("a", 1).to_string().len() as i64;
+        let (kept, deferred, bytes) = limit(ops, None, Some(3 * size));
+        assert_eq!(kept, vec![op("a", 1), op("a", 2)]);
+        assert_eq!(deferred, 2);
+        assert_eq!(bytes, 2 * size);
+
+        let (kept, deferred, bytes) = limit(vec![op("a", 1)], None, None);
+        assert_eq!((kept.len(), deferred, bytes), (1, 0, size));
+    }
+}
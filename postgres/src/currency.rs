@@ -350,15 +350,22 @@ fn supply_info() -> pgrx::JsonB {
 }
 
 /// Mint reward for work. Looks up reward_schedule, mints to self instance wallet, logs to reward_log.
+/// `reference_id`/`reference_type` name what the work was done on (a node,
+/// a parse run, a repository), stored on the ledger row for `ledger_for`.
 /// Returns the mint result or null JSON if work_type is disabled/not found.
 #[pg_extern]
-fn mint_reward(work_type: &str, details: Option<pgrx::JsonB>) -> pgrx::JsonB {
+fn mint_reward(
+    work_type: &str,
+    details: Option<pgrx::JsonB>,
+    reference_id: default!(Option<pgrx::Uuid>, "NULL"),
+    reference_type: default!(Option<&str>, "NULL"),
+) -> pgrx::JsonB {
     // Look up reward schedule
-    let schedule = Spi::get_one::<pgrx::JsonB>(&format!(
+    let schedule = Spi::get_one_with_args::<pgrx::JsonB>(
         "SELECT jsonb_build_object('reward', reward, 'enabled', enabled)
-         FROM kerai.reward_schedule WHERE work_type = '{}'",
-        sql_escape(work_type),
-    ))
+         FROM kerai.reward_schedule WHERE work_type = $1",
+        &[work_type.into()],
+    )
     .unwrap_or(None);
 
     let schedule_info = match schedule {
@@ -392,32 +399,35 @@ fn mint_reward(work_type: &str, details: Option<pgrx::JsonB>) -> pgrx::JsonB {
     .unwrap_or(1);
 
     // Insert ledger entry (mint)
-    let ledger_id = Spi::get_one::<String>(&format!(
-        "INSERT INTO kerai.ledger (from_wallet, to_wallet, amount, reason, timestamp)
-         VALUES (NULL, '{}'::uuid, {}, '{}', {})
+    let reference_id = reference_id.map(|r| r.to_string());
+    let ledger_id = Spi::get_one_with_args::<String>(
+        "INSERT INTO kerai.ledger (from_wallet, to_wallet, amount, reason, reference_id, reference_type, timestamp)
+         VALUES (NULL, $1::uuid, $2, $3, $4::uuid, $5, $6)
          RETURNING id::text",
-        sql_escape(&wallet_id),
-        reward,
-        sql_escape(&format!("reward:{}", work_type)),
-        lamport,
-    ))
+        &[
+            wallet_id.as_str().into(),
+            reward.into(),
+            format!("reward:{}", work_type).into(),
+            reference_id.as_deref().into(),
+            reference_type.into(),
+            lamport.into(),
+        ],
+    )
     .unwrap()
     .unwrap();
 
     // Log to reward_log
-    let details_str = match &details {
-        Some(d) => sql_escape(&d.0.to_string()),
-        None => "{}".to_string(),
-    };
-
-    Spi::run(&format!(
+    let details = details.unwrap_or_else(|| pgrx::JsonB(serde_json::json!({})));
+    Spi::run_with_args(
         "INSERT INTO kerai.reward_log (work_type, reward, wallet_id, details)
-         VALUES ('{}', {}, '{}'::uuid, '{}'::jsonb)",
-        sql_escape(work_type),
-        reward,
-        sql_escape(&wallet_id),
-        details_str,
-    ))
+         VALUES ($1, $2, $3::uuid, $4)",
+        &[
+            work_type.into(),
+            reward.into(),
+            wallet_id.as_str().into(),
+            details.into(),
+        ],
+    )
     .unwrap();
 
     pgrx::JsonB(serde_json::json!({
@@ -425,6 +435,70 @@ fn mint_reward(work_type: &str, details: Option<pgrx::JsonB>) -> pgrx::JsonB {
         "work_type": work_type,
         "reward": reward,
         "wallet_id": wallet_id,
+        "reference_id": reference_id,
+        "reference_type": reference_type,
+    }))
+}
+
+/// Mint the reward for `work_type` from Rust, as the parsers do after a
+/// run, for work on `reference_type` row `reference_id` (None when the row
+/// could not be found). `details` (file names and the like) travels as a
+/// parameter. Returns the mint, or null when nothing was minted.
+pub(crate) fn reward(
+    work_type: &str,
+    reference_type: &str,
+    reference_id: Option<&str>,
+    details: serde_json::Value,
+) -> serde_json::Value {
+    Spi::get_one_with_args::<pgrx::JsonB>(
+        "SELECT kerai.mint_reward($1, $2, $3::uuid, $4)",
+        &[
+            work_type.into(),
+            pgrx::JsonB(details).into(),
+            reference_id.into(),
+            reference_type.into(),
+        ],
+    )
+    .ok()
+    .flatten()
+    .map_or(serde_json::Value::Null, |j| j.0)
+}
+
+/// Everything the ledger records against `reference_id`: the mints for
+/// work on it and any transfers or settlements citing it, oldest first.
+///
+/// Returns `{reference_id, total, entries: [{id, from_wallet, to_wallet,
+/// amount, reason, reference_type, timestamp, created_at}]}`.
+#[pg_extern]
+fn ledger_for(reference_id: pgrx::Uuid) -> pgrx::JsonB {
+    let entries = Spi::get_one_with_args::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+                'id', id,
+                'from_wallet', from_wallet,
+                'to_wallet', to_wallet,
+                'amount', amount,
+                'reason', reason,
+                'reference_type', reference_type,
+                'timestamp', timestamp,
+                'created_at', created_at
+            ) ORDER BY timestamp), '[]'::jsonb)
+         FROM kerai.ledger
+         WHERE reference_id = $1",
+        &[reference_id.into()],
+    )
+    .unwrap()
+    .map_or(serde_json::json!([]), |j| j.0);
+
+    let total: i64 = entries
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|e| e["amount"].as_i64().unwrap_or(0))
+        .sum();
+    pgrx::JsonB(serde_json::json!({
+        "reference_id": reference_id.to_string(),
+        "total": total,
+        "entries": entries,
     }))
 }
 
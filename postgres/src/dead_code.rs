@@ -0,0 +1,265 @@
+/// Dead code — functions and structs nothing refers to.
+///
+/// A function is live when something `calls` it; a struct, or a function
+/// used as a value rather than called, when something `uses` it. `uses`
+/// edges are rebuilt here by name from type, struct-literal and pattern
+/// references, from call sites the call resolver left unresolved, and from
+/// the tokens of items that invoke macros (macro arguments are not walked,
+/// so `assert_eq!(helper(), 1)` would otherwise hide the call). Matching by
+/// name errs towards calling code live.
+///
+/// Entry points are never reported: `pub` items, `main`, functions with an
+/// entry attribute (`#[pg_extern]`, `#[test]`, ...) and functions in trait
+/// impls or trait definitions, which are reached through dispatch.
+use pgrx::prelude::*;
+use serde_json::json;
+
+use crate::sql::sql_text;
+
+/// Rule id of the suggestions made for dead code.
+const RULE: &str = "dead-code";
+
+/// Edges this pass owns, so re-running it replaces them.
+const RESOLVER: &str = "dead_code";
+
+/// Attributes that make a function reachable from outside the crate's own
+/// code: `#[pg_extern]`, `#[test]`, `#[tokio::test]`, ...
+const ENTRY_ATTRIBUTES: &[&str] = &[
+    "pg_extern",
+    "pg_test",
+    "pg_guard",
+    "test",
+    "bench",
+    "no_mangle",
+];
+
+/// Kinds that own the references inside them.
+const ITEM_KINDS: &[&str] = &[
+    "fn",
+    "struct",
+    "enum",
+    "union",
+    "trait",
+    "const",
+    "static",
+    "type_alias",
+];
+
+fn quoted(items: &[&str]) -> String {
+    items
+        .iter()
+        .map(|i| format!("'{i}'"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Leading CTE walking every Rust file: `sub(id, file_id, item_id, kind,
+/// content, parent_id, position, metadata)`, where `item_id` is the
+/// innermost enclosing item.
+fn rust_tree_cte() -> String {
+    format!(
+        "WITH RECURSIVE sub AS (
+            SELECT f.id, f.id AS file_id, NULL::uuid AS item_id, f.kind, f.content,
+                   f.parent_id, f.position, f.metadata
+            FROM kerai.nodes f
+            WHERE f.kind = 'file' AND f.language = 'rust' AND {outside}
+            UNION ALL
+            SELECT n.id, sub.file_id,
+                   CASE WHEN n.kind IN ({items}) THEN n.id ELSE sub.item_id END,
+                   n.kind, n.content, n.parent_id, n.position, n.metadata
+            FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+            WHERE n.kind <> 'suggestion'
+        )",
+        outside = crate::sandboxes::unsandboxed("f.path"),
+        items = quoted(ITEM_KINDS),
+    )
+}
+
+/// Replace the `uses` edges from earlier runs. Returns how many were made.
+fn resolve_uses() -> i64 {
+    Spi::run(&format!(
+        "DELETE FROM kerai.edges WHERE relation = 'uses' AND metadata->>'resolver' = '{RESOLVER}'"
+    ))
+    .unwrap();
+
+    Spi::get_one::<i64>(&format!(
+        "{tree}, resolved AS (
+            SELECT DISTINCT jsonb_array_elements_text(metadata->'sites') AS site
+            FROM kerai.edges WHERE relation = 'calls' AND metadata ? 'sites'
+        ), mentions AS (
+            -- Types, paths, struct literals and patterns name structs
+            SELECT s.item_id, s.content AS text, false AS value
+            FROM sub s
+            WHERE s.item_id IS NOT NULL
+              AND (s.kind LIKE 'type\\_%'
+                   OR s.kind IN ('expr_path', 'expr_struct', 'pat_struct', 'pat_tuple_struct', 'pat_path'))
+            UNION ALL
+            -- A path names a function too, unless it is a resolved callee
+            SELECT s.item_id, s.content, true
+            FROM sub s LEFT JOIN kerai.nodes p ON p.id = s.parent_id
+            WHERE s.item_id IS NOT NULL AND s.kind = 'expr_path'
+              AND NOT (p.kind = 'expr_call' AND s.position = 0 AND p.id::text IN (SELECT site FROM resolved))
+            UNION ALL
+            SELECT s.item_id, s.content, true
+            FROM sub s
+            WHERE s.item_id IS NOT NULL AND s.kind = 'expr_method_call'
+              AND s.id::text NOT IN (SELECT site FROM resolved)
+            UNION ALL
+            -- Macro arguments survive only in the enclosing item's source
+            SELECT DISTINCT i.id, i.metadata->>'source', true
+            FROM sub m JOIN kerai.nodes i ON i.id = m.item_id
+            WHERE m.kind = 'macro_call'
+        ), words AS (
+            SELECT DISTINCT m.item_id, w.word, m.value
+            FROM mentions m,
+                 regexp_split_to_table(COALESCE(m.text, ''), '[^A-Za-z0-9_]+') AS w(word)
+            WHERE w.word <> ''
+        ), made AS (
+            INSERT INTO kerai.edges (source_id, target_id, relation, metadata)
+            SELECT DISTINCT w.item_id, t.id, 'uses', jsonb_build_object('resolver', '{RESOLVER}')
+            FROM words w
+            JOIN sub t ON t.content = w.word
+                      AND (t.kind = 'struct' OR (t.kind = 'fn' AND w.value))
+            WHERE w.item_id <> t.id
+            ON CONFLICT (source_id, target_id, relation) DO NOTHING
+            RETURNING 1
+        )
+        SELECT count(*) FROM made",
+        tree = rust_tree_cte(),
+    ))
+    .unwrap()
+    .unwrap_or(0)
+}
+
+/// Find unreferenced functions and structs in parsed Rust, leaving a
+/// `dead-code` suggestion on each one that has none yet; reconstruction
+/// with the `suggestions` option shows those on top-level items as
+/// `// kerai:` comments.
+/// Suggestions whose target has since gained a caller are marked applied.
+///
+/// Returns `{uses, created, resolved, dead: [{id, kind, name, file,
+/// file_id, visibility}]}`, where `uses` counts the `uses` edges rebuilt.
+#[pg_extern]
+fn dead_code() -> pgrx::JsonB {
+    let uses = resolve_uses();
+
+    let entry = format!(
+        "^#\\s*\\[\\s*([A-Za-z_]+\\s*::\\s*)*({})\\M",
+        ENTRY_ATTRIBUTES.join("|")
+    );
+    let dead = Spi::get_one::<pgrx::JsonB>(&format!(
+        "{tree}, dead AS (
+            SELECT t.id, t.kind, t.content, t.file_id, t.position,
+                   COALESCE(t.metadata->>'visibility', 'private') AS visibility
+            FROM sub t
+            LEFT JOIN kerai.nodes owner ON owner.id = t.parent_id
+            WHERE t.kind IN ('fn', 'struct')
+              AND COALESCE(t.metadata->>'visibility', 'private') <> 'pub'
+              AND NOT (t.kind = 'fn' AND t.content = 'main')
+              AND NOT (owner.kind = 'trait' OR (owner.kind = 'impl' AND owner.metadata ? 'trait'))
+              AND NOT EXISTS (
+                  SELECT 1 FROM kerai.nodes a
+                  WHERE a.parent_id = t.id AND a.kind = 'attribute' AND a.content ~ {entry}
+              )
+              AND NOT EXISTS (
+                  SELECT 1 FROM kerai.edges e
+                  WHERE e.target_id = t.id AND e.relation IN ('calls', 'uses')
+                    AND e.source_id <> t.id
+              )
+        )
+        SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', d.id,
+            'kind', d.kind,
+            'name', d.content,
+            'file', COALESCE(f.metadata->>'source_path', f.content),
+            'file_id', d.file_id,
+            'visibility', d.visibility
+        ) ORDER BY f.content, d.position), '[]'::jsonb)
+        FROM dead d JOIN kerai.nodes f ON f.id = d.file_id",
+        tree = rust_tree_cte(),
+        entry = sql_text(&entry),
+    ))
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+
+    let mut created = 0;
+    let mut ids = Vec::new();
+    for item in dead.as_array().into_iter().flatten() {
+        let id = item["id"].as_str().unwrap_or_default();
+        ids.push(format!("'{id}'::uuid"));
+        let kind = if item["kind"] == "fn" {
+            "Function"
+        } else {
+            "Struct"
+        };
+        let message = format!(
+            "{kind} `{}` is never used; remove it or make it reachable",
+            item["name"].as_str().unwrap_or_default()
+        );
+        created += Spi::get_one::<i64>(&format!(
+            "WITH fresh AS (
+                SELECT kerai.uuid7() AS suggestion_id
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM kerai.edges e
+                    JOIN kerai.nodes sg ON sg.id = e.source_id
+                    WHERE e.target_id = '{id}'::uuid AND e.relation = 'suggests'
+                      AND sg.kind = 'suggestion' AND sg.metadata->>'rule' = {rule}
+                      AND sg.metadata->>'status' IN ('emitted', 'dismissed')
+                )
+            ), made AS (
+                INSERT INTO kerai.nodes (id, instance_id, kind, language, content, parent_id, position, metadata)
+                SELECT f.suggestion_id, t.instance_id, 'suggestion', 'rust', {message},
+                       '{file}'::uuid, t.position,
+                       jsonb_build_object(
+                           'rule', {rule},
+                           'status', 'emitted',
+                           'severity', 'warning',
+                           'category', 'dead_code'
+                       )
+                FROM fresh f, kerai.nodes t WHERE t.id = '{id}'::uuid
+                RETURNING id
+            ), linked AS (
+                INSERT INTO kerai.edges (source_id, target_id, relation, metadata)
+                SELECT id, '{id}'::uuid, 'suggests', jsonb_build_object('rule', {rule})
+                FROM made
+                RETURNING 1
+            )
+            SELECT count(*) FROM linked",
+            file = item["file_id"].as_str().unwrap_or_default(),
+            rule = sql_text(RULE),
+            message = sql_text(&message),
+        ))
+        .unwrap()
+        .unwrap_or(0);
+    }
+
+    // Earlier findings that are live again
+    let live = if ids.is_empty() {
+        "true".to_string()
+    } else {
+        format!("e.target_id NOT IN ({})", ids.join(", "))
+    };
+    let resolved = Spi::get_one::<i64>(&format!(
+        "WITH done AS (
+            UPDATE kerai.nodes sg
+            SET metadata = jsonb_set(sg.metadata, '{{status}}', '\"applied\"')
+            FROM kerai.edges e
+            WHERE e.source_id = sg.id AND e.relation = 'suggests'
+              AND sg.kind = 'suggestion' AND sg.metadata->>'rule' = {rule}
+              AND sg.metadata->>'status' = 'emitted'
+              AND {live}
+            RETURNING 1
+        )
+        SELECT count(*) FROM done",
+        rule = sql_text(RULE),
+    ))
+    .unwrap()
+    .unwrap_or(0);
+
+    pgrx::JsonB(json!({
+        "uses": uses,
+        "created": created,
+        "resolved": resolved,
+        "dead": dead,
+    }))
+}
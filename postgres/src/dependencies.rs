@@ -0,0 +1,173 @@
+/// Module-level dependency graphs and cycle detection.
+///
+/// Item-level `imports`, `depends_on` and `calls` edges are lifted to the
+/// modules containing their endpoints — the nearest crate, module, file,
+/// document or dependency node — so a call from one file into another
+/// becomes a single weighted edge between the two files. Edges inside a
+/// module are dropped.
+use pgrx::prelude::*;
+use serde_json::json;
+
+use crate::sql::sql_escape;
+
+/// Relations that make one module depend on another.
+const RELATIONS: &[&str] = &["imports", "depends_on", "calls"];
+
+/// Kinds that count as a module when lifting edges.
+const MODULE_KINDS: &[&str] = &["crate", "module", "file", "document", "dependency"];
+
+fn quoted(items: &[&str]) -> String {
+    items
+        .iter()
+        .map(|i| format!("'{i}'"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Leading CTEs shared by both queries, ending in `module_edges(source,
+/// target, relation, weight)`. Later CTEs may be recursive.
+fn module_edges_cte() -> String {
+    let relations = quoted(RELATIONS);
+    let kinds = quoted(MODULE_KINDS);
+    format!(
+        "WITH RECURSIVE ends AS (
+            SELECT source_id AS id FROM kerai.edges WHERE relation IN ({relations})
+            UNION
+            SELECT target_id FROM kerai.edges WHERE relation IN ({relations})
+        ), up AS (
+            SELECT n.id AS node_id, n.id, n.parent_id, n.kind, 0 AS depth
+            FROM ends JOIN kerai.nodes n ON n.id = ends.id
+            UNION ALL
+            SELECT up.node_id, p.id, p.parent_id, p.kind, up.depth + 1
+            FROM up JOIN kerai.nodes p ON p.id = up.parent_id
+            WHERE up.kind NOT IN ({kinds})
+        ), owner AS (
+            SELECT DISTINCT ON (node_id) node_id, id AS module_id
+            FROM up WHERE kind IN ({kinds})
+            ORDER BY node_id, depth
+        ), module_edges AS (
+            SELECT s.module_id AS source, t.module_id AS target, e.relation,
+                   count(*)::int AS weight
+            FROM kerai.edges e
+            JOIN owner s ON s.node_id = e.source_id
+            JOIN owner t ON t.node_id = e.target_id
+            WHERE e.relation IN ({relations}) AND s.module_id <> t.module_id
+            GROUP BY 1, 2, 3
+        )"
+    )
+}
+
+/// JSON description of module node `{alias}`.
+fn module_json(alias: &str) -> String {
+    format!(
+        "jsonb_build_object(
+            'id', {alias}.id,
+            'kind', {alias}.kind,
+            'name', COALESCE({alias}.metadata->>'source_path', {alias}.metadata->>'name', {alias}.content),
+            'path', {alias}.path::text
+        )"
+    )
+}
+
+/// Module-level dependency graph rooted at `root`.
+///
+/// Starts from the modules at or below `root` and follows their outgoing
+/// dependencies transitively, so modules outside the subtree appear when
+/// something inside depends on them (`internal` is false for those).
+///
+/// Returns `{root, modules: [{id, kind, name, path, internal}], edges:
+/// [{source, target, relation, weight}]}`, where `weight` counts the
+/// item-level edges lifted into each module edge.
+#[pg_extern]
+fn dependency_graph(root: &str) -> pgrx::JsonB {
+    let root = sql_escape(root);
+    let kinds = quoted(MODULE_KINDS);
+    let value = Spi::get_one::<pgrx::JsonB>(&format!(
+        "{edges}, seeds AS (
+            SELECT id FROM kerai.nodes
+            WHERE path <@ '{root}'::ltree AND kind IN ({kinds})
+        ), reach AS (
+            SELECT id FROM seeds
+            UNION
+            SELECT me.target FROM module_edges me JOIN reach r ON me.source = r.id
+        ), graph AS (
+            SELECT me.* FROM module_edges me JOIN reach r ON me.source = r.id
+        ), used AS (
+            SELECT source AS id FROM graph UNION SELECT target FROM graph
+        )
+        SELECT jsonb_build_object(
+            'root', '{root}',
+            'modules', COALESCE((
+                SELECT jsonb_agg({module} || jsonb_build_object(
+                    'internal', m.id IN (SELECT id FROM seeds)
+                ) ORDER BY m.path, m.content)
+                FROM kerai.nodes m WHERE m.id IN (SELECT id FROM used)
+            ), '[]'::jsonb),
+            'edges', COALESCE((
+                SELECT jsonb_agg(jsonb_build_object(
+                    'source', source,
+                    'target', target,
+                    'relation', relation,
+                    'weight', weight
+                ) ORDER BY source, target, relation)
+                FROM graph
+            ), '[]'::jsonb)
+        )",
+        edges = module_edges_cte(),
+        module = module_json("m"),
+    ))
+    .unwrap()
+    .map_or(json!({"root": root, "modules": [], "edges": []}), |j| j.0);
+    pgrx::JsonB(value)
+}
+
+/// Circular dependencies between modules, each reported once.
+///
+/// A cycle is found from its smallest module id, extending only through
+/// larger ids until an edge leads back, so rotations of the same cycle are
+/// not repeated. Cycles longer than `max_length` modules are not searched.
+///
+/// Returns `[{length, modules: [{id, kind, name, path}], relations}]`,
+/// shortest first; `modules` is in dependency order and the last one
+/// depends on the first.
+#[pg_extern]
+fn find_cycles(max_length: default!(i32, 8)) -> pgrx::JsonB {
+    let max_length = max_length.clamp(2, 32);
+    let value = Spi::get_one::<pgrx::JsonB>(&format!(
+        "{edges}, links AS (
+            SELECT source, target, array_agg(DISTINCT relation) AS relations
+            FROM module_edges GROUP BY source, target
+        ), walk AS (
+            SELECT source AS start, target AS node, ARRAY[source, target] AS trail,
+                   relations, false AS closed
+            FROM links WHERE target > source
+            UNION ALL
+            SELECT w.start, l.target, w.trail || l.target,
+                   ARRAY(SELECT DISTINCT unnest(w.relations || l.relations)),
+                   l.target = w.start
+            FROM walk w JOIN links l ON l.source = w.node
+            WHERE NOT w.closed
+              AND (l.target = w.start
+                   OR (l.target > w.start AND l.target <> ALL(w.trail)
+                       AND cardinality(w.trail) < {max_length}))
+        ), cycles AS (
+            SELECT trail[1:cardinality(trail) - 1] AS members, relations
+            FROM walk WHERE closed
+        )
+        SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'length', cardinality(c.members),
+            'modules', (
+                SELECT jsonb_agg({module} ORDER BY o.ord)
+                FROM unnest(c.members) WITH ORDINALITY AS o(id, ord)
+                JOIN kerai.nodes m ON m.id = o.id
+            ),
+            'relations', to_jsonb(c.relations)
+        ) ORDER BY cardinality(c.members), c.members), '[]'::jsonb)
+        FROM cycles c",
+        edges = module_edges_cte(),
+        module = module_json("m"),
+    ))
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+    pgrx::JsonB(value)
+}
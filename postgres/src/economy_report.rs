@@ -0,0 +1,295 @@
+/// Economy reports — ledger activity per period, locally and across a
+/// federation.
+///
+/// `economy_report` summarizes this instance's ledger for a calendar
+/// period (UTC). Peers exchange the same report as a signed sync message
+/// (`economy_summary`); `record_economy_summary` checks one against the
+/// sender's registered key and keeps it, and `federation_economy_report`
+/// merges the local report with the kept summaries for the same window.
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::crdt::{open_sync_message, sign_sync_message};
+use crate::sql::{sql_escape, sql_jsonb};
+
+/// Periods a report can cover.
+const PERIODS: &[&str] = &["day", "week", "month", "year", "all"];
+
+/// SQL for the start of the current `period` in UTC, or `None` for `all`.
+fn period_start(period: &str) -> Result<Option<String>, String> {
+    match period {
+        "all" => Ok(None),
+        p if PERIODS.contains(&p) => Ok(Some(format!(
+            "(date_trunc('{p}', now() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC')"
+        ))),
+        p => Err(format!(
+            "Unknown period '{}'; expected one of: {}",
+            p,
+            PERIODS.join(", ")
+        )),
+    }
+}
+
+/// This instance's ledger activity for `period` (day, week, month, year or
+/// all; calendar periods start at UTC midnight).
+///
+/// Returns `{instance: {name, fingerprint}, period, since, generated_at,
+/// minted, mints, transferred, transfers, actions: [{reason, count,
+/// amount}]}`. Amounts are nKoi; `actions` groups mints by reason, largest
+/// first, so it shows which work earns the most.
+#[pg_extern]
+fn economy_report(period: default!(&str, "'month'")) -> pgrx::JsonB {
+    let start = period_start(period).unwrap_or_else(|e| error!("{}", e));
+    let window = match &start {
+        Some(s) => format!("created_at >= {s}"),
+        None => "true".to_string(),
+    };
+    let since = match &start {
+        Some(s) => format!("to_char({s} AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')"),
+        None => "NULL".to_string(),
+    };
+
+    let report = Spi::get_one::<pgrx::JsonB>(&format!(
+        "WITH l AS (SELECT * FROM kerai.ledger WHERE {window})
+        SELECT jsonb_build_object(
+            'instance', (
+                SELECT jsonb_build_object('name', name, 'fingerprint', key_fingerprint)
+                FROM kerai.instances WHERE is_self = true
+            ),
+            'period', '{period}',
+            'since', {since},
+            'generated_at', to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"'),
+            'minted', (SELECT COALESCE(sum(amount), 0) FROM l WHERE from_wallet IS NULL),
+            'mints', (SELECT count(*) FROM l WHERE from_wallet IS NULL),
+            'transferred', (SELECT COALESCE(sum(amount), 0) FROM l WHERE from_wallet IS NOT NULL),
+            'transfers', (SELECT count(*) FROM l WHERE from_wallet IS NOT NULL),
+            'actions', (
+                SELECT COALESCE(jsonb_agg(jsonb_build_object(
+                    'reason', reason, 'count', n, 'amount', total
+                ) ORDER BY total DESC, reason), '[]'::jsonb)
+                FROM (
+                    SELECT reason, count(*) AS n, sum(amount) AS total
+                    FROM l WHERE from_wallet IS NULL GROUP BY reason
+                ) a
+            )
+        )",
+        period = sql_escape(period),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("Failed to build economy report"));
+
+    if report.0["instance"].is_null() {
+        error!("Self instance not found — run kerai.bootstrap_instance() first");
+    }
+    report
+}
+
+/// `economy_report(period)` signed with this instance's key, in the sync
+/// message format, for handing to a peer.
+#[pg_extern]
+fn economy_summary(period: default!(&str, "'month'")) -> pgrx::JsonB {
+    sign_sync_message(economy_report(period))
+}
+
+/// Check a peer's signed `economy_summary` and keep it, replacing the
+/// peer's previous summary for the same period. The message must verify
+/// against a registered peer's key and describe that peer's own ledger.
+///
+/// Returns `{instance, period, since}`.
+#[pg_extern]
+fn record_economy_summary(message: pgrx::JsonB) -> pgrx::JsonB {
+    let opened = open_sync_message(pgrx::JsonB(message.0.clone())).0;
+    let from = opened["from"].as_str().unwrap_or_default();
+    let report = &opened["body"];
+    if report["instance"]["fingerprint"].as_str() != Some(from) {
+        error!(
+            "Economy summary from '{}' describes another instance",
+            opened["instance"].as_str().unwrap_or(from)
+        );
+    }
+    let period = report["period"].as_str().unwrap_or_default();
+    period_start(period).unwrap_or_else(|e| error!("{}", e));
+
+    Spi::run(&format!(
+        "INSERT INTO kerai.economy_summaries (instance_id, period, report, message)
+         SELECT id, '{period}', {report}, {message}
+         FROM kerai.instances WHERE key_fingerprint = '{from}' AND is_self = false
+         ON CONFLICT (instance_id, period) DO UPDATE
+         SET report = EXCLUDED.report, message = EXCLUDED.message, received_at = now()",
+        period = sql_escape(period),
+        report = sql_jsonb(report),
+        message = sql_jsonb(&message.0),
+        from = sql_escape(from),
+    ))
+    .unwrap();
+
+    pgrx::JsonB(json!({
+        "instance": opened["instance"],
+        "period": period,
+        "since": report["since"],
+    }))
+}
+
+/// Merge per-instance reports (the local one first) into a federation
+/// report. Summaries for a different window than the local report, such
+/// as last month's, are listed as stale and left out of the totals.
+fn merge_reports(reports: &[Value]) -> Value {
+    let since = reports.first().map_or(Value::Null, |r| r["since"].clone());
+    let amount = |r: &Value, key: &str| r[key].as_i64().unwrap_or(0);
+
+    let mut instances = Vec::new();
+    let mut totals = json!({"minted": 0, "mints": 0, "transferred": 0, "transfers": 0});
+    let mut actions: Vec<(String, i64, i64)> = Vec::new();
+    for (i, report) in reports.iter().enumerate() {
+        let stale = report["since"] != since;
+        instances.push(json!({
+            "name": report["instance"]["name"],
+            "fingerprint": report["instance"]["fingerprint"],
+            "self": i == 0,
+            "minted": amount(report, "minted"),
+            "mints": amount(report, "mints"),
+            "transferred": amount(report, "transferred"),
+            "transfers": amount(report, "transfers"),
+            "generated_at": report["generated_at"],
+            "stale": stale,
+        }));
+        if stale {
+            continue;
+        }
+        for key in ["minted", "mints", "transferred", "transfers"] {
+            totals[key] = json!(amount(&totals, key) + amount(report, key));
+        }
+        for action in report["actions"].as_array().into_iter().flatten() {
+            let reason = action["reason"].as_str().unwrap_or_default();
+            match actions.iter_mut().find(|(r, _, _)| r == reason) {
+                Some((_, count, total)) => {
+                    *count += amount(action, "count");
+                    *total += amount(action, "amount");
+                }
+                None => actions.push((
+                    reason.to_string(),
+                    amount(action, "count"),
+                    amount(action, "amount"),
+                )),
+            }
+        }
+    }
+
+    instances.sort_by_key(|i| (i["stale"] == true, -i["minted"].as_i64().unwrap_or(0)));
+    actions.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    let top_minter = instances
+        .iter()
+        .find(|i| i["stale"] == false && i["minted"].as_i64().unwrap_or(0) > 0)
+        .map_or(Value::Null, |i| i["name"].clone());
+
+    json!({
+        "period": reports.first().map_or(Value::Null, |r| r["period"].clone()),
+        "since": since,
+        "instances": instances,
+        "totals": totals,
+        "top_minter": top_minter,
+        "actions": actions
+            .into_iter()
+            .map(|(reason, count, amount)| json!({"reason": reason, "count": count, "amount": amount}))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// The local `economy_report(period)` merged with the peer summaries kept
+/// by `record_economy_summary` for the same period.
+///
+/// Returns `{period, since, instances: [{name, fingerprint, self, minted,
+/// mints, transferred, transfers, generated_at, stale}], totals,
+/// top_minter, actions}`. Instances are ordered by amount minted; a peer
+/// whose summary covers an older window is marked stale and not counted.
+#[pg_extern]
+fn federation_economy_report(period: default!(&str, "'month'")) -> pgrx::JsonB {
+    let local = economy_report(period).0;
+    let peers = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(report ORDER BY received_at), '[]'::jsonb)
+         FROM kerai.economy_summaries WHERE period = '{}'",
+        sql_escape(period),
+    ))
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+
+    let mut reports = vec![local];
+    reports.extend(peers.as_array().cloned().unwrap_or_default());
+    pgrx::JsonB(merge_reports(&reports))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(name: &str, since: &str, minted: i64, actions: Value) -> Value {
+        json!({
+            "instance": {"name": name, "fingerprint": format!("fp-{name}")},
+            "period": "month",
+            "since": since,
+            "minted": minted,
+            "mints": actions.as_array().map_or(0, Vec::len),
+            "transferred": 5,
+            "transfers": 1,
+            "actions": actions,
+        })
+    }
+
+    #[test]
+    fn periods_are_checked() {
+        assert_eq!(period_start("all"), Ok(None));
+        assert!(period_start("month").unwrap().unwrap().contains("'month'"));
+        assert!(period_start("fortnight").is_err());
+        assert!(period_start("month'; DROP").is_err());
+    }
+
+    #[test]
+    fn merge_sums_current_reports() {
+        let merged = merge_reports(&[
+            report(
+                "local",
+                "2026-10-01T00:00:00Z",
+                30,
+                json!([{"reason": "reward:parse_file", "count": 3, "amount": 30}]),
+            ),
+            report(
+                "peer",
+                "2026-10-01T00:00:00Z",
+                70,
+                json!([
+                    {"reason": "reward:peer_sync", "count": 1, "amount": 50},
+                    {"reason": "reward:parse_file", "count": 2, "amount": 20},
+                ]),
+            ),
+        ]);
+        assert_eq!(merged["totals"]["minted"], 100);
+        assert_eq!(merged["totals"]["transfers"], 2);
+        assert_eq!(merged["top_minter"], "peer");
+        assert_eq!(merged["instances"][0]["name"], "peer");
+        assert_eq!(merged["instances"][1]["self"], true);
+        assert_eq!(
+            merged["actions"],
+            json!([
+                {"reason": "reward:parse_file", "count": 5, "amount": 50},
+                {"reason": "reward:peer_sync", "count": 1, "amount": 50},
+            ])
+        );
+    }
+
+    #[test]
+    fn merge_skips_stale_summaries() {
+        let merged = merge_reports(&[
+            report("local", "2026-10-01T00:00:00Z", 10, json!([])),
+            report(
+                "peer",
+                "2026-09-01T00:00:00Z",
+                500,
+                json!([{"reason": "reward:mirror_repo", "count": 5, "amount": 500}]),
+            ),
+        ]);
+        assert_eq!(merged["totals"]["minted"], 10);
+        assert_eq!(merged["top_minter"], "local");
+        assert_eq!(merged["instances"][1]["stale"], true);
+        assert_eq!(merged["actions"], json!([]));
+    }
+}
@@ -0,0 +1,271 @@
+/// Hand-made edges — link and unlink nodes through registered relations.
+///
+/// Parsers write their edges directly; these functions are for people and
+/// agents linking nodes by hand, say a design document to the code it
+/// describes. Each edge must use a relation in kerai.relations, between
+/// node kinds the relation allows, and is written as an `insert_edge` /
+/// `delete_edge` operation so it is signed and reaches peers on sync.
+///
+/// The bulk variants check every edge before writing any, so a batch with
+/// one bad edge changes nothing.
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::sql::{sql_jsonb, sql_text, sql_uuid};
+
+/// One edge of a batch, or of a single call.
+struct Edge {
+    source: String,
+    target: String,
+    relation: String,
+    metadata: Value,
+}
+
+impl Edge {
+    fn from_json(i: usize, v: &Value) -> Edge {
+        let field = |key: &str| {
+            v[key]
+                .as_str()
+                .map(String::from)
+                .unwrap_or_else(|| error!("Edge {}: missing '{}'", i, key))
+        };
+        Edge {
+            source: field("source_id"),
+            target: field("target_id"),
+            relation: field("relation"),
+            metadata: v.get("metadata").cloned().unwrap_or_else(|| json!({})),
+        }
+    }
+}
+
+/// Why the registry rejects an edge, if it does. `found` is what
+/// `lookup` read: `{registered, source_kind, target_kind, source_kinds,
+/// target_kinds}`, kinds null for missing nodes and kind lists null for
+/// relations that allow any kind.
+fn check(relation: &str, found: &Value) -> Result<(), String> {
+    if found["registered"] != true {
+        return Err(format!(
+            "Unknown relation '{}'; register it in kerai.relations",
+            relation
+        ));
+    }
+    for end in ["source", "target"] {
+        let Some(kind) = found[format!("{end}_kind")].as_str() else {
+            return Err(format!("{} node not found", end));
+        };
+        if let Some(allowed) = found[format!("{end}_kinds")].as_array() {
+            if !allowed.iter().any(|k| k == kind) {
+                let allowed: Vec<&str> = allowed.iter().filter_map(|k| k.as_str()).collect();
+                return Err(format!(
+                    "Relation '{}' needs a {} of kind {}, not {}",
+                    relation,
+                    end,
+                    allowed.join(" or "),
+                    kind
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lookup(edge: &Edge) -> Value {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_build_object(
+            'registered', r.relation IS NOT NULL,
+            'source_kind', (SELECT kind FROM kerai.nodes WHERE id = {source}),
+            'target_kind', (SELECT kind FROM kerai.nodes WHERE id = {target}),
+            'source_kinds', to_jsonb(r.source_kinds),
+            'target_kinds', to_jsonb(r.target_kinds)
+        ) FROM (SELECT 1) one
+        LEFT JOIN kerai.relations r ON r.relation = {relation}",
+        source = sql_uuid(&edge.source),
+        target = sql_uuid(&edge.target),
+        relation = sql_text(&edge.relation),
+    ))
+    .unwrap()
+    .map(|j| j.0)
+    .unwrap_or(Value::Null)
+}
+
+fn validate(edges: &[Edge]) {
+    for (i, edge) in edges.iter().enumerate() {
+        if let Err(e) = check(&edge.relation, &lookup(edge)) {
+            if edges.len() == 1 {
+                error!("{}", e);
+            }
+            error!("Edge {}: {}", i, e);
+        }
+    }
+}
+
+fn exists(edge: &Edge) -> bool {
+    Spi::get_one::<bool>(&format!(
+        "SELECT EXISTS(SELECT 1 FROM kerai.edges
+                       WHERE source_id = {} AND target_id = {} AND relation = {})",
+        sql_uuid(&edge.source),
+        sql_uuid(&edge.target),
+        sql_text(&edge.relation),
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Write one edge change as a CRDT operation. Skipped when it would change
+/// nothing, so repeating a batch adds no operations.
+fn apply(op: &str, edge: &Edge) -> bool {
+    if exists(edge) == (op == "insert_edge") {
+        return false;
+    }
+    let mut payload = json!({ "target_id": edge.target, "relation": edge.relation });
+    if op == "insert_edge" {
+        payload["metadata"] = edge.metadata.clone();
+    }
+    Spi::run(&format!(
+        "SELECT kerai.apply_op({}, {}, {})",
+        sql_text(op),
+        sql_uuid(&edge.source),
+        sql_jsonb(&payload),
+    ))
+    .unwrap_or_else(|e| error!("{} failed: {}", op, e));
+    true
+}
+
+fn parse_batch(edges: &Value) -> Vec<Edge> {
+    edges
+        .as_array()
+        .unwrap_or_else(|| error!("Edges must be a JSON array"))
+        .iter()
+        .enumerate()
+        .map(|(i, v)| Edge::from_json(i, v))
+        .collect()
+}
+
+fn single(source: pgrx::Uuid, target: pgrx::Uuid, relation: &str, metadata: Value) -> Edge {
+    Edge {
+        source: source.to_string(),
+        target: target.to_string(),
+        relation: relation.to_string(),
+        metadata,
+    }
+}
+
+/// Link `source` to `target` through `relation`, which must be registered
+/// in kerai.relations and allow both nodes' kinds.
+///
+/// Returns `{source_id, target_id, relation, added}`, `added` false when
+/// the edge was already there (its metadata is left as it was).
+#[pg_extern]
+fn add_edge(
+    source: pgrx::Uuid,
+    target: pgrx::Uuid,
+    relation: &str,
+    metadata: default!(pgrx::JsonB, "'{}'::jsonb"),
+) -> pgrx::JsonB {
+    let edge = single(source, target, relation, metadata.0);
+    validate(std::slice::from_ref(&edge));
+    let added = apply("insert_edge", &edge);
+    pgrx::JsonB(json!({
+        "source_id": edge.source,
+        "target_id": edge.target,
+        "relation": edge.relation,
+        "added": added,
+    }))
+}
+
+/// Remove the `relation` edge from `source` to `target`.
+///
+/// Returns `{source_id, target_id, relation, removed}`, `removed` false
+/// when there was no such edge.
+#[pg_extern]
+fn remove_edge(source: pgrx::Uuid, target: pgrx::Uuid, relation: &str) -> pgrx::JsonB {
+    let edge = single(source, target, relation, json!({}));
+    let removed = apply("delete_edge", &edge);
+    pgrx::JsonB(json!({
+        "source_id": edge.source,
+        "target_id": edge.target,
+        "relation": edge.relation,
+        "removed": removed,
+    }))
+}
+
+/// Add every edge of `edges`, a JSON array of `{source_id, target_id,
+/// relation, metadata?}`, checking them all first.
+///
+/// Returns `{added, existing}`.
+#[pg_extern]
+fn add_edges(edges: pgrx::JsonB) -> pgrx::JsonB {
+    let edges = parse_batch(&edges.0);
+    validate(&edges);
+    let added = edges.iter().filter(|e| apply("insert_edge", e)).count();
+    pgrx::JsonB(json!({ "added": added, "existing": edges.len() - added }))
+}
+
+/// Remove every edge of `edges`, a JSON array of `{source_id, target_id,
+/// relation}`.
+///
+/// Returns `{removed, missing}`.
+#[pg_extern]
+fn remove_edges(edges: pgrx::JsonB) -> pgrx::JsonB {
+    let edges = parse_batch(&edges.0);
+    let removed = edges.iter().filter(|e| apply("delete_edge", e)).count();
+    pgrx::JsonB(json!({ "removed": removed, "missing": edges.len() - removed }))
+}
+
+/// Registered relations: `[{relation, description, source_kinds,
+/// target_kinds, edges}]`, `edges` counting the edges that use each.
+#[pg_extern]
+fn list_relations() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'relation', r.relation,
+            'description', r.description,
+            'source_kinds', to_jsonb(r.source_kinds),
+            'target_kinds', to_jsonb(r.target_kinds),
+            'edges', (SELECT count(*) FROM kerai.edges e WHERE e.relation = r.relation)
+        ) ORDER BY r.relation), '[]'::jsonb)
+        FROM kerai.relations r",
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(source_kinds: Value) -> Value {
+        json!({
+            "registered": true,
+            "source_kind": "document",
+            "target_kind": "fn",
+            "source_kinds": source_kinds,
+            "target_kinds": null,
+        })
+    }
+
+    #[test]
+    fn check_applies_kind_lists() {
+        assert!(check("documents", &found(Value::Null)).is_ok());
+        assert!(check("documents", &found(json!(["file", "document"]))).is_ok());
+        let err = check("suggests", &found(json!(["suggestion"]))).unwrap_err();
+        assert!(
+            err.contains("source of kind suggestion, not document"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn check_rejects_unknown_relations_and_nodes() {
+        let unknown = json!({ "registered": false });
+        assert!(check("relates", &unknown)
+            .unwrap_err()
+            .contains("Unknown relation"));
+        let mut missing = found(Value::Null);
+        missing["target_kind"] = Value::Null;
+        assert_eq!(
+            check("documents", &missing).unwrap_err(),
+            "target node not found"
+        );
+    }
+}
@@ -0,0 +1,383 @@
+/// Node embeddings and semantic search.
+///
+/// A model here is an agent's MicroGPT: a node's embedding is the model's
+/// final hidden state with the node as the last token and its ancestors
+/// (as far as the context window reaches) before it, so nodes that sit in
+/// similar places and get predicted in similar walks end up close.
+/// Vectors are kept as `real[]` in `kerai.node_embeddings`; when pgvector
+/// is installed, search ranks with its `<=>` operator instead of scanning
+/// in Rust.
+///
+/// The model's vocabulary is nodes, not words, so a text query is anchored
+/// on its best full-text hits: the query vector is their rank-weighted
+/// mean, and the nearest embeddings to it are returned — including nodes
+/// that share no words with the query.
+use std::collections::HashMap;
+
+use pgrx::prelude::*;
+use serde_json::json;
+
+use crate::microgpt::{agent_id_by_name, deduct_inference_cost, load_model_config, load_weights};
+use crate::sql::sql_escape;
+
+/// Full-text hits used to anchor a query.
+const ANCHORS: i64 = 10;
+
+/// Rows per upsert statement when storing embeddings.
+const BATCH: usize = 200;
+
+/// A vocabulary entry: token index and parent node.
+pub struct VocabNode {
+    pub token: usize,
+    pub parent: Option<String>,
+}
+
+/// Token sequence for a node: its ancestors that are in the vocabulary,
+/// root first, ending with the node itself; at most `max` tokens.
+pub fn context_tokens(node: &str, vocab: &HashMap<String, VocabNode>, max: usize) -> Vec<usize> {
+    let mut tokens = Vec::new();
+    let mut current = Some(node.to_string());
+    while let Some(id) = current {
+        if tokens.len() >= max {
+            break;
+        }
+        match vocab.get(&id) {
+            Some(entry) => {
+                tokens.push(entry.token);
+                current = entry.parent.clone();
+            }
+            None => break,
+        }
+    }
+    tokens.reverse();
+    tokens
+}
+
+pub fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 {
+        0.0
+    } else {
+        dot / denom
+    }
+}
+
+/// Weighted mean of equal-length vectors; `None` if there are none or
+/// the weights sum to zero.
+pub fn weighted_mean(vectors: &[(Vec<f32>, f64)]) -> Option<Vec<f32>> {
+    let dim = vectors.first()?.0.len();
+    let total: f64 = vectors.iter().filter(|(v, _)| v.len() == dim).map(|(_, w)| w).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut mean = vec![0.0f64; dim];
+    for (v, w) in vectors.iter().filter(|(v, _)| v.len() == dim) {
+        for (m, x) in mean.iter_mut().zip(v) {
+            *m += *x as f64 * w / total;
+        }
+    }
+    Some(mean.into_iter().map(|x| x as f32).collect())
+}
+
+fn real_array(v: &[f32]) -> String {
+    let items: Vec<String> = v.iter().map(|x| x.to_string()).collect();
+    format!("'{{{}}}'::real[]", items.join(","))
+}
+
+fn pgvector_installed() -> bool {
+    Spi::get_one::<bool>("SELECT EXISTS(SELECT 1 FROM pg_extension WHERE extname = 'vector')")
+        .unwrap_or(Some(false))
+        .unwrap_or(false)
+}
+
+/// Compute and store an embedding for every node in `model`'s vocabulary,
+/// replacing earlier ones. `model` is the name of an agent with a trained
+/// MicroGPT (see `kerai.create_model`). Returns `{model, embedded, dim,
+/// model_version, pgvector}`.
+#[pg_extern]
+fn embed_nodes(model: &str) -> pgrx::JsonB {
+    let agent_id = agent_id_by_name(model).unwrap_or_else(|e| error!("{e}"));
+    let config = load_model_config(&agent_id).unwrap_or_else(|e| error!("{e}"));
+    let gpt = load_weights(&agent_id, &config).unwrap_or_else(|e| error!("{e}"));
+    let version = Spi::get_one::<i64>(&format!(
+        "SELECT max(version) FROM kerai.model_weights WHERE agent_id = '{agent_id}'::uuid"
+    ))
+    .unwrap()
+    .unwrap_or(1);
+
+    let mut vocab: HashMap<String, VocabNode> = HashMap::new();
+    let mut hashes: HashMap<String, Option<String>> = HashMap::new();
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                &format!(
+                    "SELECT v.node_id::text, v.token_idx, n.parent_id::text, n.content_hash
+                     FROM kerai.model_vocab v JOIN kerai.nodes n ON n.id = v.node_id
+                     WHERE v.model_id = '{agent_id}'::uuid"
+                ),
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            let id = row.get::<String>(1).unwrap().unwrap_or_default();
+            let token = row.get::<i32>(2).unwrap().unwrap_or(0) as usize;
+            hashes.insert(id.clone(), row.get::<String>(4).unwrap());
+            vocab.insert(
+                id,
+                VocabNode {
+                    token,
+                    parent: row.get::<String>(3).unwrap(),
+                },
+            );
+        }
+    });
+    if vocab.is_empty() {
+        error!("Model '{}' has no vocabulary; run kerai.create_model first", model);
+    }
+
+    let mut rows = Vec::new();
+    for id in vocab.keys() {
+        let tokens = context_tokens(id, &vocab, config.context_len);
+        if tokens.is_empty() || tokens.iter().any(|&t| t >= config.vocab_size) {
+            continue;
+        }
+        let vector = gpt.hidden_state(&tokens);
+        let hash = match &hashes[id] {
+            Some(h) => format!("'{}'", sql_escape(h)),
+            None => "NULL".to_string(),
+        };
+        rows.push(format!(
+            "('{agent_id}'::uuid, '{}'::uuid, {}, {}, {version}, {hash})",
+            sql_escape(id),
+            real_array(&vector),
+            vector.len(),
+        ));
+    }
+
+    Spi::run(&format!(
+        "DELETE FROM kerai.node_embeddings WHERE model_id = '{agent_id}'::uuid"
+    ))
+    .unwrap();
+    for chunk in rows.chunks(BATCH) {
+        Spi::run(&format!(
+            "INSERT INTO kerai.node_embeddings (model_id, node_id, embedding, dim, model_version, content_hash)
+             VALUES {}",
+            chunk.join(", "),
+        ))
+        .unwrap();
+    }
+    deduct_inference_cost(&agent_id);
+
+    pgrx::JsonB(json!({
+        "model": model,
+        "embedded": rows.len(),
+        "dim": config.dim,
+        "model_version": version,
+        "pgvector": pgvector_installed(),
+    }))
+}
+
+/// Model to search with: the named one, or the most recently embedded.
+fn search_model(model: Option<&str>) -> String {
+    match model {
+        Some(name) => agent_id_by_name(name).unwrap_or_else(|e| error!("{e}")),
+        None => Spi::get_one::<String>(
+            "SELECT model_id::text FROM kerai.node_embeddings
+             GROUP BY model_id ORDER BY max(embedded_at) DESC LIMIT 1",
+        )
+        .unwrap_or(None)
+        .unwrap_or_else(|| error!("No node embeddings yet; run kerai.embed_nodes first")),
+    }
+}
+
+/// Query vector: rank-weighted mean of the embeddings of the query's best
+/// full-text hits.
+fn query_vector(query: &str, model_id: &str) -> Option<Vec<f32>> {
+    let mut anchors = Vec::new();
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                &format!(
+                    "SELECT e.embedding, ts_rank(n.tsv, q.query, 1)::float8 AS rank
+                     FROM kerai.nodes n
+                     JOIN kerai.node_embeddings e ON e.node_id = n.id AND e.model_id = '{model_id}'::uuid,
+                          websearch_to_tsquery('english', '{}') q(query)
+                     WHERE n.tsv @@ q.query AND n.deleted_at IS NULL
+                     ORDER BY rank DESC
+                     LIMIT {ANCHORS}",
+                    sql_escape(query),
+                ),
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            if let Some(vector) = row.get::<Vec<f32>>(1).unwrap() {
+                anchors.push((vector, row.get::<f64>(2).unwrap().unwrap_or(0.0)));
+            }
+        }
+    });
+    weighted_mean(&anchors)
+}
+
+/// Nearest `k` embeddings by cosine similarity, scanned in Rust.
+fn nearest_native(model_id: &str, q: &[f32], k: usize) -> Vec<(String, f64)> {
+    let mut scored = Vec::new();
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                &format!(
+                    "SELECT node_id::text, embedding FROM kerai.node_embeddings
+                     WHERE model_id = '{model_id}'::uuid"
+                ),
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            let id = row.get::<String>(1).unwrap().unwrap_or_default();
+            if let Some(vector) = row.get::<Vec<f32>>(2).unwrap() {
+                scored.push((id, cosine(q, &vector)));
+            }
+        }
+    });
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(k);
+    scored
+}
+
+/// Nearest `k` embeddings by pgvector cosine distance.
+fn nearest_pgvector(model_id: &str, q: &[f32], k: usize) -> Vec<(String, f64)> {
+    let items: Vec<String> = q.iter().map(|x| x.to_string()).collect();
+    let target = format!("'[{}]'::vector", items.join(","));
+    let mut scored = Vec::new();
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                &format!(
+                    "SELECT node_id::text, (1 - (embedding::vector <=> {target}))::float8
+                     FROM kerai.node_embeddings
+                     WHERE model_id = '{model_id}'::uuid AND dim = {}
+                     ORDER BY embedding::vector <=> {target}
+                     LIMIT {k}",
+                    q.len(),
+                ),
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            scored.push((
+                row.get::<String>(1).unwrap().unwrap_or_default(),
+                row.get::<f64>(2).unwrap().unwrap_or(0.0),
+            ));
+        }
+    });
+    scored
+}
+
+/// Semantic search: the `k` nodes whose embeddings are closest to the
+/// query's, using `model` or else the most recently embedded model.
+///
+/// Returns JSON array of `{id, kind, content, path, similarity}`, most
+/// similar first; empty when no embedded node matches the query text to
+/// anchor on. Under a view (`kerai.apply_view`), neighbours outside it are
+/// dropped, so fewer than `k` may come back.
+#[pg_extern]
+fn search_semantic(
+    query: &str,
+    k: default!(i32, 10),
+    model: default!(Option<&str>, "NULL"),
+) -> pgrx::JsonB {
+    let model_id = search_model(model);
+    let Some(q) = query_vector(query, &model_id) else {
+        return pgrx::JsonB(json!([]));
+    };
+    let k = k.clamp(1, 1000) as usize;
+    let nearest = if pgvector_installed() {
+        nearest_pgvector(&model_id, &q, k)
+    } else {
+        nearest_native(&model_id, &q, k)
+    };
+    if nearest.is_empty() {
+        return pgrx::JsonB(json!([]));
+    }
+
+    let values: Vec<String> = nearest
+        .iter()
+        .enumerate()
+        .map(|(i, (id, sim))| format!("('{}'::uuid, {sim}::float8, {i})", sql_escape(id)))
+        .collect();
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', n.id,
+            'kind', n.kind,
+            'content', n.content,
+            'path', n.path::text,
+            'similarity', round(s.similarity::numeric, 4)
+        ) ORDER BY s.ord), '[]'::jsonb)
+        FROM (VALUES {}) s(id, similarity, ord)
+        JOIN kerai.nodes n ON n.id = s.id
+        WHERE n.deleted_at IS NULL AND kerai.in_view(n.path, n.kind)",
+        values.join(", "),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vocab(entries: &[(&str, usize, Option<&str>)]) -> HashMap<String, VocabNode> {
+        entries
+            .iter()
+            .map(|(id, token, parent)| {
+                (
+                    id.to_string(),
+                    VocabNode {
+                        token: *token,
+                        parent: parent.map(str::to_string),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn context_runs_root_first_and_respects_window() {
+        let v = vocab(&[
+            ("file", 0, None),
+            ("fn", 1, Some("file")),
+            ("body", 2, Some("fn")),
+            ("orphan", 3, Some("missing")),
+        ]);
+        assert_eq!(context_tokens("body", &v, 16), vec![0, 1, 2]);
+        assert_eq!(context_tokens("body", &v, 2), vec![1, 2]);
+        assert_eq!(context_tokens("orphan", &v, 16), vec![3]);
+        assert!(context_tokens("missing", &v, 16).is_empty());
+    }
+
+    #[test]
+    fn cosine_handles_direction_and_degenerate_vectors() {
+        assert!((cosine(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-9);
+        assert!(cosine(&[1.0, 0.0], &[0.0, 3.0]).abs() < 1e-9);
+        assert!((cosine(&[1.0, 1.0], &[-1.0, -1.0]) + 1.0).abs() < 1e-9);
+        assert_eq!(cosine(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn weighted_mean_leans_toward_heavier_vectors() {
+        let mean = weighted_mean(&[(vec![1.0, 0.0], 3.0), (vec![0.0, 1.0], 1.0)]).unwrap();
+        assert_eq!(mean, vec![0.75, 0.25]);
+        assert!(weighted_mean(&[]).is_none());
+        assert!(weighted_mean(&[(vec![1.0], 0.0)]).is_none());
+    }
+}
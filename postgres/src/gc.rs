@@ -0,0 +1,91 @@
+/// Garbage collection of tombstoned nodes.
+///
+/// Deleting a node only sets its `deleted_at`, so that its history and the
+/// delete itself can still reach peers. `kerai.gc` removes the rows for
+/// good once they are older than the retention window and every peer has
+/// acknowledged the delete: each peer's `acked_vector` (the version vector
+/// in its last sync message) covers the `delete_node` op. A tombstone
+/// without an op of its own — a descendant removed by a cascade, or a node
+/// dropped by a re-parse — goes with the nearest tombstone above it.
+use pgrx::datum::Interval;
+use pgrx::prelude::*;
+use serde_json::json;
+
+/// Tombstones that may be purged: aged past the retention window, their
+/// delete acknowledged by every peer, reached from a tombstone whose parent
+/// is live, and with no node left below them that stays.
+const READY_SQL: &str = "
+    WITH RECURSIVE peers AS (
+        SELECT acked_vector FROM kerai.instances WHERE is_self = false
+    ), aged AS (
+        SELECT n.id, n.parent_id FROM kerai.nodes n
+        WHERE n.deleted_at <= now() - $1::interval
+          AND NOT EXISTS (
+              SELECT 1 FROM kerai.operations o, peers p
+              WHERE o.node_id = n.id AND o.op_type = 'delete_node'
+                AND COALESCE((p.acked_vector->>o.author)::bigint, 0) < o.author_seq)
+    ), ready AS (
+        SELECT a.id FROM aged a
+        LEFT JOIN kerai.nodes p ON p.id = a.parent_id
+        WHERE p.id IS NULL OR p.deleted_at IS NULL
+        UNION ALL
+        SELECT a.id FROM aged a JOIN ready r ON a.parent_id = r.id
+    ), blocked AS (
+        SELECT n.parent_id AS id FROM kerai.nodes n
+        WHERE n.parent_id IN (SELECT id FROM ready) AND n.id NOT IN (SELECT id FROM ready)
+        UNION
+        SELECT n.parent_id FROM kerai.nodes n JOIN blocked b ON b.id = n.id
+        WHERE n.parent_id IS NOT NULL
+    )
+    SELECT COALESCE(array_agg(id::text), '{}') FROM ready
+    WHERE id NOT IN (SELECT id FROM blocked)";
+
+/// Rows pointing at purged nodes: removed, or their reference cleared.
+const PURGE_SQL: [&str; 7] = [
+    "DELETE FROM kerai.edges WHERE source_id = ANY($1::uuid[]) OR target_id = ANY($1::uuid[])",
+    "DELETE FROM kerai.associations WHERE source_id = ANY($1::uuid[]) OR target_id = ANY($1::uuid[])",
+    "DELETE FROM kerai.perspectives WHERE node_id = ANY($1::uuid[]) OR context_id = ANY($1::uuid[])",
+    "DELETE FROM kerai.model_vocab WHERE node_id = ANY($1::uuid[])",
+    "UPDATE kerai.tasks SET scope_node_id = NULL WHERE scope_node_id = ANY($1::uuid[])",
+    "UPDATE kerai.repositories SET node_id = NULL WHERE node_id = ANY($1::uuid[])",
+    "DELETE FROM kerai.nodes WHERE id = ANY($1::uuid[])",
+];
+
+/// Purge tombstones older than `retention` whose deletes every peer has
+/// acknowledged. Returns the number purged.
+pub fn purge(retention: Interval) -> usize {
+    let ids = Spi::get_one_with_args::<Vec<String>>(READY_SQL, &[retention.into()])
+        .unwrap_or_else(|e| error!("Failed to find purgeable tombstones: {}", e))
+        .unwrap_or_default();
+    if ids.is_empty() {
+        return 0;
+    }
+    for sql in PURGE_SQL {
+        Spi::run_with_args(sql, &[ids.clone().into()])
+            .unwrap_or_else(|e| error!("Failed to purge tombstones: {}", e));
+    }
+    ids.len()
+}
+
+/// Remove tombstoned nodes deleted more than `retention` ago, once every
+/// peer has acknowledged their deletes. The gc worker runs this every
+/// `kerai.gc_interval` seconds with `kerai.gc_retention_days`.
+///
+/// Returns `{purged, held, retention}`, `held` counting the tombstones past
+/// the window that stay: a peer has yet to acknowledge their delete (or the
+/// one above them), or a live node still hangs below them.
+#[pg_extern]
+fn gc(retention: default!(Interval, "'30 days'")) -> pgrx::JsonB {
+    let purged = purge(retention);
+    let (held, retention) = Spi::get_two_with_args::<i64, String>(
+        "SELECT count(*), $1::interval::text FROM kerai.nodes
+         WHERE deleted_at <= now() - $1::interval",
+        &[retention.into()],
+    )
+    .unwrap_or_else(|e| error!("Failed to count held tombstones: {}", e));
+    pgrx::JsonB(json!({
+        "purged": purged,
+        "held": held.unwrap_or(0),
+        "retention": retention,
+    }))
+}
@@ -0,0 +1,207 @@
+/// Level-of-detail graphs for rendering large trees.
+///
+/// The nodes under a path are collapsed into clusters `lod` levels below
+/// it: a cluster is every node whose path starts with the cluster's path,
+/// and a node less deep than that is a cluster of its own. Edges with both
+/// ends under the path are lifted to their ends' clusters; edges inside a
+/// cluster are dropped and the rest summed per pair of clusters, with a
+/// count per relation. A cluster's path is the path of the follow-up call
+/// that expands it.
+///
+/// Graphs are cached in kerai.graph_cache per path, level, node cap and
+/// read filter (role, view and principal in effect). A cached graph is
+/// reused while the fingerprint it was computed at — row counts and latest
+/// timestamps of the subtree and of kerai.edges — still holds, which costs
+/// a scan of the subtree but none of the edge lifting.
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+/// Deepest level of detail a call may ask for.
+const MAX_LOD: i32 = 16;
+
+/// Most clusters a call may ask for.
+const MAX_NODES: i32 = 10_000;
+
+/// Cache rows not recomputed for this long are dropped when a graph is
+/// stored.
+const CACHE_RETENTION: &str = "1 day";
+
+/// Hash of what decides which rows a read sees, and the fingerprint of the
+/// rows under `path` and of the edges, as `{filter_key, fingerprint}`.
+fn cache_key(path: &str) -> Value {
+    Spi::get_one_with_args::<pgrx::JsonB>(
+        "SELECT jsonb_build_object(
+            'filter_key', md5(concat_ws('|', current_user,
+                current_setting('kerai.view_paths', true),
+                current_setting('kerai.view_kinds', true),
+                current_setting('kerai.current_principal', true))),
+            'fingerprint', concat_ws('|',
+                (SELECT concat_ws(',', count(*), max(created_at), max(modified_at))
+                 FROM kerai.nodes WHERE path <@ $1::ltree),
+                (SELECT concat_ws(',', count(*), max(created_at)) FROM kerai.edges)))",
+        &[path.into()],
+    )
+    .unwrap()
+    .map(|j| j.0)
+    .unwrap_or_default()
+}
+
+/// The graph under `path` at `lod` levels, at most `max_nodes` clusters,
+/// computed from the tables.
+fn compute(path: &str, lod: i32, max_nodes: i32) -> Value {
+    Spi::get_one_with_args::<pgrx::JsonB>(
+        "WITH scope AS (
+            SELECT n.id, n.kind, n.path,
+                   subpath(n.path, 0, least(nlevel(n.path), nlevel($1::ltree) + $2)) AS cluster
+            FROM kerai.nodes n
+            WHERE n.path <@ $1::ltree AND nlevel(n.path) > nlevel($1::ltree)
+              AND kerai.in_view(n.path, n.kind)
+        ), clusters AS (
+            SELECT cluster, count(*)::int AS size,
+                   max(nlevel(path)) - nlevel(cluster) AS depth
+            FROM scope GROUP BY cluster
+        ), kept AS (
+            SELECT * FROM clusters ORDER BY size DESC, cluster LIMIT $3
+        ), kind_counts AS (
+            SELECT cluster, jsonb_object_agg(kind, n) AS kinds
+            FROM (SELECT cluster, kind, count(*)::int AS n FROM scope GROUP BY 1, 2) k
+            GROUP BY cluster
+        ), lifted AS (
+            SELECT s.cluster AS source, t.cluster AS target, e.relation, count(*)::int AS weight
+            FROM kerai.edges e
+            JOIN scope s ON s.id = e.source_id
+            JOIN scope t ON t.id = e.target_id
+            WHERE s.cluster <> t.cluster
+            GROUP BY 1, 2, 3
+        ), pairs AS (
+            SELECT source, target, sum(weight)::int AS weight,
+                   jsonb_object_agg(relation, weight) AS relations
+            FROM lifted
+            WHERE source IN (SELECT cluster FROM kept) AND target IN (SELECT cluster FROM kept)
+            GROUP BY source, target
+        )
+        SELECT jsonb_build_object(
+            'path', $1,
+            'lod', $2,
+            'total_nodes', (SELECT count(*) FROM clusters),
+            'truncated', (SELECT count(*) FROM clusters) > $3,
+            'nodes', COALESCE((
+                SELECT jsonb_agg(jsonb_build_object(
+                    'key', k.cluster::text,
+                    'id', r.id,
+                    'kind', r.kind,
+                    'name', COALESCE(r.metadata->>'source_path', r.metadata->>'name',
+                                     left(r.content, 80), subpath(k.cluster, -1)::text),
+                    'size', k.size,
+                    'depth', k.depth,
+                    'expandable', k.depth > 0,
+                    'kinds', kc.kinds
+                ) ORDER BY k.cluster)
+                FROM kept k
+                JOIN kind_counts kc ON kc.cluster = k.cluster
+                LEFT JOIN LATERAL (
+                    SELECT n.id, n.kind, n.metadata, n.content
+                    FROM scope s JOIN kerai.nodes n ON n.id = s.id
+                    WHERE s.path = k.cluster
+                    ORDER BY n.id LIMIT 1
+                ) r ON true
+            ), '[]'::jsonb),
+            'edges', COALESCE((
+                SELECT jsonb_agg(jsonb_build_object(
+                    'source', source::text,
+                    'target', target::text,
+                    'weight', weight,
+                    'relations', relations
+                ) ORDER BY source, target)
+                FROM pairs
+            ), '[]'::jsonb))",
+        &[path.into(), lod.into(), max_nodes.into()],
+    )
+    .unwrap()
+    .map(|j| j.0)
+    .unwrap_or_default()
+}
+
+/// Level-of-detail graph of the nodes under `path` (`''` for everything),
+/// collapsed to clusters `lod` levels below it, keeping the `max_nodes`
+/// largest clusters.
+///
+/// Returns `{path, lod, total_nodes, truncated, nodes: [{key, id, kind,
+/// name, size, depth, kinds, expandable}], edges: [{source, target, weight,
+/// relations}], cached, computed_at}`. `key` is the cluster's path and
+/// `id` the node at it, if any; `size` counts the nodes in the cluster,
+/// `depth` how far below the key they reach and `kinds` them per kind.
+/// Edges join keys, `weight` summing the edges lifted into them and
+/// `relations` splitting it per relation. An expandable cluster is expanded
+/// with `graph_lod(key)`.
+#[pg_extern]
+fn graph_lod(
+    path: default!(&str, "''"),
+    lod: default!(i32, 1),
+    max_nodes: default!(i32, 500),
+) -> pgrx::JsonB {
+    let lod = lod.clamp(1, MAX_LOD);
+    let max_nodes = max_nodes.clamp(1, MAX_NODES);
+    let key = cache_key(path);
+    let filter_key = key["filter_key"].as_str().unwrap_or_default();
+    let fingerprint = key["fingerprint"].as_str().unwrap_or_default();
+
+    let cached = Spi::get_one_with_args::<pgrx::JsonB>(
+        "SELECT graph || jsonb_build_object('cached', true, 'computed_at', computed_at)
+         FROM kerai.graph_cache
+         WHERE root = $1 AND lod = $2 AND max_nodes = $3
+           AND filter_key = $4 AND fingerprint = $5",
+        &[
+            path.into(),
+            lod.into(),
+            max_nodes.into(),
+            filter_key.into(),
+            fingerprint.into(),
+        ],
+    )
+    .unwrap();
+    if let Some(graph) = cached {
+        return graph;
+    }
+
+    let graph = compute(path, lod, max_nodes);
+    Spi::run(&format!(
+        "DELETE FROM kerai.graph_cache WHERE computed_at < now() - interval '{CACHE_RETENTION}'"
+    ))
+    .unwrap();
+    let computed_at = Spi::get_one_with_args::<pgrx::JsonB>(
+        "INSERT INTO kerai.graph_cache (root, lod, max_nodes, filter_key, fingerprint, graph)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (root, lod, max_nodes, filter_key) DO UPDATE
+         SET fingerprint = EXCLUDED.fingerprint, graph = EXCLUDED.graph,
+             computed_at = EXCLUDED.computed_at
+         RETURNING to_jsonb(computed_at)",
+        &[
+            path.into(),
+            lod.into(),
+            max_nodes.into(),
+            filter_key.into(),
+            fingerprint.into(),
+            pgrx::JsonB(graph.clone()).into(),
+        ],
+    )
+    .unwrap()
+    .map(|j| j.0)
+    .unwrap_or(Value::Null);
+
+    let mut graph = graph;
+    graph["cached"] = json!(false);
+    graph["computed_at"] = computed_at;
+    pgrx::JsonB(graph)
+}
+
+/// Drop every cached graph. Returns how many there were.
+#[pg_extern]
+fn clear_graph_cache() -> i64 {
+    Spi::get_one::<i64>(
+        "WITH cleared AS (DELETE FROM kerai.graph_cache RETURNING 1)
+         SELECT count(*) FROM cleared",
+    )
+    .unwrap()
+    .unwrap_or(0)
+}
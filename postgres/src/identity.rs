@@ -83,3 +83,16 @@ pub fn verify_signature(verifying_key: &VerifyingKey, data: &[u8], signature: &[
     };
     verifying_key.verify(data, &sig).is_ok()
 }
+
+/// Canonical bytes a kerai.versions row signature covers: `"version|"`
+/// followed by the row's signed columns as a JSON object. serde_json sorts
+/// object keys, so the bytes do not depend on how the row was read back.
+pub fn version_signable(row: &serde_json::Value) -> Vec<u8> {
+    format!("version|{}", row).into_bytes()
+}
+
+/// Parse a 32-byte Ed25519 public key.
+pub fn verifying_key_from_bytes(bytes: &[u8]) -> Option<VerifyingKey> {
+    let key_bytes: [u8; 32] = bytes.try_into().ok()?;
+    VerifyingKey::from_bytes(&key_bytes).ok()
+}
@@ -0,0 +1,58 @@
+/// Time-ordered row ids.
+///
+/// New rows get UUIDv7 ids: Unix milliseconds in the top 48 bits, then
+/// sub-millisecond time (or, from Rust, a counter) and random bits. Rust
+/// makes them with `Uuid::now_v7()`, column defaults and SQL with
+/// `kerai.uuid7()`. Ids made one after another sort one after another, so
+/// inserts append at the right edge of a primary key index, where random
+/// v4 ids land on any leaf page and leave it half full when it splits.
+/// Ids from before the switch stay v4; both kinds live in the same uuid
+/// columns and nothing reads the version.
+use std::time::Instant;
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+/// Insert `rows` ids into a temporary table's primary key, returning the
+/// index size and insert time.
+fn index_run(table: &str, id: &str, rows: i32) -> Value {
+    Spi::run(&format!("CREATE TEMP TABLE {table} (id uuid PRIMARY KEY)")).unwrap();
+    let start = Instant::now();
+    Spi::run(&format!(
+        "INSERT INTO {table} SELECT {id} FROM generate_series(1, {rows})"
+    ))
+    .unwrap();
+    let elapsed = start.elapsed();
+    let bytes = Spi::get_one::<i64>(&format!("SELECT pg_relation_size('{table}_pkey')"))
+        .unwrap()
+        .unwrap_or(0);
+    Spi::run(&format!("DROP TABLE {table}")).unwrap();
+    json!({"index_bytes": bytes, "elapsed_ms": elapsed.as_millis() as u64})
+}
+
+/// Measure what time-ordered ids save: `rows` inserts into a fresh primary
+/// key index with random v4 ids, then the same with `kerai.uuid7()`.
+///
+/// Returns `{rows, v4: {index_bytes, elapsed_ms}, v7: {...},
+/// index_saving_pct}`, the last being how much smaller the v7 index is.
+#[pg_extern]
+fn benchmark_uuid_index(rows: default!(i32, 10000)) -> pgrx::JsonB {
+    if rows < 1 {
+        error!("rows must be positive, got {}", rows);
+    }
+    let v4 = index_run("kerai_bench_uuid_v4", "gen_random_uuid()", rows);
+    let v7 = index_run("kerai_bench_uuid_v7", "kerai.uuid7()", rows);
+
+    let bytes = |run: &Value| run["index_bytes"].as_f64().unwrap_or(0.0);
+    let saving = if bytes(&v4) > 0.0 {
+        (1.0 - bytes(&v7) / bytes(&v4)) * 100.0
+    } else {
+        0.0
+    };
+    pgrx::JsonB(json!({
+        "rows": rows,
+        "v4": v4,
+        "v7": v7,
+        "index_saving_pct": (saving * 10.0).round() / 10.0,
+    }))
+}
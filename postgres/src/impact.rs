@@ -0,0 +1,332 @@
+/// Impact analysis — what a change to a node can break elsewhere.
+///
+/// Walks dependency edges backwards from the changed nodes: anything that
+/// `calls`, `imports`, `references`, `transcludes` or `mentions` a changed
+/// node is affected, then whatever depends on that, up to `max_depth` hops.
+/// Confidence starts at 1 and is multiplied by each hop's relation weight,
+/// so a transclusion (content pulled in verbatim) propagates more surely
+/// than a passing reference. Affected nodes are rolled up to the file or
+/// document containing them.
+use std::collections::{HashMap, HashSet};
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::sql::sql_escape;
+
+/// Relations followed, and how surely a change propagates across each.
+const RELATIONS: &[(&str, f64)] = &[
+    ("transcludes", 1.0),
+    ("calls", 0.9),
+    ("imports", 0.7),
+    ("references", 0.6),
+    ("mentions", 0.5),
+];
+
+/// Kinds that contain, rather than make up, a changed node; the walk up
+/// from a change stops below them.
+const CONTAINERS: &[&str] = &["file", "document", "crate", "module", "repository"];
+
+pub fn relation_weight(relation: &str) -> f64 {
+    RELATIONS
+        .iter()
+        .find(|(r, _)| *r == relation)
+        .map_or(0.0, |(_, w)| *w)
+}
+
+/// How an affected node was reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub distance: u32,
+    pub confidence: f64,
+    /// Relation of the last hop into this node.
+    pub via: String,
+}
+
+/// Breadth-first walk from `seeds` against dependency direction.
+/// `incoming(frontier)` returns `(source, target, relation)` edges whose
+/// target is in the frontier. Each node keeps its shortest distance and
+/// its best confidence over all paths found within `max_depth`.
+pub fn traverse(
+    seeds: &[String],
+    max_depth: u32,
+    mut incoming: impl FnMut(&[String]) -> Vec<(String, String, String)>,
+) -> HashMap<String, Hit> {
+    let seed_set: HashSet<&String> = seeds.iter().collect();
+    let mut confidence: HashMap<String, f64> = seeds.iter().map(|s| (s.clone(), 1.0)).collect();
+    let mut hits: HashMap<String, Hit> = HashMap::new();
+    let mut frontier: Vec<String> = seeds.to_vec();
+
+    for depth in 1..=max_depth {
+        if frontier.is_empty() {
+            break;
+        }
+        let mut next = Vec::new();
+        for (source, target, relation) in incoming(&frontier) {
+            if seed_set.contains(&source) {
+                continue;
+            }
+            let reached = confidence.get(&target).copied().unwrap_or(0.0) * relation_weight(&relation);
+            match hits.get_mut(&source) {
+                Some(hit) => {
+                    if reached > hit.confidence {
+                        hit.confidence = reached;
+                        hit.via = relation;
+                    }
+                }
+                None => {
+                    hits.insert(
+                        source.clone(),
+                        Hit {
+                            distance: depth,
+                            confidence: reached,
+                            via: relation,
+                        },
+                    );
+                    next.push(source.clone());
+                }
+            }
+            let best = confidence.entry(source).or_insert(0.0);
+            *best = best.max(reached);
+        }
+        frontier = next;
+    }
+    hits
+}
+
+fn uuid_array(ids: &[String]) -> String {
+    let quoted: Vec<String> = ids.iter().map(|id| format!("'{}'", sql_escape(id))).collect();
+    format!("ARRAY[{}]::uuid[]", quoted.join(", "))
+}
+
+/// The changed nodes themselves, everything below them, and the enclosing
+/// items above them short of their file.
+fn seed_nodes(ids: &[String]) -> Vec<String> {
+    let containers: Vec<String> = CONTAINERS.iter().map(|k| format!("'{k}'")).collect();
+    Spi::get_one::<Vec<String>>(&format!(
+        "WITH RECURSIVE down AS (
+            SELECT id FROM kerai.nodes WHERE id = ANY({ids})
+            UNION
+            SELECT n.id FROM kerai.nodes n JOIN down ON n.parent_id = down.id
+        ), up AS (
+            SELECT id, parent_id, kind FROM kerai.nodes WHERE id = ANY({ids})
+            UNION
+            SELECT n.id, n.parent_id, n.kind FROM kerai.nodes n
+            JOIN up ON n.id = up.parent_id
+            WHERE up.kind NOT IN ({containers})
+        )
+        SELECT array_agg(DISTINCT id::text) FROM (
+            SELECT id FROM down
+            UNION SELECT id FROM up WHERE kind NOT IN ({containers})
+        ) s",
+        ids = uuid_array(ids),
+        containers = containers.join(", "),
+    ))
+    .unwrap()
+    .unwrap_or_default()
+}
+
+fn incoming_edges(frontier: &[String]) -> Vec<(String, String, String)> {
+    let relations: Vec<String> = RELATIONS.iter().map(|(r, _)| format!("'{r}'")).collect();
+    let mut edges = Vec::new();
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                &format!(
+                    "SELECT source_id::text, target_id::text, relation FROM kerai.edges
+                     WHERE target_id = ANY({}) AND relation IN ({})",
+                    uuid_array(frontier),
+                    relations.join(", "),
+                ),
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            edges.push((
+                row.get::<String>(1).unwrap().unwrap_or_default(),
+                row.get::<String>(2).unwrap().unwrap_or_default(),
+                row.get::<String>(3).unwrap().unwrap_or_default(),
+            ));
+        }
+    });
+    edges
+}
+
+/// Kind, name and containing file/document of each affected node.
+fn describe(ids: &[String]) -> HashMap<String, Value> {
+    if ids.is_empty() {
+        return HashMap::new();
+    }
+    let rows = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', n.id,
+            'kind', n.kind,
+            'name', COALESCE(n.metadata->>'name', n.content),
+            'path', n.path::text,
+            'file_id', f.id,
+            'file', COALESCE(f.metadata->>'source_path', f.content),
+            'file_kind', f.kind
+        )), '[]'::jsonb)
+        FROM kerai.nodes n
+        LEFT JOIN LATERAL (
+            WITH RECURSIVE up AS (
+                SELECT id, parent_id, kind, content, metadata, 0 AS depth
+                FROM kerai.nodes WHERE id = n.id
+                UNION ALL
+                SELECT p.id, p.parent_id, p.kind, p.content, p.metadata, up.depth + 1
+                FROM kerai.nodes p JOIN up ON p.id = up.parent_id
+            )
+            SELECT id, kind, content, metadata FROM up
+            WHERE kind IN ('file', 'document') ORDER BY depth LIMIT 1
+        ) f ON true
+        WHERE n.id = ANY({})",
+        uuid_array(ids),
+    ))
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+
+    rows.as_array()
+        .cloned()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|r| Some((r["id"].as_str()?.to_string(), r)))
+        .collect()
+}
+
+/// Impact of changing `node_ids`: `{changed, max_depth, affected, files}`.
+fn analyze(node_ids: &[String], max_depth: i32) -> Value {
+    let max_depth = max_depth.clamp(1, 10) as u32;
+    let seeds = seed_nodes(node_ids);
+    let hits = traverse(&seeds, max_depth, incoming_edges);
+
+    let ids: Vec<String> = hits.keys().cloned().collect();
+    let info = describe(&ids);
+    let origin_files: HashSet<String> = describe(node_ids)
+        .values()
+        .filter_map(|v| v["file_id"].as_str().map(str::to_string))
+        .collect();
+
+    let mut affected: Vec<Value> = hits
+        .iter()
+        .filter_map(|(id, hit)| {
+            let mut entry = info.get(id)?.clone();
+            entry["distance"] = json!(hit.distance);
+            entry["confidence"] = json!((hit.confidence * 1000.0).round() / 1000.0);
+            entry["via"] = json!(hit.via);
+            Some(entry)
+        })
+        .collect();
+    affected.sort_by(|a, b| {
+        a["distance"]
+            .as_u64()
+            .cmp(&b["distance"].as_u64())
+            .then(b["confidence"].as_f64().unwrap_or(0.0).total_cmp(&a["confidence"].as_f64().unwrap_or(0.0)))
+    });
+
+    // Roll up by containing file or document
+    let mut files: Vec<Value> = Vec::new();
+    for entry in &affected {
+        let file_id = entry["file_id"].clone();
+        match files.iter_mut().find(|f| f["file_id"] == file_id) {
+            Some(f) => {
+                f["nodes"] = json!(f["nodes"].as_u64().unwrap_or(0) + 1);
+                if entry["confidence"].as_f64() > f["confidence"].as_f64() {
+                    f["confidence"] = entry["confidence"].clone();
+                }
+            }
+            None => files.push(json!({
+                "file_id": file_id,
+                "file": entry["file"],
+                "kind": entry["file_kind"],
+                "cross_file": !entry["file_id"].as_str().is_some_and(|f| origin_files.contains(f)),
+                "distance": entry["distance"],
+                "confidence": entry["confidence"],
+                "nodes": 1,
+            })),
+        }
+    }
+
+    json!({
+        "changed": node_ids,
+        "max_depth": max_depth,
+        "affected": affected,
+        "files": files,
+    })
+}
+
+/// Blast radius of changing `node_id`: nodes that depend on it (or on
+/// anything inside it) through calls, imports, references or
+/// transclusions, within `max_depth` hops.
+///
+/// Returns `{changed, max_depth, affected: [{id, kind, name, path, file_id,
+/// file, file_kind, distance, confidence, via}], files: [{file_id, file,
+/// kind, cross_file, distance, confidence, nodes}]}`, nearest first.
+#[pg_extern]
+fn impact(node_id: pgrx::Uuid, max_depth: default!(i32, 3)) -> pgrx::JsonB {
+    pgrx::JsonB(analyze(&[node_id.to_string()], max_depth))
+}
+
+/// Combined blast radius of a set of changed nodes, as for `impact`.
+#[pg_extern]
+fn impact_all(node_ids: Vec<pgrx::Uuid>, max_depth: default!(i32, 3)) -> pgrx::JsonB {
+    let ids: Vec<String> = node_ids.iter().map(|id| id.to_string()).collect();
+    if ids.is_empty() {
+        return pgrx::JsonB(json!({
+            "changed": [],
+            "max_depth": max_depth,
+            "affected": [],
+            "files": [],
+        }));
+    }
+    pgrx::JsonB(analyze(&ids, max_depth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Edge<'a> = (&'a str, &'a str, &'a str);
+
+    fn graph<'a>(edges: &'a [Edge<'a>]) -> impl FnMut(&[String]) -> Vec<(String, String, String)> + 'a {
+        move |frontier: &[String]| {
+            edges
+                .iter()
+                .filter(|(_, t, _)| frontier.iter().any(|f| f == t))
+                .map(|(s, t, r)| (s.to_string(), t.to_string(), r.to_string()))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn walks_reverse_edges_with_decaying_confidence() {
+        let edges = [
+            ("caller", "target", "calls"),
+            ("importer", "caller", "imports"),
+            ("far", "importer", "references"),
+        ];
+        let hits = traverse(&["target".to_string()], 2, graph(&edges));
+        assert_eq!(hits.len(), 2, "depth 2 stops before 'far'");
+        assert_eq!(hits["caller"].distance, 1);
+        assert!((hits["caller"].confidence - 0.9).abs() < 1e-9);
+        assert_eq!(hits["importer"].distance, 2);
+        assert!((hits["importer"].confidence - 0.63).abs() < 1e-9);
+        assert_eq!(hits["importer"].via, "imports");
+    }
+
+    #[test]
+    fn keeps_best_confidence_and_skips_seeds_and_cycles() {
+        let edges = [
+            ("a", "seed", "references"),
+            ("a", "seed", "transcludes"),
+            ("seed", "a", "calls"),
+            ("b", "a", "calls"),
+            ("a", "b", "calls"),
+        ];
+        let hits = traverse(&["seed".to_string()], 5, graph(&edges));
+        assert!(!hits.contains_key("seed"));
+        assert_eq!(hits["a"].confidence, 1.0);
+        assert_eq!(hits["a"].via, "transcludes");
+        assert_eq!(hits["b"].distance, 2);
+    }
+}
@@ -0,0 +1,340 @@
+/// Long-running operations: job records, progress and cancellation.
+///
+/// A client starting something long (an import, a sync) records it with
+/// `job_start`, which notes the backend running it, and reports each step
+/// through `job_progress`; both commit on their own, so `list_jobs` in
+/// another session shows how far it has got. Inside a single long
+/// statement, `report` streams progress to the caller as `kerai:progress`
+/// notices instead, since rows it wrote would only be visible at commit.
+///
+/// `cancel_job` flags the job and cancels its backend's current statement.
+/// `report` and `job_progress` both honour the flag, so a job stops at its
+/// next step even between statements.
+///
+/// Workers keep `heartbeat_at` fresh through `job_progress` or
+/// `job_heartbeat`. `reap_jobs` fails running jobs whose backend has gone,
+/// or whose heartbeat is stale while the backend sits idle, and puts them
+/// back in the queue with exponential backoff while attempts remain;
+/// `claim_job` hands a queued job to the next worker of its kind.
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use pgrx::prelude::*;
+use serde_json::json;
+
+use crate::sql::{sql_escape, sql_jsonb, sql_text};
+
+/// Statuses a job can finish with.
+const FINISHED: &[&str] = &["done", "cancelled", "failed"];
+
+/// Least time between two progress notices, so tight loops stay quiet.
+const NOTICE_INTERVAL: Duration = Duration::from_millis(100);
+
+thread_local! {
+    static LAST_NOTICE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Whether a cancel has been requested for the job this backend runs.
+fn cancel_requested() -> bool {
+    Spi::get_one::<bool>(
+        "SELECT EXISTS (
+            SELECT 1 FROM kerai.jobs
+            WHERE pid = pg_backend_pid() AND status = 'running' AND cancel_requested
+        )",
+    )
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Progress notice for step `done` of `total`, throttled except for the
+/// last step. Checks for cancellation either way.
+pub(crate) fn report(done: usize, total: usize, current: &str) {
+    pgrx::check_for_interrupts!();
+    let now = Instant::now();
+    let due = done >= total
+        || LAST_NOTICE.with(|last| last.get().is_none_or(|t| now - t >= NOTICE_INTERVAL));
+    if !due {
+        return;
+    }
+    LAST_NOTICE.with(|last| last.set(Some(now)));
+    if cancel_requested() {
+        error!("Job cancelled at {} of {}", done, total);
+    }
+    notice!(
+        "kerai:progress {}",
+        json!({ "done": done, "total": total, "current": current })
+    );
+}
+
+/// Record the start of a job run by this backend. `total` is the number
+/// of steps, when known; a job reaped before `max_attempts` runs is
+/// queued again, with `args` telling the worker that claims it what to do.
+/// Returns `{id, kind, pid}`.
+#[pg_extern]
+fn job_start(
+    kind: &str,
+    total: default!(Option<i32>, "NULL"),
+    max_attempts: default!(i32, 1),
+    args: default!(Option<pgrx::JsonB>, "NULL"),
+) -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "INSERT INTO kerai.jobs (kind, pid, total, max_attempts, args)
+         VALUES ({}, pg_backend_pid(), {}, {}, {})
+         RETURNING jsonb_build_object('id', id, 'kind', kind, 'pid', pid)",
+        sql_text(kind),
+        total.map_or("NULL".to_string(), |t| t.to_string()),
+        max_attempts.max(1),
+        args.map_or("NULL".to_string(), |a| sql_jsonb(&a.0)),
+    ))
+    .unwrap()
+    .unwrap()
+}
+
+/// Record that `done` steps of a running job are finished, `current`
+/// being the one under way. Returns `{cancel_requested}` so the client can
+/// stop when the job was cancelled from elsewhere.
+#[pg_extern]
+fn job_progress(
+    job_id: pgrx::Uuid,
+    done: i32,
+    current: default!(Option<&str>, "NULL"),
+    total: default!(Option<i32>, "NULL"),
+) -> pgrx::JsonB {
+    let cancel = Spi::get_one::<bool>(&format!(
+        "UPDATE kerai.jobs
+         SET done = {done}, current = {current}, total = COALESCE({total}, total),
+             updated_at = now(), heartbeat_at = now()
+         WHERE id = '{job_id}'::uuid AND status = 'running'
+         RETURNING cancel_requested",
+        current = current.map_or("NULL".to_string(), sql_text),
+        total = total.map_or("NULL".to_string(), |t| t.to_string()),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("No running job {}", job_id));
+    pgrx::JsonB(json!({ "cancel_requested": cancel }))
+}
+
+/// Note that the worker running a job is still alive, for steps too long
+/// to report progress between. Returns `{cancel_requested}`.
+#[pg_extern]
+fn job_heartbeat(job_id: pgrx::Uuid) -> pgrx::JsonB {
+    let cancel = Spi::get_one::<bool>(&format!(
+        "UPDATE kerai.jobs SET heartbeat_at = now()
+         WHERE id = '{job_id}'::uuid AND status = 'running'
+         RETURNING cancel_requested"
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("No running job {}", job_id));
+    pgrx::JsonB(json!({ "cancel_requested": cancel }))
+}
+
+/// Take the oldest queued job of `kind` that is due, running it on this
+/// backend as its next attempt. Returns the job, or null when none is
+/// waiting. Workers claiming at once each get a different job.
+#[pg_extern]
+fn claim_job(kind: &str) -> Option<pgrx::JsonB> {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "UPDATE kerai.jobs
+         SET status = 'running', pid = pg_backend_pid(), attempts = attempts + 1,
+             done = 0, current = NULL, run_after = NULL,
+             updated_at = now(), heartbeat_at = now()
+         WHERE id = (
+             SELECT id FROM kerai.jobs
+             WHERE status = 'queued' AND kind = {}
+               AND COALESCE(run_after, now()) <= now()
+             ORDER BY run_after NULLS FIRST, started_at
+             LIMIT 1
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING to_jsonb(jobs.*)",
+        sql_text(kind),
+    ))
+    .unwrap()
+}
+
+/// Mark a job finished with `status` (done, cancelled or failed) and its
+/// result, partial when cancelled.
+#[pg_extern]
+fn job_finish(
+    job_id: pgrx::Uuid,
+    status: &str,
+    result: default!(Option<pgrx::JsonB>, "NULL"),
+) -> pgrx::JsonB {
+    if !FINISHED.contains(&status) {
+        error!("Job status must be one of {}", FINISHED.join(", "));
+    }
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "UPDATE kerai.jobs
+         SET status = {status}, result = {result}, updated_at = now(), finished_at = now()
+         WHERE id = '{job_id}'::uuid AND status = 'running'
+         RETURNING to_jsonb(jobs.*)",
+        status = sql_text(status),
+        result = result.map_or("NULL".to_string(), |r| sql_jsonb(&r.0)),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("No running job {}", job_id))
+}
+
+/// Ask a running job, given by id or id prefix, to stop: flag it and
+/// cancel the statement its backend is running. A queued job is simply
+/// cancelled. Returns `{id, kind, pid, signalled}`; `signalled` is false
+/// when there was no other backend to signal.
+#[pg_extern]
+fn cancel_job(job_id: &str) -> pgrx::JsonB {
+    let matching = format!(
+        "id::text LIKE '{}%' AND status IN ('running', 'queued')",
+        sql_escape(job_id)
+    );
+    match Spi::get_one::<i64>(&format!("SELECT count(*) FROM kerai.jobs WHERE {matching}"))
+        .unwrap()
+        .unwrap_or(0)
+    {
+        0 => error!("No running or queued job matching '{}'", job_id),
+        1 => {}
+        _ => error!("'{}' matches more than one job", job_id),
+    }
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "WITH flagged AS (
+            UPDATE kerai.jobs SET
+                cancel_requested = true,
+                status = CASE WHEN status = 'queued' THEN 'cancelled' ELSE status END,
+                finished_at = CASE WHEN status = 'queued' THEN now() END,
+                updated_at = now()
+            WHERE {matching}
+            RETURNING id, kind, pid, status
+        )
+        SELECT jsonb_build_object(
+            'id', id,
+            'kind', kind,
+            'pid', pid,
+            'signalled', status = 'running' AND pid <> pg_backend_pid()
+                AND COALESCE(pg_cancel_backend(pid), false)
+        )
+        FROM flagged"
+    ))
+    .unwrap()
+    .unwrap()
+}
+
+/// Recent jobs, running ones first: `[{id, kind, status, pid, done, total,
+/// current, cancel_requested, started_at, updated_at, finished_at,
+/// result}]`. Jobs whose backend has exited without finishing show as
+/// `failed`.
+#[pg_extern]
+fn list_jobs(limit: default!(i32, 20)) -> pgrx::JsonB {
+    let jobs = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(to_jsonb(j.*) - 'status' || jsonb_build_object(
+            'status', CASE
+                WHEN j.status = 'running' AND NOT EXISTS (
+                    SELECT 1 FROM pg_stat_activity a WHERE a.pid = j.pid
+                ) THEN 'failed'
+                ELSE j.status END
+        ) ORDER BY j.status <> 'running', j.started_at DESC), '[]'::jsonb)
+        FROM (
+            SELECT * FROM kerai.jobs ORDER BY status <> 'running', started_at DESC LIMIT {}
+        ) j",
+        limit.max(1),
+    ))
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+    pgrx::JsonB(jobs)
+}
+
+/// Fail running jobs whose worker has died: its backend has gone, or its
+/// heartbeat is older than `stale_secs` while the backend is not running
+/// a statement (a long statement cannot beat, since its writes only show
+/// at commit). With `requeue`, a job with attempts left is queued again,
+/// due after `backoff_secs` doubled for each attempt made. Returns
+/// `{failed: [{id, kind, attempts}], requeued: [{id, kind, attempts,
+/// run_after}]}`.
+#[pg_extern]
+fn reap_jobs(
+    stale_secs: default!(i32, 300),
+    requeue: default!(bool, true),
+    backoff_secs: default!(i32, 30),
+) -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "WITH dead AS (
+            SELECT j.id, {requeue} AND j.attempts < j.max_attempts AS retry
+            FROM kerai.jobs j
+            LEFT JOIN pg_stat_activity a ON a.pid = j.pid
+            WHERE j.status = 'running'
+              AND (a.pid IS NULL OR (
+                  j.heartbeat_at < now() - make_interval(secs => {stale})
+                  AND (a.state IS DISTINCT FROM 'active' OR a.pid = pg_backend_pid())
+              ))
+            FOR UPDATE OF j SKIP LOCKED
+        ),
+        reaped AS (
+            UPDATE kerai.jobs j SET
+                status = CASE WHEN d.retry THEN 'queued' ELSE 'failed' END,
+                run_after = CASE WHEN d.retry
+                    THEN now() + make_interval(secs => {backoff} * power(2, j.attempts - 1))
+                END,
+                finished_at = CASE WHEN d.retry THEN NULL ELSE now() END,
+                result = jsonb_build_object(
+                    'error', 'worker lost',
+                    'attempt', j.attempts,
+                    'heartbeat_at', j.heartbeat_at
+                ),
+                updated_at = now()
+            FROM dead d
+            WHERE j.id = d.id
+            RETURNING j.id, j.kind, j.status, j.attempts, j.run_after
+        )
+        SELECT jsonb_build_object(
+            'failed', COALESCE(jsonb_agg(jsonb_build_object(
+                'id', id, 'kind', kind, 'attempts', attempts
+            )) FILTER (WHERE status = 'failed'), '[]'::jsonb),
+            'requeued', COALESCE(jsonb_agg(jsonb_build_object(
+                'id', id, 'kind', kind, 'attempts', attempts, 'run_after', run_after
+            )) FILTER (WHERE status = 'queued'), '[]'::jsonb)
+        )
+        FROM reaped",
+        requeue = requeue,
+        stale = stale_secs.max(0),
+        backoff = backoff_secs.max(0),
+    ))
+    .unwrap()
+    .unwrap()
+}
+
+/// Throughput and failure rates per job kind over the last `hours`, for
+/// the dashboard: `[{kind, runs, running, queued, done, failed, cancelled,
+/// retries, failure_rate, done_per_hour, avg_seconds, last_finished_at}]`.
+/// A run counts when it is unfinished or finished within the window;
+/// `failure_rate` is failed over done plus failed, and `avg_seconds` the
+/// mean time from first start to finish of the runs that succeeded.
+#[pg_extern]
+fn jobs_summary(hours: default!(i32, 24)) -> pgrx::JsonB {
+    let hours = hours.max(1);
+    let summary = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(to_jsonb(s.*) ORDER BY s.kind), '[]'::jsonb)
+        FROM (
+            SELECT
+                kind,
+                count(*) AS runs,
+                count(*) FILTER (WHERE status = 'running') AS running,
+                count(*) FILTER (WHERE status = 'queued') AS queued,
+                count(*) FILTER (WHERE status = 'done') AS done,
+                count(*) FILTER (WHERE status = 'failed') AS failed,
+                count(*) FILTER (WHERE status = 'cancelled') AS cancelled,
+                sum(attempts - 1) AS retries,
+                round(
+                    count(*) FILTER (WHERE status = 'failed')::numeric
+                        / NULLIF(count(*) FILTER (WHERE status IN ('done', 'failed')), 0),
+                    3
+                ) AS failure_rate,
+                round(count(*) FILTER (WHERE status = 'done')::numeric / {hours}, 2) AS done_per_hour,
+                round(avg(extract(epoch FROM finished_at - started_at))
+                    FILTER (WHERE status = 'done'), 1) AS avg_seconds,
+                max(finished_at) AS last_finished_at
+            FROM kerai.jobs
+            WHERE COALESCE(finished_at, now()) > now() - make_interval(hours => {hours})
+            GROUP BY kind
+        ) s"
+    ))
+    .unwrap()
+    .map_or(json!([]), |s| s.0);
+    pgrx::JsonB(summary)
+}
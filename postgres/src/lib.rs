@@ -1,36 +1,70 @@
 pgrx::pg_module_magic!();
 
+mod advisor;
 mod agents;
 mod bootstrap;
 mod bounties;
+mod branches;
+mod changesets;
 mod consensus;
 mod crawler;
 mod crdt;
 mod currency;
+mod dead_code;
+mod dependencies;
 mod economy;
+mod economy_report;
+mod edges;
+mod embeddings;
 mod functions;
+mod gc;
+mod graph;
 mod identity;
+mod ids;
+mod impact;
 mod init;
+mod jobs;
 mod marketplace;
+mod merkle;
 mod microgpt;
+mod notifications;
+mod outline;
 pub(crate) mod parser;
+mod partitions;
 mod peers;
 mod preferences;
+mod public_api;
 mod repo;
 mod perspectives;
 mod query;
 mod reconstruct;
+mod replace;
+mod revert;
+mod rls;
+mod rules;
+mod sandboxes;
 mod schema;
+mod seed;
+mod simulation;
+mod snapshots;
 pub mod sql;
 mod stack;
+mod staleness;
+mod subscriptions;
+mod summaries;
 mod swarm;
 mod workspace;
 mod tasks;
+mod timeline;
+mod views;
 mod workers;
 mod zkp;
 
 #[pgrx::pg_guard]
 pub extern "C-unwind" fn _PG_init() {
+    changesets::register_gucs();
+    rls::register_gucs();
+    views::register_gucs();
     workers::register_workers();
 }
 
@@ -164,6 +198,92 @@ mod tests {
         assert_eq!(rel, "calls");
     }
 
+    /// Route the change event triggers' `pg_notify` calls into a table for
+    /// the rest of the transaction, since notifications are only delivered
+    /// on commit. Returns the query for the captured events, oldest first.
+    fn capture_change_events() -> &'static str {
+        Spi::run(
+            "CREATE TABLE kerai.captured_events (seq serial, channel text, payload jsonb);
+             CREATE FUNCTION kerai.pg_notify(channel text, payload text) RETURNS void
+                 LANGUAGE sql AS 'INSERT INTO kerai.captured_events (channel, payload)
+                                  VALUES (channel, payload::jsonb)';
+             SET LOCAL search_path = kerai, pg_catalog, public",
+        )
+        .unwrap();
+        "SELECT COALESCE(jsonb_agg(payload || jsonb_build_object('channel', channel)
+                                   ORDER BY seq), '[]'::jsonb)
+         FROM kerai.captured_events"
+    }
+
+    #[pg_test]
+    fn test_change_events_from_triggers() {
+        let captured = capture_change_events();
+        let id = Spi::get_one::<String>(
+            "INSERT INTO kerai.nodes (instance_id, kind, content, position, path)
+             SELECT id, 'paragraph', 'watched', 0, 'chev.a'::ltree
+             FROM kerai.instances WHERE is_self = true
+             RETURNING id::text",
+        )
+        .unwrap()
+        .unwrap();
+        Spi::run("UPDATE kerai.nodes SET path = 'chev.b' WHERE content = 'watched'").unwrap();
+        Spi::run(
+            "INSERT INTO kerai.edges (source_id, target_id, relation)
+             SELECT id, id, 'references' FROM kerai.nodes WHERE content = 'watched'",
+        )
+        .unwrap();
+        Spi::run(
+            "INSERT INTO kerai.versions (node_id, instance_id, operation, author, timestamp)
+             SELECT id, instance_id, 'update_content', 'chev', 1
+             FROM kerai.nodes WHERE content = 'watched'",
+        )
+        .unwrap();
+        // Switched off, e.g. for bulk imports
+        Spi::run("SET LOCAL kerai.change_events = off").unwrap();
+        Spi::run("UPDATE kerai.nodes SET content = 'quiet' WHERE content = 'watched'").unwrap();
+        Spi::run("SET LOCAL kerai.change_events = on").unwrap();
+        Spi::run("DELETE FROM kerai.edges WHERE relation = 'references'").unwrap();
+
+        let events = Spi::get_one::<pgrx::JsonB>(captured).unwrap().unwrap().0;
+        let events: Vec<&serde_json::Value> = events
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|e| {
+                [&e["id"], &e["source_id"], &e["node_id"]]
+                    .iter()
+                    .any(|v| *v == id.as_str())
+            })
+            .collect();
+        let summary: Vec<_> = events
+            .iter()
+            .map(|e| {
+                (
+                    e["channel"].as_str().unwrap(),
+                    e["op"].as_str().unwrap(),
+                    e["kind"].as_str().unwrap(),
+                    e["path"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("kerai_nodes", "insert", "paragraph", "chev.a"),
+                ("kerai_nodes", "update", "paragraph", "chev.b"),
+                ("kerai_edges", "insert", "references", "chev.b"),
+                ("kerai_versions", "insert", "paragraph", "chev.b"),
+                ("kerai_edges", "delete", "references", "chev.b"),
+            ]
+        );
+        assert_eq!(events[0]["table"], "nodes");
+        assert!(events[0]["instance"].is_string());
+        assert!(events[0].get("old_path").is_none());
+        assert_eq!(events[1]["old_path"], "chev.a");
+        assert_eq!(events[2]["target_id"], id.as_str());
+        assert_eq!(events[3]["operation"], "update_content");
+    }
+
     #[pg_test]
     fn test_insert_version() {
         Spi::run(
@@ -415,6 +535,257 @@ impl Foo {
         assert_eq!(count1, count2, "Idempotent parse should not duplicate nodes");
     }
 
+    #[pg_test]
+    fn test_parse_source_incremental_keeps_ids() {
+        let v1 = "fn keep() { let a = 1; }\n\nfn edit() { let b = 2; }\n\nfn gone() {}\n";
+        let v2 = "fn keep() { let a = 1; }\n\nfn edit() { let b = 3; }\n\nfn added() {}\n";
+        Spi::run(&format!(
+            "SELECT kerai.parse_source('{}', 'test_incr.rs', incremental => true)",
+            sql_escape(v1),
+        ))
+        .unwrap();
+
+        let id_of = |name: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT n.id::text FROM kerai.nodes n
+                 JOIN kerai.nodes f ON f.id = n.parent_id
+                 WHERE f.kind = 'file' AND f.content = 'test_incr.rs'
+                   AND n.kind = 'fn' AND n.content = '{name}'",
+            ))
+            .ok()
+            .flatten()
+        };
+        let keep1 = id_of("keep").unwrap();
+        let edit1 = id_of("edit").unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.parse_source('{}', 'test_incr.rs', incremental => true)",
+            sql_escape(v2),
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(id_of("keep").as_deref(), Some(keep1.as_str()), "unchanged fn keeps its id");
+        assert_eq!(id_of("edit").as_deref(), Some(edit1.as_str()), "edited fn keeps its id");
+        assert!(id_of("gone").is_none(), "removed fn should be deleted");
+        assert!(id_of("added").is_some(), "new fn should be inserted");
+
+        let deleted = result.0["deleted"].as_u64().unwrap_or(0);
+        let unchanged = result.0["unchanged"].as_u64().unwrap_or(0);
+        assert!(deleted > 0, "removal should be reported, got {:?}", result.0);
+        assert!(unchanged > 0, "unchanged subtree should be reported, got {:?}", result.0);
+
+        let files = Spi::get_one::<i64>(
+            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'file' AND content = 'test_incr.rs'",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(files, 1, "incremental re-parse should keep a single file node");
+    }
+
+    #[pg_test]
+    fn test_diff_nodes_structural() {
+        let a = "fn keep() { let a = 1; }\n\nfn gone() {}\n";
+        let b = "fn added() {}\n\nfn keep() { let a = 1; }\n";
+        for (src, name) in [(a, "diff_a.rs"), (b, "diff_b.rs")] {
+            Spi::run(&format!("SELECT kerai.parse_source('{}', '{}')", sql_escape(src), name)).unwrap();
+        }
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.diff_nodes(
+                (SELECT id FROM kerai.nodes WHERE kind = 'file' AND content = 'diff_a.rs'),
+                (SELECT id FROM kerai.nodes WHERE kind = 'file' AND content = 'diff_b.rs'))",
+        )
+        .unwrap()
+        .unwrap();
+        let contents = |key: &str| -> Vec<String> {
+            result.0[key]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter_map(|e| e["content"].as_str().map(String::from))
+                .collect()
+        };
+
+        assert!(contents("inserted").contains(&"added".to_string()), "got {:?}", result.0);
+        assert!(contents("deleted").contains(&"gone".to_string()), "got {:?}", result.0);
+        assert!(result.0["unchanged"].as_u64().unwrap() > 0);
+        assert_eq!(result.0["file_b"]["name"], "diff_b.rs");
+    }
+
+    #[pg_test]
+    fn test_merge_nodes_three_way() {
+        let base = "fn keep() {}\n\nfn edit() { 1 }\n";
+        let ours = "fn keep() {}\n\nfn edit() { 2 }\n\nfn added() {}\n";
+        let theirs = "fn keep() {}\n\nfn edit() { 3 }\n";
+        for (src, name) in [(base, "merge_base.rs"), (ours, "merge_ours.rs"), (theirs, "merge_theirs.rs")] {
+            Spi::run(&format!("SELECT kerai.parse_source('{}', '{}')", sql_escape(src), name)).unwrap();
+        }
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.merge_nodes(
+                (SELECT id FROM kerai.nodes WHERE kind = 'file' AND content = 'merge_base.rs'),
+                (SELECT id FROM kerai.nodes WHERE kind = 'file' AND content = 'merge_ours.rs'),
+                (SELECT id FROM kerai.nodes WHERE kind = 'file' AND content = 'merge_theirs.rs'))",
+        )
+        .unwrap()
+        .unwrap();
+        let conflicts = result.0["conflicts"].as_array().unwrap();
+        assert_eq!(conflicts.len(), 1, "got {:?}", result.0);
+        assert_eq!(conflicts[0]["reason"], "modified on both sides");
+        assert_eq!(conflicts[0]["kind"], "fn");
+
+        let root = result.0["root"].as_str().unwrap();
+        let source = Spi::get_one::<String>(&format!("SELECT kerai.reconstruct_file('{}'::uuid)", root))
+            .unwrap()
+            .unwrap();
+        assert!(source.contains("<<<<<<< ours"), "got {}", source);
+        assert!(source.contains(">>>>>>> theirs"), "got {}", source);
+        assert!(source.contains("fn keep"), "got {}", source);
+        assert!(source.contains("fn added"), "got {}", source);
+    }
+
+    #[pg_test]
+    fn test_blame_attributes_crdt_edits() {
+        Spi::run(&format!(
+            "SELECT kerai.parse_source('{}', 'blame_a.rs')",
+            sql_escape("fn untouched() {}\n\nfn edited() {}\n"),
+        ))
+        .unwrap();
+        let fn_id = Spi::get_one::<String>(
+            "SELECT n.id::text FROM kerai.nodes n JOIN kerai.nodes f ON n.parent_id = f.id
+             WHERE f.content = 'blame_a.rs' AND n.kind = 'fn' AND n.content = 'edited'",
+        )
+        .unwrap()
+        .unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.apply_op('update_content', '{}'::uuid, '{{\"new_content\": \"renamed\"}}'::jsonb)",
+            fn_id
+        ))
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.blame((SELECT id FROM kerai.nodes WHERE kind = 'file' AND content = 'blame_a.rs'))",
+        )
+        .unwrap()
+        .unwrap();
+        let nodes = result.0["nodes"].as_array().unwrap();
+        let find = |content: &str| nodes.iter().find(|n| n["content"] == content).unwrap();
+
+        let edited = find("renamed");
+        assert_eq!(edited["operation"], "update_content");
+        assert!(edited["lamport_ts"].as_i64().is_some());
+        assert_eq!(find("untouched")["operation"], "parse");
+        assert!(find("untouched")["lamport_ts"].is_null());
+        assert!(!result.0["lines"].as_array().unwrap().is_empty());
+
+        // Recorded history must not block deleting the node
+        Spi::run(&format!(
+            "SELECT kerai.apply_op('delete_node', '{}'::uuid, '{{}}'::jsonb)",
+            fn_id
+        ))
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn test_branch_switch_and_merge() {
+        let inserted = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"on_main\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let node_id = inserted.0["node_id"].as_str().unwrap().to_string();
+        let content = || {
+            Spi::get_one::<String>(&format!("SELECT content FROM kerai.nodes WHERE id = '{}'::uuid", node_id))
+                .unwrap()
+                .unwrap()
+        };
+        let branch_only = || {
+            Spi::get_one::<bool>(
+                "SELECT EXISTS(SELECT 1 FROM kerai.nodes WHERE content = 'branch_only')",
+            )
+            .unwrap()
+            .unwrap()
+        };
+
+        Spi::run("SELECT kerai.create_branch('feature')").unwrap();
+        Spi::run("SELECT kerai.switch_branch('feature')").unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.apply_op('update_content', '{}'::uuid, '{{\"new_content\": \"on_feature\"}}'::jsonb)",
+            node_id
+        ))
+        .unwrap();
+        Spi::run(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"branch_only\", \"position\": 1}'::jsonb)",
+        )
+        .unwrap();
+
+        let back = Spi::get_one::<pgrx::JsonB>("SELECT kerai.switch_branch('main')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(back.0["undone"], 2);
+        assert_eq!(content(), "on_main");
+        assert!(!branch_only());
+
+        Spi::run("SELECT kerai.switch_branch('feature')").unwrap();
+        assert_eq!(content(), "on_feature");
+        assert!(branch_only());
+
+        Spi::run("SELECT kerai.switch_branch('main')").unwrap();
+        let merged = Spi::get_one::<pgrx::JsonB>("SELECT kerai.merge_branch('feature')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.0["applied"], 2, "got {:?}", merged.0);
+        assert!(merged.0["conflicts"].as_array().unwrap().is_empty());
+        assert_eq!(content(), "on_feature");
+        assert!(branch_only());
+
+        let listed = Spi::get_one::<pgrx::JsonB>("SELECT kerai.list_branches()")
+            .unwrap()
+            .unwrap();
+        let feature = listed.0.as_array().unwrap().iter().find(|b| b["name"] == "feature").unwrap();
+        assert_eq!(feature["merged_into"], "main");
+    }
+
+    #[pg_test]
+    fn test_dedup_shares_repeated_subtrees() {
+        let body = "{ let a = 1; let b = a + 2; let c = b * 3; let d = c - 4; println!(\"{}\", d); }";
+        Spi::run(&format!(
+            "SELECT kerai.parse_source('{}', 'dedup_a.rs')",
+            sql_escape(&format!("fn alpha() {body}\n")),
+        ))
+        .unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.parse_source('{}', 'dedup_b.rs')",
+            sql_escape(&format!("fn beta() {body}\n")),
+        ))
+        .unwrap();
+
+        let stub_in_b = Spi::get_one::<bool>(
+            "WITH RECURSIVE sub AS (
+                SELECT id FROM kerai.nodes WHERE kind = 'file' AND content = 'dedup_b.rs'
+                UNION ALL
+                SELECT n.id FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+            )
+            SELECT EXISTS(
+                SELECT 1 FROM kerai.nodes n
+                JOIN sub ON sub.id = n.id
+                JOIN kerai.edges e ON e.source_id = n.id AND e.relation = 'duplicates'
+                WHERE n.metadata ? 'dedup_of'
+            )",
+        )
+        .unwrap()
+        .unwrap_or(false);
+        assert!(stub_in_b, "repeated fn body should be stored as a stub with a duplicates edge");
+
+        let stats = Spi::get_one::<pgrx::JsonB>("SELECT kerai.dedup_stats()")
+            .unwrap()
+            .unwrap();
+        assert!(stats.0["shared_subtrees"].as_i64().unwrap_or(0) >= 1);
+        assert!(stats.0["nodes_saved"].as_i64().unwrap_or(0) > 0);
+        assert_eq!(stats.0["orphaned"].as_i64(), Some(0));
+    }
+
     // --- Plan 03: Reconstruction tests ---
 
     /// Helper: format source through prettyplease for canonical comparison.
@@ -532,6 +903,37 @@ impl Foo {
         .unwrap();
     }
 
+    #[pg_test]
+    fn test_reconstruct_node_emits_one_item() {
+        Spi::run(
+            "SELECT kerai.parse_source('/// A point.\n#[derive(Debug, Clone)]\nstruct Pt { x: i32 }\n\
+             impl Pt {\n    /// Twice x.\n    fn double(&self) -> i32 { self.x * 2 }\n}\n\
+             fn other() {}', 'recon_node.rs')",
+        )
+        .unwrap();
+        let item = |kind: &str, name: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT kerai.reconstruct_node(id) FROM kerai.nodes \
+                 WHERE kind = '{kind}' AND content = '{name}' LIMIT 1"
+            ))
+            .unwrap()
+            .unwrap()
+        };
+
+        let method = item("fn", "double");
+        assert_eq!(
+            method,
+            "    /// Twice x.\n    fn double(&self) -> i32 {\n        self.x * 2\n    }\n"
+        );
+        let strukt = item("struct", "Pt");
+        assert!(
+            strukt.starts_with("/// A point.\n#[derive(Clone, Debug)]\nstruct Pt"),
+            "got {strukt}"
+        );
+        assert!(!strukt.contains("other"), "got {strukt}");
+        assert!(item("file", "recon_node.rs").contains("fn other()"));
+    }
+
     #[pg_test]
     fn test_reconstruct_complex_roundtrip() {
         let source = "\
@@ -560,6 +962,346 @@ impl Config {
         assert_roundtrip(source, "recon_complex.rs");
     }
 
+    // --- Golden reconstruction tests ---
+
+    /// Per fixture directory: file extension, parse function, root node kind
+    /// and the reconstruct query (`{id}` is the root node). Adding a
+    /// language is a row here, a test below and a `tests/fixtures/<dir>/`.
+    const GOLDEN_LANGUAGES: &[(&str, &str, &str, &str, &str)] = &[
+        (
+            "rust",
+            "rs",
+            "parse_source",
+            "file",
+            "SELECT kerai.reconstruct_file_with_options('{id}'::uuid, '{\"suggestions\": false}'::jsonb)",
+        ),
+        ("go", "go", "parse_go_source", "file", "SELECT kerai.reconstruct_go_file('{id}'::uuid)"),
+        ("c", "c", "parse_c_source", "file", "SELECT kerai.reconstruct_c_file('{id}'::uuid)"),
+        (
+            "markdown",
+            "md",
+            "parse_markdown",
+            "document",
+            "SELECT kerai.reconstruct_markdown('{id}'::uuid)",
+        ),
+        (
+            "latex",
+            "tex",
+            "parse_latex_source",
+            "file",
+            "SELECT kerai.reconstruct_latex('{id}'::uuid)",
+        ),
+    ];
+
+    /// Helper: parse each fixture in `tests/fixtures/<dir>/`, reconstruct
+    /// it and compare with `<fixture>.golden`, ignoring trailing
+    /// whitespace. Fixtures only live in the test's transaction, which is
+    /// rolled back. With `KERAI_BLESS=1` in the server's environment the
+    /// goldens are rewritten from the current output instead, e.g.
+    /// `KERAI_BLESS=1 cargo pgrx test pg17 test_golden`.
+    fn assert_golden(dir: &str) {
+        let (_, ext, parse_fn, root_kind, reconstruct) = *GOLDEN_LANGUAGES
+            .iter()
+            .find(|l| l.0 == dir)
+            .expect("unknown golden language");
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(dir);
+        let bless = std::env::var("KERAI_BLESS").is_ok_and(|v| v == "1");
+
+        let mut inputs: Vec<std::path::PathBuf> = std::fs::read_dir(&fixtures)
+            .unwrap_or_else(|e| panic!("Cannot read {}: {}", fixtures.display(), e))
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|x| x == ext))
+            .collect();
+        inputs.sort();
+        assert!(
+            !inputs.is_empty(),
+            "No .{} fixtures in {}",
+            ext,
+            fixtures.display()
+        );
+
+        let mut failures = Vec::new();
+        for input in &inputs {
+            let file_name = input.file_name().unwrap().to_string_lossy().to_string();
+            let source = std::fs::read_to_string(input).unwrap();
+            let name = format!("golden_{}", file_name);
+            Spi::run(&format!(
+                "SELECT kerai.{}('{}', '{}')",
+                parse_fn,
+                sql_escape(&source),
+                sql_escape(&name),
+            ))
+            .unwrap();
+            let root = Spi::get_one::<String>(&format!(
+                "SELECT id::text FROM kerai.nodes WHERE kind = '{}' AND content = '{}'",
+                root_kind,
+                sql_escape(&name),
+            ))
+            .unwrap()
+            .unwrap_or_else(|| panic!("{} did not parse", file_name));
+            let actual = Spi::get_one::<String>(&reconstruct.replace("{id}", &root))
+                .unwrap()
+                .unwrap_or_default();
+            let actual = format!("{}\n", actual.trim_end());
+
+            let golden = input.with_file_name(format!("{}.golden", file_name));
+            if bless {
+                std::fs::write(&golden, &actual)
+                    .unwrap_or_else(|e| panic!("Cannot write {}: {}", golden.display(), e));
+                continue;
+            }
+            match std::fs::read_to_string(&golden) {
+                Ok(expected) if expected.trim_end() == actual.trim_end() => {}
+                Ok(expected) => failures.push(format!(
+                    "{}:\n--- expected\n{}\n--- actual\n{}",
+                    file_name, expected, actual
+                )),
+                Err(_) => failures.push(format!("{}: no {}", file_name, golden.display())),
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "Golden mismatches (rerun with KERAI_BLESS=1 to accept):\n{}",
+            failures.join("\n")
+        );
+    }
+
+    #[pg_test]
+    fn test_golden_rust() {
+        assert_golden("rust");
+    }
+
+    #[pg_test]
+    fn test_golden_go() {
+        assert_golden("go");
+    }
+
+    #[pg_test]
+    fn test_golden_c() {
+        assert_golden("c");
+    }
+
+    #[pg_test]
+    fn test_golden_markdown() {
+        assert_golden("markdown");
+    }
+
+    #[pg_test]
+    fn test_golden_latex() {
+        assert_golden("latex");
+    }
+
+    #[pg_test]
+    fn test_reconstruct_latex_after_node_edit() {
+        let source = "\\documentclass[11pt]{article}\n\\begin{document}\nHello.\n\\end{document}\n";
+        Spi::run_with_args(
+            "SELECT kerai.parse_latex_source($1, 'edit.tex')",
+            &[source.into()],
+        )
+        .unwrap();
+        let file = "(SELECT id FROM kerai.nodes WHERE kind = 'file' AND content = 'edit.tex')";
+        let rebuilt = Spi::get_one::<String>(&format!("SELECT kerai.reconstruct_latex({file})"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(rebuilt, source);
+
+        Spi::run(&format!(
+            "UPDATE kerai.nodes SET content = 'report'
+             WHERE kind = 'latex_documentclass' AND parent_id = {file}"
+        ))
+        .unwrap();
+        let rebuilt = Spi::get_one::<String>(&format!("SELECT kerai.reconstruct_latex({file})"))
+            .unwrap()
+            .unwrap();
+        assert!(rebuilt.starts_with("\\documentclass"), "{rebuilt}");
+        assert!(
+            rebuilt.contains("{report}\n\\begin{document}\nHello.\n"),
+            "{rebuilt}"
+        );
+    }
+
+    #[pg_test]
+    fn test_parse_latex_project_follows_includes() {
+        let tmp = tempfile::TempDir::new().expect("temp dir");
+        let files: &[(&str, &str)] = &[
+            (
+                "main.tex",
+                "\\documentclass{article}\n\\begin{document}\n\\input{chapters/intro}\n\\include{appendix}\n\\input{missing}\n\\end{document}\n",
+            ),
+            (
+                "chapters/intro.tex",
+                "\\section{Intro}\nAs in \\cite{knuth84}.\n\\input{chapters/detail}\n",
+            ),
+            // Includes intro back: parsed once, linked both ways
+            ("chapters/detail.tex", "Details.\n\\input{chapters/intro}\n"),
+            ("appendix.tex", "\\section{Appendix}\n"),
+        ];
+        for (rel, source) in files {
+            let path = tmp.path().join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        Spi::run(&format!(
+            "SELECT kerai.parse_bibtex_source('{}', 'refs.bib')",
+            sql_escape(
+                "@book{knuth84, title = {The TeXbook}, author = {Knuth, Donald}, year = {1984}}\n"
+            ),
+        ))
+        .unwrap();
+
+        let root = tmp.path().join("main.tex");
+        let summary = Spi::get_one_with_args::<pgrx::JsonB>(
+            "SELECT kerai.parse_latex_project($1)",
+            &[root.to_str().unwrap().into()],
+        )
+        .unwrap()
+        .unwrap()
+        .0;
+        assert_eq!(summary["root"], "main.tex", "{summary}");
+        assert_eq!(summary["files"].as_array().unwrap().len(), 4, "{summary}");
+        assert_eq!(summary["includes"], 4, "{summary}");
+        assert_eq!(
+            summary["missing"],
+            serde_json::json!([{"file": "main.tex", "path": "missing"}])
+        );
+        assert_eq!(summary["citations"]["linked"], 1, "{summary}");
+
+        let included = Spi::get_one::<String>(
+            "SELECT string_agg(t.content, ',' ORDER BY t.content) FROM kerai.edges e
+             JOIN kerai.nodes s ON s.id = e.source_id
+             JOIN kerai.nodes t ON t.id = e.target_id
+             WHERE e.relation = 'includes' AND s.kind = 'file' AND s.content = 'main.tex'",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(included, "appendix.tex,chapters/intro.tex");
+    }
+
+    #[pg_test]
+    fn test_latex_crossref_edges() {
+        let tmp = tempfile::TempDir::new().expect("temp dir");
+        let files: &[(&str, &str)] = &[
+            (
+                "paper.tex",
+                "\\documentclass{article}\n\\begin{document}\n\\section{Intro}\\label{sec:intro}\nAs in \\ref{sec:intro} and \\ref{fig:plot}.\n\\input{figs}\n\\end{document}\n",
+            ),
+            (
+                "figs.tex",
+                "\\begin{figure}\n\\caption{A plot}\\label{fig:plot}\n\\end{figure}\n",
+            ),
+        ];
+        for (rel, source) in files {
+            std::fs::write(tmp.path().join(rel), source).unwrap();
+        }
+        let root = tmp.path().join("paper.tex");
+        Spi::run_with_args(
+            "SELECT kerai.parse_latex_project($1)",
+            &[root.to_str().unwrap().into()],
+        )
+        .unwrap();
+
+        // A ref to a label in its own file gets a references edge
+        let linked = Spi::get_one::<pgrx::JsonB>(
+            "SELECT jsonb_agg(jsonb_build_object('ref', r.content, 'label', l.content,
+                                                 'key', e.metadata->>'label'))
+             FROM kerai.edges e
+             JOIN kerai.nodes r ON r.id = e.source_id AND r.kind = 'latex_ref'
+             JOIN kerai.nodes l ON l.id = e.target_id AND l.kind = 'latex_label'
+             WHERE e.relation = 'references'",
+        )
+        .unwrap()
+        .unwrap()
+        .0;
+        assert_eq!(
+            linked,
+            serde_json::json!([{"ref": "sec:intro", "label": "sec:intro", "key": "sec:intro"}])
+        );
+
+        // One in an included file has none; its label is found by key in
+        // a file the root includes, as GET /api/documents/:id/crossrefs does
+        let by_key = Spi::get_one::<String>(
+            "WITH RECURSIVE up AS (
+                SELECT parent_id FROM kerai.nodes
+                WHERE kind = 'latex_label' AND content = 'fig:plot'
+                UNION ALL
+                SELECT n.parent_id FROM kerai.nodes n JOIN up ON n.id = up.parent_id
+            )
+            SELECT f.content FROM up
+            JOIN kerai.nodes f ON f.id = up.parent_id AND f.kind = 'file'
+            JOIN kerai.edges e ON e.target_id = f.id AND e.relation = 'includes'
+            JOIN kerai.nodes p ON p.id = e.source_id AND p.content = 'paper.tex'",
+        )
+        .unwrap();
+        assert_eq!(by_key.as_deref(), Some("figs.tex"));
+    }
+
+    #[pg_test]
+    fn test_citation_report_and_doi_normalization() {
+        let bib = "@article{crdt11, title = {CRDTs}, author = {Shapiro, Marc}, year = {2011}, doi = {https://doi.org/10.1007/978-3-642-24550-3_29}}\n\
+                   @article{crdt11b, title = {CRDTs again}, author = {Shapiro, Marc}, year = {2011}, doi = {10.1007/978-3-642-24550-3_29}}\n\
+                   @misc{unused, title = {Unused}, url = {https://dx.doi.org/10.5555/unused}}\n";
+        Spi::run_with_args(
+            "SELECT kerai.parse_bibtex_source($1, 'cr_refs.bib')",
+            &[bib.into()],
+        )
+        .unwrap();
+        Spi::run_with_args(
+            "SELECT kerai.parse_latex_source($1, 'cr_paper.tex')",
+            &[
+                "\\documentclass{article}\n\\begin{document}\nSee \\cite{crdt11,ghost}.\n\\end{document}\n"
+                    .into(),
+            ],
+        )
+        .unwrap();
+
+        let report = Spi::get_one::<pgrx::JsonB>("SELECT kerai.citation_report()")
+            .unwrap()
+            .unwrap()
+            .0;
+        let keys = |field: &str| -> Vec<String> {
+            report[field]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter_map(|v| v["key"].as_str().map(String::from))
+                .collect()
+        };
+        assert!(
+            keys("unresolved").contains(&"ghost".to_string()),
+            "{report}"
+        );
+        assert_eq!(report["unresolved"][0]["places"][0]["file"], "cr_paper.tex");
+        assert!(keys("uncited").contains(&"unused".to_string()), "{report}");
+        assert!(!keys("uncited").contains(&"crdt11".to_string()), "{report}");
+
+        // Normalizing makes both DOIs the same, so the entries are duplicates
+        let enriched = Spi::get_one::<pgrx::JsonB>("SELECT kerai.enrich_bibtex(false)")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(enriched["enriched"], 0);
+        assert!(enriched["normalized"].as_i64().unwrap() >= 1, "{enriched}");
+        let doi = Spi::get_one::<String>(
+            "SELECT metadata->>'doi' FROM kerai.nodes WHERE kind = 'bib_entry' AND content = 'unused'",
+        )
+        .unwrap();
+        assert_eq!(doi.as_deref(), Some("10.5555/unused"));
+        let report = Spi::get_one::<pgrx::JsonB>("SELECT kerai.citation_report()")
+            .unwrap()
+            .unwrap()
+            .0;
+        let by_doi: Vec<&serde_json::Value> = report["duplicates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|d| d["by"] == "doi")
+            .collect();
+        assert_eq!(by_doi.len(), 1, "{report}");
+        assert_eq!(by_doi[0]["entries"].as_array().unwrap().len(), 2);
+    }
+
     // --- Plan 04: CRDT operation tests ---
 
     #[pg_test]
@@ -689,9 +1431,16 @@ impl Config {
         ))
         .unwrap()
         .unwrap();
-        assert_eq!(count, 0, "Node should be deleted");
-    }
-
+        assert_eq!(count, 1, "Node should be kept as a tombstone");
+        let live = Spi::get_one::<i64>(&format!(
+            "SELECT count(*)::bigint FROM kerai.nodes WHERE id = '{}'::uuid AND deleted_at IS NULL",
+            node_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(live, 0, "Node should be deleted");
+    }
+
     #[pg_test]
     fn test_crdt_delete_node_cascade() {
         // Create parent
@@ -717,13 +1466,141 @@ impl Config {
         .unwrap();
 
         let count = Spi::get_one::<i64>(
-            "SELECT count(*)::bigint FROM kerai.nodes WHERE content IN ('cascade_parent', 'cascade_child')",
+            "SELECT count(*)::bigint FROM kerai.nodes
+             WHERE content IN ('cascade_parent', 'cascade_child') AND deleted_at IS NULL",
         )
         .unwrap()
         .unwrap();
         assert_eq!(count, 0, "Parent and child should both be deleted");
     }
 
+    #[pg_test]
+    fn test_gc() {
+        let (_signing_key, pk_hex) = generate_currency_keypair();
+        Spi::run(&format!(
+            "SELECT kerai.register_peer('gc-peer', '{}', 'https://gc.example.com', NULL)",
+            pk_hex,
+        ))
+        .unwrap();
+
+        let inserted = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"gc_fn\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let node_id = inserted.0["node_id"].as_str().unwrap().to_string();
+        let deleted = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.apply_op('delete_node', '{}'::uuid, '{{}}'::jsonb)",
+            node_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let exists = || {
+            Spi::get_one::<bool>(&format!(
+                "SELECT EXISTS(SELECT 1 FROM kerai.nodes WHERE id = '{}'::uuid)",
+                node_id,
+            ))
+            .unwrap()
+            .unwrap()
+        };
+
+        // Inside the retention window nothing goes
+        let kept = Spi::get_one::<pgrx::JsonB>("SELECT kerai.gc()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(kept.0["purged"], 0);
+        assert!(exists());
+
+        // Past it, the tombstone waits for the peer to acknowledge the delete
+        let held = Spi::get_one::<pgrx::JsonB>("SELECT kerai.gc('0')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(held.0["purged"], 0, "{}", held.0);
+        assert_eq!(held.0["held"], 1, "{}", held.0);
+        assert!(exists());
+
+        Spi::run(&format!(
+            "UPDATE kerai.instances i SET acked_vector = jsonb_build_object(o.author, o.author_seq)
+             FROM kerai.operations o
+             WHERE i.name = 'gc-peer' AND o.node_id = '{}'::uuid AND o.op_type = 'delete_node'",
+            node_id,
+        ))
+        .unwrap();
+        let purged = Spi::get_one::<pgrx::JsonB>("SELECT kerai.gc('0')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(purged.0["purged"], 1, "{} after {}", purged.0, deleted.0);
+        assert!(!exists());
+    }
+
+    #[pg_test]
+    fn test_revert_and_undo() {
+        let insert = |payload: String| {
+            Spi::get_one::<pgrx::JsonB>(&format!(
+                "SELECT kerai.apply_op('insert_node', NULL, '{payload}'::jsonb)"
+            ))
+            .unwrap()
+            .unwrap()
+            .0["node_id"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        };
+        let parent_id =
+            insert(r#"{"kind": "module", "content": "rv_parent", "position": 0}"#.to_string());
+        let child_id = insert(format!(
+            r#"{{"kind": "fn", "content": "rv_child", "position": 0, "parent_id": "{parent_id}"}}"#
+        ));
+        Spi::run(&format!(
+            "SELECT kerai.apply_op('update_content', '{child_id}'::uuid, '{{\"new_content\": \"rv_edited\"}}'::jsonb)"
+        ))
+        .unwrap();
+
+        let version_id = Spi::get_one::<String>(&format!(
+            "SELECT id::text FROM kerai.versions WHERE node_id = '{child_id}'::uuid AND operation = 'update_content'"
+        ))
+        .unwrap()
+        .unwrap();
+        let reverted =
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.revert_version('{version_id}')"))
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            reverted.0["conflicts"],
+            serde_json::json!([]),
+            "{}",
+            reverted.0
+        );
+        let content = Spi::get_one::<String>(&format!(
+            "SELECT content FROM kerai.nodes WHERE id = '{child_id}'::uuid"
+        ))
+        .unwrap();
+        assert_eq!(content.as_deref(), Some("rv_child"));
+
+        // A cascade delete leaves tombstones; undoing it brings both back
+        Spi::run(&format!(
+            "SELECT kerai.apply_op('delete_node', '{parent_id}'::uuid, '{{\"cascade\": true}}'::jsonb)"
+        ))
+        .unwrap();
+        let undone = Spi::get_one::<pgrx::JsonB>("SELECT kerai.undo_last()")
+            .unwrap()
+            .unwrap();
+        let resurrected = &undone.0["undone"][0]["resurrected"];
+        assert_eq!(
+            resurrected.as_object().map(|m| m.len()),
+            Some(2),
+            "{}",
+            undone.0
+        );
+        let new_parent = resurrected[parent_id.as_str()].as_str().unwrap();
+        let new_child = resurrected[child_id.as_str()].as_str().unwrap();
+        let restored = Spi::get_one::<String>(&format!(
+            "SELECT content FROM kerai.nodes WHERE id = '{new_child}'::uuid AND parent_id = '{new_parent}'::uuid"
+        ))
+        .unwrap();
+        assert_eq!(restored.as_deref(), Some("rv_child"));
+    }
+
     #[pg_test]
     fn test_crdt_version_vector_increments() {
         // Two ops should produce author_seq >= 2
@@ -746,1633 +1623,4834 @@ impl Config {
     }
 
     #[pg_test]
-    fn test_crdt_lamport_clock_increments() {
-        let before = Spi::get_one::<i64>("SELECT kerai.lamport_clock()")
-            .unwrap()
-            .unwrap();
-
+    fn test_crdt_version_delta() {
         Spi::run(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"lc_fn\", \"position\": 0}'::jsonb)",
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"vd1\", \"position\": 0}'::jsonb)",
         )
         .unwrap();
-
-        let after = Spi::get_one::<i64>("SELECT kerai.lamport_clock()")
-            .unwrap()
-            .unwrap();
-        assert!(after > before, "Lamport clock should increase after an op");
-    }
-
-    #[pg_test]
-    #[should_panic(expected = "duplicate key value violates unique constraint")]
-    fn test_crdt_idempotent_replay() {
-        // Get current author fingerprint
         let fp = Spi::get_one::<String>(
             "SELECT key_fingerprint FROM kerai.instances WHERE is_self = true",
         )
         .unwrap()
         .unwrap();
 
-        // Apply an op
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"replay_fn\", \"position\": 0}'::jsonb)",
-        )
-        .unwrap()
-        .unwrap();
-        let seq = result.0["author_seq"].as_i64().unwrap();
-        let ts = result.0["lamport_ts"].as_i64().unwrap();
+        let detail = Spi::get_one::<pgrx::JsonB>("SELECT kerai.version_vector(true)")
+            .unwrap()
+            .unwrap();
+        let entry = &detail.0[fp.as_str()];
+        assert!(entry["seq"].as_i64().unwrap() >= 1, "got {:?}", detail.0);
+        assert!(entry["lamport"].as_i64().unwrap() >= 1, "got {:?}", detail.0);
+        assert!(entry["instance_id"].is_string(), "got {:?}", detail.0);
 
-        // Manually try to insert a duplicate (author, author_seq) — should fail
-        Spi::run(&format!(
-            "INSERT INTO kerai.operations (instance_id, op_type, author, lamport_ts, author_seq, payload)
-             SELECT id, 'insert_node', '{}', {}, {}, '{{}}'::jsonb FROM kerai.instances WHERE is_self = true",
-            fp.replace('\'', "''"),
-            ts + 1,
-            seq,
+        // An empty peer is missing everything, an up-to-date one nothing
+        let all = Spi::get_one::<pgrx::JsonB>("SELECT kerai.version_delta('{}'::jsonb)")
+            .unwrap()
+            .unwrap();
+        assert!(!all.0.as_array().unwrap().is_empty());
+        let none = Spi::get_one::<pgrx::JsonB>("SELECT kerai.version_delta(kerai.version_vector(true))")
+            .unwrap()
+            .unwrap();
+        assert_eq!(none.0, serde_json::json!([]));
+
+        let behind = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.version_delta(jsonb_build_object('{}', {}))",
+            sql_escape(&fp),
+            entry["seq"].as_i64().unwrap() - 1,
         ))
+        .unwrap()
         .unwrap();
+        let ops = behind.0.as_array().unwrap();
+        assert_eq!(ops.len(), 1, "got {:?}", behind.0);
+        assert_eq!(ops[0]["payload"]["content"], "vd1");
     }
 
     #[pg_test]
-    fn test_crdt_insert_and_delete_edge() {
-        // Create two nodes
-        let n1 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"edge_src\", \"position\": 0}'::jsonb)",
+    fn test_verify_versions_finds_tampered_rows() {
+        Spi::run(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"signed1\", \"position\": 0}'::jsonb)",
         )
-        .unwrap()
         .unwrap();
-        let src_id = n1.0["node_id"].as_str().unwrap().to_string();
-
-        let n2 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"edge_tgt\", \"position\": 1}'::jsonb)",
+        let unsigned = Spi::get_one::<i64>(
+            "SELECT count(*)::bigint FROM kerai.versions WHERE new_content = 'signed1' AND (signature IS NULL OR signed_by IS NULL)",
         )
         .unwrap()
         .unwrap();
-        let tgt_id = n2.0["node_id"].as_str().unwrap().to_string();
+        assert_eq!(unsigned, 0, "new versions should be signed");
 
-        // Insert edge
+        let report = Spi::get_one::<pgrx::JsonB>("SELECT kerai.verify_versions()").unwrap().unwrap();
+        assert!(report.0["valid"].as_u64().unwrap() >= 1, "got {:?}", report.0);
+        assert_eq!(report.0["tampered"], serde_json::json!([]));
+
+        Spi::run("UPDATE kerai.versions SET new_content = 'forged' WHERE new_content = 'signed1'").unwrap();
+        let report = Spi::get_one::<pgrx::JsonB>("SELECT kerai.verify_versions()").unwrap().unwrap();
+        let tampered = report.0["tampered"].as_array().unwrap();
+        assert_eq!(tampered.len(), 1, "got {:?}", report.0);
+        assert_eq!(tampered[0]["operation"], "insert_node");
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "public key does not match the author")]
+    fn test_remote_op_rejects_foreign_key() {
+        use ed25519_dalek::Signer;
+        let (signing_key, pk_hex) = generate_currency_keypair();
+        let payload = serde_json::json!({"kind": "fn", "content": "forged", "position": 0});
+        let signable = format!("insert_node|null|1|{}", payload);
+        let signature: String = signing_key
+            .sign(signable.as_bytes())
+            .to_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        // Validly signed, but claiming to come from this instance
+        let op = serde_json::json!({
+            "op_type": "insert_node",
+            "author": Spi::get_one::<String>("SELECT key_fingerprint FROM kerai.instances WHERE is_self")
+                .unwrap()
+                .unwrap(),
+            "author_seq": 1,
+            "lamport_ts": 1,
+            "payload": payload,
+            "signature": signature,
+            "public_key": pk_hex,
+        });
         Spi::run(&format!(
-            "SELECT kerai.apply_op('insert_edge', '{}'::uuid, '{{\"target_id\": \"{}\", \"relation\": \"calls\"}}'::jsonb)",
-            src_id, tgt_id,
+            "SELECT kerai.apply_remote_op('{}'::jsonb)",
+            sql_escape(&op.to_string()),
         ))
         .unwrap();
+    }
 
-        let edge_count = Spi::get_one::<i64>(&format!(
-            "SELECT count(*)::bigint FROM kerai.edges WHERE source_id = '{}'::uuid AND target_id = '{}'::uuid AND relation = 'calls'",
-            src_id, tgt_id,
+    /// Helper: a remote op signed by `key`, as `version_delta` returns it.
+    fn signed_remote_op(
+        key: &ed25519_dalek::SigningKey,
+        op_type: &str,
+        node_id: &str,
+        author_seq: i64,
+        lamport_ts: i64,
+        payload: serde_json::Value,
+    ) -> serde_json::Value {
+        use ed25519_dalek::Signer;
+        let public_key = key.verifying_key();
+        let author = crate::identity::fingerprint(&public_key);
+        let signable = format!("{}|{}|{}|{}", op_type, node_id, author_seq, payload);
+        serde_json::json!({
+            "op_type": op_type,
+            "node_id": node_id,
+            "author": author,
+            "author_seq": author_seq,
+            "lamport_ts": lamport_ts,
+            "payload": payload,
+            "signature": hex::encode(key.sign(signable.as_bytes()).to_bytes()),
+            "public_key": hex::encode(public_key.as_bytes()),
+        })
+    }
+
+    #[pg_test]
+    fn test_apply_operations_last_writer_wins() {
+        let (key, _) = generate_currency_keypair();
+        let node = "0b5e0000-0000-4000-8000-00000000a001";
+        let insert = signed_remote_op(&key, "insert_node", node, 1, 10,
+            serde_json::json!({"kind": "fn", "content": "lww", "position": 0}));
+        let newer = signed_remote_op(&key, "update_content", node, 3, 30,
+            serde_json::json!({"new_content": "newer"}));
+        let older = signed_remote_op(&key, "update_content", node, 2, 20,
+            serde_json::json!({"new_content": "older"}));
+
+        // Out of order within a batch: sorted causally before applying
+        let batch = serde_json::json!([newer, insert]);
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.apply_operations('{}'::jsonb)",
+            sql_escape(&batch.to_string()),
         ))
         .unwrap()
         .unwrap();
-        assert_eq!(edge_count, 1, "Edge should exist");
+        assert_eq!(result.0["applied"], 2, "got {:?}", result.0);
 
-        // Delete edge
-        Spi::run(&format!(
-            "SELECT kerai.apply_op('delete_edge', '{}'::uuid, '{{\"target_id\": \"{}\", \"relation\": \"calls\"}}'::jsonb)",
-            src_id, tgt_id,
+        // A late, older write is logged but does not win
+        let late = serde_json::json!([older, newer]);
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.apply_operations('{}'::jsonb)",
+            sql_escape(&late.to_string()),
         ))
+        .unwrap()
         .unwrap();
+        assert_eq!(result.0["superseded"], 1, "got {:?}", result.0);
+        assert_eq!(result.0["duplicates"], 1, "got {:?}", result.0);
 
-        let edge_count2 = Spi::get_one::<i64>(&format!(
-            "SELECT count(*)::bigint FROM kerai.edges WHERE source_id = '{}'::uuid AND target_id = '{}'::uuid AND relation = 'calls'",
-            src_id, tgt_id,
+        let content = Spi::get_one::<String>(&format!(
+            "SELECT content FROM kerai.nodes WHERE id = '{}'::uuid",
+            node,
         ))
         .unwrap()
         .unwrap();
-        assert_eq!(edge_count2, 0, "Edge should be deleted");
+        assert_eq!(content, "newer");
+        let versions = Spi::get_one::<i64>(&format!(
+            "SELECT count(*)::bigint FROM kerai.versions WHERE node_id = '{}'::uuid",
+            node,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(versions, 2);
     }
 
     #[pg_test]
-    fn test_crdt_signature_present() {
+    fn test_crdt_version_filter_skips_held_ops() {
         Spi::run(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"sig_fn\", \"position\": 0}'::jsonb)",
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"vf1\", \"position\": 0}'::jsonb)",
         )
         .unwrap();
 
-        let sig_len = Spi::get_one::<i32>(
-            "SELECT octet_length(signature) FROM kerai.operations ORDER BY created_at DESC LIMIT 1",
-        )
-        .unwrap()
-        .unwrap();
-        assert_eq!(sig_len, 64, "Ed25519 signature should be 64 bytes");
-    }
+        let filter = Spi::get_one::<pgrx::JsonB>("SELECT kerai.version_filter('{}'::jsonb)")
+            .unwrap()
+            .unwrap();
+        assert!(filter.0["count"].as_u64().unwrap() >= 1, "got {:?}", filter.0);
+        assert!(filter.0["bits"].is_string(), "got {:?}", filter.0);
 
-    #[pg_test]
-    fn test_crdt_ops_since_returns_operations() {
-        let fp = Spi::get_one::<String>(
-            "SELECT key_fingerprint FROM kerai.instances WHERE is_self = true",
+        // A peer holding everything here filters the whole delta away
+        let delta = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.version_delta('{}'::jsonb, kerai.version_filter('{}'::jsonb))",
         )
         .unwrap()
         .unwrap();
+        assert_eq!(delta.0, serde_json::json!([]));
 
-        // Apply two ops
-        Spi::run(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"ops_since_1\", \"position\": 0}'::jsonb)",
-        )
-        .unwrap();
-        let r2 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"ops_since_2\", \"position\": 1}'::jsonb)",
+        // A filter built past the current frontier holds nothing
+        let empty = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.version_filter(kerai.version_vector())",
         )
         .unwrap()
         .unwrap();
-        let seq2 = r2.0["author_seq"].as_i64().unwrap();
+        assert_eq!(empty.0["count"], 0);
+    }
 
-        // Get ops since seq2-1 (should include at least the last op)
-        let ops = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.ops_since('{}', {})",
-            fp.replace('\'', "''"),
-            seq2 - 1,
+    #[pg_test]
+    fn test_sync_now_logs_unreachable_peer() {
+        let (_signing_key, pk_hex) = generate_currency_keypair();
+        // Nothing listens on port 1, so the pull fails at once
+        Spi::run(&format!(
+            "SELECT kerai.register_peer('offline-peer', '{}', 'http://127.0.0.1:1', NULL)",
+            pk_hex,
         ))
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.sync_now('offline-peer')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.0["peers"], 1);
+        assert_eq!(result.0["failed"], 1);
+        let row = &result.0["results"][0];
+        assert_eq!(row["status"], "error");
+        assert_eq!(row["peer"], "offline-peer");
+        assert!(row["error"].as_str().unwrap().contains("/api/sync/pull"));
+
+        let logged = Spi::get_one::<i64>(
+            "SELECT count(*)::bigint FROM kerai.sync_log l
+             JOIN kerai.instances i ON i.id = l.peer_id
+             WHERE i.name = 'offline-peer' AND l.status = 'error'",
+        )
         .unwrap()
         .unwrap();
-        let arr = ops.0.as_array().unwrap();
-        assert!(!arr.is_empty(), "ops_since should return at least one op");
+        assert_eq!(logged, 1);
     }
 
     #[pg_test]
-    #[should_panic(expected = "Unknown op_type")]
-    fn test_crdt_invalid_op_type() {
-        Spi::run(
-            "SELECT kerai.apply_op('bogus_op', NULL, '{}'::jsonb)",
+    fn test_sync_policy_holds_peer_past_its_cap() {
+        let (_signing_key, pk_hex) = generate_currency_keypair();
+        Spi::run(&format!(
+            "SELECT kerai.register_peer('capped-peer', '{}', 'http://127.0.0.1:1', NULL)",
+            pk_hex,
+        ))
+        .unwrap();
+        let state = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.set_sync_policy('capped-peer', ARRAY['00:00-00:00'], 5)",
         )
+        .unwrap()
         .unwrap();
-    }
+        assert_eq!(state.0["in_window"], true);
+        assert_eq!(state.0["ops_left"], 5);
+        assert!(state.0["held"].is_null());
 
-    #[pg_test]
-    #[should_panic(expected = "requires a node_id")]
-    fn test_crdt_update_without_node_id() {
+        // An exchange a moment ago moved the whole minute's budget
         Spi::run(
-            "SELECT kerai.apply_op('update_content', NULL, '{\"new_content\": \"x\"}'::jsonb)",
+            "INSERT INTO kerai.sync_log (peer_id, endpoint, started_at, status, transferred)
+             SELECT id, endpoint, now(), 'ok', 5 FROM kerai.instances WHERE name = 'capped-peer'",
         )
         .unwrap();
-    }
-
-    // --- Plan 06: Peer sync tests ---
+        let held = Spi::get_one::<pgrx::JsonB>("SELECT kerai.sync_now('capped-peer')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(held.0["held"], 1);
+        assert_eq!(held.0["results"][0]["status"], "held");
 
-    /// Generate a test Ed25519 keypair. Returns (public_key_hex, fingerprint).
-    fn generate_test_keypair() -> (String, String) {
-        let mut rng = rand::rngs::OsRng;
-        let signing_key = ed25519_dalek::SigningKey::generate(&mut rng);
-        let verifying_key = signing_key.verifying_key();
-        let pk_hex: String = verifying_key.as_bytes().iter().map(|b| format!("{:02x}", b)).collect();
-        let fp = crate::identity::fingerprint(&verifying_key);
-        (pk_hex, fp)
-    }
+        // Forced, it gets as far as the (unreachable) peer
+        let forced = Spi::get_one::<pgrx::JsonB>("SELECT kerai.sync_now('capped-peer', true)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(forced.0["failed"], 1);
+
+        let ops = r#"[{"author": "a", "author_seq": 1}, {"author": "b", "author_seq": 1},
+                      {"author": "a", "author_seq": 2}]"#;
+        let cut =
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.limit_ops('{}'::jsonb, 1)", ops))
+                .unwrap()
+                .unwrap();
+        assert_eq!(cut.0["ops"].as_array().unwrap().len(), 1);
+        assert_eq!(cut.0["deferred"], 2);
+    }
+
+    #[pg_test]
+    fn test_poll_changes_by_region_with_cursor() {
+        // Ops of an old, finished transaction, so they are past the horizon
+        for (seq, path) in [(1, "poll_a.fn1"), (2, "poll_b.fn2"), (3, "poll_a.fn3")] {
+            Spi::run(&format!(
+                "INSERT INTO kerai.operations (instance_id, op_type, author, lamport_ts, author_seq, txid, path)
+                 SELECT id, 'update_content', 'poll-author', {seq}, {seq}, '3'::xid8, '{path}'::ltree
+                 FROM kerai.instances WHERE is_self = true",
+            ))
+            .unwrap();
+        }
 
-    #[pg_test]
-    fn test_register_peer() {
-        let (pk_hex, _fp) = generate_test_keypair();
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.register_peer('test-peer-1', '{}', 'https://peer1.example.com', NULL)",
-            pk_hex,
-        ))
-        .unwrap()
-        .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert_eq!(obj["name"].as_str().unwrap(), "test-peer-1");
-        assert!(obj["is_new"].as_bool().unwrap());
-        assert!(obj.contains_key("key_fingerprint"));
-        assert!(obj.contains_key("id"));
-    }
+        let first = Spi::get_one::<pgrx::JsonB>("SELECT kerai.poll_changes(NULL, 'poll_a', 1)")
+            .unwrap()
+            .unwrap();
+        let events = first.0["events"].as_array().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["path"], "poll_a.fn1");
+        assert_eq!(first.0["more"], true);
 
-    #[pg_test]
-    fn test_register_peer_idempotent() {
-        let (pk_hex, _) = generate_test_keypair();
-        // First registration
-        let r1 = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.register_peer('peer-idem', '{}', NULL, NULL)",
-            pk_hex,
+        let cursor = first.0["cursor"].as_str().unwrap().to_string();
+        let second = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.poll_changes('{}', 'poll_a', 10)",
+            cursor,
         ))
         .unwrap()
         .unwrap();
-        assert!(r1.0["is_new"].as_bool().unwrap());
+        let events = second.0["events"].as_array().unwrap();
+        assert_eq!(events.len(), 1, "Only the other poll_a op remains");
+        assert_eq!(events[0]["path"], "poll_a.fn3");
+        assert_eq!(second.0["more"], false);
 
-        // Second registration with updated endpoint
-        let r2 = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.register_peer('peer-idem-updated', '{}', 'https://updated.example.com', NULL)",
-            pk_hex,
+        let third = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.poll_changes('{}', 'poll_a', 10)",
+            second.0["cursor"].as_str().unwrap(),
         ))
         .unwrap()
         .unwrap();
-        assert!(!r2.0["is_new"].as_bool().unwrap());
-        assert_eq!(r2.0["endpoint"].as_str().unwrap(), "https://updated.example.com");
+        assert!(third.0["events"].as_array().unwrap().is_empty());
     }
 
     #[pg_test]
-    fn test_list_peers_after_add() {
-        let (pk_hex, _) = generate_test_keypair();
+    fn test_apply_op_logs_node_path() {
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"pathed_fn\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let node_id = result.0["node_id"].as_str().unwrap().to_string();
         Spi::run(&format!(
-            "SELECT kerai.register_peer('list-test-peer', '{}', NULL, NULL)",
-            pk_hex,
+            "UPDATE kerai.nodes SET path = 'region.pathed_fn' WHERE id = '{}'::uuid",
+            node_id,
         ))
         .unwrap();
-
-        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.list_peers()")
-            .unwrap()
-            .unwrap();
-        let arr = result.0.as_array().unwrap();
-        let names: Vec<&str> = arr.iter().filter_map(|v| v["name"].as_str()).collect();
-        assert!(names.contains(&"list-test-peer"), "Registered peer should appear in list");
-    }
-
-    #[pg_test]
-    fn test_get_peer() {
-        let (pk_hex, fp) = generate_test_keypair();
         Spi::run(&format!(
-            "SELECT kerai.register_peer('get-test-peer', '{}', NULL, NULL)",
-            pk_hex,
+            "SELECT kerai.apply_op('update_content', '{}'::uuid, '{{\"new_content\": \"renamed_fn\"}}'::jsonb)",
+            node_id,
         ))
         .unwrap();
+        Spi::run(&format!("SELECT kerai.apply_op('delete_node', '{}'::uuid, '{{}}'::jsonb)", node_id))
+            .unwrap();
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.get_peer('{}')",
-            fp.replace('\'', "''"),
+        let path = Spi::get_one::<String>(&format!(
+            "SELECT path::text FROM kerai.operations
+             WHERE node_id = '{}'::uuid AND op_type = 'delete_node'",
+            node_id,
         ))
-        .unwrap()
         .unwrap();
-        assert_eq!(result.0["name"].as_str().unwrap(), "get-test-peer");
+        assert_eq!(path.as_deref(), Some("region.pathed_fn"), "Deletes keep the node's last path");
     }
 
     #[pg_test]
-    fn test_remove_peer() {
-        let (pk_hex, _) = generate_test_keypair();
-        Spi::run(&format!(
-            "SELECT kerai.register_peer('remove-me', '{}', NULL, NULL)",
+    fn test_sync_message_round_trip() {
+        use ed25519_dalek::Signer;
+        let (signing_key, pk_hex) = generate_currency_keypair();
+        let registered = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.register_peer('sync-peer', '{}', 'https://sync.example.com', NULL)",
             pk_hex,
         ))
+        .unwrap()
         .unwrap();
+        let fp = registered.0["key_fingerprint"].as_str().unwrap().to_string();
 
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.remove_peer('remove-me')",
+        let body = serde_json::json!({"vector": {"someone": 3}});
+        let sent_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let signable = format!("sync|{}|{}|{}", fp, sent_at, body);
+        let signature: String = signing_key
+            .sign(signable.as_bytes())
+            .to_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let message = serde_json::json!({
+            "from": fp,
+            "public_key": pk_hex,
+            "sent_at": sent_at,
+            "body": body,
+            "signature": signature,
+        });
+
+        let opened = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.open_sync_message('{}'::jsonb)",
+            sql_escape(&message.to_string()),
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(opened.0["instance"], "sync-peer");
+        assert_eq!(opened.0["body"], body);
+        let acked = Spi::get_one::<pgrx::JsonB>(
+            "SELECT acked_vector FROM kerai.instances WHERE name = 'sync-peer'",
         )
         .unwrap()
         .unwrap();
-        assert!(result.0["removed"].as_bool().unwrap());
-    }
+        assert_eq!(acked.0, serde_json::json!({"someone": 3}));
 
-    #[pg_test]
-    #[should_panic(expected = "Cannot remove self instance")]
-    fn test_cannot_remove_self() {
-        let self_name = Spi::get_one::<String>(
-            "SELECT name FROM kerai.instances WHERE is_self = true",
+        // Our own messages carry our key and body
+        let signed = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.sign_sync_message('{\"ops\": []}'::jsonb)",
         )
         .unwrap()
         .unwrap();
+        assert_eq!(signed.0["body"], serde_json::json!({"ops": []}));
+        assert_eq!(signed.0["signature"].as_str().unwrap().len(), 128);
 
-        Spi::run(&format!(
-            "SELECT kerai.remove_peer('{}')",
-            self_name.replace('\'', "''"),
-        ))
+        let frontier = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.version_frontier('{\"a\": 5, \"b\": 2}'::jsonb, '{\"a\": 3}'::jsonb)",
+        )
+        .unwrap()
         .unwrap();
+        assert_eq!(frontier.0, serde_json::json!({"a": 3, "b": 0}));
     }
 
     #[pg_test]
-    fn test_self_public_key_hex() {
-        let pk_hex = Spi::get_one::<String>("SELECT kerai.self_public_key_hex()")
+    fn test_sync_message_reports_clock_skew() {
+        use ed25519_dalek::Signer;
+        let (signing_key, pk_hex) = generate_currency_keypair();
+        let registered = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.register_peer('slow-clock', '{}', 'https://slow.example.com', NULL)",
+            pk_hex,
+        ))
+        .unwrap()
+        .unwrap();
+        let fp = registered.0["key_fingerprint"].as_str().unwrap().to_string();
+
+        // A peer whose clock is a minute behind ours
+        let sent_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64
+            - 60;
+        let body = serde_json::json!({"vector": {}});
+        let signable = format!("sync|{}|{}|{}", fp, sent_at, body);
+        let message = serde_json::json!({
+            "from": fp,
+            "public_key": pk_hex,
+            "sent_at": sent_at,
+            "body": body,
+            "signature": hex::encode(signing_key.sign(signable.as_bytes()).to_bytes()),
+        });
+        let opened = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.open_sync_message('{}'::jsonb)",
+            sql_escape(&message.to_string()),
+        ))
+        .unwrap()
+        .unwrap();
+        let skew = opened.0["clock_skew_ms"].as_i64().unwrap();
+        assert!((-62_000..=-59_000).contains(&skew), "got {skew}");
+
+        let report = Spi::get_one::<pgrx::JsonB>("SELECT kerai.clock_skew()")
             .unwrap()
             .unwrap();
-        assert_eq!(pk_hex.len(), 64, "Hex-encoded 32-byte key should be 64 chars");
+        let peer = report.0["peers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|p| p["instance"] == "slow-clock")
+            .cloned()
+            .unwrap();
+        assert_eq!(peer["clock_skew_ms"], skew);
+        assert_eq!(peer["skewed"], true);
+        assert!(report.0["clock"].as_i64().is_some());
     }
 
     #[pg_test]
-    fn test_ops_since_includes_public_key() {
-        // Create an op first
+    fn test_public_api_views() {
         Spi::run(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"pk_test\", \"position\": 0}'::jsonb)",
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"api1\", \"position\": 0}'::jsonb)",
         )
         .unwrap();
-
-        let fp = Spi::get_one::<String>(
-            "SELECT key_fingerprint FROM kerai.instances WHERE is_self = true",
+        let nodes = Spi::get_one::<i64>("SELECT count(*)::bigint FROM kerai.v1_nodes WHERE content = 'api1'")
+            .unwrap()
+            .unwrap();
+        assert_eq!(nodes, 1);
+        let history = Spi::get_one::<i64>(
+            "SELECT count(*)::bigint FROM kerai.v1_history h JOIN kerai.v1_nodes n ON n.id = h.node_id WHERE n.content = 'api1'",
         )
         .unwrap()
         .unwrap();
+        assert!(history >= 1);
 
-        let ops = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.ops_since('{}', 0)",
-            fp.replace('\'', "''"),
-        ))
-        .unwrap()
-        .unwrap();
-        let arr = ops.0.as_array().unwrap();
-        assert!(!arr.is_empty());
-        // Each op should have a public_key field
-        let first = &arr[0];
-        assert!(first.get("public_key").is_some(), "ops_since should include public_key");
-        let pk = first["public_key"].as_str().unwrap();
-        assert_eq!(pk.len(), 64, "public_key should be 64 hex chars");
-    }
-
-    // --- Plan 07: Query / Navigation tests ---
+        let views = Spi::get_one::<pgrx::JsonB>("SELECT kerai.api_views()").unwrap().unwrap();
+        let views = views.0.as_array().unwrap();
+        assert_eq!(views.len(), 5, "got {:?}", views);
+        let v2 = views.iter().find(|v| v["name"] == "v2_history").unwrap();
+        assert!(v2["columns"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|c| c["name"] == "wall_time"));
+        let balances = views.iter().find(|v| v["name"] == "v1_balances").unwrap();
+        assert_eq!(balances["status"], "current");
+        assert!(balances["columns"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|c| c["name"] == "balance_nkoi" && c["comment"].is_string()));
 
-    #[pg_test]
-    fn test_find_by_content_pattern() {
-        Spi::run("SELECT kerai.parse_source('fn hello_world() {} fn hello_there() {} fn goodbye() {}', 'find_pattern.rs')").unwrap();
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.find('%hello%', NULL, NULL)",
+        // A v2 view supersedes v1 without changing it
+        Spi::run("CREATE VIEW kerai.v2_nodes AS SELECT id, kind FROM kerai.nodes").unwrap();
+        Spi::run("INSERT INTO kerai.api_views (name, version, description) VALUES ('v2_nodes', 2, 'Nodes')")
+            .unwrap();
+        let entry = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.deprecate_api_view('v1_nodes', 'v2_nodes', '2030-01-01')",
         )
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        let fn_matches: Vec<_> = arr.iter().filter(|v| v["kind"].as_str() == Some("fn")).collect();
-        assert!(fn_matches.len() >= 2, "Should find at least 2 fn nodes matching '%hello%', got {}", fn_matches.len());
-    }
-
-    #[pg_test]
-    fn test_find_with_kind_filter() {
-        Spi::run("SELECT kerai.parse_source('struct Hello; fn Hello() {}', 'find_kind.rs')").unwrap();
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.find('Hello', 'struct', NULL)",
+        assert_eq!(entry.0["replaced_by"], "v2_nodes");
+        let comment = Spi::get_one::<String>("SELECT obj_description('kerai.v1_nodes'::regclass)")
+            .unwrap()
+            .unwrap();
+        assert!(comment.starts_with("DEPRECATED: use kerai.v2_nodes, removed after 2030-01-01"), "got {comment}");
+        let status = Spi::get_one::<String>(
+            "SELECT v->>'status' FROM jsonb_array_elements(kerai.api_views()) v WHERE v->>'name' = 'v1_nodes'",
         )
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        assert!(!arr.is_empty(), "Should find at least one struct named Hello");
-        for item in arr {
-            assert_eq!(item["kind"].as_str().unwrap(), "struct", "All results should be struct kind");
-        }
+        assert_eq!(status, "deprecated");
     }
 
     #[pg_test]
-    fn test_find_with_limit() {
-        Spi::run("SELECT kerai.parse_source('fn aaa() {} fn aab() {} fn aac() {}', 'find_limit.rs')").unwrap();
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.find('a%', NULL, 1)",
+    fn test_row_level_security_follows_path_grants() {
+        let user_id = Spi::get_one::<String>(
+            "INSERT INTO kerai.users (handle, is_allowed) VALUES ('rls-user', true) RETURNING id::text",
         )
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        assert!(arr.len() <= 1, "Limit=1 should return at most 1 result, got {}", arr.len());
-    }
-
-    #[pg_test]
-    fn test_find_no_matches() {
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.find('zzz_nonexistent_xyz_%', NULL, NULL)",
-        )
-        .unwrap()
+        Spi::run(&format!(
+            "WITH w AS (INSERT INTO kerai.workspaces (user_id, name) VALUES ('{0}', 'rls') RETURNING id)
+             INSERT INTO kerai.sessions (user_id, workspace_id, token) SELECT '{0}', id, 'rls-token' FROM w",
+            sql_escape(&user_id),
+        ))
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        assert!(arr.is_empty(), "Nonexistent pattern should return empty array");
-    }
-
-    #[pg_test]
-    fn test_refs_finds_definitions_and_impls() {
-        let source = "struct Config {} impl Config { fn new() -> Self { Config {} } }";
+        for path in ["rlsdemo.shared", "rlsdemo.private"] {
+            Spi::run(&format!(
+                "SELECT kerai.apply_op('insert_node', NULL, '{{\"kind\": \"fn\", \"content\": \"{0}\", \"position\": 0, \"path\": \"{0}\"}}'::jsonb)",
+                path,
+            ))
+            .unwrap();
+        }
         Spi::run(&format!(
-            "SELECT kerai.parse_source('{}', 'refs_test.rs')",
-            source.replace('\'', "''"),
+            "SELECT kerai.grant_path('rlsdemo.shared', '{}'::uuid)",
+            sql_escape(&user_id),
         ))
         .unwrap();
 
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.refs('Config')",
+        let enabled = Spi::get_one::<pgrx::JsonB>("SELECT kerai.enable_rls()").unwrap().unwrap();
+        assert_eq!(enabled.0["policies"], 12);
+        // Running it again just recreates the policies
+        Spi::run("SELECT kerai.enable_rls()").unwrap();
+
+        Spi::run(
+            "CREATE ROLE kerai_rls_tester NOLOGIN;
+             GRANT USAGE ON SCHEMA kerai TO kerai_rls_tester;
+             GRANT SELECT ON kerai.nodes TO kerai_rls_tester;
+             SET LOCAL ROLE kerai_rls_tester",
         )
-        .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert_eq!(obj["symbol"].as_str().unwrap(), "Config");
+        let visible = || {
+            Spi::get_one::<i64>("SELECT count(*)::bigint FROM kerai.nodes WHERE path <@ 'rlsdemo'")
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(visible(), 0, "no principal sees nothing");
 
-        let defs = obj["definitions"].as_array().unwrap();
-        assert!(!defs.is_empty(), "Should find at least 1 definition of Config");
+        let principal = Spi::get_one::<pgrx::JsonB>("SELECT kerai.set_principal('rls-token', true)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.0["user_id"], serde_json::json!(user_id));
+        assert_eq!(visible(), 1, "only the granted subtree is visible");
+        let can_write = Spi::get_one::<bool>("SELECT kerai.rls_can_access('rlsdemo.shared', true)")
+            .unwrap()
+            .unwrap();
+        assert!(!can_write, "a read grant does not allow writes");
 
-        let impls = obj["impls"].as_array().unwrap();
-        assert!(!impls.is_empty(), "Should find at least 1 impl of Config");
+        Spi::run("RESET ROLE").unwrap();
+        Spi::run("SELECT kerai.disable_rls()").unwrap();
     }
 
     #[pg_test]
-    fn test_refs_nonexistent_symbol() {
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.refs('zzz_nonexistent_symbol_xyz')",
+    fn test_row_level_security_filters_an_api_token_principal() {
+        // kerai serve and kerai-web call set_principal with the request's
+        // API token; the superuser running the tests bypasses policies, so
+        // the reads go through a role they bind.
+        let user_id = Spi::get_one::<String>(
+            "INSERT INTO kerai.users (handle, is_allowed) VALUES ('rls-api', true) RETURNING id::text",
         )
         .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert!(obj["definitions"].as_array().unwrap().is_empty());
-        assert!(obj["references"].as_array().unwrap().is_empty());
-        assert!(obj["impls"].as_array().unwrap().is_empty());
-    }
-
-    #[pg_test]
-    fn test_tree_top_level() {
-        Spi::run("SELECT kerai.parse_source('fn top_fn() {}', 'tree_top.rs')").unwrap();
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.tree(NULL)",
-        )
+        let token = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.create_api_token('rls', '{{read}}', '{}'::uuid)",
+            sql_escape(&user_id),
+        ))
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        let file_nodes: Vec<_> = arr.iter().filter(|v| v["kind"].as_str() == Some("file")).collect();
-        assert!(!file_nodes.is_empty(), "Top-level tree should include file nodes");
-    }
-
-    #[pg_test]
-    fn test_tree_with_path() {
-        Spi::run("SELECT kerai.parse_source('fn nested() {}', 'tree_path.rs')").unwrap();
-        // Get the file node's path
-        let file_path = Spi::get_one::<String>(
-            "SELECT path::text FROM kerai.nodes WHERE kind = 'file' AND content = 'tree_path.rs'",
+        let token = token.0["token"].as_str().unwrap().to_string();
+        for path in ["rlsapi.shared", "rlsapi.private"] {
+            Spi::run(&format!(
+                "SELECT kerai.apply_op('insert_node', NULL, '{{\"kind\": \"fn\", \"content\": \"{0}\", \"position\": 0, \"path\": \"{0}\"}}'::jsonb)",
+                path,
+            ))
+            .unwrap();
+        }
+        Spi::run(
+            "CREATE ROLE kerai_rls_api_tester NOLOGIN;
+             GRANT USAGE ON SCHEMA kerai TO kerai_rls_api_tester;
+             GRANT SELECT ON kerai.nodes TO kerai_rls_api_tester",
         )
-        .unwrap()
         .unwrap();
+        let visible = || {
+            Spi::run("SET LOCAL ROLE kerai_rls_api_tester").unwrap();
+            let count = Spi::get_one::<i64>(
+                "SELECT count(*)::bigint FROM kerai.nodes WHERE path <@ 'rlsapi'",
+            )
+            .unwrap()
+            .unwrap();
+            Spi::run("RESET ROLE").unwrap();
+            count
+        };
+        Spi::run("SELECT kerai.enable_rls()").unwrap();
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.tree('{}')",
-            sql_escape(&file_path),
+        let principal = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.set_principal('{}', true)",
+            sql_escape(&token),
         ))
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        assert!(!arr.is_empty(), "Tree with file path should find descendants");
+        assert_eq!(principal.0["user_id"], serde_json::json!(user_id));
+        assert_eq!(visible(), 0, "a user with no grants sees nothing");
+
+        Spi::run(&format!(
+            "SELECT kerai.grant_path('rlsapi.shared', '{}'::uuid)",
+            sql_escape(&user_id),
+        ))
+        .unwrap();
+        assert_eq!(visible(), 1, "only the granted subtree is visible");
+
+        Spi::run(&format!(
+            "UPDATE kerai.api_tokens SET revoked_at = now() WHERE user_id = '{}'::uuid",
+            sql_escape(&user_id),
+        ))
+        .unwrap();
+        assert_eq!(visible(), 0, "a revoked token acts for no one");
+
+        Spi::run("SELECT kerai.disable_rls()").unwrap();
+        assert_eq!(visible(), 2);
     }
 
     #[pg_test]
-    fn test_children_of_file_node() {
-        Spi::run("SELECT kerai.parse_source('fn child_a() {} fn child_b() {}', 'children_test.rs')").unwrap();
-        let file_id = Spi::get_one::<pgrx::Uuid>(
-            "SELECT id FROM kerai.nodes WHERE kind = 'file' AND content = 'children_test.rs'",
+    #[should_panic(expected = "No active session")]
+    fn test_set_principal_rejects_unknown_token() {
+        Spi::run("SELECT kerai.set_principal('no-such-token')").unwrap();
+    }
+
+    #[pg_test]
+    fn test_new_ids_are_time_ordered_uuid7() {
+        let ids = Spi::get_one::<pgrx::JsonB>(
+            "SELECT jsonb_agg(id ORDER BY n) FROM (
+                SELECT n, kerai.uuid7()::text AS id FROM generate_series(1, 500) n
+            ) s",
         )
         .unwrap()
-        .unwrap();
+        .unwrap()
+        .0;
+        let ids: Vec<&str> = ids
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect();
+        assert!(ids.iter().all(|id| &id[14..15] == "7"), "{:?}", &ids[..3]);
+        assert!(
+            ids.windows(2).all(|w| w[0] < w[1]),
+            "uuid7 ids must sort in creation order"
+        );
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.children('{}'::uuid)",
-            file_id,
-        ))
+        // Parsers and column defaults make v7 ids too
+        Spi::run("SELECT kerai.parse_markdown('# Ordered\n\nIds.\n', 'uuid7.md')").unwrap();
+        let (parsed, v7) = Spi::get_two::<i64, i64>(
+            "SELECT count(*), count(*) FILTER (WHERE substr(id::text, 15, 1) = '7')
+             FROM kerai.nodes WHERE created_at = now()",
+        )
+        .unwrap();
+        assert!(parsed.unwrap() >= 3);
+        assert_eq!(parsed, v7);
+        let default = Spi::get_one::<String>(
+            "SELECT pg_get_expr(adbin, adrelid) FROM pg_attrdef
+             WHERE adrelid = 'kerai.nodes'::regclass AND adnum = 1",
+        )
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        let fn_children: Vec<_> = arr.iter().filter(|v| v["kind"].as_str() == Some("fn")).collect();
-        assert!(fn_children.len() >= 2, "File node should have at least 2 fn children, got {}", fn_children.len());
+        assert_eq!(default, "kerai.uuid7()");
+
+        let bench = Spi::get_one::<pgrx::JsonB>("SELECT kerai.benchmark_uuid_index(20000)")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert!(
+            bench["v7"]["index_bytes"].as_i64() < bench["v4"]["index_bytes"].as_i64(),
+            "{bench}"
+        );
+        assert!(bench["index_saving_pct"].as_f64().unwrap() > 0.0, "{bench}");
     }
 
     #[pg_test]
-    fn test_ancestors_of_nested_node() {
-        Spi::run("SELECT kerai.parse_source('fn outer() { let x = 1; }', 'ancestors_test.rs')").unwrap();
-        // Find a stmt_local node (the `let x = 1;`)
-        let local_id = Spi::get_one::<pgrx::Uuid>(
-            "SELECT id FROM kerai.nodes WHERE kind = 'stmt_local' AND content = 'x' LIMIT 1",
+    fn test_partitions_hold_versions_by_timestamp() {
+        Spi::run(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"part1\", \"position\": 0}'::jsonb)",
         )
         .unwrap();
 
-        if let Some(nid) = local_id {
-            let result = Spi::get_one::<pgrx::JsonB>(&format!(
-                "SELECT kerai.ancestors('{}'::uuid)",
-                nid,
-            ))
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.create_partitions()")
             .unwrap()
             .unwrap();
-            let arr = result.0.as_array().unwrap();
-            assert!(!arr.is_empty(), "Nested node should have ancestors");
-            // Should eventually reach a file node
-            let has_file = arr.iter().any(|v| v["kind"].as_str() == Some("file"));
-            assert!(has_file, "Ancestors should include the file node");
-        }
-    }
+        assert_eq!(result.0["versions"]["partitioned"], true, "got {:?}", result.0);
+        assert_eq!(result.0["ledger"]["partitioned"], true, "got {:?}", result.0);
 
-    // --- Plan 08: Agent perspectives tests ---
+        let in_default = Spi::get_one::<i64>("SELECT count(*)::bigint FROM kerai.versions_default")
+            .unwrap()
+            .unwrap();
+        assert_eq!(in_default, 0, "versions should move out of the default partition");
 
-    #[pg_test]
-    fn test_register_agent() {
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.register_agent('test-llm', 'llm', 'claude-opus-4-6', NULL)",
-        )
-        .unwrap()
-        .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert_eq!(obj["name"].as_str().unwrap(), "test-llm");
-        assert_eq!(obj["kind"].as_str().unwrap(), "llm");
-        assert!(obj["is_new"].as_bool().unwrap());
-        assert!(obj.contains_key("id"));
+        // Hybrid clock timestamps get a range of their own, not one from 0
+        let ts = Spi::get_one::<i64>("SELECT kerai.lamport_clock()").unwrap().unwrap();
+        let parts = Spi::get_one::<pgrx::JsonB>("SELECT kerai.list_partitions()")
+            .unwrap()
+            .unwrap();
+        assert!(
+            parts.0.as_array().unwrap().iter().any(|p| p["table"] == "versions"
+                && p["from_ts"].as_i64().is_some_and(|from| from > 0 && from <= ts)
+                && p["to_ts"].as_i64().is_some_and(|to| ts < to)),
+            "got {:?}",
+            parts.0
+        );
+
+        let rows = Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.versions_range({ts}, {}, NULL)", ts + 1))
+            .unwrap()
+            .unwrap();
+        assert!(
+            rows.0.as_array().unwrap().iter().any(|v| v["new_content"] == "part1"),
+            "got {:?}",
+            rows.0
+        );
+
+        // A second pass has nothing left to create
+        let again = Spi::get_one::<pgrx::JsonB>("SELECT kerai.create_partitions()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(again.0["versions"]["created"], serde_json::json!([]));
     }
 
     #[pg_test]
-    fn test_register_agent_idempotent() {
-        Spi::run("SELECT kerai.register_agent('idem-agent', 'llm', 'model-a', NULL)")
+    fn test_crdt_lamport_clock_increments() {
+        let before = Spi::get_one::<i64>("SELECT kerai.lamport_clock()")
+            .unwrap()
             .unwrap();
-        let r2 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.register_agent('idem-agent', 'tool', 'model-b', NULL)",
+
+        Spi::run(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"lc_fn\", \"position\": 0}'::jsonb)",
         )
-        .unwrap()
         .unwrap();
-        let obj = r2.0.as_object().unwrap();
-        assert!(!obj["is_new"].as_bool().unwrap());
-        assert_eq!(obj["kind"].as_str().unwrap(), "tool");
+
+        let after = Spi::get_one::<i64>("SELECT kerai.lamport_clock()")
+            .unwrap()
+            .unwrap();
+        assert!(after > before, "Lamport clock should increase after an op");
     }
 
     #[pg_test]
-    fn test_list_agents() {
-        Spi::run("SELECT kerai.register_agent('list-agent', 'human', NULL, NULL)")
-            .unwrap();
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.list_agents(NULL)",
+    fn test_ops_are_stamped_with_hybrid_clocks() {
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let first = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"hlc_fn\", \"position\": 0}'::jsonb)",
         )
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        let names: Vec<&str> = arr.iter().filter_map(|v| v["name"].as_str()).collect();
-        assert!(names.contains(&"list-agent"));
-    }
+        let ts = first.0["lamport_ts"].as_i64().unwrap();
+        assert!(((ts >> 16) - now_ms).abs() < 60_000, "physical part of {ts} is not now");
 
-    #[pg_test]
-    fn test_remove_agent() {
-        Spi::run("SELECT kerai.register_agent('remove-agent', 'tool', NULL, NULL)")
-            .unwrap();
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.remove_agent('remove-agent')",
-        )
+        // A second op in the same millisecond still moves forward
+        let second = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.apply_op('update_content', '{}'::uuid, '{{\"new_content\": \"hlc_fn2\"}}'::jsonb)",
+            first.0["node_id"].as_str().unwrap(),
+        ))
         .unwrap()
         .unwrap();
-        assert!(result.0["removed"].as_bool().unwrap());
+        assert!(second.0["lamport_ts"].as_i64().unwrap() > ts);
+
+        // History shows the stamp and the wall time it encodes
+        let (hlc, off_secs) = Spi::get_two::<i64, f64>(&format!(
+            "SELECT hlc, abs(extract(epoch FROM wall_time - now()))::float8
+             FROM kerai.v2_history WHERE node_id = '{}'::uuid ORDER BY hlc LIMIT 1",
+            first.0["node_id"].as_str().unwrap(),
+        ))
+        .unwrap();
+        assert_eq!(hlc, Some(ts));
+        assert!(off_secs.unwrap() < 60.0, "got {:?}", off_secs);
+        let legacy = Spi::get_one::<bool>("SELECT kerai.hlc_wall_time(12345) IS NULL")
+            .unwrap()
+            .unwrap();
+        assert!(legacy, "Lamport counters have no wall time");
     }
 
     #[pg_test]
-    fn test_set_perspective() {
-        // Register agent and create a node
-        Spi::run("SELECT kerai.register_agent('persp-agent', 'llm', NULL, NULL)")
-            .unwrap();
-        let node = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"persp_fn\", \"position\": 0}'::jsonb)",
+    #[should_panic(expected = "duplicate key value violates unique constraint")]
+    fn test_crdt_idempotent_replay() {
+        // Get current author fingerprint
+        let fp = Spi::get_one::<String>(
+            "SELECT key_fingerprint FROM kerai.instances WHERE is_self = true",
         )
         .unwrap()
         .unwrap();
-        let node_id = node.0["node_id"].as_str().unwrap();
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.set_perspective('persp-agent', '{}'::uuid, 0.8, NULL, 'important function')",
-            node_id,
-        ))
+        // Apply an op
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"replay_fn\", \"position\": 0}'::jsonb)",
+        )
         .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert_eq!(obj["agent"].as_str().unwrap(), "persp-agent");
-        assert_eq!(obj["weight"].as_f64().unwrap(), 0.8);
+        let seq = result.0["author_seq"].as_i64().unwrap();
+        let ts = result.0["lamport_ts"].as_i64().unwrap();
 
-        // Verify stored
-        let count = Spi::get_one::<i64>(&format!(
-            "SELECT count(*)::bigint FROM kerai.perspectives WHERE node_id = '{}'::uuid",
-            node_id,
+        // Manually try to insert a duplicate (author, author_seq) — should fail
+        Spi::run(&format!(
+            "INSERT INTO kerai.operations (instance_id, op_type, author, lamport_ts, author_seq, payload)
+             SELECT id, 'insert_node', '{}', {}, {}, '{{}}'::jsonb FROM kerai.instances WHERE is_self = true",
+            fp.replace('\'', "''"),
+            ts + 1,
+            seq,
         ))
-        .unwrap()
         .unwrap();
-        assert_eq!(count, 1);
     }
 
     #[pg_test]
-    fn test_set_perspective_with_context() {
-        Spi::run("SELECT kerai.register_agent('ctx-agent', 'llm', NULL, NULL)")
-            .unwrap();
-        // Create two nodes — one as target, one as context
+    fn test_crdt_insert_and_delete_edge() {
+        // Create two nodes
         let n1 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"ctx_target\", \"position\": 0}'::jsonb)",
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"edge_src\", \"position\": 0}'::jsonb)",
         )
         .unwrap()
         .unwrap();
+        let src_id = n1.0["node_id"].as_str().unwrap().to_string();
+
         let n2 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"module\", \"content\": \"ctx_scope\", \"position\": 1}'::jsonb)",
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"edge_tgt\", \"position\": 1}'::jsonb)",
         )
         .unwrap()
         .unwrap();
-        let target_id = n1.0["node_id"].as_str().unwrap();
-        let context_id = n2.0["node_id"].as_str().unwrap();
+        let tgt_id = n2.0["node_id"].as_str().unwrap().to_string();
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.set_perspective('ctx-agent', '{}'::uuid, 0.5, '{}'::uuid, NULL)",
-            target_id, context_id,
+        // Insert edge
+        Spi::run(&format!(
+            "SELECT kerai.apply_op('insert_edge', '{}'::uuid, '{{\"target_id\": \"{}\", \"relation\": \"calls\"}}'::jsonb)",
+            src_id, tgt_id,
         ))
-        .unwrap()
         .unwrap();
-        assert!(result.0["context_id"].as_str().is_some());
-    }
 
-    #[pg_test]
-    fn test_delete_perspective() {
-        Spi::run("SELECT kerai.register_agent('del-persp-agent', 'llm', NULL, NULL)")
-            .unwrap();
-        let node = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"del_persp_fn\", \"position\": 0}'::jsonb)",
-        )
+        let edge_count = Spi::get_one::<i64>(&format!(
+            "SELECT count(*)::bigint FROM kerai.edges WHERE source_id = '{}'::uuid AND target_id = '{}'::uuid AND relation = 'calls'",
+            src_id, tgt_id,
+        ))
         .unwrap()
         .unwrap();
-        let node_id = node.0["node_id"].as_str().unwrap();
+        assert_eq!(edge_count, 1, "Edge should exist");
 
+        // Delete edge
         Spi::run(&format!(
-            "SELECT kerai.set_perspective('del-persp-agent', '{}'::uuid, 0.9, NULL, NULL)",
-            node_id,
+            "SELECT kerai.apply_op('delete_edge', '{}'::uuid, '{{\"target_id\": \"{}\", \"relation\": \"calls\"}}'::jsonb)",
+            src_id, tgt_id,
         ))
         .unwrap();
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.delete_perspective('del-persp-agent', '{}'::uuid, NULL)",
-            node_id,
+        let edge_count2 = Spi::get_one::<i64>(&format!(
+            "SELECT count(*)::bigint FROM kerai.edges WHERE source_id = '{}'::uuid AND target_id = '{}'::uuid AND relation = 'calls'",
+            src_id, tgt_id,
         ))
         .unwrap()
         .unwrap();
-        assert!(result.0["deleted"].as_bool().unwrap());
+        assert_eq!(edge_count2, 0, "Edge should be deleted");
     }
 
     #[pg_test]
-    fn test_get_perspectives_with_filter() {
-        Spi::run("SELECT kerai.register_agent('filter-agent', 'llm', NULL, NULL)")
-            .unwrap();
-        let n1 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"high_fn\", \"position\": 0}'::jsonb)",
-        )
-        .unwrap()
-        .unwrap();
-        let n2 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"low_fn\", \"position\": 1}'::jsonb)",
+    fn test_crdt_signature_present() {
+        Spi::run(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"sig_fn\", \"position\": 0}'::jsonb)",
         )
-        .unwrap()
-        .unwrap();
-        let high_id = n1.0["node_id"].as_str().unwrap();
-        let low_id = n2.0["node_id"].as_str().unwrap();
-
-        Spi::run(&format!(
-            "SELECT kerai.set_perspective('filter-agent', '{}'::uuid, 0.9, NULL, NULL)",
-            high_id,
-        ))
-        .unwrap();
-        Spi::run(&format!(
-            "SELECT kerai.set_perspective('filter-agent', '{}'::uuid, 0.2, NULL, NULL)",
-            low_id,
-        ))
         .unwrap();
 
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.get_perspectives('filter-agent', NULL, 0.5)",
+        let sig_len = Spi::get_one::<i32>(
+            "SELECT octet_length(signature) FROM kerai.operations ORDER BY created_at DESC LIMIT 1",
         )
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        assert_eq!(arr.len(), 1, "Only the high-weight perspective should pass filter");
-        assert_eq!(arr[0]["weight"].as_f64().unwrap(), 0.9);
+        assert_eq!(sig_len, 64, "Ed25519 signature should be 64 bytes");
     }
 
     #[pg_test]
-    fn test_set_association() {
-        Spi::run("SELECT kerai.register_agent('assoc-agent', 'llm', NULL, NULL)")
-            .unwrap();
-        let n1 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"assoc_src\", \"position\": 0}'::jsonb)",
+    fn test_crdt_ops_since_returns_operations() {
+        let fp = Spi::get_one::<String>(
+            "SELECT key_fingerprint FROM kerai.instances WHERE is_self = true",
         )
         .unwrap()
         .unwrap();
-        let n2 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"assoc_tgt\", \"position\": 1}'::jsonb)",
+
+        // Apply two ops
+        Spi::run(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"ops_since_1\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap();
+        let r2 = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"ops_since_2\", \"position\": 1}'::jsonb)",
         )
         .unwrap()
         .unwrap();
-        let src_id = n1.0["node_id"].as_str().unwrap();
-        let tgt_id = n2.0["node_id"].as_str().unwrap();
+        let seq2 = r2.0["author_seq"].as_i64().unwrap();
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.set_association('assoc-agent', '{}'::uuid, '{}'::uuid, 0.7, 'depends_on', 'tight coupling')",
-            src_id, tgt_id,
+        // Get ops since seq2-1 (should include at least the last op)
+        let ops = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.ops_since('{}', {})",
+            fp.replace('\'', "''"),
+            seq2 - 1,
         ))
         .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert_eq!(obj["relation"].as_str().unwrap(), "depends_on");
-        assert_eq!(obj["weight"].as_f64().unwrap(), 0.7);
+        let arr = ops.0.as_array().unwrap();
+        assert!(!arr.is_empty(), "ops_since should return at least one op");
     }
 
     #[pg_test]
-    fn test_delete_association() {
-        Spi::run("SELECT kerai.register_agent('del-assoc-agent', 'llm', NULL, NULL)")
-            .unwrap();
-        let n1 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"del_assoc_src\", \"position\": 0}'::jsonb)",
-        )
-        .unwrap()
-        .unwrap();
-        let n2 = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"del_assoc_tgt\", \"position\": 1}'::jsonb)",
+    #[should_panic(expected = "Unknown op_type")]
+    fn test_crdt_invalid_op_type() {
+        Spi::run(
+            "SELECT kerai.apply_op('bogus_op', NULL, '{}'::jsonb)",
         )
-        .unwrap()
         .unwrap();
-        let src_id = n1.0["node_id"].as_str().unwrap();
-        let tgt_id = n2.0["node_id"].as_str().unwrap();
+    }
 
-        Spi::run(&format!(
-            "SELECT kerai.set_association('del-assoc-agent', '{}'::uuid, '{}'::uuid, 0.5, 'similar_to', NULL)",
-            src_id, tgt_id,
-        ))
+    #[pg_test]
+    #[should_panic(expected = "requires a node_id")]
+    fn test_crdt_update_without_node_id() {
+        Spi::run(
+            "SELECT kerai.apply_op('update_content', NULL, '{\"new_content\": \"x\"}'::jsonb)",
+        )
         .unwrap();
+    }
+
+    // --- Plan 06: Peer sync tests ---
+
+    /// Generate a test Ed25519 keypair. Returns (public_key_hex, fingerprint).
+    fn generate_test_keypair() -> (String, String) {
+        let mut rng = rand::rngs::OsRng;
+        let signing_key = ed25519_dalek::SigningKey::generate(&mut rng);
+        let verifying_key = signing_key.verifying_key();
+        let pk_hex: String = verifying_key.as_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+        let fp = crate::identity::fingerprint(&verifying_key);
+        (pk_hex, fp)
+    }
 
+    #[pg_test]
+    fn test_register_peer() {
+        let (pk_hex, _fp) = generate_test_keypair();
         let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.delete_association('del-assoc-agent', '{}'::uuid, '{}'::uuid, 'similar_to')",
-            src_id, tgt_id,
+            "SELECT kerai.register_peer('test-peer-1', '{}', 'https://peer1.example.com', NULL)",
+            pk_hex,
         ))
         .unwrap()
         .unwrap();
-        assert!(result.0["deleted"].as_bool().unwrap());
+        let obj = result.0.as_object().unwrap();
+        assert_eq!(obj["name"].as_str().unwrap(), "test-peer-1");
+        assert!(obj["is_new"].as_bool().unwrap());
+        assert!(obj.contains_key("key_fingerprint"));
+        assert!(obj.contains_key("id"));
     }
 
     #[pg_test]
-    fn test_consensus_multiple_agents() {
-        // Register two agents
-        Spi::run("SELECT kerai.register_agent('cons-agent-1', 'llm', NULL, NULL)")
-            .unwrap();
-        Spi::run("SELECT kerai.register_agent('cons-agent-2', 'llm', NULL, NULL)")
-            .unwrap();
-
-        // Create a node
-        let node = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"consensus_fn\", \"position\": 0}'::jsonb)",
-        )
+    fn test_register_peer_idempotent() {
+        let (pk_hex, _) = generate_test_keypair();
+        // First registration
+        let r1 = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.register_peer('peer-idem', '{}', NULL, NULL)",
+            pk_hex,
+        ))
         .unwrap()
         .unwrap();
-        let node_id = node.0["node_id"].as_str().unwrap();
+        assert!(r1.0["is_new"].as_bool().unwrap());
 
-        // Both agents rate the same node
-        Spi::run(&format!(
-            "SELECT kerai.set_perspective('cons-agent-1', '{}'::uuid, 0.8, NULL, NULL)",
-            node_id,
+        // Second registration with updated endpoint
+        let r2 = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.register_peer('peer-idem-updated', '{}', 'https://updated.example.com', NULL)",
+            pk_hex,
         ))
+        .unwrap()
         .unwrap();
+        assert!(!r2.0["is_new"].as_bool().unwrap());
+        assert_eq!(r2.0["endpoint"].as_str().unwrap(), "https://updated.example.com");
+    }
+
+    #[pg_test]
+    fn test_list_peers_after_add() {
+        let (pk_hex, _) = generate_test_keypair();
         Spi::run(&format!(
-            "SELECT kerai.set_perspective('cons-agent-2', '{}'::uuid, 0.6, NULL, NULL)",
-            node_id,
+            "SELECT kerai.register_peer('list-test-peer', '{}', NULL, NULL)",
+            pk_hex,
         ))
         .unwrap();
 
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.consensus(NULL, 2, NULL)",
-        )
-        .unwrap()
-        .unwrap();
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.list_peers()")
+            .unwrap()
+            .unwrap();
         let arr = result.0.as_array().unwrap();
-        assert!(!arr.is_empty(), "Should find consensus with 2+ agents");
-        let first = &arr[0];
-        assert_eq!(first["agent_count"].as_i64().unwrap(), 2);
-        let avg = first["avg_weight"].as_f64().unwrap();
-        assert!((avg - 0.7).abs() < 0.001, "Average should be ~0.7, got {}", avg);
+        let names: Vec<&str> = arr.iter().filter_map(|v| v["name"].as_str()).collect();
+        assert!(names.contains(&"list-test-peer"), "Registered peer should appear in list");
     }
 
     #[pg_test]
-    fn test_perspective_diff() {
-        Spi::run("SELECT kerai.register_agent('diff-agent-a', 'llm', NULL, NULL)")
-            .unwrap();
-        Spi::run("SELECT kerai.register_agent('diff-agent-b', 'llm', NULL, NULL)")
-            .unwrap();
-
-        // Create shared and unique nodes
-        let shared = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"diff_shared\", \"position\": 0}'::jsonb)",
-        )
-        .unwrap()
-        .unwrap();
-        let only_a_node = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"diff_only_a\", \"position\": 1}'::jsonb)",
-        )
-        .unwrap()
-        .unwrap();
-        let shared_id = shared.0["node_id"].as_str().unwrap();
-        let only_a_id = only_a_node.0["node_id"].as_str().unwrap();
-
-        // Agent A rates both nodes with different weights
+    fn test_get_peer() {
+        let (pk_hex, fp) = generate_test_keypair();
         Spi::run(&format!(
-            "SELECT kerai.set_perspective('diff-agent-a', '{}'::uuid, 0.9, NULL, NULL)",
-            shared_id,
+            "SELECT kerai.register_peer('get-test-peer', '{}', NULL, NULL)",
+            pk_hex,
         ))
         .unwrap();
-        Spi::run(&format!(
-            "SELECT kerai.set_perspective('diff-agent-a', '{}'::uuid, 0.5, NULL, NULL)",
-            only_a_id,
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.get_peer('{}')",
+            fp.replace('\'', "''"),
         ))
+        .unwrap()
         .unwrap();
+        assert_eq!(result.0["name"].as_str().unwrap(), "get-test-peer");
+    }
 
-        // Agent B rates shared node with different weight
+    #[pg_test]
+    fn test_remove_peer() {
+        let (pk_hex, _) = generate_test_keypair();
         Spi::run(&format!(
-            "SELECT kerai.set_perspective('diff-agent-b', '{}'::uuid, 0.3, NULL, NULL)",
-            shared_id,
+            "SELECT kerai.register_peer('remove-me', '{}', NULL, NULL)",
+            pk_hex,
         ))
         .unwrap();
 
         let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.perspective_diff('diff-agent-a', 'diff-agent-b', NULL)",
+            "SELECT kerai.remove_peer('remove-me')",
         )
         .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-
-        let only_in_a = obj["only_in_a"].as_array().unwrap();
-        assert!(!only_in_a.is_empty(), "Agent A should have unique perspectives");
-
-        let disagreements = obj["disagreements"].as_array().unwrap();
-        assert!(!disagreements.is_empty(), "Should have at least one disagreement on shared node");
-        let diff = disagreements[0]["diff"].as_f64().unwrap();
-        assert!((diff - 0.6).abs() < 0.001, "Diff should be ~0.6, got {}", diff);
+        assert!(result.0["removed"].as_bool().unwrap());
     }
 
-    // --- Plan 09: Swarm task tests ---
-
     #[pg_test]
-    fn test_create_task() {
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.create_task('Fix bug #42', 'cargo test', NULL, NULL, NULL)",
+    #[should_panic(expected = "Cannot remove self instance")]
+    fn test_cannot_remove_self() {
+        let self_name = Spi::get_one::<String>(
+            "SELECT name FROM kerai.instances WHERE is_self = true",
         )
         .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert_eq!(obj["description"].as_str().unwrap(), "Fix bug #42");
-        assert_eq!(obj["success_command"].as_str().unwrap(), "cargo test");
-        assert_eq!(obj["status"].as_str().unwrap(), "pending");
-        assert!(obj.contains_key("id"));
+
+        Spi::run(&format!(
+            "SELECT kerai.remove_peer('{}')",
+            self_name.replace('\'', "''"),
+        ))
+        .unwrap();
     }
 
     #[pg_test]
-    fn test_create_task_with_scope() {
-        // Create a scope node first
-        let node = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"module\", \"content\": \"scope_mod\", \"position\": 0}'::jsonb)",
+    fn test_self_public_key_hex() {
+        let pk_hex = Spi::get_one::<String>("SELECT kerai.self_public_key_hex()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(pk_hex.len(), 64, "Hex-encoded 32-byte key should be 64 chars");
+    }
+
+    #[pg_test]
+    fn test_ops_since_includes_public_key() {
+        // Create an op first
+        Spi::run(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"pk_test\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap();
+
+        let fp = Spi::get_one::<String>(
+            "SELECT key_fingerprint FROM kerai.instances WHERE is_self = true",
         )
         .unwrap()
         .unwrap();
-        let node_id = node.0["node_id"].as_str().unwrap();
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.create_task('Scoped task', 'make test', '{}'::uuid, 100, 300)",
-            node_id,
+        let ops = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.ops_since('{}', 0)",
+            fp.replace('\'', "''"),
         ))
         .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert_eq!(obj["status"].as_str().unwrap(), "pending");
-        assert!(obj["scope_node_id"].as_str().is_some());
-        assert_eq!(obj["budget_ops"].as_i64().unwrap(), 100);
-        assert_eq!(obj["budget_seconds"].as_i64().unwrap(), 300);
+        let arr = ops.0.as_array().unwrap();
+        assert!(!arr.is_empty());
+        // Each op should have a public_key field
+        let first = &arr[0];
+        assert!(first.get("public_key").is_some(), "ops_since should include public_key");
+        let pk = first["public_key"].as_str().unwrap();
+        assert_eq!(pk.len(), 64, "public_key should be 64 hex chars");
     }
 
-    #[pg_test]
-    fn test_list_tasks() {
-        Spi::run("SELECT kerai.create_task('Task A', 'cmd_a', NULL, NULL, NULL)")
-            .unwrap();
-        Spi::run("SELECT kerai.create_task('Task B', 'cmd_b', NULL, NULL, NULL)")
-            .unwrap();
+    // --- Plan 07: Query / Navigation tests ---
 
-        // List all
-        let all = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.list_tasks(NULL)",
+    #[pg_test]
+    fn test_find_by_content_pattern() {
+        Spi::run("SELECT kerai.parse_source('fn hello_world() {} fn hello_there() {} fn goodbye() {}', 'find_pattern.rs')").unwrap();
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.find('%hello%', NULL, NULL)",
         )
         .unwrap()
         .unwrap();
-        let arr = all.0.as_array().unwrap();
-        assert!(arr.len() >= 2, "Should have at least 2 tasks");
+        let arr = result.0.as_array().unwrap();
+        let fn_matches: Vec<_> = arr.iter().filter(|v| v["kind"].as_str() == Some("fn")).collect();
+        assert!(fn_matches.len() >= 2, "Should find at least 2 fn nodes matching '%hello%', got {}", fn_matches.len());
+    }
 
-        // List with filter
-        let pending = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.list_tasks('pending')",
+    #[pg_test]
+    fn test_find_with_kind_filter() {
+        Spi::run("SELECT kerai.parse_source('struct Hello; fn Hello() {}', 'find_kind.rs')").unwrap();
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.find('Hello', 'struct', NULL)",
         )
         .unwrap()
         .unwrap();
-        let parr = pending.0.as_array().unwrap();
-        for t in parr {
-            assert_eq!(t["status"].as_str().unwrap(), "pending");
+        let arr = result.0.as_array().unwrap();
+        assert!(!arr.is_empty(), "Should find at least one struct named Hello");
+        for item in arr {
+            assert_eq!(item["kind"].as_str().unwrap(), "struct", "All results should be struct kind");
         }
     }
 
     #[pg_test]
-    fn test_update_task_status() {
-        let task = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.create_task('Update me', 'test cmd', NULL, NULL, NULL)",
+    fn test_find_with_limit() {
+        Spi::run("SELECT kerai.parse_source('fn aaa() {} fn aab() {} fn aac() {}', 'find_limit.rs')").unwrap();
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.find('a%', NULL, 1)",
         )
         .unwrap()
         .unwrap();
-        let task_id = task.0["id"].as_str().unwrap();
+        let arr = result.0.as_array().unwrap();
+        assert!(arr.len() <= 1, "Limit=1 should return at most 1 result, got {}", arr.len());
+    }
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.update_task_status('{}'::uuid, 'running')",
-            task_id,
-        ))
+    #[pg_test]
+    fn test_find_no_matches() {
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.find('zzz_nonexistent_xyz_%', NULL, NULL)",
+        )
         .unwrap()
         .unwrap();
-        assert_eq!(result.0["status"].as_str().unwrap(), "running");
+        let arr = result.0.as_array().unwrap();
+        assert!(arr.is_empty(), "Nonexistent pattern should return empty array");
     }
 
     #[pg_test]
-    #[should_panic(expected = "Invalid task status")]
-    fn test_update_task_invalid_status() {
-        let task = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.create_task('Bad status', 'cmd', NULL, NULL, NULL)",
+    fn test_refs_finds_definitions_and_impls() {
+        let source = "struct Config {} impl Config { fn new() -> Self { Config {} } }";
+        Spi::run(&format!(
+            "SELECT kerai.parse_source('{}', 'refs_test.rs')",
+            source.replace('\'', "''"),
+        ))
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.refs('Config')",
         )
         .unwrap()
         .unwrap();
-        let task_id = task.0["id"].as_str().unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert_eq!(obj["symbol"].as_str().unwrap(), "Config");
 
-        Spi::run(&format!(
-            "SELECT kerai.update_task_status('{}'::uuid, 'bogus')",
-            task_id,
-        ))
-        .unwrap();
+        let defs = obj["definitions"].as_array().unwrap();
+        assert!(!defs.is_empty(), "Should find at least 1 definition of Config");
+
+        let impls = obj["impls"].as_array().unwrap();
+        assert!(!impls.is_empty(), "Should find at least 1 impl of Config");
     }
 
     #[pg_test]
-    fn test_launch_swarm() {
-        let task = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.create_task('Swarm task', 'cargo test', NULL, NULL, NULL)",
+    fn test_resolve_calls_links_callers_across_files() {
+        Spi::run(
+            "SELECT kerai.parse_source('pub fn shared_helper() {} struct Widget {} \
+             impl Widget { fn build() -> Self { Widget {} } fn size(&self) -> u32 { 1 } }', 'cg_lib.rs')",
+        )
+        .unwrap();
+        Spi::run(
+            "SELECT kerai.parse_source('fn run_all() { cg_lib::shared_helper(); \
+             let w = Widget::build(); w.size(); Vec::<u8>::new(); }', 'cg_main.rs')",
         )
-        .unwrap()
         .unwrap();
-        let task_id = task.0["id"].as_str().unwrap();
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.launch_swarm('{}'::uuid, 3, 'llm', 'claude-opus-4-6')",
-            task_id,
-        ))
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.resolve_calls()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.0["edges"], 3, "helper, build and size; Vec::new stays unresolved");
+        assert!(result.0["unresolved"].as_i64().unwrap() >= 1);
+
+        let refs = Spi::get_one::<pgrx::JsonB>("SELECT kerai.refs('shared_helper')")
+            .unwrap()
+            .unwrap();
+        let callers = refs.0["callers"].as_array().unwrap();
+        assert_eq!(callers.len(), 1);
+        assert_eq!(callers[0]["content"], "run_all");
+        assert_eq!(callers[0]["resolution"], "qualified");
+
+        // Re-running replaces rather than duplicates
+        let again = Spi::get_one::<pgrx::JsonB>("SELECT kerai.resolve_calls()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(again.0["edges"], 3);
+        let count = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.edges WHERE relation = 'calls' AND metadata->>'resolver' = 'resolve_calls'",
+        )
         .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert_eq!(obj["status"].as_str().unwrap(), "running");
-        assert_eq!(obj["agent_count"].as_i64().unwrap(), 3);
-        assert!(obj["swarm_name"].as_str().unwrap().starts_with("swarm-"));
+        assert_eq!(count, 3);
+    }
 
-        // Verify swarm agent was registered
-        let swarm_name = obj["swarm_name"].as_str().unwrap();
-        let agent_exists = Spi::get_one::<bool>(&format!(
-            "SELECT EXISTS(SELECT 1 FROM kerai.agents WHERE name = '{}')",
-            sql_escape(swarm_name),
-        ))
+    #[pg_test]
+    fn test_refs_nonexistent_symbol() {
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.refs('zzz_nonexistent_symbol_xyz')",
+        )
         .unwrap()
         .unwrap();
-        assert!(agent_exists, "Swarm agent should be registered");
+        let obj = result.0.as_object().unwrap();
+        assert!(obj["definitions"].as_array().unwrap().is_empty());
+        assert!(obj["references"].as_array().unwrap().is_empty());
+        assert!(obj["impls"].as_array().unwrap().is_empty());
     }
 
     #[pg_test]
-    fn test_stop_swarm() {
-        let task = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.create_task('Stop me', 'cmd', NULL, NULL, NULL)",
+    fn test_tree_top_level() {
+        Spi::run("SELECT kerai.parse_source('fn top_fn() {}', 'tree_top.rs')").unwrap();
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.tree(NULL)",
         )
         .unwrap()
         .unwrap();
-        let task_id = task.0["id"].as_str().unwrap();
+        let arr = result.0.as_array().unwrap();
+        let file_nodes: Vec<_> = arr.iter().filter(|v| v["kind"].as_str() == Some("file")).collect();
+        assert!(!file_nodes.is_empty(), "Top-level tree should include file nodes");
+    }
 
-        Spi::run(&format!(
-            "SELECT kerai.launch_swarm('{}'::uuid, 2, 'llm', NULL)",
-            task_id,
-        ))
+    #[pg_test]
+    fn test_tree_with_path() {
+        Spi::run("SELECT kerai.parse_source('fn nested() {}', 'tree_path.rs')").unwrap();
+        // Get the file node's path
+        let file_path = Spi::get_one::<String>(
+            "SELECT path::text FROM kerai.nodes WHERE kind = 'file' AND content = 'tree_path.rs'",
+        )
+        .unwrap()
         .unwrap();
 
         let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.stop_swarm('{}'::uuid)",
-            task_id,
+            "SELECT kerai.tree('{}')",
+            sql_escape(&file_path),
         ))
         .unwrap()
         .unwrap();
-        assert_eq!(result.0["status"].as_str().unwrap(), "stopped");
+        let arr = result.0.as_array().unwrap();
+        assert!(!arr.is_empty(), "Tree with file path should find descendants");
     }
 
     #[pg_test]
-    fn test_record_test_result() {
-        let task = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.create_task('Result task', 'cmd', NULL, NULL, NULL)",
+    fn test_children_of_file_node() {
+        Spi::run("SELECT kerai.parse_source('fn child_a() {} fn child_b() {}', 'children_test.rs')").unwrap();
+        let file_id = Spi::get_one::<pgrx::Uuid>(
+            "SELECT id FROM kerai.nodes WHERE kind = 'file' AND content = 'children_test.rs'",
         )
         .unwrap()
         .unwrap();
-        let task_id = task.0["id"].as_str().unwrap();
-
-        Spi::run("SELECT kerai.register_agent('result-agent', 'llm', NULL, NULL)")
-            .unwrap();
 
         let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.record_test_result('{}'::uuid, 'result-agent', true, 'all tests pass', 150, 5)",
-            task_id,
-        ))
-        .unwrap()
-        .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert!(obj["passed"].as_bool().unwrap());
-        assert_eq!(obj["duration_ms"].as_i64().unwrap(), 150);
-        assert_eq!(obj["ops_count"].as_i64().unwrap(), 5);
-
-        // Verify stored
-        let count = Spi::get_one::<i64>(&format!(
-            "SELECT count(*)::bigint FROM kerai.test_results WHERE task_id = '{}'::uuid",
-            task_id,
+            "SELECT kerai.children('{}'::uuid)",
+            file_id,
         ))
         .unwrap()
         .unwrap();
-        assert_eq!(count, 1);
+        let arr = result.0.as_array().unwrap();
+        let fn_children: Vec<_> = arr.iter().filter(|v| v["kind"].as_str() == Some("fn")).collect();
+        assert!(fn_children.len() >= 2, "File node should have at least 2 fn children, got {}", fn_children.len());
     }
 
     #[pg_test]
-    fn test_swarm_leaderboard() {
-        let task = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.create_task('Leaderboard task', 'cmd', NULL, NULL, NULL)",
+    fn test_ancestors_of_nested_node() {
+        Spi::run("SELECT kerai.parse_source('fn outer() { let x = 1; }', 'ancestors_test.rs')").unwrap();
+        // Find a stmt_local node (the `let x = 1;`)
+        let local_id = Spi::get_one::<pgrx::Uuid>(
+            "SELECT id FROM kerai.nodes WHERE kind = 'stmt_local' AND content = 'x' LIMIT 1",
         )
-        .unwrap()
         .unwrap();
-        let task_id = task.0["id"].as_str().unwrap();
 
-        Spi::run("SELECT kerai.register_agent('lb-agent-1', 'llm', NULL, NULL)")
-            .unwrap();
-        Spi::run("SELECT kerai.register_agent('lb-agent-2', 'llm', NULL, NULL)")
+        if let Some(nid) = local_id {
+            let result = Spi::get_one::<pgrx::JsonB>(&format!(
+                "SELECT kerai.ancestors('{}'::uuid)",
+                nid,
+            ))
+            .unwrap()
             .unwrap();
+            let arr = result.0.as_array().unwrap();
+            assert!(!arr.is_empty(), "Nested node should have ancestors");
+            // Should eventually reach a file node
+            let has_file = arr.iter().any(|v| v["kind"].as_str() == Some("file"));
+            assert!(has_file, "Ancestors should include the file node");
+        }
+    }
 
-        // Agent 1: 2 pass, 1 fail
-        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'lb-agent-1', true, NULL, 100, NULL)", task_id)).unwrap();
-        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'lb-agent-1', true, NULL, 120, NULL)", task_id)).unwrap();
-        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'lb-agent-1', false, NULL, 200, NULL)", task_id)).unwrap();
-
-        // Agent 2: 1 pass
-        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'lb-agent-2', true, NULL, 80, NULL)", task_id)).unwrap();
+    // --- Plan 08: Agent perspectives tests ---
 
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.swarm_leaderboard('{}'::uuid)",
-            task_id,
-        ))
+    #[pg_test]
+    fn test_register_agent() {
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.register_agent('test-llm', 'llm', 'claude-opus-4-6', NULL)",
+        )
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        assert_eq!(arr.len(), 2, "Should have 2 agents on leaderboard");
-
-        // Agent 2 should be first (100% pass rate)
-        assert_eq!(arr[0]["agent_name"].as_str().unwrap(), "lb-agent-2");
-        assert_eq!(arr[0]["pass_count"].as_i64().unwrap(), 1);
+        let obj = result.0.as_object().unwrap();
+        assert_eq!(obj["name"].as_str().unwrap(), "test-llm");
+        assert_eq!(obj["kind"].as_str().unwrap(), "llm");
+        assert!(obj["is_new"].as_bool().unwrap());
+        assert!(obj.contains_key("id"));
     }
 
     #[pg_test]
-    fn test_swarm_progress() {
-        let task = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.create_task('Progress task', 'cmd', NULL, NULL, NULL)",
+    fn test_register_agent_idempotent() {
+        Spi::run("SELECT kerai.register_agent('idem-agent', 'llm', 'model-a', NULL)")
+            .unwrap();
+        let r2 = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.register_agent('idem-agent', 'tool', 'model-b', NULL)",
         )
         .unwrap()
         .unwrap();
-        let task_id = task.0["id"].as_str().unwrap();
+        let obj = r2.0.as_object().unwrap();
+        assert!(!obj["is_new"].as_bool().unwrap());
+        assert_eq!(obj["kind"].as_str().unwrap(), "tool");
+    }
 
-        Spi::run("SELECT kerai.register_agent('prog-agent', 'llm', NULL, NULL)")
+    #[pg_test]
+    fn test_list_agents() {
+        Spi::run("SELECT kerai.register_agent('list-agent', 'human', NULL, NULL)")
             .unwrap();
-
-        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'prog-agent', true, NULL, 50, NULL)", task_id)).unwrap();
-        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'prog-agent', false, NULL, 60, NULL)", task_id)).unwrap();
-
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.swarm_progress('{}'::uuid)",
-            task_id,
-        ))
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.list_agents(NULL)",
+        )
         .unwrap()
         .unwrap();
         let arr = result.0.as_array().unwrap();
-        assert!(!arr.is_empty(), "Should have at least one time bucket");
-        let first = &arr[0];
-        assert_eq!(first["total"].as_i64().unwrap(), 2);
-        assert_eq!(first["passed"].as_i64().unwrap(), 1);
-        assert_eq!(first["failed"].as_i64().unwrap(), 1);
+        let names: Vec<&str> = arr.iter().filter_map(|v| v["name"].as_str()).collect();
+        assert!(names.contains(&"list-agent"));
     }
 
     #[pg_test]
-    fn test_swarm_status_overview() {
-        Spi::run("SELECT kerai.create_task('Status task 1', 'cmd1', NULL, NULL, NULL)")
-            .unwrap();
-        Spi::run("SELECT kerai.create_task('Status task 2', 'cmd2', NULL, NULL, NULL)")
+    fn test_remove_agent() {
+        Spi::run("SELECT kerai.register_agent('remove-agent', 'tool', NULL, NULL)")
             .unwrap();
-
         let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.swarm_status(NULL)",
+            "SELECT kerai.remove_agent('remove-agent')",
         )
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        assert!(arr.len() >= 2, "Should show at least 2 tasks in overview");
+        assert!(result.0["removed"].as_bool().unwrap());
     }
 
-    // --- Plan 10: Marketplace tests ---
-
-    /// Helper: create an attestation for the self instance. Returns attestation_id.
-    fn create_test_attestation(scope: &str, claim_type: &str) -> String {
-        Spi::get_one::<String>(&format!(
-            "INSERT INTO kerai.attestations (instance_id, scope, claim_type, perspective_count, avg_weight)
-             SELECT id, '{}'::ltree, '{}', 3, 0.75
-             FROM kerai.instances WHERE is_self = true
-             RETURNING id::text",
-            sql_escape(scope),
-            sql_escape(claim_type),
-        ))
-        .unwrap()
+    #[pg_test]
+    fn test_set_perspective() {
+        // Register agent and create a node
+        Spi::run("SELECT kerai.register_agent('persp-agent', 'llm', NULL, NULL)")
+            .unwrap();
+        let node = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"persp_fn\", \"position\": 0}'::jsonb)",
+        )
         .unwrap()
-    }
+        .unwrap();
+        let node_id = node.0["node_id"].as_str().unwrap();
 
-    #[pg_test]
-    fn test_create_auction() {
-        let att_id = create_test_attestation("pkg.auth", "expertise");
         let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.create_auction('{}'::uuid, 80000, 1000, 3600, 0, 1, 24)",
-            att_id,
+            "SELECT kerai.set_perspective('persp-agent', '{}'::uuid, 0.8, NULL, 'important function')",
+            node_id,
         ))
         .unwrap()
         .unwrap();
         let obj = result.0.as_object().unwrap();
-        assert_eq!(obj["starting_price"].as_i64().unwrap(), 80000);
-        assert_eq!(obj["current_price"].as_i64().unwrap(), 80000);
-        assert_eq!(obj["status"].as_str().unwrap(), "active");
-    }
+        assert_eq!(obj["agent"].as_str().unwrap(), "persp-agent");
+        assert_eq!(obj["weight"].as_f64().unwrap(), 0.8);
 
-    #[pg_test]
-    #[should_panic(expected = "active auction already exists")]
-    fn test_create_auction_duplicate() {
-        let att_id = create_test_attestation("pkg.dup", "expertise");
-        Spi::run(&format!(
-            "SELECT kerai.create_auction('{}'::uuid, 50000, 500, 60, 0, 1, 24)",
-            att_id,
-        ))
-        .unwrap();
-        // Second auction on same attestation should fail
-        Spi::run(&format!(
-            "SELECT kerai.create_auction('{}'::uuid, 50000, 500, 60, 0, 1, 24)",
-            att_id,
+        // Verify stored
+        let count = Spi::get_one::<i64>(&format!(
+            "SELECT count(*)::bigint FROM kerai.perspectives WHERE node_id = '{}'::uuid",
+            node_id,
         ))
+        .unwrap()
         .unwrap();
+        assert_eq!(count, 1);
     }
 
     #[pg_test]
-    fn test_place_bid() {
-        let att_id = create_test_attestation("pkg.bid", "state_transition");
-        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.create_auction('{}'::uuid, 50000, 1000, 60, 0, 1, 24)",
-            att_id,
-        ))
+    fn test_set_perspective_with_context() {
+        Spi::run("SELECT kerai.register_agent('ctx-agent', 'llm', NULL, NULL)")
+            .unwrap();
+        // Create two nodes — one as target, one as context
+        let n1 = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"ctx_target\", \"position\": 0}'::jsonb)",
+        )
         .unwrap()
         .unwrap();
-        let auction_id = auction.0["id"].as_str().unwrap();
+        let n2 = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"module\", \"content\": \"ctx_scope\", \"position\": 1}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let target_id = n1.0["node_id"].as_str().unwrap();
+        let context_id = n2.0["node_id"].as_str().unwrap();
 
-        let bid = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.place_bid('{}'::uuid, 40000)",
-            auction_id,
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.set_perspective('ctx-agent', '{}'::uuid, 0.5, '{}'::uuid, NULL)",
+            target_id, context_id,
         ))
         .unwrap()
         .unwrap();
-        assert_eq!(bid.0["max_price"].as_i64().unwrap(), 40000);
-        assert!(bid.0.as_object().unwrap().contains_key("id"));
+        assert!(result.0["context_id"].as_str().is_some());
     }
 
     #[pg_test]
-    fn test_tick_auction_price_decrement() {
-        let att_id = create_test_attestation("pkg.tick", "expertise");
-        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.create_auction('{}'::uuid, 10000, 2000, 60, 0, 1, 24)",
-            att_id,
-        ))
+    fn test_delete_perspective() {
+        Spi::run("SELECT kerai.register_agent('del-persp-agent', 'llm', NULL, NULL)")
+            .unwrap();
+        let node = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"del_persp_fn\", \"position\": 0}'::jsonb)",
+        )
         .unwrap()
         .unwrap();
-        let auction_id = auction.0["id"].as_str().unwrap();
+        let node_id = node.0["node_id"].as_str().unwrap();
 
-        let tick = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.tick_auction('{}'::uuid)",
-            auction_id,
+        Spi::run(&format!(
+            "SELECT kerai.set_perspective('del-persp-agent', '{}'::uuid, 0.9, NULL, NULL)",
+            node_id,
+        ))
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.delete_perspective('del-persp-agent', '{}'::uuid, NULL)",
+            node_id,
         ))
         .unwrap()
         .unwrap();
-        assert_eq!(tick.0["current_price"].as_i64().unwrap(), 8000);
-        assert_eq!(tick.0["action"].as_str().unwrap(), "price_decremented");
+        assert!(result.0["deleted"].as_bool().unwrap());
     }
 
     #[pg_test]
-    fn test_tick_auction_floor_hit() {
-        let att_id = create_test_attestation("pkg.floor", "expertise");
-        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.create_auction('{}'::uuid, 3000, 5000, 60, 0, 1, 24)",
-            att_id,
-        ))
+    fn test_get_perspectives_with_filter() {
+        Spi::run("SELECT kerai.register_agent('filter-agent', 'llm', NULL, NULL)")
+            .unwrap();
+        let n1 = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"high_fn\", \"position\": 0}'::jsonb)",
+        )
         .unwrap()
         .unwrap();
-        let auction_id = auction.0["id"].as_str().unwrap();
+        let n2 = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"low_fn\", \"position\": 1}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let high_id = n1.0["node_id"].as_str().unwrap();
+        let low_id = n2.0["node_id"].as_str().unwrap();
 
-        // Decrement 5000 from 3000 should hit floor
-        let tick = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.tick_auction('{}'::uuid)",
-            auction_id,
+        Spi::run(&format!(
+            "SELECT kerai.set_perspective('filter-agent', '{}'::uuid, 0.9, NULL, NULL)",
+            high_id,
+        ))
+        .unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.set_perspective('filter-agent', '{}'::uuid, 0.2, NULL, NULL)",
+            low_id,
         ))
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.get_perspectives('filter-agent', NULL, 0.5)",
+        )
         .unwrap()
         .unwrap();
-        assert_eq!(tick.0["action"].as_str().unwrap(), "open_sourced");
-        assert_eq!(tick.0["reason"].as_str().unwrap(), "floor_price_hit");
+        let arr = result.0.as_array().unwrap();
+        assert_eq!(arr.len(), 1, "Only the high-weight perspective should pass filter");
+        assert_eq!(arr[0]["weight"].as_f64().unwrap(), 0.9);
     }
 
     #[pg_test]
-    fn test_tick_auction_settlement_ready() {
-        let att_id = create_test_attestation("pkg.settle_ready", "expertise");
-        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.create_auction('{}'::uuid, 50000, 1000, 60, 0, 1, 24)",
-            att_id,
-        ))
+    fn test_import_perspectives_reports_each_item() {
+        Spi::run("SELECT kerai.register_agent('review-bot', 'tool', NULL, NULL)").unwrap();
+        let node = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"reviewed_fn\", \"position\": 0, \"path\": \"review.reviewed_fn\"}'::jsonb)",
+        )
         .unwrap()
         .unwrap();
-        let auction_id = auction.0["id"].as_str().unwrap();
+        let node_id = node.0["node_id"].as_str().unwrap().to_string();
 
-        // Place a bid high enough for the decremented price
-        Spi::run(&format!(
-            "SELECT kerai.place_bid('{}'::uuid, 49000)",
-            auction_id,
-        ))
-        .unwrap();
+        let batch = serde_json::json!([
+            {"node_path": "review.reviewed_fn", "weight": -0.4, "reasoning": "unwrap on input"},
+            {"node_id": node_id, "weight": -0.4, "reasoning": "unwrap on input"},
+            {"node_path": "review.reviewed_fn", "context_id": node_id, "weight": 0.7},
+            {"node_path": "review.missing", "weight": 0.1},
+            {"node_id": node_id, "weight": 3},
+        ]);
+        let import = |batch: &serde_json::Value| {
+            Spi::get_one_with_args::<pgrx::JsonB>(
+                "SELECT kerai.import_perspectives('review-bot', $1)",
+                &[pgrx::JsonB(batch.clone()).into()],
+            )
+            .unwrap()
+            .unwrap()
+            .0
+        };
 
-        let tick = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.tick_auction('{}'::uuid)",
-            auction_id,
-        ))
+        let first = import(&batch);
+        let statuses: Vec<&str> = first["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["status"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            statuses,
+            ["inserted", "duplicate", "inserted", "invalid", "invalid"]
+        );
+        assert_eq!(first["items"][1]["id"], first["items"][0]["id"]);
+        assert_eq!(
+            first["items"][2]["context_id"].as_str(),
+            Some(node_id.as_str())
+        );
+        assert!(first["items"][3]["error"]
+            .as_str()
+            .unwrap()
+            .contains("not found"));
+        assert_eq!(first["inserted"], 2);
+        assert_eq!(first["invalid"], 2);
+
+        // A second run changes only what the review changed
+        let rerun = import(&serde_json::json!([
+            {"node_path": "review.reviewed_fn", "weight": -0.4, "reasoning": "unwrap on input"},
+            {"node_path": "review.reviewed_fn", "context_id": node_id, "weight": 0.2},
+        ]));
+        assert_eq!(rerun["unchanged"], 1);
+        assert_eq!(rerun["updated"], 1);
+        let count = Spi::get_one_with_args::<i64>(
+            "SELECT count(*)::bigint FROM kerai.perspectives WHERE node_id = $1::uuid",
+            &[node_id.as_str().into()],
+        )
         .unwrap()
         .unwrap();
-        assert_eq!(tick.0["action"].as_str().unwrap(), "settlement_ready");
-        assert!(tick.0["qualifying_bidders"].as_i64().unwrap() >= 1);
+        assert_eq!(count, 2);
     }
 
     #[pg_test]
-    fn test_settle_auction() {
-        let att_id = create_test_attestation("pkg.settle", "expertise");
-        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.create_auction('{}'::uuid, 10000, 1000, 60, 0, 1, 24)",
-            att_id,
-        ))
+    fn test_set_association() {
+        Spi::run("SELECT kerai.register_agent('assoc-agent', 'llm', NULL, NULL)")
+            .unwrap();
+        let n1 = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"assoc_src\", \"position\": 0}'::jsonb)",
+        )
         .unwrap()
         .unwrap();
-        let auction_id = auction.0["id"].as_str().unwrap();
-
-        // Place a bid
-        Spi::run(&format!(
-            "SELECT kerai.place_bid('{}'::uuid, 10000)",
-            auction_id,
-        ))
+        let n2 = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"assoc_tgt\", \"position\": 1}'::jsonb)",
+        )
+        .unwrap()
         .unwrap();
+        let src_id = n1.0["node_id"].as_str().unwrap();
+        let tgt_id = n2.0["node_id"].as_str().unwrap();
 
-        // Settle at current price (10000)
         let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.settle_auction('{}'::uuid)",
-            auction_id,
+            "SELECT kerai.set_association('assoc-agent', '{}'::uuid, '{}'::uuid, 0.7, 'depends_on', 'tight coupling')",
+            src_id, tgt_id,
         ))
         .unwrap()
         .unwrap();
         let obj = result.0.as_object().unwrap();
-        assert_eq!(obj["status"].as_str().unwrap(), "settled");
-        assert_eq!(obj["settled_price"].as_i64().unwrap(), 10000);
-        assert_eq!(obj["bidder_count"].as_i64().unwrap(), 1);
-        assert_eq!(obj["total_revenue"].as_i64().unwrap(), 10000);
+        assert_eq!(obj["relation"].as_str().unwrap(), "depends_on");
+        assert_eq!(obj["weight"].as_f64().unwrap(), 0.7);
     }
 
     #[pg_test]
-    fn test_open_source_auction() {
-        let att_id = create_test_attestation("pkg.opensource", "expertise");
-        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.create_auction('{}'::uuid, 5000, 500, 60, 0, 1, 0)",
-            att_id,
-        ))
+    fn test_delete_association() {
+        Spi::run("SELECT kerai.register_agent('del-assoc-agent', 'llm', NULL, NULL)")
+            .unwrap();
+        let n1 = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"del_assoc_src\", \"position\": 0}'::jsonb)",
+        )
         .unwrap()
         .unwrap();
-        let auction_id = auction.0["id"].as_str().unwrap();
+        let n2 = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"del_assoc_tgt\", \"position\": 1}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let src_id = n1.0["node_id"].as_str().unwrap();
+        let tgt_id = n2.0["node_id"].as_str().unwrap();
 
-        // Place bid and settle
         Spi::run(&format!(
-            "SELECT kerai.place_bid('{}'::uuid, 5000)",
-            auction_id,
+            "SELECT kerai.set_association('del-assoc-agent', '{}'::uuid, '{}'::uuid, 0.5, 'similar_to', NULL)",
+            src_id, tgt_id,
         ))
         .unwrap();
-        Spi::run(&format!(
-            "SELECT kerai.settle_auction('{}'::uuid)",
-            auction_id,
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.delete_association('del-assoc-agent', '{}'::uuid, '{}'::uuid, 'similar_to')",
+            src_id, tgt_id,
         ))
+        .unwrap()
         .unwrap();
+        assert!(result.0["deleted"].as_bool().unwrap());
+    }
 
-        // Open-source
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.open_source_auction('{}'::uuid)",
-            auction_id,
+    #[pg_test]
+    fn test_consensus_multiple_agents() {
+        // Register two agents
+        Spi::run("SELECT kerai.register_agent('cons-agent-1', 'llm', NULL, NULL)")
+            .unwrap();
+        Spi::run("SELECT kerai.register_agent('cons-agent-2', 'llm', NULL, NULL)")
+            .unwrap();
+
+        // Create a node
+        let node = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"consensus_fn\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let node_id = node.0["node_id"].as_str().unwrap();
+
+        // Both agents rate the same node
+        Spi::run(&format!(
+            "SELECT kerai.set_perspective('cons-agent-1', '{}'::uuid, 0.8, NULL, NULL)",
+            node_id,
+        ))
+        .unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.set_perspective('cons-agent-2', '{}'::uuid, 0.6, NULL, NULL)",
+            node_id,
+        ))
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.consensus(NULL, 2, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let arr = result.0.as_array().unwrap();
+        assert!(!arr.is_empty(), "Should find consensus with 2+ agents");
+        let first = &arr[0];
+        assert_eq!(first["agent_count"].as_i64().unwrap(), 2);
+        let avg = first["avg_weight"].as_f64().unwrap();
+        assert!((avg - 0.7).abs() < 0.001, "Average should be ~0.7, got {}", avg);
+    }
+
+    #[pg_test]
+    fn test_activity_timeline() {
+        Spi::run("SELECT kerai.register_agent('timeline-agent', 'llm', NULL, NULL)").unwrap();
+        let node = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"timeline_fn\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let node_id = node.0["node_id"].as_str().unwrap().to_string();
+        Spi::run(&format!(
+            "UPDATE kerai.nodes SET path = 'tl_root.mod_a.fn_x.body' WHERE id = '{}'::uuid",
+            node_id,
+        ))
+        .unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.apply_op('update_content', '{}'::uuid, '{{\"new_content\": \"timeline_fn2\"}}'::jsonb)",
+            node_id,
+        ))
+        .unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.set_perspective('timeline-agent', '{}'::uuid, 0.5, NULL, NULL)",
+            node_id,
+        ))
+        .unwrap();
+
+        let pending = Spi::get_one::<i64>("SELECT count(*)::bigint FROM kerai.activity_deltas")
+            .unwrap()
+            .unwrap();
+        assert!(pending >= 2, "Activity should be queued");
+
+        // A short path reads the folded aggregate, which filed the insert
+        // under the node's path then (none); a deep one reads the history,
+        // by the node's path now
+        for (path, versions) in [("tl_root", 1), ("tl_root.mod_a.fn_x.body", 2)] {
+            let timeline = Spi::get_one::<pgrx::JsonB>(&format!(
+                "SELECT kerai.timeline('{}', 'day', 7)",
+                path
+            ))
+            .unwrap()
+            .unwrap();
+            let periods = timeline.0["periods"].as_array().unwrap();
+            assert_eq!(periods.len(), 7, "{}", timeline.0);
+            let today = periods.last().unwrap();
+            assert_eq!(today["versions"], versions, "{}", timeline.0);
+            assert_eq!(today["perspectives"], 1, "{}", timeline.0);
+            let agent = today["authors"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|a| a["author"] == "timeline-agent")
+                .expect("the agent should be among the authors");
+            assert_eq!(agent["total"], 1);
+        }
+        let pending = Spi::get_one::<i64>("SELECT count(*)::bigint FROM kerai.activity_deltas")
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending, 0, "Reading should fold the queue");
+
+        let weekly = Spi::get_one::<pgrx::JsonB>("SELECT kerai.timeline('tl_root', 'week', 7)")
+            .unwrap()
+            .unwrap();
+        let weeks = weekly.0["periods"].as_array().unwrap();
+        assert!((1..=2).contains(&weeks.len()), "{}", weekly.0);
+        assert_eq!(weeks.last().unwrap()["total"], 2);
+
+        let elsewhere =
+            Spi::get_one::<pgrx::JsonB>("SELECT kerai.timeline('other_root', 'day', 7)")
+                .unwrap()
+                .unwrap();
+        assert!(elsewhere.0["periods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|p| p["total"] == 0));
+    }
+
+    #[pg_test]
+    fn test_consensus_state_follows_perspective_changes() {
+        for agent in ["inc-agent-1", "inc-agent-2", "inc-agent-3"] {
+            Spi::run(&format!("SELECT kerai.register_agent('{}', 'llm', NULL, NULL)", agent))
+                .unwrap();
+        }
+        let node = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"incremental_fn\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let node_id = node.0["node_id"].as_str().unwrap().to_string();
+
+        for (agent, weight) in [("inc-agent-1", 0.9), ("inc-agent-2", -0.5), ("inc-agent-3", 0.1)] {
+            Spi::run(&format!(
+                "SELECT kerai.set_perspective('{}', '{}'::uuid, {}, NULL, NULL)",
+                agent, node_id, weight,
+            ))
+            .unwrap();
+        }
+        // Fold, then retract the minimum and move the maximum
+        Spi::run("SELECT kerai.refresh_consensus()").unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.delete_perspective('inc-agent-2', '{}'::uuid, NULL)",
+            node_id,
+        ))
+        .unwrap();
+        Spi::run(&format!(
+            "UPDATE kerai.perspectives SET weight = 0.3 WHERE node_id = '{}'::uuid
+             AND agent_id = (SELECT id FROM kerai.agents WHERE name = 'inc-agent-1')",
+            node_id,
+        ))
+        .unwrap();
+
+        let pending = Spi::get_one::<i64>("SELECT count(*)::bigint FROM kerai.perspective_deltas")
+            .unwrap()
+            .unwrap();
+        assert!(pending > 0, "Perspective changes should be queued");
+
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.consensus(NULL, 2, NULL)")
+            .unwrap()
+            .unwrap();
+        let entry = result
+            .0
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["node_id"] == node_id.as_str())
+            .expect("node should still have consensus")
+            .clone();
+        assert_eq!(entry["agent_count"], 2);
+        assert!((entry["avg_weight"].as_f64().unwrap() - 0.2).abs() < 1e-9);
+        assert!((entry["min_weight"].as_f64().unwrap() - 0.1).abs() < 1e-9);
+        assert!((entry["max_weight"].as_f64().unwrap() - 0.3).abs() < 1e-9);
+        assert!(entry["as_of"].is_string());
+
+        // Materialized state matches a from-scratch aggregate
+        let matches = Spi::get_one::<bool>(&format!(
+            "SELECT s.agent_count = v.agent_count
+                AND abs(s.weight_sum / s.perspective_count - v.avg_weight) < 1e-9
+                AND abs(sqrt(GREATEST((s.weight_sq_sum - s.weight_sum * s.weight_sum / s.perspective_count)
+                    / (s.perspective_count - 1), 0)) - v.stddev_weight) < 1e-9
+             FROM kerai.consensus_state s
+             JOIN kerai.consensus_perspectives v
+               ON v.node_id = s.node_id AND v.context_id IS NOT DISTINCT FROM s.context_id
+             WHERE s.node_id = '{}'::uuid",
+            node_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(matches, "Folded state should equal the aggregate view");
+
+        let stats = Spi::get_one::<pgrx::JsonB>("SELECT kerai.perspective_stats()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(stats.0["pending"], 0);
+        let agents = stats.0["agents"].as_array().unwrap();
+        assert!(agents.iter().any(|a| a["agent"] == "inc-agent-1"));
+        assert!(!agents.iter().any(|a| a["agent"] == "inc-agent-2"));
+    }
+
+    #[pg_test]
+    fn test_perspective_diff() {
+        Spi::run("SELECT kerai.register_agent('diff-agent-a', 'llm', NULL, NULL)")
+            .unwrap();
+        Spi::run("SELECT kerai.register_agent('diff-agent-b', 'llm', NULL, NULL)")
+            .unwrap();
+
+        // Create shared and unique nodes
+        let shared = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"diff_shared\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let only_a_node = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"diff_only_a\", \"position\": 1}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let shared_id = shared.0["node_id"].as_str().unwrap();
+        let only_a_id = only_a_node.0["node_id"].as_str().unwrap();
+
+        // Agent A rates both nodes with different weights
+        Spi::run(&format!(
+            "SELECT kerai.set_perspective('diff-agent-a', '{}'::uuid, 0.9, NULL, NULL)",
+            shared_id,
+        ))
+        .unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.set_perspective('diff-agent-a', '{}'::uuid, 0.5, NULL, NULL)",
+            only_a_id,
+        ))
+        .unwrap();
+
+        // Agent B rates shared node with different weight
+        Spi::run(&format!(
+            "SELECT kerai.set_perspective('diff-agent-b', '{}'::uuid, 0.3, NULL, NULL)",
+            shared_id,
+        ))
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.perspective_diff('diff-agent-a', 'diff-agent-b', NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+
+        let only_in_a = obj["only_in_a"].as_array().unwrap();
+        assert!(!only_in_a.is_empty(), "Agent A should have unique perspectives");
+
+        let disagreements = obj["disagreements"].as_array().unwrap();
+        assert!(!disagreements.is_empty(), "Should have at least one disagreement on shared node");
+        let diff = disagreements[0]["diff"].as_f64().unwrap();
+        assert!((diff - 0.6).abs() < 0.001, "Diff should be ~0.6, got {}", diff);
+    }
+
+    #[pg_test]
+    fn test_simulate_agents_converges_and_cleans_up() {
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.simulate_agents('{\"nodes\": 8, \"rounds\": 2, \"agents\": [{\"count\": 3, \"noise\": 0}]}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert_eq!(obj["nodes"], 8);
+        assert_eq!(obj["agents"], 3);
+        let rounds = obj["rounds"].as_array().unwrap();
+        assert_eq!(rounds.len(), 2);
+        // Agents that always agree with no noise converge on every node
+        assert_eq!(rounds[0]["converged"], 8);
+        assert_eq!(rounds[0]["sign_accuracy"], 1.0);
+        assert_eq!(obj["converged_at"], 1);
+
+        let leftovers = Spi::get_one::<i64>(
+            "SELECT (SELECT count(*) FROM kerai.agents WHERE kind = 'simulated')
+                  + (SELECT count(*) FROM kerai.nodes WHERE kind IN ('sim_graph', 'sim_node'))",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(leftovers, 0);
+    }
+
+    // --- Plan 09: Swarm task tests ---
+
+    #[pg_test]
+    fn test_create_task() {
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.create_task('Fix bug #42', 'cargo test', NULL, NULL, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert_eq!(obj["description"].as_str().unwrap(), "Fix bug #42");
+        assert_eq!(obj["success_command"].as_str().unwrap(), "cargo test");
+        assert_eq!(obj["status"].as_str().unwrap(), "pending");
+        assert!(obj.contains_key("id"));
+    }
+
+    #[pg_test]
+    fn test_create_task_with_scope() {
+        // Create a scope node first
+        let node = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"module\", \"content\": \"scope_mod\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let node_id = node.0["node_id"].as_str().unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.create_task('Scoped task', 'make test', '{}'::uuid, 100, 300)",
+            node_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert_eq!(obj["status"].as_str().unwrap(), "pending");
+        assert!(obj["scope_node_id"].as_str().is_some());
+        assert_eq!(obj["budget_ops"].as_i64().unwrap(), 100);
+        assert_eq!(obj["budget_seconds"].as_i64().unwrap(), 300);
+    }
+
+    #[pg_test]
+    fn test_list_tasks() {
+        Spi::run("SELECT kerai.create_task('Task A', 'cmd_a', NULL, NULL, NULL)")
+            .unwrap();
+        Spi::run("SELECT kerai.create_task('Task B', 'cmd_b', NULL, NULL, NULL)")
+            .unwrap();
+
+        // List all
+        let all = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.list_tasks(NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let arr = all.0.as_array().unwrap();
+        assert!(arr.len() >= 2, "Should have at least 2 tasks");
+
+        // List with filter
+        let pending = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.list_tasks('pending')",
+        )
+        .unwrap()
+        .unwrap();
+        let parr = pending.0.as_array().unwrap();
+        for t in parr {
+            assert_eq!(t["status"].as_str().unwrap(), "pending");
+        }
+    }
+
+    #[pg_test]
+    fn test_update_task_status() {
+        let task = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.create_task('Update me', 'test cmd', NULL, NULL, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let task_id = task.0["id"].as_str().unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.update_task_status('{}'::uuid, 'running')",
+            task_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(result.0["status"].as_str().unwrap(), "running");
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "Invalid task status")]
+    fn test_update_task_invalid_status() {
+        let task = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.create_task('Bad status', 'cmd', NULL, NULL, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let task_id = task.0["id"].as_str().unwrap();
+
+        Spi::run(&format!(
+            "SELECT kerai.update_task_status('{}'::uuid, 'bogus')",
+            task_id,
+        ))
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn test_launch_swarm() {
+        let task = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.create_task('Swarm task', 'cargo test', NULL, NULL, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let task_id = task.0["id"].as_str().unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.launch_swarm('{}'::uuid, 3, 'llm', 'claude-opus-4-6')",
+            task_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert_eq!(obj["status"].as_str().unwrap(), "running");
+        assert_eq!(obj["agent_count"].as_i64().unwrap(), 3);
+        assert!(obj["swarm_name"].as_str().unwrap().starts_with("swarm-"));
+
+        // Verify swarm agent was registered
+        let swarm_name = obj["swarm_name"].as_str().unwrap();
+        let agent_exists = Spi::get_one::<bool>(&format!(
+            "SELECT EXISTS(SELECT 1 FROM kerai.agents WHERE name = '{}')",
+            sql_escape(swarm_name),
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(agent_exists, "Swarm agent should be registered");
+    }
+
+    #[pg_test]
+    fn test_stop_swarm() {
+        let task = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.create_task('Stop me', 'cmd', NULL, NULL, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let task_id = task.0["id"].as_str().unwrap();
+
+        Spi::run(&format!(
+            "SELECT kerai.launch_swarm('{}'::uuid, 2, 'llm', NULL)",
+            task_id,
+        ))
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.stop_swarm('{}'::uuid)",
+            task_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(result.0["status"].as_str().unwrap(), "stopped");
+    }
+
+    #[pg_test]
+    fn test_record_test_result() {
+        let task = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.create_task('Result task', 'cmd', NULL, NULL, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let task_id = task.0["id"].as_str().unwrap();
+
+        Spi::run("SELECT kerai.register_agent('result-agent', 'llm', NULL, NULL)")
+            .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.record_test_result('{}'::uuid, 'result-agent', true, 'all tests pass', 150, 5)",
+            task_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert!(obj["passed"].as_bool().unwrap());
+        assert_eq!(obj["duration_ms"].as_i64().unwrap(), 150);
+        assert_eq!(obj["ops_count"].as_i64().unwrap(), 5);
+
+        // Verify stored
+        let count = Spi::get_one::<i64>(&format!(
+            "SELECT count(*)::bigint FROM kerai.test_results WHERE task_id = '{}'::uuid",
+            task_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[pg_test]
+    fn test_swarm_leaderboard() {
+        let task = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.create_task('Leaderboard task', 'cmd', NULL, NULL, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let task_id = task.0["id"].as_str().unwrap();
+
+        Spi::run("SELECT kerai.register_agent('lb-agent-1', 'llm', NULL, NULL)")
+            .unwrap();
+        Spi::run("SELECT kerai.register_agent('lb-agent-2', 'llm', NULL, NULL)")
+            .unwrap();
+
+        // Agent 1: 2 pass, 1 fail
+        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'lb-agent-1', true, NULL, 100, NULL)", task_id)).unwrap();
+        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'lb-agent-1', true, NULL, 120, NULL)", task_id)).unwrap();
+        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'lb-agent-1', false, NULL, 200, NULL)", task_id)).unwrap();
+
+        // Agent 2: 1 pass
+        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'lb-agent-2', true, NULL, 80, NULL)", task_id)).unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.swarm_leaderboard('{}'::uuid)",
+            task_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let arr = result.0.as_array().unwrap();
+        assert_eq!(arr.len(), 2, "Should have 2 agents on leaderboard");
+
+        // Agent 2 should be first (100% pass rate)
+        assert_eq!(arr[0]["agent_name"].as_str().unwrap(), "lb-agent-2");
+        assert_eq!(arr[0]["pass_count"].as_i64().unwrap(), 1);
+    }
+
+    #[pg_test]
+    fn test_swarm_progress() {
+        let task = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.create_task('Progress task', 'cmd', NULL, NULL, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let task_id = task.0["id"].as_str().unwrap();
+
+        Spi::run("SELECT kerai.register_agent('prog-agent', 'llm', NULL, NULL)")
+            .unwrap();
+
+        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'prog-agent', true, NULL, 50, NULL)", task_id)).unwrap();
+        Spi::run(&format!("SELECT kerai.record_test_result('{}'::uuid, 'prog-agent', false, NULL, 60, NULL)", task_id)).unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.swarm_progress('{}'::uuid)",
+            task_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let arr = result.0.as_array().unwrap();
+        assert!(!arr.is_empty(), "Should have at least one time bucket");
+        let first = &arr[0];
+        assert_eq!(first["total"].as_i64().unwrap(), 2);
+        assert_eq!(first["passed"].as_i64().unwrap(), 1);
+        assert_eq!(first["failed"].as_i64().unwrap(), 1);
+    }
+
+    #[pg_test]
+    fn test_swarm_status_overview() {
+        Spi::run("SELECT kerai.create_task('Status task 1', 'cmd1', NULL, NULL, NULL)")
+            .unwrap();
+        Spi::run("SELECT kerai.create_task('Status task 2', 'cmd2', NULL, NULL, NULL)")
+            .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.swarm_status(NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let arr = result.0.as_array().unwrap();
+        assert!(arr.len() >= 2, "Should show at least 2 tasks in overview");
+    }
+
+    // --- Plan 10: Marketplace tests ---
+
+    /// Helper: create an attestation for the self instance. Returns attestation_id.
+    fn create_test_attestation(scope: &str, claim_type: &str) -> String {
+        Spi::get_one::<String>(&format!(
+            "INSERT INTO kerai.attestations (instance_id, scope, claim_type, perspective_count, avg_weight)
+             SELECT id, '{}'::ltree, '{}', 3, 0.75
+             FROM kerai.instances WHERE is_self = true
+             RETURNING id::text",
+            sql_escape(scope),
+            sql_escape(claim_type),
+        ))
+        .unwrap()
+        .unwrap()
+    }
+
+    #[pg_test]
+    fn test_create_auction() {
+        let att_id = create_test_attestation("pkg.auth", "expertise");
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.create_auction('{}'::uuid, 80000, 1000, 3600, 0, 1, 24)",
+            att_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert_eq!(obj["starting_price"].as_i64().unwrap(), 80000);
+        assert_eq!(obj["current_price"].as_i64().unwrap(), 80000);
+        assert_eq!(obj["status"].as_str().unwrap(), "active");
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "active auction already exists")]
+    fn test_create_auction_duplicate() {
+        let att_id = create_test_attestation("pkg.dup", "expertise");
+        Spi::run(&format!(
+            "SELECT kerai.create_auction('{}'::uuid, 50000, 500, 60, 0, 1, 24)",
+            att_id,
+        ))
+        .unwrap();
+        // Second auction on same attestation should fail
+        Spi::run(&format!(
+            "SELECT kerai.create_auction('{}'::uuid, 50000, 500, 60, 0, 1, 24)",
+            att_id,
+        ))
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn test_place_bid() {
+        let att_id = create_test_attestation("pkg.bid", "state_transition");
+        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.create_auction('{}'::uuid, 50000, 1000, 60, 0, 1, 24)",
+            att_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let auction_id = auction.0["id"].as_str().unwrap();
+
+        let bid = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.place_bid('{}'::uuid, 40000)",
+            auction_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(bid.0["max_price"].as_i64().unwrap(), 40000);
+        assert!(bid.0.as_object().unwrap().contains_key("id"));
+    }
+
+    #[pg_test]
+    fn test_tick_auction_price_decrement() {
+        let att_id = create_test_attestation("pkg.tick", "expertise");
+        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.create_auction('{}'::uuid, 10000, 2000, 60, 0, 1, 24)",
+            att_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let auction_id = auction.0["id"].as_str().unwrap();
+
+        let tick = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.tick_auction('{}'::uuid)",
+            auction_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(tick.0["current_price"].as_i64().unwrap(), 8000);
+        assert_eq!(tick.0["action"].as_str().unwrap(), "price_decremented");
+    }
+
+    #[pg_test]
+    fn test_tick_auction_floor_hit() {
+        let att_id = create_test_attestation("pkg.floor", "expertise");
+        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.create_auction('{}'::uuid, 3000, 5000, 60, 0, 1, 24)",
+            att_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let auction_id = auction.0["id"].as_str().unwrap();
+
+        // Decrement 5000 from 3000 should hit floor
+        let tick = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.tick_auction('{}'::uuid)",
+            auction_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(tick.0["action"].as_str().unwrap(), "open_sourced");
+        assert_eq!(tick.0["reason"].as_str().unwrap(), "floor_price_hit");
+    }
+
+    #[pg_test]
+    fn test_tick_auction_settlement_ready() {
+        let att_id = create_test_attestation("pkg.settle_ready", "expertise");
+        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.create_auction('{}'::uuid, 50000, 1000, 60, 0, 1, 24)",
+            att_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let auction_id = auction.0["id"].as_str().unwrap();
+
+        // Place a bid high enough for the decremented price
+        Spi::run(&format!(
+            "SELECT kerai.place_bid('{}'::uuid, 49000)",
+            auction_id,
+        ))
+        .unwrap();
+
+        let tick = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.tick_auction('{}'::uuid)",
+            auction_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(tick.0["action"].as_str().unwrap(), "settlement_ready");
+        assert!(tick.0["qualifying_bidders"].as_i64().unwrap() >= 1);
+    }
+
+    #[pg_test]
+    fn test_settle_auction() {
+        let att_id = create_test_attestation("pkg.settle", "expertise");
+        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.create_auction('{}'::uuid, 10000, 1000, 60, 0, 1, 24)",
+            att_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let auction_id = auction.0["id"].as_str().unwrap();
+
+        // Place a bid
+        Spi::run(&format!(
+            "SELECT kerai.place_bid('{}'::uuid, 10000)",
+            auction_id,
+        ))
+        .unwrap();
+
+        // Settle at current price (10000)
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.settle_auction('{}'::uuid)",
+            auction_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert_eq!(obj["status"].as_str().unwrap(), "settled");
+        assert_eq!(obj["settled_price"].as_i64().unwrap(), 10000);
+        assert_eq!(obj["bidder_count"].as_i64().unwrap(), 1);
+        assert_eq!(obj["total_revenue"].as_i64().unwrap(), 10000);
+    }
+
+    #[pg_test]
+    fn test_open_source_auction() {
+        let att_id = create_test_attestation("pkg.opensource", "expertise");
+        let auction = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.create_auction('{}'::uuid, 5000, 500, 60, 0, 1, 0)",
+            att_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let auction_id = auction.0["id"].as_str().unwrap();
+
+        // Place bid and settle
+        Spi::run(&format!(
+            "SELECT kerai.place_bid('{}'::uuid, 5000)",
+            auction_id,
+        ))
+        .unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.settle_auction('{}'::uuid)",
+            auction_id,
+        ))
+        .unwrap();
+
+        // Open-source
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.open_source_auction('{}'::uuid)",
+            auction_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(result.0["status"].as_str().unwrap(), "open_sourced");
+    }
+
+    #[pg_test]
+    fn test_market_browse() {
+        let att_id = create_test_attestation("pkg.browse", "expertise");
+        Spi::run(&format!(
+            "SELECT kerai.create_auction('{}'::uuid, 20000, 500, 60, 0, 1, 24)",
+            att_id,
+        ))
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.market_browse(NULL, NULL, 'active')",
+        )
+        .unwrap()
+        .unwrap();
+        let arr = result.0.as_array().unwrap();
+        assert!(!arr.is_empty(), "Should find at least one active auction");
+    }
+
+    #[pg_test]
+    fn test_market_stats() {
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.market_stats()",
+        )
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert!(obj.contains_key("active_auctions"));
+        assert!(obj.contains_key("settled_auctions"));
+        assert!(obj.contains_key("open_sourced"));
+        assert!(obj.contains_key("total_bids"));
+        assert!(obj.contains_key("total_settlement_value"));
+        assert!(obj.contains_key("avg_settlement_price"));
+    }
+
+    #[pg_test]
+    fn test_generate_and_verify_proof() {
+        let att_id = create_test_attestation("pkg.zkp", "state_transition");
+
+        // Generate proof
+        let proof = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.generate_proof('{}'::uuid)",
+            att_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let obj = proof.0.as_object().unwrap();
+        assert_eq!(obj["proof_type"].as_str().unwrap(), "sha256_commitment");
+        let proof_hex = obj["proof_hex"].as_str().unwrap();
+        assert_eq!(proof_hex.len(), 64, "SHA-256 hex should be 64 chars");
+
+        // Verify proof using stored proof_data
+        let verify = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.verify_proof('{}'::uuid,
+                (SELECT proof_data FROM kerai.attestations WHERE id = '{}'::uuid))",
+            att_id, att_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(verify.0["valid"].as_bool().unwrap(), "Proof should verify");
+    }
+
+    #[pg_test]
+    fn test_verify_proof_invalid() {
+        let att_id = create_test_attestation("pkg.bad_proof", "expertise");
+        Spi::run(&format!(
+            "SELECT kerai.generate_proof('{}'::uuid)",
+            att_id,
+        ))
+        .unwrap();
+
+        // Verify with wrong proof data
+        let verify = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.verify_proof('{}'::uuid, '\\xdeadbeef'::bytea)",
+            att_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(!verify.0["valid"].as_bool().unwrap(), "Invalid proof should fail");
+    }
+
+    #[pg_test]
+    fn test_market_balance() {
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.market_balance()",
+        )
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert!(obj.contains_key("earnings"));
+        assert!(obj.contains_key("spending"));
+        assert!(obj.contains_key("net"));
+        assert!(obj.contains_key("active_auctions"));
+        assert!(obj.contains_key("active_bids"));
+    }
+
+    // --- Plan 12: Markdown parser tests ---
+
+    #[pg_test]
+    fn test_parse_markdown_headings() {
+        let source = "# Title\n\n## Section One\n\nParagraph under section one.\n\n## Section Two\n\n### Subsection\n\nDeep content.\n";
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.parse_markdown('{}', 'headings.md')",
+            sql_escape(source),
+        ))
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        assert!(obj["nodes"].as_u64().unwrap() > 0, "Should have parsed nodes");
+
+        // Verify heading hierarchy: H2 should be child of H1
+        let h1_id = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Title'",
+        )
+        .unwrap()
+        .unwrap();
+
+        let h2_parent = Spi::get_one::<String>(
+            "SELECT parent_id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Section One'",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(h2_parent, h1_id, "H2 should be child of H1");
+
+        // H3 should be child of H2 (Section Two)
+        let h2_two_id = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Section Two'",
+        )
+        .unwrap()
+        .unwrap();
+
+        let h3_parent = Spi::get_one::<String>(
+            "SELECT parent_id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Subsection'",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(h3_parent, h2_two_id, "H3 should be child of its preceding H2");
+    }
+
+    #[pg_test]
+    fn test_parse_markdown_paragraphs() {
+        let source = "# Main\n\nFirst paragraph.\n\nSecond paragraph.\n";
+        Spi::run(&format!(
+            "SELECT kerai.parse_markdown('{}', 'paragraphs.md')",
+            sql_escape(source),
+        ))
+        .unwrap();
+
+        let heading_id = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Main'",
+        )
+        .unwrap()
+        .unwrap();
+
+        // Paragraphs should be children of the heading
+        let para_count = Spi::get_one::<i64>(&format!(
+            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'paragraph' AND parent_id = '{}'::uuid",
+            heading_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(para_count >= 2, "Should have at least 2 paragraphs under heading, got {}", para_count);
+    }
+
+    #[pg_test]
+    fn test_summarize_subtree_and_staleness() {
+        let source = "# Sync design\n\nPeers exchange signed operations over the sync protocol. The weather was nice.\n\nEach peer replays sync operations in lamport order.\n";
+        Spi::run(&format!(
+            "SELECT kerai.parse_markdown('{}', 'summary.md')",
+            sql_escape(source),
+        ))
+        .unwrap();
+        let heading_id = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Sync design'",
+        )
+        .unwrap()
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.summarize_subtree('{}'::uuid, 'extractive', 2)",
+            heading_id,
+        ))
+        .unwrap()
+        .unwrap();
+        let obj = result.0.as_object().unwrap();
+        let summary_id = obj["summary_id"].as_str().unwrap().to_string();
+        assert_eq!(obj["stale"], false);
+        assert_eq!(obj["sentences"], 2);
+        assert!(!obj["content"].as_str().unwrap().contains("weather"));
+
+        // Editing a paragraph below the heading makes the summary stale
+        Spi::run(&format!(
+            "UPDATE kerai.nodes SET content = 'Peers gossip operations.' \
+             WHERE kind = 'paragraph' AND parent_id = '{}'::uuid AND content LIKE 'Each peer%'",
+            heading_id,
+        ))
+        .unwrap();
+        let stale = Spi::get_one::<bool>(&format!(
+            "SELECT (metadata->>'stale')::boolean FROM kerai.nodes WHERE id = '{}'::uuid",
+            summary_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(stale, "Summary should be stale after a descendant changed");
+        let queued = Spi::get_one::<pgrx::JsonB>("SELECT kerai.stale_summaries()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(queued.0[0]["summary_id"], summary_id.as_str());
+
+        // Regenerating refreshes the same summary node
+        let again = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.summarize_subtree('{}'::uuid)",
+            heading_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(again.0["summary_id"], summary_id.as_str());
+        assert_eq!(again.0["stale"], false);
+
+        // Agents attach their own summaries alongside
+        Spi::run("SELECT kerai.register_agent('summary-agent', 'llm', 'test-model', NULL)").unwrap();
+        let submitted = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.submit_summary('summary-agent', '{}'::uuid, 'How peers sync.', 'llm')",
+            heading_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(submitted.0["author"], "summary-agent");
+        let edges = Spi::get_one::<i64>(&format!(
+            "SELECT count(*) FROM kerai.edges WHERE target_id = '{}'::uuid AND relation = 'summarizes'",
+            heading_id,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(edges, 2);
+    }
+
+    #[pg_test]
+    fn test_stale_docs_and_suggestions() {
+        Spi::run(
+            "SELECT kerai.parse_source('/// Adds one\nfn stale_add(a: i32) -> i32 { a + 1 }', 'stale_doc.rs')",
+        )
+        .unwrap();
+        let doc_id = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE kind = 'doc_comment' AND content LIKE '%Adds one%'",
+        )
+        .unwrap()
+        .unwrap();
+
+        // The doc was last touched 40 days before the code
+        Spi::run(&format!(
+            "UPDATE kerai.nodes SET modified_at = now() - interval '40 days' WHERE id = '{}'::uuid",
+            doc_id,
+        ))
+        .unwrap();
+
+        let report = Spi::get_one::<pgrx::JsonB>("SELECT kerai.stale_docs(30)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.0["stale"], 1);
+        let doc = &report.0["docs"][0];
+        assert_eq!(doc["doc_id"], doc_id.as_str());
+        assert_eq!(doc["code_name"], "stale_add");
+        assert!(doc["score"].as_f64().unwrap() > 0.5);
+
+        let made = Spi::get_one::<pgrx::JsonB>("SELECT kerai.suggest_doc_updates(30)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(made.0["created"], 1);
+        let again = Spi::get_one::<pgrx::JsonB>("SELECT kerai.suggest_doc_updates(30)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(again.0["created"], 0, "One suggestion per stale doc");
+
+        // Rewriting the doc brings it current and resolves the suggestion
+        Spi::run(&format!(
+            "UPDATE kerai.nodes SET content = ' Adds one to a' WHERE id = '{}'::uuid",
+            doc_id,
+        ))
+        .unwrap();
+        let fixed = Spi::get_one::<pgrx::JsonB>("SELECT kerai.suggest_doc_updates(30)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(fixed.0["resolved"], 1);
+    }
+
+    #[pg_test]
+    fn test_parse_markdown_code_block() {
+        let source = "# Code\n\n```rust\nfn main() {\n    println!(\"hello\");\n}\n```\n";
+        Spi::run(&format!(
+            "SELECT kerai.parse_markdown('{}', 'codeblock.md')",
+            sql_escape(source),
+        ))
+        .unwrap();
+
+        let lang = Spi::get_one::<pgrx::JsonB>(
+            "SELECT metadata FROM kerai.nodes WHERE kind = 'code_block' LIMIT 1",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(lang.0["language"].as_str().unwrap(), "rust", "Code block should preserve language metadata");
+    }
+
+    #[pg_test]
+    fn test_parse_markdown_links() {
+        let source = "# Links\n\n[Example](https://example.com) and [local](other.md).\n";
+        Spi::run(&format!(
+            "SELECT kerai.parse_markdown('{}', 'links.md')",
+            sql_escape(source),
+        ))
+        .unwrap();
+
+        let link_count = Spi::get_one::<i64>(
+            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'link'",
+        )
+        .unwrap()
+        .unwrap();
+        assert!(link_count >= 2, "Should have at least 2 link nodes, got {}", link_count);
+
+        // Check URL metadata
+        let meta = Spi::get_one::<pgrx::JsonB>(
+            "SELECT metadata FROM kerai.nodes WHERE kind = 'link' AND content LIKE '%Example%' LIMIT 1",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(meta.0["url"].as_str().unwrap(), "https://example.com");
+    }
+
+    #[pg_test]
+    fn test_parse_markdown_links_documents() {
+        let parse = |source: &str, filename: &str| {
+            Spi::get_one_with_args::<pgrx::JsonB>(
+                "SELECT kerai.parse_markdown($1, $2)",
+                &[source.into(), filename.into()],
+            )
+            .unwrap()
+            .unwrap()
+            .0
+        };
+        let links = || {
+            Spi::get_one::<pgrx::JsonB>(
+                "SELECT jsonb_agg(jsonb_build_object(
+                    'from', s.content, 'to', t.content, 'link', e.metadata
+                ) ORDER BY s.content, t.content)
+                FROM kerai.edges e
+                JOIN kerai.nodes s ON s.id = e.source_id
+                JOIN kerai.nodes t ON t.id = e.target_id
+                WHERE e.relation = 'links_to' AND s.kind = 'document' AND t.kind = 'document'
+                  AND s.content IN ('ml_index.md', 'guides/ml_setup.md', 'ml_notes.md')",
+            )
+            .unwrap()
+            .unwrap()
+            .0
+        };
+
+        // Linked before the documents it links to exist
+        let setup = parse(
+            "# Setup\n\nBack to [the index](../ml_index.md#top) and [[ML_Notes|notes]].\n",
+            "guides/ml_setup.md",
+        );
+        assert_eq!(setup["edges"], 0);
+        let index = parse(
+            "# Index\n\nSee [setup](guides/ml_setup.md) and [site](https://example.com).\n",
+            "ml_index.md",
+        );
+        assert_eq!(index["edges"], 2, "{index}");
+        parse("# Notes\n\nNothing here.\n", "ml_notes.md");
+
+        let edges = links();
+        assert_eq!(edges.as_array().map(Vec::len), Some(3), "{edges}");
+        assert_eq!(edges[0]["from"], "guides/ml_setup.md");
+        assert_eq!(edges[0]["to"], "ml_index.md");
+        assert_eq!(edges[0]["link"]["url"], "../ml_index.md#top");
+        assert_eq!(edges[0]["link"]["fragment"], "top");
+        assert_eq!(edges[1]["to"], "ml_notes.md");
+        assert_eq!(edges[1]["link"]["wikilink"], "ML_Notes");
+        assert_eq!(edges[1]["link"]["alias"], "notes");
+        assert_eq!(edges[2]["from"], "ml_index.md");
+        assert_eq!(edges[2]["to"], "guides/ml_setup.md");
+
+        // Reparsing the index relinks it both ways, without duplicates
+        parse(
+            "# Index\n\nSee [setup](guides/ml_setup.md) and [site](https://example.com).\n",
+            "ml_index.md",
+        );
+        assert_eq!(links(), edges);
+    }
+
+    #[pg_test]
+    fn test_link_code_mentions() {
+        let doc = "# Ledger\n\nCall `reconcile_ledger()` for each `LedgerEntry` in \
+                   `mention_ledger.rs`; `no_such_symbol` is unknown.\n";
+        let parse_doc = || {
+            Spi::get_one_with_args::<pgrx::JsonB>(
+                "SELECT kerai.parse_markdown($1, 'mention_guide.md')",
+                &[doc.into()],
+            )
+            .unwrap()
+            .unwrap()
+            .0
+        };
+
+        // Nothing to mention until the code is parsed
+        assert_eq!(parse_doc()["edges"], 0);
+        Spi::run(
+            "SELECT kerai.parse_source(
+                'pub fn reconcile_ledger() {} pub struct LedgerEntry { amount: i64 }',
+                'mention_ledger.rs')",
+        )
+        .unwrap();
+        let linked =
+            Spi::get_one::<pgrx::JsonB>("SELECT kerai.link_code_mentions('mention_guide_md')")
+                .unwrap()
+                .unwrap()
+                .0;
+        assert_eq!(linked["documents"], 1, "{linked}");
+        assert_eq!(linked["spans"], 4, "{linked}");
+        assert_eq!(linked["edges"], 3, "{linked}");
+        assert_eq!(linked["unresolved"], 1, "{linked}");
+
+        let fn_id = Spi::get_one::<pgrx::Uuid>(
+            "SELECT id FROM kerai.nodes WHERE kind = 'fn' AND content = 'reconcile_ledger'",
+        )
+        .unwrap()
+        .unwrap();
+        let around =
+            Spi::get_one_with_args::<pgrx::JsonB>("SELECT kerai.mentions($1)", &[fn_id.into()])
+                .unwrap()
+                .unwrap()
+                .0;
+        let by = around["mentioned_by"].as_array().unwrap();
+        assert_eq!(by.len(), 1, "{around}");
+        assert_eq!(by[0]["span"], "reconcile_ledger()");
+        assert_eq!(by[0]["confidence"], 0.8);
+        assert_eq!(by[0]["file"], "mention_guide.md");
+
+        // The doc is affected by changing the function it names
+        let impact =
+            Spi::get_one_with_args::<pgrx::JsonB>("SELECT kerai.impact($1, 3)", &[fn_id.into()])
+                .unwrap()
+                .unwrap()
+                .0;
+        assert!(
+            impact["files"].to_string().contains("mention_guide.md"),
+            "{impact}"
+        );
+
+        // Reparsing relinks the doc without duplicates
+        assert_eq!(parse_doc()["edges"], 3);
+        let count = Spi::get_one::<i64>(
+            "SELECT count(*)::bigint FROM kerai.edges WHERE relation = 'mentions'",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[pg_test]
+    fn test_parse_markdown_table() {
+        let source = "# Tables\n\n| Name | Value |\n| --- | --- |\n| foo | 42 |\n| bar | 99 |\n";
+        Spi::run(&format!(
+            "SELECT kerai.parse_markdown('{}', 'table.md')",
+            sql_escape(source),
+        ))
+        .unwrap();
+
+        let table_count = Spi::get_one::<i64>(
+            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'table'",
+        )
+        .unwrap()
+        .unwrap();
+        assert!(table_count >= 1, "Should have at least 1 table node");
+
+        let cell_count = Spi::get_one::<i64>(
+            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'table_cell'",
+        )
+        .unwrap()
+        .unwrap();
+        assert!(cell_count >= 4, "Should have at least 4 table cells (2 cols x 2+ rows), got {}", cell_count);
+    }
+
+    #[pg_test]
+    fn test_parse_markdown_roundtrip() {
+        let source = "# Hello World\n\nThis is a paragraph.\n\n## Details\n\n- Item one\n- Item two\n";
+        Spi::run(&format!(
+            "SELECT kerai.parse_markdown('{}', 'roundtrip.md')",
+            sql_escape(source),
+        ))
+        .unwrap();
+
+        let doc_id = Spi::get_one::<pgrx::Uuid>(
+            "SELECT id FROM kerai.nodes WHERE kind = 'document' AND content = 'roundtrip.md'",
+        )
+        .unwrap()
+        .unwrap();
+
+        let reconstructed = Spi::get_one::<String>(&format!(
+            "SELECT kerai.reconstruct_markdown('{}'::uuid)",
+            doc_id,
+        ))
+        .unwrap()
+        .unwrap();
+
+        // Verify key content is preserved
+        assert!(reconstructed.contains("# Hello World"), "Should contain H1");
+        assert!(reconstructed.contains("This is a paragraph"), "Should contain paragraph text");
+        assert!(reconstructed.contains("## Details"), "Should contain H2");
+        assert!(reconstructed.contains("Item one"), "Should contain list items");
+    }
+
+    #[pg_test]
+    fn test_parse_markdown_idempotent() {
+        let source = "# Idempotent\n\nSame content.\n";
+        Spi::run(&format!(
+            "SELECT kerai.parse_markdown('{}', 'idempotent.md')",
+            sql_escape(source),
+        ))
+        .unwrap();
+        let count1 = Spi::get_one::<i64>(
+            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'document' AND content = 'idempotent.md'",
+        )
+        .unwrap()
+        .unwrap();
+
+        // Parse again — should delete and re-insert
+        Spi::run(&format!(
+            "SELECT kerai.parse_markdown('{}', 'idempotent.md')",
+            sql_escape(source),
+        ))
+        .unwrap();
+        let count2 = Spi::get_one::<i64>(
+            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'document' AND content = 'idempotent.md'",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(count1, count2, "Idempotent parse should not duplicate document nodes");
+        assert_eq!(count1, 1, "Should have exactly one document node");
+    }
+
+    #[pg_test]
+    fn test_outline_edits_round_trip() {
+        let source = "# A\n\n## B\n\nb text\n\n### C\n\nc text\n\n### D\n";
+        Spi::run_with_args(
+            "SELECT kerai.parse_markdown($1, 'outline.md')",
+            &[source.into()],
+        )
+        .unwrap();
+        let node = |kind: &str, content: &str| {
+            Spi::get_one_with_args::<pgrx::Uuid>(
+                "SELECT id FROM kerai.nodes WHERE kind = $1 AND content = $2",
+                &[kind.into(), content.into()],
+            )
+            .unwrap()
+            .unwrap()
+        };
+        let doc = node("document", "outline.md");
+        let markdown = || {
+            Spi::get_one_with_args::<String>("SELECT kerai.reconstruct_markdown($1)", &[doc.into()])
+                .unwrap()
+                .unwrap()
+        };
+        let edit = |sql: &str, id: pgrx::Uuid| {
+            Spi::get_one_with_args::<pgrx::JsonB>(sql, &[id.into()])
+                .unwrap()
+                .unwrap()
+                .0
+        };
+        let original = markdown();
+
+        // ### C becomes ## C, taking ### D into its section
+        let c = node("heading", "C");
+        let promoted = edit("SELECT kerai.promote_heading($1)", c);
+        assert_eq!(promoted["level"], 2);
+        assert_eq!(markdown(), original.replace("### C", "## C"), "{promoted}");
+        let (parent, level) = Spi::get_two_with_args::<pgrx::Uuid, i64>(
+            "SELECT parent_id, (metadata->>'level')::bigint FROM kerai.nodes WHERE id = $1",
+            &[node("heading", "D").into()],
+        )
+        .unwrap();
+        assert_eq!(parent, Some(c));
+        assert_eq!(level, Some(3));
+
+        // And back under ## B, letting ### D go again
+        edit("SELECT kerai.demote_heading($1)", c);
+        assert_eq!(markdown(), original);
+        let parent = Spi::get_one_with_args::<pgrx::Uuid>(
+            "SELECT parent_id FROM kerai.nodes WHERE id = $1",
+            &[node("heading", "D").into()],
+        )
+        .unwrap();
+        assert_eq!(parent, Some(node("heading", "B")));
+
+        // Splitting a paragraph and merging it back restores it
+        let split = Spi::get_one_with_args::<pgrx::JsonB>(
+            "SELECT kerai.split_node($1, 2)",
+            &[node("paragraph", "b text").into()],
+        )
+        .unwrap()
+        .unwrap()
+        .0;
+        assert!(markdown().contains("b \n\ntext"), "{}", markdown());
+        let first = node("paragraph", "b ");
+        let merged = edit("SELECT kerai.merge_with_next($1)", first);
+        assert_eq!(merged["merged_id"], split["new_node_id"]);
+        assert_eq!(markdown(), original);
+
+        // Every step went through apply_op and left versions behind
+        let versions = Spi::get_one_with_args::<i64>(
+            "SELECT count(*) FROM kerai.versions WHERE node_id = $1 AND operation = 'update_metadata'",
+            &[c.into()],
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(versions, 2);
+    }
+
+    // --- Plan 12: FTS search tests ---
+
+    #[pg_test]
+    fn test_search_fts_basic() {
+        Spi::run(
+            "SELECT kerai.parse_source('fn calculate_total() { let sum = 0; }', 'fts_basic.rs')",
+        )
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.search('calculate', NULL, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let arr = result.0.as_array().unwrap();
+        assert!(!arr.is_empty(), "FTS should find nodes matching 'calculate'");
+    }
+
+    #[pg_test]
+    fn test_search_fts_with_kind_filter() {
+        Spi::run(
+            "SELECT kerai.parse_source('struct SearchTarget { value: i32 }', 'fts_kind.rs')",
+        )
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.search('SearchTarget', 'struct', NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let arr = result.0.as_array().unwrap();
+        for item in arr {
+            assert_eq!(item["kind"].as_str().unwrap(), "struct");
+        }
+    }
+
+    #[pg_test]
+    fn test_search_fts_no_matches() {
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.search('xyzzy_nonexistent_term_zzz', NULL, NULL)",
+        )
+        .unwrap()
+        .unwrap();
+        let arr = result.0.as_array().unwrap();
+        assert!(arr.is_empty(), "FTS should return empty for non-matching terms");
+    }
+
+    #[pg_test]
+    fn test_search_fts_websearch_syntax() {
+        Spi::run(
+            "SELECT kerai.parse_markdown('# Notes\n\nThe quorum ledger settles nightly.\n\nA quorum vote needs every peer.\n', 'fts_web.md')",
+        )
+        .unwrap();
+
+        // Single-argument form, with an excluded term
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.search('quorum -ledger')")
+            .unwrap()
+            .unwrap();
+        let arr = result.0.as_array().unwrap();
+        assert_eq!(arr.len(), 1, "Excluded term should filter one paragraph: {:?}", arr);
+        assert!(arr[0]["content"].as_str().unwrap().contains("vote"));
+        assert!(arr[0]["headline"].as_str().unwrap().contains("**quorum**"));
+
+        // Quoted phrases must match in order
+        let phrase = Spi::get_one::<pgrx::JsonB>("SELECT kerai.search('\"ledger settles\"')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(phrase.0.as_array().unwrap().len(), 1);
+    }
+
+    #[pg_test]
+    fn test_find_fuzzy_tolerates_typos() {
+        Spi::run("SELECT kerai.parse_source('fn reconstruct_widget() {} fn unrelated_thing() {}', 'fuzzy.rs')")
+            .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.find_fuzzy('reconsturct_widget')")
+            .unwrap()
+            .unwrap();
+        let arr = result.0.as_array().unwrap();
+        assert!(!arr.is_empty(), "Typo should still find the symbol");
+        assert_eq!(arr[0]["name"], "reconstruct_widget");
+        assert_eq!(arr[0]["kind"], "fn");
+        assert_eq!(arr[0]["distance"], 2);
+        assert!(arr[0]["similarity"].as_f64().unwrap() > 0.3);
+
+        // Nothing within a single edit
+        let strict = Spi::get_one::<pgrx::JsonB>("SELECT kerai.find_fuzzy('reconsturct_widget', 1)")
+            .unwrap()
+            .unwrap();
+        assert!(strict.0.as_array().unwrap().is_empty());
+    }
+
+    #[pg_test]
+    fn test_impact_follows_reverse_edges() {
+        Spi::run("SELECT kerai.parse_source('fn core_fn() {}', 'impact_core.rs')").unwrap();
+        Spi::run("SELECT kerai.parse_source('fn caller_fn() {}', 'impact_caller.rs')").unwrap();
+        Spi::run("SELECT kerai.parse_source('fn outer_fn() {}', 'impact_outer.rs')").unwrap();
+        let id = |name: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT id::text FROM kerai.nodes WHERE kind = 'fn' AND content = '{}'",
+                name
+            ))
+            .unwrap()
+            .unwrap()
+        };
+        let (core, caller, outer) = (id("core_fn"), id("caller_fn"), id("outer_fn"));
+        Spi::run(&format!(
+            "INSERT INTO kerai.edges (source_id, target_id, relation) VALUES
+                ('{caller}'::uuid, '{core}'::uuid, 'calls'),
+                ('{outer}'::uuid, '{caller}'::uuid, 'imports'),
+                ('{core}'::uuid, '{outer}'::uuid, 'links_to')",
+        ))
+        .unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.impact('{core}'::uuid)"))
+            .unwrap()
+            .unwrap()
+            .0;
+        let affected = result["affected"].as_array().unwrap();
+        assert_eq!(affected.len(), 2, "Unrelated relations are not followed");
+        assert_eq!(affected[0]["id"], caller);
+        assert_eq!(affected[0]["distance"], 1);
+        assert_eq!(affected[0]["via"], "calls");
+        assert_eq!(affected[1]["id"], outer);
+        assert_eq!(affected[1]["distance"], 2);
+        assert_eq!(affected[1]["confidence"].as_f64().unwrap(), 0.63);
+
+        let files = result["files"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0]["file"], "impact_caller.rs");
+        assert_eq!(files[0]["cross_file"], true);
+
+        let shallow = Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.impact('{core}'::uuid, 1)"))
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(shallow["affected"].as_array().unwrap().len(), 1);
+    }
+
+    #[pg_test]
+    fn test_dependency_graph_and_cycles() {
+        Spi::run("SELECT kerai.parse_source('fn dep_a() {}', 'dep_a.rs')").unwrap();
+        Spi::run("SELECT kerai.parse_source('fn dep_b() {}', 'dep_b.rs')").unwrap();
+        Spi::run("SELECT kerai.parse_source('fn dep_c() {}', 'dep_c.rs')").unwrap();
+        let id = |name: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT id::text FROM kerai.nodes WHERE kind = 'fn' AND content = '{}'",
+                name
+            ))
+            .unwrap()
+            .unwrap()
+        };
+        let (a, b, c) = (id("dep_a"), id("dep_b"), id("dep_c"));
+        Spi::run(&format!(
+            "INSERT INTO kerai.edges (source_id, target_id, relation) VALUES
+                ('{a}'::uuid, '{b}'::uuid, 'calls'),
+                ('{b}'::uuid, '{c}'::uuid, 'imports'),
+                ('{c}'::uuid, '{a}'::uuid, 'calls')",
+        ))
+        .unwrap();
+
+        let root = Spi::get_one::<String>(
+            "SELECT path::text FROM kerai.nodes WHERE kind = 'file' AND content = 'dep_a.rs'",
+        )
+        .unwrap()
+        .unwrap();
+        let graph = Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.dependency_graph('{root}')"))
+            .unwrap()
+            .unwrap()
+            .0;
+        let modules = graph["modules"].as_array().unwrap();
+        assert_eq!(modules.len(), 3, "Reaches b and c transitively from a");
+        assert_eq!(
+            modules.iter().filter(|m| m["internal"] == true).count(),
+            1,
+            "Only dep_a.rs is under the root"
+        );
+        assert_eq!(graph["edges"].as_array().unwrap().len(), 3);
+
+        let cycles = Spi::get_one::<pgrx::JsonB>("SELECT kerai.find_cycles()")
+            .unwrap()
+            .unwrap()
+            .0;
+        let cycles = cycles.as_array().unwrap();
+        assert_eq!(cycles.len(), 1, "Rotations are reported once");
+        assert_eq!(cycles[0]["length"], 3);
+        assert_eq!(cycles[0]["relations"], serde_json::json!(["calls", "imports"]));
+
+        let short = Spi::get_one::<pgrx::JsonB>("SELECT kerai.find_cycles(2)")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert!(short.as_array().unwrap().is_empty());
+    }
+
+    #[pg_test]
+    fn test_graph_lod_aggregates_and_caches() {
+        Spi::run(
+            "INSERT INTO kerai.nodes (instance_id, kind, content, path)
+             SELECT i.id, v.kind, v.content, v.path::ltree
+             FROM kerai.instances i, (VALUES
+                ('module', 'a', 'lod_app.a'), ('fn', 'f1', 'lod_app.a.f1'),
+                ('fn', 'f2', 'lod_app.a.f2'), ('module', 'b', 'lod_app.b'),
+                ('fn', 'g', 'lod_app.b.g')) v(kind, content, path)
+             WHERE i.is_self",
+        )
+        .unwrap();
+        Spi::run(
+            "INSERT INTO kerai.edges (source_id, target_id, relation)
+             SELECT s.id, t.id, v.relation
+             FROM (VALUES ('lod_app.a.f1', 'lod_app.b.g', 'calls'),
+                          ('lod_app.a.f2', 'lod_app.b.g', 'calls'),
+                          ('lod_app.a.f1', 'lod_app.a.f2', 'calls')) v(source, target, relation)
+             JOIN kerai.nodes s ON s.path = v.source::ltree
+             JOIN kerai.nodes t ON t.path = v.target::ltree",
+        )
+        .unwrap();
+
+        let graph = Spi::get_one::<pgrx::JsonB>("SELECT kerai.graph_lod('lod_app')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(graph["cached"], false);
+        let nodes = graph["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0]["key"], "lod_app.a");
+        assert_eq!(nodes[0]["size"], 3);
+        assert_eq!(nodes[0]["expandable"], true);
+        let edges = graph["edges"].as_array().unwrap();
+        assert_eq!(edges.len(), 1, "The edge inside lod_app.a is dropped");
+        assert_eq!(edges[0]["weight"], 2);
+        assert_eq!(edges[0]["relations"]["calls"], 2);
+
+        let again = Spi::get_one::<pgrx::JsonB>("SELECT kerai.graph_lod('lod_app')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(again["cached"], true);
+        assert_eq!(again["nodes"], graph["nodes"]);
+
+        // A new node changes the fingerprint, and expanding shows the functions
+        Spi::run(
+            "INSERT INTO kerai.nodes (instance_id, kind, content, path)
+             SELECT id, 'fn', 'f3', 'lod_app.a.f3' FROM kerai.instances WHERE is_self",
+        )
+        .unwrap();
+        let changed = Spi::get_one::<pgrx::JsonB>("SELECT kerai.graph_lod('lod_app')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(changed["cached"], false);
+        assert_eq!(changed["nodes"][0]["size"], 4);
+        let expanded = Spi::get_one::<pgrx::JsonB>("SELECT kerai.graph_lod('lod_app.a')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(expanded["cached"], false);
+        assert_eq!(expanded["nodes"].as_array().unwrap().len(), 3);
+        let truncated = Spi::get_one::<pgrx::JsonB>("SELECT kerai.graph_lod('lod_app', 2, 1)")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(truncated["truncated"], true);
+        assert_eq!(truncated["total_nodes"], 6);
+    }
+
+    #[pg_test]
+    fn test_sandbox_create_promote_and_expire() {
+        Spi::run("SELECT kerai.parse_source('fn sbx_target() {}', 'sbx_file.rs')").unwrap();
+        let path = Spi::get_one::<String>(
+            "SELECT path::text FROM kerai.nodes WHERE kind = 'file' AND content = 'sbx_file.rs'",
+        )
+        .unwrap()
+        .unwrap();
+
+        let sandbox =
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.create_sandbox('{path}')"))
+                .unwrap()
+                .unwrap()
+                .0;
+        let id = sandbox["id"].as_str().unwrap().to_string();
+        assert!(
+            sandbox["nodes"].as_i64().unwrap() >= 2,
+            "File and fn are copied"
+        );
+
+        let copy = Spi::get_one::<String>(&format!(
+            "SELECT sn.node_id::text FROM kerai.sandbox_nodes sn
+             JOIN kerai.nodes n ON n.id = sn.origin_id
+             WHERE sn.sandbox_id = '{id}'::uuid AND n.kind = 'fn'"
+        ))
+        .unwrap()
+        .unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.apply_op('update_content', '{copy}'::uuid, '{{\"new_content\": \"sbx_renamed\"}}'::jsonb)",
+        ))
+        .unwrap();
+
+        let result =
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.promote_sandbox('{id}'::uuid)"))
+                .unwrap()
+                .unwrap()
+                .0;
+        assert!(result["conflicts"].as_array().unwrap().is_empty());
+        assert_eq!(result["changeset"][0]["op_type"], "update_content");
+
+        let renamed = Spi::get_one::<i64>(&format!(
+            "SELECT count(*) FROM kerai.nodes WHERE content = 'sbx_renamed' AND path <@ '{path}'::ltree"
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(renamed, 1, "Origin fn is renamed");
+        let left = Spi::get_one::<i64>(&format!(
+            "SELECT count(*) FROM kerai.nodes
+             WHERE path ~ (SELECT prefix || '.*' FROM kerai.sandboxes WHERE id = '{id}'::uuid)::lquery"
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(left, 0, "Sandbox copy is removed");
+        let status = Spi::get_one::<String>(&format!(
+            "SELECT status FROM kerai.sandboxes WHERE id = '{id}'::uuid"
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(status, "promoted");
+
+        let stale = Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.create_sandbox('{path}')"))
+            .unwrap()
+            .unwrap()
+            .0;
+        Spi::run(&format!(
+            "UPDATE kerai.sandboxes SET expires_at = now() - interval '1 minute' WHERE id = '{}'::uuid",
+            stale["id"].as_str().unwrap()
+        ))
+        .unwrap();
+        let cleaned = Spi::get_one::<pgrx::JsonB>("SELECT kerai.cleanup_sandboxes()")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(cleaned["expired"], 1);
+    }
+
+    #[pg_test]
+    fn test_dead_code_suggestions() {
+        let source = "fn main() { live(); }\nfn live() {}\nfn dead_helper() {}\npub fn api() {}\nstruct Unused;\n";
+        Spi::run(&format!(
+            "SELECT kerai.parse_source('{}', 'dead_code.rs')",
+            sql_escape(source),
+        ))
+        .unwrap();
+        Spi::run("SELECT kerai.resolve_calls()").unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.dead_code()")
+            .unwrap()
+            .unwrap()
+            .0;
+        let mut names: Vec<&str> = result["dead"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|d| d["file"] == "dead_code.rs")
+            .map(|d| d["name"].as_str().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["Unused", "dead_helper"],
+            "main, live and pub api are not dead"
+        );
+
+        let again = Spi::get_one::<pgrx::JsonB>("SELECT kerai.dead_code()")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(again["created"], 0, "Existing suggestions are not repeated");
+
+        let file_id = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE kind = 'file' AND content = 'dead_code.rs'",
+        )
+        .unwrap()
+        .unwrap();
+        let reconstructed = Spi::get_one::<String>(&format!(
+            "SELECT kerai.reconstruct_file_with_options('{}'::uuid, '{{\"suggestions\": true}}'::jsonb)",
+            sql_escape(&file_id),
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(
+            reconstructed.contains("// kerai: Function `dead_helper` is never used"),
+            "Dead code is surfaced as a kerai comment, got:\n{}",
+            reconstructed,
+        );
+    }
+
+    #[pg_test]
+    fn test_job_lifecycle() {
+        let started = Spi::get_one::<pgrx::JsonB>("SELECT kerai.job_start('import', 4)")
+            .unwrap()
+            .unwrap();
+        let id = started.0["id"].as_str().unwrap().to_string();
+
+        let step = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.job_progress('{id}'::uuid, 1, 'src/lib.rs')"
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(step.0["cancel_requested"], false);
+
+        // Cancelling by prefix flags the job; its own backend is not signalled
+        let cancel =
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.cancel_job('{}')", &id[..8]))
+                .unwrap()
+                .unwrap();
+        assert_eq!(cancel.0["id"], id.as_str());
+        assert_eq!(cancel.0["signalled"], false);
+
+        let step =
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.job_progress('{id}'::uuid, 2)"))
+                .unwrap()
+                .unwrap();
+        assert_eq!(step.0["cancel_requested"], true);
+
+        let finished = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.job_finish('{id}'::uuid, 'cancelled', '{{\"files\": 2}}'::jsonb)"
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(finished.0["status"], "cancelled");
+        assert_eq!(finished.0["done"], 2);
+
+        let jobs = Spi::get_one::<pgrx::JsonB>("SELECT kerai.list_jobs()")
+            .unwrap()
+            .unwrap();
+        let job = jobs
+            .0
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|j| j["id"] == id.as_str())
+            .unwrap();
+        assert_eq!(job["status"], "cancelled");
+        assert_eq!(job["result"]["files"], 2);
+    }
+
+    #[pg_test]
+    fn test_job_reaping() {
+        let start = |max_attempts: i32| {
+            let job = Spi::get_one::<pgrx::JsonB>(&format!(
+                "SELECT kerai.job_start('sync', NULL, {max_attempts}, '{{\"peer\": \"a\"}}'::jsonb)"
+            ))
+            .unwrap()
+            .unwrap();
+            job.0["id"].as_str().unwrap().to_string()
+        };
+        let once = start(1);
+        let retried = start(2);
+        let fresh = start(1);
+
+        // Only stale heartbeats are reaped; the fresh job keeps running
+        Spi::run(&format!(
+            "UPDATE kerai.jobs SET heartbeat_at = now() - interval '1 hour'
+             WHERE id IN ('{once}'::uuid, '{retried}'::uuid)"
+        ))
+        .unwrap();
+        let reaped = Spi::get_one::<pgrx::JsonB>("SELECT kerai.reap_jobs(300, true, 30)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(reaped.0["failed"].as_array().unwrap().len(), 1);
+        assert_eq!(reaped.0["failed"][0]["id"], once.as_str());
+        assert_eq!(reaped.0["requeued"].as_array().unwrap().len(), 1);
+        assert_eq!(reaped.0["requeued"][0]["id"], retried.as_str());
+
+        let status = |id: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT status FROM kerai.jobs WHERE id = '{id}'::uuid"
+            ))
+            .unwrap()
+            .unwrap()
+        };
+        assert_eq!(status(&once), "failed");
+        assert_eq!(status(&fresh), "running");
+
+        // A requeued job is not claimed before its backoff has passed
+        let early = Spi::get_one::<pgrx::JsonB>("SELECT kerai.claim_job('sync')").unwrap();
+        assert!(early.is_none());
+        Spi::run(&format!(
+            "UPDATE kerai.jobs SET run_after = now() - interval '1 second' WHERE id = '{retried}'::uuid"
+        ))
+        .unwrap();
+        let claimed = Spi::get_one::<pgrx::JsonB>("SELECT kerai.claim_job('sync')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(claimed.0["id"], retried.as_str());
+        assert_eq!(claimed.0["attempts"], 2);
+        assert_eq!(claimed.0["args"]["peer"], "a");
+
+        // Out of attempts, the next reap fails it for good
+        Spi::run(&format!(
+            "UPDATE kerai.jobs SET heartbeat_at = now() - interval '1 hour' WHERE id = '{retried}'::uuid"
+        ))
+        .unwrap();
+        let reaped = Spi::get_one::<pgrx::JsonB>("SELECT kerai.reap_jobs(300, true, 30)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(reaped.0["failed"][0]["id"], retried.as_str());
+        assert_eq!(status(&retried), "failed");
+
+        Spi::run(&format!("SELECT kerai.job_finish('{fresh}'::uuid, 'done')")).unwrap();
+        let summary = Spi::get_one::<pgrx::JsonB>("SELECT kerai.jobs_summary()")
+            .unwrap()
+            .unwrap();
+        let sync = summary
+            .0
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|s| s["kind"] == "sync")
+            .unwrap();
+        assert_eq!(sync["done"], 1);
+        assert_eq!(sync["failed"], 2);
+        assert_eq!(sync["retries"], 1);
+    }
+
+    #[pg_test]
+    fn test_seed_demo() {
+        Spi::run("SELECT kerai.bootstrap_instance()").ok();
+
+        let first = Spi::get_one::<pgrx::JsonB>("SELECT kerai.seed('demo')")
+            .unwrap()
+            .unwrap();
+        let seeded = first.0["seeded"].as_array().unwrap();
+        assert_eq!(seeded.len(), 3, "demo seeds every fixture: {}", first.0);
+        assert!(seeded.iter().all(|s| s["nodes"].as_u64().unwrap() > 0));
+
+        let count = |sql: &str| Spi::get_one::<i64>(sql).unwrap().unwrap_or(0);
+        assert_eq!(
+            count("SELECT count(*) FROM kerai.nodes WHERE kind = 'crate' AND content = 'wordfreq'"),
+            1
+        );
+        assert_eq!(count("SELECT count(*) FROM kerai.nodes WHERE kind = 'document' AND content = 'notes/index.md'"), 1);
+        assert!(count("SELECT count(*) FROM kerai.edges WHERE relation = 'cites'") >= 3);
+
+        // Seeding again finds everything in place
+        let again = Spi::get_one::<pgrx::JsonB>("SELECT kerai.seed('demo')")
+            .unwrap()
+            .unwrap();
+        assert!(again.0["seeded"].as_array().unwrap().is_empty());
+        assert_eq!(
+            again.0["skipped"],
+            serde_json::json!(["crate", "notes", "paper"])
+        );
+        assert_eq!(
+            count("SELECT count(*) FROM kerai.nodes WHERE kind = 'crate' AND content = 'wordfreq'"),
+            1
+        );
+
+        let fixtures = Spi::get_one::<pgrx::JsonB>("SELECT kerai.seed_fixtures()")
+            .unwrap()
+            .unwrap();
+        assert!(fixtures
+            .0
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|f| f["name"] == "demo"));
+    }
+
+    #[pg_test]
+    fn test_custom_rules() {
+        Spi::run("SELECT kerai.bootstrap_instance()").ok();
+        Spi::run(
+            "SELECT kerai.parse_source('fn todo_later() {}\nfn done() {}\n', 'test_rules.rs')",
+        )
+        .unwrap();
+
+        Spi::run(
+            "SELECT kerai.add_rule('no-todo-fns', 'Finish {kind} {name}', \
+             predicate => $$n.kind = 'fn' AND n.content LIKE 'todo\\_%'$$, severity => 'info')",
+        )
+        .unwrap();
+
+        let run = Spi::get_one::<pgrx::JsonB>("SELECT kerai.run_rules()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(run.0["created"], 1, "one fn matches: {}", run.0);
+
+        let listed = Spi::get_one::<pgrx::JsonB>("SELECT kerai.list_suggestions('test_rules')")
+            .unwrap()
+            .unwrap();
+        let files = listed.0.as_array().unwrap();
+        assert_eq!(files.len(), 1);
+        let found = files[0]["suggestions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|s| s["rule"] == "no-todo-fns")
+            .unwrap();
+        assert_eq!(found["message"], "Finish fn todo_later");
+        assert_eq!(found["severity"], "info");
+
+        // Nothing new on a second run; resolved once the fn no longer matches
+        let again = Spi::get_one::<pgrx::JsonB>("SELECT kerai.run_rules('no-todo-fns')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(again.0["created"], 0);
+        Spi::run(
+            "UPDATE kerai.nodes SET content = 'later' WHERE kind = 'fn' AND content = 'todo_later'",
+        )
+        .unwrap();
+        let resolved = Spi::get_one::<pgrx::JsonB>("SELECT kerai.run_rules('no-todo-fns')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(resolved.0["resolved"], 1);
+
+        let removed = Spi::get_one::<pgrx::JsonB>("SELECT kerai.remove_rule('no-todo-fns')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(removed.0["suggestions"], 1);
+        let rules = Spi::get_one::<pgrx::JsonB>("SELECT kerai.list_rules()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(rules.0, serde_json::json!([]));
+    }
+
+    #[pg_test]
+    fn test_accept_and_dismiss_suggestions() {
+        Spi::run("SELECT kerai.bootstrap_instance()").ok();
+        Spi::run("SELECT kerai.parse_source('fn todo_a() {}\nfn todo_b() {}\n', 'test_fixes.rs')")
+            .unwrap();
+        Spi::run(
+            "SELECT kerai.add_rule('finish-todo', 'Finish {name}', \
+             predicate => $$n.kind = 'fn' AND n.content LIKE 'todo\\_%'$$, \
+             fix => '{\"op_type\": \"update_content\", \
+                      \"payload\": {\"new_content\": \"done_{content}\"}}'::jsonb)",
+        )
+        .unwrap();
+        Spi::run("SELECT kerai.run_rules('finish-todo')").unwrap();
+        let suggestion_for = |name: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT e.source_id::text FROM kerai.edges e
+                 JOIN kerai.nodes t ON t.id = e.target_id
+                 WHERE e.relation = 'suggests' AND t.content = '{name}'"
+            ))
+            .unwrap()
+            .unwrap()
+        };
+
+        let accepted = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.accept_suggestion('{}')",
+            suggestion_for("todo_a")
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(accepted.0["fix_applied"], true, "{}", accepted.0);
+        assert_eq!(accepted.0["reward"]["work_type"], "accept_suggestion");
+        let renamed = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.nodes n
+             JOIN kerai.operations o ON o.node_id = n.id AND o.op_type = 'update_content'
+             WHERE n.kind = 'fn' AND n.content = 'done_todo_a'",
+        )
+        .unwrap();
+        assert_eq!(renamed, Some(1), "The fix is applied as a versioned op");
+
+        let dismissed = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.dismiss_suggestion('{}', 'kept on purpose')",
+            suggestion_for("todo_b")
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(dismissed.0["status"], "dismissed");
+        let open = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.nodes
+             WHERE kind = 'suggestion' AND metadata->>'rule' = 'finish-todo'
+               AND metadata->>'status' = 'emitted'",
+        )
+        .unwrap();
+        assert_eq!(open, Some(0));
+
+        // Neither comes back on the next run
+        let again = Spi::get_one::<pgrx::JsonB>("SELECT kerai.run_rules('finish-todo')")
+            .unwrap()
+            .unwrap();
+        assert_eq!(again.0["created"], 0);
+    }
+
+    #[pg_test]
+    fn test_render_hints() {
+        let hints = |kind: &str, language: &str| {
+            Spi::get_one::<pgrx::JsonB>(&format!(
+                "SELECT kerai.render_hints('{kind}', '{language}')"
+            ))
+            .unwrap()
+            .unwrap()
+            .0
+        };
+
+        // Registered kind: registry hints over the defaults
+        let func = hints("fn", "rust");
+        assert_eq!(func["icon"], "function");
+        assert_eq!(func["preview"], "fn {content}");
+        assert_eq!(func["syntax"], "rust");
+        assert_eq!(func["collapsible"], true);
+
+        // Registry syntax wins over the node's language
+        assert_eq!(hints("doc_comment", "rust")["syntax"], "markdown");
+
+        // Unregistered kinds are guessed from their names
+        assert_eq!(hints("go_func", "go")["icon"], "function");
+        assert_eq!(hints("go_func_type", "go")["icon"], "type");
+        let lit = hints("c_string_lit", "c");
+        assert_eq!(lit["icon"], "literal");
+        assert_eq!(lit["collapsible"], false);
+        assert_eq!(hints("zig_mystery", "zig")["icon"], "node");
+
+        // Editing the registry changes what endpoints serve
+        Spi::run(
+            "INSERT INTO kerai.kinds (kind, render_hints) \
+             VALUES ('zig_mystery', '{\"icon\": \"zig\", \"collapsible\": false}')",
+        )
+        .unwrap();
+        let zig = hints("zig_mystery", "zig");
+        assert_eq!(zig["icon"], "zig");
+        assert_eq!(zig["collapsible"], false);
+        assert_eq!(zig["preview"], "{content}");
+    }
+
+    #[pg_test]
+    fn test_grep() {
+        Spi::run("SELECT kerai.bootstrap_instance()").ok();
+        Spi::run(
+            "SELECT kerai.parse_source(\
+             'fn parse_one() {}\n\n// TODO: handle errors\nfn parse_two() {}\nfn render() {}\n', \
+             'test_grep.rs')",
+        )
+        .unwrap();
+
+        let grep = |args: &str| {
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.grep({args})"))
+                .unwrap()
+                .unwrap()
+                .0
+        };
+
+        // Comments carry their line; comment text is stored without `//`
+        let todo = grep("'TODO', kind => 'comment'");
+        let hits = todo.as_array().unwrap();
+        assert_eq!(hits.len(), 1, "one TODO comment: {}", todo);
+        assert_eq!(hits[0]["file"], "test_grep.rs");
+        assert_eq!(hits[0]["line"], 3);
+        assert_eq!(hits[0]["match"], "TODO");
+        assert_eq!(hits[0]["col"], 1);
+
+        // Regex plus kind plus path
+        let parsers = grep("'^parse_', kind => 'fn', path => 'test_grep_rs.*'");
+        let names: Vec<&str> = parsers
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|h| h["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["parse_one", "parse_two"]);
+        assert_eq!(
+            parsers[0]["span"],
+            serde_json::json!({"start": 0, "end": 6})
+        );
+
+        assert_eq!(
+            grep("'^render$', path => 'elsewhere.*'"),
+            serde_json::json!([])
+        );
+        assert_eq!(
+            grep("'(?i)RENDER', kind => 'fn'").as_array().unwrap().len(),
+            1
+        );
+    }
+
+    #[pg_test]
+    fn test_economy_report() {
+        Spi::run("SELECT kerai.bootstrap_instance()").ok();
+        Spi::run("SELECT kerai.mint_reward('parse_file', NULL)").unwrap();
+        Spi::run("SELECT kerai.mint_reward('parse_crate', NULL)").unwrap();
+
+        let report = Spi::get_one::<pgrx::JsonB>("SELECT kerai.economy_report('month')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert!(report["minted"].as_i64().unwrap() >= 60_000_000_000);
+        assert!(report["since"].as_str().unwrap().ends_with("-01T00:00:00Z"));
+        // Largest earner first
+        assert_eq!(report["actions"][0]["reason"], "reward:parse_crate");
+
+        // The signed summary carries the same report
+        let summary = Spi::get_one::<pgrx::JsonB>("SELECT kerai.economy_summary('month')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert!(summary["signature"].is_string());
+        assert_eq!(summary["body"]["minted"], report["minted"]);
+
+        // With no peer summaries the federation is just this instance
+        let federation =
+            Spi::get_one::<pgrx::JsonB>("SELECT kerai.federation_economy_report('month')")
+                .unwrap()
+                .unwrap()
+                .0;
+        let instances = federation["instances"].as_array().unwrap();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0]["self"], true);
+        assert_eq!(federation["totals"]["minted"], report["minted"]);
+        assert_eq!(federation["top_minter"], report["instance"]["name"]);
+    }
+
+    #[pg_test]
+    fn test_rewind_to_and_version_batches() {
+        let inserted = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.apply_op('insert_node', NULL, '{\"kind\": \"fn\", \"content\": \"first\", \"position\": 0}'::jsonb)",
+        )
+        .unwrap()
+        .unwrap();
+        let node_id = inserted.0["node_id"].as_str().unwrap().to_string();
+        Spi::run(&format!(
+            "SELECT kerai.apply_op('update_content', '{}'::uuid, '{{\"new_content\": \"second\"}}'::jsonb)",
+            node_id
+        ))
+        .unwrap();
+
+        let batches = Spi::get_one::<pgrx::JsonB>("SELECT kerai.version_batches()")
+            .unwrap()
+            .unwrap();
+        let batches = batches.0.as_array().unwrap().clone();
+        assert!(batches.len() >= 2, "got {:?}", batches);
+        let last = batches.last().unwrap();
+        assert_eq!(last["ops"], 1);
+        assert_eq!(last["authors"].as_array().unwrap().len(), 1);
+        assert!(last["authors"][0]["name"].is_string());
+
+        let before_update = batches[batches.len() - 2]["timestamp"].as_i64().unwrap();
+        let rewound =
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.rewind_to({before_update})"))
+                .unwrap()
+                .unwrap();
+        assert_eq!(rewound.0["undone"], 1);
+        let content = Spi::get_one::<String>(&format!(
+            "SELECT content FROM kerai.nodes WHERE id = '{}'::uuid",
+            node_id
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(content, "first");
+    }
+
+    #[pg_test]
+    fn test_import_git_commit() {
+        let import = |sha: &str, author: &str, at: &str, source: &str| {
+            let commit = serde_json::json!({
+                "sha": sha,
+                "author": author,
+                "email": format!("{}@example.com", author.to_lowercase()),
+                "committed_at": at,
+                "message": format!("commit {sha}"),
+                "files": [{"path": "hist.rs", "source": source}],
+            });
+            Spi::get_one::<pgrx::JsonB>(&format!(
+                "SELECT kerai.import_git_commit({}::jsonb)",
+                crate::sql::sql_text(&commit.to_string())
+            ))
+            .unwrap()
+            .unwrap()
+            .0
+        };
+
+        let first = import("aaa111", "Ada", "2020-01-02T03:04:05Z", "fn one() {}\n");
+        assert_eq!(first["status"], "imported");
+        assert_eq!(first["parsed"], 1);
+        assert!(first["versions"].as_i64().unwrap() > 0, "got {}", first);
+        let second = import(
+            "bbb222",
+            "Bob",
+            "2021-06-07T08:09:10Z",
+            "fn one() {}\n\nfn two() {}\n",
+        );
+        assert!(second["timestamp"].as_i64() > first["timestamp"].as_i64());
+        assert!(second["versions"].as_i64().unwrap() > 0, "got {}", second);
+
+        let dated = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.versions
+             WHERE author = 'Ada <ada@example.com>'
+               AND created_at = '2020-01-02T03:04:05Z'::timestamptz",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(dated, first["versions"].as_i64().unwrap());
+        let bob = Spi::get_one::<i64>(&format!(
+            "SELECT count(*) FROM kerai.versions
+             WHERE author = 'Bob <bob@example.com>' AND timestamp = {}",
+            second["timestamp"]
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(bob, second["versions"].as_i64().unwrap());
+
+        let again = import("aaa111", "Ada", "2020-01-02T03:04:05Z", "fn one() {}\n");
+        assert_eq!(again["status"], "already_imported");
+        assert_eq!(again["timestamp"], first["timestamp"]);
+
+        let shas = Spi::get_one::<pgrx::JsonB>("SELECT kerai.git_imported_shas()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(shas.0, serde_json::json!(["aaa111", "bbb222"]));
+
+        let batches = Spi::get_one::<pgrx::JsonB>("SELECT kerai.version_batches()")
+            .unwrap()
+            .unwrap();
+        for commit in [&first, &second] {
+            assert!(
+                batches
+                    .0
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .any(|b| b["timestamp"] == commit["timestamp"]),
+                "no batch for {}",
+                commit
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_snapshots_restore_tagged_tree() {
+        Spi::run("SELECT kerai.parse_source('fn tagged() {}', 'snap.rs', true)").unwrap();
+        let v1 = Spi::get_one::<pgrx::JsonB>("SELECT kerai.create_snapshot('v1')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert!(v1["files"].as_i64().unwrap() >= 1, "got {}", v1);
+        assert_eq!(v1["root_hash"].as_str().unwrap().len(), 64);
+
+        // Same sources, same root
+        let again = Spi::get_one::<pgrx::JsonB>("SELECT kerai.create_snapshot('v1-again')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(again["root_hash"], v1["root_hash"]);
+
+        Spi::run("SELECT kerai.parse_source('fn tagged() {}\n\nfn later() {}', 'snap.rs', true)")
+            .unwrap();
+        let v2 = Spi::get_one::<pgrx::JsonB>("SELECT kerai.create_snapshot('v2')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_ne!(v2["root_hash"], v1["root_hash"]);
+
+        let restored = Spi::get_one::<pgrx::JsonB>("SELECT kerai.restore_snapshot('v1')")
+            .unwrap()
+            .unwrap()
+            .0;
+        let file = restored["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|f| f["path"] == "snap.rs")
+            .unwrap_or_else(|| panic!("snap.rs not in {}", restored))
+            .clone();
+        let source = Spi::get_one::<String>(&format!(
+            "SELECT kerai.reconstruct_file('{}'::uuid)",
+            file["id"].as_str().unwrap()
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(source.contains("fn tagged()"), "got {}", source);
+        assert!(!source.contains("fn later()"), "got {}", source);
+
+        let listed = Spi::get_one::<pgrx::JsonB>("SELECT kerai.list_snapshots()")
+            .unwrap()
+            .unwrap();
+        assert_eq!(listed.0.as_array().unwrap().len(), 3);
+        let dropped = Spi::get_one::<pgrx::JsonB>("SELECT kerai.drop_snapshot('v1-again')")
+            .unwrap()
+            .unwrap();
+        assert!(dropped.0["nodes"].as_i64().unwrap() > 0);
+    }
+
+    #[pg_test]
+    fn test_add_edges_checks_registry() {
+        Spi::run("SELECT kerai.parse_source('fn linked_fn() {}', 'linked.rs')").unwrap();
+        Spi::run("SELECT kerai.parse_markdown('# Design', 'design.md')").unwrap();
+        let id = |kind: &str, content: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT id::text FROM kerai.nodes WHERE kind = '{kind}' AND content = '{content}'"
+            ))
+            .unwrap()
+            .unwrap()
+        };
+        let (doc, code) = (id("document", "design.md"), id("fn", "linked_fn"));
+
+        let added = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.add_edge('{doc}'::uuid, '{code}'::uuid, 'documents', '{{\"by\": \"hand\"}}')"
+        ))
+        .unwrap()
+        .unwrap()
+        .0;
+        assert_eq!(added["added"], true);
+        let ops = Spi::get_one::<i64>(&format!(
+            "SELECT count(*) FROM kerai.operations WHERE op_type = 'insert_edge' AND node_id = '{doc}'::uuid"
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(ops, 1);
+
+        // Edges already there are counted, not written again
+        let batch = serde_json::json!([
+            {"source_id": doc, "target_id": code, "relation": "documents"},
+            {"source_id": doc, "target_id": code, "relation": "references"},
+        ]);
+        let sql_batch = crate::sql::sql_jsonb(&batch);
+        let result = Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.add_edges({sql_batch})"))
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(result, serde_json::json!({"added": 1, "existing": 1}));
+
+        let removed =
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.remove_edges({sql_batch})"))
+                .unwrap()
+                .unwrap()
+                .0;
+        assert_eq!(removed, serde_json::json!({"removed": 2, "missing": 0}));
+        let left = Spi::get_one::<i64>(&format!(
+            "SELECT count(*) FROM kerai.edges WHERE source_id = '{doc}'::uuid AND target_id = '{code}'::uuid"
         ))
         .unwrap()
         .unwrap();
-        assert_eq!(result.0["status"].as_str().unwrap(), "open_sourced");
+        assert_eq!(left, 0);
     }
 
     #[pg_test]
-    fn test_market_browse() {
-        let att_id = create_test_attestation("pkg.browse", "expertise");
-        Spi::run(&format!(
-            "SELECT kerai.create_auction('{}'::uuid, 20000, 500, 60, 0, 1, 24)",
-            att_id,
-        ))
-        .unwrap();
-
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.market_browse(NULL, NULL, 'active')",
+    #[should_panic(expected = "needs a source of kind suggestion")]
+    fn test_add_edge_rejects_disallowed_kind() {
+        Spi::run("SELECT kerai.parse_source('fn unsuggested() {}', 'unsuggested.rs')").unwrap();
+        let code = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE kind = 'fn' AND content = 'unsuggested'",
         )
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        assert!(!arr.is_empty(), "Should find at least one active auction");
+        Spi::run(&format!(
+            "SELECT kerai.add_edges('[{{\"source_id\": \"{code}\", \"target_id\": \"{code}\", \"relation\": \"suggests\"}}]')"
+        ))
+        .unwrap();
     }
 
     #[pg_test]
-    fn test_market_stats() {
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.market_stats()",
+    fn test_merkle_hashes_follow_ops() {
+        Spi::run("SELECT kerai.parse_source('fn hashed_a() {}\n\nfn hashed_b() {}', 'merkle.rs')")
+            .unwrap();
+        let root = || {
+            Spi::get_one::<String>("SELECT kerai.merkle_root()")
+                .unwrap()
+                .unwrap()
+        };
+        let file_hash = || {
+            Spi::get_one::<String>(
+                "SELECT content_hash FROM kerai.nodes WHERE kind = 'file' AND content = 'merkle.rs'",
+            )
+            .unwrap()
+            .unwrap()
+        };
+        let (root_before, file_before) = (root(), file_hash());
+        let before = Spi::get_one::<pgrx::JsonB>("SELECT kerai.merkle_hashes()")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(before["root"], root_before.as_str());
+
+        let fn_id = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE kind = 'fn' AND content = 'hashed_b'",
         )
         .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert!(obj.contains_key("active_auctions"));
-        assert!(obj.contains_key("settled_auctions"));
-        assert!(obj.contains_key("open_sourced"));
-        assert!(obj.contains_key("total_bids"));
-        assert!(obj.contains_key("total_settlement_value"));
-        assert!(obj.contains_key("avg_settlement_price"));
-    }
-
-    #[pg_test]
-    fn test_generate_and_verify_proof() {
-        let att_id = create_test_attestation("pkg.zkp", "state_transition");
+        let rename = |name: &str| {
+            Spi::run(&format!(
+                "SELECT kerai.apply_op('update_content', '{fn_id}'::uuid, '{{\"new_content\": \"{name}\"}}'::jsonb)"
+            ))
+            .unwrap();
+        };
 
-        // Generate proof
-        let proof = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.generate_proof('{}'::uuid)",
-            att_id,
+        rename("hashed_c");
+        assert_ne!(file_hash(), file_before);
+        assert_ne!(root(), root_before);
+        let diff = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.merkle_diff({})",
+            crate::sql::sql_jsonb(&before)
         ))
         .unwrap()
-        .unwrap();
-        let obj = proof.0.as_object().unwrap();
-        assert_eq!(obj["proof_type"].as_str().unwrap(), "sha256_commitment");
-        let proof_hex = obj["proof_hex"].as_str().unwrap();
-        assert_eq!(proof_hex.len(), 64, "SHA-256 hex should be 64 chars");
+        .unwrap()
+        .0;
+        let diff = diff.as_array().unwrap();
+        assert!(!diff.is_empty());
+        assert!(
+            diff.iter().all(|d| d["status"] == "changed"),
+            "got {:?}",
+            diff
+        );
 
-        // Verify proof using stored proof_data
-        let verify = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.verify_proof('{}'::uuid,
-                (SELECT proof_data FROM kerai.attestations WHERE id = '{}'::uuid))",
-            att_id, att_id,
+        // Same content, same hashes
+        rename("hashed_b");
+        assert_eq!(file_hash(), file_before);
+        assert_eq!(root(), root_before);
+        let same = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.merkle_diff({})",
+            crate::sql::sql_jsonb(&before)
         ))
         .unwrap()
         .unwrap();
-        assert!(verify.0["valid"].as_bool().unwrap(), "Proof should verify");
+        assert_eq!(same.0, serde_json::json!([]));
     }
 
     #[pg_test]
-    fn test_verify_proof_invalid() {
-        let att_id = create_test_attestation("pkg.bad_proof", "expertise");
-        Spi::run(&format!(
-            "SELECT kerai.generate_proof('{}'::uuid)",
-            att_id,
-        ))
+    fn test_parse_source_logs_parse_stats() {
+        let result = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.parse_source('fn quoted() -> &''static str { \"it''s\" }', 'stats_log.rs')",
+        )
+        .unwrap()
+        .unwrap()
+        .0;
+        let stored = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.nodes WHERE content = 'quoted' AND kind = 'fn'",
+        )
+        .unwrap()
         .unwrap();
+        assert_eq!(stored, 1);
 
-        // Verify with wrong proof data
-        let verify = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.verify_proof('{}'::uuid, '\\xdeadbeef'::bytea)",
-            att_id,
-        ))
+        let stats = Spi::get_one::<pgrx::JsonB>(
+            "SELECT to_jsonb(s) FROM kerai.parse_stats s
+             WHERE kind = 'parse_source' AND target = 'stats_log.rs'",
+        )
         .unwrap()
-        .unwrap();
-        assert!(!verify.0["valid"].as_bool().unwrap(), "Invalid proof should fail");
+        .unwrap()
+        .0;
+        assert_eq!(stats["files"], 1);
+        assert_eq!(stats["nodes"], result["nodes"]);
+        assert_eq!(stats["edges"], result["edges"]);
+        assert!(stats["rows_per_sec"].as_f64().unwrap() > 0.0);
     }
 
     #[pg_test]
-    fn test_market_balance() {
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.market_balance()",
+    fn test_apply_view_filters_find_and_tree() {
+        Spi::run("SELECT kerai.parse_source('fn viewed_fn() {}', 'view_in.rs')").unwrap();
+        Spi::run("SELECT kerai.parse_source('fn viewed_other() {}', 'view_out.rs')").unwrap();
+        let file_path = Spi::get_one::<String>(
+            "SELECT path::text FROM kerai.nodes WHERE kind = 'file' AND content = 'view_in.rs'",
         )
         .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert!(obj.contains_key("earnings"));
-        assert!(obj.contains_key("spending"));
-        assert!(obj.contains_key("net"));
-        assert!(obj.contains_key("active_auctions"));
-        assert!(obj.contains_key("active_bids"));
-    }
+        Spi::run(&format!(
+            "SELECT kerai.create_view('team', '[\"{}\"]'::jsonb, '[\"fn\"]'::jsonb)",
+            sql_escape(&file_path),
+        ))
+        .unwrap();
 
-    // --- Plan 12: Markdown parser tests ---
+        let found = |pattern: &str| -> Vec<String> {
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.find('{pattern}', NULL, NULL)"))
+                .unwrap()
+                .unwrap()
+                .0
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|n| n["content"].as_str().unwrap_or_default().to_string())
+                .collect()
+        };
+        assert_eq!(found("viewed_%").len(), 2);
 
-    #[pg_test]
-    fn test_parse_markdown_headings() {
-        let source = "# Title\n\n## Section One\n\nParagraph under section one.\n\n## Section Two\n\n### Subsection\n\nDeep content.\n";
-        let result = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.parse_markdown('{}', 'headings.md')",
-            sql_escape(source),
+        let applied = Spi::get_one::<pgrx::JsonB>("SELECT kerai.apply_view('team')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(applied["on_branch"], true);
+        assert_eq!(
+            Spi::get_one::<String>("SELECT kerai.current_view()").unwrap(),
+            Some("team".to_string())
+        );
+        assert_eq!(found("viewed_%"), vec!["viewed_fn".to_string()]);
+        // The file node itself is outside the kinds filter
+        assert!(found("view_in.rs").is_empty());
+        let tree = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.tree('{}')",
+            sql_escape(&file_path)
         ))
         .unwrap()
         .unwrap();
-        let obj = result.0.as_object().unwrap();
-        assert!(obj["nodes"].as_u64().unwrap() > 0, "Should have parsed nodes");
+        assert!(tree.0.as_array().unwrap().iter().all(|n| n["kind"] == "fn"));
 
-        // Verify heading hierarchy: H2 should be child of H1
-        let h1_id = Spi::get_one::<String>(
-            "SELECT id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Title'",
+        Spi::run("SELECT kerai.clear_view()").unwrap();
+        assert_eq!(found("viewed_%").len(), 2);
+        assert_eq!(
+            Spi::get_one::<String>("SELECT kerai.current_view()").unwrap(),
+            None
+        );
+    }
+
+    #[pg_test]
+    fn test_import_vault_resolves_wikilinks() {
+        let files = [
+            r##"{"path": "Home.md", "source": "# Home\n\nSee [[Todo]], ![[diagram.png]] and [[Nowhere]].\n"}"##,
+            r##"{"path": "projects/Todo.md", "source": "# Todo\n\nBack to [[Home#Top|home]].\n"}"##,
+            r##"{"path": "projects/assets/diagram.png", "size": 42, "sha256": "abc"}"##,
+        ];
+        for file in files {
+            Spi::run(&format!(
+                "SELECT kerai.import_vault_file('notes', '{}'::jsonb)",
+                sql_escape(file),
+            ))
+            .unwrap();
+        }
+
+        let todo_path = Spi::get_one::<String>(
+            "SELECT path::text FROM kerai.nodes
+             WHERE kind = 'document' AND metadata->>'vault_path' = 'projects/Todo.md'",
         )
         .unwrap()
         .unwrap();
-
-        let h2_parent = Spi::get_one::<String>(
-            "SELECT parent_id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Section One'",
+        assert_eq!(todo_path, "notes.projects.Todo_md");
+        let heading_path = Spi::get_one::<String>(
+            "SELECT path::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Todo'",
         )
         .unwrap()
         .unwrap();
-        assert_eq!(h2_parent, h1_id, "H2 should be child of H1");
+        assert!(heading_path.starts_with("notes.projects.Todo_md."));
+        let folders = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.nodes WHERE kind = 'folder' AND metadata->>'vault' = 'notes'",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(folders, 2, "projects and projects/assets");
 
-        // H3 should be child of H2 (Section Two)
-        let h2_two_id = Spi::get_one::<String>(
-            "SELECT id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Section Two'",
+        let report = Spi::get_one::<pgrx::JsonB>("SELECT kerai.resolve_vault_links('notes')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(report["notes"], 2);
+        assert_eq!(report["assets"], 1);
+        assert_eq!(report["links"], 3);
+        assert_eq!(
+            report["unresolved"],
+            serde_json::json!([{"path": "Home.md", "target": "Nowhere"}])
+        );
+
+        let embed_target = Spi::get_one::<String>(
+            "SELECT t.kind FROM kerai.edges e
+             JOIN kerai.nodes t ON t.id = e.target_id
+             WHERE e.relation = 'links_to' AND e.metadata->>'wikilink' = 'diagram.png'",
         )
         .unwrap()
         .unwrap();
+        assert_eq!(embed_target, "asset");
 
-        let h3_parent = Spi::get_one::<String>(
-            "SELECT parent_id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Subsection'",
+        // Importing a note again replaces it; resolving again restores links
+        Spi::run(
+            "SELECT kerai.import_vault_file('notes',
+                '{\"path\": \"projects/Todo.md\", \"source\": \"# Todo\\n\"}'::jsonb)",
+        )
+        .unwrap();
+        let report = Spi::get_one::<pgrx::JsonB>("SELECT kerai.resolve_vault_links('notes')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(report["links"], 2);
+        let todos = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.nodes
+             WHERE kind = 'document' AND metadata->>'vault_path' = 'projects/Todo.md'",
         )
         .unwrap()
         .unwrap();
-        assert_eq!(h3_parent, h2_two_id, "H3 should be child of its preceding H2");
+        assert_eq!(todos, 1);
     }
 
     #[pg_test]
-    fn test_parse_markdown_paragraphs() {
-        let source = "# Main\n\nFirst paragraph.\n\nSecond paragraph.\n";
-        Spi::run(&format!(
-            "SELECT kerai.parse_markdown('{}', 'paragraphs.md')",
-            sql_escape(source),
-        ))
+    fn test_export_vault_writes_backlinks_and_round_trips() {
+        let files = [
+            r##"{"path": "Home.md", "source": "# Home\n\nSee [[Todo]] and ![[diagram.png]].\n"}"##,
+            r##"{"path": "projects/Todo.md", "source": "# Todo\n\nNothing yet.\n"}"##,
+            r##"{"path": "projects/assets/diagram.png", "size": 3, "sha256": "abc"}"##,
+        ];
+        for file in files {
+            Spi::run_with_args(
+                "SELECT kerai.import_vault_file('notes', $1::jsonb)",
+                &[file.into()],
+            )
+            .unwrap();
+        }
+        Spi::run("SELECT kerai.resolve_vault_links('notes')").unwrap();
+        // A link made outside the text, into a heading of Home
+        Spi::run(
+            "SELECT kerai.add_edge(d.id, h.id, 'links_to')
+             FROM kerai.nodes d, kerai.nodes h
+             WHERE d.metadata->>'vault_path' = 'projects/Todo.md' AND d.kind = 'document'
+               AND h.kind = 'heading' AND h.content = 'Home'",
+        )
         .unwrap();
 
-        let heading_id = Spi::get_one::<String>(
-            "SELECT id::text FROM kerai.nodes WHERE kind = 'heading' AND content = 'Main'",
+        let vault = Spi::get_one::<pgrx::JsonB>("SELECT kerai.export_vault('notes.*')")
+            .unwrap()
+            .unwrap()
+            .0;
+        let note = |path: &str| {
+            vault["notes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|n| n["path"] == path)
+                .and_then(|n| n["content"].as_str())
+                .unwrap_or_else(|| panic!("no note {path}"))
+                .to_string()
+        };
+        let home = note("Home.md");
+        let todo = note("projects/Todo.md");
+        assert!(home.contains("[[Todo]]"), "{home}");
+        assert!(
+            home.contains("## Backlinks\n\n- [[projects/Todo]]"),
+            "{home}"
+        );
+        assert!(todo.contains("## Links\n\n- [[Home#Home]]"), "{todo}");
+        assert!(todo.contains("## Backlinks\n\n- [[Home]]"), "{todo}");
+        assert_eq!(
+            vault["attachments"][0]["path"], "attachments/diagram.png",
+            "{vault}"
+        );
+        assert_eq!(
+            vault["attachments"][0]["vault_path"],
+            "projects/assets/diagram.png"
+        );
+        assert_eq!(vault["links"], 1);
+        assert_eq!(vault["backlinks"], 2);
+
+        // Importing the exported note again drops the generated block
+        let result = Spi::get_one_with_args::<pgrx::JsonB>(
+            "SELECT kerai.import_vault_file('notes', jsonb_build_object('path', 'projects/Todo.md', 'source', $1))",
+            &[todo.as_str().into()],
         )
         .unwrap()
-        .unwrap();
-
-        // Paragraphs should be children of the heading
-        let para_count = Spi::get_one::<i64>(&format!(
-            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'paragraph' AND parent_id = '{}'::uuid",
-            heading_id,
-        ))
+        .unwrap()
+        .0;
+        assert_eq!(result["wikilinks"], 0);
+        let generated = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.nodes WHERE content LIKE '%kerai:generated%'",
+        )
         .unwrap()
         .unwrap();
-        assert!(para_count >= 2, "Should have at least 2 paragraphs under heading, got {}", para_count);
+        assert_eq!(generated, 0);
     }
 
     #[pg_test]
-    fn test_parse_markdown_code_block() {
-        let source = "# Code\n\n```rust\nfn main() {\n    println!(\"hello\");\n}\n```\n";
-        Spi::run(&format!(
-            "SELECT kerai.parse_markdown('{}', 'codeblock.md')",
-            sql_escape(source),
-        ))
-        .unwrap();
+    fn test_parse_project_dispatches_by_extension() {
+        let tmp = tempfile::TempDir::new().expect("temp dir");
+        let files: &[(&str, &str)] = &[
+            (
+                "Cargo.toml",
+                "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n",
+            ),
+            ("src/lib.rs", "pub fn hello() -> u32 { 1 }\n"),
+            ("README.md", "# Demo\n\nHello.\n"),
+            ("cmd/tool/main.go", "package main\n\nfunc main() {}\n"),
+            ("notes/draft.md", "# Draft\n"),
+            (".hidden/skip.rs", "fn skipped() {}\n"),
+            ("logo.svg", "<svg/>"),
+        ];
+        for (rel, source) in files {
+            let path = tmp.path().join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        let root = tmp.path().to_str().unwrap();
 
-        let lang = Spi::get_one::<pgrx::JsonB>(
-            "SELECT metadata FROM kerai.nodes WHERE kind = 'code_block' LIMIT 1",
+        let summary = Spi::get_one_with_args::<pgrx::JsonB>(
+            "SELECT kerai.parse_project($1, NULL, ARRAY['notes/**'])",
+            &[root.into()],
+        )
+        .unwrap()
+        .unwrap()
+        .0;
+        assert_eq!(summary["files"], 3, "{summary}");
+        assert_eq!(summary["crates"], serde_json::json!(["demo"]));
+        assert_eq!(summary["packages"], 1);
+        for lang in ["rust", "markdown", "go"] {
+            assert_eq!(summary["languages"][lang]["files"], 1, "{summary}");
+        }
+
+        let parent_kind = |file: &str| {
+            Spi::get_one_with_args::<String>(
+                "SELECT p.kind FROM kerai.nodes n JOIN kerai.nodes p ON p.id = n.parent_id
+                 WHERE n.content = $1 AND n.kind IN ('file', 'document')",
+                &[file.into()],
+            )
+            .unwrap()
+            .unwrap()
+        };
+        assert_eq!(parent_kind("src/lib.rs"), "crate");
+        assert_eq!(parent_kind("README.md"), "crate");
+        assert_eq!(parent_kind("cmd/tool/main.go"), "package");
+        let crate_parent = Spi::get_one::<String>(
+            "SELECT p.kind FROM kerai.nodes n JOIN kerai.nodes p ON p.id = n.parent_id
+             WHERE n.kind = 'crate' AND n.content = 'demo'",
         )
         .unwrap()
         .unwrap();
-        assert_eq!(lang.0["language"].as_str().unwrap(), "rust", "Code block should preserve language metadata");
+        assert_eq!(crate_parent, "project");
+
+        // Parsing again replaces the tree; include globs narrow it
+        let summary = Spi::get_one_with_args::<pgrx::JsonB>(
+            "SELECT kerai.parse_project($1, ARRAY['*.md'])",
+            &[root.into()],
+        )
+        .unwrap()
+        .unwrap()
+        .0;
+        assert_eq!(summary["files"], 2, "{summary}");
+        let counts = Spi::get_one::<pgrx::JsonB>(
+            "SELECT jsonb_build_object(
+                'projects', count(*) FILTER (WHERE kind = 'project'),
+                'crates', count(*) FILTER (WHERE kind = 'crate' AND content = 'demo'),
+                'rust', count(*) FILTER (WHERE kind = 'file' AND content = 'src/lib.rs'),
+                'drafts', count(*) FILTER (WHERE kind = 'document' AND content = 'notes/draft.md'))
+             FROM kerai.nodes WHERE instance_id = (SELECT id FROM kerai.instances WHERE is_self)",
+        )
+        .unwrap()
+        .unwrap()
+        .0;
+        assert_eq!(
+            counts,
+            serde_json::json!({"projects": 1, "crates": 1, "rust": 0, "drafts": 1})
+        );
     }
 
     #[pg_test]
-    fn test_parse_markdown_links() {
-        let source = "# Links\n\n[Example](https://example.com) and [local](other.md).\n";
-        Spi::run(&format!(
-            "SELECT kerai.parse_markdown('{}', 'links.md')",
-            sql_escape(source),
-        ))
+    fn test_parse_source_detects_language() {
+        let cases: &[(&str, &str, &str, &str)] = &[
+            ("main.go", "package main\n\nfunc main() {}\n", "go", "file"),
+            (
+                "NOTES",
+                "# Notes\n\nSee [docs](docs.md).\n",
+                "markdown",
+                "document",
+            ),
+            ("Cargo.lock", "[[package]]\nname = \"x\"\n", "toml", "file"),
+            ("ci", "name: ci\non:\n  push:\n", "yaml", "file"),
+            ("script", "fn untagged() {}\n", "rust", "file"),
+        ];
+        for (name, source, language, kind) in cases {
+            let detected = Spi::get_one_with_args::<String>(
+                "SELECT kerai.detect_language($1, $2)",
+                &[(*name).into(), (*source).into()],
+            )
+            .unwrap();
+            assert_eq!(detected.as_deref(), Some(*language), "{name}");
+
+            // Parsing twice keeps a single node for the file
+            for _ in 0..2 {
+                let result = Spi::get_one_with_args::<pgrx::JsonB>(
+                    "SELECT kerai.parse_source($1, $2)",
+                    &[(*source).into(), (*name).into()],
+                )
+                .unwrap()
+                .unwrap()
+                .0;
+                assert_eq!(result["language"], *language, "{result}");
+                assert!(result["nodes"].as_u64().unwrap() > 0, "{result}");
+            }
+            let files = Spi::get_one_with_args::<i64>(
+                "SELECT count(*) FROM kerai.nodes WHERE kind = $1 AND content = $2",
+                &[(*kind).into(), (*name).into()],
+            )
+            .unwrap();
+            assert_eq!(files, Some(1), "{name}");
+        }
+
+        let unknown =
+            Spi::get_one::<String>("SELECT kerai.detect_language('blob', 'just words')").unwrap();
+        assert_eq!(unknown, None);
+        let runs = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.parse_runs WHERE kind = 'parse_source' AND target = 'main.go'",
+        )
         .unwrap();
+        assert_eq!(runs, Some(2));
+    }
 
-        let link_count = Spi::get_one::<i64>(
-            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'link'",
+    #[pg_test]
+    fn test_subscription_digests_land_in_inbox() {
+        Spi::run("INSERT INTO kerai.users (handle, is_allowed) VALUES ('digest-reader', true)")
+            .unwrap();
+        let sub = Spi::get_one::<pgrx::JsonB>(
+            "SELECT kerai.subscribe('digest-reader', 'subs_docs', ARRAY['update_content'], 'hourly')",
         )
         .unwrap()
-        .unwrap();
-        assert!(link_count >= 2, "Should have at least 2 link nodes, got {}", link_count);
+        .unwrap()
+        .0;
+        assert_eq!(sub["frequency"], "hourly");
+        assert_eq!(sub["events"], serde_json::json!(["update_content"]));
+        // Subscribing again updates the one subscription
+        Spi::run("SELECT kerai.subscribe('digest-reader', 'subs_docs', ARRAY['update_content'])")
+            .unwrap();
+        let subs = Spi::get_one::<pgrx::JsonB>("SELECT kerai.list_subscriptions('digest-reader')")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(subs.as_array().unwrap().len(), 1);
+        assert_eq!(subs[0]["frequency"], "daily");
+
+        // Not due yet, so nothing is delivered
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.deliver_digests()")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(result["digests"], 0);
+
+        // Ops of an old, finished transaction, so they are past the horizon;
+        // the subscription's cursor is rewound to take them in
+        Spi::run("UPDATE kerai.subscriptions SET cursor = '0-0'").unwrap();
+        for (seq, op, path) in [
+            (1, "update_content", "subs_docs.intro"),
+            (2, "update_content", "subs_docs.intro"),
+            (3, "move_node", "subs_docs.intro"),
+            (4, "update_content", "subs_src.lib"),
+        ] {
+            Spi::run(&format!(
+                "INSERT INTO kerai.operations (instance_id, op_type, author, lamport_ts, author_seq, txid, path)
+                 SELECT id, '{op}', 'digest-author', {seq}, {seq}, '3'::xid8, '{path}'::ltree
+                 FROM kerai.instances WHERE is_self = true",
+            ))
+            .unwrap();
+        }
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.deliver_digests(true)")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(result["digests"], 1, "{result}");
+        assert_eq!(result["events"], 2, "{result}");
+
+        let inbox = Spi::get_one::<pgrx::JsonB>("SELECT kerai.inbox('digest-reader')")
+            .unwrap()
+            .unwrap()
+            .0;
+        let digests = inbox.as_array().unwrap();
+        assert_eq!(digests.len(), 1);
+        assert_eq!(digests[0]["summary"], "2 changes under subs_docs");
+        assert_eq!(
+            digests[0]["by_type"],
+            serde_json::json!({"update_content": 2})
+        );
+        assert_eq!(digests[0]["changes"][0]["path"], "subs_docs.intro");
+
+        // The cursor moved past them: the next pass has nothing new
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.deliver_digests(true)")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(result["digests"], 0);
+
+        let removed =
+            Spi::get_one::<bool>("SELECT kerai.unsubscribe('digest-reader', 'subs_docs')").unwrap();
+        assert_eq!(removed, Some(true));
+    }
+
+    #[pg_test]
+    fn test_supported_languages_lists_registry() {
+        let languages = Spi::get_one::<pgrx::JsonB>("SELECT kerai.supported_languages()")
+            .unwrap()
+            .unwrap()
+            .0;
+        let languages = languages.as_array().unwrap();
+        let names: Vec<&str> = languages
+            .iter()
+            .map(|l| l["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            ["rust", "go", "c", "cpp", "markdown", "latex", "bibtex", "toml", "yaml"]
+        );
 
-        // Check URL metadata
-        let meta = Spi::get_one::<pgrx::JsonB>(
-            "SELECT metadata FROM kerai.nodes WHERE kind = 'link' AND content LIKE '%Example%' LIMIT 1",
-        )
-        .unwrap()
-        .unwrap();
-        assert_eq!(meta.0["url"].as_str().unwrap(), "https://example.com");
+        let cpp = &languages[3];
+        assert!(cpp["extensions"]
+            .as_array()
+            .unwrap()
+            .contains(&"hpp".into()));
+        let kinds = cpp["kinds"].as_array().unwrap();
+        assert!(kinds.contains(&"cpp_class".into()));
+        assert!(kinds.contains(&"c_function".into()));
+
+        // Every registered extension is detected as its language
+        for language in languages {
+            for ext in language["extensions"].as_array().unwrap() {
+                let detected = Spi::get_one_with_args::<String>(
+                    "SELECT kerai.detect_language($1)",
+                    &[format!("x.{}", ext.as_str().unwrap()).into()],
+                )
+                .unwrap();
+                assert_eq!(detected.as_deref(), language["name"].as_str());
+            }
+        }
     }
 
     #[pg_test]
-    fn test_parse_markdown_table() {
-        let source = "# Tables\n\n| Name | Value |\n| --- | --- |\n| foo | 42 |\n| bar | 99 |\n";
-        Spi::run(&format!(
-            "SELECT kerai.parse_markdown('{}', 'table.md')",
-            sql_escape(source),
-        ))
+    fn test_replace_previews_then_applies_one_changeset() {
+        Spi::run(
+            "SELECT kerai.parse_markdown(E'# Quokkasoft guide\\n\\nQuokkasoft and Quokkasoft.\\n', 'rp_guide.md')",
+        )
         .unwrap();
+        let replace = |dry_run: bool, files: Option<Vec<String>>| {
+            Spi::get_one_with_args::<pgrx::JsonB>(
+                "SELECT kerai.replace('*', 'Quokka(soft)', 'Numbat\\1', true, $1, $2)",
+                &[dry_run.into(), files.into()],
+            )
+            .unwrap()
+            .unwrap()
+            .0
+        };
 
-        let table_count = Spi::get_one::<i64>(
-            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'table'",
+        let preview = replace(true, None);
+        assert!(preview["changeset"].is_null());
+        assert_eq!(preview["files"][0]["file"], "rp_guide.md");
+        assert_eq!(preview["matches"], 3, "{preview}");
+        let unchanged =
+            Spi::get_one::<i64>("SELECT count(*) FROM kerai.nodes WHERE content LIKE '%Numbat%'")
+                .unwrap();
+        assert_eq!(unchanged, Some(0));
+
+        // Only the confirmed files are written
+        let skipped = replace(false, Some(vec!["other.md".into()]));
+        assert_eq!(skipped["nodes"], 0);
+
+        let applied = replace(false, Some(vec!["rp_guide.md".into()]));
+        let changeset = applied["changeset"].as_str().unwrap().to_string();
+        let ops = Spi::get_one_with_args::<i64>(
+            "SELECT count(*) FROM kerai.operations
+             WHERE op_type = 'update_content' AND payload->>'changeset' = $1",
+            &[changeset.as_str().into()],
         )
-        .unwrap()
         .unwrap();
-        assert!(table_count >= 1, "Should have at least 1 table node");
-
-        let cell_count = Spi::get_one::<i64>(
-            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'table_cell'",
+        assert_eq!(ops, Some(applied["nodes"].as_i64().unwrap()));
+        let left = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.nodes WHERE content LIKE '%Quokkasoft%' AND kind <> 'document'",
         )
-        .unwrap()
         .unwrap();
-        assert!(cell_count >= 4, "Should have at least 4 table cells (2 cols x 2+ rows), got {}", cell_count);
+        assert_eq!(left, Some(0));
     }
 
     #[pg_test]
-    fn test_parse_markdown_roundtrip() {
-        let source = "# Hello World\n\nThis is a paragraph.\n\n## Details\n\n- Item one\n- Item two\n";
-        Spi::run(&format!(
-            "SELECT kerai.parse_markdown('{}', 'roundtrip.md')",
-            sql_escape(source),
-        ))
-        .unwrap();
-
-        let doc_id = Spi::get_one::<pgrx::Uuid>(
-            "SELECT id FROM kerai.nodes WHERE kind = 'document' AND content = 'roundtrip.md'",
+    fn test_changeset_overlays_reads_until_applied() {
+        Spi::run("SELECT kerai.parse_markdown(E'# Review\\n\\nNumbats dig.\\n', 'cs_review.md')")
+            .unwrap();
+        let json = |sql: &str| Spi::get_one::<pgrx::JsonB>(sql).unwrap().unwrap().0;
+        let doc = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE kind = 'document' AND content = 'cs_review.md'",
         )
         .unwrap()
         .unwrap();
-
-        let reconstructed = Spi::get_one::<String>(&format!(
-            "SELECT kerai.reconstruct_markdown('{}'::uuid)",
-            doc_id,
-        ))
+        let para = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE content = 'Numbats dig.'",
+        )
         .unwrap()
         .unwrap();
 
-        // Verify key content is preserved
-        assert!(reconstructed.contains("# Hello World"), "Should contain H1");
-        assert!(reconstructed.contains("This is a paragraph"), "Should contain paragraph text");
-        assert!(reconstructed.contains("## Details"), "Should contain H2");
-        assert!(reconstructed.contains("Item one"), "Should contain list items");
+        let changeset = json("SELECT kerai.create_changeset('numbat edits')");
+        let cs = changeset["id"].as_str().unwrap().to_string();
+        Spi::run(&format!(
+            "SELECT kerai.stage_op('{cs}', 'update_content', '{para}', '{{\"new_content\": \"Numbats dig burrows.\"}}')"
+        ))
+        .unwrap();
+        let staged = json(&format!(
+            "SELECT kerai.stage_op('{cs}', 'insert_node', NULL,
+                jsonb_build_object('kind', 'paragraph', 'content', 'Staged wombats.',
+                                   'parent_id', '{doc}', 'position', 1000))"
+        ));
+        assert_eq!(staged["seq"], 2);
+        assert!(staged["node_id"].is_string());
+
+        let found = |pattern: &str| {
+            json(&format!("SELECT kerai.find('{pattern}', NULL, NULL)"))
+                .as_array()
+                .unwrap()
+                .len()
+        };
+        assert_eq!(found("Staged wombats%"), 0);
+
+        let applied = json(&format!("SELECT kerai.with_changeset('{cs}')"));
+        assert_eq!(applied["ops"], 2);
+        assert_eq!(found("Staged wombats%"), 1);
+        assert_eq!(found("Numbats dig burrows%"), 1);
+        let markdown =
+            Spi::get_one::<String>(&format!("SELECT kerai.reconstruct_markdown('{doc}'::uuid)"))
+                .unwrap()
+                .unwrap();
+        assert!(markdown.contains("Numbats dig burrows."), "{markdown}");
+        // Reading through the changeset leaves the graph as it was
+        let base = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.nodes WHERE content LIKE 'Staged wombats%'",
+        )
+        .unwrap();
+        assert_eq!(base, Some(0));
+
+        Spi::run("SELECT kerai.with_changeset(NULL)").unwrap();
+        assert_eq!(found("Numbats dig burrows%"), 0);
+
+        let result = json(&format!("SELECT kerai.apply_changeset('{cs}')"));
+        assert_eq!(result["applied"].as_array().unwrap().len(), 2);
+        assert_eq!(found("Staged wombats%"), 1);
+        let tagged = Spi::get_one_with_args::<i64>(
+            "SELECT count(*) FROM kerai.operations WHERE payload->>'changeset' = $1",
+            &[cs.as_str().into()],
+        )
+        .unwrap();
+        assert_eq!(tagged, Some(2));
+        let closed = json("SELECT kerai.list_changesets('applied')");
+        assert_eq!(closed[0]["id"], cs.as_str());
+    }
+
+    #[pg_test]
+    fn test_adversarial_filenames_survive_reparse() {
+        // With standard_conforming_strings off a backslash escapes the
+        // closing quote, which breaks SQL built by doubling quotes
+        Spi::run("SET LOCAL standard_conforming_strings = off").unwrap();
+        let source = "fn quoted() -> &'static str {\n    \"it's a \\\\ back'\\\\'slash\"\n}\n";
+        for name in [
+            "it's.rs",
+            "dir\\",
+            "o'k\\'; DROP TABLE kerai.nodes; --.rs",
+            "ünï'cødé ✓.rs",
+        ] {
+            for _ in 0..2 {
+                Spi::get_one_with_args::<pgrx::JsonB>(
+                    "SELECT kerai.parse_source($1, $2)",
+                    &[source.into(), name.into()],
+                )
+                .unwrap();
+            }
+            let files = Spi::get_one_with_args::<i64>(
+                "SELECT count(*) FROM kerai.nodes WHERE kind = 'file' AND content = $1",
+                &[name.into()],
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(files, 1, "re-parsing {name} should replace its nodes");
+
+            let rebuilt = Spi::get_one_with_args::<String>(
+                "SELECT kerai.reconstruct_file(id) FROM kerai.nodes
+                 WHERE kind = 'file' AND content = $1",
+                &[name.into()],
+            )
+            .unwrap()
+            .unwrap();
+            assert!(rebuilt.contains(r"it's a \\ back'\\'slash"), "{rebuilt}");
+
+            let rewarded = Spi::get_one_with_args::<i64>(
+                "SELECT count(*) FROM kerai.reward_log WHERE details->>'file' = $1",
+                &[name.into()],
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(rewarded, 2, "each parse of {name} is rewarded");
+        }
     }
 
     #[pg_test]
-    fn test_parse_markdown_idempotent() {
-        let source = "# Idempotent\n\nSame content.\n";
-        Spi::run(&format!(
-            "SELECT kerai.parse_markdown('{}', 'idempotent.md')",
-            sql_escape(source),
-        ))
-        .unwrap();
-        let count1 = Spi::get_one::<i64>(
-            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'document' AND content = 'idempotent.md'",
+    fn test_adversarial_markdown_and_crate_names() {
+        Spi::run("SET LOCAL standard_conforming_strings = off").unwrap();
+        let name = "notes\\it's ✓.md";
+        let source = "# It's \\ here\n\nA 'quoted' line.\n";
+        for _ in 0..2 {
+            Spi::get_one_with_args::<pgrx::JsonB>(
+                "SELECT kerai.parse_markdown($1, $2)",
+                &[source.into(), name.into()],
+            )
+            .unwrap();
+        }
+        let rebuilt = Spi::get_one_with_args::<String>(
+            "SELECT string_agg(kerai.reconstruct_markdown(id), '') FROM kerai.nodes
+             WHERE kind = 'document' AND content = $1",
+            &[name.into()],
         )
         .unwrap()
         .unwrap();
+        assert_eq!(rebuilt.matches("It's \\ here").count(), 1, "{rebuilt}");
 
-        // Parse again — should delete and re-insert
-        Spi::run(&format!(
-            "SELECT kerai.parse_markdown('{}', 'idempotent.md')",
-            sql_escape(source),
-        ))
-        .unwrap();
-        let count2 = Spi::get_one::<i64>(
-            "SELECT count(*)::bigint FROM kerai.nodes WHERE kind = 'document' AND content = 'idempotent.md'",
+        let crate_name = "o'brien\\";
+        Spi::get_one_with_args::<pgrx::JsonB>(
+            "SELECT kerai.parse_source($1, $2)",
+            &["fn krate() {}".into(), "krate's.rs".into()],
         )
-        .unwrap()
         .unwrap();
-
-        assert_eq!(count1, count2, "Idempotent parse should not duplicate document nodes");
-        assert_eq!(count1, 1, "Should have exactly one document node");
-    }
-
-    // --- Plan 12: FTS search tests ---
-
-    #[pg_test]
-    fn test_search_fts_basic() {
-        Spi::run(
-            "SELECT kerai.parse_source('fn calculate_total() { let sum = 0; }', 'fts_basic.rs')",
+        Spi::run_with_args(
+            "WITH c AS (
+                INSERT INTO kerai.nodes (instance_id, kind, content)
+                SELECT id, 'crate', $1 FROM kerai.instances WHERE is_self
+                RETURNING id
+             )
+             UPDATE kerai.nodes SET parent_id = (SELECT id FROM c)
+             WHERE kind = 'file' AND content = $2",
+            &[crate_name.into(), "krate's.rs".into()],
         )
         .unwrap();
-
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.search('calculate', NULL, NULL)",
+        let files = Spi::get_one_with_args::<pgrx::JsonB>(
+            "SELECT kerai.reconstruct_crate($1)",
+            &[crate_name.into()],
         )
         .unwrap()
-        .unwrap();
-        let arr = result.0.as_array().unwrap();
-        assert!(!arr.is_empty(), "FTS should find nodes matching 'calculate'");
+        .unwrap()
+        .0;
+        assert!(files["krate's.rs"].as_str().unwrap().contains("fn krate()"));
     }
 
     #[pg_test]
-    fn test_search_fts_with_kind_filter() {
-        Spi::run(
-            "SELECT kerai.parse_source('struct SearchTarget { value: i32 }', 'fts_kind.rs')",
+    fn test_adversarial_csv_cells() {
+        Spi::run("SET LOCAL standard_conforming_strings = off").unwrap();
+        let path = std::env::temp_dir().join("kerai_adversarial.csv");
+        std::fs::write(
+            &path,
+            "name,note\nO'Brien,C:\\dir\\\nünï,\"say \"\"hi\"\"; '--\"\n",
         )
         .unwrap();
-
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.search('SearchTarget', 'struct', NULL)",
+        Spi::get_one_with_args::<pgrx::JsonB>(
+            "SELECT kerai.parse_csv_file($1, 'csv_adversarial', $2)",
+            &[path.to_str().unwrap().into(), "it's \\ a project".into()],
         )
-        .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        for item in arr {
-            assert_eq!(item["kind"].as_str().unwrap(), "struct");
-        }
-    }
 
-    #[pg_test]
-    fn test_search_fts_no_matches() {
-        let result = Spi::get_one::<pgrx::JsonB>(
-            "SELECT kerai.search('xyzzy_nonexistent_term_zzz', NULL, NULL)",
+        for (name, note) in [("O'Brien", "C:\\dir\\"), ("ünï", "say \"hi\"; '--")] {
+            let found = Spi::get_one_with_args::<i64>(
+                "SELECT count(*) FROM csv_adversarial.kerai_adversarial
+                 WHERE name = $1 AND note = $2",
+                &[name.into(), note.into()],
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(found, 1, "{name} should keep its note");
+        }
+        let _ = std::fs::remove_file(&path);
+        let projects = Spi::get_one_with_args::<i64>(
+            "SELECT count(*) FROM kerai.csv_projects WHERE name = $1",
+            &["it's \\ a project".into()],
         )
         .unwrap()
         .unwrap();
-        let arr = result.0.as_array().unwrap();
-        assert!(arr.is_empty(), "FTS should return empty for non-matching terms");
+        assert_eq!(projects, 1);
     }
 
     #[pg_test]
@@ -3065,6 +7143,44 @@ impl Config {
         assert!(result.0.is_null(), "Disabled work type should return null");
     }
 
+    #[pg_test]
+    fn test_mint_reward_references_work() {
+        let changeset = "0190f0e0-0000-7000-8000-0000000000aa";
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.mint_reward('create_version', NULL, '{}'::uuid, 'changeset')",
+            changeset,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(result.0["reference_id"], changeset);
+        assert_eq!(result.0["reference_type"], "changeset");
+
+        let ledger = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.ledger_for('{}'::uuid)",
+            changeset,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(ledger.0["total"].as_i64().unwrap(), 5_000_000_000);
+        assert_eq!(ledger.0["entries"][0]["reason"], "reward:create_version");
+
+        // Parsers reward against the node they made
+        Spi::run("SELECT kerai.parse_markdown('# Earned\n\nText.', 'earned.md')").unwrap();
+        let doc_id = Spi::get_one::<String>(
+            "SELECT id::text FROM kerai.nodes WHERE kind = 'document' AND content = 'earned.md'",
+        )
+        .unwrap()
+        .unwrap();
+        let ledger =
+            Spi::get_one::<pgrx::JsonB>(&format!("SELECT kerai.ledger_for('{}'::uuid)", doc_id))
+                .unwrap()
+                .unwrap();
+        let entries = ledger.0["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["reason"], "reward:parse_markdown");
+        assert_eq!(entries[0]["reference_type"], "node");
+    }
+
     #[pg_test]
     fn test_evaluate_mining() {
         let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.evaluate_mining()")
@@ -3378,6 +7494,61 @@ impl Config {
         assert_eq!(count, 0);
     }
 
+    #[pg_test]
+    fn test_embed_nodes_and_search_semantic() {
+        Spi::run(
+            "SELECT kerai.parse_source('fn semantic_anchor() { let x = 1; } fn other() {}', 'test_embed.rs')",
+        )
+        .unwrap();
+        Spi::run(
+            "INSERT INTO kerai.agents (name, kind, wallet_id)
+             VALUES ('embed_agent', 'llm',
+                     (SELECT id FROM kerai.wallets WHERE instance_id = (SELECT id FROM kerai.instances WHERE is_self = true) LIMIT 1))
+             ON CONFLICT (name) DO NOTHING",
+        )
+        .unwrap();
+        Spi::run("SELECT kerai.create_model('embed_agent')").unwrap();
+
+        let result = Spi::get_one::<pgrx::JsonB>("SELECT kerai.embed_nodes('embed_agent')")
+            .unwrap()
+            .unwrap();
+        let embedded = result.0["embedded"].as_i64().unwrap();
+        assert!(embedded > 0, "Vocabulary nodes should be embedded");
+        let dim = result.0["dim"].as_i64().unwrap();
+        let stored = Spi::get_one::<i64>(&format!(
+            "SELECT count(*) FROM kerai.node_embeddings
+             WHERE model_id = (SELECT id FROM kerai.agents WHERE name = 'embed_agent')
+               AND dim = {dim} AND array_length(embedding, 1) = {dim}",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(stored, embedded);
+
+        // The only text hit anchors the query, so it comes back first
+        let hits = Spi::get_one::<pgrx::JsonB>("SELECT kerai.search_semantic('semantic_anchor', 3)")
+            .unwrap()
+            .unwrap();
+        let arr = hits.0.as_array().unwrap();
+        assert!(!arr.is_empty() && arr.len() <= 3);
+        assert_eq!(arr[0]["content"], "semantic_anchor");
+        assert!(arr[0]["similarity"].as_f64().unwrap() > 0.99);
+
+        let none = Spi::get_one::<pgrx::JsonB>("SELECT kerai.search_semantic('nosuchwordanywhere')")
+            .unwrap()
+            .unwrap();
+        assert!(none.0.as_array().unwrap().is_empty());
+
+        // Deleting the model drops its embeddings
+        Spi::run("SELECT kerai.delete_model('embed_agent')").unwrap();
+        let left = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.node_embeddings
+             WHERE model_id = (SELECT id FROM kerai.agents WHERE name = 'embed_agent')",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(left, 0);
+    }
+
     #[pg_test]
     fn test_tensor_byte_roundtrip() {
         use crate::microgpt::tensor::Tensor;
@@ -4289,11 +8460,177 @@ int add(int a, int b) {
         assert_eq!(count, 1, "Should have one c_typedef node named Point");
     }
 
+    #[pg_test]
+    fn test_c_include_edges() {
+        Spi::run(&format!(
+            "SELECT kerai.parse_c_source('{}', 'inc_util.h')",
+            sql_escape("int util(int x);\n"),
+        ))
+        .unwrap();
+        Spi::run(&format!(
+            "SELECT kerai.parse_c_source('{}', 'inc_main.c')",
+            sql_escape("#include <stdio.h>\n#include \"inc_util.h\"\n\nint main(void) { return util(1); }\n"),
+        ))
+        .unwrap();
+
+        let linked = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.edges e \
+             JOIN kerai.nodes t ON t.id = e.target_id \
+             WHERE e.relation = 'includes' AND t.kind = 'file' AND t.content = 'inc_util.h'",
+        )
+        .unwrap()
+        .unwrap_or(0);
+        assert_eq!(linked, 1, "quoted include should link to the parsed header");
+
+        // Re-parsing the header drops its file node; the include is relinked
+        Spi::run(&format!(
+            "SELECT kerai.parse_c_source('{}', 'inc_util.h')",
+            sql_escape("int util(int x);\nint other(void);\n"),
+        ))
+        .unwrap();
+        let relinked = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.edges e \
+             JOIN kerai.nodes t ON t.id = e.target_id \
+             WHERE e.relation = 'includes' AND t.content = 'inc_util.h'",
+        )
+        .unwrap()
+        .unwrap_or(0);
+        assert_eq!(relinked, 1, "re-parsed header should be relinked");
+    }
+
+    #[pg_test]
+    fn test_parse_cpp_source_class_namespace() {
+        let source = r#"#include "shape.hpp"
+
+namespace geo {
+
+class Circle {
+public:
+    double area() const { return 3.14 * r * r; }
+    double perimeter() const;
+private:
+    double r;
+};
+
+template <typename T>
+T twice(T x) { return x + x; }
+
+}
+"#;
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.parse_cpp_source('{}', 'circle.cpp')",
+            sql_escape(source),
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(result.0["language"], "cpp");
+
+        let class_path = Spi::get_one::<String>(
+            "SELECT path::text FROM kerai.nodes WHERE kind = 'cpp_class' AND content = 'Circle'",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(class_path, "circle_cpp.geo.Circle");
+
+        let method = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.nodes m \
+             JOIN kerai.nodes c ON c.id = m.parent_id \
+             WHERE c.kind = 'cpp_class' AND m.kind = 'c_function' AND m.content = 'area'",
+        )
+        .unwrap()
+        .unwrap_or(0);
+        assert_eq!(method, 1, "inline method should be a c_function under the class");
+
+        let templated = Spi::get_one::<i64>(
+            "SELECT count(*) FROM kerai.nodes f \
+             JOIN kerai.nodes t ON t.id = f.parent_id \
+             WHERE t.kind = 'cpp_template' AND f.kind = 'c_function' AND f.content = 'twice'",
+        )
+        .unwrap()
+        .unwrap_or(0);
+        assert_eq!(templated, 1, "templated function should sit under cpp_template");
+    }
+
     /// sql_escape helper for tests
     fn sql_escape(s: &str) -> String {
         s.replace('\'', "''")
     }
 
+    // --- TOML parser tests ---
+
+    #[pg_test]
+    fn test_parse_toml_cargo_dependents() {
+        let source = r#"[package]
+name = "toml_dep_demo"
+version = "0.1.0"
+
+[dependencies]
+serde_toml_probe = { version = "1.0", features = ["derive"] }
+
+[dev-dependencies]
+tempfile = "3"
+"#;
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.parse_toml_source('{}', 'demo/Cargo.toml')",
+            sql_escape(source),
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(result.0["edges"].as_u64(), Some(2));
+
+        let refs = Spi::get_one::<pgrx::JsonB>("SELECT kerai.refs('serde_toml_probe')")
+            .unwrap()
+            .unwrap();
+        let dependents = refs.0["dependents"].as_array().expect("dependents array");
+        assert_eq!(dependents.len(), 1);
+        assert_eq!(dependents[0]["crate"], "toml_dep_demo");
+        assert_eq!(dependents[0]["version"], "1.0");
+        assert_eq!(dependents[0]["dep_type"], "normal");
+    }
+
+    #[pg_test]
+    fn test_parse_toml_plain_keys() {
+        let source = "[server]\nport = 8080\n";
+        Spi::run(&format!(
+            "SELECT kerai.parse_toml_source('{}', 'config.toml')",
+            sql_escape(source),
+        ))
+        .unwrap();
+
+        let port = Spi::get_one::<String>(
+            "SELECT metadata->>'value' FROM kerai.nodes WHERE kind = 'toml_key' AND content = 'port'",
+        )
+        .unwrap();
+        assert_eq!(port.as_deref(), Some("8080"));
+    }
+
+    // --- YAML parser tests ---
+
+    #[pg_test]
+    fn test_parse_yaml_key_paths() {
+        let source = "services:\n  web:\n    image: nginx:1.27\n  db:\n    image: postgres:17\n";
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.parse_yaml_source('{}', 'docker-compose.yml')",
+            sql_escape(source),
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(result.0["nodes"].as_u64().unwrap_or(0) > 0);
+
+        let found = Spi::get_one::<pgrx::JsonB>("SELECT kerai.find('image', 'yaml_key', 10)")
+            .unwrap()
+            .unwrap();
+        let paths: Vec<&str> = found
+            .0
+            .as_array()
+            .expect("find should return array")
+            .iter()
+            .filter_map(|n| n["path"].as_str())
+            .collect();
+        assert!(paths.contains(&"docker_compose_yml.services.web.image"));
+        assert!(paths.contains(&"docker_compose_yml.services.db.image"));
+    }
+
     // --- Plan 19: Repository ingestion tests ---
 
     /// Helper: create a temporary git repo with some files and a commit.
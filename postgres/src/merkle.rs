@@ -0,0 +1,353 @@
+/// Merkle hashes over the node graph.
+///
+/// Every node's `content_hash` is the SHA-256 of its kind, content and
+/// metadata (location keys left out) followed by its children's hashes in
+/// position order, so it stands for its whole subtree. Tombstones (nodes
+/// with `deleted_at`) count for nothing. Parsers set it as
+/// they insert (`inserter::subtree_hashes`); `refresh` keeps it current
+/// afterwards, when CRDT ops edit, move or delete nodes or a file is
+/// reparsed under an existing crate, by rehashing from the changed node
+/// towards its root and stopping at the first node whose hash holds.
+///
+/// Node ids differ between instances for anything parsed locally, so
+/// subtrees are compared by path: `merkle_hashes` lists the hash under
+/// every path down to a depth, and `merkle_diff` names the subtrees where
+/// a peer's list departs from ours, which `kerai sync` reports.
+use std::collections::{BTreeMap, BTreeSet};
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::parser::inserter::LOCATION_KEYS;
+use crate::sql::{sql_text, sql_uuid};
+
+/// Depth `merkle_hashes` and `merkle_diff` go to by default: the roots
+/// (crates, files, documents) and what sits directly under them.
+const DEFAULT_DEPTH: i32 = 2;
+
+/// Hash of one node given its children's hashes in position order.
+pub fn node_hash<'a>(
+    kind: &str,
+    content: Option<&str>,
+    metadata: &Value,
+    children: impl IntoIterator<Item = &'a str>,
+) -> String {
+    let mut meta = metadata.clone();
+    if let Some(obj) = meta.as_object_mut() {
+        for key in LOCATION_KEYS {
+            obj.remove(*key);
+        }
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(kind.as_bytes());
+    hasher.update([0]);
+    hasher.update(content.unwrap_or("").as_bytes());
+    hasher.update([0]);
+    hasher.update(meta.to_string().as_bytes());
+    for child in children {
+        hasher.update(child.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Recompute `node_id`'s hash from its row and its children's stored
+/// hashes, then its parent's, and so on up while hashes keep changing.
+/// Dedup stubs keep the hash of the subtree they stand for.
+pub fn refresh(node_id: &str) {
+    let mut next = Some(node_id.to_string());
+    let mut seen = BTreeSet::new();
+    while let Some(id) = next.take() {
+        if !seen.insert(id.clone()) {
+            break;
+        }
+        let Some(row) = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT jsonb_build_object(
+                'kind', n.kind,
+                'content', n.content,
+                'metadata', n.metadata,
+                'hash', n.content_hash,
+                'parent_id', n.parent_id,
+                'children', COALESCE((
+                    SELECT jsonb_agg(COALESCE(c.content_hash, '') ORDER BY c.position, c.created_at)
+                    FROM kerai.nodes c WHERE c.parent_id = n.id AND c.deleted_at IS NULL
+                ), '[]'::jsonb)
+            ) FROM kerai.nodes n WHERE n.id = {}",
+            sql_uuid(&id),
+        ))
+        .unwrap()
+        .map(|j| j.0) else {
+            break;
+        };
+        if row["metadata"].get("dedup_of").is_some() {
+            break;
+        }
+
+        let children = row["children"].as_array().cloned().unwrap_or_default();
+        let hash = node_hash(
+            row["kind"].as_str().unwrap_or(""),
+            row["content"].as_str(),
+            &row["metadata"],
+            children.iter().map(|c| c.as_str().unwrap_or("")),
+        );
+        if row["hash"].as_str() == Some(hash.as_str()) {
+            break;
+        }
+        Spi::run(&format!(
+            "UPDATE kerai.nodes SET content_hash = {} WHERE id = {}",
+            sql_text(&hash),
+            sql_uuid(&id),
+        ))
+        .expect("Failed to update subtree hash");
+        next = row["parent_id"].as_str().map(String::from);
+    }
+}
+
+/// The parent of `node_id`, for rehashing it once the node moves or goes.
+pub fn parent_of(node_id: &str) -> Option<String> {
+    Spi::get_one::<String>(&format!(
+        "SELECT parent_id::text FROM kerai.nodes WHERE id = {}",
+        sql_uuid(node_id),
+    ))
+    .unwrap_or(None)
+}
+
+/// Rehash what a node op changed: the node itself, and for ops that move
+/// it in or out of a parent's child list, the parents on both sides.
+pub fn refresh_after_op(op_type: &str, node_id: &str, old_parent: Option<&str>) {
+    match op_type {
+        "update_content" | "update_metadata" => refresh(node_id),
+        "insert_node" | "move_node" => {
+            refresh(node_id);
+            if let Some(parent) = parent_of(node_id) {
+                refresh(&parent);
+            }
+            if let Some(parent) = old_parent {
+                refresh(parent);
+            }
+        }
+        "delete_node" => {
+            if let Some(parent) = old_parent {
+                refresh(parent);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Fold `(key, hash)` pairs, in key order, into one hash.
+fn fold(entries: &mut [(String, String)]) -> String {
+    entries.sort();
+    let mut hasher = Sha256::new();
+    for (key, hash) in entries.iter() {
+        hasher.update(key.as_bytes());
+        hasher.update([0]);
+        hasher.update(hash.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// `(key, hash)` of every node down to `depth` levels below the roots,
+/// keyed by path, or by parent key and `kind:content` for nodes without
+/// one. Sandbox copies are left out.
+fn keyed_hashes(depth: i32) -> Vec<(String, String)> {
+    let rows = Spi::get_one::<pgrx::JsonB>(&format!(
+        "WITH RECURSIVE tree AS (
+            SELECT n.id, COALESCE(n.path::text, n.kind || ':' || COALESCE(n.content, '')) AS key,
+                   COALESCE(n.content_hash, '') AS hash, 1 AS depth
+            FROM kerai.nodes n
+            WHERE n.parent_id IS NULL AND n.deleted_at IS NULL AND {outside}
+            UNION ALL
+            SELECT c.id, COALESCE(c.path::text, t.key || '/' || c.kind || ':' || COALESCE(c.content, '')),
+                   COALESCE(c.content_hash, ''), t.depth + 1
+            FROM tree t JOIN kerai.nodes c ON c.parent_id = t.id
+            WHERE t.depth < {depth} AND c.deleted_at IS NULL
+        )
+        SELECT COALESCE(jsonb_agg(jsonb_build_array(key, hash)), '[]'::jsonb) FROM tree",
+        outside = crate::sandboxes::unsandboxed("n.path"),
+    ))
+    .unwrap()
+    .map(|j| j.0)
+    .unwrap_or_else(|| json!([]));
+    rows.as_array()
+        .into_iter()
+        .flatten()
+        .map(|r| {
+            (
+                r[0].as_str().unwrap_or("").to_string(),
+                r[1].as_str().unwrap_or("").to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Group hashes by key; several nodes can share a path (two `impl` blocks
+/// of one type), and then their hashes are folded together.
+fn by_key(entries: Vec<(String, String)>) -> BTreeMap<String, String> {
+    let mut grouped: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+    for (key, hash) in entries {
+        grouped.entry(key.clone()).or_default().push((key, hash));
+    }
+    grouped
+        .into_iter()
+        .map(|(key, mut hashes)| {
+            let hash = if hashes.len() == 1 {
+                hashes.pop().unwrap().1
+            } else {
+                fold(&mut hashes)
+            };
+            (key, hash)
+        })
+        .collect()
+}
+
+/// Whether `key` lies under `ancestor`: a longer ltree path, or a key
+/// built from it for a node without a path.
+fn is_under(key: &str, ancestor: &str) -> bool {
+    key.len() > ancestor.len()
+        && key.starts_with(ancestor)
+        && matches!(key.as_bytes()[ancestor.len()], b'.' | b'/')
+}
+
+/// The subtrees where `ours` and `theirs` part: keys held on one side only
+/// (their descendants are not listed again), and keys on both sides whose
+/// hashes differ while nothing under them does, so the change is theirs.
+fn diff(ours: &BTreeMap<String, String>, theirs: &BTreeMap<String, String>) -> Vec<Value> {
+    let keys: BTreeSet<&String> = ours.keys().chain(theirs.keys()).collect();
+    let differing: Vec<(&str, &str)> = keys
+        .into_iter()
+        .filter_map(|k| match (ours.get(k), theirs.get(k)) {
+            (Some(a), Some(b)) if a == b => None,
+            (Some(_), Some(_)) => Some((k.as_str(), "changed")),
+            (Some(_), None) => Some((k.as_str(), "local_only")),
+            _ => Some((k.as_str(), "peer_only")),
+        })
+        .collect();
+
+    differing
+        .iter()
+        .filter(|(key, status)| {
+            let inside_one_sided = differing
+                .iter()
+                .any(|(other, s)| *s != "changed" && is_under(key, other));
+            let has_changed_below =
+                *status == "changed" && differing.iter().any(|(other, _)| is_under(other, key));
+            !inside_one_sided && !has_changed_below
+        })
+        .map(|(key, status)| json!({ "key": key, "status": status }))
+        .collect()
+}
+
+/// One hash over the subtrees at and under `path` (an ltree path), or over
+/// the whole graph when it is null. Equal roots mean equal trees, whatever
+/// the node ids. Returns null when nothing is there.
+#[pg_extern]
+fn merkle_root(path: default!(Option<&str>, "NULL")) -> Option<String> {
+    let scope = |col: &str| match path {
+        Some(p) => format!("{col} <@ {}::ltree", sql_text(p)),
+        None => "true".to_string(),
+    };
+    let tops = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_array(
+                    COALESCE(n.path::text, n.kind || ':' || COALESCE(n.content, '')),
+                    COALESCE(n.content_hash, ''))), '[]'::jsonb)
+         FROM kerai.nodes n
+         WHERE {in_scope} AND {outside} AND n.deleted_at IS NULL
+           AND NOT EXISTS (SELECT 1 FROM kerai.nodes p WHERE p.id = n.parent_id AND {parent_in_scope})",
+        in_scope = scope("n.path"),
+        parent_in_scope = scope("p.path"),
+        outside = crate::sandboxes::unsandboxed("n.path"),
+    ))
+    .unwrap()
+    .map(|j| j.0)
+    .unwrap_or_else(|| json!([]));
+
+    let mut entries: Vec<(String, String)> = tops
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|r| {
+            (
+                r[0].as_str().unwrap_or("").to_string(),
+                r[1].as_str().unwrap_or("").to_string(),
+            )
+        })
+        .collect();
+    if entries.is_empty() {
+        return None;
+    }
+    Some(fold(&mut entries))
+}
+
+/// `{root, hashes: {key: hash}}`: the graph's Merkle root and the hash of
+/// every subtree down to `depth` levels (default 2), keyed by path.
+#[pg_extern]
+fn merkle_hashes(depth: default!(Option<i32>, "NULL")) -> pgrx::JsonB {
+    let hashes = by_key(keyed_hashes(depth.unwrap_or(DEFAULT_DEPTH).max(1)));
+    pgrx::JsonB(json!({
+        "root": merkle_root(None),
+        "hashes": hashes,
+    }))
+}
+
+/// Subtrees that differ from a peer's `merkle_hashes` output, compared to
+/// the same depth: `[{key, status}]`, `status` being `changed`,
+/// `local_only` or `peer_only`. Empty when the trees match.
+#[pg_extern]
+fn merkle_diff(peer: pgrx::JsonB, depth: default!(Option<i32>, "NULL")) -> pgrx::JsonB {
+    if peer.0["root"].is_string() && peer.0["root"].as_str() == merkle_root(None).as_deref() {
+        return pgrx::JsonB(json!([]));
+    }
+    let theirs: BTreeMap<String, String> = peer.0["hashes"]
+        .as_object()
+        .unwrap_or_else(|| error!("merkle_diff expects merkle_hashes output"))
+        .iter()
+        .map(|(k, v)| (k.clone(), v.as_str().unwrap_or("").to_string()))
+        .collect();
+    let ours = by_key(keyed_hashes(depth.unwrap_or(DEFAULT_DEPTH).max(1)));
+    pgrx::JsonB(Value::Array(diff(&ours, &theirs)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn node_hash_ignores_location_keys() {
+        let a = node_hash("fn", Some("f"), &json!({"line": 1, "vis": "pub"}), ["x"]);
+        let b = node_hash("fn", Some("f"), &json!({"line": 9, "vis": "pub"}), ["x"]);
+        assert_eq!(a, b);
+        assert_ne!(a, node_hash("fn", Some("f"), &json!({"vis": "pub"}), ["y"]));
+    }
+
+    #[test]
+    fn diff_names_the_deepest_change() {
+        let ours = map(&[("app", "1"), ("app.main", "2"), ("app.util", "3")]);
+        let theirs = map(&[("app", "9"), ("app.main", "2"), ("app.util", "4")]);
+        assert_eq!(
+            diff(&ours, &theirs),
+            vec![json!({"key": "app.util", "status": "changed"})]
+        );
+    }
+
+    #[test]
+    fn diff_lists_one_sided_subtrees_once() {
+        let ours = map(&[("app", "1"), ("app.main", "2"), ("app.main.f", "3")]);
+        let theirs = map(&[("app", "8"), ("doc:a.md", "5"), ("doc:a.md/heading:A", "6")]);
+        assert_eq!(
+            diff(&ours, &theirs),
+            vec![
+                json!({"key": "app.main", "status": "local_only"}),
+                json!({"key": "doc:a.md", "status": "peer_only"}),
+            ]
+        );
+    }
+}
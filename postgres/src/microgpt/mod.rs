@@ -9,7 +9,7 @@ use self::model::{MicroGPT, ModelConfig};
 use self::tensor::Tensor;
 
 /// Helper: look up agent_id by name.
-fn agent_id_by_name(agent_name: &str) -> Result<String, String> {
+pub(crate) fn agent_id_by_name(agent_name: &str) -> Result<String, String> {
     let sql = format!(
         "SELECT id::text FROM kerai.agents WHERE name = '{}'",
         agent_name.replace('\'', "''")
@@ -45,7 +45,7 @@ fn store_weights(agent_id: &str, model: &MicroGPT) -> Result<(), String> {
 }
 
 /// Helper: load model weights from DB.
-fn load_weights(agent_id: &str, config: &ModelConfig) -> Result<MicroGPT, String> {
+pub(crate) fn load_weights(agent_id: &str, config: &ModelConfig) -> Result<MicroGPT, String> {
     let mut weight_map = std::collections::HashMap::new();
 
     let sql = format!(
@@ -79,7 +79,7 @@ fn load_weights(agent_id: &str, config: &ModelConfig) -> Result<MicroGPT, String
 }
 
 /// Helper: load model config from agent's config JSONB.
-fn load_model_config(agent_id: &str) -> Result<ModelConfig, String> {
+pub(crate) fn load_model_config(agent_id: &str) -> Result<ModelConfig, String> {
     let sql = format!(
         "SELECT config::text FROM kerai.agents WHERE id = '{agent_id}'::uuid"
     );
@@ -376,12 +376,10 @@ fn neural_search(
     // Get FTS candidates
     let escaped_query = query_text.replace('\'', "''");
     let fts_sql = format!(
-        "SELECT id::text, ts_rank(to_tsvector('english', COALESCE(content, '')),
-                                  plainto_tsquery('english', '{}')) AS rank,
+        "SELECT id::text, ts_rank(tsv, plainto_tsquery('english', '{}')) AS rank,
                 kind, path::text
          FROM kerai.nodes
-         WHERE to_tsvector('english', COALESCE(content, ''))
-               @@ plainto_tsquery('english', '{}')
+         WHERE tsv @@ plainto_tsquery('english', '{}')
          ORDER BY rank DESC
          LIMIT {}",
         escaped_query, escaped_query, lim * 2
@@ -661,7 +659,7 @@ fn model_info(agent_name: &str) -> pgrx::JsonB {
     }))
 }
 
-/// Delete a model's weights and vocabulary.
+/// Delete a model's weights, vocabulary and node embeddings.
 #[pg_extern]
 fn delete_model(agent_name: &str) -> pgrx::JsonB {
     let agent_id = agent_id_by_name(agent_name).unwrap_or_else(|e| error!("{e}"));
@@ -678,11 +676,15 @@ fn delete_model(agent_name: &str) -> pgrx::JsonB {
     let del_log = format!(
         "DELETE FROM kerai.inference_log WHERE agent_id = '{agent_id}'::uuid"
     );
+    let del_embeddings = format!(
+        "DELETE FROM kerai.node_embeddings WHERE model_id = '{agent_id}'::uuid"
+    );
 
     Spi::run(&del_weights).unwrap_or_else(|e| error!("Failed to delete weights: {e}"));
     Spi::run(&del_vocab).unwrap_or_else(|e| error!("Failed to delete vocab: {e}"));
     Spi::run(&del_runs).unwrap_or_else(|e| error!("Failed to delete runs: {e}"));
     Spi::run(&del_log).unwrap_or_else(|e| error!("Failed to delete log: {e}"));
+    Spi::run(&del_embeddings).unwrap_or_else(|e| error!("Failed to delete embeddings: {e}"));
 
     // Clear model config
     let clear_config = format!(
@@ -767,7 +769,7 @@ fn mint_training_reward(agent_id: &str, steps: usize) {
 }
 
 /// Deduct Koi for inference.
-fn deduct_inference_cost(agent_id: &str) {
+pub(crate) fn deduct_inference_cost(agent_id: &str) {
     // Look up the agent's wallet
     let wallet_sql = format!(
         "SELECT w.id::text FROM kerai.wallets w
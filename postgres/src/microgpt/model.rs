@@ -641,6 +641,18 @@ impl MicroGPT {
         indexed
     }
 
+    /// Final hidden state (after the last norm) at the last position of a
+    /// sequence: a [dim] vector summarizing the token in its context.
+    /// Keeps the last `context_len` tokens if the sequence is longer.
+    pub fn hidden_state(&self, tokens: &[usize]) -> Vec<f32> {
+        let start = tokens.len().saturating_sub(self.config.context_len);
+        let tokens = &tokens[start..];
+        let (_, cache) = self.forward(tokens);
+        let dim = self.config.dim;
+        let last = tokens.len() - 1;
+        cache.final_normed.data[last * dim..(last + 1) * dim].to_vec()
+    }
+
     /// Serialize model to a map of tensor_name → (Tensor).
     pub fn to_weight_map(&self) -> HashMap<String, Tensor> {
         let mut map = HashMap::new();
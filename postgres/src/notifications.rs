@@ -0,0 +1,82 @@
+/// Cursor-based polling of the operation log, for agents that cannot hold
+/// a websocket open.
+///
+/// A cursor is `<txid>-<seq>`: every operation ordered before it by
+/// (inserting transaction, seq) has been delivered. Only operations of
+/// transactions older than every one still running are returned, so an
+/// operation committed late by a long transaction is never skipped.
+use pgrx::prelude::*;
+
+use crate::sql::sql_escape;
+
+/// Parse a `<txid>-<seq>` cursor; `None` for anything else.
+fn parse_cursor(cursor: &str) -> Option<(u64, i64)> {
+    let (txid, seq) = cursor.split_once('-')?;
+    Some((txid.parse().ok()?, seq.parse().ok()?))
+}
+
+/// Operations after `cursor` (from the start when NULL), optionally only
+/// those on nodes under `region`. Returns `{events, cursor, more}`; pass
+/// the returned cursor to the next call. `more` means `max_events` cut
+/// the batch short.
+#[pg_extern]
+fn poll_changes(
+    cursor: default!(Option<&str>, "NULL"),
+    region: default!(Option<&str>, "NULL"),
+    max_events: default!(i32, 100),
+) -> pgrx::JsonB {
+    let (after_txid, after_seq) = match cursor {
+        Some(c) => parse_cursor(c).unwrap_or_else(|| error!("Invalid cursor: {}", c)),
+        None => (0, 0),
+    };
+    let max_events = max_events.clamp(1, 10_000);
+    let region_clause = match region.filter(|r| !r.is_empty()) {
+        Some(r) => format!("AND o.path <@ '{}'::ltree", sql_escape(r)),
+        None => String::new(),
+    };
+
+    // One statement, so the horizon and the rows come from one snapshot
+    let result = Spi::get_one::<pgrx::JsonB>(&format!(
+        "WITH horizon AS (
+            SELECT pg_snapshot_xmin(pg_current_snapshot()) AS xmin
+        ), batch AS (
+            SELECT o.txid, o.seq, o.op_type, o.node_id, o.path, o.lamport_ts, o.author, o.created_at
+            FROM kerai.operations o, horizon h
+            WHERE (o.txid, o.seq) > ('{after_txid}'::xid8, {after_seq})
+              AND o.txid < h.xmin
+              {region_clause}
+            ORDER BY o.txid, o.seq
+            LIMIT {limit}
+        ), page AS (
+            SELECT * FROM batch ORDER BY txid, seq LIMIT {max_events}
+        ), last AS (
+            SELECT txid, seq FROM page ORDER BY txid DESC, seq DESC LIMIT 1
+        )
+        SELECT jsonb_build_object(
+            'events', COALESCE((
+                SELECT jsonb_agg(jsonb_build_object(
+                    'op_type', op_type,
+                    'node_id', node_id,
+                    'path', path::text,
+                    'lamport_ts', lamport_ts,
+                    'author', author,
+                    'created_at', created_at
+                ) ORDER BY txid, seq)
+                FROM page
+            ), '[]'::jsonb),
+            'more', (SELECT count(*) FROM batch) > {max_events},
+            'cursor', CASE
+                WHEN (SELECT count(*) FROM batch) > {max_events}
+                    THEN (SELECT txid::text || '-' || seq FROM last)
+                -- Everything before the horizon is final: resume there
+                WHEN (SELECT xmin FROM horizon) > '{after_txid}'::xid8
+                    THEN (SELECT xmin::text || '-0' FROM horizon)
+                ELSE '{after_txid}-{after_seq}'
+            END
+        )",
+        limit = max_events as i64 + 1,
+    ))
+    .unwrap()
+    .unwrap();
+    result
+}
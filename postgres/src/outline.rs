@@ -0,0 +1,349 @@
+/// Outline editing — split, merge, promote and demote document nodes.
+///
+/// These are the structural edits an outliner makes besides moving a node
+/// or changing its text. Each one is a run of `kerai.apply_op` operations
+/// (content and metadata updates, an insert, moves, a delete) inside one
+/// call, so every step is versioned and reaches peers on sync, and an edit
+/// that fails part way leaves nothing behind.
+///
+/// Positions only order siblings and parsers leave gaps between them, so
+/// an edit moves only as many later siblings as it needs to make room, and
+/// merges leave gaps. A heading's section is its children — the content
+/// after it and the deeper headings — as the markdown parser builds it, so
+/// changing a heading's level also moves what its section gains or loses.
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::parser::markdown::kinds;
+use crate::sql::{sql_jsonb, sql_text, sql_uuid};
+
+/// Deepest markdown heading level.
+const MAX_LEVEL: i64 = 6;
+
+/// A node row, as far as the edits need it.
+struct Node {
+    id: String,
+    kind: String,
+    language: Option<String>,
+    content: Option<String>,
+    parent_id: Option<String>,
+    position: i32,
+    path: Option<String>,
+    metadata: Value,
+}
+
+/// Columns of a kerai.nodes row read into a `Node`.
+const NODE_JSON: &str = "jsonb_build_object(
+    'id', id, 'kind', kind, 'language', language, 'content', content,
+    'parent_id', parent_id, 'position', position, 'path', path::text,
+    'metadata', metadata)";
+
+impl Node {
+    fn from_json(v: &Value) -> Node {
+        let text = |key: &str| v[key].as_str().map(String::from);
+        Node {
+            id: text("id").unwrap_or_default(),
+            kind: text("kind").unwrap_or_default(),
+            language: text("language"),
+            content: text("content"),
+            parent_id: text("parent_id"),
+            position: v["position"].as_i64().unwrap_or(0) as i32,
+            path: text("path"),
+            metadata: v["metadata"].clone(),
+        }
+    }
+
+    /// Heading level, None for any other kind.
+    fn level(&self) -> Option<i64> {
+        (self.kind == kinds::HEADING).then(|| self.metadata["level"].as_i64().unwrap_or(1))
+    }
+
+    fn heading_level(&self) -> i64 {
+        self.level()
+            .unwrap_or_else(|| error!("Node {} is a {}, not a heading", self.id, self.kind))
+    }
+
+    fn parent(&self) -> &str {
+        self.parent_id
+            .as_deref()
+            .unwrap_or_else(|| error!("Node {} has no parent", self.id))
+    }
+}
+
+fn load(id: &str) -> Node {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT {} FROM kerai.nodes WHERE id = {}",
+        NODE_JSON,
+        sql_uuid(id),
+    ))
+    .unwrap()
+    .map(|j| Node::from_json(&j.0))
+    .unwrap_or_else(|| error!("Node not found: {}", id))
+}
+
+/// Children of `parent`, in order.
+fn children(parent: &str) -> Vec<Node> {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg({} ORDER BY position, id), '[]'::jsonb)
+         FROM kerai.nodes WHERE parent_id = {}",
+        NODE_JSON,
+        sql_uuid(parent),
+    ))
+    .unwrap()
+    .and_then(|j| {
+        j.0.as_array()
+            .map(|a| a.iter().map(Node::from_json).collect())
+    })
+    .unwrap_or_default()
+}
+
+/// Position after the last child of `parent`.
+fn end_of(parent: &str) -> i32 {
+    children(parent).last().map_or(0, |c| c.position + 1)
+}
+
+/// The operations one edit applies, in order.
+#[derive(Default)]
+struct Edit {
+    ops: Vec<Value>,
+}
+
+impl Edit {
+    /// Apply one operation; returns the node it affected.
+    fn apply(&mut self, op_type: &str, node_id: Option<&str>, payload: Value) -> String {
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.apply_op({}, {}, {})",
+            sql_text(op_type),
+            node_id.map_or_else(|| "NULL".to_string(), sql_uuid),
+            sql_jsonb(&payload),
+        ))
+        .unwrap_or_else(|e| error!("{} failed: {}", op_type, e))
+        .map_or(Value::Null, |j| j.0);
+        let affected = result["node_id"].as_str().unwrap_or_default().to_string();
+        self.ops.push(result);
+        affected
+    }
+
+    fn move_to(&mut self, node: &str, parent: &str, position: i32) {
+        self.apply(
+            "move_node",
+            Some(node),
+            json!({"new_parent_id": parent, "new_position": position}),
+        );
+    }
+
+    /// Free positions `after + 1 ..= after + count` under `parent`, moving
+    /// later siblings along only as far as they are in the way.
+    fn make_room(&mut self, parent: &str, after: i32, count: i32) {
+        let mut next = after + count + 1;
+        for sibling in children(parent).iter().filter(|s| s.position > after) {
+            if sibling.position >= next {
+                break;
+            }
+            self.move_to(&sibling.id, parent, next);
+            next += 1;
+        }
+    }
+
+    /// Move `nodes` under `parent`, after its last child.
+    fn append(&mut self, nodes: &[&Node], parent: &str) {
+        let start = end_of(parent);
+        for (i, node) in nodes.iter().enumerate() {
+            self.move_to(&node.id, parent, start + i as i32);
+        }
+    }
+
+    /// Set a heading's level. A setext underline only writes levels 1 and
+    /// 2, so the heading is written with `#`s from then on.
+    fn set_level(&mut self, node: &Node, level: i64) {
+        let mut merge = json!({"level": level});
+        if node.metadata["setext"] == true {
+            merge["setext"] = json!(false);
+            merge["underline"] = Value::Null;
+        }
+        self.apply("update_metadata", Some(&node.id), json!({"merge": merge}));
+    }
+}
+
+/// The siblings after `node` under its parent, in order.
+fn later_siblings(node: &Node) -> Vec<Node> {
+    children(node.parent())
+        .into_iter()
+        .skip_while(|s| s.id != node.id)
+        .skip(1)
+        .collect()
+}
+
+/// Split a node's content at character `at`: the node keeps the text
+/// before it, and a new node of the same kind right after it gets the rest
+/// along with the node's children, which follow the text in the document.
+/// `at` may be 0 or the content's length, leaving an empty half.
+///
+/// Returns `{node_id, new_node_id, ops}`, `ops` being the operations
+/// applied.
+#[pg_extern]
+fn split_node(node_id: pgrx::Uuid, at: i32) -> pgrx::JsonB {
+    let node = load(&node_id.to_string());
+    let parent = node.parent();
+    let content = node.content.as_deref().unwrap_or("");
+    let length = content.chars().count();
+    let at = usize::try_from(at)
+        .ok()
+        .filter(|&at| at <= length)
+        .unwrap_or_else(|| error!("Split point {} is outside 0..={}", at, length));
+    let split = content
+        .char_indices()
+        .nth(at)
+        .map_or(content.len(), |(i, _)| i);
+    let (head, tail) = content.split_at(split);
+
+    let mut edit = Edit::default();
+    edit.make_room(parent, node.position, 1);
+    edit.apply(
+        "update_content",
+        Some(&node.id),
+        json!({"new_content": head}),
+    );
+    let mut payload = json!({
+        "kind": node.kind,
+        "content": tail,
+        "parent_id": parent,
+        "position": node.position + 1,
+        "metadata": node.metadata,
+    });
+    if let Some(language) = &node.language {
+        payload["language"] = json!(language);
+    }
+    if let Some(path) = &node.path {
+        payload["path"] = json!(path);
+    }
+    let new_id = edit.apply("insert_node", None, payload);
+    for child in children(&node.id) {
+        edit.move_to(&child.id, &new_id, child.position);
+    }
+
+    pgrx::JsonB(json!({
+        "node_id": node.id,
+        "new_node_id": new_id,
+        "ops": edit.ops.len(),
+    }))
+}
+
+/// Merge the next sibling of a node into it: its content is appended after
+/// `separator`, its children follow the node's own, and it is deleted. The
+/// two must be of the same kind. Undoes `split_node` with the default
+/// separator.
+///
+/// Returns `{node_id, merged_id, ops}`.
+#[pg_extern]
+fn merge_with_next(node_id: pgrx::Uuid, separator: default!(&str, "''")) -> pgrx::JsonB {
+    let node = load(&node_id.to_string());
+    let next = later_siblings(&node)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| error!("Node {} has no next sibling to merge", node.id));
+    if next.kind != node.kind {
+        error!("Cannot merge a {} into a {}", next.kind, node.kind);
+    }
+
+    let mut edit = Edit::default();
+    let content = format!(
+        "{}{}{}",
+        node.content.as_deref().unwrap_or(""),
+        separator,
+        next.content.as_deref().unwrap_or(""),
+    );
+    edit.apply(
+        "update_content",
+        Some(&node.id),
+        json!({"new_content": content}),
+    );
+    let moved = children(&next.id);
+    edit.append(&moved.iter().collect::<Vec<_>>(), &node.id);
+    edit.apply("delete_node", Some(&next.id), json!({"cascade": false}));
+
+    pgrx::JsonB(json!({
+        "node_id": node.id,
+        "merged_id": next.id,
+        "ops": edit.ops.len(),
+    }))
+}
+
+/// Raise a markdown heading one level (`###` to `##`). The siblings after
+/// it that are not headings at the new level or above join its section,
+/// and when its parent heading is no longer above it, it moves out to
+/// follow that parent.
+///
+/// Returns `{node_id, level, ops}`.
+#[pg_extern]
+fn promote_heading(node_id: pgrx::Uuid) -> pgrx::JsonB {
+    let node = load(&node_id.to_string());
+    let level = node.heading_level();
+    if level <= 1 {
+        error!("Heading {} is already at level 1", node.id);
+    }
+    let level = level - 1;
+    let parent = load(node.parent());
+
+    let mut edit = Edit::default();
+    edit.set_level(&node, level);
+    let later = later_siblings(&node);
+    let joining: Vec<&Node> = later
+        .iter()
+        .take_while(|s| !matches!(s.level(), Some(l) if l <= level))
+        .collect();
+    edit.append(&joining, &node.id);
+
+    if parent.level().is_some_and(|l| l >= level) {
+        let grandparent = parent.parent();
+        edit.make_room(grandparent, parent.position, 1);
+        edit.move_to(&node.id, grandparent, parent.position + 1);
+    }
+
+    pgrx::JsonB(json!({"node_id": node.id, "level": level, "ops": edit.ops.len()}))
+}
+
+/// Lower a markdown heading one level (`##` to `###`). When the sibling
+/// before it is a heading above the new level, it moves into that
+/// heading's section, last; child headings at its new level leave its
+/// section, with what follows them, to come right after it.
+///
+/// Returns `{node_id, level, ops}`.
+#[pg_extern]
+fn demote_heading(node_id: pgrx::Uuid) -> pgrx::JsonB {
+    let node = load(&node_id.to_string());
+    let level = node.heading_level();
+    if level >= MAX_LEVEL {
+        error!("Heading {} is already at level {}", node.id, MAX_LEVEL);
+    }
+    let level = level + 1;
+    let siblings = children(node.parent());
+    let previous = siblings
+        .iter()
+        .take_while(|s| s.id != node.id)
+        .last()
+        .filter(|s| s.level().is_some_and(|l| l < level));
+
+    let mut edit = Edit::default();
+    edit.set_level(&node, level);
+    let own = children(&node.id);
+    let leaving: Vec<&Node> = own
+        .iter()
+        .skip_while(|c| !matches!(c.level(), Some(l) if l <= level))
+        .collect();
+
+    let (parent, position) = match previous {
+        Some(previous) => {
+            let position = end_of(&previous.id);
+            edit.move_to(&node.id, &previous.id, position);
+            (previous.id.as_str(), position)
+        }
+        None => (node.parent(), node.position),
+    };
+    edit.make_room(parent, position, leaving.len() as i32);
+    for (i, child) in leaving.iter().enumerate() {
+        edit.move_to(&child.id, parent, position + 1 + i as i32);
+    }
+
+    pgrx::JsonB(json!({"node_id": node.id, "level": level, "ops": edit.ops.len()}))
+}
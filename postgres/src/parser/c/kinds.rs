@@ -70,9 +70,76 @@ pub const C_NULL: &str = "c_null";
 // Identifiers
 pub const C_IDENT: &str = "c_ident";
 
+// C++ (only produced when parsing with language = 'cpp')
+pub const CPP_CLASS: &str = "cpp_class";
+pub const CPP_NAMESPACE: &str = "cpp_namespace";
+pub const CPP_TEMPLATE: &str = "cpp_template";
+
 // Catch-all
 pub const C_OTHER: &str = "c_other";
 
+/// Every C kind, for the parser registry.
+pub const ALL: &[&str] = &[
+    C_INCLUDE,
+    C_DEFINE,
+    C_MACRO,
+    C_IFDEF,
+    C_IF_DIRECTIVE,
+    C_PRAGMA,
+    C_FUNCTION,
+    C_DECLARATION,
+    C_TYPEDEF,
+    C_STRUCT,
+    C_UNION,
+    C_ENUM,
+    C_FIELD,
+    C_ENUMERATOR,
+    C_PARAM,
+    C_INIT_DECLARATOR,
+    C_POINTER_DECL,
+    C_ARRAY_DECL,
+    C_FUNC_DECL,
+    C_PAREN_DECL,
+    C_BLOCK,
+    C_IF,
+    C_FOR,
+    C_WHILE,
+    C_DO_WHILE,
+    C_SWITCH,
+    C_CASE,
+    C_RETURN,
+    C_BREAK,
+    C_CONTINUE,
+    C_GOTO,
+    C_LABEL,
+    C_EXPR_STMT,
+    C_CALL,
+    C_BINARY,
+    C_UNARY,
+    C_ASSIGNMENT,
+    C_TERNARY,
+    C_FIELD_ACCESS,
+    C_SUBSCRIPT,
+    C_CAST,
+    C_SIZEOF,
+    C_PAREN,
+    C_UPDATE,
+    C_PRIMITIVE_TYPE,
+    C_SIZED_TYPE,
+    C_TYPE_IDENT,
+    C_NUMBER_LIT,
+    C_STRING_LIT,
+    C_CHAR_LIT,
+    C_TRUE,
+    C_FALSE,
+    C_NULL,
+    C_IDENT,
+    C_OTHER,
+];
+
+/// Kinds only C++ sources produce.
+pub const CPP_ALL: &[&str] = &[CPP_CLASS, CPP_NAMESPACE, CPP_TEMPLATE];
+
 /// Map a tree-sitter C node kind string to a kerai C kind constant.
 pub fn ts_kind_to_c_kind(ts_kind: &str) -> &'static str {
     match ts_kind {
@@ -138,6 +205,10 @@ pub fn ts_kind_to_c_kind(ts_kind: &str) -> &'static str {
         "null" => C_NULL,
         // Identifiers
         "identifier" | "field_identifier" => C_IDENT,
+        // C++
+        "class_specifier" => CPP_CLASS,
+        "namespace_definition" => CPP_NAMESPACE,
+        "template_declaration" => CPP_TEMPLATE,
         // Catch-all
         _ => C_OTHER,
     }
@@ -11,6 +11,7 @@ use crate::parser::inserter;
 use crate::parser::kinds::Kind;
 use crate::parser::normalizer;
 use crate::parser::path_builder::PathContext;
+use crate::parser::registry::{self, LanguageParser, Placement};
 use crate::parser::treesitter::{self, TsLanguage};
 
 #[allow(dead_code)]
@@ -23,41 +24,62 @@ mod walker;
 ///
 /// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
 #[pg_extern]
-fn parse_c_source(source: &str, filename: &str) -> pgrx::JsonB {
+pub(super) fn parse_c_source(source: &str, filename: &str) -> pgrx::JsonB {
+    parse_source_as(source, filename, TsLanguage::C, "parse_c_source")
+}
+
+/// Parse a C file from disk into kerai.nodes and kerai.edges.
+///
+/// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
+#[pg_extern]
+fn parse_c_file(path: &str) -> pgrx::JsonB {
+    parse_file_as(path, TsLanguage::C, "parse_c_file")
+}
+
+/// Parse C++ source text directly into kerai.nodes and kerai.edges.
+///
+/// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
+#[pg_extern]
+pub(super) fn parse_cpp_source(source: &str, filename: &str) -> pgrx::JsonB {
+    parse_source_as(source, filename, TsLanguage::Cpp, "parse_cpp_source")
+}
+
+/// Parse a C++ file from disk into kerai.nodes and kerai.edges.
+///
+/// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
+#[pg_extern]
+fn parse_cpp_file(path: &str) -> pgrx::JsonB {
+    parse_file_as(path, TsLanguage::Cpp, "parse_cpp_file")
+}
+
+fn parse_source_as(source: &str, filename: &str, lang: TsLanguage, reward: &str) -> pgrx::JsonB {
     let start = Instant::now();
     let instance_id = super::get_self_instance_id();
+    let language = lang.name();
 
     // Delete existing nodes for this filename (idempotent re-parse)
     inserter::delete_file_nodes(&instance_id, filename);
 
-    let (node_count, edge_count) = parse_c_single(source, filename, &instance_id, None);
+    let (node_count, edge_count) = parse_c_family(source, filename, &instance_id, None, lang);
 
     // Auto-mint reward
     if node_count > 0 {
-        let details = json!({"file": filename, "language": "c", "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_c_source', '{}'::jsonb)",
-            details_str,
-        ));
+        let details = json!({"file": filename, "language": language, "nodes": node_count, "edges": edge_count});
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), filename);
+        crate::currency::reward(reward, "node", file_id.as_deref(), details);
     }
 
     let elapsed = start.elapsed();
     pgrx::JsonB(json!({
         "file": filename,
-        "language": "c",
+        "language": language,
         "nodes": node_count,
         "edges": edge_count,
         "elapsed_ms": elapsed.as_millis() as u64,
     }))
 }
 
-/// Parse a C file from disk into kerai.nodes and kerai.edges.
-///
-/// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
-#[pg_extern]
-fn parse_c_file(path: &str) -> pgrx::JsonB {
-    let start = Instant::now();
+fn parse_file_as(path: &str, lang: TsLanguage, reward: &str) -> pgrx::JsonB {
     let file_path = Path::new(path);
 
     if !file_path.exists() {
@@ -67,35 +89,64 @@ fn parse_c_file(path: &str) -> pgrx::JsonB {
     let source = std::fs::read_to_string(file_path)
         .unwrap_or_else(|e| pgrx::error!("Failed to read file: {}", e));
 
-    let instance_id = super::get_self_instance_id();
     let filename = file_path
         .file_name()
         .map(|f| f.to_string_lossy().to_string())
         .unwrap_or_else(|| path.to_string());
 
-    // Delete existing nodes for this file (idempotent re-parse)
-    inserter::delete_file_nodes(&instance_id, &filename);
+    parse_source_as(&source, &filename, lang, reward)
+}
 
-    let (node_count, edge_count) = parse_c_single(&source, &filename, &instance_id, None);
+/// C in the parser registry.
+pub(crate) struct C;
 
-    // Auto-mint reward
-    if node_count > 0 {
-        let details = json!({"file": filename, "language": "c", "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_c_file', '{}'::jsonb)",
-            details_str,
-        ));
+impl LanguageParser for C {
+    fn name(&self) -> &'static str {
+        "c"
     }
 
-    let elapsed = start.elapsed();
-    pgrx::JsonB(json!({
-        "file": filename,
-        "language": "c",
-        "nodes": node_count,
-        "edges": edge_count,
-        "elapsed_ms": elapsed.as_millis() as u64,
-    }))
+    fn extensions(&self) -> &'static [&'static str] {
+        &["c", "h"]
+    }
+
+    fn kinds(&self) -> Vec<&'static str> {
+        registry::kind_list(registry::COMMENTED, kinds::ALL)
+    }
+
+    fn parse_source(&self, source: &str, filename: &str) -> pgrx::JsonB {
+        parse_c_source(source, filename)
+    }
+
+    fn walk(&self, source: &str, filename: &str, at: &Placement) -> (usize, usize) {
+        parse_c_single(source, filename, at.instance_id, at.parent_id)
+    }
+}
+
+/// C++ in the parser registry.
+pub(crate) struct Cpp;
+
+impl LanguageParser for Cpp {
+    fn name(&self) -> &'static str {
+        "cpp"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["cpp", "cc", "cxx", "hpp", "hh", "hxx"]
+    }
+
+    fn kinds(&self) -> Vec<&'static str> {
+        let mut kinds = C.kinds();
+        kinds.extend_from_slice(kinds::CPP_ALL);
+        kinds
+    }
+
+    fn parse_source(&self, source: &str, filename: &str) -> pgrx::JsonB {
+        parse_cpp_source(source, filename)
+    }
+
+    fn walk(&self, source: &str, filename: &str, at: &Placement) -> (usize, usize) {
+        parse_cpp_single(source, filename, at.instance_id, at.parent_id)
+    }
 }
 
 /// Parse C source, insert nodes/edges, return counts.
@@ -107,27 +158,51 @@ pub(crate) fn parse_c_single(
     instance_id: &str,
     parent_id: Option<&str>,
 ) -> (usize, usize) {
+    parse_c_family(source, filename, instance_id, parent_id, TsLanguage::C)
+}
+
+/// Parse C++ source, insert nodes/edges, return counts.
+pub(crate) fn parse_cpp_single(
+    source: &str,
+    filename: &str,
+    instance_id: &str,
+    parent_id: Option<&str>,
+) -> (usize, usize) {
+    parse_c_family(source, filename, instance_id, parent_id, TsLanguage::Cpp)
+}
+
+/// Shared C/C++ pipeline: both grammars produce the same CST shapes for the
+/// C subset, so one walker handles both.
+fn parse_c_family(
+    source: &str,
+    filename: &str,
+    instance_id: &str,
+    parent_id: Option<&str>,
+    lang: TsLanguage,
+) -> (usize, usize) {
+    let language = lang.name();
+
     // 1. Normalize source
     let normalized = normalizer::normalize(source);
 
     // 2. Parse with tree-sitter
-    let tree = match treesitter::parse(&normalized, TsLanguage::C) {
+    let tree = match treesitter::parse(&normalized, lang) {
         Some(t) => t,
         None => {
-            warning!("Failed to parse C source: {}", filename);
+            warning!("Failed to parse {} source: {}", language, filename);
             return (0, 0);
         }
     };
 
     // 3. Create file node
-    let file_node_id = Uuid::new_v4().to_string();
+    let file_node_id = Uuid::now_v7().to_string();
     let path_ctx = PathContext::with_root(filename);
 
     let file_node = NodeRow {
         id: file_node_id.clone(),
         instance_id: instance_id.to_string(),
         kind: Kind::File.as_str().to_string(),
-        language: Some("c".to_string()),
+        language: Some(language.to_string()),
         content: Some(filename.to_string()),
         parent_id: parent_id.map(|s| s.to_string()),
         position: 0,
@@ -139,8 +214,14 @@ pub(crate) fn parse_c_single(
     inserter::insert_nodes(&[file_node]);
 
     // 4. Walk C CST
-    let (mut nodes, mut edges) =
-        walker::walk_c_file(&tree, &normalized, &file_node_id, instance_id, path_ctx);
+    let (mut nodes, mut edges) = walker::walk_c_file(
+        &tree,
+        &normalized,
+        &file_node_id,
+        instance_id,
+        path_ctx,
+        language,
+    );
 
     // 4b. Normalize top-level positions to span_start (line numbers)
     for node in &mut nodes {
@@ -169,7 +250,7 @@ pub(crate) fn parse_c_single(
 
     // 9. Create NodeRow + EdgeRow for each comment block
     for (block_idx, block) in blocks.iter().enumerate() {
-        let comment_id = Uuid::new_v4().to_string();
+        let comment_id = Uuid::now_v7().to_string();
         let kind = if !block.is_block_style && block.lines.len() > 1 {
             Kind::CommentBlock
         } else {
@@ -189,7 +270,7 @@ pub(crate) fn parse_c_single(
             id: comment_id.clone(),
             instance_id: instance_id.to_string(),
             kind: kind.as_str().to_string(),
-            language: Some("c".to_string()),
+            language: Some(language.to_string()),
             content: Some(content),
             parent_id: Some(file_node_id.clone()),
             position: block.start_line as i32,
@@ -209,7 +290,7 @@ pub(crate) fn parse_c_single(
         // Create "documents" edge if matched to a node
         if let Some(ref target_id) = matches[block_idx] {
             edges.push(EdgeRow {
-                id: Uuid::new_v4().to_string(),
+                id: Uuid::now_v7().to_string(),
                 source_id: comment_id,
                 target_id: target_id.clone(),
                 relation: "documents".to_string(),
@@ -219,7 +300,9 @@ pub(crate) fn parse_c_single(
     }
 
     // 10. Run C suggestion rules
-    let is_header = filename.ends_with(".h");
+    let is_header = [".h", ".hh", ".hpp", ".hxx"]
+        .iter()
+        .any(|ext| filename.ends_with(ext));
     let node_infos: Vec<suggestion_rules::CNodeInfo> = nodes
         .iter()
         .filter(|n| n.parent_id.as_deref() == Some(&file_node_id))
@@ -256,13 +339,13 @@ pub(crate) fn parse_c_single(
     let findings = suggestion_rules::run_c_rules(&node_infos);
 
     for finding in &findings {
-        let suggestion_id = Uuid::new_v4().to_string();
+        let suggestion_id = Uuid::now_v7().to_string();
 
         nodes.push(NodeRow {
             id: suggestion_id.clone(),
             instance_id: instance_id.to_string(),
             kind: Kind::Suggestion.as_str().to_string(),
-            language: Some("c".to_string()),
+            language: Some(language.to_string()),
             content: Some(finding.message.clone()),
             parent_id: Some(file_node_id.clone()),
             position: finding.line,
@@ -278,7 +361,7 @@ pub(crate) fn parse_c_single(
         });
 
         edges.push(EdgeRow {
-            id: Uuid::new_v4().to_string(),
+            id: Uuid::now_v7().to_string(),
             source_id: suggestion_id,
             target_id: finding.target_node_id.clone(),
             relation: "suggests".to_string(),
@@ -286,13 +369,69 @@ pub(crate) fn parse_c_single(
         });
     }
 
+    inserter::insert_deduped(&mut nodes, &mut edges);
+
     let node_count = nodes.len() + 1; // +1 for file node
     let edge_count = edges.len();
 
-    inserter::insert_nodes(&nodes);
-    inserter::insert_edges(&edges);
+    // 11. Link quoted #includes to parsed C/C++ files, in both directions
+    let include_ids: Vec<&str> = nodes
+        .iter()
+        .filter(|n| {
+            n.kind == kinds::C_INCLUDE
+                && n.metadata.get("system").and_then(|v| v.as_bool()) == Some(false)
+        })
+        .map(|n| n.id.as_str())
+        .collect();
+    let linked = link_includes(&include_ids, &file_node_id);
+
+    (node_count, edge_count + linked)
+}
+
+/// Create `includes` edges from `c_include` nodes to the `file` nodes they name.
+///
+/// Covers this file's own includes (`include_ids`) and includes in previously
+/// parsed files that name this file. A quoted path matches a file whose name
+/// equals it or where one ends with `/` + the other, so `"../util.h"` finds a
+/// file parsed as `util.h`. System (`<...>`) includes are never linked.
+fn link_includes(include_ids: &[&str], file_node_id: &str) -> usize {
+    let own = if include_ids.is_empty() {
+        "false".to_string()
+    } else {
+        let ids: Vec<String> = include_ids
+            .iter()
+            .map(|id| format!("'{}'::uuid", id))
+            .collect();
+        format!("i.id IN ({})", ids.join(", "))
+    };
 
-    (node_count, edge_count)
+    Spi::get_one::<i64>(&format!(
+        "WITH candidates AS (
+            SELECT i.id AS include_id, f.id AS file_id, btrim(i.content, '\"') AS inc_path
+            FROM kerai.nodes i
+            JOIN kerai.nodes f
+              ON f.kind = 'file'
+             AND f.language IN ('c', 'cpp')
+             AND f.instance_id = i.instance_id
+            WHERE i.kind = 'c_include'
+              AND i.metadata->>'system' = 'false'
+              AND ({own} OR f.id = '{file_node_id}'::uuid)
+        ),
+        inserted AS (
+            INSERT INTO kerai.edges (source_id, target_id, relation, metadata)
+            SELECT c.include_id, c.file_id, 'includes', jsonb_build_object('path', c.inc_path)
+            FROM kerai.nodes f
+            JOIN candidates c ON c.file_id = f.id
+            WHERE f.content = c.inc_path
+               OR right(f.content, length(c.inc_path) + 1) = '/' || c.inc_path
+               OR right(c.inc_path, length(f.content) + 1) = '/' || f.content
+            ON CONFLICT (source_id, target_id, relation) DO NOTHING
+            RETURNING 1
+        )
+        SELECT count(*)::bigint FROM inserted",
+    ))
+    .unwrap_or(None)
+    .unwrap_or(0) as usize
 }
 
 /// Match comment blocks to AST nodes and classify placement.
@@ -1,4 +1,7 @@
-/// C CST walker — converts tree-sitter C parse tree into NodeRow/EdgeRow vectors.
+/// C CST walker — converts tree-sitter C/C++ parse trees into NodeRow/EdgeRow vectors.
+///
+/// The C++ grammar is a superset of the C one, so both share this walker;
+/// C++-only constructs (classes, namespaces, templates) get `cpp_*` kinds.
 use serde_json::json;
 use uuid::Uuid;
 
@@ -16,6 +19,8 @@ struct CWalkCtx {
     nodes: Vec<NodeRow>,
     edges: Vec<EdgeRow>,
     path_ctx: PathContext,
+    /// `c` or `cpp`, recorded on every node.
+    language: String,
 }
 
 impl CWalkCtx {
@@ -29,12 +34,12 @@ impl CWalkCtx {
         span_start: Option<i32>,
         span_end: Option<i32>,
     ) -> String {
-        let id = Uuid::new_v4().to_string();
+        let id = Uuid::now_v7().to_string();
         self.nodes.push(NodeRow {
             id: id.clone(),
             instance_id: self.instance_id.clone(),
             kind: kind.to_string(),
-            language: Some("c".to_string()),
+            language: Some(self.language.clone()),
             content,
             parent_id: parent_id.map(|s| s.to_string()),
             position,
@@ -49,7 +54,7 @@ impl CWalkCtx {
     #[allow(dead_code)]
     fn new_edge(&mut self, source_id: &str, target_id: &str, relation: &str) {
         self.edges.push(EdgeRow {
-            id: Uuid::new_v4().to_string(),
+            id: Uuid::now_v7().to_string(),
             source_id: source_id.to_string(),
             target_id: target_id.to_string(),
             relation: relation.to_string(),
@@ -65,9 +70,8 @@ impl CWalkCtx {
 /// function_declarator → identifier`. This walks inward to find the name.
 fn unwrap_declarator_name(node: &tree_sitter::Node, source: &str) -> Option<String> {
     match node.kind() {
-        "identifier" | "field_identifier" | "type_identifier" => {
-            Some(node_text(node, source).to_string())
-        }
+        "identifier" | "field_identifier" | "type_identifier" | "qualified_identifier"
+        | "destructor_name" | "operator_name" => Some(node_text(node, source).to_string()),
         "pointer_declarator" | "array_declarator" | "function_declarator"
         | "parenthesized_declarator" | "attributed_declarator" => node
             .child_by_field_name("declarator")
@@ -94,13 +98,14 @@ fn has_storage_class(node: &tree_sitter::Node, source: &str, class: &str) -> boo
         .any(|c| c.kind() == "storage_class_specifier" && node_text(c, source) == class)
 }
 
-/// Walk a parsed C tree and produce NodeRow/EdgeRow vectors.
+/// Walk a parsed C or C++ tree and produce NodeRow/EdgeRow vectors.
 pub fn walk_c_file(
     tree: &tree_sitter::Tree,
     source: &str,
     file_node_id: &str,
     instance_id: &str,
     path_ctx: PathContext,
+    language: &str,
 ) -> (Vec<NodeRow>, Vec<EdgeRow>) {
     let mut ctx = CWalkCtx {
         source: source.to_string(),
@@ -108,6 +113,7 @@ pub fn walk_c_file(
         nodes: Vec::new(),
         edges: Vec::new(),
         path_ctx,
+        language: language.to_string(),
     };
 
     let root = tree.root_node();
@@ -147,6 +153,10 @@ fn walk_node(ctx: &mut CWalkCtx, node: &tree_sitter::Node, parent_id: &str, posi
         "struct_specifier" => walk_struct(ctx, node, parent_id, position, &source),
         "union_specifier" => walk_union(ctx, node, parent_id, position, &source),
         "enum_specifier" => walk_enum(ctx, node, parent_id, position, &source),
+        // C++
+        "class_specifier" => walk_class(ctx, node, parent_id, position, &source),
+        "namespace_definition" => walk_namespace(ctx, node, parent_id, position, &source),
+        "template_declaration" => walk_template(ctx, node, parent_id, position, &source),
         // Statements
         "compound_statement" => walk_block(ctx, node, parent_id, position),
         "if_statement" => walk_statement(ctx, node, parent_id, position, kinds::C_IF),
@@ -372,16 +382,135 @@ fn walk_struct(
         Some(span_end_line(node)),
     );
 
-    // Walk field_declaration_list → field_declaration nodes
+    // Walk field_declaration_list → fields (and C++ methods)
     if let Some(body) = node.child_by_field_name("body") {
-        let mut field_idx = 0;
-        let mut cursor = body.walk();
-        for child in body.named_children(&mut cursor) {
-            if child.kind() == "field_declaration" {
-                add_field(ctx, &child, &struct_id, field_idx, source);
-                field_idx += 1;
-            }
+        walk_member_list(ctx, &body, &struct_id, source);
+    }
+}
+
+/// Walk a C++ `class_specifier`: like a struct, but scoped in the path so
+/// methods land under the class name.
+fn walk_class(
+    ctx: &mut CWalkCtx,
+    node: &tree_sitter::Node,
+    parent_id: &str,
+    position: i32,
+    source: &str,
+) {
+    let name = node
+        .child_by_field_name("name")
+        .map(|n| node_text(&n, source).to_string());
+
+    if let Some(ref n) = name {
+        ctx.path_ctx.push(n);
+    }
+
+    let class_id = ctx.new_node(
+        kinds::CPP_CLASS,
+        name.clone(),
+        Some(parent_id),
+        position,
+        json!({"name": name, "source": node_text(node, source)}),
+        Some(span_start_line(node)),
+        Some(span_end_line(node)),
+    );
+
+    if let Some(body) = node.child_by_field_name("body") {
+        walk_member_list(ctx, &body, &class_id, source);
+    }
+
+    if name.is_some() {
+        ctx.path_ctx.pop();
+    }
+}
+
+/// Walk a `field_declaration_list`: fields, plus inline method definitions,
+/// method declarations and member templates in C++.
+fn walk_member_list(
+    ctx: &mut CWalkCtx,
+    body: &tree_sitter::Node,
+    parent_id: &str,
+    source: &str,
+) {
+    let mut member_idx = 0;
+    let mut cursor = body.walk();
+    for child in body.named_children(&mut cursor) {
+        match child.kind() {
+            "field_declaration" => add_field(ctx, &child, parent_id, member_idx, source),
+            "function_definition" => walk_function(ctx, &child, parent_id, member_idx, source),
+            "declaration" => walk_declaration(ctx, &child, parent_id, member_idx, source),
+            "template_declaration" => walk_template(ctx, &child, parent_id, member_idx, source),
+            _ => continue,
+        }
+        member_idx += 1;
+    }
+}
+
+fn walk_namespace(
+    ctx: &mut CWalkCtx,
+    node: &tree_sitter::Node,
+    parent_id: &str,
+    position: i32,
+    source: &str,
+) {
+    let name = node
+        .child_by_field_name("name")
+        .map(|n| node_text(&n, source).to_string());
+
+    if let Some(ref n) = name {
+        ctx.path_ctx.push(n);
+    }
+
+    let ns_id = ctx.new_node(
+        kinds::CPP_NAMESPACE,
+        name.clone(),
+        Some(parent_id),
+        position,
+        json!({"name": name, "source": node_text(node, source)}),
+        Some(span_start_line(node)),
+        Some(span_end_line(node)),
+    );
+
+    if let Some(body) = node.child_by_field_name("body") {
+        walk_children(ctx, &body, &ns_id);
+    }
+
+    if name.is_some() {
+        ctx.path_ctx.pop();
+    }
+}
+
+/// Walk a `template_declaration`: the template node wraps the templated
+/// declaration, which is walked as its child.
+fn walk_template(
+    ctx: &mut CWalkCtx,
+    node: &tree_sitter::Node,
+    parent_id: &str,
+    position: i32,
+    source: &str,
+) {
+    let params = node
+        .child_by_field_name("parameters")
+        .map(|p| node_text(&p, source).to_string());
+
+    let template_id = ctx.new_node(
+        kinds::CPP_TEMPLATE,
+        params.clone(),
+        Some(parent_id),
+        position,
+        json!({"parameters": params, "source": node_text(node, source)}),
+        Some(span_start_line(node)),
+        Some(span_end_line(node)),
+    );
+
+    let mut idx = 0;
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == "template_parameter_list" || child.kind() == "comment" {
+            continue;
         }
+        walk_node(ctx, &child, &template_id, idx);
+        idx += 1;
     }
 }
 
@@ -0,0 +1,386 @@
+/// Call graph resolution for parsed Rust.
+///
+/// The AST walker keeps call expressions as `expr_call` nodes (the callee
+/// is their position-0 `expr_path` child) and `expr_method_call` nodes
+/// (content is the method name). This pass matches them to `fn`
+/// definitions across all Rust files and records a `calls` edge from the
+/// enclosing function to the callee, so callers can be found by graph
+/// rather than by name.
+///
+/// Matching is by name, narrowed by path: a qualifier names the owning
+/// type (`Foo::new`, `Self::helper`) or a module or file (`util::parse`).
+/// Unqualified calls go to a free function in the same file, else to the
+/// only free function with that name. Method calls go to a method on the
+/// caller's own type, else to the only method with that name. Anything
+/// left ambiguous, or qualified by something unknown (`Vec::new`), stays
+/// unresolved rather than guessed.
+use std::collections::HashMap;
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+/// Edges this pass owns, so re-running it replaces them.
+const RESOLVER: &str = "resolve_calls";
+
+/// A function definition.
+#[derive(Debug, Clone)]
+pub struct Def {
+    pub id: String,
+    pub name: String,
+    pub file_id: String,
+    /// Base name of the impl'd type or trait, for methods.
+    pub owner: Option<String>,
+    /// Enclosing module labels and the file stem.
+    pub modules: Vec<String>,
+}
+
+/// A call site.
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub site_id: String,
+    /// Enclosing function.
+    pub caller_id: String,
+    pub file_id: String,
+    /// Owner of the enclosing function, for `Self::` and method calls.
+    pub caller_owner: Option<String>,
+    /// Callee path segments; a single segment for method calls.
+    pub segments: Vec<String>,
+    pub method: bool,
+}
+
+/// How a call was matched, and how sure the match is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolution {
+    Qualified,
+    SameFile,
+    Unique,
+    SelfMethod,
+    Method,
+}
+
+impl Resolution {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Resolution::Qualified => "qualified",
+            Resolution::SameFile => "same_file",
+            Resolution::Unique => "unique",
+            Resolution::SelfMethod => "self_method",
+            Resolution::Method => "method",
+        }
+    }
+
+    pub fn confidence(self) -> f64 {
+        match self {
+            Resolution::Qualified | Resolution::SelfMethod => 1.0,
+            Resolution::SameFile => 0.9,
+            Resolution::Unique => 0.8,
+            Resolution::Method => 0.6,
+        }
+    }
+}
+
+/// Split a token-stream path (`crate :: util :: parse :: < T >`) into its
+/// named segments, dropping generic arguments.
+pub fn path_segments(path: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    for c in path.chars() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            _ if depth > 0 || c.is_whitespace() => {}
+            ':' => {
+                if !current.is_empty() {
+                    segments.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+    segments
+}
+
+/// Base name of a type as written (`& 'a mut Foo < T >` → `Foo`).
+pub fn type_base(ty: &str) -> String {
+    let head = ty.split('<').next().unwrap_or(ty);
+    head.split(|c: char| c.is_whitespace() || c == ':' || c == '&')
+        .rfind(|s| !s.is_empty())
+        .unwrap_or(ty.trim())
+        .to_string()
+}
+
+/// Pick the definition a call refers to, if exactly one fits.
+pub fn resolve<'a>(call: &Call, defs: &[&'a Def]) -> Option<(&'a Def, Resolution)> {
+    let only = |found: Vec<&'a Def>| if found.len() == 1 { Some(found[0]) } else { None };
+    let name = call.segments.last()?;
+    let named: Vec<&Def> = defs.iter().copied().filter(|d| &d.name == name).collect();
+
+    if call.method {
+        let own: Vec<&Def> = named
+            .iter()
+            .copied()
+            .filter(|d| d.owner.is_some() && d.owner == call.caller_owner)
+            .collect();
+        if let Some(def) = only(own) {
+            return Some((def, Resolution::SelfMethod));
+        }
+        let methods = named.into_iter().filter(|d| d.owner.is_some()).collect();
+        return only(methods).map(|d| (d, Resolution::Method));
+    }
+
+    // Leading crate/self/super only anchor the path
+    let qualifiers: Vec<&String> = call.segments[..call.segments.len() - 1]
+        .iter()
+        .filter(|s| !matches!(s.as_str(), "crate" | "self" | "super"))
+        .collect();
+    if let Some(q) = qualifiers.last() {
+        let owner = if q.as_str() == "Self" {
+            call.caller_owner.clone()
+        } else {
+            Some(q.to_string())
+        };
+        let by_owner: Vec<&Def> = named
+            .iter()
+            .copied()
+            .filter(|d| d.owner.is_some() && d.owner == owner)
+            .collect();
+        if !by_owner.is_empty() {
+            return only(by_owner).map(|d| (d, Resolution::Qualified));
+        }
+        let by_module = named
+            .into_iter()
+            .filter(|d| d.owner.is_none() && d.modules.iter().any(|m| m == *q))
+            .collect();
+        return only(by_module).map(|d| (d, Resolution::Qualified));
+    }
+
+    let free: Vec<&Def> = named.into_iter().filter(|d| d.owner.is_none()).collect();
+    let local = free.iter().copied().filter(|d| d.file_id == call.file_id).collect();
+    if let Some(def) = only(local) {
+        return Some((def, Resolution::SameFile));
+    }
+    only(free).map(|d| (d, Resolution::Unique))
+}
+
+/// Functions and call sites in every Rust file.
+fn load() -> (Vec<Def>, Vec<Call>) {
+    let mut defs = Vec::new();
+    let mut calls = Vec::new();
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                "WITH RECURSIVE sub AS (
+                    SELECT id, id AS file_id, NULL::uuid AS fn_id, NULL::text AS owner,
+                           kind, content, path,
+                           regexp_replace(content, '^.*/|\\.[^.]*$', '', 'g') AS stem
+                    FROM kerai.nodes WHERE kind = 'file' AND language = 'rust'
+                    UNION ALL
+                    SELECT n.id, sub.file_id,
+                           CASE WHEN n.kind = 'fn' THEN n.id ELSE sub.fn_id END,
+                           CASE WHEN n.kind = 'impl' THEN n.metadata->>'self_ty'
+                                WHEN n.kind = 'trait' THEN n.content
+                                ELSE sub.owner END,
+                           n.kind, n.content, n.path, sub.stem
+                    FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+                )
+                SELECT 'def', id::text, content, file_id::text, NULL, owner,
+                       COALESCE(path::text, '') || '.' || stem
+                FROM sub WHERE kind = 'fn'
+                UNION ALL
+                SELECT 'call', c.id::text, f.content, c.file_id::text, c.fn_id::text, c.owner, NULL
+                FROM sub c
+                JOIN kerai.nodes f ON f.parent_id = c.id AND f.position = 0 AND f.kind = 'expr_path'
+                WHERE c.kind = 'expr_call' AND c.fn_id IS NOT NULL
+                UNION ALL
+                SELECT 'method', id::text, content, file_id::text, fn_id::text, owner, NULL
+                FROM sub WHERE kind = 'expr_method_call' AND fn_id IS NOT NULL",
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            let role = row.get::<String>(1).unwrap().unwrap_or_default();
+            let id = row.get::<String>(2).unwrap().unwrap_or_default();
+            let text = row.get::<String>(3).unwrap().unwrap_or_default();
+            let file_id = row.get::<String>(4).unwrap().unwrap_or_default();
+            let owner = row.get::<String>(6).unwrap().map(|o| type_base(&o));
+            if role == "def" {
+                let modules = row.get::<String>(7).unwrap().unwrap_or_default();
+                defs.push(Def {
+                    id,
+                    name: text,
+                    file_id,
+                    owner,
+                    modules: modules.split('.').filter(|m| !m.is_empty()).map(str::to_string).collect(),
+                });
+            } else {
+                let method = role == "method";
+                calls.push(Call {
+                    site_id: id,
+                    caller_id: row.get::<String>(5).unwrap().unwrap_or_default(),
+                    file_id,
+                    caller_owner: owner,
+                    segments: if method { vec![text] } else { path_segments(&text) },
+                    method,
+                });
+            }
+        }
+    });
+    (defs, calls)
+}
+
+/// Resolve every call site and replace the resolver's `calls` edges.
+/// Returns `{call_sites, resolved, unresolved, edges}`.
+pub(crate) fn resolve_all() -> Value {
+    let (defs, calls) = load();
+    let mut by_name: HashMap<&str, Vec<&Def>> = HashMap::new();
+    for def in &defs {
+        by_name.entry(def.name.as_str()).or_default().push(def);
+    }
+
+    // (caller, callee) → (sites, best resolution)
+    let mut edges: HashMap<(String, String), (Vec<String>, Resolution)> = HashMap::new();
+    let mut resolved = 0;
+    for call in &calls {
+        let Some(name) = call.segments.last() else { continue };
+        let Some(candidates) = by_name.get(name.as_str()) else { continue };
+        let Some((def, how)) = resolve(call, candidates) else { continue };
+        resolved += 1;
+        let entry = edges
+            .entry((call.caller_id.clone(), def.id.clone()))
+            .or_insert_with(|| (Vec::new(), how));
+        entry.0.push(call.site_id.clone());
+        if how.confidence() > entry.1.confidence() {
+            entry.1 = how;
+        }
+    }
+
+    Spi::run(&format!(
+        "DELETE FROM kerai.edges WHERE relation = 'calls' AND metadata->>'resolver' = '{RESOLVER}'"
+    ))
+    .unwrap();
+    let rows: Vec<String> = edges
+        .iter()
+        .map(|((caller, callee), (sites, how))| {
+            let meta = json!({
+                "resolver": RESOLVER,
+                "resolution": how.as_str(),
+                "confidence": how.confidence(),
+                "sites": sites,
+            });
+            format!(
+                "('{caller}'::uuid, '{callee}'::uuid, 'calls', '{}'::jsonb)",
+                meta.to_string().replace('\'', "''"),
+            )
+        })
+        .collect();
+    for chunk in rows.chunks(500) {
+        // Hand-made calls edges between the same pair are left alone
+        Spi::run(&format!(
+            "INSERT INTO kerai.edges (source_id, target_id, relation, metadata) VALUES {}
+             ON CONFLICT (source_id, target_id, relation) DO NOTHING",
+            chunk.join(", "),
+        ))
+        .unwrap();
+    }
+
+    json!({
+        "call_sites": calls.len(),
+        "resolved": resolved,
+        "unresolved": calls.len() - resolved,
+        "edges": edges.len(),
+    })
+}
+
+/// Resolve Rust call expressions to function definitions across all parsed
+/// files, replacing the `calls` edges from earlier runs. `parse_crate`
+/// runs this itself; call it after parsing files one at a time.
+#[pg_extern]
+fn resolve_calls() -> pgrx::JsonB {
+    pgrx::JsonB(resolve_all())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn def(id: &str, name: &str, file: &str, owner: Option<&str>, modules: &[&str]) -> Def {
+        Def {
+            id: id.into(),
+            name: name.into(),
+            file_id: file.into(),
+            owner: owner.map(str::to_string),
+            modules: modules.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    fn call(path: &str, file: &str, owner: Option<&str>) -> Call {
+        Call {
+            site_id: "site".into(),
+            caller_id: "caller".into(),
+            file_id: file.into(),
+            caller_owner: owner.map(str::to_string),
+            segments: path_segments(path),
+            method: false,
+        }
+    }
+
+    fn method(name: &str, owner: Option<&str>) -> Call {
+        Call {
+            segments: vec![name.into()],
+            method: true,
+            ..call("", "a", owner)
+        }
+    }
+
+    #[test]
+    fn path_segments_drop_generics_and_spacing() {
+        assert_eq!(path_segments("crate :: util :: parse"), vec!["crate", "util", "parse"]);
+        assert_eq!(path_segments("Vec :: < u8 > :: new"), vec!["Vec", "new"]);
+        assert_eq!(path_segments("collect :: < Vec < _ > >"), vec!["collect"]);
+        assert_eq!(type_base("Foo < T >"), "Foo");
+        assert_eq!(type_base("& 'a mut crate :: model :: Foo"), "Foo");
+        assert_eq!(type_base("u8"), "u8");
+    }
+
+    #[test]
+    fn resolves_qualified_and_unqualified_calls() {
+        let defs = [
+            def("new_foo", "new", "a", Some("Foo"), &["krate", "a"]),
+            def("new_bar", "new", "b", Some("Bar"), &["krate", "b"]),
+            def("parse_util", "parse", "u", None, &["krate", "util"]),
+            def("parse_local", "parse", "a", None, &["krate", "a"]),
+            def("helper", "helper", "a", Some("Foo"), &["krate", "a"]),
+        ];
+        let refs: Vec<&Def> = defs.iter().collect();
+        let pick = |c: Call| resolve(&c, &refs).map(|(d, how)| (d.id.as_str(), how));
+
+        assert_eq!(pick(call("Foo :: new", "b", None)), Some(("new_foo", Resolution::Qualified)));
+        assert_eq!(pick(call("Self :: helper", "a", Some("Foo"))), Some(("helper", Resolution::Qualified)));
+        assert_eq!(pick(call("crate :: util :: parse", "a", None)), Some(("parse_util", Resolution::Qualified)));
+        assert_eq!(pick(call("parse", "a", None)), Some(("parse_local", Resolution::SameFile)));
+        assert_eq!(pick(call("parse", "z", None)), None, "two free fns and neither local");
+        assert_eq!(pick(call("Vec :: new", "a", None)), None, "unknown qualifier");
+        assert_eq!(pick(call("new", "a", None)), None, "methods need a qualifier");
+    }
+
+    #[test]
+    fn resolves_method_calls_by_owner_then_uniqueness() {
+        let defs = [
+            def("len_foo", "len", "a", Some("Foo"), &[]),
+            def("len_bar", "len", "b", Some("Bar"), &[]),
+            def("push", "push", "a", Some("Foo"), &[]),
+            def("free_len", "len", "a", None, &[]),
+        ];
+        let refs: Vec<&Def> = defs.iter().collect();
+        let pick = |c: Call| resolve(&c, &refs).map(|(d, how)| (d.id.as_str(), how));
+
+        assert_eq!(pick(method("len", Some("Bar"))), Some(("len_bar", Resolution::SelfMethod)));
+        assert_eq!(pick(method("len", None)), None, "ambiguous across types");
+        assert_eq!(pick(method("push", None)), Some(("push", Resolution::Method)));
+    }
+}
@@ -46,7 +46,7 @@ pub fn parse_cargo_toml(
     let path_ctx = PathContext::with_root(&crate_name);
 
     // Crate root node
-    let crate_id = Uuid::new_v4().to_string();
+    let crate_id = Uuid::now_v7().to_string();
     let mut crate_meta = serde_json::Map::new();
     crate_meta.insert("version".into(), json!(version));
     crate_meta.insert("edition".into(), json!(edition));
@@ -69,7 +69,7 @@ pub fn parse_cargo_toml(
     });
 
     // Cargo.toml metadata node
-    let cargo_node_id = Uuid::new_v4().to_string();
+    let cargo_node_id = Uuid::now_v7().to_string();
     nodes.push(NodeRow {
         id: cargo_node_id.clone(),
         instance_id: instance_id.to_string(),
@@ -88,7 +88,7 @@ pub fn parse_cargo_toml(
     let mut dep_position = 0;
     if let Some(deps) = parsed.get("dependencies").and_then(|d| d.as_table()) {
         for (dep_name, dep_value) in deps {
-            let dep_id = Uuid::new_v4().to_string();
+            let dep_id = Uuid::now_v7().to_string();
             let mut dep_meta = serde_json::Map::new();
 
             match dep_value {
@@ -138,7 +138,7 @@ pub fn parse_cargo_toml(
     // Dev dependencies
     if let Some(deps) = parsed.get("dev-dependencies").and_then(|d| d.as_table()) {
         for (dep_name, dep_value) in deps {
-            let dep_id = Uuid::new_v4().to_string();
+            let dep_id = Uuid::now_v7().to_string();
             let mut dep_meta = serde_json::Map::new();
 
             match dep_value {
@@ -1,6 +1,7 @@
 /// Pass 1 — Raw Ingest: create TEXT tables and load CSV data.
 use pgrx::prelude::*;
-use crate::sql::{sql_escape, sql_text};
+use crate::sql::sql_ident;
+use serde_json::Value;
 
 const BATCH_SIZE: usize = 500;
 
@@ -93,7 +94,7 @@ pub fn create_raw_table(schema: &str, table_name: &str, columns: &[String]) -> S
 
     let col_defs: Vec<String> = columns
         .iter()
-        .map(|c| format!("\"{}\" TEXT", sql_escape(c)))
+        .map(|c| format!("{} TEXT", sql_ident(c)))
         .collect();
 
     Spi::run(&format!(
@@ -107,6 +108,8 @@ pub fn create_raw_table(schema: &str, table_name: &str, columns: &[String]) -> S
 }
 
 /// Load CSV data into a raw TEXT table using batch INSERTs.
+/// Each batch travels as one jsonb parameter of row arrays, so cell text
+/// is never spliced into the SQL.
 /// Returns the number of rows inserted.
 pub fn load_raw_data(
     qualified_table: &str,
@@ -120,12 +123,16 @@ pub fn load_raw_data(
 
     let col_list: String = columns
         .iter()
-        .map(|c| format!("\"{}\"", sql_escape(c)))
+        .map(|c| sql_ident(c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let select_list: String = (0..columns.len())
+        .map(|i| format!("r->>{}", i))
         .collect::<Vec<_>>()
         .join(", ");
 
     let mut total_rows: i64 = 0;
-    let mut batch_values: Vec<String> = Vec::with_capacity(BATCH_SIZE);
+    let mut batch_rows: Vec<Value> = Vec::with_capacity(BATCH_SIZE);
 
     for result in reader.records() {
         let record = match result {
@@ -136,40 +143,39 @@ pub fn load_raw_data(
             }
         };
 
-        let row_values: Vec<String> = (0..columns.len())
-            .map(|i| {
-                match record.get(i) {
-                    Some(val) if !val.is_empty() => sql_text(val),
-                    _ => "NULL".to_string(),
-                }
+        let row_values: Vec<Value> = (0..columns.len())
+            .map(|i| match record.get(i) {
+                Some(val) if !val.is_empty() => Value::String(val.to_string()),
+                _ => Value::Null,
             })
             .collect();
 
-        batch_values.push(format!("({})", row_values.join(", ")));
+        batch_rows.push(Value::Array(row_values));
         total_rows += 1;
 
-        if batch_values.len() >= BATCH_SIZE {
-            flush_batch(qualified_table, &col_list, &batch_values);
-            batch_values.clear();
+        if batch_rows.len() >= BATCH_SIZE {
+            flush_batch(qualified_table, &col_list, &select_list, &mut batch_rows);
         }
     }
 
     // Flush remaining
-    if !batch_values.is_empty() {
-        flush_batch(qualified_table, &col_list, &batch_values);
+    if !batch_rows.is_empty() {
+        flush_batch(qualified_table, &col_list, &select_list, &mut batch_rows);
     }
 
     total_rows
 }
 
-fn flush_batch(qualified_table: &str, col_list: &str, values: &[String]) {
+fn flush_batch(qualified_table: &str, col_list: &str, select_list: &str, rows: &mut Vec<Value>) {
     let sql = format!(
-        "INSERT INTO {} ({}) VALUES {}",
-        qualified_table,
-        col_list,
-        values.join(", "),
+        "INSERT INTO {} ({}) SELECT {} FROM jsonb_array_elements($1) AS r",
+        qualified_table, col_list, select_list,
     );
-    Spi::run(&sql).expect("Failed to insert batch");
+    Spi::run_with_args(
+        &sql,
+        &[pgrx::JsonB(Value::Array(std::mem::take(rows))).into()],
+    )
+    .expect("Failed to insert batch");
 }
 
 #[cfg(test)]
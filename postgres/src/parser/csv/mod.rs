@@ -16,19 +16,17 @@ pub mod ingest;
 mod promote;
 mod nodes;
 
-use crate::sql::sql_escape;
+use crate::sql::{sql_escape, sql_ident};
 
 /// Delete all CSV-related kerai nodes for a project (idempotent cleanup).
 fn delete_csv_nodes(instance_id: &str, project_name: &str) {
     // Find the dataset node
-    let dataset_id = Spi::get_one::<String>(&format!(
+    let dataset_id = Spi::get_one_with_args::<String>(
         "SELECT id::text FROM kerai.nodes
-         WHERE instance_id = '{}'::uuid
-         AND kind = '{}' AND content = '{}'",
-        sql_escape(instance_id),
-        kinds::CSV_DATASET,
-        sql_escape(project_name),
-    ))
+         WHERE instance_id = $1::uuid
+         AND kind = $2 AND content = $3",
+        &[instance_id.into(), kinds::CSV_DATASET.into(), project_name.into()],
+    )
     .unwrap_or(None);
 
     if let Some(did) = dataset_id {
@@ -137,7 +135,7 @@ fn parse_csv_file(
     let total_nodes = node_count + 1; // +1 for dataset node
 
     // Auto-mint reward
-    mint_csv_reward(project_name, total_nodes, edge_count);
+    mint_csv_reward(project_name, &project_id, total_nodes, edge_count);
 
     let elapsed = start.elapsed();
     pgrx::JsonB(json!({
@@ -266,7 +264,7 @@ fn parse_csv_dir(
     let total_nodes = node_count + 1; // +1 for dataset node
 
     // Auto-mint reward
-    mint_csv_reward(project_name, total_nodes, edge_count);
+    mint_csv_reward(project_name, &project_id, total_nodes, edge_count);
 
     let elapsed = start.elapsed();
     pgrx::JsonB(json!({
@@ -330,24 +328,20 @@ fn process_single_file(
 /// Ensure the target schema exists.
 fn ensure_schema(schema_name: &str) {
     Spi::run(&format!(
-        "CREATE SCHEMA IF NOT EXISTS \"{}\"",
-        sql_escape(schema_name),
+        "CREATE SCHEMA IF NOT EXISTS {}",
+        sql_ident(schema_name),
     ))
     .expect("Failed to create schema");
 }
 
 /// Auto-mint reward for CSV parsing.
-fn mint_csv_reward(project_name: &str, node_count: usize, edge_count: usize) {
+fn mint_csv_reward(project_name: &str, project_id: &str, node_count: usize, edge_count: usize) {
     if node_count > 0 {
         let details = json!({
             "project": project_name,
             "nodes": node_count,
             "edges": edge_count,
         });
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_csv', '{}'::jsonb)",
-            details_str,
-        ));
+        crate::currency::reward("parse_csv", "csv_project", Some(project_id), details);
     }
 }
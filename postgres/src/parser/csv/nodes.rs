@@ -26,7 +26,7 @@ pub fn create_dataset_node(
     source_dir: Option<&str>,
     files: &[FileInfo],
 ) -> String {
-    let dataset_id = Uuid::new_v4().to_string();
+    let dataset_id = Uuid::now_v7().to_string();
     let path_ctx = PathContext::with_root(project_name);
 
     let total_rows: i64 = files.iter().map(|f| f.row_count).sum();
@@ -80,7 +80,7 @@ pub fn create_table_and_column_nodes(
         let mut path_ctx = PathContext::with_root(project_name);
         path_ctx.push(&file.table_name);
 
-        let table_node_id = Uuid::new_v4().to_string();
+        let table_node_id = Uuid::now_v7().to_string();
         let qualified_name = format!("{}.{}", file.schema, file.table_name);
 
         let nil_total: i64 = file.column_stats.iter().map(|c| c.nil_count).sum();
@@ -112,7 +112,7 @@ pub fn create_table_and_column_nodes(
 
         // Create column nodes
         for col_stat in &file.column_stats {
-            let col_node_id = Uuid::new_v4().to_string();
+            let col_node_id = Uuid::now_v7().to_string();
             let col_path = path_ctx.child_path(&col_stat.name);
 
             all_nodes.push(NodeRow {
@@ -149,7 +149,7 @@ pub fn create_table_and_column_nodes(
                 let (ref target_table, ref target_id) = locations[j];
 
                 all_edges.push(EdgeRow {
-                    id: Uuid::new_v4().to_string(),
+                    id: Uuid::now_v7().to_string(),
                     source_id: source_id.clone(),
                     target_id: target_id.clone(),
                     relation: "shared_column".to_string(),
@@ -1,12 +1,11 @@
 /// Pass 0 — Registry: persistent infrastructure tables for CSV projects.
 use pgrx::prelude::*;
-use crate::sql::{sql_escape, sql_text};
 
 /// Ensure the registry tables exist (idempotent).
 pub fn ensure_registry_tables() {
     Spi::run(
         "CREATE TABLE IF NOT EXISTS kerai.csv_projects (
-            id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
             name        TEXT NOT NULL UNIQUE,
             schema_name TEXT NOT NULL,
             source_dir  TEXT,
@@ -16,7 +15,7 @@ pub fn ensure_registry_tables() {
 
     Spi::run(
         "CREATE TABLE IF NOT EXISTS kerai.csv_files (
-            id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
             project_id  UUID NOT NULL REFERENCES kerai.csv_projects(id),
             filename    TEXT NOT NULL,
             table_name  TEXT NOT NULL,
@@ -30,20 +29,13 @@ pub fn ensure_registry_tables() {
 
 /// Register or get a project, returning its UUID.
 pub fn register_project(name: &str, schema_name: &str, source_dir: Option<&str>) -> String {
-    let src = match source_dir {
-        Some(d) => sql_text(d),
-        None => "NULL".to_string(),
-    };
-
-    Spi::get_one::<String>(&format!(
+    Spi::get_one_with_args::<String>(
         "INSERT INTO kerai.csv_projects (name, schema_name, source_dir)
-         VALUES ({}, {}, {})
+         VALUES ($1, $2, $3)
          ON CONFLICT (name) DO UPDATE SET source_dir = EXCLUDED.source_dir
          RETURNING id::text",
-        sql_text(name),
-        sql_text(schema_name),
-        src,
-    ))
+        &[name.into(), schema_name.into(), source_dir.into()],
+    )
     .expect("Failed to register project")
     .expect("No project ID returned")
 }
@@ -55,38 +47,29 @@ pub fn register_file(
     table_name: &str,
     headers: &[String],
 ) -> String {
-    // Build a Postgres text array literal
-    let headers_arr = format!(
-        "ARRAY[{}]::text[]",
-        headers
-            .iter()
-            .map(|h| sql_text(h))
-            .collect::<Vec<_>>()
-            .join(", ")
-    );
-
-    Spi::get_one::<String>(&format!(
+    Spi::get_one_with_args::<String>(
         "INSERT INTO kerai.csv_files (project_id, filename, table_name, headers)
-         VALUES ('{}'::uuid, {}, {}, {})
+         VALUES ($1::uuid, $2, $3, $4)
          ON CONFLICT (project_id, filename) DO UPDATE
            SET table_name = EXCLUDED.table_name,
                headers = EXCLUDED.headers
          RETURNING id::text",
-        sql_escape(project_id),
-        sql_text(filename),
-        sql_text(table_name),
-        headers_arr,
-    ))
+        &[
+            project_id.into(),
+            filename.into(),
+            table_name.into(),
+            headers.to_vec().into(),
+        ],
+    )
     .expect("Failed to register file")
     .expect("No file ID returned")
 }
 
 /// Update the row count for a registered file.
 pub fn update_row_count(file_id: &str, row_count: i64) {
-    Spi::run(&format!(
-        "UPDATE kerai.csv_files SET row_count = {} WHERE id = '{}'::uuid",
-        row_count,
-        sql_escape(file_id),
-    ))
+    Spi::run_with_args(
+        "UPDATE kerai.csv_files SET row_count = $1 WHERE id = $2::uuid",
+        &[row_count.into(), file_id.into()],
+    )
     .expect("Failed to update row_count");
 }
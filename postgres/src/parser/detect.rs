@@ -0,0 +1,275 @@
+/// Language detection — which parser a file goes to.
+///
+/// The extension decides when it is one kerai parses. Otherwise a few
+/// well-known file names, then a shebang line, then the text itself are
+/// looked at, strongest signal first.
+use pgrx::prelude::*;
+
+use super::registry::REGISTRY;
+
+/// Parser for a file, by the extensions registered with `REGISTRY`.
+pub(crate) fn language_of(filename: &str) -> Option<&'static str> {
+    REGISTRY.for_file(filename).map(|p| p.name())
+}
+
+/// Parser for a file without a known extension, by its name.
+fn language_of_name(filename: &str) -> Option<&'static str> {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or(filename);
+    match name {
+        "Cargo.lock" | "Pipfile" | "Gopkg.lock" | "poetry.lock" => Some("toml"),
+        ".clang-format" | ".clang-tidy" => Some("yaml"),
+        _ => None,
+    }
+}
+
+/// Parser named by a `#!` line, if the source starts with one.
+fn language_of_shebang(source: &str) -> Option<Option<&'static str>> {
+    let line = source.lines().next()?;
+    if !line.starts_with("#!") || line.starts_with("#![") {
+        return None;
+    }
+    Some(if line.contains("rust-script") || line.contains("cargo") {
+        Some("rust")
+    } else if line.contains("gorun") || line.contains("go run") {
+        Some("go")
+    } else {
+        None
+    })
+}
+
+/// Starts of top-level Rust items and inner attributes.
+const RUST_STARTS: &[&str] = &[
+    "fn ",
+    "pub ",
+    "pub(",
+    "use ",
+    "mod ",
+    "impl ",
+    "impl<",
+    "struct ",
+    "enum ",
+    "trait ",
+    "#[",
+    "#![",
+    "//!",
+    "extern crate ",
+    "macro_rules!",
+    "async fn ",
+    "unsafe ",
+    "const fn ",
+];
+
+/// Parser for `source`, guessed from its text.
+fn language_of_content(source: &str) -> Option<&'static str> {
+    let lines: Vec<&str> = source
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    // First line that is not a `#` comment, as TOML and YAML write them
+    let first = lines
+        .iter()
+        .copied()
+        .find(|l| !(*l == "#" || l.starts_with("# ")));
+
+    if lines
+        .iter()
+        .any(|l| l.starts_with("```") || l.starts_with("~~~"))
+    {
+        return Some("markdown");
+    }
+    let latex = ["\\documentclass", "\\begin{", "\\section", "\\usepackage"];
+    if lines.iter().any(|l| latex.iter().any(|p| l.starts_with(p))) {
+        return Some("latex");
+    }
+    if first.is_some_and(is_bibtex_entry) {
+        return Some("bibtex");
+    }
+    if lines.iter().any(|l| is_go_package(l)) {
+        return Some("go");
+    }
+    let preprocessor = ["#include", "#define ", "#ifndef ", "#pragma "];
+    if lines
+        .iter()
+        .any(|l| preprocessor.iter().any(|p| l.starts_with(p)))
+    {
+        let cpp = lines.iter().any(|l| {
+            l.starts_with("namespace ")
+                || l.starts_with("template")
+                || l.starts_with("class ")
+                || l.contains("std::")
+                || (l.starts_with("#include <") && !l.ends_with(".h>"))
+        });
+        return Some(if cpp { "cpp" } else { "c" });
+    }
+    if lines
+        .iter()
+        .any(|l| RUST_STARTS.iter().any(|p| l.starts_with(p)))
+    {
+        return Some("rust");
+    }
+
+    let first = first?;
+    if first == "---" {
+        // Front matter followed by text is a markdown document
+        let body = source
+            .lines()
+            .skip(1)
+            .skip_while(|l| l.trim() != "---")
+            .skip(1)
+            .any(|l| !l.trim().is_empty());
+        return Some(if body { "markdown" } else { "yaml" });
+    }
+    if is_toml_line(first) {
+        return Some("toml");
+    }
+    if is_yaml_key(first) {
+        return Some("yaml");
+    }
+    if lines.iter().any(|l| is_heading(l)) || source.contains("](") {
+        return Some("markdown");
+    }
+    if first.starts_with("- ") {
+        return Some("yaml");
+    }
+    None
+}
+
+/// `@article{key,` and the like.
+fn is_bibtex_entry(line: &str) -> bool {
+    let Some(rest) = line.strip_prefix('@') else {
+        return false;
+    };
+    let kind_len = rest.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+    kind_len > 0
+        && matches!(
+            rest[kind_len..].trim_start().chars().next(),
+            Some('{' | '(')
+        )
+}
+
+/// `package main`, without the `;` of Java's.
+fn is_go_package(line: &str) -> bool {
+    let Some(name) = line.strip_prefix("package ") else {
+        return false;
+    };
+    let name = name.split("//").next().unwrap_or("").trim();
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '"' | '\''))
+}
+
+/// `[table]`, `[[array]]` or `key = value`.
+fn is_toml_line(line: &str) -> bool {
+    if line.starts_with('[') && line.ends_with(']') {
+        let inner = line.trim_matches(|c| c == '[' || c == ']');
+        return is_key(&inner.replace(' ', ""));
+    }
+    match line.split_once('=') {
+        Some((key, value)) => is_key(key.trim()) && !value.trim().is_empty(),
+        None => false,
+    }
+}
+
+/// `key:` or `key: value`.
+fn is_yaml_key(line: &str) -> bool {
+    match line.split_once(':') {
+        Some((key, rest)) => is_key(key) && (rest.is_empty() || rest.starts_with(' ')),
+        None => false,
+    }
+}
+
+/// `#` to `######` followed by a space.
+fn is_heading(line: &str) -> bool {
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    (1..=6).contains(&hashes) && line[hashes..].starts_with(' ')
+}
+
+/// Language of a file from its name, and from its text when the name does
+/// not tell; `None` when neither does.
+pub(crate) fn detect(filename: &str, source: &str) -> Option<&'static str> {
+    language_of(filename)
+        .or_else(|| language_of_name(filename))
+        .or_else(|| language_of_shebang(source).unwrap_or_else(|| language_of_content(source)))
+}
+
+/// Language kerai would parse a file as: `rust`, `go`, `c`, `cpp`,
+/// `markdown`, `latex`, `bibtex`, `toml` or `yaml`, or NULL when it cannot
+/// tell (`parse_source` then treats it as Rust).
+#[pg_extern]
+fn detect_language(filename: &str, source: default!(Option<&str>, "NULL")) -> Option<String> {
+    detect(filename, source.unwrap_or("")).map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_of_maps_extensions() {
+        assert_eq!(language_of("src/lib.rs"), Some("rust"));
+        assert_eq!(language_of("include/x.HPP"), Some("cpp"));
+        assert_eq!(language_of(".github/ci.yml"), Some("yaml"));
+        assert_eq!(language_of("Makefile"), None);
+        assert_eq!(language_of("logo.png"), None);
+    }
+
+    #[test]
+    fn names_and_shebangs_decide_without_extension() {
+        assert_eq!(detect("app/Cargo.lock", ""), Some("toml"));
+        assert_eq!(detect(".clang-format", "x"), Some("yaml"));
+        assert_eq!(
+            detect("build", "#!/usr/bin/env rust-script\nfn main() {}\n"),
+            Some("rust")
+        );
+        assert_eq!(
+            detect("tool", "#!/usr/bin/env gorun\npackage main\n"),
+            Some("go")
+        );
+        assert_eq!(detect("run", "#!/bin/sh\nfn x\n"), None);
+        // An inner attribute is not a shebang
+        assert_eq!(detect("lib", "#![no_std]\n"), Some("rust"));
+    }
+
+    #[test]
+    fn content_heuristics() {
+        let cases = [
+            ("fn quoted() -> &'static str { \"x\" }", Some("rust")),
+            (
+                "// Copyright\n\npackage main\n\nfunc main() {}\n",
+                Some("go"),
+            ),
+            (
+                "#include <stdio.h>\nint main(void) { return 0; }\n",
+                Some("c"),
+            ),
+            ("#include <vector>\nint main() {}\n", Some("cpp")),
+            (
+                "\\documentclass{article}\n\\begin{document}\n",
+                Some("latex"),
+            ),
+            (
+                "@article{knuth84,\n  title = {Literate},\n}\n",
+                Some("bibtex"),
+            ),
+            ("# settings\n[package]\nname = \"x\"\n", Some("toml")),
+            ("version = \"1\"\n", Some("toml")),
+            ("# comment\nname: ci\non:\n  push:\n", Some("yaml")),
+            ("---\nkey: value\n", Some("yaml")),
+            ("---\ntitle: Note\n---\n\nBody text.\n", Some("markdown")),
+            ("# Title\n\nSome text: here.\n", Some("markdown")),
+            ("Intro\n\n```sh\nls\n```\n", Some("markdown")),
+            ("- one\n- two\n", Some("yaml")),
+            ("just some words", None),
+            ("", None),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(detect("untitled", source), expected, "{:?}", source);
+        }
+    }
+}
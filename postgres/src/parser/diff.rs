@@ -0,0 +1,440 @@
+/// Structural diff of two file trees — inserted, deleted, moved and modified nodes.
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::inserter::{child_index, subtree_hashes, TreeNode, LOCATION_KEYS};
+
+/// Smallest subtree considered for cross-parent move detection. Single
+/// leaves (an identifier, a `1`) recur everywhere and would pair up by
+/// accident.
+const MOVE_MIN_NODES: usize = 2;
+
+/// Diff of tree `a` (before) against tree `b` (after), by node index.
+#[derive(Debug, Default)]
+pub struct TreeDiff {
+    /// Roots of subtrees only present in `b`.
+    pub inserted: Vec<usize>,
+    /// Roots of subtrees only present in `a`.
+    pub deleted: Vec<usize>,
+    /// Subtree roots that changed parent or sibling order.
+    pub moved: Vec<Move>,
+    /// `(a, b)` matched nodes whose own content or metadata differ.
+    pub modified: Vec<(usize, usize)>,
+    /// Matched nodes with no change of their own.
+    pub unchanged: usize,
+    /// Every matched `(a, b)` node pair, moved subtrees included.
+    pub pairs: Vec<(usize, usize)>,
+}
+
+/// A subtree present on both sides at a different place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub a: usize,
+    pub b: usize,
+    /// Parent changed, as opposed to reordered among the same siblings.
+    pub reparented: bool,
+}
+
+struct Side<'a> {
+    nodes: &'a [TreeNode],
+    hashes: HashMap<String, String>,
+    children: HashMap<&'a str, Vec<usize>>,
+    by_id: HashMap<&'a str, usize>,
+}
+
+impl<'a> Side<'a> {
+    fn new(nodes: &'a [TreeNode]) -> Self {
+        Side {
+            nodes,
+            hashes: subtree_hashes(nodes),
+            children: child_index(nodes),
+            by_id: nodes.iter().enumerate().map(|(i, n)| (n.id.as_str(), i)).collect(),
+        }
+    }
+
+    fn kids(&self, i: usize) -> &[usize] {
+        self.children
+            .get(self.nodes[i].id.as_str())
+            .map(|v| v.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn hash(&self, i: usize) -> &str {
+        &self.hashes[&self.nodes[i].id]
+    }
+
+    /// Node indices of the subtree at `root`, pre-order.
+    fn subtree(&self, root: usize) -> Vec<usize> {
+        let mut out = Vec::new();
+        let mut stack = vec![root];
+        while let Some(i) = stack.pop() {
+            out.push(i);
+            stack.extend(self.kids(i).iter().rev());
+        }
+        out
+    }
+}
+
+/// Pairing key for one child-matching pass; `None` opts the node out.
+type KeyFn = for<'s> fn(&'s Side<'s>, usize) -> Option<(&'s str, &'s str)>;
+
+/// Own content of a node: content plus metadata without location keys.
+pub fn own_changed(a: &TreeNode, b: &TreeNode) -> bool {
+    let strip = |m: &Value| {
+        let mut m = m.clone();
+        if let Some(obj) = m.as_object_mut() {
+            for key in LOCATION_KEYS {
+                obj.remove(*key);
+            }
+        }
+        m
+    };
+    a.content != b.content || strip(&a.metadata) != strip(&b.metadata)
+}
+
+/// Indices (into `seq`) of one longest strictly increasing subsequence.
+fn longest_increasing(seq: &[i32]) -> HashSet<usize> {
+    // Patience sorting: tails[k] = index ending the best run of length k+1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev: Vec<Option<usize>> = vec![None; seq.len()];
+    for i in 0..seq.len() {
+        let k = tails.partition_point(|&t| seq[t] < seq[i]);
+        if k > 0 {
+            prev[i] = Some(tails[k - 1]);
+        }
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+    let mut keep = HashSet::new();
+    let mut cur = tails.last().copied();
+    while let Some(i) = cur {
+        keep.insert(i);
+        cur = prev[i];
+    }
+    keep
+}
+
+/// Diff two file trees rooted at `a_root` and `b_root`.
+///
+/// Children of matched parents are paired by kind and subtree hash, then by
+/// kind and content, then by kind and path (same item, edited). Matched
+/// siblings that fell out of order are reported as moved. Unpaired subtrees are then
+/// searched for identical subtrees on the other side regardless of parent
+/// (moved); what remains is inserted or deleted.
+pub fn diff_trees(a: &[TreeNode], a_root: &str, b: &[TreeNode], b_root: &str) -> TreeDiff {
+    let sa = Side::new(a);
+    let sb = Side::new(b);
+    let mut diff = TreeDiff::default();
+    let (Some(&ra), Some(&rb)) = (sa.by_id.get(a_root), sb.by_id.get(b_root)) else {
+        return diff;
+    };
+
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let mut orphans_a: Vec<usize> = Vec::new();
+    let mut orphans_b: Vec<usize> = Vec::new();
+    let mut queue = VecDeque::from([(ra, rb)]);
+
+    while let Some((ia, ib)) = queue.pop_front() {
+        pairs.push((ia, ib));
+        let (ka, kb) = (sa.kids(ia), sb.kids(ib));
+        let mut taken = vec![false; ka.len()];
+        let mut matched: Vec<(usize, usize)> = Vec::new();
+        let mut pending: Vec<usize> = kb.to_vec();
+
+        let passes: [KeyFn; 3] = [
+            |s, i| Some((&s.nodes[i].kind, s.hash(i))),
+            |s, i| Some((&s.nodes[i].kind, s.nodes[i].content.as_deref()?)),
+            |s, i| Some((&s.nodes[i].kind, s.nodes[i].path.as_deref()?)),
+        ];
+        for key in passes {
+            let mut index: HashMap<(&str, &str), VecDeque<usize>> = HashMap::new();
+            for (k, &i) in ka.iter().enumerate() {
+                if !taken[k] {
+                    if let Some(key) = key(&sa, i) {
+                        index.entry(key).or_default().push_back(k);
+                    }
+                }
+            }
+            pending.retain(|&j| {
+                let hit = key(&sb, j).and_then(|key| index.get_mut(&key)?.pop_front());
+                match hit {
+                    Some(k) => {
+                        taken[k] = true;
+                        matched.push((ka[k], j));
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+
+        // Siblings kept in relative order stay put; the rest moved
+        matched.sort_by_key(|&(_, j)| b[j].position);
+        let order: Vec<i32> = matched.iter().map(|&(i, _)| a[i].position).collect();
+        let in_order = longest_increasing(&order);
+        for (k, &(i, j)) in matched.iter().enumerate() {
+            if !in_order.contains(&k) {
+                diff.moved.push(Move { a: i, b: j, reparented: false });
+            }
+            queue.push_back((i, j));
+        }
+
+        orphans_a.extend(ka.iter().enumerate().filter(|&(k, _)| !taken[k]).map(|(_, &i)| i));
+        orphans_b.extend(pending);
+    }
+
+    // Cross-parent moves: identical subtrees among the unpaired ones
+    let mut consumed = vec![false; a.len()];
+    let mut candidates: HashMap<(&str, &str), VecDeque<usize>> = HashMap::new();
+    let mut size = vec![0usize; a.len()];
+    for &root in &orphans_a {
+        let order = sa.subtree(root);
+        // Reverse pre-order visits children before their parent
+        for &i in order.iter().rev() {
+            size[i] = 1 + sa.kids(i).iter().map(|&c| size[c]).sum::<usize>();
+        }
+        for i in order {
+            if a[i].content.is_some() && size[i] >= MOVE_MIN_NODES {
+                candidates.entry((a[i].kind.as_str(), sa.hash(i))).or_default().push_back(i);
+            }
+        }
+    }
+    let mut inserted = vec![false; b.len()];
+    for &root in &orphans_b {
+        let mut stack = vec![root];
+        while let Some(j) = stack.pop() {
+            let hit = candidates
+                .get_mut(&(b[j].kind.as_str(), sb.hash(j)))
+                .and_then(|q| {
+                    // Skip subtrees already (partly) paired by an earlier move
+                    while let Some(i) = q.pop_front() {
+                        if sa.subtree(i).iter().all(|&x| !consumed[x]) {
+                            return Some(i);
+                        }
+                    }
+                    None
+                });
+            match hit {
+                Some(i) => {
+                    diff.moved.push(Move { a: i, b: j, reparented: true });
+                    // Identical hashes imply identical shape: pair pre-order
+                    for (x, y) in sa.subtree(i).into_iter().zip(sb.subtree(j)) {
+                        consumed[x] = true;
+                        pairs.push((x, y));
+                    }
+                }
+                None => {
+                    inserted[j] = true;
+                    stack.extend(sb.kids(j).iter().rev());
+                }
+            }
+        }
+    }
+
+    for (j, &ins) in inserted.iter().enumerate() {
+        let parent_inserted = b[j]
+            .parent_id
+            .as_deref()
+            .and_then(|p| sb.by_id.get(p))
+            .is_some_and(|&p| inserted[p]);
+        if ins && !parent_inserted {
+            diff.inserted.push(j);
+        }
+    }
+    // A deleted root may have lost moved descendants; it is still the root
+    diff.deleted = orphans_a.into_iter().filter(|&i| !consumed[i]).collect();
+
+    for &(i, j) in &pairs {
+        if own_changed(&a[i], &b[j]) {
+            diff.modified.push((i, j));
+        } else {
+            diff.unchanged += 1;
+        }
+    }
+    diff.pairs = pairs;
+    diff
+}
+
+/// Size of the part of a subtree not carried off by moves.
+fn remaining(side: &Side, root: usize, skip: &dyn Fn(usize) -> bool) -> usize {
+    side.subtree(root).into_iter().filter(|&i| !skip(i)).count()
+}
+
+fn node_json(n: &TreeNode) -> Value {
+    json!({
+        "id": n.id,
+        "kind": n.kind,
+        "content": n.content,
+        "path": n.path,
+        "parent_id": n.parent_id,
+    })
+}
+
+/// Render a diff as the JSON returned by `kerai.diff_nodes`.
+pub fn diff_json(a: &[TreeNode], b: &[TreeNode], diff: &TreeDiff) -> Value {
+    let sa = Side::new(a);
+    let sb = Side::new(b);
+    let moved_a: HashSet<usize> = diff.moved.iter().flat_map(|m| sa.subtree(m.a)).collect();
+    let moved_b: HashSet<usize> = diff.moved.iter().flat_map(|m| sb.subtree(m.b)).collect();
+
+    let inserted: Vec<Value> = diff
+        .inserted
+        .iter()
+        .map(|&j| {
+            let mut v = node_json(&b[j]);
+            v["nodes"] = json!(remaining(&sb, j, &|y| moved_b.contains(&y)));
+            v
+        })
+        .collect();
+    let deleted: Vec<Value> = diff
+        .deleted
+        .iter()
+        .map(|&i| {
+            let mut v = node_json(&a[i]);
+            v["nodes"] = json!(remaining(&sa, i, &|x| moved_a.contains(&x)));
+            v
+        })
+        .collect();
+    let moved: Vec<Value> = diff
+        .moved
+        .iter()
+        .map(|m| {
+            let (i, j) = (m.a, m.b);
+            json!({
+                "a_id": a[i].id,
+                "b_id": b[j].id,
+                "kind": b[j].kind,
+                "content": b[j].content,
+                "path": b[j].path,
+                "reparented": m.reparented,
+                "from_parent": a[i].parent_id,
+                "to_parent": b[j].parent_id,
+                "from_position": a[i].position,
+                "to_position": b[j].position,
+            })
+        })
+        .collect();
+    let modified: Vec<Value> = diff
+        .modified
+        .iter()
+        .map(|&(i, j)| {
+            json!({
+                "a_id": a[i].id,
+                "b_id": b[j].id,
+                "kind": b[j].kind,
+                "path": b[j].path,
+                "before": a[i].content,
+                "after": b[j].content,
+            })
+        })
+        .collect();
+
+    json!({
+        "inserted": inserted,
+        "deleted": deleted,
+        "moved": moved,
+        "modified": modified,
+        "unchanged": diff.unchanged,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, parent: Option<&str>, kind: &str, content: &str, position: i32) -> TreeNode {
+        TreeNode {
+            id: id.to_string(),
+            parent_id: parent.map(|p| p.to_string()),
+            kind: kind.to_string(),
+            content: Some(content.to_string()),
+            path: Some(format!("f.{content}")),
+            position,
+            metadata: json!({"start_line": position}),
+        }
+    }
+
+    fn ids<'a>(nodes: &'a [TreeNode], idx: &[usize]) -> Vec<&'a str> {
+        let mut out: Vec<&str> = idx.iter().map(|&i| nodes[i].id.as_str()).collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn identical_trees_are_unchanged() {
+        let a = vec![node("f", None, "file", "f", 0), node("x", Some("f"), "fn", "x", 0)];
+        let b = vec![node("g", None, "file", "f", 0), node("y", Some("g"), "fn", "x", 0)];
+        let d = diff_trees(&a, "f", &b, "g");
+        assert_eq!(d.unchanged, 2);
+        assert!(d.inserted.is_empty() && d.deleted.is_empty());
+        assert!(d.moved.is_empty() && d.modified.is_empty());
+    }
+
+    #[test]
+    fn classifies_insert_delete_modify() {
+        let a = vec![
+            node("f", None, "file", "f", 0),
+            node("keep", Some("f"), "fn", "keep", 0),
+            node("gone", Some("f"), "fn", "gone", 1),
+            node("gone_body", Some("gone"), "expr", "1", 0),
+        ];
+        let mut b = vec![
+            node("F", None, "file", "f", 0),
+            node("KEEP", Some("F"), "fn", "keep", 0),
+            node("NEW", Some("F"), "fn", "new", 1),
+            node("NEW_BODY", Some("NEW"), "expr", "2", 0),
+        ];
+        // Same item (kind + path), edited content
+        b[1].content = Some("keep2".into());
+
+        let d = diff_trees(&a, "f", &b, "F");
+        assert_eq!(ids(&b, &d.inserted), vec!["NEW"]);
+        assert_eq!(ids(&a, &d.deleted), vec!["gone"]);
+        assert_eq!(d.modified.len(), 1);
+        assert_eq!(a[d.modified[0].0].id, "keep");
+
+        let v = diff_json(&a, &b, &d);
+        assert_eq!(v["inserted"][0]["nodes"], 2);
+        assert_eq!(v["modified"][0]["after"], "keep2");
+    }
+
+    #[test]
+    fn detects_reorder_and_reparent() {
+        let a = vec![
+            node("f", None, "file", "f", 0),
+            node("m", Some("f"), "mod", "m", 0),
+            node("x", Some("f"), "fn", "x", 1),
+            node("x_body", Some("x"), "expr", "1", 0),
+            node("y", Some("f"), "fn", "y", 2),
+            node("z", Some("f"), "fn", "z", 3),
+        ];
+        let b = vec![
+            node("F", None, "file", "f", 0),
+            node("M", Some("F"), "mod", "m", 0),
+            // `x` now lives inside the module
+            node("X", Some("M"), "fn", "x", 0),
+            node("X_BODY", Some("X"), "expr", "1", 0),
+            node("Z", Some("F"), "fn", "z", 1),
+            node("Y", Some("F"), "fn", "y", 2),
+        ];
+        let d = diff_trees(&a, "f", &b, "F");
+        let moved: Vec<(&str, &str, bool)> = d
+            .moved
+            .iter()
+            .map(|m| (a[m.a].id.as_str(), b[m.b].id.as_str(), m.reparented))
+            .collect();
+        assert!(moved.contains(&("x", "X", true)));
+        assert_eq!(moved.len(), 2, "one of y/z reordered: {moved:?}");
+        assert!(d.inserted.is_empty());
+        assert!(d.deleted.is_empty());
+    }
+
+    #[test]
+    fn lis_keeps_longest_run() {
+        let keep = longest_increasing(&[0, 3, 1, 2]);
+        assert_eq!(keep, HashSet::from([0, 2, 3]));
+    }
+}
@@ -71,7 +71,7 @@ fn parse_suggestion_comment(content: &str) -> Option<(String, String)> {
     if let Some(paren_start) = trimmed.rfind('(') {
         if trimmed.ends_with(')') {
             let rule_id = trimmed[paren_start + 1..trimmed.len() - 1].trim();
-            if !rule_id.is_empty() && rule_id.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            if !rule_id.is_empty() && rule_id.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
                 let message = trimmed[..paren_start].trim().to_string();
                 return Some((message, rule_id.to_string()));
             }
@@ -138,6 +138,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_hyphenated_rule_id() {
+        let source = "// kerai: Function `f` is never used (dead-code)\nfn f() {}";
+        match &parse_kerai_directives(source)[0] {
+            KeraiDirective::SuggestionComment { rule_id, .. } => assert_eq!(rule_id, "dead-code"),
+            _ => panic!("expected suggestion comment"),
+        }
+    }
+
     #[test]
     fn test_no_kerai_comments() {
         let source = "// regular comment\nfn foo() {}";
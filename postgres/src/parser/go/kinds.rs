@@ -85,6 +85,76 @@ pub const GO_IDENT: &str = "go_ident";
 // Catch-all
 pub const GO_OTHER: &str = "go_other";
 
+/// Every Go kind, for the parser registry.
+pub const ALL: &[&str] = &[
+    GO_PACKAGE,
+    GO_IMPORT,
+    GO_IMPORT_SPEC,
+    GO_FUNC,
+    GO_METHOD,
+    GO_TYPE_DECL,
+    GO_TYPE_SPEC,
+    GO_STRUCT,
+    GO_INTERFACE,
+    GO_FIELD,
+    GO_METHOD_SPEC,
+    GO_VAR_DECL,
+    GO_VAR_SPEC,
+    GO_CONST_DECL,
+    GO_CONST_SPEC,
+    GO_BLOCK,
+    GO_IF,
+    GO_FOR,
+    GO_SWITCH,
+    GO_TYPE_SWITCH,
+    GO_SELECT,
+    GO_RETURN,
+    GO_GO,
+    GO_DEFER,
+    GO_SHORT_VAR,
+    GO_ASSIGNMENT,
+    GO_EXPRESSION_STMT,
+    GO_SEND_STMT,
+    GO_INC_STMT,
+    GO_DEC_STMT,
+    GO_LABELED_STMT,
+    GO_FALLTHROUGH,
+    GO_BREAK,
+    GO_CONTINUE,
+    GO_GOTO,
+    GO_RANGE,
+    GO_CALL,
+    GO_SELECTOR,
+    GO_COMPOSITE_LIT,
+    GO_FUNC_LIT,
+    GO_INDEX,
+    GO_SLICE,
+    GO_TYPE_ASSERTION,
+    GO_UNARY,
+    GO_BINARY,
+    GO_PAREN,
+    GO_POINTER_TYPE,
+    GO_ARRAY_TYPE,
+    GO_SLICE_TYPE,
+    GO_MAP_TYPE,
+    GO_CHANNEL_TYPE,
+    GO_FUNC_TYPE,
+    GO_QUALIFIED_TYPE,
+    GO_INT_LIT,
+    GO_FLOAT_LIT,
+    GO_STRING_LIT,
+    GO_RUNE_LIT,
+    GO_TRUE,
+    GO_FALSE,
+    GO_NIL,
+    GO_IOTA,
+    GO_CASE,
+    GO_DEFAULT_CASE,
+    GO_COMM_CLAUSE,
+    GO_IDENT,
+    GO_OTHER,
+];
+
 /// Map a tree-sitter node kind string to a kerai Go kind constant.
 pub fn ts_kind_to_go_kind(ts_kind: &str) -> &'static str {
     match ts_kind {
@@ -11,6 +11,7 @@ use crate::parser::inserter;
 use crate::parser::kinds::Kind;
 use crate::parser::normalizer;
 use crate::parser::path_builder::PathContext;
+use crate::parser::registry::{self, LanguageParser, Placement};
 use crate::parser::treesitter::{self, TsLanguage};
 
 #[allow(dead_code)]
@@ -23,7 +24,7 @@ mod walker;
 ///
 /// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
 #[pg_extern]
-fn parse_go_source(source: &str, filename: &str) -> pgrx::JsonB {
+pub(super) fn parse_go_source(source: &str, filename: &str) -> pgrx::JsonB {
     let start = Instant::now();
     let instance_id = super::get_self_instance_id();
 
@@ -35,11 +36,8 @@ fn parse_go_source(source: &str, filename: &str) -> pgrx::JsonB {
     // Auto-mint reward
     if node_count > 0 {
         let details = json!({"file": filename, "language": "go", "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_go_source', '{}'::jsonb)",
-            details_str,
-        ));
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), filename);
+        crate::currency::reward("parse_go_source", "node", file_id.as_deref(), details);
     }
 
     let elapsed = start.elapsed();
@@ -81,11 +79,8 @@ fn parse_go_file(path: &str) -> pgrx::JsonB {
     // Auto-mint reward
     if node_count > 0 {
         let details = json!({"file": filename, "language": "go", "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_go_file', '{}'::jsonb)",
-            details_str,
-        ));
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), &filename);
+        crate::currency::reward("parse_go_file", "node", file_id.as_deref(), details);
     }
 
     let elapsed = start.elapsed();
@@ -98,6 +93,31 @@ fn parse_go_file(path: &str) -> pgrx::JsonB {
     }))
 }
 
+/// Go in the parser registry.
+pub(crate) struct Go;
+
+impl LanguageParser for Go {
+    fn name(&self) -> &'static str {
+        "go"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["go"]
+    }
+
+    fn kinds(&self) -> Vec<&'static str> {
+        registry::kind_list(registry::COMMENTED, kinds::ALL)
+    }
+
+    fn parse_source(&self, source: &str, filename: &str) -> pgrx::JsonB {
+        parse_go_source(source, filename)
+    }
+
+    fn walk(&self, source: &str, filename: &str, at: &Placement) -> (usize, usize) {
+        parse_go_single(source, filename, at.instance_id, at.parent_id)
+    }
+}
+
 /// Parse Go source, insert nodes/edges, return counts.
 ///
 /// `parent_id` allows parenting the file node under a repo directory node.
@@ -120,7 +140,7 @@ pub(crate) fn parse_go_single(
     };
 
     // 3. Create file node
-    let file_node_id = Uuid::new_v4().to_string();
+    let file_node_id = Uuid::now_v7().to_string();
     let path_ctx = PathContext::with_root(filename);
 
     let file_node = NodeRow {
@@ -169,7 +189,7 @@ pub(crate) fn parse_go_single(
 
     // 9. Create NodeRow + EdgeRow for each comment block
     for (block_idx, block) in blocks.iter().enumerate() {
-        let comment_id = Uuid::new_v4().to_string();
+        let comment_id = Uuid::now_v7().to_string();
         let kind = if !block.is_block_style && block.lines.len() > 1 {
             Kind::CommentBlock
         } else {
@@ -209,7 +229,7 @@ pub(crate) fn parse_go_single(
         // Create "documents" edge if matched to a node
         if let Some(ref target_id) = matches[block_idx] {
             edges.push(EdgeRow {
-                id: Uuid::new_v4().to_string(),
+                id: Uuid::now_v7().to_string(),
                 source_id: comment_id,
                 target_id: target_id.clone(),
                 relation: "documents".to_string(),
@@ -253,7 +273,7 @@ pub(crate) fn parse_go_single(
     let findings = suggestion_rules::run_go_rules(&node_infos, pkg_name.as_deref());
 
     for finding in &findings {
-        let suggestion_id = Uuid::new_v4().to_string();
+        let suggestion_id = Uuid::now_v7().to_string();
 
         nodes.push(NodeRow {
             id: suggestion_id.clone(),
@@ -275,7 +295,7 @@ pub(crate) fn parse_go_single(
         });
 
         edges.push(EdgeRow {
-            id: Uuid::new_v4().to_string(),
+            id: Uuid::now_v7().to_string(),
             source_id: suggestion_id,
             target_id: finding.target_node_id.clone(),
             relation: "suggests".to_string(),
@@ -283,12 +303,11 @@ pub(crate) fn parse_go_single(
         });
     }
 
+    inserter::insert_deduped(&mut nodes, &mut edges);
+
     let node_count = nodes.len() + 1; // +1 for file node
     let edge_count = edges.len();
 
-    inserter::insert_nodes(&nodes);
-    inserter::insert_edges(&edges);
-
     (node_count, edge_count)
 }
 
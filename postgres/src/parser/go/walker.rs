@@ -29,7 +29,7 @@ impl GoWalkCtx {
         span_start: Option<i32>,
         span_end: Option<i32>,
     ) -> String {
-        let id = Uuid::new_v4().to_string();
+        let id = Uuid::now_v7().to_string();
         self.nodes.push(NodeRow {
             id: id.clone(),
             instance_id: self.instance_id.clone(),
@@ -48,7 +48,7 @@ impl GoWalkCtx {
 
     fn new_edge(&mut self, source_id: &str, target_id: &str, relation: &str) {
         self.edges.push(EdgeRow {
-            id: Uuid::new_v4().to_string(),
+            id: Uuid::now_v7().to_string(),
             source_id: source_id.to_string(),
             target_id: target_id.to_string(),
             relation: relation.to_string(),
@@ -1,123 +1,853 @@
-/// Batch SPI INSERT for nodes and edges.
+/// Batch SPI INSERT for nodes and edges, plus incremental re-sync of a file.
+///
+/// Each batch is one parameterized statement: the rows travel as a single
+/// jsonb argument expanded by `jsonb_to_recordset`, so nothing is escaped
+/// into the SQL text and Postgres parses one short statement per batch.
 use pgrx::prelude::*;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use super::ast_walker::{EdgeRow, NodeRow};
-use crate::sql::{sql_escape, sql_jsonb, sql_ltree, sql_opt_text, sql_uuid};
+use crate::sql::sql_uuid;
 
-const BATCH_SIZE: usize = 500;
+/// Rows per INSERT statement.
+pub const BATCH_SIZE: usize = 5000;
+
+/// Id of the live `kind` node (`file`, or `document` for markdown) a
+/// parse of `filename` left, outside sandboxes.
+pub fn root_node_id(instance_id: &str, kind: &str, filename: &str) -> Option<String> {
+    Spi::get_one_with_args::<String>(
+        &format!(
+            "SELECT id::text FROM kerai.nodes
+             WHERE instance_id = $1::uuid AND kind = $2 AND content = $3 AND {}
+               AND deleted_at IS NULL
+             LIMIT 1",
+            crate::sandboxes::unsandboxed("path"),
+        ),
+        &[instance_id.into(), kind.into(), filename.into()],
+    )
+    .ok()
+    .flatten()
+}
 
 /// Delete all nodes (and edges via CASCADE) for a given file node.
 /// Used for idempotent re-parse: delete old data, then re-insert.
 pub fn delete_file_nodes(instance_id: &str, filename: &str) {
-    let inst = sql_uuid(instance_id);
-    let fname = sql_escape(filename);
+    // $1 instance, $2 filename: the name goes in as a parameter
+    let outside = crate::sandboxes::unsandboxed("path");
+    let files = format!(
+        "SELECT id FROM kerai.nodes
+         WHERE instance_id = $1::uuid AND kind = 'file' AND content = $2 AND {outside}"
+    );
+    let parents = Spi::get_one_with_args::<pgrx::JsonB>(
+        &format!(
+            "SELECT COALESCE(jsonb_agg(DISTINCT parent_id), '[]'::jsonb) FROM kerai.nodes
+             WHERE id IN ({files}) AND parent_id IS NOT NULL"
+        ),
+        &[instance_id.into(), filename.into()],
+    )
+    .unwrap_or(None)
+    .map(|j| j.0)
+    .unwrap_or_default();
 
     // Delete edges where source or target is a child of this file
-    Spi::run(&format!(
-        "DELETE FROM kerai.edges WHERE source_id IN (
-            SELECT id FROM kerai.nodes
-            WHERE instance_id = {inst}
-            AND kind = 'file' AND content = '{fname}'
-        ) OR target_id IN (
-            SELECT id FROM kerai.nodes
-            WHERE instance_id = {inst}
-            AND kind = 'file' AND content = '{fname}'
-        )",
-    ))
+    Spi::run_with_args(
+        &format!("DELETE FROM kerai.edges WHERE source_id IN ({files}) OR target_id IN ({files})"),
+        &[instance_id.into(), filename.into()],
+    )
     .ok();
 
     // Delete child nodes (anything with parent_id pointing to file's subtree)
     // Use recursive CTE to find all descendants
-    Spi::run(&format!(
+    let descendants = format!(
         "WITH RECURSIVE descendants AS (
-            SELECT id FROM kerai.nodes
-            WHERE instance_id = {inst}
-            AND kind = 'file' AND content = '{fname}'
+            {files}
             UNION ALL
             SELECT n.id FROM kerai.nodes n
             JOIN descendants d ON n.parent_id = d.id
-        )
-        DELETE FROM kerai.edges WHERE source_id IN (SELECT id FROM descendants)
-            OR target_id IN (SELECT id FROM descendants)",
-    ))
+        )"
+    );
+    Spi::run_with_args(
+        &format!(
+            "{descendants}
+            DELETE FROM kerai.edges WHERE source_id IN (SELECT id FROM descendants)
+                OR target_id IN (SELECT id FROM descendants)"
+        ),
+        &[instance_id.into(), filename.into()],
+    )
     .ok();
 
-    Spi::run(&format!(
-        "WITH RECURSIVE descendants AS (
-            SELECT id FROM kerai.nodes
-            WHERE instance_id = {inst}
-            AND kind = 'file' AND content = '{fname}'
-            UNION ALL
-            SELECT n.id FROM kerai.nodes n
-            JOIN descendants d ON n.parent_id = d.id
-        )
-        DELETE FROM kerai.nodes WHERE id IN (SELECT id FROM descendants)",
-    ))
+    Spi::run_with_args(
+        &format!("{descendants} DELETE FROM kerai.nodes WHERE id IN (SELECT id FROM descendants)"),
+        &[instance_id.into(), filename.into()],
+    )
     .ok();
+
+    for parent in parents.as_array().into_iter().flatten() {
+        crate::merkle::refresh(parent.as_str().unwrap_or_default());
+    }
 }
 
 /// Insert nodes in batches.
+///
+/// Each node's `content_hash` is the hash of its subtree within `nodes`.
 pub fn insert_nodes(nodes: &[NodeRow]) {
+    let tree: Vec<TreeNode> = nodes.iter().map(TreeNode::from).collect();
+    insert_hashed_nodes(nodes, &subtree_hashes(&tree));
+}
+
+fn insert_hashed_nodes(nodes: &[NodeRow], hashes: &HashMap<String, String>) {
     for batch in nodes.chunks(BATCH_SIZE) {
-        let mut sql = String::from(
-            "INSERT INTO kerai.nodes (id, instance_id, kind, language, content, parent_id, position, path, metadata) VALUES ",
-        );
+        let rows: Vec<Value> = batch
+            .iter()
+            .map(|node| {
+                json!({
+                    "id": node.id,
+                    "instance_id": node.instance_id,
+                    "kind": node.kind,
+                    "language": node.language,
+                    "content": node.content,
+                    "parent_id": node.parent_id,
+                    "position": node.position,
+                    "path": node.path,
+                    "metadata": node.metadata,
+                    "content_hash": hashes.get(&node.id),
+                })
+            })
+            .collect();
 
-        for (i, node) in batch.iter().enumerate() {
-            if i > 0 {
-                sql.push_str(", ");
-            }
-            sql.push_str(&format!(
-                "({}, {}, '{}', {}, {}, {}, {}, {}, {})",
-                sql_uuid(&node.id),
-                sql_uuid(&node.instance_id),
-                sql_escape(&node.kind),
-                sql_opt_text(&node.language),
-                sql_opt_text(&node.content),
-                match &node.parent_id {
-                    Some(pid) => sql_uuid(pid),
-                    None => "NULL".to_string(),
-                },
-                node.position,
-                match &node.path {
-                    Some(p) => sql_ltree(p),
-                    None => "NULL".to_string(),
-                },
-                sql_jsonb(&node.metadata),
-            ));
-        }
-
-        Spi::run(&sql).expect("Failed to insert nodes batch");
+        Spi::run_with_args(
+            "INSERT INTO kerai.nodes (id, instance_id, kind, language, content, parent_id, position, path, metadata, content_hash)
+             SELECT id, instance_id, kind, language, content, parent_id, position, path, metadata, content_hash
+             FROM jsonb_to_recordset($1) AS r(id uuid, instance_id uuid, kind text, language text,
+                                              content text, parent_id uuid, position int,
+                                              path ltree, metadata jsonb, content_hash text)",
+            &[pgrx::JsonB(Value::Array(rows)).into()],
+        )
+        .expect("Failed to insert nodes batch");
+    }
+    refresh_parents(nodes);
+}
+
+/// Rehash the stored nodes that rows in `nodes` hang from, up to their
+/// roots: a crate a file is parsed into has a new child.
+fn refresh_parents(nodes: &[NodeRow]) {
+    let ids: HashSet<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+    let parents: HashSet<&str> = nodes
+        .iter()
+        .filter_map(|n| n.parent_id.as_deref())
+        .filter(|p| !ids.contains(p))
+        .collect();
+    for parent in parents {
+        crate::merkle::refresh(parent);
     }
 }
 
 /// Insert edges in batches.
 pub fn insert_edges(edges: &[EdgeRow]) {
-    if edges.is_empty() {
-        return;
+    for batch in edges.chunks(BATCH_SIZE) {
+        let rows: Vec<Value> = batch
+            .iter()
+            .map(|edge| {
+                json!({
+                    "id": edge.id,
+                    "source_id": edge.source_id,
+                    "target_id": edge.target_id,
+                    "relation": edge.relation,
+                    "metadata": edge.metadata,
+                })
+            })
+            .collect();
+
+        Spi::run_with_args(
+            "INSERT INTO kerai.edges (id, source_id, target_id, relation, metadata)
+             SELECT id, source_id, target_id, relation, metadata
+             FROM jsonb_to_recordset($1) AS r(id uuid, source_id uuid, target_id uuid,
+                                              relation text, metadata jsonb)
+             ON CONFLICT (source_id, target_id, relation) DO NOTHING",
+            &[pgrx::JsonB(Value::Array(rows)).into()],
+        )
+        .expect("Failed to insert edges batch");
     }
+}
 
-    for batch in edges.chunks(BATCH_SIZE) {
-        let mut sql = String::from(
-            "INSERT INTO kerai.edges (id, source_id, target_id, relation, metadata) VALUES ",
+// --- Incremental re-sync ---
+
+/// Metadata keys that only record where a node sits in the file. They are
+/// left out of content hashes so code that merely moved still matches.
+pub const LOCATION_KEYS: &[&str] = &["start_line", "end_line", "line", "col"];
+
+/// Counts from an incremental sync.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncStats {
+    pub inserted: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+}
+
+/// A node as seen by the diff: either freshly parsed or loaded from storage.
+#[derive(Debug, Clone)]
+pub struct TreeNode {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub kind: String,
+    pub content: Option<String>,
+    pub path: Option<String>,
+    pub position: i32,
+    pub metadata: Value,
+}
+
+impl From<&NodeRow> for TreeNode {
+    fn from(n: &NodeRow) -> Self {
+        TreeNode {
+            id: n.id.clone(),
+            parent_id: n.parent_id.clone(),
+            kind: n.kind.clone(),
+            content: n.content.clone(),
+            path: n.path.clone(),
+            position: n.position,
+            metadata: n.metadata.clone(),
+        }
+    }
+}
+
+/// Result of diffing a new parse against stored nodes.
+#[derive(Debug, Default)]
+pub struct SyncPlan {
+    /// New node id → stored id, for every node that keeps its UUID.
+    pub remap: HashMap<String, String>,
+    /// Stored ids of kept nodes whose row (content, position, path,
+    /// metadata) or subtree hash differs from the new parse.
+    pub updated: HashSet<String>,
+    /// Stored ids with no counterpart in the new parse.
+    pub deleted: Vec<String>,
+}
+
+/// Children of each node (by index), ordered by position then input order.
+pub fn child_index(nodes: &[TreeNode]) -> HashMap<&str, Vec<usize>> {
+    let mut children: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, n) in nodes.iter().enumerate() {
+        if let Some(ref pid) = n.parent_id {
+            children.entry(pid.as_str()).or_default().push(i);
+        }
+    }
+    for list in children.values_mut() {
+        list.sort_by_key(|&i| nodes[i].position);
+    }
+    children
+}
+
+/// SHA-256 content hash of every subtree, keyed by node id.
+///
+/// Covers kind, content, metadata (minus location keys) and the ordered
+/// child hashes; ids, positions and paths are excluded.
+pub fn subtree_hashes(nodes: &[TreeNode]) -> HashMap<String, String> {
+    let children = child_index(nodes);
+    let by_id: HashMap<&str, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.as_str(), i))
+        .collect();
+
+    // Post-order without recursion: AST depth is unbounded in generated code.
+    let mut hashes: HashMap<String, String> = HashMap::with_capacity(nodes.len());
+    let roots = nodes
+        .iter()
+        .filter(|n| n.parent_id.as_deref().is_none_or(|p| !by_id.contains_key(p)));
+    for root in roots {
+        let mut stack: Vec<(usize, bool)> = vec![(by_id[root.id.as_str()], false)];
+        while let Some((i, visited)) = stack.pop() {
+            let node = &nodes[i];
+            let kids = children.get(node.id.as_str());
+            if !visited {
+                stack.push((i, true));
+                for &c in kids.into_iter().flatten() {
+                    stack.push((c, false));
+                }
+                continue;
+            }
+
+            let hash = crate::merkle::node_hash(
+                &node.kind,
+                node.content.as_deref(),
+                &node.metadata,
+                kids.into_iter()
+                    .flatten()
+                    .map(|&c| hashes[&nodes[c].id].as_str()),
+            );
+            hashes.insert(node.id.clone(), hash);
+        }
+    }
+    hashes
+}
+
+/// Diff a freshly parsed file tree against the stored one.
+///
+/// Both slices must include their file node (`new_root` / `old_root`), which
+/// always match. Children are matched under already-matched parents: first
+/// by kind + path + subtree hash (unchanged subtrees, matched wholesale),
+/// then by kind + path + content (same item, changed body — kept, and its
+/// children diffed in turn). Unmatched stored subtrees are deleted;
+/// unmatched new ones are inserted with their fresh ids.
+pub fn plan_sync(old: &[TreeNode], old_root: &str, new: &[TreeNode], new_root: &str) -> SyncPlan {
+    let old_hashes = subtree_hashes(old);
+    let new_hashes = subtree_hashes(new);
+    let old_children = child_index(old);
+    let new_children = child_index(new);
+    let old_by_id: HashMap<&str, &TreeNode> = old.iter().map(|n| (n.id.as_str(), n)).collect();
+    let new_by_id: HashMap<&str, &TreeNode> = new.iter().map(|n| (n.id.as_str(), n)).collect();
+
+    let mut plan = SyncPlan::default();
+    let mut queue: VecDeque<(&str, &str)> = VecDeque::from([(old_root, new_root)]);
+
+    while let Some((old_id, new_id)) = queue.pop_front() {
+        let (o, n) = (old_by_id[old_id], new_by_id[new_id]);
+        plan.remap.insert(new_id.to_string(), old_id.to_string());
+        if o.content != n.content
+            || o.position != n.position
+            || o.path != n.path
+            || o.metadata != n.metadata
+            || old_hashes[old_id] != new_hashes[new_id]
+        {
+            plan.updated.insert(old_id.to_string());
+        }
+
+        let empty = Vec::new();
+        let old_kids = old_children.get(old_id).unwrap_or(&empty);
+        let new_kids = new_children.get(new_id).unwrap_or(&empty);
+        let mut taken = vec![false; old_kids.len()];
+        let mut pending: Vec<usize> = Vec::new();
+
+        // Pass 1: identical subtrees
+        let mut by_hash: HashMap<(&str, Option<&str>, &str), VecDeque<usize>> = HashMap::new();
+        for (k, &i) in old_kids.iter().enumerate() {
+            let node = &old[i];
+            by_hash
+                .entry((&node.kind, node.path.as_deref(), &old_hashes[&node.id]))
+                .or_default()
+                .push_back(k);
+        }
+        for &j in new_kids {
+            let node = &new[j];
+            let key = (node.kind.as_str(), node.path.as_deref(), new_hashes[&node.id].as_str());
+            match by_hash.get_mut(&key).and_then(|q| q.pop_front()) {
+                Some(k) => {
+                    taken[k] = true;
+                    queue.push_back((&old[old_kids[k]].id, &node.id));
+                }
+                None => pending.push(j),
+            }
+        }
+
+        // Pass 2: same item, changed body
+        for j in pending {
+            let node = &new[j];
+            let found = old_kids.iter().enumerate().find(|&(k, &i)| {
+                !taken[k]
+                    && old[i].kind == node.kind
+                    && old[i].path == node.path
+                    && old[i].content == node.content
+            });
+            if let Some((k, &i)) = found {
+                taken[k] = true;
+                queue.push_back((&old[i].id, &node.id));
+            }
+        }
+
+        for (k, &i) in old_kids.iter().enumerate() {
+            if !taken[k] {
+                collect_subtree(old, &old_children, i, &mut plan.deleted);
+            }
+        }
+    }
+
+    plan
+}
+
+fn collect_subtree(
+    nodes: &[TreeNode],
+    children: &HashMap<&str, Vec<usize>>,
+    root: usize,
+    out: &mut Vec<String>,
+) {
+    let mut stack = vec![root];
+    while let Some(i) = stack.pop() {
+        out.push(nodes[i].id.clone());
+        if let Some(kids) = children.get(nodes[i].id.as_str()) {
+            stack.extend(kids);
+        }
+    }
+}
+
+/// Load the stored subtree of a file node (the file node included),
+/// leaving out tombstones.
+pub fn load_file_tree(file_node_id: &str) -> Vec<TreeNode> {
+    let mut rows = Vec::new();
+    Spi::connect(|client| {
+        let query = format!(
+            "WITH RECURSIVE descendants AS (
+                SELECT id FROM kerai.nodes WHERE id = {}
+                UNION ALL
+                SELECT n.id FROM kerai.nodes n
+                JOIN descendants d ON n.parent_id = d.id
+                WHERE n.deleted_at IS NULL
+            )
+            SELECT n.id::text AS id, n.parent_id::text AS parent_id, n.kind,
+                   n.content, n.path::text AS path, n.position, n.metadata
+            FROM kerai.nodes n JOIN descendants d ON n.id = d.id",
+            sql_uuid(file_node_id),
         );
+        let result = client.select(&query, None, &[]).unwrap();
+        for row in result {
+            rows.push(TreeNode {
+                id: row.get_by_name::<String, _>("id").unwrap().unwrap_or_default(),
+                parent_id: row.get_by_name::<String, _>("parent_id").unwrap(),
+                kind: row.get_by_name::<String, _>("kind").unwrap().unwrap_or_default(),
+                content: row.get_by_name::<String, _>("content").unwrap(),
+                path: row.get_by_name::<String, _>("path").unwrap(),
+                position: row.get_by_name::<i32, _>("position").unwrap().unwrap_or(0),
+                metadata: row
+                    .get_by_name::<pgrx::JsonB, _>("metadata")
+                    .unwrap()
+                    .map(|j| j.0)
+                    .unwrap_or(Value::Null),
+            });
+        }
+    });
+    rows
+}
+
+/// Incrementally sync a freshly parsed file against its stored nodes.
+///
+/// `nodes` must start with the new file node. Unchanged nodes keep their
+/// stored UUIDs, so edges, perspectives and associations pointing at them
+/// survive a re-parse. Edge and node ids in `nodes`/`edges` are rewritten to
+/// the stored ids in place. Intra-file edges are rebuilt from `edges`;
+/// edges that cross into other files are kept unless an endpoint was
+/// deleted. Deleted nodes become tombstones for `kerai.gc`, keeping their
+/// history; rows referencing them are removed.
+///
+/// Falls back to a plain insert when no file node with this name exists yet.
+pub fn sync_file_nodes(
+    instance_id: &str,
+    filename: &str,
+    nodes: &mut [NodeRow],
+    edges: &mut [EdgeRow],
+) -> SyncStats {
+    let Some(old_root) = root_node_id(instance_id, "file", filename) else {
+        insert_nodes(nodes);
+        insert_edges(edges);
+        return SyncStats {
+            inserted: nodes.len(),
+            ..SyncStats::default()
+        };
+    };
 
-        for (i, edge) in batch.iter().enumerate() {
-            if i > 0 {
-                sql.push_str(", ");
+    let old = load_file_tree(&old_root);
+    let new: Vec<TreeNode> = nodes.iter().map(TreeNode::from).collect();
+    let new_root = new[0].id.clone();
+    let plan = plan_sync(&old, &old_root, &new, &new_root);
+
+    // Rewrite ids of kept nodes
+    let remap = |id: &mut String| {
+        if let Some(old_id) = plan.remap.get(id.as_str()) {
+            *id = old_id.clone();
+        }
+    };
+    for node in nodes.iter_mut() {
+        remap(&mut node.id);
+        if let Some(ref mut pid) = node.parent_id {
+            remap(pid);
+        }
+    }
+    for edge in edges.iter_mut() {
+        remap(&mut edge.source_id);
+        remap(&mut edge.target_id);
+    }
+
+    // Intra-file edges are rebuilt; rows touching deleted nodes go with them
+    let old_ids: Vec<String> = old.iter().map(|n| sql_uuid(&n.id)).collect();
+    let old_list = old_ids.join(", ");
+    Spi::run(&format!(
+        "DELETE FROM kerai.edges WHERE source_id IN ({old_list}) AND target_id IN ({old_list})"
+    ))
+    .expect("Failed to clear intra-file edges");
+
+    if !plan.deleted.is_empty() {
+        let del: Vec<String> = plan.deleted.iter().map(|id| sql_uuid(id)).collect();
+        let del_list = del.join(", ");
+        for stmt in [
+            format!("DELETE FROM kerai.edges WHERE source_id IN ({del_list}) OR target_id IN ({del_list})"),
+            format!("DELETE FROM kerai.associations WHERE source_id IN ({del_list}) OR target_id IN ({del_list})"),
+            format!("DELETE FROM kerai.perspectives WHERE node_id IN ({del_list}) OR context_id IN ({del_list})"),
+            format!("UPDATE kerai.nodes SET deleted_at = now() WHERE id IN ({del_list})"),
+        ] {
+            Spi::run(&stmt).expect("Failed to delete removed nodes");
+        }
+    }
+
+    let tree: Vec<TreeNode> = nodes.iter().map(TreeNode::from).collect();
+    let hashes = subtree_hashes(&tree);
+
+    let kept: HashSet<&str> = plan.remap.values().map(|s| s.as_str()).collect();
+    let to_update: Vec<&NodeRow> = nodes
+        .iter()
+        .filter(|n| plan.updated.contains(&n.id))
+        .collect();
+    update_nodes(&to_update, &hashes);
+
+    let fresh: Vec<NodeRow> = nodes
+        .iter()
+        .filter(|n| !kept.contains(n.id.as_str()))
+        .cloned()
+        .collect();
+    insert_hashed_nodes(&fresh, &hashes);
+    insert_edges(edges);
+    refresh_parents(nodes);
+
+    SyncStats {
+        inserted: fresh.len(),
+        updated: to_update.len(),
+        deleted: plan.deleted.len(),
+        unchanged: kept.len() - to_update.len(),
+    }
+}
+
+/// Update content, position, path, metadata and content hash of existing
+/// nodes in batches.
+fn update_nodes(nodes: &[&NodeRow], hashes: &HashMap<String, String>) {
+    for batch in nodes.chunks(BATCH_SIZE) {
+        let rows: Vec<Value> = batch
+            .iter()
+            .map(|node| {
+                json!({
+                    "id": node.id,
+                    "content": node.content,
+                    "position": node.position,
+                    "path": node.path,
+                    "metadata": node.metadata,
+                    "content_hash": hashes.get(&node.id),
+                })
+            })
+            .collect();
+
+        Spi::run_with_args(
+            "UPDATE kerai.nodes AS n
+             SET content = v.content, position = v.position, path = v.path,
+                 metadata = v.metadata, content_hash = v.content_hash
+             FROM jsonb_to_recordset($1) AS v(id uuid, content text, position int,
+                                              path ltree, metadata jsonb, content_hash text)
+             WHERE n.id = v.id",
+            &[pgrx::JsonB(Value::Array(rows)).into()],
+        )
+        .expect("Failed to update nodes batch");
+    }
+}
+
+// --- Subtree deduplication ---
+
+/// Subtrees smaller than this are always stored in full; sharing them would
+/// save less than the stub row and `duplicates` edge cost.
+const DEDUP_MIN_NODES: usize = 8;
+
+/// Outcome of deduplicating a batch before insert.
+#[derive(Debug, Default)]
+pub struct DedupPlan {
+    /// Stub root id → id of the canonical subtree it duplicates.
+    pub stubs: HashMap<String, String>,
+    /// Elided descendant id → the stub root that stands in for it.
+    pub elided: HashMap<String, String>,
+}
+
+/// Decide which subtrees of `nodes` to replace with stubs.
+///
+/// A subtree is shared when it has at least `DEDUP_MIN_NODES` nodes, its
+/// root is not a top-level item (direct child of a file, or without a
+/// parent in the batch — reconstruction reads those), and its hash matches
+/// `existing` (stored canonical subtrees) or an earlier subtree in the
+/// batch. Walks top-down, so the largest repeated subtree wins.
+pub fn plan_dedup(
+    nodes: &[TreeNode],
+    hashes: &HashMap<String, String>,
+    existing: &HashMap<String, String>,
+) -> DedupPlan {
+    let children = child_index(nodes);
+    let by_id: HashMap<&str, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.as_str(), i))
+        .collect();
+
+    let mut plan = DedupPlan::default();
+    let mut first_seen: HashMap<&str, &str> = HashMap::new();
+    let mut queue: VecDeque<usize> = nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| n.parent_id.as_deref().is_none_or(|p| !by_id.contains_key(p)))
+        .map(|(i, _)| i)
+        .collect();
+
+    while let Some(i) = queue.pop_front() {
+        let node = &nodes[i];
+        let eligible = node
+            .parent_id
+            .as_deref()
+            .and_then(|p| by_id.get(p))
+            .is_some_and(|&p| nodes[p].kind != "file");
+
+        if eligible {
+            let mut subtree = Vec::new();
+            collect_subtree(nodes, &children, i, &mut subtree);
+            if subtree.len() >= DEDUP_MIN_NODES {
+                let hash = hashes[&node.id].as_str();
+                let canonical = existing
+                    .get(hash)
+                    .map(|s| s.as_str())
+                    .or_else(|| first_seen.get(hash).copied());
+                match canonical {
+                    Some(canonical) => {
+                        for id in subtree.into_iter().skip(1) {
+                            plan.elided.insert(id, node.id.clone());
+                        }
+                        plan.stubs.insert(node.id.clone(), canonical.to_string());
+                        continue;
+                    }
+                    None => {
+                        first_seen.insert(hash, &node.id);
+                    }
+                }
             }
-            sql.push_str(&format!(
-                "({}, {}, {}, '{}', {})",
-                sql_uuid(&edge.id),
-                sql_uuid(&edge.source_id),
-                sql_uuid(&edge.target_id),
-                sql_escape(&edge.relation),
-                sql_jsonb(&edge.metadata),
-            ));
         }
 
-        sql.push_str(" ON CONFLICT (source_id, target_id, relation) DO NOTHING");
+        if let Some(kids) = children.get(node.id.as_str()) {
+            queue.extend(kids);
+        }
+    }
+
+    plan
+}
+
+/// Insert a parsed file's rows, sharing repeated subtrees.
+///
+/// Subtrees picked by `plan_dedup` keep only their root, which gets
+/// `dedup_of` (canonical id), `dedup_nodes` and `dedup_bytes` (what was not
+/// stored) in its metadata, its full-subtree `content_hash`, and a
+/// `duplicates` edge to the canonical root. Edges that pointed into an
+/// elided subtree are retargeted to its stub. Returns the number of nodes
+/// not stored.
+pub fn insert_deduped(nodes: &mut Vec<NodeRow>, edges: &mut Vec<EdgeRow>) -> usize {
+    let tree: Vec<TreeNode> = nodes.iter().map(TreeNode::from).collect();
+    let hashes = subtree_hashes(&tree);
+    let existing = query_canonical(&tree, &hashes);
+    let plan = plan_dedup(&tree, &hashes, &existing);
+
+    if plan.stubs.is_empty() {
+        insert_hashed_nodes(nodes, &hashes);
+        insert_edges(edges);
+        return 0;
+    }
+
+    // Tally what each stub saves before dropping the elided rows
+    let mut saved: HashMap<&str, (usize, usize)> = HashMap::new();
+    for n in nodes.iter() {
+        if let Some(stub) = plan.elided.get(&n.id) {
+            let entry = saved.entry(stub.as_str()).or_default();
+            entry.0 += 1;
+            entry.1 += n.content.as_deref().map_or(0, |c| c.len()) + n.metadata.to_string().len();
+        }
+    }
+    let saved: HashMap<String, (usize, usize)> =
+        saved.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+
+    nodes.retain(|n| !plan.elided.contains_key(&n.id));
+    for n in nodes.iter_mut() {
+        if let Some(canonical) = plan.stubs.get(&n.id) {
+            let (count, bytes) = saved.get(&n.id).copied().unwrap_or_default();
+            if let Some(obj) = n.metadata.as_object_mut() {
+                obj.insert("dedup_of".into(), serde_json::json!(canonical));
+                obj.insert("dedup_nodes".into(), serde_json::json!(count));
+                obj.insert("dedup_bytes".into(), serde_json::json!(bytes));
+            }
+        }
+    }
+
+    for e in edges.iter_mut() {
+        if let Some(stub) = plan.elided.get(&e.source_id) {
+            e.source_id = stub.clone();
+        }
+        if let Some(stub) = plan.elided.get(&e.target_id) {
+            e.target_id = stub.clone();
+        }
+    }
+    edges.retain(|e| e.source_id != e.target_id);
+    for (stub, canonical) in &plan.stubs {
+        let (count, _) = saved.get(stub).copied().unwrap_or_default();
+        edges.push(EdgeRow {
+            id: uuid::Uuid::now_v7().to_string(),
+            source_id: stub.clone(),
+            target_id: canonical.clone(),
+            relation: "duplicates".to_string(),
+            metadata: serde_json::json!({"nodes": count}),
+        });
+    }
+
+    insert_hashed_nodes(nodes, &hashes);
+    insert_edges(edges);
+
+    plan.elided.len()
+}
+
+/// Stored, fully materialized subtrees (not stubs themselves) for the
+/// hashes of dedup candidates in `nodes`: hash → oldest node id.
+fn query_canonical(
+    nodes: &[TreeNode],
+    hashes: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let ids: HashSet<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+    let mut wanted: Vec<String> = nodes
+        .iter()
+        .filter(|n| n.parent_id.as_deref().is_some_and(|p| ids.contains(p)))
+        .map(|n| format!("'{}'", hashes[&n.id]))
+        .collect();
+    wanted.sort();
+    wanted.dedup();
+
+    let mut found = HashMap::new();
+    if wanted.is_empty() {
+        return found;
+    }
+
+    Spi::connect(|client| {
+        let query = format!(
+            "SELECT DISTINCT ON (content_hash) content_hash, id::text AS id
+             FROM kerai.nodes
+             WHERE content_hash IN ({}) AND NOT COALESCE(metadata ? 'dedup_of', false)
+             ORDER BY content_hash, created_at",
+            wanted.join(", "),
+        );
+        let result = client.select(&query, None, &[]).unwrap();
+        for row in result {
+            let hash: String = row.get_by_name::<String, _>("content_hash").unwrap().unwrap_or_default();
+            let id: String = row.get_by_name::<String, _>("id").unwrap().unwrap_or_default();
+            found.insert(hash, id);
+        }
+    });
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(id: &str, parent: Option<&str>, kind: &str, content: &str, position: i32) -> TreeNode {
+        TreeNode {
+            id: id.to_string(),
+            parent_id: parent.map(|p| p.to_string()),
+            kind: kind.to_string(),
+            content: Some(content.to_string()),
+            path: Some(format!("f.{content}")),
+            position,
+            metadata: json!({"start_line": position}),
+        }
+    }
+
+    #[test]
+    fn hashes_ignore_location() {
+        let a = vec![node("f", None, "file", "f", 0), node("x", Some("f"), "fn", "x", 1)];
+        let b = vec![node("g", None, "file", "f", 0), node("y", Some("g"), "fn", "x", 9)];
+        assert_eq!(subtree_hashes(&a)["f"], subtree_hashes(&b)["g"]);
+    }
+
+    #[test]
+    fn unchanged_and_edited_nodes_keep_ids() {
+        let old = vec![
+            node("f", None, "file", "f", 0),
+            node("keep", Some("f"), "fn", "keep", 1),
+            node("keep_body", Some("keep"), "expr", "1", 0),
+            node("edit", Some("f"), "fn", "edit", 3),
+            node("edit_body", Some("edit"), "expr", "2", 0),
+            node("gone", Some("f"), "fn", "gone", 5),
+        ];
+        let new = vec![
+            node("F", None, "file", "f", 0),
+            node("KEEP", Some("F"), "fn", "keep", 2),
+            node("KEEP_BODY", Some("KEEP"), "expr", "1", 0),
+            node("EDIT", Some("F"), "fn", "edit", 4),
+            node("EDIT_BODY", Some("EDIT"), "expr", "3", 0),
+            node("ADDED", Some("F"), "fn", "added", 6),
+        ];
+
+        let plan = plan_sync(&old, "f", &new, "F");
+        assert_eq!(plan.remap["F"], "f");
+        assert_eq!(plan.remap["KEEP"], "keep");
+        assert_eq!(plan.remap["KEEP_BODY"], "keep_body");
+        assert_eq!(plan.remap["EDIT"], "edit");
+        assert!(!plan.remap.contains_key("EDIT_BODY"));
+        assert!(!plan.remap.contains_key("ADDED"));
+
+        let mut deleted = plan.deleted.clone();
+        deleted.sort();
+        assert_eq!(deleted, vec!["edit_body", "gone"]);
+
+        // Moved (position/start_line changed) but otherwise identical
+        assert!(plan.updated.contains("keep"));
+        assert!(!plan.updated.contains("keep_body"));
+    }
+
+    /// A statement with `leaves` children under `parent`.
+    fn block(id: &str, parent: &str, leaves: usize) -> Vec<TreeNode> {
+        let mut out = vec![node(id, Some(parent), "block", "b", 0)];
+        for i in 0..leaves {
+            out.push(node(&format!("{id}_{i}"), Some(id), "expr", "same", i as i32));
+        }
+        out
+    }
+
+    #[test]
+    fn dedup_shares_repeated_subtrees() {
+        let mut nodes = vec![
+            node("f", None, "file", "f", 0),
+            node("a", Some("f"), "fn", "a", 1),
+            node("b", Some("f"), "fn", "b", 2),
+        ];
+        nodes.extend(block("blk_a", "a", DEDUP_MIN_NODES));
+        nodes.extend(block("blk_b", "b", DEDUP_MIN_NODES));
+        let hashes = subtree_hashes(&nodes);
+
+        let plan = plan_dedup(&nodes, &hashes, &HashMap::new());
+        assert_eq!(plan.stubs.len(), 1);
+        assert_eq!(plan.stubs["blk_b"], "blk_a");
+        assert_eq!(plan.elided.len(), DEDUP_MIN_NODES);
+        assert!(plan.elided.values().all(|stub| stub == "blk_b"));
+    }
+
+    #[test]
+    fn dedup_skips_small_and_top_level_subtrees() {
+        let mut nodes = vec![
+            node("f", None, "file", "f", 0),
+            node("a", Some("f"), "fn", "a", 1),
+            node("b", Some("f"), "fn", "b", 2),
+        ];
+        nodes.extend(block("blk_a", "a", 2));
+        nodes.extend(block("blk_b", "b", 2));
+        let hashes = subtree_hashes(&nodes);
+        assert!(plan_dedup(&nodes, &hashes, &HashMap::new()).stubs.is_empty());
+
+        // Top-level items stay whole even when a stored copy exists
+        let existing = HashMap::from([(hashes["a"].clone(), "stored".to_string())]);
+        assert!(plan_dedup(&nodes, &hashes, &existing).stubs.is_empty());
+    }
+
+    #[test]
+    fn dedup_prefers_stored_canonical() {
+        let mut nodes = vec![node("f", None, "file", "f", 0), node("a", Some("f"), "fn", "a", 1)];
+        nodes.extend(block("blk_a", "a", DEDUP_MIN_NODES));
+        let hashes = subtree_hashes(&nodes);
+        let existing = HashMap::from([(hashes["blk_a"].clone(), "stored".to_string())]);
 
-        Spi::run(&sql).expect("Failed to insert edges batch");
+        let plan = plan_dedup(&nodes, &hashes, &existing);
+        assert_eq!(plan.stubs["blk_a"], "stored");
     }
 }
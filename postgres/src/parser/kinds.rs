@@ -126,6 +126,7 @@ pub enum Kind {
 
     // Reconstruction intelligence
     Suggestion,
+    Conflict,
 
     // Knowledge graph
     Reference,
@@ -134,6 +135,10 @@ pub enum Kind {
     CsvDataset,
     CsvTable,
     CsvColumn,
+
+    // Project tree (parse_project)
+    Project,
+    Package,
 }
 
 impl Kind {
@@ -251,12 +256,16 @@ impl Kind {
             Kind::TraitItemOther => "trait_item_other",
             // Reconstruction intelligence
             Kind::Suggestion => "suggestion",
+            Kind::Conflict => "conflict",
             // Knowledge graph
             Kind::Reference => "reference",
             // CSV import
             Kind::CsvDataset => "csv_dataset",
             Kind::CsvTable => "csv_table",
             Kind::CsvColumn => "csv_column",
+            // Project tree
+            Kind::Project => "project",
+            Kind::Package => "package",
         }
     }
 
@@ -288,9 +297,10 @@ impl Kind {
         Kind::TypeNever, Kind::TypeInfer, Kind::TypeOther,
         Kind::Param, Kind::ReturnType,
         Kind::ItemOther, Kind::ImplItemOther, Kind::TraitItemOther,
-        Kind::Suggestion,
+        Kind::Suggestion, Kind::Conflict,
         Kind::Reference,
         Kind::CsvDataset, Kind::CsvTable, Kind::CsvColumn,
+        Kind::Project, Kind::Package,
     ];
 }
 
@@ -403,10 +413,13 @@ impl std::str::FromStr for Kind {
             "impl_item_other" => Ok(Kind::ImplItemOther),
             "trait_item_other" => Ok(Kind::TraitItemOther),
             "suggestion" => Ok(Kind::Suggestion),
+            "conflict" => Ok(Kind::Conflict),
             "reference" => Ok(Kind::Reference),
             "csv_dataset" => Ok(Kind::CsvDataset),
             "csv_table" => Ok(Kind::CsvTable),
             "csv_column" => Ok(Kind::CsvColumn),
+            "project" => Ok(Kind::Project),
+            "package" => Ok(Kind::Package),
             other => Err(format!("unknown kind: {}", other)),
         }
     }
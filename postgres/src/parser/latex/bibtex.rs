@@ -36,7 +36,7 @@ pub fn parse_bibtex(
         // Build structured metadata for the entry
         let meta = entry_metadata(entry);
 
-        let entry_id = Uuid::new_v4().to_string();
+        let entry_id = Uuid::now_v7().to_string();
         nodes.push(NodeRow {
             id: entry_id.clone(),
             instance_id: instance_id.to_string(),
@@ -54,7 +54,7 @@ pub fn parse_bibtex(
         // Create child nodes for each field
         let fields = extract_fields(entry);
         for (field_pos, (field_name, field_value)) in fields.iter().enumerate() {
-            let field_id = Uuid::new_v4().to_string();
+            let field_id = Uuid::now_v7().to_string();
             nodes.push(NodeRow {
                 id: field_id,
                 instance_id: instance_id.to_string(),
@@ -0,0 +1,379 @@
+/// Citation health — what `link_citations` could not match, and DOI metadata
+/// for bibliography entries.
+///
+/// `citation_report` compares the keys cited by `latex_citation` nodes with
+/// the keys of `bib_entry` nodes, the same match `link_citations` makes.
+/// `enrich_bibtex` normalizes each entry's DOI (taking it from a doi.org
+/// `url` when the entry has none) and, with `doi_lookup`, fetches the DOI's
+/// CSL JSON from doi.org into the entry's `doi_metadata`. The DOI enricher
+/// worker runs the lookups every `kerai.doi_lookup_interval` seconds; it
+/// is off by default, since it reaches out to doi.org.
+use std::time::Duration;
+
+use pgrx::prelude::*;
+use serde_json::{json, Map, Value};
+
+/// How long one doi.org lookup may take.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Prefixes a DOI may be written with, lowercase.
+const DOI_PREFIXES: &[&str] = &[
+    "https://doi.org/",
+    "http://doi.org/",
+    "https://dx.doi.org/",
+    "http://dx.doi.org/",
+    "doi:",
+];
+
+/// Unresolved cite keys, duplicate bibliography entries and uncited
+/// entries, with the file each citation or entry is in.
+const REPORT_SQL: &str = "
+    WITH RECURSIVE cites AS (
+        SELECT c.id, c.parent_id, c.metadata, k.key
+        FROM kerai.nodes c
+        CROSS JOIN LATERAL jsonb_array_elements_text(
+            CASE WHEN jsonb_typeof(c.metadata->'keys') = 'array'
+                 THEN c.metadata->'keys' ELSE '[]'::jsonb END
+        ) AS k(key)
+        WHERE c.kind = 'latex_citation'
+    ), up AS (
+        -- Walk up from each citation to the file holding it
+        SELECT c.id AS cite_id, p.id, p.kind, p.parent_id, p.content
+        FROM (SELECT DISTINCT id, parent_id FROM cites) c
+        JOIN kerai.nodes p ON p.id = c.parent_id
+        UNION ALL
+        SELECT up.cite_id, p.id, p.kind, p.parent_id, p.content
+        FROM up JOIN kerai.nodes p ON p.id = up.parent_id
+        WHERE up.kind <> 'file'
+    ), located AS (
+        SELECT c.key, c.id, u.content AS file,
+               COALESCE((c.metadata->>'start_line')::int, (c.metadata->>'line')::int) AS line
+        FROM cites c
+        LEFT JOIN up u ON u.cite_id = c.id AND u.kind = 'file'
+    ), bib AS (
+        SELECT b.id, b.content AS key, lower(NULLIF(b.metadata->>'doi', '')) AS doi,
+               b.metadata->>'title' AS title, f.content AS file
+        FROM kerai.nodes b
+        LEFT JOIN kerai.nodes f ON f.id = b.parent_id
+        WHERE b.kind = 'bib_entry'
+    ), unresolved AS (
+        SELECT key, count(*) AS citations,
+               jsonb_agg(jsonb_build_object('file', file, 'line', line) ORDER BY file, line)
+                   AS places
+        FROM located l
+        WHERE NOT EXISTS (SELECT 1 FROM bib WHERE bib.key = l.key)
+        GROUP BY key
+    ), duplicates AS (
+        SELECT 'key' AS by, key AS value,
+               jsonb_agg(jsonb_build_object('id', id, 'key', key, 'file', file, 'title', title)
+                         ORDER BY file, id) AS entries
+        FROM bib GROUP BY key HAVING count(*) > 1
+        UNION ALL
+        SELECT 'doi', doi,
+               jsonb_agg(jsonb_build_object('id', id, 'key', key, 'file', file, 'title', title)
+                         ORDER BY key, file)
+        FROM bib WHERE doi IS NOT NULL GROUP BY doi HAVING count(DISTINCT key) > 1
+    )
+    SELECT jsonb_build_object(
+        'citations', (SELECT count(DISTINCT id) FROM cites),
+        'bib_entries', (SELECT count(*) FROM bib),
+        'unresolved', COALESCE((
+            SELECT jsonb_agg(jsonb_build_object('key', key, 'citations', citations,
+                                                'places', places) ORDER BY key)
+            FROM unresolved), '[]'::jsonb),
+        'duplicates', COALESCE((
+            SELECT jsonb_agg(jsonb_build_object('by', by, 'value', value, 'entries', entries)
+                             ORDER BY by DESC, value)
+            FROM duplicates), '[]'::jsonb),
+        'uncited', COALESCE((
+            SELECT jsonb_agg(jsonb_build_object('id', id, 'key', key, 'file', file,
+                                                'title', title) ORDER BY file, key)
+            FROM bib b WHERE NOT EXISTS (SELECT 1 FROM cites c WHERE c.key = b.key)), '[]'::jsonb)
+    )";
+
+/// Entries with a DOI and no `doi_metadata`, or a failed lookup more than
+/// a day old, up to `$1` of them.
+const PENDING_SQL: &str = "
+    SELECT COALESCE(jsonb_agg(jsonb_build_object('id', id, 'key', content,
+                                                 'doi', metadata->>'doi') ORDER BY content), '[]'::jsonb)
+    FROM (
+        SELECT id, content, metadata FROM kerai.nodes
+        WHERE kind = 'bib_entry' AND COALESCE(metadata->>'doi', '') <> ''
+          AND (NOT metadata ? 'doi_metadata'
+               OR (metadata->'doi_metadata' ? 'error'
+                   AND (metadata->'doi_metadata'->>'fetched_at')::timestamptz
+                       < now() - interval '1 day'))
+        ORDER BY content
+        LIMIT $1
+    ) p";
+
+/// Cite keys nothing defines, bibliography entries defined more than once,
+/// and entries nothing cites — what `link_citations` leaves unlinked.
+///
+/// Returns `{citations, bib_entries, unresolved: [{key, citations, places:
+/// [{file, line}]}], duplicates: [{by, value, entries: [{id, key, file,
+/// title}]}], uncited: [{id, key, file, title}]}`. A duplicate is `by`
+/// `key` when several entries share a cite key, or `doi` when entries
+/// with different keys share a DOI.
+#[pg_extern]
+fn citation_report() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(REPORT_SQL)
+        .unwrap()
+        .unwrap_or_else(|| pgrx::JsonB(json!({})))
+}
+
+/// A DOI as `10.prefix/suffix`, from a bare DOI, a `doi:` one or a
+/// doi.org link; `None` when `raw` is none of these.
+fn normalize_doi(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    let lower = raw.to_ascii_lowercase();
+    let start = DOI_PREFIXES
+        .iter()
+        .find(|p| lower.starts_with(*p))
+        .map_or(0, |p| p.len());
+    let doi = raw[start..].trim();
+    (doi.starts_with("10.") && doi.contains('/')).then(|| doi.to_string())
+}
+
+/// The fields of a CSL JSON record worth keeping: title, authors,
+/// container title, publisher, year, type, volume, issue, page and URL.
+/// Missing fields are left out.
+fn csl_summary(csl: &Value) -> Value {
+    // CSL titles may be a string or a list of them, volumes a number
+    let text = |v: &Value| match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Array(a) => a.first().and_then(Value::as_str).map(String::from),
+        _ => None,
+    };
+    let mut out = Map::new();
+    for (field, key) in [
+        ("title", "title"),
+        ("container-title", "container_title"),
+        ("publisher", "publisher"),
+        ("type", "type"),
+        ("volume", "volume"),
+        ("issue", "issue"),
+        ("page", "page"),
+        ("URL", "url"),
+    ] {
+        if let Some(s) = text(&csl[field]).filter(|s| !s.is_empty()) {
+            out.insert(key.into(), json!(s));
+        }
+    }
+    let authors: Vec<String> = csl["author"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|p| {
+            let name = [p["given"].as_str(), p["family"].as_str()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" ");
+            if name.is_empty() {
+                p["literal"].as_str().map(String::from)
+            } else {
+                Some(name)
+            }
+        })
+        .collect();
+    if !authors.is_empty() {
+        out.insert("authors".into(), json!(authors));
+    }
+    if let Some(year) = csl["issued"]["date-parts"][0][0].as_i64() {
+        out.insert("year".into(), json!(year));
+    }
+    Value::Object(out)
+}
+
+/// Fetch the CSL JSON record of `doi` from doi.org.
+fn lookup(http: &reqwest::blocking::Client, doi: &str) -> Result<Value, String> {
+    let mut url =
+        reqwest::Url::parse("https://doi.org/").map_err(|e| format!("Bad DOI resolver: {e}"))?;
+    url.set_path(doi);
+    let resp = http
+        .get(url)
+        .header("Accept", "application/vnd.citationstyles.csl+json")
+        .send()
+        .map_err(|e| format!("Lookup of {doi} failed: {e}"))?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(format!("doi.org returned {status} for {doi}"));
+    }
+    resp.json()
+        .map_err(|e| format!("Invalid CSL JSON for {doi}: {e}"))
+}
+
+/// Rewrite each entry's `doi` to its normalized form, filling it from a
+/// doi.org `url` when missing. Returns how many entries changed.
+fn normalize_entries() -> i64 {
+    let entries = Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', id, 'doi', metadata->>'doi', 'url', metadata->>'url')), '[]'::jsonb)
+         FROM kerai.nodes
+         WHERE kind = 'bib_entry'
+           AND (metadata ? 'doi' OR metadata->>'url' ILIKE '%doi.org/%')",
+    )
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+
+    let mut changed = 0;
+    for entry in entries.as_array().into_iter().flatten() {
+        let current = entry["doi"].as_str();
+        let doi = current
+            .and_then(normalize_doi)
+            .or_else(|| entry["url"].as_str().and_then(normalize_doi));
+        let Some(doi) = doi.filter(|d| Some(d.as_str()) != current) else {
+            continue;
+        };
+        Spi::run_with_args(
+            "UPDATE kerai.nodes SET metadata = metadata || jsonb_build_object('doi', $2)
+             WHERE id = $1::uuid",
+            &[entry["id"].as_str().unwrap_or_default().into(), doi.into()],
+        )
+        .unwrap();
+        changed += 1;
+    }
+    changed
+}
+
+/// Normalize DOIs and, with `doi_lookup`, fetch metadata for up to `limit`
+/// pending entries. Shared by `enrich_bibtex` and the DOI enricher worker.
+pub(crate) fn enrich(doi_lookup: bool, limit: i32) -> Value {
+    let normalized = normalize_entries();
+    let pending = Spi::get_one_with_args::<pgrx::JsonB>(PENDING_SQL, &[limit.max(1).into()])
+        .unwrap()
+        .map_or(json!([]), |j| j.0);
+    let pending = pending.as_array().cloned().unwrap_or_default();
+    if !doi_lookup {
+        let entries: Vec<Value> = pending
+            .into_iter()
+            .map(|mut e| {
+                e["status"] = json!("pending");
+                e
+            })
+            .collect();
+        return json!({
+            "normalized": normalized,
+            "enriched": 0,
+            "failed": 0,
+            "entries": entries,
+        });
+    }
+
+    let http = match reqwest::blocking::Client::builder()
+        .timeout(LOOKUP_TIMEOUT)
+        .user_agent(concat!("kerai/", env!("CARGO_PKG_VERSION")))
+        .build()
+    {
+        Ok(http) => http,
+        Err(e) => error!("Failed to build HTTP client: {}", e),
+    };
+    let (mut enriched, mut failed) = (0, 0);
+    let mut entries = Vec::with_capacity(pending.len());
+    for mut entry in pending {
+        let doi = entry["doi"].as_str().unwrap_or_default().to_string();
+        let stored = match lookup(&http, &doi) {
+            Ok(csl) => {
+                enriched += 1;
+                entry["status"] = json!("enriched");
+                csl_summary(&csl)
+            }
+            Err(e) => {
+                failed += 1;
+                entry["status"] = json!("failed");
+                entry["error"] = json!(e);
+                json!({"error": e})
+            }
+        };
+        Spi::run_with_args(
+            "UPDATE kerai.nodes
+             SET metadata = metadata || jsonb_build_object('doi_metadata',
+                 $2::jsonb || jsonb_build_object('fetched_at', now()))
+             WHERE id = $1::uuid",
+            &[
+                entry["id"].as_str().unwrap_or_default().into(),
+                pgrx::JsonB(stored).into(),
+            ],
+        )
+        .unwrap();
+        entries.push(entry);
+    }
+    json!({
+        "normalized": normalized,
+        "enriched": enriched,
+        "failed": failed,
+        "entries": entries,
+    })
+}
+
+/// Normalize the DOI of every `bib_entry` and, with `doi_lookup`, fetch
+/// CSL metadata from doi.org for up to `max_entries` entries that have a
+/// DOI but no `doi_metadata` yet (or whose lookup failed over a day ago),
+/// storing it in their `doi_metadata`: `{title, authors, container_title,
+/// publisher, year, type, volume, issue, page, url, fetched_at}`, or
+/// `{error, fetched_at}` when the lookup failed. Without `doi_lookup`
+/// nothing is fetched and the entries that would be are listed.
+///
+/// Returns `{normalized, enriched, failed, entries: [{id, key, doi,
+/// status, error}]}`, `status` being `enriched`, `failed` or `pending`.
+#[pg_extern]
+fn enrich_bibtex(doi_lookup: bool, max_entries: default!(i32, 50)) -> pgrx::JsonB {
+    pgrx::JsonB(enrich(doi_lookup, max_entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_doi_forms() {
+        assert_eq!(
+            normalize_doi("10.1000/xyz123").as_deref(),
+            Some("10.1000/xyz123")
+        );
+        assert_eq!(
+            normalize_doi(" doi:10.1000/ABC ").as_deref(),
+            Some("10.1000/ABC")
+        );
+        assert_eq!(
+            normalize_doi("https://dx.doi.org/10.1145/3290365").as_deref(),
+            Some("10.1145/3290365")
+        );
+        assert_eq!(
+            normalize_doi("HTTPS://DOI.ORG/10.5555/x").as_deref(),
+            Some("10.5555/x")
+        );
+        assert_eq!(normalize_doi("https://example.com/10.1/x"), None);
+        assert_eq!(normalize_doi("10.1000"), None);
+    }
+
+    #[test]
+    fn csl_summary_keeps_known_fields() {
+        let csl = json!({
+            "type": "article-journal",
+            "title": "Conflict-free Replicated Data Types",
+            "container-title": ["SSS 2011"],
+            "author": [
+                {"given": "Marc", "family": "Shapiro"},
+                {"family": "Preguiça"},
+                {"literal": "INRIA"},
+                {}
+            ],
+            "issued": {"date-parts": [[2011, 10]]},
+            "page": "",
+            "DOI": "10.1007/978-3-642-24550-3_29"
+        });
+        assert_eq!(
+            csl_summary(&csl),
+            json!({
+                "type": "article-journal",
+                "title": "Conflict-free Replicated Data Types",
+                "container_title": "SSS 2011",
+                "authors": ["Marc Shapiro", "Preguiça", "INRIA"],
+                "year": 2011
+            })
+        );
+    }
+}
@@ -52,6 +52,42 @@ pub const BIB_FIELD: &str = "bib_field";
 // Catch-all
 pub const LATEX_OTHER: &str = "latex_other";
 
+/// Every LaTeX kind, for the parser registry.
+pub const ALL: &[&str] = &[
+    LATEX_DOCUMENT,
+    LATEX_PREAMBLE,
+    LATEX_DOCUMENTCLASS,
+    LATEX_USEPACKAGE,
+    LATEX_PART,
+    LATEX_CHAPTER,
+    LATEX_SECTION,
+    LATEX_SUBSECTION,
+    LATEX_SUBSUBSECTION,
+    LATEX_PARAGRAPH,
+    LATEX_ENVIRONMENT,
+    LATEX_MATH_ENV,
+    LATEX_FIGURE,
+    LATEX_TABLE,
+    LATEX_THEOREM,
+    LATEX_DEFINITION,
+    LATEX_PROOF,
+    LATEX_INLINE_MATH,
+    LATEX_DISPLAY_MATH,
+    LATEX_CITATION,
+    LATEX_LABEL,
+    LATEX_REF,
+    LATEX_CAPTION,
+    LATEX_FOOTNOTE,
+    LATEX_INPUT,
+    LATEX_INCLUDE,
+    LATEX_COMMAND,
+    LATEX_TEXT,
+    LATEX_OTHER,
+];
+
+/// Every BibTeX kind.
+pub const BIB_ALL: &[&str] = &[BIB_ENTRY, BIB_FIELD];
+
 /// Semantic environments that get specialized kind constants.
 const THEOREM_ENVS: &[&str] = &[
     "theorem", "lemma", "proposition", "corollary", "conjecture", "claim",
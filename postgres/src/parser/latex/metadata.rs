@@ -27,7 +27,8 @@ pub fn section_metadata(node: &tree_sitter::Node, source: &str, cmd_name: &str)
 
 /// Extract metadata for an environment node.
 ///
-/// Metadata: env_name, optional args.
+/// Metadata: env_name, optional args, and the `begin` and `end` lines as
+/// written.
 pub fn environment_metadata(env_name: &str, node: &tree_sitter::Node, source: &str) -> Value {
     let mut meta = serde_json::Map::new();
     meta.insert("env_name".into(), json!(env_name));
@@ -37,6 +38,13 @@ pub fn environment_metadata(env_name: &str, node: &tree_sitter::Node, source: &s
         meta.insert("args".into(), json!(opt_arg));
     }
 
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.kind() == "begin" || child.kind() == "end" {
+            meta.insert(child.kind().into(), json!(node_text(&child, source)));
+        }
+    }
+
     meta.insert("source".into(), json!(node_text(node, source)));
     Value::Object(meta)
 }
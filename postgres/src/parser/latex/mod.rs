@@ -1,7 +1,8 @@
 /// LaTeX parser module — LaTeX/BibTeX source → kerai.nodes + kerai.edges via tree-sitter + biblatex.
 use pgrx::prelude::*;
 use serde_json::json;
-use std::path::Path;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use uuid::Uuid;
 
@@ -9,9 +10,11 @@ use crate::parser::ast_walker::{EdgeRow, NodeRow};
 use crate::parser::inserter;
 use crate::parser::kinds::Kind;
 use crate::parser::path_builder::PathContext;
+use crate::parser::registry::{self, LanguageParser, Placement};
 use crate::parser::treesitter::{self, TsLanguage};
 
 pub mod kinds;
+pub(crate) mod citations;
 mod metadata;
 mod walker;
 mod bibtex;
@@ -20,7 +23,7 @@ mod bibtex;
 ///
 /// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
 #[pg_extern]
-fn parse_latex_source(source: &str, filename: &str) -> pgrx::JsonB {
+pub(super) fn parse_latex_source(source: &str, filename: &str) -> pgrx::JsonB {
     let start = Instant::now();
     let instance_id = super::get_self_instance_id();
 
@@ -32,11 +35,8 @@ fn parse_latex_source(source: &str, filename: &str) -> pgrx::JsonB {
     // Auto-mint reward
     if node_count > 0 {
         let details = json!({"file": filename, "language": "latex", "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_latex_source', '{}'::jsonb)",
-            details_str,
-        ));
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), filename);
+        crate::currency::reward("parse_latex_source", "node", file_id.as_deref(), details);
     }
 
     let elapsed = start.elapsed();
@@ -78,11 +78,8 @@ fn parse_latex_file(path: &str) -> pgrx::JsonB {
     // Auto-mint reward
     if node_count > 0 {
         let details = json!({"file": filename, "language": "latex", "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_latex_file', '{}'::jsonb)",
-            details_str,
-        ));
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), &filename);
+        crate::currency::reward("parse_latex_file", "node", file_id.as_deref(), details);
     }
 
     let elapsed = start.elapsed();
@@ -99,7 +96,7 @@ fn parse_latex_file(path: &str) -> pgrx::JsonB {
 ///
 /// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
 #[pg_extern]
-fn parse_bibtex_source(source: &str, filename: &str) -> pgrx::JsonB {
+pub(super) fn parse_bibtex_source(source: &str, filename: &str) -> pgrx::JsonB {
     let start = Instant::now();
     let instance_id = super::get_self_instance_id();
 
@@ -111,11 +108,8 @@ fn parse_bibtex_source(source: &str, filename: &str) -> pgrx::JsonB {
     // Auto-mint reward
     if node_count > 0 {
         let details = json!({"file": filename, "language": "bibtex", "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_bibtex_source', '{}'::jsonb)",
-            details_str,
-        ));
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), filename);
+        crate::currency::reward("parse_bibtex_source", "node", file_id.as_deref(), details);
     }
 
     let elapsed = start.elapsed();
@@ -157,11 +151,8 @@ fn parse_bibtex_file(path: &str) -> pgrx::JsonB {
     // Auto-mint reward
     if node_count > 0 {
         let details = json!({"file": filename, "language": "bibtex", "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_bibtex_file', '{}'::jsonb)",
-            details_str,
-        ));
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), &filename);
+        crate::currency::reward("parse_bibtex_file", "node", file_id.as_deref(), details);
     }
 
     let elapsed = start.elapsed();
@@ -178,6 +169,7 @@ fn parse_bibtex_file(path: &str) -> pgrx::JsonB {
 ///
 /// Finds all `latex_citation` nodes and matches their keys to `bib_entry` nodes,
 /// creating `cites` edges. This should be called after parsing both .tex and .bib files.
+/// `citation_report()` lists the keys it could not match.
 ///
 /// Returns JSON: `{linked, unresolved}`.
 #[pg_extern]
@@ -264,7 +256,7 @@ fn link_citations() -> pgrx::JsonB {
         for key in keys {
             if let Some(bib_node_id) = bib_map.get(key) {
                 edges.push(EdgeRow {
-                    id: Uuid::new_v4().to_string(),
+                    id: Uuid::now_v7().to_string(),
                     source_id: cite_node_id.clone(),
                     target_id: bib_node_id.clone(),
                     relation: "cites".to_string(),
@@ -289,6 +281,189 @@ fn link_citations() -> pgrx::JsonB {
     }))
 }
 
+/// Parse a multi-file LaTeX project from its root `.tex` file on disk.
+///
+/// Follows `\input` and `\include` from the root, parsing every file they
+/// reach once, and joins each including file node to the file it includes
+/// with an `includes` edge. Paths resolve against the root's directory, as
+/// a `latex` run there would: `\include` always adds `.tex`, `\input` adds
+/// it when the name does not exist as given. Files are named by their path
+/// relative to that directory. Finishes with `link_citations()`, so the
+/// project's citations reach any parsed `.bib` entries.
+///
+/// Returns JSON: `{root, files, nodes, edges, includes, missing, citations, elapsed_ms}`,
+/// `missing` listing `{file, path}` for includes naming no readable file.
+#[pg_extern]
+fn parse_latex_project(root_tex: &str) -> pgrx::JsonB {
+    let start = Instant::now();
+    let root = Path::new(root_tex);
+    if !root.is_file() {
+        pgrx::error!("File does not exist: {}", root_tex);
+    }
+    let dir = root.parent().unwrap_or_else(|| Path::new(""));
+    let instance_id = super::get_self_instance_id();
+
+    let mut queue = VecDeque::from([root.to_path_buf()]);
+    let mut queued: HashSet<PathBuf> = HashSet::from([canonical(root)]);
+    let mut file_ids: HashMap<PathBuf, String> = HashMap::new();
+    // (including file node, included file, input node, command, path as written)
+    let mut links: Vec<(String, PathBuf, String, String, String)> = Vec::new();
+    let mut files = Vec::new();
+    let mut missing = Vec::new();
+    let (mut node_count, mut edge_count) = (0, 0);
+
+    while let Some(file) = queue.pop_front() {
+        let name = project_name(dir, &file);
+        let source = match std::fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(e) => {
+                warning!("Failed to read {}: {}", file.display(), e);
+                continue;
+            }
+        };
+
+        // Delete existing nodes for this file (idempotent re-parse)
+        inserter::delete_file_nodes(&instance_id, &name);
+        let Some(parsed) = insert_latex(&source, &name, &instance_id, None) else {
+            continue;
+        };
+        node_count += parsed.node_count;
+        edge_count += parsed.edge_count;
+
+        for (input_id, command, path) in parsed.inputs {
+            let Some(target) = resolve_input(dir, &command, &path) else {
+                missing.push(json!({"file": name, "path": path}));
+                continue;
+            };
+            let key = canonical(&target);
+            if queued.insert(key.clone()) {
+                queue.push_back(target);
+            }
+            links.push((parsed.file_node_id.clone(), key, input_id, command, path));
+        }
+        file_ids.insert(canonical(&file), parsed.file_node_id);
+        files.push(name);
+    }
+
+    let edges: Vec<EdgeRow> = links
+        .into_iter()
+        .filter_map(|(source_id, target, input_id, command, path)| {
+            Some(EdgeRow {
+                id: Uuid::now_v7().to_string(),
+                source_id,
+                target_id: file_ids.get(&target)?.clone(),
+                relation: "includes".to_string(),
+                metadata: json!({"command": command, "path": path, "input": input_id}),
+            })
+        })
+        .collect();
+    let include_count = edges.len();
+    inserter::insert_edges(&edges);
+
+    let pgrx::JsonB(citations) = link_citations();
+
+    if node_count > 0 {
+        let details = json!({"file": files[0], "language": "latex", "files": files.len(), "nodes": node_count, "edges": edge_count + include_count});
+        let root_id = file_ids.get(&canonical(root)).map(String::as_str);
+        crate::currency::reward("parse_latex_project", "node", root_id, details);
+    }
+
+    pgrx::JsonB(json!({
+        "root": files.first(),
+        "files": files,
+        "nodes": node_count,
+        "edges": edge_count + include_count,
+        "includes": include_count,
+        "missing": missing,
+        "citations": citations,
+        "elapsed_ms": start.elapsed().as_millis() as u64,
+    }))
+}
+
+/// The file an `\input{path}` or `\include{path}` in a project rooted at
+/// `dir` reads, if it exists.
+fn resolve_input(dir: &Path, command: &str, path: &str) -> Option<PathBuf> {
+    let given = dir.join(path);
+    let mut with_tex = given.clone().into_os_string();
+    with_tex.push(".tex");
+    let with_tex = PathBuf::from(with_tex);
+    if command == "\\include" {
+        return with_tex.is_file().then_some(with_tex);
+    }
+    if given.is_file() {
+        Some(given)
+    } else {
+        with_tex.is_file().then_some(with_tex)
+    }
+}
+
+/// Name of a project file: its path relative to the project directory,
+/// or as joined when it lies outside it.
+fn project_name(dir: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(dir).unwrap_or(file);
+    relative
+        .components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// A path with symlinks and `..` resolved, for telling files apart.
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// LaTeX in the parser registry.
+pub(crate) struct Latex;
+
+impl LanguageParser for Latex {
+    fn name(&self) -> &'static str {
+        "latex"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tex", "sty", "cls"]
+    }
+
+    fn kinds(&self) -> Vec<&'static str> {
+        registry::kind_list(&[Kind::File], kinds::ALL)
+    }
+
+    fn parse_source(&self, source: &str, filename: &str) -> pgrx::JsonB {
+        parse_latex_source(source, filename)
+    }
+
+    fn walk(&self, source: &str, filename: &str, at: &Placement) -> (usize, usize) {
+        parse_latex_single(source, filename, at.instance_id, at.parent_id)
+    }
+}
+
+/// BibTeX in the parser registry.
+pub(crate) struct Bibtex;
+
+impl LanguageParser for Bibtex {
+    fn name(&self) -> &'static str {
+        "bibtex"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["bib"]
+    }
+
+    fn kinds(&self) -> Vec<&'static str> {
+        registry::kind_list(&[Kind::File], kinds::BIB_ALL)
+    }
+
+    fn parse_source(&self, source: &str, filename: &str) -> pgrx::JsonB {
+        parse_bibtex_source(source, filename)
+    }
+
+    fn walk(&self, source: &str, filename: &str, at: &Placement) -> (usize, usize) {
+        parse_bibtex_single(source, filename, at.instance_id, at.parent_id)
+    }
+}
+
 /// Parse LaTeX source, insert nodes/edges, return counts.
 pub(crate) fn parse_latex_single(
     source: &str,
@@ -296,17 +471,39 @@ pub(crate) fn parse_latex_single(
     instance_id: &str,
     parent_id: Option<&str>,
 ) -> (usize, usize) {
+    match insert_latex(source, filename, instance_id, parent_id) {
+        Some(parsed) => (parsed.node_count, parsed.edge_count),
+        None => (0, 0),
+    }
+}
+
+/// What [`insert_latex`] stored for one file.
+struct ParsedLatex {
+    file_node_id: String,
+    node_count: usize,
+    edge_count: usize,
+    /// `(node id, command, path)` of each `\input`/`\include` with a path.
+    inputs: Vec<(String, String, String)>,
+}
+
+/// Parse LaTeX source and insert its file node, nodes and edges.
+fn insert_latex(
+    source: &str,
+    filename: &str,
+    instance_id: &str,
+    parent_id: Option<&str>,
+) -> Option<ParsedLatex> {
     // Parse with tree-sitter
     let tree = match treesitter::parse(source, TsLanguage::Latex) {
         Some(t) => t,
         None => {
             warning!("Failed to parse LaTeX source: {}", filename);
-            return (0, 0);
+            return None;
         }
     };
 
     // Create file node
-    let file_node_id = Uuid::new_v4().to_string();
+    let file_node_id = Uuid::now_v7().to_string();
     let path_ctx = PathContext::with_root(filename);
 
     let file_node = NodeRow {
@@ -328,13 +525,28 @@ pub(crate) fn parse_latex_single(
     let (nodes, edges, _pending_cites) =
         walker::walk_latex_file(&tree, source, &file_node_id, instance_id, path_ctx);
 
+    let inputs = nodes
+        .iter()
+        .filter(|n| n.kind == kinds::LATEX_INPUT || n.kind == kinds::LATEX_INCLUDE)
+        .filter_map(|n| {
+            let command = n.metadata.get("command")?.as_str()?;
+            let path = n.metadata.get("path")?.as_str()?.trim();
+            (!path.is_empty()).then(|| (n.id.clone(), command.to_string(), path.to_string()))
+        })
+        .collect();
+
     let node_count = nodes.len() + 1; // +1 for file node
     let edge_count = edges.len();
 
     inserter::insert_nodes(&nodes);
     inserter::insert_edges(&edges);
 
-    (node_count, edge_count)
+    Some(ParsedLatex {
+        file_node_id,
+        node_count,
+        edge_count,
+        inputs,
+    })
 }
 
 /// Parse BibTeX source, insert nodes/edges, return counts.
@@ -345,7 +557,7 @@ pub(crate) fn parse_bibtex_single(
     parent_id: Option<&str>,
 ) -> (usize, usize) {
     // Create file node
-    let file_node_id = Uuid::new_v4().to_string();
+    let file_node_id = Uuid::now_v7().to_string();
     let mut path_ctx = PathContext::with_root(filename);
 
     let file_node = NodeRow {
@@ -375,3 +587,54 @@ pub(crate) fn parse_bibtex_single(
 
     (node_count, edge_count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_adds_tex_only_when_needed_include_always() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(tmp.path().join("chapters")).unwrap();
+        for file in [
+            "chapters/intro.tex",
+            "table.tikz",
+            "appendix.tex",
+            "appendix",
+        ] {
+            std::fs::write(tmp.path().join(file), "").unwrap();
+        }
+        let dir = tmp.path();
+        assert_eq!(
+            resolve_input(dir, "\\input", "chapters/intro"),
+            Some(dir.join("chapters/intro.tex"))
+        );
+        assert_eq!(
+            resolve_input(dir, "\\input", "table.tikz"),
+            Some(dir.join("table.tikz"))
+        );
+        assert_eq!(
+            resolve_input(dir, "\\input", "appendix"),
+            Some(dir.join("appendix"))
+        );
+        assert_eq!(
+            resolve_input(dir, "\\include", "appendix"),
+            Some(dir.join("appendix.tex"))
+        );
+        assert_eq!(resolve_input(dir, "\\input", "nowhere"), None);
+    }
+
+    #[test]
+    fn project_names_are_relative_to_the_root() {
+        let dir = Path::new("/papers/thesis");
+        assert_eq!(project_name(dir, &dir.join("main.tex")), "main.tex");
+        assert_eq!(
+            project_name(dir, &dir.join("./chapters/intro.tex")),
+            "chapters/intro.tex"
+        );
+        assert_eq!(
+            project_name(Path::new(""), Path::new("main.tex")),
+            "main.tex"
+        );
+    }
+}
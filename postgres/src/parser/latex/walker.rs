@@ -1,4 +1,8 @@
 /// LaTeX CST walker — converts tree-sitter LaTeX parse tree into NodeRow/EdgeRow vectors.
+///
+/// Source between the recognized elements (prose, formatting commands,
+/// comments, `\begin{document}`) is kept as `latex_text` nodes, and positions
+/// follow document order, so `reconstruct_latex` can write the file back.
 use std::collections::HashMap;
 
 use serde_json::json;
@@ -26,39 +30,81 @@ struct LatexWalkCtx {
     pending_cites: Vec<(String, Vec<String>)>,
     /// Section stack for path building: (depth, node_id, name)
     section_stack: Vec<(u8, String, String)>,
+    /// Position of the next node, counting in document order
+    next_position: i32,
+    /// Byte offset up to which the source is covered by nodes
+    cursor: usize,
 }
 
 impl LatexWalkCtx {
-    fn new_node(
+    fn push_node(
         &mut self,
         kind: &str,
         content: Option<String>,
-        parent_id: Option<&str>,
-        position: i32,
+        parent_id: &str,
         meta: serde_json::Value,
         span_start: Option<i32>,
         span_end: Option<i32>,
     ) -> String {
-        let id = Uuid::new_v4().to_string();
+        let id = Uuid::now_v7().to_string();
         self.nodes.push(NodeRow {
             id: id.clone(),
             instance_id: self.instance_id.clone(),
             kind: kind.to_string(),
             language: Some("latex".to_string()),
             content,
-            parent_id: parent_id.map(std::string::ToString::to_string),
-            position,
+            parent_id: Some(parent_id.to_string()),
+            position: self.next_position,
             path: self.path_ctx.path(),
             metadata: meta,
             span_start,
             span_end,
         });
+        self.next_position += 1;
+        id
+    }
+
+    /// Keep the source from the cursor up to `upto` as a text node.
+    fn fill_gap(&mut self, parent_id: &str, upto: usize) {
+        if upto > self.cursor {
+            let text = self.source[self.cursor..upto].to_string();
+            self.push_node(
+                kinds::LATEX_TEXT,
+                Some(text),
+                parent_id,
+                json!({}),
+                None,
+                None,
+            );
+            self.cursor = upto;
+        }
+    }
+
+    /// Node for the whole of a tree-sitter node, after the text before it.
+    fn new_node(
+        &mut self,
+        kind: &str,
+        content: Option<String>,
+        parent_id: &str,
+        meta: serde_json::Value,
+        node: &tree_sitter::Node,
+    ) -> String {
+        self.fill_gap(parent_id, node.start_byte());
+        let id = self.push_node(
+            kind,
+            content,
+            parent_id,
+            meta,
+            Some(span_start_line(node)),
+            Some(span_end_line(node)),
+        );
+        self.cursor = self.cursor.max(node.end_byte());
         id
     }
 
     fn new_edge(&mut self, source_id: &str, target_id: &str, relation: &str) {
         self.edges.push(EdgeRow {
-            id: Uuid::new_v4().to_string(),
+            id: Uuid::now_v7().to_string(),
             source_id: source_id.to_string(),
             target_id: target_id.to_string(),
             relation: relation.to_string(),
@@ -110,16 +156,19 @@ pub fn walk_latex_file(
         pending_refs: Vec::new(),
         pending_cites: Vec::new(),
         section_stack: Vec::new(),
+        next_position: 0,
+        cursor: 0,
     };
 
     let root = tree.root_node();
     walk_children(&mut ctx, &root, file_node_id);
+    ctx.fill_gap(file_node_id, source.len());
 
     // Post-walk: resolve \ref → \label edges
     for (ref_node_id, label_key) in &ctx.pending_refs {
         if let Some(target_id) = ctx.label_map.get(label_key) {
             ctx.edges.push(EdgeRow {
-                id: Uuid::new_v4().to_string(),
+                id: Uuid::now_v7().to_string(),
                 source_id: ref_node_id.clone(),
                 target_id: target_id.clone(),
                 relation: "references".to_string(),
@@ -136,47 +185,57 @@ pub fn walk_latex_file(
 fn walk_children(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let mut cursor = node.walk();
     let children: Vec<_> = node.children(&mut cursor).collect();
-    for (i, child) in children.iter().enumerate() {
-        walk_node(ctx, child, parent_id, i as i32);
+    for child in &children {
+        walk_node(ctx, child, parent_id);
     }
 }
 
 /// Dispatch a single tree-sitter node to the appropriate handler.
-fn walk_node(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str, position: i32) {
+fn walk_node(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     match node.kind() {
         // Generic commands (\command_name{args})
         "generic_command" | "new_command_definition" | "title_declaration" => {
-            walk_generic_command(ctx, node, parent_id, position);
+            walk_generic_command(ctx, node, parent_id);
         }
 
         // Environments (\begin{...}...\end{...})
         "generic_environment" | "math_environment" => {
-            walk_environment(ctx, node, parent_id, position);
+            walk_environment(ctx, node, parent_id);
         }
 
         // Inline math $...$
         "inline_formula" => {
-            walk_inline_math(ctx, node, parent_id, position);
+            walk_inline_math(ctx, node, parent_id);
         }
 
         // Display math \[...\] or $$...$$
         "displayed_equation" => {
-            walk_display_math(ctx, node, parent_id, position);
+            walk_display_math(ctx, node, parent_id);
         }
 
         // Package inclusion
         "package_include" => {
-            walk_usepackage(ctx, node, parent_id, position);
+            walk_usepackage(ctx, node, parent_id);
         }
 
         // Document class
         "class_include" => {
-            walk_documentclass(ctx, node, parent_id, position);
+            walk_documentclass(ctx, node, parent_id);
         }
 
         // Import commands (\input, \include)
         "import" | "latex_include" => {
-            walk_input(ctx, node, parent_id, position);
+            walk_input(ctx, node, parent_id);
+        }
+
+        // Commands the grammar gives their own node kinds
+        "citation" | "label_definition" | "label_reference" | "caption" => {
+            walk_generic_command(ctx, node, parent_id);
+        }
+
+        // Sectioning nodes holding their body
+        "part" | "chapter" | "section" | "subsection" | "subsubsection" | "paragraph" => {
+            walk_section_body(ctx, node, parent_id);
         }
 
         // Text blocks and paragraphs
@@ -185,14 +244,17 @@ fn walk_node(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str,
         }
 
         // Recurse into structural nodes
-        "document" | "preamble" | "curly_group" | "curly_group_text"
-        | "curly_group_text_list" | "curly_group_command" => {
+        "document"
+        | "preamble"
+        | "curly_group"
+        | "curly_group_text"
+        | "curly_group_text_list"
+        | "curly_group_command" => {
             walk_children(ctx, node, parent_id);
         }
 
         // Skip anonymous/whitespace/comment nodes
-        "comment" | "line_comment" | "block_comment"
-        | "ERROR" | "MISSING" => {}
+        "comment" | "line_comment" | "block_comment" | "ERROR" | "MISSING" => {}
 
         // For all other named nodes, recurse into their children
         _ if node.is_named() => {
@@ -207,56 +269,49 @@ fn walk_node(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str,
 ///
 /// Dispatches to specialized handlers based on the command name
 /// (section, cite, label, ref, footnote, caption, input/include).
-fn walk_generic_command(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-) {
+fn walk_generic_command(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let source = ctx.source.clone();
     let cmd_name = extract_command_name(node, &source);
 
     match cmd_name.as_str() {
         // Sectioning commands
-        "\\part" | "\\part*" | "\\chapter" | "\\chapter*"
-        | "\\section" | "\\section*" | "\\subsection" | "\\subsection*"
-        | "\\subsubsection" | "\\subsubsection*"
+        "\\part" | "\\part*" | "\\chapter" | "\\chapter*" | "\\section" | "\\section*"
+        | "\\subsection" | "\\subsection*" | "\\subsubsection" | "\\subsubsection*"
         | "\\paragraph" | "\\paragraph*" => {
-            walk_section(ctx, node, parent_id, position, &cmd_name);
+            walk_section(ctx, node, parent_id, &cmd_name);
         }
 
         // Citations
-        "\\cite" | "\\citep" | "\\citet" | "\\citealt" | "\\citealp"
-        | "\\citeauthor" | "\\citeyear" | "\\citetext"
-        | "\\autocite" | "\\textcite" | "\\parencite"
-        | "\\Cite" | "\\Citep" | "\\Citet" => {
-            walk_citation(ctx, node, parent_id, position, &cmd_name);
+        "\\cite" | "\\citep" | "\\citet" | "\\citealt" | "\\citealp" | "\\citeauthor"
+        | "\\citeyear" | "\\citetext" | "\\autocite" | "\\textcite" | "\\parencite" | "\\Cite"
+        | "\\Citep" | "\\Citet" => {
+            walk_citation(ctx, node, parent_id, &cmd_name);
         }
 
         // Labels
         "\\label" => {
-            walk_label(ctx, node, parent_id, position);
+            walk_label(ctx, node, parent_id);
         }
 
         // References
-        "\\ref" | "\\eqref" | "\\pageref" | "\\nameref"
-        | "\\autoref" | "\\cref" | "\\Cref" | "\\vref" => {
-            walk_ref(ctx, node, parent_id, position, &cmd_name);
+        "\\ref" | "\\eqref" | "\\pageref" | "\\nameref" | "\\autoref" | "\\cref" | "\\Cref"
+        | "\\vref" => {
+            walk_ref(ctx, node, parent_id, &cmd_name);
         }
 
         // Captions
         "\\caption" => {
-            walk_caption(ctx, node, parent_id, position);
+            walk_caption(ctx, node, parent_id);
         }
 
         // Footnotes
         "\\footnote" => {
-            walk_footnote(ctx, node, parent_id, position);
+            walk_footnote(ctx, node, parent_id);
         }
 
         // File inclusion
         "\\input" | "\\include" => {
-            walk_input_cmd(ctx, node, parent_id, position, &cmd_name);
+            walk_input_cmd(ctx, node, parent_id, &cmd_name);
         }
 
         // Other commands — create a generic node only if interesting
@@ -264,15 +319,7 @@ fn walk_generic_command(
             // Skip common formatting commands to avoid noise
             if !is_formatting_command(&cmd_name) {
                 let meta = metadata::command_metadata(node, &source, &cmd_name);
-                ctx.new_node(
-                    kinds::LATEX_COMMAND,
-                    Some(cmd_name),
-                    Some(parent_id),
-                    position,
-                    meta,
-                    Some(span_start_line(node)),
-                    Some(span_end_line(node)),
-                );
+                ctx.new_node(kinds::LATEX_COMMAND, Some(cmd_name), parent_id, meta, node);
             }
         }
     }
@@ -283,7 +330,6 @@ fn walk_section(
     ctx: &mut LatexWalkCtx,
     node: &tree_sitter::Node,
     file_node_id: &str,
-    position: i32,
     cmd_name: &str,
 ) {
     let source = ctx.source.clone();
@@ -305,18 +351,55 @@ fn walk_section(
     let path_segment = sanitize_path_segment(&title);
     ctx.path_ctx.push(&path_segment);
 
-    let section_id = ctx.new_node(
+    let section_id = ctx.new_node(kind, Some(title.clone()), &parent_id, meta, node);
+
+    ctx.section_stack.push((depth, section_id, path_segment));
+}
+
+/// Walk a sectioning node that holds its body (`section`, `subsection`, ...).
+///
+/// The section node is the heading, `\section[short]{Title}`; the body after
+/// it is walked beneath the section.
+fn walk_section_body(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
+    let Some(title_group) = node.child_by_field_name("text") else {
+        walk_children(ctx, node, parent_id);
+        return;
+    };
+    let source = ctx.source.clone();
+    let heading = &source[node.start_byte()..title_group.end_byte()];
+    let cmd_name = command_prefix(heading);
+    let kind =
+        kinds::section_cmd_to_kind(cmd_name.trim_end_matches('*')).unwrap_or(kinds::LATEX_COMMAND);
+
+    let mut meta = metadata::section_metadata(node, &source, cmd_name);
+    meta["source"] = json!(heading);
+    let title = meta
+        .get("title")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string();
+
+    ctx.fill_gap(parent_id, node.start_byte());
+    let section_id = ctx.push_node(
         kind,
         Some(title.clone()),
-        Some(&parent_id),
-        position,
+        parent_id,
         meta,
         Some(span_start_line(node)),
         Some(span_end_line(node)),
     );
+    ctx.cursor = title_group.end_byte();
 
-    ctx.section_stack
-        .push((depth, section_id, path_segment));
+    ctx.path_ctx.push(&sanitize_path_segment(&title));
+    let mut cursor = node.walk();
+    let body: Vec<_> = node
+        .children(&mut cursor)
+        .filter(|c| c.start_byte() >= title_group.end_byte())
+        .collect();
+    for child in &body {
+        walk_node(ctx, child, &section_id);
+    }
+    ctx.path_ctx.pop();
 }
 
 /// Walk a citation command.
@@ -324,7 +407,6 @@ fn walk_citation(
     ctx: &mut LatexWalkCtx,
     node: &tree_sitter::Node,
     parent_id: &str,
-    position: i32,
     cmd_name: &str,
 ) {
     let source = ctx.source.clone();
@@ -342,15 +424,7 @@ fn walk_citation(
 
     let keys_str = keys.join(", ");
 
-    let cite_id = ctx.new_node(
-        kinds::LATEX_CITATION,
-        Some(keys_str),
-        Some(parent_id),
-        position,
-        meta,
-        Some(span_start_line(node)),
-        Some(span_end_line(node)),
-    );
+    let cite_id = ctx.new_node(kinds::LATEX_CITATION, Some(keys_str), parent_id, meta, node);
 
     if !keys.is_empty() {
         ctx.pending_cites.push((cite_id, keys));
@@ -358,12 +432,7 @@ fn walk_citation(
 }
 
 /// Walk a \label command.
-fn walk_label(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-) {
+fn walk_label(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let source = ctx.source.clone();
     let meta = metadata::label_metadata(node, &source);
 
@@ -373,15 +442,7 @@ fn walk_label(
         .unwrap_or("")
         .to_string();
 
-    let label_id = ctx.new_node(
-        kinds::LATEX_LABEL,
-        Some(key.clone()),
-        Some(parent_id),
-        position,
-        meta,
-        Some(span_start_line(node)),
-        Some(span_end_line(node)),
-    );
+    let label_id = ctx.new_node(kinds::LATEX_LABEL, Some(key.clone()), parent_id, meta, node);
 
     // Register in label_map: the label is attached to the parent element
     if !key.is_empty() {
@@ -390,13 +451,7 @@ fn walk_label(
 }
 
 /// Walk a \ref command.
-fn walk_ref(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-    cmd_name: &str,
-) {
+fn walk_ref(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str, cmd_name: &str) {
     let source = ctx.source.clone();
     let meta = metadata::ref_metadata(node, &source, cmd_name);
 
@@ -406,15 +461,7 @@ fn walk_ref(
         .unwrap_or("")
         .to_string();
 
-    let ref_id = ctx.new_node(
-        kinds::LATEX_REF,
-        Some(key.clone()),
-        Some(parent_id),
-        position,
-        meta,
-        Some(span_start_line(node)),
-        Some(span_end_line(node)),
-    );
+    let ref_id = ctx.new_node(kinds::LATEX_REF, Some(key.clone()), parent_id, meta, node);
 
     if !key.is_empty() {
         ctx.pending_refs.push((ref_id, key));
@@ -422,12 +469,7 @@ fn walk_ref(
 }
 
 /// Walk a \caption command.
-fn walk_caption(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-) {
+fn walk_caption(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let source = ctx.source.clone();
     let meta = metadata::caption_metadata(node, &source);
 
@@ -436,24 +478,11 @@ fn walk_caption(
         .and_then(|v| v.as_str())
         .map(std::string::ToString::to_string);
 
-    ctx.new_node(
-        kinds::LATEX_CAPTION,
-        text,
-        Some(parent_id),
-        position,
-        meta,
-        Some(span_start_line(node)),
-        Some(span_end_line(node)),
-    );
+    ctx.new_node(kinds::LATEX_CAPTION, text, parent_id, meta, node);
 }
 
 /// Walk a \footnote command.
-fn walk_footnote(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-) {
+fn walk_footnote(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let source = ctx.source.clone();
     let meta = metadata::command_metadata(node, &source, "\\footnote");
 
@@ -462,15 +491,7 @@ fn walk_footnote(
         .and_then(|v| v.as_str())
         .map(std::string::ToString::to_string);
 
-    ctx.new_node(
-        kinds::LATEX_FOOTNOTE,
-        text,
-        Some(parent_id),
-        position,
-        meta,
-        Some(span_start_line(node)),
-        Some(span_end_line(node)),
-    );
+    ctx.new_node(kinds::LATEX_FOOTNOTE, text, parent_id, meta, node);
 }
 
 /// Walk an \input or \include command.
@@ -478,7 +499,6 @@ fn walk_input_cmd(
     ctx: &mut LatexWalkCtx,
     node: &tree_sitter::Node,
     parent_id: &str,
-    position: i32,
     cmd_name: &str,
 ) {
     let source = ctx.source.clone();
@@ -494,24 +514,11 @@ fn walk_input_cmd(
         .and_then(|v| v.as_str())
         .map(std::string::ToString::to_string);
 
-    ctx.new_node(
-        kind,
-        path,
-        Some(parent_id),
-        position,
-        meta,
-        Some(span_start_line(node)),
-        Some(span_end_line(node)),
-    );
+    ctx.new_node(kind, path, parent_id, meta, node);
 }
 
 /// Walk a \usepackage command.
-fn walk_usepackage(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-) {
+fn walk_usepackage(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let source = ctx.source.clone();
     let meta = metadata::usepackage_metadata(node, &source);
 
@@ -520,24 +527,11 @@ fn walk_usepackage(
         .and_then(|v| v.as_str())
         .map(std::string::ToString::to_string);
 
-    ctx.new_node(
-        kinds::LATEX_USEPACKAGE,
-        pkg,
-        Some(parent_id),
-        position,
-        meta,
-        Some(span_start_line(node)),
-        Some(span_end_line(node)),
-    );
+    ctx.new_node(kinds::LATEX_USEPACKAGE, pkg, parent_id, meta, node);
 }
 
 /// Walk a \documentclass command.
-fn walk_documentclass(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-) {
+fn walk_documentclass(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let source = ctx.source.clone();
     let meta = metadata::documentclass_metadata(node, &source);
 
@@ -546,24 +540,11 @@ fn walk_documentclass(
         .and_then(|v| v.as_str())
         .map(std::string::ToString::to_string);
 
-    ctx.new_node(
-        kinds::LATEX_DOCUMENTCLASS,
-        class,
-        Some(parent_id),
-        position,
-        meta,
-        Some(span_start_line(node)),
-        Some(span_end_line(node)),
-    );
+    ctx.new_node(kinds::LATEX_DOCUMENTCLASS, class, parent_id, meta, node);
 }
 
 /// Walk an environment (\begin{env}...\end{env}).
-fn walk_environment(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-) {
+fn walk_environment(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let source = ctx.source.clone();
 
     // Extract environment name from the \begin{name} child
@@ -578,69 +559,62 @@ fn walk_environment(
 
     let meta = metadata::environment_metadata(&env_name, node, &source);
 
-    let env_id = ctx.new_node(
+    // The environment covers its \begin and \end; the body between them
+    // becomes its children
+    ctx.fill_gap(parent_id, node.start_byte());
+    let env_id = ctx.push_node(
         kind,
         Some(env_name),
-        Some(parent_id),
-        position,
+        parent_id,
         meta,
         Some(span_start_line(node)),
         Some(span_end_line(node)),
     );
 
-    // Recurse into environment body
-    walk_children(ctx, node, &env_id);
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    let mut body_end = node.end_byte();
+    for child in &children {
+        match child.kind() {
+            "begin" => ctx.cursor = child.end_byte(),
+            "end" => body_end = child.start_byte(),
+            _ => walk_node(ctx, child, &env_id),
+        }
+    }
+    ctx.fill_gap(&env_id, body_end);
+    ctx.cursor = node.end_byte();
 }
 
 /// Walk inline math ($..$ or \(...\)).
-fn walk_inline_math(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-) {
+fn walk_inline_math(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let source = ctx.source.clone();
     let text = node_text(node, &source).to_string();
 
     ctx.new_node(
         kinds::LATEX_INLINE_MATH,
         Some(text),
-        Some(parent_id),
-        position,
+        parent_id,
         json!({}),
-        Some(span_start_line(node)),
-        Some(span_end_line(node)),
+        node,
     );
 }
 
 /// Walk display math (\[..\] or $$..$$).
-fn walk_display_math(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-) {
+fn walk_display_math(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let source = ctx.source.clone();
     let text = node_text(node, &source).to_string();
 
     ctx.new_node(
         kinds::LATEX_DISPLAY_MATH,
         Some(text),
-        Some(parent_id),
-        position,
+        parent_id,
         json!({}),
-        Some(span_start_line(node)),
-        Some(span_end_line(node)),
+        node,
     );
 }
 
 /// Walk an \input or \include tree-sitter node (the import/latex_include kind).
-fn walk_input(
-    ctx: &mut LatexWalkCtx,
-    node: &tree_sitter::Node,
-    parent_id: &str,
-    position: i32,
-) {
+fn walk_input(ctx: &mut LatexWalkCtx, node: &tree_sitter::Node, parent_id: &str) {
     let source = ctx.source.clone();
     let text = node_text(node, &source);
     let cmd_name = if text.starts_with("\\include") {
@@ -649,7 +623,7 @@ fn walk_input(
         "\\input"
     };
 
-    walk_input_cmd(ctx, node, parent_id, position, cmd_name);
+    walk_input_cmd(ctx, node, parent_id, cmd_name);
 }
 
 /// Extract the command name from a generic_command node.
@@ -662,19 +636,23 @@ fn extract_command_name(node: &tree_sitter::Node, source: &str) -> String {
             return node_text(&child, source).to_string();
         }
     }
-    // Fallback: try the node text itself if it starts with \
-    let text = node_text(node, source);
-    if text.starts_with('\\') {
-        text.split_whitespace()
-            .next()
-            .unwrap_or(text)
-            .split('{')
-            .next()
-            .unwrap_or(text)
-            .to_string()
-    } else {
-        String::new()
+    // Fallback: the leading \name (or \name*) of the node text
+    command_prefix(node_text(node, source)).to_string()
+}
+
+/// The `\name` or `\name*` a command's text starts with, or `""`.
+fn command_prefix(text: &str) -> &str {
+    let Some(rest) = text.strip_prefix('\\') else {
+        return "";
+    };
+    let mut len = 1 + rest.len()
+        - rest
+            .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+            .len();
+    if text[len..].starts_with('*') {
+        len += 1;
     }
+    &text[..len]
 }
 
 /// Extract the environment name from a generic_environment node.
@@ -688,7 +666,9 @@ fn extract_env_name(node: &tree_sitter::Node, source: &str) -> Option<String> {
             let mut inner_cursor = child.walk();
             for inner in child.children(&mut inner_cursor) {
                 let k = inner.kind();
-                if k == "curly_group" || k == "curly_group_text" || k == "curly_group_text_list"
+                if k == "curly_group"
+                    || k == "curly_group_text"
+                    || k == "curly_group_text_list"
                     || k == "name"
                 {
                     let text = node_text(&inner, source);
@@ -726,17 +706,56 @@ fn sanitize_path_segment(s: &str) -> String {
 fn is_formatting_command(cmd: &str) -> bool {
     matches!(
         cmd,
-        "\\textbf" | "\\textit" | "\\emph" | "\\textrm" | "\\textsf" | "\\texttt"
-        | "\\textsc" | "\\textsl" | "\\textup" | "\\textnormal"
-        | "\\bf" | "\\it" | "\\em" | "\\rm" | "\\sf" | "\\tt" | "\\sc" | "\\sl"
-        | "\\small" | "\\large" | "\\Large" | "\\LARGE" | "\\huge" | "\\Huge"
-        | "\\tiny" | "\\scriptsize" | "\\footnotesize" | "\\normalsize"
-        | "\\centering" | "\\raggedright" | "\\raggedleft"
-        | "\\noindent" | "\\indent" | "\\par" | "\\newline" | "\\linebreak"
-        | "\\hspace" | "\\vspace" | "\\hfill" | "\\vfill"
-        | "\\maketitle" | "\\tableofcontents" | "\\listoffigures" | "\\listoftables"
-        | "\\clearpage" | "\\newpage" | "\\pagebreak"
-        | "\\medskip" | "\\bigskip" | "\\smallskip"
-        | "\\item"
+        "\\textbf"
+            | "\\textit"
+            | "\\emph"
+            | "\\textrm"
+            | "\\textsf"
+            | "\\texttt"
+            | "\\textsc"
+            | "\\textsl"
+            | "\\textup"
+            | "\\textnormal"
+            | "\\bf"
+            | "\\it"
+            | "\\em"
+            | "\\rm"
+            | "\\sf"
+            | "\\tt"
+            | "\\sc"
+            | "\\sl"
+            | "\\small"
+            | "\\large"
+            | "\\Large"
+            | "\\LARGE"
+            | "\\huge"
+            | "\\Huge"
+            | "\\tiny"
+            | "\\scriptsize"
+            | "\\footnotesize"
+            | "\\normalsize"
+            | "\\centering"
+            | "\\raggedright"
+            | "\\raggedleft"
+            | "\\noindent"
+            | "\\indent"
+            | "\\par"
+            | "\\newline"
+            | "\\linebreak"
+            | "\\hspace"
+            | "\\vspace"
+            | "\\hfill"
+            | "\\vfill"
+            | "\\maketitle"
+            | "\\tableofcontents"
+            | "\\listoffigures"
+            | "\\listoftables"
+            | "\\clearpage"
+            | "\\newpage"
+            | "\\pagebreak"
+            | "\\medskip"
+            | "\\bigskip"
+            | "\\smallskip"
+            | "\\item"
     )
 }
@@ -22,3 +22,34 @@ pub const STRIKETHROUGH: &str = "strikethrough";
 pub const INLINE_CODE: &str = "inline_code";
 pub const HARD_BREAK: &str = "hard_break";
 pub const HTML_BLOCK: &str = "html_block";
+
+// Vault import (`import_vault_file`)
+pub const VAULT: &str = "vault";
+pub const FOLDER: &str = "folder";
+pub const ASSET: &str = "asset";
+
+/// Every document kind `parse_markdown` produces, for the parser registry.
+pub const ALL: &[&str] = &[
+    DOCUMENT,
+    HEADING,
+    PARAGRAPH,
+    BLOCKQUOTE,
+    LIST,
+    LIST_ITEM,
+    CODE_BLOCK,
+    THEMATIC_BREAK,
+    LINK,
+    IMAGE,
+    TABLE,
+    TABLE_HEAD,
+    TABLE_ROW,
+    TABLE_CELL,
+    FOOTNOTE,
+    TEXT,
+    EMPHASIS,
+    STRONG,
+    STRIKETHROUGH,
+    INLINE_CODE,
+    HARD_BREAK,
+    HTML_BLOCK,
+];
@@ -0,0 +1,179 @@
+/// Links between markdown documents outside vaults.
+///
+/// The walker collects each document's relative links
+/// (`[setup](../guide.md#setup)`) and `[[wikilinks]]` into its `links`
+/// metadata, and `link_document` turns them into `links_to` edges from
+/// document to document. Targets resolve as in a vault, against every
+/// markdown document parsed outside one: a relative link from the linking
+/// document's folder, a wikilink from the root and then that folder, and
+/// either by file name when no path matches.
+///
+/// A document may be parsed before the ones it links to, and reparsing a
+/// document drops the edges into it, so each parse also links the other
+/// documents whose links lead to the new one.
+use std::collections::HashSet;
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::kinds;
+use super::vault::{folder_of, join, VaultIndex};
+use crate::parser::ast_walker::EdgeRow;
+use crate::parser::inserter;
+
+/// `%XX` escapes decoded, as in `my%20notes.md`.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = s
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The link `url` may make to another document, as `{target, fragment,
+/// url}`; None for URLs with a scheme, absolute paths and in-page anchors.
+pub(super) fn relative_link(url: &str) -> Option<Value> {
+    let url = url.trim();
+    let (rest, fragment) = match url.split_once('#') {
+        Some((rest, fragment)) => (rest, Some(fragment)),
+        None => (url, None),
+    };
+    let path = rest.split('?').next().unwrap_or("");
+    let scheme = path.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+    });
+    if path.is_empty() || path.starts_with('/') || scheme {
+        return None;
+    }
+    let fragment = fragment.map(percent_decode).filter(|f| !f.is_empty());
+    Some(json!({"target": percent_decode(path), "fragment": fragment, "url": url}))
+}
+
+/// The `[[wikilinks]]` in `source`, as `{target, heading, alias, embed,
+/// wikilink}`.
+pub(super) fn wikilinks(source: &str) -> Vec<Value> {
+    super::vault::wikilinks(source)
+        .iter()
+        .map(|link| {
+            let mut value = link.to_json();
+            value["wikilink"] = json!(link.target);
+            value
+        })
+        .collect()
+}
+
+/// Make the `links_to` edges out of document `doc_id` and into it from the
+/// other documents. Returns the number of edges made.
+pub(super) fn link_document(instance_id: &str, doc_id: &str) -> usize {
+    let documents = Spi::get_one_with_args::<pgrx::JsonB>(
+        &format!(
+            "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+                'id', id,
+                'path', content,
+                'links', metadata->'links'
+            ) ORDER BY content), '[]'::jsonb)
+            FROM kerai.nodes
+            WHERE instance_id = $1::uuid AND kind = '{}' AND language = 'markdown'
+              AND NOT metadata ? 'vault' AND {}",
+            kinds::DOCUMENT,
+            crate::sandboxes::unsandboxed("path"),
+        ),
+        &[instance_id.into()],
+    )
+    .unwrap()
+    .map_or(Value::Null, |j| j.0);
+    let documents = documents.as_array().cloned().unwrap_or_default();
+
+    let files: Vec<(String, String)> = documents
+        .iter()
+        .map(|d| {
+            let field = |name: &str| d[name].as_str().unwrap_or("").to_string();
+            (field("path"), field("id"))
+        })
+        .collect();
+    let index = VaultIndex::new(&files);
+
+    let mut edges = Vec::new();
+    let mut linked = HashSet::new();
+    for (document, (path, id)) in documents.iter().zip(&files) {
+        for link in document["links"].as_array().into_iter().flatten() {
+            let target = link["target"].as_str().unwrap_or("");
+            let resolved = if link["url"].is_string() {
+                index.resolve("", &join(folder_of(path), target))
+            } else {
+                index.resolve(path, target)
+            };
+            let Some(target_id) = resolved else {
+                continue;
+            };
+            if target_id == id.as_str() || (id != doc_id && target_id != doc_id) {
+                continue;
+            }
+            if linked.insert((id.as_str(), target_id)) {
+                let mut metadata = link.clone();
+                if let Some(map) = metadata.as_object_mut() {
+                    map.remove("target");
+                }
+                edges.push(EdgeRow {
+                    id: Uuid::now_v7().to_string(),
+                    source_id: id.clone(),
+                    target_id: target_id.to_string(),
+                    relation: "links_to".to_string(),
+                    metadata,
+                });
+            }
+        }
+    }
+    inserter::insert_edges(&edges);
+    edges.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_link_keeps_paths_and_skips_urls() {
+        let link = relative_link("../docs/My%20Guide.md?plain=1#set-up").unwrap();
+        assert_eq!(link["target"], "../docs/My Guide.md");
+        assert_eq!(link["fragment"], "set-up");
+        assert_eq!(link["url"], "../docs/My%20Guide.md?plain=1#set-up");
+        assert_eq!(relative_link("notes.md").unwrap()["fragment"], Value::Null);
+
+        for url in [
+            "https://example.com/a.md",
+            "mailto:me@example.com",
+            "#intro",
+            "/abs.md",
+            "",
+        ] {
+            assert_eq!(relative_link(url), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn percent_decode_leaves_stray_percents() {
+        assert_eq!(percent_decode("a%2Fb%20c"), "a/b c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%+1"), "%zz%+1");
+    }
+}
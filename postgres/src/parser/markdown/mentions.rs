@@ -0,0 +1,618 @@
+/// Code mentions — markdown code spans linked to the code they name.
+///
+/// Docs name functions and files in backticks (`` `parse_file()` ``,
+/// `` `kerai::sql::sql_escape` ``, `` `src/lib.rs` ``). After a markdown
+/// document is parsed, each code span in its paragraphs, headings, list
+/// items and table cells is matched against the symbols parsed from code
+/// (nodes of the kinds `kerai.find_fuzzy` searches, by name) and against
+/// file nodes (by path), and a `mentions` edge is made from the block to
+/// each match with a `confidence` in its metadata.
+///
+/// Confidence is shared out between equally good matches, so a name found
+/// in three places links to none of them unless the span narrows it down:
+/// a qualifier (`crdt::apply` keeps symbols with a `crdt` label in their
+/// path), a call (`apply()` keeps functions), a macro bang or a leading
+/// keyword (`struct Config`). Code parsed after the docs is picked up by
+/// running `kerai.link_code_mentions` again.
+use std::collections::HashMap;
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::kinds;
+use crate::parser::ast_walker::EdgeRow;
+use crate::parser::inserter;
+use crate::parser::path_builder::sanitize_label;
+
+/// Edges below this confidence are not made.
+const MIN_CONFIDENCE: f64 = 0.25;
+
+/// Markdown kinds whose content is inline source with code spans intact.
+const SPAN_KINDS: &[&str] = &[
+    kinds::PARAGRAPH,
+    kinds::HEADING,
+    kinds::LIST_ITEM,
+    kinds::TABLE_CELL,
+];
+
+/// Extensions that make a span a file path rather than `object.member`.
+const FILE_EXTENSIONS: &[&str] = &[
+    "rs", "go", "c", "h", "py", "ts", "js", "md", "tex", "toml", "yaml", "yml", "json", "csv",
+    "sql", "sh",
+];
+
+/// Spans too common to mean any one symbol.
+const STOP_WORDS: &[&str] = &[
+    "true", "false", "None", "Some", "Ok", "Err", "self", "Self", "null", "nil", "mut", "pub",
+];
+
+/// What a code span refers to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) enum Mention {
+    /// A file, by its path or a trailing part of it.
+    File(String),
+    /// A symbol by name, with the path segment before it and the kind the
+    /// span implies, if any.
+    Symbol {
+        name: String,
+        qualifier: Option<String>,
+        kind: Option<&'static str>,
+    },
+}
+
+/// The code spans in `text`: the text between matching runs of backticks,
+/// trimmed as CommonMark trims them.
+pub(super) fn code_spans(text: &str) -> Vec<&str> {
+    let mut spans = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('`') {
+        let after = &rest[open..];
+        let ticks = after.len() - after.trim_start_matches('`').len();
+        let body = &after[ticks..];
+        let fence = &after[..ticks];
+        // The closing run must be exactly as long as the opening one
+        let mut search = 0;
+        let mut close = None;
+        while let Some(at) = body[search..].find(fence) {
+            let at = search + at;
+            let run = body[at..].len() - body[at..].trim_start_matches('`').len();
+            if run == ticks {
+                close = Some(at);
+                break;
+            }
+            search = at + run;
+        }
+        match close {
+            Some(at) => {
+                let span = body[..at].trim();
+                if !span.is_empty() {
+                    spans.push(span);
+                }
+                rest = &body[at + ticks..];
+            }
+            None => rest = body,
+        }
+    }
+    spans
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// What `span` names, if it looks like a file path or a symbol.
+pub(super) fn classify(span: &str) -> Option<Mention> {
+    let span = span.trim();
+
+    // `src/lib.rs:42` or `./guide.md`
+    let path = span.trim_start_matches("./");
+    let path = match path.rsplit_once(':') {
+        Some((file, line)) if !line.is_empty() && line.bytes().all(|b| b.is_ascii_digit()) => file,
+        _ => path,
+    };
+    let is_path_char = |c: char| c.is_alphanumeric() || "_-./".contains(c);
+    if path.chars().all(is_path_char) && !path.contains("::") {
+        if let Some((stem, ext)) = path.rsplit_once('.') {
+            if FILE_EXTENSIONS.contains(&ext) && !stem.is_empty() && !stem.ends_with('/') {
+                return Some(Mention::File(path.to_string()));
+            }
+        }
+    }
+
+    // `fn apply`, `struct Config`
+    let (keyword_kind, body) = match span.split_once(' ') {
+        Some((keyword, rest)) => {
+            let kind = match keyword {
+                "fn" => "fn",
+                "struct" => "struct",
+                "enum" => "enum",
+                "trait" => "trait",
+                "const" => "const",
+                "static" => "static",
+                "type" => "type_alias",
+                "union" => "union",
+                _ => return None,
+            };
+            (Some(kind), rest.trim())
+        }
+        None => (None, span),
+    };
+
+    // `apply()`, `apply(op, 1)`, `Vec<T>`, `println!`
+    let mut body = body;
+    let mut kind = keyword_kind;
+    if let Some(open) = body.find('(') {
+        if !body.ends_with(')') {
+            return None;
+        }
+        body = &body[..open];
+        kind = kind.or(Some("fn"));
+    }
+    if let Some(open) = body.find('<') {
+        if !body.ends_with('>') {
+            return None;
+        }
+        body = &body[..open];
+    }
+    if let Some(macro_name) = body.strip_suffix('!') {
+        body = macro_name;
+        kind = Some("macro_def");
+    }
+
+    // `kerai::sql::sql_escape`, `Config::new`, `self.apply`
+    let segments: Vec<&str> = if body.contains("::") {
+        body.split("::").collect()
+    } else {
+        body.split('.').collect()
+    };
+    if !segments.iter().all(|s| is_identifier(s)) {
+        return None;
+    }
+    let name = *segments.last()?;
+    if name.chars().count() < 3 || STOP_WORDS.contains(&name) {
+        return None;
+    }
+    let qualifier = segments
+        .len()
+        .checked_sub(2)
+        .map(|i| segments[i])
+        .filter(|q| !matches!(*q, "self" | "Self" | "crate" | "super"));
+    Some(Mention::Symbol {
+        name: name.to_string(),
+        qualifier: qualifier.map(str::to_string),
+        kind,
+    })
+}
+
+/// Confidence in each of `candidates` equally good matches, given whether
+/// the span's qualifier and kind were found in them.
+pub(super) fn confidence(candidates: usize, qualified: bool, kind_fits: bool) -> f64 {
+    let sure = 0.7 + if qualified { 0.2 } else { 0.0 } + if kind_fits { 0.1 } else { 0.0 };
+    sure / candidates.max(1) as f64
+}
+
+/// A node a mention may resolve to.
+#[derive(Debug, Clone)]
+pub(super) struct Candidate {
+    pub id: String,
+    pub kind: String,
+    /// Symbol name, or file path (its `source_path` when it has one).
+    pub name: String,
+    /// ltree path labels.
+    pub labels: Vec<String>,
+}
+
+/// The candidates `mention` is linked to, each with its confidence; empty
+/// when none match or the best are too many to tell apart.
+pub(super) fn resolve(mention: &Mention, candidates: &[Candidate]) -> Vec<(String, f64)> {
+    let (matches, qualified, kind_fits): (Vec<&Candidate>, bool, bool) = match mention {
+        Mention::File(path) => {
+            let exact: Vec<&Candidate> = candidates
+                .iter()
+                .filter(|c| c.kind == "file" && c.name == *path)
+                .collect();
+            if exact.is_empty() {
+                let suffix = format!("/{path}");
+                let partial = candidates
+                    .iter()
+                    .filter(|c| c.kind == "file" && c.name.ends_with(&suffix))
+                    .collect();
+                (partial, false, true)
+            } else {
+                (exact, true, true)
+            }
+        }
+        Mention::Symbol {
+            name,
+            qualifier,
+            kind,
+        } => {
+            let mut matches: Vec<&Candidate> = candidates
+                .iter()
+                .filter(|c| c.kind != "file" && c.name == *name)
+                .collect();
+            let mut qualified = false;
+            if let Some(label) = qualifier.as_deref().map(sanitize_label) {
+                let narrowed: Vec<&Candidate> = matches
+                    .iter()
+                    .copied()
+                    .filter(|c| c.labels.iter().rev().skip(1).any(|l| *l == label))
+                    .collect();
+                if !narrowed.is_empty() {
+                    matches = narrowed;
+                    qualified = true;
+                }
+            }
+            let mut kind_fits = false;
+            if let Some(kind) = kind {
+                let narrowed: Vec<&Candidate> = matches
+                    .iter()
+                    .copied()
+                    .filter(|c| c.kind == *kind)
+                    .collect();
+                if !narrowed.is_empty() {
+                    matches = narrowed;
+                    kind_fits = true;
+                }
+            }
+            (matches, qualified, kind_fits)
+        }
+    };
+    let each = confidence(matches.len(), qualified, kind_fits);
+    if matches.is_empty() || each < MIN_CONFIDENCE {
+        return Vec::new();
+    }
+    let each = (each * 1000.0).round() / 1000.0;
+    matches.into_iter().map(|c| (c.id.clone(), each)).collect()
+}
+
+/// Symbols named in `mentions` and files ending in a mentioned path.
+fn candidates(mentions: &[&Mention]) -> Vec<Candidate> {
+    let mut names = Vec::new();
+    let mut files = Vec::new();
+    for mention in mentions {
+        match mention {
+            Mention::File(path) => files.push(path.rsplit('/').next().unwrap_or(path).to_string()),
+            Mention::Symbol { name, .. } => names.push(name.clone()),
+        }
+    }
+    let rows = Spi::get_one_with_args::<pgrx::JsonB>(
+        &format!(
+            "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+                'id', c.id, 'kind', c.kind, 'name', c.name, 'path', c.path
+            )), '[]'::jsonb) FROM (
+                SELECT id, kind, content AS name, path::text AS path FROM kerai.nodes
+                WHERE kind IN ({symbols}) AND content = ANY($1)
+                  AND deleted_at IS NULL AND {outside}
+                UNION ALL
+                SELECT id, kind, COALESCE(metadata->>'source_path', content), path::text
+                FROM kerai.nodes
+                WHERE kind = 'file'
+                  AND regexp_replace(COALESCE(metadata->>'source_path', content), '^.*/', '') = ANY($2)
+                  AND deleted_at IS NULL AND {outside}
+            ) c",
+            symbols = crate::query::SYMBOL_KINDS,
+            outside = crate::sandboxes::unsandboxed("path"),
+        ),
+        &[names.into(), files.into()],
+    )
+    .unwrap()
+    .map_or(Value::Null, |j| j.0);
+
+    rows.as_array()
+        .into_iter()
+        .flatten()
+        .map(|r| {
+            let field = |name: &str| r[name].as_str().unwrap_or("").to_string();
+            Candidate {
+                id: field("id"),
+                kind: field("kind"),
+                name: field("name"),
+                labels: field("path").split('.').map(str::to_string).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Remake the `mentions` edges out of the markdown documents `doc_ids`.
+/// Returns `{documents, spans, edges, unresolved}`, `unresolved` counting
+/// the code spans that looked like code but matched nothing, or too much.
+pub(super) fn link_documents(doc_ids: &[String]) -> Value {
+    if doc_ids.is_empty() {
+        return json!({"documents": 0, "spans": 0, "edges": 0, "unresolved": 0});
+    }
+    let span_kinds: Vec<String> = SPAN_KINDS.iter().map(|k| format!("'{k}'")).collect();
+    let subtree = "WITH RECURSIVE sub AS (
+            SELECT id, kind, content FROM kerai.nodes WHERE id = ANY($1::uuid[])
+            UNION ALL
+            SELECT n.id, n.kind, n.content FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+            WHERE n.deleted_at IS NULL
+        )";
+    Spi::run_with_args(
+        &format!(
+            "{subtree} DELETE FROM kerai.edges e USING sub
+             WHERE e.source_id = sub.id AND e.relation = 'mentions'"
+        ),
+        &[doc_ids.to_vec().into()],
+    )
+    .unwrap_or_else(|e| error!("Failed to clear code mentions: {}", e));
+
+    let blocks = Spi::get_one_with_args::<pgrx::JsonB>(
+        &format!(
+            "{subtree} SELECT COALESCE(jsonb_agg(jsonb_build_object('id', id, 'content', content)), '[]'::jsonb)
+             FROM sub WHERE kind IN ({}) AND content LIKE '%`%'",
+            span_kinds.join(", "),
+        ),
+        &[doc_ids.to_vec().into()],
+    )
+    .unwrap()
+    .map_or(Value::Null, |j| j.0);
+
+    let mut found: Vec<(String, String, Mention)> = Vec::new();
+    let mut spans = 0;
+    for block in blocks.as_array().into_iter().flatten() {
+        let id = block["id"].as_str().unwrap_or("");
+        for span in code_spans(block["content"].as_str().unwrap_or("")) {
+            spans += 1;
+            if let Some(mention) = classify(span) {
+                found.push((id.to_string(), span.to_string(), mention));
+            }
+        }
+    }
+
+    let mut distinct: Vec<&Mention> = found.iter().map(|(_, _, m)| m).collect();
+    distinct.sort_by_key(|m| format!("{m:?}"));
+    distinct.dedup();
+    let candidates = if distinct.is_empty() {
+        Vec::new()
+    } else {
+        candidates(&distinct)
+    };
+
+    // One edge per block and target, at its most confident span
+    let mut best: HashMap<(String, String), (String, f64)> = HashMap::new();
+    let mut unresolved = 0;
+    for (source, span, mention) in &found {
+        let targets = resolve(mention, &candidates);
+        if targets.is_empty() {
+            unresolved += 1;
+        }
+        for (target, confidence) in targets {
+            let entry = best
+                .entry((source.clone(), target))
+                .or_insert((span.clone(), 0.0));
+            if confidence > entry.1 {
+                *entry = (span.clone(), confidence);
+            }
+        }
+    }
+
+    let edges: Vec<EdgeRow> = best
+        .into_iter()
+        .map(|((source_id, target_id), (span, confidence))| EdgeRow {
+            id: Uuid::now_v7().to_string(),
+            source_id,
+            target_id,
+            relation: "mentions".to_string(),
+            metadata: json!({"span": span, "confidence": confidence}),
+        })
+        .collect();
+    inserter::insert_edges(&edges);
+
+    json!({
+        "documents": doc_ids.len(),
+        "spans": spans,
+        "edges": edges.len(),
+        "unresolved": unresolved,
+    })
+}
+
+/// Link the code spans of the markdown documents under `doc_path` (every
+/// markdown document when empty) to the symbols and files they name, with
+/// `mentions` edges carrying the span and a `confidence`. Replaces the
+/// documents' earlier mentions, so it can be rerun once more code is
+/// parsed. Returns `{documents, spans, edges, unresolved}`.
+#[pg_extern]
+fn link_code_mentions(doc_path: default!(&str, "''")) -> pgrx::JsonB {
+    let doc_ids = Spi::get_one_with_args::<Vec<String>>(
+        &format!(
+            "SELECT COALESCE(array_agg(id::text), '{{}}') FROM kerai.nodes
+             WHERE kind = '{}' AND language = 'markdown' AND deleted_at IS NULL
+               AND ($1 = '' OR path <@ $1::ltree) AND {}",
+            kinds::DOCUMENT,
+            crate::sandboxes::unsandboxed("path"),
+        ),
+        &[doc_path.into()],
+    )
+    .unwrap_or_else(|e| error!("Invalid path '{}': {}", doc_path, e))
+    .unwrap_or_default();
+    pgrx::JsonB(link_documents(&doc_ids))
+}
+
+/// Code mentions around `node_id` and its subtree, for navigating between
+/// docs and code: `{mentions, mentioned_by}`. `mentions` are the code
+/// nodes its blocks name, `mentioned_by` the doc blocks naming it; each
+/// entry is `{source_id, target_id, span, confidence}` with the other
+/// end's `kind`, `content`, `path` and containing `file` (or document),
+/// most confident first.
+#[pg_extern]
+fn mentions(node_id: pgrx::Uuid) -> pgrx::JsonB {
+    let side = |end: &str, other: &str| {
+        format!(
+            "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+                'source_id', e.source_id,
+                'target_id', e.target_id,
+                'span', e.metadata->>'span',
+                'confidence', (e.metadata->>'confidence')::float8,
+                'kind', o.kind,
+                'content', o.content,
+                'path', o.path::text,
+                'file', (
+                    WITH RECURSIVE up AS (
+                        SELECT id, parent_id, kind, content, metadata, 0 AS depth
+                        FROM kerai.nodes WHERE id = o.id
+                        UNION ALL
+                        SELECT n.id, n.parent_id, n.kind, n.content, n.metadata, up.depth + 1
+                        FROM kerai.nodes n JOIN up ON n.id = up.parent_id
+                    )
+                    SELECT COALESCE(metadata->>'source_path', content) FROM up
+                    WHERE kind IN ('file', 'document') ORDER BY depth LIMIT 1
+                )
+            ) ORDER BY (e.metadata->>'confidence')::float8 DESC, o.path), '[]'::jsonb)
+            FROM sub
+            JOIN kerai.edges e ON e.{end} = sub.id AND e.relation = 'mentions'
+            JOIN kerai.nodes o ON o.id = e.{other}
+            WHERE o.deleted_at IS NULL AND kerai.in_view(o.path, o.kind)"
+        )
+    };
+    let sql = format!(
+        "WITH RECURSIVE sub AS (
+            SELECT id FROM kerai.nodes WHERE id = $1 AND deleted_at IS NULL
+            UNION ALL
+            SELECT n.id FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+            WHERE n.deleted_at IS NULL
+        )
+        SELECT jsonb_build_object('mentions', ({}), 'mentioned_by', ({}))",
+        side("source_id", "target_id"),
+        side("target_id", "source_id"),
+    );
+    Spi::get_one_with_args::<pgrx::JsonB>(&sql, &[node_id.into()])
+        .unwrap_or_else(|e| error!("Failed to read code mentions: {}", e))
+        .unwrap_or_else(|| pgrx::JsonB(json!({"mentions": [], "mentioned_by": []})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, qualifier: Option<&str>, kind: Option<&'static str>) -> Mention {
+        Mention::Symbol {
+            name: name.to_string(),
+            qualifier: qualifier.map(str::to_string),
+            kind,
+        }
+    }
+
+    fn candidate(id: &str, kind: &str, name: &str, path: &str) -> Candidate {
+        Candidate {
+            id: id.to_string(),
+            kind: kind.to_string(),
+            name: name.to_string(),
+            labels: path.split('.').map(str::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn code_spans_match_backtick_runs() {
+        assert_eq!(
+            code_spans("Call `apply()` then ``a `tick` b`` and `` `x` ``."),
+            vec!["apply()", "a `tick` b", "`x`"]
+        );
+        assert_eq!(code_spans("an `unclosed span"), Vec::<&str>::new());
+        assert_eq!(code_spans("no spans"), Vec::<&str>::new());
+        assert_eq!(code_spans("`` and `one`"), vec!["one"]);
+    }
+
+    #[test]
+    fn classify_files_and_symbols() {
+        assert_eq!(
+            classify("src/lib.rs"),
+            Some(Mention::File("src/lib.rs".into()))
+        );
+        assert_eq!(
+            classify("./docs/guide.md"),
+            Some(Mention::File("docs/guide.md".into()))
+        );
+        assert_eq!(
+            classify("src/lib.rs:42"),
+            Some(Mention::File("src/lib.rs".into()))
+        );
+        assert_eq!(
+            classify("parse_file"),
+            Some(symbol("parse_file", None, None))
+        );
+        assert_eq!(
+            classify("parse_file()"),
+            Some(symbol("parse_file", None, Some("fn")))
+        );
+        assert_eq!(
+            classify("kerai::sql::sql_escape(s)"),
+            Some(symbol("sql_escape", Some("sql"), Some("fn")))
+        );
+        assert_eq!(
+            classify("Config::new"),
+            Some(symbol("new", Some("Config"), None))
+        );
+        assert_eq!(classify("self.apply"), Some(symbol("apply", None, None)));
+        assert_eq!(
+            classify("struct Config"),
+            Some(symbol("Config", None, Some("struct")))
+        );
+        assert_eq!(classify("Vec<Node>"), Some(symbol("Vec", None, None)));
+        assert_eq!(
+            classify("println!"),
+            Some(symbol("println", None, Some("macro_def")))
+        );
+        for not_code in [
+            "let x = 1",
+            "id",
+            "true",
+            "a + b",
+            "--verbose",
+            "foo(",
+            "x.y z",
+            "",
+        ] {
+            assert_eq!(classify(not_code), None, "{not_code}");
+        }
+    }
+
+    #[test]
+    fn confidence_is_shared_and_raised_by_qualifier_and_kind() {
+        assert!((confidence(1, true, true) - 1.0).abs() < 1e-12);
+        assert!((confidence(1, false, false) - 0.7).abs() < 1e-12);
+        assert!((confidence(2, false, true) - 0.4).abs() < 1e-12);
+        assert!(confidence(4, false, false) < MIN_CONFIDENCE);
+    }
+
+    #[test]
+    fn resolve_narrows_by_qualifier_and_kind() {
+        let candidates = [
+            candidate("a", "fn", "apply", "kerai.crdt.apply"),
+            candidate("b", "fn", "apply", "kerai.merge.apply"),
+            candidate("c", "struct", "apply", "kerai.apply"),
+            candidate("f", "file", "src/crdt/mod.rs", "mod_rs"),
+        ];
+        let ids = |r: Vec<(String, f64)>| r.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+
+        let r = resolve(&symbol("apply", Some("crdt"), Some("fn")), &candidates);
+        assert_eq!(r, vec![("a".to_string(), 1.0)]);
+        assert_eq!(
+            ids(resolve(&symbol("apply", None, Some("fn")), &candidates)),
+            ["a", "b"]
+        );
+        // Three equally likely, below the floor
+        assert!(resolve(&symbol("apply", None, None), &candidates).is_empty());
+        // A qualifier that names nothing does not rule anything out
+        assert_eq!(
+            ids(resolve(
+                &symbol("apply", Some("nowhere"), Some("struct")),
+                &candidates
+            )),
+            ["c"]
+        );
+
+        assert_eq!(
+            resolve(&Mention::File("src/crdt/mod.rs".into()), &candidates),
+            vec![("f".to_string(), 1.0)]
+        );
+        assert_eq!(
+            resolve(&Mention::File("crdt/mod.rs".into()), &candidates),
+            vec![("f".to_string(), 0.8)]
+        );
+        assert!(resolve(&Mention::File("merge/mod.rs".into()), &candidates).is_empty());
+    }
+}
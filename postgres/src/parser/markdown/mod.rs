@@ -7,52 +7,51 @@ use uuid::Uuid;
 use crate::parser::ast_walker::NodeRow;
 use crate::parser::inserter;
 use crate::parser::path_builder::PathContext;
-use crate::sql::sql_escape;
+use crate::parser::registry::{LanguageParser, Placement};
 
 /// Delete existing markdown document nodes and their children for a given filename.
-fn delete_markdown_nodes(instance_id: &str, filename: &str) {
-    // Delete edges first, then nodes via recursive CTE
-    Spi::run(&format!(
+pub(crate) fn delete_markdown_nodes(instance_id: &str, filename: &str) {
+    // Delete edges first, then nodes via recursive CTE; $1 instance, $2 filename
+    let descendants = format!(
         "WITH RECURSIVE descendants AS (
             SELECT id FROM kerai.nodes
-            WHERE instance_id = '{}'::uuid
-            AND kind = 'document' AND content = '{}'
+            WHERE instance_id = $1::uuid
+            AND kind = 'document' AND content = $2 AND {}
             UNION ALL
             SELECT n.id FROM kerai.nodes n
             JOIN descendants d ON n.parent_id = d.id
-        )
-        DELETE FROM kerai.edges WHERE source_id IN (SELECT id FROM descendants)
-            OR target_id IN (SELECT id FROM descendants)",
-        sql_escape(instance_id),
-        sql_escape(filename),
-    ))
+        )",
+        crate::sandboxes::unsandboxed("path"),
+    );
+    Spi::run_with_args(
+        &format!(
+            "{descendants}
+            DELETE FROM kerai.edges WHERE source_id IN (SELECT id FROM descendants)
+                OR target_id IN (SELECT id FROM descendants)"
+        ),
+        &[instance_id.into(), filename.into()],
+    )
     .ok();
 
-    Spi::run(&format!(
-        "WITH RECURSIVE descendants AS (
-            SELECT id FROM kerai.nodes
-            WHERE instance_id = '{}'::uuid
-            AND kind = 'document' AND content = '{}'
-            UNION ALL
-            SELECT n.id FROM kerai.nodes n
-            JOIN descendants d ON n.parent_id = d.id
-        )
-        DELETE FROM kerai.nodes WHERE id IN (SELECT id FROM descendants)",
-        sql_escape(instance_id),
-        sql_escape(filename),
-    ))
+    Spi::run_with_args(
+        &format!("{descendants} DELETE FROM kerai.nodes WHERE id IN (SELECT id FROM descendants)"),
+        &[instance_id.into(), filename.into()],
+    )
     .ok();
 }
 
 #[allow(dead_code)]
 pub mod kinds;
-mod walker;
+mod links;
+mod mentions;
+mod vault;
+pub(crate) mod walker;
 
 /// Parse a markdown document into kerai.nodes and kerai.edges.
 ///
 /// Returns JSON: `{file, nodes, edges, elapsed_ms}`.
 #[pg_extern]
-fn parse_markdown(source: &str, filename: &str) -> pgrx::JsonB {
+pub(super) fn parse_markdown(source: &str, filename: &str) -> pgrx::JsonB {
     let start = Instant::now();
     let instance_id = super::get_self_instance_id();
 
@@ -67,11 +66,8 @@ fn parse_markdown(source: &str, filename: &str) -> pgrx::JsonB {
     // Auto-mint reward for markdown parsing
     if node_count > 0 {
         let details = json!({"file": filename, "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_markdown', '{}'::jsonb)",
-            details_str,
-        ));
+        let file_id = inserter::root_node_id(&instance_id, kinds::DOCUMENT, filename);
+        crate::currency::reward("parse_markdown", "node", file_id.as_deref(), details);
     }
 
     let elapsed = start.elapsed();
@@ -83,19 +79,77 @@ fn parse_markdown(source: &str, filename: &str) -> pgrx::JsonB {
     }))
 }
 
+/// Markdown in the parser registry.
+pub(crate) struct Markdown;
+
+impl LanguageParser for Markdown {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["md"]
+    }
+
+    fn kinds(&self) -> Vec<&'static str> {
+        kinds::ALL.to_vec()
+    }
+
+    fn parse_source(&self, source: &str, filename: &str) -> pgrx::JsonB {
+        parse_markdown(source, filename)
+    }
+
+    fn walk(&self, source: &str, filename: &str, at: &Placement) -> (usize, usize) {
+        parse_markdown_single(source, filename, at.instance_id, at.parent_id)
+    }
+}
+
 /// Parse markdown source, insert nodes/edges, return counts.
 ///
 /// `parent_id` allows parenting the document node under a repo directory node.
+/// The document is linked to the documents its links lead to, and those that
+/// link to it, with `links_to` edges, and its code spans to the code they
+/// name with `mentions` edges.
 pub(crate) fn parse_markdown_single(
     source: &str,
     filename: &str,
     instance_id: &str,
     parent_id: Option<&str>,
 ) -> (usize, usize) {
+    let (doc_node_id, node_count) =
+        parse_markdown_placed(source, filename, instance_id, parent_id, None, json!({}));
+    let edge_count = links::link_document(instance_id, &doc_node_id);
+    let mentioned = mentions::link_documents(&[doc_node_id]);
+    let mention_count = mentioned["edges"].as_u64().unwrap_or(0) as usize;
+    (node_count, edge_count + mention_count)
+}
+
+/// Like `parse_markdown_single`, but the document node gets `path` (its
+/// nodes' paths follow it) and `metadata` merged into its own, and it is
+/// not linked to other documents.
+///
+/// Returns the document node id and the node count.
+pub(crate) fn parse_markdown_placed(
+    source: &str,
+    filename: &str,
+    instance_id: &str,
+    parent_id: Option<&str>,
+    path: Option<String>,
+    metadata: serde_json::Value,
+) -> (String, usize) {
     let path_ctx = PathContext::with_root(filename);
 
+    // Walk markdown and collect nodes and document links
+    let doc_node_id = Uuid::now_v7().to_string();
+    let (mut nodes, links) = walker::walk_markdown(source, filename, instance_id, &doc_node_id);
+
+    let mut doc_metadata = json!({"line_count": source.lines().count(), "links": links});
+    if let (Some(doc), serde_json::Value::Object(extra)) = (doc_metadata.as_object_mut(), metadata)
+    {
+        doc.extend(extra);
+    }
+
     // Create document root node
-    let doc_node_id = Uuid::new_v4().to_string();
     let doc_node = NodeRow {
         id: doc_node_id.clone(),
         instance_id: instance_id.to_string(),
@@ -104,21 +158,25 @@ pub(crate) fn parse_markdown_single(
         content: Some(filename.to_string()),
         parent_id: parent_id.map(|s| s.to_string()),
         position: 0,
-        path: path_ctx.path(),
-        metadata: json!({"line_count": source.lines().count()}),
+        path: path.clone().or_else(|| path_ctx.path()),
+        metadata: doc_metadata,
         span_start: None,
         span_end: None,
     };
     inserter::insert_nodes(&[doc_node]);
 
-    // Walk markdown and collect nodes/edges
-    let (nodes, edges) = walker::walk_markdown(source, filename, instance_id, &doc_node_id);
+    // The walker roots paths at the filename label; swap in the placed path
+    if let Some(root) = &path {
+        for node in &mut nodes {
+            node.path = node.path.as_deref().map(|p| match p.split_once('.') {
+                Some((_, rest)) => format!("{}.{}", root, rest),
+                None => root.clone(),
+            });
+        }
+    }
 
     let node_count = nodes.len() + 1; // +1 for document node
-    let edge_count = edges.len();
-
     inserter::insert_nodes(&nodes);
-    inserter::insert_edges(&edges);
 
-    (node_count, edge_count)
+    (doc_node_id, node_count)
 }
@@ -0,0 +1,519 @@
+/// Obsidian-style vault import — notes, attachments and folders under one
+/// ltree root, with `[[wikilinks]]` resolved into `links_to` edges.
+///
+/// `kerai import-vault` sends each file to `import_vault_file`, then calls
+/// `resolve_vault_links` once. A note becomes a markdown document whose
+/// metadata lists its wikilinks; any other file becomes an `asset` node
+/// with its size and SHA-256, not its bytes. Folders become `folder`
+/// nodes, so `notes.projects.todo_md` mirrors `projects/todo.md` in vault
+/// `notes`. Every node carries `vault` and `vault_path` metadata, and
+/// importing a file again replaces its nodes.
+///
+/// Links are resolved after every file is in, since a note may link to one
+/// imported after it. Resolution follows Obsidian: a path is looked up from
+/// the vault root and then from the linking note's folder; otherwise the
+/// link matches any file with that name, preferring the note's own folder
+/// and then the shortest path.
+use std::collections::HashSet;
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::kinds;
+use crate::parser::ast_walker::{EdgeRow, NodeRow};
+use crate::parser::inserter;
+use crate::parser::path_builder::sanitize_label;
+use crate::sql::{sql_text, sql_uuid};
+
+/// One `[[target#heading|alias]]` in a note; `embed` for `![[...]]`.
+#[derive(Debug, PartialEq)]
+pub(super) struct WikiLink {
+    pub(super) target: String,
+    heading: Option<String>,
+    alias: Option<String>,
+    embed: bool,
+}
+
+impl WikiLink {
+    pub(super) fn to_json(&self) -> Value {
+        json!({
+            "target": self.target,
+            "heading": self.heading,
+            "alias": self.alias,
+            "embed": self.embed,
+        })
+    }
+}
+
+/// Wikilinks in a note, in order, skipping code blocks and code spans.
+pub(super) fn wikilinks(source: &str) -> Vec<WikiLink> {
+    let mut links = Vec::new();
+    let mut fence: Option<&str> = None;
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        if let Some(f) = fence {
+            if trimmed.starts_with(f) {
+                fence = None;
+            }
+            continue;
+        }
+        if let Some(f) = ["```", "~~~"].into_iter().find(|f| trimmed.starts_with(f)) {
+            fence = Some(f);
+            continue;
+        }
+
+        let bytes = line.as_bytes();
+        let mut in_code = false;
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'`' => in_code = !in_code,
+                b'[' if !in_code && bytes.get(i + 1) == Some(&b'[') => {
+                    if let Some(len) = line[i + 2..].find("]]") {
+                        let embed = i > 0 && bytes[i - 1] == b'!';
+                        links.extend(parse_wikilink(&line[i + 2..i + 2 + len], embed));
+                        i += len + 4;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+    links
+}
+
+/// The inside of `[[...]]`; None when it names no other file.
+fn parse_wikilink(inner: &str, embed: bool) -> Option<WikiLink> {
+    if inner.contains('[') {
+        return None;
+    }
+    let nonempty = |s: &str| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+    let (link, alias) = match inner.split_once('|') {
+        // Inside a table the pipe is escaped as `\|`
+        Some((link, alias)) => (link.trim_end_matches('\\'), nonempty(alias)),
+        None => (inner, None),
+    };
+    let (target, heading) = match link.split_once('#') {
+        Some((target, heading)) => (target, nonempty(heading)),
+        None => (link, None),
+    };
+    // `[[#Heading]]` points into the same note
+    let target = nonempty(target)?;
+    Some(WikiLink {
+        target,
+        heading,
+        alias,
+        embed,
+    })
+}
+
+/// Lookup key of a vault path or link: lowercase, `/`-separated, without
+/// the `.md` of notes.
+fn link_key(path: &str) -> String {
+    let path = path.trim().replace('\\', "/").to_lowercase();
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    path.strip_suffix(".md").unwrap_or(path).to_string()
+}
+
+/// Folder part of a vault path, "" at the root.
+pub(super) fn folder_of(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// `rel` from `folder`, with `.` and `..` resolved.
+pub(super) fn join(folder: &str, rel: &str) -> String {
+    let mut parts: Vec<&str> = folder.split('/').filter(|p| !p.is_empty()).collect();
+    for part in rel.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            p => parts.push(p),
+        }
+    }
+    parts.join("/")
+}
+
+/// Notes and attachments of one vault by link key, with their node ids.
+pub(super) struct VaultIndex<'a> {
+    entries: Vec<(String, &'a str)>,
+}
+
+impl<'a> VaultIndex<'a> {
+    /// From `(vault path, node id)` pairs.
+    pub(super) fn new(files: &'a [(String, String)]) -> Self {
+        VaultIndex {
+            entries: files
+                .iter()
+                .map(|(path, id)| (link_key(path), id.as_str()))
+                .collect(),
+        }
+    }
+
+    /// Node id a link in note `from` leads to.
+    pub(super) fn resolve(&self, from: &str, target: &str) -> Option<&'a str> {
+        let key = link_key(target);
+        let folder = link_key(folder_of(from));
+        for candidate in [key.clone(), join(&folder, &key)] {
+            if let Some((_, id)) = self.entries.iter().find(|(k, _)| *k == candidate) {
+                return Some(id);
+            }
+        }
+
+        let suffix = format!("/{}", key);
+        let rank = |k: &String| (folder_of(k) != folder, k.len(), k.clone());
+        self.entries
+            .iter()
+            .filter(|(k, _)| k.ends_with(&suffix))
+            .min_by(|a, b| rank(&a.0).cmp(&rank(&b.0)))
+            .map(|(_, id)| *id)
+    }
+}
+
+fn vault_node(
+    instance_id: &str,
+    kind: &str,
+    content: &str,
+    parent_id: Option<&str>,
+    path: String,
+    metadata: Value,
+) -> NodeRow {
+    NodeRow {
+        id: Uuid::now_v7().to_string(),
+        instance_id: instance_id.to_string(),
+        kind: kind.to_string(),
+        language: None,
+        content: Some(content.to_string()),
+        parent_id: parent_id.map(String::from),
+        position: 0,
+        path: Some(path),
+        metadata,
+        span_start: None,
+        span_end: None,
+    }
+}
+
+/// SQL condition for the nodes of `vault` imported from `vault_path`.
+fn entry_condition(instance_id: &str, vault: &str, vault_path: &str) -> String {
+    format!(
+        "instance_id = {} AND metadata->>'vault' = {} AND metadata->>'vault_path' = {}",
+        sql_uuid(instance_id),
+        sql_text(vault),
+        sql_text(vault_path),
+    )
+}
+
+/// Id and path of the vault's root node, created on first import.
+fn vault_root(instance_id: &str, vault: &str) -> (String, String) {
+    let path = sanitize_label(vault);
+    let existing = Spi::get_one::<String>(&format!(
+        "SELECT id::text FROM kerai.nodes
+         WHERE instance_id = {} AND kind = {} AND content = {} AND parent_id IS NULL
+         LIMIT 1",
+        sql_uuid(instance_id),
+        sql_text(kinds::VAULT),
+        sql_text(vault),
+    ))
+    .unwrap_or(None);
+    if let Some(id) = existing {
+        return (id, path);
+    }
+    let root = vault_node(
+        instance_id,
+        kinds::VAULT,
+        vault,
+        None,
+        path.clone(),
+        json!({"vault": vault}),
+    );
+    inserter::insert_nodes(std::slice::from_ref(&root));
+    (root.id, path)
+}
+
+/// Id and path of folder `dir` (`a/b`), creating it and its parents;
+/// `created` counts the new folder nodes.
+fn folder(
+    instance_id: &str,
+    vault: &str,
+    root: (String, String),
+    dir: &str,
+    created: &mut usize,
+) -> (String, String) {
+    let mut current = root;
+    let mut vault_path = String::new();
+    for part in dir.split('/').filter(|p| !p.is_empty()) {
+        if !vault_path.is_empty() {
+            vault_path.push('/');
+        }
+        vault_path.push_str(part);
+        let path = format!("{}.{}", current.1, sanitize_label(part));
+        let existing = Spi::get_one::<String>(&format!(
+            "SELECT id::text FROM kerai.nodes WHERE kind = {} AND {} LIMIT 1",
+            sql_text(kinds::FOLDER),
+            entry_condition(instance_id, vault, &vault_path),
+        ))
+        .unwrap_or(None);
+        current = match existing {
+            Some(id) => (id, path),
+            None => {
+                let node = vault_node(
+                    instance_id,
+                    kinds::FOLDER,
+                    part,
+                    Some(&current.0),
+                    path.clone(),
+                    json!({"vault": vault, "vault_path": vault_path}),
+                );
+                inserter::insert_nodes(std::slice::from_ref(&node));
+                *created += 1;
+                (node.id, path)
+            }
+        };
+    }
+    current
+}
+
+/// Remove the nodes an earlier import of `vault_path` made, with every
+/// edge to or from them. Links into the file come back with the next
+/// `resolve_vault_links`.
+fn delete_entry(instance_id: &str, vault: &str, vault_path: &str) {
+    let subtree = format!(
+        "WITH RECURSIVE d AS (
+            SELECT id FROM kerai.nodes
+            WHERE kind IN ({}, {}) AND {}
+            UNION ALL
+            SELECT n.id FROM kerai.nodes n JOIN d ON n.parent_id = d.id
+        )",
+        sql_text(kinds::DOCUMENT),
+        sql_text(kinds::ASSET),
+        entry_condition(instance_id, vault, vault_path),
+    );
+    Spi::run(&format!(
+        "{} DELETE FROM kerai.edges
+         WHERE source_id IN (SELECT id FROM d) OR target_id IN (SELECT id FROM d)",
+        subtree
+    ))
+    .unwrap();
+    Spi::run(&format!(
+        "{} DELETE FROM kerai.nodes WHERE id IN (SELECT id FROM d)",
+        subtree
+    ))
+    .unwrap();
+}
+
+/// Import one file of vault `vault`: `{path, source}` for a markdown note,
+/// `{path, size, sha256}` for an attachment. `path` is relative to the
+/// vault root and `/`-separated.
+///
+/// Returns `{path, kind, nodes, edges, wikilinks, folders}`, where
+/// `folders` counts the folder nodes it had to create.
+#[pg_extern]
+fn import_vault_file(vault: &str, file: pgrx::JsonB) -> pgrx::JsonB {
+    let file = file.0;
+    if vault.trim().is_empty() {
+        error!("Vault name must not be empty");
+    }
+    let path = file["path"].as_str().unwrap_or("").trim_matches('/');
+    if path.is_empty() || path.split('/').any(|p| p.is_empty() || p == "..") {
+        error!("Invalid vault file path: '{}'", path);
+    }
+
+    let instance_id = crate::parser::get_self_instance_id();
+    let mut folders = 0;
+    let root = vault_root(&instance_id, vault);
+    let (parent_id, parent_path) = folder(&instance_id, vault, root, folder_of(path), &mut folders);
+    delete_entry(&instance_id, vault, path);
+
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let node_path = format!("{}.{}", parent_path, sanitize_label(name));
+    let mut result = match file["source"].as_str() {
+        Some(source) => {
+            // Links and backlinks an export appended are not the note's own
+            let source = &crate::reconstruct::strip_generated(source);
+            let links = wikilinks(source);
+            let metadata = json!({
+                "vault": vault,
+                "vault_path": path,
+                "wikilinks": links.iter().map(WikiLink::to_json).collect::<Vec<_>>(),
+            });
+            let (_, nodes) = super::parse_markdown_placed(
+                source,
+                path,
+                &instance_id,
+                Some(&parent_id),
+                Some(node_path),
+                metadata,
+            );
+            json!({
+                "path": path,
+                "kind": kinds::DOCUMENT,
+                "nodes": nodes,
+                "edges": 0,
+                "wikilinks": links.len(),
+            })
+        }
+        None => {
+            let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+            let metadata = json!({
+                "vault": vault,
+                "vault_path": path,
+                "size": file["size"],
+                "sha256": file["sha256"],
+                "extension": extension,
+            });
+            inserter::insert_nodes(&[vault_node(
+                &instance_id,
+                kinds::ASSET,
+                name,
+                Some(&parent_id),
+                node_path,
+                metadata,
+            )]);
+            json!({"path": path, "kind": kinds::ASSET, "nodes": 1, "edges": 0, "wikilinks": 0})
+        }
+    };
+    result["folders"] = json!(folders);
+    pgrx::JsonB(result)
+}
+
+/// Turn the wikilinks of every note in `vault` into `links_to` edges from
+/// the note to the note or attachment they name, replacing the edges of an
+/// earlier run. The edge metadata keeps the link text, heading and alias.
+///
+/// Returns `{notes, assets, links, unresolved}`, where `unresolved` lists
+/// `{path, target}` for each link that matches no file.
+#[pg_extern]
+fn resolve_vault_links(vault: &str) -> pgrx::JsonB {
+    let instance_id = crate::parser::get_self_instance_id();
+    let entries = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', id,
+            'kind', kind,
+            'path', metadata->>'vault_path',
+            'wikilinks', metadata->'wikilinks'
+        ) ORDER BY metadata->>'vault_path'), '[]'::jsonb)
+        FROM kerai.nodes
+        WHERE instance_id = {} AND kind IN ({}, {}) AND metadata->>'vault' = {}",
+        sql_uuid(&instance_id),
+        sql_text(kinds::DOCUMENT),
+        sql_text(kinds::ASSET),
+        sql_text(vault),
+    ))
+    .unwrap()
+    .map_or(Value::Null, |j| j.0);
+    let entries = entries.as_array().cloned().unwrap_or_default();
+
+    let files: Vec<(String, String)> = entries
+        .iter()
+        .map(|e| {
+            let field = |name: &str| e[name].as_str().unwrap_or("").to_string();
+            (field("path"), field("id"))
+        })
+        .collect();
+    let index = VaultIndex::new(&files);
+
+    Spi::run(&format!(
+        "DELETE FROM kerai.edges e USING kerai.nodes n
+         WHERE e.source_id = n.id AND e.relation = 'links_to' AND e.metadata ? 'wikilink'
+           AND n.kind = {} AND n.instance_id = {} AND n.metadata->>'vault' = {}",
+        sql_text(kinds::DOCUMENT),
+        sql_uuid(&instance_id),
+        sql_text(vault),
+    ))
+    .unwrap();
+
+    let mut edges = Vec::new();
+    let mut linked = HashSet::new();
+    let mut missing = HashSet::new();
+    let mut unresolved = Vec::new();
+    let mut notes = 0;
+    for (entry, (path, id)) in entries.iter().zip(&files) {
+        if entry["kind"] != kinds::DOCUMENT {
+            continue;
+        }
+        notes += 1;
+        for link in entry["wikilinks"].as_array().into_iter().flatten() {
+            let target = link["target"].as_str().unwrap_or("");
+            match index.resolve(path, target) {
+                Some(target_id) if target_id != id.as_str() => {
+                    if linked.insert((id.as_str(), target_id)) {
+                        edges.push(EdgeRow {
+                            id: Uuid::now_v7().to_string(),
+                            source_id: id.clone(),
+                            target_id: target_id.to_string(),
+                            relation: "links_to".to_string(),
+                            metadata: json!({
+                                "wikilink": target,
+                                "heading": link["heading"],
+                                "alias": link["alias"],
+                                "embed": link["embed"],
+                            }),
+                        });
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    if missing.insert((path.as_str(), target)) {
+                        unresolved.push(json!({"path": path, "target": target}));
+                    }
+                }
+            }
+        }
+    }
+    inserter::insert_edges(&edges);
+
+    pgrx::JsonB(json!({
+        "notes": notes,
+        "assets": files.len() - notes,
+        "links": edges.len(),
+        "unresolved": unresolved,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wikilinks_finds_targets_headings_and_aliases() {
+        let source = "See [[Note]] and ![[img.png]], [[dir/Other#Intro|the intro]].\n\
+                      Same note: [[#Top]]; in a table [[Note\\|alias]].\n\
+                      `[[not a link]]`\n\
+                      ```\n[[nor this]]\n```\n";
+        let links = wikilinks(source);
+        let targets: Vec<&str> = links.iter().map(|l| l.target.as_str()).collect();
+        assert_eq!(targets, ["Note", "img.png", "dir/Other", "Note"]);
+        assert!(links[1].embed);
+        assert_eq!(links[2].heading.as_deref(), Some("Intro"));
+        assert_eq!(links[2].alias.as_deref(), Some("the intro"));
+        assert_eq!(links[3].alias.as_deref(), Some("alias"));
+    }
+
+    #[test]
+    fn resolve_prefers_paths_then_own_folder_then_shortest() {
+        let files: Vec<(String, String)> = [
+            ("Home.md", "home"),
+            ("a/Todo.md", "a-todo"),
+            ("b/Todo.md", "b-todo"),
+            ("b/deep/Todo.md", "deep-todo"),
+            ("assets/img.png", "img"),
+        ]
+        .iter()
+        .map(|(p, id)| (p.to_string(), id.to_string()))
+        .collect();
+        let index = VaultIndex::new(&files);
+
+        assert_eq!(index.resolve("a/Todo.md", "home"), Some("home"));
+        assert_eq!(index.resolve("b/deep/Todo.md", "Todo"), Some("deep-todo"));
+        assert_eq!(index.resolve("Home.md", "Todo"), Some("a-todo"));
+        assert_eq!(index.resolve("Home.md", "b/Todo.md"), Some("b-todo"));
+        assert_eq!(index.resolve("b/Todo.md", "../a/Todo"), Some("a-todo"));
+        assert_eq!(index.resolve("Home.md", "deep/Todo"), Some("deep-todo"));
+        assert_eq!(index.resolve("Home.md", "IMG.png"), Some("img"));
+        assert_eq!(index.resolve("Home.md", "Missing"), None);
+    }
+}
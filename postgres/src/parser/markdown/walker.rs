@@ -1,9 +1,18 @@
-/// Markdown walker — pulldown-cmark events → NodeRow/EdgeRow.
+/// Markdown walker — pulldown-cmark events → NodeRows and document links.
+///
+/// Paragraphs, headings, tight list items and table cells keep their inline
+/// source verbatim (emphasis markers, escapes, link syntax), one line per
+/// source line with container prefixes stripped. Block markup the tree
+/// can't otherwise carry — list markers, code fences, setext underlines,
+/// thematic break characters — goes in metadata, so `reconstruct_markdown`
+/// can write the document back as it was.
+use std::ops::Range;
+
 use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd, CodeBlockKind};
 use serde_json::{json, Value};
 use uuid::Uuid;
 
-use crate::parser::ast_walker::{EdgeRow, NodeRow};
+use crate::parser::ast_walker::NodeRow;
 use crate::parser::path_builder::{PathContext, sanitize_label};
 use super::kinds;
 
@@ -12,23 +21,88 @@ struct StackEntry {
     node_id: String,
     kind: String,
     heading_level: Option<u8>,
+    /// Inline source, for the kinds whose content is their inline markup
+    inline: Option<InlineSource>,
+}
+
+/// Inline source of one block, gathered from the ranges of its inline
+/// events. Each source line is taken from its first event to its last, so
+/// prefixes like `> ` or list indentation on continuation lines drop out.
+#[derive(Default)]
+struct InlineSource {
+    text: String,
+    /// Source range of the current line so far
+    line: Option<Range<usize>>,
+}
+
+impl InlineSource {
+    fn touch(&mut self, range: &Range<usize>) {
+        match &mut self.line {
+            Some(line) => line.end = line.end.max(range.end),
+            None => self.line = Some(range.clone()),
+        }
+    }
+
+    /// A soft or hard break: close the line, keeping a hard break's markup
+    /// (trailing spaces or a backslash).
+    fn line_break(&mut self, source: &str, range: &Range<usize>) {
+        if let Some(line) = self.line.take() {
+            self.text.push_str(&source[line.start..range.start]);
+        }
+        self.text
+            .push_str(source[range.clone()].trim_end_matches(['\n', '\r']));
+        self.text.push('\n');
+    }
+
+    fn finish(mut self, source: &str) -> Option<String> {
+        if let Some(line) = self.line.take() {
+            self.text.push_str(&source[line]);
+        }
+        Some(self.text).filter(|t| !t.is_empty())
+    }
+}
+
+/// Kinds whose content is their inline source.
+fn holds_inline(kind: &str) -> bool {
+    matches!(
+        kind,
+        kinds::PARAGRAPH | kinds::HEADING | kinds::LIST_ITEM | kinds::TABLE_CELL
+    )
+}
+
+/// Inline kinds, which live inside their block's inline source.
+fn is_inline(kind: &str) -> bool {
+    matches!(
+        kind,
+        kinds::EMPHASIS | kinds::STRONG | kinds::STRIKETHROUGH | kinds::LINK | kinds::IMAGE
+    )
 }
 
-/// Walk markdown source and produce NodeRow/EdgeRow vectors.
+/// Inline source of the innermost open block, if it collects any.
+fn inline_owner(stack: &mut [StackEntry]) -> Option<&mut InlineSource> {
+    stack
+        .iter_mut()
+        .rev()
+        .find(|e| !is_inline(&e.kind))
+        .and_then(|e| e.inline.as_mut())
+}
+
+/// Walk markdown source and produce its nodes, with the links that may
+/// lead to other documents: relative links, then `[[wikilinks]]`.
 pub fn walk_markdown(
     source: &str,
     filename: &str,
     instance_id: &str,
     document_node_id: &str,
-) -> (Vec<NodeRow>, Vec<EdgeRow>) {
+) -> (Vec<NodeRow>, Vec<Value>) {
     let opts = Options::ENABLE_TABLES
         | Options::ENABLE_FOOTNOTES
         | Options::ENABLE_STRIKETHROUGH
         | Options::ENABLE_HEADING_ATTRIBUTES;
-    let parser = Parser::new_ext(source, opts);
+    let parser = Parser::new_ext(source, opts).into_offset_iter();
 
     let mut nodes: Vec<NodeRow> = Vec::new();
-    let mut edges: Vec<EdgeRow> = Vec::new();
+    let mut links: Vec<Value> = Vec::new();
     let mut path_ctx = PathContext::with_root(&sanitize_label(filename));
 
     // Stack for container elements (headings, blockquotes, lists, etc.)
@@ -42,12 +116,20 @@ pub fn walk_markdown(
     let mut text_accum = String::new();
     let mut current_link: Option<(String, String)> = None; // (url, title)
 
-    for event in parser {
+    for (event, range) in parser {
         match event {
             Event::Start(tag) => {
-                let (kind, metadata, name) = tag_to_kind_meta(&tag);
+                let (kind, mut metadata, name) = tag_to_kind_meta(&tag);
+                if let Value::Object(ref mut map) = metadata {
+                    map.extend(markup_meta(&tag, &source[range.clone()]));
+                }
+                if is_inline(kind) {
+                    if let Some(inline) = inline_owner(&mut stack) {
+                        inline.touch(&range);
+                    }
+                }
 
-                let node_id = Uuid::new_v4().to_string();
+                let node_id = Uuid::now_v7().to_string();
 
                 // Determine parent based on heading hierarchy or stack
                 let parent_id = if kind == kinds::HEADING {
@@ -111,15 +193,26 @@ pub fn walk_markdown(
                     } else {
                         None
                     },
+                    inline: holds_inline(kind).then(InlineSource::default),
                 });
 
                 text_accum.clear();
             }
 
             Event::End(tag_end) => {
-                if let Some(entry) = stack.pop() {
-                    // Set content from accumulated text
-                    if !text_accum.is_empty() {
+                if let Some(mut entry) = stack.pop() {
+                    if is_inline(&entry.kind) {
+                        if let Some(inline) = inline_owner(&mut stack) {
+                            inline.touch(&range);
+                        }
+                    }
+
+                    // Blocks keep their inline source; others the accumulated text
+                    if let Some(inline) = entry.inline.take() {
+                        if let Some(node) = nodes.iter_mut().rev().find(|n| n.id == entry.node_id) {
+                            node.content = inline.finish(source);
+                        }
+                    } else if !text_accum.is_empty() {
                         if let Some(node) = nodes.iter_mut().rev().find(|n| n.id == entry.node_id) {
                             node.content = Some(text_accum.clone());
 
@@ -139,19 +232,24 @@ pub fn walk_markdown(
                         }
                     }
 
-                    // Create edges for links to track cross-references
+                    // Relative links may lead to another document
                     if entry.kind == kinds::LINK {
                         if let Some(node) = nodes.iter().rev().find(|n| n.id == entry.node_id) {
                             if let Some(url) = node.metadata.get("url").and_then(|v| v.as_str()) {
-                                // If it's an internal link (no scheme), try to create edge
-                                if !url.contains("://") && !url.starts_with('#') {
-                                    edges.push(EdgeRow {
-                                        id: Uuid::new_v4().to_string(),
-                                        source_id: entry.node_id.clone(),
-                                        target_id: document_node_id.to_string(),
-                                        relation: "links_to".to_string(),
-                                        metadata: json!({"url": url}),
-                                    });
+                                links.extend(super::links::relative_link(url));
+                            }
+                        }
+                    }
+
+                    // The delimiter row follows the head row
+                    if entry.kind == kinds::TABLE_HEAD {
+                        let row = source[range.end..].lines().next().unwrap_or("").trim();
+                        if let Some(table) = stack.last() {
+                            if let Some(node) =
+                                nodes.iter_mut().rev().find(|n| n.id == table.node_id)
+                            {
+                                if let Value::Object(ref mut map) = node.metadata {
+                                    map.insert("delimiter".to_string(), json!(row));
                                 }
                             }
                         }
@@ -172,10 +270,16 @@ pub fn walk_markdown(
             }
 
             Event::Text(text) => {
+                if let Some(inline) = inline_owner(&mut stack) {
+                    inline.touch(&range);
+                }
                 text_accum.push_str(&text);
             }
 
             Event::Code(code) => {
+                if let Some(inline) = inline_owner(&mut stack) {
+                    inline.touch(&range);
+                }
                 // Inline code — add as text to parent
                 text_accum.push('`');
                 text_accum.push_str(&code);
@@ -183,13 +287,24 @@ pub fn walk_markdown(
             }
 
             Event::SoftBreak => {
+                if let Some(inline) = inline_owner(&mut stack) {
+                    inline.line_break(source, &range);
+                }
                 text_accum.push(' ');
             }
 
             Event::HardBreak => {
+                if let Some(inline) = inline_owner(&mut stack) {
+                    inline.line_break(source, &range);
+                }
                 text_accum.push('\n');
             }
 
+            // Lines of an open HTML block make up its content
+            Event::Html(html) if stack.last().is_some_and(|e| e.kind == kinds::HTML_BLOCK) => {
+                text_accum.push_str(&html);
+            }
+
             Event::Html(html) => {
                 // Raw HTML block
                 let parent_id = stack.last()
@@ -198,7 +313,7 @@ pub fn walk_markdown(
                     .unwrap_or_else(|| document_node_id.to_string());
 
                 nodes.push(NodeRow {
-                    id: Uuid::new_v4().to_string(),
+                    id: Uuid::now_v7().to_string(),
                     instance_id: instance_id.to_string(),
                     kind: kinds::HTML_BLOCK.to_string(),
                     language: Some("markdown".to_string()),
@@ -214,14 +329,45 @@ pub fn walk_markdown(
             }
 
             Event::InlineHtml(html) => {
+                if let Some(inline) = inline_owner(&mut stack) {
+                    inline.touch(&range);
+                }
                 text_accum.push_str(&html);
             }
 
             Event::FootnoteReference(name) => {
+                if let Some(inline) = inline_owner(&mut stack) {
+                    inline.touch(&range);
+                }
                 text_accum.push_str(&format!("[^{}]", name));
             }
 
+            Event::Rule => {
+                let parent_id = stack.last()
+                    .map(|e| e.node_id.clone())
+                    .or_else(|| heading_stack.last().map(|(_, id)| id.clone()))
+                    .unwrap_or_else(|| document_node_id.to_string());
+
+                nodes.push(NodeRow {
+                    id: Uuid::now_v7().to_string(),
+                    instance_id: instance_id.to_string(),
+                    kind: kinds::THEMATIC_BREAK.to_string(),
+                    language: Some("markdown".to_string()),
+                    content: None,
+                    parent_id: Some(parent_id),
+                    position,
+                    path: path_ctx.path(),
+                    metadata: json!({"markup": source[range].trim()}),
+                    span_start: None,
+                    span_end: None,
+                });
+                position += 1;
+            }
+
             Event::TaskListMarker(checked) => {
+                if let Some(inline) = inline_owner(&mut stack) {
+                    inline.touch(&range);
+                }
                 // Update the current list_item's metadata
                 if let Some(entry) = stack.last() {
                     if let Some(node) = nodes.iter_mut().rev().find(|n| n.id == entry.node_id) {
@@ -236,7 +382,8 @@ pub fn walk_markdown(
         }
     }
 
-    (nodes, edges)
+    links.extend(super::links::wikilinks(source));
+    (nodes, links)
 }
 
 /// Extract kind, metadata, and optional name from a pulldown-cmark Tag.
@@ -312,6 +459,40 @@ fn tag_to_kind_meta<'a>(tag: &'a Tag<'a>) -> (&'a str, Value, Option<String>) {
     }
 }
 
+/// Metadata recording how a tag was written, from its source `markup`:
+/// setext headings and their underline, list markers (the bullet, or the
+/// `.` or `)` after an ordered item's number), code fences and emphasis
+/// delimiters.
+fn markup_meta(tag: &Tag, markup: &str) -> Vec<(String, Value)> {
+    let markup = markup.trim_start();
+    let first = markup.chars().next().unwrap_or_default();
+    let run = |c: char| markup.len() - markup.trim_start_matches(c).len();
+    let mut meta = Vec::new();
+    match tag {
+        Tag::Heading { .. } if first != '#' => {
+            let underline = markup.trim_end().lines().last().unwrap_or("").trim();
+            meta.push(("setext".to_string(), json!(true)));
+            meta.push(("underline".to_string(), json!(underline)));
+        }
+        Tag::List(Some(_)) => {
+            let delimiter = markup.trim_start_matches(|c: char| c.is_ascii_digit());
+            let delimiter = delimiter.chars().next().unwrap_or('.');
+            meta.push(("marker".to_string(), json!(delimiter.to_string())));
+        }
+        Tag::List(None) => meta.push(("marker".to_string(), json!(first.to_string()))),
+        Tag::CodeBlock(CodeBlockKind::Fenced(_)) => {
+            meta.push(("fence".to_string(), json!(&markup[..run(first)])));
+        }
+        Tag::Emphasis => meta.push(("delimiter".to_string(), json!(first.to_string()))),
+        Tag::Strong => meta.push(("delimiter".to_string(), json!(first.to_string().repeat(2)))),
+        Tag::Strikethrough => {
+            meta.push(("delimiter".to_string(), json!(&markup[..run(first)])));
+        }
+        _ => {}
+    }
+    meta
+}
+
 fn heading_level_from_tag(tag: &Tag) -> u8 {
     match tag {
         Tag::Heading { level, .. } => heading_level_num(level),
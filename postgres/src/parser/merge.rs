@@ -0,0 +1,539 @@
+/// Three-way structural merge of file trees.
+///
+/// `ours` and `theirs` are both matched against their common ancestor `base`
+/// with [`diff_trees`], then every node is decided on its own: a change made
+/// on one side wins over the base, and different changes on both sides are a
+/// conflict. Content conflicts, and deleting a subtree the other side edited,
+/// become `conflict` nodes carrying both versions; conflicting moves and
+/// reorders keep ours and are only reported.
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+use super::diff::{diff_trees, own_changed};
+use super::inserter::{child_index, subtree_hashes, TreeNode};
+use super::kinds::Kind;
+
+/// A node of the merged tree. Parents come before their children.
+#[derive(Debug)]
+pub struct MergedNode {
+    /// Index of the parent in [`Merge::nodes`]; `None` for the root.
+    pub parent: Option<usize>,
+    /// Ids of the ours/theirs nodes this node stands for.
+    pub sources: Vec<String>,
+    pub kind: String,
+    pub content: Option<String>,
+    pub path: Option<String>,
+    pub position: i32,
+    pub metadata: Value,
+}
+
+/// A change the merge could not reconcile.
+#[derive(Debug)]
+pub struct Conflict {
+    pub reason: &'static str,
+    /// Index of the `conflict` node recording it, if one was made.
+    pub node: Option<usize>,
+    pub kind: String,
+    pub path: Option<String>,
+    pub base: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Merge {
+    pub nodes: Vec<MergedNode>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Identity of a node across the three trees: matched to the base, or only
+/// inserted on one side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Base(usize),
+    Ours(usize),
+    Theirs(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Take {
+    Ours,
+    Theirs,
+}
+
+struct Decision {
+    parent: Option<Key>,
+    take: Take,
+    /// Conflict recorded as a `conflict` node in place of this one.
+    conflict: Option<&'static str>,
+    /// Kept with its whole subtree because the other side deleted it.
+    whole: bool,
+}
+
+struct Tree<'a> {
+    nodes: &'a [TreeNode],
+    kids: HashMap<&'a str, Vec<usize>>,
+    by_id: HashMap<&'a str, usize>,
+    hashes: HashMap<String, String>,
+}
+
+impl<'a> Tree<'a> {
+    fn new(nodes: &'a [TreeNode]) -> Self {
+        Tree {
+            nodes,
+            kids: child_index(nodes),
+            by_id: nodes.iter().enumerate().map(|(i, n)| (n.id.as_str(), i)).collect(),
+            hashes: subtree_hashes(nodes),
+        }
+    }
+
+    fn kids(&self, i: usize) -> &[usize] {
+        self.kids.get(self.nodes[i].id.as_str()).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    fn parent(&self, i: usize) -> Option<usize> {
+        self.by_id.get(self.nodes[i].parent_id.as_deref()?).copied()
+    }
+
+    fn hash(&self, i: usize) -> &str {
+        &self.hashes[&self.nodes[i].id]
+    }
+
+    /// Node indices of the subtree at `root`, pre-order.
+    fn subtree(&self, root: usize) -> Vec<usize> {
+        let mut out = Vec::new();
+        let mut stack = vec![root];
+        while let Some(i) = stack.pop() {
+            out.push(i);
+            stack.extend(self.kids(i).iter().rev());
+        }
+        out
+    }
+}
+
+/// Text a conflict shows for one side: the item's source if it has one.
+fn side_text(n: &TreeNode) -> Value {
+    match n.metadata.get("source").and_then(|s| s.as_str()) {
+        Some(source) => json!(source),
+        None => json!(n.content),
+    }
+}
+
+/// `seq` restricted to keys in `keep`.
+fn only(seq: &[Key], keep: &HashSet<Key>) -> Vec<Key> {
+    seq.iter().copied().filter(|k| keep.contains(k)).collect()
+}
+
+/// Merge `ours` and `theirs`, both descended from `base`.
+///
+/// The roots always match each other. The result is a fresh tree: node ids
+/// are left to the caller, [`MergedNode::sources`] says where each came from.
+pub fn merge_trees(
+    base: &[TreeNode],
+    base_root: &str,
+    ours: &[TreeNode],
+    ours_root: &str,
+    theirs: &[TreeNode],
+    theirs_root: &str,
+) -> Merge {
+    let (b, o, t) = (Tree::new(base), Tree::new(ours), Tree::new(theirs));
+    let mut merge = Merge::default();
+    let (Some(&rb), Some(&ro), Some(&rt)) =
+        (b.by_id.get(base_root), o.by_id.get(ours_root), t.by_id.get(theirs_root))
+    else {
+        return merge;
+    };
+
+    let mut o_of = vec![None; base.len()];
+    let mut b_of_o = vec![None; ours.len()];
+    for (i, j) in diff_trees(base, base_root, ours, ours_root).pairs {
+        o_of[i] = Some(j);
+        b_of_o[j] = Some(i);
+    }
+    let mut t_of = vec![None; base.len()];
+    let mut b_of_t = vec![None; theirs.len()];
+    for (i, j) in diff_trees(base, base_root, theirs, theirs_root).pairs {
+        t_of[i] = Some(j);
+        b_of_t[j] = Some(i);
+    }
+    let ours_key = |j: usize| b_of_o[j].map_or(Key::Ours(j), Key::Base);
+    let theirs_key = |j: usize| b_of_t[j].map_or(Key::Theirs(j), Key::Base);
+
+    let mut decided: HashMap<Key, Decision> = HashMap::new();
+    // Conflicts by key, resolved to node indices once the tree is laid out
+    let mut pending: Vec<(Key, &'static str)> = Vec::new();
+
+    // Pre-order, so a parent is always decided before its children
+    for i in b.subtree(rb) {
+        let key = Key::Base(i);
+        let decision = match (o_of[i], t_of[i]) {
+            (Some(oi), Some(ti)) => {
+                let parent = b.parent(i).filter(|_| i != rb).map(|pb| {
+                    let pb = Key::Base(pb);
+                    let po = o.parent(oi).map_or(pb, ours_key);
+                    let pt = t.parent(ti).map_or(pb, theirs_key);
+                    if po == pb {
+                        pt
+                    } else {
+                        if pt != pb && pt != po {
+                            pending.push((key, "moved on both sides"));
+                        }
+                        po
+                    }
+                });
+                let changed_o = own_changed(&base[i], &ours[oi]);
+                let changed_t = own_changed(&base[i], &theirs[ti]);
+                let mut conflict = None;
+                if changed_o && changed_t && own_changed(&ours[oi], &theirs[ti]) {
+                    pending.push((key, "modified on both sides"));
+                    // The root has nowhere to put markers; it keeps ours
+                    conflict = (i != rb).then_some("modified on both sides");
+                }
+                let take = if changed_t && !changed_o { Take::Theirs } else { Take::Ours };
+                Decision { parent, take, conflict, whole: false }
+            }
+            (Some(oi), None) => {
+                let parent = o.parent(oi).map(ours_key);
+                let inherited = parent
+                    .and_then(|p| decided.get(&p))
+                    .is_some_and(|d| d.whole && d.take == Take::Ours);
+                if !inherited && o.hash(oi) == b.hash(i) {
+                    continue;
+                }
+                let conflict = (!inherited).then_some("modified in ours, deleted in theirs");
+                if let Some(reason) = conflict {
+                    pending.push((key, reason));
+                }
+                Decision { parent, take: Take::Ours, conflict, whole: true }
+            }
+            (None, Some(ti)) => {
+                let parent = t.parent(ti).map(theirs_key);
+                let inherited = parent
+                    .and_then(|p| decided.get(&p))
+                    .is_some_and(|d| d.whole && d.take == Take::Theirs);
+                if !inherited && t.hash(ti) == b.hash(i) {
+                    continue;
+                }
+                let conflict = (!inherited).then_some("deleted in ours, modified in theirs");
+                if let Some(reason) = conflict {
+                    pending.push((key, reason));
+                }
+                Decision { parent, take: Take::Theirs, conflict, whole: true }
+            }
+            (None, None) => continue,
+        };
+        decided.insert(key, decision);
+    }
+
+    // Insertions; theirs skips subtrees ours inserted identically at the same place
+    let mut inserted_o: HashMap<(Key, &str), Vec<usize>> = HashMap::new();
+    for j in o.subtree(ro) {
+        if b_of_o[j].is_some() {
+            continue;
+        }
+        let Some(parent) = o.parent(j).map(ours_key) else { continue };
+        if !decided.contains_key(&parent) {
+            continue;
+        }
+        if let Key::Base(_) = parent {
+            inserted_o.entry((parent, o.hash(j))).or_default().push(j);
+        }
+        decided.insert(
+            Key::Ours(j),
+            Decision { parent: Some(parent), take: Take::Ours, conflict: None, whole: false },
+        );
+    }
+    for j in t.subtree(rt) {
+        if b_of_t[j].is_some() {
+            continue;
+        }
+        let Some(parent) = t.parent(j).map(theirs_key) else { continue };
+        if !decided.contains_key(&parent) {
+            continue;
+        }
+        if let Key::Base(_) = parent {
+            let twin = inserted_o.get_mut(&(parent, t.hash(j))).and_then(|v| v.pop());
+            if twin.is_some() {
+                continue;
+            }
+        }
+        decided.insert(
+            Key::Theirs(j),
+            Decision { parent: Some(parent), take: Take::Theirs, conflict: None, whole: false },
+        );
+    }
+
+    // A move theirs made may hang a node below itself once ours moved too
+    let mut keys: Vec<Key> = decided.keys().copied().collect();
+    keys.sort_by_key(|k| match *k {
+        Key::Base(i) => (0, i),
+        Key::Ours(i) => (1, i),
+        Key::Theirs(i) => (2, i),
+    });
+    for &key in &keys {
+        let Key::Base(i) = key else { continue };
+        let (Some(oi), Some(_)) = (o_of[i], t_of[i]) else { continue };
+        let mut seen = HashSet::from([key]);
+        let mut cur = decided[&key].parent;
+        while let Some(p) = cur {
+            if !seen.insert(p) {
+                break;
+            }
+            cur = decided.get(&p).and_then(|d| d.parent);
+        }
+        if cur == Some(key) {
+            let po = o.parent(oi).map(ours_key);
+            if let Some(d) = decided.get_mut(&key) {
+                d.parent = po;
+            }
+            pending.push((key, "moved on both sides"));
+        }
+    }
+
+    let mut children: HashMap<Key, HashSet<Key>> = HashMap::new();
+    for (&key, d) in &decided {
+        if let Some(p) = d.parent {
+            children.entry(p).or_default().insert(key);
+        }
+    }
+
+    // Lay the tree out top-down, ordering each child list three ways
+    let mut index: HashMap<Key, usize> = HashMap::new();
+    let mut stack: Vec<(Key, Option<usize>, i32)> = vec![(Key::Base(rb), None, 0)];
+    while let Some((key, parent, position)) = stack.pop() {
+        let (bi, oi, ti) = match key {
+            Key::Base(i) => (Some(i), o_of[i], t_of[i]),
+            Key::Ours(j) => (None, Some(j), None),
+            Key::Theirs(j) => (None, None, Some(j)),
+        };
+        let d = &decided[&key];
+        let chosen = match (d.take, oi, ti) {
+            (Take::Ours, Some(j), _) | (Take::Theirs, Some(j), None) => &ours[j],
+            (_, _, Some(j)) => &theirs[j],
+            (_, None, None) => unreachable!("decided node present on neither side"),
+        };
+        let mut node = MergedNode {
+            parent,
+            sources: oi
+                .map(|j| ours[j].id.clone())
+                .into_iter()
+                .chain(ti.map(|j| theirs[j].id.clone()))
+                .collect(),
+            kind: chosen.kind.clone(),
+            content: chosen.content.clone(),
+            path: chosen.path.clone(),
+            position,
+            metadata: chosen.metadata.clone(),
+        };
+        if let Some(reason) = d.conflict {
+            node.kind = Kind::Conflict.as_str().to_string();
+            node.metadata = json!({
+                "reason": reason,
+                "kind": chosen.kind,
+                "base": bi.map_or(Value::Null, |i| side_text(&base[i])),
+                "ours": oi.map_or(Value::Null, |j| side_text(&ours[j])),
+                "theirs": ti.map_or(Value::Null, |j| side_text(&theirs[j])),
+            });
+        }
+        let at = merge.nodes.len();
+        merge.nodes.push(node);
+        index.insert(key, at);
+
+        let Some(set) = children.get(&key) else { continue };
+        let seq_o: Vec<Key> = oi.map(|j| o.kids(j).iter().map(|&c| ours_key(c)).collect()).unwrap_or_default();
+        let seq_t: Vec<Key> = ti.map(|j| t.kids(j).iter().map(|&c| theirs_key(c)).collect()).unwrap_or_default();
+        let seq_b: Vec<Key> = bi.map(|i| b.kids(i).iter().map(|&c| Key::Base(c)).collect()).unwrap_or_default();
+
+        // Keys all three sides order decide who reordered
+        let shared: HashSet<Key> = seq_b
+            .iter()
+            .copied()
+            .filter(|k| set.contains(k) && seq_o.contains(k) && seq_t.contains(k))
+            .collect();
+        let (base_order, ours_order, theirs_order) =
+            (only(&seq_b, &shared), only(&seq_o, &shared), only(&seq_t, &shared));
+        let theirs_reordered = theirs_order != base_order;
+        let ours_reordered = ours_order != base_order;
+        if ours_reordered && theirs_reordered && ours_order != theirs_order {
+            pending.push((key, "reordered on both sides"));
+        }
+        let (spine, other) = if theirs_reordered && !ours_reordered {
+            (&seq_t, &seq_o)
+        } else {
+            (&seq_o, &seq_t)
+        };
+
+        // The other side's additions go after their nearest placed predecessor
+        let mut order = only(spine, set);
+        let mut after: Option<Key> = None;
+        for &k in other.iter() {
+            if !set.contains(&k) {
+                continue;
+            }
+            if !order.contains(&k) {
+                let at = after.and_then(|a| order.iter().position(|&x| x == a)).map_or(0, |p| p + 1);
+                order.insert(at, k);
+            }
+            after = Some(k);
+        }
+        let mut rest: Vec<Key> = set.iter().copied().filter(|k| !order.contains(k)).collect();
+        rest.sort_by_key(|k| keys.iter().position(|x| x == k));
+        order.extend(rest);
+
+        for (pos, &k) in order.iter().enumerate().rev() {
+            stack.push((k, Some(at), pos as i32));
+        }
+    }
+
+    // Decided nodes whose parent the merge dropped are lost; say so
+    for &key in &keys {
+        let d = &decided[&key];
+        if !index.contains_key(&key) && d.parent.is_some_and(|p| !decided.contains_key(&p)) {
+            pending.push((key, "parent deleted"));
+        }
+    }
+
+    for (key, reason) in pending {
+        let (bi, oi, ti) = match key {
+            Key::Base(i) => (Some(i), o_of[i], t_of[i]),
+            Key::Ours(j) => (None, Some(j), None),
+            Key::Theirs(j) => (None, None, Some(j)),
+        };
+        let any = oi.map(|j| &ours[j]).or(ti.map(|j| &theirs[j])).or(bi.map(|i| &base[i]));
+        let node = index
+            .get(&key)
+            .copied()
+            .filter(|&n| merge.nodes[n].kind == Kind::Conflict.as_str());
+        merge.conflicts.push(Conflict {
+            reason,
+            node,
+            kind: any.map(|n| n.kind.clone()).unwrap_or_default(),
+            path: any.and_then(|n| n.path.clone()),
+            base: bi.map(|i| base[i].id.clone()),
+            ours: oi.map(|j| ours[j].id.clone()),
+            theirs: ti.map(|j| theirs[j].id.clone()),
+        });
+    }
+    merge
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, parent: &str, name: &str, source: &str, position: i32) -> TreeNode {
+        TreeNode {
+            id: id.to_string(),
+            parent_id: Some(parent.to_string()),
+            kind: "fn".to_string(),
+            content: Some(name.to_string()),
+            path: Some(format!("f.{name}")),
+            position,
+            metadata: json!({"start_line": position, "source": source}),
+        }
+    }
+
+    /// A file `<side>f` holding one fn per `(name, source)`, in order.
+    fn file(side: &str, items: &[(&str, &str)]) -> Vec<TreeNode> {
+        let root = format!("{side}f");
+        let mut nodes = vec![TreeNode {
+            id: root.clone(),
+            parent_id: None,
+            kind: "file".to_string(),
+            content: Some("f.rs".to_string()),
+            path: Some("f".to_string()),
+            position: 0,
+            metadata: json!({}),
+        }];
+        for (pos, (name, source)) in items.iter().enumerate() {
+            nodes.push(node(&format!("{side}{name}"), &root, name, source, pos as i32));
+        }
+        nodes
+    }
+
+    fn run(base: &[(&str, &str)], ours: &[(&str, &str)], theirs: &[(&str, &str)]) -> Merge {
+        merge_trees(&file("b", base), "bf", &file("o", ours), "of", &file("t", theirs), "tf")
+    }
+
+    /// Content of the root's children, in merged order.
+    fn top(m: &Merge) -> Vec<String> {
+        let mut kids: Vec<&MergedNode> = m.nodes.iter().filter(|n| n.parent == Some(0)).collect();
+        kids.sort_by_key(|n| n.position);
+        kids.iter().map(|n| n.content.clone().unwrap_or_default()).collect()
+    }
+
+    #[test]
+    fn disjoint_changes_merge_cleanly() {
+        let base = [("a", "fn a() {}"), ("b", "fn b() {}"), ("c", "fn c() {}")];
+        let m = run(
+            &base,
+            &[("a", "fn a() { 1 }"), ("b", "fn b() {}"), ("c", "fn c() {}")],
+            &[("a", "fn a() {}"), ("b", "fn b() {}"), ("d", "fn d() {}")],
+        );
+        assert!(m.conflicts.is_empty(), "{:?}", m.conflicts);
+        assert_eq!(top(&m), ["a", "b", "d"]);
+        assert_eq!(m.nodes[1].metadata["source"], "fn a() { 1 }");
+        assert_eq!(m.nodes[0].sources, ["of", "tf"]);
+    }
+
+    #[test]
+    fn insertions_follow_a_reorder() {
+        let base = [("a", "fn a() {}"), ("b", "fn b() {}"), ("c", "fn c() {}")];
+        let m = run(
+            &base,
+            &[("a", "fn a() {}"), ("b", "fn b() {}"), ("x", "fn x() {}"), ("c", "fn c() {}")],
+            &[("c", "fn c() {}"), ("a", "fn a() {}"), ("b", "fn b() {}")],
+        );
+        assert!(m.conflicts.is_empty(), "{:?}", m.conflicts);
+        assert_eq!(top(&m), ["c", "a", "b", "x"]);
+    }
+
+    #[test]
+    fn same_insertion_on_both_sides_is_kept_once() {
+        let base = [("a", "fn a() {}")];
+        let both = [("a", "fn a() {}"), ("x", "fn x() {}")];
+        let m = run(&base, &both, &both);
+        assert!(m.conflicts.is_empty(), "{:?}", m.conflicts);
+        assert_eq!(top(&m), ["a", "x"]);
+    }
+
+    #[test]
+    fn edits_of_one_node_on_both_sides_conflict() {
+        let base = [("a", "fn a() {}"), ("s", "fn s() {}")];
+        let m = run(
+            &base,
+            &[("a", "fn a() { 1 }"), ("s", "fn s() {}")],
+            &[("a", "fn a() { 2 }"), ("s", "fn s() {}")],
+        );
+        assert_eq!(m.conflicts.len(), 1, "{:?}", m.conflicts);
+        let c = &m.conflicts[0];
+        assert_eq!(c.reason, "modified on both sides");
+        let n = &m.nodes[c.node.expect("conflict node")];
+        assert_eq!(n.kind, "conflict");
+        assert_eq!(n.metadata["kind"], "fn");
+        assert_eq!(n.metadata["base"], "fn a() {}");
+        assert_eq!(n.metadata["ours"], "fn a() { 1 }");
+        assert_eq!(n.metadata["theirs"], "fn a() { 2 }");
+        assert_eq!(top(&m), ["a", "s"]);
+    }
+
+    #[test]
+    fn deleting_an_edited_node_conflicts() {
+        let base = [("a", "fn a() {}"), ("b", "fn b() {}")];
+        let mut theirs = file("t", &[("a", "fn a() { y }"), ("b", "fn b() {}")]);
+        theirs.push(node("ty", "ta", "y", "y", 0));
+        let m = merge_trees(&file("b", &base), "bf", &file("o", &[("b", "fn b() {}")]), "of", &theirs, "tf");
+        assert_eq!(m.conflicts.len(), 1, "{:?}", m.conflicts);
+        assert_eq!(m.conflicts[0].reason, "deleted in ours, modified in theirs");
+        let n = &m.nodes[m.conflicts[0].node.expect("conflict node")];
+        assert_eq!(n.metadata["ours"], Value::Null);
+        assert_eq!(n.metadata["theirs"], "fn a() { y }");
+        // Its subtree stays whole under the conflict node
+        assert!(m.nodes.iter().any(|x| x.content.as_deref() == Some("y")));
+
+        // Deleting an untouched node just deletes it
+        let m = run(&base, &[("b", "fn b() {}")], &base);
+        assert!(m.conflicts.is_empty(), "{:?}", m.conflicts);
+        assert_eq!(top(&m), ["b"]);
+    }
+}
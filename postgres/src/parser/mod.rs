@@ -7,19 +7,25 @@ use std::time::Instant;
 use uuid::Uuid;
 
 pub(crate) mod ast_walker;
+mod call_graph;
 mod cargo_parser;
 #[allow(dead_code)]
 mod comment_extractor;
 mod crate_walker;
+mod detect;
+pub(crate) mod diff;
 mod flag_parser;
 #[allow(dead_code)]
 pub(crate) mod inserter;
 pub mod kinds;
+pub(crate) mod merge;
 #[allow(dead_code)]
 mod metadata;
 mod normalizer;
 #[allow(dead_code)]
 mod path_builder;
+mod project;
+pub(crate) mod registry;
 pub mod markdown;
 mod suggestion_rules;
 mod treesitter;
@@ -27,6 +33,8 @@ pub mod go;
 pub mod c;
 pub mod latex;
 pub mod csv;
+pub mod toml;
+pub mod yaml;
 
 use ast_walker::NodeRow;
 use comment_extractor::{CommentBlock, CommentPlacement};
@@ -72,6 +80,12 @@ fn parse_crate(path: &str) -> pgrx::JsonB {
 
     let file_count = rs_files.len();
 
+    // Files are written a batch at a time rather than one by one: a crate
+    // of small files otherwise costs a dedup lookup, two INSERTs and a
+    // rehash of the crate node per file.
+    let mut pending_nodes: Vec<NodeRow> = Vec::new();
+    let mut pending_edges: Vec<ast_walker::EdgeRow> = Vec::new();
+
     for (file_idx, file_path) in rs_files.iter().enumerate() {
         let source = match std::fs::read_to_string(file_path) {
             Ok(s) => s,
@@ -87,20 +101,45 @@ fn parse_crate(path: &str) -> pgrx::JsonB {
             .to_string_lossy()
             .to_string();
 
-        let (nodes, edges) = parse_single_file(
+        if let Some((nodes, edges)) = build_file_rows(
             &source,
             &filename,
             &instance_id,
             Some(&crate_node_id),
             &crate_name,
             file_idx as i32,
-        );
-
-        total_nodes += nodes;
-        total_edges += edges;
+            Some(&filename),
+        ) {
+            pending_nodes.extend(nodes);
+            pending_edges.extend(edges);
+        }
+        if pending_nodes.len() >= inserter::BATCH_SIZE {
+            inserter::insert_deduped(&mut pending_nodes, &mut pending_edges);
+            total_nodes += pending_nodes.len();
+            total_edges += pending_edges.len();
+            pending_nodes.clear();
+            pending_edges.clear();
+        }
+        crate::jobs::report(file_idx + 1, file_count, &filename);
+    }
+    if !pending_nodes.is_empty() {
+        inserter::insert_deduped(&mut pending_nodes, &mut pending_edges);
+        total_nodes += pending_nodes.len();
+        total_edges += pending_edges.len();
     }
 
+    // Link call sites now that every file is in
+    let calls = call_graph::resolve_all();
+
     let elapsed = start.elapsed();
+    let run_id = record_run(
+        "parse_crate",
+        &crate_name,
+        file_count,
+        total_nodes,
+        total_edges,
+        elapsed.as_millis() as u64,
+    );
 
     // Auto-mint reward for crate parsing
     let details = json!({
@@ -109,25 +148,32 @@ fn parse_crate(path: &str) -> pgrx::JsonB {
         "nodes": total_nodes,
         "edges": total_edges,
     });
-    let details_str = details.to_string().replace('\'', "''");
-    let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-        "SELECT kerai.mint_reward('parse_crate', '{}'::jsonb)",
-        details_str,
-    ));
+    crate::currency::reward("parse_crate", "parse_run", Some(&run_id), details);
 
     pgrx::JsonB(json!({
         "crate": crate_name,
         "files": file_count,
         "nodes": total_nodes,
         "edges": total_edges,
+        "calls": calls,
         "elapsed_ms": elapsed.as_millis() as u64,
     }))
 }
 
-/// Parse a single Rust file into kerai.nodes and kerai.edges.
+/// Parse a single file into kerai.nodes and kerai.edges, with the parser
+/// for its language (see `detect_language`).
+///
+/// With `incremental`, a Rust file is diffed against its stored nodes
+/// instead of being deleted and reinserted (see `parse_source`).
+/// `source_path`, the file's path relative to its project root, is kept in
+/// a Rust file node's metadata so checkouts can write it back to the same
+/// place.
 #[pg_extern]
-fn parse_file(path: &str) -> pgrx::JsonB {
-    let start = Instant::now();
+fn parse_file(
+    path: &str,
+    incremental: default!(bool, false),
+    source_path: default!(Option<&str>, "NULL"),
+) -> pgrx::JsonB {
     let file_path = Path::new(path);
 
     if !file_path.exists() {
@@ -137,72 +183,189 @@ fn parse_file(path: &str) -> pgrx::JsonB {
     let source = std::fs::read_to_string(file_path)
         .unwrap_or_else(|e| pgrx::error!("Failed to read file: {}", e));
 
-    let instance_id = get_self_instance_id();
     let filename = file_path
         .file_name()
         .map(|f| f.to_string_lossy().to_string())
         .unwrap_or_else(|| path.to_string());
 
-    // Delete existing nodes for this file (idempotent re-parse)
-    inserter::delete_file_nodes(&instance_id, &filename);
+    parse_detected("parse_file", &source, &filename, incremental, source_path)
+}
+
+/// Parse source text directly (not from a file), with the parser for the
+/// language `detect_language` finds from `filename` and the text. Text it
+/// cannot place is parsed as Rust.
+///
+/// By default the file's existing nodes are deleted and everything is
+/// reinserted. For Rust, `incremental => true` instead diffs the new parse
+/// against stored nodes by path and content hash: unchanged subtrees keep
+/// their UUIDs (and everything referencing them), and only changed
+/// subtrees are inserted, updated or deleted. The result then also carries
+/// `{inserted, updated, deleted, unchanged}`.
+#[pg_extern]
+fn parse_source(source: &str, filename: &str, incremental: default!(bool, false)) -> pgrx::JsonB {
+    parse_detected("parse_source", source, filename, incremental, None)
+}
+
+/// Shared body of `parse_file`/`parse_source`: the registered parser for
+/// the detected language parses the file and mints its reward.
+fn parse_detected(
+    kind: &str,
+    source: &str,
+    filename: &str,
+    incremental: bool,
+    source_path: Option<&str>,
+) -> pgrx::JsonB {
+    let start = Instant::now();
+    let language = detect::detect(filename, source).unwrap_or("rust");
+    let mut result = match registry::REGISTRY.get(language) {
+        // Only Rust parses incrementally
+        Some(parser) if language != "rust" => parser.parse_source(source, filename).0,
+        _ => rust_source(source, filename, incremental, source_path).0,
+    };
+
+    result["language"] = json!(language);
+    let nodes = result["nodes"].as_u64().unwrap_or(0) as usize;
+    let edges = result["edges"].as_u64().unwrap_or(0) as usize;
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+    record_run(kind, filename, 1, nodes, edges, elapsed_ms);
+    pgrx::JsonB(result)
+}
 
-    let (node_count, edge_count) =
-        parse_single_file(&source, &filename, &instance_id, None, &filename, 0);
+/// Parse Rust source as a standalone file, minting the `parse_file` reward.
+fn rust_source(
+    source: &str,
+    filename: &str,
+    incremental: bool,
+    source_path: Option<&str>,
+) -> pgrx::JsonB {
+    let start = Instant::now();
+    let instance_id = get_self_instance_id();
+    let (node_count, edge_count, sync) =
+        parse_rust_source(source, filename, &instance_id, incremental, source_path);
 
     // Auto-mint reward for file parsing
     if node_count > 0 {
         let details = json!({"file": filename, "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_file', '{}'::jsonb)",
-            details_str,
-        ));
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), filename);
+        crate::currency::reward("parse_file", "node", file_id.as_deref(), details);
     }
 
-    let elapsed = start.elapsed();
-    pgrx::JsonB(json!({
-        "file": filename,
-        "nodes": node_count,
-        "edges": edge_count,
-        "elapsed_ms": elapsed.as_millis() as u64,
-    }))
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+    parse_result(filename, node_count, edge_count, sync, elapsed_ms)
 }
 
-/// Parse Rust source text directly (not from a file).
-#[pg_extern]
-fn parse_source(source: &str, filename: &str) -> pgrx::JsonB {
-    let start = Instant::now();
-    let instance_id = get_self_instance_id();
+/// Rust in the parser registry.
+pub(crate) struct Rust;
 
-    // Delete existing nodes for this filename (idempotent)
-    inserter::delete_file_nodes(&instance_id, filename);
+impl registry::LanguageParser for Rust {
+    fn name(&self) -> &'static str {
+        "rust"
+    }
 
-    let (node_count, edge_count) =
-        parse_single_file(source, filename, &instance_id, None, filename, 0);
+    fn extensions(&self) -> &'static [&'static str] {
+        &["rs"]
+    }
 
-    // Auto-mint reward for source parsing
-    if node_count > 0 {
-        let details = json!({"file": filename, "nodes": node_count, "edges": edge_count});
-        let details_str = details.to_string().replace('\'', "''");
-        let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-            "SELECT kerai.mint_reward('parse_file', '{}'::jsonb)",
-            details_str,
-        ));
+    fn kinds(&self) -> Vec<&'static str> {
+        Kind::ALL.iter().map(Kind::as_str).collect()
     }
 
-    let elapsed = start.elapsed();
-    pgrx::JsonB(json!({
+    fn parse_source(&self, source: &str, filename: &str) -> pgrx::JsonB {
+        rust_source(source, filename, false, None)
+    }
+
+    fn walk(&self, source: &str, filename: &str, at: &registry::Placement) -> (usize, usize) {
+        parse_single_file(
+            source,
+            filename,
+            at.instance_id,
+            at.parent_id,
+            at.path_root,
+            at.position,
+            at.source_path,
+        )
+    }
+}
+
+/// Shared body of `parse_file`/`parse_source`: full replace or incremental sync.
+fn parse_rust_source(
+    source: &str,
+    filename: &str,
+    instance_id: &str,
+    incremental: bool,
+    source_path: Option<&str>,
+) -> (usize, usize, Option<inserter::SyncStats>) {
+    if incremental {
+        let (nodes, edges, stats) =
+            parse_single_file_incremental(source, filename, instance_id, source_path);
+        return (nodes, edges, Some(stats));
+    }
+
+    // Delete existing nodes for this filename (idempotent re-parse)
+    inserter::delete_file_nodes(instance_id, filename);
+    let (nodes, edges) =
+        parse_single_file(source, filename, instance_id, None, filename, 0, source_path);
+    (nodes, edges, None)
+}
+
+/// Log a parse to kerai.parse_runs, which kerai.parse_stats reads, with
+/// the root path its nodes went under (the target as a path label), which
+/// the activity timeline files it under. Returns the run's id, which
+/// rewards for the parse refer to.
+fn record_run(
+    kind: &str,
+    target: &str,
+    files: usize,
+    nodes: usize,
+    edges: usize,
+    elapsed_ms: u64,
+) -> String {
+    Spi::get_one_with_args::<String>(
+        "INSERT INTO kerai.parse_runs (kind, target, files, nodes, edges, elapsed_ms, path)
+         VALUES ($1, $2, $3, $4, $5, $6, $7::ltree)
+         RETURNING id::text",
+        &[
+            kind.into(),
+            target.into(),
+            (files as i64).into(),
+            (nodes as i64).into(),
+            (edges as i64).into(),
+            (elapsed_ms as i64).into(),
+            path_builder::sanitize_label(target).into(),
+        ],
+    )
+    .expect("Failed to record parse run")
+    .expect("No parse run ID returned")
+}
+
+fn parse_result(
+    filename: &str,
+    node_count: usize,
+    edge_count: usize,
+    sync: Option<inserter::SyncStats>,
+    elapsed_ms: u64,
+) -> pgrx::JsonB {
+    let mut result = json!({
         "file": filename,
+        "language": "rust",
         "nodes": node_count,
         "edges": edge_count,
-        "elapsed_ms": elapsed.as_millis() as u64,
-    }))
+        "elapsed_ms": elapsed_ms,
+    });
+    if let Some(stats) = sync {
+        result["inserted"] = json!(stats.inserted);
+        result["updated"] = json!(stats.updated);
+        result["deleted"] = json!(stats.deleted);
+        result["unchanged"] = json!(stats.unchanged);
+    }
+    pgrx::JsonB(result)
 }
 
 /// Parse a directory tree in parallel using pg_background workers.
 ///
-/// Walks the directory, discovers parseable files (.rs, .go, .c, .h, .md),
-/// and processes them through a sliding-window worker pool that keeps
+/// Walks the directory, discovers files with an extension kerai parses
+/// (.rs, .go, .c, .h, .md, .toml, .yml, ...), and hands each to
+/// `kerai.parse_source` through a sliding-window worker pool that keeps
 /// `max_workers` background workers saturated without exceeding capacity.
 ///
 /// As each worker completes, a new file is immediately launched from the
@@ -273,44 +436,14 @@ fn parallel_parse(path: &str, max_workers: default!(i32, 0)) -> pgrx::JsonB {
             .to_string_lossy()
             .to_string();
 
-        let ext = file_path
-            .extension()
-            .map(|e| e.to_string_lossy().to_string())
-            .unwrap_or_default();
-
-        let cmd = match ext.as_str() {
-            "rs" => {
-                format!(
-                    "SELECT kerai.parse_source(pg_read_file('{}'), '{}')",
-                    abs_path,
-                    filename.replace('\'', "''")
-                )
-            }
-            "go" => format!("SELECT kerai.parse_go_file('{}')", abs_path),
-            "c" | "h" => format!("SELECT kerai.parse_c_file('{}')", abs_path),
-            "md" => {
-                let safe_name = filename.replace('\'', "''");
-                format!(
-                    "SELECT kerai.parse_markdown(pg_read_file('{}'), '{}')",
-                    abs_path, safe_name
-                )
-            }
-            "tex" | "sty" | "cls" => {
-                let safe_name = filename.replace('\'', "''");
-                format!(
-                    "SELECT kerai.parse_latex_source(pg_read_file('{}'), '{}')",
-                    abs_path, safe_name
-                )
-            }
-            "bib" => {
-                let safe_name = filename.replace('\'', "''");
-                format!(
-                    "SELECT kerai.parse_bibtex_source(pg_read_file('{}'), '{}')",
-                    abs_path, safe_name
-                )
-            }
-            _ => continue,
-        };
+        if detect::language_of(&filename).is_none() {
+            continue;
+        }
+        let cmd = format!(
+            "SELECT kerai.parse_source(pg_read_file('{}'), '{}')",
+            abs_path,
+            filename.replace('\'', "''")
+        );
 
         queue.push((filename, cmd));
     }
@@ -469,7 +602,57 @@ pub(crate) fn parse_single_file(
     parent_id: Option<&str>,
     path_root: &str,
     position: i32,
+    source_path: Option<&str>,
 ) -> (usize, usize) {
+    let Some((mut nodes, mut edges)) = build_file_rows(
+        source,
+        filename,
+        instance_id,
+        parent_id,
+        path_root,
+        position,
+        source_path,
+    ) else {
+        return (0, 0);
+    };
+
+    inserter::insert_deduped(&mut nodes, &mut edges);
+
+    (nodes.len(), edges.len())
+}
+
+/// Parse a single Rust file's source and sync it against the stored nodes
+/// for `filename`, keeping UUIDs of unchanged subtrees.
+///
+/// Returns node/edge counts of the new parse plus the sync breakdown.
+pub(crate) fn parse_single_file_incremental(
+    source: &str,
+    filename: &str,
+    instance_id: &str,
+    source_path: Option<&str>,
+) -> (usize, usize, inserter::SyncStats) {
+    let Some((mut nodes, mut edges)) =
+        build_file_rows(source, filename, instance_id, None, filename, 0, source_path)
+    else {
+        return (0, 0, inserter::SyncStats::default());
+    };
+
+    let stats = inserter::sync_file_nodes(instance_id, filename, &mut nodes, &mut edges);
+    (nodes.len(), edges.len(), stats)
+}
+
+/// Parse Rust source into rows without writing them; the file node is first.
+///
+/// Returns `None` when the source does not parse.
+fn build_file_rows(
+    source: &str,
+    filename: &str,
+    instance_id: &str,
+    parent_id: Option<&str>,
+    path_root: &str,
+    position: i32,
+    source_path: Option<&str>,
+) -> Option<(Vec<NodeRow>, Vec<ast_walker::EdgeRow>)> {
     // 1. Normalize source
     let normalized = normalizer::normalize(source);
 
@@ -499,15 +682,18 @@ pub(crate) fn parse_single_file(
         Ok(f) => f,
         Err(e) => {
             warning!("Failed to parse {}: {}", filename, e);
-            return (0, 0);
+            return None;
         }
     };
 
     // 3. Create file node (with kerai_flags if present)
-    let file_node_id = Uuid::new_v4().to_string();
+    let file_node_id = Uuid::now_v7().to_string();
     let path_ctx = PathContext::with_root(path_root);
 
     let mut file_metadata = json!({"line_count": normalized.lines().count()});
+    if let Some(source_path) = source_path {
+        file_metadata["source_path"] = json!(source_path);
+    }
     if let Some(ref flags) = kerai_flags {
         file_metadata
             .as_object_mut()
@@ -529,8 +715,6 @@ pub(crate) fn parse_single_file(
         span_end: None,
     };
 
-    inserter::insert_nodes(&[file_node]);
-
     // 4. Walk AST
     let (mut nodes, mut edges) =
         ast_walker::walk_file(&syn_file, &file_node_id, instance_id, path_ctx);
@@ -569,7 +753,7 @@ pub(crate) fn parse_single_file(
 
     // 9. Create NodeRow + EdgeRow for each comment block
     for (block_idx, block) in blocks.iter().enumerate() {
-        let comment_id = Uuid::new_v4().to_string();
+        let comment_id = Uuid::now_v7().to_string();
         let kind = if !block.is_block_style && block.lines.len() > 1 {
             Kind::CommentBlock
         } else {
@@ -609,7 +793,7 @@ pub(crate) fn parse_single_file(
         // Create "documents" edge if matched to a node
         if let Some(ref target_id) = matches[block_idx] {
             edges.push(ast_walker::EdgeRow {
-                id: Uuid::new_v4().to_string(),
+                id: Uuid::now_v7().to_string(),
                 source_id: comment_id,
                 target_id: target_id.clone(),
                 relation: "documents".to_string(),
@@ -693,7 +877,7 @@ pub(crate) fn parse_single_file(
                 continue;
             }
 
-            let suggestion_id = Uuid::new_v4().to_string();
+            let suggestion_id = Uuid::now_v7().to_string();
             let content_hash = simple_hash(&finding.target_node_id);
 
             nodes.push(NodeRow {
@@ -717,7 +901,7 @@ pub(crate) fn parse_single_file(
             });
 
             edges.push(ast_walker::EdgeRow {
-                id: Uuid::new_v4().to_string(),
+                id: Uuid::now_v7().to_string(),
                 source_id: suggestion_id,
                 target_id: finding.target_node_id.clone(),
                 relation: "suggests".to_string(),
@@ -729,13 +913,8 @@ pub(crate) fn parse_single_file(
         update_suggestion_statuses(&prev_suggestions, &findings, &file_node_id);
     }
 
-    let node_count = nodes.len() + 1; // +1 for file node
-    let edge_count = edges.len();
-
-    inserter::insert_nodes(&nodes);
-    inserter::insert_edges(&edges);
-
-    (node_count, edge_count)
+    nodes.insert(0, file_node);
+    Some((nodes, edges))
 }
 
 /// Query previously dismissed suggestion rule+target pairs for a file.
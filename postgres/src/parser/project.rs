@@ -0,0 +1,344 @@
+/// Parse a whole directory tree — every file a kerai parser understands —
+/// under one `project` node.
+///
+/// A folder with a Cargo.toml that has a `[package]` becomes a `crate`
+/// node, as `parse_crate` makes it, and a folder of `.go` files a
+/// `package` node. Each file hangs off the crate or package it belongs to,
+/// or off the project node. Parsing the same root again replaces the
+/// whole tree.
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::time::Instant;
+
+use pgrx::prelude::*;
+use serde_json::json;
+use uuid::Uuid;
+
+use super::ast_walker::NodeRow;
+use super::detect::language_of;
+use super::kinds::Kind;
+use super::path_builder::sanitize_label;
+use super::registry::{LanguageParser, Placement, REGISTRY};
+use super::{cargo_parser, inserter};
+
+/// Folders never walked into, besides hidden ones.
+const SKIPPED_DIRS: &[&str] = &["target", "tgt", "node_modules", "vendor"];
+
+/// Whether `path` (relative, `/`-separated) matches glob `pattern`. `*` and
+/// `?` stay within one folder and `**` spans any number of them. As in
+/// .gitignore, a pattern without `/` is matched against the file name.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let target = if pattern.contains('/') {
+        path
+    } else {
+        file_name(path)
+    };
+    let pattern: Vec<char> = pattern.trim_start_matches("./").chars().collect();
+    let target: Vec<char> = target.chars().collect();
+    glob_chars(&pattern, &target)
+}
+
+fn glob_chars(p: &[char], s: &[char]) -> bool {
+    match p {
+        [] => s.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` also matches no folder at all
+            (rest.first() == Some(&'/') && glob_chars(&rest[1..], s))
+                || (0..=s.len()).any(|i| glob_chars(rest, &s[i..]))
+        }
+        ['*', rest @ ..] => (0..=s.len())
+            .take_while(|&i| i == 0 || s[i - 1] != '/')
+            .any(|i| glob_chars(rest, &s[i..])),
+        ['?', rest @ ..] => s.first().is_some_and(|&c| c != '/') && glob_chars(rest, &s[1..]),
+        [c, rest @ ..] => s.first() == Some(c) && glob_chars(rest, &s[1..]),
+    }
+}
+
+/// Last part of a relative path.
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Folder part of a relative path, "" at the root.
+fn dir_of(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Whether `path` lies in folder `dir` or below it.
+fn within(path: &str, dir: &str) -> bool {
+    dir.is_empty() || path.starts_with(&format!("{}/", dir))
+}
+
+/// Per-language tally for the summary.
+#[derive(Default)]
+struct Tally {
+    files: usize,
+    nodes: usize,
+    edges: usize,
+}
+
+/// Remove the tree an earlier `parse_project` of `root_path` made.
+fn delete_project(instance_id: &str, root_path: &str) {
+    let subtree = "WITH RECURSIVE d AS (
+            SELECT id FROM kerai.nodes
+            WHERE instance_id = $1::uuid AND kind = 'project' AND metadata->>'root_path' = $2
+            UNION ALL
+            SELECT n.id FROM kerai.nodes n JOIN d ON n.parent_id = d.id
+        )";
+    Spi::run_with_args(
+        &format!(
+            "{subtree} DELETE FROM kerai.edges
+             WHERE source_id IN (SELECT id FROM d) OR target_id IN (SELECT id FROM d)"
+        ),
+        &[instance_id.into(), root_path.into()],
+    )
+    .unwrap();
+    Spi::run_with_args(
+        &format!("{subtree} DELETE FROM kerai.nodes WHERE id IN (SELECT id FROM d)"),
+        &[instance_id.into(), root_path.into()],
+    )
+    .unwrap();
+}
+
+/// Parse every file under `root_path` with the parser its extension calls
+/// for. With `include_globs`, only files matching one of them are parsed;
+/// files matching any of `exclude_globs` never are. Hidden folders,
+/// `target`, `node_modules` and `vendor` are skipped.
+///
+/// Returns `{project, root, files, nodes, edges, skipped, crates,
+/// packages, languages: {<language>: {files, nodes, edges}}, calls,
+/// elapsed_ms}`.
+#[pg_extern]
+fn parse_project(
+    root_path: &str,
+    include_globs: default!(Option<Vec<String>>, "NULL"),
+    exclude_globs: default!(Option<Vec<String>>, "NULL"),
+) -> pgrx::JsonB {
+    let start = Instant::now();
+    let root = Path::new(root_path);
+    if !root.is_dir() {
+        pgrx::error!("Project path is not a directory: {}", root_path);
+    }
+    let include = include_globs.unwrap_or_default();
+    let exclude = exclude_globs.unwrap_or_default();
+    let excluded = |rel: &str| exclude.iter().any(|g| glob_match(g, rel));
+    let included = |rel: &str| include.is_empty() || include.iter().any(|g| glob_match(g, rel));
+
+    // Every file left after the exclusions, by relative path
+    let mut files: Vec<String> = Vec::new();
+    for entry in walkdir::WalkDir::new(root)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            e.depth() == 0
+                || !(name.starts_with('.')
+                    || (e.file_type().is_dir() && SKIPPED_DIRS.contains(&name.as_ref())))
+        })
+    {
+        let Ok(entry) = entry else { continue };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry
+            .path()
+            .strip_prefix(root)
+            .unwrap_or(entry.path())
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if !excluded(&rel) {
+            files.push(rel);
+        }
+    }
+
+    let instance_id = super::get_self_instance_id();
+    delete_project(&instance_id, root_path);
+
+    let project_name = std::fs::canonicalize(root)
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| root_path.to_string());
+    let project_id = Uuid::now_v7().to_string();
+    let project_label = sanitize_label(&project_name);
+    inserter::insert_nodes(&[NodeRow {
+        id: project_id.clone(),
+        instance_id: instance_id.clone(),
+        kind: Kind::Project.as_str().to_string(),
+        language: None,
+        content: Some(project_name.clone()),
+        parent_id: None,
+        position: 0,
+        path: Some(project_label.clone()),
+        metadata: json!({"root_path": root_path, "include": include, "exclude": exclude}),
+        span_start: None,
+        span_end: None,
+    }]);
+    let mut total_nodes = 1;
+
+    // Crates first, deepest folder first so files find their nearest one
+    let mut crates: Vec<(String, String, String)> = Vec::new(); // (dir, node id, name)
+    for rel in files.iter().filter(|f| file_name(f) == "Cargo.toml") {
+        let Ok((mut nodes, crate_id, crate_name)) =
+            cargo_parser::parse_cargo_toml(&root.join(rel), &instance_id)
+        else {
+            // A workspace manifest is parsed as plain TOML below
+            continue;
+        };
+        nodes[0].parent_id = Some(project_id.clone());
+        nodes[0].position = crates.len() as i32;
+        inserter::insert_nodes(&nodes);
+        total_nodes += nodes.len();
+        crates.push((dir_of(rel).to_string(), crate_id, crate_name));
+    }
+    crates.sort_by_key(|(dir, _, _)| std::cmp::Reverse(dir.len()));
+
+    // Go packages, one per folder of .go files
+    let mut packages: BTreeMap<String, String> = BTreeMap::new(); // dir -> node id
+    for rel in files.iter().filter(|f| language_of(f) == Some("go")) {
+        let dir = dir_of(rel);
+        if packages.contains_key(dir) || !included(rel) {
+            continue;
+        }
+        let id = Uuid::now_v7().to_string();
+        let mut path = project_label.clone();
+        for part in dir.split('/').filter(|p| !p.is_empty()) {
+            path.push('.');
+            path.push_str(&sanitize_label(part));
+        }
+        inserter::insert_nodes(&[NodeRow {
+            id: id.clone(),
+            instance_id: instance_id.clone(),
+            kind: Kind::Package.as_str().to_string(),
+            language: Some("go".to_string()),
+            content: Some(if dir.is_empty() {
+                project_name.clone()
+            } else {
+                dir.to_string()
+            }),
+            parent_id: Some(project_id.clone()),
+            position: packages.len() as i32,
+            path: Some(path),
+            metadata: json!({"dir": dir}),
+            span_start: None,
+            span_end: None,
+        }]);
+        total_nodes += 1;
+        packages.insert(dir.to_string(), id);
+    }
+
+    let crate_dirs: HashSet<&str> = crates.iter().map(|(dir, _, _)| dir.as_str()).collect();
+    let mut languages: BTreeMap<&str, Tally> = BTreeMap::new();
+    let mut total_edges = 0;
+    let mut skipped = 0;
+    let parsed: Vec<(&String, &'static dyn LanguageParser)> = files
+        .iter()
+        .filter(|rel| included(rel))
+        .filter_map(|rel| REGISTRY.for_file(rel).map(|parser| (rel, parser)))
+        // A crate's manifest is already its crate node
+        .filter(|(rel, _)| !(file_name(rel) == "Cargo.toml" && crate_dirs.contains(dir_of(rel))))
+        .collect();
+
+    for (done, &(rel, parser)) in parsed.iter().enumerate() {
+        let source = match std::fs::read_to_string(root.join(rel.as_str())) {
+            Ok(s) => s,
+            Err(e) => {
+                warning!("Skipping {}: {}", rel, e);
+                skipped += 1;
+                continue;
+            }
+        };
+        let owner = crates.iter().find(|(dir, _, _)| within(rel, dir));
+        let parent = match packages.get(dir_of(rel)) {
+            Some(id) if parser.name() == "go" => id.as_str(),
+            _ => owner.map_or(project_id.as_str(), |(_, id, _)| id.as_str()),
+        };
+        let parent = Some(parent);
+
+        let at = Placement {
+            instance_id: &instance_id,
+            parent_id: parent,
+            path_root: owner.map_or(project_name.as_str(), |(_, _, name)| name.as_str()),
+            position: done as i32,
+            source_path: Some(rel),
+        };
+        let (nodes, edges) = parser.walk(&source, rel, &at);
+        let tally = languages.entry(parser.name()).or_default();
+        tally.files += 1;
+        tally.nodes += nodes;
+        tally.edges += edges;
+        total_nodes += nodes;
+        total_edges += edges;
+        crate::jobs::report(done + 1, parsed.len(), rel);
+    }
+
+    // Link call sites now that every Rust file is in
+    let calls = if languages.contains_key("rust") {
+        json!(super::call_graph::resolve_all())
+    } else {
+        json!(null)
+    };
+
+    let file_count = parsed.len() - skipped;
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+    let run_id = super::record_run(
+        "parse_project",
+        &project_name,
+        file_count,
+        total_nodes,
+        total_edges,
+        elapsed_ms,
+    );
+    for (_, _, crate_name) in &crates {
+        crate::currency::reward(
+            "parse_crate",
+            "parse_run",
+            Some(&run_id),
+            json!({"crate": crate_name, "project": project_name}),
+        );
+    }
+
+    let languages: serde_json::Map<String, serde_json::Value> = languages
+        .into_iter()
+        .map(|(lang, t)| {
+            (
+                lang.to_string(),
+                json!({"files": t.files, "nodes": t.nodes, "edges": t.edges}),
+            )
+        })
+        .collect();
+    pgrx::JsonB(json!({
+        "project": project_name,
+        "root": root_path,
+        "files": file_count,
+        "nodes": total_nodes,
+        "edges": total_edges,
+        "skipped": skipped,
+        "crates": crates.iter().map(|(_, _, name)| name).collect::<Vec<_>>(),
+        "packages": packages.len(),
+        "languages": languages,
+        "calls": calls,
+        "elapsed_ms": elapsed_ms,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_follows_gitignore_rules() {
+        assert!(glob_match("*.md", "docs/guide/intro.md"));
+        assert!(!glob_match("*.md", "docs/intro.mdx"));
+        assert!(glob_match("docs/*.md", "docs/intro.md"));
+        assert!(!glob_match("docs/*.md", "docs/guide/intro.md"));
+        assert!(glob_match("docs/**/*.md", "docs/intro.md"));
+        assert!(glob_match("docs/**/*.md", "docs/guide/intro.md"));
+        assert!(glob_match("**/tests/**", "kerai/tests/cli.rs"));
+        assert!(glob_match("src/?.rs", "src/a.rs"));
+        assert!(!glob_match("src/?.rs", "src/ab.rs"));
+        assert!(glob_match("./fixtures/*", "fixtures/a.csv"));
+    }
+}
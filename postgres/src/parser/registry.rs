@@ -0,0 +1,157 @@
+/// Parser registry — every language kerai parses, behind one trait.
+///
+/// A language joins by implementing `LanguageParser` in its own module and
+/// being listed in `REGISTRY`; extension lookup, `parse_file`/`parse_source`
+/// dispatch, `parse_project` and `supported_languages` all go through here.
+use pgrx::prelude::*;
+use serde_json::json;
+
+use super::kinds::Kind;
+
+/// Where a walked file's nodes go in the graph.
+pub(crate) struct Placement<'a> {
+    pub instance_id: &'a str,
+    /// Node the file node is parented under (a crate, package or project)
+    pub parent_id: Option<&'a str>,
+    /// Leading ltree label of Rust item paths (the crate or project name)
+    pub path_root: &'a str,
+    /// Order of the file among its siblings
+    pub position: i32,
+    /// Path relative to the project root, kept in Rust file node metadata
+    pub source_path: Option<&'a str>,
+}
+
+/// One language's parser: what it claims and how it walks a file.
+pub(crate) trait LanguageParser: Sync {
+    /// Language name, as `detect_language` reports it.
+    fn name(&self) -> &'static str;
+
+    /// Lowercase file extensions the parser owns, without the dot.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Node kinds the parser produces, shared ones included.
+    fn kinds(&self) -> Vec<&'static str>;
+
+    /// Parse `source` as a standalone file, replacing an earlier parse of
+    /// it, and mint the language's reward.
+    ///
+    /// Returns JSON: `{file, nodes, edges, elapsed_ms, ...}`.
+    fn parse_source(&self, source: &str, filename: &str) -> pgrx::JsonB;
+
+    /// Insert `source`'s nodes and edges at `at`, returning their counts.
+    fn walk(&self, source: &str, filename: &str, at: &Placement) -> (usize, usize);
+}
+
+/// The registered parsers, in the order `supported_languages` lists them.
+pub(crate) struct ParserRegistry {
+    parsers: &'static [&'static dyn LanguageParser],
+}
+
+pub(crate) static REGISTRY: ParserRegistry = ParserRegistry {
+    parsers: &[
+        &super::Rust,
+        &super::go::Go,
+        &super::c::C,
+        &super::c::Cpp,
+        &super::markdown::Markdown,
+        &super::latex::Latex,
+        &super::latex::Bibtex,
+        &super::toml::Toml,
+        &super::yaml::Yaml,
+    ],
+};
+
+impl ParserRegistry {
+    pub(crate) fn all(&self) -> &'static [&'static dyn LanguageParser] {
+        self.parsers
+    }
+
+    /// Parser for a language name.
+    pub(crate) fn get(&self, name: &str) -> Option<&'static dyn LanguageParser> {
+        self.parsers.iter().copied().find(|p| p.name() == name)
+    }
+
+    /// Parser owning a file's extension.
+    pub(crate) fn for_file(&self, filename: &str) -> Option<&'static dyn LanguageParser> {
+        let ext = filename.rsplit_once('.')?.1.to_lowercase();
+        self.parsers
+            .iter()
+            .copied()
+            .find(|p| p.extensions().contains(&ext.as_str()))
+    }
+}
+
+/// Shared kinds of the parsers that extract comments and suggestions.
+pub(crate) const COMMENTED: &[Kind] = &[
+    Kind::File,
+    Kind::Comment,
+    Kind::CommentBlock,
+    Kind::Suggestion,
+];
+
+/// `shared` kinds as strings, followed by a language's own.
+pub(crate) fn kind_list(shared: &[Kind], own: &[&'static str]) -> Vec<&'static str> {
+    shared
+        .iter()
+        .map(Kind::as_str)
+        .chain(own.iter().copied())
+        .collect()
+}
+
+/// Every language kerai parses, with the extensions it claims and the node
+/// kinds it produces: `[{name, extensions, kinds}]`.
+#[pg_extern]
+fn supported_languages() -> pgrx::JsonB {
+    let languages: Vec<serde_json::Value> = REGISTRY
+        .all()
+        .iter()
+        .map(|p| {
+            json!({
+                "name": p.name(),
+                "extensions": p.extensions(),
+                "kinds": p.kinds(),
+            })
+        })
+        .collect();
+    pgrx::JsonB(json!(languages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn extensions_belong_to_one_parser() {
+        let mut seen = HashSet::new();
+        for parser in REGISTRY.all() {
+            assert!(!parser.extensions().is_empty(), "{}", parser.name());
+            for ext in parser.extensions() {
+                assert_eq!(*ext, ext.to_lowercase());
+                assert!(seen.insert(*ext), "{} claimed twice", ext);
+            }
+        }
+        assert_eq!(
+            REGISTRY.for_file("src/Main.GO").map(|p| p.name()),
+            Some("go")
+        );
+        assert!(REGISTRY.for_file("Makefile").is_none());
+    }
+
+    #[test]
+    fn kinds_are_unique_per_parser() {
+        let mut names = HashSet::new();
+        for parser in REGISTRY.all() {
+            assert!(names.insert(parser.name()));
+            let kinds = parser.kinds();
+            let unique: HashSet<_> = kinds.iter().collect();
+            assert_eq!(unique.len(), kinds.len(), "{}", parser.name());
+            assert!(!kinds.is_empty(), "{}", parser.name());
+        }
+        assert!(REGISTRY
+            .get("bibtex")
+            .unwrap()
+            .kinds()
+            .contains(&"bib_entry"));
+    }
+}
@@ -0,0 +1,12 @@
+// TOML node kind constants, prefixed with `toml_` to avoid collisions
+// with other language kinds in the `kerai.nodes.kind` column.
+//
+// Dependency entries in a Cargo.toml reuse the shared `dependency` kind so
+// they are queried the same way as those produced by `parse_crate`.
+
+pub const TOML_TABLE: &str = "toml_table";
+pub const TOML_ARRAY_TABLE: &str = "toml_array_table";
+pub const TOML_KEY: &str = "toml_key";
+
+/// Every TOML kind, for the parser registry.
+pub const ALL: &[&str] = &[TOML_TABLE, TOML_ARRAY_TABLE, TOML_KEY];
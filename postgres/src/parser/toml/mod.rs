@@ -0,0 +1,168 @@
+/// TOML parser module — TOML source → kerai.nodes + kerai.edges.
+///
+/// Cargo.toml files additionally get a crate node with `depends_on` edges to
+/// each dependency, so `kerai.refs('serde')` can report dependent crates.
+use pgrx::prelude::*;
+use serde_json::json;
+use std::path::Path;
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::parser::ast_walker::NodeRow;
+use crate::parser::inserter;
+use crate::parser::kinds::Kind;
+use crate::parser::path_builder::PathContext;
+use crate::parser::registry::{self, LanguageParser, Placement};
+
+pub mod kinds;
+mod walker;
+
+/// Parse TOML source text directly into kerai.nodes and kerai.edges.
+///
+/// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
+#[pg_extern]
+pub(super) fn parse_toml_source(source: &str, filename: &str) -> pgrx::JsonB {
+    let start = Instant::now();
+    let instance_id = super::get_self_instance_id();
+
+    // Delete existing nodes for this filename (idempotent re-parse)
+    inserter::delete_file_nodes(&instance_id, filename);
+
+    let (node_count, edge_count) = parse_toml_single(source, filename, &instance_id, None);
+
+    // Auto-mint reward
+    if node_count > 0 {
+        let details = json!({"file": filename, "language": "toml", "nodes": node_count, "edges": edge_count});
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), filename);
+        crate::currency::reward("parse_toml_source", "node", file_id.as_deref(), details);
+    }
+
+    let elapsed = start.elapsed();
+    pgrx::JsonB(json!({
+        "file": filename,
+        "language": "toml",
+        "nodes": node_count,
+        "edges": edge_count,
+        "elapsed_ms": elapsed.as_millis() as u64,
+    }))
+}
+
+/// Parse a TOML file from disk into kerai.nodes and kerai.edges.
+///
+/// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
+#[pg_extern]
+fn parse_toml_file(path: &str) -> pgrx::JsonB {
+    let start = Instant::now();
+    let file_path = Path::new(path);
+
+    if !file_path.exists() {
+        pgrx::error!("File does not exist: {}", path);
+    }
+
+    let source = std::fs::read_to_string(file_path)
+        .unwrap_or_else(|e| pgrx::error!("Failed to read file: {}", e));
+
+    let instance_id = super::get_self_instance_id();
+    let filename = file_path
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string());
+
+    // Delete existing nodes for this file (idempotent re-parse)
+    inserter::delete_file_nodes(&instance_id, &filename);
+
+    let (node_count, edge_count) = parse_toml_single(&source, &filename, &instance_id, None);
+
+    // Auto-mint reward
+    if node_count > 0 {
+        let details = json!({"file": filename, "language": "toml", "nodes": node_count, "edges": edge_count});
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), &filename);
+        crate::currency::reward("parse_toml_file", "node", file_id.as_deref(), details);
+    }
+
+    let elapsed = start.elapsed();
+    pgrx::JsonB(json!({
+        "file": filename,
+        "language": "toml",
+        "nodes": node_count,
+        "edges": edge_count,
+        "elapsed_ms": elapsed.as_millis() as u64,
+    }))
+}
+
+/// TOML in the parser registry.
+pub(crate) struct Toml;
+
+impl LanguageParser for Toml {
+    fn name(&self) -> &'static str {
+        "toml"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["toml"]
+    }
+
+    fn kinds(&self) -> Vec<&'static str> {
+        registry::kind_list(&[Kind::File, Kind::Crate, Kind::Dependency], kinds::ALL)
+    }
+
+    fn parse_source(&self, source: &str, filename: &str) -> pgrx::JsonB {
+        parse_toml_source(source, filename)
+    }
+
+    fn walk(&self, source: &str, filename: &str, at: &Placement) -> (usize, usize) {
+        parse_toml_single(source, filename, at.instance_id, at.parent_id)
+    }
+}
+
+/// Parse TOML source, insert nodes/edges, return counts.
+///
+/// A file whose name ends in `Cargo.toml` is treated as a Cargo manifest.
+pub(crate) fn parse_toml_single(
+    source: &str,
+    filename: &str,
+    instance_id: &str,
+    parent_id: Option<&str>,
+) -> (usize, usize) {
+    let table: toml::Table = match source.parse() {
+        Ok(t) => t,
+        Err(e) => {
+            warning!("Failed to parse TOML source {}: {}", filename, e);
+            return (0, 0);
+        }
+    };
+
+    let is_cargo = Path::new(filename)
+        .file_name()
+        .is_some_and(|f| f == "Cargo.toml");
+
+    // Create file node
+    let file_node_id = Uuid::now_v7().to_string();
+    let path_ctx = PathContext::with_root(filename);
+
+    let file_node = NodeRow {
+        id: file_node_id.clone(),
+        instance_id: instance_id.to_string(),
+        kind: Kind::File.as_str().to_string(),
+        language: Some("toml".to_string()),
+        content: Some(filename.to_string()),
+        parent_id: parent_id.map(std::string::ToString::to_string),
+        position: 0,
+        path: path_ctx.path(),
+        metadata: json!({"line_count": source.lines().count(), "cargo_manifest": is_cargo}),
+        span_start: None,
+        span_end: None,
+    };
+    inserter::insert_nodes(&[file_node]);
+
+    let (nodes, edges) =
+        walker::walk_toml_file(&table, &file_node_id, instance_id, path_ctx, is_cargo);
+
+    let node_count = nodes.len() + 1; // +1 for file node
+    let edge_count = edges.len();
+
+    inserter::insert_nodes(&nodes);
+    inserter::insert_edges(&edges);
+
+    (node_count, edge_count)
+}
@@ -0,0 +1,390 @@
+/// TOML walker — converts a parsed `toml::Table` into NodeRow/EdgeRow vectors.
+///
+/// Tables become `toml_table` nodes, arrays of tables become `toml_array_table`
+/// nodes with one `toml_table` child per element, and everything else becomes a
+/// `toml_key` node carrying its value in metadata. In a Cargo manifest, entries
+/// of the dependency tables become `dependency` nodes linked from the crate node
+/// by `depends_on` edges.
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::parser::ast_walker::{EdgeRow, NodeRow};
+use crate::parser::kinds::Kind;
+use crate::parser::path_builder::PathContext;
+
+use super::kinds;
+
+/// Cargo dependency table names and the `dep_type` recorded for each.
+const DEPENDENCY_TABLES: [(&str, &str); 3] = [
+    ("dependencies", "normal"),
+    ("dev-dependencies", "dev"),
+    ("build-dependencies", "build"),
+];
+
+/// Walk context accumulator passed through the recursion.
+struct TomlWalkCtx {
+    instance_id: String,
+    nodes: Vec<NodeRow>,
+    edges: Vec<EdgeRow>,
+    path_ctx: PathContext,
+    is_cargo: bool,
+    /// Crate node id, set when walking a Cargo.toml with a `[package]` name.
+    crate_id: Option<String>,
+}
+
+impl TomlWalkCtx {
+    fn new_node(
+        &mut self,
+        kind: &str,
+        content: &str,
+        segment: &str,
+        parent_id: &str,
+        position: i32,
+        meta: Value,
+    ) -> String {
+        let id = Uuid::now_v7().to_string();
+        self.nodes.push(NodeRow {
+            id: id.clone(),
+            instance_id: self.instance_id.clone(),
+            kind: kind.to_string(),
+            language: Some("toml".to_string()),
+            content: Some(content.to_string()),
+            parent_id: Some(parent_id.to_string()),
+            position,
+            path: Some(self.path_ctx.child_path(segment)),
+            metadata: meta,
+            span_start: None,
+            span_end: None,
+        });
+        id
+    }
+}
+
+/// Walk a parsed TOML document rooted at `file_node_id`.
+///
+/// `is_cargo` enables Cargo.toml handling: a `crate` node for `[package]` and
+/// `dependency` nodes with `depends_on` edges for the dependency tables.
+pub fn walk_toml_file(
+    table: &toml::Table,
+    file_node_id: &str,
+    instance_id: &str,
+    path_ctx: PathContext,
+    is_cargo: bool,
+) -> (Vec<NodeRow>, Vec<EdgeRow>) {
+    let mut ctx = TomlWalkCtx {
+        instance_id: instance_id.to_string(),
+        nodes: Vec::new(),
+        edges: Vec::new(),
+        path_ctx,
+        is_cargo,
+        crate_id: None,
+    };
+
+    let mut position = 0;
+    if is_cargo {
+        let package = table.get("package").and_then(|p| p.as_table());
+        if let Some(name) = package.and_then(|p| p.get("name")).and_then(|n| n.as_str()) {
+            let mut meta = serde_json::Map::new();
+            for field in ["version", "edition", "description"] {
+                if let Some(v) = package.and_then(|p| p.get(field)).and_then(|v| v.as_str()) {
+                    meta.insert(field.into(), json!(v));
+                }
+            }
+            let id = Uuid::now_v7().to_string();
+            ctx.nodes.push(NodeRow {
+                id: id.clone(),
+                instance_id: ctx.instance_id.clone(),
+                kind: Kind::Crate.as_str().to_string(),
+                language: Some("rust".to_string()),
+                content: Some(name.to_string()),
+                parent_id: Some(file_node_id.to_string()),
+                position,
+                path: Some(ctx.path_ctx.child_path(name)),
+                metadata: Value::Object(meta),
+                span_start: None,
+                span_end: None,
+            });
+            ctx.crate_id = Some(id);
+            position += 1;
+        }
+    }
+
+    walk_table(&mut ctx, table, file_node_id, &[], position);
+    (ctx.nodes, ctx.edges)
+}
+
+fn walk_table(
+    ctx: &mut TomlWalkCtx,
+    table: &toml::Table,
+    parent_id: &str,
+    keys: &[String],
+    start_position: i32,
+) {
+    let dep_table = if ctx.is_cargo { dependency_table(keys) } else { None };
+
+    for (i, (key, value)) in table.iter().enumerate() {
+        let position = start_position + i as i32;
+
+        if let Some((dep_type, target)) = dep_table {
+            walk_dependency(ctx, key, value, parent_id, position, dep_type, target, keys);
+            continue;
+        }
+
+        match value {
+            toml::Value::Table(t) => {
+                let id = ctx.new_node(
+                    kinds::TOML_TABLE,
+                    key,
+                    key,
+                    parent_id,
+                    position,
+                    json!({"keys": t.len()}),
+                );
+                let child_keys = push_key(keys, key);
+                ctx.path_ctx.push(key);
+                walk_table(ctx, t, &id, &child_keys, 0);
+                ctx.path_ctx.pop();
+            }
+            toml::Value::Array(items) if !items.is_empty() && items.iter().all(|v| v.is_table()) => {
+                let id = ctx.new_node(
+                    kinds::TOML_ARRAY_TABLE,
+                    key,
+                    key,
+                    parent_id,
+                    position,
+                    json!({"count": items.len()}),
+                );
+                let child_keys = push_key(keys, key);
+                ctx.path_ctx.push(key);
+                for (idx, item) in items.iter().enumerate() {
+                    let Some(t) = item.as_table() else { continue };
+                    let elem_id = ctx.new_node(
+                        kinds::TOML_TABLE,
+                        key,
+                        &idx.to_string(),
+                        &id,
+                        idx as i32,
+                        json!({"index": idx, "keys": t.len()}),
+                    );
+                    ctx.path_ctx.push(&idx.to_string());
+                    walk_table(ctx, t, &elem_id, &child_keys, 0);
+                    ctx.path_ctx.pop();
+                }
+                ctx.path_ctx.pop();
+            }
+            _ => {
+                ctx.new_node(
+                    kinds::TOML_KEY,
+                    key,
+                    key,
+                    parent_id,
+                    position,
+                    json!({"value": toml_to_json(value), "type": value.type_str()}),
+                );
+            }
+        }
+    }
+}
+
+/// Emit a `dependency` node (and a `depends_on` edge from the crate) for one entry.
+#[allow(clippy::too_many_arguments)]
+fn walk_dependency(
+    ctx: &mut TomlWalkCtx,
+    name: &str,
+    value: &toml::Value,
+    parent_id: &str,
+    position: i32,
+    dep_type: &str,
+    target: Option<&str>,
+    keys: &[String],
+) {
+    let mut meta = dependency_metadata(value);
+    meta.insert("dep_type".into(), json!(dep_type));
+    if let Some(target) = target {
+        meta.insert("target".into(), json!(target));
+    }
+    let version = meta.get("version").cloned();
+
+    let dep_id = ctx.new_node(
+        Kind::Dependency.as_str(),
+        name,
+        name,
+        parent_id,
+        position,
+        Value::Object(meta),
+    );
+
+    // `[workspace.dependencies]` declares versions for members; the root crate
+    // doesn't depend on them itself.
+    let is_workspace = keys.first().is_some_and(|k| k == "workspace");
+    if let (Some(crate_id), false) = (&ctx.crate_id, is_workspace) {
+        let mut edge_meta = json!({"dep_type": dep_type});
+        if let Some(v) = version {
+            edge_meta["version"] = v;
+        }
+        ctx.edges.push(EdgeRow {
+            id: Uuid::now_v7().to_string(),
+            source_id: crate_id.clone(),
+            target_id: dep_id,
+            relation: "depends_on".to_string(),
+            metadata: edge_meta,
+        });
+    }
+}
+
+/// Recognise the Cargo dependency tables: `[dependencies]`,
+/// `[target.<cfg>.dependencies]` and `[workspace.dependencies]` (plus dev/build).
+///
+/// Returns the dep_type and, for target tables, the target cfg.
+fn dependency_table(keys: &[String]) -> Option<(&'static str, Option<&str>)> {
+    let last = keys.last()?;
+    let dep_type = DEPENDENCY_TABLES
+        .iter()
+        .find(|(name, _)| name == last)
+        .map(|(_, t)| *t)?;
+    match keys {
+        [_] => Some((dep_type, None)),
+        [ws, _] if ws == "workspace" => Some((dep_type, None)),
+        [t, cfg, _] if t == "target" => Some((dep_type, Some(cfg.as_str()))),
+        _ => None,
+    }
+}
+
+/// Extract version/features/source information from a dependency value,
+/// which is either a bare version string or an inline table.
+fn dependency_metadata(value: &toml::Value) -> serde_json::Map<String, Value> {
+    let mut meta = serde_json::Map::new();
+    match value {
+        toml::Value::String(ver) => {
+            meta.insert("version".into(), json!(ver));
+        }
+        toml::Value::Table(t) => {
+            for field in ["version", "path", "git", "branch", "tag", "rev", "package"] {
+                if let Some(v) = t.get(field).and_then(|v| v.as_str()) {
+                    meta.insert(field.into(), json!(v));
+                }
+            }
+            if let Some(features) = t.get("features").and_then(|f| f.as_array()) {
+                let feat_list: Vec<&str> = features.iter().filter_map(|f| f.as_str()).collect();
+                meta.insert("features".into(), json!(feat_list));
+            }
+            if let Some(optional) = t.get("optional").and_then(|o| o.as_bool()) {
+                meta.insert("optional".into(), json!(optional));
+            }
+            if let Some(default) = t.get("default-features").and_then(|d| d.as_bool()) {
+                meta.insert("default_features".into(), json!(default));
+            }
+            if let Some(ws) = t.get("workspace").and_then(|w| w.as_bool()) {
+                meta.insert("workspace".into(), json!(ws));
+            }
+        }
+        _ => {}
+    }
+    meta
+}
+
+fn push_key(keys: &[String], key: &str) -> Vec<String> {
+    let mut out = keys.to_vec();
+    out.push(key.to_string());
+    out
+}
+
+/// Convert a TOML value to JSON, rendering datetimes as their TOML text.
+fn toml_to_json(value: &toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => json!(s),
+        toml::Value::Integer(i) => json!(i),
+        toml::Value::Float(f) => json!(f),
+        toml::Value::Boolean(b) => json!(b),
+        toml::Value::Datetime(d) => json!(d.to_string()),
+        toml::Value::Array(items) => Value::Array(items.iter().map(toml_to_json).collect()),
+        toml::Value::Table(t) => Value::Object(
+            t.iter()
+                .map(|(k, v)| (k.clone(), toml_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk(source: &str, is_cargo: bool) -> (Vec<NodeRow>, Vec<EdgeRow>) {
+        let table: toml::Table = source.parse().unwrap();
+        walk_toml_file(&table, "file", "inst", PathContext::with_root("Cargo.toml"), is_cargo)
+    }
+
+    const MANIFEST: &str = r#"
+[package]
+name = "demo"
+version = "0.1.0"
+
+[dependencies]
+serde = { version = "1", features = ["derive"] }
+anyhow = "1.0"
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+
+[dev-dependencies]
+tempfile = "3"
+"#;
+
+    #[test]
+    fn cargo_manifest_links_crate_to_dependencies() {
+        let (nodes, edges) = walk(MANIFEST, true);
+        let crate_node = nodes.iter().find(|n| n.kind == "crate").unwrap();
+        assert_eq!(crate_node.content.as_deref(), Some("demo"));
+
+        let deps: Vec<&NodeRow> = nodes.iter().filter(|n| n.kind == "dependency").collect();
+        assert_eq!(deps.len(), 4);
+        assert_eq!(edges.len(), 4);
+        assert!(edges.iter().all(|e| e.relation == "depends_on" && e.source_id == crate_node.id));
+
+        let serde = deps.iter().find(|n| n.content.as_deref() == Some("serde")).unwrap();
+        assert_eq!(serde.metadata["version"], "1");
+        assert_eq!(serde.metadata["features"], json!(["derive"]));
+        let edge = edges.iter().find(|e| e.target_id == serde.id).unwrap();
+        assert_eq!(edge.metadata["version"], "1");
+        assert_eq!(edge.metadata["dep_type"], "normal");
+
+        let libc = deps.iter().find(|n| n.content.as_deref() == Some("libc")).unwrap();
+        assert_eq!(libc.metadata["target"], "cfg(unix)");
+        let tempfile = deps.iter().find(|n| n.content.as_deref() == Some("tempfile")).unwrap();
+        assert_eq!(tempfile.metadata["dep_type"], "dev");
+    }
+
+    #[test]
+    fn workspace_dependencies_have_no_edges() {
+        let src = "[package]\nname = \"root\"\n\n[workspace.dependencies]\nserde = \"1\"\n";
+        let (nodes, edges) = walk(src, true);
+        assert!(nodes.iter().any(|n| n.kind == "dependency"));
+        assert!(edges.is_empty());
+    }
+
+    #[test]
+    fn plain_toml_produces_tables_and_keys() {
+        let src = "title = \"x\"\n\n[server]\nport = 8080\n\n[[bin]]\nname = \"a\"\n\n[[bin]]\nname = \"b\"\n";
+        let (nodes, edges) = walk(src, false);
+        assert!(edges.is_empty());
+        let port = nodes.iter().find(|n| n.content.as_deref() == Some("port")).unwrap();
+        assert_eq!(port.kind, kinds::TOML_KEY);
+        assert_eq!(port.metadata["value"], 8080);
+        assert_eq!(port.metadata["type"], "integer");
+        assert_eq!(nodes.iter().filter(|n| n.kind == kinds::TOML_ARRAY_TABLE).count(), 1);
+        assert_eq!(
+            nodes
+                .iter()
+                .filter(|n| n.kind == kinds::TOML_TABLE && n.content.as_deref() == Some("bin"))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn dependencies_table_outside_cargo_is_plain() {
+        let (nodes, _) = walk(MANIFEST, false);
+        assert!(nodes.iter().all(|n| n.kind != "dependency" && n.kind != "crate"));
+    }
+}
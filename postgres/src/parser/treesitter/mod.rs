@@ -6,6 +6,7 @@ pub mod cursor;
 pub enum TsLanguage {
     Go,
     C,
+    Cpp,
     Latex,
 }
 
@@ -14,6 +15,7 @@ impl TsLanguage {
         match self {
             TsLanguage::Go => tree_sitter_go::LANGUAGE.into(),
             TsLanguage::C => tree_sitter_c::LANGUAGE.into(),
+            TsLanguage::Cpp => tree_sitter_cpp::LANGUAGE.into(),
             TsLanguage::Latex => tree_sitter_latex::language().into(),
         }
     }
@@ -22,6 +24,7 @@ impl TsLanguage {
         match self {
             TsLanguage::Go => "go",
             TsLanguage::C => "c",
+            TsLanguage::Cpp => "cpp",
             TsLanguage::Latex => "latex",
         }
     }
@@ -0,0 +1,17 @@
+// YAML node kind constants, prefixed with `yaml_` to avoid collisions
+// with other language kinds in the `kerai.nodes.kind` column.
+
+pub const YAML_DOCUMENT: &str = "yaml_document";
+pub const YAML_MAPPING: &str = "yaml_mapping";
+pub const YAML_SEQUENCE: &str = "yaml_sequence";
+pub const YAML_KEY: &str = "yaml_key";
+pub const YAML_SCALAR: &str = "yaml_scalar";
+
+/// Every YAML kind, for the parser registry.
+pub const ALL: &[&str] = &[
+    YAML_DOCUMENT,
+    YAML_MAPPING,
+    YAML_SEQUENCE,
+    YAML_KEY,
+    YAML_SCALAR,
+];
@@ -0,0 +1,163 @@
+/// YAML parser module — YAML source → kerai.nodes.
+///
+/// Aimed at CI and config files (docker-compose, GitHub Actions): key paths
+/// become ltree paths, so `kerai find image --kind yaml_key` finds every
+/// `image:` entry across parsed files.
+use pgrx::prelude::*;
+use serde_json::json;
+use std::path::Path;
+use std::time::Instant;
+use uuid::Uuid;
+use yaml_rust2::YamlLoader;
+
+use crate::parser::ast_walker::NodeRow;
+use crate::parser::inserter;
+use crate::parser::kinds::Kind;
+use crate::parser::path_builder::PathContext;
+use crate::parser::registry::{self, LanguageParser, Placement};
+
+pub mod kinds;
+mod walker;
+
+/// Parse YAML source text directly into kerai.nodes.
+///
+/// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
+#[pg_extern]
+pub(super) fn parse_yaml_source(source: &str, filename: &str) -> pgrx::JsonB {
+    let start = Instant::now();
+    let instance_id = super::get_self_instance_id();
+
+    // Delete existing nodes for this filename (idempotent re-parse)
+    inserter::delete_file_nodes(&instance_id, filename);
+
+    let (node_count, edge_count) = parse_yaml_single(source, filename, &instance_id, None);
+
+    // Auto-mint reward
+    if node_count > 0 {
+        let details = json!({"file": filename, "language": "yaml", "nodes": node_count, "edges": edge_count});
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), filename);
+        crate::currency::reward("parse_yaml_source", "node", file_id.as_deref(), details);
+    }
+
+    let elapsed = start.elapsed();
+    pgrx::JsonB(json!({
+        "file": filename,
+        "language": "yaml",
+        "nodes": node_count,
+        "edges": edge_count,
+        "elapsed_ms": elapsed.as_millis() as u64,
+    }))
+}
+
+/// Parse a YAML file from disk into kerai.nodes.
+///
+/// Returns JSON: `{file, language, nodes, edges, elapsed_ms}`.
+#[pg_extern]
+fn parse_yaml_file(path: &str) -> pgrx::JsonB {
+    let start = Instant::now();
+    let file_path = Path::new(path);
+
+    if !file_path.exists() {
+        pgrx::error!("File does not exist: {}", path);
+    }
+
+    let source = std::fs::read_to_string(file_path)
+        .unwrap_or_else(|e| pgrx::error!("Failed to read file: {}", e));
+
+    let instance_id = super::get_self_instance_id();
+    let filename = file_path
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string());
+
+    // Delete existing nodes for this file (idempotent re-parse)
+    inserter::delete_file_nodes(&instance_id, &filename);
+
+    let (node_count, edge_count) = parse_yaml_single(&source, &filename, &instance_id, None);
+
+    // Auto-mint reward
+    if node_count > 0 {
+        let details = json!({"file": filename, "language": "yaml", "nodes": node_count, "edges": edge_count});
+        let file_id = inserter::root_node_id(&instance_id, Kind::File.as_str(), &filename);
+        crate::currency::reward("parse_yaml_file", "node", file_id.as_deref(), details);
+    }
+
+    let elapsed = start.elapsed();
+    pgrx::JsonB(json!({
+        "file": filename,
+        "language": "yaml",
+        "nodes": node_count,
+        "edges": edge_count,
+        "elapsed_ms": elapsed.as_millis() as u64,
+    }))
+}
+
+/// YAML in the parser registry.
+pub(crate) struct Yaml;
+
+impl LanguageParser for Yaml {
+    fn name(&self) -> &'static str {
+        "yaml"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["yml", "yaml"]
+    }
+
+    fn kinds(&self) -> Vec<&'static str> {
+        registry::kind_list(&[Kind::File], kinds::ALL)
+    }
+
+    fn parse_source(&self, source: &str, filename: &str) -> pgrx::JsonB {
+        parse_yaml_source(source, filename)
+    }
+
+    fn walk(&self, source: &str, filename: &str, at: &Placement) -> (usize, usize) {
+        parse_yaml_single(source, filename, at.instance_id, at.parent_id)
+    }
+}
+
+/// Parse YAML source, insert nodes, return counts.
+///
+/// YAML has no cross-references we track, so the edge count is always zero;
+/// it is returned to keep the shape of the other `parse_*_single` helpers.
+pub(crate) fn parse_yaml_single(
+    source: &str,
+    filename: &str,
+    instance_id: &str,
+    parent_id: Option<&str>,
+) -> (usize, usize) {
+    let docs = match YamlLoader::load_from_str(source) {
+        Ok(d) => d,
+        Err(e) => {
+            warning!("Failed to parse YAML source {}: {}", filename, e);
+            return (0, 0);
+        }
+    };
+
+    // Create file node
+    let file_node_id = Uuid::now_v7().to_string();
+    let path_ctx = PathContext::with_root(filename);
+
+    let file_node = NodeRow {
+        id: file_node_id.clone(),
+        instance_id: instance_id.to_string(),
+        kind: Kind::File.as_str().to_string(),
+        language: Some("yaml".to_string()),
+        content: Some(filename.to_string()),
+        parent_id: parent_id.map(std::string::ToString::to_string),
+        position: 0,
+        path: path_ctx.path(),
+        metadata: json!({"line_count": source.lines().count(), "documents": docs.len()}),
+        span_start: None,
+        span_end: None,
+    };
+    inserter::insert_nodes(&[file_node]);
+
+    let nodes = walker::walk_yaml_file(&docs, &file_node_id, instance_id, path_ctx);
+    let node_count = nodes.len() + 1; // +1 for file node
+
+    inserter::insert_nodes(&nodes);
+
+    (node_count, 0)
+}
@@ -0,0 +1,230 @@
+/// YAML walker — converts parsed `yaml_rust2::Yaml` documents into NodeRow vectors.
+///
+/// Each document becomes a `yaml_document` node holding its root value.
+/// Mapping entries become `yaml_key` nodes whose single child is the value
+/// (`yaml_mapping`, `yaml_sequence` or `yaml_scalar`). Paths follow key paths,
+/// so `services.web.image` in docker-compose.yml lands at
+/// `docker_compose_yml.services.web.image`; sequence items add their index.
+use serde_json::{json, Value};
+use uuid::Uuid;
+use yaml_rust2::Yaml;
+
+use crate::parser::ast_walker::NodeRow;
+use crate::parser::path_builder::PathContext;
+
+use super::kinds;
+
+/// Walk context accumulator passed through the recursion.
+struct YamlWalkCtx {
+    instance_id: String,
+    nodes: Vec<NodeRow>,
+    path_ctx: PathContext,
+}
+
+impl YamlWalkCtx {
+    fn new_node(
+        &mut self,
+        kind: &str,
+        content: Option<String>,
+        parent_id: &str,
+        position: i32,
+        meta: Value,
+    ) -> String {
+        let id = Uuid::now_v7().to_string();
+        self.nodes.push(NodeRow {
+            id: id.clone(),
+            instance_id: self.instance_id.clone(),
+            kind: kind.to_string(),
+            language: Some("yaml".to_string()),
+            content,
+            parent_id: Some(parent_id.to_string()),
+            position,
+            path: self.path_ctx.path(),
+            metadata: meta,
+            span_start: None,
+            span_end: None,
+        });
+        id
+    }
+}
+
+/// Walk all documents of a YAML stream rooted at `file_node_id`.
+///
+/// A single-document file keeps key paths directly under the file path;
+/// multi-document streams insert a `doc_N` segment per document.
+pub fn walk_yaml_file(
+    docs: &[Yaml],
+    file_node_id: &str,
+    instance_id: &str,
+    path_ctx: PathContext,
+) -> Vec<NodeRow> {
+    let mut ctx = YamlWalkCtx {
+        instance_id: instance_id.to_string(),
+        nodes: Vec::new(),
+        path_ctx,
+    };
+
+    let multi = docs.len() > 1;
+    for (idx, doc) in docs.iter().enumerate() {
+        if multi {
+            ctx.path_ctx.push(&format!("doc_{idx}"));
+        }
+        let doc_id = ctx.new_node(
+            kinds::YAML_DOCUMENT,
+            None,
+            file_node_id,
+            idx as i32,
+            json!({"index": idx}),
+        );
+        walk_value(&mut ctx, doc, &doc_id, 0);
+        if multi {
+            ctx.path_ctx.pop();
+        }
+    }
+
+    ctx.nodes
+}
+
+fn walk_value(ctx: &mut YamlWalkCtx, value: &Yaml, parent_id: &str, position: i32) {
+    match value {
+        Yaml::Hash(map) => {
+            let id = ctx.new_node(
+                kinds::YAML_MAPPING,
+                None,
+                parent_id,
+                position,
+                json!({"keys": map.len()}),
+            );
+            for (i, (k, v)) in map.iter().enumerate() {
+                let key = scalar_text(k).unwrap_or_else(|| "?".to_string());
+                ctx.path_ctx.push(&key);
+                let key_id = ctx.new_node(
+                    kinds::YAML_KEY,
+                    Some(key),
+                    &id,
+                    i as i32,
+                    json!({"value_type": type_name(v)}),
+                );
+                walk_value(ctx, v, &key_id, 0);
+                ctx.path_ctx.pop();
+            }
+        }
+        Yaml::Array(items) => {
+            let id = ctx.new_node(
+                kinds::YAML_SEQUENCE,
+                None,
+                parent_id,
+                position,
+                json!({"count": items.len()}),
+            );
+            for (i, item) in items.iter().enumerate() {
+                ctx.path_ctx.push(&i.to_string());
+                walk_value(ctx, item, &id, i as i32);
+                ctx.path_ctx.pop();
+            }
+        }
+        scalar => {
+            ctx.new_node(
+                kinds::YAML_SCALAR,
+                scalar_text(scalar),
+                parent_id,
+                position,
+                json!({"type": type_name(scalar), "value": scalar_json(scalar)}),
+            );
+        }
+    }
+}
+
+/// Text form of a scalar; `None` for null and for collections.
+fn scalar_text(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn scalar_json(value: &Yaml) -> Value {
+    match value {
+        Yaml::String(s) => json!(s),
+        Yaml::Integer(i) => json!(i),
+        Yaml::Real(s) => s.parse::<f64>().map_or_else(|_| json!(s), |f| json!(f)),
+        Yaml::Boolean(b) => json!(b),
+        _ => Value::Null,
+    }
+}
+
+fn type_name(value: &Yaml) -> &'static str {
+    match value {
+        Yaml::Hash(_) => "mapping",
+        Yaml::Array(_) => "sequence",
+        Yaml::String(_) => "string",
+        Yaml::Integer(_) => "integer",
+        Yaml::Real(_) => "float",
+        Yaml::Boolean(_) => "boolean",
+        Yaml::Null => "null",
+        Yaml::Alias(_) => "alias",
+        Yaml::BadValue => "invalid",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust2::YamlLoader;
+
+    fn walk(source: &str) -> Vec<NodeRow> {
+        let docs = YamlLoader::load_from_str(source).unwrap();
+        walk_yaml_file(&docs, "file", "inst", PathContext::with_root("compose.yml"))
+    }
+
+    #[test]
+    fn key_paths_mirror_nesting() {
+        let nodes = walk("services:\n  web:\n    image: nginx\n    ports:\n      - \"80:80\"\n");
+        let image = nodes
+            .iter()
+            .find(|n| n.kind == kinds::YAML_KEY && n.content.as_deref() == Some("image"))
+            .unwrap();
+        assert_eq!(image.path.as_deref(), Some("compose_yml.services.web.image"));
+
+        let scalar = nodes
+            .iter()
+            .find(|n| n.parent_id.as_deref() == Some(image.id.as_str()))
+            .unwrap();
+        assert_eq!(scalar.kind, kinds::YAML_SCALAR);
+        assert_eq!(scalar.content.as_deref(), Some("nginx"));
+
+        let port = nodes
+            .iter()
+            .find(|n| n.kind == kinds::YAML_SCALAR && n.content.as_deref() == Some("80:80"))
+            .unwrap();
+        assert_eq!(port.path.as_deref(), Some("compose_yml.services.web.ports._0"));
+    }
+
+    #[test]
+    fn scalar_types_recorded() {
+        let nodes = walk("retries: 3\nratio: 0.5\nenabled: true\nnothing: ~\n");
+        let types: Vec<&str> = nodes
+            .iter()
+            .filter(|n| n.kind == kinds::YAML_SCALAR)
+            .map(|n| n.metadata["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(types, vec!["integer", "float", "boolean", "null"]);
+    }
+
+    #[test]
+    fn multi_document_streams_get_doc_segments() {
+        let nodes = walk("a: 1\n---\nb: 2\n");
+        let docs: Vec<&NodeRow> = nodes
+            .iter()
+            .filter(|n| n.kind == kinds::YAML_DOCUMENT)
+            .collect();
+        assert_eq!(docs.len(), 2);
+        let b = nodes
+            .iter()
+            .find(|n| n.kind == kinds::YAML_KEY && n.content.as_deref() == Some("b"))
+            .unwrap();
+        assert_eq!(b.path.as_deref(), Some("compose_yml.doc_1.b"));
+    }
+}
@@ -0,0 +1,295 @@
+/// Range partitioning of kerai.versions and kerai.ledger by `timestamp`.
+///
+/// Both tables are append-mostly and keyed by a hybrid logical clock
+/// timestamp, so they are split into fixed-width `timestamp` ranges
+/// (`kerai.partition_days` of clock time wide, named `<table>_p<from>`) plus
+/// a default partition catching anything outside them. Lamport counters
+/// from before hybrid clocks get ranges `kerai.partition_span` wide.
+/// `create_partitions` keeps ranges ahead of the newest row; the partition
+/// worker calls it periodically. Rows that landed in the default partition
+/// are moved into a new range partition when it is created, which is also
+/// how installs migrated from unpartitioned tables get their history split
+/// up.
+use std::sync::OnceLock;
+
+use pgrx::prelude::*;
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::crdt::hlc;
+use crate::sql::sql_uuid;
+
+/// Tables partitioned by `timestamp` range.
+const TABLES: &[&str] = &["versions", "ledger"];
+
+/// Empty ranges kept ready beyond the one holding the newest row.
+const AHEAD: i64 = 2;
+
+/// Partitions created per table per call, so a first pass over a large
+/// migrated table does not hold its locks for too long.
+const MAX_PER_PASS: usize = 100;
+
+/// Upper bound of a range partition from its `pg_get_expr(relpartbound)`
+/// text, e.g. `FOR VALUES FROM ('0') TO ('100000')`.
+fn upper_bound(expr: &str) -> Option<i64> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"TO \('?(-?\d+)'?\)").expect("partition bound regex"));
+    re.captures(expr)?.get(1)?.as_str().parse().ok()
+}
+
+/// Ranges `[from, to)` to create so that partitions reach `AHEAD` spans past
+/// `data_max`. New ranges continue from `top`, the highest existing upper
+/// bound, or start at the span holding `data_min` on a table without any.
+/// `data_min` is the oldest row at or past `top`: when it is spans away, as
+/// after the jump from Lamport counters to hybrid clock timestamps, the
+/// empty gap is left to the default partition.
+fn plan_ranges(
+    top: Option<i64>,
+    data_min: Option<i64>,
+    data_max: Option<i64>,
+    span: i64,
+) -> Vec<(i64, i64)> {
+    let span = span.max(1);
+    let floor = |ts: i64| ts.div_euclid(span) * span;
+    let target = floor(data_max.unwrap_or(0)) + (AHEAD + 1) * span;
+    let mut from = match top {
+        Some(top) => top.max(data_min.map_or(top, floor)),
+        None => floor(data_min.unwrap_or(0)),
+    };
+
+    let mut ranges = Vec::new();
+    while from < target && ranges.len() < MAX_PER_PASS {
+        ranges.push((from, from + span));
+        from += span;
+    }
+    ranges
+}
+
+fn is_partitioned(table: &str) -> bool {
+    Spi::get_one::<bool>(&format!(
+        "SELECT relkind = 'p' FROM pg_class WHERE oid = 'kerai.{table}'::regclass"
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Highest upper bound among a table's range partitions.
+fn top_bound(table: &str) -> Option<i64> {
+    let mut top: Option<i64> = None;
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                &format!(
+                    "SELECT pg_get_expr(c.relpartbound, c.oid) AS bound
+                     FROM pg_inherits i JOIN pg_class c ON c.oid = i.inhrelid
+                     WHERE i.inhparent = 'kerai.{table}'::regclass"
+                ),
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            let bound = row.get_by_name::<String, _>("bound").unwrap().unwrap_or_default();
+            if let Some(to) = upper_bound(&bound) {
+                top = Some(top.map_or(to, |t| t.max(to)));
+            }
+        }
+    });
+    top
+}
+
+/// Create one range partition, moving any rows of its range out of the
+/// default partition first (attaching would fail while they are there).
+fn create_partition(table: &str, from: i64, to: i64) -> String {
+    let name = format!("{table}_p{from}").replace('-', "m");
+    for stmt in [
+        format!("CREATE TABLE kerai.{name} (LIKE kerai.{table} INCLUDING DEFAULTS INCLUDING CONSTRAINTS)"),
+        format!(
+            "WITH moved AS (
+                DELETE FROM kerai.{table}_default WHERE timestamp >= {from} AND timestamp < {to}
+                RETURNING *
+            ) INSERT INTO kerai.{name} SELECT * FROM moved"
+        ),
+        format!("ALTER TABLE kerai.{table} ATTACH PARTITION kerai.{name} FOR VALUES FROM ({from}) TO ({to})"),
+    ] {
+        Spi::run(&stmt).unwrap_or_else(|e| error!("Failed to create partition {}: {}", name, e));
+    }
+    name
+}
+
+/// Create the partitions each table is missing, `span` timestamps wide for
+/// Lamport counters and `days` wide for hybrid clock timestamps. Returns
+/// `{table: {partitioned, created: [name]}}`; tables not yet migrated to
+/// partitioning report `partitioned: false` and are left alone.
+pub fn ensure_partitions(span: i64, days: i64) -> Value {
+    let mut result = serde_json::Map::new();
+    for &table in TABLES {
+        if !is_partitioned(table) {
+            result.insert(table.into(), json!({"partitioned": false, "created": []}));
+            continue;
+        }
+        let top = top_bound(table);
+        let past_top = top
+            .map(|top| format!("WHERE timestamp >= {top}"))
+            .unwrap_or_default();
+        let (data_min, data_max) = Spi::get_two::<i64, i64>(&format!(
+            "SELECT min(timestamp), max(timestamp) FROM kerai.{table} {past_top}"
+        ))
+        .unwrap();
+        let span = if data_max.is_some_and(hlc::is_hlc) {
+            hlc::span_days(days)
+        } else {
+            span
+        };
+        let created: Vec<String> = plan_ranges(top, data_min, data_max, span)
+            .into_iter()
+            .map(|(from, to)| create_partition(table, from, to))
+            .collect();
+        result.insert(table.into(), json!({"partitioned": true, "created": created}));
+    }
+    Value::Object(result)
+}
+
+/// Create missing `timestamp` range partitions for kerai.versions and
+/// kerai.ledger now, instead of waiting for the partition worker.
+///
+/// Returns `{versions: {partitioned, created}, ledger: {...}}`.
+#[pg_extern]
+fn create_partitions() -> pgrx::JsonB {
+    pgrx::JsonB(ensure_partitions(
+        crate::workers::PARTITION_SPAN.get() as i64,
+        crate::workers::PARTITION_DAYS.get() as i64,
+    ))
+}
+
+/// Partitions of kerai.versions and kerai.ledger: `[{table, partition,
+/// from_ts, to_ts, rows}]`, ranges in order and the default partition
+/// last (with null bounds). `rows` is the planner's estimate.
+#[pg_extern]
+fn list_partitions() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'table', p.relname,
+            'partition', c.relname,
+            'from_ts', (regexp_match(b.expr, 'FROM \\(''?(-?\\d+)'))[1]::bigint,
+            'to_ts', (regexp_match(b.expr, 'TO \\(''?(-?\\d+)'))[1]::bigint,
+            'rows', GREATEST(c.reltuples, 0)::bigint
+        ) ORDER BY p.relname, b.expr = 'DEFAULT',
+            (regexp_match(b.expr, 'FROM \\(''?(-?\\d+)'))[1]::bigint), '[]'::jsonb)
+        FROM pg_inherits i
+        JOIN pg_class c ON c.oid = i.inhrelid
+        JOIN pg_class p ON p.oid = i.inhparent
+        CROSS JOIN LATERAL (SELECT pg_get_expr(c.relpartbound, c.oid) AS expr) b
+        WHERE p.oid IN ('kerai.versions'::regclass, 'kerai.ledger'::regclass)",
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+/// `timestamp` range predicate with literal bounds, so the planner prunes
+/// partitions outside `[from_ts, to_ts)` at plan time.
+pub fn ts_range(alias: &str, from_ts: i64, to_ts: i64) -> String {
+    format!("{alias}.timestamp >= {from_ts} AND {alias}.timestamp < {to_ts}")
+}
+
+/// Versions with `from_ts <= timestamp < to_ts`, optionally of one node,
+/// oldest first. Only the partitions covering the range are scanned.
+///
+/// Returns `[{id, node_id, operation, author, timestamp, branch_id,
+/// old_content, new_content}]`.
+#[pg_extern]
+fn versions_range(from_ts: i64, to_ts: i64, node_id: Option<pgrx::Uuid>) -> pgrx::JsonB {
+    let node_clause = node_id
+        .map(|id| format!("AND v.node_id = {}", sql_uuid(&id.to_string())))
+        .unwrap_or_default();
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', v.id,
+            'node_id', v.node_id,
+            'operation', v.operation,
+            'author', v.author,
+            'timestamp', v.timestamp,
+            'branch_id', v.branch_id,
+            'old_content', v.old_content,
+            'new_content', v.new_content
+        ) ORDER BY v.timestamp, v.created_at), '[]'::jsonb)
+        FROM kerai.versions v
+        WHERE {} {node_clause}",
+        ts_range("v", from_ts, to_ts),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+/// Ledger entries with `from_ts <= timestamp < to_ts`, optionally to or
+/// from one wallet, oldest first. Only the partitions covering the range
+/// are scanned.
+///
+/// Returns `[{id, from_wallet, to_wallet, amount, reason, timestamp}]`.
+#[pg_extern]
+fn ledger_range(from_ts: i64, to_ts: i64, wallet_id: Option<pgrx::Uuid>) -> pgrx::JsonB {
+    let wallet_clause = wallet_id
+        .map(|id| {
+            let w = sql_uuid(&id.to_string());
+            format!("AND (l.from_wallet = {w} OR l.to_wallet = {w})")
+        })
+        .unwrap_or_default();
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', l.id,
+            'from_wallet', l.from_wallet,
+            'to_wallet', l.to_wallet,
+            'amount', l.amount,
+            'reason', l.reason,
+            'timestamp', l.timestamp
+        ) ORDER BY l.timestamp, l.created_at), '[]'::jsonb)
+        FROM kerai.ledger l
+        WHERE {} {wallet_clause}",
+        ts_range("l", from_ts, to_ts),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upper_bound_reads_quoted_and_bare_bounds() {
+        assert_eq!(upper_bound("FOR VALUES FROM ('0') TO ('100000')"), Some(100000));
+        assert_eq!(upper_bound("FOR VALUES FROM (-5) TO (10)"), Some(10));
+        assert_eq!(upper_bound("DEFAULT"), None);
+    }
+
+    #[test]
+    fn ranges_start_at_the_oldest_row_and_run_ahead() {
+        let ranges = plan_ranges(None, Some(250), Some(420), 100);
+        assert_eq!(ranges, vec![(200, 300), (300, 400), (400, 500), (500, 600), (600, 700)]);
+        // An empty table still gets its first spans
+        assert_eq!(plan_ranges(None, None, None, 100), vec![(0, 100), (100, 200), (200, 300)]);
+    }
+
+    #[test]
+    fn ranges_continue_from_existing_partitions() {
+        assert_eq!(plan_ranges(Some(700), Some(250), Some(420), 100), vec![]);
+        assert_eq!(plan_ranges(Some(500), Some(0), Some(420), 100), vec![(500, 600), (600, 700)]);
+        // A changed span carries on from wherever the old ranges ended
+        assert_eq!(plan_ranges(Some(550), Some(0), Some(420), 100), vec![(550, 650), (650, 750)]);
+    }
+
+    #[test]
+    fn ranges_skip_the_gap_to_hybrid_clock_timestamps() {
+        let span = hlc::span_days(7);
+        let first = hlc::pack(1_790_000_000_000, 0);
+        let ranges = plan_ranges(Some(300_000), Some(first), Some(first), span);
+        let start = first.div_euclid(span) * span;
+        assert_eq!(ranges[0], (start, start + span));
+        assert_eq!(ranges.len(), 3);
+    }
+
+    #[test]
+    fn a_pass_is_capped() {
+        assert_eq!(plan_ranges(None, Some(0), Some(1_000_000), 1).len(), MAX_PER_PASS);
+    }
+}
@@ -95,6 +95,7 @@ fn list_peers() -> pgrx::JsonB {
                 'endpoint', endpoint,
                 'connection', connection,
                 'last_seen', last_seen,
+                'clock_skew_ms', clock_skew_ms,
                 'public_key', encode(public_key, 'hex')
             ) ORDER BY name),
             '[]'::jsonb
@@ -105,7 +106,8 @@ fn list_peers() -> pgrx::JsonB {
     json
 }
 
-/// Get a single peer by fingerprint.
+/// Get a single peer by fingerprint, with its sync policy and throttle
+/// state under `sync` unless it is this instance.
 #[pg_extern]
 fn get_peer(fingerprint: &str) -> pgrx::JsonB {
     let row = Spi::get_one::<pgrx::JsonB>(&format!(
@@ -116,6 +118,7 @@ fn get_peer(fingerprint: &str) -> pgrx::JsonB {
             'endpoint', endpoint,
             'connection', connection,
             'last_seen', last_seen,
+            'clock_skew_ms', clock_skew_ms,
             'public_key', encode(public_key, 'hex'),
             'is_self', is_self
         ) FROM kerai.instances WHERE key_fingerprint = '{}'",
@@ -123,10 +126,15 @@ fn get_peer(fingerprint: &str) -> pgrx::JsonB {
     ))
     .unwrap_or(None);
 
-    match row {
-        Some(j) => j,
+    let mut peer = match row {
+        Some(j) => j.0,
         None => error!("Peer not found: {}", fingerprint),
+    };
+    if peer["is_self"] == false {
+        let id = peer["id"].as_str().unwrap_or_default().to_string();
+        peer["sync"] = crate::crdt::throttle::state(&id);
     }
+    pgrx::JsonB(peer)
 }
 
 /// Remove a non-self peer by name. Returns JSON with removal status.
@@ -1,10 +1,13 @@
 /// Perspective and association CRUD — weighted views of the codebase.
+use std::collections::HashMap;
+
 use pgrx::prelude::*;
+use serde_json::{json, Value};
 
 use crate::sql::sql_escape;
 
 /// Resolve agent name to agent_id. Errors if not found.
-fn resolve_agent(name: &str) -> String {
+pub(crate) fn resolve_agent(name: &str) -> String {
     Spi::get_one::<String>(&format!(
         "SELECT id::text FROM kerai.agents WHERE name = '{}'",
         sql_escape(name),
@@ -144,6 +147,272 @@ fn get_perspectives(
     json
 }
 
+/// Where an imported claim points: a node's id (the same on every
+/// instance) or its ltree path.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum NodeRef {
+    Id(String),
+    Path(String),
+}
+
+/// One validated item of an import batch.
+#[derive(Debug, PartialEq)]
+struct Claim {
+    node: NodeRef,
+    context: Option<NodeRef>,
+    weight: f64,
+    reasoning: Option<String>,
+}
+
+/// Check that `path` is an ltree: dot-separated labels of letters, digits,
+/// `_` and `-`.
+fn check_path(path: &str) -> Result<(), String> {
+    for label in path.split('.') {
+        if label.is_empty() || label.len() > 1000 {
+            return Err(format!("invalid path '{}': bad label length", path));
+        }
+        if let Some(c) = label
+            .chars()
+            .find(|c| !(c.is_alphanumeric() || *c == '_' || *c == '-'))
+        {
+            return Err(format!("invalid path '{}': unexpected '{}'", path, c));
+        }
+    }
+    Ok(())
+}
+
+/// Read `{prefix}_id` or `{prefix}_path` from an item; `None` when neither
+/// is given.
+fn node_ref(item: &Value, prefix: &str) -> Result<Option<NodeRef>, String> {
+    let id_key = format!("{prefix}_id");
+    let path_key = format!("{prefix}_path");
+    match (&item[&id_key], &item[&path_key]) {
+        (Value::Null, Value::Null) => Ok(None),
+        (Value::String(id), Value::Null) => uuid::Uuid::parse_str(id)
+            .map(|u| Some(NodeRef::Id(u.to_string())))
+            .map_err(|_| format!("{} is not a uuid: '{}'", id_key, id)),
+        (Value::Null, Value::String(path)) => {
+            check_path(path)?;
+            Ok(Some(NodeRef::Path(path.clone())))
+        }
+        (Value::Null, _) => Err(format!("{} must be a string", path_key)),
+        (_, Value::Null) => Err(format!("{} must be a string", id_key)),
+        _ => Err(format!("give {} or {}, not both", id_key, path_key)),
+    }
+}
+
+/// Validate one item: `{node_id | node_path, context_id | context_path?,
+/// weight, reasoning?}`.
+fn parse_claim(item: &Value) -> Result<Claim, String> {
+    if !item.is_object() {
+        return Err("item must be an object".to_string());
+    }
+    let node = node_ref(item, "node")?.ok_or("missing node_id or node_path")?;
+    let context = node_ref(item, "context")?;
+    let weight = item["weight"].as_f64().ok_or("weight must be a number")?;
+    if !(-1.0..=1.0).contains(&weight) {
+        return Err(format!(
+            "weight must be between -1.0 and 1.0, got {}",
+            weight
+        ));
+    }
+    let reasoning = match &item["reasoning"] {
+        Value::Null => None,
+        Value::String(r) => Some(r.clone()),
+        _ => return Err("reasoning must be a string".to_string()),
+    };
+    Ok(Claim {
+        node,
+        context,
+        weight,
+        reasoning,
+    })
+}
+
+/// Resolve every id and path in `refs` with one query each, to the node
+/// id or an error (missing node, or a path shared by several nodes).
+fn resolve_refs(refs: &[&NodeRef]) -> HashMap<NodeRef, Result<String, String>> {
+    let mut resolved = HashMap::new();
+    for (by_path, matches) in [(false, "n.id = k::uuid"), (true, "n.path = k::ltree")] {
+        let keys: Vec<&str> = refs
+            .iter()
+            .filter_map(|r| match (r, by_path) {
+                (NodeRef::Id(k), false) | (NodeRef::Path(k), true) => Some(k.as_str()),
+                _ => None,
+            })
+            .collect();
+        if keys.is_empty() {
+            continue;
+        }
+        let found = Spi::get_one_with_args::<pgrx::JsonB>(
+            &format!(
+                "SELECT COALESCE(jsonb_object_agg(k, ids), '{{}}'::jsonb) FROM (
+                    SELECT k, jsonb_agg(n.id::text) AS ids
+                    FROM jsonb_array_elements_text($1) k
+                    JOIN kerai.nodes n ON {}
+                    GROUP BY k
+                ) found",
+                matches
+            ),
+            &[pgrx::JsonB(json!(keys)).into()],
+        )
+        .unwrap_or_else(|e| error!("Failed to resolve nodes: {}", e))
+        .map_or(Value::Null, |j| j.0);
+        for key in keys {
+            let node = if by_path {
+                NodeRef::Path(key.to_string())
+            } else {
+                NodeRef::Id(key.to_string())
+            };
+            let ids = found[key].as_array().cloned().unwrap_or_default();
+            let result = match ids.as_slice() {
+                [id] => Ok(id.as_str().unwrap_or_default().to_string()),
+                [] => Err(format!("node not found: {}", key)),
+                _ => Err(format!("path matches {} nodes: {}", ids.len(), key)),
+            };
+            resolved.insert(node, result);
+        }
+    }
+    resolved
+}
+
+/// Write one claim, returning the perspective id and whether it was
+/// `inserted`, `updated` or `unchanged`.
+fn write_claim(
+    agent_id: &str,
+    node_id: &str,
+    context_id: Option<&str>,
+    claim: &Claim,
+) -> (String, &'static str) {
+    let existing = Spi::get_one_with_args::<pgrx::JsonB>(
+        "SELECT jsonb_build_object('id', id::text, 'weight', weight, 'reasoning', reasoning)
+         FROM kerai.perspectives
+         WHERE agent_id = $1::uuid AND node_id = $2::uuid
+           AND context_id IS NOT DISTINCT FROM $3::uuid
+         FOR UPDATE",
+        &[agent_id.into(), node_id.into(), context_id.into()],
+    )
+    .unwrap_or(None)
+    .map(|j| j.0);
+
+    let Some(existing) = existing else {
+        let id = Spi::get_one_with_args::<String>(
+            "INSERT INTO kerai.perspectives (agent_id, node_id, weight, context_id, reasoning)
+             VALUES ($1::uuid, $2::uuid, $3, $4::uuid, $5)
+             RETURNING id::text",
+            &[
+                agent_id.into(),
+                node_id.into(),
+                claim.weight.into(),
+                context_id.into(),
+                claim.reasoning.as_deref().into(),
+            ],
+        )
+        .unwrap_or_else(|e| error!("Failed to insert perspective: {}", e))
+        .unwrap_or_default();
+        return (id, "inserted");
+    };
+
+    let id = existing["id"].as_str().unwrap_or_default().to_string();
+    if existing["weight"].as_f64() == Some(claim.weight)
+        && existing["reasoning"].as_str() == claim.reasoning.as_deref()
+    {
+        return (id, "unchanged");
+    }
+    Spi::run_with_args(
+        "UPDATE kerai.perspectives SET weight = $2, reasoning = $3, updated_at = now()
+         WHERE id = $1::uuid",
+        &[
+            id.as_str().into(),
+            claim.weight.into(),
+            claim.reasoning.as_deref().into(),
+        ],
+    )
+    .unwrap_or_else(|e| error!("Failed to update perspective: {}", e));
+    (id, "updated")
+}
+
+/// Import a batch of perspectives for one agent, as a review tool would
+/// after a run.
+///
+/// `perspectives` is a JSON array of `{node_id | node_path, context_id |
+/// context_path?, weight, reasoning?}`: nodes are named by id or by ltree
+/// path, and a path must match exactly one node. Bad items are reported
+/// rather than failing the batch. An item repeating an earlier one of the
+/// batch is a `duplicate`; otherwise it is `inserted`, `updated` (a new
+/// weight or reasoning for an existing perspective) or `unchanged`.
+///
+/// Returns `{agent, inserted, updated, unchanged, duplicates, invalid,
+/// items: [{index, status, id?, node_id?, context_id?, error?}]}`.
+#[pg_extern]
+fn import_perspectives(agent_name: &str, perspectives: pgrx::JsonB) -> pgrx::JsonB {
+    let agent_id = resolve_agent(agent_name);
+    let items = perspectives
+        .0
+        .as_array()
+        .cloned()
+        .unwrap_or_else(|| error!("Perspectives must be a JSON array"));
+
+    let claims: Vec<Result<Claim, String>> = items.iter().map(parse_claim).collect();
+    let refs: Vec<&NodeRef> = claims
+        .iter()
+        .flatten()
+        .flat_map(|c| std::iter::once(&c.node).chain(c.context.as_ref()))
+        .collect();
+    let resolved = resolve_refs(&refs);
+    let lookup = |r: &NodeRef| resolved[r].clone();
+
+    let mut counts: HashMap<&str, i64> = HashMap::new();
+    let mut seen: HashMap<(String, Option<String>), (f64, Option<String>, String)> = HashMap::new();
+    let mut results = Vec::with_capacity(items.len());
+
+    for (index, claim) in claims.iter().enumerate() {
+        let resolved = claim.as_ref().map_err(Clone::clone).and_then(|c| {
+            let node = lookup(&c.node)?;
+            let context = c.context.as_ref().map(lookup).transpose()?;
+            Ok((c, node, context))
+        });
+        let (claim, node_id, context_id) = match resolved {
+            Ok(r) => r,
+            Err(e) => {
+                *counts.entry("invalid").or_default() += 1;
+                results.push(json!({ "index": index, "status": "invalid", "error": e }));
+                continue;
+            }
+        };
+
+        let key = (node_id.clone(), context_id.clone());
+        let (id, status) = match seen.get(&key) {
+            Some((weight, reasoning, id))
+                if *weight == claim.weight && *reasoning == claim.reasoning =>
+            {
+                (id.clone(), "duplicate")
+            }
+            _ => write_claim(&agent_id, &node_id, context_id.as_deref(), claim),
+        };
+        seen.insert(key, (claim.weight, claim.reasoning.clone(), id.clone()));
+        *counts.entry(status).or_default() += 1;
+        results.push(json!({
+            "index": index,
+            "status": status,
+            "id": id,
+            "node_id": node_id,
+            "context_id": context_id,
+        }));
+    }
+
+    let count = |status: &str| counts.get(status).copied().unwrap_or(0);
+    pgrx::JsonB(json!({
+        "agent": agent_name,
+        "inserted": count("inserted"),
+        "updated": count("updated"),
+        "unchanged": count("unchanged"),
+        "duplicates": count("duplicate"),
+        "invalid": count("invalid"),
+        "items": results,
+    }))
+}
+
 /// Set or update an association (agent's weighted link between two nodes).
 #[pg_extern]
 fn set_association(
@@ -272,3 +541,46 @@ fn get_associations(
     .unwrap_or_else(|| pgrx::JsonB(serde_json::json!([])));
     json
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims_name_nodes_by_id_or_path() {
+        let claim = parse_claim(&json!({
+            "node_path": "src.lib.parse",
+            "context_id": "9E1B2C4D-0000-4000-8000-000000000001",
+            "weight": -0.5,
+            "reasoning": "unchecked unwrap",
+        }))
+        .unwrap();
+        assert_eq!(claim.node, NodeRef::Path("src.lib.parse".into()));
+        assert_eq!(
+            claim.context,
+            Some(NodeRef::Id("9e1b2c4d-0000-4000-8000-000000000001".into()))
+        );
+        assert_eq!(claim.weight, -0.5);
+        assert_eq!(claim.reasoning.as_deref(), Some("unchecked unwrap"));
+
+        let bare =
+            parse_claim(&json!({"node_id": "9e1b2c4d-0000-4000-8000-000000000001", "weight": 1}))
+                .unwrap();
+        assert_eq!(bare.context, None);
+        assert_eq!(bare.reasoning, None);
+    }
+
+    #[test]
+    fn bad_claims_say_why() {
+        let err = |v: Value| parse_claim(&v).unwrap_err();
+        assert_eq!(err(json!({"weight": 0.1})), "missing node_id or node_path");
+        assert!(err(json!({"node_id": "nope", "weight": 0.1})).contains("not a uuid"));
+        assert!(err(json!({"node_path": "a..b", "weight": 0.1})).contains("invalid path"));
+        assert!(err(json!({"node_path": "a.b c", "weight": 0.1})).contains("unexpected ' '"));
+        assert!(err(json!({"node_path": "a", "node_id": "x", "weight": 0})).contains("not both"));
+        assert!(err(json!({"node_path": "a", "weight": 1.5})).contains("between -1.0 and 1.0"));
+        assert!(err(json!({"node_path": "a", "weight": "high"})).contains("must be a number"));
+        assert!(err(json!({"node_path": "a", "weight": 0, "reasoning": 3})).contains("reasoning"));
+        assert_eq!(err(json!("a.b")), "item must be an object");
+    }
+}
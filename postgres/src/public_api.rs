@@ -0,0 +1,129 @@
+/// Versioned public views (`kerai.v1_*`) for BI tools and other readers.
+///
+/// The views are registered in kerai.api_views. A view's columns never
+/// change once released; a breaking change ships as a new `vN+1` view and
+/// the old one is deprecated here, which records the replacement and a
+/// sunset date and prefixes the view's comment so the notice shows up in
+/// catalog browsers. Read access goes through the `kerai_reader` role.
+use pgrx::prelude::*;
+
+use crate::sql::{sql_escape, sql_ident, sql_opt_text};
+
+/// Registered public views with their columns and deprecation state:
+/// `[{name, version, description, status, columns: [{name, type, comment}],
+/// introduced_at, deprecated_at, replaced_by, sunset_at}]`, newest version
+/// first within each name. `status` is `current`, `deprecated` or `sunset`
+/// (past its sunset date).
+#[pg_extern]
+fn api_views() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'name', a.name,
+            'version', a.version,
+            'description', a.description,
+            'status', CASE
+                WHEN a.sunset_at IS NOT NULL AND a.sunset_at <= now() THEN 'sunset'
+                WHEN a.deprecated_at IS NOT NULL THEN 'deprecated'
+                ELSE 'current' END,
+            'columns', (
+                SELECT COALESCE(jsonb_agg(jsonb_build_object(
+                    'name', att.attname,
+                    'type', format_type(att.atttypid, att.atttypmod),
+                    'comment', col_description(att.attrelid, att.attnum)
+                ) ORDER BY att.attnum), '[]'::jsonb)
+                FROM pg_attribute att
+                WHERE att.attrelid = to_regclass('kerai.' || a.name)
+                  AND att.attnum > 0 AND NOT att.attisdropped
+            ),
+            'introduced_at', a.introduced_at,
+            'deprecated_at', a.deprecated_at,
+            'replaced_by', a.replaced_by,
+            'sunset_at', a.sunset_at
+        ) ORDER BY regexp_replace(a.name, '^v\\d+_', ''), a.version DESC), '[]'::jsonb)
+        FROM kerai.api_views a",
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(serde_json::json!([])))
+}
+
+/// Mark a public view deprecated in favour of `replaced_by` (which must be
+/// registered too), to be dropped after `sunset_at` if given. The view keeps
+/// working unchanged; its comment becomes a `DEPRECATED` notice naming the
+/// replacement.
+///
+/// Returns the updated registry entry.
+#[pg_extern]
+fn deprecate_api_view(
+    name: &str,
+    replaced_by: &str,
+    sunset_at: default!(Option<&str>, "NULL"),
+) -> pgrx::JsonB {
+    for view in [name, replaced_by] {
+        let registered = Spi::get_one::<bool>(&format!(
+            "SELECT EXISTS(SELECT 1 FROM kerai.api_views WHERE name = '{}')",
+            sql_escape(view),
+        ))
+        .unwrap()
+        .unwrap_or(false);
+        if !registered {
+            error!("'{}' is not a registered API view", view);
+        }
+    }
+    if name == replaced_by {
+        error!("An API view cannot replace itself");
+    }
+
+    Spi::run(&format!(
+        "UPDATE kerai.api_views
+         SET deprecated_at = COALESCE(deprecated_at, now()), replaced_by = '{}',
+             sunset_at = {}::timestamptz
+         WHERE name = '{}'",
+        sql_escape(replaced_by),
+        sql_opt_text(&sunset_at.map(str::to_string)),
+        sql_escape(name),
+    ))
+    .unwrap_or_else(|e| error!("Failed to deprecate {}: {}", name, e));
+
+    let (description, sunset) = Spi::get_two::<String, String>(&format!(
+        "SELECT description, sunset_at::date::text FROM kerai.api_views WHERE name = '{}'",
+        sql_escape(name),
+    ))
+    .unwrap();
+    let description = description.unwrap_or_default();
+    let sunset_note = sunset
+        .map(|date| format!(", removed after {}", date))
+        .unwrap_or_default();
+    Spi::run(&format!(
+        "COMMENT ON VIEW kerai.{} IS '{}'",
+        sql_ident(name),
+        sql_escape(&format!(
+            "DEPRECATED: use kerai.{replaced_by}{sunset_note}. {description}"
+        )),
+    ))
+    .unwrap();
+
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT to_jsonb(a) FROM kerai.api_views a WHERE name = '{}'",
+        sql_escape(name),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("API view '{}' vanished", name))
+}
+
+/// Give `role` read access to the public views by granting it
+/// `kerai_reader`. Returns the role name.
+#[pg_extern]
+fn grant_api_reader(role: &str) -> String {
+    let exists = Spi::get_one::<bool>(&format!(
+        "SELECT EXISTS(SELECT 1 FROM pg_roles WHERE rolname = '{}')",
+        sql_escape(role),
+    ))
+    .unwrap()
+    .unwrap_or(false);
+    if !exists {
+        error!("Role '{}' does not exist", role);
+    }
+    Spi::run(&format!("GRANT kerai_reader TO {}", sql_ident(role)))
+        .unwrap_or_else(|e| error!("Failed to grant kerai_reader to {}: {}", role, e));
+    role.to_string()
+}
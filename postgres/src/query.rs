@@ -1,14 +1,29 @@
-/// Query & Navigation — find, refs, tree, children, ancestors, search.
+/// Query & Navigation — find, grep, find_fuzzy, refs, tree, children, ancestors, search, diff, merge, dedup stats.
 use pgrx::prelude::*;
 use serde_json::json;
 
-use crate::sql::sql_escape;
+use crate::changesets;
+use crate::parser::ast_walker::NodeRow;
+use crate::parser::diff::{diff_json, diff_trees};
+use crate::parser::get_self_instance_id;
+use crate::parser::inserter::{insert_nodes, load_file_tree, TreeNode};
+use crate::parser::merge::merge_trees;
+use crate::sql::{sql_escape, sql_jsonb, sql_uuid};
 
 /// Search nodes by content pattern (ILIKE) with optional kind filter and limit.
+/// Reads through the changeset `kerai.with_changeset` put in effect, if any.
 ///
 /// Returns JSON array of `{id, kind, content, path, parent_id, metadata}`.
 #[pg_extern]
 fn find(pattern: &str, kind_filter: Option<&str>, limit: Option<i32>) -> pgrx::JsonB {
+    if let Some(changeset) = changesets::active() {
+        return pgrx::JsonB(changesets::read_through(
+            &changeset,
+            "SELECT kerai.find($1->>'pattern', $1->>'kind', ($1->>'limit')::int)",
+            json!({"pattern": pattern, "kind": kind_filter, "limit": limit}),
+        ));
+    }
+
     let limit_val = limit.unwrap_or(50).max(1).min(1000);
     let escaped_pattern = sql_escape(pattern);
 
@@ -28,7 +43,7 @@ fn find(pattern: &str, kind_filter: Option<&str>, limit: Option<i32>) -> pgrx::J
                 'metadata', metadata
             ) AS r
             FROM kerai.nodes
-            WHERE content ILIKE '{}' {}
+            WHERE content ILIKE '{}' {} AND deleted_at IS NULL AND kerai.in_view(path, kind)
             ORDER BY kind, content
             LIMIT {}
         ) sub",
@@ -40,9 +55,179 @@ fn find(pattern: &str, kind_filter: Option<&str>, limit: Option<i32>) -> pgrx::J
         .unwrap_or_else(|| pgrx::JsonB(serde_json::json!([])))
 }
 
-/// Find all definitions, references, and impl blocks for a symbol.
+/// Regex search over node content, narrowed by kind and by an lquery on
+/// the node path. `pattern` is a Postgres regular expression; start it
+/// with `(?i)` to ignore case.
 ///
-/// Returns `{symbol, definitions: [...], references: [...], impls: [...]}`.
+/// Returns JSON array of `{id, kind, path, file, line, col, end_line, span,
+/// match, text}`, in path order. `span` is the match's character range in
+/// the node content, `text` the content line it starts on and `col` its
+/// column in that line. `line` comes from the node's `start_line`/`line`
+/// metadata, or its position for items directly under a Rust file, and is
+/// null when neither is known.
+#[pg_extern]
+fn grep(
+    pattern: &str,
+    kind: default!(Option<&str>, "NULL"),
+    path: default!(Option<&str>, "NULL"),
+    limit: default!(Option<i32>, "NULL"),
+) -> pgrx::JsonB {
+    let limit_val = limit.unwrap_or(200).max(1).min(5000);
+    let escaped_pattern = sql_escape(pattern);
+
+    let kind_clause = match kind {
+        Some(k) => format!("AND n.kind = '{}'", sql_escape(k)),
+        None => String::new(),
+    };
+    let path_clause = match path {
+        Some(p) => format!("AND n.path ~ '{}'::lquery", sql_escape(p)),
+        None => String::new(),
+    };
+
+    let sql = format!(
+        "WITH RECURSIVE hits AS (
+            SELECT n.id, n.kind, n.content, n.parent_id, n.path, n.position, n.metadata,
+                   regexp_instr(n.content, '{0}') AS at,
+                   regexp_substr(n.content, '{0}') AS hit
+            FROM kerai.nodes n
+            WHERE n.content ~ '{0}' {kind_clause} {path_clause}
+              AND n.deleted_at IS NULL AND kerai.in_view(n.path, n.kind)
+            ORDER BY n.path, n.position
+            LIMIT {limit_val}
+        ), up AS (
+            -- Walk up from each hit to the file or document holding it
+            SELECT h.id AS hit_id, n.id, n.kind, n.parent_id
+            FROM hits h JOIN kerai.nodes n ON n.id = h.id
+            UNION ALL
+            SELECT up.hit_id, p.id, p.kind, p.parent_id
+            FROM up JOIN kerai.nodes p ON p.id = up.parent_id
+            WHERE up.kind NOT IN ('file', 'document')
+        ), located AS (
+            SELECT h.*, f.content AS file, par.kind AS parent_kind,
+                   left(h.content, h.at - 1) AS before
+            FROM hits h
+            LEFT JOIN up u ON u.hit_id = h.id AND u.kind IN ('file', 'document')
+            LEFT JOIN kerai.nodes f ON f.id = u.id
+            LEFT JOIN kerai.nodes par ON par.id = h.parent_id
+        ), lined AS (
+            SELECT l.*,
+                   length(l.before) - length(replace(l.before, E'\\n', '')) AS newlines,
+                   COALESCE((l.metadata->>'start_line')::int, (l.metadata->>'line')::int,
+                            CASE WHEN l.parent_kind = 'file' THEN l.position END) AS first_line
+            FROM located l
+        )
+        SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', id,
+            'kind', kind,
+            'path', path::text,
+            'file', file,
+            'line', first_line + newlines,
+            'col', length(regexp_replace(before, '^.*\\n', '')) + 1,
+            'end_line', (metadata->>'end_line')::int,
+            'span', jsonb_build_object('start', at - 1, 'end', at - 1 + length(hit)),
+            'match', hit,
+            'text', split_part(content, E'\\n', newlines::int + 1)
+        ) ORDER BY path, position), '[]'::jsonb)
+        FROM lined",
+        escaped_pattern,
+    );
+
+    Spi::get_one::<pgrx::JsonB>(&sql)
+        .unwrap()
+        .unwrap_or_else(|| pgrx::JsonB(serde_json::json!([])))
+}
+
+/// Node kinds that name a symbol; matches the predicate of the
+/// `idx_nodes_symbol_trgm` index so fuzzy lookups can use it.
+pub(crate) const SYMBOL_KINDS: &str = "'fn', 'struct', 'enum', 'trait', 'const', 'static', \
+    'type_alias', 'union', 'macro_def', 'variant', 'field'";
+
+/// Trigram candidates considered before edit distance is applied.
+const FUZZY_CANDIDATES: i64 = 200;
+
+/// Case-insensitive Levenshtein distance between two names.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != cb);
+            cur[j + 1] = substitute.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// Symbols whose names are close to `name`, for when the exact spelling
+/// is not known. Candidates come from the pg_trgm index (`%` operator) and
+/// are kept when within `max_distance` edits.
+///
+/// Returns JSON array of `{id, kind, name, path, similarity, distance}`,
+/// nearest first.
+#[pg_extern]
+fn find_fuzzy(
+    name: &str,
+    max_distance: default!(i32, 3),
+    kind_filter: default!(Option<&str>, "NULL"),
+    limit: default!(Option<i32>, "NULL"),
+) -> pgrx::JsonB {
+    let limit_val = limit.unwrap_or(20).max(1).min(1000) as usize;
+    let max_distance = max_distance.max(0) as usize;
+
+    let kind_clause = match kind_filter {
+        Some(k) => format!("AND kind = '{}'", sql_escape(k)),
+        None => String::new(),
+    };
+
+    let candidates = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(r ORDER BY sim DESC), '[]'::jsonb) FROM (
+            SELECT jsonb_build_object(
+                'id', id,
+                'kind', kind,
+                'name', content,
+                'path', path::text,
+                'similarity', round(similarity(content, '{0}')::numeric, 3)
+            ) AS r,
+            similarity(content, '{0}') AS sim
+            FROM kerai.nodes
+            WHERE kind IN ({SYMBOL_KINDS}) AND content % '{0}' {kind_clause}
+              AND deleted_at IS NULL AND kerai.in_view(path, kind)
+            ORDER BY sim DESC
+            LIMIT {FUZZY_CANDIDATES}
+        ) sub",
+        sql_escape(name),
+    ))
+    .unwrap()
+    .and_then(|j| j.0.as_array().cloned())
+    .unwrap_or_default();
+
+    let mut matches: Vec<(usize, serde_json::Value)> = candidates
+        .into_iter()
+        .filter_map(|mut c| {
+            let distance = edit_distance(name, c["name"].as_str().unwrap_or_default());
+            (distance <= max_distance).then(|| {
+                c["distance"] = json!(distance);
+                (distance, c)
+            })
+        })
+        .collect();
+    // Fewest edits first; candidates arrive most similar first
+    matches.sort_by_key(|(distance, _)| *distance);
+    matches.truncate(limit_val);
+
+    pgrx::JsonB(json!(matches.into_iter().map(|(_, c)| c).collect::<Vec<_>>()))
+}
+
+/// Find all definitions, callers, references, impl blocks, and dependent crates for a symbol.
+///
+/// Returns `{symbol, definitions: [...], callers: [...], references: [...], impls: [...], dependents: [...]}`.
+/// `callers` are functions with a `calls` edge to a function named `symbol`
+/// (see `kerai.resolve_calls`); `references` are name matches.
+/// `dependents` lists crates with a `depends_on` edge to a dependency named `symbol`.
 #[pg_extern]
 fn refs(symbol: &str) -> pgrx::JsonB {
     let escaped = sql_escape(symbol);
@@ -57,7 +242,7 @@ fn refs(symbol: &str) -> pgrx::JsonB {
             'metadata', metadata
         ) ORDER BY kind, path::text), '[]'::jsonb)
         FROM kerai.nodes
-        WHERE content = '{}' AND kind IN (
+        WHERE content = '{}' AND deleted_at IS NULL AND kind IN (
             'fn', 'struct', 'enum', 'trait', 'const', 'static',
             'type_alias', 'union', 'macro_def', 'variant', 'field'
         )",
@@ -76,7 +261,7 @@ fn refs(symbol: &str) -> pgrx::JsonB {
         ) ORDER BY n.kind, n.path::text), '[]'::jsonb)
         FROM kerai.nodes n
         LEFT JOIN kerai.nodes p ON n.parent_id = p.id
-        WHERE n.content = '{}' AND n.kind IN (
+        WHERE n.content = '{}' AND n.deleted_at IS NULL AND n.kind IN (
             'expr_path', 'expr_method_call', 'type_path', 'expr_call',
             'expr_field', 'pat_path', 'pat_ident', 'pat_struct',
             'pat_tuple_struct', 'use'
@@ -94,7 +279,42 @@ fn refs(symbol: &str) -> pgrx::JsonB {
             'metadata', metadata
         ) ORDER BY path::text), '[]'::jsonb)
         FROM kerai.nodes
-        WHERE kind = 'impl' AND metadata->>'self_ty' = '{}'",
+        WHERE kind = 'impl' AND metadata->>'self_ty' = '{}' AND deleted_at IS NULL",
+        escaped,
+    );
+
+    // Callers: functions with a resolved `calls` edge to a definition
+    let callers_sql = format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', c.id,
+            'kind', c.kind,
+            'content', c.content,
+            'path', c.path::text,
+            'callee_id', d.id,
+            'callee_path', d.path::text,
+            'resolution', e.metadata->>'resolution',
+            'sites', jsonb_array_length(COALESCE(e.metadata->'sites', '[]'::jsonb))
+        ) ORDER BY c.path::text), '[]'::jsonb)
+        FROM kerai.edges e
+        JOIN kerai.nodes d ON d.id = e.target_id
+        JOIN kerai.nodes c ON c.id = e.source_id
+        WHERE e.relation = 'calls' AND d.kind = 'fn' AND d.content = '{}'",
+        escaped,
+    );
+
+    // Dependents: crates whose manifest depends on a crate named `symbol`
+    let dependents_sql = format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', c.id,
+            'crate', c.content,
+            'version', e.metadata->>'version',
+            'dep_type', e.metadata->>'dep_type',
+            'path', d.path::text
+        ) ORDER BY c.content), '[]'::jsonb)
+        FROM kerai.edges e
+        JOIN kerai.nodes d ON d.id = e.target_id
+        JOIN kerai.nodes c ON c.id = e.source_id
+        WHERE e.relation = 'depends_on' AND d.kind = 'dependency' AND d.content = '{}'",
         escaped,
     );
 
@@ -108,11 +328,21 @@ fn refs(symbol: &str) -> pgrx::JsonB {
         .unwrap()
         .unwrap_or_else(|| pgrx::JsonB(serde_json::json!([])));
 
+    let callers = Spi::get_one::<pgrx::JsonB>(&callers_sql)
+        .unwrap()
+        .unwrap_or_else(|| pgrx::JsonB(serde_json::json!([])));
+
+    let dependents = Spi::get_one::<pgrx::JsonB>(&dependents_sql)
+        .unwrap()
+        .unwrap_or_else(|| pgrx::JsonB(serde_json::json!([])));
+
     pgrx::JsonB(serde_json::json!({
         "symbol": symbol,
         "definitions": definitions.0,
+        "callers": callers.0,
         "references": references.0,
         "impls": impls.0,
+        "dependents": dependents.0,
     }))
 }
 
@@ -122,9 +352,18 @@ fn refs(symbol: &str) -> pgrx::JsonB {
 /// - Path with lquery wildcards (`*`, `|`, `!`): use `path ~ pattern::lquery`.
 /// - Otherwise: use `path <@ pattern::ltree` for subtree.
 ///
-/// Each node includes a `child_count`.
+/// Each node includes a `child_count`. Reads through the changeset
+/// `kerai.with_changeset` put in effect, if any.
 #[pg_extern]
 fn tree(path_pattern: Option<&str>) -> pgrx::JsonB {
+    if let Some(changeset) = changesets::active() {
+        return pgrx::JsonB(changesets::read_through(
+            &changeset,
+            "SELECT kerai.tree($1->>'path')",
+            json!({"path": path_pattern}),
+        ));
+    }
+
     let sql = match path_pattern {
         None => {
             // Top-level: nodes with no parent (crate/module/file roots)
@@ -133,10 +372,11 @@ fn tree(path_pattern: Option<&str>) -> pgrx::JsonB {
                 'kind', n.kind,
                 'content', n.content,
                 'path', n.path::text,
-                'child_count', (SELECT count(*) FROM kerai.nodes c WHERE c.parent_id = n.id)
+                'child_count', (SELECT count(*) FROM kerai.nodes c WHERE c.parent_id = n.id AND c.deleted_at IS NULL)
             ) ORDER BY n.path::text, n.position), '[]'::jsonb)
             FROM kerai.nodes n
-            WHERE n.parent_id IS NULL".to_string()
+            WHERE n.parent_id IS NULL AND n.deleted_at IS NULL
+              AND kerai.in_view(n.path, n.kind)".to_string()
         }
         Some(pattern) => {
             let escaped = sql_escape(pattern);
@@ -154,10 +394,10 @@ fn tree(path_pattern: Option<&str>) -> pgrx::JsonB {
                     'kind', n.kind,
                     'content', n.content,
                     'path', n.path::text,
-                    'child_count', (SELECT count(*) FROM kerai.nodes c WHERE c.parent_id = n.id)
+                    'child_count', (SELECT count(*) FROM kerai.nodes c WHERE c.parent_id = n.id AND c.deleted_at IS NULL)
                 ) ORDER BY n.path::text, n.position), '[]'::jsonb)
                 FROM kerai.nodes n
-                WHERE {}",
+                WHERE {} AND n.deleted_at IS NULL AND kerai.in_view(n.path, n.kind)",
                 where_clause,
             )
         }
@@ -180,10 +420,10 @@ fn children(node_id: pgrx::Uuid) -> pgrx::JsonB {
             'content', n.content,
             'path', n.path::text,
             'position', n.position,
-            'child_count', (SELECT count(*) FROM kerai.nodes c WHERE c.parent_id = n.id)
+            'child_count', (SELECT count(*) FROM kerai.nodes c WHERE c.parent_id = n.id AND c.deleted_at IS NULL)
         ) ORDER BY n.position), '[]'::jsonb)
         FROM kerai.nodes n
-        WHERE n.parent_id = '{}'::uuid",
+        WHERE n.parent_id = '{}'::uuid AND n.deleted_at IS NULL",
         node_id,
     );
 
@@ -225,14 +465,24 @@ fn ancestors(node_id: pgrx::Uuid) -> pgrx::JsonB {
         .unwrap_or_else(|| pgrx::JsonB(serde_json::json!([])))
 }
 
-/// Full-text search using PostgreSQL tsvector/tsquery with ranking.
+/// Full-text search over the indexed `tsv` column, ranked by relevance.
 ///
-/// Unlike `find` which uses ILIKE pattern matching, `search` uses proper
-/// FTS with `plainto_tsquery` and `ts_rank` for relevance-ranked results.
+/// Unlike `find` which uses ILIKE pattern matching, `search` takes web
+/// search syntax (`"exact phrase"`, `or`, `-excluded`) through
+/// `websearch_to_tsquery` and ranks with `ts_rank`, normalized by document
+/// length so long nodes don't win on volume alone.
 ///
-/// Returns JSON array of `{id, kind, content, path, rank, metadata}`.
+/// Returns JSON array of `{id, kind, content, path, rank, headline, metadata}`;
+/// `headline` is the matching fragment with terms wrapped in `**`.
+/// `kind_filter` and `language_filter` keep only nodes of that kind or
+/// language.
 #[pg_extern]
-fn search(query: &str, kind_filter: Option<&str>, limit: Option<i32>) -> pgrx::JsonB {
+fn search(
+    query: &str,
+    kind_filter: default!(Option<&str>, "NULL"),
+    limit: default!(Option<i32>, "NULL"),
+    language_filter: default!(Option<&str>, "NULL"),
+) -> pgrx::JsonB {
     let limit_val = limit.unwrap_or(50).max(1).min(1000);
     let escaped_query = sql_escape(query);
 
@@ -240,25 +490,33 @@ fn search(query: &str, kind_filter: Option<&str>, limit: Option<i32>) -> pgrx::J
         Some(k) => format!("AND n.kind = '{}'", sql_escape(k)),
         None => String::new(),
     };
+    let language_clause = match language_filter {
+        Some(l) => format!("AND n.language = '{}'", sql_escape(l)),
+        None => String::new(),
+    };
 
+    // Headlines are costly, so they're only built for the page returned
     let sql = format!(
-        "SELECT COALESCE(jsonb_agg(r ORDER BY rank DESC), '[]'::jsonb) FROM (
-            SELECT jsonb_build_object(
-                'id', n.id,
-                'kind', n.kind,
-                'content', n.content,
-                'path', n.path::text,
-                'rank', ts_rank(to_tsvector('english', COALESCE(n.content, '')), q.query),
-                'metadata', n.metadata
-            ) AS r,
-            ts_rank(to_tsvector('english', COALESCE(n.content, '')), q.query) AS rank
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', n.id,
+            'kind', n.kind,
+            'content', n.content,
+            'path', n.path::text,
+            'rank', hits.rank,
+            'headline', ts_headline('english', COALESCE(n.content, ''), hits.query,
+                                    'StartSel=**, StopSel=**, MaxFragments=1, MaxWords=20, MinWords=5'),
+            'metadata', n.metadata
+        ) ORDER BY hits.rank DESC), '[]'::jsonb)
+        FROM (
+            SELECT n.id, q.query, ts_rank(n.tsv, q.query, 1) AS rank
             FROM kerai.nodes n,
-                 plainto_tsquery('english', '{}') q(query)
-            WHERE to_tsvector('english', COALESCE(n.content, '')) @@ q.query {}
+                 websearch_to_tsquery('english', '{}') q(query)
+            WHERE n.tsv @@ q.query {} {} AND n.deleted_at IS NULL AND kerai.in_view(n.path, n.kind)
             ORDER BY rank DESC
             LIMIT {}
-        ) sub",
-        escaped_query, kind_clause, limit_val,
+        ) hits
+        JOIN kerai.nodes n ON n.id = hits.id",
+        escaped_query, kind_clause, language_clause, limit_val,
     );
 
     Spi::get_one::<pgrx::JsonB>(&sql)
@@ -319,9 +577,9 @@ fn context_search(
 
     // When no agent join, reference pw columns directly as NULLs
     let combined_expr = if agent_join.is_empty() {
-        "ts_rank(to_tsvector('english', COALESCE(n.content, '')), q.query) AS combined_score"
+        "ts_rank(n.tsv, q.query) AS combined_score"
     } else {
-        "ts_rank(to_tsvector('english', COALESCE(n.content, '')), q.query) * (1.0 + COALESCE(pw.avg_weight, 0.0)) AS combined_score"
+        "ts_rank(n.tsv, q.query) * (1.0 + COALESCE(pw.avg_weight, 0.0)) AS combined_score"
     };
 
     let sql = format!(
@@ -330,7 +588,7 @@ fn context_search(
             'kind', n.kind,
             'content', n.content,
             'path', n.path::text,
-            'fts_rank', ts_rank(to_tsvector('english', COALESCE(n.content, '')), q.query),
+            'fts_rank', ts_rank(n.tsv, q.query),
             'perspective_weight', sub.perspective_weight,
             'combined_score', sub.combined_score,
             'agents', sub.agents
@@ -342,7 +600,7 @@ fn context_search(
             FROM kerai.nodes n,
                  plainto_tsquery('english', '{escaped_query}') q(query)
             {agent_join}
-            WHERE to_tsvector('english', COALESCE(n.content, '')) @@ q.query
+            WHERE n.tsv @@ q.query AND n.deleted_at IS NULL AND kerai.in_view(n.path, n.kind)
             ORDER BY combined_score DESC
             LIMIT {limit_val}
         ) sub
@@ -354,3 +612,171 @@ fn context_search(
         .unwrap()
         .unwrap_or_else(|| pgrx::JsonB(json!([])))
 }
+
+/// Structural diff of two file subtrees (`a` before, `b` after).
+///
+/// Returns `{file_a, file_b, inserted, deleted, moved, modified, unchanged}`.
+/// Inserted/deleted entries are subtree roots with a `nodes` count; moved
+/// and modified entries pair an `a_id` with a `b_id`.
+#[pg_extern]
+fn diff_nodes(file_a: pgrx::Uuid, file_b: pgrx::Uuid) -> pgrx::JsonB {
+    let (a_id, b_id) = (file_a.to_string(), file_b.to_string());
+    let a = load_file_tree(&a_id);
+    let b = load_file_tree(&b_id);
+    for (id, tree) in [(&a_id, &a), (&b_id, &b)] {
+        if tree.is_empty() {
+            pgrx::error!("diff_nodes: node {} not found", id);
+        }
+    }
+
+    let diff = diff_trees(&a, &a_id, &b, &b_id);
+    let mut result = diff_json(&a, &b, &diff);
+    let name = |tree: &[TreeNode], id: &str| {
+        tree.iter().find(|n| n.id == id).and_then(|n| n.content.clone())
+    };
+    result["file_a"] = json!({"id": a_id, "name": name(&a, &a_id)});
+    result["file_b"] = json!({"id": b_id, "name": name(&b, &b_id)});
+    pgrx::JsonB(result)
+}
+
+/// Three-way structural merge of file subtrees `ours` and `theirs` against
+/// their common ancestor `base`.
+///
+/// The merged tree is inserted as a new file node with no parent, its
+/// intra-file edges copied from both sides. Edits that collide become
+/// `conflict` nodes holding the base, ours and theirs text, which
+/// `reconstruct_file` renders between conflict markers.
+///
+/// Returns `{root, nodes, conflicts}`; each conflict is `{node_id, reason,
+/// kind, path, base_id, ours_id, theirs_id}`, with `node_id` null for move
+/// and reorder conflicts, which keep ours.
+#[pg_extern]
+fn merge_nodes(base: pgrx::Uuid, ours: pgrx::Uuid, theirs: pgrx::Uuid) -> pgrx::JsonB {
+    let ids = [base.to_string(), ours.to_string(), theirs.to_string()];
+    let trees: Vec<Vec<TreeNode>> = ids.iter().map(|id| load_file_tree(id)).collect();
+    for (id, tree) in ids.iter().zip(&trees) {
+        if tree.is_empty() {
+            pgrx::error!("merge_nodes: node {} not found", id);
+        }
+    }
+
+    let merge = merge_trees(&trees[0], &ids[0], &trees[1], &ids[1], &trees[2], &ids[2]);
+    let instance_id = get_self_instance_id();
+    let language = Spi::get_one::<String>(&format!(
+        "SELECT language FROM kerai.nodes WHERE id = {}",
+        sql_uuid(&ids[1]),
+    ))
+    .unwrap();
+    let new_ids: Vec<String> =
+        merge.nodes.iter().map(|_| uuid::Uuid::now_v7().to_string()).collect();
+
+    let rows: Vec<NodeRow> = merge
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| {
+            let mut metadata = n.metadata.clone();
+            if n.parent.is_none() {
+                metadata["merged_from"] = json!({"base": ids[0], "ours": ids[1], "theirs": ids[2]});
+            }
+            NodeRow {
+                id: new_ids[i].clone(),
+                instance_id: instance_id.clone(),
+                kind: n.kind.clone(),
+                language: language.clone(),
+                content: n.content.clone(),
+                parent_id: n.parent.map(|p| new_ids[p].clone()),
+                position: n.position,
+                path: n.path.clone(),
+                metadata,
+                span_start: None,
+                span_end: None,
+            }
+        })
+        .collect();
+    insert_nodes(&rows);
+
+    // Edges between nodes that both made it into the merge, from either side
+    let map: Vec<serde_json::Value> = merge
+        .nodes
+        .iter()
+        .zip(&new_ids)
+        .flat_map(|(n, new_id)| n.sources.iter().map(move |old| json!({"old_id": old, "new_id": new_id})))
+        .collect();
+    Spi::run(&format!(
+        "WITH m AS (
+            SELECT * FROM jsonb_to_recordset({}) AS m(old_id uuid, new_id uuid)
+        )
+        INSERT INTO kerai.edges (source_id, target_id, relation, metadata)
+        SELECT DISTINCT ON (s.new_id, t.new_id, e.relation) s.new_id, t.new_id, e.relation, e.metadata
+        FROM kerai.edges e
+        JOIN m s ON s.old_id = e.source_id
+        JOIN m t ON t.old_id = e.target_id
+        ON CONFLICT DO NOTHING",
+        sql_jsonb(&json!(map)),
+    ))
+    .expect("Failed to copy edges into merged tree");
+
+    let conflicts: Vec<serde_json::Value> = merge
+        .conflicts
+        .iter()
+        .map(|c| {
+            json!({
+                "node_id": c.node.map(|i| &new_ids[i]),
+                "reason": c.reason,
+                "kind": c.kind,
+                "path": c.path,
+                "base_id": c.base,
+                "ours_id": c.ours,
+                "theirs_id": c.theirs,
+            })
+        })
+        .collect();
+
+    pgrx::JsonB(json!({
+        "root": new_ids.first(),
+        "nodes": new_ids.len(),
+        "conflicts": conflicts,
+    }))
+}
+
+/// Space saved by subtree deduplication.
+///
+/// `shared_subtrees` counts stub nodes standing in for a repeated subtree,
+/// `nodes_saved`/`bytes_saved` sum what those stubs did not store
+/// (content plus metadata text), and `orphaned` counts stubs whose
+/// `duplicates` edge is gone because the canonical subtree was deleted.
+#[pg_extern]
+fn dedup_stats() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(
+        "SELECT jsonb_build_object(
+            'total_nodes', (SELECT count(*) FROM kerai.nodes),
+            'hashed_nodes', (SELECT count(*) FROM kerai.nodes WHERE content_hash IS NOT NULL),
+            'shared_subtrees', count(*),
+            'nodes_saved', COALESCE(sum((metadata->>'dedup_nodes')::bigint), 0),
+            'bytes_saved', COALESCE(sum((metadata->>'dedup_bytes')::bigint), 0),
+            'orphaned', count(*) FILTER (WHERE NOT EXISTS (
+                SELECT 1 FROM kerai.edges e
+                WHERE e.source_id = n.id AND e.relation = 'duplicates'
+            ))
+        )
+        FROM kerai.nodes n
+        WHERE metadata ? 'dedup_of'",
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!({})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_typos() {
+        assert_eq!(edit_distance("reconstruct_file", "reconstruct_file"), 0);
+        assert_eq!(edit_distance("reconsturct_file", "reconstruct_file"), 2);
+        assert_eq!(edit_distance("Parse", "parse"), 0);
+        assert_eq!(edit_distance("parse", "parser"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
@@ -135,20 +135,18 @@ fn query_suggestions(
         std::collections::HashMap::new();
 
     Spi::connect(|client| {
-        let query = format!(
-            "SELECT n.content, n.metadata->>'rule' AS rule, \
+        let query = "SELECT n.content, n.metadata->>'rule' AS rule, \
              e.target_id::text AS target_id \
              FROM kerai.nodes n \
              JOIN kerai.edges e ON e.source_id = n.id \
-             WHERE n.parent_id = '{}'::uuid \
+             WHERE n.parent_id = $1::uuid \
+             AND n.deleted_at IS NULL \
              AND n.kind = 'suggestion' \
              AND n.metadata->>'status' = 'emitted' \
              AND e.relation = 'suggests' \
-             ORDER BY n.position ASC",
-            file_node_id.replace('\'', "''")
-        );
+             ORDER BY n.position ASC";
 
-        let result = client.select(&query, None, &[]).unwrap();
+        let result = client.select(query, None, &[file_node_id.into()]).unwrap();
         for row in result {
             let message: String = row
                 .get_by_name::<String, _>("content")
@@ -252,6 +250,11 @@ fn emit_item(
     item: &ChildItem,
     direct_comment_ids: &std::collections::HashSet<String>,
 ) {
+    if item.kind == Kind::Conflict.as_str() {
+        emit_conflict(parts, item);
+        return;
+    }
+
     if let Some(ref source) = item.source {
         let processed = source.clone();
 
@@ -296,6 +299,19 @@ fn emit_item(
     }
 }
 
+/// Emit both sides of a merge conflict between conflict markers.
+fn emit_conflict(parts: &mut Vec<String>, item: &ChildItem) {
+    parts.push("<<<<<<< ours".to_string());
+    if let Some(ref ours) = item.ours {
+        parts.push(ours.clone());
+    }
+    parts.push("=======".to_string());
+    if let Some(ref theirs) = item.theirs {
+        parts.push(theirs.clone());
+    }
+    parts.push(">>>>>>> theirs".to_string());
+}
+
 /// Emit a comment (line or block style) into the parts list.
 fn emit_comment(parts: &mut Vec<String>, content: &str, style: &str) {
     if style == "block" {
@@ -328,13 +344,10 @@ pub fn query_file_flags(file_node_id: &str) -> FileFlags {
     };
 
     Spi::connect(|client| {
-        let query = format!(
-            "SELECT metadata->'kerai_flags' AS flags \
-             FROM kerai.nodes WHERE id = '{}'::uuid",
-            file_node_id.replace('\'', "''")
-        );
+        let query = "SELECT metadata->'kerai_flags' AS flags \
+             FROM kerai.nodes WHERE id = $1::uuid";
 
-        let result = client.select(&query, None, &[]).unwrap();
+        let result = client.select(query, None, &[file_node_id.into()]).unwrap();
         for row in result {
             if let Some(flags_json) = row.get_by_name::<pgrx::JsonB, _>("flags").unwrap() {
                 let v = &flags_json.0;
@@ -364,6 +377,9 @@ struct ChildItem {
     source: Option<String>,
     placement: Option<String>,
     style: Option<String>,
+    /// Both sides of a `conflict` node.
+    ours: Option<String>,
+    theirs: Option<String>,
     /// Set to true when this comment was above a use item and was consumed by import sorting.
     consumed_by_import_sort: bool,
 }
@@ -373,19 +389,19 @@ fn query_child_items(file_node_id: &str) -> Vec<ChildItem> {
 
     Spi::connect(|client| {
         // Order by position (line number for both items and comments)
-        let query = format!(
-            "SELECT id::text, kind, content, \
+        let query = "SELECT id::text, kind, content, \
              metadata->>'source' AS source_text, \
              metadata->>'placement' AS placement, \
-             metadata->>'style' AS style \
+             metadata->>'style' AS style, \
+             metadata->>'ours' AS ours, \
+             metadata->>'theirs' AS theirs \
              FROM kerai.nodes \
-             WHERE parent_id = '{}'::uuid \
+             WHERE parent_id = $1::uuid \
+             AND deleted_at IS NULL \
              AND kind NOT IN ('doc_comment', 'attribute', 'suggestion') \
-             ORDER BY position ASC",
-            file_node_id.replace('\'', "''")
-        );
+             ORDER BY position ASC";
 
-        let result = client.select(&query, None, &[]).unwrap();
+        let result = client.select(query, None, &[file_node_id.into()]).unwrap();
 
         for row in result {
             let id: String = row.get_by_name::<String, _>("id")
@@ -398,9 +414,11 @@ fn query_child_items(file_node_id: &str) -> Vec<ChildItem> {
             let source: Option<String> = row.get_by_name::<String, _>("source_text").unwrap();
             let placement: Option<String> = row.get_by_name::<String, _>("placement").unwrap();
             let style: Option<String> = row.get_by_name::<String, _>("style").unwrap();
+            let ours: Option<String> = row.get_by_name::<String, _>("ours").unwrap();
+            let theirs: Option<String> = row.get_by_name::<String, _>("theirs").unwrap();
 
             items.push(ChildItem {
-                id, kind, content, source, placement, style,
+                id, kind, content, source, placement, style, ours, theirs,
                 consumed_by_import_sort: false,
             });
         }
@@ -449,19 +467,17 @@ fn query_trailing_comments(
     let mut comments = Vec::new();
 
     Spi::connect(|client| {
-        let query = format!(
-            "SELECT n.id::text, n.content, n.metadata->>'style' AS style \
+        let query = "SELECT n.id::text, n.content, n.metadata->>'style' AS style \
              FROM kerai.nodes n \
              JOIN kerai.edges e ON e.source_id = n.id \
-             WHERE e.target_id = '{}'::uuid \
+             WHERE e.target_id = $1::uuid \
              AND e.relation = 'documents' \
+             AND n.deleted_at IS NULL \
              AND n.kind IN ('comment', 'comment_block') \
              AND COALESCE(n.metadata->>'placement', 'above') = 'trailing' \
-             ORDER BY n.position ASC",
-            item_node_id.replace('\'', "''"),
-        );
+             ORDER BY n.position ASC";
 
-        let result = client.select(&query, None, &[]).unwrap();
+        let result = client.select(query, None, &[item_node_id.into()]).unwrap();
         for row in result {
             let id: String = row.get_by_name::<String, _>("id")
                 .unwrap()
@@ -483,16 +499,14 @@ fn query_inner_doc_comments(file_node_id: &str) -> Vec<String> {
     let mut docs = Vec::new();
 
     Spi::connect(|client| {
-        let query = format!(
-            "SELECT content FROM kerai.nodes \
-             WHERE parent_id = '{}'::uuid \
+        let query = "SELECT content FROM kerai.nodes \
+             WHERE parent_id = $1::uuid \
+             AND deleted_at IS NULL \
              AND kind = 'doc_comment' \
              AND (metadata->>'inner')::boolean = true \
-             ORDER BY position ASC",
-            file_node_id.replace('\'', "''")
-        );
+             ORDER BY position ASC";
 
-        let result = client.select(&query, None, &[]).unwrap();
+        let result = client.select(query, None, &[file_node_id.into()]).unwrap();
         for row in result {
             let content: String = row.get_by_name::<String, _>("content")
                 .unwrap()
@@ -508,18 +522,16 @@ fn query_outer_doc_comments(item_node_id: &str) -> Vec<String> {
     let mut docs = Vec::new();
 
     Spi::connect(|client| {
-        let query = format!(
-            "SELECT n.content FROM kerai.nodes n \
+        let query = "SELECT n.content FROM kerai.nodes n \
              JOIN kerai.edges e ON e.source_id = n.id \
-             WHERE e.target_id = '{}'::uuid \
+             WHERE e.target_id = $1::uuid \
              AND e.relation = 'documents' \
+             AND n.deleted_at IS NULL \
              AND n.kind = 'doc_comment' \
              AND COALESCE((n.metadata->>'inner')::boolean, false) = false \
-             ORDER BY n.position ASC",
-            item_node_id.replace('\'', "''")
-        );
+             ORDER BY n.position ASC";
 
-        let result = client.select(&query, None, &[]).unwrap();
+        let result = client.select(query, None, &[item_node_id.into()]).unwrap();
         for row in result {
             let content: String = row.get_by_name::<String, _>("content")
                 .unwrap()
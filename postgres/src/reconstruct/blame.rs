@@ -0,0 +1,130 @@
+/// Line attribution for `kerai.blame` — maps reconstructed source lines back
+/// to the nodes that produced them.
+///
+/// Nodes carry no source spans once stored, so each node is anchored by
+/// finding its content in the reconstructed text, scanning forward in
+/// document order. A line is blamed on the most recently modified node
+/// anchored on it; lines with no anchor (braces, blank lines) inherit the
+/// closest anchor above them.
+
+/// Last modification of one node, in document order.
+#[derive(Debug, Clone)]
+pub struct Attribution {
+    pub node_id: String,
+    pub content: Option<String>,
+    pub lamport_ts: Option<i64>,
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Find `needle` in `source` at or after `from`, without matching inside a
+/// longer identifier (so a parameter `x` does not anchor on `max`).
+fn find_token(source: &str, needle: &str, from: usize) -> Option<usize> {
+    let mut start = from;
+    while let Some(p) = source[start..].find(needle) {
+        let at = start + p;
+        let end = at + needle.len();
+        let before_ok = !needle.starts_with(is_ident)
+            || !source[..at].chars().next_back().is_some_and(is_ident);
+        let after_ok = !needle.ends_with(is_ident)
+            || !source[end..].chars().next().is_some_and(is_ident);
+        if before_ok && after_ok {
+            return Some(at);
+        }
+        start = at + needle.chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+/// Byte offset of each node's content in `source`, as `(offset, index)`.
+fn anchors(source: &str, nodes: &[Attribution]) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    let mut cursor = 0;
+    for (idx, node) in nodes.iter().enumerate() {
+        let needle = node
+            .content
+            .as_deref()
+            .and_then(|c| c.lines().map(str::trim).find(|l| !l.is_empty()));
+        let Some(needle) = needle else {
+            continue;
+        };
+        // Children sit inside their parent, so the cursor stays on the match
+        if let Some(at) = find_token(source, needle, cursor) {
+            found.push((at, idx));
+            cursor = at;
+        }
+    }
+    found
+}
+
+/// Index into `nodes` blamed for each line of `source`. Lines before the
+/// first anchor go to `nodes[0]` (the root being blamed).
+pub fn annotate(source: &str, nodes: &[Attribution]) -> Vec<usize> {
+    let anchors = anchors(source, nodes);
+    let mut blamed = Vec::new();
+    let mut inherited = 0;
+    let mut next = 0;
+    let mut line_start = 0;
+
+    for line in source.split_inclusive('\n') {
+        let line_end = line_start + line.len();
+        let mut best: Option<usize> = None;
+        while next < anchors.len() && anchors[next].0 < line_end {
+            let idx = anchors[next].1;
+            // Newest modification wins; on a tie the deeper (later) node does
+            if best.is_none_or(|b| nodes[idx].lamport_ts >= nodes[b].lamport_ts) {
+                best = Some(idx);
+            }
+            inherited = idx;
+            next += 1;
+        }
+        blamed.push(best.unwrap_or(inherited));
+        line_start = line_end;
+    }
+    blamed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, content: Option<&str>, ts: Option<i64>) -> Attribution {
+        Attribution {
+            node_id: id.into(),
+            content: content.map(String::from),
+            lamport_ts: ts,
+        }
+    }
+
+    #[test]
+    fn token_match_respects_identifier_boundaries() {
+        assert_eq!(find_token("let max = x;", "x", 0), Some(10));
+        assert_eq!(find_token("a.b", ".", 0), Some(1));
+        assert_eq!(find_token("maximum", "max", 0), None);
+    }
+
+    #[test]
+    fn newest_node_on_a_line_wins() {
+        let source = "fn add(a: i32) -> i32 {\n    a + 1\n}\n";
+        let nodes = vec![
+            node("file", Some("lib.rs"), None),
+            node("fn", Some("add"), Some(3)),
+            node("param", Some("a"), Some(9)),
+            node("body", Some("a + 1"), None),
+        ];
+        let blamed = annotate(source, &nodes);
+        let ids: Vec<&str> = blamed.iter().map(|&i| nodes[i].node_id.as_str()).collect();
+        // Line 1: `add` (ts 3) vs `a` (ts 9); line 2: unversioned body;
+        // line 3: the closing brace inherits the last anchor above it
+        assert_eq!(ids, vec!["param", "body", "body"]);
+    }
+
+    #[test]
+    fn lines_before_any_anchor_go_to_the_root() {
+        let source = "// header\nfn main() {}\n";
+        let nodes = vec![node("file", None, None), node("fn", Some("main"), Some(1))];
+        assert_eq!(annotate(source, &nodes), vec![0, 1]);
+    }
+}
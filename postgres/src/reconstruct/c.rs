@@ -7,15 +7,16 @@ use crate::sql::sql_escape;
 
 /// Reconstruct a C source file from its stored AST nodes.
 ///
-/// Takes the UUID of a file-kind node and returns C source text.
+/// Takes the UUID of a file-kind node (language `c` or `cpp`) and returns
+/// its source text.
 #[pg_extern]
 fn reconstruct_c_file(file_node_id: pgrx::Uuid) -> String {
     let id_str = file_node_id.to_string();
 
-    // Validate that the node exists and is a C file node
+    // Validate that the node exists and is a C/C++ file node
     let (kind, language) = Spi::connect(|client| {
         let query = format!(
-            "SELECT kind, language FROM kerai.nodes WHERE id = '{}'::uuid",
+            "SELECT kind, language FROM kerai.nodes WHERE id = '{}'::uuid AND deleted_at IS NULL",
             sql_escape(&id_str)
         );
         let result = client.select(&query, None, &[]).unwrap();
@@ -42,9 +43,9 @@ fn reconstruct_c_file(file_node_id: pgrx::Uuid) -> String {
         );
     }
 
-    if language != "c" {
+    if language != "c" && language != "cpp" {
         pgrx::error!(
-            "Node {} has language '{}', expected 'c'",
+            "Node {} has language '{}', expected 'c' or 'cpp'",
             id_str,
             language
         );
@@ -117,7 +118,7 @@ fn query_child_items(file_node_id: &str) -> Vec<ChildItem> {
     Spi::connect(|client| {
         let query = format!(
             "SELECT kind, content, metadata FROM kerai.nodes \
-             WHERE parent_id = '{}'::uuid \
+             WHERE parent_id = '{}'::uuid AND deleted_at IS NULL \
              ORDER BY position ASC, id ASC",
             sql_escape(file_node_id)
         );
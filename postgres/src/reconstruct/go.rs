@@ -15,7 +15,7 @@ fn reconstruct_go_file(file_node_id: pgrx::Uuid) -> String {
     // Validate that the node exists and is a Go file node
     let (kind, language) = Spi::connect(|client| {
         let query = format!(
-            "SELECT kind, language FROM kerai.nodes WHERE id = '{}'::uuid",
+            "SELECT kind, language FROM kerai.nodes WHERE id = '{}'::uuid AND deleted_at IS NULL",
             sql_escape(&id_str)
         );
         let result = client.select(&query, None, &[]).unwrap();
@@ -117,7 +117,7 @@ fn query_child_items(file_node_id: &str) -> Vec<ChildItem> {
     Spi::connect(|client| {
         let query = format!(
             "SELECT kind, content, metadata FROM kerai.nodes \
-             WHERE parent_id = '{}'::uuid \
+             WHERE parent_id = '{}'::uuid AND deleted_at IS NULL \
              ORDER BY position ASC, id ASC",
             sql_escape(file_node_id)
         );
@@ -0,0 +1,106 @@
+/// Single-item reconstruction — one fn, struct, impl or method by node id.
+///
+/// An item's stored `source` carries its attributes and doc comments but
+/// not where it sits. To indent it as it reads in its file, the source is
+/// wrapped in a stand-in for each enclosing impl, trait or inline mod,
+/// formatted, and the stand-in lines are dropped again.
+
+/// Stand-in opening line for an enclosing node of `kind`; `None` for kinds
+/// that do not indent their children.
+fn opener(kind: &str) -> Option<&'static str> {
+    match kind {
+        "impl" => Some("impl __Kerai {"),
+        "trait" => Some("trait __Kerai {"),
+        "module" => Some("mod __kerai {"),
+        _ => None,
+    }
+}
+
+/// Format an item's `source` as it appears inside `enclosing`, the kinds
+/// of the nodes between it and its file, outermost first. Falls back to
+/// the raw source, indented, when it does not parse.
+pub fn format_item(source: &str, enclosing: &[String]) -> String {
+    let openers: Vec<&str> = enclosing.iter().filter_map(|k| opener(k)).collect();
+    let depth = openers.len();
+
+    let mut wrapped = openers.join("\n");
+    wrapped.push('\n');
+    wrapped.push_str(source);
+    wrapped.push_str(&"\n}".repeat(depth));
+
+    let formatted = match syn::parse_file(&wrapped) {
+        Ok(parsed) => prettyplease::unparse(&parsed),
+        Err(_) => return indent(source, depth),
+    };
+    let lines: Vec<&str> = formatted.lines().collect();
+    if lines.len() < 2 * depth {
+        return indent(source, depth);
+    }
+    let mut out = lines[depth..lines.len() - depth].join("\n");
+    out.push('\n');
+    out
+}
+
+fn indent(source: &str, depth: usize) -> String {
+    let pad = "    ".repeat(depth);
+    let mut out: String = source
+        .lines()
+        .map(|l| {
+            if l.is_empty() {
+                String::new()
+            } else {
+                format!("{pad}{l}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(k: &[&str]) -> Vec<String> {
+        k.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn top_level_item_keeps_docs_and_attributes() {
+        let out = format_item(
+            "# [doc = \" Adds one.\"] # [inline] pub fn inc (x : u32) -> u32 { x + 1 }",
+            &[],
+        );
+        assert_eq!(
+            out,
+            "/// Adds one.\n#[inline]\npub fn inc(x: u32) -> u32 {\n    x + 1\n}\n"
+        );
+    }
+
+    #[test]
+    fn method_is_indented_for_its_impl() {
+        let out = format_item(
+            "# [doc = \" The length.\"] pub fn len (& self) -> usize { self . n }",
+            &kinds(&["impl"]),
+        );
+        assert_eq!(
+            out,
+            "    /// The length.\n    pub fn len(&self) -> usize {\n        self.n\n    }\n"
+        );
+    }
+
+    #[test]
+    fn trait_method_without_body_and_nested_mods() {
+        let out = format_item("fn name (& self) -> String ;", &kinds(&["module", "trait"]));
+        assert_eq!(out, "        fn name(&self) -> String;\n");
+        // Blocks and other non-indenting ancestors are skipped.
+        let out = format_item("struct S ;", &kinds(&["module", "block"]));
+        assert_eq!(out, "    struct S;\n");
+    }
+
+    #[test]
+    fn unparsable_source_is_indented_raw() {
+        assert_eq!(format_item("fn (", &kinds(&["impl"])), "    fn (\n");
+    }
+}
@@ -0,0 +1,370 @@
+/// Reconstruct LaTeX source from stored nodes.
+///
+/// The LaTeX walker numbers nodes in document order and keeps the source
+/// between the elements it recognizes as `latex_text` nodes, so a file is
+/// rebuilt by writing its nodes out in position order, with each
+/// environment's `\begin` before its body and `\end` after it. A node is
+/// written as its recorded `source` unless its content was edited since —
+/// a section retitled, a citation's keys changed, an environment renamed —
+/// in which case it is written afresh from its content and metadata.
+use std::collections::HashMap;
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::parser::latex::kinds;
+
+/// Node of the file's subtree.
+struct TexNode {
+    id: String,
+    parent_id: Option<String>,
+    kind: String,
+    content: Option<String>,
+    metadata: Value,
+}
+
+/// Every node below `$1`, in position order.
+const SUBTREE_SQL: &str = "
+    WITH RECURSIVE sub AS (
+        SELECT id, parent_id, kind, content, metadata, position
+        FROM kerai.nodes WHERE parent_id = $1::uuid AND deleted_at IS NULL
+        UNION ALL
+        SELECT n.id, n.parent_id, n.kind, n.content, n.metadata, n.position
+        FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+        WHERE n.deleted_at IS NULL
+    )
+    SELECT id::text, parent_id::text, kind, content, metadata
+    FROM sub ORDER BY position";
+
+/// Reconstruct a LaTeX source file from its stored nodes.
+///
+/// Takes the UUID of a file-kind node parsed as LaTeX and returns its source.
+#[pg_extern]
+fn reconstruct_latex(file_node_id: pgrx::Uuid) -> String {
+    let id_str = file_node_id.to_string();
+    if let Some(changeset) = crate::changesets::active() {
+        return super::read_text_through(
+            &changeset,
+            "SELECT to_jsonb(kerai.reconstruct_latex(($1->>'id')::uuid))",
+            json!({"id": id_str}),
+        );
+    }
+
+    // Validate that the node exists and is a LaTeX file node
+    let node = Spi::get_one_with_args::<pgrx::JsonB>(
+        "SELECT jsonb_build_object('kind', kind, 'language', language)
+         FROM kerai.nodes WHERE id = $1::uuid AND deleted_at IS NULL",
+        &[id_str.as_str().into()],
+    )
+    .expect("Failed to query node")
+    .unwrap_or_else(|| pgrx::error!("Node not found: {}", id_str))
+    .0;
+
+    if node["kind"] != "file" || node["language"] != "latex" {
+        pgrx::error!(
+            "Node {} is {} '{}', expected a latex file",
+            id_str,
+            node["language"].as_str().unwrap_or("untyped"),
+            node["kind"].as_str().unwrap_or_default()
+        );
+    }
+
+    render(&load_nodes(&id_str))
+}
+
+/// Load the subtree below a file node, in position order.
+fn load_nodes(file_node_id: &str) -> Vec<TexNode> {
+    let mut nodes = Vec::new();
+
+    Spi::connect(|client| {
+        let result = client
+            .select(SUBTREE_SQL, None, &[file_node_id.into()])
+            .unwrap();
+        for row in result {
+            nodes.push(TexNode {
+                id: row
+                    .get_by_name::<String, _>("id")
+                    .unwrap()
+                    .unwrap_or_default(),
+                parent_id: row.get_by_name::<String, _>("parent_id").unwrap(),
+                kind: row
+                    .get_by_name::<String, _>("kind")
+                    .unwrap()
+                    .unwrap_or_default(),
+                content: row.get_by_name::<String, _>("content").unwrap(),
+                metadata: row
+                    .get_by_name::<pgrx::JsonB, _>("metadata")
+                    .unwrap()
+                    .map_or(json!({}), |j| j.0),
+            });
+        }
+    });
+
+    nodes
+}
+
+fn is_environment(kind: &str) -> bool {
+    matches!(
+        kind,
+        kinds::LATEX_ENVIRONMENT
+            | kinds::LATEX_MATH_ENV
+            | kinds::LATEX_FIGURE
+            | kinds::LATEX_TABLE
+            | kinds::LATEX_THEOREM
+            | kinds::LATEX_DEFINITION
+            | kinds::LATEX_PROOF
+    )
+}
+
+/// Write out `nodes`, given in position order, closing each environment
+/// before the first node outside it.
+fn render(nodes: &[TexNode]) -> String {
+    let parents: HashMap<&str, Option<&str>> = nodes
+        .iter()
+        .map(|n| (n.id.as_str(), n.parent_id.as_deref()))
+        .collect();
+    let within = |node: &str, env: &str| {
+        let mut at = parents.get(node).copied().flatten();
+        while let Some(parent) = at {
+            if parent == env {
+                return true;
+            }
+            at = parents.get(parent).copied().flatten();
+        }
+        false
+    };
+
+    let mut out = String::new();
+    let mut open: Vec<&TexNode> = Vec::new();
+    for node in nodes {
+        while let Some(env) = open.last() {
+            if within(&node.id, &env.id) {
+                break;
+            }
+            out.push_str(&delimiter(env, "end"));
+            open.pop();
+        }
+        if is_environment(&node.kind) {
+            out.push_str(&delimiter(node, "begin"));
+            open.push(node);
+        } else {
+            out.push_str(&element(node));
+        }
+    }
+    while let Some(env) = open.pop() {
+        out.push_str(&delimiter(env, "end"));
+    }
+    out
+}
+
+fn meta_str<'a>(node: &'a TexNode, key: &str) -> Option<&'a str> {
+    node.metadata.get(key).and_then(|v| v.as_str())
+}
+
+/// An environment's `\begin` (with its optional argument) or `\end`.
+fn delimiter(env: &TexNode, which: &str) -> String {
+    let name = env.content.as_deref().unwrap_or_default();
+    if meta_str(env, "env_name") == Some(name) {
+        if let Some(recorded) = meta_str(env, which) {
+            return recorded.to_string();
+        }
+    }
+    match meta_str(env, "args") {
+        Some(args) if which == "begin" => format!("\\begin{{{}}}[{}]", name, args),
+        _ => format!("\\{}{{{}}}", which, name),
+    }
+}
+
+/// The content the walker derives from a node's metadata; a node whose
+/// content still matches it is unedited.
+fn parsed_content(node: &TexNode) -> Option<String> {
+    let key = match node.kind.as_str() {
+        kinds::LATEX_PART
+        | kinds::LATEX_CHAPTER
+        | kinds::LATEX_SECTION
+        | kinds::LATEX_SUBSECTION
+        | kinds::LATEX_SUBSUBSECTION
+        | kinds::LATEX_PARAGRAPH => "title",
+        kinds::LATEX_CITATION => {
+            let keys = node.metadata.get("keys")?.as_array()?;
+            let keys: Vec<&str> = keys.iter().filter_map(|k| k.as_str()).collect();
+            return Some(keys.join(", "));
+        }
+        kinds::LATEX_LABEL | kinds::LATEX_REF => "key",
+        kinds::LATEX_CAPTION => "text",
+        kinds::LATEX_FOOTNOTE => "arg",
+        kinds::LATEX_USEPACKAGE => "package",
+        kinds::LATEX_DOCUMENTCLASS => "class",
+        kinds::LATEX_INPUT | kinds::LATEX_INCLUDE => "path",
+        kinds::LATEX_COMMAND => "command",
+        _ => return None,
+    };
+    meta_str(node, key).map(str::to_string)
+}
+
+/// A non-environment node: text and math as stored, others as recorded
+/// or, once edited, written from their content.
+fn element(node: &TexNode) -> String {
+    let content = node.content.as_deref().unwrap_or_default();
+    match node.kind.as_str() {
+        kinds::LATEX_TEXT | kinds::LATEX_INLINE_MATH | kinds::LATEX_DISPLAY_MATH => {
+            return content.to_string();
+        }
+        _ => {}
+    }
+
+    if let Some(recorded) = meta_str(node, "source") {
+        if parsed_content(node).unwrap_or_default() == content {
+            return recorded.to_string();
+        }
+    }
+
+    // The command as parsed, else the one the kind stands for
+    let command = meta_str(node, "command")
+        .map(str::to_string)
+        .unwrap_or_else(|| match node.kind.as_str() {
+            kinds::LATEX_CITATION => "\\cite".to_string(),
+            kinds::LATEX_REF => "\\ref".to_string(),
+            kinds::LATEX_FOOTNOTE => "\\footnote".to_string(),
+            kind => format!("\\{}", kind.trim_start_matches("latex_")),
+        });
+    let optional = |key: &str| match node.metadata.get(key) {
+        Some(Value::String(s)) => format!("[{}]", s),
+        Some(Value::Array(items)) => {
+            let items: Vec<&str> = items.iter().filter_map(|v| v.as_str()).collect();
+            format!("[{}]", items.join(","))
+        }
+        _ => String::new(),
+    };
+
+    match node.kind.as_str() {
+        kinds::LATEX_PART
+        | kinds::LATEX_CHAPTER
+        | kinds::LATEX_SECTION
+        | kinds::LATEX_SUBSECTION
+        | kinds::LATEX_SUBSUBSECTION
+        | kinds::LATEX_PARAGRAPH => {
+            format!("{}{}{{{}}}", command, optional("short_title"), content)
+        }
+        kinds::LATEX_CITATION => {
+            let keys: Vec<&str> = content.split(',').map(str::trim).collect();
+            format!("{}{}{{{}}}", command, optional("note"), keys.join(","))
+        }
+        kinds::LATEX_CAPTION => format!("{}{}{{{}}}", command, optional("short_caption"), content),
+        kinds::LATEX_USEPACKAGE | kinds::LATEX_DOCUMENTCLASS => {
+            format!("{}{}{{{}}}", command, optional("options"), content)
+        }
+        kinds::LATEX_LABEL
+        | kinds::LATEX_REF
+        | kinds::LATEX_FOOTNOTE
+        | kinds::LATEX_INPUT
+        | kinds::LATEX_INCLUDE => format!("{}{{{}}}", command, content),
+        // A renamed command keeps its arguments
+        kinds::LATEX_COMMAND => {
+            let recorded = meta_str(node, "source").unwrap_or_default();
+            format!(
+                "{}{}",
+                content,
+                recorded.strip_prefix(command.as_str()).unwrap_or_default()
+            )
+        }
+        _ => meta_str(node, "source").unwrap_or(content).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, parent: &str, kind: &str, content: &str, metadata: Value) -> TexNode {
+        TexNode {
+            id: id.to_string(),
+            parent_id: Some(parent.to_string()),
+            kind: kind.to_string(),
+            content: Some(content.to_string()),
+            metadata,
+        }
+    }
+
+    fn document() -> Vec<TexNode> {
+        vec![
+            node(
+                "1",
+                "f",
+                kinds::LATEX_DOCUMENTCLASS,
+                "article",
+                json!({"class": "article", "options": ["11pt"], "source": "\\documentclass[11pt]{article}"}),
+            ),
+            node(
+                "2",
+                "f",
+                kinds::LATEX_TEXT,
+                "\n\\begin{document}\n",
+                json!({}),
+            ),
+            node(
+                "3",
+                "f",
+                kinds::LATEX_SECTION,
+                "Intro",
+                json!({"command": "\\section*", "title": "Intro", "source": "\\section*{Intro}"}),
+            ),
+            node("4", "3", kinds::LATEX_TEXT, "\nAs shown ", json!({})),
+            node(
+                "5",
+                "3",
+                kinds::LATEX_CITATION,
+                "knuth, lamport",
+                json!({"command": "\\citep", "keys": ["knuth", "lamport"], "source": "\\citep{knuth,lamport}"}),
+            ),
+            node("6", "3", kinds::LATEX_TEXT, ", ", json!({})),
+            node("7", "3", kinds::LATEX_INLINE_MATH, "$x^2$", json!({})),
+            node("8", "3", kinds::LATEX_TEXT, ".\n", json!({})),
+            node(
+                "9",
+                "3",
+                kinds::LATEX_MATH_ENV,
+                "equation",
+                json!({"env_name": "equation", "begin": "\\begin{equation}", "end": "\\end{equation}"}),
+            ),
+            node("10", "9", kinds::LATEX_TEXT, "\n  E = mc^2 ", json!({})),
+            node(
+                "11",
+                "9",
+                kinds::LATEX_LABEL,
+                "eq:e",
+                json!({"key": "eq:e", "source": "\\label{eq:e}"}),
+            ),
+            node("12", "9", kinds::LATEX_TEXT, "\n", json!({})),
+            node("13", "3", kinds::LATEX_TEXT, "\n", json!({})),
+            node("14", "f", kinds::LATEX_TEXT, "\\end{document}\n", json!({})),
+        ]
+    }
+
+    const SOURCE: &str = "\\documentclass[11pt]{article}\n\\begin{document}\n\\section*{Intro}\nAs shown \\citep{knuth,lamport}, $x^2$.\n\\begin{equation}\n  E = mc^2 \\label{eq:e}\n\\end{equation}\n\\end{document}\n";
+
+    #[test]
+    fn unedited_nodes_render_as_parsed() {
+        assert_eq!(render(&document()), SOURCE);
+    }
+
+    #[test]
+    fn edited_nodes_render_from_content() {
+        let mut nodes = document();
+        nodes[2].content = Some("Introduction".to_string());
+        nodes[4].content = Some("knuth".to_string());
+        nodes[8].content = Some("align".to_string());
+        nodes[10].content = Some("eq:energy".to_string());
+        let rendered = render(&nodes);
+        assert!(
+            rendered.contains("\\section*{Introduction}\n"),
+            "{rendered}"
+        );
+        assert!(rendered.contains("\\citep{knuth}, "), "{rendered}");
+        assert!(
+            rendered.contains("\\begin{align}\n  E = mc^2 \\label{eq:energy}\n\\end{align}"),
+            "{rendered}"
+        );
+        assert!(rendered.starts_with("\\documentclass[11pt]{article}"));
+    }
+}
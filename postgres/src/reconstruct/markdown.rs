@@ -1,218 +1,403 @@
-/// Reconstruct CommonMark from stored document nodes via SPI queries.
+/// Reconstruct CommonMark from stored document nodes.
+///
+/// The document's subtree is loaded in one query and rendered from what the
+/// markdown walker stored: the inline source of paragraphs, headings, tight
+/// list items and table cells, and block markup in metadata (list markers,
+/// code fences, setext underlines, thematic breaks). A parsed document
+/// comes back byte for byte, short of its trailing newline, as long as its
+/// blocks are separated by single blank lines, its tables written as
+/// `| a | b |` rows and its quotes without lazy continuation lines — the
+/// layouts the tree doesn't keep.
+use std::collections::HashMap;
+
 use pgrx::prelude::*;
+use serde_json::{json, Value};
 
 use crate::parser::markdown::kinds;
 
-/// Child node from the database.
+/// Node of the document's subtree.
 struct MdNode {
     id: String,
     kind: String,
     content: Option<String>,
-    metadata: serde_json::Value,
+    metadata: Value,
 }
 
+/// Children of each node by parent id, in position order.
+type Tree = HashMap<String, Vec<MdNode>>;
+
+/// Every node below `$1`, in position order.
+const SUBTREE_SQL: &str = "
+    WITH RECURSIVE sub AS (
+        SELECT id, parent_id, kind, content, metadata, position
+        FROM kerai.nodes WHERE parent_id = $1::uuid AND deleted_at IS NULL
+        UNION ALL
+        SELECT n.id, n.parent_id, n.kind, n.content, n.metadata, n.position
+        FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+        WHERE n.deleted_at IS NULL
+    )
+    SELECT id::text, parent_id::text, kind, content, metadata
+    FROM sub ORDER BY position";
+
 /// Reconstruct a markdown document from its stored node tree.
 /// Takes the UUID of a document-kind node and returns CommonMark text.
 #[pg_extern]
 fn reconstruct_markdown(document_node_id: pgrx::Uuid) -> String {
     let id_str = document_node_id.to_string();
+    if let Some(changeset) = crate::changesets::active() {
+        return super::read_text_through(
+            &changeset,
+            "SELECT to_jsonb(kerai.reconstruct_markdown(($1->>'id')::uuid))",
+            json!({"id": id_str}),
+        );
+    }
 
     // Validate that the node exists and is a document node
-    let kind = Spi::get_one::<String>(&format!(
-        "SELECT kind FROM kerai.nodes WHERE id = '{}'::uuid",
-        id_str.replace('\'', "''")
-    ))
+    let kind = Spi::get_one_with_args::<String>(
+        "SELECT kind FROM kerai.nodes WHERE id = $1::uuid AND deleted_at IS NULL",
+        &[id_str.as_str().into()],
+    )
     .expect("Failed to query node")
     .unwrap_or_else(|| pgrx::error!("Node not found: {}", id_str));
 
     if kind != "document" {
-        pgrx::error!(
-            "Node {} is kind '{}', expected 'document'",
-            id_str,
-            kind
-        );
+        pgrx::error!("Node {} is kind '{}', expected 'document'", id_str, kind);
     }
 
-    let mut output = String::new();
-    reconstruct_children(&id_str, &mut output, 0);
-    output.trim_end().to_string()
+    render_document(&load_tree(&id_str), &id_str)
 }
 
-/// Recursively reconstruct children of a node.
-fn reconstruct_children(parent_id: &str, output: &mut String, depth: usize) {
-    let children = query_children(parent_id);
+/// Load the subtree below a document.
+fn load_tree(document_id: &str) -> Tree {
+    let mut tree = Tree::new();
 
-    for child in &children {
-        emit_node(child, output, depth);
-    }
-}
+    Spi::connect(|client| {
+        let result = client
+            .select(SUBTREE_SQL, None, &[document_id.into()])
+            .unwrap();
+        for row in result {
+            let parent: String = row
+                .get_by_name::<String, _>("parent_id")
+                .unwrap()
+                .unwrap_or_default();
+            let id: String = row
+                .get_by_name::<String, _>("id")
+                .unwrap()
+                .unwrap_or_default();
+            let kind: String = row
+                .get_by_name::<String, _>("kind")
+                .unwrap()
+                .unwrap_or_default();
+            let content: Option<String> = row.get_by_name::<String, _>("content").unwrap();
+            let metadata: pgrx::JsonB = row
+                .get_by_name::<pgrx::JsonB, _>("metadata")
+                .unwrap()
+                .unwrap_or_else(|| pgrx::JsonB(serde_json::json!({})));
 
-/// Emit a single node as CommonMark.
-fn emit_node(node: &MdNode, output: &mut String, depth: usize) {
-    match node.kind.as_str() {
-        kinds::HEADING => {
-            let level = node.metadata.get("level")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(1) as usize;
-            let hashes = "#".repeat(level);
-            let text = node.content.as_deref().unwrap_or("");
-            output.push_str(&format!("{} {}\n\n", hashes, text));
-
-            // Recurse into heading's children (sub-sections and content)
-            reconstruct_children(&node.id, output, depth);
+            tree.entry(parent).or_default().push(MdNode {
+                id,
+                kind,
+                content,
+                metadata: metadata.0,
+            });
         }
+    });
+
+    tree
+}
 
-        kinds::PARAGRAPH => {
-            let text = node.content.as_deref().unwrap_or("");
-            if !text.is_empty() {
-                output.push_str(text);
-                output.push_str("\n\n");
+/// Render the blocks below `root` as one document, blank line separated.
+fn render_document(tree: &Tree, root: &str) -> String {
+    blocks(tree, root).join("\n\n").trim_end().to_string()
+}
+
+fn children<'a>(tree: &'a Tree, id: &str) -> &'a [MdNode] {
+    tree.get(id).map_or(&[], Vec::as_slice)
+}
+
+fn meta_str<'a>(node: &'a MdNode, key: &str) -> Option<&'a str> {
+    node.metadata.get(key).and_then(|v| v.as_str())
+}
+
+fn meta_bool(node: &MdNode, key: &str) -> bool {
+    node.metadata
+        .get(key)
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Rendered blocks below `parent`, one string each. A heading's section
+/// follows it as blocks of its own.
+fn blocks(tree: &Tree, parent: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for node in children(tree, parent) {
+        let text = node.content.as_deref().unwrap_or("");
+        match node.kind.as_str() {
+            kinds::HEADING => {
+                out.push(heading(node, text));
+                out.extend(blocks(tree, &node.id));
             }
-        }
 
-        kinds::BLOCKQUOTE => {
-            // Collect blockquote content from children
-            let children = query_children(&node.id);
-            for child in &children {
-                let text = child.content.as_deref().unwrap_or("");
-                for line in text.lines() {
-                    output.push_str(&format!("> {}\n", line));
+            kinds::PARAGRAPH => {
+                if !text.is_empty() {
+                    out.push(text.to_string());
                 }
             }
-            output.push('\n');
-        }
-
-        kinds::CODE_BLOCK => {
-            let lang = node.metadata.get("language")
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-            let content = node.content.as_deref().unwrap_or("");
-            output.push_str(&format!("```{}\n{}\n```\n\n", lang, content.trim_end()));
-        }
 
-        kinds::LIST => {
-            let ordered = node.metadata.get("ordered")
-                .and_then(|v| v.as_bool())
-                .unwrap_or(false);
-            let start = node.metadata.get("start")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(1);
-            let items = query_children(&node.id);
-            for (i, item) in items.iter().enumerate() {
-                let prefix = if ordered {
-                    format!("{}. ", start as usize + i)
-                } else {
-                    "- ".to_string()
-                };
-                let text = item.content.as_deref().unwrap_or("");
-                output.push_str(&format!("{}{}\n", prefix, text));
+            kinds::BLOCKQUOTE => {
+                let body = blocks(tree, &node.id).join("\n\n");
+                let lines: Vec<String> = body
+                    .lines()
+                    .map(|line| {
+                        if line.is_empty() {
+                            ">".to_string()
+                        } else {
+                            format!("> {}", line)
+                        }
+                    })
+                    .collect();
+                out.push(lines.join("\n"));
             }
-            output.push('\n');
-        }
 
-        kinds::LIST_ITEM => {
-            // Handled by parent LIST
-            let text = node.content.as_deref().unwrap_or("");
-            output.push_str(&format!("- {}\n", text));
-        }
+            kinds::CODE_BLOCK => out.push(code_block(node, text)),
 
-        kinds::THEMATIC_BREAK => {
-            output.push_str("---\n\n");
-        }
+            kinds::LIST => out.push(list(tree, node)),
 
-        kinds::TABLE => {
-            let children = query_children(&node.id);
-            for (i, child) in children.iter().enumerate() {
-                emit_table_row(child, output);
-                if i == 0 {
-                    // After header row, emit separator
-                    let cells = query_children(&child.id);
-                    let sep: Vec<String> = cells.iter().map(|_| "---".to_string()).collect();
-                    output.push_str(&format!("| {} |\n", sep.join(" | ")));
-                }
+            kinds::LIST_ITEM => out.push(list_item(tree, node, "-", false)),
+
+            kinds::THEMATIC_BREAK => {
+                out.push(meta_str(node, "markup").unwrap_or("---").to_string());
             }
-            output.push('\n');
-        }
 
-        kinds::LINK => {
-            let text = node.content.as_deref().unwrap_or("");
-            let url = node.metadata.get("url")
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-            output.push_str(&format!("[{}]({})", text, url));
-        }
+            kinds::TABLE => out.push(table(tree, node)),
 
-        kinds::IMAGE => {
-            let alt = node.content.as_deref().unwrap_or("");
-            let url = node.metadata.get("url")
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-            output.push_str(&format!("![{}]({})\n\n", alt, url));
-        }
+            kinds::HTML_BLOCK => out.push(text.trim_end_matches('\n').to_string()),
 
-        kinds::HTML_BLOCK => {
-            let content = node.content.as_deref().unwrap_or("");
-            output.push_str(content);
-            output.push('\n');
-        }
+            // Already part of their block's inline source
+            kinds::EMPHASIS | kinds::STRONG | kinds::STRIKETHROUGH => {}
+            kinds::LINK | kinds::IMAGE => {}
 
-        // Inline formatting nodes — emit content directly
-        kinds::EMPHASIS | kinds::STRONG | kinds::STRIKETHROUGH
-        | kinds::TEXT | kinds::INLINE_CODE => {
-            if let Some(text) = &node.content {
-                output.push_str(text);
+            kinds::FOOTNOTE => {
+                let name = meta_str(node, "name").unwrap_or("");
+                let body = blocks(tree, &node.id).join("\n\n");
+                out.push(indent(&body, &format!("[^{}]: ", name), 4));
             }
-        }
 
-        _ => {
-            // Unknown kind — emit content if present, then recurse
-            if let Some(text) = &node.content {
-                output.push_str(text);
-                output.push_str("\n\n");
+            _ => {
+                // Unknown kind — emit content if present, then recurse
+                if !text.is_empty() {
+                    out.push(text.to_string());
+                }
+                out.extend(blocks(tree, &node.id));
             }
-            reconstruct_children(&node.id, output, depth + 1);
         }
     }
+    out
+}
+
+/// An ATX heading, or a setext one with its underline.
+fn heading(node: &MdNode, text: &str) -> String {
+    let level = node
+        .metadata
+        .get("level")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(1) as usize;
+    if meta_bool(node, "setext") {
+        let underline =
+            meta_str(node, "underline").unwrap_or(if level == 1 { "===" } else { "---" });
+        return format!("{}\n{}", text, underline);
+    }
+    let mut line = format!("{} {}", "#".repeat(level), text);
+    if let Some(id) = meta_str(node, "id") {
+        line.push_str(&format!(" {{#{}}}", id));
+    }
+    line.trim_end().to_string()
+}
+
+/// A fenced code block with its fence and info string, or an indented one.
+fn code_block(node: &MdNode, text: &str) -> String {
+    let code = text.strip_suffix('\n').unwrap_or(text);
+    if meta_bool(node, "indented") {
+        let lines: Vec<String> = code
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    String::new()
+                } else {
+                    format!("    {}", line)
+                }
+            })
+            .collect();
+        return lines.join("\n");
+    }
+    let fence = meta_str(node, "fence").unwrap_or("```");
+    let info = meta_str(node, "language").unwrap_or("");
+    if text.is_empty() {
+        format!("{}{}\n{}", fence, info, fence)
+    } else {
+        format!("{}{}\n{}\n{}", fence, info, code, fence)
+    }
 }
 
-/// Emit a table row (head or body).
-fn emit_table_row(node: &MdNode, output: &mut String) {
-    let cells = query_children(&node.id);
-    let cell_texts: Vec<String> = cells.iter()
-        .map(|c| c.content.as_deref().unwrap_or("").to_string())
+/// A list, numbering ordered items from its start. A list is loose, with
+/// blank lines between items, when its items hold paragraphs.
+fn list(tree: &Tree, node: &MdNode) -> String {
+    let ordered = meta_bool(node, "ordered");
+    let start = node
+        .metadata
+        .get("start")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(1);
+    let marker = meta_str(node, "marker").unwrap_or(if ordered { "." } else { "-" });
+
+    let items = children(tree, &node.id);
+    let loose = items.iter().any(|item| {
+        children(tree, &item.id)
+            .iter()
+            .any(|c| c.kind == kinds::PARAGRAPH)
+    });
+
+    let rendered: Vec<String> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let marker = if ordered {
+                format!("{}{}", start as usize + i, marker)
+            } else {
+                marker.to_string()
+            };
+            list_item(tree, item, &marker, loose)
+        })
         .collect();
-    output.push_str(&format!("| {} |\n", cell_texts.join(" | ")));
+    rendered.join(if loose { "\n\n" } else { "\n" })
 }
 
-/// Query direct children of a node, ordered by position.
-fn query_children(parent_id: &str) -> Vec<MdNode> {
-    let mut children = Vec::new();
+/// One item: its marker, then its inline text and blocks indented under it.
+fn list_item(tree: &Tree, item: &MdNode, marker: &str, loose: bool) -> String {
+    let mut parts: Vec<String> = item
+        .content
+        .iter()
+        .filter(|t| !t.is_empty())
+        .cloned()
+        .collect();
+    parts.extend(blocks(tree, &item.id));
+    let body = parts.join(if loose { "\n\n" } else { "\n" });
+    if body.is_empty() {
+        return marker.to_string();
+    }
+    indent(&body, &format!("{} ", marker), marker.len() + 1)
+}
 
-    Spi::connect(|client| {
-        let query = format!(
-            "SELECT id::text, kind, content, metadata \
-             FROM kerai.nodes \
-             WHERE parent_id = '{}'::uuid \
-             ORDER BY position ASC",
-            parent_id.replace('\'', "''")
-        );
+/// `body` with `first` before its first line and the rest indented by
+/// `width` spaces, leaving blank lines empty.
+fn indent(body: &str, first: &str, width: usize) -> String {
+    let lines: Vec<String> = body
+        .lines()
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => format!("{}{}", first, line),
+            _ if line.is_empty() => String::new(),
+            _ => format!("{}{}", " ".repeat(width), line),
+        })
+        .collect();
+    lines.join("\n")
+}
 
-        let result = client.select(&query, None, &[]).unwrap();
-        for row in result {
-            let id: String = row.get_by_name::<String, _>("id")
-                .unwrap()
-                .unwrap_or_default();
-            let kind: String = row.get_by_name::<String, _>("kind")
-                .unwrap()
+/// A pipe table: head row, delimiter row, body rows.
+fn table(tree: &Tree, node: &MdNode) -> String {
+    let row = |row: &MdNode| {
+        let cells: Vec<&str> = children(tree, &row.id)
+            .iter()
+            .map(|c| c.content.as_deref().unwrap_or(""))
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let delimiter = meta_str(node, "delimiter")
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            let aligns: Vec<&str> = node
+                .metadata
+                .get("alignments")
+                .and_then(|v| v.as_array())
+                .map(|a| a.iter().filter_map(|v| v.as_str()).collect())
                 .unwrap_or_default();
-            let content: Option<String> = row.get_by_name::<String, _>("content").unwrap();
-            let metadata: pgrx::JsonB = row.get_by_name::<pgrx::JsonB, _>("metadata")
-                .unwrap()
-                .unwrap_or_else(|| pgrx::JsonB(serde_json::json!({})));
+            let cells: Vec<&str> = aligns
+                .iter()
+                .map(|a| match *a {
+                    "left" => ":---",
+                    "center" => ":---:",
+                    "right" => "---:",
+                    _ => "---",
+                })
+                .collect();
+            format!("| {} |", cells.join(" | "))
+        });
 
-            children.push(MdNode { id, kind, content, metadata: metadata.0 });
+    let mut lines = Vec::new();
+    for (i, child) in children(tree, &node.id).iter().enumerate() {
+        lines.push(row(child));
+        if i == 0 {
+            lines.push(delimiter.clone());
         }
-    });
+    }
+    lines.join("\n")
+}
 
-    children
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::markdown::walker::walk_markdown;
+
+    /// Documents that must come back exactly as written.
+    const CORPUS: &[&str] = &[
+        "# Title\n\nA paragraph with *emphasis*, __strong__, `code` and a [link](https://example.com \"Example\").\n",
+        "Setext Title\n============\n\nSub title\n---------\n\nText under it.\n",
+        "## Heading {#anchor}\n\nLine one\ncontinued  \nafter a hard break\\\nand another.\n",
+        "* one\n* two\n  + nested _a_\n  + nested **b**\n* three\n",
+        "3) third\n4) fourth\n\n1. one\n2. two\n",
+        "- loose one\n\n- loose two\n\n  with a second paragraph\n",
+        "- [ ] todo\n- [x] done\n",
+        "~~~~\n~~~\nnot a fence\n~~~\n~~~~\n\n```rust\nfn main() {}\n```\n\n    indented\n\n    code\n",
+        "> quoted *text*\n> still quoted\n>\n> - a list\n> - inside\n",
+        "| Name | Count |\n|:-----|------:|\n| a | 1 |\n| `b\\|c` | 2 |\n",
+        "Above\n\n***\n\n- - -\n\nBelow with \\*escaped\\* stars and ~~strike~~.\n",
+        "<div class=\"note\">\n<p>raw html</p>\n</div>\n\nText with a footnote[^1].\n\n[^1]: The note.\n",
+        "# It's \\ here\n\nA 'quoted' line.\n",
+    ];
+
+    fn round_trip(source: &str) -> String {
+        let (nodes, _) = walk_markdown(source, "doc.md", "instance", "doc");
+        let mut tree = Tree::new();
+        for node in nodes {
+            tree.entry(node.parent_id.clone().unwrap_or_default())
+                .or_default()
+                .push(MdNode {
+                    id: node.id,
+                    kind: node.kind,
+                    content: node.content,
+                    metadata: node.metadata,
+                });
+        }
+        render_document(&tree, "doc")
+    }
+
+    #[test]
+    fn corpus_round_trips() {
+        for source in CORPUS {
+            assert_eq!(round_trip(source), source.trim_end(), "\n{}", source);
+        }
+    }
+
+    #[test]
+    fn fixtures_round_trip() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/markdown");
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|x| x == "md") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                assert_eq!(round_trip(&source), source.trim_end(), "{}", path.display());
+            }
+        }
+    }
 }
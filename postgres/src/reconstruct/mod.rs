@@ -3,14 +3,31 @@ use pgrx::prelude::*;
 use serde_json::json;
 
 mod assembler;
+mod blame;
 mod derive_orderer;
 mod formatter;
 mod go;
 mod c;
 mod import_sorter;
+mod item;
+mod latex;
 mod markdown;
+mod vault;
 
 use assembler::{AssemblyOptions, query_file_flags};
+use blame::Attribution;
+use crate::changesets;
+pub(crate) use vault::strip_generated;
+
+/// Reconstruction `query` (returning the source as a JSONB string) run as
+/// if `changeset` were applied; the reconstruct functions go through it
+/// while `kerai.with_changeset` has one in effect.
+fn read_text_through(changeset: &str, query: &str, args: serde_json::Value) -> String {
+    changesets::read_through(changeset, query, args)
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
 
 /// Parse reconstruction options from a JSONB parameter.
 fn parse_options(options: Option<pgrx::JsonB>) -> AssemblyOptions {
@@ -48,13 +65,21 @@ fn reconstruct_file_with_options(
     options: Option<pgrx::JsonB>,
 ) -> String {
     let id_str = file_node_id.to_string();
+    if let Some(changeset) = changesets::active() {
+        return read_text_through(
+            &changeset,
+            "SELECT to_jsonb(kerai.reconstruct_file_with_options(
+                ($1->>'id')::uuid, NULLIF($1->'options', 'null')))",
+            json!({"id": id_str, "options": options.map(|o| o.0)}),
+        );
+    }
     let opts = parse_options(options);
 
     // Validate that the node exists and is a file node
-    let kind = Spi::get_one::<String>(&format!(
-        "SELECT kind FROM kerai.nodes WHERE id = '{}'::uuid",
-        id_str.replace('\'', "''")
-    ))
+    let kind = Spi::get_one_with_args::<String>(
+        "SELECT kind FROM kerai.nodes WHERE id = $1::uuid AND deleted_at IS NULL",
+        &[id_str.as_str().into()],
+    )
     .expect("Failed to query node")
     .unwrap_or_else(|| pgrx::error!("Node not found: {}", id_str));
 
@@ -80,6 +105,79 @@ fn reconstruct_file_with_options(
     }
 }
 
+/// Reconstruct a single item — a fn, struct, impl, method or inline mod —
+/// by node id, with its attributes and doc comments, indented as it sits
+/// in its file (a method inside an impl, an item inside an inline mod).
+/// Derives are ordered as `reconstruct_file` would for the item's file.
+/// A file node reconstructs the whole file.
+#[pg_extern]
+fn reconstruct_node(node_id: pgrx::Uuid) -> String {
+    let id_str = node_id.to_string();
+    if let Some(changeset) = changesets::active() {
+        return read_text_through(
+            &changeset,
+            "SELECT to_jsonb(kerai.reconstruct_node(($1->>'id')::uuid))",
+            json!({"id": id_str}),
+        );
+    }
+
+    // The node, its file, and the kinds of the nodes in between
+    let node = Spi::get_one_with_args::<pgrx::JsonB>(
+        "WITH RECURSIVE up AS (
+            SELECT id, parent_id, kind, 0 AS depth FROM kerai.nodes
+            WHERE id = $1::uuid AND deleted_at IS NULL
+            UNION ALL
+            SELECT n.id, n.parent_id, n.kind, u.depth + 1
+            FROM kerai.nodes n JOIN up u ON n.id = u.parent_id
+            WHERE u.kind <> 'file'
+        )
+        SELECT jsonb_build_object(
+            'kind', (SELECT kind FROM up WHERE depth = 0),
+            'source', (SELECT metadata->>'source' FROM kerai.nodes WHERE id = $1::uuid),
+            'file_id', (SELECT id FROM up WHERE kind = 'file' ORDER BY depth LIMIT 1),
+            'enclosing', COALESCE(
+                (SELECT jsonb_agg(kind ORDER BY depth DESC) FROM up
+                 WHERE depth > 0 AND kind <> 'file'),
+                '[]'::jsonb))
+        WHERE EXISTS (SELECT 1 FROM up)",
+        &[id_str.as_str().into()],
+    )
+    .expect("Failed to query node")
+    .map(|j| j.0)
+    .unwrap_or_else(|| pgrx::error!("Node not found: {}", id_str));
+
+    let kind = node["kind"].as_str().unwrap_or_default();
+    if kind == "file" {
+        return reconstruct_file(node_id);
+    }
+    let Some(source) = node["source"].as_str() else {
+        pgrx::error!(
+            "Node {} is kind '{}', which has no item source to reconstruct",
+            id_str,
+            kind
+        );
+    };
+    let enclosing: Vec<String> = node["enclosing"]
+        .as_array()
+        .map(|a| {
+            a.iter()
+                .filter_map(|k| k.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let formatted = item::format_item(source, &enclosing);
+    let skip = node["file_id"].as_str().is_some_and(|file_id| {
+        let flags = query_file_flags(file_id);
+        flags.skip_order_derives || flags.skip_all
+    });
+    if skip {
+        formatted
+    } else {
+        derive_orderer::order_derives(&formatted)
+    }
+}
+
 /// Reconstruct all files in a crate, returning a JSON map of {filename: source}.
 #[pg_extern]
 fn reconstruct_crate(crate_name: &str) -> pgrx::JsonB {
@@ -95,11 +193,11 @@ fn reconstruct_crate_with_options(
     let opts = parse_options(options);
 
     // Find the crate node
-    let crate_node_id = Spi::get_one::<String>(&format!(
+    let crate_node_id = Spi::get_one_with_args::<String>(
         "SELECT id::text FROM kerai.nodes \
-         WHERE kind = 'crate' AND content = '{}'",
-        crate_name.replace('\'', "''")
-    ))
+         WHERE kind = 'crate' AND content = $1 AND deleted_at IS NULL",
+        &[crate_name.into()],
+    )
     .expect("Failed to query crate node")
     .unwrap_or_else(|| pgrx::error!("Crate not found: {}", crate_name));
 
@@ -107,14 +205,11 @@ fn reconstruct_crate_with_options(
     let mut files = serde_json::Map::new();
 
     Spi::connect(|client| {
-        let query = format!(
-            "SELECT id::text, content FROM kerai.nodes \
-             WHERE parent_id = '{}'::uuid AND kind = 'file' \
-             ORDER BY position ASC",
-            crate_node_id.replace('\'', "''")
-        );
+        let query = "SELECT id::text, content FROM kerai.nodes \
+             WHERE parent_id = $1::uuid AND kind = 'file' AND deleted_at IS NULL \
+             ORDER BY position ASC";
 
-        let result = client.select(&query, None, &[]).unwrap();
+        let result = client.select(query, None, &[crate_node_id.as_str().into()]).unwrap();
         for row in result {
             let file_id: String = row.get_by_name::<String, _>("id").unwrap().unwrap_or_default();
             let filename: String = row.get_by_name::<String, _>("content").unwrap().unwrap_or_default();
@@ -134,3 +229,91 @@ fn reconstruct_crate_with_options(
 
     pgrx::JsonB(serde_json::Value::Object(files))
 }
+
+/// Per-node authorship for a node and its subtree.
+///
+/// Each node is attributed to the latest `kerai.versions` row for it (by
+/// Lamport timestamp): the authoring instance, `lamport_ts`, operation and
+/// `version_id`, which `kerai.revert_version` takes.
+/// Nodes never touched by an op since parsing get `operation: "parse"`, the
+/// instance that parsed them and a null `lamport_ts`.
+///
+/// Returns `{node_id, kind, name, nodes: [...], lines: [{line, text,
+/// node_id, author, lamport_ts}]}`. For a file node `lines` annotates the
+/// reconstructed source; for anything else, the node's own content.
+#[pg_extern]
+fn blame(node_id: pgrx::Uuid) -> pgrx::JsonB {
+    let id_str = node_id.to_string();
+    let nodes = Spi::get_one_with_args::<pgrx::JsonB>(
+        "WITH RECURSIVE sub AS (
+            SELECT id, ARRAY[position] AS ord FROM kerai.nodes WHERE id = $1::uuid
+            UNION ALL
+            SELECT n.id, s.ord || n.position FROM kerai.nodes n JOIN sub s ON n.parent_id = s.id
+            WHERE n.deleted_at IS NULL
+        ),
+        latest AS (
+            SELECT DISTINCT ON (v.node_id) v.id, v.node_id, v.author, v.timestamp, v.operation
+            FROM kerai.versions v JOIN sub s ON v.node_id = s.id
+            ORDER BY v.node_id, v.timestamp DESC, v.created_at DESC
+        )
+        SELECT jsonb_agg(jsonb_build_object(
+            'node_id', n.id,
+            'kind', n.kind,
+            'content', n.content,
+            'author', COALESCE(vi.name, l.author, ni.name),
+            'fingerprint', COALESCE(l.author, ni.key_fingerprint),
+            'lamport_ts', l.timestamp,
+            'version_id', l.id,
+            'operation', COALESCE(l.operation, 'parse')
+        ) ORDER BY s.ord)
+        FROM sub s
+        JOIN kerai.nodes n ON n.id = s.id
+        LEFT JOIN latest l ON l.node_id = n.id
+        LEFT JOIN kerai.instances vi ON vi.key_fingerprint = l.author
+        LEFT JOIN kerai.instances ni ON ni.id = n.instance_id",
+        &[id_str.as_str().into()],
+    )
+    .expect("Failed to query node history")
+    .map(|j| j.0)
+    .unwrap_or_else(|| pgrx::error!("Node not found: {}", id_str));
+    let nodes = nodes.as_array().cloned().unwrap_or_default();
+
+    let root = &nodes[0];
+    let source = if root["kind"] == "file" {
+        reconstruct_file(node_id)
+    } else {
+        root["content"].as_str().unwrap_or_default().to_string()
+    };
+
+    let attributions: Vec<Attribution> = nodes
+        .iter()
+        .map(|n| Attribution {
+            node_id: n["node_id"].as_str().unwrap_or_default().to_string(),
+            content: n["content"].as_str().map(String::from),
+            lamport_ts: n["lamport_ts"].as_i64(),
+        })
+        .collect();
+    let lines: Vec<serde_json::Value> = source
+        .lines()
+        .zip(blame::annotate(&source, &attributions))
+        .enumerate()
+        .map(|(i, (text, idx))| {
+            let n = &nodes[idx];
+            json!({
+                "line": i + 1,
+                "text": text,
+                "node_id": n["node_id"],
+                "author": n["author"],
+                "lamport_ts": n["lamport_ts"],
+            })
+        })
+        .collect();
+
+    pgrx::JsonB(json!({
+        "node_id": id_str,
+        "kind": root["kind"],
+        "name": root["content"],
+        "nodes": nodes,
+        "lines": lines,
+    }))
+}
@@ -0,0 +1,315 @@
+/// Export markdown documents as an Obsidian-style vault.
+///
+/// Each document comes back as a note at its vault path (or its file name
+/// for documents parsed outside a vault), and each asset as a file under
+/// `attachments/`. Wikilinks already in a note's text come back as they
+/// were written. `links_to` and `references` edges that are not in the
+/// text, and the links into each note, go into a generated block at the
+/// end of the note. `import_vault_file` strips that block again, so
+/// exporting and re-importing a vault adds no links.
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::parser::markdown::kinds;
+
+/// First and last line of the block `export_vault` appends to a note.
+const GENERATED_START: &str = "<!-- kerai:generated -->";
+const GENERATED_END: &str = "<!-- /kerai:generated -->";
+
+/// `source` without the blocks an earlier export generated.
+pub(crate) fn strip_generated(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut generated = false;
+    for line in source.split_inclusive('\n') {
+        match line.trim() {
+            GENERATED_START => generated = true,
+            GENERATED_END if generated => generated = false,
+            _ if !generated => out.push_str(line),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// The block listing a note's extra links and its backlinks; empty when
+/// there are neither.
+fn generated_block(links: &BTreeSet<String>, backlinks: &BTreeSet<String>) -> String {
+    if links.is_empty() && backlinks.is_empty() {
+        return String::new();
+    }
+    let mut block = format!("{}\n", GENERATED_START);
+    for (title, items) in [("Links", links), ("Backlinks", backlinks)] {
+        if items.is_empty() {
+            continue;
+        }
+        block.push_str(&format!("## {}\n\n", title));
+        for item in items {
+            block.push_str(&format!("- {}\n", item));
+        }
+        block.push('\n');
+    }
+    block.push_str(GENERATED_END);
+    block.push('\n');
+    block
+}
+
+/// `path`, or `name (2).ext` and so on when another file already took it.
+fn unique_path(path: String, taken: &mut HashSet<String>) -> String {
+    if taken.insert(path.to_lowercase()) {
+        return path;
+    }
+    let (stem, ext) = match path.rsplit_once('.') {
+        Some((stem, ext)) if !stem.ends_with('/') && !ext.contains('/') => {
+            (stem.to_string(), format!(".{}", ext))
+        }
+        _ => (path.clone(), String::new()),
+    };
+    (2..)
+        .map(|n| format!("{} ({}){}", stem, n, ext))
+        .find(|candidate| taken.insert(candidate.to_lowercase()))
+        .unwrap()
+}
+
+/// Wikilink target of a vault file: its path, and without `.md` for notes.
+fn link_target(path: &str) -> &str {
+    path.strip_suffix(".md").unwrap_or(path)
+}
+
+/// SQL condition on `n.path` for a path pattern bound as `$2`: an lquery
+/// when it has wildcards, otherwise the subtree under that path.
+fn path_clause(pattern: Option<&str>) -> &'static str {
+    match pattern {
+        None => "$2::text IS NULL",
+        Some(p) if p.contains('*') || p.contains('|') || p.contains('!') => "n.path ~ $2::lquery",
+        Some(_) => "n.path <@ $2::ltree",
+    }
+}
+
+/// Elements of a `jsonb_agg` result.
+fn rows(json: Option<pgrx::JsonB>) -> Vec<Value> {
+    match json {
+        Some(pgrx::JsonB(Value::Array(rows))) => rows,
+        _ => Vec::new(),
+    }
+}
+
+/// Export the markdown documents whose path matches `path_pattern` (all of
+/// them when NULL) as vault files, with the assets in the same paths or
+/// linked from them.
+///
+/// Returns `{notes: [{path, content}], attachments: [{path, vault,
+/// vault_path, size, sha256}], links, backlinks}`. Paths are relative to
+/// the vault root; attachment bytes are not stored, so the caller copies
+/// them from `vault_path` in the original vault.
+#[pg_extern]
+fn export_vault(path_pattern: Option<&str>) -> pgrx::JsonB {
+    let instance_id = crate::parser::get_self_instance_id();
+    let clause = path_clause(path_pattern);
+
+    let documents = rows(
+        Spi::get_one_with_args(
+            &format!(
+                "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+                'id', n.id,
+                'name', n.content,
+                'vault_path', n.metadata->>'vault_path',
+                'content', kerai.reconstruct_markdown(n.id)
+            ) ORDER BY n.path), '[]'::jsonb)
+            FROM kerai.nodes n
+            WHERE n.instance_id = $1::uuid AND n.kind = '{}' AND {} AND n.deleted_at IS NULL",
+                kinds::DOCUMENT,
+                clause
+            ),
+            &[instance_id.as_str().into(), path_pattern.into()],
+        )
+        .unwrap(),
+    );
+    let doc_ids: Vec<String> = documents
+        .iter()
+        .map(|d| d["id"].as_str().unwrap_or("").to_string())
+        .collect();
+
+    // Link edges out of every node of an exported document, with the
+    // document each end belongs to, if any
+    let edges = rows(
+        Spi::get_one_with_args(
+            &format!(
+                "WITH RECURSIVE sub AS (
+                SELECT id, id AS doc FROM kerai.nodes WHERE id = ANY($1::uuid[])
+                UNION ALL
+                SELECT n.id, sub.doc FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+                WHERE n.deleted_at IS NULL
+            )
+            SELECT COALESCE(jsonb_agg(jsonb_build_object(
+                'source', s.doc,
+                'target', e.target_id,
+                'target_doc', t.doc,
+                'kind', n.kind,
+                'content', n.content,
+                'in_text', e.metadata ?| array['wikilink', 'url']
+            ) ORDER BY e.id), '[]'::jsonb)
+            FROM kerai.edges e
+            JOIN sub s ON s.id = e.source_id
+            JOIN kerai.nodes n ON n.id = e.target_id
+            LEFT JOIN sub t ON t.id = e.target_id
+            WHERE e.relation IN ('links_to', 'references')
+              AND (t.doc IS NOT NULL OR n.kind = '{}')",
+                kinds::ASSET
+            ),
+            &[doc_ids.clone().into()],
+        )
+        .unwrap(),
+    );
+    let linked_assets: Vec<String> = edges
+        .iter()
+        .filter(|e| e["target_doc"].is_null())
+        .filter_map(|e| e["target"].as_str().map(String::from))
+        .collect();
+
+    let assets = rows(
+        Spi::get_one_with_args(
+            &format!(
+                "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+                'id', n.id,
+                'name', n.content,
+                'vault', n.metadata->>'vault',
+                'vault_path', n.metadata->>'vault_path',
+                'size', n.metadata->'size',
+                'sha256', n.metadata->>'sha256'
+            ) ORDER BY n.path), '[]'::jsonb)
+            FROM kerai.nodes n
+            WHERE n.instance_id = $1::uuid AND n.kind = '{}' AND n.deleted_at IS NULL
+              AND ({} OR n.id = ANY($3::uuid[]))",
+                kinds::ASSET,
+                clause
+            ),
+            &[
+                instance_id.as_str().into(),
+                path_pattern.into(),
+                linked_assets.into(),
+            ],
+        )
+        .unwrap(),
+    );
+
+    // Where every exported file lands
+    let mut taken = HashSet::new();
+    let mut paths: HashMap<String, String> = HashMap::new();
+    for doc in &documents {
+        let mut path = doc["vault_path"]
+            .as_str()
+            .or(doc["name"].as_str())
+            .unwrap_or("untitled")
+            .trim_matches('/')
+            .to_string();
+        if !path.to_lowercase().ends_with(".md") {
+            path.push_str(".md");
+        }
+        let id = doc["id"].as_str().unwrap_or("").to_string();
+        paths.insert(id, unique_path(path, &mut taken));
+    }
+    let mut attachments = Vec::new();
+    for asset in &assets {
+        let name = asset["name"].as_str().unwrap_or("attachment");
+        let path = unique_path(format!("attachments/{}", name), &mut taken);
+        let id = asset["id"].as_str().unwrap_or("").to_string();
+        paths.insert(id, path.clone());
+        attachments.push(json!({
+            "path": path,
+            "vault": asset["vault"],
+            "vault_path": asset["vault_path"],
+            "size": asset["size"],
+            "sha256": asset["sha256"],
+        }));
+    }
+
+    let mut links: HashMap<&str, BTreeSet<String>> = HashMap::new();
+    let mut backlinks: HashMap<&str, BTreeSet<String>> = HashMap::new();
+    for edge in &edges {
+        let source = edge["source"].as_str().unwrap_or("");
+        let Some(source_path) = paths.get(source) else {
+            continue;
+        };
+        let link = match edge["target_doc"].as_str() {
+            Some(doc) if doc == source => continue,
+            Some(doc) => {
+                let Some(path) = paths.get(doc) else { continue };
+                backlinks
+                    .entry(doc)
+                    .or_default()
+                    .insert(format!("[[{}]]", link_target(source_path)));
+                match (edge["kind"].as_str(), edge["content"].as_str()) {
+                    (Some(kinds::HEADING), Some(heading)) => {
+                        format!("[[{}#{}]]", link_target(path), heading)
+                    }
+                    _ => format!("[[{}]]", link_target(path)),
+                }
+            }
+            None => match paths.get(edge["target"].as_str().unwrap_or("")) {
+                Some(path) => format!("![[{}]]", path),
+                None => continue,
+            },
+        };
+        // Wikilinks and relative links are already in the note's text
+        if edge["in_text"] != true {
+            links.entry(source).or_default().insert(link);
+        }
+    }
+
+    let empty = BTreeSet::new();
+    let notes: Vec<Value> = documents
+        .iter()
+        .map(|doc| {
+            let id = doc["id"].as_str().unwrap_or("");
+            let mut content = doc["content"].as_str().unwrap_or("").to_string();
+            let block = generated_block(
+                links.get(id).unwrap_or(&empty),
+                backlinks.get(id).unwrap_or(&empty),
+            );
+            if !block.is_empty() {
+                content.push_str("\n\n");
+                content.push_str(&block);
+            } else {
+                content.push('\n');
+            }
+            json!({"path": paths[id], "content": content})
+        })
+        .collect();
+
+    pgrx::JsonB(json!({
+        "notes": notes,
+        "attachments": attachments,
+        "links": links.values().map(BTreeSet::len).sum::<usize>(),
+        "backlinks": backlinks.values().map(BTreeSet::len).sum::<usize>(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_block_strips_back_out() {
+        let links: BTreeSet<String> = ["[[b]]".to_string()].into();
+        let backlinks: BTreeSet<String> = ["[[c]]".to_string(), "[[a]]".to_string()].into();
+        let note = format!("# A\n\nText.\n\n{}", generated_block(&links, &backlinks));
+        assert!(note.contains("## Backlinks\n\n- [[a]]\n- [[c]]\n"));
+        assert_eq!(strip_generated(&note), "# A\n\nText.\n\n");
+        assert_eq!(generated_block(&BTreeSet::new(), &BTreeSet::new()), "");
+    }
+
+    #[test]
+    fn unique_path_numbers_collisions() {
+        let mut taken = HashSet::new();
+        assert_eq!(unique_path("a/Note.md".into(), &mut taken), "a/Note.md");
+        assert_eq!(unique_path("a/note.md".into(), &mut taken), "a/note (2).md");
+        assert_eq!(unique_path("a/Note.md".into(), &mut taken), "a/Note (3).md");
+        assert_eq!(unique_path("x.d/README".into(), &mut taken), "x.d/README");
+        assert_eq!(
+            unique_path("x.d/README".into(), &mut taken),
+            "x.d/README (2)"
+        );
+    }
+}
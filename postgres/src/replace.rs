@@ -0,0 +1,175 @@
+/// Find and replace — one string or regex rewritten across every node under
+/// an lquery, previewed per file before it is applied.
+///
+/// Matching and rewriting are done by Postgres: a literal pattern with
+/// `replace`, a regex with `regexp_replace(..., 'g')`, so `\1` and `\&` in
+/// the replacement refer to the match. Applying logs one signed
+/// `update_content` operation per changed node (each recorded in
+/// kerai.versions), all carrying the same `changeset` id in their payload.
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+/// Nodes under `$1` whose content `$2` changes when replaced by `$3`
+/// (not file or document nodes, whose content is the file name),
+/// each with the file or document holding it, in file and path order.
+/// `$4` is whether `$2` is a regex; `$5` limits to those files.
+const CHANGES_SQL: &str = "
+    WITH RECURSIVE hits AS (
+        SELECT n.id, n.kind, n.path, n.position, n.parent_id, n.content,
+               CASE WHEN $4 THEN regexp_replace(n.content, $2, $3, 'g')
+                    ELSE replace(n.content, $2, $3) END AS replaced,
+               CASE WHEN $4 THEN regexp_count(n.content, $2)
+                    ELSE (length(n.content) - length(replace(n.content, $2, ''))) / length($2)
+               END AS matches
+        FROM kerai.nodes n
+        WHERE n.path ~ $1::lquery AND n.kind NOT IN ('file', 'document')
+          AND CASE WHEN $4 THEN n.content ~ $2 ELSE strpos(n.content, $2) > 0 END
+          AND kerai.in_view(n.path, n.kind)
+    ), up AS (
+        -- Walk up from each hit to the file or document holding it
+        SELECT h.id AS hit_id, h.id, h.kind, h.parent_id
+        FROM hits h
+        UNION ALL
+        SELECT up.hit_id, p.id, p.kind, p.parent_id
+        FROM up JOIN kerai.nodes p ON p.id = up.parent_id
+        WHERE up.kind NOT IN ('file', 'document')
+    ), located AS (
+        SELECT h.*, f.content AS file
+        FROM hits h
+        LEFT JOIN up u ON u.hit_id = h.id AND u.kind IN ('file', 'document')
+        LEFT JOIN kerai.nodes f ON f.id = u.id
+    )
+    SELECT COALESCE(jsonb_agg(jsonb_build_object(
+        'id', id,
+        'kind', kind,
+        'path', path::text,
+        'file', file,
+        'matches', matches,
+        'before', content,
+        'after', replaced
+    ) ORDER BY file, path, position), '[]'::jsonb)
+    FROM located
+    WHERE replaced IS DISTINCT FROM content
+      AND ($5::text[] IS NULL OR COALESCE(file, '') = ANY($5))";
+
+/// Group changed nodes by their `file`, keeping the order they come in.
+///
+/// Returns `[{file, nodes, matches, changes: [...]}]`; nodes outside any
+/// file share one group with a null `file`.
+fn group_by_file(changes: &[Value]) -> Vec<Value> {
+    let mut files: Vec<Value> = Vec::new();
+    for change in changes {
+        let group = match files.iter_mut().position(|f| f["file"] == change["file"]) {
+            Some(i) => &mut files[i],
+            None => {
+                files
+                    .push(json!({"file": change["file"], "nodes": 0, "matches": 0, "changes": []}));
+                files.last_mut().unwrap()
+            }
+        };
+        group["nodes"] = json!(group["nodes"].as_u64().unwrap_or(0) + 1);
+        group["matches"] =
+            json!(group["matches"].as_u64().unwrap_or(0) + change["matches"].as_u64().unwrap_or(0));
+        let mut entry = change.clone();
+        if let Some(entry) = entry.as_object_mut() {
+            entry.remove("file");
+        }
+        group["changes"].as_array_mut().unwrap().push(entry);
+    }
+    files
+}
+
+/// Replace `pattern` with `replacement` in the content of every node whose
+/// path matches the lquery `path` (`*` for the whole graph). With `regex`,
+/// `pattern` is a Postgres regular expression and the replacement may use
+/// `\1`..`\9` and `\&`; otherwise both are taken literally.
+///
+/// With `dry_run` nothing is written; otherwise every change is applied as
+/// an `update_content` operation in one changeset. `files` limits either
+/// to nodes in those files or documents (as `files[].file` names them, `''`
+/// for nodes outside any), e.g. the ones confirmed after a preview.
+///
+/// Returns `{dry_run, changeset, nodes, matches, files: [{file, nodes,
+/// matches, changes: [{id, kind, path, matches, before, after}]}]}`;
+/// `changeset` is null for a dry run.
+#[pg_extern]
+fn replace(
+    path: &str,
+    pattern: &str,
+    replacement: &str,
+    regex: bool,
+    dry_run: bool,
+    files: default!(Option<Vec<String>>, "NULL"),
+) -> pgrx::JsonB {
+    if pattern.is_empty() {
+        error!("replace needs a non-empty pattern");
+    }
+
+    let changes = Spi::get_one_with_args::<pgrx::JsonB>(
+        CHANGES_SQL,
+        &[
+            path.into(),
+            pattern.into(),
+            replacement.into(),
+            regex.into(),
+            files.into(),
+        ],
+    )
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+    let changes = changes.as_array().cloned().unwrap_or_default();
+
+    let changeset = if dry_run || changes.is_empty() {
+        None
+    } else {
+        let changeset = uuid::Uuid::now_v7().to_string();
+        for change in &changes {
+            Spi::run_with_args(
+                "SELECT kerai.apply_op('update_content', $1::uuid, $2)",
+                &[
+                    change["id"].as_str().unwrap_or_default().into(),
+                    pgrx::JsonB(json!({
+                        "new_content": change["after"],
+                        "changeset": changeset,
+                    }))
+                    .into(),
+                ],
+            )
+            .unwrap();
+        }
+        Some(changeset)
+    };
+
+    let matches: u64 = changes.iter().filter_map(|c| c["matches"].as_u64()).sum();
+    pgrx::JsonB(json!({
+        "dry_run": dry_run,
+        "changeset": changeset,
+        "nodes": changes.len(),
+        "matches": matches,
+        "files": group_by_file(&changes),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_by_file_keeps_order_and_sums() {
+        let changes = vec![
+            json!({"id": "1", "file": "b.md", "matches": 2}),
+            json!({"id": "2", "file": "a.md", "matches": 1}),
+            json!({"id": "3", "file": "b.md", "matches": 3}),
+            json!({"id": "4", "file": null, "matches": 1}),
+        ];
+        let files = group_by_file(&changes);
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0]["file"], "b.md");
+        assert_eq!(files[0]["nodes"], 2);
+        assert_eq!(files[0]["matches"], 5);
+        assert_eq!(files[0]["changes"][1], json!({"id": "3", "matches": 3}));
+        assert_eq!(files[1]["file"], "a.md");
+        assert!(files[2]["file"].is_null());
+        assert!(group_by_file(&[]).is_empty());
+    }
+}
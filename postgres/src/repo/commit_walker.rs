@@ -48,7 +48,7 @@ pub fn walk_commits(
             .find_commit(oid)
             .map_err(|e| format!("find_commit failed: {}", e))?;
 
-        let node_id = Uuid::new_v4().to_string();
+        let node_id = Uuid::now_v7().to_string();
         let sha = oid.to_string();
         oid_to_node.insert(sha.clone(), node_id.clone());
 
@@ -84,7 +84,7 @@ pub fn walk_commits(
             if let Ok(parent) = commit.parent(parent_id) {
                 let parent_sha = parent.id().to_string();
                 edges.push(EdgeRow {
-                    id: Uuid::new_v4().to_string(),
+                    id: Uuid::now_v7().to_string(),
                     source_id: node_id.clone(),
                     target_id: parent_sha, // placeholder — resolved below
                     relation: "parent_commit".to_string(),
@@ -0,0 +1,242 @@
+/// Git history import — replay commits as kerai.versions.
+///
+/// `kerai import-git` walks a repository oldest commit first and hands each
+/// one to `import_git_commit` with the changed files' contents. Each file
+/// is reparsed (Rust incrementally, so unchanged items keep their node ids
+/// and their blame), and every node the parse inserted, edited or moved
+/// gets a version stamped with the commit's author and date. All versions
+/// of one commit share a Lamport timestamp, so commit order is Lamport
+/// order and `kerai postgres export --git --history` replays them as the
+/// same commits.
+///
+/// Parsing goes through the internal parsers rather than the `parse_*`
+/// functions, so historical file versions earn no reward.
+use pgrx::prelude::*;
+use serde_json::json;
+
+use crate::crdt::{clock, sign_version};
+use crate::parser::{go, inserter, markdown, parse_single_file_incremental};
+use crate::sql::{sql_text, sql_uuid};
+
+use super::get_self_instance_id;
+use super::language_detect::{classify, LanguageClass, ParseableLanguage};
+
+/// `Name <email>` as git writes an identity, with the characters git
+/// rejects in either part removed.
+fn author_ident(name: &str, email: &str) -> String {
+    let clean = |s: &str| {
+        s.chars()
+            .filter(|c| !matches!(c, '<' | '>' | '\n' | '\r'))
+            .collect::<String>()
+            .trim()
+            .to_string()
+    };
+    format!("{} <{}>", clean(name), clean(email))
+}
+
+/// Reparse one file version, replacing the nodes of its previous version.
+/// Returns false for files with no parser.
+fn parse_version(path: &str, source: &str, instance_id: &str) -> bool {
+    let LanguageClass::Parseable(language) = classify(path, Some(source.as_bytes())) else {
+        return false;
+    };
+    match language {
+        ParseableLanguage::Rust => {
+            parse_single_file_incremental(source, path, instance_id, Some(path));
+        }
+        ParseableLanguage::Go => {
+            inserter::delete_file_nodes(instance_id, path);
+            go::parse_go_single(source, path, instance_id, None);
+        }
+        ParseableLanguage::C => {
+            inserter::delete_file_nodes(instance_id, path);
+            crate::parser::c::parse_c_single(source, path, instance_id, None);
+        }
+        ParseableLanguage::Cpp => {
+            inserter::delete_file_nodes(instance_id, path);
+            crate::parser::c::parse_cpp_single(source, path, instance_id, None);
+        }
+        ParseableLanguage::Markdown => {
+            markdown::delete_markdown_nodes(instance_id, path);
+            markdown::parse_markdown_single(source, path, instance_id, None);
+        }
+    }
+    true
+}
+
+/// Version every node under the file parsed at `path` that is new or whose
+/// content, parent or position differs from its latest version. Returns
+/// the ids of the versions written.
+fn version_file(
+    path: &str,
+    instance_id: &str,
+    author: &str,
+    ts: i64,
+    committed_at: &str,
+) -> Vec<String> {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "WITH RECURSIVE sub AS (
+            SELECT id FROM (
+                SELECT id FROM kerai.nodes
+                WHERE kind IN ('file', 'document') AND content = {path} AND instance_id = {inst}
+                ORDER BY created_at DESC LIMIT 1
+            ) root
+            UNION ALL
+            SELECT n.id FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+        ), changed AS (
+            SELECT n.id, lv.snap,
+                CASE
+                    WHEN lv.snap IS NULL THEN 'insert_node'
+                    WHEN lv.snap->>'content' IS DISTINCT FROM n.content THEN 'update_content'
+                    WHEN (lv.snap->>'parent_id')::uuid IS DISTINCT FROM n.parent_id
+                      OR (lv.snap->>'position')::integer IS DISTINCT FROM n.position
+                        THEN 'move_node'
+                END AS op
+            FROM kerai.nodes n
+            JOIN sub ON sub.id = n.id
+            LEFT JOIN LATERAL (
+                SELECT v.new_snapshot AS snap FROM kerai.versions v
+                WHERE v.node_id = n.id
+                ORDER BY v.timestamp DESC, v.created_at DESC LIMIT 1
+            ) lv ON true
+        ), ins AS (
+            INSERT INTO kerai.versions (node_id, instance_id, operation, old_parent, new_parent,
+                old_position, new_position, old_content, new_content, old_snapshot, new_snapshot,
+                branch_id, author, timestamp, signed_by, created_at)
+            SELECT n.id, {inst}, c.op, (c.snap->>'parent_id')::uuid, n.parent_id,
+                (c.snap->>'position')::integer, n.position, c.snap->>'content', n.content,
+                c.snap, to_jsonb(n) - 'tsv',
+                (SELECT id FROM kerai.branches WHERE is_current), {author}, {ts},
+                (SELECT id FROM kerai.instances WHERE is_self), {committed_at}::timestamptz
+            FROM changed c JOIN kerai.nodes n ON n.id = c.id
+            WHERE c.op IS NOT NULL
+            RETURNING id
+        )
+        SELECT COALESCE(jsonb_agg(id::text), '[]'::jsonb) FROM ins",
+        path = sql_text(path),
+        inst = sql_uuid(instance_id),
+        author = sql_text(author),
+        committed_at = sql_text(committed_at),
+    ))
+    .unwrap()
+    .and_then(|j| j.0.as_array().cloned())
+    .unwrap_or_default()
+    .iter()
+    .filter_map(|v| v.as_str().map(String::from))
+    .collect()
+}
+
+/// Replay one git commit: reparse its changed files and version what
+/// changed under a single new Lamport timestamp.
+///
+/// `commit` is `{sha, author, email, committed_at, message, files: [{path,
+/// source} | {path, deleted: true}]}`, with `committed_at` in ISO 8601.
+/// Files with no parser are skipped; deleted files lose their nodes, and
+/// like any delete that is not versioned. A sha already imported is left
+/// alone.
+///
+/// Returns `{sha, status, timestamp, parsed, skipped, deleted, versions}`,
+/// `status` being `imported` or `already_imported`.
+#[pg_extern]
+fn import_git_commit(commit: pgrx::JsonB) -> pgrx::JsonB {
+    let commit = commit.0;
+    let sha = commit["sha"]
+        .as_str()
+        .unwrap_or_else(|| error!("Commit has no sha"));
+    let committed_at = commit["committed_at"]
+        .as_str()
+        .unwrap_or_else(|| error!("Commit {} has no committed_at", sha));
+
+    let previous = Spi::get_one::<i64>(&format!(
+        "SELECT timestamp FROM kerai.git_imports WHERE sha = {}",
+        sql_text(sha),
+    ))
+    .unwrap();
+    if let Some(ts) = previous {
+        return pgrx::JsonB(json!({
+            "sha": sha,
+            "status": "already_imported",
+            "timestamp": ts,
+            "parsed": 0,
+            "skipped": 0,
+            "deleted": 0,
+            "versions": 0,
+        }));
+    }
+
+    let instance_id = get_self_instance_id();
+    let author = author_ident(
+        commit["author"].as_str().unwrap_or("unknown"),
+        commit["email"].as_str().unwrap_or(""),
+    );
+    let ts = clock::next_lamport_ts();
+
+    let (mut parsed, mut skipped, mut deleted) = (Vec::new(), 0, 0);
+    for file in commit["files"].as_array().into_iter().flatten() {
+        let Some(path) = file["path"].as_str() else {
+            continue;
+        };
+        if file["deleted"] == true {
+            markdown::delete_markdown_nodes(&instance_id, path);
+            inserter::delete_file_nodes(&instance_id, path);
+            deleted += 1;
+        } else if parse_version(path, file["source"].as_str().unwrap_or(""), &instance_id) {
+            parsed.push(path);
+        } else {
+            skipped += 1;
+        }
+    }
+
+    let mut versions = 0;
+    for path in &parsed {
+        for version_id in version_file(path, &instance_id, &author, ts, committed_at) {
+            sign_version(&version_id);
+            versions += 1;
+        }
+    }
+
+    Spi::run(&format!(
+        "INSERT INTO kerai.git_imports (sha, timestamp, author, committed_at, message, files, versions)
+         VALUES ({}, {ts}, {}, {}::timestamptz, {}, {}, {versions})",
+        sql_text(sha),
+        sql_text(&author),
+        sql_text(committed_at),
+        sql_text(commit["message"].as_str().unwrap_or("")),
+        parsed.len() + deleted,
+    ))
+    .unwrap();
+
+    pgrx::JsonB(json!({
+        "sha": sha,
+        "status": "imported",
+        "timestamp": ts,
+        "parsed": parsed.len(),
+        "skipped": skipped,
+        "deleted": deleted,
+        "versions": versions,
+    }))
+}
+
+/// Shas already replayed by `import_git_commit`, oldest first.
+#[pg_extern]
+fn git_imported_shas() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(sha ORDER BY timestamp), '[]'::jsonb) FROM kerai.git_imports",
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn author_ident_strips_what_git_rejects() {
+        assert_eq!(
+            author_ident("Ada Lovelace", "ada@example.com"),
+            "Ada Lovelace <ada@example.com>"
+        );
+        assert_eq!(author_ident(" <Bob>\n", "bob@x>"), "Bob <bob@x>");
+    }
+}
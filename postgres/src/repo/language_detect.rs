@@ -7,6 +7,7 @@ pub enum ParseableLanguage {
     Rust,
     Go,
     C,
+    Cpp,
     Markdown,
 }
 
@@ -16,6 +17,7 @@ impl ParseableLanguage {
             Self::Rust => "rust",
             Self::Go => "go",
             Self::C => "c",
+            Self::Cpp => "cpp",
             Self::Markdown => "markdown",
         }
     }
@@ -82,6 +84,9 @@ fn classify_extension(ext: &str, content_sample: Option<&[u8]>) -> LanguageClass
         "rs" => LanguageClass::Parseable(ParseableLanguage::Rust),
         "go" => LanguageClass::Parseable(ParseableLanguage::Go),
         "c" | "h" => LanguageClass::Parseable(ParseableLanguage::C),
+        "cpp" | "cc" | "cxx" | "hpp" | "hxx" | "hh" => {
+            LanguageClass::Parseable(ParseableLanguage::Cpp)
+        }
         "md" | "markdown" => LanguageClass::Parseable(ParseableLanguage::Markdown),
 
         // Opaque text — scripting
@@ -105,9 +110,6 @@ fn classify_extension(ext: &str, content_sample: Option<&[u8]>) -> LanguageClass
         "scala" | "sc" => LanguageClass::OpaqueText("scala".to_string()),
         "swift" => LanguageClass::OpaqueText("swift".to_string()),
         "cs" => LanguageClass::OpaqueText("csharp".to_string()),
-        "cpp" | "cc" | "cxx" | "hpp" | "hxx" | "hh" => {
-            LanguageClass::OpaqueText("cpp".to_string())
-        }
         "zig" => LanguageClass::OpaqueText("zig".to_string()),
         "nim" => LanguageClass::OpaqueText("nim".to_string()),
         "d" => LanguageClass::OpaqueText("d".to_string()),
@@ -219,6 +221,14 @@ mod tests {
             classify("header.h", None),
             LanguageClass::Parseable(ParseableLanguage::C)
         );
+        assert_eq!(
+            classify("widget.cpp", None),
+            LanguageClass::Parseable(ParseableLanguage::Cpp)
+        );
+        assert_eq!(
+            classify("widget.hpp", None),
+            LanguageClass::Parseable(ParseableLanguage::Cpp)
+        );
         assert_eq!(
             classify("README.md", None),
             LanguageClass::Parseable(ParseableLanguage::Markdown)
@@ -7,11 +7,12 @@ use uuid::Uuid;
 
 use crate::parser::ast_walker::NodeRow;
 use crate::parser::inserter;
-use crate::sql::{sql_escape, sql_opt_text, sql_text, sql_uuid};
+use crate::sql::{sql_opt_text, sql_text, sql_uuid};
 
 mod census;
 mod cloner;
 mod commit_walker;
+mod history;
 pub mod kinds;
 mod language_detect;
 mod tree_walker;
@@ -89,7 +90,7 @@ fn mirror_repo_inner(url: &str, _refspec: Option<&str>) -> pgrx::JsonB {
             update_repo_head(&repo_id, &new_head);
 
             // Mint reward
-            mint_mirror_reward(&instance_id, url, commit_count, &tree_stats);
+            mint_mirror_reward(&repo_id, url, commit_count, &tree_stats);
 
             let elapsed = start.elapsed();
             pgrx::JsonB(json!({
@@ -123,7 +124,7 @@ fn mirror_repo_inner(url: &str, _refspec: Option<&str>) -> pgrx::JsonB {
                 .unwrap_or_else(|e| pgrx::error!("Failed to get HEAD: {}", e));
 
             // Create repo root node
-            let repo_node_id = Uuid::new_v4().to_string();
+            let repo_node_id = Uuid::now_v7().to_string();
             let repo_node = NodeRow {
                 id: repo_node_id.clone(),
                 instance_id: instance_id.clone(),
@@ -162,7 +163,7 @@ fn mirror_repo_inner(url: &str, _refspec: Option<&str>) -> pgrx::JsonB {
                 .unwrap_or_else(|e| pgrx::error!("Tree walk failed: {}", e));
 
             // Mint reward
-            mint_mirror_reward(&instance_id, url, commit_count, &tree_stats);
+            mint_mirror_reward(&repo_id, url, commit_count, &tree_stats);
 
             let elapsed = start.elapsed();
             pgrx::JsonB(json!({
@@ -358,7 +359,7 @@ fn insert_repo_record(
     head_commit: &str,
     node_id: &str,
 ) -> String {
-    let id = Uuid::new_v4().to_string();
+    let id = Uuid::now_v7().to_string();
 
     Spi::run(&format!(
         "INSERT INTO kerai.repositories (id, instance_id, url, name, local_path, head_commit, last_sync, node_id) \
@@ -388,7 +389,7 @@ fn update_repo_head(repo_id: &str, new_head: &str) {
 
 /// Mint a reward for mirror_repo work.
 fn mint_mirror_reward(
-    _instance_id: &str,
+    repo_id: &str,
     url: &str,
     commits: usize,
     stats: &tree_walker::TreeWalkStats,
@@ -401,9 +402,5 @@ fn mint_mirror_reward(
         "opaque_text": stats.opaque_text,
         "opaque_binary": stats.opaque_binary,
     });
-    let details_str = sql_escape(&details.to_string());
-    let _ = Spi::get_one::<pgrx::JsonB>(&format!(
-        "SELECT kerai.mint_reward('mirror_repo', '{}'::jsonb)",
-        details_str,
-    ));
+    crate::currency::reward("mirror_repo", "repository", Some(repo_id), details);
 }
@@ -78,7 +78,7 @@ pub fn walk_tree(
         match entry.kind() {
             Some(git2::ObjectType::Tree) => {
                 // Directory node
-                let dir_id = Uuid::new_v4().to_string();
+                let dir_id = Uuid::now_v7().to_string();
 
                 // Find parent directory
                 let parent_id = if root.is_empty() {
@@ -200,7 +200,7 @@ pub fn walk_tree(
                             let line_count = source.lines().count();
 
                             pending_nodes.push(NodeRow {
-                                id: Uuid::new_v4().to_string(),
+                                id: Uuid::now_v7().to_string(),
                                 instance_id: instance_id.to_string(),
                                 kind: kinds::REPO_OPAQUE_TEXT.to_string(),
                                 language: Some(lang),
@@ -287,6 +287,7 @@ fn dispatch_parser(
                 Some(parent_id),
                 filename,
                 0,
+                Some(filename),
             );
         }
         ParseableLanguage::Go => {
@@ -295,6 +296,9 @@ fn dispatch_parser(
         ParseableLanguage::C => {
             crate::parser::c::parse_c_single(source, filename, instance_id, Some(parent_id));
         }
+        ParseableLanguage::Cpp => {
+            crate::parser::c::parse_cpp_single(source, filename, instance_id, Some(parent_id));
+        }
         ParseableLanguage::Markdown => {
             crate::parser::markdown::parse_markdown_single(
                 source,
@@ -317,7 +321,7 @@ fn make_binary_node(
     position: i32,
 ) -> NodeRow {
     NodeRow {
-        id: Uuid::new_v4().to_string(),
+        id: Uuid::now_v7().to_string(),
         instance_id: instance_id.to_string(),
         kind: kinds::REPO_OPAQUE_BINARY.to_string(),
         language: None,
@@ -464,7 +468,7 @@ pub fn walk_tree_incremental(
                             };
 
                             pending_nodes.push(NodeRow {
-                                id: Uuid::new_v4().to_string(),
+                                id: Uuid::now_v7().to_string(),
                                 instance_id: instance_id.to_string(),
                                 kind: kinds::REPO_OPAQUE_TEXT.to_string(),
                                 language: Some(lang),
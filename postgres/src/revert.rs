@@ -0,0 +1,345 @@
+/// Reverting versions — applying the inverse of recorded node changes.
+///
+/// A version is reverted together with the others its operation recorded
+/// (same author and Lamport time): the descendants a cascade delete
+/// removed, the children a delete handed up, the files of an imported
+/// commit. Each inverse goes through `kerai.apply_op`, so it is versioned
+/// and reaches peers like any edit, and carries the version it undoes as
+/// `revert_of` in its payload:
+///
+/// - `update_content` writes the old content back;
+/// - `update_metadata` writes back the old values of the keys it changed
+///   and nulls the keys it added;
+/// - `move_node` moves the node back;
+/// - `insert_node` deletes the node;
+/// - `delete_node` inserts the node again from its tombstone, under a new
+///   id since peers drop any op for a deleted one, and moves the children
+///   it handed up back under it. Edges to it were not kept and stay gone.
+///
+/// A node changed again since is left as it is and reported as a conflict,
+/// as is one that no longer exists.
+use pgrx::prelude::*;
+use serde_json::{json, Map, Value};
+
+use crate::sql::{sql_jsonb, sql_text, sql_uuid};
+
+/// Versions recorded by the same operation as `version_id`, oldest first,
+/// each with whether it has been reverted already.
+fn operation_versions(version_id: &str) -> Vec<Value> {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_agg(jsonb_build_object(
+            'id', w.id, 'node_id', w.node_id, 'operation', w.operation,
+            'old_parent', w.old_parent, 'new_parent', w.new_parent,
+            'old_position', w.old_position, 'new_position', w.new_position,
+            'old_content', w.old_content, 'new_content', w.new_content,
+            'old_snapshot', w.old_snapshot, 'new_snapshot', w.new_snapshot,
+            'reverted', EXISTS (
+                SELECT 1 FROM kerai.operations o WHERE o.payload->>'revert_of' = w.id::text
+            )
+        ) ORDER BY w.id)
+        FROM kerai.versions v
+        JOIN kerai.versions w ON w.author = v.author AND w.timestamp = v.timestamp
+        WHERE v.id = {}",
+        sql_uuid(version_id),
+    ))
+    .unwrap()
+    .and_then(|j| j.0.as_array().cloned())
+    .unwrap_or_default()
+}
+
+/// Tombstones ordered so each comes after the tombstone of its old parent.
+fn parents_first(mut pending: Vec<Value>) -> Vec<Value> {
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let waiting = |v: &Value| pending.iter().any(|p| p["node_id"] == v["old_parent"]);
+        let (mut ready, rest): (Vec<Value>, Vec<Value>) =
+            pending.iter().cloned().partition(|v| !waiting(v));
+        if ready.is_empty() {
+            // A cycle cannot come from a tree; take the rest as they are
+            ready = rest;
+            pending = Vec::new();
+        } else {
+            pending = rest;
+        }
+        ordered.append(&mut ready);
+    }
+    ordered
+}
+
+/// The node's row as it is now, or null when it is gone or a tombstone.
+fn current_row(node_id: &str) -> Value {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT to_jsonb(n) - 'tsv' FROM kerai.nodes n WHERE id = {} AND deleted_at IS NULL",
+        sql_uuid(node_id),
+    ))
+    .unwrap()
+    .map_or(Value::Null, |j| j.0)
+}
+
+fn node_exists(node_id: &str) -> bool {
+    Spi::get_one::<bool>(&format!(
+        "SELECT EXISTS (SELECT 1 FROM kerai.nodes WHERE id = {} AND deleted_at IS NULL)",
+        sql_uuid(node_id),
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Metadata merge undoing a version's: the old value of each key it
+/// changed, null for those it added. `None` when a key has changed since.
+fn metadata_inverse(old: &Value, new: &Value, current: &Value) -> Option<Value> {
+    let mut merge = Map::new();
+    for (key, value) in new.as_object().into_iter().flatten() {
+        if old.get(key) == Some(value) {
+            continue;
+        }
+        if current.get(key) != Some(value) {
+            return None;
+        }
+        merge.insert(key.clone(), old.get(key).cloned().unwrap_or(Value::Null));
+    }
+    Some(Value::Object(merge))
+}
+
+/// One revert in progress: the ops applied, the versions left alone and
+/// the nodes brought back (old id → new id).
+#[derive(Default)]
+struct Revert {
+    reverted: Vec<Value>,
+    ops: Vec<Value>,
+    conflicts: Vec<Value>,
+    resurrected: Map<String, Value>,
+}
+
+impl Revert {
+    fn apply(
+        &mut self,
+        version: &Value,
+        op_type: &str,
+        node_id: Option<&str>,
+        payload: Value,
+    ) -> Value {
+        let mut payload = payload;
+        payload["revert_of"] = version["id"].clone();
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.apply_op({}, {}, {})",
+            sql_text(op_type),
+            node_id.map_or_else(|| "NULL".to_string(), sql_uuid),
+            sql_jsonb(&payload),
+        ))
+        .unwrap_or_else(|e| error!("{} failed: {}", op_type, e))
+        .map_or(Value::Null, |j| j.0);
+        self.reverted.push(version["id"].clone());
+        self.ops.push(result.clone());
+        result
+    }
+
+    fn conflict(&mut self, version: &Value, reason: &str) {
+        self.conflicts.push(json!({
+            "version_id": version["id"],
+            "node_id": version["node_id"],
+            "reason": reason,
+        }));
+    }
+
+    /// A parent id as it is after this revert: resurrected nodes' new ids.
+    fn parent(&self, id: &Value) -> Option<String> {
+        let id = id.as_str()?;
+        let mapped = self
+            .resurrected
+            .get(id)
+            .and_then(Value::as_str)
+            .unwrap_or(id);
+        Some(mapped.to_string())
+    }
+
+    /// Insert a deleted node again from its tombstone.
+    fn resurrect(&mut self, version: &Value) {
+        let snapshot = &version["old_snapshot"];
+        let parent = self.parent(&version["old_parent"]);
+        if parent.as_deref().is_some_and(|p| !node_exists(p)) {
+            return self.conflict(version, "parent missing");
+        }
+        let payload = json!({
+            "kind": snapshot["kind"],
+            "language": snapshot["language"],
+            "content": snapshot["content"],
+            "parent_id": parent,
+            "position": snapshot["position"],
+            "path": snapshot["path"],
+            "metadata": snapshot["metadata"],
+        });
+        let result = self.apply(version, "insert_node", None, payload);
+        if let Some(old_id) = version["node_id"].as_str() {
+            self.resurrected
+                .insert(old_id.to_string(), result["node_id"].clone());
+        }
+    }
+
+    /// Undo an insert, update or move, unless the node changed since.
+    fn invert(&mut self, version: &Value) {
+        let node_id = version["node_id"].as_str().unwrap_or_default();
+        let row = current_row(node_id);
+        if row.is_null() {
+            return self.conflict(version, "node deleted");
+        }
+        let unchanged = |column: &str, key: &str| row[column] == version[key];
+        match version["operation"].as_str().unwrap_or_default() {
+            "insert_node" if unchanged("content", "new_content") => {
+                self.apply(version, "delete_node", Some(node_id), json!({}));
+            }
+            "update_content" if unchanged("content", "new_content") => {
+                let payload = json!({"new_content": version["old_content"]});
+                self.apply(version, "update_content", Some(node_id), payload);
+            }
+            "move_node"
+                if unchanged("parent_id", "new_parent")
+                    && unchanged("position", "new_position") =>
+            {
+                let Some(parent) = self.parent(&version["old_parent"]) else {
+                    return self.conflict(version, "cannot move back to the root");
+                };
+                let payload =
+                    json!({"new_parent_id": parent, "new_position": version["old_position"]});
+                self.apply(version, "move_node", Some(node_id), payload);
+            }
+            "update_metadata" => {
+                let old = &version["old_snapshot"]["metadata"];
+                let new = &version["new_snapshot"]["metadata"];
+                match metadata_inverse(old, new, &row["metadata"]) {
+                    Some(merge) => {
+                        self.apply(
+                            version,
+                            "update_metadata",
+                            Some(node_id),
+                            json!({"merge": merge}),
+                        );
+                    }
+                    None => self.conflict(version, "changed since"),
+                }
+            }
+            _ => self.conflict(version, "changed since"),
+        }
+    }
+}
+
+/// Revert the operation that recorded `version_id` (see the module doc).
+fn revert(version_id: &str) -> Value {
+    let versions = operation_versions(version_id);
+    if versions.is_empty() {
+        error!("Version not found: {}", version_id);
+    }
+    if versions.iter().any(|v| v["reverted"] == true) {
+        error!("Version {} has already been reverted", version_id);
+    }
+
+    let (tombstones, changes): (Vec<Value>, Vec<Value>) = versions
+        .into_iter()
+        .partition(|v| v["operation"] == "delete_node");
+    let mut revert = Revert::default();
+    for version in parents_first(tombstones) {
+        revert.resurrect(&version);
+    }
+    for version in changes.iter().rev() {
+        revert.invert(version);
+    }
+
+    json!({
+        "version_id": version_id,
+        "reverted": revert.reverted,
+        "ops": revert.ops,
+        "conflicts": revert.conflicts,
+        "resurrected": revert.resurrected,
+    })
+}
+
+/// Revert a version and the others its operation recorded, applying the
+/// inverse operations as new versions.
+///
+/// Returns `{version_id, reverted: [version ids], ops: [apply_op results],
+/// conflicts: [{version_id, node_id, reason}], resurrected: {old id: new
+/// id}}`.
+#[pg_extern]
+fn revert_version(version_id: pgrx::Uuid) -> pgrx::JsonB {
+    pgrx::JsonB(revert(&version_id.to_string()))
+}
+
+/// Revert the last `n` operations `author` made on the current branch
+/// (deletes included), newest first. `author` is a key fingerprint or
+/// instance name, this instance by default. Reverts and operations
+/// already reverted are skipped, so repeated calls step further back.
+///
+/// Returns `{author, undone: [revert_version results]}`.
+#[pg_extern]
+fn undo_last(author: default!(Option<&str>, "NULL"), n: default!(i32, 1)) -> pgrx::JsonB {
+    let fingerprint = Spi::get_one::<String>(&format!(
+        "SELECT COALESCE(
+            (SELECT key_fingerprint FROM kerai.instances
+             WHERE {} ORDER BY is_self DESC LIMIT 1),
+            {})",
+        author.map_or("is_self".to_string(), |a| format!("name = {}", sql_text(a))),
+        author.map_or("NULL".to_string(), sql_text),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("No instance identity — run kerai.bootstrap_instance()"));
+
+    let last = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(id ORDER BY timestamp DESC), '[]'::jsonb) FROM (
+            SELECT DISTINCT ON (v.timestamp) v.id::text AS id, v.timestamp
+            FROM kerai.versions v
+            WHERE v.author = {author}
+              AND (v.branch_id IS NULL
+                   OR v.branch_id = (SELECT id FROM kerai.branches WHERE is_current))
+              AND NOT EXISTS (
+                  SELECT 1 FROM kerai.operations o
+                  WHERE o.author = v.author AND o.lamport_ts = v.timestamp
+                    AND o.payload ? 'revert_of')
+              AND NOT EXISTS (
+                  SELECT 1 FROM kerai.versions w
+                  JOIN kerai.operations o ON o.payload->>'revert_of' = w.id::text
+                  WHERE w.author = v.author AND w.timestamp = v.timestamp)
+            ORDER BY v.timestamp DESC, v.id
+            LIMIT {n}
+        ) last",
+        author = sql_text(&fingerprint),
+        n = n.max(0),
+    ))
+    .unwrap()
+    .and_then(|j| j.0.as_array().cloned())
+    .unwrap_or_default();
+
+    let undone: Vec<Value> = last.iter().filter_map(Value::as_str).map(revert).collect();
+    pgrx::JsonB(json!({ "author": fingerprint, "undone": undone }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tombstones_come_after_their_parents() {
+        let tombstone = |id: &str, parent: &str| json!({"node_id": id, "old_parent": parent});
+        let ordered = parents_first(vec![
+            tombstone("grandchild", "child"),
+            tombstone("child", "root"),
+            tombstone("root", "elsewhere"),
+        ]);
+        let ids: Vec<&str> = ordered
+            .iter()
+            .map(|v| v["node_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["root", "child", "grandchild"]);
+    }
+
+    #[test]
+    fn metadata_inverse_restores_changed_and_nulls_added_keys() {
+        let old = json!({"a": 1, "b": 2});
+        let new = json!({"a": 1, "b": 3, "c": 4});
+        assert_eq!(
+            metadata_inverse(&old, &new, &new),
+            Some(json!({"b": 2, "c": null}))
+        );
+        let since = json!({"a": 1, "b": 5, "c": 4});
+        assert_eq!(metadata_inverse(&old, &new, &since), None);
+    }
+}
@@ -0,0 +1,190 @@
+/// Optional row-level security on nodes, edges and versions for users who
+/// connect with psql or other SQL clients directly.
+///
+/// Access is granted per node subtree in kerai.path_grants (an ltree path
+/// and everything under it, read or read/write, for one user or for every
+/// allowed user); admins see everything. The acting user comes from the
+/// `kerai.current_principal` setting, which holds a session or API token
+/// and is set with `kerai.set_principal`; kerai serve and kerai-web set it
+/// for each request. Policies bind roles other than the tables' owner, so
+/// the web server (connecting as owner) is unaffected unless
+/// `enable_rls(force => true)` is used.
+use std::ffi::CString;
+
+use pgrx::guc::{GucContext, GucFlags, GucRegistry, GucSetting};
+use pgrx::prelude::*;
+use serde_json::json;
+
+use crate::sql::{sql_escape, sql_ltree};
+
+/// Session or API token of the user row-level security acts for.
+static CURRENT_PRINCIPAL: GucSetting<Option<CString>> = GucSetting::<Option<CString>>::new(None);
+
+/// Tables with policies, and the access check each applies for a read
+/// (`{write}` = false) or a write.
+const POLICY_TABLES: &[(&str, &str)] = &[
+    ("nodes", "kerai.rls_can_access(path, {write})"),
+    ("edges", "kerai.rls_node_access(source_id, {write})"),
+    ("versions", "kerai.rls_node_access(node_id, {write})"),
+];
+
+pub fn register_gucs() {
+    GucRegistry::define_string_guc(
+        c"kerai.current_principal",
+        c"Session or API token row-level security acts for",
+        c"Set with kerai.set_principal(token); kerai.path_grants for that token's user decide which nodes are visible.",
+        &CURRENT_PRINCIPAL,
+        GucContext::Userset,
+        GucFlags::NO_SHOW_ALL | GucFlags::NOT_IN_SAMPLE,
+    );
+}
+
+/// Statements creating one table's policies, dropping any earlier ones
+/// first so they can be re-run.
+fn policy_statements(table: &str, check: &str) -> Vec<String> {
+    let read = check.replace("{write}", "false");
+    let write = check.replace("{write}", "true");
+    let mut stmts = vec![format!("ALTER TABLE kerai.{table} ENABLE ROW LEVEL SECURITY")];
+    for (suffix, body) in [
+        ("read", format!("FOR SELECT USING ({read})")),
+        ("insert", format!("FOR INSERT WITH CHECK ({write})")),
+        ("update", format!("FOR UPDATE USING ({write}) WITH CHECK ({write})")),
+        ("delete", format!("FOR DELETE USING ({write})")),
+    ] {
+        stmts.push(format!("DROP POLICY IF EXISTS kerai_{table}_{suffix} ON kerai.{table}"));
+        stmts.push(format!("CREATE POLICY kerai_{table}_{suffix} ON kerai.{table} {body}"));
+    }
+    stmts
+}
+
+/// Turn on row-level security for nodes, edges and versions, (re)creating
+/// their policies. Safe to run again. With `force`, the policies also bind
+/// the tables' owner.
+///
+/// Returns `{tables, policies, forced}`.
+#[pg_extern]
+fn enable_rls(force: default!(bool, false)) -> pgrx::JsonB {
+    let mut policies = 0;
+    for (table, check) in POLICY_TABLES {
+        for stmt in policy_statements(table, check) {
+            if stmt.starts_with("CREATE POLICY") {
+                policies += 1;
+            }
+            Spi::run(&stmt).unwrap_or_else(|e| error!("enable_rls failed on {}: {}", table, e));
+        }
+        let force_sql = if force { "FORCE" } else { "NO FORCE" };
+        Spi::run(&format!("ALTER TABLE kerai.{table} {force_sql} ROW LEVEL SECURITY")).unwrap();
+    }
+    let tables: Vec<&str> = POLICY_TABLES.iter().map(|(t, _)| *t).collect();
+    pgrx::JsonB(json!({"tables": tables, "policies": policies, "forced": force}))
+}
+
+/// Turn row-level security off again and drop kerai's policies.
+#[pg_extern]
+fn disable_rls() -> pgrx::JsonB {
+    for (table, _) in POLICY_TABLES {
+        for suffix in ["read", "insert", "update", "delete"] {
+            Spi::run(&format!("DROP POLICY IF EXISTS kerai_{table}_{suffix} ON kerai.{table}"))
+                .unwrap();
+        }
+        Spi::run(&format!(
+            "ALTER TABLE kerai.{table} NO FORCE ROW LEVEL SECURITY, DISABLE ROW LEVEL SECURITY"
+        ))
+        .unwrap();
+    }
+    let tables: Vec<&str> = POLICY_TABLES.iter().map(|(t, _)| *t).collect();
+    pgrx::JsonB(json!({"tables": tables, "disabled": true}))
+}
+
+/// Act as the user of `token`, a session or API token, for row-level
+/// security, for the rest of the session or, with `local`, the current
+/// transaction. Fails for unknown, expired or revoked tokens, tokens with
+/// no user, and users who are not allowed in.
+///
+/// Returns `{user_id}`.
+#[pg_extern]
+fn set_principal(token: &str, local: default!(bool, false)) -> pgrx::JsonB {
+    let set = |value: &str| {
+        Spi::run(&format!(
+            "SELECT set_config('kerai.current_principal', '{}', {})",
+            sql_escape(value),
+            local,
+        ))
+        .unwrap()
+    };
+    set(token);
+    // Resolved by the SECURITY DEFINER helper, so direct SQL users need no
+    // access to kerai.sessions or kerai.api_tokens
+    match Spi::get_one::<String>("SELECT kerai.principal_user()::text").unwrap_or(None) {
+        Some(user_id) => pgrx::JsonB(json!({"user_id": user_id})),
+        None => {
+            set("");
+            error!("No active session or API token matches that token");
+        }
+    }
+}
+
+/// Stop acting as any user; row-level security then shows no rows.
+#[pg_extern]
+fn clear_principal() -> bool {
+    Spi::run("SELECT set_config('kerai.current_principal', '', false)").unwrap();
+    true
+}
+
+/// Let `user_id` (every allowed user when NULL) read, or with `can_write`
+/// also change, the nodes at and under `path`. Re-granting updates
+/// `can_write`.
+///
+/// Returns the grant `{id, path, user_id, can_write}`.
+#[pg_extern]
+fn grant_path(path: &str, user_id: Option<pgrx::Uuid>, can_write: default!(bool, false)) -> pgrx::JsonB {
+    let user_sql = user_id
+        .map(|u| format!("'{}'::uuid", u))
+        .unwrap_or_else(|| "NULL".to_string());
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "INSERT INTO kerai.path_grants (path, user_id, can_write)
+         VALUES ({}, {}, {})
+         ON CONFLICT (path, user_id) DO UPDATE SET can_write = EXCLUDED.can_write
+         RETURNING jsonb_build_object('id', id, 'path', path::text, 'user_id', user_id, 'can_write', can_write)",
+        sql_ltree(path),
+        user_sql,
+        can_write,
+    ))
+    .unwrap_or_else(|e| error!("Failed to grant {}: {}", path, e))
+    .unwrap()
+}
+
+/// Remove the grant of `path` to `user_id` (or to every user when NULL).
+/// Returns whether one existed.
+#[pg_extern]
+fn revoke_path(path: &str, user_id: Option<pgrx::Uuid>) -> bool {
+    let user_sql = user_id
+        .map(|u| format!("= '{}'::uuid", u))
+        .unwrap_or_else(|| "IS NULL".to_string());
+    Spi::get_one::<bool>(&format!(
+        "WITH d AS (DELETE FROM kerai.path_grants WHERE path = {} AND user_id {} RETURNING 1)
+         SELECT EXISTS(SELECT 1 FROM d)",
+        sql_ltree(path),
+        user_sql,
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_are_dropped_before_being_created() {
+        let stmts = policy_statements("edges", "kerai.rls_node_access(source_id, {write})");
+        assert_eq!(stmts[0], "ALTER TABLE kerai.edges ENABLE ROW LEVEL SECURITY");
+        assert_eq!(stmts.len(), 9);
+        for pair in stmts[1..].chunks(2) {
+            assert!(pair[0].starts_with("DROP POLICY IF EXISTS kerai_edges_"));
+            assert!(pair[1].starts_with("CREATE POLICY kerai_edges_"));
+        }
+        assert!(stmts[2].contains("FOR SELECT USING (kerai.rls_node_access(source_id, false))"));
+        assert!(stmts[8].contains("FOR DELETE USING (kerai.rls_node_access(source_id, true))"));
+    }
+}
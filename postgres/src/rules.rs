@@ -0,0 +1,590 @@
+/// Custom lint rules — project rules defined in SQL rather than code.
+///
+/// A rule in `kerai.rules` matches nodes by a SQL `predicate` over the node
+/// (aliased `n`), an lquery `pattern` on its path, or both. `run_rules`
+/// leaves a suggestion on each matching node, exactly as the built-in
+/// rules do (a `suggestion` node under the file plus a `suggests` edge), so
+/// reconstruction, the pre-commit hook and `kerai lint` treat them alike.
+/// A suggestion whose node no longer matches is marked applied on the next
+/// run.
+///
+/// A rule may also carry a `fix`, the operation that resolves a match, and
+/// the reward work type credited when someone takes it.
+/// `accept_suggestion` applies the fix through `kerai.apply_op`, so it is
+/// versioned like any edit, marks the suggestion applied and mints the
+/// reward; `dismiss_suggestion` closes it without an edit, and a dismissed
+/// suggestion is never made again.
+///
+/// Predicates are SQL run as whoever calls `run_rules`; only let trusted
+/// roles write to `kerai.rules`.
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::sql::{sql_escape, sql_jsonb, sql_text, sql_uuid};
+
+/// Severities a rule may give its suggestions, in rising order.
+const SEVERITIES: &[&str] = &["info", "warning"];
+
+/// Marks suggestions made here, so runs never touch built-in ones.
+const ORIGIN: &str = "rules";
+
+/// Operations a fix may make: edits to nodes and edges.
+const FIX_OPS: &[&str] = &[
+    "insert_node",
+    "update_content",
+    "update_metadata",
+    "move_node",
+    "delete_node",
+    "insert_edge",
+    "delete_edge",
+];
+
+/// Work type credited for an accepted suggestion whose rule names none.
+const ACCEPT_REWARD: &str = "accept_suggestion";
+
+/// Whether `name` can be a rule id. Rule ids are echoed in `// kerai:`
+/// comments, whose parser accepts letters, digits, `_` and `-`.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Query for the ids of the nodes a rule matches.
+fn matching(predicate: Option<&str>, pattern: Option<&str>) -> String {
+    format!(
+        "SELECT n.id FROM kerai.nodes n
+         WHERE n.kind <> 'suggestion' AND {outside} AND {pattern} AND ({predicate})",
+        outside = crate::sandboxes::unsandboxed("n.path"),
+        pattern = pattern.map_or("true".to_string(), |p| format!(
+            "n.path ~ {}::lquery",
+            sql_text(p)
+        )),
+        predicate = predicate.unwrap_or("true"),
+    )
+}
+
+/// Check a fix: `{op_type, payload, node_id}`, `op_type` one of
+/// [`FIX_OPS`] and `payload` and `node_id` optional.
+fn check_fix(fix: &Value) -> Result<(), String> {
+    let op_type = fix["op_type"].as_str().ok_or("Fix needs an op_type")?;
+    if !FIX_OPS.contains(&op_type) {
+        return Err(format!("Fix op_type must be one of {}", FIX_OPS.join(", ")));
+    }
+    if !(fix["payload"].is_object() || fix["payload"].is_null()) {
+        return Err("Fix payload must be an object".to_string());
+    }
+    if !(fix["node_id"].is_string() || fix["node_id"].is_null()) {
+        return Err("Fix node_id must be a string".to_string());
+    }
+    Ok(())
+}
+
+/// `fix` with `{kind}`, `{name}`, `{path}` and `{content}` in its strings
+/// filled in from `target`, a kerai.nodes row as JSON.
+fn fill_fix(fix: &Value, target: &Value) -> Value {
+    match fix {
+        Value::String(s) => {
+            let field = |key: &str| target[key].as_str().unwrap_or_default();
+            let name = target["metadata"]["name"]
+                .as_str()
+                .unwrap_or_else(|| field("content"));
+            Value::String(
+                s.replace("{kind}", field("kind"))
+                    .replace("{name}", name)
+                    .replace("{path}", field("path"))
+                    .replace("{content}", field("content")),
+            )
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|i| fill_fix(i, target)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), fill_fix(v, target)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Condition that suggestion `sg` was made by this engine for rule `name`.
+fn made_by(name: &str) -> String {
+    format!(
+        "sg.kind = 'suggestion' AND sg.metadata->>'rule' = {} AND sg.metadata->>'origin' = '{ORIGIN}'",
+        sql_text(name)
+    )
+}
+
+/// Define a rule, or redefine the one with the same name. `message` may
+/// use `{kind}`, `{name}` and `{path}` for the matched node. At least one
+/// of `predicate` and `pattern` is needed; the predicate is tried once so
+/// a broken one fails here rather than at the next run.
+///
+/// `fix` is the operation accepting a suggestion applies, as `{op_type,
+/// payload, node_id}` for `kerai.apply_op`; its strings may use the same
+/// placeholders and `{content}`, and `node_id` defaults to the matched
+/// node. `reward_work_type` is the reward_schedule entry credited on
+/// acceptance instead of `accept_suggestion`.
+#[pg_extern]
+fn add_rule(
+    name: &str,
+    message: &str,
+    predicate: default!(Option<&str>, "NULL"),
+    pattern: default!(Option<&str>, "NULL"),
+    severity: default!(&str, "'warning'"),
+    category: default!(&str, "'custom'"),
+    fix: default!(Option<pgrx::JsonB>, "NULL"),
+    reward_work_type: default!(Option<&str>, "NULL"),
+) -> pgrx::JsonB {
+    if !valid_name(name) {
+        error!(
+            "Rule name '{}' may only contain letters, digits, '_' and '-'",
+            name
+        );
+    }
+    if predicate.is_none() && pattern.is_none() {
+        error!("Rule '{}' needs a predicate or a pattern", name);
+    }
+    if !SEVERITIES.contains(&severity) {
+        error!("Rule severity must be one of {}", SEVERITIES.join(", "));
+    }
+    let fix = fix.map(|j| j.0);
+    if let Some(Err(e)) = fix.as_ref().map(check_fix) {
+        error!("Rule '{}': {}", name, e);
+    }
+    if let Some(work_type) = reward_work_type {
+        let scheduled = Spi::get_one::<bool>(&format!(
+            "SELECT EXISTS (SELECT 1 FROM kerai.reward_schedule WHERE work_type = {})",
+            sql_text(work_type)
+        ))
+        .unwrap()
+        .unwrap_or(false);
+        if !scheduled {
+            error!("No reward schedule for work type '{}'", work_type);
+        }
+    }
+    Spi::run(&format!("{} LIMIT 0", matching(predicate, pattern))).unwrap();
+
+    let opt = |v: Option<&str>| v.map_or("NULL".to_string(), sql_text);
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "INSERT INTO kerai.rules
+             (name, message, predicate, pattern, severity, category, fix, reward_work_type)
+         VALUES ({name}, {message}, {predicate}, {pattern}::lquery, {severity}, {category},
+                 {fix}, {reward_work_type})
+         ON CONFLICT (name) DO UPDATE SET
+             message = EXCLUDED.message, predicate = EXCLUDED.predicate,
+             pattern = EXCLUDED.pattern, severity = EXCLUDED.severity,
+             category = EXCLUDED.category, fix = EXCLUDED.fix,
+             reward_work_type = EXCLUDED.reward_work_type, updated_at = now()
+         RETURNING to_jsonb(rules.*)",
+        name = sql_text(name),
+        message = sql_text(message),
+        predicate = opt(predicate),
+        pattern = opt(pattern),
+        severity = sql_text(severity),
+        category = sql_text(category),
+        fix = fix.as_ref().map_or("NULL".to_string(), sql_jsonb),
+        reward_work_type = opt(reward_work_type),
+    ))
+    .unwrap()
+    .unwrap()
+}
+
+/// Delete a rule and the suggestions it made. Returns `{name, suggestions}`.
+#[pg_extern]
+fn remove_rule(name: &str) -> pgrx::JsonB {
+    let found = Spi::get_one::<i64>(&format!(
+        "WITH gone AS (DELETE FROM kerai.rules WHERE name = {} RETURNING 1)
+         SELECT count(*) FROM gone",
+        sql_text(name)
+    ))
+    .unwrap()
+    .unwrap_or(0);
+    if found == 0 {
+        error!("Rule not found: {}", name);
+    }
+
+    let made = format!("SELECT sg.id FROM kerai.nodes sg WHERE {}", made_by(name));
+    Spi::run(&format!(
+        "DELETE FROM kerai.edges WHERE source_id IN ({made}) OR target_id IN ({made})"
+    ))
+    .unwrap();
+    let suggestions = Spi::get_one::<i64>(&format!(
+        "WITH gone AS (DELETE FROM kerai.nodes sg WHERE {} RETURNING 1)
+         SELECT count(*) FROM gone",
+        made_by(name)
+    ))
+    .unwrap()
+    .unwrap_or(0);
+
+    pgrx::JsonB(json!({ "name": name, "suggestions": suggestions }))
+}
+
+/// All rules, by name.
+#[pg_extern]
+fn list_rules() -> pgrx::JsonB {
+    let rules = Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(to_jsonb(r.*) ORDER BY r.name), '[]'::jsonb) FROM kerai.rules r",
+    )
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+    pgrx::JsonB(rules)
+}
+
+/// Evaluate one rule: suggest on new matches, resolve what no longer
+/// matches. Returns `{name, matched, created, resolved}`.
+fn run_rule(rule: &Value) -> Value {
+    let name = rule["name"].as_str().unwrap_or_default();
+    let matches = matching(rule["predicate"].as_str(), rule["pattern"].as_str());
+
+    let made = Spi::get_one::<pgrx::JsonB>(&format!(
+        "WITH RECURSIVE matched AS ({matches}),
+        up AS (
+            -- Walk up from each match to the file or document holding it
+            SELECT m.id AS target_id, n.id, n.kind, n.parent_id
+            FROM matched m JOIN kerai.nodes n ON n.id = m.id
+            UNION ALL
+            SELECT up.target_id, p.id, p.kind, p.parent_id
+            FROM up JOIN kerai.nodes p ON p.id = up.parent_id
+            WHERE up.kind NOT IN ('file', 'document')
+        ), owner AS (
+            SELECT target_id, id AS file_id FROM up WHERE kind IN ('file', 'document')
+        ), fresh AS (
+            SELECT m.id AS target_id, kerai.uuid7() AS suggestion_id
+            FROM matched m
+            WHERE NOT EXISTS (
+                SELECT 1 FROM kerai.edges e
+                JOIN kerai.nodes sg ON sg.id = e.source_id
+                WHERE e.target_id = m.id AND e.relation = 'suggests'
+                  AND {made_by} AND sg.metadata->>'status' IN ('emitted', 'dismissed')
+            )
+        ), made AS (
+            INSERT INTO kerai.nodes (id, instance_id, kind, language, content, parent_id, position, metadata)
+            SELECT f.suggestion_id, t.instance_id, 'suggestion', t.language,
+                   replace(replace(replace({message},
+                       '{{kind}}', t.kind),
+                       '{{name}}', COALESCE(t.metadata->>'name', t.content, '')),
+                       '{{path}}', COALESCE(t.path::text, '')),
+                   COALESCE(o.file_id, t.parent_id),
+                   COALESCE((t.metadata->>'start_line')::int, (t.metadata->>'line')::int, t.position),
+                   jsonb_strip_nulls(jsonb_build_object(
+                       'rule', {rule},
+                       'status', 'emitted',
+                       'severity', {severity},
+                       'category', {category},
+                       'origin', '{ORIGIN}',
+                       'fix', {fix}
+                   ))
+            FROM fresh f
+            JOIN kerai.nodes t ON t.id = f.target_id
+            LEFT JOIN owner o ON o.target_id = f.target_id
+            RETURNING id
+        ), linked AS (
+            INSERT INTO kerai.edges (source_id, target_id, relation, metadata)
+            SELECT f.suggestion_id, f.target_id, 'suggests', jsonb_build_object('rule', {rule})
+            FROM fresh f JOIN made m ON m.id = f.suggestion_id
+            RETURNING 1
+        )
+        SELECT jsonb_build_object(
+            'matched', (SELECT count(*) FROM matched),
+            'created', (SELECT count(*) FROM linked)
+        )",
+        made_by = made_by(name),
+        message = sql_text(rule["message"].as_str().unwrap_or_default()),
+        rule = sql_text(name),
+        severity = sql_text(rule["severity"].as_str().unwrap_or("warning")),
+        category = sql_text(rule["category"].as_str().unwrap_or("custom")),
+        fix = sql_jsonb(&rule["fix"]),
+    ))
+    .unwrap()
+    .map_or(json!({}), |j| j.0);
+
+    let resolved = Spi::get_one::<i64>(&format!(
+        "WITH done AS (
+            UPDATE kerai.nodes sg
+            SET metadata = jsonb_set(sg.metadata, '{{status}}', '\"applied\"')
+            FROM kerai.edges e
+            WHERE e.source_id = sg.id AND e.relation = 'suggests'
+              AND {made_by} AND sg.metadata->>'status' = 'emitted'
+              AND e.target_id NOT IN ({matches})
+            RETURNING 1
+        )
+        SELECT count(*) FROM done",
+        made_by = made_by(name),
+    ))
+    .unwrap()
+    .unwrap_or(0);
+
+    json!({
+        "name": name,
+        "matched": made["matched"],
+        "created": made["created"],
+        "resolved": resolved,
+    })
+}
+
+/// Evaluate every rule, or only `rule_name`, creating suggestions on
+/// matching nodes that have none for the rule (dismissed ones are not
+/// repeated) and marking applied those whose node stopped matching.
+///
+/// Returns `{created, resolved, rules: [{name, matched, created,
+/// resolved}]}`.
+#[pg_extern]
+fn run_rules(rule_name: default!(Option<&str>, "NULL")) -> pgrx::JsonB {
+    let filter = rule_name.map_or(String::new(), |n| {
+        format!("WHERE name = '{}'", sql_escape(n))
+    });
+    let rules = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(to_jsonb(r.*) ORDER BY r.name), '[]'::jsonb)
+         FROM kerai.rules r {filter}"
+    ))
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+    let rules = rules.as_array().cloned().unwrap_or_default();
+    if let (Some(name), true) = (rule_name, rules.is_empty()) {
+        error!("Rule not found: {}", name);
+    }
+
+    let results: Vec<Value> = rules.iter().map(run_rule).collect();
+    let total = |key: &str| results.iter().filter_map(|r| r[key].as_i64()).sum::<i64>();
+    pgrx::JsonB(json!({
+        "created": total("created"),
+        "resolved": total("resolved"),
+        "rules": results,
+    }))
+}
+
+/// Open (`emitted`) suggestions from every rule, built-in or custom,
+/// grouped by the file or document they are in; `file` keeps only files
+/// whose name or source path starts with it.
+///
+/// Returns `[{file, file_id, suggestions: [{id, rule, severity, category,
+/// message, line, target_id, target_kind}]}]`.
+#[pg_extern]
+fn list_suggestions(file: default!(Option<&str>, "NULL")) -> pgrx::JsonB {
+    let filter = file.map_or("true".to_string(), |f| {
+        format!(
+            "(f.content LIKE '{0}%' OR f.metadata->>'source_path' LIKE '{0}%')",
+            sql_escape(
+                &f.replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            )
+        )
+    });
+    let list = Spi::get_one::<pgrx::JsonB>(&format!(
+        "WITH RECURSIVE open AS (
+            SELECT sg.id, sg.content, sg.position, sg.metadata, sg.parent_id, e.target_id
+            FROM kerai.nodes sg
+            LEFT JOIN kerai.edges e ON e.source_id = sg.id AND e.relation = 'suggests'
+            WHERE sg.kind = 'suggestion' AND sg.metadata->>'status' = 'emitted'
+        ), up AS (
+            SELECT o.id AS suggestion_id, p.id, p.kind, p.parent_id
+            FROM open o JOIN kerai.nodes p ON p.id = o.parent_id
+            UNION ALL
+            SELECT up.suggestion_id, p.id, p.kind, p.parent_id
+            FROM up JOIN kerai.nodes p ON p.id = up.parent_id
+            WHERE up.kind NOT IN ('file', 'document')
+        ), placed AS (
+            SELECT o.*, u.id AS file_id
+            FROM open o
+            JOIN up u ON u.suggestion_id = o.id AND u.kind IN ('file', 'document')
+        ), per_file AS (
+            SELECT f.id, COALESCE(f.metadata->>'source_path', f.content) AS file,
+                   jsonb_agg(jsonb_build_object(
+                       'id', s.id,
+                       'rule', s.metadata->>'rule',
+                       'severity', COALESCE(s.metadata->>'severity', 'info'),
+                       'category', s.metadata->>'category',
+                       'message', s.content,
+                       'line', s.position,
+                       'target_id', s.target_id,
+                       'target_kind', t.kind
+                   ) ORDER BY s.position, s.metadata->>'rule') AS suggestions
+            FROM placed s
+            JOIN kerai.nodes f ON f.id = s.file_id
+            LEFT JOIN kerai.nodes t ON t.id = s.target_id
+            WHERE {filter} AND {outside}
+            GROUP BY f.id, f.content, f.metadata
+        )
+        SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'file', file, 'file_id', id, 'suggestions', suggestions
+        ) ORDER BY file), '[]'::jsonb)
+        FROM per_file",
+        outside = crate::sandboxes::unsandboxed("f.path"),
+    ))
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+    pgrx::JsonB(list)
+}
+
+/// Suggestion `id` with what closing it needs: `{rule, status, fix,
+/// reward_work_type, target_id, target}`, `target` being the suggested
+/// node's row. A suggestion made here falls back to its rule's current fix.
+/// Errors unless the suggestion exists and is still open.
+fn open_suggestion(id: &str) -> Value {
+    let found = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_build_object(
+            'rule', sg.metadata->>'rule',
+            'status', sg.metadata->>'status',
+            'fix', COALESCE(sg.metadata->'fix', r.fix),
+            'reward_work_type', r.reward_work_type,
+            'target_id', e.target_id,
+            'target', to_jsonb(t.*)
+        )
+        FROM kerai.nodes sg
+        LEFT JOIN kerai.edges e ON e.source_id = sg.id AND e.relation = 'suggests'
+        LEFT JOIN kerai.nodes t ON t.id = e.target_id
+        LEFT JOIN kerai.rules r
+            ON r.name = sg.metadata->>'rule' AND sg.metadata->>'origin' = '{ORIGIN}'
+        WHERE sg.id = {} AND sg.kind = 'suggestion'
+        LIMIT 1",
+        sql_uuid(id)
+    ))
+    .unwrap()
+    .map(|j| j.0)
+    .unwrap_or_else(|| error!("Suggestion not found: {}", id));
+    let status = found["status"].as_str().unwrap_or("emitted");
+    if status != "emitted" {
+        error!("Suggestion {} is already {}", id, status);
+    }
+    found
+}
+
+/// Apply one operation through `kerai.apply_op`; returns its result.
+fn apply(op_type: &str, node_id: Option<&str>, payload: &Value) -> Value {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT kerai.apply_op({}, {}, {})",
+        sql_text(op_type),
+        node_id.map_or_else(|| "NULL".to_string(), sql_uuid),
+        sql_jsonb(payload),
+    ))
+    .unwrap_or_else(|e| error!("{} failed: {}", op_type, e))
+    .map_or(Value::Null, |j| j.0)
+}
+
+/// Accept an open suggestion: apply its fix, if it has one, mark it
+/// applied and credit its rule's reward work type (`accept_suggestion`
+/// unless the rule names another) to this instance.
+///
+/// Returns `{id, rule, status, fix_applied, op, reward}`, `op` being
+/// `kerai.apply_op`'s result for the fix and `reward` the mint, both null
+/// when there was none.
+#[pg_extern]
+fn accept_suggestion(suggestion_id: pgrx::Uuid) -> pgrx::JsonB {
+    let id = suggestion_id.to_string();
+    let suggestion = open_suggestion(&id);
+    let rule = suggestion["rule"].clone();
+
+    let op = match &suggestion["fix"] {
+        Value::Null => Value::Null,
+        fix => {
+            let fix = fill_fix(fix, &suggestion["target"]);
+            if let Err(e) = check_fix(&fix) {
+                error!("Suggestion {}: {}", id, e);
+            }
+            let node_id = fix["node_id"]
+                .as_str()
+                .or_else(|| suggestion["target_id"].as_str());
+            let payload = match &fix["payload"] {
+                Value::Null => json!({}),
+                payload => payload.clone(),
+            };
+            let op_type = fix["op_type"].as_str().unwrap_or_default();
+            apply(op_type, node_id, &payload)
+        }
+    };
+    let fix_applied = !op.is_null();
+    apply(
+        "update_metadata",
+        Some(&id),
+        &json!({"merge": {"status": "applied", "resolution": "accepted"}}),
+    );
+
+    let work_type = suggestion["reward_work_type"]
+        .as_str()
+        .unwrap_or(ACCEPT_REWARD);
+    let details = json!({"rule": rule, "fix_applied": fix_applied});
+    let reward = crate::currency::reward(work_type, "suggestion", Some(&id), details);
+
+    pgrx::JsonB(json!({
+        "id": id,
+        "rule": rule,
+        "status": "applied",
+        "fix_applied": fix_applied,
+        "op": op,
+        "reward": reward,
+    }))
+}
+
+/// Dismiss an open suggestion without changing its node, noting `reason`.
+/// Neither its rule nor the built-in rules make it again.
+///
+/// Returns `{id, rule, status, reason}`.
+#[pg_extern]
+fn dismiss_suggestion(
+    suggestion_id: pgrx::Uuid,
+    reason: default!(Option<&str>, "NULL"),
+) -> pgrx::JsonB {
+    let id = suggestion_id.to_string();
+    let suggestion = open_suggestion(&id);
+    apply(
+        "update_metadata",
+        Some(&id),
+        &json!({"merge": {
+            "status": "dismissed",
+            "resolution": "dismissed",
+            "dismiss_reason": reason,
+        }}),
+    );
+    pgrx::JsonB(json!({
+        "id": id,
+        "rule": suggestion["rule"],
+        "status": "dismissed",
+        "reason": reason,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_names_round_trip_through_kerai_comments() {
+        assert!(valid_name("no-unwrap"));
+        assert!(valid_name("max_args_5"));
+        assert!(!valid_name(""));
+        assert!(!valid_name("no unwrap"));
+        assert!(!valid_name("rule(1)"));
+    }
+
+    #[test]
+    fn matching_combines_pattern_and_predicate() {
+        let sql = matching(Some("n.kind = 'fn'"), Some("*.tests.*"));
+        assert!(sql.contains("n.path ~ '*.tests.*'::lquery"));
+        assert!(sql.contains("(n.kind = 'fn')"));
+        assert!(matching(None, Some("x")).ends_with("AND (true)"));
+    }
+
+    #[test]
+    fn fixes_are_filled_from_the_target() {
+        let fix = json!({
+            "op_type": "update_content",
+            "payload": {"new_content": "{content}.expect(\"{name} in {path}\")"},
+        });
+        let target = json!({
+            "kind": "expr",
+            "content": "x.unwrap()",
+            "path": "app.main",
+            "metadata": {"name": "x"},
+        });
+        let filled = fill_fix(&fix, &target);
+        assert_eq!(
+            filled["payload"]["new_content"],
+            "x.unwrap().expect(\"x in app.main\")"
+        );
+        assert!(check_fix(&filled).is_ok());
+        assert!(check_fix(&json!({"op_type": "transfer_koi"})).is_err());
+        assert!(check_fix(&json!({"op_type": "delete_node", "payload": 1})).is_err());
+    }
+}
@@ -0,0 +1,523 @@
+/// Sandboxes — disposable copies of a subtree for experiments.
+///
+/// `create_sandbox` clones every node under a path (and the edges leaving
+/// them) into kerai.nodes with fresh ids, under a `kerai_sandbox_<id>`
+/// path prefix. The copies are ordinary nodes, so every kerai function
+/// works on them, and anything done to them leaves the original untouched.
+/// Each copy remembers its origin and the origin's row at clone time.
+///
+/// `promote_sandbox` replays what changed in the sandbox onto the origin
+/// as signed `apply_op` operations: content, metadata keys, moves, new
+/// nodes and deletions. A field the origin also changed since the clone
+/// is a conflict and keeps the origin's value. Promoted, discarded and
+/// expired sandboxes have their nodes removed; the sandbox cleaner worker
+/// expires sandboxes past their `expires_at`.
+use std::collections::HashMap;
+
+use pgrx::prelude::*;
+use serde_json::{json, Map, Value};
+
+use crate::sql::{sql_escape, sql_jsonb, sql_text, sql_uuid};
+
+/// Condition that a node, by its `column` path, is outside every sandbox.
+/// Parser lookups by filename use it so re-parsing a file leaves its
+/// sandbox copies alone.
+pub(crate) fn unsandboxed(column: &str) -> String {
+    format!("({column} IS NULL OR NOT {column} ~ 'kerai_sandbox_*.*')")
+}
+
+/// Row of an active sandbox as `{id, prefix, source_path}`.
+fn active_sandbox(sandbox_id: &str) -> Value {
+    let row = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_build_object('id', id, 'prefix', prefix, 'source_path', source_path::text,
+                                   'status', status)
+         FROM kerai.sandboxes WHERE id = {}",
+        sql_uuid(sandbox_id),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("Sandbox not found: {}", sandbox_id))
+    .0;
+    if row["status"] != "active" {
+        error!(
+            "Sandbox {} is {}",
+            sandbox_id,
+            row["status"].as_str().unwrap_or("")
+        );
+    }
+    row
+}
+
+/// CTE `tree(id)`: a sandbox's copied nodes plus everything added below them.
+fn tree_cte(sandbox_id: &str) -> String {
+    format!(
+        "WITH RECURSIVE tree AS (
+            SELECT n.id, 0 AS depth FROM kerai.nodes n
+            JOIN kerai.sandbox_nodes m ON m.node_id = n.id
+            WHERE m.sandbox_id = {sid}
+            UNION
+            SELECT c.id, t.depth + 1 FROM kerai.nodes c JOIN tree t ON c.parent_id = t.id
+        )",
+        sid = sql_uuid(sandbox_id),
+    )
+}
+
+/// Delete a sandbox's nodes and whatever refers to them.
+fn remove_nodes(sandbox_id: &str) -> i64 {
+    let ids = format!(
+        "{}, ids AS (SELECT DISTINCT id FROM tree)",
+        tree_cte(sandbox_id)
+    );
+    let removed = Spi::get_one::<i64>(&format!("{ids} SELECT count(*) FROM ids"))
+        .unwrap()
+        .unwrap_or(0);
+    for stmt in [
+        "DELETE FROM kerai.edges
+         WHERE source_id IN (SELECT id FROM ids) OR target_id IN (SELECT id FROM ids)",
+        "DELETE FROM kerai.associations
+         WHERE source_id IN (SELECT id FROM ids) OR target_id IN (SELECT id FROM ids)",
+        "DELETE FROM kerai.perspectives
+         WHERE node_id IN (SELECT id FROM ids) OR context_id IN (SELECT id FROM ids)",
+        "DELETE FROM kerai.versions WHERE node_id IN (SELECT id FROM ids)",
+        "UPDATE kerai.tasks SET scope_node_id = NULL WHERE scope_node_id IN (SELECT id FROM ids)",
+        "DELETE FROM kerai.nodes WHERE id IN (SELECT id FROM ids)",
+    ] {
+        Spi::run(&format!("{ids} {stmt}")).expect("Failed to remove sandbox nodes");
+    }
+    removed
+}
+
+/// Close a sandbox: drop its nodes and record how it ended.
+fn close(sandbox_id: &str, status: &str, result: &Value) -> i64 {
+    let removed = remove_nodes(sandbox_id);
+    let sid = sql_uuid(sandbox_id);
+    Spi::run(&format!(
+        "DELETE FROM kerai.sandbox_nodes WHERE sandbox_id = {sid}"
+    ))
+    .unwrap();
+    Spi::run(&format!(
+        "UPDATE kerai.sandboxes SET status = {}, closed_at = now(), result = {} WHERE id = {sid}",
+        sql_text(status),
+        sql_jsonb(result),
+    ))
+    .unwrap();
+    removed
+}
+
+/// Clone the subtree at `from_path` into a new sandbox that expires after
+/// `ttl_minutes` (default `kerai.sandbox_ttl`).
+///
+/// Returns `{id, prefix, path, source_path, nodes, edges, expires_at}`;
+/// `path` is where the copy of `from_path` now lives.
+#[pg_extern]
+fn create_sandbox(from_path: &str, ttl_minutes: default!(Option<i32>, "NULL")) -> pgrx::JsonB {
+    let ttl = ttl_minutes
+        .unwrap_or_else(|| crate::workers::SANDBOX_TTL.get())
+        .max(1);
+    let from = sql_escape(from_path);
+    let roots = format!(
+        "SELECT n.id FROM kerai.nodes n
+         LEFT JOIN kerai.nodes p ON p.id = n.parent_id
+         WHERE n.path <@ '{from}'::ltree AND {outside}
+           AND (p.id IS NULL OR p.path IS NULL OR NOT p.path <@ '{from}'::ltree)",
+        outside = unsandboxed("n.path"),
+    );
+    let found = Spi::get_one::<bool>(&format!("SELECT EXISTS({roots})"))
+        .unwrap()
+        .unwrap_or(false);
+    if !found {
+        error!("No nodes under path: {}", from_path);
+    }
+
+    let (id, prefix) = match Spi::get_two::<String, String>(&format!(
+        "INSERT INTO kerai.sandboxes (id, prefix, source_path, expires_at)
+         SELECT g, 'kerai_sandbox_' || replace(g::text, '-', ''), '{from}'::ltree,
+                now() + make_interval(mins => {ttl})
+         FROM kerai.uuid7() g
+         RETURNING id::text, prefix",
+    ))
+    .unwrap()
+    {
+        (Some(id), Some(prefix)) => (id, prefix),
+        _ => error!("Failed to create sandbox"),
+    };
+    let sid = sql_uuid(&id);
+
+    // Copy the subtree with fresh ids, remembering each origin row
+    let nodes = Spi::get_one::<i64>(&format!(
+        "WITH RECURSIVE sub AS (
+            {roots}
+            UNION
+            SELECT c.id FROM kerai.nodes c JOIN sub ON c.parent_id = sub.id
+        ), src AS (
+            SELECT n.*, kerai.uuid7() AS new_id FROM kerai.nodes n WHERE n.id IN (SELECT id FROM sub)
+        ), mapped AS (
+            INSERT INTO kerai.sandbox_nodes (sandbox_id, node_id, origin_id, base)
+            SELECT {sid}, new_id, id, to_jsonb(src) - 'tsv' - 'new_id' FROM src
+        ), copied AS (
+            INSERT INTO kerai.nodes (id, instance_id, kind, language, content, parent_id,
+                                     position, path, metadata, content_hash)
+            SELECT s.new_id, s.instance_id, s.kind, s.language, s.content, p.new_id,
+                   s.position, '{prefix}'::ltree || s.path, s.metadata, s.content_hash
+            FROM src s LEFT JOIN src p ON p.id = s.parent_id
+            RETURNING 1
+        )
+        SELECT count(*) FROM copied",
+    ))
+    .unwrap()
+    .unwrap_or(0);
+
+    // Edges leaving copied nodes; ones into the subtree point at the copies
+    let edges = Spi::get_one::<i64>(&format!(
+        "WITH copied AS (
+            INSERT INTO kerai.edges (source_id, target_id, relation, metadata)
+            SELECT s.node_id, COALESCE(t.node_id, e.target_id), e.relation, e.metadata
+            FROM kerai.edges e
+            JOIN kerai.sandbox_nodes s ON s.sandbox_id = {sid} AND s.origin_id = e.source_id
+            LEFT JOIN kerai.sandbox_nodes t ON t.sandbox_id = {sid} AND t.origin_id = e.target_id
+            ON CONFLICT DO NOTHING
+            RETURNING 1
+        )
+        SELECT count(*) FROM copied",
+    ))
+    .unwrap()
+    .unwrap_or(0);
+
+    let expires_at = Spi::get_one::<String>(&format!(
+        "SELECT expires_at::text FROM kerai.sandboxes WHERE id = {sid}"
+    ))
+    .unwrap()
+    .unwrap_or_default();
+
+    pgrx::JsonB(json!({
+        "id": id,
+        "prefix": prefix,
+        "path": format!("{prefix}.{from_path}"),
+        "source_path": from_path,
+        "nodes": nodes,
+        "edges": edges,
+        "expires_at": expires_at,
+    }))
+}
+
+/// Node-row fields a sandbox edit can change, as the ops that carry them:
+/// `(op_type, payload)` turning `base` into `sandbox`. `parent` is the
+/// sandbox node's parent translated back to origin ids.
+pub fn changes(base: &Value, sandbox: &Value, parent: Option<&str>) -> Vec<(&'static str, Value)> {
+    let mut ops = Vec::new();
+    if sandbox["content"] != base["content"] {
+        ops.push((
+            "update_content",
+            json!({"new_content": sandbox["content"].as_str().unwrap_or("")}),
+        ));
+    }
+    let merge: Map<String, Value> = sandbox["metadata"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(k, v)| base["metadata"].get(k.as_str()) != Some(v))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    if !merge.is_empty() {
+        ops.push(("update_metadata", json!({ "merge": merge })));
+    }
+    if parent != base["parent_id"].as_str() || sandbox["position"] != base["position"] {
+        ops.push((
+            "move_node",
+            json!({"new_parent_id": parent, "new_position": sandbox["position"]}),
+        ));
+    }
+    ops
+}
+
+/// Whether the origin changed, since `base` was taken, the fields `op`
+/// would write.
+pub fn origin_changed(base: &Value, origin: &Value, op: &str, payload: &Value) -> bool {
+    match op {
+        "update_content" => origin["content"] != base["content"],
+        "update_metadata" => payload["merge"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .any(|(k, _)| origin["metadata"].get(k.as_str()) != base["metadata"].get(k.as_str())),
+        "move_node" => {
+            origin["parent_id"] != base["parent_id"] || origin["position"] != base["position"]
+        }
+        _ => ["content", "metadata", "parent_id", "position"]
+            .iter()
+            .any(|f| origin[*f] != base[*f]),
+    }
+}
+
+fn apply(op: &str, node_id: Option<&str>, payload: &Value) -> String {
+    let result = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT kerai.apply_op({}, {}, {})",
+        sql_text(op),
+        node_id.map_or("NULL::uuid".to_string(), sql_uuid),
+        sql_jsonb(payload),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("apply_op {} returned nothing", op))
+    .0;
+    result["node_id"].as_str().unwrap_or_default().to_string()
+}
+
+/// Apply a sandbox's changes to the nodes it was cloned from, then remove
+/// the sandbox's nodes.
+///
+/// New nodes are inserted first (parents before children), then edits to
+/// copied nodes are applied, then nodes deleted in the sandbox are deleted
+/// at the origin. Every change is a signed operation; together they are
+/// the returned `changeset`. Removed metadata keys and edge changes are
+/// not carried over.
+///
+/// Returns `{sandbox, changeset: [{op_type, node_id, sandbox_node_id}],
+/// conflicts: [{node_id, sandbox_node_id, op_type, reason}]}`.
+#[pg_extern]
+fn promote_sandbox(sandbox_id: pgrx::Uuid) -> pgrx::JsonB {
+    let sandbox_id = sandbox_id.to_string();
+    let sandbox = active_sandbox(&sandbox_id);
+    let prefix = sandbox["prefix"].as_str().unwrap_or_default();
+    let sid = sql_uuid(&sandbox_id);
+
+    let entries = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'node_id', m.node_id, 'origin_id', m.origin_id, 'base', m.base,
+            'sandbox', (SELECT to_jsonb(n) - 'tsv' FROM kerai.nodes n WHERE n.id = m.node_id),
+            'origin', (SELECT to_jsonb(o) - 'tsv' FROM kerai.nodes o WHERE o.id = m.origin_id)
+        ) ORDER BY nlevel(COALESCE((m.base->>'path')::ltree, ''::ltree)) DESC), '[]'::jsonb)
+        FROM kerai.sandbox_nodes m WHERE m.sandbox_id = {sid}",
+    ))
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+    let entries = entries.as_array().cloned().unwrap_or_default();
+    let added = Spi::get_one::<pgrx::JsonB>(&format!(
+        "{tree}, depths AS (SELECT id, min(depth) AS depth FROM tree GROUP BY id)
+         SELECT COALESCE(jsonb_agg(to_jsonb(n) - 'tsv' ORDER BY d.depth, n.position), '[]'::jsonb)
+         FROM depths d JOIN kerai.nodes n ON n.id = d.id
+         WHERE n.id NOT IN (SELECT node_id FROM kerai.sandbox_nodes WHERE sandbox_id = {sid})",
+        tree = tree_cte(&sandbox_id),
+    ))
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+
+    // Sandbox id -> origin id, growing as new nodes are inserted
+    let mut origin_of: HashMap<String, String> = entries
+        .iter()
+        .filter_map(|e| {
+            Some((
+                e["node_id"].as_str()?.to_string(),
+                e["origin_id"].as_str()?.to_string(),
+            ))
+        })
+        .collect();
+    let mut changeset = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for node in added.as_array().into_iter().flatten() {
+        let sandbox_node = node["id"].as_str().unwrap_or_default().to_string();
+        let parent = node["parent_id"]
+            .as_str()
+            .map(|p| origin_of.get(p).cloned().unwrap_or_else(|| p.to_string()));
+        let path = node["path"].as_str().map(|p| {
+            p.strip_prefix(prefix)
+                .map_or(p, |rest| rest.trim_start_matches('.'))
+                .to_string()
+        });
+        let metadata = match &node["metadata"] {
+            Value::Null => json!({}),
+            m => m.clone(),
+        };
+        let payload = json!({
+            "kind": node["kind"],
+            "language": node["language"],
+            "content": node["content"],
+            "parent_id": parent,
+            "position": node["position"],
+            "path": path.filter(|p| !p.is_empty()),
+            "metadata": metadata,
+        });
+        let id = apply("insert_node", None, &payload);
+        changeset.push(
+            json!({"op_type": "insert_node", "node_id": id, "sandbox_node_id": sandbox_node}),
+        );
+        origin_of.insert(sandbox_node, id);
+    }
+
+    let mut deleted = Vec::new();
+    for entry in &entries {
+        let (base, origin) = (&entry["base"], &entry["origin"]);
+        let origin_id = entry["origin_id"].as_str().unwrap_or_default();
+        let sandbox_node = entry["node_id"].as_str().unwrap_or_default();
+        let conflict = |op: &str, reason: &str| json!({"node_id": origin_id, "sandbox_node_id": sandbox_node, "op_type": op, "reason": reason});
+
+        if entry["sandbox"].is_null() {
+            if origin.is_null() {
+                continue;
+            }
+            if origin_changed(base, origin, "delete_node", &Value::Null) {
+                conflicts.push(conflict(
+                    "delete_node",
+                    "changed at origin, deleted in sandbox",
+                ));
+            } else {
+                deleted.push(origin_id.to_string());
+            }
+            continue;
+        }
+
+        // A copied root keeps its place unless moved under another node
+        let parent = match entry["sandbox"]["parent_id"].as_str() {
+            Some(p) => Some(origin_of.get(p).cloned().unwrap_or_else(|| p.to_string())),
+            None => base["parent_id"].as_str().map(str::to_string),
+        };
+        for (op, payload) in changes(base, &entry["sandbox"], parent.as_deref()) {
+            if origin.is_null() {
+                conflicts.push(conflict(op, "deleted at origin"));
+            } else if origin_changed(base, origin, op, &payload) {
+                conflicts.push(conflict(op, "changed at origin and in sandbox"));
+            } else {
+                apply(op, Some(origin_id), &payload);
+                changeset.push(
+                    json!({"op_type": op, "node_id": origin_id, "sandbox_node_id": sandbox_node}),
+                );
+            }
+        }
+    }
+
+    // Deepest first, so each delete has no deleted children left to reparent
+    for origin_id in deleted {
+        let exists = Spi::get_one::<bool>(&format!(
+            "SELECT EXISTS(SELECT 1 FROM kerai.nodes WHERE id = {})",
+            sql_uuid(&origin_id),
+        ))
+        .unwrap()
+        .unwrap_or(false);
+        if exists {
+            apply("delete_node", Some(&origin_id), &json!({"cascade": false}));
+            changeset.push(
+                json!({"op_type": "delete_node", "node_id": origin_id, "sandbox_node_id": null}),
+            );
+        }
+    }
+
+    let result = json!({
+        "sandbox": sandbox_id,
+        "changeset": changeset,
+        "conflicts": conflicts,
+    });
+    close(&sandbox_id, "promoted", &result);
+    pgrx::JsonB(result)
+}
+
+/// Throw a sandbox away without promoting it.
+///
+/// Returns `{sandbox, removed}`.
+#[pg_extern]
+fn drop_sandbox(sandbox_id: pgrx::Uuid) -> pgrx::JsonB {
+    let sandbox_id = sandbox_id.to_string();
+    active_sandbox(&sandbox_id);
+    let removed = close(&sandbox_id, "discarded", &Value::Null);
+    pgrx::JsonB(json!({"sandbox": sandbox_id, "removed": removed}))
+}
+
+/// Expire active sandboxes past their `expires_at`; returns how many.
+pub fn expire() -> usize {
+    let ids = Spi::get_one::<Vec<String>>(
+        "SELECT array_agg(id::text) FROM kerai.sandboxes
+         WHERE status = 'active' AND expires_at <= now()",
+    )
+    .unwrap()
+    .unwrap_or_default();
+    for id in &ids {
+        close(id, "expired", &Value::Null);
+    }
+    ids.len()
+}
+
+/// Expire abandoned sandboxes now rather than waiting for the sandbox
+/// cleaner worker. Returns `{expired}`.
+#[pg_extern]
+fn cleanup_sandboxes() -> pgrx::JsonB {
+    pgrx::JsonB(json!({ "expired": expire() }))
+}
+
+/// Sandboxes, newest first: `[{id, prefix, source_path, status,
+/// created_by, created_at, expires_at, closed_at, nodes}]`. `nodes` counts
+/// the copies still present.
+#[pg_extern]
+fn list_sandboxes() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', s.id,
+            'prefix', s.prefix,
+            'source_path', s.source_path::text,
+            'status', s.status,
+            'created_by', s.created_by,
+            'created_at', s.created_at,
+            'expires_at', s.expires_at,
+            'closed_at', s.closed_at,
+            'nodes', (SELECT count(*) FROM kerai.sandbox_nodes m
+                      JOIN kerai.nodes n ON n.id = m.node_id WHERE m.sandbox_id = s.id)
+        ) ORDER BY s.created_at DESC), '[]'::jsonb)
+        FROM kerai.sandboxes s",
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(content: &str, parent: &str, position: i64, metadata: Value) -> Value {
+        json!({"content": content, "parent_id": parent, "position": position, "metadata": metadata})
+    }
+
+    #[test]
+    fn changes_cover_content_metadata_and_moves() {
+        let base = row("fn a() {}", "p1", 0, json!({"name": "a", "line": 1}));
+        assert!(changes(&base, &base, Some("p1")).is_empty());
+
+        let edited = row("fn b() {}", "p1", 0, json!({"name": "b", "line": 1}));
+        let ops: Vec<&str> = changes(&base, &edited, Some("p1"))
+            .iter()
+            .map(|(op, _)| *op)
+            .collect();
+        assert_eq!(ops, vec!["update_content", "update_metadata"]);
+        assert_eq!(
+            changes(&base, &edited, Some("p1"))[1].1,
+            json!({"merge": {"name": "b"}})
+        );
+
+        let moved = row("fn a() {}", "p2", 3, json!({"name": "a", "line": 1}));
+        let ops = changes(&base, &moved, Some("p2"));
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].1, json!({"new_parent_id": "p2", "new_position": 3}));
+    }
+
+    #[test]
+    fn origin_changes_conflict_only_on_written_fields() {
+        let base = row("x", "p1", 0, json!({"name": "a", "line": 1}));
+        let origin = row("x", "p1", 0, json!({"name": "a", "line": 9}));
+        assert!(!origin_changed(
+            &base,
+            &origin,
+            "update_content",
+            &Value::Null
+        ));
+        assert!(!origin_changed(
+            &base,
+            &origin,
+            "update_metadata",
+            &json!({"merge": {"name": "b"}})
+        ));
+        assert!(origin_changed(
+            &base,
+            &origin,
+            "update_metadata",
+            &json!({"merge": {"line": 2}})
+        ));
+        assert!(origin_changed(&base, &origin, "delete_node", &Value::Null));
+        assert!(!origin_changed(&base, &base, "delete_node", &Value::Null));
+    }
+}
@@ -1,11 +1,24 @@
 use pgrx::prelude::*;
 
-// Schema bootstrap — marker for dependency ordering.
+// Schema bootstrap — marker for dependency ordering, and kerai.uuid7, which
+// every table's id default uses (see ids.rs).
 // The kerai schema is created automatically by PostgreSQL
 // because of `schema = kerai` in kerai.control.
 extension_sql!(
     r#"
 -- schema kerai is auto-created by PostgreSQL via .control file
+
+-- A time-ordered UUIDv7: Unix milliseconds, then 12 bits of sub-millisecond
+-- time, then random bits, so ids made in sequence sort in sequence.
+CREATE FUNCTION kerai.uuid7() RETURNS uuid
+LANGUAGE sql VOLATILE PARALLEL SAFE AS $$
+    SELECT encode(
+        substring(int8send(floor(t)::bigint) FROM 3)
+        || int2send((28672 + floor((t - floor(t)) * 4096))::int2)  -- 0x7000: version 7
+        || substring(uuid_send(gen_random_uuid()) FROM 9),
+        'hex')::uuid
+    FROM (SELECT extract(epoch FROM clock_timestamp()) * 1000 AS t) now
+$$;
 "#,
     name = "schema_bootstrap"
 );
@@ -14,7 +27,7 @@ extension_sql!(
 extension_sql!(
     r#"
 CREATE TABLE kerai.instances (
-    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id              UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     name            TEXT NOT NULL,
     public_key      BYTEA NOT NULL,
     key_fingerprint TEXT NOT NULL UNIQUE,
@@ -23,6 +36,12 @@ CREATE TABLE kerai.instances (
     description     TEXT,
     is_self         BOOLEAN NOT NULL DEFAULT false,
     last_seen       TIMESTAMPTZ,
+    clock_skew_ms   BIGINT,           -- peer clock minus ours, from its last sync message
+    clock_checked_at TIMESTAMPTZ,
+    sync_windows    TEXT[],           -- 'HH:MM-HH:MM' local times syncing is allowed; NULL: any
+    sync_max_ops_per_minute INTEGER,  -- NULL: no cap
+    sync_max_bytes_per_hour BIGINT,   -- NULL: no cap
+    acked_vector    JSONB,            -- version vector in the peer's last sync message
     metadata        JSONB DEFAULT '{}'::jsonb,
     created_at      TIMESTAMPTZ NOT NULL DEFAULT now()
 );
@@ -42,7 +61,7 @@ CREATE INDEX idx_instances_last_seen ON kerai.instances (last_seen);
 extension_sql!(
     r#"
 CREATE TABLE kerai.nodes (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     instance_id UUID NOT NULL REFERENCES kerai.instances(id),
     kind        TEXT NOT NULL,
     language    TEXT,
@@ -51,17 +70,27 @@ CREATE TABLE kerai.nodes (
     position    INTEGER NOT NULL DEFAULT 0,
     path        ltree,
     metadata    JSONB DEFAULT '{}'::jsonb,
-    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+    content_hash TEXT,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    modified_at TIMESTAMPTZ NOT NULL DEFAULT now(),  -- last content or parent change
+    deleted_at  TIMESTAMPTZ,                          -- tombstone, purged by kerai.gc
+    tsv         tsvector GENERATED ALWAYS AS (to_tsvector('english', COALESCE(content, ''))) STORED
 );
 
 CREATE INDEX idx_nodes_instance ON kerai.nodes (instance_id);
+CREATE INDEX idx_nodes_content_hash ON kerai.nodes (content_hash) WHERE content_hash IS NOT NULL;
 CREATE INDEX idx_nodes_kind ON kerai.nodes (kind);
 CREATE INDEX idx_nodes_parent ON kerai.nodes (parent_id);
 CREATE INDEX idx_nodes_path ON kerai.nodes USING gist (path);
 CREATE INDEX idx_nodes_language ON kerai.nodes (language) WHERE language IS NOT NULL;
 CREATE INDEX idx_nodes_parent_position ON kerai.nodes (parent_id, position);
-CREATE INDEX idx_nodes_content_fts ON kerai.nodes
-    USING gin (to_tsvector('english', COALESCE(content, '')));
+CREATE INDEX idx_nodes_tsv ON kerai.nodes USING gin (tsv);
+CREATE INDEX idx_nodes_deleted ON kerai.nodes (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX idx_nodes_kind_created ON kerai.nodes (kind, created_at, id);
+-- Fuzzy symbol lookup (kerai.find_fuzzy); the kinds match query.rs SYMBOL_KINDS
+CREATE INDEX idx_nodes_symbol_trgm ON kerai.nodes USING gin (content gin_trgm_ops)
+    WHERE kind IN ('fn', 'struct', 'enum', 'trait', 'const', 'static',
+                   'type_alias', 'union', 'macro_def', 'variant', 'field');
 "#,
     name = "table_nodes",
     requires = ["table_instances"]
@@ -71,7 +100,7 @@ CREATE INDEX idx_nodes_content_fts ON kerai.nodes
 extension_sql!(
     r#"
 CREATE TABLE kerai.edges (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     source_id   UUID NOT NULL REFERENCES kerai.nodes(id),
     target_id   UUID NOT NULL REFERENCES kerai.nodes(id),
     relation    TEXT NOT NULL,
@@ -89,12 +118,38 @@ CREATE UNIQUE INDEX idx_edges_unique_rel
     requires = ["table_nodes"]
 );
 
+// Table: branches — named lines of node history
+extension_sql!(
+    r#"
+CREATE TABLE kerai.branches (
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
+    name        TEXT NOT NULL UNIQUE,
+    parent_id   UUID REFERENCES kerai.branches(id),
+    fork_ts     BIGINT NOT NULL DEFAULT 0,
+    is_current  BOOLEAN NOT NULL DEFAULT false,
+    merged_into UUID REFERENCES kerai.branches(id),
+    merged_at   TIMESTAMPTZ,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Exactly one branch is checked out into kerai.nodes
+CREATE UNIQUE INDEX idx_branches_current ON kerai.branches (is_current) WHERE is_current;
+
+INSERT INTO kerai.branches (name, is_current) VALUES ('main', true);
+"#,
+    name = "table_branches",
+    requires = ["schema_bootstrap"]
+);
+
 // Table: versions — edit history with Lamport timestamps
+// Range-partitioned by timestamp; see partitions.rs for range upkeep.
 extension_sql!(
     r#"
+-- node_id is not a foreign key: a branch keeps the history of nodes that
+-- only exist while it is checked out.
 CREATE TABLE kerai.versions (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-    node_id     UUID NOT NULL REFERENCES kerai.nodes(id),
+    id          UUID NOT NULL DEFAULT kerai.uuid7(),
+    node_id     UUID NOT NULL,
     instance_id UUID NOT NULL REFERENCES kerai.instances(id),
     operation   TEXT NOT NULL,
     old_parent  UUID,
@@ -103,11 +158,19 @@ CREATE TABLE kerai.versions (
     new_position INTEGER,
     old_content TEXT,
     new_content TEXT,
+    old_snapshot JSONB,
+    new_snapshot JSONB,
+    branch_id   UUID REFERENCES kerai.branches(id),
+    merged_from UUID,
     author      TEXT NOT NULL,
     timestamp   BIGINT NOT NULL,
     signature   BYTEA,
-    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
-);
+    signed_by   UUID REFERENCES kerai.instances(id),
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (id, timestamp)
+) PARTITION BY RANGE (timestamp);
+
+CREATE TABLE kerai.versions_default PARTITION OF kerai.versions DEFAULT;
 
 CREATE INDEX idx_versions_node ON kerai.versions (node_id);
 CREATE INDEX idx_versions_instance ON kerai.versions (instance_id);
@@ -115,16 +178,18 @@ CREATE INDEX idx_versions_timestamp ON kerai.versions (timestamp);
 CREATE INDEX idx_versions_author ON kerai.versions (author);
 CREATE INDEX idx_versions_node_timestamp
     ON kerai.versions (node_id, timestamp);
+CREATE INDEX idx_versions_branch_timestamp
+    ON kerai.versions (branch_id, timestamp);
 "#,
     name = "table_versions",
-    requires = ["table_nodes"]
+    requires = ["table_nodes", "table_branches"]
 );
 
 // Table: wallets — token wallets for instances and system
 extension_sql!(
     r#"
 CREATE TABLE kerai.wallets (
-    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id              UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     instance_id     UUID REFERENCES kerai.instances(id),
     public_key      BYTEA NOT NULL,
     key_fingerprint TEXT NOT NULL UNIQUE,
@@ -144,10 +209,11 @@ CREATE INDEX idx_wallets_type ON kerai.wallets (wallet_type);
 // Table: ledger — immutable transaction log
 // All amounts are in nKoi (nano-Koi): 1 Koi = 1,000,000,000 nKoi (10^9).
 // 9 whole digits + implicit decimal + 9 fractional digits, stored as BIGINT.
+// Range-partitioned by timestamp like versions.
 extension_sql!(
     r#"
 CREATE TABLE kerai.ledger (
-    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id              UUID NOT NULL DEFAULT kerai.uuid7(),
     from_wallet     UUID REFERENCES kerai.wallets(id),
     to_wallet       UUID NOT NULL REFERENCES kerai.wallets(id),
     amount          BIGINT NOT NULL CHECK (amount > 0),  -- nKoi
@@ -156,8 +222,11 @@ CREATE TABLE kerai.ledger (
     reference_type  TEXT,
     signature       BYTEA,
     timestamp       BIGINT NOT NULL,
-    created_at      TIMESTAMPTZ NOT NULL DEFAULT now()
-);
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (id, timestamp)
+) PARTITION BY RANGE (timestamp);
+
+CREATE TABLE kerai.ledger_default PARTITION OF kerai.ledger DEFAULT;
 
 CREATE INDEX idx_ledger_from ON kerai.ledger (from_wallet);
 CREATE INDEX idx_ledger_to ON kerai.ledger (to_wallet);
@@ -175,7 +244,7 @@ CREATE INDEX idx_ledger_reference
 extension_sql!(
     r#"
 CREATE TABLE kerai.pricing (
-    id            UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id            UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     instance_id   UUID NOT NULL REFERENCES kerai.instances(id),
     resource_type TEXT NOT NULL,
     scope         ltree,
@@ -200,7 +269,7 @@ CREATE UNIQUE INDEX idx_pricing_unique
 extension_sql!(
     r#"
 CREATE TABLE kerai.attestations (
-    id                UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id                UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     instance_id       UUID NOT NULL REFERENCES kerai.instances(id),
     scope             ltree NOT NULL,
     claim_type        TEXT NOT NULL,
@@ -233,7 +302,7 @@ CREATE INDEX idx_attestations_expires
 extension_sql!(
     r#"
 CREATE TABLE kerai.challenges (
-    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id              UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     attestation_id  UUID NOT NULL REFERENCES kerai.attestations(id),
     challenger_id   UUID NOT NULL REFERENCES kerai.instances(id),
     challenge_type  TEXT NOT NULL,
@@ -259,7 +328,7 @@ CREATE INDEX idx_challenges_status ON kerai.challenges (status);
 extension_sql!(
     r#"
 CREATE TABLE kerai.agents (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     wallet_id   UUID REFERENCES kerai.wallets(id),
     name        TEXT NOT NULL UNIQUE,
     kind        TEXT NOT NULL,
@@ -278,7 +347,7 @@ CREATE INDEX idx_agents_kind ON kerai.agents(kind);
 extension_sql!(
     r#"
 CREATE TABLE kerai.perspectives (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     agent_id    UUID NOT NULL REFERENCES kerai.agents(id),
     node_id     UUID NOT NULL REFERENCES kerai.nodes(id),
     weight      DOUBLE PRECISION NOT NULL DEFAULT 0,
@@ -293,6 +362,7 @@ CREATE INDEX idx_perspectives_agent ON kerai.perspectives(agent_id);
 CREATE INDEX idx_perspectives_node ON kerai.perspectives(node_id);
 CREATE INDEX idx_perspectives_context ON kerai.perspectives(context_id) WHERE context_id IS NOT NULL;
 CREATE INDEX idx_perspectives_weight ON kerai.perspectives(weight);
+CREATE INDEX idx_perspectives_agent_created ON kerai.perspectives(agent_id, created_at, id);
 "#,
     name = "table_perspectives",
     requires = ["table_agents", "table_nodes"]
@@ -302,7 +372,7 @@ CREATE INDEX idx_perspectives_weight ON kerai.perspectives(weight);
 extension_sql!(
     r#"
 CREATE TABLE kerai.associations (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     agent_id    UUID NOT NULL REFERENCES kerai.agents(id),
     source_id   UUID NOT NULL REFERENCES kerai.nodes(id),
     target_id   UUID NOT NULL REFERENCES kerai.nodes(id),
@@ -363,7 +433,7 @@ WHERE NOT EXISTS (
 extension_sql!(
     r#"
 CREATE TABLE kerai.auctions (
-    id                  UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id                  UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     attestation_id      UUID NOT NULL REFERENCES kerai.attestations(id),
     seller_wallet       UUID NOT NULL REFERENCES kerai.wallets(id),
     auction_type        TEXT NOT NULL DEFAULT 'dutch',
@@ -397,7 +467,7 @@ CREATE INDEX idx_auctions_floor ON kerai.auctions(floor_price);
 extension_sql!(
     r#"
 CREATE TABLE kerai.bids (
-    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id              UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     auction_id      UUID NOT NULL REFERENCES kerai.auctions(id),
     bidder_wallet   UUID NOT NULL REFERENCES kerai.wallets(id),
     max_price       BIGINT NOT NULL,
@@ -426,7 +496,7 @@ CREATE INDEX IF NOT EXISTS idx_challenges_auction ON kerai.challenges(auction_id
 extension_sql!(
     r#"
 CREATE TABLE kerai.tasks (
-    id               UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id               UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     description      TEXT NOT NULL,
     scope_node_id    UUID REFERENCES kerai.nodes(id),
     success_command  TEXT NOT NULL,
@@ -453,7 +523,7 @@ CREATE INDEX idx_tasks_swarm ON kerai.tasks (swarm_id) WHERE swarm_id IS NOT NUL
 extension_sql!(
     r#"
 CREATE UNLOGGED TABLE kerai.test_results (
-    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id              UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     task_id         UUID NOT NULL REFERENCES kerai.tasks(id),
     agent_id        UUID NOT NULL REFERENCES kerai.agents(id),
     version_vector  JSONB NOT NULL DEFAULT '{}'::jsonb,
@@ -477,7 +547,7 @@ CREATE INDEX idx_test_results_task_created ON kerai.test_results (task_id, creat
 extension_sql!(
     r#"
 CREATE TABLE kerai.bounties (
-    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id              UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     poster_wallet   UUID NOT NULL REFERENCES kerai.wallets(id),
     scope           ltree NOT NULL,
     description     TEXT NOT NULL,
@@ -503,7 +573,7 @@ CREATE INDEX idx_bounties_reward ON kerai.bounties (reward);
 extension_sql!(
     r#"
 CREATE TABLE kerai.operations (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     instance_id UUID NOT NULL REFERENCES kerai.instances(id),
     op_type     TEXT NOT NULL,
     node_id     UUID,
@@ -512,13 +582,20 @@ CREATE TABLE kerai.operations (
     author_seq  BIGINT NOT NULL,
     payload     JSONB NOT NULL DEFAULT '{}'::jsonb,
     signature   BYTEA,
-    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    seq         BIGINT GENERATED ALWAYS AS IDENTITY,          -- order within a transaction
+    txid        xid8 NOT NULL DEFAULT pg_current_xact_id(),   -- inserting transaction, for poll cursors
+    path        ltree                                         -- node's path when the op was logged
 );
 
 CREATE INDEX idx_operations_instance ON kerai.operations (instance_id);
+CREATE INDEX idx_operations_txid_seq ON kerai.operations (txid, seq);
+CREATE INDEX idx_operations_path ON kerai.operations USING gist (path);
 CREATE INDEX idx_operations_node ON kerai.operations (node_id) WHERE node_id IS NOT NULL;
 CREATE INDEX idx_operations_author ON kerai.operations (author);
 CREATE INDEX idx_operations_lamport ON kerai.operations (lamport_ts);
+CREATE INDEX idx_operations_revert_of ON kerai.operations ((payload->>'revert_of'))
+    WHERE payload ? 'revert_of';
 CREATE UNIQUE INDEX idx_operations_author_seq
     ON kerai.operations (author, author_seq);
 "#,
@@ -526,15 +603,37 @@ CREATE UNIQUE INDEX idx_operations_author_seq
     requires = ["table_instances"]
 );
 
-// Table: version_vector — CRDT version tracking (stub for Plan 04)
+// Table: version_vector — per-author CRDT clock: highest author_seq and
+// Lamport timestamp seen from each instance
 extension_sql!(
     r#"
 CREATE TABLE kerai.version_vector (
-    author  TEXT PRIMARY KEY,
-    max_seq BIGINT NOT NULL DEFAULT 0
+    author      TEXT PRIMARY KEY,
+    instance_id UUID REFERENCES kerai.instances(id),
+    max_seq     BIGINT NOT NULL DEFAULT 0,
+    max_lamport BIGINT NOT NULL DEFAULT 0,
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now()
 );
 "#,
     name = "table_version_vector",
+    requires = ["table_instances"]
+);
+
+// Function: hlc_wall_time — decodes the hybrid logical clock timestamps in
+// operations.lamport_ts and versions.timestamp (see crdt/hlc.rs)
+extension_sql!(
+    r#"
+-- Estimated wall clock time of a timestamp (physical_ms << 16 | logical)
+-- stamped by an instance whose clock runs skew_ms ahead of this one; NULL
+-- for a Lamport counter from before hybrid clocks.
+CREATE FUNCTION kerai.hlc_wall_time(ts bigint, skew_ms bigint DEFAULT 0) RETURNS timestamptz
+LANGUAGE sql IMMUTABLE PARALLEL SAFE AS $$
+    SELECT CASE WHEN ts >> 16 >= 1577836800000
+        THEN to_timestamp(((ts >> 16) - COALESCE(skew_ms, 0)) / 1000.0)
+    END
+$$;
+"#,
+    name = "function_hlc_wall_time",
     requires = ["schema_bootstrap"]
 );
 
@@ -542,7 +641,7 @@ CREATE TABLE kerai.version_vector (
 extension_sql!(
     r#"
 CREATE TABLE kerai.reward_schedule (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     work_type   TEXT NOT NULL UNIQUE,
     reward      BIGINT NOT NULL CHECK (reward > 0),  -- nKoi
     enabled     BOOLEAN NOT NULL DEFAULT true,
@@ -557,7 +656,7 @@ CREATE TABLE kerai.reward_schedule (
 extension_sql!(
     r#"
 CREATE TABLE kerai.reward_log (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     work_type   TEXT NOT NULL,
     reward      BIGINT NOT NULL,  -- nKoi
     wallet_id   UUID NOT NULL REFERENCES kerai.wallets(id),
@@ -573,7 +672,7 @@ CREATE TABLE kerai.reward_log (
 extension_sql!(
     r#"
 CREATE TABLE kerai.repositories (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     instance_id UUID NOT NULL REFERENCES kerai.instances(id),
     url         TEXT NOT NULL,
     name        TEXT NOT NULL,
@@ -603,6 +702,7 @@ INSERT INTO kerai.reward_schedule (work_type, reward) VALUES
     ('create_version',     5000000000),  --  5 Koi
     ('bounty_settlement', 20000000000),  -- 20 Koi
     ('peer_sync',         15000000000),  -- 15 Koi
+    ('accept_suggestion',  1000000000),  --  1 Koi
     ('model_training',    25000000000),  -- 25 Koi
     ('mirror_repo',      100000000000);  -- 100 Koi
 "#,
@@ -623,7 +723,7 @@ ALTER TABLE kerai.wallets ADD COLUMN nonce BIGINT NOT NULL DEFAULT 0;
 extension_sql!(
     r#"
 CREATE TABLE kerai.preferences (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     instance_id UUID NOT NULL REFERENCES kerai.instances(id),
     category    TEXT NOT NULL,
     key         TEXT NOT NULL,
@@ -662,7 +762,7 @@ CREATE INDEX idx_model_vocab_node ON kerai.model_vocab (node_id);
 extension_sql!(
     r#"
 CREATE TABLE kerai.model_weights (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     agent_id    UUID NOT NULL REFERENCES kerai.agents(id),
     tensor_name TEXT NOT NULL,
     tensor_data BYTEA NOT NULL,
@@ -682,7 +782,7 @@ CREATE INDEX idx_model_weights_agent ON kerai.model_weights (agent_id);
 extension_sql!(
     r#"
 CREATE TABLE kerai.training_runs (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     agent_id    UUID NOT NULL REFERENCES kerai.agents(id),
     config      JSONB NOT NULL,
     walk_type   TEXT NOT NULL,
@@ -704,7 +804,7 @@ CREATE INDEX idx_training_runs_agent ON kerai.training_runs (agent_id);
 extension_sql!(
     r#"
 CREATE UNLOGGED TABLE kerai.inference_log (
-    id            UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id            UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     agent_id      UUID NOT NULL,
     context_nodes UUID[] NOT NULL,
     predicted     UUID NOT NULL,
@@ -720,11 +820,428 @@ CREATE INDEX idx_inference_log_agent ON kerai.inference_log (agent_id);
     requires = ["table_agents"]
 );
 
+// Tables: sandboxes and sandbox_nodes — disposable subtree copies. Each
+// copied node keeps its origin and the origin's row at clone time (base),
+// which promotion diffs against.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.sandboxes (
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
+    prefix      TEXT NOT NULL UNIQUE,           -- ltree label the copies live under
+    source_path ltree NOT NULL,
+    status      TEXT NOT NULL DEFAULT 'active'
+                CHECK (status IN ('active', 'promoted', 'discarded', 'expired')),
+    created_by  TEXT NOT NULL DEFAULT current_user,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at  TIMESTAMPTZ NOT NULL,
+    closed_at   TIMESTAMPTZ,
+    result      JSONB                           -- promotion changeset and conflicts
+);
+
+CREATE INDEX idx_sandboxes_expiry ON kerai.sandboxes (expires_at) WHERE status = 'active';
+
+CREATE TABLE kerai.sandbox_nodes (
+    sandbox_id  UUID NOT NULL REFERENCES kerai.sandboxes(id) ON DELETE CASCADE,
+    node_id     UUID NOT NULL,                  -- the copy; may be deleted in the sandbox
+    origin_id   UUID NOT NULL,
+    base        JSONB NOT NULL,
+    PRIMARY KEY (sandbox_id, node_id)
+);
+
+CREATE INDEX idx_sandbox_nodes_origin ON kerai.sandbox_nodes (origin_id);
+"#,
+    name = "table_sandboxes",
+    requires = ["table_nodes"]
+);
+
+// Tables: changesets and changeset_ops — node operations staged for review
+// before they are applied. kerai.read_with_changeset runs a read with a
+// changeset's ops applied in a subtransaction it then rolls back; the
+// caught error is what undoes them, and the result survives in `result`.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.changesets (
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
+    description TEXT,
+    status      TEXT NOT NULL DEFAULT 'open'
+                CHECK (status IN ('open', 'applied', 'discarded')),
+    created_by  TEXT NOT NULL DEFAULT current_user,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    closed_at   TIMESTAMPTZ
+);
+
+CREATE TABLE kerai.changeset_ops (
+    changeset_id UUID NOT NULL REFERENCES kerai.changesets(id) ON DELETE CASCADE,
+    seq          INTEGER NOT NULL,
+    op_type      TEXT NOT NULL,
+    node_id      UUID NOT NULL,                 -- preassigned for insert_node
+    payload      JSONB NOT NULL,
+    staged_at    TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (changeset_id, seq)
+);
+
+CREATE FUNCTION kerai.read_with_changeset(changeset uuid, query text, args jsonb DEFAULT '{}')
+RETURNS jsonb
+LANGUAGE plpgsql AS $$
+DECLARE
+    result jsonb;
+BEGIN
+    BEGIN
+        PERFORM set_config('kerai.changeset', '', true);
+        PERFORM kerai.preview_changeset(changeset);
+        EXECUTE query INTO result USING args;
+        RAISE EXCEPTION USING ERRCODE = 'KR001';
+    EXCEPTION WHEN SQLSTATE 'KR001' THEN
+        NULL;
+    END;
+    RETURN result;
+END $$;
+"#,
+    name = "table_changesets",
+    requires = ["table_nodes"]
+);
+
+// Table: jobs — long-running operations, for progress and cancellation.
+// pid is the backend running the job; cancel_job signals it. reap_jobs
+// fails jobs whose heartbeat has gone stale, queueing them again while
+// attempts remain.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.jobs (
+    id               UUID PRIMARY KEY DEFAULT kerai.uuid7(),
+    kind             TEXT NOT NULL,              -- import, sync, ...
+    pid              INTEGER NOT NULL,
+    status           TEXT NOT NULL DEFAULT 'running'
+                     CHECK (status IN ('queued', 'running', 'done', 'cancelled', 'failed')),
+    total            INTEGER,
+    done             INTEGER NOT NULL DEFAULT 0,
+    current          TEXT,
+    cancel_requested BOOLEAN NOT NULL DEFAULT false,
+    result           JSONB,
+    args             JSONB,                      -- what a retry of the job runs
+    attempts         INTEGER NOT NULL DEFAULT 1,
+    max_attempts     INTEGER NOT NULL DEFAULT 1,
+    run_after        TIMESTAMPTZ,                -- when a queued job is due
+    heartbeat_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    started_at       TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at       TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at      TIMESTAMPTZ
+);
+
+CREATE INDEX idx_jobs_running ON kerai.jobs (pid) WHERE status = 'running';
+CREATE INDEX idx_jobs_queued ON kerai.jobs (kind, run_after) WHERE status = 'queued';
+"#,
+    name = "table_jobs",
+    requires = ["schema_bootstrap"]
+);
+
+// Table: rules — user-defined lint rules evaluated by run_rules.
+// predicate is SQL over the node aliased `n`; pattern must match its path.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.rules (
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
+    name        TEXT NOT NULL UNIQUE,                -- rule id on suggestions
+    message     TEXT NOT NULL,                       -- {kind}, {name}, {path} filled in
+    predicate   TEXT,
+    pattern     lquery,
+    severity    TEXT NOT NULL DEFAULT 'warning' CHECK (severity IN ('info', 'warning')),
+    category    TEXT NOT NULL DEFAULT 'custom',
+    fix         JSONB,                               -- {op_type, payload, node_id} applied on accept
+    reward_work_type TEXT,                           -- credited on accept, default accept_suggestion
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK (predicate IS NOT NULL OR pattern IS NOT NULL)
+);
+"#,
+    name = "table_rules",
+    requires = ["schema_bootstrap"]
+);
+
+// Table: kinds — registry of node kinds with hints for rendering them, so a
+// frontend can draw kinds it has never seen. Preview templates fill in
+// {content} and {metadata.<key>}; kinds without a row fall back to
+// kerai.render_hints' guesses.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.kinds (
+    kind          TEXT PRIMARY KEY,
+    description   TEXT,
+    render_hints  JSONB NOT NULL DEFAULT '{}'::jsonb,  -- icon, collapsible, preview, syntax
+    updated_at    TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO kerai.kinds (kind, description, render_hints) VALUES
+    ('crate',          'Rust crate',            '{"icon": "package", "preview": "crate {content}"}'),
+    ('module',         'Rust module',           '{"icon": "folder", "preview": "mod {content}"}'),
+    ('file',           'Source file',           '{"icon": "file"}'),
+    ('fn',             'Rust function',         '{"icon": "function", "preview": "fn {content}"}'),
+    ('struct',         'Rust struct',           '{"icon": "struct", "preview": "struct {content}"}'),
+    ('enum',           'Rust enum',             '{"icon": "enum", "preview": "enum {content}"}'),
+    ('trait',          'Rust trait',            '{"icon": "interface", "preview": "trait {content}"}'),
+    ('impl',           'Rust impl block',       '{"icon": "impl", "preview": "impl {content}"}'),
+    ('use',            'Rust use declaration',  '{"icon": "import", "collapsible": false, "preview": "use {content}"}'),
+    ('doc_comment',    'Doc comment',           '{"icon": "comment", "collapsible": false, "syntax": "markdown"}'),
+    ('document',       'Markdown document',     '{"icon": "book"}'),
+    ('heading',        'Markdown heading',      '{"icon": "heading", "preview": "{content}"}'),
+    ('paragraph',      'Markdown paragraph',    '{"icon": "paragraph", "collapsible": false, "syntax": "markdown"}'),
+    ('code_block',     'Fenced code block',     '{"icon": "code", "preview": "```{metadata.language}"}'),
+    ('list',           'Markdown list',         '{"icon": "list"}'),
+    ('list_item',      'Markdown list item',    '{"icon": "bullet", "collapsible": false}'),
+    ('blockquote',     'Markdown blockquote',   '{"icon": "quote"}'),
+    ('table',          'Markdown table',        '{"icon": "table"}'),
+    ('link',           'Markdown link',         '{"icon": "link", "collapsible": false}'),
+    ('image',          'Markdown image',        '{"icon": "image", "collapsible": false}'),
+    ('latex_document', 'LaTeX document',        '{"icon": "book", "syntax": "latex"}'),
+    ('latex_section',  'LaTeX section',         '{"icon": "heading", "preview": "{content}"}'),
+    ('latex_display_math', 'Display math',      '{"icon": "math", "collapsible": false, "syntax": "latex"}'),
+    ('latex_inline_math',  'Inline math',       '{"icon": "math", "collapsible": false, "syntax": "latex"}'),
+    ('bib_entry',      'BibTeX entry',          '{"icon": "citation", "preview": "@{metadata.entry_type} {content}", "syntax": "bibtex"}'),
+    ('suggestion',     'Suggestion',            '{"icon": "lightbulb", "collapsible": false}'),
+    ('csv_table',      'CSV table',             '{"icon": "table"}'),
+    ('digest',         'Subscription digest',   '{"icon": "inbox", "collapsible": false}');
+
+-- Hints for a node: defaults, then a guess from the kind's name so kinds
+-- from new parsers (go_func, c_string_lit, ...) look like their relatives,
+-- then whatever the registry says about the kind itself.
+CREATE FUNCTION kerai.render_hints(node_kind text, node_language text) RETURNS jsonb
+LANGUAGE sql STABLE AS $$
+    SELECT jsonb_strip_nulls(jsonb_build_object(
+            'icon', 'node',
+            'collapsible', true,
+            'preview', '{content}',
+            'syntax', node_language
+        ))
+        || CASE
+            WHEN node_kind ~ '(^|_)(comment|comment_block)(_|$)' THEN
+                '{"icon": "comment", "collapsible": false}'
+            WHEN node_kind ~ '(^|_)(lit|true|false|nil|null|iota|scalar)(_|$)' THEN
+                '{"icon": "literal", "collapsible": false}'
+            WHEN node_kind ~ '(^|_)(ident|lifetime|label)(_|$)' THEN
+                '{"icon": "identifier", "collapsible": false}'
+            WHEN node_kind ~ '(^|_)(if|for|while|loop|switch|select|case|match|return|break|continue|goto|defer)(_|$)' THEN
+                '{"icon": "control"}'
+            WHEN node_kind ~ '(^|_)type(_|$)' THEN
+                '{"icon": "type"}'
+            WHEN node_kind ~ '(^|_)(fn|func|function|method|closure)(_|$)' THEN
+                '{"icon": "function"}'
+            WHEN node_kind ~ '(^|_)(struct|class|union|typedef)(_|$)' THEN
+                '{"icon": "struct"}'
+            WHEN node_kind ~ '(^|_)(interface|trait)(_|$)' THEN
+                '{"icon": "interface"}'
+            WHEN node_kind ~ '(^|_)(enum|enumerator|variant)(_|$)' THEN
+                '{"icon": "enum"}'
+            WHEN node_kind ~ '(^|_)(import|include|use|usepackage)(_|$)' THEN
+                '{"icon": "import", "collapsible": false}'
+            WHEN node_kind ~ '(^|_)(package|namespace|module|mod)(_|$)' THEN
+                '{"icon": "folder"}'
+            WHEN node_kind ~ '(^|_)(field|param|key)(_|$)' THEN
+                '{"icon": "field"}'
+            WHEN node_kind ~ '(^|_)(heading|part|chapter|section|subsection|subsubsection)(_|$)' THEN
+                '{"icon": "heading"}'
+            WHEN node_kind ~ '(^|_)(table|dataset)(_|$)' THEN
+                '{"icon": "table"}'
+            WHEN node_kind ~ '(^|_)math(_|$)' THEN
+                '{"icon": "math", "collapsible": false}'
+            WHEN node_kind ~ '(^|_)(expr|call|binary|unary|assignment)(_|$)' THEN
+                '{"icon": "expression"}'
+            WHEN node_kind ~ '(^|_)(stmt|block)(_|$)' THEN
+                '{"icon": "block"}'
+            WHEN node_kind ~ '^pat_' THEN
+                '{"icon": "pattern"}'
+            ELSE '{}'
+        END::jsonb
+        || COALESCE((SELECT k.render_hints FROM kerai.kinds k WHERE k.kind = node_kind), '{}'::jsonb)
+$$;
+"#,
+    name = "table_kinds",
+    requires = ["schema_bootstrap"]
+);
+
+// Table: economy_summaries — latest signed economy report from each peer per
+// period, merged by federation_economy_report.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.economy_summaries (
+    instance_id  UUID NOT NULL REFERENCES kerai.instances(id) ON DELETE CASCADE,
+    period       TEXT NOT NULL,
+    report       JSONB NOT NULL,       -- the peer's economy_report
+    message      JSONB NOT NULL,       -- signed message it arrived in
+    received_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (instance_id, period)
+);
+"#,
+    name = "table_economy_summaries",
+    requires = ["table_instances"]
+);
+
+// Table: git_imports — git commits replayed into kerai.versions by
+// import_git_commit, one Lamport timestamp each.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.git_imports (
+    sha           TEXT PRIMARY KEY,
+    timestamp     BIGINT NOT NULL,      -- Lamport timestamp of its versions
+    author        TEXT NOT NULL,        -- "Name <email>", as on its versions
+    committed_at  TIMESTAMPTZ NOT NULL,
+    message       TEXT NOT NULL DEFAULT '',
+    files         INTEGER NOT NULL DEFAULT 0,
+    versions      INTEGER NOT NULL DEFAULT 0,
+    imported_at   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_git_imports_timestamp ON kerai.git_imports (timestamp);
+"#,
+    name = "table_git_imports",
+    requires = ["schema_bootstrap"]
+);
+
+// Table: snapshots — named, frozen copies of the file and document trees
+// (release tags); snapshot_nodes holds each node's row and Merkle hash.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.snapshots (
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
+    name        TEXT NOT NULL UNIQUE,
+    branch_id   UUID REFERENCES kerai.branches(id),
+    timestamp   BIGINT NOT NULL,      -- Lamport time it was taken at
+    root_hash   TEXT NOT NULL,        -- Merkle root over its files' paths and hashes
+    files       INTEGER NOT NULL DEFAULT 0,
+    nodes       INTEGER NOT NULL DEFAULT 0,
+    instance_id UUID REFERENCES kerai.instances(id),
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE kerai.snapshot_nodes (
+    snapshot_id UUID NOT NULL REFERENCES kerai.snapshots(id) ON DELETE CASCADE,
+    node_id     UUID NOT NULL,        -- no FK: the node may be gone since
+    hash        TEXT,                 -- subtree hash; NULL for crate nodes above files
+    node        JSONB NOT NULL,       -- the kerai.nodes row
+    PRIMARY KEY (snapshot_id, node_id)
+);
+"#,
+    name = "table_snapshots",
+    requires = ["table_nodes", "table_branches"]
+);
+
+// Table: relations — registry of edge relations. kerai.add_edge only links
+// nodes through registered relations, and only between the kinds a relation
+// lists when it lists any.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.relations (
+    relation      TEXT PRIMARY KEY,
+    description   TEXT,
+    source_kinds  TEXT[],                               -- NULL: any kind
+    target_kinds  TEXT[],
+    updated_at    TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO kerai.relations (relation, description, source_kinds, target_kinds) VALUES
+    ('calls',         'Function calls function',             NULL, NULL),
+    ('uses',          'Item uses another item',              NULL, NULL),
+    ('includes',      'File includes another file',          NULL, NULL),
+    ('depends_on',    'Crate depends on a dependency',       NULL, '{dependency}'),
+    ('documents',     'Documentation describes code',        NULL, NULL),
+    ('references',    'Text mentions code by name',          NULL, NULL),
+    ('links_to',      'Markdown link to another node',       NULL, NULL),
+    ('mentions',      'Markdown code span names code',       NULL, NULL),
+    ('cites',         'Citation of a bibliography entry',    NULL, NULL),
+    ('summarizes',    'Summary of a node',                   NULL, NULL),
+    ('suggests',      'Suggestion about a node',             '{suggestion}', NULL),
+    ('duplicates',    'Near-duplicate code',                 NULL, NULL),
+    ('shared_column', 'CSV columns of the same name',        '{csv_column}', '{csv_column}'),
+    ('parent_commit', 'Commit follows its parent',           NULL, NULL)
+;
+"#,
+    name = "table_relations",
+    requires = ["schema_bootstrap"]
+);
+
+// Table: parse_runs — one row per parse_crate / parse_file / parse_source
+// call, with its size and wall time; kerai.parse_stats adds throughput.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.parse_runs (
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
+    kind        TEXT NOT NULL,
+    target      TEXT NOT NULL,
+    files       INTEGER NOT NULL,
+    nodes       BIGINT NOT NULL,
+    edges       BIGINT NOT NULL,
+    elapsed_ms  BIGINT NOT NULL,
+    path        ltree,          -- root of the tree it parsed
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_parse_runs_created ON kerai.parse_runs (created_at);
+
+CREATE VIEW kerai.parse_stats AS
+SELECT kind, target, files, nodes, edges, elapsed_ms,
+       round((nodes + edges) * 1000.0 / GREATEST(elapsed_ms, 1), 1) AS rows_per_sec,
+       created_at
+FROM kerai.parse_runs;
+"#,
+    name = "table_parse_runs",
+    requires = ["schema_bootstrap"]
+);
+
+// Table: views — named slices of the graph (paths and kinds) a team works
+// in; kerai.apply_view puts one in effect for a session.
+// kerai.in_view is what queries call to honour it; it is plain SQL so the
+// planner can inline it.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.views (
+    name         TEXT PRIMARY KEY,
+    description  TEXT,
+    paths        ltree[],                               -- NULL: every path
+    kinds        TEXT[],                                -- NULL: every kind
+    branch       TEXT,                                  -- branch the view is read on
+    frontier     JSONB,                                 -- version vector it is pinned to
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE FUNCTION kerai.in_view(node_path ltree, node_kind text) RETURNS boolean
+LANGUAGE sql STABLE AS $$
+    SELECT (COALESCE(current_setting('kerai.view_paths', true), '') = ''
+            OR COALESCE(node_path <@ string_to_array(current_setting('kerai.view_paths', true), ',')::ltree[], false))
+       AND (COALESCE(current_setting('kerai.view_kinds', true), '') = ''
+            OR node_kind = ANY(string_to_array(current_setting('kerai.view_kinds', true), ',')))
+$$;
+"#,
+    name = "table_views",
+    requires = ["schema_bootstrap"]
+);
+
+// Table: node_embeddings — one vector per node per model, for semantic search.
+// Plain real[] so it works without pgvector; search casts to vector when the
+// extension is installed.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.node_embeddings (
+    model_id      UUID NOT NULL REFERENCES kerai.agents(id),
+    node_id       UUID NOT NULL REFERENCES kerai.nodes(id) ON DELETE CASCADE,
+    embedding     REAL[] NOT NULL,
+    dim           INTEGER NOT NULL,
+    model_version BIGINT NOT NULL,
+    content_hash  TEXT,
+    embedded_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (model_id, node_id)
+);
+
+CREATE INDEX idx_node_embeddings_node ON kerai.node_embeddings (node_id);
+"#,
+    name = "table_node_embeddings",
+    requires = ["table_agents", "table_nodes"]
+);
+
 // Table: stack — general-purpose content stack per instance
 extension_sql!(
     r#"
 CREATE TABLE kerai.stack (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     instance_id UUID NOT NULL REFERENCES kerai.instances(id),
     position    INTEGER NOT NULL,
     label       TEXT,
@@ -743,7 +1260,7 @@ CREATE INDEX idx_stack_instance ON kerai.stack (instance_id);
 extension_sql!(
     r#"
 CREATE TABLE kerai.users (
-    id             UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id             UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     did            TEXT UNIQUE,
     handle         TEXT,
     auth_provider  TEXT NOT NULL DEFAULT 'anonymous',
@@ -762,7 +1279,7 @@ CREATE TABLE kerai.users (
 extension_sql!(
     r#"
 CREATE TABLE kerai.workspaces (
-    id             UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id             UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     user_id        UUID NOT NULL REFERENCES kerai.users(id),
     name           TEXT NOT NULL,
     is_active      BOOLEAN DEFAULT false,
@@ -796,11 +1313,26 @@ CREATE INDEX idx_stack_items_workspace_position ON kerai.stack_items (workspace_
     requires = ["table_workspaces"]
 );
 
+// Table: workspace_words — user-defined words scoped to a workspace
+extension_sql!(
+    r#"
+CREATE TABLE kerai.workspace_words (
+    workspace_id   UUID NOT NULL REFERENCES kerai.workspaces(id) ON DELETE CASCADE,
+    name           TEXT NOT NULL,
+    body           TEXT NOT NULL,
+    created_at     TIMESTAMPTZ DEFAULT now(),
+    PRIMARY KEY (workspace_id, name)
+);
+"#,
+    name = "table_workspace_words",
+    requires = ["table_workspaces"]
+);
+
 // Table: sessions — user sessions with workspace binding
 extension_sql!(
     r#"
 CREATE TABLE kerai.sessions (
-    id             UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id             UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     user_id        UUID NOT NULL REFERENCES kerai.users(id),
     workspace_id   UUID NOT NULL REFERENCES kerai.workspaces(id),
     token          TEXT NOT NULL UNIQUE,
@@ -813,11 +1345,94 @@ CREATE INDEX idx_sessions_token ON kerai.sessions (token);
     requires = ["table_users", "table_workspaces"]
 );
 
+// Table: api_tokens — bearer tokens for the HTTP API, stored as sha256
+extension_sql!(
+    r#"
+CREATE TABLE kerai.api_tokens (
+    id             UUID PRIMARY KEY DEFAULT kerai.uuid7(),
+    name           TEXT NOT NULL,
+    token_hash     TEXT NOT NULL UNIQUE,
+    scopes         TEXT[] NOT NULL DEFAULT '{read}'
+                   CHECK (scopes <@ ARRAY['read', 'write', 'admin']::text[]),
+    user_id        UUID REFERENCES kerai.users(id) ON DELETE CASCADE,
+    instance_id    UUID REFERENCES kerai.instances(id) ON DELETE CASCADE,
+    created_by     UUID REFERENCES kerai.users(id) ON DELETE SET NULL,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at     TIMESTAMPTZ,
+    last_used_at   TIMESTAMPTZ,
+    revoked_at     TIMESTAMPTZ
+);
+CREATE INDEX idx_api_tokens_user ON kerai.api_tokens (user_id);
+"#,
+    name = "table_api_tokens",
+    requires = ["table_users", "table_instances"]
+);
+
+// Function: create_api_token — issue an API token, returned once; how the
+// first admin token is made, since POST /api/tokens needs an admin
+extension_sql!(
+    r#"
+CREATE FUNCTION kerai.create_api_token(
+    token_name      text,
+    token_scopes    text[] DEFAULT '{read}',
+    for_user        uuid DEFAULT NULL,
+    for_instance    uuid DEFAULT NULL,
+    expires_in_days integer DEFAULT NULL,
+    issued_by       uuid DEFAULT NULL
+) RETURNS jsonb
+LANGUAGE plpgsql AS $$
+DECLARE
+    token  text := 'kerai_' || replace(gen_random_uuid()::text, '-', '')
+                            || replace(gen_random_uuid()::text, '-', '');
+    issued jsonb;
+BEGIN
+    INSERT INTO kerai.api_tokens
+        (name, token_hash, scopes, user_id, instance_id, created_by, expires_at)
+    VALUES (
+        token_name,
+        encode(sha256(convert_to(token, 'UTF8')), 'hex'),
+        token_scopes,
+        for_user,
+        COALESCE(for_instance, (SELECT id FROM kerai.instances WHERE is_self)),
+        issued_by,
+        now() + expires_in_days * interval '1 day'
+    )
+    RETURNING jsonb_build_object(
+        'id', id, 'name', name, 'scopes', scopes, 'user_id', user_id,
+        'instance_id', instance_id, 'created_at', created_at,
+        'expires_at', expires_at)
+    INTO issued;
+    RETURN issued || jsonb_build_object('token', token);
+END;
+$$;
+"#,
+    name = "fn_create_api_token",
+    requires = ["table_api_tokens"]
+);
+
+// Table: audit_log — security-relevant events (e.g. words refused by role)
+extension_sql!(
+    r#"
+CREATE TABLE kerai.audit_log (
+    id             BIGSERIAL PRIMARY KEY,
+    user_id        UUID REFERENCES kerai.users(id) ON DELETE SET NULL,
+    workspace_id   UUID REFERENCES kerai.workspaces(id) ON DELETE SET NULL,
+    action         TEXT NOT NULL,
+    word           TEXT,
+    detail         JSONB NOT NULL DEFAULT '{}',
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX idx_audit_log_user ON kerai.audit_log (user_id, created_at);
+"#,
+    name = "table_audit_log",
+    requires = ["table_users", "table_workspaces"]
+);
+
 // Table: csv_projects — CSV import project registry
 extension_sql!(
     r#"
 CREATE TABLE kerai.csv_projects (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     name        TEXT NOT NULL UNIQUE,
     schema_name TEXT NOT NULL,
     source_dir  TEXT,
@@ -832,7 +1447,7 @@ CREATE TABLE kerai.csv_projects (
 extension_sql!(
     r#"
 CREATE TABLE kerai.csv_files (
-    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    id          UUID PRIMARY KEY DEFAULT kerai.uuid7(),
     project_id  UUID NOT NULL REFERENCES kerai.csv_projects(id),
     filename    TEXT NOT NULL,
     table_name  TEXT NOT NULL,
@@ -881,3 +1496,742 @@ CREATE TABLE kerai.config (
     name = "table_config",
     requires = ["schema_bootstrap"]
 );
+
+// Table: index_advice — missing-index suggestions from the index advisor
+extension_sql!(
+    r#"
+CREATE TABLE kerai.index_advice (
+    id                BIGSERIAL PRIMARY KEY,
+    table_name        TEXT NOT NULL,
+    pattern           TEXT NOT NULL,
+    index_sql         TEXT NOT NULL UNIQUE,
+    reason            TEXT NOT NULL,
+    queries           INTEGER NOT NULL DEFAULT 0,
+    calls             BIGINT NOT NULL DEFAULT 0,
+    total_ms          DOUBLE PRECISION NOT NULL DEFAULT 0,
+    estimated_benefit DOUBLE PRECISION NOT NULL DEFAULT 0,
+    status            TEXT NOT NULL DEFAULT 'open'
+                      CHECK (status IN ('open', 'applied', 'dismissed')),
+    first_seen        TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_seen         TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_index_advice_status ON kerai.index_advice (status, estimated_benefit DESC);
+"#,
+    name = "table_index_advice",
+    requires = ["schema_bootstrap"]
+);
+
+// Table: api_views — registry of the versioned public views (kerai.v1_*)
+extension_sql!(
+    r#"
+CREATE TABLE kerai.api_views (
+    name          TEXT PRIMARY KEY,
+    version       INTEGER NOT NULL,
+    description   TEXT NOT NULL,
+    introduced_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    deprecated_at TIMESTAMPTZ,
+    replaced_by   TEXT,
+    sunset_at     TIMESTAMPTZ
+);
+"#,
+    name = "table_api_views",
+    requires = ["schema_bootstrap"]
+);
+
+// Views: v1 public API — stable, read-only columns over the internal tables.
+// Columns of a vN view never change; breaking changes ship as a vN+1 view and
+// the old one is marked with kerai.deprecate_api_view(). Readers get SELECT
+// on these views through the kerai_reader role and nothing else; the views
+// run as their owner, so no grants on the underlying tables are needed.
+extension_sql!(
+    r#"
+CREATE VIEW kerai.v1_nodes AS
+SELECT
+    n.id,
+    n.instance_id,
+    n.kind,
+    n.language,
+    n.content,
+    n.parent_id,
+    n.position,
+    n.path::text AS path,
+    n.metadata,
+    n.created_at
+FROM kerai.nodes n;
+
+COMMENT ON VIEW kerai.v1_nodes IS 'Stable API v1: AST and document nodes';
+COMMENT ON COLUMN kerai.v1_nodes.id IS 'Node UUID';
+COMMENT ON COLUMN kerai.v1_nodes.instance_id IS 'Instance that created the node';
+COMMENT ON COLUMN kerai.v1_nodes.kind IS 'Node kind, e.g. fn, struct, heading, paragraph';
+COMMENT ON COLUMN kerai.v1_nodes.language IS 'Source language, NULL for non-code nodes';
+COMMENT ON COLUMN kerai.v1_nodes.content IS 'Node text (name for code items)';
+COMMENT ON COLUMN kerai.v1_nodes.parent_id IS 'Parent node, NULL for roots';
+COMMENT ON COLUMN kerai.v1_nodes.position IS 'Order among siblings';
+COMMENT ON COLUMN kerai.v1_nodes.path IS 'Dotted ltree path as text';
+COMMENT ON COLUMN kerai.v1_nodes.metadata IS 'Kind-specific attributes (JSON object)';
+COMMENT ON COLUMN kerai.v1_nodes.created_at IS 'Creation time';
+
+CREATE VIEW kerai.v1_edges AS
+SELECT
+    e.id,
+    e.source_id,
+    e.target_id,
+    e.relation,
+    e.metadata,
+    e.created_at
+FROM kerai.edges e;
+
+COMMENT ON VIEW kerai.v1_edges IS 'Stable API v1: typed relationships between nodes';
+COMMENT ON COLUMN kerai.v1_edges.id IS 'Edge UUID';
+COMMENT ON COLUMN kerai.v1_edges.source_id IS 'Node the edge starts at';
+COMMENT ON COLUMN kerai.v1_edges.target_id IS 'Node the edge points to';
+COMMENT ON COLUMN kerai.v1_edges.relation IS 'Relation name, e.g. calls, imports, depends_on';
+COMMENT ON COLUMN kerai.v1_edges.metadata IS 'Relation-specific attributes (JSON object)';
+COMMENT ON COLUMN kerai.v1_edges.created_at IS 'Creation time';
+
+CREATE VIEW kerai.v1_history AS
+SELECT
+    v.id,
+    v.node_id,
+    v.operation,
+    v.author,
+    v.timestamp AS lamport_ts,
+    v.branch_id,
+    v.old_parent,
+    v.new_parent,
+    v.old_content,
+    v.new_content,
+    v.created_at
+FROM kerai.versions v;
+
+COMMENT ON VIEW kerai.v1_history IS 'Stable API v1: per-node change history';
+COMMENT ON COLUMN kerai.v1_history.id IS 'Version UUID';
+COMMENT ON COLUMN kerai.v1_history.node_id IS 'Node the change applies to';
+COMMENT ON COLUMN kerai.v1_history.operation IS 'Operation type, e.g. insert_node, update_content';
+COMMENT ON COLUMN kerai.v1_history.author IS 'Key fingerprint of the authoring instance';
+COMMENT ON COLUMN kerai.v1_history.lamport_ts IS 'Hybrid logical clock timestamp (Lamport counter before hybrid clocks); orders changes across instances';
+COMMENT ON COLUMN kerai.v1_history.branch_id IS 'Branch the change was made on, NULL for the main line';
+COMMENT ON COLUMN kerai.v1_history.old_parent IS 'Parent before the change';
+COMMENT ON COLUMN kerai.v1_history.new_parent IS 'Parent after the change';
+COMMENT ON COLUMN kerai.v1_history.old_content IS 'Content before the change';
+COMMENT ON COLUMN kerai.v1_history.new_content IS 'Content after the change';
+COMMENT ON COLUMN kerai.v1_history.created_at IS 'Wall-clock time the change was recorded';
+
+CREATE VIEW kerai.v2_history AS
+SELECT
+    v.id,
+    v.node_id,
+    v.operation,
+    v.author,
+    v.timestamp AS hlc,
+    kerai.hlc_wall_time(v.timestamp, i.clock_skew_ms) AS wall_time,
+    v.branch_id,
+    v.old_parent,
+    v.new_parent,
+    v.old_content,
+    v.new_content,
+    v.created_at
+FROM kerai.versions v
+LEFT JOIN kerai.instances i ON i.id = v.instance_id;
+
+COMMENT ON VIEW kerai.v2_history IS 'Stable API v2: per-node change history with hybrid logical clock times';
+COMMENT ON COLUMN kerai.v2_history.id IS 'Version UUID';
+COMMENT ON COLUMN kerai.v2_history.node_id IS 'Node the change applies to';
+COMMENT ON COLUMN kerai.v2_history.operation IS 'Operation type, e.g. insert_node, update_content';
+COMMENT ON COLUMN kerai.v2_history.author IS 'Key fingerprint of the authoring instance';
+COMMENT ON COLUMN kerai.v2_history.hlc IS 'Hybrid logical clock timestamp (physical_ms << 16 | logical); orders changes across instances';
+COMMENT ON COLUMN kerai.v2_history.wall_time IS 'When the change was made, estimated from hlc and the authoring instance''s clock skew; NULL for changes stamped before hybrid clocks';
+COMMENT ON COLUMN kerai.v2_history.branch_id IS 'Branch the change was made on, NULL for the main line';
+COMMENT ON COLUMN kerai.v2_history.old_parent IS 'Parent before the change';
+COMMENT ON COLUMN kerai.v2_history.new_parent IS 'Parent after the change';
+COMMENT ON COLUMN kerai.v2_history.old_content IS 'Content before the change';
+COMMENT ON COLUMN kerai.v2_history.new_content IS 'Content after the change';
+COMMENT ON COLUMN kerai.v2_history.created_at IS 'Wall-clock time this instance recorded the change';
+
+CREATE VIEW kerai.v1_balances AS
+SELECT
+    w.id AS wallet_id,
+    w.key_fingerprint,
+    w.wallet_type,
+    w.label,
+    COALESCE(t.received, 0) - COALESCE(t.sent, 0) AS balance_nkoi,
+    t.last_activity_at
+FROM kerai.wallets w
+LEFT JOIN (
+    SELECT wallet_id,
+           SUM(amount) FILTER (WHERE dir = 'in')::bigint AS received,
+           SUM(amount) FILTER (WHERE dir = 'out')::bigint AS sent,
+           max(created_at) AS last_activity_at
+    FROM (
+        SELECT to_wallet AS wallet_id, amount, created_at, 'in' AS dir FROM kerai.ledger
+        UNION ALL
+        SELECT from_wallet, amount, created_at, 'out' FROM kerai.ledger WHERE from_wallet IS NOT NULL
+    ) moves
+    GROUP BY wallet_id
+) t ON t.wallet_id = w.id;
+
+COMMENT ON VIEW kerai.v1_balances IS 'Stable API v1: wallet balances derived from the ledger';
+COMMENT ON COLUMN kerai.v1_balances.wallet_id IS 'Wallet UUID';
+COMMENT ON COLUMN kerai.v1_balances.key_fingerprint IS 'Fingerprint of the wallet key';
+COMMENT ON COLUMN kerai.v1_balances.wallet_type IS 'instance, human, agent, external or system';
+COMMENT ON COLUMN kerai.v1_balances.label IS 'Display label';
+COMMENT ON COLUMN kerai.v1_balances.balance_nkoi IS 'Balance in nKoi (1 Koi = 10^9 nKoi)';
+COMMENT ON COLUMN kerai.v1_balances.last_activity_at IS 'Time of the latest ledger entry, NULL if none';
+
+INSERT INTO kerai.api_views (name, version, description) VALUES
+    ('v1_nodes', 1, 'AST and document nodes'),
+    ('v1_edges', 1, 'Typed relationships between nodes'),
+    ('v1_history', 1, 'Per-node change history'),
+    ('v2_history', 2, 'Per-node change history with hybrid logical clock times'),
+    ('v1_balances', 1, 'Wallet balances derived from the ledger');
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'kerai_reader') THEN
+        CREATE ROLE kerai_reader NOLOGIN;
+    END IF;
+END
+$$;
+GRANT USAGE ON SCHEMA kerai TO kerai_reader;
+GRANT SELECT ON kerai.v1_nodes, kerai.v1_edges, kerai.v1_history, kerai.v2_history,
+    kerai.v1_balances, kerai.api_views TO kerai_reader;
+"#,
+    name = "views_api_v1",
+    requires = [
+        "table_api_views",
+        "table_nodes",
+        "table_edges",
+        "table_versions",
+        "table_ledger",
+        "function_hlc_wall_time"
+    ]
+);
+
+// Table: path_grants — which users may read/write which node subtrees under
+// row-level security (see kerai.enable_rls())
+extension_sql!(
+    r#"
+CREATE TABLE kerai.path_grants (
+    id          BIGSERIAL PRIMARY KEY,
+    path        ltree NOT NULL,
+    user_id     UUID REFERENCES kerai.users(id) ON DELETE CASCADE,  -- NULL: every allowed user
+    can_write   BOOLEAN NOT NULL DEFAULT false,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE NULLS NOT DISTINCT (path, user_id)
+);
+
+CREATE INDEX idx_path_grants_path ON kerai.path_grants USING gist (path);
+CREATE INDEX idx_path_grants_user ON kerai.path_grants (user_id);
+"#,
+    name = "table_path_grants",
+    requires = ["table_users"]
+);
+
+// Functions: RLS helpers — the policies kerai.enable_rls() creates call these.
+// kerai.current_principal holds a session or API token rather than a user id,
+// so a direct SQL user cannot act as someone else just by setting it. They
+// run as the extension owner so policies can read users, tokens and grants.
+extension_sql!(
+    r#"
+CREATE FUNCTION kerai.principal_user() RETURNS uuid
+LANGUAGE sql STABLE SECURITY DEFINER SET search_path = kerai, public, pg_temp AS $$
+    WITH p AS (SELECT NULLIF(current_setting('kerai.current_principal', true), '') AS token)
+    SELECT s.user_id
+    FROM kerai.sessions s
+    JOIN kerai.users u ON u.id = s.user_id, p
+    WHERE s.token = p.token
+      AND s.expires_at > now()
+      AND (u.is_allowed OR u.is_admin)
+    UNION ALL
+    SELECT t.user_id
+    FROM kerai.api_tokens t
+    JOIN kerai.users u ON u.id = t.user_id, p
+    WHERE t.token_hash = encode(sha256(convert_to(p.token, 'UTF8')), 'hex')
+      AND t.revoked_at IS NULL
+      AND (t.expires_at IS NULL OR t.expires_at > now())
+      AND (u.is_allowed OR u.is_admin)
+    LIMIT 1
+$$;
+
+CREATE FUNCTION kerai.rls_can_access(target ltree, want_write boolean) RETURNS boolean
+LANGUAGE sql STABLE SECURITY DEFINER SET search_path = kerai, public, pg_temp AS $$
+    WITH p AS (SELECT kerai.principal_user() AS user_id)
+    SELECT EXISTS (
+            SELECT 1 FROM kerai.users u, p WHERE u.id = p.user_id AND u.is_admin
+        )
+        OR (target IS NOT NULL AND EXISTS (
+            SELECT 1 FROM kerai.path_grants g, p
+            WHERE p.user_id IS NOT NULL
+              AND (g.user_id = p.user_id OR g.user_id IS NULL)
+              AND target <@ g.path
+              AND (g.can_write OR NOT want_write)
+        ))
+$$;
+
+CREATE FUNCTION kerai.rls_node_access(node uuid, want_write boolean) RETURNS boolean
+LANGUAGE sql STABLE SECURITY DEFINER SET search_path = kerai, public, pg_temp AS $$
+    SELECT kerai.rls_can_access((SELECT path FROM kerai.nodes WHERE id = node), want_write)
+$$;
+"#,
+    name = "functions_rls",
+    requires = ["table_path_grants", "table_sessions", "table_api_tokens", "table_nodes"]
+);
+
+// Table: sync_log — one row per background sync exchange with a peer
+extension_sql!(
+    r#"
+CREATE TABLE kerai.sync_log (
+    id           BIGSERIAL PRIMARY KEY,
+    peer_id      UUID NOT NULL REFERENCES kerai.instances(id) ON DELETE CASCADE,
+    endpoint     TEXT NOT NULL,
+    started_at   TIMESTAMPTZ NOT NULL,
+    finished_at  TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp(),
+    status       TEXT NOT NULL CHECK (status IN ('ok', 'error', 'held')),
+    pulled       INTEGER NOT NULL DEFAULT 0,   -- ops applied from the peer
+    superseded   INTEGER NOT NULL DEFAULT 0,
+    duplicates   INTEGER NOT NULL DEFAULT 0,
+    pushed       INTEGER NOT NULL DEFAULT 0,   -- ops the peer applied from us
+    clock_skew_ms BIGINT,                      -- peer clock minus ours, from its signed reply
+    transferred  INTEGER NOT NULL DEFAULT 0,   -- ops sent either way, counted against the op cap
+    bytes        BIGINT NOT NULL DEFAULT 0,    -- their size as JSON, counted against the byte cap
+    deferred     INTEGER NOT NULL DEFAULT 0,   -- ops left for a later exchange by the caps
+    error        TEXT                          -- or, when held, why
+);
+
+CREATE INDEX idx_sync_log_peer ON kerai.sync_log (peer_id, started_at DESC);
+"#,
+    name = "table_sync_log",
+    requires = ["table_instances"]
+);
+
+// Incremental consensus: perspective changes are queued as deltas by trigger
+// and folded into consensus_state / agent_stats by the consensus worker, so
+// reads never aggregate kerai.perspectives.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.perspective_deltas (
+    id          BIGSERIAL PRIMARY KEY,
+    agent_id    UUID NOT NULL,
+    node_id     UUID NOT NULL,
+    context_id  UUID,
+    weight      DOUBLE PRECISION NOT NULL,
+    diff        SMALLINT NOT NULL CHECK (diff IN (-1, 1)),
+    agent_diff  SMALLINT NOT NULL DEFAULT 0,   -- change in distinct agents on (node, context)
+    queued_at   TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp()
+);
+
+CREATE TABLE kerai.consensus_state (
+    node_id            UUID NOT NULL,
+    context_id         UUID,
+    agent_count        INTEGER NOT NULL DEFAULT 0,
+    perspective_count  INTEGER NOT NULL DEFAULT 0,
+    weight_sum         DOUBLE PRECISION NOT NULL DEFAULT 0,
+    weight_sq_sum      DOUBLE PRECISION NOT NULL DEFAULT 0,
+    min_weight         DOUBLE PRECISION,
+    max_weight         DOUBLE PRECISION,
+    updated_at         TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp(),
+    UNIQUE NULLS NOT DISTINCT (node_id, context_id)
+);
+
+CREATE INDEX idx_consensus_state_agents ON kerai.consensus_state (agent_count);
+
+CREATE TABLE kerai.agent_stats (
+    agent_id           UUID PRIMARY KEY,
+    perspective_count  INTEGER NOT NULL DEFAULT 0,
+    weight_sum         DOUBLE PRECISION NOT NULL DEFAULT 0,
+    updated_at         TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp()
+);
+
+CREATE FUNCTION kerai.queue_perspective_delta() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF TG_OP = 'UPDATE'
+       AND NEW.weight = OLD.weight
+       AND NEW.agent_id = OLD.agent_id
+       AND NEW.node_id = OLD.node_id
+       AND NEW.context_id IS NOT DISTINCT FROM OLD.context_id THEN
+        RETURN NULL;
+    END IF;
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        INSERT INTO kerai.perspective_deltas (agent_id, node_id, context_id, weight, diff, agent_diff)
+        VALUES (OLD.agent_id, OLD.node_id, OLD.context_id, OLD.weight, -1,
+            CASE WHEN EXISTS (
+                SELECT 1 FROM kerai.perspectives p
+                WHERE p.agent_id = OLD.agent_id AND p.node_id = OLD.node_id
+                  AND p.context_id IS NOT DISTINCT FROM OLD.context_id AND p.id <> OLD.id
+            ) THEN 0 ELSE -1 END);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        INSERT INTO kerai.perspective_deltas (agent_id, node_id, context_id, weight, diff, agent_diff)
+        VALUES (NEW.agent_id, NEW.node_id, NEW.context_id, NEW.weight, 1,
+            CASE WHEN EXISTS (
+                SELECT 1 FROM kerai.perspectives p
+                WHERE p.agent_id = NEW.agent_id AND p.node_id = NEW.node_id
+                  AND p.context_id IS NOT DISTINCT FROM NEW.context_id AND p.id <> NEW.id
+            ) THEN 0 ELSE 1 END);
+    END IF;
+    RETURN NULL;
+END;
+$$;
+
+CREATE FUNCTION kerai.clear_consensus_state() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    TRUNCATE kerai.perspective_deltas, kerai.consensus_state, kerai.agent_stats;
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER perspectives_queue_delta
+    AFTER INSERT OR UPDATE OR DELETE ON kerai.perspectives
+    FOR EACH ROW EXECUTE FUNCTION kerai.queue_perspective_delta();
+
+CREATE TRIGGER perspectives_truncate_state
+    AFTER TRUNCATE ON kerai.perspectives
+    FOR EACH STATEMENT EXECUTE FUNCTION kerai.clear_consensus_state();
+"#,
+    name = "table_consensus_state",
+    requires = ["table_perspectives"]
+);
+
+// Incremental activity timeline: new versions, perspective weights and
+// parse runs are queued by trigger and folded into activity_daily by the
+// activity worker, so kerai.timeline reads counts rather than history.
+extension_sql!(
+    r#"
+CREATE TABLE kerai.activity_deltas (
+    id         BIGSERIAL PRIMARY KEY,
+    day        DATE NOT NULL,
+    source     TEXT NOT NULL CHECK (source IN ('version', 'perspective', 'parse')),
+    author     TEXT NOT NULL,
+    scope      ltree NOT NULL,
+    queued_at  TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp()
+);
+
+CREATE TABLE kerai.activity_daily (
+    day        DATE NOT NULL,
+    source     TEXT NOT NULL,
+    author     TEXT NOT NULL,
+    scope      ltree NOT NULL,
+    events     INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (day, source, author, scope)
+);
+
+CREATE INDEX idx_activity_daily_scope ON kerai.activity_daily USING gist (scope);
+
+CREATE FUNCTION kerai.activity_scope(path ltree) RETURNS ltree
+LANGUAGE sql IMMUTABLE AS $$
+    SELECT COALESCE(subpath(path, 0, least(nlevel(path), 3)), ''::ltree)
+$$;
+
+CREATE FUNCTION kerai.queue_activity() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF TG_TABLE_NAME = 'perspectives' THEN
+        IF TG_OP = 'UPDATE' AND NEW.weight = OLD.weight THEN
+            RETURN NULL;
+        END IF;
+        INSERT INTO kerai.activity_deltas (day, source, author, scope)
+        SELECT (NEW.updated_at AT TIME ZONE 'UTC')::date, 'perspective',
+               COALESCE((SELECT name FROM kerai.agents WHERE id = NEW.agent_id), NEW.agent_id::text),
+               kerai.activity_scope((SELECT path FROM kerai.nodes WHERE id = NEW.node_id));
+    ELSIF TG_TABLE_NAME = 'parse_runs' THEN
+        INSERT INTO kerai.activity_deltas (day, source, author, scope)
+        SELECT (NEW.created_at AT TIME ZONE 'UTC')::date, 'parse',
+               COALESCE((SELECT key_fingerprint FROM kerai.instances WHERE is_self), ''),
+               kerai.activity_scope(NEW.path);
+    ELSE
+        INSERT INTO kerai.activity_deltas (day, source, author, scope)
+        SELECT (NEW.created_at AT TIME ZONE 'UTC')::date, 'version', NEW.author,
+               kerai.activity_scope((SELECT path FROM kerai.nodes WHERE id = NEW.node_id));
+    END IF;
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER versions_queue_activity
+    AFTER INSERT ON kerai.versions
+    FOR EACH ROW EXECUTE FUNCTION kerai.queue_activity();
+
+CREATE TRIGGER perspectives_queue_activity
+    AFTER INSERT OR UPDATE OF weight ON kerai.perspectives
+    FOR EACH ROW EXECUTE FUNCTION kerai.queue_activity();
+
+CREATE TRIGGER parse_runs_queue_activity
+    AFTER INSERT ON kerai.parse_runs
+    FOR EACH ROW EXECUTE FUNCTION kerai.queue_activity();
+"#,
+    name = "table_activity_timeline",
+    requires = [
+        "table_versions",
+        "table_perspectives",
+        "table_parse_runs",
+        "table_agents",
+        "table_instances"
+    ]
+);
+
+// Table: session_recordings — opt-in log of web editor websocket traffic
+extension_sql!(
+    r#"
+CREATE TABLE kerai.session_recordings (
+    id            BIGSERIAL PRIMARY KEY,
+    recording_id  UUID NOT NULL,               -- one per websocket connection
+    seq           INTEGER NOT NULL,
+    offset_ms     INTEGER NOT NULL,            -- since the connection opened
+    direction     TEXT NOT NULL CHECK (direction IN ('in', 'out')),
+    user_id       UUID REFERENCES kerai.users(id) ON DELETE SET NULL,
+    workspace_id  UUID REFERENCES kerai.workspaces(id) ON DELETE SET NULL,
+    message       JSONB NOT NULL,              -- content strings replaced by hashes
+    outcome       JSONB,                       -- server's result for an 'in' message
+    recorded_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (recording_id, seq)
+);
+
+CREATE INDEX idx_session_recordings_time ON kerai.session_recordings (recorded_at);
+"#,
+    name = "table_session_recordings",
+    requires = ["table_users", "table_workspaces"]
+);
+
+// Table: notification_cursors — per-session position in the operation
+// stream for /api/notifications/poll, one per watched region
+extension_sql!(
+    r#"
+CREATE TABLE kerai.notification_cursors (
+    session_id  UUID NOT NULL REFERENCES kerai.sessions(id) ON DELETE CASCADE,
+    region      ltree NOT NULL DEFAULT '',     -- '' watches everything
+    cursor      TEXT NOT NULL DEFAULT '0-0',   -- from kerai.poll_changes; everything before it was delivered
+    delivered   BIGINT NOT NULL DEFAULT 0,     -- events delivered so far
+    polled_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (session_id, region)
+);
+"#,
+    name = "table_notification_cursors",
+    requires = ["table_sessions", "table_operations"]
+);
+
+// Table: subscriptions — users following a subtree or lquery, with the
+// operation types and digest frequency they want; the digest worker rolls
+// matching operations into `digest` nodes (kerai.deliver_digests)
+extension_sql!(
+    r#"
+CREATE TABLE kerai.subscriptions (
+    id           UUID PRIMARY KEY DEFAULT kerai.uuid7(),
+    user_id      UUID NOT NULL REFERENCES kerai.users(id) ON DELETE CASCADE,
+    pattern      TEXT NOT NULL,                 -- ltree subtree root, or lquery with * | !
+    event_types  TEXT[],                        -- op_types digested; NULL: every one
+    frequency    TEXT NOT NULL DEFAULT 'daily'
+                 CHECK (frequency IN ('hourly', 'daily', 'weekly')),
+    cursor       TEXT NOT NULL DEFAULT '0-0',   -- poll cursor; everything before it was digested
+    digested_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (user_id, pattern)
+);
+
+CREATE INDEX idx_nodes_digest_user ON kerai.nodes ((metadata->>'user_id'), created_at)
+    WHERE kind = 'digest';
+"#,
+    name = "table_subscriptions",
+    requires = ["table_users", "table_nodes", "table_operations"]
+);
+
+// Trigger: summary staleness — a change under a summarized subtree flags
+// the summaries of every ancestor stale (kerai.summarize_subtree)
+extension_sql!(
+    r#"
+CREATE FUNCTION kerai.mark_summaries_stale() RETURNS trigger
+LANGUAGE plpgsql AS $$
+DECLARE
+    changed UUID[];
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM kerai.edges WHERE relation = 'summarizes') THEN
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP = 'INSERT' THEN
+        IF NEW.kind = 'summary' THEN RETURN NULL; END IF;
+        changed := ARRAY[NEW.id, NEW.parent_id];
+    ELSIF TG_OP = 'DELETE' THEN
+        IF OLD.kind = 'summary' THEN RETURN NULL; END IF;
+        changed := ARRAY[OLD.parent_id];
+    ELSE
+        IF NEW.kind = 'summary'
+           OR (NEW.content IS NOT DISTINCT FROM OLD.content
+               AND NEW.parent_id IS NOT DISTINCT FROM OLD.parent_id
+               AND NEW.position = OLD.position
+               AND NEW.deleted_at IS NOT DISTINCT FROM OLD.deleted_at) THEN
+            RETURN NULL;
+        END IF;
+        changed := ARRAY[NEW.id, NEW.parent_id, OLD.parent_id];
+    END IF;
+
+    WITH RECURSIVE up AS (
+        SELECT id, parent_id FROM kerai.nodes WHERE id = ANY(changed)
+        UNION
+        SELECT n.id, n.parent_id FROM kerai.nodes n JOIN up ON n.id = up.parent_id
+    )
+    UPDATE kerai.nodes s
+    SET metadata = s.metadata || jsonb_build_object('stale', true, 'stale_since', now())
+    FROM kerai.edges e
+    WHERE e.relation = 'summarizes'
+      AND e.source_id = s.id
+      AND e.target_id IN (SELECT id FROM up)
+      AND NOT COALESCE((s.metadata->>'stale')::boolean, false);
+    RETURN NULL;
+END $$;
+
+CREATE TRIGGER nodes_mark_summaries_stale
+    AFTER INSERT OR UPDATE OR DELETE ON kerai.nodes
+    FOR EACH ROW EXECUTE FUNCTION kerai.mark_summaries_stale();
+"#,
+    name = "trigger_summary_staleness",
+    requires = ["table_nodes", "table_edges"]
+);
+
+// Staleness of documentation: doc nodes linked to code by `documents` or
+// `references` edges, against the code subtree's latest change
+extension_sql!(
+    r#"
+CREATE FUNCTION kerai.touch_node_modified() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    NEW.modified_at := now();
+    RETURN NEW;
+END $$;
+
+CREATE TRIGGER nodes_touch_modified
+    BEFORE UPDATE OF content, parent_id ON kerai.nodes
+    FOR EACH ROW
+    WHEN (OLD.content IS DISTINCT FROM NEW.content OR OLD.parent_id IS DISTINCT FROM NEW.parent_id)
+    EXECUTE FUNCTION kerai.touch_node_modified();
+
+-- score is 1 - 0.5^(gap / kerai.stale_doc_days): 0.5 at the threshold
+CREATE VIEW kerai.doc_staleness AS
+SELECT l.doc_id, l.code_id, l.relation,
+       d.modified_at AS doc_modified,
+       c.code_modified,
+       g.gap_days,
+       1 - power(0.5, g.gap_days / t.days) AS score,
+       g.gap_days >= t.days AS over_threshold
+FROM (
+    SELECT source_id AS doc_id, target_id AS code_id, relation
+    FROM kerai.edges WHERE relation IN ('documents', 'references')
+) l
+JOIN kerai.nodes d ON d.id = l.doc_id
+CROSS JOIN LATERAL (
+    WITH RECURSIVE sub AS (
+        SELECT id, modified_at FROM kerai.nodes WHERE id = l.code_id
+        UNION ALL
+        SELECT n.id, n.modified_at FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+    )
+    SELECT max(modified_at) AS code_modified FROM sub
+) c
+CROSS JOIN LATERAL (
+    SELECT GREATEST(extract(epoch FROM c.code_modified - d.modified_at) / 86400.0, 0)::float8 AS gap_days
+) g
+CROSS JOIN LATERAL (
+    SELECT COALESCE(NULLIF(current_setting('kerai.stale_doc_days', true), '')::float8, 30) AS days
+) t
+WHERE c.code_modified IS NOT NULL;
+"#,
+    name = "view_doc_staleness",
+    requires = ["table_nodes", "table_edges"]
+);
+
+// Triggers: change events — each row change to nodes, edges and versions is
+// sent as JSON ({table, op, id, path, kind, instance, ...}) on kerai_nodes,
+// kerai_edges or kerai_versions, for kerai-web's filtered subscriptions.
+// SET kerai.change_events = off skips them, e.g. for a bulk import.
+extension_sql!(
+    r#"
+CREATE FUNCTION kerai.notify_change() RETURNS trigger
+LANGUAGE plpgsql AS $$
+DECLARE
+    event jsonb;
+BEGIN
+    IF NOT COALESCE(NULLIF(current_setting('kerai.change_events', true), '')::boolean, true) THEN
+        RETURN NULL;
+    END IF;
+
+    IF TG_ARGV[0] = 'nodes' THEN
+        IF TG_OP = 'DELETE' THEN
+            event := jsonb_build_object('id', OLD.id, 'path', OLD.path, 'kind', OLD.kind,
+                                        'instance', OLD.instance_id, 'parent_id', OLD.parent_id);
+        ELSE
+            event := jsonb_build_object('id', NEW.id, 'path', NEW.path, 'kind', NEW.kind,
+                                        'instance', NEW.instance_id, 'parent_id', NEW.parent_id);
+            IF TG_OP = 'UPDATE' AND OLD.path IS DISTINCT FROM NEW.path THEN
+                event := event || jsonb_build_object('old_path', OLD.path);
+            END IF;
+        END IF;
+    ELSIF TG_ARGV[0] = 'edges' THEN
+        IF TG_OP = 'DELETE' THEN
+            event := jsonb_build_object('id', OLD.id, 'kind', OLD.relation,
+                                        'source_id', OLD.source_id, 'target_id', OLD.target_id);
+        ELSE
+            event := jsonb_build_object('id', NEW.id, 'kind', NEW.relation,
+                                        'source_id', NEW.source_id, 'target_id', NEW.target_id);
+        END IF;
+        event := event || COALESCE((
+            SELECT jsonb_build_object('path', n.path, 'instance', n.instance_id)
+            FROM kerai.nodes n WHERE n.id = (event->>'source_id')::uuid
+        ), '{}'::jsonb);
+    ELSE
+        IF TG_OP = 'DELETE' THEN
+            event := jsonb_build_object('id', OLD.id, 'node_id', OLD.node_id,
+                                        'instance', OLD.instance_id, 'operation', OLD.operation,
+                                        'author', OLD.author);
+        ELSE
+            event := jsonb_build_object('id', NEW.id, 'node_id', NEW.node_id,
+                                        'instance', NEW.instance_id, 'operation', NEW.operation,
+                                        'author', NEW.author);
+        END IF;
+        event := event || COALESCE((
+            SELECT jsonb_build_object('path', n.path, 'kind', n.kind)
+            FROM kerai.nodes n WHERE n.id = (event->>'node_id')::uuid
+        ), '{}'::jsonb);
+    END IF;
+
+    PERFORM pg_notify('kerai_' || TG_ARGV[0],
+        (jsonb_build_object('table', TG_ARGV[0], 'op', lower(TG_OP)) || event)::text);
+    RETURN NULL;
+END $$;
+
+CREATE TRIGGER nodes_notify_change
+    AFTER INSERT OR UPDATE OR DELETE ON kerai.nodes
+    FOR EACH ROW EXECUTE FUNCTION kerai.notify_change('nodes');
+
+CREATE TRIGGER edges_notify_change
+    AFTER INSERT OR UPDATE OR DELETE ON kerai.edges
+    FOR EACH ROW EXECUTE FUNCTION kerai.notify_change('edges');
+
+CREATE TRIGGER versions_notify_change
+    AFTER INSERT OR UPDATE OR DELETE ON kerai.versions
+    FOR EACH ROW EXECUTE FUNCTION kerai.notify_change('versions');
+"#,
+    name = "trigger_change_events",
+    requires = ["table_nodes", "table_edges", "table_versions"]
+);
+
+// Table: graph_cache — level-of-detail graphs computed by kerai.graph_lod,
+// reused while the subtree and edges they were computed from are unchanged
+extension_sql!(
+    r#"
+CREATE TABLE kerai.graph_cache (
+    root        TEXT NOT NULL,               -- ltree path graphed, '' for everything
+    lod         INTEGER NOT NULL,
+    max_nodes   INTEGER NOT NULL,
+    filter_key  TEXT NOT NULL,               -- md5 of the role, view and principal read through
+    fingerprint TEXT NOT NULL,               -- row counts and latest timestamps it was computed at
+    graph       JSONB NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (root, lod, max_nodes, filter_key)
+);
+
+CREATE INDEX idx_graph_cache_computed ON kerai.graph_cache (computed_at);
+"#,
+    name = "table_graph_cache",
+    requires = ["table_nodes", "table_edges"]
+);
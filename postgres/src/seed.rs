@@ -0,0 +1,270 @@
+/// Seeding — bundled fixtures for tests, docs and demos.
+///
+/// Each fixture is a gzipped NDJSON file compiled into the extension, one
+/// `{"path", "content"}` line per source file. Seeding runs the files
+/// through the ordinary parsers, so seeded nodes are exactly what importing
+/// the same files would give: a fixture with a `Cargo.toml` is written to a
+/// temporary directory and parsed with `parse_crate`, and any other fixture
+/// is parsed file by file under a `<fixture>/` prefix. Citations are linked
+/// once everything is in.
+///
+/// A fixture already in the database is skipped, so seeding twice leaves
+/// the same state as seeding once.
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use pgrx::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::sql::sql_text;
+
+struct Fixture {
+    name: &'static str,
+    description: &'static str,
+    data: &'static [u8],
+}
+
+const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "crate",
+        description: "Small Rust crate: a word-frequency library and CLI",
+        data: include_bytes!("../fixtures/crate.ndjson.gz"),
+    },
+    Fixture {
+        name: "notes",
+        description: "Markdown knowledge base about the crate",
+        data: include_bytes!("../fixtures/notes.ndjson.gz"),
+    },
+    Fixture {
+        name: "paper",
+        description: "LaTeX paper with a BibTeX bibliography",
+        data: include_bytes!("../fixtures/paper.ndjson.gz"),
+    },
+];
+
+/// Names that seed several fixtures at once.
+const SETS: &[(&str, &[&str])] = &[("demo", &["crate", "notes", "paper"])];
+
+#[derive(Debug, Deserialize)]
+struct FixtureFile {
+    path: String,
+    content: String,
+}
+
+/// Decompress and split a fixture into its files.
+fn load(fixture: &Fixture) -> Result<Vec<FixtureFile>, String> {
+    let mut text = String::new();
+    GzDecoder::new(fixture.data)
+        .read_to_string(&mut text)
+        .map_err(|e| format!("fixture {} is not valid gzip: {e}", fixture.name))?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("fixture {} has a bad line: {e}", fixture.name))
+        })
+        .collect()
+}
+
+/// Fixtures a name stands for: itself, or the members of a set.
+fn resolve(name: &str) -> Option<Vec<&'static Fixture>> {
+    let names: Vec<&str> = match SETS.iter().find(|(set, _)| *set == name) {
+        Some((_, members)) => members.to_vec(),
+        None => vec![name],
+    };
+    names
+        .iter()
+        .map(|n| FIXTURES.iter().find(|f| f.name == *n))
+        .collect()
+}
+
+/// Name of the crate a `Cargo.toml` declares.
+fn crate_name(cargo_toml: &str) -> Option<String> {
+    let value: toml::Value = toml::from_str(cargo_toml).ok()?;
+    Some(value.get("package")?.get("name")?.as_str()?.to_string())
+}
+
+/// Filename a document fixture's file is parsed under.
+fn document_filename(fixture: &Fixture, path: &str) -> String {
+    format!("{}/{}", fixture.name, path)
+}
+
+fn exists(condition: &str) -> bool {
+    Spi::get_one::<bool>(&format!(
+        "SELECT EXISTS (SELECT 1 FROM kerai.nodes WHERE {condition})"
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Whether a fixture's files are already in the database.
+fn present(fixture: &Fixture, files: &[FixtureFile]) -> bool {
+    if let Some(cargo) = files.iter().find(|f| f.path == "Cargo.toml") {
+        return crate_name(&cargo.content).is_some_and(|name| {
+            exists(&format!("kind = 'crate' AND content = {}", sql_text(&name)))
+        });
+    }
+    files.iter().any(|f| {
+        exists(&format!(
+            "kind IN ('file', 'document') AND content = {}",
+            sql_text(&document_filename(fixture, &f.path))
+        ))
+    })
+}
+
+/// Write a crate fixture out and parse it with `parse_crate`.
+fn seed_crate(files: &[FixtureFile]) -> Value {
+    let dir = tempfile::tempdir()
+        .unwrap_or_else(|e| error!("Failed to create a directory for the fixture: {}", e));
+    for file in files {
+        let path = dir.path().join(Path::new(&file.path));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .unwrap_or_else(|e| error!("Failed to write fixture file: {}", e));
+        }
+        std::fs::write(&path, &file.content)
+            .unwrap_or_else(|e| error!("Failed to write fixture file: {}", e));
+    }
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT kerai.parse_crate({})",
+        sql_text(&dir.path().to_string_lossy()),
+    ))
+    .unwrap()
+    .map_or(json!({}), |j| j.0)
+}
+
+/// Parse each file of a document fixture by its extension. Returns the
+/// number of nodes made.
+fn seed_documents(fixture: &Fixture, files: &[FixtureFile]) -> u64 {
+    let mut nodes = 0;
+    for file in files {
+        let parser = match Path::new(&file.path).extension().and_then(|e| e.to_str()) {
+            Some("md") => "parse_markdown",
+            Some("tex") => "parse_latex_source",
+            Some("bib") => "parse_bibtex_source",
+            _ => {
+                warning!("Skipping fixture file {}: no parser for it", file.path);
+                continue;
+            }
+        };
+        let result = Spi::get_one::<pgrx::JsonB>(&format!(
+            "SELECT kerai.{parser}({}, {})",
+            sql_text(&file.content),
+            sql_text(&document_filename(fixture, &file.path)),
+        ))
+        .unwrap();
+        nodes += result.and_then(|r| r.0["nodes"].as_u64()).unwrap_or(0);
+    }
+    nodes
+}
+
+/// Seed the database with a bundled fixture, or with every fixture of a
+/// set (`demo` is all of them). Fixtures already present are skipped.
+///
+/// Returns `{fixture, seeded: [{name, files, nodes}], skipped: [name],
+/// citations}`, where `citations` is the `link_citations` result.
+#[pg_extern]
+fn seed(fixture_name: &str) -> pgrx::JsonB {
+    let fixtures = resolve(fixture_name).unwrap_or_else(|| {
+        let names: Vec<&str> = FIXTURES
+            .iter()
+            .map(|f| f.name)
+            .chain(SETS.iter().map(|(set, _)| *set))
+            .collect();
+        error!(
+            "Unknown fixture '{}'; available: {}",
+            fixture_name,
+            names.join(", ")
+        )
+    });
+
+    let mut seeded = Vec::new();
+    let mut skipped = Vec::new();
+    for fixture in &fixtures {
+        let files = load(fixture).unwrap_or_else(|e| error!("{}", e));
+        if present(fixture, &files) {
+            skipped.push(fixture.name);
+        } else {
+            let nodes = if files.iter().any(|f| f.path == "Cargo.toml") {
+                seed_crate(&files)["nodes"].as_u64().unwrap_or(0)
+            } else {
+                seed_documents(fixture, &files)
+            };
+            seeded.push(json!({ "name": fixture.name, "files": files.len(), "nodes": nodes }));
+        }
+    }
+
+    let citations = if seeded.is_empty() {
+        Value::Null
+    } else {
+        Spi::get_one::<pgrx::JsonB>("SELECT kerai.link_citations()")
+            .unwrap()
+            .map_or(Value::Null, |j| j.0)
+    };
+
+    pgrx::JsonB(json!({
+        "fixture": fixture_name,
+        "seeded": seeded,
+        "skipped": skipped,
+        "citations": citations,
+    }))
+}
+
+/// The bundled fixtures and sets: `[{name, description, files}]`, where a
+/// set's `files` lists its fixtures.
+#[pg_extern]
+fn seed_fixtures() -> pgrx::JsonB {
+    let mut list: Vec<Value> = FIXTURES
+        .iter()
+        .map(|f| {
+            let files: Vec<String> = load(f)
+                .unwrap_or_else(|e| error!("{}", e))
+                .into_iter()
+                .map(|file| file.path)
+                .collect();
+            json!({ "name": f.name, "description": f.description, "files": files })
+        })
+        .collect();
+    list.extend(SETS.iter().map(|(set, members)| {
+        json!({
+            "name": set,
+            "description": format!("Every fixture: {}", members.join(", ")),
+            "files": members,
+        })
+    }));
+    pgrx::JsonB(json!(list))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_decompress() {
+        for fixture in FIXTURES {
+            let files = load(fixture).unwrap();
+            assert!(!files.is_empty(), "{} has no files", fixture.name);
+            assert!(files.iter().all(|f| !f.content.is_empty()));
+        }
+    }
+
+    #[test]
+    fn crate_fixture_declares_its_name() {
+        let files = load(resolve("crate").unwrap()[0]).unwrap();
+        let cargo = files.iter().find(|f| f.path == "Cargo.toml").unwrap();
+        assert_eq!(crate_name(&cargo.content).as_deref(), Some("wordfreq"));
+        assert!(files.iter().any(|f| f.path == "src/lib.rs"));
+    }
+
+    #[test]
+    fn sets_resolve_to_fixtures() {
+        let demo = resolve("demo").unwrap();
+        assert_eq!(
+            demo.iter().map(|f| f.name).collect::<Vec<_>>(),
+            ["crate", "notes", "paper"]
+        );
+        assert!(resolve("nope").is_none());
+    }
+}
@@ -0,0 +1,455 @@
+/// Agent simulation — synthetic agents rating a seeded graph, for tuning
+/// consensus thresholds before real swarms run.
+///
+/// `simulate_agents` builds a throwaway graph under a `kerai_sim_*` path,
+/// gives each node a hidden true weight drawn from the seed, and registers
+/// synthetic agents (kind `simulated`) from a list of profiles. Each round
+/// every agent rates its share of the nodes into kerai.perspectives, with
+/// the graph's root as the context, so the delta triggers queue the
+/// changes and the consensus fold runs exactly as it does for real agents.
+/// The folded consensus is then scored against the hidden weights.
+///
+/// The same config and seed give the same ratings. The graph, the agents
+/// and their perspectives are removed afterwards unless `keep` is set.
+use std::time::Instant;
+
+use pgrx::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Value};
+
+/// How a group of synthetic agents rates nodes.
+#[derive(Debug, Clone, PartialEq)]
+struct Profile {
+    name: String,
+    count: usize,
+    /// Chance a rating follows the node's true weight rather than a guess
+    agreement: f64,
+    /// Standard deviation of the noise added to every rating
+    noise: f64,
+    /// Share of the nodes each agent rates in a round
+    coverage: f64,
+    /// How far each rating is pulled toward the previous round's consensus
+    influence: f64,
+}
+
+#[derive(Debug, PartialEq)]
+struct Config {
+    seed: u64,
+    nodes: usize,
+    rounds: usize,
+    profiles: Vec<Profile>,
+    /// A node has converged with at least this many agents...
+    min_agents: i64,
+    /// ...whose weights have at most this standard deviation
+    max_stddev: f64,
+    /// Converged share of the nodes that ends the search for `converged_at`
+    target: f64,
+    keep: bool,
+}
+
+/// Folded consensus on one node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stat {
+    agents: i64,
+    avg: f64,
+    stddev: Option<f64>,
+}
+
+fn number(config: &Value, key: &str, default: f64, range: (f64, f64)) -> Result<f64, String> {
+    let value = match &config[key] {
+        Value::Null => default,
+        v => v.as_f64().ok_or(format!("{} must be a number", key))?,
+    };
+    if value < range.0 || value > range.1 {
+        return Err(format!(
+            "{} must be between {} and {}, got {}",
+            key, range.0, range.1, value
+        ));
+    }
+    Ok(value)
+}
+
+fn parse_profile(i: usize, v: &Value) -> Result<Profile, String> {
+    let field = |key, default, range| {
+        number(v, key, default, range).map_err(|e| format!("agents[{}]: {}", i, e))
+    };
+    Ok(Profile {
+        name: v["name"]
+            .as_str()
+            .map_or_else(|| format!("profile{}", i), String::from),
+        count: field("count", 1.0, (1.0, 1000.0))? as usize,
+        agreement: field("agreement", 1.0, (0.0, 1.0))?,
+        noise: field("noise", 0.1, (0.0, 2.0))?,
+        coverage: field("coverage", 1.0, (0.0, 1.0))?,
+        influence: field("influence", 0.0, (0.0, 1.0))?,
+    })
+}
+
+/// Read a config, filling in defaults: 50 nodes, 5 rounds, seed 42, and
+/// one profile of five agents that mostly agree.
+fn parse_config(config: &Value) -> Result<Config, String> {
+    if !config.is_object() && !config.is_null() {
+        return Err("config must be a JSON object".to_string());
+    }
+    let profiles = match &config["agents"] {
+        Value::Null => vec![json!({"name": "agent", "count": 5, "agreement": 0.8, "noise": 0.2})],
+        Value::Array(a) if !a.is_empty() => a.clone(),
+        _ => return Err("agents must be a non-empty array of profiles".to_string()),
+    };
+    Ok(Config {
+        seed: number(config, "seed", 42.0, (0.0, u32::MAX as f64))? as u64,
+        nodes: number(config, "nodes", 50.0, (1.0, 100_000.0))? as usize,
+        rounds: number(config, "rounds", 5.0, (1.0, 1000.0))? as usize,
+        profiles: profiles
+            .iter()
+            .enumerate()
+            .map(|(i, p)| parse_profile(i, p))
+            .collect::<Result<_, _>>()?,
+        min_agents: number(config, "min_agents", 2.0, (1.0, 100_000.0))? as i64,
+        max_stddev: number(config, "max_stddev", 0.2, (0.0, 2.0))?,
+        target: number(config, "target", 0.9, (0.0, 1.0))?,
+        keep: config["keep"].as_bool().unwrap_or(false),
+    })
+}
+
+/// A standard normal sample (Box–Muller).
+fn gaussian(rng: &mut StdRng) -> f64 {
+    let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let v: f64 = rng.gen();
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+/// One agent's rating of a node whose true weight is `truth`: the truth
+/// with chance `agreement`, otherwise a uniform guess, plus noise, then
+/// pulled `influence` of the way toward `consensus`.
+fn rate(rng: &mut StdRng, profile: &Profile, truth: f64, consensus: Option<f64>) -> f64 {
+    let signal = if rng.gen::<f64>() < profile.agreement {
+        truth
+    } else {
+        rng.gen_range(-1.0..=1.0)
+    };
+    let own = signal + profile.noise * gaussian(rng);
+    let pulled = consensus.map_or(own, |c| own + profile.influence * (c - own));
+    pulled.clamp(-1.0, 1.0)
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, n) = values.fold((0.0, 0), |(s, n), v| (s + v, n + 1));
+    (n > 0).then(|| sum / n as f64)
+}
+
+/// Score a round's consensus against the true weights: how many nodes
+/// converged, how far the averages are from the truth, and how far they
+/// moved since the previous round.
+fn round_metrics(
+    round: usize,
+    truths: &[f64],
+    stats: &[Option<Stat>],
+    previous: &[Option<Stat>],
+    config: &Config,
+) -> Value {
+    let rated: Vec<(f64, Stat)> = truths
+        .iter()
+        .zip(stats)
+        .filter_map(|(t, s)| Some((*t, (*s)?)))
+        .collect();
+    let converged = rated
+        .iter()
+        .filter(|(_, s)| {
+            s.agents >= config.min_agents && s.stddev.is_some_and(|d| d <= config.max_stddev)
+        })
+        .count();
+    let sign_right = rated
+        .iter()
+        .filter(|(t, s)| (*t >= 0.0) == (s.avg >= 0.0))
+        .count();
+    let shift = mean(
+        stats
+            .iter()
+            .zip(previous)
+            .filter_map(|(s, p)| Some((s.as_ref()?.avg - p.as_ref()?.avg).abs())),
+    );
+    json!({
+        "round": round,
+        "rated": rated.len(),
+        "converged": converged,
+        "converged_fraction": converged as f64 / truths.len().max(1) as f64,
+        "mean_stddev": mean(rated.iter().filter_map(|(_, s)| s.stddev)),
+        "mean_abs_error": mean(rated.iter().map(|(t, s)| (s.avg - t).abs())),
+        "sign_accuracy": (!rated.is_empty()).then(|| sign_right as f64 / rated.len() as f64),
+        "mean_shift": shift,
+    })
+}
+
+/// Create the graph: a `sim_graph` root and one `sim_node` per true
+/// weight. Returns the root id and the node ids in order.
+fn seed_graph(prefix: &str, truths: &[f64], config: &Value) -> (String, Vec<String>) {
+    let instance_id = crate::parser::get_self_instance_id();
+    let root = Spi::get_one_with_args::<String>(
+        "INSERT INTO kerai.nodes (instance_id, kind, content, path, metadata)
+         VALUES ($1::uuid, 'sim_graph', $2, $2::ltree, $3)
+         RETURNING id::text",
+        &[
+            instance_id.as_str().into(),
+            prefix.into(),
+            pgrx::JsonB(config.clone()).into(),
+        ],
+    )
+    .unwrap_or_else(|e| error!("Failed to create simulation graph: {}", e))
+    .unwrap_or_default();
+    let ids = Spi::get_one_with_args::<pgrx::JsonB>(
+        "WITH created AS (
+            INSERT INTO kerai.nodes (instance_id, kind, content, parent_id, position, path, metadata)
+            SELECT $1::uuid, 'sim_node', 'n' || (i - 1), $2::uuid, (i - 1)::int,
+                ($3 || '.n' || (i - 1))::ltree, jsonb_build_object('truth', t::float8)
+            FROM jsonb_array_elements_text($4) WITH ORDINALITY AS w(t, i)
+            RETURNING id, position
+        )
+        SELECT jsonb_agg(id::text ORDER BY position) FROM created",
+        &[
+            instance_id.as_str().into(),
+            root.as_str().into(),
+            prefix.into(),
+            pgrx::JsonB(json!(truths)).into(),
+        ],
+    )
+    .unwrap_or_else(|e| error!("Failed to create simulation nodes: {}", e))
+    .map_or(Value::Null, |j| j.0);
+    let ids = ids
+        .as_array()
+        .map(|a| {
+            a.iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default();
+    (root, ids)
+}
+
+/// Register one agent per profile member. Returns `(agent id, profile
+/// index)` pairs.
+fn register(prefix: &str, profiles: &[Profile]) -> Vec<(String, usize)> {
+    let mut agents = Vec::new();
+    for (p, profile) in profiles.iter().enumerate() {
+        for i in 0..profile.count {
+            let id = Spi::get_one_with_args::<String>(
+                "INSERT INTO kerai.agents (name, kind, config)
+                 VALUES ($1, 'simulated', $2)
+                 RETURNING id::text",
+                &[
+                    format!("{}-{}-{}", prefix, profile.name, i).as_str().into(),
+                    pgrx::JsonB(json!({
+                        "profile": profile.name,
+                        "agreement": profile.agreement,
+                        "noise": profile.noise,
+                        "coverage": profile.coverage,
+                        "influence": profile.influence,
+                    }))
+                    .into(),
+                ],
+            )
+            .unwrap_or_else(|e| error!("Failed to register simulated agent: {}", e))
+            .unwrap_or_default();
+            agents.push((id, p));
+        }
+    }
+    agents
+}
+
+/// Fold the queued deltas and read the consensus on each node in the
+/// graph's context.
+fn read_consensus(root: &str, nodes: &[String]) -> Vec<Option<Stat>> {
+    crate::consensus::fold_deltas();
+    let found = Spi::get_one_with_args::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_object_agg(node_id, jsonb_build_object(
+            'agents', agent_count,
+            'avg', weight_sum / perspective_count,
+            'stddev', CASE WHEN perspective_count > 1 THEN sqrt(GREATEST(
+                (weight_sq_sum - weight_sum * weight_sum / perspective_count)
+                    / (perspective_count - 1), 0)) END
+        )), '{}'::jsonb)
+        FROM kerai.consensus_state
+        WHERE context_id = $1::uuid AND perspective_count > 0",
+        &[root.into()],
+    )
+    .unwrap_or_else(|e| error!("Failed to read simulated consensus: {}", e))
+    .map_or(Value::Null, |j| j.0);
+    nodes
+        .iter()
+        .map(|id| {
+            let s = &found[id];
+            Some(Stat {
+                agents: s["agents"].as_i64()?,
+                avg: s["avg"].as_f64()?,
+                stddev: s["stddev"].as_f64(),
+            })
+        })
+        .collect()
+}
+
+fn cleanup(root: &str, prefix: &str) {
+    Spi::run_with_args(
+        "DELETE FROM kerai.perspectives WHERE context_id = $1::uuid",
+        &[root.into()],
+    )
+    .unwrap_or_else(|e| error!("Failed to remove simulated perspectives: {}", e));
+    crate::consensus::fold_deltas();
+    for sql in [
+        "DELETE FROM kerai.agent_stats WHERE agent_id IN (
+            SELECT id FROM kerai.agents WHERE kind = 'simulated' AND name LIKE $1 || '-%')",
+        "DELETE FROM kerai.agents WHERE kind = 'simulated' AND name LIKE $1 || '-%'",
+        "DELETE FROM kerai.nodes WHERE path <@ $1::ltree AND kind = 'sim_node'",
+        "DELETE FROM kerai.nodes WHERE path = $1::ltree AND kind = 'sim_graph'",
+    ] {
+        Spi::run_with_args(sql, &[prefix.into()])
+            .unwrap_or_else(|e| error!("Failed to clean up simulation: {}", e));
+    }
+}
+
+/// Simulate agents rating a seeded graph and report how consensus forms.
+///
+/// `config` (every key optional): `seed`, `nodes` (50), `rounds` (5),
+/// `agents` — profiles of `{name, count, agreement, noise, coverage,
+/// influence}`, `agreement` the chance a rating follows the truth,
+/// `coverage` the share of nodes rated per round and `influence` the pull
+/// toward the last round's consensus — and the thresholds under test:
+/// `min_agents` (2) and `max_stddev` (0.2) for a node to count as
+/// converged, `target` (0.9) the converged share for `converged_at`.
+/// `keep: true` leaves the graph, agents and perspectives in place.
+///
+/// Returns `{seed, graph, nodes, agents, rounds: [{round, rated,
+/// converged, converged_fraction, mean_stddev, mean_abs_error,
+/// sign_accuracy, mean_shift}], converged_at, kept, elapsed_ms}`.
+#[pg_extern]
+fn simulate_agents(config: default!(pgrx::JsonB, "'{}'::jsonb")) -> pgrx::JsonB {
+    let start = Instant::now();
+    let cfg = parse_config(&config.0).unwrap_or_else(|e| error!("{}", e));
+    let mut rng = StdRng::seed_from_u64(cfg.seed);
+
+    let truths: Vec<f64> = (0..cfg.nodes).map(|_| rng.gen_range(-1.0..=1.0)).collect();
+    let prefix = format!(
+        "kerai_sim_{}",
+        &uuid::Uuid::new_v4().simple().to_string()[..12]
+    );
+    let (root, nodes) = seed_graph(&prefix, &truths, &config.0);
+    let agents = register(&prefix, &cfg.profiles);
+
+    let mut previous: Vec<Option<Stat>> = vec![None; nodes.len()];
+    let mut rounds = Vec::with_capacity(cfg.rounds);
+    let mut converged_at = None;
+    for round in 1..=cfg.rounds {
+        let mut ratings = Vec::new();
+        for (agent_id, p) in &agents {
+            let profile = &cfg.profiles[*p];
+            for (i, node_id) in nodes.iter().enumerate() {
+                if rng.gen::<f64>() >= profile.coverage {
+                    continue;
+                }
+                let consensus = previous[i].map(|s| s.avg);
+                let weight = rate(&mut rng, profile, truths[i], consensus);
+                ratings.push(json!({"agent_id": agent_id, "node_id": node_id, "weight": weight}));
+            }
+        }
+        Spi::run_with_args(
+            "INSERT INTO kerai.perspectives (agent_id, node_id, weight, context_id, reasoning)
+             SELECT r.agent_id, r.node_id, r.weight, $2::uuid, 'simulated'
+             FROM jsonb_to_recordset($1) AS r(agent_id uuid, node_id uuid, weight float8)
+             ON CONFLICT (agent_id, node_id, context_id)
+             DO UPDATE SET weight = EXCLUDED.weight, updated_at = now()",
+            &[pgrx::JsonB(json!(ratings)).into(), root.as_str().into()],
+        )
+        .unwrap_or_else(|e| error!("Failed to write simulated perspectives: {}", e));
+
+        let stats = read_consensus(&root, &nodes);
+        let metrics = round_metrics(round, &truths, &stats, &previous, &cfg);
+        if converged_at.is_none()
+            && metrics["converged_fraction"].as_f64().unwrap_or(0.0) >= cfg.target
+        {
+            converged_at = Some(round);
+        }
+        rounds.push(metrics);
+        previous = stats;
+    }
+
+    if !cfg.keep {
+        cleanup(&root, &prefix);
+    }
+
+    pgrx::JsonB(json!({
+        "seed": cfg.seed,
+        "graph": if cfg.keep { json!(prefix) } else { Value::Null },
+        "nodes": nodes.len(),
+        "agents": agents.len(),
+        "rounds": rounds,
+        "converged_at": converged_at,
+        "kept": cfg.keep,
+        "elapsed_ms": start.elapsed().as_millis() as u64,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_defaults_and_limits() {
+        let cfg = parse_config(&json!({})).unwrap();
+        assert_eq!((cfg.seed, cfg.nodes, cfg.rounds), (42, 50, 5));
+        assert_eq!(cfg.profiles.len(), 1);
+        assert_eq!(cfg.profiles[0].count, 5);
+        assert_eq!(cfg.profiles[0].influence, 0.0);
+
+        let cfg = parse_config(&json!({
+            "agents": [{"name": "herd", "count": 3, "influence": 0.5}, {"noise": 0.6}]
+        }))
+        .unwrap();
+        assert_eq!(cfg.profiles[0].name, "herd");
+        assert_eq!(cfg.profiles[1].name, "profile1");
+        assert_eq!(cfg.profiles[1].agreement, 1.0);
+
+        let err = parse_config(&json!({"agents": [{"agreement": 1.5}]})).unwrap_err();
+        assert!(err.starts_with("agents[0]: agreement"), "{err}");
+        assert!(parse_config(&json!({"agents": []})).is_err());
+        assert!(parse_config(&json!({"rounds": "many"})).is_err());
+    }
+
+    #[test]
+    fn ratings_are_seeded_and_bounded() {
+        let profile = parse_profile(0, &json!({"noise": 0.5, "agreement": 0.5})).unwrap();
+        let draw = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..100)
+                .map(|_| rate(&mut rng, &profile, 0.9, None))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(draw(7), draw(7));
+        assert!(draw(7).iter().all(|w| (-1.0..=1.0).contains(w)));
+
+        let herd = parse_profile(0, &json!({"noise": 0.0, "influence": 1.0})).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!((rate(&mut rng, &herd, 0.9, Some(-0.3)) + 0.3).abs() < 1e-12);
+    }
+
+    #[test]
+    fn metrics_score_against_the_truth() {
+        let cfg = parse_config(&json!({})).unwrap();
+        let stat = |agents, avg, stddev| {
+            Some(Stat {
+                agents,
+                avg,
+                stddev: Some(stddev),
+            })
+        };
+        let truths = [0.5, -0.5, 0.2];
+        let stats = [stat(3, 0.4, 0.1), stat(3, 0.1, 0.5), None];
+        let previous = [stat(3, 0.2, 0.1), None, None];
+        let m = round_metrics(2, &truths, &stats, &previous, &cfg);
+        assert_eq!(m["rated"], 2);
+        assert_eq!(m["converged"], 1);
+        assert!((m["converged_fraction"].as_f64().unwrap() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(m["sign_accuracy"], 0.5);
+        assert!((m["mean_abs_error"].as_f64().unwrap() - 0.35).abs() < 1e-9);
+        assert!((m["mean_shift"].as_f64().unwrap() - 0.2).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,274 @@
+/// Snapshots — named, frozen copies of the parsed tree, for release tags.
+///
+/// `create_snapshot` copies every file and document subtree (plus the
+/// crate nodes above them) into kerai.snapshot_nodes, each node with its
+/// Merkle hash: SHA-256 over its kind, content, metadata and its children's
+/// hashes, as `content_hash` is computed at parse time. The snapshot's
+/// `root_hash` covers every file's path and hash, so two snapshots with
+/// the same root hold the same sources.
+///
+/// Nodes keep changing after a snapshot, and parser writes are not
+/// versioned, so a snapshot cannot be rebuilt from kerai.versions.
+/// `restore_snapshot` instead puts the frozen rows back into kerai.nodes
+/// inside a transaction the caller rolls back once it has reconstructed
+/// what it needs, as `kerai postgres checkout --snapshot` does.
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::crdt::clock;
+use crate::parser::inserter::{load_file_tree, subtree_hashes};
+use crate::sql::{sql_text, sql_uuid};
+
+/// Node ids per INSERT when copying nodes into a snapshot.
+const BATCH_SIZE: usize = 1000;
+
+/// Merkle root over `(kind, path, hash)` of each file and document, taken
+/// in path order so it does not depend on node ids.
+fn root_hash(roots: &mut [(String, String, String)]) -> String {
+    roots.sort();
+    let mut hasher = Sha256::new();
+    for (path, kind, hash) in roots.iter() {
+        for part in [kind, path, hash] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+    }
+    hex::encode(hasher.finalize())
+}
+
+fn snapshot_id(name: &str) -> String {
+    Spi::get_one::<String>(&format!(
+        "SELECT id::text FROM kerai.snapshots WHERE name = {}",
+        sql_text(name),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("Snapshot not found: {}", name))
+}
+
+/// Record the current file and document trees as snapshot `name`.
+///
+/// Returns `{id, name, timestamp, root_hash, files, nodes}`, `timestamp`
+/// being the Lamport time the snapshot was taken at.
+#[pg_extern]
+fn create_snapshot(name: &str) -> pgrx::JsonB {
+    if name.trim().is_empty() {
+        error!("Snapshot name must not be empty");
+    }
+    let taken = Spi::get_one::<bool>(&format!(
+        "SELECT EXISTS(SELECT 1 FROM kerai.snapshots WHERE name = {})",
+        sql_text(name),
+    ))
+    .unwrap()
+    .unwrap_or(false);
+    if taken {
+        error!("Snapshot already exists: {}", name);
+    }
+
+    let roots = Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', id, 'kind', kind, 'path', COALESCE(metadata->>'source_path', content)
+        )), '[]'::jsonb)
+        FROM kerai.nodes WHERE kind IN ('file', 'document')",
+    )
+    .unwrap()
+    .map(|j| j.0)
+    .unwrap_or_else(|| json!([]));
+    let roots = roots.as_array().cloned().unwrap_or_default();
+
+    let mut hashed: Vec<(String, String)> = Vec::new();
+    let mut files = Vec::with_capacity(roots.len());
+    for root in &roots {
+        let id = root["id"].as_str().unwrap_or_default();
+        let hashes = subtree_hashes(&load_file_tree(id));
+        files.push((
+            root["path"].as_str().unwrap_or_default().to_string(),
+            root["kind"].as_str().unwrap_or_default().to_string(),
+            hashes.get(id).cloned().unwrap_or_default(),
+        ));
+        hashed.extend(hashes);
+    }
+    let root_hash = root_hash(&mut files);
+    let timestamp = clock::current_lamport_ts();
+
+    let id = Spi::get_one::<String>(&format!(
+        "INSERT INTO kerai.snapshots (name, branch_id, timestamp, root_hash, files, nodes, instance_id)
+         VALUES ({}, (SELECT id FROM kerai.branches WHERE is_current), {}, {}, {}, {},
+                 (SELECT id FROM kerai.instances WHERE is_self))
+         RETURNING id::text",
+        sql_text(name),
+        timestamp,
+        sql_text(&root_hash),
+        roots.len(),
+        hashed.len(),
+    ))
+    .unwrap()
+    .unwrap_or_else(|| error!("Failed to record snapshot {}", name));
+
+    for batch in hashed.chunks(BATCH_SIZE) {
+        let values: Vec<String> = batch
+            .iter()
+            .map(|(node, hash)| format!("({}, {})", sql_uuid(node), sql_text(hash)))
+            .collect();
+        Spi::run(&format!(
+            "INSERT INTO kerai.snapshot_nodes (snapshot_id, node_id, hash, node)
+             SELECT {}, n.id, h.hash, to_jsonb(n) - 'tsv'
+             FROM (VALUES {}) AS h(id, hash) JOIN kerai.nodes n ON n.id = h.id
+             ON CONFLICT (snapshot_id, node_id) DO NOTHING",
+            sql_uuid(&id),
+            values.join(", "),
+        ))
+        .expect("Failed to copy snapshot nodes");
+    }
+
+    // The crate nodes files hang from, so restored files have a parent
+    Spi::run(&format!(
+        "WITH RECURSIVE above AS (
+            SELECT n.parent_id AS id FROM kerai.snapshot_nodes s
+            JOIN kerai.nodes n ON n.id = s.node_id
+            WHERE s.snapshot_id = {id} AND n.kind IN ('file', 'document') AND n.parent_id IS NOT NULL
+            UNION
+            SELECT p.parent_id FROM above a JOIN kerai.nodes p ON p.id = a.id
+            WHERE p.parent_id IS NOT NULL
+        )
+        INSERT INTO kerai.snapshot_nodes (snapshot_id, node_id, hash, node)
+        SELECT {id}, n.id, NULL, to_jsonb(n) - 'tsv'
+        FROM above a JOIN kerai.nodes n ON n.id = a.id
+        ON CONFLICT (snapshot_id, node_id) DO NOTHING",
+        id = sql_uuid(&id),
+    ))
+    .expect("Failed to copy snapshot ancestors");
+
+    pgrx::JsonB(json!({
+        "id": id,
+        "name": name,
+        "timestamp": timestamp,
+        "root_hash": root_hash,
+        "files": roots.len(),
+        "nodes": hashed.len(),
+    }))
+}
+
+/// All snapshots, oldest first: `[{name, timestamp, root_hash, files,
+/// nodes, branch, created_at}]`.
+#[pg_extern]
+fn list_snapshots() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'name', s.name,
+            'timestamp', s.timestamp,
+            'root_hash', s.root_hash,
+            'files', s.files,
+            'nodes', s.nodes,
+            'branch', b.name,
+            'created_at', s.created_at
+        ) ORDER BY s.created_at, s.name), '[]'::jsonb)
+        FROM kerai.snapshots s
+        LEFT JOIN kerai.branches b ON b.id = s.branch_id",
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+/// Delete snapshot `name` and its node copies. Returns `{name, nodes}`.
+#[pg_extern]
+fn drop_snapshot(name: &str) -> pgrx::JsonB {
+    let id = snapshot_id(name);
+    let nodes = Spi::get_one::<i64>(&format!(
+        "WITH gone AS (DELETE FROM kerai.snapshot_nodes WHERE snapshot_id = {id} RETURNING 1)
+         SELECT count(*) FROM gone",
+        id = sql_uuid(&id),
+    ))
+    .unwrap()
+    .unwrap_or(0);
+    Spi::run(&format!(
+        "DELETE FROM kerai.snapshots WHERE id = {}",
+        sql_uuid(&id)
+    ))
+    .unwrap();
+    pgrx::JsonB(json!({ "name": name, "nodes": nodes }))
+}
+
+/// Put snapshot `name` back into kerai.nodes: its nodes return as they
+/// were, and nodes added under them since are detached, so reconstructing
+/// one of its files gives the file as it stood. Only allowed inside an
+/// explicit transaction, which the caller rolls back once done reading.
+///
+/// Returns `{name, timestamp, root_hash, files: [{id, path}]}`, the files
+/// being the snapshot's file nodes, one per path (the newest).
+#[pg_extern]
+fn restore_snapshot(name: &str) -> pgrx::JsonB {
+    if !unsafe { pg_sys::IsTransactionBlock() } {
+        error!("kerai.restore_snapshot must run inside BEGIN ... ROLLBACK");
+    }
+    let id = sql_uuid(&snapshot_id(name));
+
+    Spi::run(&format!(
+        "UPDATE kerai.nodes SET parent_id = NULL
+         WHERE parent_id IN (SELECT node_id FROM kerai.snapshot_nodes WHERE snapshot_id = {id})
+           AND id NOT IN (SELECT node_id FROM kerai.snapshot_nodes WHERE snapshot_id = {id})",
+    ))
+    .expect("Failed to detach nodes newer than the snapshot");
+
+    Spi::run(&format!(
+        "INSERT INTO kerai.nodes (id, instance_id, kind, language, content, parent_id,
+                                  position, path, metadata, content_hash, created_at,
+                                  modified_at)
+         SELECT r.id, r.instance_id, r.kind, r.language, r.content, r.parent_id,
+                r.position, r.path, r.metadata, r.content_hash, r.created_at, r.modified_at
+         FROM kerai.snapshot_nodes s, jsonb_populate_record(NULL::kerai.nodes, s.node) r
+         WHERE s.snapshot_id = {id}
+         ON CONFLICT (id) DO UPDATE SET
+             kind = EXCLUDED.kind,
+             language = EXCLUDED.language,
+             content = EXCLUDED.content,
+             parent_id = EXCLUDED.parent_id,
+             position = EXCLUDED.position,
+             path = EXCLUDED.path,
+             metadata = EXCLUDED.metadata,
+             content_hash = EXCLUDED.content_hash,
+             modified_at = EXCLUDED.modified_at",
+    ))
+    .expect("Failed to restore snapshot nodes");
+
+    let restored = Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_build_object(
+            'name', s.name,
+            'timestamp', s.timestamp,
+            'root_hash', s.root_hash,
+            'files', COALESCE((
+                SELECT jsonb_agg(jsonb_build_object('id', f.id, 'path', f.path) ORDER BY f.path)
+                FROM (
+                    SELECT DISTINCT ON (path) id, path FROM (
+                        SELECT n.node_id AS id,
+                               COALESCE(n.node->'metadata'->>'source_path', n.node->>'content') AS path,
+                               n.node->>'created_at' AS created_at
+                        FROM kerai.snapshot_nodes n
+                        WHERE n.snapshot_id = s.id AND n.node->>'kind' = 'file'
+                    ) x ORDER BY path, created_at DESC
+                ) f
+            ), '[]'::jsonb)
+        ) FROM kerai.snapshots s WHERE s.id = {id}",
+    ))
+    .unwrap()
+    .map(|j| j.0)
+    .unwrap_or(Value::Null);
+    pgrx::JsonB(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(path: &str, hash: &str) -> (String, String, String) {
+        (path.to_string(), "file".to_string(), hash.to_string())
+    }
+
+    #[test]
+    fn root_hash_ignores_file_order() {
+        let a = root_hash(&mut [root("a.rs", "1"), root("b.rs", "2")]);
+        let b = root_hash(&mut [root("b.rs", "2"), root("a.rs", "1")]);
+        assert_eq!(a, b);
+        assert_ne!(a, root_hash(&mut [root("a.rs", "2"), root("b.rs", "1")]));
+    }
+}
@@ -1,13 +1,16 @@
 /// Centralized SQL string helpers for SPI queries.
 ///
-/// pgrx 0.17 supports parameterized queries via `SpiClient::select`
-/// and `Spi::run_with_args` ($1-style positional parameters), but this
-/// codebase predates those and uses string interpolation throughout.
-/// These helpers centralize escaping to reduce duplication and bug risk.
+/// pgrx 0.17 supports parameterized queries via `SpiClient::select`,
+/// `Spi::get_one_with_args` and `Spi::run_with_args` ($1-style positional
+/// parameters). Text that comes from outside the database — filenames,
+/// source, CSV cells, crate names, reward details — goes in as a
+/// parameter: doubling quotes is not enough once a session turns off
+/// `standard_conforming_strings`, where a backslash escapes the closing
+/// quote. The parser's delete and batch-write paths, reconstruction, CSV
+/// ingest and reward minting all work that way.
 ///
-/// TODO: Migrate high-traffic queries (especially `inserter.rs` batch
-/// inserts) to use $1-style parameterized queries for proper type safety
-/// instead of string interpolation.
+/// Older code still interpolates ids, kinds and numbers it produced
+/// itself; these helpers centralize that escaping.
 
 /// Escape a string for use in a SQL literal (double single quotes).
 pub fn sql_escape(s: &str) -> String {
@@ -49,3 +52,8 @@ pub fn sql_jsonb(val: &serde_json::Value) -> String {
 pub fn sql_ltree(path: &str) -> String {
     format!("'{}'::ltree", sql_escape(path))
 }
+
+/// Quote an identifier (role, table or view name): `"escaped""name"`
+pub fn sql_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
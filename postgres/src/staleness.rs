@@ -0,0 +1,193 @@
+/// Documentation staleness — docs whose code moved on without them.
+///
+/// A doc node is linked to the code it describes by a `documents` edge
+/// (doc comments) or a `references` edge (LaTeX `\ref`). The gap is how far
+/// the code subtree's latest change (`modified_at`) runs ahead of the doc's
+/// own; `kerai.doc_staleness` exposes it per link. The score grows with the
+/// gap and reaches 0.5 at `kerai.stale_doc_days`, past which the doc counts
+/// as stale and can get a `stale_doc` suggestion.
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::sql::sql_text;
+
+/// Rule id of the suggestions made for stale docs.
+const RULE: &str = "stale_doc";
+
+/// Staleness score for a doc `gap_days` behind its code: 0 when current,
+/// 0.5 at the threshold, approaching 1 as the gap grows.
+pub fn score(gap_days: f64, threshold_days: f64) -> f64 {
+    if gap_days <= 0.0 {
+        return 0.0;
+    }
+    1.0 - 0.5_f64.powf(gap_days / threshold_days.max(f64::MIN_POSITIVE))
+}
+
+fn threshold(threshold_days: Option<i32>) -> f64 {
+    threshold_days.unwrap_or_else(|| crate::workers::STALE_DOC_DAYS.get()).max(1) as f64
+}
+
+/// Linked doc/code pairs where the code changed after the doc, worst first.
+fn behind(limit: i64) -> Vec<Value> {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(x ORDER BY (x->>'gap_days')::float8 DESC), '[]'::jsonb) FROM (
+            SELECT jsonb_build_object(
+                'doc_id', s.doc_id,
+                'doc_kind', d.kind,
+                'doc_excerpt', left(split_part(COALESCE(d.content, ''), E'\\n', 1), 80),
+                'code_id', s.code_id,
+                'code_kind', c.kind,
+                'code_name', COALESCE(c.metadata->>'name', c.content),
+                'relation', s.relation,
+                'file', (
+                    WITH RECURSIVE up AS (
+                        SELECT id, parent_id, kind, content, metadata, 0 AS depth
+                        FROM kerai.nodes WHERE id = s.doc_id
+                        UNION ALL
+                        SELECT n.id, n.parent_id, n.kind, n.content, n.metadata, up.depth + 1
+                        FROM kerai.nodes n JOIN up ON n.id = up.parent_id
+                    )
+                    SELECT COALESCE(metadata->>'source_path', content) FROM up
+                    WHERE kind IN ('file', 'document') ORDER BY depth LIMIT 1
+                ),
+                'doc_modified', s.doc_modified,
+                'code_modified', s.code_modified,
+                'gap_days', round(s.gap_days::numeric, 2)
+            ) AS x
+            FROM kerai.doc_staleness s
+            JOIN kerai.nodes d ON d.id = s.doc_id
+            JOIN kerai.nodes c ON c.id = s.code_id
+            WHERE s.gap_days > 0
+            ORDER BY s.gap_days DESC
+            LIMIT {limit}
+        ) t",
+    ))
+    .unwrap()
+    .and_then(|j| j.0.as_array().cloned())
+    .unwrap_or_default()
+}
+
+/// Report docs that lag the code they describe, worst first:
+/// `{threshold_days, stale, docs: [{doc_id, doc_kind, doc_excerpt, code_id,
+/// code_kind, code_name, relation, file, doc_modified, code_modified,
+/// gap_days, score, stale}]}`. `stale` counts docs past the threshold
+/// (`kerai.stale_doc_days` unless given).
+#[pg_extern]
+fn stale_docs(
+    threshold_days: default!(Option<i32>, "NULL"),
+    limit: default!(i32, 100),
+) -> pgrx::JsonB {
+    let days = threshold(threshold_days);
+    let mut docs = behind(limit.max(1) as i64);
+    let mut stale = 0;
+    for doc in &mut docs {
+        let gap = doc["gap_days"].as_f64().unwrap_or(0.0);
+        let over = gap >= days;
+        if over {
+            stale += 1;
+        }
+        doc["score"] = json!((score(gap, days) * 1000.0).round() / 1000.0);
+        doc["stale"] = json!(over);
+    }
+    pgrx::JsonB(json!({
+        "threshold_days": days,
+        "stale": stale,
+        "docs": docs,
+    }))
+}
+
+/// Emit a `stale_doc` suggestion on each doc node past the threshold that
+/// has none yet, and mark earlier ones applied once their doc caught up.
+/// Returns `{threshold_days, created, resolved}`.
+#[pg_extern]
+fn suggest_doc_updates(threshold_days: default!(Option<i32>, "NULL")) -> pgrx::JsonB {
+    let days = threshold(threshold_days);
+
+    // One suggestion per doc, about its most outdated link
+    let created = Spi::get_one::<i64>(&format!(
+        "WITH worst AS (
+            SELECT DISTINCT ON (s.doc_id) s.doc_id, s.code_id, s.gap_days
+            FROM kerai.doc_staleness s
+            WHERE s.gap_days >= {days}
+            ORDER BY s.doc_id, s.gap_days DESC
+        ), fresh AS (
+            SELECT w.*, kerai.uuid7() AS suggestion_id
+            FROM worst w
+            WHERE NOT EXISTS (
+                SELECT 1 FROM kerai.edges e
+                JOIN kerai.nodes sg ON sg.id = e.source_id
+                WHERE e.target_id = w.doc_id AND e.relation = 'suggests'
+                  AND sg.kind = 'suggestion' AND sg.metadata->>'rule' = {rule}
+                  AND sg.metadata->>'status' IN ('emitted', 'dismissed')
+            )
+        ), made AS (
+            INSERT INTO kerai.nodes (id, instance_id, kind, language, content, parent_id, position, metadata)
+            SELECT f.suggestion_id, d.instance_id, 'suggestion', d.language,
+                   format('Documentation may be out of date: %s %s changed %s days after it',
+                          c.kind, COALESCE(c.metadata->>'name', c.content, ''), floor(f.gap_days)),
+                   d.parent_id, d.position,
+                   jsonb_build_object(
+                       'rule', {rule},
+                       'status', 'emitted',
+                       'severity', 'info',
+                       'category', 'documentation',
+                       'code_id', f.code_id,
+                       'gap_days', round(f.gap_days::numeric, 2)
+                   )
+            FROM fresh f
+            JOIN kerai.nodes d ON d.id = f.doc_id
+            JOIN kerai.nodes c ON c.id = f.code_id
+            RETURNING id
+        ), linked AS (
+            INSERT INTO kerai.edges (source_id, target_id, relation, metadata)
+            SELECT f.suggestion_id, f.doc_id, 'suggests', jsonb_build_object('rule', {rule})
+            FROM fresh f JOIN made m ON m.id = f.suggestion_id
+            RETURNING 1
+        )
+        SELECT count(*) FROM linked",
+        rule = sql_text(RULE),
+    ))
+    .unwrap()
+    .unwrap_or(0);
+
+    let resolved = Spi::get_one::<i64>(&format!(
+        "WITH done AS (
+            UPDATE kerai.nodes sg
+            SET metadata = jsonb_set(sg.metadata, '{{status}}', '\"applied\"')
+            FROM kerai.edges e
+            WHERE e.source_id = sg.id AND e.relation = 'suggests'
+              AND sg.kind = 'suggestion' AND sg.metadata->>'rule' = {rule}
+              AND sg.metadata->>'status' = 'emitted'
+              AND NOT EXISTS (
+                  SELECT 1 FROM kerai.doc_staleness s
+                  WHERE s.doc_id = e.target_id AND s.gap_days >= {days}
+              )
+            RETURNING 1
+        )
+        SELECT count(*) FROM done",
+        rule = sql_text(RULE),
+    ))
+    .unwrap()
+    .unwrap_or(0);
+
+    pgrx::JsonB(json!({
+        "threshold_days": days,
+        "created": created,
+        "resolved": resolved,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_is_half_at_threshold_and_bounded() {
+        assert_eq!(score(0.0, 30.0), 0.0);
+        assert_eq!(score(-5.0, 30.0), 0.0);
+        assert!((score(30.0, 30.0) - 0.5).abs() < 1e-12);
+        assert!((score(60.0, 30.0) - 0.75).abs() < 1e-12);
+        assert!(score(10.0, 30.0) < score(11.0, 30.0));
+        assert!(score(10_000.0, 30.0) <= 1.0);
+    }
+}
@@ -0,0 +1,418 @@
+/// Subscriptions — users following documents or modules, with digests.
+///
+/// A subscription in kerai.subscriptions names a user, a path (a subtree
+/// root, or an lquery when it has wildcards), the operation types the user
+/// cares about and how often to hear about them. The digest worker calls
+/// `deliver_digests` every `kerai.digest_interval` seconds; each
+/// subscription whose period has passed gets the matching operations since
+/// its cursor rolled up into one `digest` node in the user's inbox, which
+/// `inbox` (and `GET /api/inbox`) reads back. Digest nodes have no path and
+/// are not logged as operations, so they never show up in digests.
+///
+/// Cursors are `kerai.poll_changes` cursors: only operations of
+/// transactions older than every one still running are digested, so none
+/// committed late are skipped.
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::crdt::VALID_OP_TYPES;
+
+/// Node kind of inbox entries.
+const DIGEST_KIND: &str = "digest";
+
+/// Changed nodes listed in one digest; the counts cover the rest.
+const DIGEST_NODES: i64 = 50;
+
+/// Digest frequencies and the SQL interval each waits between digests.
+const FREQUENCIES: &[(&str, &str)] = &[
+    ("hourly", "1 hour"),
+    ("daily", "1 day"),
+    ("weekly", "7 days"),
+];
+
+/// Length of a digest period as an SQL interval, `None` for an unknown
+/// frequency.
+fn period(frequency: &str) -> Option<&'static str> {
+    FREQUENCIES
+        .iter()
+        .find(|(name, _)| *name == frequency)
+        .map(|(_, interval)| *interval)
+}
+
+/// SQL expression for the period of subscription `s`.
+fn period_sql() -> String {
+    let arms: String = FREQUENCIES
+        .iter()
+        .map(|(name, interval)| format!(" WHEN '{}' THEN interval '{}'", name, interval))
+        .collect();
+    format!("CASE s.frequency{} END", arms)
+}
+
+/// Whether a subscription path is an lquery rather than a subtree root.
+fn is_lquery(pattern: &str) -> bool {
+    pattern.contains('*') || pattern.contains('|') || pattern.contains('!')
+}
+
+/// SQL condition on `o.path` for a subscription path bound as `$n`.
+fn path_clause(pattern: &str, n: usize) -> String {
+    if is_lquery(pattern) {
+        format!("o.path ~ ${}::lquery", n)
+    } else {
+        format!("o.path <@ ${}::ltree", n)
+    }
+}
+
+/// Parse a `<txid>-<seq>` cursor; `None` for anything else.
+fn parse_cursor(cursor: &str) -> Option<(u64, i64)> {
+    let (txid, seq) = cursor.split_once('-')?;
+    Some((txid.parse().ok()?, seq.parse().ok()?))
+}
+
+/// Id of the user `user_ref` names, by id or handle.
+fn resolve_user(user_ref: &str) -> String {
+    Spi::get_one_with_args::<String>(
+        "SELECT id::text FROM kerai.users WHERE id::text = $1 OR handle = $1
+         ORDER BY id::text = $1 DESC LIMIT 1",
+        &[user_ref.into()],
+    )
+    .unwrap()
+    .unwrap_or_else(|| error!("User not found: {}", user_ref))
+}
+
+/// Subscription rows as JSON, those of `user_id` or all of them.
+fn subscriptions_json(user_id: Option<&str>) -> Value {
+    Spi::get_one_with_args::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', s.id,
+            'user_id', s.user_id,
+            'handle', u.handle,
+            'path', s.pattern,
+            'events', to_jsonb(s.event_types),
+            'frequency', s.frequency,
+            'digested_at', s.digested_at,
+            'created_at', s.created_at
+        ) ORDER BY u.handle, s.pattern), '[]'::jsonb)
+        FROM kerai.subscriptions s JOIN kerai.users u ON u.id = s.user_id
+        WHERE $1::uuid IS NULL OR s.user_id = $1::uuid",
+        &[user_id.into()],
+    )
+    .unwrap()
+    .map_or(json!([]), |j| j.0)
+}
+
+/// Follow the nodes under `path` for user `user_ref` (an id or handle),
+/// or change how an existing subscription to it is delivered.
+///
+/// `path` is a subtree root, or an lquery when it has `*`, `|` or `!`.
+/// `event_types` limits the digest to those operation types (NULL for all
+/// of them); `frequency` is `hourly`, `daily` or `weekly`. The first
+/// digest covers what happens from now on. Returns the subscription.
+#[pg_extern]
+fn subscribe(
+    user_ref: &str,
+    path: &str,
+    event_types: default!(Option<Vec<String>>, "NULL"),
+    frequency: default!(&str, "'daily'"),
+) -> pgrx::JsonB {
+    let user_id = resolve_user(user_ref);
+    if period(frequency).is_none() {
+        error!(
+            "Unknown digest frequency '{}': expected hourly, daily or weekly",
+            frequency
+        );
+    }
+    let path = path.trim();
+    let cast = if is_lquery(path) { "lquery" } else { "ltree" };
+    // Rejects a malformed path with ltree's own syntax error
+    Spi::get_one_with_args::<bool>(&format!("SELECT $1::{} IS NOT NULL", cast), &[path.into()])
+        .unwrap();
+    let event_types = event_types.filter(|t| !t.is_empty());
+    for t in event_types.iter().flatten() {
+        if !VALID_OP_TYPES.contains(&t.as_str()) {
+            error!("Unknown event type: '{}'", t);
+        }
+    }
+
+    let id = Spi::get_one_with_args::<String>(
+        "INSERT INTO kerai.subscriptions (user_id, pattern, event_types, frequency, cursor)
+         VALUES ($1::uuid, $2, $3, $4, pg_snapshot_xmin(pg_current_snapshot())::text || '-0')
+         ON CONFLICT (user_id, pattern) DO UPDATE SET
+             event_types = EXCLUDED.event_types,
+             frequency = EXCLUDED.frequency
+         RETURNING id::text",
+        &[
+            user_id.as_str().into(),
+            path.into(),
+            event_types.into(),
+            frequency.into(),
+        ],
+    )
+    .unwrap()
+    .unwrap();
+
+    let all = subscriptions_json(Some(&user_id));
+    let row = all
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|s| s["id"] == id.as_str())
+        .cloned()
+        .unwrap_or(Value::Null);
+    pgrx::JsonB(row)
+}
+
+/// Stop following `path` for user `user_ref`. Returns whether the user
+/// was subscribed to it.
+#[pg_extern]
+fn unsubscribe(user_ref: &str, path: &str) -> bool {
+    let user_id = resolve_user(user_ref);
+    Spi::get_one_with_args::<bool>(
+        "WITH d AS (
+            DELETE FROM kerai.subscriptions WHERE user_id = $1::uuid AND pattern = $2 RETURNING 1
+        )
+        SELECT EXISTS(SELECT 1 FROM d)",
+        &[user_id.as_str().into(), path.trim().into()],
+    )
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Subscriptions of user `user_ref`, or of everyone when NULL, as
+/// `[{id, user_id, handle, path, events, frequency, digested_at,
+/// created_at}]`.
+#[pg_extern]
+fn list_subscriptions(user_ref: default!(Option<&str>, "NULL")) -> pgrx::JsonB {
+    let user_id = user_ref.map(resolve_user);
+    pgrx::JsonB(subscriptions_json(user_id.as_deref()))
+}
+
+/// Digest of the operations matching one subscription since `cursor`, and
+/// the cursor to resume from. The digest is null when nothing matched.
+fn digest(subscription: &Value, cursor: &str) -> (Value, String) {
+    let pattern = subscription["path"].as_str().unwrap_or("");
+    let (after_txid, after_seq) = parse_cursor(cursor).unwrap_or((0, 0));
+    let events: Option<Vec<String>> = subscription["events"].as_array().map(|a| {
+        a.iter()
+            .filter_map(|t| t.as_str().map(String::from))
+            .collect()
+    });
+
+    // One statement, so the horizon and the rows come from one snapshot
+    let row = Spi::get_one_with_args::<pgrx::JsonB>(
+        &format!(
+            "WITH horizon AS (
+                SELECT pg_snapshot_xmin(pg_current_snapshot()) AS xmin
+            ), matched AS (
+                SELECT o.op_type, o.node_id, o.path, o.author, o.created_at
+                FROM kerai.operations o, horizon h
+                WHERE (o.txid, o.seq) > ($1::text::xid8, $2)
+                  AND o.txid < h.xmin
+                  AND {}
+                  AND ($4::text[] IS NULL OR o.op_type = ANY($4::text[]))
+            ), by_node AS (
+                SELECT node_id, max(path::text) AS path, count(*) AS events,
+                       jsonb_agg(DISTINCT op_type) AS types,
+                       jsonb_agg(DISTINCT author) AS authors,
+                       max(created_at) AS last_at
+                FROM matched GROUP BY node_id
+            )
+            SELECT jsonb_build_object(
+                'events', (SELECT count(*) FROM matched),
+                'nodes', (SELECT count(*) FROM by_node),
+                'from', (SELECT min(created_at) FROM matched),
+                'to', (SELECT max(created_at) FROM matched),
+                'by_type', COALESCE((
+                    SELECT jsonb_object_agg(op_type, n)
+                    FROM (SELECT op_type, count(*) AS n FROM matched GROUP BY op_type) t
+                ), '{{}}'::jsonb),
+                'changes', COALESCE((
+                    SELECT jsonb_agg(jsonb_build_object(
+                        'node_id', node_id, 'path', path, 'events', events,
+                        'types', types, 'authors', authors, 'last_at', last_at
+                    ) ORDER BY last_at DESC)
+                    FROM (SELECT * FROM by_node ORDER BY last_at DESC LIMIT $5) top
+                ), '[]'::jsonb),
+                'horizon', (SELECT xmin::text FROM horizon)
+            )",
+            path_clause(pattern, 3)
+        ),
+        &[
+            after_txid.to_string().into(),
+            after_seq.into(),
+            pattern.into(),
+            events.into(),
+            DIGEST_NODES.into(),
+        ],
+    )
+    .unwrap()
+    .unwrap()
+    .0;
+
+    // Everything before the horizon is final: resume there
+    let horizon: u64 = row["horizon"]
+        .as_str()
+        .and_then(|h| h.parse().ok())
+        .unwrap_or(0);
+    let next = if horizon > after_txid {
+        format!("{}-0", horizon)
+    } else {
+        cursor.to_string()
+    };
+    if row["events"] == 0 {
+        return (Value::Null, next);
+    }
+    let mut digest = row;
+    if let Some(d) = digest.as_object_mut() {
+        d.remove("horizon");
+    }
+    (digest, next)
+}
+
+/// Roll up what happened under each due subscription into a digest node
+/// in its user's inbox, and advance the subscription. A subscription is
+/// due once its frequency has passed since its last digest; `force`
+/// delivers every one now. Subscriptions with nothing new get no digest.
+///
+/// Returns `{subscriptions, digests, events}`: how many were due, how many
+/// digests were delivered and how many operations they cover.
+#[pg_extern]
+fn deliver_digests(force: default!(bool, false)) -> pgrx::JsonB {
+    pgrx::JsonB(deliver(force))
+}
+
+/// Body of `deliver_digests`, for the digest worker.
+pub(crate) fn deliver(force: bool) -> Value {
+    let due = Spi::get_one_with_args::<pgrx::JsonB>(
+        &format!(
+            "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'id', s.id,
+            'user_id', s.user_id,
+            'path', s.pattern,
+            'events', to_jsonb(s.event_types),
+            'frequency', s.frequency,
+            'cursor', s.cursor
+        ) ORDER BY s.created_at), '[]'::jsonb)
+        FROM kerai.subscriptions s
+        WHERE $1 OR s.digested_at + {} <= now()",
+            period_sql()
+        ),
+        &[force.into()],
+    )
+    .unwrap()
+    .map_or(json!([]), |j| j.0);
+    let due = due.as_array().cloned().unwrap_or_default();
+
+    let instance_id = crate::parser::get_self_instance_id();
+    let mut digests = 0;
+    let mut events = 0;
+    for subscription in &due {
+        let id = subscription["id"].as_str().unwrap_or("");
+        let cursor = subscription["cursor"].as_str().unwrap_or("0-0");
+        let (digest, next) = digest(subscription, cursor);
+
+        if !digest.is_null() {
+            let count = digest["events"].as_i64().unwrap_or(0);
+            let path = subscription["path"].as_str().unwrap_or("");
+            let summary = format!(
+                "{} {} under {}",
+                count,
+                if count == 1 { "change" } else { "changes" },
+                path
+            );
+            let mut metadata = digest;
+            metadata["subscription_id"] = subscription["id"].clone();
+            metadata["user_id"] = subscription["user_id"].clone();
+            metadata["subscription_path"] = json!(path);
+            metadata["frequency"] = subscription["frequency"].clone();
+            Spi::run_with_args(
+                &format!(
+                    "INSERT INTO kerai.nodes (instance_id, kind, content, metadata)
+                     VALUES ($1::uuid, '{}', $2, $3)",
+                    DIGEST_KIND
+                ),
+                &[
+                    instance_id.as_str().into(),
+                    summary.as_str().into(),
+                    pgrx::JsonB(metadata).into(),
+                ],
+            )
+            .unwrap();
+            digests += 1;
+            events += count;
+        }
+
+        Spi::run_with_args(
+            "UPDATE kerai.subscriptions SET cursor = $2, digested_at = now() WHERE id = $1::uuid",
+            &[id.into(), next.as_str().into()],
+        )
+        .unwrap();
+    }
+
+    json!({
+        "subscriptions": due.len(),
+        "digests": digests,
+        "events": events,
+    })
+}
+
+/// Digests in the inbox of user `user_ref`, newest first, as
+/// `[{id, summary, created_at, subscription_id, path, frequency, events,
+/// nodes, from, to, by_type, changes}]`.
+#[pg_extern]
+fn inbox(user_ref: &str, max_items: default!(i32, 50)) -> pgrx::JsonB {
+    let user_id = resolve_user(user_ref);
+    Spi::get_one_with_args::<pgrx::JsonB>(
+        &format!(
+            "SELECT COALESCE(jsonb_agg(item ORDER BY created_at DESC), '[]'::jsonb)
+            FROM (
+                SELECT n.created_at, jsonb_build_object(
+                    'id', n.id,
+                    'summary', n.content,
+                    'created_at', n.created_at,
+                    'subscription_id', n.metadata->'subscription_id',
+                    'path', n.metadata->'subscription_path',
+                    'frequency', n.metadata->'frequency',
+                    'events', n.metadata->'events',
+                    'nodes', n.metadata->'nodes',
+                    'from', n.metadata->'from',
+                    'to', n.metadata->'to',
+                    'by_type', n.metadata->'by_type',
+                    'changes', n.metadata->'changes'
+                ) AS item
+                FROM kerai.nodes n
+                WHERE n.kind = '{}' AND n.metadata->>'user_id' = $1
+                ORDER BY n.created_at DESC
+                LIMIT $2
+            ) items",
+            DIGEST_KIND
+        ),
+        &[user_id.as_str().into(), max_items.clamp(1, 1000).into()],
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequencies_have_periods() {
+        assert_eq!(period("hourly"), Some("1 hour"));
+        assert_eq!(period("weekly"), Some("7 days"));
+        assert_eq!(period("monthly"), None);
+        assert_eq!(
+            period_sql(),
+            "CASE s.frequency WHEN 'hourly' THEN interval '1 hour' \
+             WHEN 'daily' THEN interval '1 day' WHEN 'weekly' THEN interval '7 days' END"
+        );
+    }
+
+    #[test]
+    fn path_clause_picks_lquery_for_wildcards() {
+        assert_eq!(path_clause("docs.guide", 3), "o.path <@ $3::ltree");
+        assert_eq!(path_clause("*.parser.*", 3), "o.path ~ $3::lquery");
+        assert_eq!(parse_cursor("812-4"), Some((812, 4)));
+        assert_eq!(parse_cursor("812"), None);
+    }
+}
@@ -0,0 +1,359 @@
+/// Summary nodes for large subtrees.
+///
+/// A summary is a `summary` node outside the tree, linked to the node it
+/// covers by a `summarizes` edge, one per (node, method). The built-in
+/// `extractive` method picks the subtree's most central sentences and
+/// headings; agents can submit summaries of their own (e.g. LLM-written)
+/// under any other method name. Any change below a summarized node flags
+/// its summaries stale (see `kerai.mark_summaries_stale`) until they are
+/// regenerated.
+use std::collections::{HashMap, HashSet};
+
+use pgrx::prelude::*;
+use serde_json::json;
+
+use crate::parser::get_self_instance_id;
+use crate::perspectives::resolve_agent;
+use crate::sql::{sql_escape, sql_jsonb, sql_text};
+
+/// Node kinds whose content is prose worth summarizing.
+const TEXT_KINDS: &[&str] = &[
+    "heading",
+    "paragraph",
+    "blockquote",
+    "list_item",
+    "table_cell",
+    "doc_comment",
+    "comment",
+    "comment_block",
+];
+
+/// Score multiplier for headings, which are short but carry structure.
+const HEADING_WEIGHT: f64 = 1.5;
+
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "are", "but", "not", "you", "all", "any", "can", "had", "her", "was",
+    "one", "our", "out", "has", "his", "how", "its", "may", "who", "did", "get", "let", "say",
+    "she", "too", "use", "that", "with", "have", "this", "will", "your", "from", "they", "been",
+    "than", "them", "then", "were", "what", "when", "which", "into", "more", "also", "each",
+    "such", "there", "their", "these", "those", "would", "about", "other", "only", "some",
+];
+
+/// A piece of text from the subtree, in document order.
+#[derive(Debug, Clone)]
+pub struct Passage {
+    pub text: String,
+    pub heading: bool,
+}
+
+/// Split prose into sentences at `.`, `!` or `?` followed by whitespace,
+/// and at line breaks.
+pub fn sentences(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for line in text.lines() {
+        let mut start = 0;
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        for (i, &(pos, c)) in chars.iter().enumerate() {
+            let at_break = matches!(c, '.' | '!' | '?')
+                && chars.get(i + 1).is_some_and(|&(_, next)| next.is_whitespace());
+            if at_break {
+                out.push(line[start..=pos].trim().to_string());
+                start = pos + 1;
+            }
+        }
+        out.push(line[start..].trim().to_string());
+    }
+    out.retain(|s| !s.is_empty());
+    out
+}
+
+/// Lowercased content words and their counts.
+fn terms(sentence: &str) -> HashMap<String, f64> {
+    let mut counts = HashMap::new();
+    for word in sentence
+        .split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|w| w.chars().count() > 2 && !STOPWORDS.contains(&w.as_str()))
+    {
+        *counts.entry(word).or_insert(0.0) += 1.0;
+    }
+    counts
+}
+
+fn cosine(a: &HashMap<String, f64>, b: &HashMap<String, f64>) -> f64 {
+    let dot: f64 = a.iter().filter_map(|(w, x)| b.get(w).map(|y| x * y)).sum();
+    if dot == 0.0 {
+        return 0.0;
+    }
+    let norm = |v: &HashMap<String, f64>| v.values().map(|x| x * x).sum::<f64>().sqrt();
+    dot / (norm(a) * norm(b))
+}
+
+/// Pick up to `max` sentences (headings count as one) by centrality: each
+/// scores the sum of its similarity to every other sentence. The picks
+/// are returned in document order.
+pub fn extract(passages: &[Passage], max: usize) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut candidates: Vec<(String, bool)> = Vec::new();
+    for passage in passages {
+        let parts = if passage.heading {
+            vec![passage.text.trim().to_string()]
+        } else {
+            sentences(&passage.text)
+        };
+        for part in parts {
+            if !part.is_empty() && seen.insert(part.clone()) {
+                candidates.push((part, passage.heading));
+            }
+        }
+    }
+
+    let vectors: Vec<_> = candidates.iter().map(|(s, _)| terms(s)).collect();
+    let mut scored: Vec<(usize, f64)> = (0..candidates.len())
+        .filter(|&i| !vectors[i].is_empty())
+        .map(|i| {
+            let centrality: f64 = (0..vectors.len())
+                .filter(|&j| j != i)
+                .map(|j| cosine(&vectors[i], &vectors[j]))
+                .sum();
+            let weight = if candidates[i].1 { HEADING_WEIGHT } else { 1.0 };
+            (i, centrality * weight)
+        })
+        .collect();
+    // Highest score first; earlier text wins ties
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    scored.truncate(max);
+    scored.sort_by_key(|&(i, _)| i);
+
+    scored
+        .into_iter()
+        .map(|(i, _)| candidates[i].0.clone())
+        .collect()
+}
+
+fn require_node(node_id: &str) {
+    let exists = Spi::get_one::<bool>(&format!(
+        "SELECT EXISTS (SELECT 1 FROM kerai.nodes WHERE id = '{}'::uuid)",
+        sql_escape(node_id),
+    ))
+    .unwrap()
+    .unwrap_or(false);
+    if !exists {
+        error!("Node not found: {}", node_id);
+    }
+}
+
+/// Create or refresh the (node, method) summary and clear its stale flag.
+fn store_summary(node_id: &str, method: &str, content: &str, extra: serde_json::Value) -> pgrx::JsonB {
+    let mut metadata = json!({
+        "method": method,
+        "stale": false,
+    });
+    if let (Some(meta), Some(extra)) = (metadata.as_object_mut(), extra.as_object()) {
+        meta.extend(extra.clone());
+    }
+
+    let existing = Spi::get_one::<String>(&format!(
+        "SELECT s.id::text FROM kerai.edges e
+         JOIN kerai.nodes s ON s.id = e.source_id
+         WHERE e.target_id = '{}'::uuid AND e.relation = 'summarizes'
+           AND s.metadata->>'method' = {}",
+        sql_escape(node_id),
+        sql_text(method),
+    ))
+    .unwrap_or(None);
+
+    let summary_id = match existing {
+        Some(id) => {
+            Spi::run(&format!(
+                "UPDATE kerai.nodes
+                 SET content = {}, metadata = {} || jsonb_build_object('summarized_at', now())
+                 WHERE id = '{}'::uuid",
+                sql_text(content),
+                sql_jsonb(&metadata),
+                id,
+            ))
+            .unwrap();
+            id
+        }
+        None => {
+            let id = Spi::get_one::<String>(&format!(
+                "INSERT INTO kerai.nodes (instance_id, kind, content, metadata)
+                 VALUES ('{}'::uuid, 'summary', {}, {} || jsonb_build_object('summarized_at', now()))
+                 RETURNING id::text",
+                get_self_instance_id(),
+                sql_text(content),
+                sql_jsonb(&metadata),
+            ))
+            .unwrap()
+            .unwrap();
+            Spi::run(&format!(
+                "INSERT INTO kerai.edges (source_id, target_id, relation)
+                 VALUES ('{}'::uuid, '{}'::uuid, 'summarizes')",
+                id,
+                sql_escape(node_id),
+            ))
+            .unwrap();
+            id
+        }
+    };
+
+    let mut result = json!({
+        "summary_id": summary_id,
+        "node_id": node_id,
+        "content": content,
+    });
+    if let (Some(out), Some(meta)) = (result.as_object_mut(), metadata.as_object()) {
+        out.extend(meta.clone());
+    }
+    pgrx::JsonB(result)
+}
+
+/// Summarize the subtree under `node_id` with a built-in method and attach
+/// the result as its summary node. Only `extractive` is built in; agents
+/// submit other kinds through `kerai.submit_summary`.
+#[pg_extern]
+fn summarize_subtree(
+    node_id: pgrx::Uuid,
+    method: default!(&str, "'extractive'"),
+    max_sentences: default!(i32, 5),
+) -> pgrx::JsonB {
+    if method != "extractive" {
+        error!(
+            "Unknown summary method '{}': only 'extractive' is built in, \
+             submit others with kerai.submit_summary",
+            method
+        );
+    }
+    let nid = node_id.to_string();
+    require_node(&nid);
+
+    let kinds = TEXT_KINDS.iter().map(|k| sql_text(k)).collect::<Vec<_>>().join(", ");
+    let mut passages = Vec::new();
+    Spi::connect(|client| {
+        let rows = client
+            .select(
+                &format!(
+                    "WITH RECURSIVE sub AS (
+                        SELECT id, kind, content, ARRAY[]::int[] AS ord
+                        FROM kerai.nodes WHERE id = '{nid}'::uuid
+                        UNION ALL
+                        SELECT n.id, n.kind, n.content, sub.ord || n.position
+                        FROM kerai.nodes n JOIN sub ON n.parent_id = sub.id
+                    )
+                    SELECT kind, content FROM sub
+                    WHERE kind IN ({kinds}) AND COALESCE(content, '') <> ''
+                    ORDER BY ord",
+                ),
+                None,
+                &[],
+            )
+            .unwrap();
+        for row in rows {
+            let kind = row.get::<String>(1).unwrap().unwrap_or_default();
+            passages.push(Passage {
+                text: row.get::<String>(2).unwrap().unwrap_or_default(),
+                heading: kind == "heading",
+            });
+        }
+    });
+    if passages.is_empty() {
+        error!("Nothing to summarize under {}", nid);
+    }
+
+    let picked = extract(&passages, max_sentences.max(1) as usize);
+    store_summary(
+        &nid,
+        method,
+        &picked.join("\n"),
+        json!({"sentences": picked.len(), "passages": passages.len()}),
+    )
+}
+
+/// Attach a summary written by an agent (for example by an LLM) to
+/// `node_id`, replacing any earlier summary made with the same method.
+#[pg_extern]
+fn submit_summary(
+    agent_name: &str,
+    node_id: pgrx::Uuid,
+    summary: &str,
+    method: default!(&str, "'agent'"),
+) -> pgrx::JsonB {
+    if method == "extractive" {
+        error!("'extractive' summaries are generated with kerai.summarize_subtree");
+    }
+    if summary.trim().is_empty() {
+        error!("Summary must not be empty");
+    }
+    let agent_id = resolve_agent(agent_name);
+    let nid = node_id.to_string();
+    require_node(&nid);
+    store_summary(
+        &nid,
+        method,
+        summary.trim(),
+        json!({"author": agent_name, "agent_id": agent_id}),
+    )
+}
+
+/// Summaries flagged stale, oldest first — the queue agents regenerate from.
+#[pg_extern]
+fn stale_summaries(limit: default!(i32, 100)) -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT COALESCE(jsonb_agg(x ORDER BY x->>'stale_since'), '[]'::jsonb) FROM (
+            SELECT jsonb_build_object(
+                'summary_id', s.id,
+                'node_id', e.target_id,
+                'method', s.metadata->>'method',
+                'author', s.metadata->>'author',
+                'summarized_at', s.metadata->>'summarized_at',
+                'stale_since', s.metadata->>'stale_since'
+            ) AS x
+            FROM kerai.nodes s
+            JOIN kerai.edges e ON e.source_id = s.id AND e.relation = 'summarizes'
+            WHERE s.kind = 'summary' AND (s.metadata->>'stale')::boolean
+            ORDER BY s.metadata->>'stale_since'
+            LIMIT {}
+        ) t",
+        limit.max(1),
+    ))
+    .unwrap()
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn para(text: &str) -> Passage {
+        Passage { text: text.into(), heading: false }
+    }
+
+    #[test]
+    fn splits_sentences_on_terminators_and_lines() {
+        assert_eq!(
+            sentences("One thing. Another thing! Version 1.2 ships\nNext line?"),
+            vec!["One thing.", "Another thing!", "Version 1.2 ships", "Next line?"],
+        );
+    }
+
+    #[test]
+    fn extract_prefers_central_sentences_in_order() {
+        let passages = vec![
+            Passage { text: "Replication protocol".into(), heading: true },
+            para("The replication protocol ships operations between peers. Lunch was good."),
+            para("Peers apply replication operations in lamport order."),
+            para("Operations carry a lamport clock for the protocol."),
+        ];
+        assert_eq!(
+            extract(&passages, 2),
+            vec!["Replication protocol", "The replication protocol ships operations between peers."],
+        );
+    }
+
+    #[test]
+    fn extract_skips_duplicates_and_empty() {
+        let passages = vec![para("Same words here."), para("Same words here."), para("")];
+        assert_eq!(extract(&passages, 5), vec!["Same words here."]);
+    }
+}
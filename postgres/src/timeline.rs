@@ -0,0 +1,206 @@
+/// Activity timelines — versions, perspective changes and parses per day
+/// or week, with a breakdown per author.
+///
+/// Each new version, perspective weight and parse run is queued by trigger
+/// in kerai.activity_deltas; `fold_activity` (the activity worker, or any
+/// writable read) adds the queue into kerai.activity_daily, one count per
+/// UTC day, source, author and scope. The scope is the first three labels
+/// of the node's path at the time (`kerai.activity_scope`), so a timeline
+/// for a path that short reads only the aggregate; a deeper path is
+/// counted from the history tables themselves, by the nodes' paths now,
+/// where a perspective counts once, at its latest change.
+///
+/// Authors are instance key fingerprints for versions and parses (named
+/// after the instance when it is registered) and agent names for
+/// perspectives.
+use pgrx::prelude::*;
+use serde_json::json;
+
+/// Deltas folded per statement by `fold_activity`.
+const FOLD_BATCH: i64 = 10_000;
+
+/// Longest window, in days, a timeline covers.
+const MAX_DAYS: i32 = 3660;
+
+/// Every recorded event with its UTC day, source, author and node path —
+/// what kerai.activity_daily counts.
+const HISTORY_SQL: &str = "
+    SELECT (v.created_at AT TIME ZONE 'UTC')::date AS day, 'version' AS source, v.author, n.path
+    FROM kerai.versions v LEFT JOIN kerai.nodes n ON n.id = v.node_id
+    UNION ALL
+    SELECT (p.updated_at AT TIME ZONE 'UTC')::date, 'perspective',
+           COALESCE(a.name, p.agent_id::text), n.path
+    FROM kerai.perspectives p
+    LEFT JOIN kerai.agents a ON a.id = p.agent_id
+    LEFT JOIN kerai.nodes n ON n.id = p.node_id
+    UNION ALL
+    SELECT (r.created_at AT TIME ZONE 'UTC')::date, 'parse',
+           COALESCE((SELECT key_fingerprint FROM kerai.instances WHERE is_self), ''), r.path
+    FROM kerai.parse_runs r";
+
+/// Fold queued activity into kerai.activity_daily, a batch at a time until
+/// the queue is empty. Returns the number of deltas folded; deltas locked
+/// by a concurrent fold are left to it.
+pub(crate) fn fold_activity() -> i64 {
+    let mut total = 0;
+    loop {
+        let folded = Spi::get_one::<i64>(&format!(
+            "WITH batch AS (
+                DELETE FROM kerai.activity_deltas
+                WHERE id IN (
+                    SELECT id FROM kerai.activity_deltas
+                    ORDER BY id LIMIT {FOLD_BATCH}
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING day, source, author, scope
+            ), folded AS (
+                INSERT INTO kerai.activity_daily AS a (day, source, author, scope, events)
+                SELECT day, source, author, scope, count(*) FROM batch
+                GROUP BY day, source, author, scope
+                ON CONFLICT (day, source, author, scope)
+                DO UPDATE SET events = a.events + EXCLUDED.events
+            )
+            SELECT count(*) FROM batch",
+        ))
+        .unwrap()
+        .unwrap_or(0);
+        if folded == 0 {
+            break;
+        }
+        total += folded;
+    }
+    total
+}
+
+/// Fold queued activity now, or rebuild kerai.activity_daily from the
+/// history tables when `rebuild` is true.
+#[pg_extern]
+fn refresh_timeline(rebuild: default!(bool, false)) -> pgrx::JsonB {
+    if rebuild {
+        let backfill = format!(
+            "INSERT INTO kerai.activity_daily (day, source, author, scope, events)
+             SELECT day, source, author, kerai.activity_scope(path), count(*)
+             FROM ({HISTORY_SQL}) h
+             GROUP BY 1, 2, 3, 4"
+        );
+        for stmt in [
+            "LOCK TABLE kerai.versions, kerai.perspectives, kerai.parse_runs IN SHARE MODE",
+            "DELETE FROM kerai.activity_deltas",
+            "DELETE FROM kerai.activity_daily",
+            backfill.as_str(),
+        ] {
+            Spi::run(stmt).unwrap();
+        }
+        let rows = Spi::get_one::<i64>("SELECT count(*)::bigint FROM kerai.activity_daily")
+            .unwrap()
+            .unwrap_or(0);
+        return pgrx::JsonB(json!({"rebuilt": true, "rows": rows}));
+    }
+
+    pgrx::JsonB(json!({"rebuilt": false, "folded": fold_activity()}))
+}
+
+/// Activity per period over the last `days` days (the first period
+/// reaching back to cover them), oldest first and including empty ones:
+/// `{path, granularity, days, as_of, periods: [{period, versions,
+/// perspectives, parses, total, authors: [{author, name, versions,
+/// perspectives, parses, total}]}]}`, authors busiest first.
+///
+/// `path` limits it to events on nodes under that path (parses: runs that
+/// parsed under it); `granularity` is `day` or `week` (weeks start on
+/// Monday). `as_of` is the time up to which the aggregate reflects every
+/// event; queued ones are folded first unless the transaction is read-only.
+#[pg_extern]
+fn timeline(
+    path: default!(&str, "''"),
+    granularity: default!(&str, "'day'"),
+    days: default!(i32, 365),
+) -> pgrx::JsonB {
+    if !matches!(granularity, "day" | "week") {
+        error!("granularity must be 'day' or 'week', not '{}'", granularity);
+    }
+    let days = days.clamp(1, MAX_DAYS);
+    let read_only = Spi::get_one::<bool>("SELECT current_setting('transaction_read_only')::bool")
+        .unwrap_or(Some(true))
+        .unwrap_or(true);
+    if !read_only {
+        fold_activity();
+    }
+
+    // The aggregate's scopes answer for paths no deeper than themselves
+    let aggregated = Spi::get_one_with_args::<bool>(
+        "SELECT $1 = '' OR kerai.activity_scope($1::ltree) = $1::ltree",
+        &[path.into()],
+    )
+    .unwrap_or_else(|e| error!("Invalid path '{}': {}", path, e))
+    .unwrap_or(true);
+    let rows = if aggregated {
+        "SELECT day, source, author, events FROM kerai.activity_daily
+         WHERE $1 = '' OR scope <@ $1::ltree"
+            .to_string()
+    } else {
+        format!("SELECT day, source, author, 1 AS events FROM ({HISTORY_SQL}) h WHERE h.path <@ $1::ltree")
+    };
+
+    let periods = Spi::get_one_with_args::<pgrx::JsonB>(
+        &format!(
+            "WITH bounds AS (
+                SELECT date_trunc($2, ((now() AT TIME ZONE 'UTC')::date - ($3 - 1))::timestamp)::date AS first,
+                       (now() AT TIME ZONE 'UTC')::date AS last
+            ), rows AS ({rows}
+            ), by_author AS (
+                SELECT date_trunc($2, r.day::timestamp)::date AS period, r.author,
+                    COALESCE(sum(r.events) FILTER (WHERE r.source = 'version'), 0) AS versions,
+                    COALESCE(sum(r.events) FILTER (WHERE r.source = 'perspective'), 0) AS perspectives,
+                    COALESCE(sum(r.events) FILTER (WHERE r.source = 'parse'), 0) AS parses,
+                    sum(r.events) AS total
+                FROM rows r, bounds b
+                WHERE r.day BETWEEN b.first AND b.last
+                GROUP BY 1, 2
+            ), by_period AS (
+                SELECT a.period, sum(a.versions) AS versions, sum(a.perspectives) AS perspectives,
+                    sum(a.parses) AS parses, sum(a.total) AS total,
+                    jsonb_agg(jsonb_build_object(
+                        'author', a.author,
+                        'name', i.name,
+                        'versions', a.versions,
+                        'perspectives', a.perspectives,
+                        'parses', a.parses,
+                        'total', a.total
+                    ) ORDER BY a.total DESC, a.author) AS authors
+                FROM by_author a
+                LEFT JOIN kerai.instances i ON i.key_fingerprint = a.author
+                GROUP BY a.period
+            )
+            SELECT COALESCE(jsonb_agg(jsonb_build_object(
+                'period', p.period,
+                'versions', COALESCE(bp.versions, 0),
+                'perspectives', COALESCE(bp.perspectives, 0),
+                'parses', COALESCE(bp.parses, 0),
+                'total', COALESCE(bp.total, 0),
+                'authors', COALESCE(bp.authors, '[]'::jsonb)
+            ) ORDER BY p.period), '[]'::jsonb)
+            FROM bounds b,
+                generate_series(b.first, b.last, ('1 ' || $2)::interval) AS s(at),
+                LATERAL (SELECT s.at::date AS period) p
+            LEFT JOIN by_period bp ON bp.period = p.period"
+        ),
+        &[path.into(), granularity.into(), days.into()],
+    )
+    .unwrap_or_else(|e| error!("Failed to read the timeline: {}", e))
+    .map_or(json!([]), |j| j.0);
+
+    let as_of = Spi::get_one::<String>(
+        "SELECT COALESCE((SELECT min(queued_at) FROM kerai.activity_deltas), clock_timestamp())::text",
+    )
+    .unwrap()
+    .unwrap_or_default();
+
+    pgrx::JsonB(json!({
+        "path": (!path.is_empty()).then_some(path),
+        "granularity": granularity,
+        "days": days,
+        "as_of": as_of,
+        "periods": periods,
+    }))
+}
@@ -0,0 +1,271 @@
+/// Named views — per-team slices of one graph, without copying it.
+///
+/// A view in kerai.views names subtrees (ltree paths) and node kinds.
+/// `apply_view` copies them into the `kerai.view_paths` and
+/// `kerai.view_kinds` settings, and find, grep, tree, search and the
+/// document listings then only return nodes `kerai.in_view` accepts.
+/// With `local`, the view lasts for the current transaction only, which
+/// is what pooled web connections use.
+///
+/// A view also records the branch and version frontier it is meant to be
+/// read at. Checking out a branch changes kerai.nodes for every session,
+/// so `apply_view` reports whether that branch is checked out rather than
+/// switching to it.
+use std::ffi::CString;
+
+use pgrx::guc::{GucContext, GucFlags, GucRegistry, GucSetting};
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+
+use crate::sql::{sql_jsonb, sql_opt_text, sql_text};
+
+/// Name of the view in effect.
+static VIEW: GucSetting<Option<CString>> = GucSetting::<Option<CString>>::new(None);
+/// Comma-separated subtree paths of the view in effect.
+static VIEW_PATHS: GucSetting<Option<CString>> = GucSetting::<Option<CString>>::new(None);
+/// Comma-separated node kinds of the view in effect.
+static VIEW_KINDS: GucSetting<Option<CString>> = GucSetting::<Option<CString>>::new(None);
+
+pub fn register_gucs() {
+    GucRegistry::define_string_guc(
+        c"kerai.view",
+        c"Named view in effect for this session",
+        c"Set with kerai.apply_view(name), which also sets kerai.view_paths and kerai.view_kinds.",
+        &VIEW,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+    GucRegistry::define_string_guc(
+        c"kerai.view_paths",
+        c"Subtree paths queries are limited to, comma-separated",
+        c"Empty for every path. find, grep, tree, search and document listings skip nodes outside them.",
+        &VIEW_PATHS,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+    GucRegistry::define_string_guc(
+        c"kerai.view_kinds",
+        c"Node kinds queries are limited to, comma-separated",
+        c"Empty for every kind. find, grep, tree, search and document listings skip other kinds.",
+        &VIEW_KINDS,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+}
+
+/// A view filter from JSON (`null` or an array of strings) as the
+/// comma-separated setting value, empty for no filter.
+fn setting_value(field: &str, value: &Value) -> Result<String, String> {
+    let Some(items) = value.as_array() else {
+        return match value {
+            Value::Null => Ok(String::new()),
+            _ => Err(format!("View {} must be a JSON array of strings", field)),
+        };
+    };
+    let mut parts = Vec::with_capacity(items.len());
+    for item in items {
+        let Some(s) = item.as_str().map(str::trim) else {
+            return Err(format!("View {} must be a JSON array of strings", field));
+        };
+        if s.is_empty() || s.contains(',') {
+            return Err(format!("Invalid view {} entry: '{}'", field, s));
+        }
+        parts.push(s);
+    }
+    Ok(parts.join(","))
+}
+
+/// SQL array literal for a filter column: `NULL` when unfiltered.
+fn array_sql(setting: &str, element: &str) -> String {
+    if setting.is_empty() {
+        return "NULL".to_string();
+    }
+    format!("string_to_array({}, ',')::{}[]", sql_text(setting), element)
+}
+
+fn set(name: &str, value: &str, local: bool) {
+    Spi::run(&format!(
+        "SELECT set_config({}, {}, {})",
+        sql_text(name),
+        sql_text(value),
+        local,
+    ))
+    .unwrap();
+}
+
+/// Define view `name`, or replace its filters. `paths` and `kinds` are
+/// JSON arrays of strings, null for no limit; `paths` are ltree subtree
+/// roots. `branch` and `frontier` (a version vector) record where the view
+/// is meant to be read.
+///
+/// Returns the view as `list_views` shows it.
+#[pg_extern]
+fn create_view(
+    name: &str,
+    paths: default!(Option<pgrx::JsonB>, "NULL"),
+    kinds: default!(Option<pgrx::JsonB>, "NULL"),
+    branch: default!(Option<&str>, "NULL"),
+    frontier: default!(Option<pgrx::JsonB>, "NULL"),
+    description: default!(Option<&str>, "NULL"),
+) -> pgrx::JsonB {
+    if name.trim().is_empty() {
+        error!("View name must not be empty");
+    }
+    let paths = paths.map_or(Value::Null, |j| j.0);
+    let kinds = kinds.map_or(Value::Null, |j| j.0);
+    let paths = setting_value("paths", &paths).unwrap_or_else(|e| error!("{}", e));
+    let kinds = setting_value("kinds", &kinds).unwrap_or_else(|e| error!("{}", e));
+    if let Some(b) = branch {
+        let known = Spi::get_one::<bool>(&format!(
+            "SELECT EXISTS(SELECT 1 FROM kerai.branches WHERE name = {})",
+            sql_text(b),
+        ))
+        .unwrap()
+        .unwrap_or(false);
+        if !known {
+            error!("Branch not found: {}", b);
+        }
+    }
+
+    Spi::run(&format!(
+        "INSERT INTO kerai.views (name, description, paths, kinds, branch, frontier)
+         VALUES ({name}, {description}, {paths}, {kinds}, {branch}, {frontier})
+         ON CONFLICT (name) DO UPDATE SET
+             description = EXCLUDED.description,
+             paths = EXCLUDED.paths,
+             kinds = EXCLUDED.kinds,
+             branch = EXCLUDED.branch,
+             frontier = EXCLUDED.frontier,
+             updated_at = now()",
+        name = sql_text(name),
+        description = sql_opt_text(&description.map(String::from)),
+        paths = array_sql(&paths, "ltree"),
+        kinds = array_sql(&kinds, "text"),
+        branch = sql_opt_text(&branch.map(String::from)),
+        frontier = frontier.map_or("NULL".to_string(), |f| sql_jsonb(&f.0)),
+    ))
+    .unwrap_or_else(|e| error!("Failed to create view {}: {}", name, e));
+
+    pgrx::JsonB(view_json(name))
+}
+
+/// One view as `{name, description, paths, kinds, branch, frontier,
+/// updated_at}`, null when there is no such view.
+fn view_json(name: &str) -> Value {
+    Spi::get_one::<pgrx::JsonB>(&format!(
+        "SELECT jsonb_build_object(
+            'name', name,
+            'description', description,
+            'paths', to_jsonb(paths::text[]),
+            'kinds', to_jsonb(kinds),
+            'branch', branch,
+            'frontier', frontier,
+            'updated_at', updated_at
+        ) FROM kerai.views WHERE name = {}",
+        sql_text(name),
+    ))
+    .unwrap()
+    .map_or(Value::Null, |j| j.0)
+}
+
+/// All views by name, each as `create_view` returns it plus `active`:
+/// whether it is the one in effect.
+#[pg_extern]
+fn list_views() -> pgrx::JsonB {
+    Spi::get_one::<pgrx::JsonB>(
+        "SELECT COALESCE(jsonb_agg(jsonb_build_object(
+            'name', name,
+            'description', description,
+            'paths', to_jsonb(paths::text[]),
+            'kinds', to_jsonb(kinds),
+            'branch', branch,
+            'frontier', frontier,
+            'updated_at', updated_at,
+            'active', COALESCE(name = current_setting('kerai.view', true), false)
+        ) ORDER BY name), '[]'::jsonb)
+        FROM kerai.views",
+    )
+    .unwrap()
+    .unwrap_or_else(|| pgrx::JsonB(json!([])))
+}
+
+/// Delete view `name`. Sessions that applied it keep its filters until
+/// they clear them. Returns whether it existed.
+#[pg_extern]
+fn drop_view(name: &str) -> bool {
+    Spi::get_one::<bool>(&format!(
+        "WITH d AS (DELETE FROM kerai.views WHERE name = {} RETURNING 1)
+         SELECT EXISTS(SELECT 1 FROM d)",
+        sql_text(name),
+    ))
+    .unwrap()
+    .unwrap_or(false)
+}
+
+/// Put view `name` in effect for the rest of the session or, with
+/// `local`, the current transaction.
+///
+/// Returns the view plus `current_branch` and `on_branch`, false when the
+/// view names a branch other than the one checked out.
+#[pg_extern]
+fn apply_view(name: &str, local: default!(bool, false)) -> pgrx::JsonB {
+    let mut view = view_json(name);
+    if view.is_null() {
+        error!("View not found: {}", name);
+    }
+    let paths = setting_value("paths", &view["paths"]).unwrap_or_default();
+    let kinds = setting_value("kinds", &view["kinds"]).unwrap_or_default();
+    set("kerai.view", name, local);
+    set("kerai.view_paths", &paths, local);
+    set("kerai.view_kinds", &kinds, local);
+
+    let current =
+        Spi::get_one::<String>("SELECT name FROM kerai.branches WHERE is_current").unwrap_or(None);
+    view["on_branch"] =
+        json!(view["branch"].is_null() || view["branch"].as_str() == current.as_deref());
+    view["current_branch"] = json!(current);
+    pgrx::JsonB(view)
+}
+
+/// Stop filtering by any view for the rest of the session.
+#[pg_extern]
+fn clear_view() -> bool {
+    for setting in ["kerai.view", "kerai.view_paths", "kerai.view_kinds"] {
+        set(setting, "", false);
+    }
+    true
+}
+
+/// The view in effect, null when none is.
+#[pg_extern]
+fn current_view() -> Option<String> {
+    VIEW.get()
+        .and_then(|v| v.into_string().ok())
+        .filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setting_value_joins_filters() {
+        assert_eq!(setting_value("kinds", &Value::Null).unwrap(), "");
+        assert_eq!(
+            setting_value("paths", &json!(["core.src", " web "])).unwrap(),
+            "core.src,web"
+        );
+        assert!(setting_value("kinds", &json!("fn")).is_err());
+        assert!(setting_value("kinds", &json!(["fn,struct"])).is_err());
+        assert!(setting_value("kinds", &json!([""])).is_err());
+    }
+
+    #[test]
+    fn array_sql_is_null_without_filter() {
+        assert_eq!(array_sql("", "text"), "NULL");
+        assert_eq!(
+            array_sql("a.b,c", "ltree"),
+            "string_to_array('a.b,c', ',')::ltree[]"
+        );
+    }
+}
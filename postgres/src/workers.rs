@@ -1,6 +1,560 @@
+use std::ffi::CString;
+use std::time::Duration;
+
+use pgrx::bgworkers::{BackgroundWorker, BackgroundWorkerBuilder, SignalWakeFlags};
+use pgrx::guc::{GucContext, GucFlags, GucRegistry, GucSetting};
 use pgrx::prelude::*;
 
-/// Register background workers. Real workers come in Plans 04, 06, 10.
+/// Seconds between index advisor samples; 0 disables the worker's sampling.
+static ADVISOR_INTERVAL: GucSetting<i32> = GucSetting::<i32>::new(3600);
+
+/// Database the background workers connect to (where the extension is installed).
+static WORKER_DATABASE: GucSetting<Option<CString>> =
+    GucSetting::<Option<CString>>::new(Some(c"postgres"));
+
+/// Width, in Lamport ticks, of each versions/ledger `timestamp` partition
+/// of Lamport counters from before hybrid clocks.
+pub static PARTITION_SPAN: GucSetting<i32> = GucSetting::<i32>::new(100_000);
+
+/// Width, in days, of each versions/ledger partition of hybrid clock timestamps.
+pub static PARTITION_DAYS: GucSetting<i32> = GucSetting::<i32>::new(7);
+
+/// Seconds between partition maintenance passes; 0 disables the worker's passes.
+static PARTITION_INTERVAL: GucSetting<i32> = GucSetting::<i32>::new(600);
+
+/// Seconds between sync passes with peers that have endpoints; 0 disables the worker's passes.
+static SYNC_INTERVAL: GucSetting<i32> = GucSetting::<i32>::new(300);
+
+/// Seconds between folds of queued perspective deltas; 0 disables the worker's folds.
+static CONSENSUS_INTERVAL: GucSetting<i32> = GucSetting::<i32>::new(5);
+
+/// Seconds between folds of queued activity into the timeline; 0 disables the worker's folds.
+static ACTIVITY_INTERVAL: GucSetting<i32> = GucSetting::<i32>::new(60);
+
+/// Days code may run ahead of the docs describing it before they count as stale.
+pub static STALE_DOC_DAYS: GucSetting<i32> = GucSetting::<i32>::new(30);
+
+/// Whether row changes to nodes, edges and versions are sent as change
+/// events (see the `trigger_change_events` schema block).
+static CHANGE_EVENTS: GucSetting<bool> = GucSetting::<bool>::new(true);
+
+/// Minutes a sandbox lives before the sandbox cleaner expires it.
+pub static SANDBOX_TTL: GucSetting<i32> = GucSetting::<i32>::new(1440);
+
+/// Seconds between sandbox cleanup passes; 0 disables the worker's passes.
+static SANDBOX_INTERVAL: GucSetting<i32> = GucSetting::<i32>::new(300);
+
+/// Seconds between tombstone garbage collection passes; 0 disables the worker's passes.
+static GC_INTERVAL: GucSetting<i32> = GucSetting::<i32>::new(3600);
+
+/// Days a tombstoned node is kept before garbage collection may purge it.
+static GC_RETENTION_DAYS: GucSetting<i32> = GucSetting::<i32>::new(30);
+
+/// Seconds between subscription digest passes; 0 disables the worker's passes.
+static DIGEST_INTERVAL: GucSetting<i32> = GucSetting::<i32>::new(300);
+
+/// Seconds between DOI metadata lookups for bibliography entries; 0 (the
+/// default, as lookups reach doi.org) disables the worker's lookups.
+static DOI_LOOKUP_INTERVAL: GucSetting<i32> = GucSetting::<i32>::new(0);
+
+/// Register GUCs and background workers. Workers only start when kerai is
+/// listed in `shared_preload_libraries`.
 pub fn register_workers() {
-    info!("Kerai: background worker registration (no workers yet)");
+    GucRegistry::define_int_guc(
+        c"kerai.advisor_interval",
+        c"Seconds between index advisor samples",
+        c"How often the index advisor samples pg_stat_statements. 0 disables it.",
+        &ADVISOR_INTERVAL,
+        0,
+        86400,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+    GucRegistry::define_string_guc(
+        c"kerai.worker_database",
+        c"Database kerai's background workers connect to",
+        c"The database where the kerai extension (and pg_stat_statements, for the index advisor) is installed.",
+        &WORKER_DATABASE,
+        GucContext::Postmaster,
+        GucFlags::default(),
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.partition_span",
+        c"Lamport timestamp range covered by each versions/ledger partition",
+        c"New partitions of kerai.versions and kerai.ledger holding Lamport counters from before hybrid clocks cover this many timestamps each.",
+        &PARTITION_SPAN,
+        1000,
+        i32::MAX,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.partition_days",
+        c"Days of hybrid clock time covered by each versions/ledger partition",
+        c"New partitions of kerai.versions and kerai.ledger holding hybrid logical clock timestamps cover this many days each.",
+        &PARTITION_DAYS,
+        1,
+        3650,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.partition_interval",
+        c"Seconds between partition maintenance passes",
+        c"How often the partition worker creates upcoming versions/ledger partitions. 0 disables it.",
+        &PARTITION_INTERVAL,
+        0,
+        86400,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.sync_interval",
+        c"Seconds between sync passes with peers",
+        c"How often the sync worker exchanges operations with registered peers that have an HTTP endpoint. 0 disables it.",
+        &SYNC_INTERVAL,
+        0,
+        86400,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.stale_doc_days",
+        c"Days code may change ahead of its documentation before the docs are stale",
+        c"Doc nodes whose linked code changed this many days after them are reported by kerai.stale_docs and get suggestions.",
+        &STALE_DOC_DAYS,
+        1,
+        36500,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+    GucRegistry::define_bool_guc(
+        c"kerai.change_events",
+        c"Send row changes to nodes, edges and versions as change events",
+        c"When on, each row change to kerai.nodes, kerai.edges and kerai.versions is sent as JSON on kerai_nodes, kerai_edges or kerai_versions. Turn off for bulk imports.",
+        &CHANGE_EVENTS,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.consensus_interval",
+        c"Seconds between folds of perspective changes into consensus state",
+        c"How often the consensus worker folds queued perspective deltas into kerai.consensus_state and kerai.agent_stats. 0 disables it.",
+        &CONSENSUS_INTERVAL,
+        0,
+        86400,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.activity_interval",
+        c"Seconds between folds of queued activity into the timeline",
+        c"How often the activity worker folds queued versions, perspective changes and parse runs into kerai.activity_daily. 0 disables it.",
+        &ACTIVITY_INTERVAL,
+        0,
+        86400,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.sandbox_ttl",
+        c"Minutes a sandbox lives before it is expired",
+        c"Default lifetime of sandboxes made by kerai.create_sandbox; the sandbox cleaner removes them once it passes.",
+        &SANDBOX_TTL,
+        1,
+        i32::MAX,
+        GucContext::Userset,
+        GucFlags::UNIT_MIN,
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.sandbox_interval",
+        c"Seconds between sandbox cleanup passes",
+        c"How often the sandbox cleaner expires abandoned sandboxes and removes their nodes. 0 disables it.",
+        &SANDBOX_INTERVAL,
+        0,
+        86400,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.gc_interval",
+        c"Seconds between tombstone garbage collection passes",
+        c"How often the garbage collector purges tombstoned nodes past kerai.gc_retention_days that every peer has acknowledged. 0 disables it.",
+        &GC_INTERVAL,
+        0,
+        86400,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.gc_retention_days",
+        c"Days tombstoned nodes are kept before garbage collection",
+        c"The garbage collector purges a tombstoned node only once it was deleted this many days ago, so that peers syncing late still learn of the delete.",
+        &GC_RETENTION_DAYS,
+        0,
+        36500,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.digest_interval",
+        c"Seconds between subscription digest passes",
+        c"How often the digest worker delivers due subscription digests to users' inboxes. 0 disables it.",
+        &DIGEST_INTERVAL,
+        0,
+        86400,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+    GucRegistry::define_int_guc(
+        c"kerai.doi_lookup_interval",
+        c"Seconds between DOI metadata lookups for bibliography entries",
+        c"How often the DOI enricher fetches doi.org metadata for bib entries that have a DOI but none yet. 0 disables it.",
+        &DOI_LOOKUP_INTERVAL,
+        0,
+        86400,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+
+    if !unsafe { pg_sys::process_shared_preload_libraries_in_progress } {
+        return;
+    }
+    BackgroundWorkerBuilder::new("kerai index advisor")
+        .set_function("kerai_index_advisor_main")
+        .set_library("kerai")
+        .enable_spi_access()
+        .load();
+    BackgroundWorkerBuilder::new("kerai partition maintainer")
+        .set_function("kerai_partition_main")
+        .set_library("kerai")
+        .enable_spi_access()
+        .load();
+    BackgroundWorkerBuilder::new("kerai peer sync")
+        .set_function("kerai_sync_main")
+        .set_library("kerai")
+        .enable_spi_access()
+        .load();
+    BackgroundWorkerBuilder::new("kerai consensus folder")
+        .set_function("kerai_consensus_main")
+        .set_library("kerai")
+        .enable_spi_access()
+        .load();
+    BackgroundWorkerBuilder::new("kerai activity folder")
+        .set_function("kerai_activity_main")
+        .set_library("kerai")
+        .enable_spi_access()
+        .load();
+    BackgroundWorkerBuilder::new("kerai sandbox cleaner")
+        .set_function("kerai_sandbox_main")
+        .set_library("kerai")
+        .enable_spi_access()
+        .load();
+    BackgroundWorkerBuilder::new("kerai garbage collector")
+        .set_function("kerai_gc_main")
+        .set_library("kerai")
+        .enable_spi_access()
+        .load();
+    BackgroundWorkerBuilder::new("kerai digest sender")
+        .set_function("kerai_digest_main")
+        .set_library("kerai")
+        .enable_spi_access()
+        .load();
+    BackgroundWorkerBuilder::new("kerai DOI enricher")
+        .set_function("kerai_doi_main")
+        .set_library("kerai")
+        .enable_spi_access()
+        .load();
+}
+
+/// Database name for a worker to connect to.
+fn worker_database() -> String {
+    WORKER_DATABASE
+        .get()
+        .and_then(|db| db.into_string().ok())
+        .unwrap_or_else(|| "postgres".into())
+}
+
+/// Wait out one worker interval. Returns `None` on shutdown, otherwise
+/// whether this pass should run (`interval` 0 keeps the worker idle).
+fn wait_pass(interval: i32) -> Option<bool> {
+    let wait = Duration::from_secs(if interval > 0 { interval as u64 } else { 60 });
+    BackgroundWorker::wait_latch(Some(wait)).then_some(interval > 0)
+}
+
+fn extension_installed() -> bool {
+    Spi::get_one::<bool>("SELECT EXISTS(SELECT 1 FROM pg_extension WHERE extname = 'kerai')")
+        .unwrap_or(Some(false))
+        .unwrap_or(false)
+}
+
+/// Index advisor worker: samples `pg_stat_statements` into
+/// `kerai.index_advice` every `kerai.advisor_interval` seconds.
+#[pg_guard]
+#[no_mangle]
+pub extern "C-unwind" fn kerai_index_advisor_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+    BackgroundWorker::connect_worker_to_spi(Some(&worker_database()), None);
+
+    // Re-read the interval on every pass so a SIGHUP'd value takes effect
+    while let Some(run) = wait_pass(ADVISOR_INTERVAL.get()) {
+        if !run {
+            continue;
+        }
+
+        BackgroundWorker::transaction(|| {
+            let ready = extension_installed()
+                && Spi::get_one::<bool>(
+                    "SELECT EXISTS(SELECT 1 FROM pg_extension WHERE extname = 'pg_stat_statements')",
+                )
+                .unwrap_or(Some(false))
+                .unwrap_or(false);
+            if ready {
+                let sampled = crate::advisor::sample();
+                log!("kerai index advisor: sampled {} statements", sampled);
+            }
+        });
+    }
+}
+
+/// Partition worker: keeps `timestamp` range partitions of kerai.versions
+/// and kerai.ledger ahead of the newest rows every
+/// `kerai.partition_interval` seconds.
+#[pg_guard]
+#[no_mangle]
+pub extern "C-unwind" fn kerai_partition_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+    BackgroundWorker::connect_worker_to_spi(Some(&worker_database()), None);
+
+    while let Some(run) = wait_pass(PARTITION_INTERVAL.get()) {
+        if !run {
+            continue;
+        }
+
+        BackgroundWorker::transaction(|| {
+            if extension_installed() {
+                let result = crate::partitions::ensure_partitions(
+                    PARTITION_SPAN.get() as i64,
+                    PARTITION_DAYS.get() as i64,
+                );
+                for (table, r) in result.as_object().into_iter().flatten() {
+                    let created = r["created"].as_array().map_or(0, |c| c.len());
+                    if created > 0 {
+                        log!("kerai partition maintainer: created {} {} partitions", created, table);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Sync worker: every `kerai.sync_interval` seconds, pulls from and pushes
+/// to each registered peer with an HTTP endpoint as its sync policy allows,
+/// logging each exchange in kerai.sync_log. Each peer gets its own transaction, so one slow or
+/// failing peer does not hold back the others' results.
+#[pg_guard]
+#[no_mangle]
+pub extern "C-unwind" fn kerai_sync_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+    BackgroundWorker::connect_worker_to_spi(Some(&worker_database()), None);
+
+    while let Some(run) = wait_pass(SYNC_INTERVAL.get()) {
+        if !run {
+            continue;
+        }
+
+        let peers = BackgroundWorker::transaction(|| {
+            if extension_installed() {
+                crate::crdt::sync::peers(None)
+            } else {
+                Vec::new()
+            }
+        });
+        for peer in &peers {
+            if BackgroundWorker::sigterm_received() {
+                break;
+            }
+            let row = BackgroundWorker::transaction(|| crate::crdt::sync::sync_peer(peer, false));
+            if row["status"] == "held" {
+                debug1!(
+                    "kerai peer sync: {} held: {}",
+                    peer["name"].as_str().unwrap_or_default(),
+                    row["error"].as_str().unwrap_or_default(),
+                );
+            } else if row["status"] == "ok" {
+                log!(
+                    "kerai peer sync: {} pulled {}, pushed {}",
+                    peer["name"].as_str().unwrap_or_default(),
+                    row["pulled"],
+                    row["pushed"],
+                );
+            } else {
+                warning!(
+                    "kerai peer sync: {} failed: {}",
+                    peer["name"].as_str().unwrap_or_default(),
+                    row["error"].as_str().unwrap_or_default(),
+                );
+            }
+        }
+    }
+}
+
+/// Consensus worker: every `kerai.consensus_interval` seconds, folds queued
+/// perspective deltas into kerai.consensus_state and kerai.agent_stats so
+/// consensus and stats queries find little or nothing left to fold.
+#[pg_guard]
+#[no_mangle]
+pub extern "C-unwind" fn kerai_consensus_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+    BackgroundWorker::connect_worker_to_spi(Some(&worker_database()), None);
+
+    while let Some(run) = wait_pass(CONSENSUS_INTERVAL.get()) {
+        if !run {
+            continue;
+        }
+
+        BackgroundWorker::transaction(|| {
+            if extension_installed() {
+                let folded = crate::consensus::fold_deltas();
+                if folded > 0 {
+                    debug1!("kerai consensus folder: folded {} perspective deltas", folded);
+                }
+            }
+        });
+    }
+}
+
+/// Activity worker: every `kerai.activity_interval` seconds, folds queued
+/// activity into kerai.activity_daily so timelines read it ready-made.
+#[pg_guard]
+#[no_mangle]
+pub extern "C-unwind" fn kerai_activity_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+    BackgroundWorker::connect_worker_to_spi(Some(&worker_database()), None);
+
+    while let Some(run) = wait_pass(ACTIVITY_INTERVAL.get()) {
+        if !run {
+            continue;
+        }
+
+        BackgroundWorker::transaction(|| {
+            if extension_installed() {
+                let folded = crate::timeline::fold_activity();
+                if folded > 0 {
+                    debug1!("kerai activity folder: folded {} activity deltas", folded);
+                }
+            }
+        });
+    }
+}
+
+/// Sandbox worker: every `kerai.sandbox_interval` seconds, expires active
+/// sandboxes past their `expires_at` and removes their nodes.
+#[pg_guard]
+#[no_mangle]
+pub extern "C-unwind" fn kerai_sandbox_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+    BackgroundWorker::connect_worker_to_spi(Some(&worker_database()), None);
+
+    while let Some(run) = wait_pass(SANDBOX_INTERVAL.get()) {
+        if !run {
+            continue;
+        }
+
+        BackgroundWorker::transaction(|| {
+            if extension_installed() {
+                let expired = crate::sandboxes::expire();
+                if expired > 0 {
+                    log!("kerai sandbox cleaner: expired {} sandboxes", expired);
+                }
+            }
+        });
+    }
+}
+
+/// Garbage collector worker: every `kerai.gc_interval` seconds, purges
+/// tombstoned nodes older than `kerai.gc_retention_days` whose deletes
+/// every peer has acknowledged.
+#[pg_guard]
+#[no_mangle]
+pub extern "C-unwind" fn kerai_gc_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+    BackgroundWorker::connect_worker_to_spi(Some(&worker_database()), None);
+
+    while let Some(run) = wait_pass(GC_INTERVAL.get()) {
+        if !run {
+            continue;
+        }
+
+        BackgroundWorker::transaction(|| {
+            if extension_installed() {
+                let retention = pgrx::datum::Interval::new(0, GC_RETENTION_DAYS.get(), 0)
+                    .unwrap_or_else(|e| error!("Invalid kerai.gc_retention_days: {}", e));
+                let purged = crate::gc::purge(retention);
+                if purged > 0 {
+                    log!("kerai garbage collector: purged {} tombstones", purged);
+                }
+            }
+        });
+    }
+}
+
+/// Digest worker: every `kerai.digest_interval` seconds, rolls the
+/// operations under each due subscription into a digest in its user's
+/// inbox.
+#[pg_guard]
+#[no_mangle]
+pub extern "C-unwind" fn kerai_digest_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+    BackgroundWorker::connect_worker_to_spi(Some(&worker_database()), None);
+
+    while let Some(run) = wait_pass(DIGEST_INTERVAL.get()) {
+        if !run {
+            continue;
+        }
+
+        BackgroundWorker::transaction(|| {
+            if extension_installed() {
+                let result = crate::subscriptions::deliver(false);
+                if result["digests"].as_u64().unwrap_or(0) > 0 {
+                    log!(
+                        "kerai digest sender: delivered {} digests covering {} changes",
+                        result["digests"],
+                        result["events"],
+                    );
+                }
+            }
+        });
+    }
+}
+
+/// DOI worker: every `kerai.doi_lookup_interval` seconds, fetches doi.org
+/// metadata for a batch of bibliography entries that have a DOI but no
+/// `doi_metadata` yet.
+#[pg_guard]
+#[no_mangle]
+pub extern "C-unwind" fn kerai_doi_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+    BackgroundWorker::connect_worker_to_spi(Some(&worker_database()), None);
+
+    while let Some(run) = wait_pass(DOI_LOOKUP_INTERVAL.get()) {
+        if !run {
+            continue;
+        }
+
+        BackgroundWorker::transaction(|| {
+            if extension_installed() {
+                let result = crate::parser::latex::citations::enrich(true, 25);
+                if result["enriched"].as_u64().unwrap_or(0) + result["failed"].as_u64().unwrap_or(0)
+                    > 0
+                {
+                    log!(
+                        "kerai DOI enricher: enriched {} entries, {} lookups failed",
+                        result["enriched"],
+                        result["failed"],
+                    );
+                }
+            }
+        });
+    }
 }
@@ -0,0 +1,15 @@
+typedef struct {
+    int x;
+    int y;
+} point;
+
+static int origin_x = 0;
+
+int add(int a, int b) {
+    return a + b;
+}
+
+int main(void) {
+    point p = {1, 2};
+    return add(p.x, p.y) - origin_x;
+}
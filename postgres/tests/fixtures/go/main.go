@@ -0,0 +1,17 @@
+package main
+
+import "fmt"
+
+type point struct {
+	x int
+	y int
+}
+
+func add(a, b int) int {
+	return a + b
+}
+
+func main() {
+	p := point{x: 1, y: 2}
+	fmt.Println(add(p.x, p.y))
+}
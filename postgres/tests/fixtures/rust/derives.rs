@@ -0,0 +1,5 @@
+#[derive(Debug, PartialEq, Clone)]
+struct Point {
+    x: f64,
+    y: f64,
+}
@@ -0,0 +1,9 @@
+fn main() {
+    let values = vec![1, 2, 3];
+    let total: i32 = values.iter().sum();
+    if total > 5 {
+        println!("large: {}", total);
+    } else {
+        println!("small");
+    }
+}
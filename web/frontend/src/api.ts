@@ -2,22 +2,88 @@
 
 const BASE = '/api';
 
+/// An application/problem+json error body; `code` is stable, `detail` is not.
+export class ApiError extends Error {
+  constructor(
+    public status: number,
+    public code: string,
+    public detail: string,
+  ) {
+    super(`${status} ${code}: ${detail}`);
+  }
+}
+
+async function fail(res: Response): Promise<never> {
+  const text = await res.text();
+  try {
+    const problem = JSON.parse(text);
+    throw new ApiError(res.status, problem.code ?? 'unknown', problem.detail ?? text);
+  } catch (e) {
+    if (e instanceof ApiError) throw e;
+    throw new ApiError(res.status, 'unknown', text);
+  }
+}
+
+const TOKEN_KEY = 'kerai-api-token';
+
+/// Remember the API token sent as `Authorization: Bearer` (writes need one
+/// with the write scope); `null` forgets it.
+export function setApiToken(token: string | null) {
+  if (token) localStorage.setItem(TOKEN_KEY, token);
+  else localStorage.removeItem(TOKEN_KEY);
+}
+
+export const getApiToken = (): string | null => localStorage.getItem(TOKEN_KEY);
+
+function headers(): Record<string, string> {
+  const token = getApiToken();
+  return token
+    ? { 'Content-Type': 'application/json', Authorization: `Bearer ${token}` }
+    : { 'Content-Type': 'application/json' };
+}
+
 async function request<T>(path: string, opts?: RequestInit): Promise<T> {
   const res = await fetch(`${BASE}${path}`, {
-    headers: { 'Content-Type': 'application/json' },
+    headers: headers(),
     ...opts,
   });
-  if (!res.ok) {
-    const text = await res.text();
-    throw new Error(`${res.status}: ${text}`);
-  }
+  if (!res.ok) return fail(res);
   return res.json();
 }
 
+/// One page of a listing; pass `next` back as `cursor` for the page after.
+export interface Page<T> {
+  items: T[];
+  next: string | null;
+}
+
+/// Paging and filters for /documents, /search?sort=created and /perspectives.
+export interface PageOptions {
+  limit?: number;
+  order?: 'asc' | 'desc';
+  cursor?: string;
+  kind?: string;
+  language?: string;
+}
+
+async function requestPage<T>(path: string): Promise<Page<T>> {
+  const res = await fetch(`${BASE}${path}`, { headers: headers() });
+  if (!res.ok) return fail(res);
+  return { items: await res.json(), next: res.headers.get('X-Next-Cursor') };
+}
+
+function setPage(params: URLSearchParams, page?: PageOptions): URLSearchParams {
+  for (const [key, value] of Object.entries(page ?? {})) {
+    if (value !== undefined && value !== '') params.set(key, String(value));
+  }
+  return params;
+}
+
 export interface Document {
   id: string;
   content: string;
   metadata: Record<string, unknown>;
+  language: string | null;
   created_at: string;
 }
 
@@ -31,6 +97,49 @@ export interface TreeNode {
   depth: number;
 }
 
+export interface CrossRefLabel {
+  id: string;
+  key: string;
+  kind: 'section' | 'figure' | 'table' | 'equation' | 'theorem' | 'definition' | 'other';
+  target_id: string;
+  target_kind: string;
+  title: string | null;
+  file: string | null;
+  line: number | null;
+  backlinks: string[];
+}
+
+export interface CrossRef {
+  id: string;
+  key: string;
+  command: string | null;
+  section_id: string | null;
+  file: string | null;
+  line: number | null;
+  label_id: string | null;
+}
+
+export interface CrossRefs {
+  labels: CrossRefLabel[];
+  refs: CrossRef[];
+  unresolved: number;
+}
+
+export interface DocumentLink {
+  source_id: string;
+  target_id: string;
+  target_kind: string;
+  target_content: string | null;
+  link: Record<string, unknown>;
+}
+
+export interface Backlink {
+  id: string;
+  content: string | null;
+  path: string | null;
+  links: DocumentLink[];
+}
+
 export interface SearchResult {
   id: string;
   kind: string;
@@ -41,8 +150,123 @@ export interface SearchResult {
   agents?: Array<{ agent: string; weight: number; reasoning: string }>;
 }
 
+/// A member of a document's editing room (see the WebSocket `presence` events).
+export interface PresenceMember {
+  connection: number;
+  user_id: string | null;
+  handle: string;
+  node_id: string | null;
+  mode: 'viewing' | 'editing' | null;
+}
+
+/// A cluster of a level-of-detail graph: every node under `key`.
+export interface GraphNode {
+  key: string;
+  id: string | null;
+  kind: string | null;
+  name: string;
+  size: number;
+  depth: number;
+  kinds: Record<string, number>;
+  expandable: boolean;
+}
+
+/// Edges between two clusters, summed, with a count per relation.
+export interface GraphEdge {
+  source: string;
+  target: string;
+  weight: number;
+  relations: Record<string, number>;
+}
+
+export interface LodGraph {
+  path: string;
+  lod: number;
+  total_nodes: number;
+  truncated: boolean;
+  nodes: GraphNode[];
+  edges: GraphEdge[];
+  cached: boolean;
+  computed_at: string;
+}
+
+export interface Suggestion {
+  id: string;
+  rule: string;
+  severity: string;
+  category: string | null;
+  message: string;
+  line: number | null;
+  target_id: string | null;
+  target_kind: string | null;
+}
+
+export interface FileSuggestions {
+  file: string;
+  file_id: string;
+  suggestions: Suggestion[];
+}
+
+export interface AcceptedSuggestion {
+  id: string;
+  rule: string;
+  status: 'applied';
+  fix_applied: boolean;
+  op: { op_type: string; node_id: string } | null;
+  reward: { work_type: string; reward: number } | null;
+}
+
+export interface JobKindSummary {
+  kind: string;
+  runs: number;
+  running: number;
+  queued: number;
+  done: number;
+  failed: number;
+  cancelled: number;
+  retries: number;
+  failure_rate: number | null;
+  done_per_hour: number;
+  avg_seconds: number | null;
+  last_finished_at: string | null;
+}
+
+export interface TimelineAuthor {
+  author: string;
+  name: string | null;
+  versions: number;
+  perspectives: number;
+  parses: number;
+  total: number;
+}
+
+export interface TimelinePeriod {
+  period: string;
+  versions: number;
+  perspectives: number;
+  parses: number;
+  total: number;
+  authors: TimelineAuthor[];
+}
+
+export interface Timeline {
+  path: string | null;
+  granularity: 'day' | 'week';
+  days: number;
+  as_of: string;
+  periods: TimelinePeriod[];
+}
+
+/// Query string reading as if a staged changeset were applied, for review.
+const changesetQuery = (changeset?: string) =>
+  changeset ? `?${new URLSearchParams({ changeset })}` : '';
+
 // Documents
-export const listDocuments = () => request<Document[]>('/documents');
+export const listDocuments = (page?: PageOptions, changeset?: string) => {
+  const params = setPage(new URLSearchParams(), page);
+  if (changeset) params.set('changeset', changeset);
+  return requestPage<Document>(`/documents?${params}`);
+};
 
 export const createDocument = (source: string, filename: string) =>
   request<{ file: string; nodes: number; edges: number }>('/documents', {
@@ -50,16 +274,36 @@ export const createDocument = (source: string, filename: string) =>
     body: JSON.stringify({ source, filename }),
   });
 
-export const getDocumentTree = (id: string) =>
-  request<TreeNode[]>(`/documents/${id}/tree`);
+export const getDocumentTree = (id: string, changeset?: string) =>
+  request<TreeNode[]>(`/documents/${id}/tree${changesetQuery(changeset)}`);
+
+export const getDocumentCrossRefs = (id: string, changeset?: string) =>
+  request<CrossRefs>(`/documents/${id}/crossrefs${changesetQuery(changeset)}`);
+
+export const getDocumentBacklinks = (id: string, changeset?: string) =>
+  request<Backlink[]>(`/documents/${id}/backlinks${changesetQuery(changeset)}`);
 
-export const getDocumentMarkdown = async (id: string): Promise<string> => {
-  const res = await fetch(`${BASE}/documents/${id}/markdown`);
-  if (!res.ok) throw new Error(`${res.status}: ${await res.text()}`);
+export const getDocumentPresence = (id: string) =>
+  request<{ document_id: string; members: PresenceMember[] }>(`/documents/${id}/presence`);
+
+export const getDocumentMarkdown = async (id: string, changeset?: string): Promise<string> => {
+  const res = await fetch(`${BASE}/documents/${id}/markdown${changesetQuery(changeset)}`, {
+    headers: headers(),
+  });
+  if (!res.ok) return fail(res);
   return res.text();
 };
 
 // Nodes
+/// One item (fn, struct, impl, method) as source, indented as in its file.
+export const getNodeSource = async (id: string, changeset?: string): Promise<string> => {
+  const res = await fetch(`${BASE}/nodes/${id}/source${changesetQuery(changeset)}`, {
+    headers: headers(),
+  });
+  if (!res.ok) return fail(res);
+  return res.text();
+};
+
 export const applyOp = (op_type: string, node_id: string | null, payload: Record<string, unknown>) =>
   request<{ op_type: string; node_id: string }>('/nodes', {
     method: 'POST',
@@ -77,17 +321,139 @@ export const deleteNode = (nodeId: string) =>
     method: 'DELETE',
   });
 
+export interface OutlineEdit {
+  node_id: string;
+  ops: number;
+}
+
+export const splitNode = (nodeId: string, at: number) =>
+  request<OutlineEdit & { new_node_id: string }>(`/nodes/${nodeId}/split`, {
+    method: 'POST',
+    body: JSON.stringify({ at }),
+  });
+
+export const mergeWithNext = (nodeId: string, separator = '') =>
+  request<OutlineEdit & { merged_id: string }>(`/nodes/${nodeId}/merge-with-next`, {
+    method: 'POST',
+    body: JSON.stringify({ separator }),
+  });
+
+export const promoteHeading = (nodeId: string) =>
+  request<OutlineEdit & { level: number }>(`/nodes/${nodeId}/promote`, { method: 'POST' });
+
+export const demoteHeading = (nodeId: string) =>
+  request<OutlineEdit & { level: number }>(`/nodes/${nodeId}/demote`, { method: 'POST' });
+
+/// A `mentions` edge from a doc block's code span to the code it names,
+/// described by its other end.
+export interface CodeMention {
+  source_id: string;
+  target_id: string;
+  span: string;
+  confidence: number;
+  kind: string;
+  content: string | null;
+  path: string | null;
+  file: string | null;
+}
+
+export const getMentions = (nodeId: string, changeset?: string) =>
+  request<{ mentions: CodeMention[]; mentioned_by: CodeMention[] }>(
+    `/nodes/${nodeId}/mentions${changesetQuery(changeset)}`,
+  );
+
 // Search
-export const search = (q: string, kind?: string, limit?: number) => {
+export const search = (q: string, kind?: string, limit?: number, changeset?: string) => {
   const params = new URLSearchParams({ q });
   if (kind) params.set('kind', kind);
   if (limit) params.set('limit', String(limit));
+  if (changeset) params.set('changeset', changeset);
   return request<SearchResult[]>(`/search?${params}`);
 };
 
+/// Full-text matches by creation time, a page at a time, rather than ranked.
+export const searchByCreated = (q: string, page?: PageOptions, changeset?: string) => {
+  const params = setPage(new URLSearchParams({ q, sort: 'created' }), page);
+  if (changeset) params.set('changeset', changeset);
+  return requestPage<SearchResult>(`/search?${params}`);
+};
+
 export const suggest = (text: string, agents?: string, limit?: number) => {
   const params = new URLSearchParams({ text });
   if (agents) params.set('agents', agents);
   if (limit) params.set('limit', String(limit));
   return request<SearchResult[]>(`/suggest?${params}`);
 };
+
+// Perspectives
+export interface Perspective {
+  id: string;
+  node_id: string;
+  weight: number;
+  context_id: string | null;
+  reasoning: string | null;
+  node_kind: string;
+  node_content: string | null;
+  created_at: string;
+  updated_at: string;
+}
+
+export const getPerspectives = (agent: string, page?: PageOptions, minWeight?: number) => {
+  const params = setPage(new URLSearchParams({ agent }), page);
+  if (minWeight !== undefined) params.set('min_weight', String(minWeight));
+  return requestPage<Perspective>(`/perspectives?${params}`);
+};
+
+// Graph
+export const getGraph = (path = '', lod?: number, maxNodes?: number) => {
+  const params = new URLSearchParams({ path });
+  if (lod) params.set('lod', String(lod));
+  if (maxNodes) params.set('max_nodes', String(maxNodes));
+  return request<LodGraph>(`/graph?${params}`);
+};
+
+// Suggestions review
+export const listSuggestions = (file?: string) =>
+  request<FileSuggestions[]>(`/suggestions${file ? `?${new URLSearchParams({ file })}` : ''}`);
+
+export const acceptSuggestion = (id: string) =>
+  request<AcceptedSuggestion>(`/suggestions/${id}/accept`, { method: 'POST' });
+
+export const dismissSuggestion = (id: string, reason?: string) =>
+  request<{ id: string; rule: string; status: 'dismissed'; reason: string | null }>(
+    `/suggestions/${id}/dismiss`,
+    { method: 'POST', body: JSON.stringify({ reason: reason ?? null }) },
+  );
+
+// Activity timeline
+export const getTimeline = (path = '', granularity: 'day' | 'week' = 'day', days?: number) => {
+  const params = new URLSearchParams({ path, granularity });
+  if (days) params.set('days', String(days));
+  return request<Timeline>(`/timeline?${params}`);
+};
+
+// Job metrics
+export const getJobsSummary = (hours?: number) =>
+  request<JobKindSummary[]>(`/jobs/summary${hours ? `?${new URLSearchParams({ hours: String(hours) })}` : ''}`);
+
+// API tokens (admin); `token` is only ever returned here
+export interface ApiToken {
+  id: string;
+  name: string;
+  token: string;
+  scopes: ('read' | 'write' | 'admin')[];
+  user_id: string | null;
+  instance_id: string | null;
+  created_at: string;
+  expires_at: string | null;
+}
+
+export const createApiToken = (
+  name: string,
+  scopes: ApiToken['scopes'] = ['read'],
+  expiresInDays?: number,
+) =>
+  request<ApiToken>('/tokens', {
+    method: 'POST',
+    body: JSON.stringify({ name, scopes, expires_in_days: expiresInDays ?? null }),
+  });
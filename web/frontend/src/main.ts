@@ -88,7 +88,7 @@ function onContentChange(): void {
 
 async function loadDocuments(): Promise<void> {
   try {
-    const docs = await api.listDocuments();
+    const { items: docs } = await api.listDocuments();
     const select = document.getElementById('doc-select') as HTMLSelectElement;
     // Clear existing options except the first
     while (select.options.length > 1) select.remove(1);
@@ -1,16 +1,33 @@
 /// WebSocket client for real-time updates.
+import { getApiToken } from './api'
 
 type MessageHandler = (payload: Record<string, unknown>) => void;
 
+/// Change events to receive instead of every `kerai_ops` notification:
+/// those on one of `tables` under one of `paths` (ltree prefixes) with one
+/// of `kinds`; a missing list lets everything through.
+export interface ChangeFilter {
+  tables?: Array<'nodes' | 'edges' | 'versions'>;
+  paths?: string[];
+  kinds?: string[];
+}
+
 export class WsClient {
   private ws: WebSocket | null = null;
   private handlers: MessageHandler[] = [];
   private reconnectTimer: number | null = null;
   private url: string;
+  private documentId: string | null = null;
+  private filter: ChangeFilter | null = null;
 
-  constructor(url?: string) {
+  /// `token` is a session token, for servers other than the page's own,
+  /// which see the `kerai_session` cookie instead, or an API token; without
+  /// one the stored API token is sent, which `op` messages need on kerai-web.
+  constructor(url?: string, token?: string) {
     const proto = location.protocol === 'https:' ? 'wss:' : 'ws:';
-    this.url = url || `${proto}//${location.host}/api/ws`;
+    const sent = token ?? getApiToken();
+    const query = sent ? `?token=${encodeURIComponent(sent)}` : '';
+    this.url = url || `${proto}//${location.host}/api/ws${query}`;
   }
 
   connect(): void {
@@ -24,6 +41,8 @@ export class WsClient {
         clearTimeout(this.reconnectTimer);
         this.reconnectTimer = null;
       }
+      if (this.filter) this.subscribe(this.filter);
+      if (this.documentId) this.join(this.documentId);
     };
 
     this.ws.onmessage = (event) => {
@@ -59,6 +78,55 @@ export class WsClient {
     }
   }
 
+  /// Join a document's room; rejoined after a reconnect. The server
+  /// answers `joined` with the Lamport timestamp of each edited node.
+  join(documentId: string): void {
+    this.documentId = documentId;
+    this.sendMessage({ type: 'join', document_id: documentId });
+  }
+
+  leave(): void {
+    this.documentId = null;
+    this.sendMessage({ type: 'leave' });
+  }
+
+  /// Receive `change` events passing `filter` in place of `kerai_ops`
+  /// notifications; resubscribed after a reconnect.
+  subscribe(filter: ChangeFilter): void {
+    this.filter = filter;
+    this.sendMessage({ type: 'subscribe', ...filter });
+  }
+
+  unsubscribe(): void {
+    this.filter = null;
+    this.sendMessage({ type: 'unsubscribe' });
+  }
+
+  /// Share the node being viewed or edited in the joined document (none
+  /// when `nodeId` is null). Other members get a `presence` event.
+  cursor(nodeId: string | null, mode: 'viewing' | 'editing' = 'viewing'): void {
+    this.sendMessage(nodeId ? { type: 'cursor', node_id: nodeId, mode } : { type: 'cursor' });
+  }
+
+  /// Submit an edit to the joined document. `baseTs` is the timestamp of
+  /// the last operation seen on `node_id`; the server answers `ack`, or
+  /// `rejected` when the node has changed since.
+  edit(op: {
+    ref?: string;
+    op_type: 'insert_node' | 'update_content' | 'move_node';
+    node_id?: string;
+    payload: Record<string, unknown>;
+    base_ts?: number;
+  }): void {
+    this.sendMessage({ type: 'op', ...op });
+  }
+
+  private sendMessage(message: Record<string, unknown>): void {
+    if (this.ws?.readyState === WebSocket.OPEN) {
+      this.ws.send(JSON.stringify(message));
+    }
+  }
+
   private scheduleReconnect(): void {
     if (this.reconnectTimer) return;
     this.reconnectTimer = window.setTimeout(() => {
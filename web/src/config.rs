@@ -1,20 +1,10 @@
 /// Configuration from environment variables.
+use kerai_cli::serve::config::{Config, DEFAULT_DATABASE_URL};
 
-#[derive(Debug, Clone)]
-pub struct Config {
-    pub database_url: String,
-    pub listen_addr: String,
-    pub static_dir: Option<String>,
-}
-
-impl Config {
-    pub fn from_env() -> Self {
-        Self {
-            database_url: std::env::var("DATABASE_URL")
-                .unwrap_or_else(|_| "host=/tmp dbname=kerai".to_string()),
-            listen_addr: std::env::var("LISTEN_ADDR")
-                .unwrap_or_else(|_| "0.0.0.0:62830".to_string()),
-            static_dir: std::env::var("STATIC_DIR").ok(),
-        }
-    }
+/// `DATABASE_URL` and `LISTEN_ADDR`, the rest as `kerai serve` reads them.
+pub fn from_env() -> Config {
+    let database_url =
+        std::env::var("DATABASE_URL").unwrap_or_else(|_| DEFAULT_DATABASE_URL.to_string());
+    let listen_addr = std::env::var("LISTEN_ADDR").unwrap_or_else(|_| "0.0.0.0:62830".to_string());
+    Config::from_env(&database_url, &listen_addr)
 }
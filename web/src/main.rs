@@ -1,6 +1,5 @@
 mod config;
 mod db;
-mod notify;
 mod routes;
 
 use tower_http::cors::CorsLayer;
@@ -10,7 +9,7 @@ use tower_http::services::ServeDir;
 async fn main() {
     tracing_subscriber::fmt::init();
 
-    let config = config::Config::from_env();
+    let config = config::from_env();
     tracing::info!("Starting kerai-web on {}", config.listen_addr);
     tracing::info!("Database: {}", config.database_url);
 
@@ -18,7 +17,12 @@ async fn main() {
     let pool = db::Pool::new(config.clone());
 
     // Start LISTEN/NOTIFY background task
-    let notify_tx = notify::start_listener(config.database_url.clone());
+    let notify_tx = kerai_cli::serve::notify::start_listener(config.database_url.clone());
+
+    // Fail or requeue jobs whose worker has died
+    if let Some(interval) = config.reap_interval {
+        kerai_cli::serve::reaper::start(pool.clone(), interval);
+    }
 
     // Build router
     let mut app = routes::build_router(pool, notify_tx)
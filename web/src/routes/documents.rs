@@ -5,6 +5,7 @@ use serde_json::Value;
 use std::sync::Arc;
 
 use crate::db::Pool;
+use kerai_cli::serve::error::ApiError;
 
 #[derive(Deserialize)]
 pub struct ParseMarkdownRequest {
@@ -16,10 +17,8 @@ pub struct ParseMarkdownRequest {
 pub async fn create_document(
     State(pool): State<Arc<Pool>>,
     Json(req): Json<ParseMarkdownRequest>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let sql = format!(
         "SELECT kerai.parse_markdown('{}', '{}')",
@@ -27,33 +26,7 @@ pub async fn create_document(
         req.filename.replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
-
-    let result: Value = row.get(0);
-    Ok(Json(result))
-}
-
-/// GET /api/documents — list document nodes
-pub async fn list_documents(
-    State(pool): State<Arc<Pool>>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
-
-    let sql = "SELECT COALESCE(jsonb_agg(jsonb_build_object(
-        'id', id,
-        'content', content,
-        'metadata', metadata,
-        'created_at', created_at
-    ) ORDER BY created_at DESC), '[]'::jsonb)
-    FROM kerai.nodes WHERE kind = 'document'";
-
-    let row = client.query_one(sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
@@ -63,20 +36,19 @@ pub async fn list_documents(
 pub async fn document_tree(
     State(pool): State<Arc<Pool>>,
     Path(doc_id): Path<String>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     // Recursive CTE to get the full tree
     let sql = format!(
         "WITH RECURSIVE tree AS (
             SELECT id, kind, content, parent_id, position, metadata, 0 AS depth
-            FROM kerai.nodes WHERE id = '{}'::uuid
+            FROM kerai.nodes WHERE id = '{}'::uuid AND deleted_at IS NULL
             UNION ALL
             SELECT n.id, n.kind, n.content, n.parent_id, n.position, n.metadata, t.depth + 1
             FROM kerai.nodes n
             JOIN tree t ON n.parent_id = t.id
+            WHERE n.deleted_at IS NULL
         )
         SELECT COALESCE(jsonb_agg(jsonb_build_object(
             'id', id,
@@ -91,9 +63,7 @@ pub async fn document_tree(
         doc_id.replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
@@ -103,19 +73,15 @@ pub async fn document_tree(
 pub async fn document_markdown(
     State(pool): State<Arc<Pool>>,
     Path(doc_id): Path<String>,
-) -> Result<String, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<String, ApiError> {
+    let client = pool.get().await?;
 
     let sql = format!(
         "SELECT kerai.reconstruct_markdown('{}'::uuid)",
         doc_id.replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
 
     let result: String = row.get(0);
     Ok(result)
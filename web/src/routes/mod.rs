@@ -7,19 +7,28 @@ pub mod perspectives;
 pub mod search;
 pub mod ws;
 
+use axum::middleware;
 use axum::routing::{delete, get, patch, post};
 use axum::Router;
 use std::sync::Arc;
 use tokio::sync::broadcast;
 
 use crate::db::Pool;
+use kerai_cli::serve::notify::Notification;
+use kerai_cli::serve::rooms::Rooms;
+use kerai_cli::serve::routes::{self as shared, graph, jobs, suggestions, timeline};
+use kerai_cli::serve::tokens;
 use ws::WsState;
 
-/// Build the application router with all API routes.
-pub fn build_router(pool: Arc<Pool>, notify_tx: broadcast::Sender<String>) -> Router {
+/// Build the application router with all API routes. Every `/api` request
+/// may carry an API token; writes other than `POST /api/tokens`, which
+/// checks for an admin itself, and `POST /api/eval`, which only computes,
+/// need one with the write scope.
+pub fn build_router(pool: Arc<Pool>, notify_tx: broadcast::Sender<Notification>) -> Router {
     let ws_state = Arc::new(WsState {
         pool: pool.clone(),
         notify_tx,
+        rooms: Rooms::default(),
     });
 
     let api = Router::new()
@@ -30,16 +39,36 @@ pub fn build_router(pool: Arc<Pool>, notify_tx: broadcast::Sender<String>) -> Ro
         .route("/nodes/{id}/content", patch(nodes::update_content))
         .route("/nodes/{id}/move", post(nodes::move_node))
         .route("/nodes/{id}", delete(nodes::delete_node))
-        // Documents
+        // Outline edits (shared with kerai serve)
+        .route("/nodes/{id}/split", post(shared::nodes::split_node))
+        .route("/nodes/{id}/merge-with-next", post(shared::nodes::merge_with_next))
+        .route("/nodes/{id}/promote", post(shared::nodes::promote_heading))
+        .route("/nodes/{id}/demote", post(shared::nodes::demote_heading))
+        // Code mentions and single-item source (shared with kerai serve)
+        .route("/nodes/{id}/mentions", get(shared::nodes::node_mentions))
+        .route("/nodes/{id}/source", get(shared::nodes::node_source))
+        // Documents (paged listing, LaTeX cross-references and backlinks shared with kerai serve)
         .route("/documents", post(documents::create_document))
-        .route("/documents", get(documents::list_documents))
+        .route("/documents", get(shared::documents::list_documents))
         .route("/documents/{id}/tree", get(documents::document_tree))
         .route("/documents/{id}/markdown", get(documents::document_markdown))
-        // Search
-        .route("/search", get(search::search))
+        .route("/documents/{id}/crossrefs", get(shared::documents::document_crossrefs))
+        .route("/documents/{id}/backlinks", get(shared::documents::document_backlinks))
+        // Graph (shared with kerai serve)
+        .route("/graph", get(graph::graph))
+        // Job metrics (shared with kerai serve)
+        .route("/jobs/summary", get(jobs::summary))
+        // Activity timeline (shared with kerai serve)
+        .route("/timeline", get(timeline::timeline))
+        // Suggestions review (shared with kerai serve)
+        .route("/suggestions", get(suggestions::list))
+        .route("/suggestions/{id}/accept", post(suggestions::accept))
+        .route("/suggestions/{id}/dismiss", post(suggestions::dismiss))
+        // Search (paged listing shared with kerai serve)
+        .route("/search", get(shared::search::search))
         .route("/suggest", get(search::suggest))
-        // Perspectives
-        .route("/perspectives", get(perspectives::get_perspectives))
+        // Perspectives (paged listing shared with kerai serve)
+        .route("/perspectives", get(shared::perspectives::get_perspectives))
         .route("/consensus", get(perspectives::consensus))
         // Models
         .route("/models", post(models::create_model))
@@ -50,19 +79,80 @@ pub fn build_router(pool: Arc<Pool>, notify_tx: broadcast::Sender<String>) -> Ro
         .route("/models/{agent}/info", get(models::model_info))
         .route("/models/{agent}", delete(models::delete_model))
         .route("/models/feedback", post(models::record_selection))
-        .with_state(pool);
+        .layer(middleware::from_fn(tokens::require_write))
+        .with_state(pool.clone());
+
+    // API tokens (admin)
+    let tokens_router = Router::new()
+        .route("/tokens", post(shared::tokens::create))
+        .with_state(pool.clone());
 
     // WebSocket needs its own state
     let ws_router = Router::new()
         .route("/ws", get(ws::ws_handler))
+        .route("/documents/{id}/presence", get(ws::document_presence))
         .with_state(ws_state);
 
     let eval_router = Router::new()
         .route("/eval", post(eval::eval));
 
+    let api_routers = Router::new()
+        .merge(api)
+        .merge(tokens_router)
+        .merge(ws_router)
+        .merge(eval_router)
+        .layer(middleware::from_fn_with_state(pool, tokens::authenticate));
+
     Router::new()
         .route("/", get(eval::terminal_page))
-        .nest("/api", api)
-        .nest("/api", ws_router)
-        .nest("/api", eval_router)
+        .nest("/api", api_routers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use kerai_cli::serve::config::Config;
+    use tower::ServiceExt;
+
+    /// Status of `method path` on the kerai-web router, with no database
+    /// behind it: a registered route gets past routing and fails later.
+    async fn status(method: &str, path: &str) -> StatusCode {
+        let pool = Pool::new(Config::from_env(
+            "postgresql://kerai@127.0.0.1:1/kerai",
+            "127.0.0.1:0",
+        ));
+        let (notify_tx, _) = broadcast::channel(1);
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        build_router(pool, notify_tx)
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn outline_edits_are_routed() {
+        for action in ["split", "merge-with-next", "promote", "demote"] {
+            // A GET on a POST route, so the write check and database are not reached
+            let path = format!("/api/nodes/00000000-0000-0000-0000-000000000001/{action}");
+            assert_eq!(
+                status("GET", &path).await,
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{path}"
+            );
+        }
+        assert_eq!(status("GET", "/api/nodes/x/rename").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn backlinks_are_routed() {
+        let path = "/api/documents/00000000-0000-0000-0000-000000000001/backlinks";
+        assert_ne!(status("GET", path).await, StatusCode::NOT_FOUND);
+    }
 }
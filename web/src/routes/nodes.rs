@@ -5,6 +5,7 @@ use serde_json::{json, Value};
 use std::sync::Arc;
 
 use crate::db::Pool;
+use kerai_cli::serve::error::ApiError;
 
 #[derive(Deserialize)]
 pub struct ApplyOpRequest {
@@ -22,10 +23,8 @@ pub struct UpdateContentRequest {
 pub async fn create_node(
     State(pool): State<Arc<Pool>>,
     Json(req): Json<ApplyOpRequest>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let node_id_param = req.node_id
         .map(|id| format!("'{}'::uuid", id.replace('\'', "''")))
@@ -38,9 +37,7 @@ pub async fn create_node(
         req.payload.to_string().replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
@@ -51,10 +48,8 @@ pub async fn update_content(
     State(pool): State<Arc<Pool>>,
     Path(node_id): Path<String>,
     Json(req): Json<UpdateContentRequest>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let payload = json!({"new_content": req.content});
     let sql = format!(
@@ -63,9 +58,7 @@ pub async fn update_content(
         payload.to_string().replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
@@ -76,10 +69,8 @@ pub async fn move_node(
     State(pool): State<Arc<Pool>>,
     Path(node_id): Path<String>,
     Json(payload): Json<Value>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let sql = format!(
         "SELECT kerai.apply_op('move_node', '{}'::uuid, '{}'::jsonb)",
@@ -87,31 +78,25 @@ pub async fn move_node(
         payload.to_string().replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
 }
 
-/// DELETE /api/nodes/:id — delete a node
+/// DELETE /api/nodes/:id — delete a node (a tombstone until kerai.gc purges it)
 pub async fn delete_node(
     State(pool): State<Arc<Pool>>,
     Path(node_id): Path<String>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let sql = format!(
         "SELECT kerai.apply_op('delete_node', '{}'::uuid, '{{\"cascade\": false}}'::jsonb)",
         node_id.replace('\'', "''"),
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::BAD_REQUEST, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
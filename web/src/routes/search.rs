@@ -5,13 +5,7 @@ use serde_json::Value;
 use std::sync::Arc;
 
 use crate::db::Pool;
-
-#[derive(Deserialize)]
-pub struct SearchParams {
-    pub q: String,
-    pub kind: Option<String>,
-    pub limit: Option<i32>,
-}
+use kerai_cli::serve::error::ApiError;
 
 #[derive(Deserialize)]
 pub struct ContextSearchParams {
@@ -20,46 +14,12 @@ pub struct ContextSearchParams {
     pub limit: Option<i32>,
 }
 
-/// GET /api/search — full-text search
-pub async fn search(
-    State(pool): State<Arc<Pool>>,
-    Query(params): Query<SearchParams>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
-
-    let kind_param = params.kind
-        .map(|k| format!("'{}'", k.replace('\'', "''")))
-        .unwrap_or_else(|| "NULL".to_string());
-
-    let limit_param = params.limit
-        .map(|l| l.to_string())
-        .unwrap_or_else(|| "NULL".to_string());
-
-    let sql = format!(
-        "SELECT kerai.search('{}', {}, {})",
-        params.q.replace('\'', "''"),
-        kind_param,
-        limit_param,
-    );
-
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
-
-    let result: Value = row.get(0);
-    Ok(Json(result))
-}
-
 /// GET /api/suggest — context-aware search for AI suggestions
 pub async fn suggest(
     State(pool): State<Arc<Pool>>,
     Query(params): Query<ContextSearchParams>,
-) -> Result<Json<Value>, (axum::http::StatusCode, String)> {
-    let client = pool.get().await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+) -> Result<Json<Value>, ApiError> {
+    let client = pool.get().await?;
 
     let agents_param = params.agents
         .map(|a| {
@@ -81,9 +41,7 @@ pub async fn suggest(
         limit_param,
     );
 
-    let row = client.query_one(&sql, &[]).await.map_err(|e| {
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?;
+    let row = client.query_one(&sql, &[]).await?;
 
     let result: Value = row.get(0);
     Ok(Json(result))
@@ -1,56 +1,173 @@
-use axum::extract::{State, WebSocketUpgrade};
+/// WebSocket channel: the kerai_ops NOTIFY relay, change event
+/// subscriptions, plus collaborative editing.
+///
+/// Every client receives each `kerai_ops` notification until it
+/// subscribes to change events instead:
+///
+/// - `{"type": "subscribe", tables?, paths?, kinds?}` answers `{"type":
+///   "subscribed", tables, paths, kinds}`. From then on the client gets
+///   `{"type": "change", table, op, id, path, kind, instance, ...}` for each
+///   change event that passes the filters, and no more `kerai_ops`
+///   notifications. Subscribing again replaces the filters.
+/// - `{"type": "unsubscribe"}` goes back to `kerai_ops` notifications.
+///
+/// A client editing a document joins its room with `{"type": "join",
+/// "document_id"}`, then sends `op`, `cursor` and `leave` messages; the
+/// protocol is `kerai_cli::serve::rooms`', shared with `kerai serve`.
+/// `GET /api/documents/{id}/presence` lists a room's members.
+///
+/// Messages without a `type` are applied as bare operations, as before.
+///
+/// Like any other kerai-web write, `op` messages and bare operations need
+/// an API token with the write scope, given at upgrade time as `Authorization:
+/// Bearer` or, since browsers cannot set headers on a WebSocket, the `token`
+/// query parameter.
+use axum::extract::{Path, Query, State, WebSocketUpgrade};
 use axum::extract::ws::{Message, WebSocket};
+use axum::http::HeaderMap;
 use axum::response::IntoResponse;
+use axum::{Extension, Json};
 use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc, watch};
 
 use crate::db::Pool;
+use kerai_cli::serve::error::ApiError;
+use kerai_cli::serve::notify::{self, Notification, Subscription};
+use kerai_cli::serve::rooms::{self, Identity, Rooms, Session};
+use kerai_cli::serve::tokens::{self, ApiIdentity, Scope};
 
 /// Shared state for WebSocket handlers.
 pub struct WsState {
     pub pool: Arc<Pool>,
-    pub notify_tx: broadcast::Sender<String>,
+    pub notify_tx: broadcast::Sender<Notification>,
+    pub rooms: Rooms,
 }
 
 /// GET /api/ws — WebSocket upgrade
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<WsState>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, state))
+    api: Option<Extension<ApiIdentity>>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let token = query
+        .get("token")
+        .cloned()
+        .or_else(|| session_token(&headers));
+    let api = match (api, token.as_deref()) {
+        (Some(Extension(api)), _) => Some(api),
+        (None, Some(token)) if tokens::is_api_token(token) => {
+            Some(tokens::resolve(&state.pool, token).await?)
+        }
+        _ => None,
+    };
+    let identity = match &api {
+        Some(api) => Identity {
+            user_id: api.user_id.map(|id| id.to_string()),
+            handle: api.name.clone(),
+        },
+        None => rooms::identify(&state.pool, token.as_deref()).await,
+    };
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, state, identity, api)))
+}
+
+/// GET /api/documents/{id}/presence — who is in a document's room:
+/// `{document_id, members: [{connection, user_id, handle, node_id, mode}]}`.
+pub async fn document_presence(
+    State(state): State<Arc<WsState>>,
+    Path(document_id): Path<String>,
+) -> Json<Value> {
+    let members = state.rooms.present(&document_id);
+    Json(json!({"document_id": document_id, "members": members}))
 }
 
-async fn handle_socket(socket: WebSocket, state: Arc<WsState>) {
+/// The session token a request carries, as a bearer token or the
+/// `kerai_session` cookie.
+fn session_token(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let cookie = || {
+        headers
+            .get("cookie")?
+            .to_str()
+            .ok()?
+            .split(';')
+            .find_map(|pair| pair.trim().strip_prefix("kerai_session="))
+    };
+    bearer
+        .or_else(cookie)
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    state: Arc<WsState>,
+    identity: Identity,
+    api: Option<ApiIdentity>,
+) {
     let (mut sender, mut receiver) = socket.split();
 
     // Subscribe to NOTIFY broadcast
     let mut notify_rx = state.notify_tx.subscribe();
 
-    // Forward notifications to WebSocket client
+    // Replies and room events for this client
+    let (outbox, mut outbox_rx) = mpsc::unbounded_channel::<String>();
+
+    // The client's change event filters, once it subscribes
+    let (subscription, subscription_rx) = watch::channel::<Option<Subscription>>(None);
+
+    // Forward notifications and replies to WebSocket client
     let send_task = tokio::spawn(async move {
-        while let Ok(payload) = notify_rx.recv().await {
+        loop {
+            let payload = tokio::select! {
+                notified = notify_rx.recv() => match notified {
+                    Ok(notification) => {
+                        match notify::relayed(&notification, subscription_rx.borrow().as_ref()) {
+                            Some(payload) => payload,
+                            None => continue,
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                reply = outbox_rx.recv() => match reply {
+                    Some(payload) => payload,
+                    None => break,
+                },
+            };
             if sender.send(Message::Text(payload.into())).await.is_err() {
                 break;
             }
         }
     });
 
-    // Receive messages from WebSocket client (operations)
-    let pool = state.pool.clone();
+    // Receive messages from WebSocket client (room requests and operations)
+    let connection = state.rooms.connection_id();
+    let recv_state = state.clone();
     let recv_task = tokio::spawn(async move {
+        let mut session = Session::new(connection, identity, outbox, subscription);
         while let Some(Ok(msg)) = receiver.next().await {
             match msg {
                 Message::Text(text) => {
-                    // Parse as operation and execute
-                    if let Err(e) = handle_client_op(&pool, &text).await {
-                        tracing::warn!("client op error: {}", e);
+                    let reply =
+                        handle_message(&recv_state, &mut session, api.as_ref(), &text).await;
+                    if let Some(reply) = reply {
+                        let _ = session.outbox.send(reply.to_string());
                     }
                 }
                 Message::Close(_) => break,
                 _ => {}
             }
         }
+        session.leave(&recv_state.rooms);
     });
 
     // Wait for either task to finish
@@ -60,31 +177,56 @@ async fn handle_socket(socket: WebSocket, state: Arc<WsState>) {
     }
 }
 
-async fn handle_client_op(pool: &Pool, text: &str) -> Result<(), String> {
-    let op: serde_json::Value = serde_json::from_str(text)
-        .map_err(|e| format!("invalid JSON: {}", e))?;
+/// Handle one client message, returning the reply to send it, if any.
+async fn handle_message(
+    state: &WsState,
+    session: &mut Session,
+    api: Option<&ApiIdentity>,
+    text: &str,
+) -> Option<Value> {
+    let msg: Value = match serde_json::from_str(text) {
+        Ok(msg) => msg,
+        Err(e) => {
+            let error = format!("invalid JSON: {}", e);
+            return Some(rooms::error_reply(&Value::Null, &error));
+        }
+    };
+    let writes = matches!(msg["type"].as_str(), None | Some("op"));
+    if writes {
+        if let Err(e) = tokens::require_scope(api, Scope::Write) {
+            return Some(rooms::error_reply(&msg, &e.detail));
+        }
+    }
+    if msg["type"].is_string() {
+        return rooms::handle_message(&state.pool, &state.rooms, session, &msg).await;
+    }
+    if let Err(e) = handle_client_op(&state.pool, &msg).await {
+        tracing::warn!("client op error: {}", e);
+    }
+    None
+}
 
+async fn handle_client_op(pool: &Pool, op: &Value) -> Result<(), String> {
     let op_type = op["op_type"].as_str()
         .ok_or_else(|| "missing op_type".to_string())?;
 
-    let node_id_param = op.get("node_id")
+    let node_id = op.get("node_id")
         .and_then(|v| v.as_str())
-        .map(|id| format!("'{}'::uuid", id.replace('\'', "''")))
-        .unwrap_or_else(|| "NULL".to_string());
+        .map(|id| uuid::Uuid::parse_str(id).map_err(|_| format!("invalid node_id '{id}'")))
+        .transpose()?;
 
     let empty_payload = serde_json::json!({});
     let payload = op.get("payload")
         .unwrap_or(&empty_payload);
 
-    let sql = format!(
-        "SELECT kerai.apply_op('{}', {}, '{}'::jsonb)",
-        op_type.replace('\'', "''"),
-        node_id_param,
-        payload.to_string().replace('\'', "''"),
-    );
-
     let client = pool.get().await.map_err(|e| e.to_string())?;
-    client.execute(&sql, &[]).await.map_err(|e| e.to_string())?;
+    client
+        .execute(
+            "SELECT kerai.apply_op($1, $2::uuid, $3::jsonb)",
+            &[&op_type, &node_id, payload],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
 
     Ok(())
 }